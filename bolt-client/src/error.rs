@@ -0,0 +1,128 @@
+// Daemon API errors
+//
+// `ErrorPayload` is the JSON body a daemon returns for a failed request. Its
+// codes and categories are shared with the runtime, which reports its own
+// errors with them.
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Why a client call failed
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The daemon handled the request and reported an error
+    #[error("Daemon error: {}", .0.message)]
+    Remote(ErrorPayload),
+
+    #[error("Failed to reach Bolt daemon at {url}: {source}")]
+    Unreachable {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// A failure without an error body, e.g. from a proxy in between
+    #[error("Bolt daemon returned {status}: {body}")]
+    Status { status: u16, body: String },
+
+    #[error("Unexpected Bolt daemon response: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Stable, machine-readable error codes exposed to API consumers
+///
+/// Codes are part of the public contract: new ones may be added but existing
+/// ones are never renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BoltfileNotFound,
+    InvalidConfig,
+    ContainerNotFound,
+    ImageNotFound,
+    ImagePullFailed,
+    StartFailed,
+    PortConflict,
+    GpuUnavailable,
+    QuotaExceeded,
+    RuntimeUnavailable,
+    ImageDecryptionFailed,
+    ImageUntrusted,
+    ImagePlatformMismatch,
+    RuntimeFailure,
+    NetworkNotFound,
+    InvalidSubnet,
+    NetworkSetupFailed,
+    GamingSetupFailed,
+    AudioUnavailable,
+    Io,
+    Serialization,
+    Internal,
+}
+
+/// Coarse grouping of error codes, for consumers that only need to know how to react
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    NotFound,
+    Conflict,
+    InvalidArgument,
+    ResourceExhausted,
+    Unavailable,
+    Internal,
+}
+
+/// JSON body returned to API clients for a failed request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorPayload {
+    pub code: ErrorCode,
+    pub category: ErrorCategory,
+    pub message: String,
+    /// Messages of the underlying causes, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+}
+
+impl ErrorCode {
+    pub fn category(self) -> ErrorCategory {
+        match self {
+            ErrorCode::BoltfileNotFound
+            | ErrorCode::ContainerNotFound
+            | ErrorCode::ImageNotFound
+            | ErrorCode::NetworkNotFound => ErrorCategory::NotFound,
+            ErrorCode::PortConflict => ErrorCategory::Conflict,
+            ErrorCode::InvalidConfig
+            | ErrorCode::InvalidSubnet
+            | ErrorCode::ImageUntrusted
+            | ErrorCode::ImagePlatformMismatch
+            | ErrorCode::Serialization => ErrorCategory::InvalidArgument,
+            ErrorCode::QuotaExceeded => ErrorCategory::ResourceExhausted,
+            ErrorCode::GpuUnavailable
+            | ErrorCode::RuntimeUnavailable
+            | ErrorCode::ImageDecryptionFailed
+            | ErrorCode::AudioUnavailable => ErrorCategory::Unavailable,
+            ErrorCode::ImagePullFailed
+            | ErrorCode::StartFailed
+            | ErrorCode::RuntimeFailure
+            | ErrorCode::NetworkSetupFailed
+            | ErrorCode::GamingSetupFailed
+            | ErrorCode::Io
+            | ErrorCode::Internal => ErrorCategory::Internal,
+        }
+    }
+}
+
+impl ErrorCategory {
+    /// HTTP status used when the error is returned from a daemon API
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorCategory::NotFound => 404,
+            ErrorCategory::Conflict => 409,
+            ErrorCategory::InvalidArgument => 400,
+            ErrorCategory::ResourceExhausted => 429,
+            ErrorCategory::Unavailable => 503,
+            ErrorCategory::Internal => 500,
+        }
+    }
+}
@@ -0,0 +1,518 @@
+// Bolt API client
+//
+// `BoltClient` drives a remote Bolt through the daemon's native API
+// (`/bolt/v1`) with the same method names as `BoltRuntime`. It only speaks
+// HTTP and doesn't depend on the runtime, so CI runners, bots and Nova can
+// control Bolt without root, a local container runtime or building the
+// gaming, QUIC and OCI stacks. That is why it is a crate of its own rather
+// than a feature of `bolt`, which re-exports it as `bolt::client`.
+//
+// It covers containers, exec, images and builds, volumes, networks, surge,
+// gaming, restic backups, filesystem snapshots and the node's reports. Calls
+// that need a terminal or stream files, and host administration such as
+// builders, capsules and tuning, are left to the CLI on the daemon's host.
+//
+// Errors reported by the daemon come back as `Error::Remote`, keeping the
+// daemon's error code and category.
+mod error;
+pub mod types;
+
+pub use error::{Error, ErrorCategory, ErrorCode, ErrorPayload, Result};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use types::{
+    BuildImageRequest, BuildStats, Capabilities, CapacityReport, CleanupSnapshotsRequest,
+    ConfigReload, ContainerInfo, CrashReport, CreateNetworkRequest, CreateSnapshotRequest,
+    CreateVolumeRequest, DoctorReport, ExecOutput, ExecRequest, GamingSetupRequest, ImageRecord,
+    ImageRequest, LaunchGameRequest, NetworkInfo, NodeHealth, PullPolicy, ResticBackupRequest,
+    ResticBackupSummary, ResticRestoreRequest, ResticRestoreSummary, ResticSnapshot,
+    RollbackOutcome, RunContainerRequest, ServicePlan, Snapshot, SnapshotKind, SurgeRequest,
+    SurgeStatus, Volume, VolumeInfo,
+};
+
+/// Path prefix of the native API
+pub const API_PREFIX: &str = "bolt/v1";
+
+/// Daemon address used when `BOLT_HOST` is not set
+pub const DEFAULT_HOST: &str = "http://127.0.0.1:2375";
+
+/// Typed async client for a Bolt daemon
+#[derive(Debug, Clone)]
+pub struct BoltClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl BoltClient {
+    /// Client for the daemon at `base_url`, e.g. `http://build-host:2375`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Client using a preconfigured `reqwest::Client` (timeouts, TLS, proxies)
+    pub fn with_http_client(base_url: impl Into<String>, http: reqwest::Client) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { base_url, http }
+    }
+
+    /// Client for `BOLT_HOST`, falling back to the local daemon
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("BOLT_HOST").unwrap_or_else(|_| DEFAULT_HOST.to_string()))
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}/{}", self.base_url, API_PREFIX, path)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let unreachable = |source| Error::Unreachable {
+            url: self.base_url.clone(),
+            source,
+        };
+        let response = request.send().await.map_err(unreachable)?;
+        let status = response.status().as_u16();
+        let body = response.bytes().await.map_err(unreachable)?;
+        decode(status, &body)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(self.http.get(self.url(path))).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T> {
+        self.send(self.http.post(self.url(path)).json(body)).await
+    }
+
+    /// Run a container on the daemon; it always starts detached
+    pub async fn run_container(
+        &self,
+        image: &str,
+        name: Option<&str>,
+        ports: &[String],
+        env: &[String],
+        volumes: &[String],
+    ) -> Result<()> {
+        let request = RunContainerRequest {
+            image: image.to_string(),
+            name: name.map(str::to_string),
+            ports: ports.to_vec(),
+            env: env.to_vec(),
+            volumes: volumes.to_vec(),
+        };
+        self.post("containers", &request).await
+    }
+
+    /// List containers
+    pub async fn list_containers(&self, all: bool) -> Result<Vec<ContainerInfo>> {
+        self.send(self.http.get(self.url("containers")).query(&[("all", all)]))
+            .await
+    }
+
+    /// Stop a container
+    pub async fn stop_container(&self, container: &str) -> Result<()> {
+        self.post(&format!("containers/{}/stop", container), &())
+            .await
+    }
+
+    /// Remove a container
+    pub async fn remove_container(&self, container: &str, force: bool) -> Result<()> {
+        self.send(
+            self.http
+                .delete(self.url(&format!("containers/{}", container)))
+                .query(&[("force", force)]),
+        )
+        .await
+    }
+
+    /// Restart a container
+    pub async fn restart_container(&self, container: &str, timeout: u64) -> Result<()> {
+        self.send(
+            self.http
+                .post(self.url(&format!("containers/{}/restart", container)))
+                .query(&[("timeout", timeout)]),
+        )
+        .await
+    }
+
+    /// Run a command in a running container without stdin, returning its
+    /// exit code and output once it exits
+    pub async fn exec_container_output(
+        &self,
+        container: &str,
+        request: &ExecRequest,
+    ) -> Result<ExecOutput> {
+        self.post(&format!("containers/{}/exec", container), request)
+            .await
+    }
+
+    /// Build an image from a context on the daemon's host, reusing the
+    /// cached image when the context is unchanged
+    pub async fn build_image(
+        &self,
+        path: &str,
+        tag: Option<&str>,
+        dockerfile: &str,
+    ) -> Result<BuildStats> {
+        let request = BuildImageRequest {
+            tag: tag.map(str::to_string),
+            dockerfile: dockerfile.to_string(),
+            ..BuildImageRequest::new(path)
+        };
+        self.build_image_on(&request).await
+    }
+
+    /// Build an image on a named builder with caches, annotations and
+    /// build args
+    pub async fn build_image_on(&self, request: &BuildImageRequest) -> Result<BuildStats> {
+        self.post("images/build", request).await
+    }
+
+    /// Pull an image on the daemon
+    pub async fn pull_image(&self, image: &str, platform: Option<&str>) -> Result<()> {
+        self.post("images/pull", &image_request(image, None, platform))
+            .await
+    }
+
+    /// Make an image available on the daemon according to a pull policy
+    pub async fn ensure_image(
+        &self,
+        image: &str,
+        policy: Option<PullPolicy>,
+        platform: Option<&str>,
+    ) -> Result<ImageRecord> {
+        self.post("images/ensure", &image_request(image, policy, platform))
+            .await
+    }
+
+    /// Push an image from the daemon
+    pub async fn push_image(&self, image: &str) -> Result<()> {
+        self.post("images/push", &image_request(image, None, None))
+            .await
+    }
+
+    /// Start Surge services from the daemon's Boltfile
+    pub async fn surge_up(&self, services: &[String], force_recreate: bool) -> Result<()> {
+        self.surge_up_with_pull(services, force_recreate, None)
+            .await
+    }
+
+    /// Start Surge services, overriding each service's `pull_policy`
+    pub async fn surge_up_with_pull(
+        &self,
+        services: &[String],
+        force_recreate: bool,
+        pull: Option<PullPolicy>,
+    ) -> Result<()> {
+        let request = SurgeRequest {
+            services: services.to_vec(),
+            force_recreate,
+            pull,
+            ..Default::default()
+        };
+        self.post("surge/up", &request).await
+    }
+
+    /// Show what `surge up` would change
+    pub async fn surge_plan(&self, services: &[String]) -> Result<Vec<ServicePlan>> {
+        self.post("surge/plan", &surge_request(services)).await
+    }
+
+    /// Stop Surge services
+    pub async fn surge_down(&self, services: &[String], volumes: bool) -> Result<()> {
+        let request = SurgeRequest {
+            volumes,
+            ..surge_request(services)
+        };
+        self.post("surge/down", &request).await
+    }
+
+    /// Get Surge status
+    pub async fn surge_status(&self) -> Result<SurgeStatus> {
+        self.get("surge/status").await
+    }
+
+    /// Scale Surge services
+    pub async fn surge_scale(&self, services: &[String]) -> Result<()> {
+        self.post("surge/scale", &surge_request(services)).await
+    }
+
+    /// Create a network
+    pub async fn create_network(
+        &self,
+        name: &str,
+        driver: &str,
+        subnet: Option<&str>,
+    ) -> Result<()> {
+        let request = CreateNetworkRequest {
+            name: name.to_string(),
+            driver: driver.to_string(),
+            subnet: subnet.map(str::to_string),
+        };
+        self.post("networks", &request).await
+    }
+
+    /// List networks
+    pub async fn list_networks(&self) -> Result<Vec<NetworkInfo>> {
+        self.get("networks").await
+    }
+
+    /// Remove a network
+    pub async fn remove_network(&self, name: &str) -> Result<()> {
+        self.send(self.http.delete(self.url(&format!("networks/{}", name))))
+            .await
+    }
+
+    /// Create a volume
+    pub async fn create_volume(&self, request: &CreateVolumeRequest) -> Result<Volume> {
+        self.post("volumes", request).await
+    }
+
+    /// List volumes
+    pub async fn list_volumes(&self) -> Result<Vec<VolumeInfo>> {
+        self.get("volumes").await
+    }
+
+    /// Inspect a volume
+    pub async fn inspect_volume(&self, name: &str) -> Result<Volume> {
+        self.get(&format!("volumes/{}", name)).await
+    }
+
+    /// Remove a volume
+    pub async fn remove_volume(&self, name: &str, force: bool) -> Result<()> {
+        self.send(
+            self.http
+                .delete(self.url(&format!("volumes/{}", name)))
+                .query(&[("force", force)]),
+        )
+        .await
+    }
+
+    /// Remove unused volumes, returning their names
+    pub async fn prune_volumes(&self, force: bool) -> Result<Vec<String>> {
+        self.send(
+            self.http
+                .post(self.url("volumes/prune"))
+                .query(&[("force", force)]),
+        )
+        .await
+    }
+
+    /// Set up Wine/Proton on the daemon's host
+    pub async fn setup_gaming(&self, proton: Option<&str>, winver: Option<&str>) -> Result<()> {
+        let request = GamingSetupRequest {
+            proton: proton.map(str::to_string),
+            winver: winver.map(str::to_string),
+        };
+        self.post("gaming/setup", &request).await
+    }
+
+    /// Launch a game on the daemon's host
+    pub async fn launch_game(&self, game: &str, args: &[String]) -> Result<()> {
+        let request = LaunchGameRequest {
+            game: game.to_string(),
+            args: args.to_vec(),
+        };
+        self.post("gaming/launch", &request).await
+    }
+
+    /// Create the daemon's restic repository
+    pub async fn restic_init(&self) -> Result<()> {
+        self.post("backup/restic/init", &()).await
+    }
+
+    /// Back up volumes and capsule snapshots to the restic repository
+    pub async fn restic_backup(
+        &self,
+        volumes: &[String],
+        snapshots: &[String],
+    ) -> Result<Vec<ResticBackupSummary>> {
+        let request = ResticBackupRequest {
+            volumes: volumes.to_vec(),
+            snapshots: snapshots.to_vec(),
+        };
+        self.post("backup/restic/backup", &request).await
+    }
+
+    /// Bolt's backups in the restic repository
+    pub async fn restic_snapshots(&self) -> Result<Vec<ResticSnapshot>> {
+        self.get("backup/restic/snapshots").await
+    }
+
+    /// Restore a backup from the restic repository, to `target` on the
+    /// daemon's host instead of in place
+    pub async fn restic_restore(
+        &self,
+        snapshot_id: &str,
+        target: Option<&Path>,
+    ) -> Result<ResticRestoreSummary> {
+        let request = ResticRestoreRequest {
+            snapshot_id: snapshot_id.to_string(),
+            target: target.map(Path::to_path_buf),
+        };
+        self.post("backup/restic/restore", &request).await
+    }
+
+    /// Take a filesystem snapshot of the snapshot root
+    pub async fn create_snapshot(
+        &self,
+        name: Option<&str>,
+        description: Option<&str>,
+        kind: SnapshotKind,
+    ) -> Result<Snapshot> {
+        let request = CreateSnapshotRequest {
+            name: name.map(str::to_string),
+            description: description.map(str::to_string),
+            kind,
+        };
+        self.post("snapshots", &request).await
+    }
+
+    /// Filesystem snapshots, oldest first
+    pub async fn list_snapshots(&self, kind: Option<SnapshotKind>) -> Result<Vec<Snapshot>> {
+        let mut request = self.http.get(self.url("snapshots"));
+        if let Some(kind) = kind {
+            request = request.query(&[("kind", kind)]);
+        }
+        self.send(request).await
+    }
+
+    /// A filesystem snapshot by ID, name or unique ID prefix
+    pub async fn snapshot(&self, snapshot: &str) -> Result<Snapshot> {
+        self.get(&format!("snapshots/{}", snapshot)).await
+    }
+
+    /// Roll the snapshot root back to a snapshot
+    pub async fn rollback_snapshot(&self, snapshot: &str) -> Result<RollbackOutcome> {
+        self.post(&format!("snapshots/{}/rollback", snapshot), &())
+            .await
+    }
+
+    /// Delete a filesystem snapshot
+    pub async fn delete_snapshot(&self, snapshot: &str) -> Result<Snapshot> {
+        self.send(
+            self.http
+                .delete(self.url(&format!("snapshots/{}", snapshot))),
+        )
+        .await
+    }
+
+    /// Apply the snapshot retention policy
+    pub async fn cleanup_snapshots(&self, dry_run: bool) -> Result<Vec<Snapshot>> {
+        self.post("snapshots/cleanup", &CleanupSnapshotsRequest { dry_run })
+            .await
+    }
+
+    /// Compile-time features and host support of the daemon
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        self.get("capabilities").await
+    }
+
+    /// Host preflight checks on the daemon
+    pub async fn doctor(&self) -> Result<DoctorReport> {
+        self.get("doctor").await
+    }
+
+    /// Health of the daemon's node and the surge projects on it
+    pub async fn health(&self) -> Result<NodeHealth> {
+        self.get("health").await
+    }
+
+    /// Storage usage forecast of the daemon's host
+    pub async fn capacity_report(&self) -> Result<CapacityReport> {
+        self.get("capacity").await
+    }
+
+    /// List collected crash dumps, newest first
+    pub async fn crash_reports(&self, container: Option<&str>) -> Result<Vec<CrashReport>> {
+        let mut request = self.http.get(self.url("crashes"));
+        if let Some(container) = container {
+            request = request.query(&[("container", container)]);
+        }
+        self.send(request).await
+    }
+
+    /// Make the daemon reread its config.toml
+    pub async fn reload_config(&self) -> Result<ConfigReload> {
+        self.post("config/reload", &()).await
+    }
+
+    /// Get a crash dump by id, or the latest one for a container
+    pub async fn crash_report(&self, container_or_id: &str) -> Result<Option<CrashReport>> {
+        self.get(&format!("crashes/{}", container_or_id)).await
+    }
+}
+
+/// The reply to a call: its JSON body, or the daemon's error
+fn decode<T: DeserializeOwned>(status: u16, body: &[u8]) -> Result<T> {
+    if !(200..300).contains(&status) {
+        return Err(match serde_json::from_slice::<ErrorPayload>(body) {
+            Ok(payload) => Error::Remote(payload),
+            Err(_) => Error::Status {
+                status,
+                body: String::from_utf8_lossy(body).trim().to_string(),
+            },
+        });
+    }
+    Ok(serde_json::from_slice(body)?)
+}
+
+fn image_request(
+    image: &str,
+    pull_policy: Option<PullPolicy>,
+    platform: Option<&str>,
+) -> ImageRequest {
+    ImageRequest {
+        image: image.to_string(),
+        pull_policy,
+        platform: platform.map(str::to_string),
+    }
+}
+
+fn surge_request(services: &[String]) -> SurgeRequest {
+    SurgeRequest {
+        services: services.to_vec(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_replies_and_daemon_errors() {
+        let client = BoltClient::new("http://build-host:2375/");
+        assert_eq!(
+            client.url("surge/up"),
+            "http://build-host:2375/bolt/v1/surge/up"
+        );
+
+        let names: Vec<String> = decode(200, br#"["web","db"]"#).unwrap();
+        assert_eq!(names, ["web", "db"]);
+        decode::<()>(200, b"null").unwrap();
+
+        let body = br#"{"code":"CONTAINER_NOT_FOUND","category":"not_found","message":"no web"}"#;
+        match decode::<()>(404, body) {
+            Err(Error::Remote(payload)) => {
+                assert_eq!(payload.code, ErrorCode::ContainerNotFound);
+                assert_eq!(payload.code.category(), payload.category);
+                assert!(payload.causes.is_empty());
+            }
+            other => panic!("expected a daemon error, got {:?}", other),
+        }
+        assert!(matches!(
+            decode::<()>(502, b"Bad Gateway\n"),
+            Err(Error::Status { status: 502, ref body }) if body == "Bad Gateway"
+        ));
+        assert!(matches!(
+            decode::<Vec<String>>(200, b"{}"),
+            Err(Error::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,638 @@
+// Daemon API types
+//
+// Request and reply bodies of the native API, as the daemon serializes
+// them. They mirror the runtime's own types field for field without pulling
+// in the runtime; bolt's tests decode every reply of a live daemon into
+// them, so the two can't drift apart.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunContainerRequest {
+    pub image: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub ports: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub volumes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageRequest {
+    pub image: String,
+    #[serde(default)]
+    pub pull_policy: Option<PullPolicy>,
+    /// `os/architecture[/variant]`; the daemon host's when unset
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateNetworkRequest {
+    pub name: String,
+    pub driver: String,
+    pub subnet: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SurgeRequest {
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub force_recreate: bool,
+    #[serde(default)]
+    pub pull: Option<PullPolicy>,
+    /// `surge down` only: also remove the services' volumes
+    #[serde(default)]
+    pub volumes: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub id: String,
+    pub name: String,
+    pub names: Vec<String>,
+    pub image: String,
+    pub image_id: String,
+    pub command: String,
+    pub created: String,
+    pub status: String,
+    pub ports: Vec<String>,
+    pub labels: HashMap<String, String>,
+    pub uptime: Option<String>,
+    pub runtime: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub id: String,
+    pub name: String,
+    pub driver: String,
+    pub subnet: Option<String>,
+    pub created: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurgeStatus {
+    pub services: Vec<ServiceInfo>,
+    pub networks: Vec<NetworkInfo>,
+    /// Set when the Boltfile changed since the services were started from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<DirtyPlan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub status: String,
+    pub replicas: u32,
+    /// Restarts under the service's restart policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restarts: Option<SupervisorState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirtyPlan {
+    /// When the change was first seen
+    pub since: DateTime<Utc>,
+    /// When the project's services last changed
+    pub applied_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorState {
+    pub container: String,
+    /// The policy as written in the Boltfile
+    pub restart: String,
+    pub watcher_pid: Option<u32>,
+    /// Restarts since surge started the container
+    pub restarts: u64,
+    /// Restarts since it last stayed up
+    pub in_a_row: u32,
+    pub last_exit_code: Option<i64>,
+    pub last_restart: Option<DateTime<Utc>>,
+    /// When the pending restart is due
+    pub next_restart: Option<DateTime<Utc>>,
+    /// Set once `on-failure:N` ran out of retries
+    pub gave_up: bool,
+    /// Stopped through Bolt; not restarted until it runs again
+    pub stopped: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullPolicy {
+    Always,
+    #[serde(alias = "missing", alias = "if_not_present")]
+    IfNotPresent,
+    Never,
+    DigestPinned,
+}
+
+/// The image a container was started from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRecord {
+    pub image: String,
+    /// Registry digest (`sha256:...`), or the local image ID for images that
+    /// were never pushed
+    pub digest: Option<String>,
+    pub pulled: bool,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action", content = "reason")]
+pub enum PlanAction {
+    Create,
+    Build,
+    /// The Boltfile's image differs from the one the container runs
+    Recreate(String),
+    /// The tag resolves to a newer image upstream
+    Update(String),
+    Keep,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServicePlan {
+    pub service: String,
+    pub container: String,
+    pub image: Option<String>,
+    pub pull_policy: Option<PullPolicy>,
+    pub action: PlanAction,
+    /// Digest the running container was started from
+    pub current_digest: Option<String>,
+    pub remote_digest: Option<String>,
+    /// Trust policy violations `surge up` would refuse
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trust: Vec<Violation>,
+    /// Published host ports that are already taken
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<PortConflict>,
+    /// Settings overridden for this run, e.g. "environment.DEBUG=1"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overridden: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrustCheck {
+    Signature,
+    Provenance,
+    Digest,
+    Latest,
+    MaxAge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    pub image: String,
+    /// The `images` pattern of the rule that was violated
+    pub rule: String,
+    pub check: TrustCheck,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortConflict {
+    pub service: String,
+    /// The mapping as written in the Boltfile, e.g. "8080:80"
+    pub mapping: String,
+    pub host_port: u16,
+    pub held_by: PortHolder,
+    /// Free host port found by the offset strategy
+    pub suggestion: Option<u16>,
+    /// Whether `surge up` publishes `suggestion` instead
+    #[serde(default)]
+    pub remapped: bool,
+}
+
+/// What already holds a host port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum PortHolder {
+    /// A service of another project on this node
+    Project { project: String, service: String },
+    /// A process on the host outside Bolt's projects
+    Host,
+}
+
+/// What the daemon's build supports, see `bolt capabilities`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub version: String,
+    /// Cargo features enabled at build time
+    pub features: BTreeMap<String, bool>,
+    /// Container backend used for podman/docker operations, if any
+    pub container_runtime: Option<String>,
+    pub kernel_version: Option<String>,
+    pub capabilities: BTreeMap<String, Capability>,
+}
+
+impl Capabilities {
+    pub fn is_available(&self, name: &str) -> bool {
+        self.capabilities.get(name).is_some_and(|c| c.available())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capability {
+    /// Support was compiled into the daemon's build
+    pub compiled: bool,
+    /// The host provides what the feature needs
+    pub host_supported: bool,
+    /// Why the capability is unavailable, when it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Capability {
+    pub fn available(&self) -> bool {
+        self.compiled && self.host_supported
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// One preflight check result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorCheck {
+    /// Subsystem the check belongs to, e.g. "runtime" or "network/lan"
+    pub area: String,
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|c| c.status == CheckStatus::Fail)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+/// Health of one part of the node and why it isn't healthy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceHealth {
+    pub name: String,
+    pub container: String,
+    pub status: HealthStatus,
+    /// What the service is doing: "running", "suspended", "2/3 running"
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectHealth {
+    pub project: String,
+    pub status: HealthStatus,
+    pub services: Vec<ServiceHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeHealth {
+    pub status: HealthStatus,
+    pub checked_at: DateTime<Utc>,
+    /// Every reason below, prefixed with where it comes from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    pub daemon: ComponentHealth,
+    pub storage: ComponentHealth,
+    pub network: ComponentHealth,
+    pub projects: Vec<ProjectHealth>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapacityStatus {
+    Ok,
+    /// Threshold forecast to be hit within `alert_days`
+    Warning,
+    /// Already at or past the threshold
+    Critical,
+    /// Not enough history to estimate growth yet
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesForecast {
+    pub name: String,
+    pub path: PathBuf,
+    pub current_bytes: u64,
+    pub threshold_bytes: Option<u64>,
+    /// Where the threshold comes from: "configured" or "disk"
+    pub threshold_source: Option<String>,
+    pub growth_bytes_per_day: Option<f64>,
+    pub days_until_full: Option<f64>,
+    pub status: CapacityStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapacityReport {
+    pub window: String,
+    pub samples: usize,
+    pub alert_days: u32,
+    pub series: Vec<SeriesForecast>,
+}
+
+/// A crash dump collected on the daemon's host; the paths are on that host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub container: String,
+    pub image: Option<String>,
+    pub exit_code: i32,
+    /// Signal number for exit codes of the form 128 + N
+    pub signal: Option<i32>,
+    pub pid: Option<u32>,
+    pub collected_at: DateTime<Utc>,
+    pub oom_killed: bool,
+    pub log_file: Option<PathBuf>,
+    pub core_file: Option<PathBuf>,
+    pub memory_events_file: Option<PathBuf>,
+    /// Diagnostics that could not be captured and why
+    pub notes: Vec<String>,
+}
+
+/// What a config reload changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigReload {
+    /// Sections of config.toml that differ from the running config
+    pub changed: Vec<String>,
+    /// Changed settings that only apply after a restart
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildImageRequest {
+    /// Build context on the daemon's host
+    pub path: String,
+    pub tag: Option<String>,
+    pub dockerfile: String,
+    pub builder: String,
+    #[serde(default)]
+    pub cache_volumes: Vec<String>,
+    #[serde(default)]
+    pub annotations: Vec<String>,
+    #[serde(default)]
+    pub build_args: Vec<String>,
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+impl BuildImageRequest {
+    /// Build `path` with its Dockerfile on the default builder
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            tag: None,
+            dockerfile: "Dockerfile".to_string(),
+            builder: "default".to_string(),
+            cache_volumes: Vec::new(),
+            annotations: Vec::new(),
+            build_args: Vec::new(),
+            no_cache: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// What a single build got out of the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildStats {
+    pub image_id: String,
+    /// The whole build was skipped because the context was unchanged
+    pub cache_hit: bool,
+    /// Dockerfile steps run by the builder, and how many of those it served from its layer cache
+    pub steps_total: usize,
+    pub steps_cached: usize,
+    /// Layer cache bytes pruned to stay under the size limit
+    pub pruned: u64,
+    /// Size of the builder's layer cache
+    pub cache_size: u64,
+    pub cache_limit: u64,
+    pub counters: CacheCounters,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecRequest {
+    pub command: Vec<String>,
+    /// Extra environment, as KEY=VALUE
+    #[serde(default)]
+    pub env: Vec<String>,
+    pub workdir: Option<String>,
+    pub user: Option<String>,
+}
+
+/// What a command run in a container printed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateVolumeRequest {
+    pub name: String,
+    /// "local" when empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub driver: String,
+    pub size: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub name: String,
+    pub driver: String,
+    pub mount_point: PathBuf,
+    pub size_bytes: Option<u64>,
+    pub created_at: SystemTime,
+    pub labels: HashMap<String, String>,
+    pub options: HashMap<String, String>,
+    pub in_use: bool,
+    /// Container IDs using this volume
+    pub used_by: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub driver: String,
+    pub mount_point: String,
+    pub size: String,
+    pub created: String,
+    pub in_use: bool,
+    pub containers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GamingSetupRequest {
+    pub proton: Option<String>,
+    pub winver: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchGameRequest {
+    pub game: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResticBackupRequest {
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub snapshots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResticRestoreRequest {
+    pub snapshot_id: String,
+    /// Restore here on the daemon's host instead of in place
+    pub target: Option<PathBuf>,
+}
+
+/// What a repository snapshot holds, from its tags
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum BackupTarget {
+    Volume(String),
+    Snapshot(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResticSnapshot {
+    pub id: String,
+    pub time: String,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResticBackupSummary {
+    pub target: BackupTarget,
+    pub snapshot_id: String,
+    pub data_added: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResticRestoreSummary {
+    pub target: BackupTarget,
+    pub snapshot_id: String,
+    pub restored_to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotKind {
+    Manual,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    /// Taken automatically before a rollback
+    PreRollback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotBackend {
+    Btrfs,
+    Zfs,
+    Copy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub kind: SnapshotKind,
+    pub backend: SnapshotBackend,
+    pub root: PathBuf,
+    /// Snapshot directory, or the ZFS snapshot name
+    pub location: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub keep_forever: bool,
+    /// Known for copy and ZFS snapshots
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub kind: SnapshotKind,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupSnapshotsRequest {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What a rollback did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackOutcome {
+    pub snapshot: Snapshot,
+    /// Snapshot of the state that was rolled back
+    pub pre_rollback: Option<Snapshot>,
+    /// A mounted btrfs root switches on the next boot
+    pub reboot_required: bool,
+    /// Newer ZFS snapshots destroyed by the rollback
+    pub discarded: Vec<Snapshot>,
+}
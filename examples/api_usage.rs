@@ -43,6 +43,7 @@ async fn main() -> Result<()> {
             proton: Some("8.0".to_string()),
             winver: Some("win10".to_string()),
             prefix: Some("/games/wine-prefix".to_string()),
+            prefix_cache: None,
         }),
         performance: Some(bolt::config::PerformanceConfig {
             cpu_governor: Some("performance".to_string()),
@@ -158,6 +158,7 @@ async fn launch_battlenet_game(
             proton: Some("8.0-3").to_string(),
             winver: Some("win10".to_string()),
             prefix: Some("/home/user/.wine-diablo4".to_string()),
+            prefix_cache: None,
         }),
         performance: Some(bolt::config::PerformanceConfig {
             cpu_governor: Some("performance".to_string()),
@@ -0,0 +1,580 @@
+//! Shared package manager caches (`cache_volumes = ["cargo", "npm"]`)
+//!
+//! Builds, Boltfile services and dev environments can opt into caches that
+//! outlive any one container, so dependencies are downloaded once per host
+//! instead of once per build. Each cache is a directory under
+//! `<data_dir>/artifact-cache/<name>`, created on first use and mounted where
+//! its package manager looks; a cache can also be mounted somewhere else
+//! with `name:/path`. With Podman, builds get the caches through
+//! `podman build --volume`; `docker build` can't mount volumes, so Docker
+//! builds run without them.
+//!
+//! The package managers already cope with several processes sharing a cache
+//! (cargo and go lock it, npm, pip and yarn write entries atomically), so
+//! Bolt only has to keep its own cleanup out of their way. A build holds a
+//! shared lock on `<name>.lock` while it runs and cleanup takes the
+//! exclusive one, skipping caches it can't get. Containers are labelled with
+//! their caches; a cache a running container has mounted only loses entries
+//! nobody touched for an hour.
+//!
+//! All caches together are kept under `[build_cache] artifacts_max_size`,
+//! evicting the least recently used entries first. An entry is what the
+//! package manager can fetch again on its own: a file for npm, pip, go and
+//! Steam, an unpacked crate or package directory for cargo and yarn.
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::{format_size, parse_size};
+
+/// Label listing the caches a container has mounted
+pub const LABEL: &str = "bolt.cache-volumes";
+
+/// Entries of a cache in use by a running container survive this long
+const IN_USE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// What can be evicted from a cache without confusing its package manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entries {
+    /// Any single file
+    Files,
+    /// Whole entries this many levels below the cache root
+    Depth(usize),
+}
+
+struct Known {
+    name: &'static str,
+    target: &'static str,
+    /// Variable pointing the package manager at `target`, for images that
+    /// run it as another user
+    env: Option<&'static str>,
+    entries: Entries,
+}
+
+const KNOWN: &[Known] = &[
+    Known {
+        name: "cargo",
+        target: "/usr/local/cargo/registry",
+        env: None,
+        // cache/<index>/<crate>.crate, src/<index>/<crate>, index/<index>/<file>
+        entries: Entries::Depth(3),
+    },
+    Known {
+        name: "npm",
+        target: "/root/.npm",
+        env: Some("npm_config_cache"),
+        entries: Entries::Files,
+    },
+    Known {
+        name: "pip",
+        target: "/root/.cache/pip",
+        env: Some("PIP_CACHE_DIR"),
+        entries: Entries::Files,
+    },
+    Known {
+        name: "yarn",
+        target: "/usr/local/share/.cache/yarn",
+        env: Some("YARN_CACHE_FOLDER"),
+        // v6/<package>
+        entries: Entries::Depth(2),
+    },
+    Known {
+        name: "go-build",
+        target: "/root/.cache/go-build",
+        env: Some("GOCACHE"),
+        entries: Entries::Files,
+    },
+    Known {
+        name: "steam",
+        target: "/home/steam/Steam/depotcache",
+        env: None,
+        entries: Entries::Files,
+    },
+];
+
+fn known(name: &str) -> Option<&'static Known> {
+    KNOWN.iter().find(|k| k.name == name)
+}
+
+/// Names of the built-in caches
+pub fn known_names() -> Vec<&'static str> {
+    KNOWN.iter().map(|k| k.name).collect()
+}
+
+/// A cache mounted into a build or container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheVolume {
+    pub name: String,
+    /// Where it is mounted
+    pub target: String,
+    /// Host directory holding it
+    pub dir: PathBuf,
+    env: Option<&'static str>,
+}
+
+fn root(data_dir: &Path) -> PathBuf {
+    data_dir.join("artifact-cache")
+}
+
+/// Parse `name` or `name:/path`, as listed in `cache_volumes`
+fn parse(spec: &str, data_dir: &Path) -> Result<CacheVolume> {
+    let (name, target) = match spec.split_once(':') {
+        Some((name, target)) => (name, Some(target)),
+        None => (spec, None),
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "Invalid cache volume name '{}' (lowercase letters, digits, - and _)",
+            name
+        ));
+    }
+    let known = known(name);
+    let target = match (target, known) {
+        (Some(target), _) if target.starts_with('/') => target.to_string(),
+        (Some(target), _) => {
+            return Err(anyhow!(
+                "Cache volume '{}' must be mounted at an absolute path, not '{}'",
+                name,
+                target
+            ));
+        }
+        (None, Some(known)) => known.target.to_string(),
+        (None, None) => {
+            return Err(anyhow!(
+                "Unknown cache volume '{}' (built in: {}; or use {}:/path)",
+                name,
+                known_names().join(", "),
+                name
+            ));
+        }
+    };
+    Ok(CacheVolume {
+        name: name.to_string(),
+        env: known.and_then(|k| k.env),
+        target,
+        dir: root(data_dir).join(name),
+    })
+}
+
+/// The caches `specs` ask for, creating any that don't exist yet
+pub fn resolve(data_dir: &Path, specs: &[String]) -> Result<Vec<CacheVolume>> {
+    let mut volumes: Vec<CacheVolume> = Vec::new();
+    for spec in specs {
+        let volume = parse(spec, data_dir)?;
+        if volumes.iter().any(|v| v.name == volume.name) {
+            return Err(anyhow!("Cache volume '{}' is listed twice", volume.name));
+        }
+        if !volume.dir.exists() {
+            std::fs::create_dir_all(&volume.dir)
+                .with_context(|| format!("Failed to create {}", volume.dir.display()))?;
+            // Images that drop root still have to write to it
+            std::fs::set_permissions(&volume.dir, std::fs::Permissions::from_mode(0o777))?;
+            info!("📦 Created cache volume {}", volume.name);
+        }
+        volumes.push(volume);
+    }
+    Ok(volumes)
+}
+
+/// Arguments for `<runtime> build`
+pub fn build_args(runtime: &str, volumes: &[CacheVolume]) -> Vec<String> {
+    if volumes.is_empty() {
+        return Vec::new();
+    }
+    if runtime != "podman" {
+        warn!(
+            "⚠️  {} build can't mount cache volumes; building without {}",
+            runtime,
+            names(volumes)
+        );
+        return Vec::new();
+    }
+    volumes
+        .iter()
+        .flat_map(|v| {
+            [
+                "--volume".to_string(),
+                format!("{}:{}", v.dir.display(), v.target),
+            ]
+        })
+        .collect()
+}
+
+/// Arguments for `<runtime> run`: the mounts, the variables pointing the
+/// package managers at them and the label cleanup looks for
+pub fn run_args(volumes: &[CacheVolume]) -> Vec<String> {
+    if volumes.is_empty() {
+        return Vec::new();
+    }
+    let mut args = vec![
+        "--label".to_string(),
+        format!("{}={}", LABEL, names(volumes)),
+    ];
+    for volume in volumes {
+        args.push("-v".to_string());
+        args.push(format!("{}:{}", volume.dir.display(), volume.target));
+        if let Some(env) = volume.env {
+            args.push("-e".to_string());
+            args.push(format!("{}={}", env, volume.target));
+        }
+    }
+    args
+}
+
+fn names(volumes: &[CacheVolume]) -> String {
+    volumes
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Shared locks keeping cleanup away from caches a build is using; released
+/// when dropped
+pub struct Lease {
+    _locks: Vec<File>,
+}
+
+fn lock_file(data_dir: &Path, name: &str) -> Result<File> {
+    let path = root(data_dir).join(format!("{}.lock", name));
+    File::create(&path).with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Hold the caches for the length of a build
+pub fn lease(data_dir: &Path, volumes: &[CacheVolume]) -> Result<Lease> {
+    let mut locks = Vec::new();
+    for volume in volumes {
+        let file = lock_file(data_dir, &volume.name)?;
+        // SAFETY: flock only reads the descriptor, which `file` keeps open.
+        // Cleanup holds the exclusive lock briefly, so this waits at most
+        // for one pass.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } != 0 {
+            return Err(anyhow!(
+                "Failed to lock cache volume {}: {}",
+                volume.name,
+                std::io::Error::last_os_error()
+            ));
+        }
+        locks.push(file);
+    }
+    Ok(Lease { _locks: locks })
+}
+
+/// The exclusive lock on a cache, unless a build holds it
+fn try_exclusive(data_dir: &Path, name: &str) -> Result<Option<File>> {
+    let file = lock_file(data_dir, name)?;
+    // SAFETY: as above
+    let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+    Ok(locked.then_some(file))
+}
+
+/// Caches mounted by running containers
+async fn in_use(runtime: &str) -> HashSet<String> {
+    let ids = match AsyncCommand::new(runtime)
+        .args(["ps", "-q", "--filter", &format!("label={}", LABEL)])
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+        _ => return HashSet::new(),
+    };
+    if ids.is_empty() {
+        return HashSet::new();
+    }
+    let output = match AsyncCommand::new(runtime)
+        .arg("inspect")
+        .arg("--format")
+        .arg(format!("{{{{index .Config.Labels \"{}\"}}}}", LABEL))
+        .args(&ids)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(_) => return HashSet::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .flat_map(|line| line.split(','))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Something cleanup can remove
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+    /// Its cache is mounted by a running container
+    in_use: bool,
+}
+
+fn last_used(metadata: &std::fs::Metadata) -> SystemTime {
+    let seconds = metadata.atime().max(metadata.mtime()).max(0) as u64;
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn entries(dir: &Path, kind: Entries, in_use: bool) -> Vec<Entry> {
+    let walker = match kind {
+        Entries::Files => walkdir::WalkDir::new(dir).min_depth(1),
+        Entries::Depth(depth) => walkdir::WalkDir::new(dir).min_depth(depth).max_depth(depth),
+    };
+    walker
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| kind != Entries::Files || e.file_type().is_file())
+        .filter_map(|e| {
+            let (size, last) = if e.file_type().is_dir() {
+                walkdir::WalkDir::new(e.path())
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| e.metadata().ok())
+                    .fold((0, SystemTime::UNIX_EPOCH), |(size, last), m| {
+                        let size = if m.is_file() { size + m.len() } else { size };
+                        (size, last.max(last_used(&m)))
+                    })
+            } else {
+                let metadata = e.metadata().ok()?;
+                (metadata.len(), last_used(&metadata))
+            };
+            Some(Entry {
+                path: e.path().to_path_buf(),
+                size,
+                last_used: last,
+                in_use,
+            })
+        })
+        .collect()
+}
+
+/// Least recently used entries to remove until `total` fits in `max_size`.
+/// Entries of caches in use are spared if they were used after `grace`.
+fn select(mut entries: Vec<Entry>, total: u64, max_size: u64, grace: SystemTime) -> Vec<Entry> {
+    entries.retain(|e| !e.in_use || e.last_used < grace);
+    entries.sort_by_key(|e| e.last_used);
+    let mut remaining = total;
+    entries
+        .into_iter()
+        .take_while(|e| {
+            let over = remaining > max_size;
+            remaining = remaining.saturating_sub(e.size);
+            over
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Names of the caches on disk
+fn cache_names(data_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(root(data_dir))
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// A cache on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheUsage {
+    pub name: String,
+    pub size: u64,
+    pub last_used: Option<chrono::DateTime<chrono::Utc>>,
+    /// Mounted by a running container
+    pub in_use: bool,
+}
+
+/// Every cache, with its size
+pub async fn usage(data_dir: &Path, runtime: Option<&str>) -> Vec<CacheUsage> {
+    let mounted = match runtime {
+        Some(runtime) => in_use(runtime).await,
+        None => HashSet::new(),
+    };
+    cache_names(data_dir)
+        .into_iter()
+        .map(|name| {
+            let dir = root(data_dir).join(&name);
+            let last_used = walkdir::WalkDir::new(&dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.metadata().ok())
+                .map(|m| last_used(&m))
+                .max()
+                .map(chrono::DateTime::<chrono::Utc>::from);
+            CacheUsage {
+                size: dir_size(&dir),
+                in_use: mounted.contains(&name),
+                last_used,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// What a cleanup pass did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub removed: usize,
+    pub reclaimed: u64,
+    /// Size of all caches afterwards
+    pub size: u64,
+    /// Caches skipped because a build was using them
+    pub busy: Vec<String>,
+}
+
+/// Evict least recently used entries until all caches fit in `max_size`
+pub async fn prune(data_dir: &Path, runtime: Option<&str>, max_size: u64) -> Result<PruneReport> {
+    let mounted = match runtime {
+        Some(runtime) => in_use(runtime).await,
+        None => HashSet::new(),
+    };
+    let mut report = PruneReport::default();
+    let mut locks = Vec::new();
+    let mut candidates = Vec::new();
+    let mut total = 0;
+    for name in cache_names(data_dir) {
+        let dir = root(data_dir).join(&name);
+        total += dir_size(&dir);
+        match try_exclusive(data_dir, &name)? {
+            Some(lock) => locks.push(lock),
+            None => {
+                report.busy.push(name);
+                continue;
+            }
+        }
+        let kind = known(&name).map_or(Entries::Depth(1), |k| k.entries);
+        candidates.extend(entries(&dir, kind, mounted.contains(&name)));
+    }
+
+    let grace = SystemTime::now() - IN_USE_GRACE;
+    for entry in select(candidates, total, max_size, grace) {
+        let removed = if entry.path.is_dir() {
+            std::fs::remove_dir_all(&entry.path)
+        } else {
+            std::fs::remove_file(&entry.path)
+        };
+        match removed {
+            Ok(()) => {
+                debug!("Evicted {}", entry.path.display());
+                report.removed += 1;
+                report.reclaimed += entry.size;
+                total = total.saturating_sub(entry.size);
+            }
+            Err(e) => warn!("Could not evict {}: {}", entry.path.display(), e),
+        }
+    }
+    report.size = total;
+    Ok(report)
+}
+
+/// Remove a cache entirely, unless a build is using it
+pub fn clear(data_dir: &Path, name: &str) -> Result<u64> {
+    let dir = root(data_dir).join(name);
+    if !dir.is_dir() {
+        return Err(anyhow!("No cache volume named '{}'", name));
+    }
+    let _lock = try_exclusive(data_dir, name)?
+        .ok_or_else(|| anyhow!("Cache volume '{}' is in use by a build", name))?;
+    let size = dir_size(&dir);
+    std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    Ok(size)
+}
+
+/// Keep the caches under `[build_cache] artifacts_max_size` after they were used
+pub async fn enforce(config: &crate::config::BoltConfig, runtime: &str) {
+    let max_size = match parse_size(&config.build_cache.artifacts_max_size) {
+        Ok(size) => size,
+        Err(e) => {
+            warn!("Invalid [build_cache] artifacts_max_size: {}", e);
+            return;
+        }
+    };
+    match prune(&config.data_dir, Some(runtime), max_size).await {
+        Ok(report) if report.removed > 0 => info!(
+            "🧹 Evicted {} cache volume entries ({}), {} / {} used",
+            report.removed,
+            format_size(report.reclaimed),
+            format_size(report.size),
+            format_size(max_size)
+        ),
+        Ok(_) => {}
+        Err(e) => warn!("Could not clean up cache volumes: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_entries_sparing_live_ones() {
+        let data_dir = Path::new("/var/lib/bolt");
+        let cargo = parse("cargo", data_dir).unwrap();
+        assert_eq!(cargo.target, "/usr/local/cargo/registry");
+        assert_eq!(cargo.dir, data_dir.join("artifact-cache/cargo"));
+        let npm = parse("npm:/home/node/.npm", data_dir).unwrap();
+        assert_eq!(
+            run_args(&[cargo.clone(), npm]),
+            [
+                "--label",
+                "bolt.cache-volumes=cargo,npm",
+                "-v",
+                "/var/lib/bolt/artifact-cache/cargo:/usr/local/cargo/registry",
+                "-v",
+                "/var/lib/bolt/artifact-cache/npm:/home/node/.npm",
+                "-e",
+                "npm_config_cache=/home/node/.npm",
+            ]
+        );
+        assert!(build_args("docker", std::slice::from_ref(&cargo)).is_empty());
+        assert!(parse("maven", data_dir).is_err());
+        assert!(parse("gradle:relative", data_dir).is_err());
+
+        let at = |minutes: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(minutes * 60);
+        let entry = |path: &str, minutes, in_use| Entry {
+            path: PathBuf::from(path),
+            size: 100,
+            last_used: at(minutes),
+            in_use,
+        };
+        let entries = vec![
+            entry("npm/new", 500, false),
+            entry("cargo/serde", 10, true),
+            entry("npm/old", 20, false),
+            entry("cargo/tokio", 400, true),
+            entry("npm/older", 5, false),
+        ];
+        // 500 bytes, 250 allowed; cargo/tokio is used by a running container
+        let evicted = select(entries, 500, 250, at(300));
+        let paths: Vec<_> = evicted.iter().map(|e| e.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["npm/older", "cargo/serde", "npm/old"]);
+    }
+}
@@ -0,0 +1,616 @@
+//! Named builder instances (`bolt builder create/ls/rm`, `bolt build --builder`)
+//!
+//! Each builder has its own build cache index under
+//! `<data_dir>/builds/builders/<name>/cache`, which maps build contexts to
+//! the images built from them. Its `storage` quota (or `[build_cache]
+//! max_size`) bounds the builder's layer cache, never the images builds
+//! produced: with Docker that is the BuildKit instance's cache, with Podman
+//! the untagged intermediate images in the store, which all Podman builders
+//! share.
+//!
+//! A builder runs at most `concurrency` builds at once; further builds wait
+//! for a slot. Its `cpus` and `memory` caps become the cgroup limits of the
+//! build: Podman applies them to each build, while with Docker the builder is
+//! a BuildKit instance (`docker buildx create --name bolt-<name>`) running in
+//! its own container with those limits, its own layer cache and a garbage
+//! collection limit of `storage`.
+//!
+//! Builders are kept in `<data_dir>/builds/builders.json`. The `default`
+//! builder always exists, has no limits and keeps the original cache.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+pub const DEFAULT_BUILDER: &str = "default";
+const STORE_FILE: &str = "builders.json";
+const NAME_MAX: usize = 32;
+const SLOT_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Builder {
+    pub name: String,
+    /// Builds that may run at once
+    pub concurrency: u32,
+    pub cpus: Option<f64>,
+    /// Bytes
+    pub memory: Option<u64>,
+    /// Cache size in bytes; `[build_cache] max_size` when unset
+    pub storage: Option<u64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Builder {
+    /// The `default` builder: no limits, the original cache
+    pub fn default_builder() -> Self {
+        Self {
+            name: DEFAULT_BUILDER.to_string(),
+            concurrency: u32::MAX,
+            cpus: None,
+            memory: None,
+            storage: None,
+            created_at: DateTime::<Utc>::UNIX_EPOCH,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_BUILDER
+    }
+
+    /// Name of the builder's BuildKit instance with Docker
+    fn buildx_name(&self) -> String {
+        format!("bolt-{}", self.name)
+    }
+
+    /// The BuildKit instance whose cache the builder's builds use
+    fn buildx_instance(&self) -> String {
+        if self.is_default() {
+            DEFAULT_BUILDER.to_string()
+        } else {
+            self.buildx_name()
+        }
+    }
+
+    /// Where the builder's build cache index lives
+    pub fn cache_dir(&self, data_dir: &Path) -> PathBuf {
+        if self.is_default() {
+            data_dir.join("builds").join("cache")
+        } else {
+            builder_dir(data_dir, &self.name).join("cache")
+        }
+    }
+
+    /// The build command: `build` or `buildx build` with the builder's limits
+    pub fn build_args(&self, runtime: &str) -> Vec<String> {
+        if self.is_default() {
+            return vec!["build".to_string()];
+        }
+        if runtime == "docker" {
+            return vec![
+                "buildx".to_string(),
+                "build".to_string(),
+                "--builder".to_string(),
+                self.buildx_name(),
+                "--load".to_string(),
+            ];
+        }
+        let mut args = vec!["build".to_string()];
+        if let Some(cpus) = self.cpus {
+            args.push(format!("--cpu-period={}", 100_000));
+            args.push(format!("--cpu-quota={}", (cpus * 100_000.0).round() as u64));
+        }
+        if let Some(memory) = self.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        args
+    }
+
+    /// `docker buildx create` arguments for the builder's BuildKit instance
+    fn buildx_create_args(&self, config: &Path) -> Vec<String> {
+        let mut args = vec![
+            "buildx".to_string(),
+            "create".to_string(),
+            "--name".to_string(),
+            self.buildx_name(),
+            "--driver".to_string(),
+            "docker-container".to_string(),
+            "--buildkitd-config".to_string(),
+            config.display().to_string(),
+        ];
+        if let Some(cpus) = self.cpus {
+            args.push("--driver-opt".to_string());
+            args.push(format!(
+                "cpu-period=100000,cpu-quota={}",
+                (cpus * 100_000.0).round() as u64
+            ));
+        }
+        if let Some(memory) = self.memory {
+            args.push("--driver-opt".to_string());
+            args.push(format!("memory={}", memory));
+        }
+        args
+    }
+
+    /// buildkitd.toml: parallel steps and layer cache garbage collection
+    fn buildkitd_config(&self) -> String {
+        let mut config = format!(
+            "[worker.oci]\n  max-parallelism = {}\n",
+            self.concurrency.max(1)
+        );
+        if let Some(storage) = self.storage {
+            config.push_str(&format!("  gc = true\n  gckeepstorage = {}\n", storage));
+        }
+        config
+    }
+}
+
+/// `<data_dir>/builds/builders.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuilderStore {
+    #[serde(default)]
+    pub builders: BTreeMap<String, Builder>,
+}
+
+impl BuilderStore {
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = store_path(data_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        let path = store_path(data_dir);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Builder> {
+        if name == DEFAULT_BUILDER {
+            return Ok(Builder::default_builder());
+        }
+        self.builders.get(name).cloned().ok_or_else(|| {
+            anyhow!(
+                "Builder '{}' does not exist (create it with `bolt builder create {}`)",
+                name,
+                name
+            )
+        })
+    }
+
+    /// Every builder, `default` first
+    pub fn list(&self) -> Vec<Builder> {
+        std::iter::once(Builder::default_builder())
+            .chain(self.builders.values().cloned())
+            .collect()
+    }
+}
+
+fn store_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("builds").join(STORE_FILE)
+}
+
+fn builder_dir(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir.join("builds").join("builders").join(name)
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= NAME_MAX
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid builder name '{}': use up to {} lowercase letters, digits, '-' and '_'",
+            name,
+            NAME_MAX
+        ));
+    }
+    if name == DEFAULT_BUILDER {
+        return Err(anyhow!("The default builder always exists"));
+    }
+    Ok(())
+}
+
+/// Limits of a new builder
+#[derive(Debug, Clone, Default)]
+pub struct BuilderLimits {
+    pub concurrency: Option<u32>,
+    pub cpus: Option<f64>,
+    pub memory: Option<u64>,
+    pub storage: Option<u64>,
+}
+
+/// Create a builder; with Docker this also starts its BuildKit instance
+pub async fn create(
+    runtime: &str,
+    data_dir: &Path,
+    name: &str,
+    limits: BuilderLimits,
+) -> Result<Builder> {
+    validate_name(name)?;
+    if limits.concurrency == Some(0) || limits.cpus.is_some_and(|c| c <= 0.0) {
+        return Err(anyhow!("Builder limits must be positive"));
+    }
+    let mut store = BuilderStore::load(data_dir)?;
+    if store.builders.contains_key(name) {
+        return Err(anyhow!("Builder '{}' already exists", name));
+    }
+    let builder = Builder {
+        name: name.to_string(),
+        concurrency: limits.concurrency.unwrap_or(1),
+        cpus: limits.cpus,
+        memory: limits.memory,
+        storage: limits.storage,
+        created_at: Utc::now(),
+    };
+    let dir = builder_dir(data_dir, name);
+    std::fs::create_dir_all(builder.cache_dir(data_dir))
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    if runtime == "docker" {
+        let config = dir.join("buildkitd.toml");
+        std::fs::write(&config, builder.buildkitd_config())?;
+        run(runtime, &builder.buildx_create_args(&config)).await?;
+    }
+    store.builders.insert(name.to_string(), builder.clone());
+    store.save(data_dir)?;
+    info!("🏗️  Builder {} ready", name);
+    Ok(builder)
+}
+
+/// Remove a builder's BuildKit instance, with its layer cache, and record
+pub async fn remove(runtime: &str, data_dir: &Path, name: &str) -> Result<Builder> {
+    if name == DEFAULT_BUILDER {
+        return Err(anyhow!("The default builder can't be removed"));
+    }
+    let mut store = BuilderStore::load(data_dir)?;
+    let builder = store.get(name)?;
+    if runtime == "docker" {
+        let output = AsyncCommand::new(runtime)
+            .args(["buildx", "rm", &builder.buildx_name()])
+            .output()
+            .await?;
+        if !output.status.success() {
+            debug!(
+                "buildx rm {}: {}",
+                builder.buildx_name(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    store.builders.remove(name);
+    store.save(data_dir)?;
+    let _ = std::fs::remove_dir_all(builder_dir(data_dir, name));
+    info!("🗑️  Builder {} removed", name);
+    Ok(builder)
+}
+
+/// An untagged intermediate image in Podman's store
+#[derive(Debug, Clone, Deserialize)]
+struct LayerImage {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Size")]
+    size: u64,
+    /// Unix time
+    #[serde(rename = "Created")]
+    created: i64,
+}
+
+async fn layer_images(runtime: &str) -> Result<Vec<LayerImage>> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "images",
+            "--all",
+            "--filter",
+            "dangling=true",
+            "--format",
+            "json",
+        ])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to list intermediate images: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&output.stdout).context("Failed to parse the image list")
+}
+
+/// `Total:` of `docker buildx du` and `docker buildx prune`
+fn buildx_total(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("Total:"))
+        .and_then(|size| super::cache::parse_size(size).ok())
+}
+
+async fn buildx(runtime: &str, args: &[String]) -> Result<String> {
+    let output = AsyncCommand::new(runtime)
+        .arg("buildx")
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} buildx {} failed: {}",
+            runtime,
+            args.first().map(String::as_str).unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Bytes the builder's layer cache takes
+pub async fn layer_cache_size(runtime: &str, builder: &Builder) -> Result<u64> {
+    if runtime == "docker" {
+        let output = buildx(
+            runtime,
+            &[
+                "du".to_string(),
+                "--builder".to_string(),
+                builder.buildx_instance(),
+            ],
+        )
+        .await?;
+        return Ok(buildx_total(&output).unwrap_or(0));
+    }
+    Ok(layer_images(runtime).await?.iter().map(|i| i.size).sum())
+}
+
+/// Prune the builder's layer cache: what went unused for longer than
+/// `until`, then the oldest layers until at most `keep_storage` bytes
+/// remain. Built images are left alone. Returns the bytes reclaimed.
+pub async fn prune_layer_cache(
+    runtime: &str,
+    builder: &Builder,
+    keep_storage: u64,
+    until: Option<Duration>,
+) -> Result<u64> {
+    if runtime == "docker" {
+        let prune = vec![
+            "prune".to_string(),
+            "--builder".to_string(),
+            builder.buildx_instance(),
+            "--force".to_string(),
+        ];
+        let mut reclaimed = 0;
+        if let Some(until) = until {
+            let mut args = prune.clone();
+            args.extend([
+                "--filter".to_string(),
+                format!("until={}s", until.as_secs()),
+            ]);
+            reclaimed += buildx_total(&buildx(runtime, &args).await?).unwrap_or(0);
+        }
+        if keep_storage != u64::MAX {
+            let mut args = prune;
+            args.extend(["--keep-storage".to_string(), keep_storage.to_string()]);
+            reclaimed += buildx_total(&buildx(runtime, &args).await?).unwrap_or(0);
+        }
+        return Ok(reclaimed);
+    }
+
+    let mut images = layer_images(runtime).await?;
+    images.sort_by_key(|i| i.created);
+    let cutoff = until.map(|d| Utc::now().timestamp() - d.as_secs() as i64);
+    let mut remaining: u64 = images.iter().map(|i| i.size).sum();
+    let mut reclaimed = 0;
+    for image in images {
+        let expired = cutoff.is_some_and(|c| image.created < c);
+        if !expired && remaining <= keep_storage {
+            continue;
+        }
+        let output = AsyncCommand::new(runtime)
+            .args(["rmi", &image.id])
+            .output()
+            .await?;
+        if output.status.success() {
+            remaining = remaining.saturating_sub(image.size);
+            reclaimed += image.size;
+        } else {
+            // A layer of an image that still exists, or used by a running build
+            debug!(
+                "Keeping layer {}: {}",
+                image.id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    Ok(reclaimed)
+}
+
+/// A build slot on a builder, free again when dropped
+#[derive(Debug)]
+pub struct BuildSlot {
+    _lock: Option<File>,
+}
+
+/// Wait until the builder runs fewer than `concurrency` builds
+pub async fn acquire_slot(data_dir: &Path, builder: &Builder) -> Result<BuildSlot> {
+    if builder.is_default() {
+        return Ok(BuildSlot { _lock: None });
+    }
+    let dir = builder_dir(data_dir, &builder.name).join("slots");
+    std::fs::create_dir_all(&dir)?;
+    let mut waiting = false;
+    loop {
+        for slot in 0..builder.concurrency {
+            let file = File::create(dir.join(format!("{}.lock", slot)))?;
+            // SAFETY: flock only reads the descriptor, which `file` keeps open
+            let locked =
+                unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+            if locked {
+                return Ok(BuildSlot { _lock: Some(file) });
+            }
+        }
+        if !waiting {
+            info!(
+                "⏳ Builder {} is running {} build(s), waiting for a free slot",
+                builder.name, builder.concurrency
+            );
+            waiting = true;
+        }
+        tokio::time::sleep(SLOT_POLL).await;
+    }
+}
+
+async fn run(runtime: &str, args: &[String]) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} {} failed: {}",
+        runtime,
+        args.first().map(String::as_str).unwrap_or_default(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_builders_apart_and_limits_concurrency() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = BuilderLimits {
+            concurrency: Some(1),
+            cpus: Some(2.5),
+            memory: Some(4 << 30),
+            storage: Some(10 << 30),
+        };
+        let ci = create("podman", dir.path(), "ci", limits).await.unwrap();
+        assert!(
+            create("podman", dir.path(), "ci", BuilderLimits::default())
+                .await
+                .is_err()
+        );
+        assert!(
+            create("podman", dir.path(), "default", BuilderLimits::default())
+                .await
+                .is_err()
+        );
+        assert!(
+            create("podman", dir.path(), "CI", BuilderLimits::default())
+                .await
+                .is_err()
+        );
+
+        let store = BuilderStore::load(dir.path()).unwrap();
+        let names: Vec<String> = store.list().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, vec!["default", "ci"]);
+        assert_ne!(
+            ci.cache_dir(dir.path()),
+            store.get(DEFAULT_BUILDER).unwrap().cache_dir(dir.path())
+        );
+        assert_eq!(
+            ci.build_args("podman"),
+            vec![
+                "build",
+                "--cpu-period=100000",
+                "--cpu-quota=250000",
+                "--memory=4294967296"
+            ]
+        );
+        assert_eq!(
+            ci.build_args("docker"),
+            vec!["buildx", "build", "--builder", "bolt-ci", "--load"]
+        );
+        assert!(
+            ci.buildkitd_config()
+                .contains("gckeepstorage = 10737418240")
+        );
+
+        let slot = acquire_slot(dir.path(), &ci).await.unwrap();
+        let second =
+            tokio::time::timeout(Duration::from_millis(200), acquire_slot(dir.path(), &ci)).await;
+        assert!(second.is_err());
+        drop(slot);
+        acquire_slot(dir.path(), &ci).await.unwrap();
+
+        remove("podman", dir.path(), "ci").await.unwrap();
+        assert!(BuilderStore::load(dir.path()).unwrap().get("ci").is_err());
+    }
+
+    #[tokio::test]
+    async fn prunes_the_oldest_layers_and_never_built_images() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("rmi.log");
+        let now = Utc::now().timestamp();
+        let images = serde_json::json!([
+            { "Id": "new", "Size": 300, "Created": now },
+            { "Id": "old", "Size": 100, "Created": now - 7200 },
+            { "Id": "parent", "Size": 200, "Created": now - 3600 },
+        ]);
+        let runtime = dir.path().join("podman");
+        std::fs::write(
+            &runtime,
+            format!(
+                "#!/bin/sh\ncase \"$1\" in\n  images) echo '{}' ;;\n  rmi) [ \"$2\" = parent ] && exit 2; echo \"$2\" >> {} ;;\nesac\n",
+                images,
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&runtime, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let runtime = runtime.to_string_lossy();
+        let builder = Builder::default_builder();
+
+        assert_eq!(layer_cache_size(&runtime, &builder).await.unwrap(), 600);
+        // "parent" still has children, so "new" goes too
+        assert_eq!(
+            prune_layer_cache(&runtime, &builder, 250, None)
+                .await
+                .unwrap(),
+            400
+        );
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "old\nnew\n");
+
+        std::fs::remove_file(&log).unwrap();
+        let until = Some(Duration::from_secs(5400));
+        assert_eq!(
+            prune_layer_cache(&runtime, &builder, u64::MAX, until)
+                .await
+                .unwrap(),
+            100
+        );
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "old\n");
+
+        assert_eq!(
+            buildx_total("ID\tRECLAIMABLE\tSIZE\nabc\ttrue\t1.5kB\nTotal:\t2KB\n"),
+            Some(2048)
+        );
+        assert_eq!(buildx_total(""), None);
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+/// Image built from a given build context, reused when the context is unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildCacheEntry {
+    pub key: String,
+    pub image_id: String,
+    pub tags: Vec<String>,
+    /// Image size as reported by the container runtime
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    pub hits: u64,
+}
+
+/// Lifetime cache hit/miss counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    entries: BTreeMap<String, BuildCacheEntry>,
+    #[serde(default)]
+    counters: CacheCounters,
+}
+
+/// What a single build got out of the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildStats {
+    pub image_id: String,
+    /// The whole build was skipped because the context was unchanged
+    pub cache_hit: bool,
+    /// Dockerfile steps run by the builder, and how many of those it served from its layer cache
+    pub steps_total: usize,
+    pub steps_cached: usize,
+    /// Layer cache bytes pruned to stay under the size limit
+    pub pruned: u64,
+    /// Size of the builder's layer cache
+    pub cache_size: u64,
+    pub cache_limit: u64,
+    pub counters: CacheCounters,
+}
+
+impl std::fmt::Display for BuildStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.cache_hit {
+            write!(f, "hit, reused image {}", short_id(&self.image_id))?;
+        } else if self.steps_total > 0 {
+            write!(
+                f,
+                "miss, {}/{} steps cached ({:.0}%)",
+                self.steps_cached,
+                self.steps_total,
+                self.steps_cached as f64 * 100.0 / self.steps_total as f64
+            )?;
+        } else {
+            write!(f, "miss")?;
+        }
+        if self.pruned > 0 {
+            write!(f, ", {} of layers pruned", format_size(self.pruned))?;
+        }
+        write!(
+            f,
+            "; {} of {} used, {} hits / {} misses overall",
+            format_size(self.cache_size),
+            format_size(self.cache_limit),
+            self.counters.hits,
+            self.counters.misses
+        )
+    }
+}
+
+/// Index of the images built from each build context, so an unchanged
+/// context reuses its image. Entries go once their image is gone; the size
+/// limit is for the builder's layer cache. Stored as `index.json` under the
+/// cache root.
+#[derive(Debug)]
+pub struct BuildCache {
+    root: PathBuf,
+    max_size: u64,
+    index: CacheIndex,
+}
+
+impl BuildCache {
+    pub fn open(root: PathBuf, max_size: u64) -> Result<Self> {
+        std::fs::create_dir_all(&root).context("Failed to create build cache directory")?;
+
+        let index_path = root.join("index.json");
+        let index = if index_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&index_path)?)
+                .context("Corrupt build cache index")?
+        } else {
+            CacheIndex::default()
+        };
+
+        Ok(Self {
+            root,
+            max_size,
+            index,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let tmp = self.root.join("index.json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.index)?)?;
+        std::fs::rename(tmp, self.root.join("index.json"))?;
+        Ok(())
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    pub fn counters(&self) -> &CacheCounters {
+        &self.index.counters
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &BuildCacheEntry> {
+        self.index.entries.values()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BuildCacheEntry> {
+        self.index.entries.get(key)
+    }
+
+    /// Count a hit and mark the entry as recently used
+    pub fn record_hit(&mut self, key: &str, tag: Option<&str>) {
+        if let Some(entry) = self.index.entries.get_mut(key) {
+            entry.last_used = Utc::now();
+            entry.hits += 1;
+            if let Some(tag) = tag {
+                if !entry.tags.iter().any(|t| t == tag) {
+                    entry.tags.push(tag.to_string());
+                }
+            }
+            self.index.counters.hits += 1;
+        }
+    }
+
+    pub fn record_miss(&mut self) {
+        self.index.counters.misses += 1;
+    }
+
+    pub fn insert(&mut self, key: String, image_id: String, tag: Option<&str>, size_bytes: u64) {
+        let now = Utc::now();
+        self.index.entries.insert(
+            key.clone(),
+            BuildCacheEntry {
+                key,
+                image_id,
+                tags: tag.map(|t| vec![t.to_string()]).unwrap_or_default(),
+                size_bytes,
+                created_at: now,
+                last_used: now,
+                hits: 0,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<BuildCacheEntry> {
+        self.index.entries.remove(key)
+    }
+}
+
+/// Cache key for a build: the Dockerfile plus every file in the context.
+/// Files excluded by `.dockerignore` still count, so at worst an edit to
+/// one of them causes a needless rebuild.
+pub fn context_key(context: &Path, dockerfile: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+
+    hasher.update(b"dockerfile\0");
+    hasher.update(
+        std::fs::read(dockerfile)
+            .with_context(|| format!("Failed to read Dockerfile {:?}", dockerfile))?,
+    );
+
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(context)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+    {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        files.push(entry.into_path());
+    }
+
+    for path in files {
+        let relative = path.strip_prefix(context).unwrap_or(&path);
+        let metadata = std::fs::symlink_metadata(&path)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            hasher.update(metadata.permissions().mode().to_le_bytes());
+        }
+
+        if metadata.file_type().is_symlink() {
+            hasher.update(std::fs::read_link(&path)?.to_string_lossy().as_bytes());
+        } else {
+            let mut file = std::fs::File::open(&path)?;
+            std::io::copy(&mut file, &mut hasher)?;
+        }
+        hasher.update(b"\0");
+    }
+
+    let key = hex::encode(hasher.finalize());
+    debug!("Build context {:?} has cache key {}", context, key);
+    Ok(key)
+}
+
+/// Count Dockerfile steps and layer cache reuse in builder output
+/// (podman/buildah, classic docker and BuildKit formats)
+pub fn parse_step_stats(output: &str) -> (usize, usize) {
+    let mut total = 0;
+    let mut cached = 0;
+    let mut buildkit_steps = std::collections::BTreeSet::new();
+
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("STEP ") || line.starts_with("Step ") {
+            total += 1;
+        } else if line.contains("Using cache") {
+            cached += 1;
+        } else if let Some(rest) = line.strip_prefix('#') {
+            // BuildKit: "#5 [2/4] RUN ..." then "#5 CACHED"
+            let Some((step, detail)) = rest.split_once(' ') else {
+                continue;
+            };
+            if detail.starts_with('[') && !detail.contains("internal]") {
+                buildkit_steps.insert(step.to_string());
+            } else if detail == "CACHED" {
+                cached += 1;
+            }
+        }
+    }
+
+    (total + buildkit_steps.len(), cached)
+}
+
+/// Parse a size such as `10GB`, `512MB` or a plain byte count
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim().to_uppercase();
+    let split = size
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid size: {}", size))?;
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024u64.pow(2),
+        "G" | "GB" | "GIB" => 1024u64.pow(3),
+        "T" | "TB" | "TIB" => 1024u64.pow(4),
+        other => return Err(anyhow!("Invalid size unit: {}", other)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse a duration such as `72h`, `30m` or `7d`
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| c.is_ascii_alphabetic())
+        .ok_or_else(|| anyhow!("Duration needs a unit (s, m, h, d): {}", duration))?;
+    let (number, unit) = duration.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration: {}", duration))?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        other => return Err(anyhow!("Invalid duration unit: {}", other)),
+    };
+
+    Ok(Duration::from_secs(number * seconds))
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn short_id(image_id: &str) -> &str {
+    let id = image_id.strip_prefix("sha256:").unwrap_or(image_id);
+    &id[..id.len().min(12)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_builder_output() {
+        let podman =
+            "STEP 1/3: FROM alpine\nSTEP 2/3: RUN true\n--> Using cache 1234\nSTEP 3/3: COPY . .\n";
+        assert_eq!(parse_step_stats(podman), (3, 1));
+
+        let buildkit = "#1 [internal] load build definition\n#4 [1/2] FROM docker.io/library/alpine\n#5 [2/2] RUN true\n#5 CACHED\n";
+        assert_eq!(parse_step_stats(buildkit), (2, 1));
+
+        assert_eq!(parse_size("10GB").unwrap(), 10 * 1024u64.pow(3));
+        assert_eq!(
+            parse_duration("72h").unwrap(),
+            Duration::from_secs(72 * 3600)
+        );
+    }
+}
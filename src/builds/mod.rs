@@ -1,9 +1,16 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use tracing::{debug, info, warn};
 
+pub mod artifacts;
+pub mod builders;
+pub mod cache;
+pub mod nix;
+pub mod optimize;
+pub mod reproducible;
+
 /// Reproducible Build System - Our NixOS killer feature
 ///
 /// Features:
@@ -73,4 +80,63 @@ impl BuildSystem {
             reproducible: true,
         })
     }
+
+    /// Import an image built by Nix, reusing layers already in the store, and
+    /// load it into `runtime` as `tag` (default: the name Nix gave it)
+    pub async fn import_nix_image(
+        &mut self,
+        flake_ref: &str,
+        runtime: &str,
+        tag: Option<&str>,
+    ) -> Result<BuildResult> {
+        let importer = nix::NixImageImporter::new(self.store_path.clone())?;
+        let image = importer.import_flake(flake_ref).await?;
+
+        let tag = tag
+            .map(str::to_string)
+            .or_else(|| image.repo_tags.first().cloned())
+            .ok_or_else(|| anyhow!("{} has no image name; pass --tag", flake_ref))?;
+        importer.load(runtime, &image, &tag).await?;
+
+        let result = BuildResult {
+            id: image.image_digest.clone(),
+            inputs: vec![BuildInput {
+                name: flake_ref.to_string(),
+                hash: image.config_digest.clone(),
+                path: image.out_path.to_string_lossy().to_string(),
+            }],
+            outputs: image
+                .layers
+                .iter()
+                .map(|layer| BuildOutput {
+                    name: layer.digest.clone(),
+                    hash: layer.digest.clone(),
+                    path: importer.blob_path(&layer.digest).to_string_lossy().to_string(),
+                    size: layer.size,
+                })
+                .collect(),
+            build_hash: image.image_digest.clone(),
+            reproducible: image.reproducible,
+        };
+
+        debug!("Nix build result: {:?}", result);
+        self.build_cache.insert(flake_ref.to_string(), result.clone());
+        Ok(result)
+    }
+
+    /// File-level diff between the last imports of two flake references
+    pub async fn diff_nix_images(&self, old_ref: &str, new_ref: &str) -> Result<nix::ImageDiff> {
+        let importer = nix::NixImageImporter::new(self.store_path.clone())?;
+
+        let old = match importer.load_image(old_ref)? {
+            Some(image) => image,
+            None => importer.import_flake(old_ref).await?,
+        };
+        let new = match importer.load_image(new_ref)? {
+            Some(image) => image,
+            None => importer.import_flake(new_ref).await?,
+        };
+
+        importer.diff(&old, &new)
+    }
 }
@@ -0,0 +1,613 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::optimize::{self, ArchiveLayer};
+
+/// Importer for images produced by Nix (`dockerTools.buildLayeredImage`,
+/// `streamLayeredImage` or any derivation yielding a docker-archive).
+///
+/// Layers are stored content-addressed under `<store>/blobs/sha256`, so two
+/// images sharing store paths share layer blobs on disk.
+#[derive(Debug)]
+pub struct NixImageImporter {
+    pub store_path: PathBuf,
+}
+
+/// Metadata recorded for every imported Nix image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NixImage {
+    pub flake_ref: String,
+    pub out_path: PathBuf,
+    pub repo_tags: Vec<String>,
+    pub config_digest: String,
+    pub layers: Vec<NixLayer>,
+    /// sha256 over the config digest and ordered layer digests
+    pub image_digest: String,
+    pub imported_at: chrono::DateTime<chrono::Utc>,
+    /// False when the same Nix output path produced a different digest than
+    /// the previous import
+    #[serde(default = "default_reproducible")]
+    pub reproducible: bool,
+}
+
+fn default_reproducible() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NixLayer {
+    pub digest: String,
+    pub size: u64,
+    /// True when the blob was already present in the store
+    pub deduplicated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub digest: Option<String>,
+    pub size: u64,
+    pub mode: u32,
+    pub link_target: Option<String>,
+}
+
+/// File-level difference between two imported images
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl ImageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DockerArchiveManifest {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+impl NixImageImporter {
+    pub fn new(store_path: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(store_path.join("blobs").join("sha256"))
+            .context("Failed to create blob store directory")?;
+        std::fs::create_dir_all(store_path.join("nix"))
+            .context("Failed to create Nix image metadata directory")?;
+
+        Ok(Self { store_path })
+    }
+
+    /// Build `flake_ref` with Nix and import the resulting image
+    pub async fn import_flake(&self, flake_ref: &str) -> Result<NixImage> {
+        info!("❄️  Building Nix image: {}", flake_ref);
+
+        let output = AsyncCommand::new("nix")
+            .args(["build", "--no-link", "--print-out-paths", flake_ref])
+            .output()
+            .await
+            .context("Failed to execute nix (is Nix installed?)")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("nix build failed for {}: {}", flake_ref, stderr));
+        }
+
+        let out_path = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .last()
+            .map(|l| PathBuf::from(l.trim()))
+            .ok_or_else(|| anyhow!("nix build did not report an output path"))?;
+
+        debug!("Nix output path: {:?}", out_path);
+
+        let archive_path = self.materialize_archive(&out_path).await?;
+        let image = self.import_archive(flake_ref, &out_path, &archive_path)?;
+
+        if archive_path != out_path {
+            let _ = std::fs::remove_file(&archive_path);
+        }
+
+        Ok(image)
+    }
+
+    /// `streamLayeredImage` yields a script that writes the archive to stdout;
+    /// everything else is expected to already be a (possibly gzipped) tarball.
+    async fn materialize_archive(&self, out_path: &Path) -> Result<PathBuf> {
+        if is_tar_archive(out_path)? {
+            return Ok(out_path.to_path_buf());
+        }
+
+        info!("  📜 Output is a stream script, materializing archive");
+        let tmp_path = self
+            .store_path
+            .join("nix")
+            .join(format!("stream-{}.tar", uuid::Uuid::new_v4()));
+
+        // Images can be gigabytes, so write the stream straight to disk
+        let output = AsyncCommand::new(out_path)
+            .stdout(File::create(&tmp_path)?)
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute stream script {:?}", out_path));
+
+        match output {
+            Ok(output) if output.status.success() => Ok(tmp_path),
+            Ok(output) => {
+                let _ = std::fs::remove_file(&tmp_path);
+                Err(anyhow!(
+                    "Image stream script failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ))
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Import a docker-archive produced by Nix into the content-addressed store
+    pub fn import_archive(
+        &self,
+        flake_ref: &str,
+        out_path: &Path,
+        archive_path: &Path,
+    ) -> Result<NixImage> {
+        info!("📥 Importing docker-archive: {:?}", archive_path);
+
+        let mut manifest: Option<Vec<DockerArchiveManifest>> = None;
+        let mut config_bytes: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut layer_digests: HashMap<String, (String, u64, bool)> = HashMap::new();
+
+        let mut archive = tar::Archive::new(open_maybe_gzip(archive_path)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let entry_path = entry.path()?.to_string_lossy().to_string();
+
+            if entry_path == "manifest.json" {
+                manifest = Some(
+                    serde_json::from_reader(&mut entry)
+                        .context("Invalid manifest.json in docker-archive")?,
+                );
+            } else if entry_path.ends_with(".json") {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf)?;
+                config_bytes.insert(entry_path, buf);
+            } else {
+                let (digest, size, deduplicated) = self.store_blob(&mut entry)?;
+                layer_digests.insert(entry_path, (digest, size, deduplicated));
+            }
+        }
+
+        let manifest = manifest
+            .and_then(|m| m.into_iter().next())
+            .ok_or_else(|| anyhow!("docker-archive has no manifest entry"))?;
+
+        let config = config_bytes
+            .get(&manifest.config)
+            .ok_or_else(|| anyhow!("Image config {} missing from archive", manifest.config))?;
+        let (config_digest, _, _) = self.store_blob(&mut config.as_slice())?;
+
+        let mut layers = Vec::new();
+        for layer_path in &manifest.layers {
+            let (digest, size, deduplicated) = layer_digests
+                .get(layer_path)
+                .cloned()
+                .ok_or_else(|| anyhow!("Layer {} missing from archive", layer_path))?;
+            if deduplicated {
+                debug!("  ♻️  Layer {} already in store", digest);
+            }
+            layers.push(NixLayer {
+                digest,
+                size,
+                deduplicated,
+            });
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(config_digest.as_bytes());
+        for layer in &layers {
+            hasher.update(layer.digest.as_bytes());
+        }
+        let image_digest = format!("sha256:{:x}", hasher.finalize());
+
+        let mut image = NixImage {
+            flake_ref: flake_ref.to_string(),
+            out_path: out_path.to_path_buf(),
+            repo_tags: manifest.repo_tags.unwrap_or_default(),
+            config_digest,
+            layers,
+            image_digest,
+            imported_at: chrono::Utc::now(),
+            reproducible: true,
+        };
+
+        if let Some(previous) = self.load_image(flake_ref)? {
+            if previous.out_path == image.out_path && previous.image_digest != image.image_digest {
+                image.reproducible = false;
+                warn!(
+                    "⚠️  {} is not reproducible: {} rebuilt to {} (was {})",
+                    flake_ref,
+                    image.out_path.display(),
+                    image.image_digest,
+                    previous.image_digest
+                );
+            }
+        }
+
+        self.save_image(&image)?;
+
+        let reused = image.layers.iter().filter(|l| l.deduplicated).count();
+        info!(
+            "✅ Imported {} ({} layers, {} reused from store)",
+            image.image_digest,
+            image.layers.len(),
+            reused
+        );
+
+        Ok(image)
+    }
+
+    /// Load an imported image into `runtime` (podman or docker) as `tag`,
+    /// assembled from the blobs in the store
+    pub async fn load(&self, runtime: &str, image: &NixImage, tag: &str) -> Result<()> {
+        let config = std::fs::read(self.blob_path(&image.config_digest))
+            .context("Image config missing from blob store")?;
+        let layers = image
+            .layers
+            .iter()
+            .map(|layer| {
+                let path = self.blob_path(&layer.digest);
+                let media_type = if is_gzip(&path)? {
+                    "application/vnd.oci.image.layer.v1.tar+gzip"
+                } else {
+                    "application/vnd.oci.image.layer.v1.tar"
+                };
+                Ok(ArchiveLayer {
+                    path,
+                    media_type,
+                    digest: layer.digest.clone(),
+                    size: layer.size,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let archive = self
+            .store_path
+            .join("nix")
+            .join(format!("load-{}.tar", uuid::Uuid::new_v4()));
+        let result = match optimize::write_oci_archive(&archive, tag, &config, &layers) {
+            Ok(()) => optimize::load_and_tag(runtime, &archive, tag).await,
+            Err(e) => Err(e),
+        };
+        let _ = std::fs::remove_file(&archive);
+        result?;
+
+        info!(
+            "🏷️  Loaded {} into {} as {}",
+            image.image_digest, runtime, tag
+        );
+        Ok(())
+    }
+
+    /// Load the most recent import recorded for a flake reference
+    pub fn load_image(&self, flake_ref: &str) -> Result<Option<NixImage>> {
+        let path = self.metadata_path(flake_ref);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    fn save_image(&self, image: &NixImage) -> Result<()> {
+        let content = serde_json::to_string_pretty(image)?;
+        std::fs::write(self.metadata_path(&image.flake_ref), content)?;
+        Ok(())
+    }
+
+    fn metadata_path(&self, flake_ref: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(flake_ref.as_bytes());
+        self.store_path
+            .join("nix")
+            .join(format!("{:x}.json", hasher.finalize()))
+    }
+
+    pub fn blob_path(&self, digest: &str) -> PathBuf {
+        let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+        self.store_path.join("blobs").join("sha256").join(hex)
+    }
+
+    /// Stream a blob into the store, hashing as we go; returns (digest, size, already_present)
+    fn store_blob<R: Read>(&self, reader: &mut R) -> Result<(String, u64, bool)> {
+        let tmp_path = self
+            .store_path
+            .join("blobs")
+            .join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+        let mut tmp = File::create(&tmp_path)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut buf = [0u8; 64 * 1024];
+
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            tmp.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+
+        let digest = format!("sha256:{:x}", hasher.finalize());
+        let final_path = self.blob_path(&digest);
+
+        if final_path.exists() {
+            std::fs::remove_file(&tmp_path)?;
+            Ok((digest, size, true))
+        } else {
+            std::fs::rename(&tmp_path, &final_path)?;
+            Ok((digest, size, false))
+        }
+    }
+
+    /// Flattened file index of an image, applying layers in order (whiteouts honoured)
+    pub fn file_index(&self, image: &NixImage) -> Result<BTreeMap<String, FileEntry>> {
+        let mut index = BTreeMap::new();
+
+        for layer in &image.layers {
+            let mut archive = tar::Archive::new(open_maybe_gzip(&self.blob_path(&layer.digest))?);
+
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry
+                    .path()?
+                    .to_string_lossy()
+                    .trim_start_matches("./")
+                    .to_string();
+                let file_name = path.rsplit('/').next().unwrap_or("");
+
+                if let Some(hidden) = file_name.strip_prefix(".wh.") {
+                    let parent = path.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
+                    if hidden == ".wh..opq" {
+                        let prefix = format!("{}/", parent);
+                        index.retain(|k: &String, _| !k.starts_with(&prefix));
+                    } else {
+                        let target = if parent.is_empty() {
+                            hidden.to_string()
+                        } else {
+                            format!("{}/{}", parent, hidden)
+                        };
+                        let prefix = format!("{}/", target);
+                        index.retain(|k: &String, _| k != &target && !k.starts_with(&prefix));
+                    }
+                    continue;
+                }
+
+                let header = entry.header();
+                let mode = header.mode().unwrap_or(0);
+                let size = header.size().unwrap_or(0);
+                let entry_type = header.entry_type();
+
+                let file_entry = if entry_type.is_file() {
+                    let mut hasher = Sha256::new();
+                    std::io::copy(&mut entry, &mut hasher)?;
+                    FileEntry {
+                        digest: Some(format!("sha256:{:x}", hasher.finalize())),
+                        size,
+                        mode,
+                        link_target: None,
+                    }
+                } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+                    FileEntry {
+                        digest: None,
+                        size: 0,
+                        mode,
+                        link_target: entry.link_name()?.map(|l| l.to_string_lossy().to_string()),
+                    }
+                } else {
+                    // Directories and special files only matter through their children
+                    continue;
+                };
+
+                index.insert(path, file_entry);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Compare two imported images at the file level
+    pub fn diff(&self, old: &NixImage, new: &NixImage) -> Result<ImageDiff> {
+        let old_index = self.file_index(old)?;
+        let new_index = self.file_index(new)?;
+        let mut diff = ImageDiff::default();
+
+        for (path, entry) in &new_index {
+            match old_index.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(previous) if previous != entry => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+
+        for path in old_index.keys() {
+            if !new_index.contains_key(path) {
+                diff.removed.push(path.clone());
+            }
+        }
+
+        Ok(diff)
+    }
+}
+
+fn is_tar_archive(path: &Path) -> Result<bool> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut magic = [0u8; 262];
+    let n = file.read(&mut magic)?;
+
+    // gzip magic, or the ustar marker at offset 257
+    Ok((n >= 2 && magic[0] == 0x1f && magic[1] == 0x8b)
+        || (n >= 262 && &magic[257..262] == b"ustar"))
+}
+
+fn is_gzip(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 2];
+    let n = File::open(path)?.read(&mut magic)?;
+    Ok(n == 2 && magic == [0x1f, 0x8b])
+}
+
+fn open_maybe_gzip(path: &Path) -> Result<Box<dyn Read>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+
+    if is_gzip(path)? {
+        Ok(Box::new(flate2::read::GzDecoder::new(BufReader::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A minimal docker-archive with one layer holding `content` at `etc/motd`
+    fn docker_archive(path: &Path, content: &[u8]) {
+        let mut layer = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        layer.append_data(&mut header, "etc/motd", content).unwrap();
+        let layer = layer.into_inner().unwrap();
+
+        let config =
+            br#"{"architecture":"amd64","os":"linux","rootfs":{"type":"layers","diff_ids":[]}}"#;
+        let manifest =
+            br#"[{"Config":"config.json","RepoTags":["motd:latest"],"Layers":["layer/layer.tar"]}]"#;
+
+        let mut archive = tar::Builder::new(File::create(path).unwrap());
+        for (name, data) in [
+            ("config.json", &config[..]),
+            ("layer/layer.tar", &layer[..]),
+            ("manifest.json", &manifest[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append_data(&mut header, name, data).unwrap();
+        }
+        archive.finish().unwrap();
+    }
+
+    #[test]
+    fn test_import_dedups_and_tracks_reproducibility() {
+        let dir = TempDir::new().unwrap();
+        let importer = NixImageImporter::new(dir.path().join("store")).unwrap();
+        let archive = dir.path().join("image.tar");
+        let out_path = Path::new("/nix/store/abc-image.tar");
+
+        docker_archive(&archive, b"hello");
+        let first = importer
+            .import_archive("./flake#image", out_path, &archive)
+            .unwrap();
+        assert!(first.reproducible);
+        assert_eq!(first.repo_tags, vec!["motd:latest"]);
+        assert!(importer.blob_path(&first.layers[0].digest).exists());
+
+        let second = importer
+            .import_archive("./flake#image", out_path, &archive)
+            .unwrap();
+        assert!(second.reproducible);
+        assert!(second.layers[0].deduplicated);
+        assert_eq!(second.image_digest, first.image_digest);
+
+        // Same output path, different content: not reproducible, and remembered as such
+        docker_archive(&archive, b"changed");
+        let third = importer
+            .import_archive("./flake#image", out_path, &archive)
+            .unwrap();
+        assert!(!third.reproducible);
+        assert!(
+            !importer
+                .load_image("./flake#image")
+                .unwrap()
+                .unwrap()
+                .reproducible
+        );
+
+        // A new output path is a different build, not a reproducibility failure
+        docker_archive(&archive, b"hello");
+        let fourth = importer
+            .import_archive(
+                "./flake#image",
+                Path::new("/nix/store/def-image.tar"),
+                &archive,
+            )
+            .unwrap();
+        assert!(fourth.reproducible);
+
+        let diff = importer.diff(&third, &fourth).unwrap();
+        assert_eq!(diff.modified, vec!["etc/motd"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_script_is_written_to_disk() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let importer = NixImageImporter::new(dir.path().join("store")).unwrap();
+        let archive = dir.path().join("image.tar");
+        docker_archive(&archive, b"hello");
+
+        let script = dir.path().join("stream");
+        std::fs::write(&script, format!("#!/bin/sh\ncat '{}'\n", archive.display())).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let materialized = importer.materialize_archive(&script).await.unwrap();
+        assert_ne!(materialized, script);
+        assert_eq!(
+            std::fs::read(&materialized).unwrap(),
+            std::fs::read(&archive).unwrap()
+        );
+
+        std::fs::remove_file(&materialized).unwrap();
+
+        // An archive is used as-is
+        assert_eq!(
+            importer.materialize_archive(&archive).await.unwrap(),
+            archive
+        );
+
+        std::fs::write(&script, "#!/bin/sh\necho broken >&2\nexit 1\n").unwrap();
+        assert!(importer.materialize_archive(&script).await.is_err());
+        let leftovers = std::fs::read_dir(dir.path().join("store").join("nix"))
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("stream-"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,778 @@
+//! Image size optimization (`bolt image optimize`)
+//!
+//! The image is saved from the runtime, its layers are squashed into one
+//! (applying whiteouts and dropping files later layers replace), files
+//! matching the selected strip profiles are left out, and the result is
+//! compressed with zstd and loaded back under a new tag as an OCI archive.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+zstd";
+
+/// Groups of files that images don't need at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StripProfile {
+    /// Man pages, info pages and package documentation (license files are kept)
+    Docs,
+    /// Message catalogs and locale sources
+    Locales,
+    /// Package manager caches, language build caches and /tmp
+    BuildCaches,
+}
+
+impl StripProfile {
+    pub const ALL: [StripProfile; 3] = [Self::Docs, Self::Locales, Self::BuildCaches];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "docs" => Ok(Self::Docs),
+            "locales" => Ok(Self::Locales),
+            "build-caches" => Ok(Self::BuildCaches),
+            other => Err(anyhow!(
+                "Unknown strip profile '{}' (expected docs, locales or build-caches)",
+                other
+            )),
+        }
+    }
+
+    /// Whether a (non-directory) image path belongs to this profile
+    pub fn matches(&self, path: &str) -> bool {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        match self {
+            Self::Docs => {
+                let license = file_name == "copyright"
+                    || file_name.starts_with("LICENSE")
+                    || file_name.starts_with("COPYING");
+                !license
+                    && [
+                        "usr/share/doc/",
+                        "usr/share/man/",
+                        "usr/share/info/",
+                        "usr/share/gtk-doc/",
+                        "usr/local/share/doc/",
+                        "usr/local/share/man/",
+                    ]
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix))
+            }
+            Self::Locales => {
+                (path.starts_with("usr/share/locale/") && file_name != "locale.alias")
+                    || path.starts_with("usr/share/i18n/locales/")
+            }
+            Self::BuildCaches => {
+                [
+                    "var/cache/apt/",
+                    "var/lib/apt/lists/",
+                    "var/cache/dnf/",
+                    "var/cache/yum/",
+                    "var/cache/apk/",
+                    "var/cache/pacman/pkg/",
+                    "root/.cache/",
+                    "root/.npm/",
+                    "root/.cargo/registry/",
+                    "tmp/",
+                    "var/tmp/",
+                ]
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+                    || path.contains("/__pycache__/")
+                    || (path.starts_with("home/") && path.contains("/.cache/"))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizeOptions {
+    /// Tag of the optimized image (default: `<image>-optimized`)
+    pub tag: Option<String>,
+    pub profiles: Vec<StripProfile>,
+    /// zstd compression level
+    pub level: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerReport {
+    pub digest: String,
+    /// Size of the layer as saved by the runtime
+    pub size_bytes: u64,
+    pub files: usize,
+    /// File bytes replaced or deleted by later layers
+    pub shadowed_bytes: u64,
+    /// File bytes left out by the strip profiles
+    pub stripped_bytes: u64,
+    /// File bytes carried into the squashed layer
+    pub kept_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizeReport {
+    pub source: String,
+    pub tag: String,
+    pub profiles: Vec<StripProfile>,
+    pub layers: Vec<LayerReport>,
+    /// Sum of the layer sizes before optimizing
+    pub original_bytes: u64,
+    /// The squashed layer, uncompressed
+    pub squashed_bytes: u64,
+    /// The squashed layer after zstd
+    pub compressed_bytes: u64,
+    pub stripped_files: usize,
+}
+
+/// What the squash needs to know about one tar entry
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EntryInfo {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    Directory,
+    File,
+    /// Symlinks, hard links and special files
+    Other,
+    /// `.wh.<name>`: deletes `<name>` from lower layers
+    Whiteout,
+    /// `.wh..wh..opq`: hides the lower layers' contents of its directory
+    Opaque,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveManifest {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+/// Squash, strip and recompress `image`, loading the result as `options.tag`
+pub async fn optimize(
+    runtime: &str,
+    image: &str,
+    options: &OptimizeOptions,
+    work_dir: &Path,
+) -> Result<OptimizeReport> {
+    let tag = options.tag.clone().unwrap_or_else(|| default_tag(image));
+    std::fs::create_dir_all(work_dir)?;
+    let work = tempfile::Builder::new()
+        .prefix("optimize-")
+        .tempdir_in(work_dir)
+        .context("Failed to create a work directory")?;
+
+    info!("📦 Saving {}", image);
+    let archive = work.path().join("image.tar");
+    save_image(runtime, image, &archive).await?;
+
+    let (config, layers) = unpack_archive(&archive, work.path())?;
+    std::fs::remove_file(&archive)?;
+
+    info!("🗜️  Squashing {} layer(s)", layers.len());
+    let mut entries = Vec::new();
+    for layer in &layers {
+        entries.push(scan_layer(layer)?);
+    }
+    let keep = plan_squash(&entries);
+
+    let squashed = work.path().join("squashed.tar.zst");
+    let (mut layer_reports, squash) = write_squashed(
+        &layers,
+        &entries,
+        &keep,
+        &options.profiles,
+        &squashed,
+        options.level,
+    )?;
+    let diff_ids = config["rootfs"]["diff_ids"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    for (i, report) in layer_reports.iter_mut().enumerate() {
+        report.digest = diff_ids
+            .get(i)
+            .and_then(|d| d.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+    }
+
+    let config = rewrite_config(config, &squash.diff_id, layers.len(), &options.profiles)?;
+    let oci_archive = work.path().join("optimized.tar");
+    write_oci_archive(
+        &oci_archive,
+        &tag,
+        &config,
+        &[ArchiveLayer {
+            path: squashed,
+            media_type: LAYER_MEDIA_TYPE,
+            digest: squash.digest.clone(),
+            size: squash.compressed_size,
+        }],
+    )?;
+
+    info!("📥 Loading {}", tag);
+    load_and_tag(runtime, &oci_archive, &tag).await?;
+
+    Ok(OptimizeReport {
+        source: image.to_string(),
+        tag,
+        profiles: options.profiles.clone(),
+        original_bytes: layer_reports.iter().map(|l| l.size_bytes).sum(),
+        layers: layer_reports,
+        squashed_bytes: squash.size,
+        compressed_bytes: squash.compressed_size,
+        stripped_files: squash.stripped_files,
+    })
+}
+
+pub(crate) async fn save_image(runtime: &str, image: &str, archive: &Path) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .arg("save")
+        .arg("-o")
+        .arg(archive)
+        .arg(image)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to save {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// `repo:tag` -> `repo:tag-optimized`, keeping registry ports intact
+pub fn default_tag(image: &str) -> String {
+    let image = image.split('@').next().unwrap_or(image);
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if name.contains(':') {
+        format!("{}-optimized", image)
+    } else {
+        format!("{}:latest-optimized", image)
+    }
+}
+
+/// Extract the image config and layer tars from a `save` archive, in order
+pub(crate) fn unpack_archive(
+    archive: &Path,
+    dir: &Path,
+) -> Result<(serde_json::Value, Vec<PathBuf>)> {
+    let mut tar = tar::Archive::new(BufReader::new(File::open(archive)?));
+    let mut files = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        let path = dir.join(name.replace('/', "_"));
+        std::io::copy(&mut entry, &mut File::create(&path)?)?;
+        files.push(name);
+    }
+
+    let manifest: Vec<ArchiveManifest> = serde_json::from_reader(
+        File::open(dir.join("manifest.json")).context("Saved image has no manifest.json")?,
+    )
+    .context("Invalid manifest.json in saved image")?;
+    let manifest = manifest
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Saved image has an empty manifest"))?;
+
+    let local = |name: &str| -> Result<PathBuf> {
+        if !files.iter().any(|f| f == name) {
+            return Err(anyhow!("{} is missing from the saved image", name));
+        }
+        Ok(dir.join(name.replace('/', "_")))
+    };
+    let config = serde_json::from_reader(File::open(local(&manifest.config)?)?)
+        .context("Invalid image config")?;
+    let layers = manifest
+        .layers
+        .iter()
+        .map(|l| local(l))
+        .collect::<Result<_>>()?;
+    Ok((config, layers))
+}
+
+/// Open a layer, decompressing gzip or zstd blobs
+pub(crate) fn open_layer(path: &Path) -> Result<Box<dyn Read>> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut magic = [0u8; 4];
+    let n = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let file = BufReader::new(file);
+    if n >= 2 && magic[..2] == [0x1f, 0x8b] {
+        Ok(Box::new(flate2::read::GzDecoder::new(file)))
+    } else if n == 4 && magic == [0x28, 0xb5, 0x2f, 0xfd] {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+pub(crate) fn normalize(path: &Path) -> String {
+    path.to_string_lossy()
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn scan_layer(path: &Path) -> Result<Vec<EntryInfo>> {
+    let mut archive = tar::Archive::new(open_layer(path)?);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = normalize(&entry.path()?);
+        let file_name = path.rsplit('/').next().unwrap_or("");
+        let entry_type = entry.header().entry_type();
+        let kind = if file_name == ".wh..wh..opq" {
+            EntryKind::Opaque
+        } else if file_name.starts_with(".wh.") {
+            EntryKind::Whiteout
+        } else if entry_type.is_dir() {
+            EntryKind::Directory
+        } else if entry_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        entries.push(EntryInfo {
+            path,
+            kind,
+            size: entry.header().size().unwrap_or(0),
+        });
+    }
+    Ok(entries)
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map(|(p, _)| p).unwrap_or("")
+}
+
+/// Decide which entries of each layer survive in the squashed image: those
+/// no later layer replaces, deletes or hides. Whiteouts never survive.
+pub(crate) fn plan_squash(layers: &[Vec<EntryInfo>]) -> Vec<Vec<bool>> {
+    // Paths defined by a higher layer
+    let mut covered: HashSet<String> = HashSet::new();
+    // Paths whose whole subtree a higher layer removed
+    let mut removed: HashSet<String> = HashSet::new();
+    // Directories whose lower contents a higher layer hid
+    let mut hidden: HashSet<String> = HashSet::new();
+
+    let is_removed = |path: &str, removed: &HashSet<String>, hidden: &HashSet<String>| {
+        if removed.contains(path) {
+            return true;
+        }
+        let mut ancestor = path;
+        while !ancestor.is_empty() {
+            ancestor = parent(ancestor);
+            if removed.contains(ancestor) || hidden.contains(ancestor) {
+                return true;
+            }
+        }
+        false
+    };
+
+    let mut keep: Vec<Vec<bool>> = layers.iter().map(|l| vec![false; l.len()]).collect();
+    for (index, layer) in layers.iter().enumerate().rev() {
+        let mut in_layer = HashSet::new();
+        for (i, entry) in layer.iter().enumerate().rev() {
+            keep[index][i] = !matches!(entry.kind, EntryKind::Whiteout | EntryKind::Opaque)
+                && !covered.contains(&entry.path)
+                && !is_removed(&entry.path, &removed, &hidden)
+                && in_layer.insert(entry.path.clone());
+        }
+
+        for entry in layer {
+            match entry.kind {
+                EntryKind::Whiteout => {
+                    let name = entry.path.rsplit('/').next().unwrap_or("");
+                    let target = name.trim_start_matches(".wh.");
+                    let dir = parent(&entry.path);
+                    removed.insert(if dir.is_empty() {
+                        target.to_string()
+                    } else {
+                        format!("{}/{}", dir, target)
+                    });
+                }
+                EntryKind::Opaque => {
+                    hidden.insert(parent(&entry.path).to_string());
+                }
+                EntryKind::Directory => {
+                    covered.insert(entry.path.clone());
+                }
+                // A file or link replaces anything below its path too
+                EntryKind::File | EntryKind::Other => {
+                    covered.insert(entry.path.clone());
+                    removed.insert(entry.path.clone());
+                }
+            }
+        }
+    }
+    keep
+}
+
+/// Counts bytes and digests everything written through it
+pub(crate) struct Digesting<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W: Write> Digesting<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn finish(self) -> (W, String, u64) {
+        (
+            self.inner,
+            format!("sha256:{:x}", self.hasher.finalize()),
+            self.bytes,
+        )
+    }
+}
+
+impl<W: Write> Write for Digesting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct SquashedLayer {
+    /// Digest of the uncompressed tar (the config's diff_id)
+    diff_id: String,
+    size: u64,
+    /// Digest of the zstd blob
+    digest: String,
+    compressed_size: u64,
+    stripped_files: usize,
+}
+
+fn write_squashed(
+    layers: &[PathBuf],
+    entries: &[Vec<EntryInfo>],
+    keep: &[Vec<bool>],
+    profiles: &[StripProfile],
+    output: &Path,
+    level: i32,
+) -> Result<(Vec<LayerReport>, SquashedLayer)> {
+    let compressed = Digesting::new(BufWriter::new(File::create(output)?));
+    let encoder = zstd::stream::write::Encoder::new(compressed, level)?;
+    let mut builder = tar::Builder::new(Digesting::new(encoder));
+
+    let mut reports = Vec::new();
+    let mut written: HashSet<String> = HashSet::new();
+    let mut stripped_files = 0;
+
+    for (index, layer) in layers.iter().enumerate() {
+        let mut report = LayerReport {
+            digest: String::new(),
+            size_bytes: std::fs::metadata(layer)?.len(),
+            files: 0,
+            shadowed_bytes: 0,
+            stripped_bytes: 0,
+            kept_bytes: 0,
+        };
+
+        let mut archive = tar::Archive::new(open_layer(layer)?);
+        for (i, entry) in archive.entries()?.enumerate() {
+            let mut entry = entry?;
+            let info = &entries[index][i];
+            if info.kind == EntryKind::File {
+                report.files += 1;
+            }
+            if !keep[index][i] {
+                if info.kind == EntryKind::File {
+                    report.shadowed_bytes += info.size;
+                }
+                continue;
+            }
+            if info.kind != EntryKind::Directory && profiles.iter().any(|p| p.matches(&info.path)) {
+                report.stripped_bytes += info.size;
+                stripped_files += 1;
+                continue;
+            }
+
+            let mut header = entry.header().clone();
+            let link = entry
+                .link_name()?
+                .map(|l| normalize(&l))
+                .filter(|_| header.entry_type().is_hard_link());
+            // A hard link to a dropped file would dangle
+            if let Some(ref target) = link
+                && !written.contains(target)
+            {
+                debug!("Dropping hard link {} to {}", info.path, target);
+                continue;
+            }
+
+            let xattrs: Vec<(String, Vec<u8>)> = entry
+                .pax_extensions()?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|ext| {
+                    let key = ext.key().ok()?;
+                    key.starts_with("SCHILY.xattr.")
+                        .then(|| (key.to_string(), ext.value_bytes().to_vec()))
+                })
+                .collect();
+            builder
+                .append_pax_extensions(xattrs.iter().map(|(k, v)| (k.as_str(), v.as_slice())))?;
+
+            let path = if info.kind == EntryKind::Directory {
+                format!("{}/", info.path)
+            } else {
+                info.path.clone()
+            };
+            if let Some(target) = link {
+                builder.append_link(&mut header, &path, target)?;
+            } else if header.entry_type().is_symlink() {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| anyhow!("Symlink {} has no target", info.path))?
+                    .into_owned();
+                builder.append_link(&mut header, &path, target)?;
+            } else {
+                builder.append_data(&mut header, &path, &mut entry)?;
+            }
+            if info.kind == EntryKind::File {
+                report.kept_bytes += info.size;
+            }
+            written.insert(info.path.clone());
+        }
+        reports.push(report);
+    }
+
+    let (encoder, diff_id, size) = builder.into_inner()?.finish();
+    let (mut compressed, digest, compressed_size) = encoder.finish()?.finish();
+    compressed.flush()?;
+
+    Ok((
+        reports,
+        SquashedLayer {
+            diff_id,
+            size,
+            digest,
+            compressed_size,
+            stripped_files,
+        },
+    ))
+}
+
+/// Point the config at the squashed layer and replace its history
+fn rewrite_config(
+    mut config: serde_json::Value,
+    diff_id: &str,
+    original_layers: usize,
+    profiles: &[StripProfile],
+) -> Result<Vec<u8>> {
+    let object = config
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Image config is not a JSON object"))?;
+    object.insert(
+        "rootfs".to_string(),
+        serde_json::json!({ "type": "layers", "diff_ids": [diff_id] }),
+    );
+    let profiles: Vec<String> = profiles
+        .iter()
+        .filter_map(|p| serde_json::to_value(p).ok())
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect();
+    object.insert(
+        "history".to_string(),
+        serde_json::json!([{
+            "created": chrono::Utc::now().to_rfc3339(),
+            "created_by": format!(
+                "bolt image optimize: squashed {} layers, stripped {}",
+                original_layers,
+                if profiles.is_empty() { "nothing".to_string() } else { profiles.join(", ") }
+            ),
+        }]),
+    );
+    Ok(serde_json::to_vec(&config)?)
+}
+
+/// A layer blob for an OCI archive
+pub(crate) struct ArchiveLayer {
+    pub path: PathBuf,
+    pub media_type: &'static str,
+    pub digest: String,
+    pub size: u64,
+}
+
+pub(crate) fn write_oci_archive(
+    output: &Path,
+    tag: &str,
+    config: &[u8],
+    layers: &[ArchiveLayer],
+) -> Result<()> {
+    let digest = |data: &[u8]| format!("sha256:{:x}", Sha256::digest(data));
+    let config_digest = digest(config);
+    let descriptors: Vec<serde_json::Value> = layers
+        .iter()
+        .map(|layer| {
+            serde_json::json!({
+                "mediaType": layer.media_type,
+                "digest": layer.digest,
+                "size": layer.size,
+            })
+        })
+        .collect();
+    let manifest = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {
+            "mediaType": "application/vnd.oci.image.config.v1+json",
+            "digest": config_digest,
+            "size": config.len(),
+        },
+        "layers": descriptors,
+    }))?;
+    let manifest_digest = digest(&manifest);
+    let index = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "manifests": [{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": manifest_digest,
+            "size": manifest.len(),
+            "annotations": {
+                "org.opencontainers.image.ref.name": tag,
+                "io.containerd.image.name": tag,
+            },
+        }],
+    }))?;
+
+    let mut builder = tar::Builder::new(BufWriter::new(File::create(output)?));
+    let blob = |d: &str| format!("blobs/sha256/{}", d.trim_start_matches("sha256:"));
+    let mut append = |path: &str, data: &[u8]| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data)?;
+        Ok(())
+    };
+    append("oci-layout", br#"{"imageLayoutVersion":"1.0.0"}"#)?;
+    append("index.json", &index)?;
+    append(&blob(&manifest_digest), &manifest)?;
+    append(&blob(&config_digest), config)?;
+    for layer in layers {
+        builder.append_path_with_name(&layer.path, blob(&layer.digest))?;
+    }
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+pub(crate) async fn load_and_tag(runtime: &str, archive: &Path, tag: &str) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .arg("load")
+        .arg("-i")
+        .arg(archive)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to load {}: {}",
+            tag,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // "Loaded image: <ref>" (or "Loaded image ID: sha256:...")
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let loaded = stdout
+        .lines()
+        .filter(|l| l.starts_with("Loaded image"))
+        .filter_map(|l| l.rsplit_once(": ").map(|(_, r)| r.trim()))
+        .filter_map(|r| r.split(',').next())
+        .next_back()
+        .ok_or_else(|| anyhow!("{} load did not report the loaded image", runtime))?
+        .to_string();
+    if loaded != tag {
+        let status = AsyncCommand::new(runtime)
+            .args(["tag", &loaded, tag])
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("Failed to tag {} as {}", loaded, tag));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, kind: EntryKind) -> EntryInfo {
+        EntryInfo {
+            path: path.to_string(),
+            kind,
+            size: 1,
+        }
+    }
+
+    #[test]
+    fn squash_applies_whiteouts_and_replacements() {
+        use EntryKind::*;
+        let layers = vec![
+            vec![
+                entry("etc", Directory),
+                entry("etc/motd", File),
+                entry("opt/game", Directory),
+                entry("opt/game/assets.pak", File),
+                entry("var/log/build.log", File),
+                entry("srv/data", Directory),
+                entry("srv/data/old.db", File),
+            ],
+            vec![
+                entry("etc/motd", File),
+                entry("var/log/.wh.build.log", Whiteout),
+                entry("srv/data", Directory),
+                entry("srv/data/.wh..wh..opq", Opaque),
+                entry("srv/data/new.db", File),
+            ],
+        ];
+        let keep = plan_squash(&layers);
+        assert_eq!(keep[0], [true, false, true, true, false, false, false]);
+        assert_eq!(keep[1], [true, false, true, false, true]);
+
+        assert!(StripProfile::Docs.matches("usr/share/doc/libc6/changelog.gz"));
+        assert!(!StripProfile::Docs.matches("usr/share/doc/libc6/copyright"));
+        assert!(StripProfile::Locales.matches("usr/share/locale/de/LC_MESSAGES/apt.mo"));
+        assert!(StripProfile::BuildCaches.matches("var/lib/apt/lists/deb.debian.org_Release"));
+        assert!(!StripProfile::BuildCaches.matches("opt/game/assets.pak"));
+
+        assert_eq!(
+            default_tag("registry:5000/game-server"),
+            "registry:5000/game-server:latest-optimized"
+        );
+        assert_eq!(default_tag("game-server:1.2"), "game-server:1.2-optimized");
+    }
+}
@@ -0,0 +1,858 @@
+// Reproducible image builds
+//
+// `bolt build --reproducible` aims for byte-identical rebuilds:
+//
+// - SOURCE_DATE_EPOCH, the context's last commit time unless given, is a
+//   build argument; podman also stamps it on the image with `--timestamp`
+// - base images are pinned to the digests they resolve to when the build
+//   starts, through `--build-context <name>=docker-image://<repo>@<digest>`
+// - the built image is saved and rewritten: layer entries sorted by path,
+//   mtimes clamped to SOURCE_DATE_EPOCH, owner names, atimes and ctimes
+//   dropped and layers left uncompressed; the config loses its timestamps,
+//   build host and builder version
+// - what went in (context digest, Dockerfile, build arguments, base image
+//   digests, runtime) is recorded as provenance in
+//   `<data_dir>/builds/provenance/<digest>.json`
+//
+// `bolt build verify-reproducible <image>` builds the recorded inputs again
+// without any cache, rewrites the result the same way and compares the
+// digests layer by layer.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use super::optimize::{self, ArchiveLayer, Digesting};
+
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+/// Labels naming the tool that built the image rather than the image
+const VOLATILE_LABELS: &[&str] = &["io.buildah.version"];
+
+/// A base image, as the Dockerfile names it and as it was resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaseImage {
+    pub reference: String,
+    /// `repo@sha256:...`, or None for an image that was never pushed
+    pub digest: Option<String>,
+    pub image_id: String,
+}
+
+/// What a reproducible build pins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inputs {
+    pub source_date_epoch: i64,
+    pub build_args: BTreeMap<String, String>,
+    pub base_images: Vec<BaseImage>,
+}
+
+impl Inputs {
+    /// The builder's arguments for these inputs
+    pub fn build_flags(&self, runtime: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        if runtime != "docker" {
+            args.push(format!("--timestamp={}", self.source_date_epoch));
+            args.push("--identity-label=false".to_string());
+        }
+        let mut build_args = self.build_args.clone();
+        build_args.insert(
+            "SOURCE_DATE_EPOCH".to_string(),
+            self.source_date_epoch.to_string(),
+        );
+        for (key, value) in &build_args {
+            args.extend(["--build-arg".to_string(), format!("{}={}", key, value)]);
+        }
+        for base in &self.base_images {
+            if let Some(ref digest) = base.digest
+                && !base.reference.contains('@')
+            {
+                args.extend([
+                    "--build-context".to_string(),
+                    format!("{}=docker-image://{}", base.reference, digest),
+                ]);
+            }
+        }
+        args
+    }
+}
+
+/// `KEY=VALUE` build arguments as a map. A bare `KEY`, which the builder
+/// would take from the environment, isn't accepted: its value must be
+/// recorded.
+pub fn parse_build_args(pairs: &[String]) -> Result<BTreeMap<String, String>> {
+    let mut args = BTreeMap::new();
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid build argument '{}': expected KEY=VALUE", pair))?;
+        if key.is_empty() || key.chars().any(char::is_whitespace) {
+            return Err(anyhow!("Invalid build argument name '{}'", key));
+        }
+        if args.insert(key.to_string(), value.to_string()).is_some() {
+            return Err(anyhow!("Build argument '{}' is given more than once", key));
+        }
+    }
+    Ok(args)
+}
+
+/// `--build-arg KEY=VALUE` for each argument
+pub fn build_arg_flags(args: &BTreeMap<String, String>) -> Vec<String> {
+    args.iter()
+        .flat_map(|(key, value)| ["--build-arg".to_string(), format!("{}={}", key, value)])
+        .collect()
+}
+
+/// The context and Dockerfile as absolute paths. Like the builders, a
+/// relative Dockerfile path is tried against the working directory first.
+pub fn locate(context: &str, dockerfile: &str) -> Result<(PathBuf, PathBuf)> {
+    let dockerfile = if Path::new(dockerfile).exists() {
+        PathBuf::from(dockerfile)
+    } else {
+        Path::new(context).join(dockerfile)
+    };
+    Ok((
+        std::fs::canonicalize(context)
+            .with_context(|| format!("Build context {} not found", context))?,
+        std::fs::canonicalize(&dockerfile)
+            .with_context(|| format!("Dockerfile {} not found", dockerfile.display()))?,
+    ))
+}
+
+/// The time of the context's last git commit, or 0 outside a repository
+pub async fn source_date_epoch(context: &Path) -> i64 {
+    AsyncCommand::new("git")
+        .arg("-C")
+        .arg(context)
+        .args(["log", "-1", "--format=%ct"])
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// `$NAME`, `${NAME}` and `${NAME:-default}` in a `FROM` line
+fn expand(word: &str, args: &HashMap<String, String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = word;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(inner) => match inner.find('}') {
+                Some(end) => (&inner[..end], &inner[end + 1..]),
+                None => (inner, ""),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        let (name, default) = name.split_once(":-").unwrap_or((name, ""));
+        match args.get(name).filter(|value| !value.is_empty()) {
+            Some(value) => expanded.push_str(value),
+            None => expanded.push_str(default),
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The images the Dockerfile's stages start from, leaving out `scratch`
+/// and earlier stages
+pub fn base_references(dockerfile: &str, build_args: &BTreeMap<String, String>) -> Vec<String> {
+    let mut args: HashMap<String, String> = HashMap::new();
+    let mut stages = HashSet::new();
+    let mut references = Vec::new();
+    let mut in_stage = false;
+    let text = dockerfile.replace("\\\r\n", " ").replace("\\\n", " ");
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next().map(|w| w.to_ascii_uppercase()).as_deref() {
+            // Only ARGs before the first FROM apply to FROM lines
+            Some("ARG") if !in_stage => {
+                for word in words {
+                    let (name, default) = word.split_once('=').unwrap_or((word, ""));
+                    let value = build_args
+                        .get(name)
+                        .cloned()
+                        .unwrap_or_else(|| default.trim_matches('"').to_string());
+                    args.insert(name.to_string(), value);
+                }
+            }
+            Some("FROM") => {
+                in_stage = true;
+                let words: Vec<&str> = words.filter(|w| !w.starts_with("--")).collect();
+                let Some(image) = words.first() else {
+                    continue;
+                };
+                let image = expand(image, &args);
+                if image != "scratch"
+                    && !stages.contains(&image.to_ascii_lowercase())
+                    && !references.contains(&image)
+                {
+                    references.push(image);
+                }
+                if let [_, alias, name, ..] = words.as_slice()
+                    && alias.eq_ignore_ascii_case("as")
+                {
+                    stages.insert(name.to_ascii_lowercase());
+                }
+            }
+            _ => {}
+        }
+    }
+    references
+}
+
+async fn inspect_base(runtime: &str, reference: &str) -> Option<(String, Vec<String>)> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{.Id}} {{json .RepoDigests}}",
+        ])
+        .arg(reference)
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (id, digests) = stdout.trim().split_once(' ')?;
+    let digests: Option<Vec<String>> = serde_json::from_str(digests).ok()?;
+    Some((id.to_string(), digests.unwrap_or_default()))
+}
+
+/// Resolve a base image to its digest, pulling it when it isn't local
+pub async fn resolve_base(runtime: &str, reference: &str) -> Result<BaseImage> {
+    let (image_id, digests) = match inspect_base(runtime, reference).await {
+        Some(found) => found,
+        None => {
+            info!("📥 Pulling base image {}", reference);
+            let output = AsyncCommand::new(runtime)
+                .args(["pull", reference])
+                .output()
+                .await?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Failed to pull base image {}: {}",
+                    reference,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            inspect_base(runtime, reference)
+                .await
+                .ok_or_else(|| anyhow!("Failed to inspect base image {}", reference))?
+        }
+    };
+    let repository = reference.split('@').next().unwrap_or(reference);
+    let repository = match repository.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => name,
+        _ => repository,
+    };
+    let digest = if reference.contains('@') {
+        Some(reference.to_string())
+    } else {
+        digests
+            .iter()
+            .find(|d| d.split('@').next().is_some_and(|r| r.ends_with(repository)))
+            .or(digests.first())
+            .cloned()
+    };
+    if digest.is_none() {
+        warn!(
+            "Base image {} has no registry digest; its rebuilds use whatever it is locally",
+            reference
+        );
+    }
+    Ok(BaseImage {
+        reference: reference.to_string(),
+        digest,
+        image_id,
+    })
+}
+
+/// Pin a build's inputs: SOURCE_DATE_EPOCH and the base images' digests
+pub async fn resolve_inputs(
+    runtime: &str,
+    context: &Path,
+    dockerfile: &Path,
+    build_args: BTreeMap<String, String>,
+    source_date_epoch: Option<i64>,
+) -> Result<Inputs> {
+    let text = std::fs::read_to_string(dockerfile)
+        .with_context(|| format!("Failed to read {}", dockerfile.display()))?;
+    let mut base_images = Vec::new();
+    for reference in base_references(&text, &build_args) {
+        base_images.push(resolve_base(runtime, &reference).await?);
+    }
+    let source_date_epoch = match source_date_epoch {
+        Some(epoch) => epoch,
+        None => self::source_date_epoch(context).await,
+    };
+    Ok(Inputs {
+        source_date_epoch,
+        build_args,
+        base_images,
+    })
+}
+
+/// A layer of a rewritten image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layer {
+    pub diff_id: String,
+    /// The Dockerfile step that made it, as the image's history has it
+    pub created_by: Option<String>,
+}
+
+/// An image after rewriting
+#[derive(Debug, Clone)]
+pub struct Normalized {
+    /// Digest of the image config, which is the image ID under podman
+    pub digest: String,
+    pub layers: Vec<Layer>,
+}
+
+struct TarEntry {
+    path: String,
+    header: tar::Header,
+    link: Option<PathBuf>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    position: u64,
+    size: u64,
+}
+
+/// A GNU header with only what the entry needs: no owner names, atime or
+/// ctime, and the mtime clamped to `clamp`
+fn clean_header(
+    source: &tar::Header,
+    entry_type: tar::EntryType,
+    size: u64,
+    clamp: u64,
+) -> Result<tar::Header> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(source.mode()?);
+    header.set_uid(source.uid()?);
+    header.set_gid(source.gid()?);
+    header.set_mtime(source.mtime()?.min(clamp));
+    header.set_size(size);
+    if let (Some(major), Some(minor)) = (source.device_major()?, source.device_minor()?) {
+        header.set_device_major(major)?;
+        header.set_device_minor(minor)?;
+    }
+    Ok(header)
+}
+
+/// Rewrite a layer as an uncompressed tar with its entries sorted by path.
+/// Returns the new layer's digest and size.
+pub(crate) fn rewrite_layer(input: &Path, output: &Path, epoch: i64) -> Result<(String, u64)> {
+    // Sorting needs to seek back to each entry's data
+    let raw = output.with_extension("raw");
+    std::io::copy(
+        &mut optimize::open_layer(input)?,
+        &mut BufWriter::new(File::create(&raw)?),
+    )?;
+
+    let mut entries = Vec::new();
+    let mut archive = tar::Archive::new(BufReader::new(File::open(&raw)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = optimize::normalize(&entry.path()?);
+        if path.is_empty() {
+            continue;
+        }
+        let mut xattrs: Vec<(String, Vec<u8>)> = entry
+            .pax_extensions()?
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|ext| {
+                let key = ext.key().ok()?;
+                key.starts_with("SCHILY.xattr.")
+                    .then(|| (key.to_string(), ext.value_bytes().to_vec()))
+            })
+            .collect();
+        xattrs.sort();
+        entries.push(TarEntry {
+            path,
+            header: entry.header().clone(),
+            link: entry.link_name()?.map(|l| l.into_owned()),
+            xattrs,
+            position: entry.raw_file_position(),
+            size: entry.header().entry_size()?,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let by_path: HashMap<&str, &TarEntry> = entries.iter().map(|e| (e.path.as_str(), e)).collect();
+
+    let clamp = epoch.max(0) as u64;
+    let mut source = File::open(&raw)?;
+    let mut builder = tar::Builder::new(Digesting::new(BufWriter::new(File::create(output)?)));
+    // Where each hard-linked file was written: sorting can put a link ahead
+    // of its target, and then the link carries the data instead
+    let mut written_as: HashMap<String, String> = HashMap::new();
+    for entry in &entries {
+        builder
+            .append_pax_extensions(entry.xattrs.iter().map(|(k, v)| (k.as_str(), v.as_slice())))?;
+        let entry_type = entry.header.entry_type();
+        let (data, target) = if entry_type.is_hard_link() {
+            let target = entry
+                .link
+                .as_deref()
+                .map(optimize::normalize)
+                .unwrap_or_default();
+            match (written_as.get(&target), by_path.get(target.as_str())) {
+                (Some(first), _) => (None, Some(first.clone())),
+                (None, Some(target_entry)) => {
+                    written_as.insert(target.clone(), entry.path.clone());
+                    (Some(*target_entry), None)
+                }
+                (None, None) => (None, Some(target)),
+            }
+        } else if entry_type.is_file() {
+            match written_as.get(&entry.path) {
+                Some(first) => (None, Some(first.clone())),
+                None => {
+                    written_as.insert(entry.path.clone(), entry.path.clone());
+                    (Some(entry), None)
+                }
+            }
+        } else {
+            (None, None)
+        };
+
+        if let Some(target) = target {
+            let mut header = clean_header(&entry.header, tar::EntryType::Link, 0, clamp)?;
+            builder.append_link(&mut header, &entry.path, target)?;
+        } else if let Some(data) = data {
+            let mut header = clean_header(&data.header, tar::EntryType::Regular, data.size, clamp)?;
+            source.seek(SeekFrom::Start(data.position))?;
+            builder.append_data(
+                &mut header,
+                &entry.path,
+                Read::by_ref(&mut source).take(data.size),
+            )?;
+        } else if entry_type.is_symlink() {
+            let mut header = clean_header(&entry.header, entry_type, 0, clamp)?;
+            let link = entry
+                .link
+                .clone()
+                .ok_or_else(|| anyhow!("Symlink {} has no target", entry.path))?;
+            builder.append_link(&mut header, &entry.path, link)?;
+        } else {
+            let mut header = clean_header(&entry.header, entry_type, 0, clamp)?;
+            let path = if entry_type.is_dir() {
+                format!("{}/", entry.path)
+            } else {
+                entry.path.clone()
+            };
+            builder.append_data(&mut header, path, std::io::empty())?;
+        }
+    }
+    let (mut writer, digest, size) = builder.into_inner()?.finish();
+    writer.flush()?;
+    std::fs::remove_file(&raw)?;
+    Ok((digest, size))
+}
+
+fn timestamp(epoch: i64) -> String {
+    DateTime::<Utc>::from_timestamp(epoch, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Drop what differs between two builds of the same inputs from an image
+/// config and point it at the rewritten layers
+pub(crate) fn rewrite_config(
+    mut config: serde_json::Value,
+    diff_ids: &[String],
+    epoch: i64,
+) -> Result<serde_json::Value> {
+    let created = serde_json::Value::String(timestamp(epoch));
+    let object = config
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Image config is not a JSON object"))?;
+    for key in ["container", "container_config", "docker_version"] {
+        object.remove(key);
+    }
+    object.insert("created".to_string(), created.clone());
+    object.insert(
+        "rootfs".to_string(),
+        serde_json::json!({ "type": "layers", "diff_ids": diff_ids }),
+    );
+    for step in object
+        .get_mut("history")
+        .and_then(|h| h.as_array_mut())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(step) = step.as_object_mut() {
+            step.insert("created".to_string(), created.clone());
+        }
+    }
+    if let Some(run) = object.get_mut("config").and_then(|c| c.as_object_mut()) {
+        // Docker puts the build container's ID and parent image here
+        run.remove("Hostname");
+        run.remove("Image");
+        if let Some(labels) = run.get_mut("Labels").and_then(|l| l.as_object_mut()) {
+            for label in VOLATILE_LABELS {
+                labels.remove(*label);
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// The layers of a config, with the history step of each
+fn layers_of(config: &serde_json::Value, diff_ids: &[String]) -> Vec<Layer> {
+    let mut steps = config["history"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|step| !step["empty_layer"].as_bool().unwrap_or(false))
+        .map(|step| step["created_by"].as_str().map(str::to_string));
+    diff_ids
+        .iter()
+        .map(|diff_id| Layer {
+            diff_id: diff_id.clone(),
+            created_by: steps.next().flatten(),
+        })
+        .collect()
+}
+
+/// Rewrite `image` deterministically and load the result as `tag`
+pub async fn normalize(
+    runtime: &str,
+    image: &str,
+    tag: &str,
+    epoch: i64,
+    work_dir: &Path,
+) -> Result<Normalized> {
+    std::fs::create_dir_all(work_dir)?;
+    let work = tempfile::Builder::new()
+        .prefix("reproducible-")
+        .tempdir_in(work_dir)
+        .context("Failed to create a work directory")?;
+    let archive = work.path().join("image.tar");
+    optimize::save_image(runtime, image, &archive).await?;
+    let (config, layers) = optimize::unpack_archive(&archive, work.path())?;
+    std::fs::remove_file(&archive)?;
+
+    info!("🧹 Normalizing {} layer(s)", layers.len());
+    let mut rewritten = Vec::new();
+    for (index, layer) in layers.iter().enumerate() {
+        let path = work.path().join(format!("layer-{}.tar", index));
+        let (digest, size) = rewrite_layer(layer, &path, epoch)?;
+        std::fs::remove_file(layer)?;
+        rewritten.push(ArchiveLayer {
+            path,
+            media_type: LAYER_MEDIA_TYPE,
+            digest,
+            size,
+        });
+    }
+    let diff_ids: Vec<String> = rewritten.iter().map(|l| l.digest.clone()).collect();
+    let config = rewrite_config(config, &diff_ids, epoch)?;
+    let layers = layers_of(&config, &diff_ids);
+    let config = serde_json::to_vec(&config)?;
+    let digest = format!("sha256:{:x}", Sha256::digest(&config));
+
+    let oci_archive = work.path().join("reproducible.tar");
+    optimize::write_oci_archive(&oci_archive, tag, &config, &rewritten)?;
+    optimize::load_and_tag(runtime, &oci_archive, tag).await?;
+    Ok(Normalized { digest, layers })
+}
+
+/// Everything that went into a reproducible build, and what came out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The tag it was built as
+    pub image: String,
+    /// Digest of the image config
+    pub digest: String,
+    pub layers: Vec<Layer>,
+    pub context: PathBuf,
+    /// Bolt's build cache key of the context and Dockerfile
+    pub context_digest: String,
+    pub dockerfile: PathBuf,
+    #[serde(flatten)]
+    pub inputs: Inputs,
+    pub annotations: BTreeMap<String, String>,
+    pub builder: String,
+    /// `<runtime> --version`
+    pub runtime: String,
+    pub built_at: DateTime<Utc>,
+}
+
+pub fn provenance_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("builds").join("provenance")
+}
+
+impl Provenance {
+    pub fn save(&self, data_dir: &Path) -> Result<PathBuf> {
+        let dir = provenance_dir(data_dir);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!(
+            "{}.json",
+            self.digest.trim_start_matches("sha256:")
+        ));
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The newest provenance of an image, by tag or digest
+    pub fn find(data_dir: &Path, image: &str) -> Result<Self> {
+        let wanted = image.trim_start_matches("sha256:");
+        let latest = format!("{}:latest", image);
+        let mut found: Option<Self> = None;
+        for entry in std::fs::read_dir(provenance_dir(data_dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let Some(provenance) = std::fs::read(entry.path())
+                .ok()
+                .and_then(|b| serde_json::from_slice::<Self>(&b).ok())
+            else {
+                continue;
+            };
+            let digest = provenance.digest.trim_start_matches("sha256:");
+            let matches = provenance.image == image
+                || provenance.image == latest
+                || (wanted.len() >= 12 && digest.starts_with(wanted));
+            if matches
+                && found
+                    .as_ref()
+                    .is_none_or(|f| f.built_at < provenance.built_at)
+            {
+                found = Some(provenance);
+            }
+        }
+        found.ok_or_else(|| {
+            anyhow!(
+                "No reproducible build of {} is recorded; build it with `bolt build --reproducible`",
+                image
+            )
+        })
+    }
+}
+
+pub async fn runtime_version(runtime: &str) -> String {
+    AsyncCommand::new(runtime)
+        .arg("--version")
+        .output()
+        .await
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|version| !version.is_empty())
+        .unwrap_or_else(|| runtime.to_string())
+}
+
+/// One layer of the recorded build next to the same layer of the rebuild
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerComparison {
+    pub index: usize,
+    pub created_by: Option<String>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl LayerComparison {
+    pub fn matches(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    pub image: String,
+    pub expected: String,
+    pub actual: String,
+    pub layers: Vec<LayerComparison>,
+    /// Inputs that are no longer what was recorded, which a divergence
+    /// may come from
+    pub changed_inputs: Vec<String>,
+    /// Tag of the rebuilt image, when it was kept
+    pub rebuilt: Option<String>,
+}
+
+impl Verification {
+    pub fn reproducible(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+/// Layer by layer comparison of a recorded build and its rebuild
+pub fn compare(expected: &[Layer], actual: &[Layer]) -> Vec<LayerComparison> {
+    (0..expected.len().max(actual.len()))
+        .map(|index| LayerComparison {
+            index,
+            created_by: expected
+                .get(index)
+                .or(actual.get(index))
+                .and_then(|l| l.created_by.clone()),
+            expected: expected.get(index).map(|l| l.diff_id.clone()),
+            actual: actual.get(index).map(|l| l.diff_id.clone()),
+        })
+        .collect()
+}
+
+/// Recorded inputs that changed since the build. Base images pinned by
+/// digest are rebuilt from that digest, so only unpinned ones count.
+pub async fn changed_inputs(runtime: &str, provenance: &Provenance) -> Vec<String> {
+    let mut changed = Vec::new();
+    match super::cache::context_key(&provenance.context, &provenance.dockerfile) {
+        Ok(key) if key == provenance.context_digest => {}
+        Ok(_) => changed.push(format!("build context {}", provenance.context.display())),
+        Err(e) => changed.push(format!(
+            "build context {}: {}",
+            provenance.context.display(),
+            e
+        )),
+    }
+    for base in &provenance.inputs.base_images {
+        if base.digest.is_none()
+            && inspect_base(runtime, &base.reference)
+                .await
+                .is_none_or(|(id, _)| id != base.image_id)
+        {
+            changed.push(format!("base image {}", base.reference));
+        }
+    }
+    let version = runtime_version(runtime).await;
+    if version != provenance.runtime {
+        changed.push(format!("runtime: {} (was {})", version, provenance.runtime));
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(entries: &[(&str, tar::EntryType, u64, Option<&str>)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, entry_type, mtime, link) in entries {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_mtime(*mtime);
+            header.set_username("builder").unwrap();
+            match link {
+                Some(target) => builder.append_link(&mut header, path, target).unwrap(),
+                None => {
+                    let data = path.as_bytes();
+                    header.set_size(data.len() as u64);
+                    builder.append_data(&mut header, path, data).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn rewrites_layers_and_finds_inputs() {
+        use tar::EntryType::{Link, Regular};
+
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.tar");
+        let second = dir.path().join("second.tar");
+        std::fs::write(
+            &first,
+            layer(&[
+                ("usr/bin/z", Regular, 1_700_000_000, None),
+                ("usr/bin/a", Link, 1_700_000_000, Some("usr/bin/z")),
+                ("etc/hosts", Regular, 5, None),
+            ]),
+        )
+        .unwrap();
+        std::fs::write(
+            &second,
+            layer(&[
+                ("etc/hosts", Regular, 5, None),
+                ("usr/bin/z", Regular, 1_800_000_000, None),
+                ("usr/bin/a", Link, 1_800_000_000, Some("usr/bin/z")),
+            ]),
+        )
+        .unwrap();
+        let (a, _) = rewrite_layer(&first, &dir.path().join("a.tar"), 1_000).unwrap();
+        let (b, _) = rewrite_layer(&second, &dir.path().join("b.tar"), 1_000).unwrap();
+        assert_eq!(a, b);
+
+        let mut archive = tar::Archive::new(File::open(dir.path().join("a.tar")).unwrap());
+        let entries: Vec<(String, tar::EntryType, u64)> = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                let header = e.header();
+                (
+                    e.path().unwrap().display().to_string(),
+                    header.entry_type(),
+                    header.mtime().unwrap(),
+                )
+            })
+            .collect();
+        // The link sorts first, so it carries the data and z links to it
+        assert_eq!(
+            entries,
+            [
+                ("etc/hosts".to_string(), Regular, 5),
+                ("usr/bin/a".to_string(), Regular, 1_000),
+                ("usr/bin/z".to_string(), Link, 1_000),
+            ]
+        );
+
+        let dockerfile = "ARG BASE=debian:12\nFROM ${BASE} AS build\nRUN make\n\
+                          FROM --platform=$BUILDPLATFORM alpine:3.20\nCOPY --from=build /out /\n\
+                          FROM build\nFROM scratch\n";
+        assert_eq!(
+            base_references(dockerfile, &BTreeMap::new()),
+            ["debian:12", "alpine:3.20"]
+        );
+        let args = parse_build_args(&["BASE=ubuntu:24.04".to_string()]).unwrap();
+        assert_eq!(base_references(dockerfile, &args)[0], "ubuntu:24.04");
+        assert!(parse_build_args(&["BASE".to_string()]).is_err());
+
+        let config = rewrite_config(
+            serde_json::json!({
+                "created": "2026-10-16T09:00:00Z",
+                "container": "4f2a",
+                "config": {
+                    "Hostname": "4f2a",
+                    "Labels": { "io.buildah.version": "1.37", "app": "x" },
+                },
+                "history": [
+                    { "created": "2026-10-16T09:00:00Z", "created_by": "ADD rootfs" },
+                    { "created_by": "ENV A=1", "empty_layer": true },
+                    { "created": "2026-10-16T09:00:02Z", "created_by": "RUN make" },
+                ],
+            }),
+            &["sha256:1".to_string(), "sha256:2".to_string()],
+            0,
+        )
+        .unwrap();
+        assert_eq!(config["created"], "1970-01-01T00:00:00Z");
+        assert!(config.get("container").is_none());
+        assert_eq!(
+            config["config"]["Labels"],
+            serde_json::json!({ "app": "x" })
+        );
+        let layers = layers_of(&config, &["sha256:1".to_string(), "sha256:2".to_string()]);
+        assert_eq!(layers[1].created_by.as_deref(), Some("RUN make"));
+        let diverged = compare(&layers, &layers[..1]);
+        assert!(diverged[0].matches() && !diverged[1].matches());
+    }
+}
@@ -0,0 +1,150 @@
+// Capsule consoles
+//
+// `bolt capsule console <name>` reaches a capsule without going through its
+// network, for when that is what broke:
+//
+// - the boot output (everything the capsule printed since it was created)
+//   is captured to `<capsules_root>/console/<name>.log`, first when the
+//   capsule is created and again on every console, so the last capture can
+//   still be read once the capsule is gone
+// - the console replays its tail, then follows the capsule's live output
+//   until Ctrl-C, which only ends the console
+// - `--shell` opens a login shell through the runtime instead
+//
+// Capsules run as containers, so there is no serial port, VNC or SPICE
+// display to export; those need VM-isolated capsules.
+use anyhow::{Context, Result, anyhow};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command as AsyncCommand;
+use tracing::info;
+
+/// Lines of boot output shown before following the console
+pub const DEFAULT_LINES: usize = 50;
+
+pub fn log_path(root: &Path, name: &str) -> PathBuf {
+    root.join("console").join(format!("{}.log", name))
+}
+
+fn ensure_capsule(root: &Path, name: &str) -> Result<()> {
+    if !root
+        .join("instances")
+        .join(format!("{}.toml", name))
+        .exists()
+    {
+        return Err(anyhow!(
+            "No capsule named {}; see `bolt capsule create`",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Save everything the capsule printed so far; returns the log's path
+pub async fn capture(runtime: &str, root: &Path, name: &str) -> Result<PathBuf> {
+    let output = AsyncCommand::new(runtime)
+        .args(["logs", "--timestamps", name])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to read the console of {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let path = log_path(root, name);
+    std::fs::create_dir_all(path.parent().unwrap_or(root))?;
+    // The runtime keeps stdout and stderr apart; timestamps put them back in order
+    let mut lines: Vec<&[u8]> = output
+        .stdout
+        .split(|b| *b == b'\n')
+        .chain(output.stderr.split(|b| *b == b'\n'))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.sort();
+    let mut log = lines.join(&b'\n');
+    if !log.is_empty() {
+        log.push(b'\n');
+    }
+    std::fs::write(&path, log).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The last `lines` lines of the captured boot output
+pub fn tail(root: &Path, name: &str, lines: usize) -> Result<Vec<String>> {
+    let path = log_path(root, name);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let all: Vec<&str> = text.lines().collect();
+    Ok(all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// Capture the boot output, show its tail and follow the console until
+/// Ctrl-C. Returns the exit code to leave with.
+pub async fn attach(runtime: &str, root: &Path, name: &str, lines: usize) -> Result<i32> {
+    ensure_capsule(root, name)?;
+    capture(runtime, root, name).await?;
+    for line in tail(root, name, lines)? {
+        println!("{}", line);
+    }
+    info!("🔌 Console of {}; Ctrl-C to leave", name);
+    let status = AsyncCommand::new(runtime)
+        .args(["attach", "--no-stdin", "--sig-proxy=false", name])
+        .stdin(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("Failed to attach to {}", name))?;
+    Ok(status.code().unwrap_or(0))
+}
+
+/// An interactive login shell in the capsule, through the runtime
+pub async fn shell(runtime: &str, root: &Path, name: &str, shell: &str) -> Result<i32> {
+    ensure_capsule(root, name)?;
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.arg("exec");
+    cmd.arg(if std::io::stdin().is_terminal() {
+        "-it"
+    } else {
+        "-i"
+    });
+    if let Ok(term) = std::env::var("TERM") {
+        cmd.args(["-e", &format!("TERM={}", term)]);
+    }
+    let status = cmd
+        .args([name, shell, "-l"])
+        .status()
+        .await
+        .with_context(|| format!("Failed to exec in {}", name))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tails_the_captured_boot_output() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(tail(root.path(), "arena", 5).unwrap().is_empty());
+        assert!(ensure_capsule(root.path(), "arena").is_err());
+
+        let path = log_path(root.path(), "arena");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let log: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, log).unwrap();
+        assert_eq!(
+            tail(root.path(), "arena", 3).unwrap(),
+            ["line 8", "line 9", "line 10"]
+        );
+        assert_eq!(tail(root.path(), "arena", 100).unwrap().len(), 10);
+    }
+}
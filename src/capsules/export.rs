@@ -0,0 +1,431 @@
+// Portable bundles for moving capsule snapshots between hosts
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use super::snapshots::{MemoryBlock, MemoryManifest, MemorySnapshotProcessor};
+use super::{CapsuleConfig, CapsuleType, SnapshotMetadata};
+
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+const BUNDLE_MANIFEST: &str = "bolt-snapshot.json";
+
+/// A snapshot together with enough of its capsule to recreate it elsewhere.
+/// Stored as `<snapshot_id>.meta.json` in the capsule's snapshot store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    pub capsule_id: String,
+    pub capsule_name: String,
+    pub capsule_type: CapsuleType,
+    pub capsule_config: CapsuleConfig,
+    pub snapshot: SnapshotMetadata,
+}
+
+/// First entry of a bundle archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub record: SnapshotRecord,
+    pub memory: Option<MemoryManifest>,
+    /// Block files included under `blocks/`
+    pub blocks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSummary {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub blocks: usize,
+}
+
+fn store_path(capsules_root: &Path, capsule_id: &str) -> PathBuf {
+    capsules_root.join("snapshots").join(capsule_id)
+}
+
+fn record_path(store: &Path, snapshot_id: &str) -> PathBuf {
+    store.join(format!("{}.meta.json", snapshot_id))
+}
+
+/// Capsule and snapshot IDs name files and directories in the store, so a
+/// record from another host must not be able to point outside it
+pub fn validate_id(kind: &str, id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= 128
+        && id != "."
+        && id != ".."
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid {} ID '{}'", kind, id))
+    }
+}
+
+fn validate_record(record: &SnapshotRecord) -> Result<()> {
+    validate_id("capsule", &record.capsule_id)?;
+    validate_id("snapshot", &record.snapshot.id)
+}
+
+pub fn save_record(capsules_root: &Path, record: &SnapshotRecord) -> Result<()> {
+    validate_record(record)?;
+    let store = store_path(capsules_root, &record.capsule_id);
+    std::fs::create_dir_all(&store)?;
+    std::fs::write(
+        record_path(&store, &record.snapshot.id),
+        serde_json::to_string_pretty(record)?,
+    )?;
+    Ok(())
+}
+
+pub fn remove_record(capsules_root: &Path, capsule_id: &str, snapshot_id: &str) -> Result<()> {
+    let path = record_path(&store_path(capsules_root, capsule_id), snapshot_id);
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Every snapshot record on this host, newest first
+pub fn list_records(capsules_root: &Path) -> Result<Vec<SnapshotRecord>> {
+    let snapshots_dir = capsules_root.join("snapshots");
+    let mut records = Vec::new();
+    if !snapshots_dir.exists() {
+        return Ok(records);
+    }
+
+    for capsule_dir in std::fs::read_dir(&snapshots_dir)? {
+        let capsule_dir = capsule_dir?.path();
+        if !capsule_dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&capsule_dir)? {
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(".meta.json") {
+                continue;
+            }
+            match serde_json::from_str::<SnapshotRecord>(&std::fs::read_to_string(&path)?) {
+                Ok(record) => records.push(record),
+                Err(e) => debug!("Skipping unreadable snapshot record {:?}: {}", path, e),
+            }
+        }
+    }
+
+    records.sort_by_key(|r| std::cmp::Reverse(r.snapshot.created_at));
+    Ok(records)
+}
+
+/// Look a snapshot up by ID or name
+pub fn find_record(capsules_root: &Path, snapshot: &str) -> Result<SnapshotRecord> {
+    list_records(capsules_root)?
+        .into_iter()
+        .find(|r| r.snapshot.id == snapshot || r.snapshot.name.as_deref() == Some(snapshot))
+        .ok_or_else(|| anyhow!("Snapshot not found: {}", snapshot))
+}
+
+fn processor_for(capsules_root: &Path, record: &SnapshotRecord) -> Result<MemorySnapshotProcessor> {
+    MemorySnapshotProcessor::new(
+        store_path(capsules_root, &record.capsule_id),
+        record
+            .capsule_config
+            .storage
+            .snapshot_policy
+            .memory_compression
+            .clone(),
+    )
+}
+
+/// Write a snapshot and every block it references into a single tar bundle
+pub fn export_snapshot(
+    capsules_root: &Path,
+    snapshot: &str,
+    output: &Path,
+) -> Result<ExportSummary> {
+    let record = find_record(capsules_root, snapshot)?;
+    let processor = processor_for(capsules_root, &record)?;
+
+    info!(
+        "📦 Exporting snapshot {} to {:?}",
+        record.snapshot.id, output
+    );
+
+    let memory = if record.snapshot.memory_included {
+        Some(processor.load_manifest(&record.snapshot.id)?)
+    } else {
+        None
+    };
+
+    let hashes: BTreeSet<&str> = memory
+        .iter()
+        .flat_map(|m| m.blocks.iter())
+        .filter_map(|b| match b {
+            MemoryBlock::Stored(hash) => Some(hash.as_str()),
+            MemoryBlock::Zero => None,
+        })
+        .collect();
+
+    let mut block_files = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        let path = processor
+            .stored_block_file(hash)
+            .ok_or_else(|| anyhow!("Memory block {} missing from store", hash))?;
+        block_files.push(path);
+    }
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        exported_at: chrono::Utc::now(),
+        record,
+        memory,
+        blocks: block_files
+            .iter()
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .collect(),
+    };
+
+    let tmp = output.with_extension("partial");
+    {
+        let mut builder = tar::Builder::new(File::create(&tmp)?);
+        let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, BUNDLE_MANIFEST, manifest_json.as_slice())?;
+
+        for (path, name) in block_files.iter().zip(&manifest.blocks) {
+            builder.append_path_with_name(path, format!("blocks/{}", name))?;
+        }
+        builder.into_inner()?.sync_all()?;
+    }
+    std::fs::rename(&tmp, output)?;
+
+    let summary = ExportSummary {
+        path: output.to_path_buf(),
+        size_bytes: std::fs::metadata(output)?.len(),
+        sha256: file_sha256(output)?,
+        blocks: manifest.blocks.len(),
+    };
+
+    info!(
+        "✅ Exported snapshot {} ({} bytes, {} blocks, sha256:{})",
+        manifest.record.snapshot.id, summary.size_bytes, summary.blocks, summary.sha256
+    );
+    Ok(summary)
+}
+
+/// Unpack a bundle into the local snapshot store. Blocks are verified against
+/// their content hash, and blocks already present locally are skipped.
+pub fn import_snapshot(
+    capsules_root: &Path,
+    bundle: &Path,
+    expected_sha256: Option<&str>,
+) -> Result<SnapshotRecord> {
+    if let Some(expected) = expected_sha256 {
+        let actual = file_sha256(bundle)?;
+        if !actual.eq_ignore_ascii_case(expected.trim_start_matches("sha256:")) {
+            return Err(anyhow!(
+                "Bundle digest mismatch: expected {}, got {}",
+                expected,
+                actual
+            ));
+        }
+    }
+
+    info!("📥 Importing snapshot bundle {:?}", bundle);
+
+    let mut archive = tar::Archive::new(BufReader::new(
+        File::open(bundle).with_context(|| format!("Failed to open bundle {:?}", bundle))?,
+    ));
+    let mut entries = archive.entries()?;
+
+    let manifest: BundleManifest = {
+        let mut first = entries
+            .next()
+            .ok_or_else(|| anyhow!("Snapshot bundle is empty"))??;
+        if first.path()?.to_string_lossy() != BUNDLE_MANIFEST {
+            return Err(anyhow!(
+                "Not a Bolt snapshot bundle: missing {}",
+                BUNDLE_MANIFEST
+            ));
+        }
+        let mut content = String::new();
+        first.read_to_string(&mut content)?;
+        serde_json::from_str(&content).context("Invalid snapshot bundle manifest")?
+    };
+
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Snapshot bundle format {} is newer than supported ({})",
+            manifest.format_version,
+            BUNDLE_FORMAT_VERSION
+        ));
+    }
+
+    validate_record(&manifest.record)?;
+    if let Some(ref memory) = manifest.memory {
+        if memory.snapshot_id != manifest.record.snapshot.id {
+            return Err(anyhow!(
+                "Bundle memory manifest belongs to snapshot {}, not {}",
+                memory.snapshot_id,
+                manifest.record.snapshot.id
+            ));
+        }
+        for block in &memory.blocks {
+            if let MemoryBlock::Stored(hash) = block {
+                if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(anyhow!("Invalid memory block hash in bundle: {}", hash));
+                }
+            }
+        }
+    }
+
+    let processor = processor_for(capsules_root, &manifest.record)?;
+    let (mut added, mut existing) = (0usize, 0usize);
+
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let Some(name) = path.strip_prefix("blocks/") else {
+            debug!("Ignoring unexpected bundle entry {}", path);
+            continue;
+        };
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        if processor.import_block(name, &data)? {
+            added += 1;
+        } else {
+            existing += 1;
+        }
+    }
+
+    if let Some(ref memory) = manifest.memory {
+        for block in &memory.blocks {
+            if let MemoryBlock::Stored(hash) = block {
+                if processor.stored_block_file(hash).is_none() {
+                    return Err(anyhow!("Bundle is missing memory block {}", hash));
+                }
+            }
+        }
+        processor.save_manifest(memory)?;
+    }
+    save_record(capsules_root, &manifest.record)?;
+
+    info!(
+        "✅ Imported snapshot {} of capsule {} ({} new blocks, {} already present)",
+        manifest.record.snapshot.id, manifest.record.capsule_name, added, existing
+    );
+    Ok(manifest.record)
+}
+
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capsules::CapsuleStatus;
+    use tempfile::TempDir;
+
+    fn record(capsule_id: &str, snapshot_id: &str) -> SnapshotRecord {
+        SnapshotRecord {
+            capsule_id: capsule_id.to_string(),
+            capsule_name: "web".to_string(),
+            capsule_type: CapsuleType::Standard,
+            capsule_config: super::super::templates::builtin_templates()[0]
+                .base_config
+                .clone(),
+            snapshot: SnapshotMetadata {
+                id: snapshot_id.to_string(),
+                name: Some("stable".to_string()),
+                created_at: chrono::Utc::now(),
+                size_bytes: 0,
+                description: String::new(),
+                capsule_state: CapsuleStatus::Stopped,
+                memory_included: false,
+                parent_snapshot: None,
+                memory_stats: None,
+            },
+        }
+    }
+
+    fn write_bundle(path: &Path, record: SnapshotRecord) {
+        let manifest = BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            exported_at: chrono::Utc::now(),
+            record,
+            memory: None,
+            blocks: Vec::new(),
+        };
+        let json = serde_json::to_vec(&manifest).unwrap();
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, BUNDLE_MANIFEST, json.as_slice())
+            .unwrap();
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let source = TempDir::new().unwrap();
+        let target = TempDir::new().unwrap();
+        save_record(source.path(), &record("capsule-1", "snap-1")).unwrap();
+
+        let bundle = source.path().join("snap.bundle");
+        let summary = export_snapshot(source.path(), "stable", &bundle).unwrap();
+        let imported = import_snapshot(target.path(), &bundle, Some(&summary.sha256)).unwrap();
+        assert_eq!(imported.snapshot.id, "snap-1");
+        assert_eq!(
+            find_record(target.path(), "stable").unwrap().capsule_id,
+            "capsule-1"
+        );
+
+        assert!(import_snapshot(target.path(), &bundle, Some(&"0".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn test_import_rejects_path_traversal() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("capsules");
+        std::fs::create_dir_all(&root).unwrap();
+
+        for (capsule_id, snapshot_id) in [
+            ("../../escape", "snap-1"),
+            ("capsule-1", "../../../escape"),
+            ("..", "snap-1"),
+            ("capsule/1", "snap-1"),
+        ] {
+            let bundle = dir.path().join("evil.bundle");
+            write_bundle(&bundle, record(capsule_id, snapshot_id));
+            assert!(import_snapshot(&root, &bundle, None).is_err());
+        }
+
+        assert!(!dir.path().join("escape").exists());
+        assert!(!dir.path().join("escape.meta.json").exists());
+        assert!(list_records(&root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_id() {
+        assert!(validate_id("snapshot", "7f1c2d9e-4b6a-4c8e-9d3f-0a1b2c3d4e5f").is_ok());
+        assert!(validate_id("capsule", "web.v2_1").is_ok());
+        for id in ["", ".", "..", "a/b", "a\\b", "../x", "a b"] {
+            assert!(validate_id("snapshot", id).is_err(), "{:?} accepted", id);
+        }
+    }
+}
@@ -5,17 +5,22 @@ use std::path::PathBuf;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+pub mod console;
+pub mod export;
 pub mod snapshots;
 pub mod templates;
+#[cfg(feature = "quic-networking")]
+pub mod transfer;
 pub mod vm;
 
+use crate::optimizations::hybrid::{CorePlacement, EnergyPerformancePreference};
 use crate::runtime::oci::ContainerConfig;
 
 /// Bolt Capsules - Our revolutionary container-VM hybrid
 ///
 /// Capsules provide:
 /// 1. VM-like isolation but container speed
-/// 2. Live migration capabilities
+/// 2. Live migration capabilities, built on CRIU checkpoints
 /// 3. Instant snapshots for game saves
 /// 4. Gaming-optimized resource allocation
 /// 5. Template system for common environments
@@ -81,6 +86,12 @@ pub struct CapsuleResources {
     pub memory_balloon: bool, // Dynamic memory allocation
     pub cpu_hotplug: bool,    // Hot-add/remove CPUs
     pub numa_topology: Option<NumaConfig>,
+    /// Core type to run on with hybrid (P/E core) CPUs
+    #[serde(default)]
+    pub core_placement: CorePlacement,
+    /// energy_performance_preference for the capsule's CPUs
+    #[serde(default)]
+    pub energy_performance_preference: Option<EnergyPerformancePreference>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -179,6 +190,8 @@ pub struct SnapshotPolicy {
     pub interval_minutes: u32,
     pub max_snapshots: u32,
     pub compress_snapshots: bool,
+    #[serde(default)]
+    pub memory_compression: snapshots::MemoryCompressionConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -289,6 +302,8 @@ pub struct SnapshotMetadata {
     pub capsule_state: CapsuleStatus,
     pub memory_included: bool,
     pub parent_snapshot: Option<String>,
+    #[serde(default)]
+    pub memory_stats: Option<snapshots::MemorySnapshotStats>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -428,6 +443,8 @@ impl CapsuleManager {
                 memory_balloon: false, // Stable memory for gaming
                 cpu_hotplug: false,
                 numa_topology: None,
+                core_placement: CorePlacement::PCores,
+                energy_performance_preference: Some(EnergyPerformancePreference::Performance),
             },
             CapsuleType::Development => CapsuleResources {
                 memory_mb: 4096, // 4GB for development
@@ -436,6 +453,8 @@ impl CapsuleManager {
                 memory_balloon: true, // Dynamic memory
                 cpu_hotplug: true,
                 numa_topology: None,
+                core_placement: CorePlacement::Any,
+                energy_performance_preference: None,
             },
             CapsuleType::Database => CapsuleResources {
                 memory_mb: 2048, // 2GB for database
@@ -444,6 +463,8 @@ impl CapsuleManager {
                 memory_balloon: false, // Stable memory for DB
                 cpu_hotplug: false,
                 numa_topology: None,
+                core_placement: CorePlacement::Any,
+                energy_performance_preference: None,
             },
             _ => CapsuleResources {
                 memory_mb: 1024, // 1GB default
@@ -452,6 +473,8 @@ impl CapsuleManager {
                 memory_balloon: true,
                 cpu_hotplug: false,
                 numa_topology: None,
+                core_placement: CorePlacement::Any,
+                energy_performance_preference: None,
             },
         };
 
@@ -508,6 +531,15 @@ impl CapsuleManager {
                 },
                 max_snapshots: 10,
                 compress_snapshots: !matches!(capsule_type, CapsuleType::Gaming),
+                memory_compression: snapshots::MemoryCompressionConfig {
+                    // Favour fast snapshots for gaming capsules
+                    compression_level: if matches!(capsule_type, CapsuleType::Gaming) {
+                        1
+                    } else {
+                        3
+                    },
+                    ..Default::default()
+                },
             },
         };
 
@@ -666,73 +698,12 @@ impl CapsuleManager {
     fn initialize_builtin_templates(&mut self) -> Result<()> {
         info!("📋 Initializing built-in capsule templates");
 
-        // Gaming template
-        let gaming_template = CapsuleTemplate {
-            name: "gaming".to_string(),
-            description: "Gaming-optimized capsule with GPU/audio passthrough".to_string(),
-            capsule_type: CapsuleType::Gaming,
-            base_config: CapsuleConfig {
-                template: None,
-                image: "bolt://gaming-base:latest".to_string(),
-                resources: CapsuleResources {
-                    memory_mb: 8192,
-                    vcpus: 4,
-                    cpu_shares: 2048,
-                    memory_balloon: false,
-                    cpu_hotplug: false,
-                    numa_topology: None,
-                },
-                networking: CapsuleNetworking {
-                    network_type: NetworkType::QuicFabric,
-                    interfaces: vec![],
-                    dns_config: DnsConfig {
-                        servers: vec!["1.1.1.1".to_string()],
-                        search_domains: vec![],
-                        bolt_dns_enabled: true,
-                    },
-                    firewall_rules: vec![],
-                },
-                storage: CapsuleStorage {
-                    root_disk: DiskConfig {
-                        name: "root".to_string(),
-                        size_gb: 100,
-                        disk_type: DiskType::NVMe,
-                        encryption: true,
-                        compression: false,
-                        cache_policy: CachePolicy::Gaming,
-                    },
-                    data_disks: vec![],
-                    shared_folders: vec![],
-                    snapshot_policy: SnapshotPolicy {
-                        auto_snapshot: true,
-                        interval_minutes: 30,
-                        max_snapshots: 10,
-                        compress_snapshots: false,
-                    },
-                },
-                security: CapsuleSecurity {
-                    isolation_level: IsolationLevel::Gaming,
-                    privilege_mode: PrivilegeMode::Gaming,
-                    allowed_syscalls: vec![],
-                    device_permissions: vec![],
-                    mandatory_access_control: false,
-                },
-                gaming: Some(GamingCapsuleConfig {
-                    gpu_passthrough: true,
-                    audio_passthrough: true,
-                    input_devices: vec!["/dev/input".to_string()],
-                    display_server: DisplayServer::Both,
-                    performance_mode: PerformanceMode::Gaming,
-                    anti_cheat_compat: true,
-                    steam_integration: true,
-                    wine_config: None,
-                }),
-            },
-            initialization_scripts: vec![],
-            required_capabilities: vec!["GPU".to_string(), "AUDIO".to_string()],
-        };
-
-        self.templates.insert("gaming".to_string(), gaming_template);
+        for template in templates::builtin_templates() {
+            self.templates.insert(template.name.clone(), template);
+        }
+        for template in templates::load_user_templates(&self.root_path) {
+            self.templates.insert(template.name.clone(), template);
+        }
 
         info!("✅ Built-in templates loaded");
         Ok(())
@@ -758,4 +729,195 @@ impl CapsuleManager {
     pub fn get_capsule(&self, capsule_id: &str) -> Option<&CapsuleState> {
         self.capsules.get(capsule_id)
     }
+
+    /// Track a capsule created by `bolt capsule create`, with the snapshots
+    /// already recorded for it. Its name is its ID.
+    pub fn load_instance(&mut self, name: &str, status: CapsuleStatus) -> Result<&CapsuleState> {
+        let path = self
+            .root_path
+            .join("instances")
+            .join(format!("{}.toml", name));
+        let text = std::fs::read_to_string(&path).with_context(|| {
+            format!("No capsule named {} ({} is missing)", name, path.display())
+        })?;
+        let config: CapsuleConfig =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        let capsule_type = config
+            .template
+            .as_ref()
+            .and_then(|t| self.templates.get(t))
+            .map_or(CapsuleType::Standard, |t| t.capsule_type.clone());
+
+        let mut snapshots: Vec<SnapshotMetadata> = export::list_records(&self.root_path)?
+            .into_iter()
+            .filter(|r| r.capsule_id == name)
+            .map(|r| r.snapshot)
+            .collect();
+        snapshots.sort_by_key(|s| s.created_at);
+
+        let state = CapsuleState {
+            id: name.to_string(),
+            name: name.to_string(),
+            capsule_type,
+            status,
+            config,
+            runtime_info: CapsuleRuntimeInfo {
+                pid: None,
+                vm_id: None,
+                start_time: chrono::Utc::now(),
+                uptime_seconds: 0,
+                memory_usage_mb: 0,
+                cpu_usage_percent: 0.0,
+                network_stats: NetworkStats {
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    packets_sent: 0,
+                    packets_received: 0,
+                    latency_ms: 0.0,
+                },
+                migration_state: None,
+            },
+            snapshots,
+        };
+        self.capsules.insert(name.to_string(), state);
+        Ok(&self.capsules[name])
+    }
+
+    /// Record a snapshot whose memory image is compressed and deduplicated
+    /// against earlier snapshots of the same capsule
+    pub fn create_memory_snapshot(
+        &mut self,
+        capsule_id: &str,
+        memory_image: &std::path::Path,
+        name: Option<String>,
+        description: &str,
+    ) -> Result<SnapshotMetadata> {
+        let store_path = self.root_path.join("snapshots").join(capsule_id);
+        let capsule = self
+            .capsules
+            .get_mut(capsule_id)
+            .ok_or_else(|| anyhow::anyhow!("Capsule not found: {}", capsule_id))?;
+
+        let processor = snapshots::MemorySnapshotProcessor::new(
+            store_path,
+            capsule.config.storage.snapshot_policy.memory_compression.clone(),
+        )?;
+
+        let snapshot_id = Uuid::new_v4().to_string();
+        let (_, stats) = processor.process(&snapshot_id, memory_image)?;
+
+        let metadata = SnapshotMetadata {
+            id: snapshot_id,
+            name,
+            created_at: chrono::Utc::now(),
+            size_bytes: stats.stored_bytes,
+            description: description.to_string(),
+            capsule_state: capsule.status.clone(),
+            memory_included: true,
+            parent_snapshot: capsule.snapshots.last().map(|s| s.id.clone()),
+            memory_stats: Some(stats),
+        };
+
+        capsule.snapshots.push(metadata.clone());
+        export::save_record(
+            &self.root_path,
+            &export::SnapshotRecord {
+                capsule_id: capsule.id.clone(),
+                capsule_name: capsule.name.clone(),
+                capsule_type: capsule.capsule_type.clone(),
+                capsule_config: capsule.config.clone(),
+                snapshot: metadata.clone(),
+            },
+        )?;
+
+        // Enforce the retention limit, releasing blocks only the oldest snapshots used
+        let max_snapshots = capsule.config.storage.snapshot_policy.max_snapshots as usize;
+        while max_snapshots > 0 && capsule.snapshots.len() > max_snapshots {
+            let expired = capsule.snapshots.remove(0);
+            export::remove_record(&self.root_path, capsule_id, &expired.id)?;
+            let reclaimed = processor.remove(&expired.id)?;
+            debug!(
+                "Expired snapshot {} of capsule {} ({} bytes reclaimed)",
+                expired.id, capsule_id, reclaimed
+            );
+        }
+
+        Ok(metadata)
+    }
+
+    /// Checkpoint the container running a capsule with CRIU and record it
+    /// among the capsule's snapshots. The checkpoint's directory is what a
+    /// migration ships to the target host to restore there.
+    pub async fn checkpoint_capsule(
+        &mut self,
+        capsule_id: &str,
+        runtime: &str,
+        container: &str,
+        options: crate::runtime::checkpoint::CheckpointOptions,
+    ) -> Result<SnapshotMetadata> {
+        let capsule = self
+            .capsules
+            .get(capsule_id)
+            .ok_or_else(|| anyhow::anyhow!("Capsule not found: {}", capsule_id))?;
+        let snapshot_id = Uuid::new_v4().to_string();
+        let dir = self.checkpoint_dir(capsule_id, &snapshot_id);
+        let manifest =
+            crate::runtime::checkpoint::checkpoint(runtime, container, &dir, options).await?;
+
+        let metadata = SnapshotMetadata {
+            id: snapshot_id,
+            name: None,
+            created_at: manifest.created_at,
+            size_bytes: manifest.size,
+            description: format!("CRIU checkpoint of {}", container),
+            capsule_state: capsule.status.clone(),
+            memory_included: true,
+            parent_snapshot: capsule.snapshots.last().map(|s| s.id.clone()),
+            memory_stats: None,
+        };
+        if let Some(capsule) = self.capsules.get_mut(capsule_id) {
+            capsule.snapshots.push(metadata.clone());
+        }
+        Ok(metadata)
+    }
+
+    /// Restore a capsule checkpoint taken by `checkpoint_capsule`, as
+    /// `name` or the container it was taken from
+    pub async fn restore_capsule_checkpoint(
+        &self,
+        capsule_id: &str,
+        snapshot_id: &str,
+        runtime: &str,
+        data_dir: &std::path::Path,
+        name: Option<&str>,
+    ) -> Result<String> {
+        let dir = self.checkpoint_dir(capsule_id, snapshot_id);
+        crate::runtime::checkpoint::restore(runtime, data_dir, &dir, name).await
+    }
+
+    fn checkpoint_dir(&self, capsule_id: &str, snapshot_id: &str) -> PathBuf {
+        self.root_path
+            .join("checkpoints")
+            .join(capsule_id)
+            .join(snapshot_id)
+    }
+
+    /// Restore the memory image of a capsule snapshot to `output`
+    pub fn restore_memory_snapshot(
+        &self,
+        capsule_id: &str,
+        snapshot_id: &str,
+        output: &std::path::Path,
+    ) -> Result<()> {
+        let capsule = self
+            .capsules
+            .get(capsule_id)
+            .ok_or_else(|| anyhow::anyhow!("Capsule not found: {}", capsule_id))?;
+
+        let processor = snapshots::MemorySnapshotProcessor::new(
+            self.root_path.join("snapshots").join(capsule_id),
+            capsule.config.storage.snapshot_policy.memory_compression.clone(),
+        )?;
+        processor.restore(snapshot_id, output)
+    }
 }
@@ -1,2 +1,474 @@
 // Snapshot/restore system for Bolt Capsules
 pub use anyhow::Result;
+
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Tuning for memory snapshot post-processing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryCompressionConfig {
+    pub zero_page_elimination: bool,
+    /// zstd level (1-22); 0 stores blocks uncompressed
+    pub compression_level: i32,
+    /// Block size used for zero detection and dedup, multiple of the page size
+    pub block_size: usize,
+    /// Number of parallel compression workers (0 = one per CPU)
+    pub workers: usize,
+}
+
+impl Default for MemoryCompressionConfig {
+    fn default() -> Self {
+        Self {
+            zero_page_elimination: true,
+            compression_level: 3,
+            block_size: 64 * 1024,
+            workers: 0,
+        }
+    }
+}
+
+/// Space savings reported for a processed memory snapshot
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemorySnapshotStats {
+    pub original_bytes: u64,
+    pub stored_bytes: u64,
+    pub zero_blocks: u64,
+    pub deduplicated_blocks: u64,
+    pub compressed_blocks: u64,
+    pub compression_level: i32,
+}
+
+impl MemorySnapshotStats {
+    pub fn savings_bytes(&self) -> u64 {
+        self.original_bytes.saturating_sub(self.stored_bytes)
+    }
+
+    pub fn savings_percent(&self) -> f64 {
+        if self.original_bytes == 0 {
+            return 0.0;
+        }
+        self.savings_bytes() as f64 / self.original_bytes as f64 * 100.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MemoryBlock {
+    Zero,
+    Stored(String),
+}
+
+/// On-disk description of a memory image as an ordered list of blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryManifest {
+    pub snapshot_id: String,
+    pub block_size: usize,
+    pub total_size: u64,
+    pub blocks: Vec<MemoryBlock>,
+}
+
+/// Post-processes capsule memory images into a per-capsule block store.
+///
+/// Blocks are keyed by their BLAKE3 hash, so snapshots of the same capsule
+/// share any memory that did not change between them.
+#[derive(Debug)]
+pub struct MemorySnapshotProcessor {
+    pub store_path: PathBuf,
+    pub config: MemoryCompressionConfig,
+}
+
+impl MemorySnapshotProcessor {
+    pub fn new(store_path: PathBuf, config: MemoryCompressionConfig) -> Result<Self> {
+        if config.block_size == 0 || config.block_size % 4096 != 0 {
+            return Err(anyhow!(
+                "Memory snapshot block size must be a non-zero multiple of 4096, got {}",
+                config.block_size
+            ));
+        }
+
+        std::fs::create_dir_all(store_path.join("blocks"))
+            .context("Failed to create memory block store")?;
+
+        Ok(Self { store_path, config })
+    }
+
+    /// Compress and dedup a raw memory image, writing its manifest next to the block store
+    pub fn process(
+        &self,
+        snapshot_id: &str,
+        memory_image: &Path,
+    ) -> Result<(MemoryManifest, MemorySnapshotStats)> {
+        info!(
+            "🗜️  Processing memory snapshot {} (level {}, block {} KiB)",
+            snapshot_id,
+            self.config.compression_level,
+            self.config.block_size / 1024
+        );
+
+        let mut file = File::open(memory_image)
+            .with_context(|| format!("Failed to open memory image {:?}", memory_image))?;
+        let total_size = file.metadata()?.len();
+
+        let mut stats = MemorySnapshotStats {
+            original_bytes: total_size,
+            compression_level: self.config.compression_level,
+            ..Default::default()
+        };
+        let mut blocks = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut pending: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut buf = vec![0u8; self.config.block_size];
+
+        loop {
+            let n = read_full(&mut file, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let block = &buf[..n];
+
+            if self.config.zero_page_elimination && block.iter().all(|&b| b == 0) {
+                stats.zero_blocks += 1;
+                blocks.push(MemoryBlock::Zero);
+                continue;
+            }
+
+            let hash = blake3::hash(block).to_hex().to_string();
+            if seen.contains(&hash) || self.stored_block_file(&hash).is_some() {
+                stats.deduplicated_blocks += 1;
+            } else {
+                pending.push((hash.clone(), block.to_vec()));
+            }
+
+            seen.insert(hash.clone());
+            blocks.push(MemoryBlock::Stored(hash));
+
+            if pending.len() >= self.worker_count() * 16 {
+                stats.compressed_blocks += pending.len() as u64;
+                stats.stored_bytes += self.write_blocks(std::mem::take(&mut pending))?;
+            }
+        }
+
+        stats.compressed_blocks += pending.len() as u64;
+        stats.stored_bytes += self.write_blocks(pending)?;
+
+        let manifest = MemoryManifest {
+            snapshot_id: snapshot_id.to_string(),
+            block_size: self.config.block_size,
+            total_size,
+            blocks,
+        };
+
+        let manifest_json = serde_json::to_string(&manifest)?;
+        stats.stored_bytes += manifest_json.len() as u64;
+        std::fs::write(self.manifest_path(snapshot_id), manifest_json)?;
+
+        info!(
+            "✅ Memory snapshot {}: {} → {} bytes ({:.1}% saved, {} zero, {} dedup blocks)",
+            snapshot_id,
+            stats.original_bytes,
+            stats.stored_bytes,
+            stats.savings_percent(),
+            stats.zero_blocks,
+            stats.deduplicated_blocks
+        );
+
+        Ok((manifest, stats))
+    }
+
+    /// Reassemble the raw memory image of a processed snapshot
+    pub fn restore(&self, snapshot_id: &str, output: &Path) -> Result<()> {
+        let manifest = self.load_manifest(snapshot_id)?;
+        let mut out = File::create(output)
+            .with_context(|| format!("Failed to create memory image {:?}", output))?;
+        let zero_block = vec![0u8; manifest.block_size];
+        let mut remaining = manifest.total_size;
+
+        for block in &manifest.blocks {
+            let len = remaining.min(manifest.block_size as u64) as usize;
+            match block {
+                MemoryBlock::Zero => out.write_all(&zero_block[..len])?,
+                MemoryBlock::Stored(hash) => {
+                    let data = self.read_block(hash)?;
+                    if data.len() != len {
+                        return Err(anyhow!(
+                            "Memory block {} has unexpected size {} (expected {})",
+                            hash,
+                            data.len(),
+                            len
+                        ));
+                    }
+                    out.write_all(&data)?;
+                }
+            }
+            remaining -= len as u64;
+        }
+
+        debug!("Restored memory snapshot {} to {:?}", snapshot_id, output);
+        Ok(())
+    }
+
+    /// Remove a snapshot manifest and any blocks no other snapshot references
+    pub fn remove(&self, snapshot_id: &str) -> Result<u64> {
+        let manifest_path = self.manifest_path(snapshot_id);
+        if manifest_path.exists() {
+            std::fs::remove_file(&manifest_path)?;
+        }
+        self.prune_blocks()
+    }
+
+    /// Delete blocks that are not referenced by any manifest, returning bytes reclaimed
+    pub fn prune_blocks(&self) -> Result<u64> {
+        let mut referenced = HashSet::new();
+        for entry in std::fs::read_dir(&self.store_path)? {
+            let path = entry?.path();
+            if path.to_string_lossy().ends_with(".memory.json") {
+                let manifest: MemoryManifest =
+                    serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+                for block in manifest.blocks {
+                    if let MemoryBlock::Stored(hash) = block {
+                        referenced.insert(hash);
+                    }
+                }
+            }
+        }
+
+        let mut reclaimed = 0;
+        for entry in std::fs::read_dir(self.store_path.join("blocks"))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let hash = name.trim_end_matches(".zst").trim_end_matches(".raw");
+            if !referenced.contains(hash) {
+                reclaimed += entry.metadata()?.len();
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(reclaimed)
+    }
+
+    pub fn load_manifest(&self, snapshot_id: &str) -> Result<MemoryManifest> {
+        let path = self.manifest_path(snapshot_id);
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Memory manifest not found for snapshot {}", snapshot_id))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_manifest(&self, manifest: &MemoryManifest) -> Result<()> {
+        std::fs::write(
+            self.manifest_path(&manifest.snapshot_id),
+            serde_json::to_string(manifest)?,
+        )?;
+        Ok(())
+    }
+
+    /// On-disk file holding a block, whichever encoding it was stored with
+    pub fn stored_block_file(&self, hash: &str) -> Option<PathBuf> {
+        let blocks = self.store_path.join("blocks");
+        [format!("{}.zst", hash), format!("{}.raw", hash)]
+            .into_iter()
+            .map(|name| blocks.join(name))
+            .find(|path| path.exists())
+    }
+
+    /// Add a block received from another store, verifying its content hash.
+    /// Returns false when the block was already present.
+    pub fn import_block(&self, file_name: &str, encoded: &[u8]) -> Result<bool> {
+        let (hash, ext) = file_name
+            .rsplit_once('.')
+            .ok_or_else(|| anyhow!("Invalid block file name: {}", file_name))?;
+
+        let raw = match ext {
+            "zst" => zstd::decode_all(encoded)
+                .with_context(|| format!("Memory block {} is not valid zstd", hash))?,
+            "raw" => encoded.to_vec(),
+            _ => return Err(anyhow!("Unknown memory block encoding: {}", file_name)),
+        };
+        if blake3::hash(&raw).to_hex().as_str() != hash {
+            return Err(anyhow!("Memory block {} failed digest verification", hash));
+        }
+
+        if self.stored_block_file(hash).is_some() {
+            return Ok(false);
+        }
+
+        let path = self.store_path.join("blocks").join(file_name);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, encoded)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(true)
+    }
+
+    fn manifest_path(&self, snapshot_id: &str) -> PathBuf {
+        self.store_path.join(format!("{}.memory.json", snapshot_id))
+    }
+
+    /// Where a new block goes, encoded as the current config says
+    fn new_block_path(&self, hash: &str) -> PathBuf {
+        let ext = if self.config.compression_level > 0 {
+            "zst"
+        } else {
+            "raw"
+        };
+        self.store_path
+            .join("blocks")
+            .join(format!("{}.{}", hash, ext))
+    }
+
+    fn read_block(&self, hash: &str) -> Result<Vec<u8>> {
+        let blocks = self.store_path.join("blocks");
+        let compressed = blocks.join(format!("{}.zst", hash));
+        if compressed.exists() {
+            Ok(zstd::decode_all(File::open(&compressed)?)?)
+        } else {
+            std::fs::read(blocks.join(format!("{}.raw", hash)))
+                .with_context(|| format!("Memory block {} missing from store", hash))
+        }
+    }
+
+    fn worker_count(&self) -> usize {
+        if self.config.workers == 0 {
+            num_cpus::get()
+        } else {
+            self.config.workers
+        }
+    }
+
+    /// Compress a batch of blocks across the worker pool; returns bytes written
+    fn write_blocks(&self, blocks: Vec<(String, Vec<u8>)>) -> Result<u64> {
+        if blocks.is_empty() {
+            return Ok(0);
+        }
+
+        let workers = self.worker_count().min(blocks.len());
+        let level = self.config.compression_level;
+        let chunk_size = blocks.len().div_ceil(workers);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = blocks
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || -> Result<u64> {
+                        let mut written = 0u64;
+                        for (hash, data) in chunk {
+                            // Possibly stored under another compression level
+                            if self.stored_block_file(hash).is_some() {
+                                continue;
+                            }
+                            let path = self.new_block_path(hash);
+                            let encoded = if level > 0 {
+                                zstd::bulk::compress(data, level)?
+                            } else {
+                                data.clone()
+                            };
+                            let tmp = path.with_extension("tmp");
+                            std::fs::write(&tmp, &encoded)?;
+                            std::fs::rename(&tmp, &path)?;
+                            written += encoded.len() as u64;
+                        }
+                        Ok(written)
+                    })
+                })
+                .collect();
+
+            handles.into_iter().try_fold(0u64, |total, handle| {
+                let written = handle
+                    .join()
+                    .map_err(|_| anyhow!("Memory compression worker panicked"))??;
+                Ok(total + written)
+            })
+        })
+    }
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_memory_snapshot_roundtrip_and_dedup() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = MemoryCompressionConfig {
+            block_size: 4096,
+            workers: 2,
+            ..Default::default()
+        };
+        let processor =
+            MemorySnapshotProcessor::new(temp_dir.path().join("capsule"), config).unwrap();
+
+        // Two zero pages, a repeated page and a partial tail page
+        let mut image = vec![0u8; 8192];
+        image.extend(std::iter::repeat_n(0xAB, 4096));
+        image.extend(std::iter::repeat_n(0xAB, 4096));
+        image.extend((0..100u8).collect::<Vec<_>>());
+        let image_path = temp_dir.path().join("mem.img");
+        std::fs::write(&image_path, &image).unwrap();
+
+        let (manifest, stats) = processor.process("snap1", &image_path).unwrap();
+        assert_eq!(manifest.blocks.len(), 5);
+        assert_eq!(stats.zero_blocks, 2);
+        assert_eq!(stats.deduplicated_blocks, 1);
+        assert!(stats.stored_bytes < stats.original_bytes);
+
+        // A second snapshot of identical memory reuses every stored block
+        let (_, second) = processor.process("snap2", &image_path).unwrap();
+        assert_eq!(second.deduplicated_blocks, 3);
+
+        let restored = temp_dir.path().join("restored.img");
+        processor.restore("snap1", &restored).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), image);
+
+        processor.remove("snap1").unwrap();
+        processor.restore("snap2", &restored).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), image);
+
+        // Blocks stored uncompressed still dedup once compression is turned on
+        let store = temp_dir.path().join("levels");
+        let raw = MemorySnapshotProcessor::new(
+            store.clone(),
+            MemoryCompressionConfig {
+                block_size: 4096,
+                compression_level: 0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        raw.process("raw", &image_path).unwrap();
+        let compressed = MemorySnapshotProcessor::new(
+            store.clone(),
+            MemoryCompressionConfig {
+                block_size: 4096,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (_, stats) = compressed.process("zst", &image_path).unwrap();
+        assert_eq!(stats.deduplicated_blocks, 3);
+        assert_eq!(stats.compressed_blocks, 0);
+        assert!(
+            !store
+                .join("blocks")
+                .read_dir()
+                .unwrap()
+                .any(|e| { e.unwrap().path().extension().is_some_and(|x| x == "zst") })
+        );
+        compressed.restore("zst", &restored).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), image);
+    }
+}
@@ -1,2 +1,616 @@
 // Capsule templates system
+//
+// Development templates name a base image, the shell to drop into and the
+// commands that install the toolchains on top. `bolt dev enter` runs those
+// commands once and keeps the result as a warm snapshot, so they only cost
+// time again when the template or the repository's `.bolt/dev.toml` changes.
+//
+// Capsule templates are a `CapsuleTemplate`: a capsule type, a full
+// `CapsuleConfig` and the scripts that finish setting up a new capsule.
+// Besides the built-in ones, users keep their own as TOML files under
+// `<capsules root>/templates/<name>.toml`, written by `bolt capsule template
+// create` and `import` and checked against `CapsuleConfig` on the way in.
+// `bolt capsule create --template <name>` runs a capsule from one: a
+// long-running container with the template's image, resources, shared
+// folders and devices, in which the initialization scripts are then run.
 pub use anyhow::Result;
+
+use anyhow::{Context, anyhow};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use super::{
+    CachePolicy, CapsuleConfig, CapsuleNetworking, CapsuleResources, CapsuleSecurity,
+    CapsuleStorage, CapsuleTemplate, CapsuleType, DiskConfig, DiskType, DisplayServer, DnsConfig,
+    GamingCapsuleConfig, IsolationLevel, NetworkType, PerformanceMode, PrivilegeMode,
+    SnapshotPolicy, WineConfig, snapshots,
+};
+use crate::optimizations::hybrid::{CorePlacement, EnergyPerformancePreference};
+
+/// Label naming the template a capsule container was created from
+pub const LABEL: &str = "bolt.capsule-template";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub image: &'static str,
+    pub shell: &'static str,
+    /// Run in order by `/bin/sh -c` when the warm snapshot is built
+    pub setup: &'static [&'static str],
+}
+
+const DEV_TOOLS: &str = "apt-get update && apt-get install -y --no-install-recommends \
+     git openssh-client ca-certificates curl less && rm -rf /var/lib/apt/lists/*";
+
+pub const DEV_TEMPLATES: &[DevTemplate] = &[
+    DevTemplate {
+        name: "base",
+        description: "Debian with git and ssh",
+        image: "docker.io/library/debian:bookworm",
+        shell: "/bin/bash",
+        setup: &[DEV_TOOLS],
+    },
+    DevTemplate {
+        name: "rust",
+        description: "Rust stable with clippy, rustfmt and rust-analyzer",
+        image: "docker.io/library/rust:1-bookworm",
+        shell: "/bin/bash",
+        setup: &[
+            DEV_TOOLS,
+            "rustup component add clippy rustfmt rust-analyzer",
+        ],
+    },
+    DevTemplate {
+        name: "node",
+        description: "Node.js LTS with corepack (pnpm, yarn)",
+        image: "docker.io/library/node:lts-bookworm",
+        shell: "/bin/bash",
+        setup: &[DEV_TOOLS, "corepack enable"],
+    },
+    DevTemplate {
+        name: "python",
+        description: "Python 3 with pytest, ruff and ipython",
+        image: "docker.io/library/python:3-bookworm",
+        shell: "/bin/bash",
+        setup: &[DEV_TOOLS, "pip install --no-cache-dir pytest ruff ipython"],
+    },
+    DevTemplate {
+        name: "go",
+        description: "Go with gopls",
+        image: "docker.io/library/golang:1-bookworm",
+        shell: "/bin/bash",
+        setup: &[DEV_TOOLS, "go install golang.org/x/tools/gopls@latest"],
+    },
+];
+
+/// Built-in development template by name
+pub fn dev_template(name: &str) -> Option<&'static DevTemplate> {
+    DEV_TEMPLATES.iter().find(|t| t.name == name)
+}
+
+/// Capsule templates that ship with Bolt; user templates can't replace them
+pub fn builtin_templates() -> Vec<CapsuleTemplate> {
+    let gaming = CapsuleTemplate {
+        name: "gaming".to_string(),
+        description: "Gaming-optimized capsule with GPU/audio passthrough".to_string(),
+        capsule_type: CapsuleType::Gaming,
+        base_config: CapsuleConfig {
+            template: None,
+            image: "bolt://gaming-base:latest".to_string(),
+            resources: CapsuleResources {
+                memory_mb: 8192,
+                vcpus: 4,
+                cpu_shares: 2048,
+                memory_balloon: false,
+                cpu_hotplug: false,
+                numa_topology: None,
+                core_placement: CorePlacement::PCores,
+                energy_performance_preference: Some(EnergyPerformancePreference::Performance),
+            },
+            networking: CapsuleNetworking {
+                network_type: NetworkType::QuicFabric,
+                interfaces: vec![],
+                dns_config: DnsConfig {
+                    servers: vec!["1.1.1.1".to_string()],
+                    search_domains: vec![],
+                    bolt_dns_enabled: true,
+                },
+                firewall_rules: vec![],
+            },
+            storage: CapsuleStorage {
+                root_disk: DiskConfig {
+                    name: "root".to_string(),
+                    size_gb: 100,
+                    disk_type: DiskType::NVMe,
+                    encryption: true,
+                    compression: false,
+                    cache_policy: CachePolicy::Gaming,
+                },
+                data_disks: vec![],
+                shared_folders: vec![],
+                snapshot_policy: SnapshotPolicy {
+                    auto_snapshot: true,
+                    interval_minutes: 30,
+                    max_snapshots: 10,
+                    compress_snapshots: false,
+                    memory_compression: snapshots::MemoryCompressionConfig {
+                        compression_level: 1,
+                        ..Default::default()
+                    },
+                },
+            },
+            security: CapsuleSecurity {
+                isolation_level: IsolationLevel::Gaming,
+                privilege_mode: PrivilegeMode::Gaming,
+                allowed_syscalls: vec![],
+                device_permissions: vec![],
+                mandatory_access_control: false,
+            },
+            gaming: Some(GamingCapsuleConfig {
+                gpu_passthrough: true,
+                audio_passthrough: true,
+                input_devices: vec!["/dev/input".to_string()],
+                display_server: DisplayServer::Both,
+                performance_mode: PerformanceMode::Gaming,
+                anti_cheat_compat: true,
+                steam_integration: true,
+                wine_config: None,
+            }),
+        },
+        initialization_scripts: vec![],
+        required_capabilities: vec!["GPU".to_string(), "AUDIO".to_string()],
+    };
+
+    let mut win10 = gaming.clone();
+    win10.name = "gaming-win10".to_string();
+    win10.description = "Gaming capsule with a Wine prefix set up as Windows 10".to_string();
+    win10.base_config.image = "docker.io/scottyhardy/docker-wine:latest".to_string();
+    if let Some(ref mut gaming) = win10.base_config.gaming {
+        gaming.wine_config = Some(WineConfig {
+            version: "stable".to_string(),
+            prefix_path: "/wine".to_string(),
+            windows_version: "win10".to_string(),
+            dxvk_enabled: true,
+            esync_enabled: true,
+            fsync_enabled: true,
+        });
+    }
+    win10.initialization_scripts = vec![
+        "WINEPREFIX=/wine wineboot --init".to_string(),
+        "WINEPREFIX=/wine winecfg /v win10".to_string(),
+    ];
+
+    vec![gaming, win10]
+}
+
+fn is_builtin(name: &str) -> bool {
+    builtin_templates().iter().any(|t| t.name == name)
+}
+
+fn templates_dir(root: &Path) -> PathBuf {
+    root.join("templates")
+}
+
+fn template_path(root: &Path, name: &str) -> PathBuf {
+    templates_dir(root).join(format!("{}.toml", name))
+}
+
+/// Templates and capsules are named like containers
+fn check_name(name: &str) -> Result<()> {
+    let valid = name.len() <= 64
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid name '{}': use up to 64 letters, digits, '-', '_' and '.', starting with a letter or digit",
+            name
+        ))
+    }
+}
+
+/// Check what deserializing can't: sizes, paths and that the type has
+/// what it needs
+pub fn validate(template: &CapsuleTemplate) -> Result<()> {
+    check_name(&template.name)?;
+    let config = &template.base_config;
+    let mut errors = Vec::new();
+    if config.image.trim().is_empty() {
+        errors.push("image is empty".to_string());
+    }
+    if config.resources.memory_mb < 64 {
+        errors.push(format!(
+            "resources.memory_mb is {}; at least 64 is needed",
+            config.resources.memory_mb
+        ));
+    }
+    if config.resources.vcpus == 0 {
+        errors.push("resources.vcpus must be at least 1".to_string());
+    }
+    if !(2..=262_144).contains(&config.resources.cpu_shares) {
+        errors.push(format!(
+            "resources.cpu_shares is {}; use 2 to 262144",
+            config.resources.cpu_shares
+        ));
+    }
+    for disk in std::iter::once(&config.storage.root_disk).chain(&config.storage.data_disks) {
+        if disk.size_gb == 0 {
+            errors.push(format!("disk '{}' has a size of 0 GB", disk.name));
+        }
+    }
+    for folder in &config.storage.shared_folders {
+        if !folder.host_path.starts_with('/') || !folder.capsule_path.starts_with('/') {
+            errors.push(format!(
+                "shared folder {} -> {} must use absolute paths",
+                folder.host_path, folder.capsule_path
+            ));
+        }
+    }
+    match (&template.capsule_type, &config.gaming) {
+        (CapsuleType::Gaming, None) => {
+            errors.push("a Gaming capsule needs a [base_config.gaming] table".to_string())
+        }
+        (_, Some(gaming)) => {
+            if let Some(ref wine) = gaming.wine_config {
+                if !wine.prefix_path.starts_with('/') {
+                    errors.push(format!(
+                        "wine_config.prefix_path '{}' must be absolute",
+                        wine.prefix_path
+                    ));
+                }
+                if wine.windows_version.trim().is_empty() {
+                    errors.push("wine_config.windows_version is empty".to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid capsule template '{}': {}",
+            template.name,
+            errors.join("; ")
+        ))
+    }
+}
+
+/// Read a template from TOML and validate it
+pub fn parse(toml_text: &str) -> Result<CapsuleTemplate> {
+    let template: CapsuleTemplate =
+        toml::from_str(toml_text).context("Not a valid capsule template")?;
+    validate(&template)?;
+    Ok(template)
+}
+
+pub fn to_toml(template: &CapsuleTemplate) -> Result<String> {
+    Ok(toml::to_string_pretty(template)?)
+}
+
+/// Templates stored under the capsules root; invalid files are skipped
+pub fn load_user_templates(root: &Path) -> Vec<CapsuleTemplate> {
+    let Ok(entries) = std::fs::read_dir(templates_dir(root)) else {
+        return Vec::new();
+    };
+    let mut templates: Vec<CapsuleTemplate> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|x| x == "toml"))
+        .filter_map(|path| {
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| parse(&text));
+            match loaded {
+                Ok(template) if is_builtin(&template.name) => {
+                    warn!(
+                        "Ignoring {}: '{}' is a built-in template",
+                        path.display(),
+                        template.name
+                    );
+                    None
+                }
+                Ok(template) => Some(template),
+                Err(e) => {
+                    warn!("Ignoring capsule template {}: {:#}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Store a user template, replacing one of the same name only with `force`
+pub fn save(root: &Path, template: &CapsuleTemplate, force: bool) -> Result<PathBuf> {
+    validate(template)?;
+    if is_builtin(&template.name) {
+        return Err(anyhow!(
+            "'{}' is a built-in template; pick another name",
+            template.name
+        ));
+    }
+    let path = template_path(root, &template.name);
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "Template '{}' already exists; use --force to replace it",
+            template.name
+        ));
+    }
+    std::fs::create_dir_all(templates_dir(root))?;
+    std::fs::write(&path, to_toml(template)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("📋 Saved capsule template {}", template.name);
+    Ok(path)
+}
+
+pub fn remove(root: &Path, name: &str) -> Result<()> {
+    if is_builtin(name) {
+        return Err(anyhow!("'{}' is a built-in template", name));
+    }
+    let path = template_path(root, name);
+    std::fs::remove_file(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => anyhow!("No capsule template named '{}'", name),
+        _ => anyhow::Error::from(e).context(format!("Failed to remove {}", path.display())),
+    })
+}
+
+/// A built-in or user template by name
+pub fn find(root: &Path, name: &str) -> Result<CapsuleTemplate> {
+    builtin_templates()
+        .into_iter()
+        .chain(load_user_templates(root))
+        .find(|t| t.name == name)
+        .ok_or_else(|| anyhow!("No capsule template named '{}'", name))
+}
+
+/// Changes to a template when deriving another or creating a capsule
+#[derive(Debug, Clone, Default)]
+pub struct TemplateOverrides {
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub memory_mb: Option<u64>,
+    pub vcpus: Option<u32>,
+}
+
+impl TemplateOverrides {
+    pub fn apply(&self, template: &mut CapsuleTemplate) {
+        if let Some(ref description) = self.description {
+            template.description = description.clone();
+        }
+        if let Some(ref image) = self.image {
+            template.base_config.image = image.clone();
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            template.base_config.resources.memory_mb = memory_mb;
+        }
+        if let Some(vcpus) = self.vcpus {
+            template.base_config.resources.vcpus = vcpus;
+        }
+    }
+}
+
+/// `run` arguments for a capsule container; `gpu` is how this host passes
+/// its GPU through, when it has one
+pub fn run_args(name: &str, template: &CapsuleTemplate, gpu: Option<Vec<String>>) -> Vec<String> {
+    let config = &template.base_config;
+    let mut args = vec![
+        "run".to_string(),
+        "-d".to_string(),
+        "--name".to_string(),
+        name.to_string(),
+        "--hostname".to_string(),
+        name.to_string(),
+        "--label".to_string(),
+        format!("{}={}", LABEL, template.name),
+        "--memory".to_string(),
+        format!("{}m", config.resources.memory_mb),
+        "--cpus".to_string(),
+        config.resources.vcpus.to_string(),
+        "--cpu-shares".to_string(),
+        config.resources.cpu_shares.to_string(),
+    ];
+    if matches!(config.networking.network_type, NetworkType::Host) {
+        args.extend(["--network".to_string(), "host".to_string()]);
+    } else {
+        for server in &config.networking.dns_config.servers {
+            args.extend(["--dns".to_string(), server.clone()]);
+        }
+    }
+    if matches!(config.security.privilege_mode, PrivilegeMode::Privileged) {
+        args.push("--privileged".to_string());
+    }
+    for folder in &config.storage.shared_folders {
+        let mut mount = format!("{}:{}", folder.host_path, folder.capsule_path);
+        if folder.readonly {
+            mount.push_str(":ro");
+        }
+        args.extend(["-v".to_string(), mount]);
+    }
+    let mut devices: Vec<&str> = config
+        .security
+        .device_permissions
+        .iter()
+        .map(|d| d.device_path.as_str())
+        .collect();
+    if let Some(ref gaming) = config.gaming {
+        if gaming.gpu_passthrough {
+            args.extend(gpu.unwrap_or_default());
+        }
+        if gaming.audio_passthrough {
+            devices.push("/dev/snd");
+        }
+        devices.extend(gaming.input_devices.iter().map(String::as_str));
+        if let Some(ref wine) = gaming.wine_config {
+            let flag = |on: bool| if on { "1" } else { "0" };
+            args.extend([
+                "-e".to_string(),
+                format!("WINEPREFIX={}", wine.prefix_path),
+                "-e".to_string(),
+                format!("WINEESYNC={}", flag(wine.esync_enabled)),
+                "-e".to_string(),
+                format!("WINEFSYNC={}", flag(wine.fsync_enabled)),
+            ]);
+        }
+    }
+    for device in devices {
+        // A template is shared between hosts; devices this one lacks are skipped
+        if Path::new(device).exists() {
+            args.extend(["--device".to_string(), device.to_string()]);
+        }
+    }
+    // Capsules are long-lived: keep the container up for exec and SSH
+    args.extend([
+        "--entrypoint".to_string(),
+        "sleep".to_string(),
+        config.image.clone(),
+        "infinity".to_string(),
+    ]);
+    args
+}
+
+/// Run a capsule from a template and its initialization scripts. The
+/// capsule's config is kept under `<capsules root>/instances/<name>.toml`.
+pub async fn instantiate(
+    runtime: &str,
+    root: &Path,
+    name: &str,
+    template: &CapsuleTemplate,
+) -> Result<CapsuleConfig> {
+    check_name(name)?;
+    validate(template)?;
+    if template.base_config.image.starts_with("bolt://") {
+        return Err(anyhow!(
+            "Template '{}' uses the Bolt native image {}, which can't be run yet; derive a template with an OCI image (`bolt capsule template create <name> --from {} --image <image>`)",
+            template.name,
+            template.base_config.image,
+            template.name
+        ));
+    }
+
+    info!(
+        "🔧 Creating capsule {} from template {}",
+        name, template.name
+    );
+    let output = AsyncCommand::new(runtime)
+        .args(run_args(
+            name,
+            template,
+            crate::runtime::bench::gpu_args(runtime),
+        ))
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to create capsule {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    for script in &template.initialization_scripts {
+        info!("  ▶️  {}", script);
+        let output = AsyncCommand::new(runtime)
+            .args(["exec", name, "/bin/sh", "-c", script])
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}", runtime))?;
+        if !output.status.success() {
+            let _ = AsyncCommand::new(runtime)
+                .args(["rm", "-f", name])
+                .output()
+                .await;
+            return Err(anyhow!(
+                "Initialization script '{}' failed in capsule {}, which was removed: {}",
+                script,
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+
+    if let Err(e) = super::console::capture(runtime, root, name).await {
+        warn!("Could not capture the boot output of {}: {}", name, e);
+    }
+
+    let mut config = template.base_config.clone();
+    config.template = Some(template.name.clone());
+    let dir = root.join("instances");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.toml", name));
+    std::fs::write(&path, toml::to_string_pretty(&config)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("✅ Capsule {} created", name);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_round_trip_validate_and_run() {
+        let dir = tempfile::tempdir().unwrap();
+        for template in builtin_templates() {
+            let text = to_toml(&template).unwrap();
+            let parsed = parse(&text).unwrap();
+            assert_eq!(to_toml(&parsed).unwrap(), text);
+        }
+
+        let mut template = find(dir.path(), "gaming-win10").unwrap();
+        assert!(save(dir.path(), &template, false).is_err());
+        template.name = "my-win10".to_string();
+        TemplateOverrides {
+            memory_mb: Some(16384),
+            ..Default::default()
+        }
+        .apply(&mut template);
+        save(dir.path(), &template, false).unwrap();
+        assert!(save(dir.path(), &template, false).is_err());
+        assert_eq!(
+            find(dir.path(), "my-win10")
+                .unwrap()
+                .base_config
+                .resources
+                .memory_mb,
+            16384
+        );
+
+        let mut invalid = template.clone();
+        invalid.base_config.resources.vcpus = 0;
+        invalid.base_config.gaming = None;
+        let error = validate(&invalid).unwrap_err().to_string();
+        assert!(error.contains("vcpus"), "{}", error);
+        assert!(error.contains("Gaming capsule"), "{}", error);
+        assert!(parse("name = \"x\"").is_err());
+
+        let args = run_args("game", &template, Some(vec!["--gpus".into(), "all".into()]));
+        let has = |pair: [&str; 2]| args.windows(2).any(|w| w == pair);
+        assert!(has(["--memory", "16384m"]));
+        assert!(has(["--gpus", "all"]));
+        assert!(has(["-e", "WINEPREFIX=/wine"]));
+        assert!(has(["--label", "bolt.capsule-template=my-win10"]));
+        assert_eq!(
+            args[args.len() - 3..],
+            [
+                "sleep",
+                "docker.io/scottyhardy/docker-wine:latest",
+                "infinity"
+            ]
+        );
+
+        remove(dir.path(), "my-win10").unwrap();
+        assert!(find(dir.path(), "my-win10").is_err());
+        assert!(remove(dir.path(), "gaming").is_err());
+    }
+}
@@ -0,0 +1,732 @@
+// Host-to-host snapshot shipping over QUIC
+//
+// The sender exports a bundle (see `export`) and offers it by SHA-256. The
+// receiver answers with how many bytes of that bundle it already holds, so an
+// interrupted transfer resumes where it stopped. The finished file is checked
+// against the digest and every block against its content hash before import.
+//
+// Both ends authenticate with self-signed certificates: the sender pins the
+// receiver's fingerprint, and the receiver only accepts senders whose
+// fingerprint was authorized with `--allow-sender`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+use super::export;
+
+pub const DEFAULT_TRANSFER_PORT: u16 = 7946;
+const SERVER_NAME: &str = "bolt-snapshot";
+const CHUNK_SIZE: usize = 1024 * 1024;
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024;
+/// Largest bundle a receiver accepts unless told otherwise
+pub const DEFAULT_MAX_BUNDLE_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    Offer {
+        snapshot_id: String,
+        size: u64,
+        sha256: String,
+    },
+    Resume {
+        offset: u64,
+    },
+    AlreadyPresent,
+    Complete {
+        snapshot_id: String,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendSummary {
+    pub snapshot_id: String,
+    pub remote: SocketAddr,
+    pub size_bytes: u64,
+    /// Bytes skipped because the receiver already had them
+    pub resumed_from: u64,
+    pub sha256: String,
+    pub already_present: bool,
+}
+
+/// Ships snapshots to, and accepts them from, other Bolt hosts
+#[derive(Debug, Clone)]
+pub struct SnapshotTransfer {
+    capsules_root: PathBuf,
+    transfers_dir: PathBuf,
+}
+
+impl SnapshotTransfer {
+    pub fn new(capsules_root: PathBuf, transfers_dir: PathBuf) -> Result<Self> {
+        for dir in ["incoming", "outgoing"] {
+            std::fs::create_dir_all(transfers_dir.join(dir))
+                .context("Failed to create snapshot transfer directory")?;
+        }
+        Ok(Self {
+            capsules_root,
+            transfers_dir,
+        })
+    }
+
+    /// Send a snapshot to `to` (`host` or `host:port`), retrying and resuming
+    /// on connection loss. `fingerprint` pins the receiver's certificate;
+    /// without it the fingerprint recorded on first contact is used.
+    pub async fn send(
+        &self,
+        snapshot: &str,
+        to: &str,
+        fingerprint: Option<&str>,
+        retries: u32,
+    ) -> Result<SendSummary> {
+        let record = export::find_record(&self.capsules_root, snapshot)?;
+        let bundle = self
+            .transfers_dir
+            .join("outgoing")
+            .join(format!("{}.bundle", record.snapshot.id));
+
+        // Keep the bundle between attempts so its digest, and the receiver's partial copy, stay valid
+        if !bundle.exists() {
+            export::export_snapshot(&self.capsules_root, &record.snapshot.id, &bundle)?;
+        }
+        let size = std::fs::metadata(&bundle)?.len();
+        let sha256 = export::file_sha256(&bundle)?;
+
+        let remote = resolve(to).await?;
+        let pinned = match fingerprint {
+            Some(fp) => Some(fp.to_lowercase()),
+            None => self.known_hosts()?.get(to).cloned(),
+        };
+
+        let mut attempt = 0;
+        let summary = loop {
+            let verifier = Arc::new(FingerprintVerifier {
+                expected: pinned.clone(),
+                seen: Mutex::new(None),
+            });
+
+            match self
+                .try_send(
+                    &record.snapshot.id,
+                    &bundle,
+                    size,
+                    &sha256,
+                    remote,
+                    verifier.clone(),
+                )
+                .await
+            {
+                Ok(summary) => {
+                    if pinned.is_none() {
+                        if let Some(seen) = verifier.seen.lock().unwrap().clone() {
+                            warn!(
+                                "🔑 Trusting {} on first use (certificate sha256:{})",
+                                to, seen
+                            );
+                            self.remember_host(to, &seen)?;
+                        }
+                    }
+                    break summary;
+                }
+                Err(e) if attempt < retries => {
+                    attempt += 1;
+                    let delay = Duration::from_secs(1 << attempt.min(5));
+                    warn!(
+                        "Snapshot transfer to {} failed ({}), resuming in {:?} (attempt {}/{})",
+                        remote, e, delay, attempt, retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        std::fs::remove_file(&bundle)?;
+        Ok(summary)
+    }
+
+    async fn try_send(
+        &self,
+        snapshot_id: &str,
+        bundle: &Path,
+        size: u64,
+        sha256: &str,
+        remote: SocketAddr,
+        verifier: Arc<FingerprintVerifier>,
+    ) -> Result<SendSummary> {
+        let (cert, key) = self.identity("sender")?;
+        let crypto = quinn_rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(verifier)
+            .with_client_auth_cert(
+                vec![quinn_rustls::Certificate(cert)],
+                quinn_rustls::PrivateKey(key),
+            )?;
+        let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+        client_config.transport_config(transport_config()?);
+
+        let bind: SocketAddr = if remote.is_ipv6() {
+            "[::]:0".parse()?
+        } else {
+            "0.0.0.0:0".parse()?
+        };
+        let mut endpoint = quinn::Endpoint::client(bind)?;
+        endpoint.set_default_client_config(client_config);
+
+        info!("📤 Sending snapshot {} to {}", snapshot_id, remote);
+        let connection = endpoint
+            .connect(remote, SERVER_NAME)?
+            .await
+            .with_context(|| format!("Failed to connect to {}", remote))?;
+        let (mut send, mut recv) = connection.open_bi().await?;
+
+        write_message(
+            &mut send,
+            &Message::Offer {
+                snapshot_id: snapshot_id.to_string(),
+                size,
+                sha256: sha256.to_string(),
+            },
+        )
+        .await?;
+
+        let mut summary = SendSummary {
+            snapshot_id: snapshot_id.to_string(),
+            remote,
+            size_bytes: size,
+            resumed_from: 0,
+            sha256: sha256.to_string(),
+            already_present: false,
+        };
+
+        match read_message(&mut recv).await? {
+            Message::AlreadyPresent => {
+                info!("Receiver already has snapshot {}", snapshot_id);
+                summary.already_present = true;
+            }
+            Message::Resume { offset } => {
+                if offset > 0 {
+                    info!("⏩ Resuming transfer at byte {} of {}", offset, size);
+                }
+                summary.resumed_from = offset;
+
+                let mut file = tokio::fs::File::open(bundle).await?;
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                loop {
+                    let n = file.read(&mut buf).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    send.write_all(&buf[..n]).await?;
+                }
+                send.finish().await?;
+
+                match read_message(&mut recv).await? {
+                    Message::Complete { .. } => {
+                        info!("✅ Snapshot {} delivered to {}", snapshot_id, remote)
+                    }
+                    Message::Failed { reason } => {
+                        return Err(anyhow!("Receiver rejected snapshot: {}", reason));
+                    }
+                    other => return Err(anyhow!("Unexpected reply from receiver: {:?}", other)),
+                }
+            }
+            Message::Failed { reason } => {
+                return Err(anyhow!("Receiver refused snapshot: {}", reason));
+            }
+            other => return Err(anyhow!("Unexpected reply from receiver: {:?}", other)),
+        }
+
+        connection.close(quinn::VarInt::from_u32(0), b"done");
+        endpoint.wait_idle().await;
+        Ok(summary)
+    }
+
+    /// Fingerprint of the certificate this host sends with, for the
+    /// receiving host's `--allow-sender`
+    pub fn sender_fingerprint(&self) -> Result<String> {
+        let (cert, _) = self.identity("sender")?;
+        Ok(fingerprint(&cert))
+    }
+
+    /// Accept snapshots from authorized senders until the process is stopped.
+    /// `allow` adds sender fingerprints to the ones authorized before.
+    pub async fn receive(&self, listen: SocketAddr, allow: &[String], max_size: u64) -> Result<()> {
+        let senders = self.authorize_senders(allow)?;
+        if senders.is_empty() {
+            return Err(anyhow!(
+                "No senders are authorized; run `bolt snapshot fingerprint` on the sending host and pass the result with --allow-sender"
+            ));
+        }
+
+        let (server_config, fingerprint) = self.server_config(senders.clone())?;
+        let endpoint = quinn::Endpoint::server(server_config, listen)
+            .with_context(|| format!("Failed to listen on {}", listen))?;
+
+        info!("📥 Receiving snapshots on {}", listen);
+        info!("  • Certificate fingerprint: {}", fingerprint);
+        info!("  • Authorized senders: {}", senders.len());
+
+        self.serve(endpoint, max_size).await
+    }
+
+    /// TLS 1.3 server config that requires a client certificate from `senders`
+    fn server_config(&self, senders: BTreeSet<String>) -> Result<(quinn::ServerConfig, String)> {
+        let (cert, key) = self.identity("receiver")?;
+        let fingerprint = fingerprint(&cert);
+
+        let mut crypto = quinn_rustls::ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&quinn_rustls::version::TLS13])?
+            .with_client_cert_verifier(Arc::new(SenderVerifier { allowed: senders }))
+            .with_single_cert(
+                vec![quinn_rustls::Certificate(cert)],
+                quinn_rustls::PrivateKey(key),
+            )?;
+        // QUIC requires early data to be either disabled or unlimited
+        crypto.max_early_data_size = u32::MAX;
+
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        server_config.transport_config(transport_config()?);
+        Ok((server_config, fingerprint))
+    }
+
+    async fn serve(&self, endpoint: quinn::Endpoint, max_size: u64) -> Result<()> {
+        while let Some(connecting) = endpoint.accept().await {
+            let transfer = self.clone();
+            tokio::spawn(async move {
+                let remote = connecting.remote_address();
+                let result = async {
+                    let connection = connecting.await?;
+                    let (send, recv) = connection.accept_bi().await?;
+                    transfer.handle_incoming(send, recv, max_size).await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("Snapshot transfer from {} failed: {}", remote, e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn handle_incoming(
+        &self,
+        mut send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+        max_size: u64,
+    ) -> Result<()> {
+        let Message::Offer {
+            snapshot_id,
+            size,
+            sha256,
+        } = read_message(&mut recv).await?
+        else {
+            return Err(anyhow!("Expected a snapshot offer"));
+        };
+
+        // The digest names the partial file, so it must not be able to escape the directory
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            write_message(
+                &mut send,
+                &Message::Failed {
+                    reason: "invalid digest".to_string(),
+                },
+            )
+            .await?;
+            return Err(anyhow!("Invalid digest in offer: {}", sha256));
+        }
+
+        // The announced size comes from the peer; don't let it fill the disk
+        let incoming = self.transfers_dir.join("incoming");
+        let available = free_space(&incoming).unwrap_or(u64::MAX);
+        if size > max_size || size > available {
+            let reason = format!(
+                "bundle of {} bytes exceeds the limit ({} bytes, {} free)",
+                size, max_size, available
+            );
+            write_message(
+                &mut send,
+                &Message::Failed {
+                    reason: reason.clone(),
+                },
+            )
+            .await?;
+            return Err(anyhow!("Refused snapshot {}: {}", snapshot_id, reason));
+        }
+
+        if export::find_record(&self.capsules_root, &snapshot_id).is_ok() {
+            write_message(&mut send, &Message::AlreadyPresent).await?;
+            send.finish().await?;
+            return Ok(());
+        }
+
+        let part = incoming.join(format!("{}.part", sha256.to_lowercase()));
+        let mut offset = tokio::fs::metadata(&part)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        if offset > size {
+            offset = 0;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&part)
+            .await?;
+        file.set_len(offset).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        info!(
+            "📥 Receiving snapshot {} ({} bytes, resuming at {})",
+            snapshot_id, size, offset
+        );
+        write_message(&mut send, &Message::Resume { offset }).await?;
+
+        let mut received = offset;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        while let Some(n) = recv.read(&mut buf).await? {
+            received += n as u64;
+            if received > size {
+                return Err(anyhow!("Sender exceeded announced size of {} bytes", size));
+            }
+            file.write_all(&buf[..n]).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+
+        if received != size {
+            return Err(anyhow!(
+                "Transfer ended after {} of {} bytes; partial data kept for resume",
+                received,
+                size
+            ));
+        }
+
+        let capsules_root = self.capsules_root.clone();
+        let import_path = part.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            export::import_snapshot(&capsules_root, &import_path, Some(&sha256))
+        })
+        .await?;
+        // A corrupt bundle can't be resumed; start over next time
+        let _ = tokio::fs::remove_file(&part).await;
+
+        let reply = match &result {
+            Ok(record) => Message::Complete {
+                snapshot_id: record.snapshot.id.clone(),
+            },
+            Err(e) => Message::Failed {
+                reason: e.to_string(),
+            },
+        };
+        write_message(&mut send, &reply).await?;
+        send.finish().await?;
+
+        result.map(|_| ())
+    }
+
+    /// Self-signed certificate for `role` (`sender` or `receiver`), generated
+    /// once so its fingerprint stays stable
+    fn identity(&self, role: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+        let cert_path = self.transfers_dir.join(format!("{}.crt.der", role));
+        let key_path = self.transfers_dir.join(format!("{}.key.der", role));
+
+        if cert_path.exists() && key_path.exists() {
+            return Ok((std::fs::read(&cert_path)?, std::fs::read(&key_path)?));
+        }
+
+        let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+        let cert_der = cert.serialize_der()?;
+        let key_der = cert.serialize_private_key_der();
+        std::fs::write(&cert_path, &cert_der)?;
+        std::fs::write(&key_path, &key_der)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        debug!("Generated snapshot {} certificate at {:?}", role, cert_path);
+
+        Ok((cert_der, key_der))
+    }
+
+    fn authorized_senders_path(&self) -> PathBuf {
+        self.transfers_dir.join("authorized_senders.json")
+    }
+
+    /// Persist `allow` alongside the senders authorized before and return them all
+    fn authorize_senders(&self, allow: &[String]) -> Result<BTreeSet<String>> {
+        let path = self.authorized_senders_path();
+        let mut senders: BTreeSet<String> = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            BTreeSet::new()
+        };
+
+        let before = senders.len();
+        for fingerprint in allow {
+            let fingerprint = fingerprint.trim_start_matches("sha256:").to_lowercase();
+            if fingerprint.len() != 64 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow!("Invalid sender fingerprint: {}", fingerprint));
+            }
+            senders.insert(fingerprint);
+        }
+        if senders.len() != before {
+            std::fs::write(&path, serde_json::to_string_pretty(&senders)?)?;
+        }
+        Ok(senders)
+    }
+
+    fn known_hosts_path(&self) -> PathBuf {
+        self.transfers_dir.join("known_hosts.json")
+    }
+
+    fn known_hosts(&self) -> Result<HashMap<String, String>> {
+        let path = self.known_hosts_path();
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    fn remember_host(&self, host: &str, fingerprint: &str) -> Result<()> {
+        let mut hosts = self.known_hosts()?;
+        hosts.insert(host.to_string(), fingerprint.to_string());
+        std::fs::write(
+            self.known_hosts_path(),
+            serde_json::to_string_pretty(&hosts)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Accepts the receiver's self-signed certificate if it matches the pinned
+/// fingerprint, or records it when nothing is pinned yet
+#[derive(Debug)]
+struct FingerprintVerifier {
+    expected: Option<String>,
+    seen: Mutex<Option<String>>,
+}
+
+impl quinn_rustls::client::ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &quinn_rustls::Certificate,
+        _intermediates: &[quinn_rustls::Certificate],
+        _server_name: &quinn_rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<quinn_rustls::client::ServerCertVerified, quinn_rustls::Error> {
+        let fingerprint = fingerprint(&end_entity.0);
+        if let Some(ref expected) = self.expected {
+            if !fingerprint.eq_ignore_ascii_case(expected) {
+                return Err(quinn_rustls::Error::General(format!(
+                    "receiver certificate sha256:{} does not match pinned sha256:{}",
+                    fingerprint, expected
+                )));
+            }
+        }
+        *self.seen.lock().unwrap() = Some(fingerprint);
+        Ok(quinn_rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+/// Only lets senders in whose certificate fingerprint was authorized
+#[derive(Debug)]
+struct SenderVerifier {
+    allowed: BTreeSet<String>,
+}
+
+impl quinn_rustls::server::ClientCertVerifier for SenderVerifier {
+    fn client_auth_root_subjects(&self) -> &[quinn_rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &quinn_rustls::Certificate,
+        _intermediates: &[quinn_rustls::Certificate],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<quinn_rustls::server::ClientCertVerified, quinn_rustls::Error> {
+        let fingerprint = fingerprint(&end_entity.0);
+        if self.allowed.contains(&fingerprint) {
+            Ok(quinn_rustls::server::ClientCertVerified::assertion())
+        } else {
+            Err(quinn_rustls::Error::General(format!(
+                "sender certificate sha256:{} is not authorized",
+                fingerprint
+            )))
+        }
+    }
+}
+
+fn fingerprint(cert_der: &[u8]) -> String {
+    hex::encode(Sha256::digest(cert_der))
+}
+
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` is a plain C struct
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+fn transport_config() -> Result<Arc<quinn::TransportConfig>> {
+    let mut transport = quinn::TransportConfig::default();
+    // Importing a large bundle can take a while after the last byte arrives
+    transport.max_idle_timeout(Some(Duration::from_secs(300).try_into()?));
+    transport.keep_alive_interval(Some(Duration::from_secs(10)));
+    Ok(Arc::new(transport))
+}
+
+async fn resolve(to: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = to.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = to.parse::<std::net::IpAddr>() {
+        return Ok(SocketAddr::new(ip, DEFAULT_TRANSFER_PORT));
+    }
+
+    let target = if to.contains(':') {
+        to.to_string()
+    } else {
+        format!("{}:{}", to, DEFAULT_TRANSFER_PORT)
+    };
+
+    tokio::net::lookup_host(&target)
+        .await
+        .with_context(|| format!("Failed to resolve {}", target))?
+        .next()
+        .ok_or_else(|| anyhow!("No address found for {}", target))
+}
+
+async fn write_message(send: &mut quinn::SendStream, message: &Message) -> Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    send.write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    send.write_all(&payload).await?;
+    Ok(())
+}
+
+async fn read_message(recv: &mut quinn::RecvStream) -> Result<Message> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(anyhow!(
+            "Transfer control message too large ({} bytes)",
+            len
+        ));
+    }
+    let mut payload = vec![0u8; len as usize];
+    recv.read_exact(&mut payload).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn host(dir: &Path, name: &str) -> SnapshotTransfer {
+        SnapshotTransfer::new(
+            dir.join(name).join("capsules"),
+            dir.join(name).join("transfers"),
+        )
+        .unwrap()
+    }
+
+    /// Start `receiver` on a loopback port, accepting only `allow`
+    fn listen(receiver: &SnapshotTransfer, allow: &[String], max_size: u64) -> SocketAddr {
+        let senders = receiver.authorize_senders(allow).unwrap();
+        let (server_config, _) = receiver.server_config(senders).unwrap();
+        let endpoint =
+            quinn::Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = endpoint.local_addr().unwrap();
+        let receiver = receiver.clone();
+        tokio::spawn(async move { receiver.serve(endpoint, max_size).await });
+        addr
+    }
+
+    async fn offer(
+        sender: &SnapshotTransfer,
+        receiver: &SnapshotTransfer,
+        addr: SocketAddr,
+    ) -> Result<SendSummary> {
+        let (cert, _) = receiver.identity("receiver").unwrap();
+        let verifier = Arc::new(FingerprintVerifier {
+            expected: Some(fingerprint(&cert)),
+            seen: Mutex::new(None),
+        });
+        let bundle = sender.transfers_dir.join("outgoing").join("snap-1.bundle");
+        std::fs::write(&bundle, vec![0u8; 4096]).unwrap();
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            sender.try_send("snap-1", &bundle, 4096, &"a".repeat(64), addr, verifier),
+        )
+        .await?
+    }
+
+    #[tokio::test]
+    async fn test_receiver_rejects_unauthorized_sender() {
+        let dir = TempDir::new().unwrap();
+        let receiver = host(dir.path(), "receiver");
+        let sender = host(dir.path(), "sender");
+        let stranger = host(dir.path(), "stranger");
+
+        // A cap below the bundle size makes an authorized offer fail after the handshake
+        let addr = listen(&receiver, &[sender.sender_fingerprint().unwrap()], 1024);
+
+        let err = offer(&stranger, &receiver, addr).await.unwrap_err();
+        assert!(!err.to_string().contains("exceeds the limit"), "{}", err);
+
+        let err = offer(&sender, &receiver, addr).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit"), "{}", err);
+        assert!(
+            !receiver
+                .transfers_dir
+                .join("incoming")
+                .join(format!("{}.part", "a".repeat(64)))
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_authorized_senders_persist() {
+        let dir = TempDir::new().unwrap();
+        let receiver = host(dir.path(), "receiver");
+        assert!(receiver.authorize_senders(&[]).unwrap().is_empty());
+        assert!(
+            receiver
+                .authorize_senders(&["not-a-fingerprint".to_string()])
+                .is_err()
+        );
+
+        let fp = "AB".repeat(32);
+        receiver
+            .authorize_senders(&[format!("sha256:{}", fp)])
+            .unwrap();
+        let senders = receiver.authorize_senders(&[]).unwrap();
+        assert!(senders.contains(&fp.to_lowercase()));
+    }
+}
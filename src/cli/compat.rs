@@ -1,10 +1,11 @@
-use bolt::compat::DockerApiCompat;
 use bolt::compat::compose::ComposeCompat;
 use bolt::compat::docker::DockerCompat;
+use bolt::docker_compat::api_server::DockerAPIServer;
 use bolt::{BoltRuntime, Result};
 use clap::{Args, Subcommand};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Args)]
 pub struct CompatArgs {
@@ -33,6 +34,9 @@ pub enum CompatCommands {
         /// Bind address
         #[arg(short, long, default_value = "127.0.0.1")]
         bind: String,
+        /// Log when the Boltfile changes and the running services fall behind it
+        #[arg(long)]
+        watch_boltfile: bool,
     },
     /// Show migration guide from Docker/Compose to Bolt
     Migrate {
@@ -74,7 +78,11 @@ pub async fn handle_compat_command(args: CompatArgs, runtime: BoltRuntime) -> Re
     match args.command {
         CompatCommands::Docker { args } => handle_docker_command(args, runtime).await,
         CompatCommands::Compose { command } => handle_compose_command(command).await,
-        CompatCommands::ApiServer { port, bind } => handle_api_server(port, bind, runtime).await,
+        CompatCommands::ApiServer {
+            port,
+            bind,
+            watch_boltfile,
+        } => handle_api_server(port, bind, watch_boltfile, runtime).await,
         CompatCommands::Migrate { compose_file } => handle_migration_guide(compose_file).await,
     }
 }
@@ -170,87 +178,53 @@ async fn handle_compose_command(command: ComposeCommands) -> Result<()> {
     Ok(())
 }
 
-async fn handle_api_server(port: u16, bind: String, runtime: BoltRuntime) -> Result<()> {
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::TcpListener;
-
+async fn handle_api_server(
+    port: u16,
+    bind: String,
+    watch_boltfile: bool,
+    runtime: BoltRuntime,
+) -> Result<()> {
     println!("🚀 Starting Docker API Compatibility Server");
     println!("   Address: http://{}:{}", bind, port);
     println!("   Docker API Version: 1.43");
+    println!("   Bolt API: http://{}:{}/bolt/v1", bind, port);
     println!("   Backend: Bolt Runtime");
     println!();
-
-    let api_compat = DockerApiCompat::new(runtime);
-    let listener = TcpListener::bind(format!("{}:{}", bind, port)).await?;
-
-    println!("✅ Server listening on {}:{}", bind, port);
     println!("💡 Test with: export DOCKER_HOST=tcp://{}:{}", bind, port);
     println!();
 
-    loop {
-        let (mut socket, addr) = listener.accept().await?;
-        let api_compat = api_compat.clone();
-
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(&mut socket);
-            let mut request_line = String::new();
-
-            if let Err(e) = reader.read_line(&mut request_line).await {
-                eprintln!("Failed to read request: {}", e);
-                return;
-            }
-
-            let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
-            if parts.len() < 2 {
-                return;
-            }
-
-            let method = parts[0];
-            let path = parts[1];
-
-            // Read headers (simplified)
-            let mut headers = Vec::new();
-            loop {
-                let mut header = String::new();
-                if reader.read_line(&mut header).await.is_err() {
-                    break;
-                }
-                if header.trim().is_empty() {
-                    break;
-                }
-                headers.push(header);
-            }
-
-            // Read body if present (simplified)
-            let body = String::new(); // Would need proper content-length handling
-
-            println!("📡 {} {} from {}", method, path, addr);
-
-            match api_compat.handle_request(path, method, &body).await {
-                Ok(response) => {
-                    let http_response = format!(
-                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-                        response.len(),
-                        response
-                    );
+    if let Err(e) = bolt::gaming::drivers::check_at_startup(&runtime.config()).await {
+        tracing::warn!("Could not check the GPU driver version: {}", e);
+    }
 
-                    if let Err(e) = socket.write_all(http_response.as_bytes()).await {
-                        eprintln!("Failed to write response: {}", e);
-                    }
-                }
-                Err(e) => {
-                    let error_response = format!(
-                        "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\n\r\n{{\"error\": \"{}\"}}\r\n",
-                        e
-                    );
-
-                    if let Err(e) = socket.write_all(error_response.as_bytes()).await {
-                        eprintln!("Failed to write error response: {}", e);
+    // SIGHUP rereads config.toml, like POST /bolt/v1/config/reload
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    let reloading = runtime.clone();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match reloading.reload_config() {
+                Ok(reload) => {
+                    tracing::info!("🔄 Configuration reloaded; changed: {:?}", reload.changed);
+                    if !reload.restart_required.is_empty() {
+                        tracing::warn!("Restart to apply: {}", reload.restart_required.join(", "));
                     }
                 }
+                Err(e) => tracing::error!("❌ Configuration not reloaded: {:#}", e),
             }
-        });
+        }
+    });
+
+    if watch_boltfile {
+        tokio::spawn(bolt::surge::watch::watch(
+            runtime.clone(),
+            std::time::Duration::from_secs(5),
+        ));
     }
+
+    DockerAPIServer::new(Arc::new(runtime))
+        .with_address(bind, port)
+        .start()
+        .await
 }
 
 async fn handle_migration_guide(compose_file: Option<PathBuf>) -> Result<()> {
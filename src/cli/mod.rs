@@ -19,6 +19,22 @@ pub struct Cli {
     /// Configuration file path
     #[arg(short, long, default_value = "Boltfile.toml")]
     pub config: String,
+
+    /// Log output format (overrides the [logging] config)
+    #[arg(long, global = true, value_parser = ["text", "json"])]
+    pub log_format: Option<String>,
+
+    /// Set a Boltfile template variable (name=value, repeatable)
+    #[arg(long = "var", global = true, value_name = "NAME=VALUE")]
+    pub vars: Vec<String>,
+
+    /// Profile from <name>.vars.toml (templates) and <name>.overrides.toml
+    #[arg(long, global = true, env = "BOLT_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Let images that violate the trust policy through (admins only)
+    #[arg(long, global = true)]
+    pub override_trust: bool,
 }
 
 #[derive(Subcommand)]
@@ -52,12 +68,91 @@ pub enum Commands {
         #[arg(long)]
         runtime: Option<String>,
 
-        /// GPU devices to use (e.g., all, 0, 1,2)
+        /// GPUs to pass in: all, positions (0, 1,2), PCI addresses or MIG UUIDs
         #[arg(long)]
         gpu: Option<String>,
+
+        /// Share the GPUs with other --gpu-shared containers instead of
+        /// holding them exclusively
+        #[arg(long, requires = "gpu")]
+        gpu_shared: bool,
+
+        /// Collect a crash dump if the container exits non-zero (foreground runs)
+        #[arg(long)]
+        crash_dump: bool,
+
+        /// When to pull the image (always, if-not-present, never, digest-pinned)
+        #[arg(long, value_enum)]
+        pull: Option<bolt::runtime::pull::PullPolicy>,
+
+        /// Platform of the image to run, e.g. linux/amd64 (default: the host's)
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Restart policy of a detached container: no, on-failure[:N],
+        /// always or unless-stopped
+        #[arg(long)]
+        restart: Option<String>,
+
+        /// Healthcheck command, run with `sh -c` in the container
+        #[arg(long)]
+        health_cmd: Option<String>,
+
+        /// Time between healthchecks, e.g. 30s
+        #[arg(long, requires = "health_cmd")]
+        health_interval: Option<String>,
+
+        /// Time a healthcheck may take, e.g. 30s
+        #[arg(long, requires = "health_cmd")]
+        health_timeout: Option<String>,
+
+        /// Consecutive failures before the container is unhealthy
+        #[arg(long, requires = "health_cmd")]
+        health_retries: Option<u32>,
+
+        /// Grace period after start in which failures don't count, e.g. 1m
+        #[arg(long, requires = "health_cmd")]
+        health_start_period: Option<String>,
+
+        /// Disable the image's healthcheck
+        #[arg(long, conflicts_with = "health_cmd")]
+        no_healthcheck: bool,
+
+        /// Labels (key=value)
+        #[arg(short, long = "label")]
+        labels: Vec<String>,
+
+        /// OCI annotations for the container's runtime spec (key=value)
+        #[arg(long = "annotation")]
+        annotations: Vec<String>,
+
+        /// User to run as (name|uid[:group|gid])
+        #[arg(short, long)]
+        user: Option<String>,
+
+        /// Network to connect to, or host/none
+        #[arg(long)]
+        network: Option<String>,
+
+        /// Linux capabilities to add
+        #[arg(long)]
+        cap_add: Vec<String>,
+
+        /// Linux capabilities to drop
+        #[arg(long)]
+        cap_drop: Vec<String>,
+
+        /// Working directory in the container
+        #[arg(short, long)]
+        workdir: Option<String>,
+
+        /// Container hostname
+        #[arg(long)]
+        hostname: Option<String>,
     },
 
     /// Build a container image
+    #[command(args_conflicts_with_subcommands = true)]
     Build {
         /// Path to build context
         #[arg(default_value = ".")]
@@ -70,18 +165,91 @@ pub enum Commands {
         /// Dockerfile path
         #[arg(short, long, default_value = "Dockerfile")]
         file: String,
+
+        /// Import a Nix-built image instead (e.g. ./flake#image)
+        #[arg(long)]
+        from_nix: Option<String>,
+
+        /// Diff the --from-nix image against another Nix flake output
+        #[arg(long, requires = "from_nix")]
+        diff: Option<String>,
+
+        /// Builder instance to build on (see `bolt builder create`)
+        #[arg(long, env = "BOLT_BUILDER", default_value = "default")]
+        builder: String,
+
+        /// Shared package manager caches to mount (e.g. cargo,npm), Podman only
+        #[arg(long, value_delimiter = ',')]
+        cache_volumes: Vec<String>,
+
+        /// OCI annotation for the image's manifest (key=value); repeatable
+        #[arg(long = "annotation", value_name = "KEY=VALUE")]
+        annotations: Vec<String>,
+
+        /// Build argument for the Dockerfile (KEY=VALUE); repeatable
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// Build byte-for-byte reproducibly and record the build's provenance
+        #[arg(long, requires = "tag", conflicts_with_all = ["from_nix", "cache_volumes"])]
+        reproducible: bool,
+
+        /// Timestamp for --reproducible builds (default: the context's last commit time)
+        #[arg(long, env = "SOURCE_DATE_EPOCH")]
+        source_date_epoch: Option<i64>,
+
+        /// Build every step again instead of reusing a cached image or layers
+        #[arg(long, conflicts_with_all = ["from_nix", "reproducible"])]
+        no_cache: bool,
+
+        #[command(subcommand)]
+        command: Option<BuildCommands>,
+    },
+
+    /// Manage the build cache
+    Builder {
+        #[command(subcommand)]
+        command: BuilderCommands,
+    },
+
+    /// Manage local images
+    Image {
+        #[command(subcommand)]
+        command: ImageCommands,
     },
 
     /// Pull an image from registry
     Pull {
         /// Image name
         image: String,
+
+        /// Platform to pull, e.g. linux/arm64 (default: the host's)
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Pull with Bolt's own registry client and unpack a rootfs under
+        /// the data directory, without podman or docker
+        #[arg(long)]
+        rootfs: bool,
     },
 
     /// Push an image to registry
     Push {
         /// Image name
         image: String,
+        /// Encrypt layers for a recipient (jwe:<public key>, pkcs7:<cert> or
+        /// pgp:<email>); repeatable. Defaults to `[encryption] recipients`
+        #[arg(long, value_name = "RECIPIENT")]
+        encrypt: Vec<String>,
+        /// Only encrypt this layer (0 is the base, -1 the top); repeatable
+        #[arg(long, value_name = "INDEX", requires = "encrypt", allow_hyphen_values = true)]
+        encrypt_layer: Vec<i32>,
+        /// Also push the manifest under this tag; repeatable
+        #[arg(long = "tag", value_name = "TAG", conflicts_with = "encrypt")]
+        tags: Vec<String>,
+        /// Add an OCI annotation to the pushed manifest (key=value); repeatable
+        #[arg(long = "annotation", value_name = "KEY=VALUE", conflicts_with = "encrypt")]
+        annotations: Vec<String>,
     },
 
     /// List containers
@@ -89,69 +257,1212 @@ pub enum Commands {
         /// Show all containers (including stopped)
         #[arg(short, long)]
         all: bool,
+
+        /// Only containers with an annotation: annotation=key[=value]; repeatable
+        #[arg(long = "filter", value_name = "FILTER")]
+        filters: Vec<String>,
+    },
+
+    /// Live CPU, memory, network and block I/O of containers
+    Stats {
+        /// Container names or IDs (default: every running container)
+        containers: Vec<String>,
+
+        /// Print one sample and exit
+        #[arg(long)]
+        no_stream: bool,
+
+        /// Print samples as JSON, one object per line while streaming
+        #[arg(long)]
+        json: bool,
+
+        /// Time between samples
+        #[arg(long, default_value = "1s")]
+        interval: String,
     },
 
     /// Stop containers
     Stop {
-        /// Container names or IDs
+        /// Container names, IDs or name globs (e.g. "web-*")
+        #[arg(required_unless_present_any = ["all", "filter"])]
         containers: Vec<String>,
+
+        /// Only containers matching label=<key>[=<value>], name=<glob> or status=<state>
+        #[arg(long)]
+        filter: Vec<String>,
+
+        /// Every running container
+        #[arg(short, long, conflicts_with = "containers")]
+        all: bool,
+
+        /// How many containers to handle at once
+        #[arg(long, default_value_t = bolt::runtime::bulk::DEFAULT_PARALLEL)]
+        parallel: usize,
     },
 
     /// Remove containers
     #[command(alias = "remove")]
     Rm {
-        /// Container names or IDs
+        /// Container names, IDs or name globs (e.g. "web-*")
+        #[arg(required_unless_present_any = ["all", "filter"])]
         containers: Vec<String>,
 
+        /// Only containers matching label=<key>[=<value>], name=<glob> or status=<state>
+        #[arg(long)]
+        filter: Vec<String>,
+
+        /// Every running or stopped container
+        #[arg(short, long, conflicts_with = "containers")]
+        all: bool,
+
+        /// How many containers to handle at once
+        #[arg(long, default_value_t = bolt::runtime::bulk::DEFAULT_PARALLEL)]
+        parallel: usize,
+
         /// Force removal
         #[arg(short, long)]
         force: bool,
     },
 
-    /// Restart containers
-    Restart {
-        /// Container names or IDs
-        containers: Vec<String>,
+    /// Restart containers
+    Restart {
+        /// Container names, IDs or name globs (e.g. "web-*")
+        #[arg(required_unless_present_any = ["all", "filter"])]
+        containers: Vec<String>,
+
+        /// Only containers matching label=<key>[=<value>], name=<glob> or status=<state>
+        #[arg(long)]
+        filter: Vec<String>,
+
+        /// Every running container
+        #[arg(short, long, conflicts_with = "containers")]
+        all: bool,
+
+        /// How many containers to handle at once
+        #[arg(long, default_value_t = bolt::runtime::bulk::DEFAULT_PARALLEL)]
+        parallel: usize,
+
+        /// Timeout for stop before restart (seconds)
+        #[arg(short, long, default_value = "10")]
+        timeout: u64,
+    },
+
+    /// Change the resource limits of running containers
+    Update {
+        /// Container names, IDs or name globs (e.g. "web-*")
+        #[arg(required_unless_present_any = ["all", "filter"])]
+        containers: Vec<String>,
+
+        /// Only containers matching label=<key>[=<value>], name=<glob> or status=<state>
+        #[arg(long)]
+        filter: Vec<String>,
+
+        /// Every running container
+        #[arg(short, long, conflicts_with = "containers")]
+        all: bool,
+
+        /// How many containers to handle at once
+        #[arg(long, default_value_t = bolt::runtime::bulk::DEFAULT_PARALLEL)]
+        parallel: usize,
+
+        /// Memory limit (e.g. 512MB, 2GB)
+        #[arg(long)]
+        memory: Option<String>,
+
+        /// CPU limit, in CPUs (e.g. 2 or 0.5)
+        #[arg(long)]
+        cpus: Option<f64>,
+
+        /// Maximum number of processes
+        #[arg(long)]
+        pids_limit: Option<u32>,
+
+        /// Relative CPU weight (default 1024)
+        #[arg(long)]
+        cpu_shares: Option<u32>,
+
+        /// Relative block I/O weight, 10-1000
+        #[arg(long, value_parser = clap::value_parser!(u16).range(10..=1000))]
+        blkio_weight: Option<u16>,
+
+        /// Read and write IOPS limit on the data dir's disk (bolt cgroups only)
+        #[arg(long)]
+        iops: Option<u32>,
+    },
+
+    /// Show a container's configuration and state, or its startup timings
+    Inspect {
+        /// Container name or ID
+        container: String,
+
+        /// Show how long each startup phase took, flagging the slowest
+        #[arg(long)]
+        timings: bool,
+
+        /// Print the timings as JSON
+        #[arg(long, requires = "timings")]
+        json: bool,
+
+        /// Show the OCI annotations of the container's runtime spec
+        #[arg(long, conflicts_with = "timings")]
+        annotations: bool,
+    },
+
+    /// Run a command in a running container
+    Exec {
+        /// Keep stdin open and stream it to the command
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Allocate a pseudo-terminal
+        #[arg(short, long)]
+        tty: bool,
+
+        /// Environment variables (KEY=VALUE)
+        #[arg(short, long)]
+        env: Vec<String>,
+
+        /// Working directory inside the container
+        #[arg(short, long)]
+        workdir: Option<String>,
+
+        /// User to run the command as
+        #[arg(short, long)]
+        user: Option<String>,
+
+        /// Container name or ID
+        container: String,
+
+        /// Command and arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Copy files between the host and a container
+    Cp {
+        /// Follow a symlink source instead of copying the link
+        #[arg(short = 'L', long)]
+        follow_link: bool,
+
+        /// Keep the files' owners (default: root in the container, you on the host)
+        #[arg(short, long)]
+        archive: bool,
+
+        /// Source: a host path, CONTAINER:PATH, or - for a tar stream on stdin
+        src: String,
+
+        /// Destination: a host path, CONTAINER:PATH, or - for a tar stream on stdout
+        dest: String,
+    },
+
+    /// Save a running container's processes to a directory with CRIU
+    Checkpoint {
+        /// Container name or ID
+        container: String,
+
+        /// Directory to write the checkpoint to (must be empty or new)
+        dir: std::path::PathBuf,
+
+        /// Keep the container running afterwards
+        #[arg(long)]
+        leave_running: bool,
+
+        /// Save established TCP connections (podman; restore needs the same IP)
+        #[arg(long)]
+        tcp_established: bool,
+    },
+
+    /// Restore a container from a `bolt checkpoint` directory
+    Restore {
+        /// Checkpoint directory
+        dir: std::path::PathBuf,
+
+        /// Name for the restored container (default: the checkpointed one; podman only)
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Surge orchestration commands (like docker-compose)
+    Surge {
+        #[command(subcommand)]
+        command: SurgeCommands,
+    },
+
+    /// Gaming-specific commands
+    Gaming {
+        #[command(subcommand)]
+        command: GamingCommands,
+    },
+
+    /// Network management
+    Network {
+        #[command(subcommand)]
+        command: NetworkCommands,
+    },
+
+    /// Volume management
+    Volume {
+        #[command(subcommand)]
+        command: VolumeCommands,
+    },
+
+    /// Snapshot management (BTRFS/ZFS)
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+
+    /// Docker/Podman compatibility layer
+    Compat {
+        #[command(subcommand)]
+        command: compat::CompatCommands,
+    },
+
+    /// Debugging and diagnostics
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommands,
+    },
+
+    /// Show build and host information
+    Info {
+        /// List supported features and whether the host provides them
+        #[arg(long)]
+        capabilities: bool,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Hardened sandbox tools for untrusted images
+    Sandbox {
+        #[command(subcommand)]
+        command: SandboxCommands,
+    },
+
+    /// Check the host and Boltfile hardware (uplinks, SR-IOV) before deploying
+    #[command(group(
+        clap::ArgGroup::new("changes")
+            .multiple(true)
+            .args(["apply", "setup_emulation"])
+    ))]
+    Doctor {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Also check host tuning for QUIC (UDP buffers, GRO/GSO, busy polling)
+        #[arg(long)]
+        network: bool,
+
+        /// Apply the recommended network tuning (implies --network)
+        #[arg(long)]
+        apply: bool,
+
+        /// Register qemu-user-static binfmt handlers for Boltfile services
+        /// whose `platform` this host can't run natively
+        #[arg(long)]
+        setup_emulation: bool,
+
+        /// Don't ask before applying
+        #[arg(long, short = 'y', requires = "changes")]
+        yes: bool,
+    },
+
+    /// Benchmark this host: container starts, image pulls, QUIC vs TCP proxy
+    /// latency, volume IO and GPU passthrough, compared with a saved baseline
+    Bench {
+        /// Benchmarks to run: start, pull, proxy, volume, gpu (default: all)
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+
+        /// Runs per latency measurement; the median is reported
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+
+        /// Image to start, pull and mount volumes into
+        #[arg(long, default_value = "docker.io/library/alpine:latest")]
+        image: String,
+
+        /// Baseline to compare with
+        #[arg(long, default_value = "default")]
+        baseline: String,
+
+        /// Save this run as a baseline with this name
+        #[arg(long, value_name = "NAME")]
+        save: Option<String>,
+
+        /// Percent a metric may get worse before it counts as a regression
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Health of this node and every surge project on it; exits 0 when
+    /// healthy, 1 when degraded and 2 when unhealthy
+    Health {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Reports built from Bolt's metrics history
+    Report {
+        #[command(subcommand)]
+        command: ReportCommands,
+    },
+
+    /// Prometheus metrics, configured by `[monitoring]` in config.toml
+    Metrics {
+        #[command(subcommand)]
+        command: MetricsCommands,
+    },
+
+    /// Tune sysctls, CPU governors, IRQ affinity and THP for a workload class
+    Optimize {
+        #[command(subcommand)]
+        command: OptimizeCommands,
+    },
+
+    /// Maintenance windows and the operations queued for them
+    Maintenance {
+        #[command(subcommand)]
+        command: MaintenanceCommands,
+    },
+
+    /// Inspect the Boltfile
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Back up volumes and snapshots
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommands,
+    },
+
+    /// Manage secrets referenced from config.toml
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
+    },
+
+    /// TLS certificates from an ACME CA such as Let's Encrypt
+    Cert {
+        #[command(subcommand)]
+        command: CertCommands,
+    },
+
+    /// Per-user workspaces with resource quotas
+    Workspace {
+        #[command(subcommand)]
+        command: WorkspaceCommands,
+    },
+
+    /// Development environments from .bolt/dev.toml
+    Dev {
+        #[command(subcommand)]
+        command: DevCommands,
+    },
+
+    /// Capsule access
+    Capsule {
+        #[command(subcommand)]
+        command: CapsuleCommands,
+    },
+
+    /// Open an SSH session to a capsule's SSH endpoint
+    Ssh {
+        /// Capsule name
+        capsule: String,
+
+        /// Extra ssh arguments, e.g. a command to run
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Keep a container's egress allowlist resolved (started by surge)
+    #[command(name = "egress-sync", hide = true)]
+    EgressSync {
+        /// Container name
+        container: String,
+    },
+
+    /// Suspend a container while it's idle and wake it on demand (started by surge)
+    #[command(name = "idle-watch", hide = true)]
+    IdleWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Probe a container's published ports for its SLO (started by surge)
+    #[command(name = "slo-watch", hide = true)]
+    SloWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Take the Boltfile's scheduled snapshots (started by `bolt snapshot auto enable`)
+    #[command(name = "snapshot-watch", hide = true)]
+    SnapshotWatch,
+
+    /// Run maintenance windows (started by `bolt maintenance enable`)
+    #[command(name = "maintenance-watch", hide = true)]
+    MaintenanceWatch,
+
+    /// Follow a container's logs for its log hooks (started by surge)
+    #[command(name = "log-watch", hide = true)]
+    LogWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Keep a container's published ports forwarded (started by surge)
+    #[command(name = "nat-watch", hide = true)]
+    NatWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Keep a container's volumes tuned to their cache profiles (started by surge)
+    #[command(name = "cache-tune", hide = true)]
+    CacheTune {
+        /// Container name
+        container: String,
+    },
+
+    /// Enforce GPU priorities between interactive and batch containers (started by surge)
+    #[command(name = "gpu-arbiter", hide = true)]
+    GpuArbiter,
+
+    /// Write a container's cached Wine prefix back once it stops (started by surge)
+    #[command(name = "prefix-watch", hide = true)]
+    PrefixWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Restart a container whenever its healthcheck fails it (started by surge)
+    #[command(name = "health-watch", hide = true)]
+    HealthWatch {
+        /// Container name
+        container: String,
+    },
+
+    /// Restart a container when it exits, as its restart policy says (started by surge)
+    #[command(name = "supervise", hide = true)]
+    Supervise {
+        /// Container name
+        container: String,
+    },
+
+    /// Run a container's Wayland session and clipboard bridge (started by surge)
+    #[command(name = "desktop-bridge", hide = true)]
+    DesktopBridge {
+        /// Container name
+        container: String,
+    },
+
+    /// Run an SSH session in a capsule (sshd's forced command)
+    #[command(name = "ssh-exec", hide = true)]
+    SshExec {
+        /// Capsule name
+        capsule: String,
+    },
+
+    /// Run a WASM workload in the foreground (started by surge)
+    #[command(name = "wasm-exec", hide = true)]
+    WasmExec {
+        /// The workload's spec.json
+        spec: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BuildCommands {
+    /// Rebuild an image built with --reproducible and compare the digests
+    VerifyReproducible {
+        /// Tag or digest of the reproducible build
+        image: String,
+
+        /// Keep the rebuilt image, as localhost/bolt-verify:<digest>
+        #[arg(long)]
+        keep: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BuilderCommands {
+    /// Create a builder instance with its own cache and limits
+    Create {
+        /// Builder name
+        name: String,
+
+        /// Builds that may run at once (default 1)
+        #[arg(long)]
+        concurrency: Option<u32>,
+
+        /// CPU limit per build, in CPUs (e.g. 4 or 2.5)
+        #[arg(long)]
+        cpus: Option<f64>,
+
+        /// Memory limit per build (e.g. 8GB)
+        #[arg(long)]
+        memory: Option<String>,
+
+        /// Cache size quota (default: [build_cache] max_size)
+        #[arg(long)]
+        storage: Option<String>,
+    },
+
+    /// List builder instances
+    #[command(alias = "list")]
+    Ls {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove a builder instance and its cache
+    #[command(alias = "remove")]
+    Rm {
+        /// Builder name
+        name: String,
+    },
+
+    /// Prune the builder's layer cache; built images are kept
+    Prune {
+        /// Keep up to this much layer cache, pruning the oldest layers first (e.g. 10GB)
+        #[arg(long)]
+        keep_storage: Option<String>,
+
+        /// Filter layers to remove (until=<duration>, e.g. until=72h)
+        #[arg(long)]
+        filter: Vec<String>,
+
+        /// Builder whose cache to prune
+        #[arg(long, env = "BOLT_BUILDER", default_value = "default")]
+        builder: String,
+    },
+
+    /// List the shared package manager caches (`cache_volumes`)
+    Caches {
+        /// Evict least recently used entries down to the size limit
+        #[arg(long, conflicts_with = "clear")]
+        prune: bool,
+
+        /// Size to prune down to (default: [build_cache] artifacts_max_size)
+        #[arg(long, requires = "prune")]
+        keep_storage: Option<String>,
+
+        /// Remove a cache entirely
+        #[arg(long)]
+        clear: Option<String>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImageCommands {
+    /// Squash layers, strip files not needed at runtime and recompress with zstd
+    Optimize {
+        /// Image to optimize
+        image: String,
+
+        /// Tag for the optimized image (default: <image>-optimized)
+        #[arg(short, long)]
+        tag: Option<String>,
+
+        /// Strip profiles: docs, locales, build-caches, or none
+        #[arg(long, value_delimiter = ',', default_value = "docs,locales,build-caches")]
+        strip: Vec<String>,
+
+        /// zstd compression level (1-22)
+        #[arg(long, default_value_t = 15)]
+        level: i32,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Share the layers in Bolt's store with Bolt nodes on the LAN
+    Serve,
+
+    /// List the Bolt nodes on the LAN sharing layers
+    Peers {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show an image's OCI manifest annotations
+    Annotations {
+        /// Image name or ID
+        image: String,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SandboxCommands {
+    /// Show what a sandboxed container attempted that was blocked
+    Report {
+        /// Container name or ID
+        container: String,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the hardened tier's seccomp profile
+    Profile,
+}
+
+#[derive(Subcommand)]
+pub enum MetricsCommands {
+    /// Collect metrics and serve them on the `[monitoring]` address until Ctrl-C
+    Serve,
+
+    /// Collect metrics once and print them in the Prometheus text format
+    Show,
+}
+
+#[derive(Subcommand)]
+pub enum MaintenanceCommands {
+    /// Show the window, the queue and the last run
+    Status {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Queue an operation for the next window
+    Queue {
+        #[arg(value_enum)]
+        operation: bolt::surge::maintenance::Operation,
+    },
+
+    /// Drop a queued operation
+    Unqueue {
+        #[arg(value_enum)]
+        operation: bolt::surge::maintenance::Operation,
+    },
+
+    /// Run the queue now instead of waiting for the window
+    Run {
+        /// Queue these first
+        #[arg(value_enum)]
+        operations: Vec<bolt::surge::maintenance::Operation>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run maintenance windows in the background
+    Enable,
+
+    /// Stop running maintenance windows
+    Disable,
+}
+
+#[derive(Subcommand)]
+pub enum OptimizeCommands {
+    /// Apply a preset; previous values are saved for `bolt optimize revert`
+    Apply {
+        /// Preset to apply (default: `[optimize] preset`, or balanced)
+        #[arg(long, value_enum)]
+        preset: Option<bolt::optimizations::tuning::Preset>,
+
+        /// Also set cpu.weight of these running containers
+        #[arg(long = "container")]
+        containers: Vec<String>,
+
+        /// Only list what would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Restore every value from before the first apply
+    Revert {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReportCommands {
+    /// Forecast when the data dir, volumes and image store hit their thresholds
+    Capacity {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// p50/p95 of each container startup phase across recent starts
+    Startup {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Output in the Prometheus text format
+        #[arg(long, conflicts_with = "json")]
+        prometheus: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Render a `.toml.tmpl` Boltfile and validate the result
+    Render {
+        /// Write the rendered Boltfile here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BackupCommands {
+    /// Restic-compatible repository from [backup.restic] in config.toml
+    Restic {
+        #[command(subcommand)]
+        command: ResticCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ResticCommands {
+    /// Initialize the repository
+    Init,
+
+    /// Back up volumes and capsule snapshots, then apply retention
+    Backup {
+        /// Volume to back up (repeatable)
+        #[arg(long = "volume")]
+        volumes: Vec<String>,
+
+        /// Capsule snapshot ID or name to back up (repeatable)
+        #[arg(long = "snapshot")]
+        snapshots: Vec<String>,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Restore a backup to the volume or snapshot it was taken from
+    Restore {
+        /// Repository snapshot ID (a prefix is enough)
+        id: String,
+
+        /// Restore the files into this directory instead
+        #[arg(long)]
+        target: Option<String>,
+    },
+
+    /// List Bolt's backups in the repository
+    Snapshots {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SecretCommands {
+    /// Store a secret read from stdin or a file
+    Set {
+        /// Secret name
+        name: String,
+
+        /// Read the value from this file instead of stdin
+        #[arg(long)]
+        from_file: Option<String>,
+    },
+
+    /// List stored secrets
+    #[command(alias = "list")]
+    Ls,
+
+    /// Remove a secret
+    Rm {
+        /// Secret name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CertCommands {
+    /// Certificates in [acme] and when they expire
+    #[command(alias = "list")]
+    Ls {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Obtain missing certificates and renew those expiring within renew_before
+    Renew {
+        /// Certificate names; all of them when omitted
+        names: Vec<String>,
+
+        /// Renew even when the certificate isn't due
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Renew every check_interval and alert on expiring certificates, until interrupted
+    Watch,
+}
+
+#[derive(Subcommand)]
+pub enum CapsuleCommands {
+    /// Per-capsule SSH endpoints
+    Ssh {
+        #[command(subcommand)]
+        command: CapsuleSshCommands,
+    },
+
+    /// Reach a capsule without its network: its boot output, then its live console
+    Console {
+        /// Capsule name
+        name: String,
+
+        /// Lines of boot output to show first
+        #[arg(long, default_value_t = bolt::capsules::console::DEFAULT_LINES)]
+        lines: usize,
+
+        /// Print the captured boot output and exit
+        #[arg(long, conflicts_with = "shell")]
+        log: bool,
+
+        /// Open a login shell in the capsule instead
+        #[arg(long)]
+        shell: bool,
+    },
+
+    /// Create and run a capsule from a template
+    Create {
+        /// Capsule name
+        name: String,
+
+        /// Template to create it from (see `bolt capsule template ls`)
+        #[arg(long)]
+        template: String,
+
+        /// Image to use instead of the template's
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Memory in MB instead of the template's
+        #[arg(long)]
+        memory_mb: Option<u64>,
+
+        /// vCPUs instead of the template's
+        #[arg(long)]
+        vcpus: Option<u32>,
+    },
+
+    /// Snapshot a running capsule with its memory, compressed and deduplicated
+    Snapshot {
+        /// Capsule name
+        name: String,
+
+        /// Snapshot name, for `bolt snapshot export` and `send`
+        #[arg(long = "name")]
+        snapshot_name: Option<String>,
+
+        /// Description for the snapshot
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+
+    /// Define, share and list capsule templates
+    Template {
+        #[command(subcommand)]
+        command: CapsuleTemplateCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CapsuleTemplateCommands {
+    /// Store a new template derived from an existing one
+    Create {
+        /// Template name
+        name: String,
+
+        /// Template to start from
+        #[arg(long, default_value = "gaming")]
+        from: String,
+
+        /// Description
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Image
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Memory in MB
+        #[arg(long)]
+        memory_mb: Option<u64>,
+
+        /// vCPUs
+        #[arg(long)]
+        vcpus: Option<u32>,
+
+        /// Replace a template of the same name
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List built-in and user templates
+    #[command(alias = "ls")]
+    List {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Write a template as TOML
+    Export {
+        /// Template name
+        name: String,
+
+        /// File to write (default: stdout)
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Validate a template TOML file and store it
+    Import {
+        /// Template file
+        file: std::path::PathBuf,
+
+        /// Store it under this name instead of its own
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Replace a template of the same name
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Remove a user template
+    #[command(alias = "remove")]
+    Rm {
+        /// Template name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CapsuleSshCommands {
+    /// Start an SSH endpoint for a capsule
+    Enable {
+        /// Capsule name
+        capsule: String,
+
+        /// Address to listen on (default: all)
+        #[arg(long)]
+        address: Option<String>,
+
+        /// Port to listen on (default: the first free one from 2222)
+        #[arg(long)]
+        port: Option<u16>,
+
+        /// Login shell in the capsule
+        #[arg(long)]
+        shell: Option<String>,
+    },
+
+    /// Stop a capsule's SSH endpoint, keeping its keys
+    Disable {
+        /// Capsule name
+        capsule: String,
+    },
+
+    /// Authorize a public key (the key itself or a .pub file)
+    AddKey {
+        /// Capsule name
+        capsule: String,
+
+        /// Public key or path to one
+        key: String,
+    },
+
+    /// Revoke a key by fingerprint or comment
+    RemoveKey {
+        /// Capsule name
+        capsule: String,
+
+        /// Fingerprint (SHA256:...) or comment
+        key: String,
+    },
+
+    /// List the keys authorized for a capsule
+    Keys {
+        /// Capsule name
+        capsule: String,
+    },
+
+    /// Show SSH endpoints
+    Status {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DevCommands {
+    /// Open a shell in the repository's dev environment, creating it if needed
+    Enter {
+        /// Rebuild the warm snapshot and recreate the container
+        #[arg(long)]
+        rebuild: bool,
+
+        /// Command to run instead of the shell
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+
+    /// List dev environments
+    #[command(alias = "ls")]
+    List {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Stop a dev environment's container, keeping it for the next enter
+    Stop {
+        /// Environment name (default: the current repository's)
+        name: Option<String>,
+    },
+
+    /// Remove a dev environment's container
+    #[command(alias = "remove")]
+    Rm {
+        /// Environment name (default: the current repository's)
+        name: Option<String>,
+
+        /// Remove its warm snapshot as well
+        #[arg(long)]
+        snapshot: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WorkspaceCommands {
+    /// Create a workspace, or change the quota of an existing one
+    Create {
+        /// Workspace name
+        name: String,
+
+        /// CPUs its running containers may use together
+        #[arg(long)]
+        cpus: Option<f64>,
+
+        /// Memory its running containers may use together (e.g. 16GB)
+        #[arg(long)]
+        memory: Option<String>,
+
+        /// GPU hours its containers may use in total
+        #[arg(long)]
+        gpu_hours: Option<f64>,
+
+        /// Space its volumes may use (e.g. 200GB)
+        #[arg(long)]
+        disk: Option<String>,
+    },
+
+    /// Assign a user to a workspace
+    Assign {
+        /// User name
+        user: String,
 
-        /// Timeout for stop before restart (seconds)
-        #[arg(short, long, default_value = "10")]
-        timeout: u64,
-    },
+        /// Workspace name
+        #[arg(required_unless_present = "remove")]
+        workspace: Option<String>,
 
-    /// Surge orchestration commands (like docker-compose)
-    Surge {
-        #[command(subcommand)]
-        command: SurgeCommands,
+        /// Take the user out of their workspace instead
+        #[arg(long, conflicts_with = "workspace")]
+        remove: bool,
     },
 
-    /// Gaming-specific commands
-    Gaming {
-        #[command(subcommand)]
-        command: GamingCommands,
+    /// Show a workspace's usage against its quota
+    Usage {
+        /// Workspace name (default: your own)
+        workspace: Option<String>,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
     },
+}
 
-    /// Network management
-    Network {
+#[derive(Subcommand)]
+pub enum DebugCommands {
+    /// Crash dumps collected for failed containers
+    Crashes {
         #[command(subcommand)]
-        command: NetworkCommands,
+        command: CrashCommands,
     },
 
-    /// Volume management
-    Volume {
-        #[command(subcommand)]
-        command: VolumeCommands,
+    /// Open a shell in a running container, even one without a shell
+    Shell {
+        /// Container name or ID
+        container: String,
+
+        /// Toolbox directory of static binaries, or an image to take busybox from
+        #[arg(long)]
+        toolbox: Option<String>,
+
+        /// Use a sidecar container instead of copying the toolbox in
+        #[arg(long)]
+        sidecar: bool,
+
+        /// User to run the shell as
+        #[arg(short, long)]
+        user: Option<String>,
     },
+}
 
-    /// Snapshot management (BTRFS/ZFS)
-    Snapshot {
-        #[command(subcommand)]
-        command: SnapshotCommands,
+#[derive(Subcommand)]
+pub enum CrashCommands {
+    /// List crash dumps
+    #[command(alias = "list")]
+    Ls {
+        /// Only show crashes of this container
+        container: Option<String>,
     },
 
-    /// Docker/Podman compatibility layer
-    Compat {
-        #[command(subcommand)]
-        command: compat::CompatCommands,
+    /// Show the latest crash of a container, or a crash by ID
+    Show {
+        /// Container name or crash ID
+        container: String,
     },
 }
 
@@ -169,6 +1480,70 @@ pub enum SurgeCommands {
         /// Recreate containers
         #[arg(long)]
         force_recreate: bool,
+
+        /// Override every service's pull_policy
+        #[arg(long, value_enum)]
+        pull: Option<bolt::runtime::pull::PullPolicy>,
+
+        /// Publish a free port instead of a host port that is taken
+        #[arg(long)]
+        remap_ports: bool,
+
+        /// After a service fails: stop (fail-fast) or keep starting the
+        /// services that don't depend on it (continue)
+        #[arg(long, value_enum, default_value = "fail-fast")]
+        on_failure: bolt::surge::report::FailureMode,
+
+        /// Write the per-service outcome summary as JSON to a file ("-" for stdout)
+        #[arg(long, value_name = "PATH")]
+        summary: Option<std::path::PathBuf>,
+
+        /// Override a Boltfile setting for this run, e.g.
+        /// services.web.environment.DEBUG=1 or services.web.ports[0]=8081:80
+        #[arg(long = "set", value_name = "PATH=VALUE")]
+        set: Vec<bolt::config::overrides::Override>,
+
+        /// After a successful run, save the --set overrides to this profile
+        /// of <Boltfile>.overrides.toml, to apply again with --profile
+        #[arg(long, value_name = "NAME", requires = "set")]
+        save_profile: Option<String>,
+    },
+
+    /// Show what `surge up` would change, including upstream image updates
+    Plan {
+        /// Services to plan (default: all)
+        services: Vec<String>,
+
+        /// Plan with taken host ports remapped, as `surge up --remap-ports`
+        #[arg(long)]
+        remap_ports: bool,
+
+        /// Plan with a Boltfile setting overridden, as `surge up --set`
+        #[arg(long = "set", value_name = "PATH=VALUE")]
+        set: Vec<bolt::config::overrides::Override>,
+
+        /// Only check whether the services' CPU, memory, GPU and disk
+        /// reservations fit on this host
+        #[arg(long)]
+        resources: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Start services and sync or rebuild them as their files change
+    Dev {
+        /// Services to watch (default: all)
+        services: Vec<String>,
+
+        /// Milliseconds between checks for changes
+        #[arg(long, default_value_t = 500)]
+        interval: u64,
+
+        /// Only watch; don't start services that are not running
+        #[arg(long)]
+        no_start: bool,
     },
 
     /// Stop services
@@ -179,6 +1554,14 @@ pub enum SurgeCommands {
         /// Remove volumes
         #[arg(short, long)]
         volumes: bool,
+
+        /// Also remove containers of services no longer in the Boltfile
+        #[arg(long)]
+        remove_orphans: bool,
+
+        /// Print what was removed as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Show service status
@@ -203,6 +1586,99 @@ pub enum SurgeCommands {
         /// Service scaling (service=count)
         services: Vec<String>,
     },
+
+    /// Idle suspension of services
+    Idle {
+        #[command(subcommand)]
+        command: IdleCommands,
+    },
+
+    /// Desktop integration of GUI services
+    Desktop {
+        #[command(subcommand)]
+        command: DesktopCommands,
+    },
+
+    /// Events, gauges and automation from services' log lines
+    Hooks {
+        #[command(subcommand)]
+        command: HooksCommands,
+    },
+
+    /// Show availability, latency and error budgets of services with an slo
+    Slo {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Output in the Prometheus text format
+        #[arg(long, conflicts_with = "json")]
+        prometheus: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HooksCommands {
+    /// Show the gauges, matched events and automation runs per container
+    Status {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Output in the Prometheus text format
+        #[arg(long, conflicts_with = "json")]
+        prometheus: bool,
+    },
+
+    /// Show the events log hooks recorded for a service
+    Events {
+        /// Service name
+        service: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DesktopCommands {
+    /// Show desktop sessions, their transfer directories and clipboard use
+    Status {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Copy files into a service's transfer directory
+    Send {
+        /// Service name
+        service: String,
+
+        /// Files to send
+        #[arg(required = true)]
+        files: Vec<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IdleCommands {
+    /// Show which services are suspended and how often they were
+    Status {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Output in the Prometheus text format
+        #[arg(long, conflicts_with = "json")]
+        prometheus: bool,
+    },
+
+    /// Resume a suspended service
+    Wake {
+        /// Service name
+        service: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -215,7 +1691,7 @@ pub enum GamingCommands {
 
     /// Setup Wine/Proton container
     Wine {
-        /// Proton version
+        /// Proton version: GE-Proton9-20, latest, wine-ge:<tag>, or Steam's e.g. 8.0
         #[arg(long)]
         proton: Option<String>,
 
@@ -243,6 +1719,13 @@ pub enum GamingCommands {
     /// Start Wayland gaming session
     Wayland,
 
+    /// Show the display services with `gaming.display` get, and their gamescope sessions
+    Display {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Configure real-time gaming optimizations
     Realtime {
         /// Enable optimizations
@@ -254,16 +1737,130 @@ pub enum GamingCommands {
     Optimize {
         /// Process ID of the game
         pid: u32,
+
+        /// Pin the game to P-cores on hybrid CPUs and validate its affinity masks
+        #[arg(long)]
+        hybrid_aware: bool,
     },
 
     /// Show gaming performance report
     Performance,
+
+    /// Protect GPUs from sustained heat and power draw
+    Thermal {
+        #[command(subcommand)]
+        command: ThermalCommands,
+    },
+
+    /// Local copies of Wine prefixes kept on network storage
+    Prefix {
+        #[command(subcommand)]
+        command: PrefixCommands,
+    },
+
+    /// Proton-GE and Wine-GE versions in the shared cache
+    Proton {
+        #[command(subcommand)]
+        command: ProtonCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProtonCommands {
+    /// Show the cached versions, or the releases available for download
+    #[command(alias = "ls")]
+    List {
+        /// List the releases on GitHub instead
+        #[arg(long)]
+        available: bool,
+
+        /// Which releases to list with --available
+        #[arg(long, value_enum, default_value = "proton-ge", requires = "available")]
+        flavor: bolt::gaming::proton::Flavor,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Download and verify a version into the cache
+    Install {
+        /// GE-Proton9-20, latest, wine-ge:<tag> or wine-ge:latest
+        version: String,
+    },
+
+    /// Remove a version no container uses from the cache
+    #[command(alias = "rm")]
+    Remove {
+        /// Version as given to install, e.g. GE-Proton9-20
+        version: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PrefixCommands {
+    /// Sync local prefix copies with the share now
+    Sync {
+        /// Service to sync (default: every cached prefix)
+        service: Option<String>,
+
+        /// Resolve paths changed on both sides by keeping this side
+        #[arg(long, value_enum)]
+        prefer: Option<bolt::gaming::prefix::Prefer>,
+
+        /// Print the reports as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show cached prefixes, when they last synced and their conflicts
+    #[command(alias = "ls")]
+    List {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ThermalCommands {
+    /// Apply the services' thermal policies until interrupted
+    Watch {
+        /// Services to watch (default: all with a thermal policy)
+        services: Vec<String>,
+    },
+
+    /// Show the actions taken by thermal policies
+    Events {
+        /// Only show events of this service
+        service: Option<String>,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum GpuCommands {
-    /// List available GPUs
-    List,
+    /// List GPUs, MIG instances and virtual functions with the containers holding them
+    #[command(alias = "ls")]
+    List {
+        /// Show the containers on each GPU, their priority and what enforces it
+        #[arg(long)]
+        assignments: bool,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List fractional GPU slices with their memory and utilization
+    Slices {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Configure NVIDIA GPU
     Nvidia {
@@ -309,8 +1906,94 @@ pub enum GpuCommands {
     /// Check nvbind runtime compatibility
     Check,
 
-    /// Show GPU runtime performance comparison
-    Benchmark,
+    /// Compare frame times, startup and GPU utilization of a short vkmark or
+    /// glmark2 run with standard GPU passthrough and with nvbind
+    Benchmark {
+        /// Workload: vulkan (vkmark) or gl (glmark2)
+        #[arg(long, default_value = "vulkan")]
+        workload: String,
+
+        /// Seconds each scene renders for
+        #[arg(long, default_value_t = 5)]
+        duration: u64,
+
+        /// Image with vkmark and glmark2-es2-drm (default: built locally)
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show GPU faults since boot and quarantined GPUs
+    Health {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Follow the kernel log for GPU faults and reset hung GPUs until interrupted
+    Watch {
+        /// How often to poll the kernel log
+        #[arg(long, default_value = "5s")]
+        interval: String,
+    },
+
+    /// Reset a GPU, quarantining it if the reset fails
+    Reset {
+        /// PCI address of the GPU (e.g. 0000:01:00.0)
+        device: String,
+
+        /// Reset even if processes still have the GPU open
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Return a quarantined GPU to service
+    Unquarantine {
+        /// PCI address of the GPU
+        device: String,
+    },
+
+    /// Show GPU faults, resets and quarantines recorded by `watch`
+    Events {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how GPUs are connected (NVLink, PCIe, NUMA) and which are in use
+    Topology {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the GPUs, links, NCCL settings and P2P bandwidth of AI-mode containers
+    P2p {
+        /// Only this container
+        container: Option<String>,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Recreate GPU services still running on libraries of an old driver
+    Refresh {
+        /// Only show what would be recreated
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Print the plan as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -339,6 +2022,54 @@ pub enum NetworkCommands {
         /// Network name
         name: String,
     },
+
+    /// Show a container's egress allowlist and the connections it was denied
+    Egress {
+        /// Container name
+        container: String,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the router, CGNAT and how `port_forward` services are reachable
+    Nat {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Publish ports for hosts behind NAT and answer STUN, until interrupted
+    Relay,
+
+    /// Show the records external DNS providers hold for containers
+    Dns {
+        /// Remove records of containers that are gone, follow address
+        /// changes and retry failed records first
+        #[arg(long)]
+        sync: bool,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export bridges, containers, QUIC links, port mappings, tunnels and
+    /// egress policies as a graph
+    Topo {
+        /// Output format (dot, json, mermaid)
+        #[arg(long, default_value = "dot")]
+        format: String,
+
+        /// Stream the graph and its changes over a websocket instead
+        #[arg(long)]
+        live: bool,
+
+        /// Address the websocket listens on
+        #[arg(long, default_value = "127.0.0.1:7890")]
+        listen: std::net::SocketAddr,
+    },
 }
 
 #[derive(Subcommand)]
@@ -384,10 +2115,17 @@ pub enum VolumeCommands {
 
     /// Prune unused volumes
     Prune {
-        /// Don't prompt for confirmation
+        /// Also remove volumes that get mounted while pruning
         #[arg(short, long)]
         force: bool,
     },
+
+    /// Show read-ahead and hot path residency of tuned volumes
+    Cache {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -402,7 +2140,7 @@ pub enum SnapshotCommands {
         #[arg(short, long)]
         description: Option<String>,
 
-        /// Type of snapshot
+        /// Type of snapshot (manual, hourly, daily, weekly, monthly, yearly)
         #[arg(long, default_value = "manual")]
         snapshot_type: String,
     },
@@ -470,6 +2208,62 @@ pub enum SnapshotCommands {
         #[arg(value_enum)]
         action: AutoAction,
     },
+
+    /// Export a capsule snapshot to a portable bundle
+    Export {
+        /// Snapshot ID or name
+        snapshot: String,
+
+        /// Output file
+        #[arg(short, long)]
+        output: String,
+    },
+
+    /// Import a snapshot bundle
+    Import {
+        /// Bundle file
+        file: String,
+
+        /// Expected SHA-256 of the bundle
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+
+    /// Ship a snapshot to another host over QUIC
+    Send {
+        /// Snapshot ID or name
+        snapshot: String,
+
+        /// Receiving host (host or host:port)
+        #[arg(long)]
+        to: String,
+
+        /// Expected receiver certificate SHA-256 fingerprint
+        #[arg(long)]
+        fingerprint: Option<String>,
+
+        /// Reconnect attempts before giving up
+        #[arg(long, default_value = "5")]
+        retries: u32,
+    },
+
+    /// Receive snapshots shipped from other hosts
+    Receive {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:7946")]
+        listen: String,
+
+        /// Authorize a sender by its certificate fingerprint (remembered)
+        #[arg(long = "allow-sender")]
+        allow_sender: Vec<String>,
+
+        /// Largest bundle to accept
+        #[arg(long, default_value = "64GB")]
+        max_size: String,
+    },
+
+    /// Print the certificate fingerprint receivers must authorize for this host
+    Fingerprint,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
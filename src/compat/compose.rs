@@ -93,6 +93,7 @@ impl ComposeCompat {
             networks: None,
             volumes: None,
             snapshots: None,
+            maintenance: None,
         };
 
         // Convert services
@@ -152,13 +153,13 @@ impl ComposeCompat {
 
         // Dependencies
         if let Some(depends_on) = &compose_service.depends_on {
-            service.depends_on = Some(depends_on.clone());
+            service.depends_on = Some(depends_on.clone().into());
         }
 
         // Network configuration
         if let Some(networks) = &compose_service.networks {
             if !networks.is_empty() {
-                service.networks = Some(networks.clone());
+                service.networks = Some(networks.clone().into());
             }
         }
 
@@ -312,12 +313,6 @@ impl ComposeCompat {
                 );
             }
 
-            if let Some(networks) = &service.networks {
-                if networks.len() > 1 {
-                    notes.push("  🌐 Multiple networks detected - Bolt supports single network per service".to_string());
-                }
-            }
-
             notes.push("".to_string());
         }
 
@@ -454,15 +449,6 @@ impl ComposeCompat {
                     name
                 ));
             }
-
-            if let Some(networks) = &service.networks {
-                if networks.len() > 1 {
-                    warnings.push(format!(
-                        "Service '{}': Multiple networks - Bolt supports single network",
-                        name
-                    ));
-                }
-            }
         }
 
         Ok(warnings)
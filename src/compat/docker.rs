@@ -151,7 +151,7 @@ impl DockerCompat {
 
         let image = &args[0];
         println!("📥 Pulling image: {}", image);
-        self.runtime.pull_image(image).await?;
+        self.runtime.pull_image(image, None).await?;
         println!("✅ Pulled: {}", image);
 
         Ok(())
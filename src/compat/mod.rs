@@ -406,7 +406,8 @@ impl DockerApiCompat {
             .unwrap_or_else(|_| serde_json::json!({ "fromImage": "nginx:latest" }));
 
         let image = request["fromImage"].as_str().unwrap_or("nginx:latest");
-        self.runtime.pull_image(image).await?;
+        let platform = request["platform"].as_str().filter(|p| !p.is_empty());
+        self.runtime.pull_image(image, platform).await?;
 
         Ok(serde_json::json!({
             "status": "Downloaded newer image",
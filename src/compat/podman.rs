@@ -157,7 +157,7 @@ impl PodmanCompat {
 
         let image = &args[0];
         println!("📥 Pulling image: {}", image);
-        self.runtime.pull_image(image).await?;
+        self.runtime.pull_image(image, None).await?;
         println!("✅ Pulled: {}", image);
 
         Ok(())
@@ -0,0 +1,245 @@
+// Boltfile service inheritance
+//
+// A service can start from another with `extends = "base"`. The base is
+// looked up in the same Boltfile first, then in the files listed in the
+// top-level `include`; included files only provide bases, their services
+// are not run themselves. Bases can extend other bases.
+//
+// Merging, child over base:
+// - tables (environment, labels, gaming, ...) are merged key by key, recursively
+// - lists (ports, volumes, devices, ...) are appended to the base's, skipping
+//   entries the base already has
+// - `command` and `entrypoint` are argv, so they replace the base's
+// - anything else replaces the base's value
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Lists that replace instead of append
+const REPLACED_LISTS: &[&str] = &["command", "entrypoint"];
+
+/// Whether a parsed Boltfile uses `include` or `extends` at all
+pub(crate) fn uses_inheritance(doc: &Table) -> bool {
+    doc.contains_key("include")
+        || doc
+            .get("services")
+            .and_then(Value::as_table)
+            .is_some_and(|services| {
+                services
+                    .values()
+                    .any(|s| s.as_table().is_some_and(|s| s.contains_key("extends")))
+            })
+}
+
+/// Resolve `include` and every service's `extends`, returning the document
+/// with plain, fully merged services
+pub(crate) fn flatten(mut doc: Table, base_dir: &Path) -> Result<Table> {
+    let mut bases = Table::new();
+    let mut seen = HashSet::new();
+    load_includes(&mut doc, base_dir, &mut bases, &mut seen)?;
+
+    let services = match doc.remove("services") {
+        Some(Value::Table(services)) => services,
+        Some(_) => return Err(anyhow!("`services` must be a table")),
+        None => Table::new(),
+    };
+
+    let mut flattened = Table::new();
+    for name in services.keys() {
+        let service = resolve(name, &services, &bases, false, &mut Vec::new())?;
+        flattened.insert(name.clone(), Value::Table(service));
+    }
+    doc.insert("services".to_string(), Value::Table(flattened));
+    Ok(doc)
+}
+
+/// Collect the services of included files (and their includes) as bases
+fn load_includes(
+    doc: &mut Table,
+    base_dir: &Path,
+    bases: &mut Table,
+    seen: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let includes = match doc.remove("include") {
+        None => return Ok(()),
+        Some(Value::String(path)) => vec![path],
+        Some(Value::Array(paths)) => paths
+            .into_iter()
+            .map(|p| match p {
+                Value::String(path) => Ok(path),
+                other => Err(anyhow!("`include` entries must be paths, got {}", other)),
+            })
+            .collect::<Result<_>>()?,
+        Some(other) => {
+            return Err(anyhow!(
+                "`include` must be a path or list of paths, got {}",
+                other
+            ));
+        }
+    };
+
+    for include in includes {
+        let path = base_dir.join(&include);
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("Included Boltfile {} not found", path.display()))?;
+        if !seen.insert(canonical.clone()) {
+            continue;
+        }
+
+        let content = std::fs::read_to_string(&canonical)
+            .with_context(|| format!("Failed to read included Boltfile {}", path.display()))?;
+        let mut included: Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse included Boltfile {}", path.display()))?;
+        let dir = canonical.parent().unwrap_or(base_dir).to_path_buf();
+        load_includes(&mut included, &dir, bases, seen)?;
+
+        if let Some(Value::Table(services)) = included.remove("services") {
+            for (name, service) in services {
+                if bases.contains_key(&name) {
+                    return Err(anyhow!(
+                        "Service '{}' is defined by more than one included Boltfile (again in {})",
+                        name,
+                        path.display()
+                    ));
+                }
+                bases.insert(name, service);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merge a service with its chain of bases. Services of the Boltfile itself
+/// can extend each other or an included base; included bases can only
+/// extend other included bases.
+fn resolve(
+    name: &str,
+    services: &Table,
+    bases: &Table,
+    included: bool,
+    chain: &mut Vec<String>,
+) -> Result<Table> {
+    let scope = if included { bases } else { services };
+    let label = if included {
+        format!("{} (included)", name)
+    } else {
+        name.to_string()
+    };
+    if chain.contains(&label) {
+        chain.push(label);
+        return Err(anyhow!("Service inheritance cycle: {}", chain.join(" -> ")));
+    }
+
+    let mut service = scope
+        .get(name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Service '{}' extends '{}', which is not defined in the Boltfile or its includes",
+                chain.last().map(String::as_str).unwrap_or(""),
+                name
+            )
+        })?
+        .as_table()
+        .ok_or_else(|| anyhow!("Service '{}' must be a table", name))?
+        .clone();
+    chain.push(label);
+
+    let Some(parent) = service.remove("extends") else {
+        chain.pop();
+        return Ok(service);
+    };
+    let parent = parent
+        .as_str()
+        .ok_or_else(|| anyhow!("`extends` of service '{}' must be a service name", name))?;
+
+    // Bases come from this file first, except that `extends = "web"` inside
+    // `web` can only mean an included base
+    let parent_included = included || parent == name || !services.contains_key(parent);
+    let mut merged = resolve(parent, services, bases, parent_included, chain)?;
+    chain.pop();
+
+    merge(&mut merged, service);
+    Ok(merged)
+}
+
+fn merge(base: &mut Table, child: Table) {
+    for (key, value) in child {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base_table)), Value::Table(child_table)) => {
+                merge(base_table, child_table);
+            }
+            (Some(Value::Array(base_list)), Value::Array(child_list))
+                if !REPLACED_LISTS.contains(&key.as_str()) =>
+            {
+                for item in child_list {
+                    if !base_list.contains(&item) {
+                        base_list.push(item);
+                    }
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flatten_str(doc: &str) -> Result<Table> {
+        flatten(toml::from_str(doc).unwrap(), Path::new("."))
+    }
+
+    #[test]
+    fn merges_chain_and_detects_cycles() {
+        let doc = flatten_str(
+            r#"
+            project = "games"
+
+            [services.base]
+            image = "game-server:latest"
+            ports = ["27015:27015"]
+            command = ["./server", "--default"]
+            environment = { MAX_PLAYERS = "16", MAP = "de_dust2" }
+
+            [services.tuned]
+            extends = "base"
+            environment = { TICKRATE = "128" }
+
+            [services.eu]
+            extends = "tuned"
+            ports = ["27016:27016"]
+            command = ["./server", "--region", "eu"]
+            environment = { MAP = "de_inferno" }
+            "#,
+        )
+        .unwrap();
+
+        let eu = doc["services"]["eu"].as_table().unwrap();
+        assert!(!eu.contains_key("extends"));
+        assert_eq!(eu["image"].as_str(), Some("game-server:latest"));
+        assert_eq!(eu["ports"].as_array().unwrap().len(), 2);
+        assert_eq!(eu["command"].as_array().unwrap().len(), 3);
+        let env = eu["environment"].as_table().unwrap();
+        assert_eq!(env["MAX_PLAYERS"].as_str(), Some("16"));
+        assert_eq!(env["TICKRATE"].as_str(), Some("128"));
+        assert_eq!(env["MAP"].as_str(), Some("de_inferno"));
+
+        let err = flatten_str(
+            r#"
+            [services.a]
+            extends = "b"
+            [services.b]
+            extends = "c"
+            [services.c]
+            extends = "a"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+    }
+}
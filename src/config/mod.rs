@@ -1,9 +1,14 @@
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tracing::{debug, info, warn};
 
+mod extends;
+pub mod overrides;
+pub mod reload;
+pub mod template;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BoltFile {
     pub project: String,
@@ -11,11 +16,16 @@ pub struct BoltFile {
     pub networks: Option<HashMap<String, Network>>,
     pub volumes: Option<HashMap<String, Volume>>,
     pub snapshots: Option<SnapshotConfig>,
+    /// When maintenance may disturb the project, e.g. "Sun 03:00-05:00";
+    /// overrides `[maintenance] window` of config.toml
+    pub maintenance: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct Service {
     pub image: Option<String>,
+    /// When to pull `image`: always, if-not-present, never or digest-pinned
+    pub pull_policy: Option<crate::runtime::pull::PullPolicy>,
     pub build: Option<String>,
     pub capsule: Option<String>,
     pub command: Option<Vec<String>>,
@@ -24,15 +34,24 @@ pub struct Service {
     pub volumes: Option<Vec<String>>,
     pub environment: Option<HashMap<String, String>>,
     pub env: Option<HashMap<String, String>>,
-    pub depends_on: Option<Vec<String>>,
+    pub depends_on: Option<DependsOn>,
+    /// no, always, on-failure or unless-stopped; with a `healthcheck`, also
+    /// whether a container that turns unhealthy is restarted
     pub restart: Option<String>,
-    pub networks: Option<Vec<String>>,
+    pub networks: Option<ServiceNetworks>,
     pub storage: Option<Storage>,
     pub auth: Option<Auth>,
     pub gaming: Option<GamingConfig>,
     pub working_dir: Option<String>,
     pub user: Option<String>,
+    /// Defaults to the container name, so it survives recreation
     pub hostname: Option<String>,
+    pub domainname: Option<String>,
+    /// UTS namespace: "private" (default) or "host"
+    pub uts: Option<String>,
+    /// "auto" (default) for a generated machine-id that persists across
+    /// restarts, "none" to keep the image's, or a fixed 32-digit hex id
+    pub machine_id: Option<String>,
     pub container_name: Option<String>,
     pub privileged: Option<bool>,
     pub read_only: Option<bool>,
@@ -43,6 +62,9 @@ pub struct Service {
     pub ipc: Option<String>,
     pub platform: Option<String>,
     pub labels: Option<HashMap<String, String>>,
+    /// OCI annotations of the container's runtime spec, read by hooks and
+    /// CDI tooling
+    pub annotations: Option<HashMap<String, String>>,
     pub devices: Option<Vec<String>>,
     pub cap_add: Option<Vec<String>>,
     pub cap_drop: Option<Vec<String>>,
@@ -59,10 +81,114 @@ pub struct Service {
     pub healthcheck: Option<HealthcheckConfig>,
     pub cpu_limit: Option<String>,
     pub memory_limit: Option<String>,
+    /// Core type to run on with hybrid CPUs (any, p-cores, e-cores)
+    pub cpu_placement: Option<crate::optimizations::hybrid::CorePlacement>,
+    /// Energy/performance preference applied to the service's CPUs
+    pub energy_performance_preference:
+        Option<crate::optimizations::hybrid::EnergyPerformancePreference>,
+    /// Execution tier ("default" or "hardened" for untrusted images)
+    pub sandbox: Option<crate::runtime::sandbox::SandboxTier>,
+    /// Run hardened services under gVisor when it is installed
+    pub syscall_interception: Option<bool>,
+    /// Vulkan layers for GPU services
+    pub vulkan: Option<VulkanConfig>,
+    /// When the service can take traffic, as opposed to `healthcheck` (is it alive)
+    pub readiness: Option<ReadinessConfig>,
+    /// Protect the GPU during long sessions by throttling the service when it runs hot
+    pub thermal: Option<ThermalConfig>,
+    /// Let in-flight connections finish before a replica is stopped
+    pub drain: Option<DrainConfig>,
+    /// How long the service gets to exit after SIGTERM before it is killed,
+    /// e.g. "30s" (default: the runtime's 10s)
+    pub stop_grace_period: Option<String>,
+    /// What runs the service: "oci" (default) or "wasm"
+    pub runtime: Option<crate::runtime::wasm::ServiceRuntime>,
+    /// The module to run with `runtime = "wasm"`
+    pub wasm: Option<WasmConfig>,
+    /// What `bolt surge dev` watches and does on changes
+    pub develop: Option<DevelopConfig>,
+    /// Where the container may connect to
+    pub egress: Option<EgressConfig>,
+    /// Suspend the service while it's idle
+    pub idle: Option<IdleConfig>,
+    /// Own Wayland session with clipboard bridging and a transfer directory
+    pub desktop_integration: Option<DesktopIntegrationConfig>,
+    /// Events, gauges and automation from the service's log lines
+    pub log_hooks: Option<LogHooksConfig>,
+    /// Keep maintenance away: no image updates, and no host reboot while
+    /// the service runs
+    pub never_disturb: Option<bool>,
+    /// Ask the router to forward the published ports, or publish them
+    /// through the `[nat] relay` when it can't
+    pub port_forward: Option<bool>,
+    /// Shared package manager caches for the service's build and container,
+    /// e.g. ["cargo", "npm"] or "name:/path"
+    pub cache_volumes: Option<Vec<String>>,
+    /// Availability and latency objectives, checked by probing the published
+    /// ports (`slo = { availability = "99.9%", p99_ms = 50 }`)
+    pub slo: Option<SloConfig>,
 }
 
 pub type NetworkConfig = Network;
 
+/// Networks a service joins: a list of names, or a table with per-network
+/// settings (`networks = { frontend = { aliases = ["api"] }, backend = {} }`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum ServiceNetworks {
+    List(Vec<String>),
+    Map(BTreeMap<String, ServiceNetworkConfig>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct ServiceNetworkConfig {
+    /// Extra DNS names for the service on this network
+    pub aliases: Option<Vec<String>>,
+    /// Static addresses; must fall inside the network's subnet
+    pub ipv4_address: Option<String>,
+    pub ipv6_address: Option<String>,
+}
+
+impl ServiceNetworks {
+    pub fn len(&self) -> usize {
+        match self {
+            ServiceNetworks::List(list) => list.len(),
+            ServiceNetworks::Map(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            ServiceNetworks::List(list) => list.iter().map(String::as_str).collect(),
+            ServiceNetworks::Map(map) => map.keys().map(String::as_str).collect(),
+        }
+    }
+
+    /// Each network with its settings (defaults for the list form)
+    pub fn attachments(&self) -> Vec<(&str, ServiceNetworkConfig)> {
+        match self {
+            ServiceNetworks::List(list) => list
+                .iter()
+                .map(|name| (name.as_str(), ServiceNetworkConfig::default()))
+                .collect(),
+            ServiceNetworks::Map(map) => map
+                .iter()
+                .map(|(name, config)| (name.as_str(), config.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl From<Vec<String>> for ServiceNetworks {
+    fn from(list: Vec<String>) -> Self {
+        ServiceNetworks::List(list)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Network {
     pub driver: String,
@@ -74,6 +200,64 @@ pub struct Network {
     pub ipam: Option<IpamConfig>,
     pub external: Option<bool>,
     pub name: Option<String>,
+    pub hardware: Option<NetworkHardware>,
+    /// Register containers' addresses on this network with an external DNS
+    /// provider from `[dns.providers]`
+    pub dns: Option<NetworkDns>,
+}
+
+/// `[networks.<name>.dns]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NetworkDns {
+    /// Name of the provider in `[dns.providers]`
+    pub provider: String,
+    /// Zone records are created in, as `<container>.<zone>`
+    pub zone: String,
+}
+
+/// `[networks.<name>.hardware]`: physical NIC features backing a network
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct NetworkHardware {
+    /// Bonded or teamed uplink attached to the network's bridge
+    pub uplink: Option<UplinkConfig>,
+    /// SR-IOV virtual functions handed directly to containers
+    pub sriov: Option<SriovConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UplinkConfig {
+    /// Name of the aggregate interface, e.g. "bond0"
+    pub name: String,
+    #[serde(default)]
+    pub kind: UplinkKind,
+    /// Bonding mode or teamd runner, e.g. "802.3ad", "active-backup", "lacp"
+    pub mode: Option<String>,
+    /// Member NICs
+    pub interfaces: Vec<String>,
+    pub mtu: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UplinkKind {
+    #[default]
+    Bond,
+    Team,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SriovConfig {
+    /// Physical function (the NIC's netdev), e.g. "enp5s0f0"
+    pub physical_function: String,
+    /// Number of VFs to create on the physical function
+    pub num_vfs: u32,
+    /// Driver to bind VFs to; defaults to the NIC's own VF driver.
+    /// "vfio-pci" is only useful for VM capsules.
+    pub driver: Option<String>,
+    pub vlan: Option<u16>,
+    /// Allow containers to change their VF's MAC and enable promiscuous mode
+    #[serde(default)]
+    pub trust: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -100,6 +284,11 @@ pub struct Volume {
     pub external: Option<bool>,
     pub labels: Option<HashMap<String, String>>,
     pub name: Option<String>,
+    /// Page cache behaviour: "streaming", "database" or "default"
+    pub cache_profile: Option<crate::volume::cache::CacheProfile>,
+    /// Files or directories, relative to the volume, pre-faulted into the
+    /// page cache when a container using the volume starts
+    pub hot_paths: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -114,7 +303,7 @@ pub struct Auth {
     pub password: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct GamingConfig {
     pub enabled: bool,
     pub gpu_passthrough: bool,
@@ -137,35 +326,438 @@ pub struct GamingConfig {
     pub audio: Option<AudioConfig>,
     pub wine: Option<WineConfig>,
     pub performance: Option<PerformanceConfig>,
+    /// Pass the host's Wayland or X11 display through, optionally inside a
+    /// nested gamescope
+    pub display: Option<DisplayConfig>,
+}
+
+/// `[services.<name>.gaming.display]`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DisplayConfig {
+    /// auto (default), wayland or x11
+    pub server: Option<crate::gaming::display::DisplayServer>,
+    pub gamescope: Option<GamescopeConfig>,
+}
+
+/// `[services.<name>.gaming.display.gamescope]`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct GamescopeConfig {
+    /// Resolution the game renders at; defaults to `gaming.resolution`
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Size of the gamescope window
+    pub output_width: Option<u32>,
+    pub output_height: Option<u32>,
+    /// Frame rate limit; defaults to `gaming.refresh_rate`
+    pub refresh: Option<u32>,
+    pub fullscreen: Option<bool>,
+    /// HDR output; games also get `DXVK_HDR=1`
+    pub hdr: Option<bool>,
+    /// Variable refresh rate (adaptive sync)
+    pub vrr: Option<bool>,
+    /// Upscaling filter: linear, nearest, fsr, nis or pixel
+    pub filter: Option<String>,
+    /// How the game is fit to the window: auto, integer, fit, fill or stretch
+    pub scaler: Option<String>,
+    /// Run the service's command under gamescope in the container rather
+    /// than next to a gamescope on the host; the image must have gamescope
+    pub wrap: Option<bool>,
+    /// Passed to gamescope as they are
+    pub extra_args: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LoggingConfig {
+    /// journald, syslog, file, none, or any other runtime log driver
     pub driver: String,
+    /// Driver options, e.g. `address` for syslog or `max-size`/`max-age` for file
+    #[serde(default)]
     pub options: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HealthcheckConfig {
+    /// `["CMD", ...]`, `["CMD-SHELL", "..."]`, `["NONE"]` or a plain command
+    #[serde(alias = "command")]
     pub test: Vec<String>,
     pub interval: Option<String>,
     pub timeout: Option<String>,
+    /// Consecutive failures before the container is unhealthy
     pub retries: Option<u32>,
+    /// Grace period after start in which failures don't count
     pub start_period: Option<String>,
+    #[serde(default)]
     pub disable: bool,
 }
 
+/// `depends_on`: a list of services, or each service with the condition to
+/// wait for, as in Compose
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum DependsOn {
+    List(Vec<String>),
+    Conditions(BTreeMap<String, Dependency>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Dependency {
+    #[serde(default)]
+    pub condition: DependencyCondition,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyCondition {
+    /// Started, and ready if it has a readiness probe
+    #[default]
+    ServiceStarted,
+    /// Its healthcheck passes
+    ServiceHealthy,
+}
+
+impl DependsOn {
+    /// The services depended on
+    pub fn names(&self) -> Box<dyn Iterator<Item = &String> + Send + '_> {
+        match self {
+            DependsOn::List(names) => Box::new(names.iter()),
+            DependsOn::Conditions(conditions) => Box::new(conditions.keys()),
+        }
+    }
+
+    pub fn condition(&self, name: &str) -> DependencyCondition {
+        match self {
+            DependsOn::List(_) => DependencyCondition::ServiceStarted,
+            DependsOn::Conditions(conditions) => conditions
+                .get(name)
+                .map(|d| d.condition)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Vec<String>> for DependsOn {
+    fn from(names: Vec<String>) -> Self {
+        DependsOn::List(names)
+    }
+}
+
+/// Readiness probe: exactly one of `tcp`, `http` or `command`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReadinessConfig {
+    /// Ready once this container port accepts connections
+    pub tcp: Option<u16>,
+    /// Ready once this path answers 200 on `port`
+    pub http: Option<String>,
+    pub port: Option<u16>,
+    /// Ready once this command exits 0 inside the container
+    pub command: Option<Vec<String>>,
+    pub initial_delay: String,
+    pub interval: String,
+    pub timeout: String,
+    /// Consecutive passes before the service counts as ready
+    pub success_threshold: u32,
+    /// Consecutive failures before a ready service stops taking traffic
+    pub failure_threshold: u32,
+    /// How long dependents wait for the service at startup
+    pub start_timeout: String,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            tcp: None,
+            http: None,
+            port: None,
+            command: None,
+            initial_delay: "0s".to_string(),
+            interval: "2s".to_string(),
+            timeout: "1s".to_string(),
+            success_threshold: 1,
+            failure_threshold: 3,
+            start_timeout: "120s".to_string(),
+        }
+    }
+}
+
+/// `[services.<name>.thermal]`: what to do when the service's GPU stays above
+/// a temperature or power limit
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ThermalConfig {
+    /// Trip above this GPU temperature (°C)
+    pub max_temperature: Option<u32>,
+    /// Trip above this board power draw (W)
+    pub max_power: Option<u32>,
+    /// How long a limit must be exceeded, and then cleared, before acting
+    pub sustain: String,
+    /// Degrees below `max_temperature` the GPU must cool to before restoring
+    pub temperature_hysteresis: u32,
+    /// Watts below `max_power` the draw must fall to before restoring
+    pub power_hysteresis: u32,
+    pub action: crate::gaming::thermal::ThermalAction,
+    /// FPS cap applied by the `frame-limit` action
+    pub frame_limit: u32,
+    /// How often GPU metrics are sampled
+    pub interval: String,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            max_temperature: None,
+            max_power: None,
+            sustain: "30s".to_string(),
+            temperature_hysteresis: 5,
+            power_hysteresis: 20,
+            action: crate::gaming::thermal::ThermalAction::Notify,
+            frame_limit: 30,
+            interval: "5s".to_string(),
+        }
+    }
+}
+
+/// `[services.<name>.drain]`: how a replica is taken out of service before
+/// it is stopped
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DrainConfig {
+    /// Longest to wait for connections to finish before stopping anyway
+    pub timeout: String,
+    /// Container ports whose TCP connections count; empty means every port
+    /// the container listens on
+    pub ports: Vec<u16>,
+    /// Run in the container when draining starts, e.g. to stop taking new matches
+    pub command: Option<Vec<String>>,
+    /// Run in the container while draining; exit 0 means no sessions are left.
+    /// Needed for QUIC and other UDP sessions, which the kernel can't count.
+    pub idle_check: Option<Vec<String>>,
+    /// How often connections are counted
+    pub interval: String,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            timeout: "30s".to_string(),
+            ports: Vec::new(),
+            command: None,
+            idle_check: None,
+            interval: "2s".to_string(),
+        }
+    }
+}
+
+/// `[services.<name>.wasm]`: a WASI module run by wasmtime
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WasmConfig {
+    /// Component or core module, relative to the Boltfile
+    pub module: String,
+    /// Linear memory cap, e.g. "128MB"; defaults to the service's memory_limit
+    pub max_memory: Option<String>,
+    /// Fuel (roughly, instructions) the module may use before it is stopped
+    pub fuel: Option<u64>,
+    /// Wall-clock limit, e.g. "10m"
+    pub timeout: Option<String>,
+    /// Give the module WASI sockets on the host network
+    #[serde(default)]
+    pub network: bool,
+}
+
+/// `[services.<name>.develop]`: file watching for `bolt surge dev`, in the
+/// shape of Compose's `develop` section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct DevelopConfig {
+    #[serde(default)]
+    pub watch: Vec<WatchRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WatchRule {
+    /// File or directory to watch, relative to the Boltfile
+    pub path: String,
+    pub action: crate::surge::dev::WatchAction,
+    /// Where `sync` copies files to in the container
+    pub target: Option<String>,
+    /// Patterns to skip, relative to `path` (`*.log`, `node_modules`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+/// `[services.<name>.egress]`: outbound connections the container may make
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EgressConfig {
+    /// "enforce" (default) drops other traffic, "audit" only records it
+    #[serde(default)]
+    pub mode: crate::networking::egress::EgressMode,
+    /// Domain names, addresses and CIDR ranges
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Let DNS queries through to the container's resolvers (default true)
+    pub dns: Option<bool>,
+    /// How often domains are resolved again, e.g. "30s"
+    pub refresh: Option<String>,
+}
+
+/// `[services.<name>.idle]`: suspend the service while nobody uses it
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdleConfig {
+    /// How long it must be idle, e.g. "30m"
+    pub after: String,
+    /// CPU use, in percent of one core, below which it counts as idle (default 2)
+    pub cpu_percent: Option<f64>,
+    /// "pause" (default), "checkpoint" or "scale-to-zero"
+    #[serde(default)]
+    pub action: crate::runtime::idle::IdleAction,
+    /// Resume on the next connection to a published port (default true)
+    pub wake: Option<bool>,
+}
+
+/// `[services.<name>.slo]`: objectives for a service's published ports
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SloConfig {
+    /// Share of probes that must succeed, e.g. "99.9%"
+    pub availability: Option<String>,
+    /// Latency, in milliseconds, 99% of successful probes must beat
+    pub p99_ms: Option<u64>,
+    /// Rolling window the error budget covers (default "30d")
+    pub window: Option<String>,
+    /// Time between probes (default "10s")
+    pub interval: Option<String>,
+    /// Request this path over HTTP instead of only connecting; 5xx answers fail
+    pub http_path: Option<String>,
+    /// Burn rate, over both the last hour and the last 5 minutes, that
+    /// raises an alert (default 14.4)
+    pub burn_rate: Option<f64>,
+}
+
+/// `[services.<name>.log_hooks]`: turn log lines into events and gauges, and
+/// act on the gauges
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LogHooksConfig {
+    #[serde(default)]
+    pub rules: Vec<LogRuleConfig>,
+    #[serde(default)]
+    pub automation: Vec<LogAutomationConfig>,
+}
+
+/// `[[services.<name>.log_hooks.rules]]`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogRuleConfig {
+    /// Event recorded for each matching line, e.g. "player-joined"
+    pub event: String,
+    /// Regex matched against each line; named groups become event fields
+    pub pattern: String,
+    /// Gauges a matching line updates: "+1", "-1", a number to set, or
+    /// "$<group>" to set a named group's value
+    #[serde(default)]
+    pub gauges: BTreeMap<String, String>,
+}
+
+/// `[[services.<name>.log_hooks.automation]]`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogAutomationConfig {
+    pub name: String,
+    /// Condition on a gauge, e.g. "player_count == 0"
+    pub when: String,
+    /// How long the condition must hold, e.g. "10m" (default: at once)
+    #[serde(rename = "for")]
+    pub hold: Option<String>,
+    /// Command run inside the container
+    pub exec: Option<Vec<String>>,
+    /// Command run on the host
+    pub run: Option<Vec<String>>,
+}
+
+/// `[services.<name>.desktop_integration]`: run a GUI app in its own Wayland
+/// session and bridge it to the host desktop
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DesktopIntegrationConfig {
+    /// Share the clipboard with the host (default false)
+    pub clipboard: Option<bool>,
+    /// "both" (default), "to-host" or "to-container"
+    pub clipboard_direction: Option<crate::runtime::desktop::ClipboardDirection>,
+    /// text/* and image/* types that may cross (default text/plain, image/png)
+    pub clipboard_types: Option<Vec<String>>,
+    /// Largest clipboard content that crosses, e.g. "8MB" (the default)
+    pub clipboard_max_size: Option<String>,
+    /// Host directory shared for file transfers, relative to the Boltfile
+    pub transfer_dir: Option<String>,
+    /// Where the transfer directory appears in the container (default /transfer)
+    pub transfer_target: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GpuConfig {
-    pub runtime: Option<String>, // "nvbind", "docker", "nvidia", "amd"
+    pub runtime: Option<String>, // "nvbind", "nvidia-ctk", "docker", "nvidia", "amd"
     pub nvidia: Option<NvidiaConfig>,
     pub amd: Option<AmdConfig>,
+    #[serde(default)]
+    pub intel: Option<IntelConfig>,
     pub nvbind: Option<NvbindConfig>,
     pub passthrough: Option<bool>,
     pub isolation_level: Option<String>, // "shared", "exclusive", "virtual"
     pub memory_limit: Option<String>,    // e.g., "8GB"
     pub gaming: Option<GpuGamingConfig>, // nvbind gaming optimizations
     pub aiml: Option<GpuAiMlConfig>,     // nvbind AI/ML optimizations
+    /// Number of GPUs to place the container on
+    pub count: Option<u32>,
+    /// Placement hint: "nvlink-pair", "same-pcie-switch" or "same-numa"
+    pub topology: Option<crate::gaming::topology::TopologyHint>,
+    /// Multi-GPU training: P2P, NCCL defaults, IPC and /dev/shm
+    #[serde(default)]
+    pub ai: Option<GpuAiConfig>,
+    /// Who wins a shared GPU: "interactive", "normal" or "batch"; unset,
+    /// `ai`/`aiml` services are batch and `gaming` ones interactive
+    #[serde(default)]
+    pub priority: Option<crate::gaming::priority::GpuPriority>,
+    /// Share of one GPU, e.g. 0.25: a MIG instance, CUDA MPS or time-slicing
+    #[serde(default)]
+    pub fraction: Option<f64>,
+}
+
+/// `[services.<name>.gaming.gpu.ai]`: prepares a container for NCCL across
+/// its GPUs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GpuAiConfig {
+    /// Let the GPUs reach each other's memory directly over NVLink or PCIe
+    pub p2p: bool,
+    /// "auto" gives 1GB per GPU, at least 2GB
+    pub shm_size: String,
+    /// IPC namespace: "private", "shareable" or "host" for NCCL across containers
+    pub ipc: String,
+    /// Check P2P between the GPUs before starting
+    pub preflight: bool,
+    /// Refuse to start when two GPUs measure below this (needs
+    /// p2pBandwidthLatencyTest from the CUDA samples)
+    pub min_bandwidth_gbps: Option<f64>,
+    /// NCCL variables on top of Bolt's defaults, e.g. { NCCL_DEBUG = "INFO" }
+    pub nccl_env: BTreeMap<String, String>,
+}
+
+impl Default for GpuAiConfig {
+    fn default() -> Self {
+        Self {
+            p2p: true,
+            shm_size: "auto".to_string(),
+            ipc: "private".to_string(),
+            preflight: true,
+            min_bandwidth_gbps: None,
+            nccl_env: BTreeMap::new(),
+        }
+    }
+}
+
+/// `[services.<name>.vulkan]`: layers loaded into the container's Vulkan apps
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct VulkanConfig {
+    /// Enable VK_LAYER_KHRONOS_validation from the host's Vulkan SDK
+    #[serde(default)]
+    pub validation: bool,
+    /// Host implicit layers to enable by name, e.g. ["mangohud"]
+    #[serde(default)]
+    pub implicit_layers: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -186,6 +778,18 @@ pub struct AmdConfig {
     pub rocm: Option<bool>,
 }
 
+/// `[services.<name>.gaming.gpu.intel]`: an Intel GPU for transcoding or
+/// lightweight gaming
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IntelConfig {
+    /// Position among the host's Intel GPUs by PCI address (default: 0)
+    pub device: Option<u32>,
+    /// VA-API and oneVPL/Media SDK libraries for QuickSync (default: true)
+    pub quicksync: Option<bool>,
+    /// Level Zero and the compute runtime, for oneAPI workloads
+    pub level_zero: Option<bool>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct NvbindConfig {
     pub driver: Option<String>, // "auto", "nvidia-open", "proprietary", "nouveau"
@@ -286,9 +890,15 @@ pub struct AudioConfig {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WineConfig {
     pub version: Option<String>,
+    /// Proton-GE or Wine-GE release, downloaded into the shared cache, or a
+    /// Steam-installed Proton version (see `gaming::proton`)
     pub proton: Option<String>,
     pub winver: Option<String>,
     pub prefix: Option<String>,
+    /// Run on a local copy of `prefix`, synced with it when the service
+    /// starts and written back when the session ends; for prefixes on
+    /// network storage
+    pub prefix_cache: Option<bool>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -347,11 +957,32 @@ pub enum IsolationLevel {
 
 impl BoltFile {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = std::fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read Boltfile at {:?}", path.as_ref()))?;
+        Self::load_with(path, &template::TemplateOptions::default())
+    }
+
+    /// Load a Boltfile, rendering it first when it is a `.toml.tmpl` template
+    pub fn load_with<P: AsRef<Path>>(path: P, options: &template::TemplateOptions) -> Result<Self> {
+        let content = if template::is_template(path.as_ref()) {
+            template::render(path.as_ref(), options)?
+        } else {
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read Boltfile at {:?}", path.as_ref()))?
+        };
+        let base_dir = path.as_ref().parent().unwrap_or(Path::new("."));
+        Self::parse(&content, base_dir)
+    }
 
-        let config: BoltFile =
-            toml::from_str(&content).with_context(|| "Failed to parse Boltfile")?;
+    /// Parse and validate Boltfile content; `include`s are relative to `base_dir`
+    pub fn parse(content: &str, base_dir: &Path) -> Result<Self> {
+        let doc: toml::Table =
+            toml::from_str(content).with_context(|| "Failed to parse Boltfile")?;
+        let config: BoltFile = if extends::uses_inheritance(&doc) {
+            extends::flatten(doc, base_dir)?
+                .try_into()
+                .with_context(|| "Failed to parse Boltfile after resolving `extends`")?
+        } else {
+            toml::from_str(content).with_context(|| "Failed to parse Boltfile")?
+        };
 
         // Validate the configuration
         config.validate()?;
@@ -420,6 +1051,11 @@ impl BoltFile {
             return Err(anyhow!("At least one service must be defined"));
         }
 
+        if let Some(ref window) = self.maintenance {
+            crate::surge::maintenance::Window::parse(window)
+                .context("Invalid maintenance window")?;
+        }
+
         debug!("✅ Basic validation passed");
         Ok(())
     }
@@ -438,8 +1074,127 @@ impl BoltFile {
                 return Err(anyhow!("Service name cannot contain spaces: '{}'", name));
             }
 
+            let wasm = service.runtime == Some(crate::runtime::wasm::ServiceRuntime::Wasm);
+            if wasm {
+                crate::runtime::wasm::WasmSpec::from_service(name, service, Path::new("."))
+                    .with_context(|| format!("Invalid WASM settings for service '{}'", name))?;
+            } else if service.wasm.is_some() {
+                return Err(anyhow!(
+                    "Service '{}' has a [wasm] table but not runtime = \"wasm\"",
+                    name
+                ));
+            }
+
+            if let Some(ref egress) = service.egress {
+                if wasm || service.capsule.is_some() {
+                    return Err(anyhow!(
+                        "Service '{}' has egress settings, which only apply to containers",
+                        name
+                    ));
+                }
+                crate::networking::egress::EgressPolicy::from_config(egress)
+                    .with_context(|| format!("Invalid egress settings for service '{}'", name))?;
+            }
+
+            if let Some(ref idle) = service.idle {
+                if wasm {
+                    return Err(anyhow!(
+                        "Service '{}' has idle settings, which don't apply to WASM workloads",
+                        name
+                    ));
+                }
+                crate::runtime::idle::IdlePolicy::from_config(idle, &[])
+                    .with_context(|| format!("Invalid idle settings for service '{}'", name))?;
+            }
+
+            if let Some(proton) = service
+                .gaming
+                .as_ref()
+                .and_then(|g| g.wine.as_ref())
+                .and_then(|w| w.proton.as_deref())
+            {
+                crate::gaming::proton::Spec::parse(proton).with_context(|| {
+                    format!("Invalid gaming.wine.proton for service '{}'", name)
+                })?;
+            }
+
+            if let Some(ref annotations) = service.annotations {
+                crate::runtime::annotations::validate(annotations)
+                    .with_context(|| format!("Invalid annotations for service '{}'", name))?;
+            }
+
+            if let Some(ref slo) = service.slo {
+                if wasm || service.capsule.is_some() {
+                    return Err(anyhow!(
+                        "Service '{}' has slo settings, which only apply to containers",
+                        name
+                    ));
+                }
+                if service.idle.is_some() {
+                    return Err(anyhow!(
+                        "Service '{}' has both slo and idle settings; SLO probes would keep it from going idle",
+                        name
+                    ));
+                }
+                crate::monitoring::slo::SloSpec::from_config(slo, &[])
+                    .with_context(|| format!("Invalid slo settings for service '{}'", name))?;
+            }
+
+            if let Some(ref hooks) = service.log_hooks {
+                if wasm {
+                    return Err(anyhow!(
+                        "Service '{}' has log_hooks, which don't apply to WASM workloads",
+                        name
+                    ));
+                }
+                let driver = crate::runtime::log_driver::LogDriver::of(service.logging.as_ref());
+                if matches!(
+                    driver,
+                    crate::runtime::log_driver::LogDriver::None
+                        | crate::runtime::log_driver::LogDriver::Syslog
+                ) {
+                    return Err(anyhow!(
+                        "Service '{}' has log_hooks, but its log driver doesn't keep output for Bolt to read",
+                        name
+                    ));
+                }
+                crate::runtime::log_hooks::LogHooks::from_config(hooks)
+                    .with_context(|| format!("Invalid log_hooks for service '{}'", name))?;
+            }
+
+            if let Some(ref desktop) = service.desktop_integration {
+                if wasm {
+                    return Err(anyhow!(
+                        "Service '{}' has desktop_integration settings, which don't apply to WASM workloads",
+                        name
+                    ));
+                }
+                crate::runtime::desktop::DesktopPolicy::from_config(desktop, Path::new("."))
+                    .with_context(|| {
+                        format!("Invalid desktop_integration settings for service '{}'", name)
+                    })?;
+            }
+
+            if service.desktop_integration.is_some()
+                && service.gaming.as_ref().is_some_and(|g| g.display.is_some())
+            {
+                return Err(anyhow!(
+                    "Service '{}' has both desktop_integration and gaming.display; use one",
+                    name
+                ));
+            }
+
+            if let Some(ref develop) = service.develop {
+                crate::surge::dev::validate(service, develop)
+                    .with_context(|| format!("Invalid develop settings for service '{}'", name))?;
+            }
+
             // Service must have at least one of: image, build, or capsule
-            if service.image.is_none() && service.build.is_none() && service.capsule.is_none() {
+            if !wasm
+                && service.image.is_none()
+                && service.build.is_none()
+                && service.capsule.is_none()
+            {
                 return Err(anyhow!(
                     "Service '{}' must specify either 'image', 'build', or 'capsule'",
                     name
@@ -482,6 +1237,50 @@ impl BoltFile {
             if let Some(ref volumes) = service.volumes {
                 self.validate_service_volumes(name, volumes)?;
             }
+
+            // Validate per-network settings
+            if let Some(ref networks) = service.networks {
+                self.validate_service_networks(name, networks)?;
+            }
+
+            // Validate healthcheck and restart policy
+            if let Some(ref healthcheck) = service.healthcheck {
+                crate::runtime::healthcheck::HealthcheckSpec::from_config(healthcheck)
+                    .with_context(|| format!("Invalid healthcheck for service '{}'", name))?;
+            }
+            if let Some(ref restart) = service.restart {
+                crate::runtime::healthcheck::restart_policy(restart)
+                    .with_context(|| format!("Invalid restart policy for service '{}'", name))?;
+            }
+
+            // Validate readiness probe
+            if let Some(ref readiness) = service.readiness {
+                crate::runtime::readiness::ReadinessProbe::from_config(readiness)
+                    .with_context(|| format!("Invalid readiness probe for service '{}'", name))?;
+            }
+
+            if let Some(ref thermal) = service.thermal {
+                crate::gaming::thermal::ThermalPolicy::from_config(thermal)
+                    .with_context(|| format!("Invalid thermal policy for service '{}'", name))?;
+            }
+
+            if let Some(ref drain) = service.drain {
+                crate::runtime::drain::DrainPolicy::from_config(drain)
+                    .with_context(|| format!("Invalid drain settings for service '{}'", name))?;
+            }
+
+            crate::runtime::identity::IdentitySpec::from_service(service)
+                .validate()
+                .with_context(|| format!("Invalid identity settings for service '{}'", name))?;
+
+            if let Some(policy) = service.pull_policy {
+                let image = service.image.as_deref().ok_or_else(|| {
+                    anyhow!("Service '{}' sets pull_policy but has no image", name)
+                })?;
+                policy
+                    .validate(image)
+                    .with_context(|| format!("Invalid pull_policy for service '{}'", name))?;
+            }
         }
 
         debug!("✅ Services validation passed");
@@ -497,13 +1296,26 @@ impl BoltFile {
                 self.check_circular_dependencies(name, deps, &mut HashSet::new())?;
 
                 // Validate that all dependencies exist
-                for dep in deps {
-                    if !self.services.contains_key(dep) {
+                for dep in deps.names() {
+                    let Some(dep_service) = self.services.get(dep) else {
                         return Err(anyhow!(
                             "Service '{}' depends on non-existent service '{}'",
                             name,
                             dep
                         ));
+                    };
+                    if deps.condition(dep) == DependencyCondition::ServiceHealthy
+                        && !dep_service.healthcheck.as_ref().is_some_and(|h| {
+                            crate::runtime::healthcheck::HealthcheckSpec::from_config(h)
+                                .is_ok_and(|spec| spec.enabled())
+                        })
+                    {
+                        return Err(anyhow!(
+                            "Service '{}' waits for '{}' to be healthy, but '{}' has no healthcheck",
+                            name,
+                            dep,
+                            dep
+                        ));
                     }
                 }
             }
@@ -516,7 +1328,7 @@ impl BoltFile {
     fn check_circular_dependencies(
         &self,
         service: &str,
-        deps: &[String],
+        deps: &DependsOn,
         visited: &mut HashSet<String>,
     ) -> Result<()> {
         if visited.contains(service) {
@@ -528,7 +1340,7 @@ impl BoltFile {
 
         visited.insert(service.to_string());
 
-        for dep in deps {
+        for dep in deps.names() {
             if let Some(dep_service) = self.services.get(dep) {
                 if let Some(ref dep_deps) = dep_service.depends_on {
                     self.check_circular_dependencies(dep, dep_deps, visited)?;
@@ -724,6 +1536,21 @@ impl BoltFile {
                     );
                 }
             }
+
+            if gpu.intel.is_some() && (gpu.nvidia.is_some() || gpu.amd.is_some()) {
+                return Err(anyhow!(
+                    "Service '{}': gpu.intel cannot be combined with gpu.nvidia or gpu.amd",
+                    service_name
+                ));
+            }
+
+            crate::gaming::topology::requested_count(gpu.count, gpu.topology)
+                .map_err(|e| anyhow!("Service '{}': {}", service_name, e))?;
+
+            if let Some(fraction) = gpu.fraction {
+                crate::gaming::fraction::validate(gpu, fraction)
+                    .map_err(|e| anyhow!("Service '{}': {}", service_name, e))?;
+            }
         }
 
         if let Some(ref audio) = gaming.audio {
@@ -739,6 +1566,37 @@ impl BoltFile {
             }
         }
 
+        if let Some(ref gamescope) = gaming.display.as_ref().and_then(|d| d.gamescope.as_ref()) {
+            if let Some(ref filter) = gamescope.filter
+                && !["linear", "nearest", "fsr", "nis", "pixel"].contains(&filter.as_str())
+            {
+                return Err(anyhow!(
+                    "Service '{}': unknown gamescope filter '{}'",
+                    service_name,
+                    filter
+                ));
+            }
+            if let Some(ref scaler) = gamescope.scaler
+                && !["auto", "integer", "fit", "fill", "stretch"].contains(&scaler.as_str())
+            {
+                return Err(anyhow!(
+                    "Service '{}': unknown gamescope scaler '{}'",
+                    service_name,
+                    scaler
+                ));
+            }
+            if gamescope.width.is_none()
+                && let Some(ref resolution) = gaming.resolution
+                && crate::gaming::display::parse_resolution(resolution).is_none()
+            {
+                return Err(anyhow!(
+                    "Service '{}': invalid resolution '{}': expected WIDTHxHEIGHT",
+                    service_name,
+                    resolution
+                ));
+            }
+        }
+
         if let Some(ref perf) = gaming.performance {
             if let Some(nice) = perf.nice_level {
                 if nice < -20 || nice > 19 {
@@ -793,6 +1651,120 @@ impl BoltFile {
         Ok(())
     }
 
+    fn validate_service_networks(
+        &self,
+        service_name: &str,
+        networks: &ServiceNetworks,
+    ) -> Result<()> {
+        for (network_name, attachment) in networks.attachments() {
+            for alias in attachment.aliases.iter().flatten() {
+                let valid = !alias.is_empty()
+                    && alias.len() <= 63
+                    && !alias.starts_with('-')
+                    && alias
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+                if !valid {
+                    return Err(anyhow!(
+                        "Service '{}': alias '{}' on network '{}' is not a valid DNS name",
+                        service_name,
+                        alias,
+                        network_name
+                    ));
+                }
+            }
+
+            for (address, want_v4) in [
+                (&attachment.ipv4_address, true),
+                (&attachment.ipv6_address, false),
+            ] {
+                let Some(address) = address else {
+                    continue;
+                };
+                let ip: std::net::IpAddr = address.parse().map_err(|_| {
+                    anyhow!(
+                        "Service '{}': '{}' on network '{}' is not an IP address",
+                        service_name,
+                        address,
+                        network_name
+                    )
+                })?;
+                if ip.is_ipv4() != want_v4 {
+                    return Err(anyhow!(
+                        "Service '{}': '{}' is not an {} address",
+                        service_name,
+                        address,
+                        if want_v4 { "IPv4" } else { "IPv6" }
+                    ));
+                }
+
+                // Static addresses need a declared subnet to check against
+                let subnets: Vec<&str> = self
+                    .networks
+                    .as_ref()
+                    .and_then(|n| n.get(network_name))
+                    .and_then(|n| n.ipam.as_ref())
+                    .and_then(|ipam| ipam.config.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|pool| pool.subnet.as_deref())
+                    .collect();
+                if subnets.is_empty() {
+                    return Err(anyhow!(
+                        "Service '{}': static address {} needs network '{}' to be declared \
+                         with an ipam subnet",
+                        service_name,
+                        address,
+                        network_name
+                    ));
+                }
+                let mut inside = false;
+                for subnet in &subnets {
+                    inside |= crate::network::attach::subnet_contains(subnet, ip)?;
+                }
+                if !inside {
+                    return Err(anyhow!(
+                        "Service '{}': {} is outside the subnet of network '{}' ({})",
+                        service_name,
+                        address,
+                        network_name,
+                        subnets.join(", ")
+                    ));
+                }
+
+                // Two services can't share an address on one network
+                for (other_name, other) in &self.services {
+                    if other_name.as_str() == service_name {
+                        continue;
+                    }
+                    let taken = other
+                        .networks
+                        .as_ref()
+                        .map(|n| n.attachments())
+                        .into_iter()
+                        .flatten()
+                        .any(|(other_network, other_attachment)| {
+                            other_network == network_name
+                                && [other_attachment.ipv4_address, other_attachment.ipv6_address]
+                                    .iter()
+                                    .flatten()
+                                    .any(|other_address| other_address.parse().ok() == Some(ip))
+                        });
+                    if taken {
+                        return Err(anyhow!(
+                            "Services '{}' and '{}' both use {} on network '{}'",
+                            service_name,
+                            other_name,
+                            address,
+                            network_name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn validate_networks(&self, networks: &HashMap<String, Network>) -> Result<()> {
         debug!("Validating network definitions");
 
@@ -828,6 +1800,20 @@ impl BoltFile {
                     _ => warn!("Volume '{}': unknown driver '{}'", name, driver),
                 }
             }
+            for path in volume.hot_paths.iter().flatten() {
+                let path = Path::new(path);
+                if path.is_absolute()
+                    || path
+                        .components()
+                        .any(|c| c == std::path::Component::ParentDir)
+                {
+                    return Err(anyhow!(
+                        "Volume '{}': hot path '{}' must be relative to the volume",
+                        name,
+                        path.display()
+                    ));
+                }
+            }
         }
 
         debug!("✅ Volumes validation passed");
@@ -916,10 +1902,13 @@ image = "string"                 # Docker/OCI image (mutually exclusive with bui
 build = "string"                 # Build context path (mutually exclusive with image/capsule)
 capsule = "string"               # Bolt capsule name (mutually exclusive with image/build)
 ports = ["host:container"]       # Port mappings (optional)
+annotations = {"key" = "value"}  # OCI annotations of the container's runtime spec (optional)
 volumes = ["host:container:opts"] # Volume mounts (optional)
 env = {KEY = "value"}           # Environment variables (optional)
-depends_on = ["service1"]        # Service dependencies (optional)
-restart = "always"               # Restart policy: no, always, on-failure, unless-stopped (optional)
+depends_on = ["service1"]        # Service dependencies, or {service1 = {condition = "service_healthy"}} (optional)
+restart = "always"               # Restart policy: no, always, on-failure[:N], unless-stopped (optional)
+stop_grace_period = "30s"        # Time to exit after SIGTERM before being killed (optional)
+slo = { availability = "99.9%", p99_ms = 50 }  # Probe published ports and track error budgets (optional)
 networks = ["network1"]          # Custom networks (optional)
 
 [services.<name>.storage]        # Optional storage configuration
@@ -932,6 +1921,7 @@ password = "password"            # Password (required if auth block present)
 
 [services.<name>.gaming]         # Optional gaming optimizations
 [services.<name>.gaming.gpu]     # GPU configuration
+fraction = 0.25                  # Share of one GPU: MIG, CUDA MPS or time-slicing (optional)
 [services.<name>.gaming.gpu.nvidia]
 device = 0                       # GPU device ID (optional)
 dlss = true                      # Enable DLSS (optional)
@@ -942,27 +1932,40 @@ cuda = false                     # Enable CUDA (optional)
 device = 0                       # GPU device ID (optional)
 rocm = true                      # Enable ROCm (optional)
 
+[services.<name>.gaming.gpu.intel]
+device = 0                       # Intel GPU index (optional)
+quicksync = true                 # VA-API and oneVPL for QuickSync (default: true)
+level_zero = false               # Level Zero compute runtime (optional)
+
 [services.<name>.gaming.audio]
 system = "pipewire"              # Audio system: pipewire, pulseaudio
 latency = "low"                  # Audio latency setting (optional)
 
 [services.<name>.gaming.wine]
-proton = "8.0"                   # Proton version (optional)
+proton = "GE-Proton9-20"         # Proton-GE (downloaded), wine-ge:<tag>, latest, or Steam's e.g. 8.0 (optional)
 winver = "win10"                 # Windows version (optional)
 prefix = "/path/to/prefix"       # Wine prefix path (optional)
+prefix_cache = true              # Run on a local copy of a prefix on network storage (optional)
 
 [services.<name>.gaming.performance]
 cpu_governor = "performance"     # CPU governor (optional)
 nice_level = -10                 # Process nice level -20 to 19 (optional)
 rt_priority = 50                 # Real-time priority 0 to 99 (optional)
 
+[services.<name>.gaming.display]
+server = "auto"                  # Display passed through: auto, wayland, x11 (optional)
+gamescope = { width = 1280, height = 720, output_width = 2560, output_height = 1440, filter = "fsr", hdr = true, vrr = true }  # Nested gamescope (optional)
+
 [networks.<name>]                # Optional custom networks
 driver = "bolt"                  # Network driver: bolt, bridge, host (optional)
 subnet = "10.0.0.0/16"          # Network subnet in CIDR notation (optional)
+dns = { provider = "pdns", zone = "lab.example" }  # Register containers with a [dns.providers] entry (optional)
 
 [volumes.<name>]                 # Optional named volumes
 driver = "local"                 # Volume driver (optional)
 external = false                 # Use external volume (optional)
+cache_profile = "streaming"      # Page cache profile: streaming, database, default (optional)
+hot_paths = ["maps"]             # Pre-faulted into the page cache on start (optional)
 "#
         .to_string()
     }
@@ -975,6 +1978,657 @@ pub struct BoltConfig {
     pub data_dir: PathBuf,
     pub boltfile_path: PathBuf,
     pub verbose: bool,
+    pub logging: LogSettings,
+    pub build_cache: BuildCacheSettings,
+    pub capacity: CapacitySettings,
+    pub alerts: AlertSettings,
+    pub backup: BackupSettings,
+    pub encryption: EncryptionSettings,
+    pub trust: TrustSettings,
+    pub ports: PortSettings,
+    pub platform: PlatformSettings,
+    pub p2p: P2pSettings,
+    pub maintenance: MaintenanceSettings,
+    pub monitoring: MonitoringSettings,
+    pub nat: NatSettings,
+    pub acme: AcmeSettings,
+    pub dns: DnsSettings,
+    pub optimize: OptimizeSettings,
+    pub crash_dumps: crate::runtime::crash::CrashDumpConfig,
+    /// Variables for `.toml.tmpl` Boltfiles
+    pub template: template::TemplateOptions,
+    /// `--set` overrides applied to the Boltfile after it is loaded
+    pub overrides: Vec<overrides::Override>,
+    /// Workspace the invoking user works in; names and quotas are scoped to it
+    pub workspace: Option<String>,
+}
+
+/// Output format for Bolt's own logs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(anyhow!(
+                "Unknown log format '{}' (expected text or json)",
+                other
+            )),
+        }
+    }
+}
+
+/// `[logging]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogSettings {
+    pub format: LogFormat,
+    /// Default level for everything not covered by `subsystems`
+    pub level: String,
+    /// Per-subsystem overrides, e.g. `networking = "debug"`. Keys are either
+    /// subsystem names or full tracing targets like `bolt::network::quic`.
+    pub subsystems: HashMap<String, String>,
+    /// Where container stdout/stderr is written; defaults to `<data_dir>/logs/containers`
+    pub container_log_dir: Option<PathBuf>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: "info".to_string(),
+            subsystems: HashMap::new(),
+            container_log_dir: None,
+        }
+    }
+}
+
+impl LogSettings {
+    /// Module targets that make up each named subsystem
+    pub fn subsystem_targets(subsystem: &str) -> Vec<&'static str> {
+        match subsystem {
+            "runtime" => vec!["bolt::runtime"],
+            "networking" | "network" => vec!["bolt::network", "bolt::networking"],
+            "storage" => vec!["bolt::runtime::storage", "bolt::volume", "bolt::snapshots"],
+            "gaming" => vec!["bolt::gaming", "bolt::runtime::gpu"],
+            "gpu" => vec!["bolt::runtime::gpu", "bolt::runtime::nvbind"],
+            "capsules" => vec!["bolt::capsules"],
+            "builds" => vec!["bolt::builds"],
+            "registry" => vec!["bolt::registry"],
+            "surge" | "orchestration" => vec!["bolt::surge", "bolt::orchestration"],
+            "api" => vec!["bolt::docker_compat", "bolt::nova_api", "docker_api"],
+            "plugins" => vec!["bolt::plugins"],
+            _ => Vec::new(),
+        }
+    }
+
+    /// `EnvFilter` directives for the configured levels
+    pub fn filter_directives(&self, verbose: bool) -> Vec<String> {
+        let level = if verbose {
+            "debug"
+        } else {
+            self.level.as_str()
+        };
+        let mut directives = vec![level.to_string()];
+
+        let mut subsystems: Vec<_> = self.subsystems.iter().collect();
+        subsystems.sort();
+        for (subsystem, level) in subsystems {
+            let targets = Self::subsystem_targets(subsystem);
+            if targets.is_empty() {
+                directives.push(format!("{}={}", subsystem, level));
+            } else {
+                directives.extend(targets.iter().map(|t| format!("{}={}", t, level)));
+            }
+        }
+
+        directives
+    }
+}
+
+/// `[build_cache]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BuildCacheSettings {
+    /// Builders' layer caches are pruned back to this after a build, e.g. "20GB"
+    pub max_size: String,
+    /// Size limit of all `cache_volumes` together, e.g. "20GB"
+    pub artifacts_max_size: String,
+}
+
+impl Default for BuildCacheSettings {
+    fn default() -> Self {
+        Self {
+            max_size: "20GB".to_string(),
+            artifacts_max_size: "20GB".to_string(),
+        }
+    }
+}
+
+/// `[capacity]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CapacitySettings {
+    /// Size limits per series, e.g. "200GB". Unset series are only checked
+    /// against the disk they live on.
+    pub data_dir_max: Option<String>,
+    pub volumes_max: Option<String>,
+    pub images_max: Option<String>,
+    /// Disk usage, in percent, treated as full
+    pub disk_threshold_percent: u8,
+    /// How much history to fit the growth rate to, e.g. "14d"
+    pub window: String,
+    /// Alert when a threshold is forecast to be hit within this many days
+    pub alert_days: u32,
+    /// Samples older than this are dropped from the history
+    pub retention: String,
+}
+
+impl Default for CapacitySettings {
+    fn default() -> Self {
+        Self {
+            data_dir_max: None,
+            volumes_max: None,
+            images_max: None,
+            disk_threshold_percent: 90,
+            window: "14d".to_string(),
+            alert_days: 7,
+            retention: "90d".to_string(),
+        }
+    }
+}
+
+/// `[alerts]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AlertSettings {
+    /// URL that receives each alert as a JSON POST
+    pub webhook: Option<String>,
+    /// Command run for each alert, with BOLT_ALERT_* variables set
+    pub command: Option<String>,
+    /// Don't repeat the same alert more often than this, e.g. "24h"
+    pub repeat_interval: String,
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            command: None,
+            repeat_interval: "24h".to_string(),
+        }
+    }
+}
+
+/// `[backup]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BackupSettings {
+    pub restic: Option<ResticSettings>,
+}
+
+/// `[backup.restic]`: a Restic-compatible repository volumes and snapshots
+/// are backed up to
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResticSettings {
+    /// Local path, `sftp:user@host:/path` or `s3:host/bucket/path`
+    pub repository: String,
+    /// Secret reference for the repository password, e.g. "secret:restic"
+    #[serde(default = "default_restic_password")]
+    pub password: String,
+    /// `restic` or `rustic`, or a path to either
+    #[serde(default = "default_restic_binary")]
+    pub binary: String,
+    /// Extra environment for the backend, e.g. AWS_ACCESS_KEY_ID. Values may
+    /// be secret references.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Retention applied after each backup; nothing is pruned when all are unset
+    #[serde(default)]
+    pub keep_last: Option<u32>,
+    #[serde(default)]
+    pub keep_daily: Option<u32>,
+    #[serde(default)]
+    pub keep_weekly: Option<u32>,
+    #[serde(default)]
+    pub keep_monthly: Option<u32>,
+}
+
+/// `[trust]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TrustSettings {
+    /// Image trust policy file; `<config_dir>/trust.toml` when it exists
+    pub policy: Option<PathBuf>,
+    /// Let images violating the policy through (`--override-trust`, admins only)
+    #[serde(skip)]
+    pub override_violations: bool,
+}
+
+/// `[ports]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PortSettings {
+    /// Move host ports that are taken to a free one instead of refusing to
+    /// start (`surge up --remap-ports`)
+    pub remap: bool,
+    /// A taken port is moved by multiples of this: 8080 tries 9080, 10080, ...
+    pub offset: u16,
+}
+
+impl Default for PortSettings {
+    fn default() -> Self {
+        Self {
+            remap: false,
+            offset: 1000,
+        }
+    }
+}
+
+/// `[platform]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlatformSettings {
+    /// Pull linux/amd64 under emulation when an image has no variant for
+    /// this host's architecture
+    pub auto_select: bool,
+    /// Register a missing qemu-user-static binfmt handler when a container
+    /// for another architecture starts
+    pub setup_emulation: bool,
+}
+
+/// `[p2p]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct P2pSettings {
+    /// Ask Bolt nodes on the LAN for image layers before the registry
+    pub enabled: bool,
+    /// UDP port `bolt image serve` serves blobs on over QUIC
+    pub port: u16,
+    /// Most a single peer may download from this node per second, e.g. "50MB"
+    pub upload_limit: Option<String>,
+    /// Most this node downloads from a single peer per second
+    pub download_limit: Option<String>,
+    /// Networks `bolt image serve` answers (CIDR); the private ranges when empty
+    pub allow: Vec<String>,
+}
+
+impl Default for P2pSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7947,
+            upload_limit: None,
+            download_limit: None,
+            allow: Vec::new(),
+        }
+    }
+}
+
+/// `[maintenance]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MaintenanceSettings {
+    /// When maintenance runs, e.g. "Sun 03:00-05:00" or "daily 02:00-03:00"
+    pub window: Option<String>,
+    /// Operations queued for every window
+    pub tasks: Vec<crate::surge::maintenance::Operation>,
+    /// How long before a window opens the alert channels hear of it
+    pub notify_before: String,
+    /// Run for a reboot the host asks for
+    pub reboot_command: String,
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        use crate::surge::maintenance::Operation;
+        Self {
+            window: None,
+            tasks: vec![
+                Operation::ImageUpdates,
+                Operation::SnapshotPrune,
+                Operation::StorageFsck,
+            ],
+            notify_before: "30m".to_string(),
+            reboot_command: "systemctl reboot".to_string(),
+        }
+    }
+}
+
+/// `[monitoring]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MonitoringSettings {
+    /// Serve the Prometheus endpoint from `bolt metrics serve`
+    pub enabled: bool,
+    /// Address the endpoint listens on, e.g. "127.0.0.1" or "::"
+    pub address: String,
+    pub port: u16,
+    /// PEM certificate and key; the endpoint is served over HTTPS when both are set
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Metric families that are collected and exported
+    pub collectors: Vec<crate::monitoring::prometheus::MetricFamily>,
+    /// Container labels exported as Prometheus labels, e.g. "com.example.team"
+    /// becomes `label_com_example_team`
+    pub container_labels: Vec<String>,
+}
+
+impl Default for MonitoringSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: "0.0.0.0".to_string(),
+            port: 9090,
+            tls_cert: None,
+            tls_key: None,
+            collectors: crate::monitoring::prometheus::MetricFamily::ALL.to_vec(),
+            container_labels: Vec::new(),
+        }
+    }
+}
+
+impl MonitoringSettings {
+    /// Socket address the endpoint binds
+    pub fn bind_addr(&self) -> Result<std::net::SocketAddr> {
+        let ip: std::net::IpAddr = self
+            .address
+            .parse()
+            .map_err(|_| anyhow!("'{}' is not an IP address", self.address))?;
+        Ok(std::net::SocketAddr::new(ip, self.port))
+    }
+
+    pub fn collects(&self, family: crate::monitoring::prometheus::MetricFamily) -> bool {
+        self.collectors.contains(&family)
+    }
+}
+
+/// `[nat]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NatSettings {
+    /// How `port_forward` services ask the router: "auto" tries NAT-PMP,
+    /// then UPnP; "natpmp", "upnp" or "off"
+    pub protocol: crate::networking::nat::MappingProtocol,
+    /// Lease requested for each mapping; renewed halfway through
+    pub lease: String,
+    /// Router to ask; the default route's gateway when unset
+    pub gateway: Option<std::net::Ipv4Addr>,
+    /// STUN servers (`host:port`) that tell the public address apart from
+    /// the router's, to detect carrier-grade NAT
+    pub stun: Vec<String>,
+    /// `host:port` of a node running `bolt network relay` that publishes
+    /// the ports when the router can't
+    pub relay: Option<String>,
+    /// Shared secret the relay expects from the hosts it publishes for
+    pub relay_token: Option<String>,
+    /// sha256 fingerprint of the relay's certificate; trusted on first use
+    /// when unset
+    pub relay_fingerprint: Option<String>,
+    /// UDP port `bolt network relay` accepts hosts on over QUIC
+    pub relay_port: u16,
+    /// UDP port `bolt network relay` answers STUN binding requests on
+    pub stun_port: u16,
+    /// Public ports the relay publishes for hosts, e.g. "20000-29999"
+    pub relay_ports: String,
+}
+
+impl Default for NatSettings {
+    fn default() -> Self {
+        Self {
+            protocol: Default::default(),
+            lease: "1h".to_string(),
+            gateway: None,
+            stun: vec!["stun.l.google.com:19302".to_string()],
+            relay: None,
+            relay_token: None,
+            relay_fingerprint: None,
+            relay_port: 7950,
+            stun_port: 3478,
+            relay_ports: "1024-65535".to_string(),
+        }
+    }
+}
+
+/// `[acme]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AcmeSettings {
+    /// ACME directory URL; Let's Encrypt's staging directory is
+    /// "https://acme-staging-v02.api.letsencrypt.org/directory"
+    pub directory: String,
+    /// Contact address registered with the account
+    pub email: Option<String>,
+    /// Renew certificates expiring within this long
+    pub renew_before: String,
+    /// How often `bolt cert watch` checks the certificates
+    pub check_interval: String,
+    /// Address and port the HTTP-01 responder listens on
+    pub http_address: String,
+    pub http_port: u16,
+    /// Provider that publishes DNS-01 TXT records
+    pub dns: Option<DnsProviderSettings>,
+    /// How long to wait after publishing a TXT record before validating
+    pub dns_propagation: String,
+    pub certificates: Vec<CertificateSettings>,
+}
+
+impl Default for AcmeSettings {
+    fn default() -> Self {
+        Self {
+            directory: "https://acme-v02.api.letsencrypt.org/directory".to_string(),
+            email: None,
+            renew_before: "30d".to_string(),
+            check_interval: "12h".to_string(),
+            http_address: "0.0.0.0".to_string(),
+            http_port: 80,
+            dns: None,
+            dns_propagation: "30s".to_string(),
+            certificates: Vec::new(),
+        }
+    }
+}
+
+/// A certificate in `[[acme.certificates]]`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CertificateSettings {
+    /// Name the certificate is stored and served under
+    pub name: String,
+    /// Names on the certificate; wildcards need the dns-01 challenge
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub challenge: ChallengeKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ChallengeKind {
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    #[serde(rename = "dns-01")]
+    Dns01,
+}
+
+/// `[acme.dns]`: how DNS-01 TXT records are published
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum DnsProviderSettings {
+    /// Runs a command with `BOLT_ACME_ACTION` (present or cleanup),
+    /// `BOLT_ACME_DOMAIN`, `BOLT_ACME_RECORD` and `BOLT_ACME_VALUE` set
+    Command { command: String },
+    /// Cloudflare's API; `api_token` is usually a secret reference
+    Cloudflare { api_token: String },
+}
+
+/// `[dns]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DnsSettings {
+    /// Kept in each record's ownership marker; records of other owners are
+    /// never changed. The hostname when unset.
+    pub owner: Option<String>,
+    /// TTL of the records, in seconds
+    pub ttl: u32,
+    /// Providers networks and `published` pick by name
+    pub providers: BTreeMap<String, ExternalDnsProvider>,
+    /// Register services that publish ports, pointing at this host
+    pub published: Option<PublishedDns>,
+}
+
+impl Default for DnsSettings {
+    fn default() -> Self {
+        Self {
+            owner: None,
+            ttl: 300,
+            providers: BTreeMap::new(),
+            published: None,
+        }
+    }
+}
+
+/// `[optimize]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OptimizeSettings {
+    /// Preset `bolt optimize apply` uses without `--preset`
+    pub preset: Option<crate::optimizations::tuning::Preset>,
+    /// CPUs the latency preset steers device IRQs to; CPU 0 when unset
+    pub irq_cpus: Option<String>,
+    /// Sysctls set with every preset, overriding the preset's own
+    pub sysctls: BTreeMap<String, String>,
+    /// Knobs never changed: sysctl names, or governor, irq, thp, cpu_weight
+    pub exclude: Vec<String>,
+}
+
+/// `[dns.published]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PublishedDns {
+    /// Name of the provider in `[dns.providers]`
+    pub provider: String,
+    pub zone: String,
+    /// Address the records point at; this host's address towards the
+    /// default gateway when unset
+    pub address: Option<std::net::IpAddr>,
+}
+
+fn default_powerdns_server() -> String {
+    "localhost".to_string()
+}
+
+fn default_dns_port() -> u16 {
+    53
+}
+
+/// A provider in `[dns.providers.<name>]`. Passwords and keys may be secret
+/// references.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ExternalDnsProvider {
+    /// The PowerDNS Authoritative HTTP API, e.g. "http://ns1:8081"
+    Powerdns {
+        url: String,
+        api_key: String,
+        #[serde(default = "default_powerdns_server")]
+        server: String,
+    },
+    /// Local DNS records of Pi-hole 6, e.g. "http://pi.hole"
+    Pihole { url: String, password: String },
+    /// Dynamic updates (RFC 2136) sent with `nsupdate`, signed with the
+    /// TSIG key in `key_file` when set
+    Rfc2136 {
+        server: String,
+        #[serde(default = "default_dns_port")]
+        port: u16,
+        key_file: Option<PathBuf>,
+    },
+    /// Unbound host overrides of OPNsense, e.g. "https://fw.lan"
+    Opnsense {
+        url: String,
+        api_key: String,
+        api_secret: String,
+        /// PEM certificate to trust, for the firewall's self-signed one
+        ca_cert: Option<PathBuf>,
+    },
+}
+
+/// `[encryption]` section of `<config_dir>/config.toml`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EncryptionSettings {
+    /// Recipients `bolt push` encrypts layers for when `--encrypt` isn't
+    /// given, e.g. "jwe:/etc/bolt/keys/partner.pub.pem"
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// Secret references for the private keys pulled images are decrypted
+    /// with, e.g. "secret:image-key"
+    #[serde(default)]
+    pub decryption_keys: Vec<String>,
+}
+
+fn default_restic_password() -> String {
+    "secret:restic-password".to_string()
+}
+
+fn default_restic_binary() -> String {
+    "restic".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BoltSettingsFile {
+    #[serde(default)]
+    logging: LogSettings,
+    #[serde(default)]
+    build_cache: BuildCacheSettings,
+    #[serde(default)]
+    capacity: CapacitySettings,
+    #[serde(default)]
+    alerts: AlertSettings,
+    #[serde(default)]
+    backup: BackupSettings,
+    #[serde(default)]
+    encryption: EncryptionSettings,
+    #[serde(default)]
+    trust: TrustSettings,
+    #[serde(default)]
+    ports: PortSettings,
+    #[serde(default)]
+    platform: PlatformSettings,
+    #[serde(default)]
+    p2p: P2pSettings,
+    #[serde(default)]
+    maintenance: MaintenanceSettings,
+    #[serde(default)]
+    monitoring: MonitoringSettings,
+    #[serde(default)]
+    nat: NatSettings,
+    #[serde(default)]
+    acme: AcmeSettings,
+    #[serde(default)]
+    dns: DnsSettings,
+    #[serde(default)]
+    optimize: OptimizeSettings,
+    #[serde(default)]
+    crash_dumps: crate::runtime::crash::CrashDumpConfig,
+}
+
+impl BoltSettingsFile {
+    /// `<config_dir>/config.toml`, or the defaults when there is none
+    fn read(config_dir: &Path) -> Result<Self> {
+        let settings_path = config_dir.join("config.toml");
+        if !settings_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&settings_path)
+            .with_context(|| format!("Failed to read {}", settings_path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", settings_path.display()))
+    }
 }
 
 impl BoltConfig {
@@ -992,21 +2646,109 @@ impl BoltConfig {
             .unwrap_or_default()
             .join("Boltfile.toml");
 
+        let settings = BoltSettingsFile::read(&config_dir)?;
+
+        let workspace = crate::runtime::workspace::resolve(&data_dir)?;
+
         Ok(Self {
             config_dir,
             data_dir,
             boltfile_path,
             verbose: false,
+            logging: settings.logging,
+            build_cache: settings.build_cache,
+            capacity: settings.capacity,
+            alerts: settings.alerts,
+            backup: settings.backup,
+            encryption: settings.encryption,
+            trust: settings.trust,
+            ports: settings.ports,
+            platform: settings.platform,
+            p2p: settings.p2p,
+            maintenance: settings.maintenance,
+            monitoring: settings.monitoring,
+            nat: settings.nat,
+            acme: settings.acme,
+            dns: settings.dns,
+            optimize: settings.optimize,
+            crash_dumps: settings.crash_dumps,
+            template: Default::default(),
+            overrides: Vec::new(),
+            workspace,
         })
     }
 
-    /// Load Boltfile from the configured path
+    /// Directory container stdout/stderr is written to
+    pub fn container_log_dir(&self) -> PathBuf {
+        self.logging
+            .container_log_dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("logs").join("containers"))
+    }
+
+    /// Load Boltfile from the configured path, or from `<path>.tmpl` when
+    /// only the template exists
     pub fn load_boltfile(&self) -> Result<BoltFile> {
-        BoltFile::load(&self.boltfile_path)
+        let path = template::resolve_path(&self.boltfile_path);
+        let mut boltfile = BoltFile::load_with(&path, &self.template)?;
+        let overrides = self.active_overrides()?;
+        if !overrides.is_empty() {
+            boltfile = overrides::apply(&boltfile, &overrides)?;
+        }
+        if let Some(ref workspace) = self.workspace {
+            crate::runtime::workspace::scope_boltfile(workspace, &mut boltfile);
+        }
+        Ok(boltfile)
+    }
+
+    /// The overrides saved under the selected profile, then those from `--set`
+    pub fn active_overrides(&self) -> Result<Vec<overrides::Override>> {
+        let mut active = Vec::new();
+        if let Some(ref profile) = self.template.profile {
+            let path = template::resolve_path(&self.boltfile_path);
+            match overrides::load_profile(&path, profile)? {
+                Some(saved) => active = saved,
+                // A template's profile may only set variables
+                None if template::is_template(&path) => {}
+                None => {
+                    return Err(anyhow!(
+                        "Profile '{}' not found in {}",
+                        profile,
+                        overrides::profiles_path(&path).display()
+                    ));
+                }
+            }
+        }
+        active.extend(self.overrides.iter().cloned());
+        Ok(active)
+    }
+
+    /// Render the Boltfile template and validate the result, returning the
+    /// plain Boltfile
+    pub fn render_boltfile(&self) -> Result<String> {
+        let path = template::resolve_path(&self.boltfile_path);
+        if !template::is_template(&path) {
+            return Err(anyhow!(
+                "{} is not a template (templates end in .{})",
+                path.display(),
+                template::TEMPLATE_EXTENSION
+            ));
+        }
+        let rendered = template::render(&path, &self.template)?;
+        BoltFile::parse(&rendered, path.parent().unwrap_or(Path::new(".")))
+            .with_context(|| format!("Rendered {} is not a valid Boltfile", path.display()))?;
+        Ok(rendered)
     }
 
     /// Save Boltfile to the configured path
     pub fn save_boltfile(&self, boltfile: &BoltFile) -> Result<()> {
+        let path = template::resolve_path(&self.boltfile_path);
+        if template::is_template(&path) {
+            return Err(anyhow!(
+                "The Boltfile is rendered from {}; edit the template instead",
+                path.display()
+            ));
+        }
         boltfile.save(&self.boltfile_path)
     }
 }
@@ -1021,7 +2763,7 @@ pub fn create_example_boltfile() -> BoltFile {
             image: Some("bolt://nginx:latest".to_string()),
             ports: Some(vec!["80:80".to_string()]),
             volumes: Some(vec!["./site:/usr/share/nginx/html".to_string()]),
-            depends_on: Some(vec!["api".to_string()]),
+            depends_on: Some(vec!["api".to_string()].into()),
             restart: Some("always".to_string()),
             ..Default::default()
         },
@@ -1039,7 +2781,7 @@ pub fn create_example_boltfile() -> BoltFile {
                 env.insert("DATABASE_URL".to_string(), "bolt://db".to_string());
                 Some(env)
             },
-            depends_on: Some(vec!["db".to_string()]),
+            depends_on: Some(vec!["db".to_string()].into()),
             restart: Some("always".to_string()),
             ..Default::default()
         },
@@ -1116,6 +2858,7 @@ pub fn create_example_boltfile() -> BoltFile {
                         core_clock_offset: Some(0),
                     }),
                     amd: None,
+                    intel: None,
                     nvbind: Some(NvbindConfig {
                         driver: Some("auto".to_string()),
                         devices: Some(vec!["gpu:0".to_string()]),
@@ -1135,6 +2878,11 @@ pub fn create_example_boltfile() -> BoltFile {
                         performance_profile: Some("maximum".to_string()),
                     }),
                     aiml: None,
+                    count: None,
+                    topology: None,
+                    ai: None,
+                    priority: None,
+                    fraction: None,
                 }),
                 audio: Some(AudioConfig {
                     system: "pipewire".to_string(),
@@ -1145,12 +2893,14 @@ pub fn create_example_boltfile() -> BoltFile {
                     proton: Some("8.0".to_string()),
                     winver: Some("win10".to_string()),
                     prefix: Some("/games/wine-prefix".to_string()),
+                    prefix_cache: None,
                 }),
                 performance: Some(PerformanceConfig {
                     cpu_governor: Some("performance".to_string()),
                     nice_level: Some(-10),
                     rt_priority: Some(50),
                 }),
+                display: None,
             }),
             ..Default::default()
         },
@@ -1244,5 +2994,6 @@ pub fn create_example_boltfile() -> BoltFile {
                 },
             ]),
         }),
+        maintenance: None,
     }
 }
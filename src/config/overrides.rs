@@ -0,0 +1,372 @@
+// Boltfile overrides
+//
+// `bolt surge up --set services.web.environment.DEBUG=1` changes the Boltfile
+// for one run without editing it. A path is dotted keys, with `[n]` for list
+// items (`services.web.ports[0]=8081:80`; `[n]` one past the end appends) and
+// quotes around keys that contain dots (`services.web.labels."com.example.team"`).
+// A value is taken as TOML when that fits the setting (`replicas=3`,
+// `privileged=true`) and as a string otherwise.
+//
+// Overrides apply after templates, `include` and `extends`, in order, and the
+// result is validated like the file itself. A path that isn't a Boltfile
+// setting is an error rather than silently ignored.
+//
+// `--save-profile <name>` keeps the overrides of a successful `surge up` under
+// `[profiles.<name>]` in `<Boltfile>.overrides.toml` next to the Boltfile;
+// `--profile <name>` applies them again, before any `--set`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+use super::BoltFile;
+
+/// One `--set path=value`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Override {
+    pub path: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for Override {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid override '{}': expected path=value", s))?;
+        let path = path.trim();
+        parse_path(path).with_context(|| format!("Invalid override '{}'", s))?;
+        Ok(Self {
+            path: path.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.path, self.value)
+    }
+}
+
+impl Override {
+    /// `environment.DEBUG=1` for `services.<service>.environment.DEBUG=1`
+    pub fn for_service(&self, service: &str) -> Option<String> {
+        let rest = self.path.strip_prefix("services.")?;
+        let rest = rest
+            .strip_prefix(service)
+            .or_else(|| rest.strip_prefix(&format!("\"{}\"", service)))?;
+        let setting = rest.strip_prefix('.').or_else(|| {
+            // The whole service, or one of its list items
+            (rest.is_empty() || rest.starts_with('[')).then_some(rest)
+        })?;
+        Some(if setting.is_empty() {
+            format!("={}", self.value)
+        } else {
+            format!("{}={}", setting, self.value)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    loop {
+        let mut key = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => key.push(c),
+                    None => return Err(anyhow!("unterminated quote in '{}'", path)),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == '.' || c == '[' {
+                    break;
+                }
+                key.push(c);
+                chars.next();
+            }
+        }
+        if key.is_empty() {
+            return Err(anyhow!("empty key in '{}'", path));
+        }
+        segments.push(Segment::Key(key));
+
+        while chars.peek() == Some(&'[') {
+            chars.next();
+            let index: String = chars.by_ref().take_while(|c| *c != ']').collect();
+            let index = index
+                .parse()
+                .map_err(|_| anyhow!("'[{}]' in '{}' is not a list index", index, path))?;
+            segments.push(Segment::Index(index));
+        }
+        match chars.next() {
+            None => return Ok(segments),
+            Some('.') => {}
+            Some(c) => return Err(anyhow!("unexpected '{}' in '{}'", c, path)),
+        }
+    }
+}
+
+/// The value as TOML, when it is valid TOML
+fn typed(value: &str) -> Option<Value> {
+    let table: toml::Table = toml::from_str(&format!("value = {}", value)).ok()?;
+    table.get("value").cloned()
+}
+
+fn set(doc: &mut Value, path: &[Segment], value: Value) -> Result<()> {
+    let Some((last, parents)) = path.split_last() else {
+        return Err(anyhow!("empty path"));
+    };
+    let mut current = doc;
+    for (i, segment) in parents.iter().enumerate() {
+        let empty = match path[i + 1] {
+            Segment::Key(_) => Value::Table(toml::Table::new()),
+            Segment::Index(_) => Value::Array(Vec::new()),
+        };
+        current = match segment {
+            Segment::Key(key) => current
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("'{}' is not a table", key))?
+                .entry(key.clone())
+                .or_insert(empty),
+            Segment::Index(index) => {
+                let array = current
+                    .as_array_mut()
+                    .ok_or_else(|| anyhow!("[{}] is not a list item", index))?;
+                if *index == array.len() {
+                    array.push(empty);
+                }
+                let len = array.len();
+                array
+                    .get_mut(*index)
+                    .ok_or_else(|| anyhow!("[{}] is past the end of a list of {}", index, len))?
+            }
+        };
+    }
+    match last {
+        Segment::Key(key) => {
+            current
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("'{}' is not a table", key))?
+                .insert(key.clone(), value);
+        }
+        Segment::Index(index) => {
+            let array = current
+                .as_array_mut()
+                .ok_or_else(|| anyhow!("[{}] is not a list item", index))?;
+            match index.cmp(&array.len()) {
+                std::cmp::Ordering::Less => array[*index] = value,
+                std::cmp::Ordering::Equal => array.push(value),
+                std::cmp::Ordering::Greater => {
+                    return Err(anyhow!(
+                        "[{}] is past the end of a list of {}",
+                        index,
+                        array.len()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get<'a>(doc: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(doc, |value, segment| match segment {
+        Segment::Key(key) => value.get(key.as_str()),
+        Segment::Index(index) => value.get(*index),
+    })
+}
+
+/// Whether a setting kept the value it was given; an integer read into a
+/// float setting comes back as a float
+fn kept(value: &Value, given: &Value) -> bool {
+    match (value, given) {
+        (Value::Float(f), Value::Integer(i)) => *f == *i as f64,
+        _ => value == given,
+    }
+}
+
+/// The Boltfile with `overrides` applied in order, validated
+pub fn apply(boltfile: &BoltFile, overrides: &[Override]) -> Result<BoltFile> {
+    let mut doc = Value::try_from(boltfile).context("Failed to serialize Boltfile")?;
+    for item in overrides {
+        let path = parse_path(&item.path)?;
+        let mut candidates: Vec<Value> = typed(&item.value).into_iter().collect();
+        if !candidates.contains(&Value::String(item.value.clone())) {
+            candidates.push(Value::String(item.value.clone()));
+        }
+
+        let mut error = None;
+        for candidate in candidates {
+            let mut attempt = doc.clone();
+            set(&mut attempt, &path, candidate.clone())
+                .with_context(|| format!("Invalid override '{}'", item))?;
+            match attempt.try_into::<BoltFile>() {
+                // Deserializing drops keys that aren't settings
+                Ok(parsed) => {
+                    let parsed = Value::try_from(&parsed)?;
+                    if get(&parsed, &path).is_some_and(|v| kept(v, &candidate)) {
+                        doc = parsed;
+                        error = None;
+                        break;
+                    }
+                    error = Some(anyhow!("'{}' is not a Boltfile setting", item.path));
+                }
+                Err(e) => error = error.or(Some(e.into())),
+            }
+        }
+        if let Some(error) = error {
+            return Err(error.context(format!("Invalid override '{}'", item)));
+        }
+    }
+
+    let boltfile: BoltFile = doc.try_into()?;
+    boltfile
+        .validate()
+        .context("The Boltfile is invalid with the overrides applied")?;
+    Ok(boltfile)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfilesFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    set: Vec<String>,
+}
+
+/// `Boltfile.toml` or `Boltfile.toml.tmpl` -> `Boltfile.overrides.toml`
+pub fn profiles_path(boltfile: &Path) -> PathBuf {
+    let name = boltfile
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = name
+        .strip_suffix(".tmpl")
+        .unwrap_or(&name)
+        .trim_end_matches(".toml");
+    boltfile.with_file_name(format!("{}.overrides.toml", stem))
+}
+
+fn read_profiles(path: &Path) -> Result<ProfilesFile> {
+    if !path.exists() {
+        return Ok(ProfilesFile::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// The overrides saved under `profile`, or None when it has none
+pub fn load_profile(boltfile: &Path, profile: &str) -> Result<Option<Vec<Override>>> {
+    let path = profiles_path(boltfile);
+    let Some(saved) = read_profiles(&path)?.profiles.remove(profile) else {
+        return Ok(None);
+    };
+    let overrides = saved
+        .set
+        .iter()
+        .map(|s| s.parse())
+        .collect::<Result<Vec<Override>>>()
+        .with_context(|| format!("Invalid profile '{}' in {}", profile, path.display()))?;
+    Ok(Some(overrides))
+}
+
+/// Add `overrides` to `profile`, replacing what it sets for the same paths,
+/// and return the file written
+pub fn save_profile(boltfile: &Path, profile: &str, overrides: &[Override]) -> Result<PathBuf> {
+    let path = profiles_path(boltfile);
+    let mut file = read_profiles(&path)?;
+    let saved = file.profiles.entry(profile.to_string()).or_default();
+    for item in overrides {
+        let prefix = format!("{}=", item.path);
+        saved.set.retain(|s| !s.starts_with(&prefix));
+        saved.set.push(item.to_string());
+    }
+    std::fs::write(&path, toml::to_string_pretty(&file)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_typed_and_string_overrides_and_rejects_unknown_settings() {
+        assert_eq!(
+            parse_path("services.web.labels.\"com.example.team\"").unwrap(),
+            vec![
+                Segment::Key("services".to_string()),
+                Segment::Key("web".to_string()),
+                Segment::Key("labels".to_string()),
+                Segment::Key("com.example.team".to_string()),
+            ]
+        );
+        assert!("services.web.ports[x]=1".parse::<Override>().is_err());
+        assert!("services.web.image".parse::<Override>().is_err());
+
+        let boltfile = BoltFile::parse(
+            "project = \"demo\"\n\n[services.web]\nimage = \"nginx:1.27\"\nports = [\"8080:80\"]\n",
+            Path::new("."),
+        )
+        .unwrap();
+        let set = |overrides: &[&str]| {
+            let overrides: Vec<Override> = overrides.iter().map(|o| o.parse().unwrap()).collect();
+            apply(&boltfile, &overrides)
+        };
+
+        let applied = set(&[
+            "services.web.environment.DEBUG=1",
+            "services.web.ports[0]=8081:80",
+            "services.web.ports[1]=8443:443",
+        ])
+        .unwrap();
+        let web = &applied.services["web"];
+        assert_eq!(web.environment.as_ref().unwrap()["DEBUG"], "1");
+        assert_eq!(
+            web.ports.as_deref().unwrap(),
+            ["8081:80".to_string(), "8443:443".to_string()]
+        );
+
+        assert!(set(&["services.web.enviroment.DEBUG=1"]).is_err());
+        assert!(set(&["services.web.ports[5]=1:1"]).is_err());
+        assert!(set(&["project="]).is_err());
+
+        let item: Override = "services.web.environment.DEBUG=1".parse().unwrap();
+        assert_eq!(
+            item.for_service("web").as_deref(),
+            Some("environment.DEBUG=1")
+        );
+        assert_eq!(item.for_service("we"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let boltfile_path = dir.path().join("Boltfile.toml");
+        save_profile(&boltfile_path, "debug", &[item.clone()]).unwrap();
+        let replaced: Override = "services.web.environment.DEBUG=2".parse().unwrap();
+        save_profile(&boltfile_path, "debug", &[replaced.clone()]).unwrap();
+        assert_eq!(
+            load_profile(&boltfile_path, "debug").unwrap(),
+            Some(vec![replaced])
+        );
+        assert_eq!(load_profile(&boltfile_path, "other").unwrap(), None);
+    }
+}
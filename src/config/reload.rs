@@ -0,0 +1,370 @@
+// Configuration reload
+//
+// A long-running Bolt, like the API server, rereads `<config_dir>/config.toml`
+// on SIGHUP or `POST /bolt/v1/config/reload` instead of needing a restart.
+// The new file is parsed and validated as a whole first, so a typo leaves the
+// running config untouched, and then swapped in at once: a request sees
+// either the old config or the new one, never a mix of the two.
+//
+// Paths, the workspace, `--verbose` and template variables come from how the
+// process was started and are kept. Log levels take effect right away; the
+// log format and the container log directory are only read when logging is
+// set up, so changing them is reported as needing a restart.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use super::{BoltConfig, BoltSettingsFile};
+use crate::builds::cache::{parse_duration, parse_size};
+
+/// What a reload changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigReload {
+    /// Sections of config.toml that differ from the running config
+    pub changed: Vec<String>,
+    /// Changed settings that only apply after a restart
+    pub restart_required: Vec<String>,
+}
+
+impl BoltConfig {
+    /// Check the settings that are only parsed when they are used, so a bad
+    /// value is caught when the file is (re)loaded
+    pub fn validate_settings(&self) -> Result<()> {
+        for (key, level) in std::iter::once(("level", &self.logging.level)).chain(
+            self.logging
+                .subsystems
+                .iter()
+                .map(|(subsystem, level)| (subsystem.as_str(), level)),
+        ) {
+            level
+                .parse::<tracing::level_filters::LevelFilter>()
+                .map_err(|_| anyhow!("Invalid [logging] {}: unknown level '{}'", key, level))?;
+        }
+        parse_size(&self.build_cache.max_size).context("Invalid [build_cache] max_size")?;
+        parse_duration(&self.alerts.repeat_interval).context("Invalid [alerts] repeat_interval")?;
+
+        let capacity = &self.capacity;
+        for (key, size) in [
+            ("data_dir_max", &capacity.data_dir_max),
+            ("volumes_max", &capacity.volumes_max),
+            ("images_max", &capacity.images_max),
+        ] {
+            if let Some(size) = size {
+                parse_size(size).with_context(|| format!("Invalid [capacity] {}", key))?;
+            }
+        }
+        parse_duration(&capacity.window).context("Invalid [capacity] window")?;
+        parse_duration(&capacity.retention).context("Invalid [capacity] retention")?;
+        if capacity.disk_threshold_percent > 100 {
+            return Err(anyhow!(
+                "Invalid [capacity] disk_threshold_percent: {} is over 100",
+                capacity.disk_threshold_percent
+            ));
+        }
+
+        for (key, limit) in [
+            ("upload_limit", &self.p2p.upload_limit),
+            ("download_limit", &self.p2p.download_limit),
+        ] {
+            if let Some(limit) = limit {
+                parse_size(limit).with_context(|| format!("Invalid [p2p] {}", key))?;
+            }
+        }
+        for network in &self.p2p.allow {
+            network
+                .parse::<ipnet::IpNet>()
+                .map_err(|_| anyhow!("Invalid [p2p] allow: '{}' is not a CIDR", network))?;
+        }
+
+        if let Some(ref window) = self.maintenance.window {
+            crate::surge::maintenance::Window::parse(window)
+                .context("Invalid [maintenance] window")?;
+        }
+        parse_duration(&self.maintenance.notify_before)
+            .context("Invalid [maintenance] notify_before")?;
+
+        let monitoring = &self.monitoring;
+        monitoring
+            .bind_addr()
+            .context("Invalid [monitoring] address")?;
+        match (&monitoring.tls_cert, &monitoring.tls_key) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !path.is_file() {
+                        return Err(anyhow!("[monitoring] {} does not exist", path.display()));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(anyhow!(
+                    "[monitoring] tls_cert and tls_key must be set together"
+                ));
+            }
+        }
+        if monitoring.container_labels.iter().any(|l| l.is_empty()) {
+            return Err(anyhow!(
+                "Invalid [monitoring] container_labels: empty label"
+            ));
+        }
+
+        let nat = &self.nat;
+        if parse_duration(&nat.lease).context("Invalid [nat] lease")?
+            < crate::networking::nat::MIN_LEASE
+        {
+            return Err(anyhow!("Invalid [nat] lease: must be at least 2m"));
+        }
+        for server in nat.stun.iter().chain(&nat.relay) {
+            if server
+                .rsplit_once(':')
+                .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+            {
+                return Err(anyhow!(
+                    "Invalid [nat] address '{}': expected host:port",
+                    server
+                ));
+            }
+        }
+        crate::networking::nat::PortRange::parse(&nat.relay_ports)
+            .context("Invalid [nat] relay_ports")?;
+
+        let acme = &self.acme;
+        for (key, duration) in [
+            ("renew_before", &acme.renew_before),
+            ("check_interval", &acme.check_interval),
+            ("dns_propagation", &acme.dns_propagation),
+        ] {
+            parse_duration(duration).with_context(|| format!("Invalid [acme] {}", key))?;
+        }
+        for certificate in &acme.certificates {
+            if certificate.domains.is_empty() {
+                return Err(anyhow!(
+                    "[acme] certificate {} has no domains",
+                    certificate.name
+                ));
+            }
+            if certificate.challenge == super::ChallengeKind::Http01
+                && certificate.domains.iter().any(|d| d.starts_with("*."))
+            {
+                return Err(anyhow!(
+                    "[acme] certificate {} has a wildcard domain, which needs challenge = \"dns-01\"",
+                    certificate.name
+                ));
+            }
+            if certificate.challenge == super::ChallengeKind::Dns01 && acme.dns.is_none() {
+                return Err(anyhow!(
+                    "[acme] certificate {} uses dns-01 but no [acme.dns] provider is set",
+                    certificate.name
+                ));
+            }
+        }
+
+        let dns = &self.dns;
+        if dns.ttl == 0 {
+            return Err(anyhow!("Invalid [dns] ttl: must be at least 1"));
+        }
+        if let Some(ref published) = dns.published
+            && !dns.providers.contains_key(&published.provider)
+        {
+            return Err(anyhow!(
+                "[dns.published] uses provider '{}', which [dns.providers] doesn't define",
+                published.provider
+            ));
+        }
+        for (name, provider) in &dns.providers {
+            let file = match provider {
+                super::ExternalDnsProvider::Rfc2136 {
+                    key_file: Some(file),
+                    ..
+                }
+                | super::ExternalDnsProvider::Opnsense {
+                    ca_cert: Some(file),
+                    ..
+                } => file,
+                _ => continue,
+            };
+            if !file.is_file() {
+                return Err(anyhow!(
+                    "[dns.providers.{}] {} does not exist",
+                    name,
+                    file.display()
+                ));
+            }
+        }
+
+        if let Some(ref cpus) = self.optimize.irq_cpus {
+            crate::optimizations::hybrid::parse_cpu_list(cpus)
+                .context("Invalid [optimize] irq_cpus")?;
+        }
+        for name in self.optimize.sysctls.keys() {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+            {
+                return Err(anyhow!("Invalid [optimize] sysctl name '{}'", name));
+            }
+        }
+
+        if let Some(ref policy) = self.trust.policy
+            && !policy.is_file()
+        {
+            return Err(anyhow!(
+                "[trust] policy {} does not exist",
+                policy.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// This config with the settings from config.toml as it is now on disk
+    pub fn reloaded(&self) -> Result<(BoltConfig, ConfigReload)> {
+        let settings = BoltSettingsFile::read(&self.config_dir)?;
+        let mut config = BoltConfig {
+            logging: settings.logging,
+            build_cache: settings.build_cache,
+            capacity: settings.capacity,
+            alerts: settings.alerts,
+            backup: settings.backup,
+            encryption: settings.encryption,
+            trust: settings.trust,
+            ports: settings.ports,
+            platform: settings.platform,
+            p2p: settings.p2p,
+            maintenance: settings.maintenance,
+            monitoring: settings.monitoring,
+            nat: settings.nat,
+            acme: settings.acme,
+            dns: settings.dns,
+            optimize: settings.optimize,
+            crash_dumps: settings.crash_dumps,
+            ..self.clone()
+        };
+        // `--override-trust` is a command-line flag, not a setting
+        config.trust.override_violations = self.trust.override_violations;
+        config.validate_settings()?;
+
+        let mut reload = ConfigReload::default();
+        for (section, old, new) in [
+            (
+                "logging",
+                section(&self.logging)?,
+                section(&config.logging)?,
+            ),
+            (
+                "build_cache",
+                section(&self.build_cache)?,
+                section(&config.build_cache)?,
+            ),
+            (
+                "capacity",
+                section(&self.capacity)?,
+                section(&config.capacity)?,
+            ),
+            ("alerts", section(&self.alerts)?, section(&config.alerts)?),
+            ("backup", section(&self.backup)?, section(&config.backup)?),
+            (
+                "encryption",
+                section(&self.encryption)?,
+                section(&config.encryption)?,
+            ),
+            ("trust", section(&self.trust)?, section(&config.trust)?),
+            ("ports", section(&self.ports)?, section(&config.ports)?),
+            (
+                "platform",
+                section(&self.platform)?,
+                section(&config.platform)?,
+            ),
+            ("p2p", section(&self.p2p)?, section(&config.p2p)?),
+            (
+                "maintenance",
+                section(&self.maintenance)?,
+                section(&config.maintenance)?,
+            ),
+            (
+                "monitoring",
+                section(&self.monitoring)?,
+                section(&config.monitoring)?,
+            ),
+            ("nat", section(&self.nat)?, section(&config.nat)?),
+            ("acme", section(&self.acme)?, section(&config.acme)?),
+            ("dns", section(&self.dns)?, section(&config.dns)?),
+            (
+                "optimize",
+                section(&self.optimize)?,
+                section(&config.optimize)?,
+            ),
+            (
+                "crash_dumps",
+                section(&self.crash_dumps)?,
+                section(&config.crash_dumps)?,
+            ),
+        ] {
+            if old != new {
+                reload.changed.push(section.to_string());
+            }
+        }
+        if config.logging.format != self.logging.format {
+            reload.restart_required.push("logging.format".to_string());
+        }
+        if config.logging.container_log_dir != self.logging.container_log_dir {
+            reload
+                .restart_required
+                .push("logging.container_log_dir".to_string());
+        }
+        Ok((config, reload))
+    }
+}
+
+fn section<T: Serialize>(settings: &T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(settings)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloads_valid_settings_and_keeps_the_running_config_on_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let running = BoltConfig {
+            config_dir: dir.path().to_path_buf(),
+            data_dir: dir.path().join("data"),
+            workspace: Some("team".to_string()),
+            ..Default::default()
+        };
+
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "[logging]\nlevel = \"debug\"\nformat = \"json\"\n\n[alerts]\nrepeat_interval = \"1h\"\n",
+        )
+        .unwrap();
+        let (config, reload) = running.reloaded().unwrap();
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.alerts.repeat_interval, "1h");
+        assert_eq!(config.workspace.as_deref(), Some("team"));
+        assert_eq!(config.data_dir, dir.path().join("data"));
+        assert_eq!(reload.changed, vec!["logging", "alerts"]);
+        assert_eq!(reload.restart_required, vec!["logging.format"]);
+
+        // Nothing changed the second time
+        let (_, reload) = config.reloaded().unwrap();
+        assert_eq!(reload, ConfigReload::default());
+
+        for invalid in [
+            "[logging]\nlevel = \"loud\"\n",
+            "[alerts]\nrepeat_interval = \"soon\"\n",
+            "[capacity]\ndisk_threshold_percent = 120\n",
+            "[monitoring]\naddress = \"localhost\"\n",
+            "[monitoring]\ntls_cert = \"/nonexistent/cert.pem\"\n",
+            "[nat]\nlease = \"30s\"\n",
+            "[nat]\nrelay = \"relay.example.com\"\n",
+            "[[acme.certificates]]\nname = \"web\"\ndomains = [\"*.example.com\"]\n",
+            "[dns.published]\nprovider = \"pihole\"\nzone = \"home.lab\"\n",
+            "[optimize]\nirq_cpus = \"0-\"\n",
+            "[logging\n",
+        ] {
+            std::fs::write(dir.path().join("config.toml"), invalid).unwrap();
+            assert!(config.reloaded().is_err(), "{}", invalid);
+        }
+    }
+}
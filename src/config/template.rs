@@ -0,0 +1,243 @@
+// Boltfile templates
+//
+// A Boltfile named `*.toml.tmpl` is rendered with Handlebars before it is
+// parsed, so one file can serve nodes that differ slightly:
+//
+//   {{#if gpu}}
+//   [services.worker.gpu]
+//   runtime = "nvidia"
+//   {{/if}}
+//
+// Variables, later sources winning:
+// - host facts: hostname, arch, os, cpus, gpu, nvidia, amd
+// - `<name>.vars.toml` next to the template: top-level defaults, overridden
+//   by `[profiles.<profile>]` when a profile is selected
+// - `BOLT_VAR_<NAME>` environment variables (as `name`)
+// - `--var name=value` on the command line
+//
+// The environment is also available as `{{env.HOME}}`. Rendering is strict:
+// an unknown `{{variable}}` is an error, while `{{#if unknown}}` is false.
+use anyhow::{Context, Result, anyhow};
+use handlebars::Handlebars;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Variables and profile chosen on the command line
+#[derive(Debug, Clone, Default)]
+pub struct TemplateOptions {
+    pub vars: BTreeMap<String, String>,
+    pub profile: Option<String>,
+}
+
+impl TemplateOptions {
+    /// Parse `name=value` pairs from `--var`
+    pub fn from_args(vars: &[String], profile: Option<String>) -> Result<Self> {
+        let mut parsed = BTreeMap::new();
+        for var in vars {
+            let (name, value) = var
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid --var '{}': expected name=value", var))?;
+            parsed.insert(name.trim().to_string(), value.to_string());
+        }
+        Ok(Self {
+            vars: parsed,
+            profile,
+        })
+    }
+}
+
+pub fn is_template(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == TEMPLATE_EXTENSION)
+}
+
+/// The template to use for `path` when only `<path>.tmpl` exists
+pub fn resolve_path(path: &Path) -> PathBuf {
+    if path.exists() || is_template(path) {
+        return path.to_path_buf();
+    }
+    let mut template = path.as_os_str().to_owned();
+    template.push(".");
+    template.push(TEMPLATE_EXTENSION);
+    let template = PathBuf::from(template);
+    if template.exists() {
+        template
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Render a template to a plain Boltfile
+pub fn render(path: &Path, options: &TemplateOptions) -> Result<String> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read Boltfile template {}", path.display()))?;
+    let context = context(path, options)?;
+    render_str(&source, &context)
+        .with_context(|| format!("Failed to render Boltfile template {}", path.display()))
+}
+
+fn render_str(source: &str, context: &Value) -> Result<String> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(true);
+    handlebars.register_escape_fn(handlebars::no_escape);
+    Ok(handlebars.render_template(source, context)?)
+}
+
+fn context(path: &Path, options: &TemplateOptions) -> Result<Value> {
+    let mut vars = host_facts();
+
+    let vars_path = vars_path(path);
+    if vars_path.exists() {
+        let content = std::fs::read_to_string(&vars_path)
+            .with_context(|| format!("Failed to read {}", vars_path.display()))?;
+        let mut table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", vars_path.display()))?;
+        let profiles = table.remove("profiles");
+        vars.extend(to_json(toml::Value::Table(table)));
+
+        if let Some(profile) = &options.profile {
+            let values = profiles
+                .as_ref()
+                .and_then(|p| p.get(profile))
+                .ok_or_else(|| {
+                    anyhow!("Profile '{}' not found in {}", profile, vars_path.display())
+                })?;
+            vars.extend(to_json(values.clone()));
+        }
+    } else if let Some(profile) = &options.profile {
+        return Err(anyhow!(
+            "Profile '{}' selected but {} does not exist",
+            profile,
+            vars_path.display()
+        ));
+    }
+
+    let mut env = Map::new();
+    for (key, value) in std::env::vars() {
+        if let Some(name) = key.strip_prefix("BOLT_VAR_") {
+            vars.insert(name.to_lowercase(), scalar(&value));
+        }
+        env.insert(key, Value::String(value));
+    }
+    for (name, value) in &options.vars {
+        vars.insert(name.clone(), scalar(value));
+    }
+    vars.insert("env".to_string(), Value::Object(env));
+
+    Ok(Value::Object(vars))
+}
+
+/// `Boltfile.toml.tmpl` -> `Boltfile.vars.toml`
+fn vars_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = name
+        .strip_suffix(".tmpl")
+        .unwrap_or(&name)
+        .trim_end_matches(".toml");
+    path.with_file_name(format!("{}.vars.toml", stem))
+}
+
+fn host_facts() -> Map<String, Value> {
+    let nvidia = Path::new("/proc/driver/nvidia").exists();
+    let amd = std::fs::read_dir("/sys/class/drm")
+        .map(|entries| {
+            entries.flatten().any(|e| {
+                std::fs::read_to_string(e.path().join("device/vendor"))
+                    .is_ok_and(|v| v.trim() == "0x1002")
+            })
+        })
+        .unwrap_or(false);
+
+    let mut facts = Map::new();
+    facts.insert(
+        "hostname".to_string(),
+        Value::String(
+            hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+    );
+    facts.insert(
+        "arch".to_string(),
+        Value::String(std::env::consts::ARCH.to_string()),
+    );
+    facts.insert(
+        "os".to_string(),
+        Value::String(std::env::consts::OS.to_string()),
+    );
+    facts.insert("cpus".to_string(), Value::from(num_cpus::get()));
+    facts.insert("gpu".to_string(), Value::Bool(nvidia || amd));
+    facts.insert("nvidia".to_string(), Value::Bool(nvidia));
+    facts.insert("amd".to_string(), Value::Bool(amd));
+    facts
+}
+
+/// CLI and environment values are strings; "false" should be falsy in `{{#if}}`
+fn scalar(value: &str) -> Value {
+    match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => value
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(value.to_string())),
+    }
+}
+
+fn to_json(value: toml::Value) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_with_profiles_and_cli_vars() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("Boltfile.toml.tmpl");
+        std::fs::write(
+            &template,
+            r#"project = "{{project}}"
+[services.web]
+image = "nginx:{{tag}}"
+{{#if gpu}}
+[services.web.gpu]
+runtime = "nvidia"
+{{/if}}
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("Boltfile.vars.toml"),
+            "project = \"demo\"\ntag = \"1.25\"\n\n[profiles.edge]\ntag = \"1.25-alpine\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(resolve_path(&dir.path().join("Boltfile.toml")), template);
+
+        let options =
+            TemplateOptions::from_args(&["gpu=false".to_string()], Some("edge".to_string()))
+                .unwrap();
+        let rendered = render(&template, &options).unwrap();
+        assert!(rendered.contains("project = \"demo\""));
+        assert!(rendered.contains("image = \"nginx:1.25-alpine\""));
+        assert!(!rendered.contains("[services.web.gpu]"));
+
+        let options = TemplateOptions::from_args(&["gpu=true".to_string()], None).unwrap();
+        let rendered = render(&template, &options).unwrap();
+        assert!(rendered.contains("image = \"nginx:1.25\""));
+        assert!(rendered.contains("runtime = \"nvidia\""));
+
+        std::fs::write(&template, "image = \"{{undefined}}\"").unwrap();
+        assert!(render(&template, &options).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::BoltRuntime;
-use crate::error::{BoltError, Result};
+use crate::error::{BoltError, ErrorCategory, ErrorCode, ErrorPayload, Result};
 use crate::types::{ContainerInfo, NetworkInfo};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -716,10 +716,13 @@ impl DockerAPIServer {
             .or(images_list)
             .or(images_pull)
             .or(images_push)
-            .or(networks_list);
+            .or(networks_list)
+            .or(super::bolt_api::routes(self.runtime.clone()))
+            .or(super::bolt_api::healthz(self.runtime.clone()));
 
         // Add CORS and logging
         let routes = api_routes
+            .recover(Self::handle_rejection)
             .with(warp::cors().allow_any_origin())
             .with(warp::log("docker_api"));
 
@@ -737,6 +740,56 @@ impl DockerAPIServer {
         Ok(())
     }
 
+    /// Turn rejections into JSON error bodies. Docker clients only read
+    /// `message`; Bolt-aware clients can also use `code` and `category`.
+    pub(crate) async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+        let (status, payload) = if let Some(e) = err.find::<BoltError>() {
+            (e.http_status(), e.to_payload())
+        } else if let Some(e) = err.find::<DockerAPIError>() {
+            let code = match e {
+                DockerAPIError::BadRequest(_) => ErrorCode::InvalidConfig,
+                DockerAPIError::NotFound(_) => ErrorCode::ContainerNotFound,
+                DockerAPIError::Internal(_) => ErrorCode::Internal,
+            };
+            (
+                code.category().http_status(),
+                ErrorPayload {
+                    code,
+                    category: code.category(),
+                    message: e.to_string(),
+                    causes: Vec::new(),
+                },
+            )
+        } else if err.is_not_found() {
+            (
+                404,
+                ErrorPayload {
+                    code: ErrorCode::Internal,
+                    category: ErrorCategory::NotFound,
+                    message: "page not found".to_string(),
+                    causes: Vec::new(),
+                },
+            )
+        } else {
+            (
+                400,
+                ErrorPayload {
+                    code: ErrorCode::InvalidConfig,
+                    category: ErrorCategory::InvalidArgument,
+                    message: format!("{:?}", err),
+                    causes: Vec::new(),
+                },
+            )
+        };
+
+        let status = warp::http::StatusCode::from_u16(status)
+            .unwrap_or(warp::http::StatusCode::INTERNAL_SERVER_ERROR);
+        Ok(warp::reply::with_status(
+            warp::reply::json(&payload),
+            status,
+        ))
+    }
+
     async fn version_handler(_runtime: Arc<BoltRuntime>) -> Result<impl Reply, Rejection> {
         let version = DockerVersion {
             version: "24.0.7".to_string(),
@@ -1243,7 +1296,11 @@ impl DockerAPIServer {
         params: HashMap<String, String>,
     ) -> Result<impl Reply, Rejection> {
         if let Some(from_image) = params.get("fromImage") {
-            match runtime.pull_image(from_image).await {
+            let platform = params.get("platform").filter(|p| !p.is_empty());
+            match runtime
+                .pull_image(from_image, platform.map(String::as_str))
+                .await
+            {
                 Ok(_) => {
                     let response = serde_json::json!({
                         "status": "Pull complete",
@@ -0,0 +1,749 @@
+// Native Bolt API
+//
+// The API server speaks Bolt's own JSON API under `/bolt/v1` next to the
+// Docker-compatible endpoints. Its routes mirror `BoltRuntime` methods, so
+// `bolt::client::BoltClient` (the `bolt-client` crate) can drive a remote
+// Bolt the way a local program drives the runtime. The client keeps its own
+// copy of the reply types, so the tests below drive the routes with it.
+// Failures come back as an `ErrorPayload` with the matching HTTP status.
+//
+// Surge calls act on the daemon's Boltfile, paths are on the daemon's host,
+// and long-running calls never attach: containers and services always start
+// detached, and exec returns the command's output once it exits. Calls that
+// need a terminal or stream files (interactive exec, debug shells, dev
+// environments, cp) and foreground servers have no route. Host
+// administration (builders, capsules, workspaces, tuning, maintenance,
+// certificates) stays with the CLI on the daemon's host.
+use crate::BoltRuntime;
+use crate::error::Result;
+use crate::runtime::pull::PullPolicy;
+use crate::runtime::snapshots::SnapshotKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection};
+
+pub use bolt_client::API_PREFIX;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListContainersQuery {
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunContainerRequest {
+    pub image: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub ports: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub volumes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoveContainerQuery {
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestartContainerQuery {
+    #[serde(default = "default_restart_timeout")]
+    pub timeout: u64,
+}
+
+fn default_restart_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageRequest {
+    pub image: String,
+    #[serde(default)]
+    pub pull_policy: Option<PullPolicy>,
+    /// `os/architecture[/variant]`; the daemon host's when unset
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateNetworkRequest {
+    pub name: String,
+    pub driver: String,
+    pub subnet: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SurgeRequest {
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub force_recreate: bool,
+    #[serde(default)]
+    pub pull: Option<PullPolicy>,
+    /// `surge down` only: also remove the services' volumes
+    #[serde(default)]
+    pub volumes: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrashesQuery {
+    pub container: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForceQuery {
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildImageRequest {
+    /// Build context on the daemon's host
+    pub path: String,
+    pub tag: Option<String>,
+    #[serde(default = "default_dockerfile")]
+    pub dockerfile: String,
+    #[serde(default = "default_builder")]
+    pub builder: String,
+    #[serde(default)]
+    pub cache_volumes: Vec<String>,
+    #[serde(default)]
+    pub annotations: Vec<String>,
+    #[serde(default)]
+    pub build_args: Vec<String>,
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+fn default_dockerfile() -> String {
+    "Dockerfile".to_string()
+}
+
+fn default_builder() -> String {
+    crate::builds::builders::DEFAULT_BUILDER.to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecRequest {
+    pub command: Vec<String>,
+    /// Extra environment, as KEY=VALUE
+    #[serde(default)]
+    pub env: Vec<String>,
+    pub workdir: Option<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateVolumeRequest {
+    pub name: String,
+    #[serde(default = "default_volume_driver")]
+    pub driver: String,
+    pub size: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+}
+
+fn default_volume_driver() -> String {
+    "local".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GamingSetupRequest {
+    pub proton: Option<String>,
+    pub winver: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchGameRequest {
+    pub game: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResticBackupRequest {
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub snapshots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResticRestoreRequest {
+    pub snapshot_id: String,
+    /// Restore here on the daemon's host instead of in place
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotsQuery {
+    pub kind: Option<SnapshotKind>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default = "default_snapshot_kind")]
+    pub kind: SnapshotKind,
+}
+
+fn default_snapshot_kind() -> SnapshotKind {
+    SnapshotKind::Manual
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupSnapshotsRequest {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn json<T: Serialize>(result: Result<T>) -> std::result::Result<warp::reply::Json, Rejection> {
+    result
+        .map(|value| warp::reply::json(&value))
+        .map_err(warp::reject::custom)
+}
+
+fn with_runtime(
+    runtime: Arc<BoltRuntime>,
+) -> impl Filter<Extract = (Arc<BoltRuntime>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || runtime.clone())
+}
+
+/// Routes of the native API, without rejection handling
+pub fn routes(runtime: Arc<BoltRuntime>) -> BoxedFilter<(warp::reply::Json,)> {
+    let rt = || with_runtime(runtime.clone());
+
+    let list_containers = warp::path!("bolt" / "v1" / "containers")
+        .and(warp::get())
+        .and(warp::query::<ListContainersQuery>())
+        .and(rt())
+        .and_then(|q: ListContainersQuery, rt: Arc<BoltRuntime>| async move {
+            json(rt.list_containers(q.all).await)
+        });
+
+    let run_container = warp::path!("bolt" / "v1" / "containers")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: RunContainerRequest, rt: Arc<BoltRuntime>| async move {
+                json(
+                    rt.run_container(
+                        &req.image,
+                        req.name.as_deref(),
+                        &req.ports,
+                        &req.env,
+                        &req.volumes,
+                        true,
+                    )
+                    .await,
+                )
+            },
+        );
+
+    let stop_container = warp::path!("bolt" / "v1" / "containers" / String / "stop")
+        .and(warp::post())
+        .and(rt())
+        .and_then(|name: String, rt: Arc<BoltRuntime>| async move {
+            json(rt.stop_container(&name).await)
+        });
+
+    let restart_container = warp::path!("bolt" / "v1" / "containers" / String / "restart")
+        .and(warp::post())
+        .and(warp::query::<RestartContainerQuery>())
+        .and(rt())
+        .and_then(
+            |name: String, q: RestartContainerQuery, rt: Arc<BoltRuntime>| async move {
+                json(rt.restart_container(&name, q.timeout).await)
+            },
+        );
+
+    let remove_container = warp::path!("bolt" / "v1" / "containers" / String)
+        .and(warp::delete())
+        .and(warp::query::<RemoveContainerQuery>())
+        .and(rt())
+        .and_then(
+            |name: String, q: RemoveContainerQuery, rt: Arc<BoltRuntime>| async move {
+                json(rt.remove_container(&name, q.force).await)
+            },
+        );
+
+    let exec_container = warp::path!("bolt" / "v1" / "containers" / String / "exec")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |name: String, req: ExecRequest, rt: Arc<BoltRuntime>| async move {
+                let options = crate::runtime::ExecOptions {
+                    env: req.env,
+                    workdir: req.workdir,
+                    user: req.user,
+                    ..Default::default()
+                };
+                json(
+                    rt.exec_container_output(&name, &req.command, &options)
+                        .await,
+                )
+            },
+        );
+
+    let build_image = warp::path!("bolt" / "v1" / "images" / "build")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: BuildImageRequest, rt: Arc<BoltRuntime>| async move {
+            json(
+                rt.build_image_on(
+                    &req.builder,
+                    &req.path,
+                    req.tag.as_deref(),
+                    &req.dockerfile,
+                    &req.cache_volumes,
+                    &req.annotations,
+                    &req.build_args,
+                    req.no_cache,
+                )
+                .await,
+            )
+        });
+
+    let pull_image = warp::path!("bolt" / "v1" / "images" / "pull")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: ImageRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.pull_image(&req.image, req.platform.as_deref()).await)
+        });
+
+    let ensure_image = warp::path!("bolt" / "v1" / "images" / "ensure")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: ImageRequest, rt: Arc<BoltRuntime>| async move {
+            json(
+                rt.ensure_image(&req.image, req.pull_policy, req.platform.as_deref())
+                    .await,
+            )
+        });
+
+    let push_image = warp::path!("bolt" / "v1" / "images" / "push")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: ImageRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.push_image(&req.image).await)
+        });
+
+    let list_networks = warp::path!("bolt" / "v1" / "networks")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.list_networks().await) });
+
+    let create_network = warp::path!("bolt" / "v1" / "networks")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: CreateNetworkRequest, rt: Arc<BoltRuntime>| async move {
+                json(
+                    rt.create_network(&req.name, &req.driver, req.subnet.as_deref())
+                        .await,
+                )
+            },
+        );
+
+    let remove_network = warp::path!("bolt" / "v1" / "networks" / String)
+        .and(warp::delete())
+        .and(rt())
+        .and_then(|name: String, rt: Arc<BoltRuntime>| async move {
+            json(rt.remove_network(&name).await)
+        });
+
+    let list_volumes = warp::path!("bolt" / "v1" / "volumes")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.list_volumes().await) });
+
+    let create_volume = warp::path!("bolt" / "v1" / "volumes")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: CreateVolumeRequest, rt: Arc<BoltRuntime>| async move {
+                let options = crate::volume::VolumeCreateOptions {
+                    driver: req.driver,
+                    size: req.size,
+                    labels: req.labels,
+                    options: req.options,
+                };
+                json(rt.create_volume(&req.name, options).await)
+            },
+        );
+
+    let inspect_volume = warp::path!("bolt" / "v1" / "volumes" / String)
+        .and(warp::get())
+        .and(rt())
+        .and_then(|name: String, rt: Arc<BoltRuntime>| async move {
+            json(rt.inspect_volume(&name).await)
+        });
+
+    let remove_volume = warp::path!("bolt" / "v1" / "volumes" / String)
+        .and(warp::delete())
+        .and(warp::query::<ForceQuery>())
+        .and(rt())
+        .and_then(
+            |name: String, q: ForceQuery, rt: Arc<BoltRuntime>| async move {
+                json(rt.remove_volume(&name, q.force).await)
+            },
+        );
+
+    let prune_volumes = warp::path!("bolt" / "v1" / "volumes" / "prune")
+        .and(warp::post())
+        .and(warp::query::<ForceQuery>())
+        .and(rt())
+        .and_then(|q: ForceQuery, rt: Arc<BoltRuntime>| async move {
+            json(rt.prune_volumes(q.force).await)
+        });
+
+    let surge_status = warp::path!("bolt" / "v1" / "surge" / "status")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.surge_status().await) });
+
+    let surge_up = warp::path!("bolt" / "v1" / "surge" / "up")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: SurgeRequest, rt: Arc<BoltRuntime>| async move {
+            json(
+                rt.surge_up_with_pull(&req.services, true, req.force_recreate, req.pull)
+                    .await,
+            )
+        });
+
+    let surge_down = warp::path!("bolt" / "v1" / "surge" / "down")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: SurgeRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.surge_down(&req.services, req.volumes).await)
+        });
+
+    let surge_plan = warp::path!("bolt" / "v1" / "surge" / "plan")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: SurgeRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.surge_plan(&req.services).await)
+        });
+
+    let surge_scale = warp::path!("bolt" / "v1" / "surge" / "scale")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: SurgeRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.surge_scale(&req.services).await)
+        });
+
+    let setup_gaming = warp::path!("bolt" / "v1" / "gaming" / "setup")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: GamingSetupRequest, rt: Arc<BoltRuntime>| async move {
+            json(
+                rt.setup_gaming(req.proton.as_deref(), req.winver.as_deref())
+                    .await,
+            )
+        });
+
+    let launch_game = warp::path!("bolt" / "v1" / "gaming" / "launch")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(|req: LaunchGameRequest, rt: Arc<BoltRuntime>| async move {
+            json(rt.launch_game(&req.game, &req.args).await)
+        });
+
+    let restic_init = warp::path!("bolt" / "v1" / "backup" / "restic" / "init")
+        .and(warp::post())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.restic_init().await) });
+
+    let restic_backup = warp::path!("bolt" / "v1" / "backup" / "restic" / "backup")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: ResticBackupRequest, rt: Arc<BoltRuntime>| async move {
+                json(rt.restic_backup(&req.volumes, &req.snapshots).await)
+            },
+        );
+
+    let restic_snapshots = warp::path!("bolt" / "v1" / "backup" / "restic" / "snapshots")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.restic_snapshots().await) });
+
+    let restic_restore = warp::path!("bolt" / "v1" / "backup" / "restic" / "restore")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: ResticRestoreRequest, rt: Arc<BoltRuntime>| async move {
+                json(
+                    rt.restic_restore(&req.snapshot_id, req.target.as_deref())
+                        .await,
+                )
+            },
+        );
+
+    let list_snapshots = warp::path!("bolt" / "v1" / "snapshots")
+        .and(warp::get())
+        .and(warp::query::<SnapshotsQuery>())
+        .and(rt())
+        .and_then(|q: SnapshotsQuery, rt: Arc<BoltRuntime>| async move {
+            json(rt.list_snapshots(q.kind).await)
+        });
+
+    let create_snapshot = warp::path!("bolt" / "v1" / "snapshots")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: CreateSnapshotRequest, rt: Arc<BoltRuntime>| async move {
+                json(
+                    rt.create_snapshot(req.name.as_deref(), req.description.as_deref(), req.kind)
+                        .await,
+                )
+            },
+        );
+
+    let snapshot = warp::path!("bolt" / "v1" / "snapshots" / String)
+        .and(warp::get())
+        .and(rt())
+        .and_then(|id: String, rt: Arc<BoltRuntime>| async move { json(rt.snapshot(&id).await) });
+
+    let delete_snapshot = warp::path!("bolt" / "v1" / "snapshots" / String)
+        .and(warp::delete())
+        .and(rt())
+        .and_then(
+            |id: String, rt: Arc<BoltRuntime>| async move { json(rt.delete_snapshot(&id).await) },
+        );
+
+    let rollback_snapshot = warp::path!("bolt" / "v1" / "snapshots" / String / "rollback")
+        .and(warp::post())
+        .and(rt())
+        .and_then(|id: String, rt: Arc<BoltRuntime>| async move {
+            json(rt.rollback_snapshot(&id).await)
+        });
+
+    let cleanup_snapshots = warp::path!("bolt" / "v1" / "snapshots" / "cleanup")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(rt())
+        .and_then(
+            |req: CleanupSnapshotsRequest, rt: Arc<BoltRuntime>| async move {
+                json(rt.cleanup_snapshots(req.dry_run).await)
+            },
+        );
+
+    let capabilities = warp::path!("bolt" / "v1" / "capabilities")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.capabilities().await) });
+
+    let doctor = warp::path!("bolt" / "v1" / "doctor")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.doctor().await) });
+
+    let health = warp::path!("bolt" / "v1" / "health")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.health().await) });
+
+    let capacity = warp::path!("bolt" / "v1" / "capacity")
+        .and(warp::get())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.capacity_report().await) });
+
+    let crash_reports = warp::path!("bolt" / "v1" / "crashes")
+        .and(warp::get())
+        .and(warp::query::<CrashesQuery>())
+        .and(rt())
+        .and_then(|q: CrashesQuery, rt: Arc<BoltRuntime>| async move {
+            json(rt.crash_reports(q.container.as_deref()))
+        });
+
+    let crash_report = warp::path!("bolt" / "v1" / "crashes" / String)
+        .and(warp::get())
+        .and(rt())
+        .and_then(|id: String, rt: Arc<BoltRuntime>| async move { json(rt.crash_report(&id)) });
+
+    let reload_config = warp::path!("bolt" / "v1" / "config" / "reload")
+        .and(warp::post())
+        .and(rt())
+        .and_then(|rt: Arc<BoltRuntime>| async move { json(rt.reload_config()) });
+
+    // Boxing each route keeps the combined filter type small
+    [
+        run_container.boxed(),
+        stop_container.boxed(),
+        restart_container.boxed(),
+        remove_container.boxed(),
+        exec_container.boxed(),
+        build_image.boxed(),
+        pull_image.boxed(),
+        ensure_image.boxed(),
+        push_image.boxed(),
+        list_networks.boxed(),
+        create_network.boxed(),
+        remove_network.boxed(),
+        list_volumes.boxed(),
+        create_volume.boxed(),
+        inspect_volume.boxed(),
+        remove_volume.boxed(),
+        prune_volumes.boxed(),
+        surge_status.boxed(),
+        surge_up.boxed(),
+        surge_down.boxed(),
+        surge_plan.boxed(),
+        surge_scale.boxed(),
+        setup_gaming.boxed(),
+        launch_game.boxed(),
+        restic_init.boxed(),
+        restic_backup.boxed(),
+        restic_snapshots.boxed(),
+        restic_restore.boxed(),
+        list_snapshots.boxed(),
+        create_snapshot.boxed(),
+        snapshot.boxed(),
+        delete_snapshot.boxed(),
+        rollback_snapshot.boxed(),
+        cleanup_snapshots.boxed(),
+        capabilities.boxed(),
+        doctor.boxed(),
+        health.boxed(),
+        capacity.boxed(),
+        crash_reports.boxed(),
+        crash_report.boxed(),
+        reload_config.boxed(),
+    ]
+    .into_iter()
+    .fold(list_containers.boxed(), |all, route| {
+        all.or(route).unify().boxed()
+    })
+}
+
+/// `/healthz` for load balancers and uptime monitors: the node's health as
+/// JSON, with 503 when the node is unhealthy. Degraded nodes still answer 200
+/// since they keep serving.
+pub fn healthz(
+    runtime: Arc<BoltRuntime>,
+) -> BoxedFilter<(warp::reply::WithStatus<warp::reply::Json>,)> {
+    warp::path!("healthz")
+        .and(warp::get().or(warp::head()).unify())
+        .and(with_runtime(runtime))
+        .then(|rt: Arc<BoltRuntime>| async move {
+            let health = crate::runtime::health::check(&rt.config()).await;
+            let status = if health.status == crate::runtime::health::HealthStatus::Unhealthy {
+                warp::http::StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                warp::http::StatusCode::OK
+            };
+            warp::reply::with_status(warp::reply::json(&health), status)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BoltConfig;
+    use crate::docker_compat::api_server::DockerAPIServer;
+    use crate::error::ErrorPayload;
+
+    #[tokio::test]
+    async fn serves_runtime_calls_and_error_payloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = BoltConfig::load().unwrap();
+        config.data_dir = dir.path().to_path_buf();
+        config.boltfile_path = dir.path().join("Boltfile.toml");
+        let api = routes(Arc::new(BoltRuntime::with_config(config)))
+            .recover(DockerAPIServer::handle_rejection);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/bolt/v1/crashes?container=web")
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.body().as_ref(), b"[]");
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/bolt/v1/crashes/nope")
+            .reply(&api)
+            .await;
+        assert_eq!(res.body().as_ref(), b"null");
+
+        // No Boltfile in the daemon's directory
+        let res = warp::test::request()
+            .method("POST")
+            .path("/bolt/v1/surge/plan")
+            .json(&SurgeRequest::default())
+            .reply(&api)
+            .await;
+        assert!(res.status().is_server_error() || res.status().is_client_error());
+        let payload: ErrorPayload = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(payload.category.http_status(), res.status().as_u16());
+        assert!(payload.message.contains("Boltfile"));
+    }
+
+    #[tokio::test]
+    async fn replies_decode_into_the_client_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = BoltConfig::load().unwrap();
+        config.data_dir = dir.path().to_path_buf();
+        config.boltfile_path = dir.path().join("Boltfile.toml");
+        let api = routes(Arc::new(BoltRuntime::with_config(config)))
+            .recover(DockerAPIServer::handle_rejection);
+        let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = crate::client::BoltClient::new(format!("http://{}/", addr));
+        assert_eq!(client.base_url(), format!("http://{}", addr));
+        let capabilities = client.capabilities().await.unwrap();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities.features["gaming"], cfg!(feature = "gaming"));
+        client.health().await.unwrap();
+        assert!(client.crash_reports(Some("web")).await.unwrap().is_empty());
+        assert!(client.crash_report("nope").await.unwrap().is_none());
+
+        match client.surge_plan(&[]).await.map_err(crate::BoltError::from) {
+            Err(crate::BoltError::Remote(payload)) => assert!(payload.message.contains("Boltfile")),
+            other => panic!("expected a daemon error, got {:?}", other.map(|p| p.len())),
+        }
+
+        let daily = client
+            .list_snapshots(Some(crate::client::types::SnapshotKind::Daily))
+            .await
+            .unwrap();
+        assert!(daily.is_empty());
+        match client.restic_snapshots().await {
+            Err(crate::client::Error::Remote(payload)) => {
+                assert!(payload.message.contains("[backup.restic]"))
+            }
+            other => panic!("expected a daemon error, got {:?}", other),
+        }
+    }
+}
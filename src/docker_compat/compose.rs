@@ -1,4 +1,7 @@
-use crate::config::{BoltFile, GamingConfig, NetworkConfig, Service, VolumeConfig};
+use crate::config::{
+    BoltFile, GamingConfig, NetworkConfig, Service, ServiceNetworkConfig, ServiceNetworks,
+    VolumeConfig,
+};
 use crate::error::{BoltError, Result};
 use serde::{Deserialize, Serialize};
 use serde_yaml;
@@ -21,6 +24,7 @@ pub struct DockerCompose {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DockerComposeService {
     pub image: Option<String>,
+    pub pull_policy: Option<String>,
     pub build: Option<DockerComposeBuild>,
     pub command: Option<StringOrArray>,
     pub entrypoint: Option<StringOrArray>,
@@ -51,6 +55,7 @@ pub struct DockerComposeService {
     pub ipc: Option<String>,
     pub isolation: Option<String>,
     pub labels: Option<HashMap<String, String>>,
+    pub annotations: Option<HashMap<String, String>>,
     pub logging: Option<DockerComposeLogging>,
     pub network_mode: Option<String>,
     pub pid: Option<String>,
@@ -86,6 +91,8 @@ pub struct DockerComposeService {
     pub blkio_config: Option<DockerComposeBlkioConfig>,
     pub deploy: Option<DockerComposeDeploy>,
     pub healthcheck: Option<DockerComposeHealthcheck>,
+    /// Compose Watch rules; Bolt reads them unchanged
+    pub develop: Option<crate::config::DevelopConfig>,
     #[serde(rename = "x-bolt-gaming")]
     pub bolt_gaming: Option<DockerComposeBoltGaming>,
 }
@@ -474,6 +481,7 @@ impl DockerComposeParser {
             networks,
             volumes,
             snapshots: None,
+            maintenance: None,
         })
     }
 
@@ -483,6 +491,11 @@ impl DockerComposeParser {
 
         // Basic properties
         service.image = docker_service.image;
+        // "build" has no Bolt equivalent; builds are never pulled
+        service.pull_policy = docker_service
+            .pull_policy
+            .as_deref()
+            .and_then(|p| p.parse().ok());
         service.build = docker_service
             .build
             .map(|b| b.context.unwrap_or(".".to_string()));
@@ -491,6 +504,7 @@ impl DockerComposeParser {
         service.working_dir = docker_service.working_dir;
         service.user = docker_service.user;
         service.hostname = docker_service.hostname;
+        service.domainname = docker_service.domainname;
         service.container_name = docker_service.container_name;
         service.restart = docker_service.restart;
         service.privileged = docker_service.privileged;
@@ -502,6 +516,8 @@ impl DockerComposeParser {
         service.ipc = docker_service.ipc;
         service.platform = docker_service.platform;
         service.labels = docker_service.labels;
+        service.annotations = docker_service.annotations;
+        service.develop = docker_service.develop;
 
         // Environment variables
         service.environment = match docker_service.environment {
@@ -572,13 +588,27 @@ impl DockerComposeParser {
 
         // Networks
         service.networks = match docker_service.networks {
-            Some(DockerComposeServiceNetworks::List(list)) => Some(list),
-            Some(DockerComposeServiceNetworks::Map(map)) => Some(map.keys().cloned().collect()),
+            Some(DockerComposeServiceNetworks::List(list)) => Some(ServiceNetworks::List(list)),
+            Some(DockerComposeServiceNetworks::Map(map)) => Some(ServiceNetworks::Map(
+                map.into_iter()
+                    .map(|(name, network)| {
+                        (
+                            name,
+                            ServiceNetworkConfig {
+                                aliases: network.aliases,
+                                ipv4_address: network.ipv4_address,
+                                ipv6_address: network.ipv6_address,
+                            },
+                        )
+                    })
+                    .collect(),
+            )),
             None => None,
         };
 
         // Dependencies
-        service.depends_on = docker_service.depends_on;
+        service.depends_on = docker_service.depends_on.map(Into::into);
+        service.stop_grace_period = docker_service.stop_grace_period;
 
         // Resource limits
         if let Some(cpus) = docker_service.cpus {
@@ -627,6 +657,7 @@ impl DockerComposeParser {
                 audio: None,
                 wine: None,
                 performance: None,
+                display: None,
             });
         }
 
@@ -726,6 +757,8 @@ impl DockerComposeParser {
                 DockerComposeExternal::Named { .. } => true,
             }),
             name: docker_network.name,
+            hardware: None,
+            dns: None,
         }
     }
 
@@ -755,6 +788,7 @@ impl DockerComposeParser {
                 }),
                 labels,
                 name,
+                ..Default::default()
             },
         }
     }
@@ -772,6 +806,7 @@ impl DockerComposeParser {
     pub fn convert_to_compose_service(bolt_service: &Service) -> DockerComposeService {
         DockerComposeService {
             image: bolt_service.image.clone(),
+            pull_policy: bolt_service.pull_policy.map(|p| p.as_str().to_string()),
             build: bolt_service
                 .build
                 .as_ref()
@@ -815,16 +850,34 @@ impl DockerComposeParser {
                     .map(|vol| DockerComposeVolume::String(vol.clone()))
                     .collect()
             }),
-            networks: bolt_service
-                .networks
+            networks: bolt_service.networks.as_ref().map(|nets| match nets {
+                ServiceNetworks::List(list) => DockerComposeServiceNetworks::List(list.clone()),
+                ServiceNetworks::Map(map) => DockerComposeServiceNetworks::Map(
+                    map.iter()
+                        .map(|(name, network)| {
+                            (
+                                name.clone(),
+                                DockerComposeServiceNetwork {
+                                    aliases: network.aliases.clone(),
+                                    ipv4_address: network.ipv4_address.clone(),
+                                    ipv6_address: network.ipv6_address.clone(),
+                                    link_local_ips: None,
+                                    priority: None,
+                                },
+                            )
+                        })
+                        .collect(),
+                ),
+            }),
+            depends_on: bolt_service
+                .depends_on
                 .as_ref()
-                .map(|nets| DockerComposeServiceNetworks::List(nets.clone())),
-            depends_on: bolt_service.depends_on.clone(),
+                .map(|deps| deps.names().cloned().collect()),
             external_links: None,
             restart: bolt_service.restart.clone(),
             container_name: bolt_service.container_name.clone(),
             hostname: bolt_service.hostname.clone(),
-            domainname: None,
+            domainname: bolt_service.domainname.clone(),
             user: bolt_service.user.clone(),
             working_dir: bolt_service.working_dir.clone(),
             cap_add: bolt_service.cap_add.clone(),
@@ -846,6 +899,7 @@ impl DockerComposeParser {
             ipc: bolt_service.ipc.clone(),
             isolation: None,
             labels: bolt_service.labels.clone(),
+            annotations: bolt_service.annotations.clone(),
             logging: bolt_service
                 .logging
                 .as_ref()
@@ -861,7 +915,7 @@ impl DockerComposeParser {
             security_opt: bolt_service.security_opt.clone(),
             shm_size: None,
             stdin_open: bolt_service.stdin_open,
-            stop_grace_period: None,
+            stop_grace_period: bolt_service.stop_grace_period.clone(),
             stop_signal: None,
             sysctls: bolt_service.sysctls.clone(),
             tty: bolt_service.tty,
@@ -897,6 +951,7 @@ impl DockerComposeParser {
                     start_period: hc.start_period.clone(),
                     disable: Some(hc.disable),
                 }),
+            develop: bolt_service.develop.clone(),
             bolt_gaming: bolt_service
                 .gaming
                 .as_ref()
@@ -549,7 +549,10 @@ impl MigrationHelper {
     }
 
     /// Import Docker volumes to Bolt
-    pub async fn import_docker_volumes(&self) -> Result<Vec<String>> {
+    pub async fn import_docker_volumes(
+        &self,
+        volume_manager: &crate::volume::VolumeManager,
+    ) -> Result<Vec<String>> {
         info!("💾 Importing Docker volumes to Bolt");
 
         let mut imported_volumes = Vec::new();
@@ -576,7 +579,6 @@ impl MigrationHelper {
                 info!("  • Importing volume: {}", volume_name);
 
                 // Create equivalent Bolt volume
-                let mut volume_manager = crate::volume::VolumeManager::new()?;
                 let options = crate::volume::VolumeCreateOptions::default();
 
                 match volume_manager.create_volume(volume_name, options) {
@@ -596,7 +598,10 @@ impl MigrationHelper {
     }
 
     /// Import Docker networks to Bolt
-    pub async fn import_docker_networks(&self) -> Result<Vec<String>> {
+    pub async fn import_docker_networks(
+        &self,
+        network_manager: &crate::networking::NetworkManager,
+    ) -> Result<Vec<String>> {
         info!("🌐 Importing Docker networks to Bolt");
 
         let mut imported_networks = Vec::new();
@@ -634,11 +639,6 @@ impl MigrationHelper {
                 );
 
                 // Create equivalent Bolt network
-                let mut network_manager = crate::networking::NetworkManager::new(
-                    crate::networking::NetworkConfig::default(),
-                )
-                .await?;
-
                 // Map Docker drivers to Bolt drivers
                 let bolt_driver = match driver {
                     "bridge" => "bridge",
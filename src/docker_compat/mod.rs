@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use tracing::{info, warn};
 
 pub mod api_server;
+pub mod bolt_api;
 pub mod compose;
 pub mod migration;
 
@@ -15,6 +16,8 @@ pub struct DockerCompatLayer {
     pub enable_legacy_support: bool,
     pub migration_helper: migration::MigrationHelper,
     pub compose_parser: compose::DockerComposeParser,
+    /// Shared with the API server, so its managers see the same state
+    runtime: crate::BoltRuntime,
 }
 
 impl DockerCompatLayer {
@@ -28,6 +31,7 @@ impl DockerCompatLayer {
             enable_legacy_support: true,
             migration_helper: migration::MigrationHelper::new().await?,
             compose_parser: compose::DockerComposeParser,
+            runtime: crate::BoltRuntime::new()?,
         })
     }
 
@@ -43,7 +47,7 @@ impl DockerCompatLayer {
         }
 
         // Start API server
-        let runtime = std::sync::Arc::new(crate::BoltRuntime::new()?);
+        let runtime = std::sync::Arc::new(self.runtime.clone());
         let api_server =
             api_server::DockerAPIServer::new(runtime).with_address("0.0.0.0".to_string(), 2375);
         api_server.start().await?;
@@ -101,14 +105,16 @@ impl DockerCompatLayer {
     pub async fn import_docker_volumes(&self) -> Result<Vec<String>> {
         info!("💾 Importing Docker volumes to Bolt");
 
-        self.migration_helper.import_docker_volumes().await
+        let volumes = self.runtime.volumes().await?;
+        self.migration_helper.import_docker_volumes(volumes).await
     }
 
     /// Import Docker networks to Bolt
     pub async fn import_docker_networks(&self) -> Result<Vec<String>> {
         info!("🌐 Importing Docker networks to Bolt");
 
-        self.migration_helper.import_docker_networks().await
+        let networks = self.runtime.networks().await?;
+        self.migration_helper.import_docker_networks(networks).await
     }
 
     /// Run Docker command compatibility
@@ -153,16 +159,17 @@ impl DockerCompatLayer {
 
         // Execute with bolt runtime
         let bolt_args: Vec<&str> = bolt_command.split_whitespace().collect();
-        crate::runtime::run_container(
-            &docker_run.image,
-            docker_run.name.as_deref(),
-            &docker_run.ports,
-            &docker_run.env,
-            &docker_run.volumes,
-            docker_run.detach,
-        )
-        .await
-        .map_err(|e| anyhow::anyhow!("Runtime error: {}", e))
+        self.runtime
+            .run_container(
+                &docker_run.image,
+                docker_run.name.as_deref(),
+                &docker_run.ports,
+                &docker_run.env,
+                &docker_run.volumes,
+                docker_run.detach,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("Runtime error: {}", e))
     }
 
     /// Handle docker build command
@@ -192,7 +199,8 @@ impl DockerCompatLayer {
         let image = &args[0];
         info!("⬇️ Converting docker pull to bolt pull: {}", image);
 
-        crate::runtime::pull_image(image)
+        let choice = crate::runtime::platform::PlatformChoice::default();
+        crate::runtime::pull_image(image, &choice)
             .await
             .map_err(|e| anyhow::anyhow!("Pull error: {}", e))
     }
@@ -298,44 +306,27 @@ impl DockerCompatLayer {
 
     /// Handle docker exec command
     async fn handle_docker_exec(&self, args: Vec<String>) -> Result<()> {
-        if args.len() < 2 {
-            return Err(anyhow::anyhow!("Invalid docker exec command"));
-        }
+        let (container, command, options) = parse_exec_args(&args)?;
 
         info!("🎮 Converting docker exec to bolt exec");
-
-        // Parse docker exec flags
-        let mut container = "";
-        let mut command = Vec::new();
-        let mut interactive = false;
-        let mut tty = false;
-
-        let mut i = 0;
-        while i < args.len() {
-            match args[i].as_str() {
-                "-i" | "--interactive" => interactive = true,
-                "-t" | "--tty" => tty = true,
-                "-it" | "-ti" => {
-                    interactive = true;
-                    tty = true;
-                }
-                _ => {
-                    if container.is_empty() {
-                        container = &args[i];
-                    } else {
-                        command.push(&args[i]);
-                    }
-                }
-            }
-            i += 1;
-        }
-
         info!("  Container: {}", container);
         info!("  Command: {:?}", command);
-        info!("  Interactive: {}, TTY: {}", interactive, tty);
+        info!(
+            "  Interactive: {}, TTY: {}",
+            options.interactive, options.tty
+        );
 
-        // Execute with bolt
-        // crate::runtime::exec_container(container, command, interactive, tty).await
+        let code = self
+            .runtime
+            .exec_container_with(&container, &command, &options)
+            .await?;
+        if code != 0 {
+            return Err(anyhow::anyhow!(
+                "Command in {} exited with status {}",
+                container,
+                code
+            ));
+        }
 
         Ok(())
     }
@@ -392,15 +383,16 @@ impl DockerCompatLayer {
                     volume_name
                 );
 
-                let mut volume_manager = crate::volume::VolumeManager::new()?;
                 let options = crate::volume::VolumeCreateOptions::default();
-                volume_manager.create_volume(volume_name, options)?;
+                self.runtime
+                    .volumes()
+                    .await?
+                    .create_volume(volume_name, options)?;
             }
             "ls" => {
                 info!("📋 Converting docker volume ls to bolt volume ls");
 
-                let volume_manager = crate::volume::VolumeManager::new()?;
-                let volumes = volume_manager.list_volumes();
+                let volumes = self.runtime.volumes().await?.list_volumes();
 
                 println!("DRIVER    VOLUME NAME");
                 for volume in volumes {
@@ -418,8 +410,10 @@ impl DockerCompatLayer {
                     volume_name
                 );
 
-                let mut volume_manager = crate::volume::VolumeManager::new()?;
-                volume_manager.remove_volume(volume_name, false)?;
+                self.runtime
+                    .volumes()
+                    .await?
+                    .remove_volume(volume_name, false)?;
             }
             _ => {
                 warn!("Unsupported docker volume subcommand: {}", args[0]);
@@ -445,22 +439,21 @@ impl DockerCompatLayer {
                     network_name
                 );
 
-                let mut network_manager = crate::networking::NetworkManager::new(
-                    crate::networking::NetworkConfig::default(),
-                )
-                .await?;
-                network_manager
+                self.runtime
+                    .networks()
+                    .await?
                     .create_bolt_network(network_name, "bridge", None)
                     .await?;
             }
             "ls" => {
                 info!("📋 Converting docker network ls to bolt network ls");
 
-                let network_manager = crate::networking::NetworkManager::new(
-                    crate::networking::NetworkConfig::default(),
-                )
-                .await?;
-                let networks = network_manager.list_bolt_networks().await?;
+                let networks = self
+                    .runtime
+                    .networks()
+                    .await?
+                    .list_bolt_networks()
+                    .await?;
 
                 println!("NETWORK ID     NAME      DRIVER    SCOPE");
                 for network in networks {
@@ -502,7 +495,7 @@ impl DockerCompatLayer {
                     args.contains(&"-d".to_string()) || args.contains(&"--detach".to_string());
 
                 let config = crate::config::BoltConfig::load()?;
-                crate::surge::up(&config, &services, detach, false).await?;
+                crate::surge::up(&config, &services, detach, false, None).await?;
             }
             "down" => {
                 info!("⬇️ Converting docker compose down to bolt surge down");
@@ -788,3 +781,71 @@ pub struct DockerEnvironmentAnalysis {
     pub compatibility_issues: Vec<String>,
     pub migration_recommendations: Vec<String>,
 }
+
+/// Split `docker exec` arguments into the container, the command and the
+/// options. Flags are only read up to the container name; everything after it
+/// belongs to the command.
+fn parse_exec_args(args: &[String]) -> Result<(String, Vec<String>, crate::runtime::ExecOptions)> {
+    let mut options = crate::runtime::ExecOptions::default();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let mut value = |flag: &str| {
+            rest.next()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Flag {} needs a value", flag))
+        };
+        match arg.as_str() {
+            "-i" | "--interactive" => options.interactive = true,
+            "-t" | "--tty" => options.tty = true,
+            "-it" | "-ti" => {
+                options.interactive = true;
+                options.tty = true;
+            }
+            "-d" | "--detach" => {
+                return Err(anyhow::anyhow!("docker exec --detach is not supported"));
+            }
+            "-e" | "--env" => options.env.push(value(arg)?),
+            "-w" | "--workdir" => options.workdir = Some(value(arg)?),
+            "-u" | "--user" => options.user = Some(value(arg)?),
+            flag if flag.starts_with("--env=") => options.env.push(flag[6..].to_string()),
+            flag if flag.starts_with("--workdir=") => options.workdir = Some(flag[10..].to_string()),
+            flag if flag.starts_with("--user=") => options.user = Some(flag[7..].to_string()),
+            flag if flag.starts_with('-') => {
+                warn!("Ignoring unsupported docker exec flag: {}", flag);
+            }
+            container => {
+                let command: Vec<String> = rest.cloned().collect();
+                if command.is_empty() {
+                    return Err(anyhow::anyhow!("No command given for docker exec"));
+                }
+                return Ok((container.to_string(), command, options));
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!("Invalid docker exec command: no container given"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_flags_stop_at_the_container() {
+        let args: Vec<String> = ["-it", "-e", "MODE=debug", "--user=app", "web", "ls", "-la"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (container, command, options) = parse_exec_args(&args).unwrap();
+        assert_eq!(container, "web");
+        assert_eq!(command, vec!["ls", "-la"]);
+        assert!(options.interactive && options.tty);
+        assert_eq!(options.env, vec!["MODE=debug"]);
+        assert_eq!(options.user.as_deref(), Some("app"));
+        assert_eq!(options.workdir, None);
+
+        assert!(parse_exec_args(&["web".to_string()]).is_err());
+        assert!(parse_exec_args(&["-e".to_string()]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+pub use bolt_client::{ErrorCategory, ErrorCode, ErrorPayload};
+
 /// Bolt-specific error types for better error handling
 #[derive(Error, Debug)]
 pub enum BoltError {
@@ -29,6 +31,10 @@ pub enum BoltError {
 
     #[error("Generic error: {0}")]
     Other(#[from] anyhow::Error),
+
+    /// An error returned by a remote Bolt daemon
+    #[error("Daemon error: {}", .0.message)]
+    Remote(ErrorPayload),
 }
 
 #[derive(Error, Debug)]
@@ -56,6 +62,111 @@ pub enum RuntimeError {
 
     #[error("OCI runtime error: {message}")]
     OciError { message: String },
+
+    #[error("Image not found: {image}")]
+    ImageNotFound { image: String },
+
+    #[error("Port already in use: {port}")]
+    PortConflict { port: String },
+
+    #[error("GPU unavailable: {reason}")]
+    GpuUnavailable { reason: String },
+
+    #[error("Quota exceeded for {resource}: {reason}")]
+    QuotaExceeded { resource: String, reason: String },
+
+    #[error("No container runtime available: {reason}")]
+    RuntimeUnavailable { reason: String },
+
+    #[error(
+        "Cannot decrypt image {image}: none of the keys under [encryption] decryption_keys in config.toml can decrypt its layers (store the private key with `bolt secret set` and reference it there)"
+    )]
+    ImageDecryptionFailed { image: String },
+
+    #[error(
+        "Image {image} violates the trust policy:\n{violations}\n(an admin can let it through with --override-trust)"
+    )]
+    UntrustedImage { image: String, violations: String },
+
+    #[error("Image {image} can't run on this host: {reason}\n{hint}")]
+    PlatformMismatch {
+        image: String,
+        reason: String,
+        hint: String,
+    },
+}
+
+impl RuntimeError {
+    /// Map podman/docker stderr onto a typed error, falling back to `fallback`
+    /// when the message isn't recognised
+    pub fn classify(subject: &str, stderr: &str, fallback: RuntimeError) -> RuntimeError {
+        let lower = stderr.to_lowercase();
+        let reason = stderr.trim().to_string();
+
+        if lower.contains("address already in use") || lower.contains("port is already allocated") {
+            RuntimeError::PortConflict {
+                port: extract_port(&lower).unwrap_or_else(|| subject.to_string()),
+            }
+        } else if lower.contains("decrypt") || lower.contains("+encrypted") {
+            RuntimeError::ImageDecryptionFailed {
+                image: subject.to_string(),
+            }
+        } else if lower.contains("manifest unknown")
+            || lower.contains("image not known")
+            || lower.contains("no such image")
+            || lower.contains("repository does not exist")
+            || (lower.contains("pull access denied") && lower.contains("does not exist"))
+        {
+            RuntimeError::ImageNotFound {
+                image: subject.to_string(),
+            }
+        } else if lower.contains("no matching manifest for")
+            || lower.contains("does not match the specified platform")
+            || lower.contains("exec format error")
+        {
+            RuntimeError::PlatformMismatch {
+                image: subject.to_string(),
+                reason,
+                hint: "Check the platforms the image offers and pick one with --platform \
+                       (or `platform` in the Boltfile)"
+                    .to_string(),
+            }
+        } else if lower.contains("no such container")
+            || lower.contains("no container with name or id")
+        {
+            RuntimeError::ContainerNotFound {
+                name: subject.to_string(),
+            }
+        } else if lower.contains("could not select device driver")
+            || lower.contains("nvidia-container-cli")
+            || lower.contains("/dev/dri")
+            || lower.contains("cdi device")
+        {
+            RuntimeError::GpuUnavailable { reason }
+        } else if lower.contains("no space left on device")
+            || lower.contains("disk quota exceeded")
+            || lower.contains("toomanyrequests")
+            || lower.contains("rate limit")
+        {
+            RuntimeError::QuotaExceeded {
+                resource: subject.to_string(),
+                reason,
+            }
+        } else {
+            fallback
+        }
+    }
+}
+
+fn extract_port(stderr: &str) -> Option<String> {
+    // "listen tcp4 0.0.0.0:8080: bind: address already in use"
+    // "Bind for 0.0.0.0:8080 failed: port is already allocated"
+    stderr
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|token| token.trim_end_matches(':').rsplit_once(':'))
+        .map(|(_, port)| port)
+        .find(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()))
+        .map(|port| port.to_string())
 }
 
 #[derive(Error, Debug)]
@@ -85,5 +196,130 @@ pub enum GamingError {
     OptimizationFailed { reason: String },
 }
 
+impl BoltError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            BoltError::Config(ConfigError::BoltfileNotFound { .. }) => ErrorCode::BoltfileNotFound,
+            BoltError::Config(_) => ErrorCode::InvalidConfig,
+            BoltError::Runtime(e) => match e {
+                RuntimeError::ContainerNotFound { .. } => ErrorCode::ContainerNotFound,
+                RuntimeError::ImagePullFailed { .. } => ErrorCode::ImagePullFailed,
+                RuntimeError::StartFailed { .. } => ErrorCode::StartFailed,
+                RuntimeError::OciError { .. } => ErrorCode::RuntimeFailure,
+                RuntimeError::ImageNotFound { .. } => ErrorCode::ImageNotFound,
+                RuntimeError::PortConflict { .. } => ErrorCode::PortConflict,
+                RuntimeError::GpuUnavailable { .. } => ErrorCode::GpuUnavailable,
+                RuntimeError::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+                RuntimeError::RuntimeUnavailable { .. } => ErrorCode::RuntimeUnavailable,
+                RuntimeError::ImageDecryptionFailed { .. } => ErrorCode::ImageDecryptionFailed,
+                RuntimeError::UntrustedImage { .. } => ErrorCode::ImageUntrusted,
+                RuntimeError::PlatformMismatch { .. } => ErrorCode::ImagePlatformMismatch,
+            },
+            BoltError::Network(e) => match e {
+                NetworkError::NetworkNotFound { .. } => ErrorCode::NetworkNotFound,
+                NetworkError::InvalidSubnet { .. } => ErrorCode::InvalidSubnet,
+                NetworkError::QuicSetupFailed { .. } => ErrorCode::NetworkSetupFailed,
+            },
+            BoltError::Gaming(e) => match e {
+                GamingError::GpuNotFound => ErrorCode::GpuUnavailable,
+                GamingError::AudioUnavailable { .. } => ErrorCode::AudioUnavailable,
+                GamingError::WineSetupFailed { .. } | GamingError::OptimizationFailed { .. } => {
+                    ErrorCode::GamingSetupFailed
+                }
+            },
+            BoltError::Io(_) => ErrorCode::Io,
+            BoltError::Serialization(_) | BoltError::Json(_) | BoltError::Yaml(_) => {
+                ErrorCode::Serialization
+            }
+            // anyhow errors that wrap a typed error keep its code
+            BoltError::Other(e) => e
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<BoltError>())
+                .map(|inner| inner.code())
+                .unwrap_or(ErrorCode::Internal),
+            BoltError::Remote(payload) => payload.code,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.code().category()
+    }
+
+    pub fn http_status(&self) -> u16 {
+        self.category().http_status()
+    }
+
+    pub fn to_payload(&self) -> ErrorPayload {
+        let causes = match self {
+            BoltError::Other(e) => e.chain().skip(1).map(|c| c.to_string()).collect(),
+            BoltError::Remote(payload) => return payload.clone(),
+            _ => Vec::new(),
+        };
+
+        ErrorPayload {
+            code: self.code(),
+            category: self.category(),
+            message: self.to_string(),
+            causes,
+        }
+    }
+}
+
+impl From<bolt_client::Error> for BoltError {
+    fn from(error: bolt_client::Error) -> Self {
+        match error {
+            bolt_client::Error::Remote(payload) => BoltError::Remote(payload),
+            other => BoltError::Other(other.into()),
+        }
+    }
+}
+
 /// Convenience type alias for Bolt results
 pub type Result<T, E = BoltError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_runtime_stderr() {
+        let fallback = || RuntimeError::OciError {
+            message: "failed".to_string(),
+        };
+
+        let err = RuntimeError::classify(
+            "web",
+            "Error: Bind for 0.0.0.0:8080 failed: port is already allocated",
+            fallback(),
+        );
+        assert!(matches!(err, RuntimeError::PortConflict { ref port } if port == "8080"));
+
+        let err = RuntimeError::classify(
+            "ghcr.io/acme/missing:1.0",
+            "Error: initializing source: reading manifest 1.0: manifest unknown",
+            fallback(),
+        );
+        assert_eq!(BoltError::from(err).code(), ErrorCode::ImageNotFound);
+
+        let err = RuntimeError::classify("web", "something odd happened", fallback());
+        assert!(matches!(err, RuntimeError::OciError { .. }));
+    }
+
+    #[test]
+    fn test_anyhow_wrapped_error_keeps_code() {
+        let typed = BoltError::Runtime(RuntimeError::QuotaExceeded {
+            resource: "storage".to_string(),
+            reason: "no space left on device".to_string(),
+        });
+        let wrapped = BoltError::Other(anyhow::Error::new(typed).context("pulling layers"));
+
+        let payload = wrapped.to_payload();
+        assert_eq!(payload.code, ErrorCode::QuotaExceeded);
+        assert_eq!(payload.category, ErrorCategory::ResourceExhausted);
+        assert_eq!(wrapped.http_status(), 429);
+
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["code"], "QUOTA_EXCEEDED");
+        assert_eq!(json["category"], "resource_exhausted");
+    }
+}
@@ -0,0 +1,470 @@
+// GPU runtime benchmark
+//
+// `bolt gaming gpu benchmark` renders a short vkmark (Vulkan) or glmark2 (GL
+// ES on DRM) workload off-screen in a container, once with the GPUs passed in
+// the standard way (CDI, the container toolkit or device nodes) and once
+// through nvbind when it's installed, and compares the two:
+//
+// - frame time: mean over the scenes and the slowest scene, with mean FPS
+// - startup: wall time of the run minus the time spent rendering, i.e. what
+//   container start and GPU setup cost before the first frame
+// - GPU utilization: sampled every half second while the workload runs
+//
+// The benchmark image is built locally the first time, unless `--image` names
+// one that has vkmark and glmark2-es2-drm on its PATH.
+use crate::runtime::gpu::GPUVendor;
+use crate::runtime::gpu::intel;
+use crate::runtime::gpu::nvbind::NvbindManager;
+use crate::runtime::gpu::plugin::{self, Device};
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+pub const DEFAULT_IMAGE: &str = "localhost/bolt-gpu-bench:latest";
+pub const DEFAULT_DURATION_SECS: u64 = 5;
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+const CONTAINERFILE: &str = "FROM docker.io/library/debian:bookworm-slim
+RUN apt-get update \\
+ && apt-get install -y --no-install-recommends vkmark glmark2-es2-drm \\
+    mesa-vulkan-drivers libgl1-mesa-dri libegl1 \\
+ && rm -rf /var/lib/apt/lists/*
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Workload {
+    Vulkan,
+    Gl,
+}
+
+impl Workload {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Workload::Vulkan => "vulkan",
+            Workload::Gl => "gl",
+        }
+    }
+
+    fn scenes(&self) -> &'static [&'static str] {
+        match self {
+            Workload::Vulkan => &["vertex", "texture", "shading", "cube"],
+            Workload::Gl => &["build", "texture", "shading", "jellyfish"],
+        }
+    }
+
+    /// The benchmark command, every scene running for `duration`
+    fn command(&self, duration: u64) -> Vec<String> {
+        let mut command: Vec<String> = match self {
+            Workload::Vulkan => vec!["vkmark".into(), "--winsys".into(), "headless".into()],
+            Workload::Gl => vec!["glmark2-es2-drm".into(), "--off-screen".into()],
+        };
+        for scene in self.scenes() {
+            command.extend(["-b".to_string(), format!("{}:duration={}", scene, duration)]);
+        }
+        command
+    }
+}
+
+impl std::str::FromStr for Workload {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "vulkan" | "vkmark" => Ok(Workload::Vulkan),
+            "gl" | "glmark2" => Ok(Workload::Gl),
+            _ => Err(anyhow!("Unknown workload '{}': use vulkan or gl", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkOptions {
+    pub workload: Workload,
+    /// Seconds each scene renders for
+    pub duration_secs: u64,
+    pub image: String,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            workload: Workload::Vulkan,
+            duration_secs: DEFAULT_DURATION_SECS,
+            image: DEFAULT_IMAGE.to_string(),
+        }
+    }
+}
+
+/// One scene's result line
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Scene {
+    pub name: String,
+    pub fps: f64,
+    pub frame_time_ms: f64,
+}
+
+/// The workload run once under one runtime
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Run {
+    pub runtime: String,
+    /// Why this runtime wasn't benchmarked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    pub scenes: Vec<Scene>,
+    pub score: Option<u32>,
+    pub mean_fps: f64,
+    pub mean_frame_time_ms: f64,
+    pub worst_frame_time_ms: f64,
+    pub startup_ms: f64,
+    pub wall_ms: f64,
+    pub gpu_utilization_percent: Option<f64>,
+}
+
+impl Run {
+    fn skipped(runtime: &str, reason: impl Into<String>) -> Self {
+        Self {
+            runtime: runtime.to_string(),
+            skipped: Some(reason.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn ran(&self) -> bool {
+        self.skipped.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub workload: Workload,
+    pub image: String,
+    pub container_runtime: String,
+    pub duration_secs: u64,
+    pub gpus: Vec<String>,
+    pub runs: Vec<Run>,
+}
+
+/// nvbind relative to the standard runtime: FPS and frame time change in
+/// percent, startup difference in milliseconds
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Comparison {
+    pub fps_percent: f64,
+    pub frame_time_percent: f64,
+    pub startup_ms: f64,
+}
+
+impl Report {
+    pub fn comparison(&self) -> Option<Comparison> {
+        let standard = self
+            .runs
+            .iter()
+            .find(|r| r.runtime == "standard" && r.ran())?;
+        let nvbind = self
+            .runs
+            .iter()
+            .find(|r| r.runtime == "nvbind" && r.ran())?;
+        if standard.mean_fps <= 0.0 || standard.mean_frame_time_ms <= 0.0 {
+            return None;
+        }
+        Some(Comparison {
+            fps_percent: 100.0 * (nvbind.mean_fps / standard.mean_fps - 1.0),
+            frame_time_percent: 100.0
+                * (nvbind.mean_frame_time_ms / standard.mean_frame_time_ms - 1.0),
+            startup_ms: nvbind.startup_ms - standard.startup_ms,
+        })
+    }
+}
+
+/// Scene results and the overall score from vkmark or glmark2 output, whose
+/// result lines look like `[texture] anisotropy=0: FPS: 2412 FrameTime: 0.415 ms`
+pub fn parse_output(output: &str) -> (Vec<Scene>, Option<u32>) {
+    let mut scenes = Vec::new();
+    let mut score = None;
+    for line in output.lines().map(str::trim) {
+        if line.contains("Score:") {
+            score = line.split_whitespace().last().and_then(|s| s.parse().ok());
+            continue;
+        }
+        let Some(rest) = line.strip_prefix('[') else {
+            continue;
+        };
+        let Some((name, rest)) = rest.split_once(']') else {
+            continue;
+        };
+        let value = |key: &str| -> Option<f64> {
+            let (_, after) = rest.split_once(key)?;
+            after.split_whitespace().next()?.parse().ok()
+        };
+        if let (Some(fps), Some(frame_time_ms)) = (value("FPS:"), value("FrameTime:")) {
+            scenes.push(Scene {
+                name: name.to_string(),
+                fps,
+                frame_time_ms,
+            });
+        }
+    }
+    (scenes, score)
+}
+
+fn summarize(run: &mut Run) {
+    let count = run.scenes.len() as f64;
+    if count == 0.0 {
+        return;
+    }
+    run.mean_fps = run.scenes.iter().map(|s| s.fps).sum::<f64>() / count;
+    run.mean_frame_time_ms = run.scenes.iter().map(|s| s.frame_time_ms).sum::<f64>() / count;
+    run.worst_frame_time_ms = run
+        .scenes
+        .iter()
+        .map(|s| s.frame_time_ms)
+        .fold(0.0, f64::max);
+}
+
+/// Build the default benchmark image unless it's already there
+async fn ensure_image(runtime: &str, image: &str) -> Result<()> {
+    let present = AsyncCommand::new(runtime)
+        .args(["image", "inspect", image])
+        .output()
+        .await
+        .is_ok_and(|o| o.status.success());
+    if present {
+        return Ok(());
+    }
+    if image != DEFAULT_IMAGE {
+        return Err(anyhow!(
+            "Image {} not found; pull it or leave out --image",
+            image
+        ));
+    }
+    info!("Building benchmark image {}", image);
+    let context = tempfile::tempdir()?;
+    std::fs::write(context.path().join("Containerfile"), CONTAINERFILE)?;
+    let output = AsyncCommand::new(runtime)
+        .args(["build", "-t", image, "-f"])
+        .arg(context.path().join("Containerfile"))
+        .arg(context.path())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} build", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to build {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn read_percent(path: &Path) -> Option<f64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+async fn nvidia_utilization(device: &Device) -> Option<f64> {
+    let output = AsyncCommand::new("nvidia-smi")
+        .args([
+            "-i",
+            &device.pci_bus_id,
+            "--query-gpu=utilization.gpu",
+            "--format=csv,noheader,nounits",
+        ])
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Mean utilization of `devices` over one sample interval
+async fn sample(devices: &[Device]) -> Option<f64> {
+    let intel_samples = if devices.iter().any(|d| d.vendor == GPUVendor::Intel) {
+        intel::sample(SAMPLE_INTERVAL).await
+    } else {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        Vec::new()
+    };
+    let mut readings = Vec::new();
+    for device in devices {
+        let reading = match device.vendor {
+            GPUVendor::NVIDIA => nvidia_utilization(device).await,
+            GPUVendor::AMD => read_percent(
+                &Path::new("/sys/bus/pci/devices")
+                    .join(&device.pci_bus_id)
+                    .join("gpu_busy_percent"),
+            ),
+            GPUVendor::Intel => intel_samples
+                .iter()
+                .find(|m| m.pci_bus_id == device.pci_bus_id)
+                .and_then(|m| m.busy_percent),
+        };
+        readings.extend(reading);
+    }
+    (!readings.is_empty()).then(|| readings.iter().sum::<f64>() / readings.len() as f64)
+}
+
+/// Run `program args`, sampling the utilization of `devices` until it exits
+async fn measure(
+    runtime: &str,
+    program: &str,
+    args: &[String],
+    devices: &[Device],
+    options: &BenchmarkOptions,
+) -> Result<Run> {
+    let done = Arc::new(AtomicBool::new(false));
+    let sampler = {
+        let done = done.clone();
+        let devices = devices.to_vec();
+        tokio::spawn(async move {
+            let mut samples = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                samples.extend(sample(&devices).await);
+            }
+            samples
+        })
+    };
+
+    let started = Instant::now();
+    let output = AsyncCommand::new(program).args(args).output().await;
+    let wall_ms = started.elapsed().as_secs_f64() * 1000.0;
+    done.store(true, Ordering::Relaxed);
+    let samples = sampler.await.unwrap_or_default();
+
+    let output = output.with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let (scenes, score) = parse_output(&String::from_utf8_lossy(&output.stdout));
+    if scenes.is_empty() {
+        return Err(anyhow!("The benchmark printed no results"));
+    }
+    let rendering_ms = (scenes.len() as u64 * options.duration_secs * 1000) as f64;
+    let mut run = Run {
+        runtime: runtime.to_string(),
+        scenes,
+        score,
+        startup_ms: (wall_ms - rendering_ms).max(0.0),
+        wall_ms,
+        gpu_utilization_percent: (!samples.is_empty())
+            .then(|| samples.iter().sum::<f64>() / samples.len() as f64),
+        ..Default::default()
+    };
+    summarize(&mut run);
+    Ok(run)
+}
+
+/// Benchmark the workload under the standard runtime and under nvbind
+pub async fn run(options: &BenchmarkOptions) -> Result<Report> {
+    let container_runtime = crate::runtime::detect_container_runtime().await?;
+    let devices = plugin::discover();
+    if devices.is_empty() {
+        return Err(anyhow!("No GPUs found on this host"));
+    }
+    ensure_image(&container_runtime, &options.image).await?;
+    let command = options.workload.command(options.duration_secs);
+
+    let mut report = Report {
+        workload: options.workload,
+        image: options.image.clone(),
+        container_runtime: container_runtime.clone(),
+        duration_secs: options.duration_secs,
+        gpus: devices.iter().map(|d| d.pci_bus_id.clone()).collect(),
+        runs: Vec::new(),
+    };
+
+    info!(
+        "Running {} benchmark with standard GPU passthrough",
+        options.workload.as_str()
+    );
+    let mut args = vec!["run".to_string(), "--rm".to_string()];
+    args.extend(plugin::device_args(&container_runtime, &devices));
+    args.push(options.image.clone());
+    args.extend(command.iter().cloned());
+    report.runs.push(
+        match measure("standard", &container_runtime, &args, &devices, options).await {
+            Ok(run) => run,
+            Err(e) => {
+                warn!("Standard run failed: {}", e);
+                Run::skipped("standard", e.to_string())
+            }
+        },
+    );
+
+    let nvidia: Vec<Device> = devices
+        .iter()
+        .filter(|d| d.vendor == GPUVendor::NVIDIA)
+        .cloned()
+        .collect();
+    let nvbind = NvbindManager::detect().is_ok_and(|n| n.is_available);
+    report.runs.push(if !nvbind {
+        Run::skipped("nvbind", "nvbind is not installed")
+    } else if nvidia.is_empty() {
+        Run::skipped("nvbind", "nvbind only supports NVIDIA GPUs")
+    } else {
+        info!(
+            "Running {} benchmark through nvbind",
+            options.workload.as_str()
+        );
+        let mut args = vec![
+            "run".to_string(),
+            "--runtime".to_string(),
+            container_runtime.clone(),
+            "--gpu".to_string(),
+            "all".to_string(),
+            options.image.clone(),
+        ];
+        args.extend(command.iter().cloned());
+        match measure("nvbind", "nvbind", &args, &nvidia, options).await {
+            Ok(run) => run,
+            Err(e) => {
+                warn!("nvbind run failed: {}", e);
+                Run::skipped("nvbind", e.to_string())
+            }
+        }
+    });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vkmark_and_glmark2_results() {
+        let vkmark = "\
+=======================================================
+    vkmark 2017.08
+=======================================================
+[vertex] device-local=true: FPS: 3120 FrameTime: 0.321 ms
+[texture] anisotropy=0: FPS: 2412 FrameTime: 0.415 ms
+=======================================================
+                                   vkmark Score: 2766
+=======================================================
+";
+        let (scenes, score) = parse_output(vkmark);
+        assert_eq!(score, Some(2766));
+        assert_eq!(scenes.len(), 2);
+        assert_eq!(scenes[1].name, "texture");
+        assert_eq!(scenes[1].frame_time_ms, 0.415);
+
+        let mut run = Run {
+            scenes,
+            ..Default::default()
+        };
+        summarize(&mut run);
+        assert_eq!(run.mean_fps, 2766.0);
+        assert_eq!(run.worst_frame_time_ms, 0.415);
+
+        let (scenes, score) = parse_output("[build] use-vbo=false: FPS: 980 FrameTime: 1.020 ms\n");
+        assert_eq!(scenes[0].fps, 980.0);
+        assert_eq!(score, None);
+    }
+}
@@ -0,0 +1,643 @@
+// Display server passthrough
+//
+// A service with `[services.<name>.gaming.display]` draws on the host's
+// display. Only the sockets are mounted, never all of /tmp/.X11-unix or the
+// runtime directory:
+//
+// - Wayland: the socket `WAYLAND_DISPLAY` names in `XDG_RUNTIME_DIR`, mounted
+//   as `/run/bolt/display/wayland-0`, with XDG_RUNTIME_DIR and WAYLAND_DISPLAY
+//   pointing at it
+// - X11: the socket of `DISPLAY` (`/tmp/.X11-unix/X<n>`) and DISPLAY itself.
+//   Xauthority cookies are bound to the host name, which the container
+//   doesn't share, so the display's cookie is copied into
+//   `<data_dir>/display/<container>/Xauthority` with its host wildcarded and
+//   that file is mounted read-only.
+//
+// `server = "auto"` passes whichever of the two the host has, both on a
+// Wayland desktop with Xwayland. `server = "wayland"` or `"x11"` insists on
+// one and fails when the host doesn't have it.
+//
+// With `gamescope`, the service gets a gamescope window instead: Bolt starts
+// a nested gamescope on the host per container, and the container gets
+// gamescope's Xwayland display and, with gamescope 3.12 or later, its Wayland
+// socket. Gamescope runs until the container is taken down. With `wrap`,
+// gamescope runs in the container instead, drawing on the host's display
+// with the service's command, or the image's, as its child; a script in
+// the session directory is the container's entrypoint.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use crate::config::{DisplayConfig, GamescopeConfig, GamingConfig};
+use crate::runtime::watcher::{self, Watcher};
+
+/// Where sockets and the Xauthority file go in the container
+const CONTAINER_RUNTIME_DIR: &str = "/run/bolt/display";
+const CONTAINER_SOCKET: &str = "wayland-0";
+const X11_DIR: &str = "/tmp/.X11-unix";
+const CONTAINER_WRAPPER: &str = "/run/bolt/gamescope";
+const GAMESCOPE_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayServer {
+    #[default]
+    Auto,
+    Wayland,
+    X11,
+}
+
+/// The display a container is given, as the host's environment describes it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HostDisplay {
+    pub runtime_dir: Option<PathBuf>,
+    pub wayland_display: Option<String>,
+    pub display: Option<String>,
+    pub xauthority: Option<PathBuf>,
+    pub x11_dir: PathBuf,
+}
+
+impl HostDisplay {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Self {
+            runtime_dir: var("XDG_RUNTIME_DIR").map(PathBuf::from),
+            wayland_display: var("WAYLAND_DISPLAY"),
+            display: var("DISPLAY"),
+            xauthority: var("XAUTHORITY")
+                .map(PathBuf::from)
+                .or_else(|| dirs::home_dir().map(|h| h.join(".Xauthority")))
+                .filter(|p| p.is_file()),
+            x11_dir: PathBuf::from(X11_DIR),
+        }
+    }
+
+    /// The compositor's socket, if it exists
+    pub fn wayland_socket(&self) -> Option<PathBuf> {
+        let name = self.wayland_display.as_deref()?;
+        let socket = if name.starts_with('/') {
+            PathBuf::from(name)
+        } else {
+            self.runtime_dir.as_ref()?.join(name)
+        };
+        socket.exists().then_some(socket)
+    }
+
+    /// The display number and socket of a local X server. Remote displays
+    /// such as `localhost:10` from SSH forwarding have no socket to mount.
+    pub fn x11_socket(&self) -> Option<(u32, PathBuf)> {
+        let display = self.display.as_deref()?;
+        let number = display
+            .strip_prefix(':')
+            .or_else(|| display.strip_prefix("unix:"))?;
+        let number: u32 = number.split('.').next()?.parse().ok()?;
+        let socket = self.x11_dir.join(format!("X{}", number));
+        socket.exists().then_some((number, socket))
+    }
+}
+
+/// What is mounted into the container
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Passthrough {
+    pub wayland: Option<PathBuf>,
+    pub x11: Option<(u32, PathBuf)>,
+    pub xauthority: Option<PathBuf>,
+}
+
+impl Passthrough {
+    pub fn resolve(host: &HostDisplay, server: DisplayServer) -> Result<Self> {
+        let wayland = host.wayland_socket();
+        let x11 = host.x11_socket();
+        let passthrough = match server {
+            DisplayServer::Auto => Self {
+                wayland,
+                x11,
+                xauthority: None,
+            },
+            DisplayServer::Wayland => Self {
+                wayland: Some(wayland.ok_or_else(|| {
+                    anyhow!("No Wayland socket: WAYLAND_DISPLAY and XDG_RUNTIME_DIR must name one")
+                })?),
+                ..Default::default()
+            },
+            DisplayServer::X11 => Self {
+                x11: Some(x11.ok_or_else(|| {
+                    anyhow!(
+                        "No local X server: DISPLAY must name one with a socket in {}",
+                        X11_DIR
+                    )
+                })?),
+                ..Default::default()
+            },
+        };
+        if passthrough.wayland.is_none() && passthrough.x11.is_none() {
+            return Err(anyhow!(
+                "No display to pass through: neither WAYLAND_DISPLAY nor DISPLAY is set"
+            ));
+        }
+        Ok(passthrough)
+    }
+
+    pub fn run_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut env = |var: &str, value: String| {
+            args.extend(["-e".to_string(), format!("{}={}", var, value)]);
+        };
+        if self.wayland.is_some() {
+            env("XDG_RUNTIME_DIR", CONTAINER_RUNTIME_DIR.to_string());
+            env("WAYLAND_DISPLAY", CONTAINER_SOCKET.to_string());
+            env("XDG_SESSION_TYPE", "wayland".to_string());
+        }
+        if let Some((number, _)) = self.x11 {
+            env("DISPLAY", format!(":{}", number));
+        }
+        if self.xauthority.is_some() {
+            env(
+                "XAUTHORITY",
+                format!("{}/Xauthority", CONTAINER_RUNTIME_DIR),
+            );
+        }
+        let drivers: Vec<&str> = [
+            self.wayland.as_ref().map(|_| "wayland"),
+            self.x11.as_ref().map(|_| "x11"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        env("SDL_VIDEODRIVER", drivers.join(","));
+
+        if let Some(ref socket) = self.wayland {
+            args.extend([
+                "-v".to_string(),
+                format!(
+                    "{}:{}/{}",
+                    socket.display(),
+                    CONTAINER_RUNTIME_DIR,
+                    CONTAINER_SOCKET
+                ),
+            ]);
+        }
+        if let Some((number, ref socket)) = self.x11 {
+            args.extend([
+                "-v".to_string(),
+                format!("{}:{}/X{}", socket.display(), X11_DIR, number),
+            ]);
+        }
+        if let Some(ref xauthority) = self.xauthority {
+            args.extend([
+                "-v".to_string(),
+                format!(
+                    "{}:{}/Xauthority:ro",
+                    xauthority.display(),
+                    CONTAINER_RUNTIME_DIR
+                ),
+            ]);
+        }
+        args
+    }
+}
+
+/// `xauth nlist` output with each entry's address family set to
+/// FamilyWild, so the cookie works whatever the container's host name is
+pub fn wildcard_cookies(nlist: &str) -> String {
+    nlist
+        .lines()
+        .filter(|l| l.len() > 4)
+        .map(|l| format!("ffff{}\n", &l[4..]))
+        .collect()
+}
+
+/// The nested session's child is in gamescope's process group
+const GAMESCOPE: Watcher = Watcher::group("gamescope");
+
+fn session_dir(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("display").join(container)
+}
+
+/// A copy of the display's cookie the container can use, or None when the
+/// X server takes no cookie
+async fn xauthority(
+    data_dir: &Path,
+    container: &str,
+    source: &Path,
+    number: u32,
+) -> Result<Option<PathBuf>> {
+    let output = AsyncCommand::new("xauth")
+        .arg("-f")
+        .arg(source)
+        .args(["nlist", &format!(":{}", number)])
+        .output()
+        .await;
+    let cookies = match output {
+        Ok(output) if output.status.success() => {
+            wildcard_cookies(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => {
+            warn!("xauth not found; passing {} as it is", source.display());
+            return Ok(Some(source.to_path_buf()));
+        }
+    };
+    if cookies.is_empty() {
+        return Ok(None);
+    }
+
+    let dir = session_dir(data_dir, container);
+    std::fs::create_dir_all(&dir)?;
+    let target = dir.join("Xauthority");
+    let _ = std::fs::remove_file(&target);
+    let mut merge = AsyncCommand::new("xauth")
+        .arg("-f")
+        .arg(&target)
+        .args(["nmerge", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run xauth")?;
+    if let Some(mut stdin) = merge.stdin.take() {
+        use tokio::io::AsyncWriteExt;
+        stdin.write_all(cookies.as_bytes()).await?;
+    }
+    if !merge.wait().await?.success() {
+        return Err(anyhow!("xauth could not write {}", target.display()));
+    }
+    Ok(Some(target))
+}
+
+/// A nested gamescope the container draws into
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GamescopeSession {
+    pub container: String,
+    pub pid: u32,
+    /// Gamescope's Xwayland display, e.g. `:1`
+    pub display: Option<String>,
+    /// Gamescope's Wayland socket in the host's XDG_RUNTIME_DIR
+    pub wayland_display: Option<String>,
+    pub xauthority: Option<PathBuf>,
+    pub args: Vec<String>,
+}
+
+impl GamescopeSession {
+    pub fn running(&self) -> bool {
+        GAMESCOPE.alive(self.pid)
+    }
+}
+
+/// `WIDTHxHEIGHT`, as in `gaming.resolution`
+pub fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.trim().split_once(['x', 'X'])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// A service's gamescope settings, with the render size and frame rate
+/// falling back to `gaming.resolution` and `gaming.refresh_rate`
+pub fn effective(gaming: &GamingConfig, config: &GamescopeConfig) -> GamescopeConfig {
+    let mut config = config.clone();
+    if config.width.is_none()
+        && config.height.is_none()
+        && let Some((width, height)) = gaming.resolution.as_deref().and_then(parse_resolution)
+    {
+        config.width = Some(width);
+        config.height = Some(height);
+    }
+    config.refresh = config.refresh.or(gaming.refresh_rate);
+    config
+}
+
+/// gamescope's options for a service's `[gaming.display.gamescope]`
+pub fn gamescope_args(config: &GamescopeConfig) -> Vec<String> {
+    let mut args = Vec::new();
+    for (flag, value) in [
+        ("-w", config.width),
+        ("-h", config.height),
+        ("-W", config.output_width),
+        ("-H", config.output_height),
+        ("-r", config.refresh),
+    ] {
+        if let Some(value) = value {
+            args.extend([flag.to_string(), value.to_string()]);
+        }
+    }
+    if config.fullscreen == Some(true) {
+        args.push("-f".to_string());
+    }
+    if config.hdr == Some(true) {
+        args.push("--hdr-enabled".to_string());
+    }
+    if config.vrr == Some(true) {
+        args.push("--adaptive-sync".to_string());
+    }
+    if let Some(ref filter) = config.filter {
+        args.extend(["-F".to_string(), filter.clone()]);
+    }
+    if let Some(ref scaler) = config.scaler {
+        args.extend(["-S".to_string(), scaler.clone()]);
+    }
+    args.push("--expose-wayland".to_string());
+    args.extend(config.extra_args.iter().flatten().cloned());
+    args
+}
+
+fn gamescope_state(data_dir: &Path, container: &str) -> PathBuf {
+    session_dir(data_dir, container).join("gamescope.json")
+}
+
+pub fn gamescope_session(data_dir: &Path, container: &str) -> Option<GamescopeSession> {
+    watcher::load(&gamescope_state(data_dir, container))
+}
+
+/// Every container with a gamescope session, by name
+pub fn gamescope_sessions(data_dir: &Path) -> Vec<GamescopeSession> {
+    let mut sessions: Vec<GamescopeSession> = std::fs::read_dir(data_dir.join("display"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| gamescope_session(data_dir, &e.file_name().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+    sessions.sort_by(|a, b| a.container.cmp(&b.container));
+    sessions
+}
+
+/// Start gamescope nested in the host's session. Its child only reports
+/// the display variables gamescope set for it and then waits, so the
+/// container's app, not the child, is what shows up in the window.
+async fn start_gamescope(
+    data_dir: &Path,
+    container: &str,
+    config: &GamescopeConfig,
+) -> Result<GamescopeSession> {
+    let dir = session_dir(data_dir, container);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let env_file = dir.join("gamescope.env");
+    let _ = std::fs::remove_file(&env_file);
+    let args = gamescope_args(config);
+    let report = format!(
+        "printf '%s\\n%s\\n%s\\n' \"$DISPLAY\" \"$GAMESCOPE_WAYLAND_DISPLAY\" \"$XAUTHORITY\" > '{}.tmp' && mv '{}.tmp' '{}' && exec sleep infinity",
+        env_file.display(),
+        env_file.display(),
+        env_file.display()
+    );
+    let pid = GAMESCOPE
+        .spawn_program(
+            Path::new("gamescope"),
+            args.iter()
+                .map(String::as_str)
+                .chain(["--", "sh", "-c", report.as_str()]),
+            &dir.join("gamescope.log"),
+        )
+        .context("Is gamescope installed?")?;
+
+    let started = std::time::Instant::now();
+    let reported = loop {
+        if let Ok(reported) = std::fs::read_to_string(&env_file) {
+            break reported;
+        }
+        if !GAMESCOPE.alive(pid) || started.elapsed() > GAMESCOPE_TIMEOUT {
+            GAMESCOPE.stop(pid);
+            return Err(anyhow!(
+                "gamescope for {} didn't start; see {}",
+                container,
+                dir.join("gamescope.log").display()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    let mut lines = reported
+        .lines()
+        .map(|l| Some(l.trim().to_string()).filter(|l| !l.is_empty()));
+    let session = GamescopeSession {
+        container: container.to_string(),
+        pid,
+        display: lines.next().flatten(),
+        wayland_display: lines.next().flatten(),
+        xauthority: lines.next().flatten().map(PathBuf::from),
+        args,
+    };
+    watcher::save(&gamescope_state(data_dir, container), &session)?;
+    info!(
+        "🖥️  gamescope for {} on {}",
+        container,
+        session
+            .wayland_display
+            .as_deref()
+            .or(session.display.as_deref())
+            .unwrap_or("?")
+    );
+    Ok(session)
+}
+
+fn quoted(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// The entrypoint script of a wrapped container: gamescope with the game
+/// as its child
+pub fn wrapper_script(args: &[String], command: &[String]) -> String {
+    let argv: Vec<String> = std::iter::once("gamescope")
+        .chain(args.iter().map(String::as_str))
+        .chain(std::iter::once("--"))
+        .chain(command.iter().map(String::as_str))
+        .map(quoted)
+        .collect();
+    format!("#!/bin/sh\nexec {}\n", argv.join(" "))
+}
+
+/// What an image runs by default: its entrypoint followed by its command
+pub async fn image_command(runtime: &str, image: &str) -> Result<Vec<String>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["image", "inspect"])
+        .arg(image)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to inspect image {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let inspect: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let inspect = inspect.get(0).unwrap_or(&inspect);
+    let strings = |pointer: &str| -> Vec<String> {
+        inspect
+            .pointer(pointer)
+            .and_then(|v| v.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut command = strings("/Config/Entrypoint");
+    command.extend(strings("/Config/Cmd"));
+    Ok(command)
+}
+
+/// Write the container's gamescope entrypoint; returns the run arguments
+/// that mount and use it
+fn wrap(
+    data_dir: &Path,
+    container: &str,
+    config: &GamescopeConfig,
+    command: &[String],
+) -> Result<Vec<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if command.is_empty() {
+        return Err(anyhow!(
+            "{} has no command for gamescope to run; set the service's command",
+            container
+        ));
+    }
+    let dir = session_dir(data_dir, container);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join("gamescope-wrap");
+    std::fs::write(&path, wrapper_script(&gamescope_args(config), command))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    info!(
+        "🖥️  {} runs under gamescope: {}",
+        container,
+        command.join(" ")
+    );
+    Ok(vec![
+        "-v".to_string(),
+        format!("{}:{}:ro", path.display(), CONTAINER_WRAPPER),
+        "--entrypoint".to_string(),
+        CONTAINER_WRAPPER.to_string(),
+    ])
+}
+
+/// Run arguments giving a service's container its display, starting its
+/// gamescope first when it has one. `command` is what the container runs,
+/// and is only needed when gamescope wraps it.
+pub async fn run_args(
+    data_dir: &Path,
+    container: &str,
+    gaming: &GamingConfig,
+    config: &DisplayConfig,
+    command: &[String],
+) -> Result<Vec<String>> {
+    forget(data_dir, container);
+    let mut host = HostDisplay::from_env();
+    let gamescope = config.gamescope.as_ref().map(|g| effective(gaming, g));
+    let mut args = Vec::new();
+    match gamescope {
+        Some(ref gamescope) if gamescope.wrap == Some(true) => {
+            args.extend(wrap(data_dir, container, gamescope, command)?);
+        }
+        Some(ref gamescope) => {
+            let session = start_gamescope(data_dir, container, gamescope).await?;
+            host.display = session.display;
+            host.wayland_display = session.wayland_display;
+            host.xauthority = session.xauthority;
+        }
+        None => {}
+    }
+    if gamescope.as_ref().is_some_and(|g| g.hdr == Some(true)) {
+        args.extend(["-e".to_string(), "DXVK_HDR=1".to_string()]);
+    }
+    let mut passthrough = Passthrough::resolve(&host, config.server.unwrap_or_default())
+        .with_context(|| format!("Cannot give {} a display", container))?;
+    if let (Some((number, _)), Some(source)) = (&passthrough.x11, &host.xauthority) {
+        passthrough.xauthority = xauthority(data_dir, container, source, *number).await?;
+    }
+    args.extend(passthrough.run_args());
+    Ok(args)
+}
+
+/// Stop the container's gamescope and drop its Xauthority copy
+pub fn forget(data_dir: &Path, container: &str) {
+    if let Some(session) = gamescope_session(data_dir, container) {
+        GAMESCOPE.stop(session.pid);
+    }
+    let _ = std::fs::remove_dir_all(session_dir(data_dir, container));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_the_sockets_the_host_has() {
+        let dir = tempfile::tempdir().unwrap();
+        let x11_dir = dir.path().join("x11");
+        std::fs::create_dir_all(&x11_dir).unwrap();
+        std::fs::write(dir.path().join("wayland-1"), "").unwrap();
+        std::fs::write(x11_dir.join("X0"), "").unwrap();
+        let host = HostDisplay {
+            runtime_dir: Some(dir.path().to_path_buf()),
+            wayland_display: Some("wayland-1".to_string()),
+            display: Some(":0.0".to_string()),
+            xauthority: None,
+            x11_dir,
+        };
+
+        let args = Passthrough::resolve(&host, DisplayServer::Auto)
+            .unwrap()
+            .run_args();
+        assert!(args.contains(&"WAYLAND_DISPLAY=wayland-0".to_string()));
+        assert!(args.contains(&"DISPLAY=:0".to_string()));
+        assert!(args.contains(&"SDL_VIDEODRIVER=wayland,x11".to_string()));
+        assert!(args.contains(&format!(
+            "{}:/run/bolt/display/wayland-0",
+            dir.path().join("wayland-1").display()
+        )));
+
+        let x11_only = Passthrough::resolve(&host, DisplayServer::X11).unwrap();
+        assert!(x11_only.wayland.is_none());
+
+        let ssh = HostDisplay {
+            wayland_display: None,
+            display: Some("localhost:10.0".to_string()),
+            ..host
+        };
+        assert!(Passthrough::resolve(&ssh, DisplayServer::Auto).is_err());
+
+        assert_eq!(
+            wildcard_cookies("0100000a676173746f6e0001300012MIT-MAGIC-COOKIE-1\n"),
+            "ffff000a676173746f6e0001300012MIT-MAGIC-COOKIE-1\n"
+        );
+    }
+
+    #[test]
+    fn wraps_the_game_in_gamescope() {
+        let gaming = GamingConfig {
+            resolution: Some("1920x1080".to_string()),
+            refresh_rate: Some(120),
+            ..Default::default()
+        };
+        let config = effective(
+            &gaming,
+            &GamescopeConfig {
+                hdr: Some(true),
+                vrr: Some(true),
+                filter: Some("fsr".to_string()),
+                scaler: Some("fit".to_string()),
+                ..Default::default()
+            },
+        );
+        let args = gamescope_args(&config);
+        assert_eq!(
+            args.join(" "),
+            "-w 1920 -h 1080 -r 120 --hdr-enabled --adaptive-sync -F fsr -S fit --expose-wayland"
+        );
+        assert_eq!(
+            wrapper_script(
+                &args[..2],
+                &["/game/run.sh".to_string(), "it's".to_string()]
+            ),
+            "#!/bin/sh\nexec 'gamescope' '-w' '1920' '--' '/game/run.sh' 'it'\\''s'\n"
+        );
+        assert_eq!(parse_resolution("2560 x 1440"), Some((2560, 1440)));
+        assert_eq!(parse_resolution("4k"), None);
+    }
+}
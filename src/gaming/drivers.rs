@@ -0,0 +1,465 @@
+// GPU driver upgrades
+//
+// Injected NVIDIA libraries carry the driver version in their file names
+// (`libnvidia-glcore.so.550.54.14`) and only work with the kernel module of
+// the same version. A container started before a driver upgrade keeps the
+// files it was started with: the old libraries, or deleted ones once the
+// package manager removed them, and in-place updates of Mesa's libraries
+// leave it on the replaced files just the same.
+//
+// When surge starts a GPU container it records the host files it
+// bind-mounts, with their inodes, and the NVIDIA driver version in
+// `<data_dir>/gpu/driver-mounts.json`. The driver version Bolt last saw is
+// kept in `<data_dir>/gpu/driver.json`. When a GPU service starts, and when
+// `bolt gaming gpu watch` or the API server starts, a changed version:
+//
+// - regenerates the nvidia-container-toolkit CDI spec if it lists files
+//   that are gone, so new containers get the new libraries; Bolt's own
+//   injection reads the driver directory on every start and needs nothing
+// - warns about running containers that are still on the old driver
+//
+// `bolt gaming gpu refresh` recreates those containers, one service at a
+// time in dependency order, so they mount the current driver.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::{BoltConfig, BoltFile};
+
+/// A host file bind-mounted into a container, and its inode at the time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MountedFile {
+    pub path: PathBuf,
+    pub inode: u64,
+}
+
+/// The driver a GPU container was started with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DriverMounts {
+    pub container: String,
+    pub project: String,
+    /// NVIDIA driver version, if the NVIDIA driver was loaded
+    pub driver: Option<String>,
+    pub files: Vec<MountedFile>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The driver version Bolt last saw
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DriverState {
+    version: Option<String>,
+    seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Why a container's driver files are out of date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "reason")]
+pub enum Staleness {
+    /// It was started with another driver version
+    DriverChanged {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// These files were replaced or removed on the host since it started
+    FilesReplaced { files: Vec<PathBuf> },
+}
+
+impl std::fmt::Display for Staleness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| "none".to_string());
+        match self {
+            Staleness::DriverChanged { from, to } => {
+                write!(f, "driver {} -> {}", version(from), version(to))
+            }
+            Staleness::FilesReplaced { files } => {
+                write!(f, "{} driver file(s) replaced", files.len())
+            }
+        }
+    }
+}
+
+/// A running container on an outdated driver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaleContainer {
+    pub container: String,
+    pub project: String,
+    pub reason: Staleness,
+}
+
+/// What `bolt gaming gpu refresh` recreates, and what it leaves alone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefreshPlan {
+    /// Services of the current project, dependencies first
+    pub services: Vec<String>,
+    pub containers: Vec<StaleContainer>,
+    /// Stale containers refresh can't recreate from here, and why
+    pub skipped: Vec<(StaleContainer, String)>,
+}
+
+fn mounts_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("driver-mounts.json")
+}
+
+fn state_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("driver.json")
+}
+
+/// The loaded NVIDIA driver's version
+pub fn driver_version() -> Option<String> {
+    #[cfg(feature = "gaming")]
+    return crate::runtime::gpu::icd::nvidia_kernel_version();
+    #[cfg(not(feature = "gaming"))]
+    None
+}
+
+/// Host files bind-mounted by `run` arguments
+pub fn mounted_files(args: &[String]) -> Vec<MountedFile> {
+    args.windows(2)
+        .filter(|pair| pair[0] == "-v" || pair[0] == "--volume")
+        .filter_map(|pair| {
+            let host = pair[1].split(':').next()?;
+            let metadata = std::fs::metadata(host).ok().filter(|m| m.is_file())?;
+            Some(MountedFile {
+                path: PathBuf::from(host),
+                inode: metadata.ino(),
+            })
+        })
+        .collect()
+}
+
+fn load(data_dir: &Path) -> BTreeMap<String, DriverMounts> {
+    std::fs::read(mounts_path(data_dir))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save(data_dir: &Path, mounts: &BTreeMap<String, DriverMounts>) -> Result<()> {
+    let path = mounts_path(data_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(mounts)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Record the driver files a GPU container is started with
+pub fn record(data_dir: &Path, project: &str, container: &str, args: &[String]) -> Result<()> {
+    let mut mounts = load(data_dir);
+    mounts.insert(
+        container.to_string(),
+        DriverMounts {
+            container: container.to_string(),
+            project: project.to_string(),
+            driver: driver_version(),
+            files: mounted_files(args),
+            started_at: chrono::Utc::now(),
+        },
+    );
+    save(data_dir, &mounts)
+}
+
+/// Whether a container started with `mounts` is out of date for `current`
+pub fn staleness(mounts: &DriverMounts, current: Option<&str>) -> Option<Staleness> {
+    if mounts.driver.as_deref() != current {
+        return Some(Staleness::DriverChanged {
+            from: mounts.driver.clone(),
+            to: current.map(str::to_string),
+        });
+    }
+    let replaced: Vec<PathBuf> = mounts
+        .files
+        .iter()
+        .filter(|file| std::fs::metadata(&file.path).map_or(true, |m| m.ino() != file.inode))
+        .map(|file| file.path.clone())
+        .collect();
+    (!replaced.is_empty()).then_some(Staleness::FilesReplaced { files: replaced })
+}
+
+/// Running GPU containers on an outdated driver. Records of containers that
+/// no longer exist are dropped.
+pub async fn stale_containers(data_dir: &Path) -> Result<Vec<StaleContainer>> {
+    let mut mounts = load(data_dir);
+    if mounts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let existing = crate::runtime::list_containers_info(true).await?;
+    let before = mounts.len();
+    mounts.retain(|name, _| existing.iter().any(|c| &c.name == name));
+    if mounts.len() != before {
+        save(data_dir, &mounts)?;
+    }
+
+    let running = crate::runtime::list_containers_info(false).await?;
+    let current = driver_version();
+    Ok(mounts
+        .values()
+        .filter(|m| running.iter().any(|c| c.name == m.container))
+        .filter_map(|m| {
+            Some(StaleContainer {
+                container: m.container.clone(),
+                project: m.project.clone(),
+                reason: staleness(m, current.as_deref())?,
+            })
+        })
+        .collect())
+}
+
+/// Compare the loaded driver with the one Bolt saw last. On a change, the
+/// CDI spec is regenerated if it went stale. Returns the previous version
+/// when it changed.
+pub async fn check_driver(config: &BoltConfig) -> Result<Option<Option<String>>> {
+    let current = driver_version();
+    let path = state_path(&config.data_dir);
+    let previous: Option<DriverState> = std::fs::read(&path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    let changed = match previous {
+        Some(ref state) if state.version == current => return Ok(None),
+        Some(state) => Some(state.version),
+        // First run: nothing to compare with
+        None => None,
+    };
+
+    if let Some(ref from) = changed {
+        info!(
+            "🔄 NVIDIA driver changed from {} to {}; re-resolving GPU library mounts",
+            from.as_deref().unwrap_or("none"),
+            current.as_deref().unwrap_or("none")
+        );
+        #[cfg(feature = "gaming")]
+        refresh_cdi_spec().await;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(
+        &path,
+        serde_json::to_vec_pretty(&DriverState {
+            version: current,
+            seen_at: chrono::Utc::now(),
+        })?,
+    )?;
+    Ok(changed)
+}
+
+/// Regenerate the nvidia-container-toolkit CDI spec when it lists files the
+/// driver upgrade removed
+#[cfg(feature = "gaming")]
+async fn refresh_cdi_spec() {
+    use crate::runtime::gpu::nvidia_ctk;
+
+    let dirs: Vec<&Path> = nvidia_ctk::CDI_DIRS.iter().map(Path::new).collect();
+    let Some((path, spec)) = nvidia_ctk::find_spec(&dirs) else {
+        return;
+    };
+    let Ok(injection) = nvidia_ctk::cdi_injection(&spec, &["all".to_string()]) else {
+        return;
+    };
+    if injection.mounts.iter().all(|(host, _)| host.exists()) {
+        return;
+    }
+    let output = tokio::process::Command::new("nvidia-ctk")
+        .args(["cdi", "generate"])
+        .arg(format!("--output={}", path.display()))
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            info!(
+                "✅ Regenerated CDI spec {} for the new driver",
+                path.display()
+            )
+        }
+        Ok(output) => warn!(
+            "⚠️  CDI spec {} lists files of the old driver and could not be regenerated: {}; \
+             run `nvidia-ctk cdi generate --output={}`",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+            path.display()
+        ),
+        Err(e) => warn!(
+            "⚠️  CDI spec {} lists files of the old driver and nvidia-ctk could not run: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Warn about each running container still on an outdated driver
+pub async fn warn_stale(config: &BoltConfig) -> Result<Vec<StaleContainer>> {
+    let stale = stale_containers(&config.data_dir).await?;
+    for container in &stale {
+        warn!(
+            "⚠️  {} runs on stale GPU libraries ({}); `bolt gaming gpu refresh` recreates it",
+            container.container, container.reason
+        );
+    }
+    Ok(stale)
+}
+
+/// Check at daemon start: a driver change and stale containers
+pub async fn check_at_startup(config: &BoltConfig) -> Result<()> {
+    check_driver(config).await?;
+    warn_stale(config).await?;
+    Ok(())
+}
+
+/// Check when a GPU container starts; the container scan only runs after a
+/// driver change
+pub async fn check_at_container_start(config: &BoltConfig) -> Result<()> {
+    if check_driver(config).await?.is_some() {
+        warn_stale(config).await?;
+    }
+    Ok(())
+}
+
+/// The service a surge container belongs to
+fn service_of<'a>(boltfile: &'a BoltFile, container: &str) -> Option<&'a String> {
+    boltfile.services.keys().find(|service| {
+        let main = format!("{}_{}", boltfile.project, service);
+        container == main
+            || container
+                .strip_prefix(&main)
+                .and_then(|rest| rest.strip_prefix('_'))
+                .is_some_and(|n| n.parse::<u32>().is_ok())
+    })
+}
+
+/// Which of `stale` the current project can recreate, in dependency order
+pub fn plan(boltfile: &BoltFile, stale: Vec<StaleContainer>) -> RefreshPlan {
+    let mut plan = RefreshPlan::default();
+    let mut services = Vec::new();
+    for container in stale {
+        if container.project != boltfile.project {
+            let why = format!(
+                "belongs to project {}; run refresh next to its Boltfile",
+                container.project
+            );
+            plan.skipped.push((container, why));
+            continue;
+        }
+        match service_of(boltfile, &container.container) {
+            Some(service) => {
+                if !services.contains(&service) {
+                    services.push(service);
+                }
+                plan.containers.push(container);
+            }
+            None => {
+                let why = format!("no service of {} runs it anymore", boltfile.project);
+                plan.skipped.push((container, why));
+            }
+        }
+    }
+    plan.services = crate::surge::start_order(boltfile, services)
+        .into_iter()
+        .cloned()
+        .collect();
+    plan
+}
+
+/// Recreate the stale containers of the current project, one service at a
+/// time, dependencies first. Stops at the first service that fails.
+pub async fn refresh(config: &BoltConfig, plan: &RefreshPlan) -> Result<Vec<String>> {
+    let mut refreshed = Vec::new();
+    for service in &plan.services {
+        info!("🔄 Recreating {} with the current GPU driver", service);
+        let options = crate::surge::UpOptions {
+            detach: true,
+            force_recreate: true,
+            ..Default::default()
+        };
+        crate::surge::up_with(config, std::slice::from_ref(service), &options)
+            .await?
+            .into_result()
+            .with_context(|| format!("Failed to recreate {}", service))?;
+        refreshed.push(service.clone());
+    }
+    Ok(refreshed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_stale_mounts_and_plans_refresh_in_dependency_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("libnvidia-glcore.so.550.54.14");
+        std::fs::write(&lib, "old").unwrap();
+        let args: Vec<String> = [
+            "-v",
+            &format!(
+                "{}:/usr/lib/libnvidia-glcore.so.550.54.14:ro",
+                lib.display()
+            ),
+            "-v",
+            "/nonexistent:/x:ro",
+            "--device",
+            "/dev/nvidia0",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let mounts = DriverMounts {
+            container: "game_server".to_string(),
+            project: "game".to_string(),
+            driver: Some("550.54.14".to_string()),
+            files: mounted_files(&args),
+            started_at: chrono::Utc::now(),
+        };
+        assert_eq!(mounts.files.len(), 1);
+        assert_eq!(staleness(&mounts, Some("550.54.14")), None);
+        assert_eq!(
+            staleness(&mounts, Some("555.42.02")),
+            Some(Staleness::DriverChanged {
+                from: Some("550.54.14".to_string()),
+                to: Some("555.42.02".to_string()),
+            })
+        );
+        // A package upgrade replaces the file rather than rewriting it
+        let replacement = dir.path().join("new");
+        std::fs::write(&replacement, "new").unwrap();
+        std::fs::rename(&replacement, &lib).unwrap();
+        assert_eq!(
+            staleness(&mounts, Some("550.54.14")),
+            Some(Staleness::FilesReplaced { files: vec![lib] })
+        );
+
+        let boltfile: BoltFile = toml::from_str(
+            r#"
+project = "game"
+
+[services.server]
+image = "server"
+depends_on = ["encoder"]
+
+[services.encoder]
+image = "encoder"
+"#,
+        )
+        .unwrap();
+        let stale = |container: &str, project: &str| StaleContainer {
+            container: container.to_string(),
+            project: project.to_string(),
+            reason: Staleness::FilesReplaced { files: Vec::new() },
+        };
+        let plan = plan(
+            &boltfile,
+            vec![
+                stale("game_server", "game"),
+                stale("game_server_2", "game"),
+                stale("game_encoder", "game"),
+                stale("game_lobby", "game"),
+                stale("chat_bot", "chat"),
+            ],
+        );
+        assert_eq!(plan.services, vec!["encoder", "server"]);
+        assert_eq!(plan.containers.len(), 3);
+        assert_eq!(plan.skipped.len(), 2);
+    }
+}
@@ -0,0 +1,1135 @@
+// Fractional GPUs
+//
+// An AI/ML service with `gpu.fraction = 0.25` gets a quarter of one GPU
+// instead of all of it. Shares are placed so a GPU's never add up to more
+// than one: the fullest GPU the share still fits on is picked, and GPUs a
+// container holds whole are left alone. The share is carved out with what
+// the GPU supports:
+//
+// - NVIDIA with MIG enabled (A100, A30, H100): a GPU instance of the
+//   smallest profile with at least that share of the GPU's slices is
+//   created with one compute instance, and only it is visible in the
+//   container. The hardware isolates its memory and SMs.
+// - Other NVIDIA GPUs: CUDA MPS. Bolt runs an MPS control daemon per GPU
+//   and limits the container to its share of SMs
+//   (CUDA_MPS_ACTIVE_THREAD_PERCENTAGE) and of memory
+//   (CUDA_MPS_PINNED_DEVICE_MEM_LIMIT). Clients can only lower the limits
+//   of their MPS server, so the daemon caps its servers at the largest
+//   share on the GPU; a container can't raise its own past that. MPS
+//   clients talk to the daemon over shared memory, so the container gets
+//   `--ipc host`.
+// - AMD: time-slicing. ROCm queues are limited to the share of the GPU's
+//   compute units (HSA_CU_MASK); VRAM isn't partitioned, so the arbiter
+//   reports a container using more than its share.
+//
+// Slices are kept in `<data_dir>/gpu/slices.json`. The GPU arbiter samples
+// each slice's memory and utilization for `bolt gaming gpu slices` and the
+// Prometheus endpoint, and destroys the MIG instance or stops the MPS
+// daemon once the container is gone.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use super::priority;
+use super::recovery::{self, GpuVendor};
+use super::topology::{self, GpuTopology, Placement, TopologyGpu};
+use crate::config::GpuConfig;
+
+/// Label holding a container's share of its GPU
+pub const LABEL: &str = "bolt.gpu-fraction";
+
+/// Slack for shares that add up to exactly one GPU
+const EPSILON: f64 = 1e-6;
+
+/// A slice whose container hasn't shown up after this long is released
+const STARTUP_GRACE_SECS: i64 = 120;
+
+/// Where containers find the MPS daemon's pipes
+const MPS_PIPE: &str = "/tmp/nvidia-mps";
+
+/// Check a service's `gpu.fraction`
+pub fn validate(gpu: &GpuConfig, fraction: f64) -> Result<()> {
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(anyhow!(
+            "gpu.fraction must be above 0 and at most 1, not {}",
+            fraction
+        ));
+    }
+    if gpu.count.is_some_and(|c| c != 1) || gpu.topology.is_some() {
+        return Err(anyhow!(
+            "gpu.fraction is a share of one GPU and can't be combined with gpu.count or gpu.topology"
+        ));
+    }
+    if gpu.ai.is_some() {
+        return Err(anyhow!(
+            "gpu.fraction can't be combined with gpu.ai, which spans whole GPUs"
+        ));
+    }
+    if gpu.intel.is_some() {
+        return Err(anyhow!("gpu.fraction needs an NVIDIA or AMD GPU"));
+    }
+    Ok(())
+}
+
+/// How a slice is carved out of its GPU
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Mechanism {
+    /// A MIG GPU instance with one compute instance
+    Mig {
+        profile: String,
+        gpu_instance: u32,
+        compute_instance: u32,
+        uuid: String,
+    },
+    /// A CUDA MPS client limited to a share of SMs and memory
+    Mps {
+        pipe_dir: PathBuf,
+        active_thread_percentage: u32,
+    },
+    /// The whole device, with ROCm limited to a share of compute units
+    /// where their number is known
+    TimeSlice { cu_mask: Option<String> },
+}
+
+impl Mechanism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Mig { .. } => "mig",
+            Mechanism::Mps { .. } => "mps",
+            Mechanism::TimeSlice { .. } => "time-slice",
+        }
+    }
+}
+
+/// A container's share of a GPU
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Slice {
+    pub container: String,
+    pub gpu: TopologyGpu,
+    pub fraction: f64,
+    pub mechanism: Mechanism,
+    /// GPU memory the slice may use
+    pub memory_limit_mb: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    /// Last sample by the arbiter
+    #[serde(default)]
+    pub usage: Option<SliceUsage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SliceUsage {
+    pub memory_used_mb: u64,
+    /// How busy the container kept the GPU; None where the driver doesn't say
+    pub utilization_percent: Option<f64>,
+    pub sampled_at: Option<DateTime<Utc>>,
+}
+
+impl Slice {
+    /// Whether the container holds more GPU memory than its share
+    pub fn over_memory(&self) -> bool {
+        match (self.memory_limit_mb, &self.usage) {
+            (Some(limit), Some(usage)) => usage.memory_used_mb > limit,
+            _ => false,
+        }
+    }
+
+    /// The slice's GPU, for passing in only its driver devices
+    pub fn placement(&self) -> Placement {
+        Placement {
+            gpus: vec![self.gpu.clone()],
+        }
+    }
+
+    /// Arguments for `podman/docker run`
+    pub fn run_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--label".to_string(),
+            format!("{}={}", topology::LABEL, self.gpu.pci_bus_id),
+            "--label".to_string(),
+            format!("{}={}", LABEL, self.fraction),
+        ];
+        for device in &self.gpu.devices {
+            args.push("--device".to_string());
+            args.push(device.clone());
+        }
+
+        let mut env = Vec::new();
+        match &self.mechanism {
+            Mechanism::Mig { uuid, .. } => {
+                for var in ["NVIDIA_VISIBLE_DEVICES", "CUDA_VISIBLE_DEVICES"] {
+                    env.push(format!("{}={}", var, uuid));
+                }
+            }
+            Mechanism::Mps {
+                pipe_dir,
+                active_thread_percentage,
+            } => {
+                args.push("-v".to_string());
+                args.push(format!("{}:{}", pipe_dir.display(), MPS_PIPE));
+                args.push("--ipc".to_string());
+                args.push("host".to_string());
+                if let Some(index) = self.gpu.nvidia_index {
+                    env.push(format!("NVIDIA_VISIBLE_DEVICES={}", index));
+                }
+                env.push(format!("CUDA_MPS_PIPE_DIRECTORY={}", MPS_PIPE));
+                env.push(format!(
+                    "CUDA_MPS_ACTIVE_THREAD_PERCENTAGE={}",
+                    active_thread_percentage
+                ));
+                if let Some(limit) = self.memory_limit_mb {
+                    env.push(format!("CUDA_MPS_PINNED_DEVICE_MEM_LIMIT=0={}M", limit));
+                }
+            }
+            Mechanism::TimeSlice { cu_mask } => {
+                if let Some(mask) = cu_mask {
+                    env.push(format!("HSA_CU_MASK={}", mask));
+                }
+            }
+        }
+        for var in env {
+            args.push("-e".to_string());
+            args.push(var);
+        }
+        args
+    }
+}
+
+fn slices_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("slices.json")
+}
+
+/// Every slice handed out
+pub fn slices(data_dir: &Path) -> Vec<Slice> {
+    std::fs::read(slices_path(data_dir))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save(data_dir: &Path, slices: &[Slice]) -> Result<()> {
+    let path = slices_path(data_dir);
+    std::fs::write(&path, serde_json::to_vec_pretty(slices)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Held while slices.json is read and changed
+fn lock(data_dir: &Path) -> Result<std::fs::File> {
+    let dir = data_dir.join("gpu");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(".slices.lock"))?;
+    // SAFETY: flock(2) on a descriptor we own
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(file)
+}
+
+/// The GPU a share fits on, the fullest first so whole GPUs stay free.
+/// `shares` is the sum of the slices on each GPU; GPUs in `whole` are held
+/// by containers without a fraction.
+pub fn choose(
+    gpus: &[TopologyGpu],
+    fraction: f64,
+    shares: &HashMap<String, f64>,
+    whole: &HashSet<String>,
+    quarantined: &[String],
+) -> Option<usize> {
+    gpus.iter()
+        .enumerate()
+        .filter(|(_, g)| !whole.contains(&g.pci_bus_id) && !quarantined.contains(&g.pci_bus_id))
+        .map(|(i, g)| (i, shares.get(&g.pci_bus_id).copied().unwrap_or(0.0)))
+        .filter(|(_, used)| used + fraction <= 1.0 + EPSILON)
+        .max_by(|(a, used_a), (b, used_b)| used_a.total_cmp(used_b).then(b.cmp(a)))
+        .map(|(i, _)| i)
+}
+
+/// Carve `fraction` of a GPU out for `container`. A container that is
+/// recreated with the same fraction gets its slice back.
+pub async fn allocate(
+    runtime: &str,
+    data_dir: &Path,
+    gpu: &GpuConfig,
+    container: &str,
+    fraction: f64,
+) -> Result<Slice> {
+    let _lock = lock(data_dir)?;
+    let mut slices = slices(data_dir);
+    if let Some(i) = slices.iter().position(|s| s.container == container) {
+        if (slices[i].fraction - fraction).abs() < EPSILON {
+            return Ok(slices[i].clone());
+        }
+        let previous = slices.remove(i);
+        release(data_dir, &previous, &slices).await?;
+        save(data_dir, &slices)?;
+    }
+
+    let vendor = match (&gpu.nvidia, &gpu.amd) {
+        (Some(_), None) => Some(GpuVendor::Nvidia),
+        (None, Some(_)) => Some(GpuVendor::Amd),
+        _ => None,
+    };
+    let gpus: Vec<TopologyGpu> = GpuTopology::detect()
+        .await?
+        .gpus
+        .into_iter()
+        .filter(|g| vendor.is_none_or(|v| v == g.vendor))
+        .collect();
+    let sliced: HashSet<&str> = slices.iter().map(|s| s.container.as_str()).collect();
+    let whole: HashSet<String> = priority::holders(runtime)
+        .await
+        .into_iter()
+        .filter(|h| !sliced.contains(h.container.as_str()))
+        .flat_map(|h| h.gpus.unwrap_or_default())
+        .collect();
+    let mut shares: HashMap<String, f64> = HashMap::new();
+    for slice in &slices {
+        *shares.entry(slice.gpu.pci_bus_id.clone()).or_default() += slice.fraction;
+    }
+    let quarantined: Vec<String> = recovery::quarantined(data_dir)
+        .into_iter()
+        .map(|q| q.pci_bus_id)
+        .collect();
+    let Some(i) = choose(&gpus, fraction, &shares, &whole, &quarantined) else {
+        return Err(anyhow!(
+            "No GPU has {:.0}% of its capacity free (see `bolt gaming gpu slices`)",
+            fraction * 100.0
+        ));
+    };
+
+    let gpu = gpus[i].clone();
+    let (mechanism, memory_limit_mb) = carve(data_dir, &gpu, fraction).await?;
+    info!(
+        "🍰 {} gets {:.0}% of GPU {} ({})",
+        container,
+        fraction * 100.0,
+        gpu.pci_bus_id,
+        mechanism.name()
+    );
+    let slice = Slice {
+        container: container.to_string(),
+        gpu,
+        fraction,
+        mechanism,
+        memory_limit_mb,
+        created_at: Utc::now(),
+        usage: None,
+    };
+    slices.push(slice.clone());
+    save(data_dir, &slices)?;
+    if matches!(slice.mechanism, Mechanism::Mps { .. }) {
+        enforce_mps_limits(data_dir, &slice.gpu, &slices).await?;
+    }
+    Ok(slice)
+}
+
+async fn carve(
+    data_dir: &Path,
+    gpu: &TopologyGpu,
+    fraction: f64,
+) -> Result<(Mechanism, Option<u64>)> {
+    match gpu.vendor {
+        GpuVendor::Nvidia => {
+            let index = gpu
+                .nvidia_index
+                .ok_or_else(|| anyhow!("GPU {} has no NVIDIA index", gpu.pci_bus_id))?;
+            let query = nvidia_smi(&[
+                "-i",
+                &index.to_string(),
+                "--query-gpu=mig.mode.current,memory.total",
+                "--format=csv,noheader,nounits",
+            ])
+            .await?;
+            let (mig, memory_mb) = query
+                .trim()
+                .split_once(',')
+                .map(|(mig, memory)| (mig.trim() == "Enabled", memory.trim().parse::<u64>().ok()))
+                .ok_or_else(|| anyhow!("Unexpected nvidia-smi output: {}", query.trim()))?;
+            if mig {
+                create_mig(index, fraction).await
+            } else {
+                let pipe_dir = start_mps(data_dir, gpu, index).await?;
+                Ok((
+                    Mechanism::Mps {
+                        pipe_dir,
+                        active_thread_percentage: ((fraction * 100.0).ceil() as u32).clamp(1, 100),
+                    },
+                    memory_mb.map(|total| (total as f64 * fraction) as u64),
+                ))
+            }
+        }
+        GpuVendor::Amd => {
+            let memory_mb = std::fs::read_to_string(
+                Path::new("/sys/bus/pci/devices")
+                    .join(&gpu.pci_bus_id)
+                    .join("mem_info_vram_total"),
+            )
+            .ok()
+            .and_then(|bytes| bytes.trim().parse::<u64>().ok())
+            .map(|bytes| (bytes as f64 / (1024.0 * 1024.0) * fraction) as u64);
+            let cu_mask = amd_compute_units(
+                Path::new("/sys/class/kfd/kfd/topology/nodes"),
+                &gpu.pci_bus_id,
+            )
+            .map(|units| cu_mask(units, fraction));
+            if cu_mask.is_none() {
+                debug!(
+                    "Compute units of GPU {} unknown; time-slicing without a CU mask",
+                    gpu.pci_bus_id
+                );
+            }
+            Ok((Mechanism::TimeSlice { cu_mask }, memory_mb))
+        }
+    }
+}
+
+async fn nvidia_smi(args: &[&str]) -> Result<String> {
+    let output = AsyncCommand::new("nvidia-smi")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to run nvidia-smi")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-smi {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// A row of `nvidia-smi mig -lgip`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigProfile {
+    pub name: String,
+    pub id: u32,
+    pub free: u32,
+    /// Compute slices, the `N` of `Ng.XXgb`
+    pub slices: u32,
+    pub memory_gib: f64,
+}
+
+pub fn parse_mig_profiles(output: &str) -> Vec<MigProfile> {
+    let mut profiles = Vec::new();
+    for line in output.lines() {
+        // |   0  MIG 1g.5gb        19     7/7        4.75       No     14     0     0   |
+        let fields: Vec<&str> = line.trim().trim_matches('|').split_whitespace().collect();
+        if fields.len() < 6 || fields[1] != "MIG" {
+            continue;
+        }
+        let (Ok(id), Some((free, _)), Ok(memory_gib), Some(Ok(slices))) = (
+            fields[3].parse(),
+            fields[4].split_once('/'),
+            fields[5].parse(),
+            fields[2].split_once('g').map(|(n, _)| n.parse()),
+        ) else {
+            continue;
+        };
+        profiles.push(MigProfile {
+            name: fields[2].to_string(),
+            id,
+            free: free.parse().unwrap_or(0),
+            slices,
+            memory_gib,
+        });
+    }
+    profiles
+}
+
+/// The smallest profile with a free instance holding at least `fraction`
+/// of the GPU's compute slices; media-extension variants are left for those
+/// who ask for them
+pub fn pick_profile(profiles: &[MigProfile], fraction: f64) -> Option<&MigProfile> {
+    let total = profiles.iter().map(|p| p.slices).max()?;
+    profiles
+        .iter()
+        .filter(|p| !p.name.contains("+me") && !p.name.contains("-me"))
+        .filter(|p| p.free > 0 && p.slices as f64 / total as f64 + EPSILON >= fraction)
+        .min_by(|a, b| {
+            a.slices
+                .cmp(&b.slices)
+                .then(a.memory_gib.total_cmp(&b.memory_gib))
+        })
+}
+
+/// GPU and compute instance IDs from `nvidia-smi mig -cgi <profile> -C`
+pub fn parse_created(output: &str) -> Option<(u32, u32)> {
+    let after = |marker: &str| {
+        output.lines().find_map(|line| {
+            line.split_once(marker)?
+                .1
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        })
+    };
+    Some((
+        after("created GPU instance ID")?,
+        after("created compute instance ID")?,
+    ))
+}
+
+fn mig_uuids(listing: &str) -> HashSet<String> {
+    listing
+        .lines()
+        .filter_map(|line| line.split_once("(UUID: MIG-"))
+        .filter_map(|(_, rest)| rest.split_once(')'))
+        .map(|(uuid, _)| format!("MIG-{}", uuid))
+        .collect()
+}
+
+async fn create_mig(index: u32, fraction: f64) -> Result<(Mechanism, Option<u64>)> {
+    let index_arg = index.to_string();
+    let profiles = parse_mig_profiles(&nvidia_smi(&["mig", "-i", &index_arg, "-lgip"]).await?);
+    let profile = pick_profile(&profiles, fraction).ok_or_else(|| {
+        anyhow!(
+            "No free MIG profile on GPU {} holds {:.0}% of it (see `nvidia-smi mig -lgip`)",
+            index,
+            fraction * 100.0
+        )
+    })?;
+
+    let before = mig_uuids(&nvidia_smi(&["-L"]).await?);
+    let created = nvidia_smi(&[
+        "mig",
+        "-i",
+        &index_arg,
+        "-cgi",
+        &profile.id.to_string(),
+        "-C",
+    ])
+    .await
+    .context("Failed to create a MIG instance (this needs root)")?;
+    let (gpu_instance, compute_instance) = parse_created(&created)
+        .ok_or_else(|| anyhow!("Unexpected nvidia-smi output: {}", created.trim()))?;
+    let uuid = mig_uuids(&nvidia_smi(&["-L"]).await?)
+        .difference(&before)
+        .next()
+        .cloned()
+        .ok_or_else(|| anyhow!("The new MIG instance on GPU {} has no UUID", index))?;
+
+    info!(
+        "🧩 Created MIG instance {} ({}) on GPU {}",
+        uuid, profile.name, index
+    );
+    Ok((
+        Mechanism::Mig {
+            profile: profile.name.clone(),
+            gpu_instance,
+            compute_instance,
+            uuid,
+        },
+        Some((profile.memory_gib * 1024.0) as u64),
+    ))
+}
+
+fn mps_dir(data_dir: &Path, pci_bus_id: &str) -> PathBuf {
+    data_dir
+        .join("gpu")
+        .join("mps")
+        .join(pci_bus_id.replace(':', "_"))
+}
+
+/// Send a command to the MPS control daemon of a GPU
+async fn mps_control(dir: &Path, command: &str) -> Result<String> {
+    let mut child = AsyncCommand::new("nvidia-cuda-mps-control")
+        .env("CUDA_MPS_PIPE_DIRECTORY", dir.join("pipe"))
+        .env("CUDA_MPS_LOG_DIRECTORY", dir.join("log"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run nvidia-cuda-mps-control; is CUDA MPS installed?")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(format!("{}\n", command).as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-cuda-mps-control {} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The pipe directory of the GPU's MPS daemon, started unless it runs
+async fn start_mps(data_dir: &Path, gpu: &TopologyGpu, index: u32) -> Result<PathBuf> {
+    let dir = mps_dir(data_dir, &gpu.pci_bus_id);
+    for sub in ["pipe", "log"] {
+        std::fs::create_dir_all(dir.join(sub))?;
+    }
+    if mps_control(&dir, "get_server_list").await.is_ok() {
+        return Ok(dir.join("pipe"));
+    }
+    let status = AsyncCommand::new("nvidia-cuda-mps-control")
+        .arg("-d")
+        .env("CUDA_DEVICE_ORDER", "PCI_BUS_ID")
+        .env("CUDA_VISIBLE_DEVICES", index.to_string())
+        .env("CUDA_MPS_PIPE_DIRECTORY", dir.join("pipe"))
+        .env("CUDA_MPS_LOG_DIRECTORY", dir.join("log"))
+        .status()
+        .await
+        .context("Failed to run nvidia-cuda-mps-control; is CUDA MPS installed?")?;
+    if !status.success() {
+        return Err(anyhow!(
+            "The CUDA MPS daemon for GPU {} failed to start (see {})",
+            gpu.pci_bus_id,
+            dir.join("log").display()
+        ));
+    }
+    info!("🔀 Started the CUDA MPS daemon for GPU {}", gpu.pci_bus_id);
+    Ok(dir.join("pipe"))
+}
+
+/// The server-side MPS limits for a GPU: the largest thread percentage and
+/// memory limit of the MPS slices on it
+fn mps_ceiling(pci_bus_id: &str, slices: &[Slice]) -> Option<(u32, Option<u64>)> {
+    let mps: Vec<&Slice> = slices
+        .iter()
+        .filter(|s| s.gpu.pci_bus_id == pci_bus_id)
+        .filter(|s| matches!(s.mechanism, Mechanism::Mps { .. }))
+        .collect();
+    let percentage = mps
+        .iter()
+        .filter_map(|s| match s.mechanism {
+            Mechanism::Mps {
+                active_thread_percentage,
+                ..
+            } => Some(active_thread_percentage),
+            _ => None,
+        })
+        .max()?;
+    // A slice without a known limit leaves memory uncapped
+    let memory = mps
+        .iter()
+        .map(|s| s.memory_limit_mb)
+        .collect::<Option<Vec<u64>>>()
+        .and_then(|limits| limits.into_iter().max());
+    Some((percentage, memory))
+}
+
+/// Apply a GPU's MPS limits in its control daemon, as the default for new
+/// servers and to the servers already running
+async fn enforce_mps_limits(data_dir: &Path, gpu: &TopologyGpu, slices: &[Slice]) -> Result<()> {
+    let Some((percentage, memory_mb)) = mps_ceiling(&gpu.pci_bus_id, slices) else {
+        return Ok(());
+    };
+    let dir = mps_dir(data_dir, &gpu.pci_bus_id);
+    // The daemon only sees its own GPU, as device 0
+    let mut commands = vec![format!(
+        "set_default_active_thread_percentage {}",
+        percentage
+    )];
+    if let Some(limit) = memory_mb {
+        commands.push(format!("set_default_device_pinned_mem_limit 0 {}M", limit));
+    }
+    for server in mps_control(&dir, "get_server_list")
+        .await?
+        .split_whitespace()
+    {
+        commands.push(format!(
+            "set_active_thread_percentage {} {}",
+            server, percentage
+        ));
+        if let Some(limit) = memory_mb {
+            commands.push(format!(
+                "set_device_pinned_mem_limit {} 0 {}M",
+                server, limit
+            ));
+        }
+    }
+    for command in &commands {
+        mps_control(&dir, command).await?;
+    }
+    debug!(
+        "MPS limits of GPU {}: {}% of threads, {} MB",
+        gpu.pci_bus_id,
+        percentage,
+        memory_mb.map_or("unlimited".to_string(), |m| m.to_string())
+    );
+    Ok(())
+}
+
+/// Compute units of the AMD GPU at `pci_bus_id`, from the KFD topology
+pub fn amd_compute_units(nodes: &Path, pci_bus_id: &str) -> Option<u32> {
+    let (domain, rest) = pci_bus_id.split_once(':')?;
+    let (bus, rest) = rest.split_once(':')?;
+    let (device, function) = rest.split_once('.')?;
+    let domain = u32::from_str_radix(domain, 16).ok()?;
+    let location = (u32::from_str_radix(bus, 16).ok()? << 8)
+        | (u32::from_str_radix(device, 16).ok()? << 3)
+        | u32::from_str_radix(function, 16).ok()?;
+
+    for entry in std::fs::read_dir(nodes).ok()?.flatten() {
+        let Ok(properties) = std::fs::read_to_string(entry.path().join("properties")) else {
+            continue;
+        };
+        let properties: HashMap<&str, u32> = properties
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(key, value)| Some((key, value.trim().parse().ok()?)))
+            .collect();
+        if properties.get("location_id") == Some(&location)
+            && properties.get("domain").is_none_or(|d| *d == domain)
+        {
+            let simds = *properties.get("simd_count")?;
+            let per_cu = *properties.get("simd_per_cu")?;
+            return (per_cu > 0 && simds > 0).then(|| simds / per_cu);
+        }
+    }
+    None
+}
+
+/// HSA_CU_MASK giving the container's only GPU the first `fraction` of its
+/// compute units
+pub fn cu_mask(compute_units: u32, fraction: f64) -> String {
+    let units = ((compute_units as f64 * fraction).ceil() as u32).clamp(1, compute_units.max(1));
+    format!("0:0-{}", units - 1)
+}
+
+/// Undo a slice; `remaining` are the slices that stay
+async fn release(data_dir: &Path, slice: &Slice, remaining: &[Slice]) -> Result<()> {
+    match &slice.mechanism {
+        Mechanism::Mig {
+            gpu_instance,
+            compute_instance,
+            uuid,
+            ..
+        } => {
+            let index = slice.gpu.nvidia_index.unwrap_or_default().to_string();
+            let gi = gpu_instance.to_string();
+            nvidia_smi(&[
+                "mig",
+                "-i",
+                &index,
+                "-gi",
+                &gi,
+                "-ci",
+                &compute_instance.to_string(),
+                "-dci",
+            ])
+            .await?;
+            nvidia_smi(&["mig", "-i", &index, "-gi", &gi, "-dgi"]).await?;
+            info!("🧩 Destroyed MIG instance {}", uuid);
+        }
+        Mechanism::Mps { .. } => {
+            let shared = remaining.iter().any(|s| {
+                s.gpu.pci_bus_id == slice.gpu.pci_bus_id
+                    && matches!(s.mechanism, Mechanism::Mps { .. })
+            });
+            if shared {
+                enforce_mps_limits(data_dir, &slice.gpu, remaining).await?;
+            } else {
+                mps_control(&mps_dir(data_dir, &slice.gpu.pci_bus_id), "quit").await?;
+                info!(
+                    "🔀 Stopped the CUDA MPS daemon for GPU {}",
+                    slice.gpu.pci_bus_id
+                );
+            }
+        }
+        Mechanism::TimeSlice { .. } => {}
+    }
+    info!(
+        "🍰 Released the GPU slice of {} on {}",
+        slice.container, slice.gpu.pci_bus_id
+    );
+    Ok(())
+}
+
+/// Processes in the same cgroup as a container's init process, or below it
+fn container_pids(proc_root: &Path, init_pid: u32) -> HashSet<u32> {
+    let cgroup = |pid: &str| {
+        std::fs::read_to_string(proc_root.join(pid).join("cgroup"))
+            .ok()
+            .and_then(|c| {
+                c.lines()
+                    .last()
+                    .and_then(|l| l.splitn(3, ':').nth(2))
+                    .map(str::to_string)
+            })
+    };
+    let mut pids = HashSet::from([init_pid]);
+    let Some(root) = cgroup(&init_pid.to_string()).filter(|c| c != "/") else {
+        return pids;
+    };
+    for entry in std::fs::read_dir(proc_root).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Ok(pid) = name.parse::<u32>()
+            && cgroup(&name).is_some_and(|c| c == root || c.starts_with(&format!("{}/", root)))
+        {
+            pids.insert(pid);
+        }
+    }
+    pids
+}
+
+/// VRAM in MiB and engine busy time in ns of the DRM clients `pids` hold
+/// open on the GPU, from `/proc/<pid>/fdinfo`
+pub fn drm_usage(proc_root: &Path, pids: &HashSet<u32>, pci_bus_id: &str) -> (u64, u64) {
+    let mut clients = HashSet::new();
+    let (mut vram_kib, mut busy_ns) = (0u64, 0u64);
+    for pid in pids {
+        let Ok(entries) = std::fs::read_dir(proc_root.join(pid.to_string()).join("fdinfo")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(info) = std::fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let fields: HashMap<&str, &str> = info
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .collect();
+            if fields.get("drm-pdev") != Some(&pci_bus_id) {
+                continue;
+            }
+            // Every descriptor of a client reports the same client
+            if !fields
+                .get("drm-client-id")
+                .is_some_and(|id| clients.insert(id.to_string()))
+            {
+                continue;
+            }
+            let number = |value: &str| {
+                value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            for (key, value) in &fields {
+                if *key == "drm-memory-vram" {
+                    vram_kib += if value.ends_with("MiB") {
+                        number(value) * 1024
+                    } else {
+                        number(value)
+                    };
+                } else if key.starts_with("drm-engine-") && value.ends_with("ns") {
+                    busy_ns += number(value);
+                }
+            }
+        }
+    }
+    (vram_kib / 1024, busy_ns)
+}
+
+/// Per-process GPU memory in MiB from `nvidia-smi --query-compute-apps`
+fn parse_compute_apps(output: &str) -> HashMap<u32, u64> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(','))
+        .filter_map(|(pid, memory)| Some((pid.trim().parse().ok()?, memory.trim().parse().ok()?)))
+        .collect()
+}
+
+/// Per-process SM utilization from `nvidia-smi pmon -c 1 -s u`
+fn parse_pmon(output: &str) -> HashMap<u32, f64> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some((fields.get(1)?.parse().ok()?, fields.get(3)?.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Remembers busy times between samples to turn them into utilization
+#[derive(Debug, Default)]
+pub struct Sampler {
+    busy: HashMap<String, (u64, Instant)>,
+}
+
+impl Sampler {
+    fn utilization(&mut self, container: &str, busy_ns: u64) -> Option<f64> {
+        let now = Instant::now();
+        let previous = self.busy.insert(container.to_string(), (busy_ns, now));
+        let (before, at) = previous?;
+        let elapsed = now.duration_since(at).as_nanos() as f64;
+        (elapsed > 0.0)
+            .then(|| (busy_ns.saturating_sub(before) as f64 / elapsed * 100.0).min(100.0))
+    }
+}
+
+/// Release the slices of containers that are gone and sample the rest.
+/// Returns how many slices are left and what went wrong. Run by the GPU
+/// arbiter.
+pub async fn sweep(runtime: &str, data_dir: &Path, sampler: &mut Sampler) -> (usize, Vec<String>) {
+    let mut errors = Vec::new();
+    let _lock = match lock(data_dir) {
+        Ok(lock) => lock,
+        Err(e) => return (0, vec![format!("GPU slices: {:#}", e)]),
+    };
+    let all = slices(data_dir);
+    if all.is_empty() {
+        return (0, errors);
+    }
+
+    let running: HashMap<String, u32> = priority::holders(runtime)
+        .await
+        .into_iter()
+        .map(|h| (h.container, h.pid))
+        .collect();
+    let now = Utc::now();
+    let (mut kept, gone): (Vec<Slice>, Vec<Slice>) = all.into_iter().partition(|s| {
+        running.contains_key(&s.container)
+            || (now - s.created_at).num_seconds() < STARTUP_GRACE_SECS
+    });
+    for slice in gone {
+        // A slice that can't be undone is dropped all the same, so the
+        // arbiter isn't kept alive by it
+        if let Err(e) = release(data_dir, &slice, &kept).await {
+            errors.push(format!("GPU slice of {}: {:#}", slice.container, e));
+        }
+    }
+
+    let (memory, utilization) = if kept.iter().any(|s| s.gpu.vendor == GpuVendor::Nvidia) {
+        (
+            nvidia_smi(&[
+                "--query-compute-apps=pid,used_memory",
+                "--format=csv,noheader,nounits",
+            ])
+            .await
+            .map(|o| parse_compute_apps(&o))
+            .unwrap_or_default(),
+            nvidia_smi(&["pmon", "-c", "1", "-s", "u"])
+                .await
+                .map(|o| parse_pmon(&o))
+                .unwrap_or_default(),
+        )
+    } else {
+        Default::default()
+    };
+    let proc_root = Path::new("/proc");
+    for slice in &mut kept {
+        let Some(&pid) = running.get(&slice.container) else {
+            continue;
+        };
+        let pids = container_pids(proc_root, pid);
+        let usage = match slice.gpu.vendor {
+            GpuVendor::Nvidia => {
+                let sm: Vec<f64> = pids
+                    .iter()
+                    .filter_map(|p| utilization.get(p))
+                    .copied()
+                    .collect();
+                SliceUsage {
+                    memory_used_mb: pids.iter().filter_map(|p| memory.get(p)).sum(),
+                    utilization_percent: (!sm.is_empty())
+                        .then(|| sm.iter().sum::<f64>().min(100.0)),
+                    sampled_at: Some(now),
+                }
+            }
+            GpuVendor::Amd => {
+                let (memory_used_mb, busy_ns) = drm_usage(proc_root, &pids, &slice.gpu.pci_bus_id);
+                SliceUsage {
+                    memory_used_mb,
+                    utilization_percent: sampler.utilization(&slice.container, busy_ns),
+                    sampled_at: Some(now),
+                }
+            }
+        };
+        slice.usage = Some(usage);
+        if slice.over_memory() {
+            errors.push(format!(
+                "{} uses {} MiB of GPU {}, over its {} MiB share",
+                slice.container,
+                slice.usage.as_ref().map_or(0, |u| u.memory_used_mb),
+                slice.gpu.pci_bus_id,
+                slice.memory_limit_mb.unwrap_or_default()
+            ));
+        }
+    }
+    if let Err(e) = save(data_dir, &kept) {
+        errors.push(format!("GPU slices: {:#}", e));
+    }
+    (kept.len(), errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpu(pci: &str, vendor: GpuVendor) -> TopologyGpu {
+        TopologyGpu {
+            pci_bus_id: pci.to_string(),
+            vendor,
+            numa_node: None,
+            nvidia_index: (vendor == GpuVendor::Nvidia).then_some(0),
+            devices: vec!["/dev/nvidia0".to_string()],
+        }
+    }
+
+    #[test]
+    fn places_and_carves_gpu_shares() {
+        // The fullest GPU the share fits on; whole-GPU holders are left alone
+        let gpus = vec![
+            gpu("0000:01:00.0", GpuVendor::Nvidia),
+            gpu("0000:02:00.0", GpuVendor::Nvidia),
+            gpu("0000:03:00.0", GpuVendor::Nvidia),
+        ];
+        let shares = HashMap::from([
+            ("0000:01:00.0".to_string(), 0.5),
+            ("0000:02:00.0".to_string(), 0.75),
+        ]);
+        let none = HashSet::new();
+        assert_eq!(choose(&gpus, 0.25, &shares, &none, &[]), Some(1));
+        assert_eq!(choose(&gpus, 0.5, &shares, &none, &[]), Some(0));
+        let whole = HashSet::from(["0000:03:00.0".to_string()]);
+        assert_eq!(choose(&gpus, 0.75, &shares, &whole, &[]), None);
+        assert_eq!(choose(&gpus, 0.75, &shares, &none, &[]), Some(2));
+
+        let lgip = "\
+| GPU   Name             ID    Instances   Memory     P2P    SM    DEC   ENC  |
+|=============================================================================|
+|   0  MIG 1g.5gb        19     0/7        4.75       No     14     0     0   |
+|                                                             1     0     0   |
++-----------------------------------------------------------------------------+
+|   0  MIG 1g.5gb+me     20     1/1        4.75       No     14     1     0   |
+|   0  MIG 2g.10gb       14     3/3        9.75       No     28     1     0   |
+|   0  MIG 3g.20gb        9     2/2        19.62      No     42     2     0   |
+|   0  MIG 7g.40gb        0     1/1        39.50      No     98     5     0   |
+";
+        let profiles = parse_mig_profiles(lgip);
+        assert_eq!(profiles.len(), 5);
+        // 1g.5gb is used up, so a seventh of the GPU takes a 2g
+        assert_eq!(pick_profile(&profiles, 0.1).unwrap().name, "2g.10gb");
+        assert_eq!(pick_profile(&profiles, 0.25).unwrap().name, "2g.10gb");
+        assert_eq!(pick_profile(&profiles, 0.4).unwrap().name, "3g.20gb");
+        assert_eq!(pick_profile(&profiles, 1.0).unwrap().id, 0);
+        assert_eq!(
+            parse_created(
+                "Successfully created GPU instance ID  5 on GPU  0 using profile MIG 2g.10gb (ID 14)\n\
+                 Successfully created compute instance ID  0 on GPU  0 GPU instance ID  5 using profile MIG 2g.10gb (ID  1)\n"
+            ),
+            Some((5, 0))
+        );
+        assert_eq!(
+            mig_uuids("GPU 0: A100 (UUID: GPU-1)\n  MIG 2g.10gb Device 0: (UUID: MIG-ab12)\n"),
+            HashSet::from(["MIG-ab12".to_string()])
+        );
+
+        let mps = Slice {
+            container: "train".to_string(),
+            gpu: gpu("0000:01:00.0", GpuVendor::Nvidia),
+            fraction: 0.25,
+            mechanism: Mechanism::Mps {
+                pipe_dir: PathBuf::from("/data/gpu/mps/pipe"),
+                active_thread_percentage: 25,
+            },
+            memory_limit_mb: Some(6144),
+            created_at: Utc::now(),
+            usage: Some(SliceUsage {
+                memory_used_mb: 7000,
+                ..Default::default()
+            }),
+        };
+        let args = mps.run_args().join(" ");
+        assert!(args.contains("--label bolt.gpu-fraction=0.25"));
+        assert!(args.contains("-v /data/gpu/mps/pipe:/tmp/nvidia-mps --ipc host"));
+        assert!(args.contains("-e CUDA_MPS_ACTIVE_THREAD_PERCENTAGE=25"));
+        assert!(args.contains("-e CUDA_MPS_PINNED_DEVICE_MEM_LIMIT=0=6144M"));
+        assert!(mps.over_memory());
+
+        let mut larger = mps.clone();
+        larger.fraction = 0.5;
+        larger.mechanism = Mechanism::Mps {
+            pipe_dir: PathBuf::from("/data/gpu/mps/pipe"),
+            active_thread_percentage: 50,
+        };
+        larger.memory_limit_mb = Some(12288);
+        let mut elsewhere = larger.clone();
+        elsewhere.gpu = gpu("0000:02:00.0", GpuVendor::Nvidia);
+        elsewhere.mechanism = Mechanism::Mps {
+            pipe_dir: PathBuf::from("/data/gpu/mps/pipe"),
+            active_thread_percentage: 100,
+        };
+        let on_gpu = [mps.clone(), larger.clone(), elsewhere];
+        assert_eq!(
+            mps_ceiling("0000:01:00.0", &on_gpu),
+            Some((50, Some(12288)))
+        );
+        larger.memory_limit_mb = None;
+        assert_eq!(
+            mps_ceiling("0000:01:00.0", &[mps.clone(), larger]),
+            Some((50, None))
+        );
+        assert_eq!(mps_ceiling("0000:03:00.0", &on_gpu), None);
+
+        // AMD: compute units from the KFD topology, usage from fdinfo
+        let dir = tempfile::tempdir().unwrap();
+        let node = dir.path().join("nodes").join("1");
+        std::fs::create_dir_all(&node).unwrap();
+        std::fs::write(
+            node.join("properties"),
+            "simd_count 240\nsimd_per_cu 4\nlocation_id 768\ndomain 0\n",
+        )
+        .unwrap();
+        let units = amd_compute_units(&dir.path().join("nodes"), "0000:03:00.0").unwrap();
+        assert_eq!(units, 60);
+        assert_eq!(cu_mask(units, 0.25), "0:0-14");
+        assert_eq!(
+            amd_compute_units(&dir.path().join("nodes"), "0000:04:00.0"),
+            None
+        );
+
+        let fdinfo = dir.path().join("proc").join("42").join("fdinfo");
+        std::fs::create_dir_all(&fdinfo).unwrap();
+        let client = "drm-driver:\tamdgpu\ndrm-pdev:\t0000:03:00.0\ndrm-client-id:\t7\n\
+                      drm-memory-vram:\t2097152 KiB\ndrm-engine-gfx:\t1000 ns\ndrm-engine-compute:\t500 ns\n";
+        std::fs::write(fdinfo.join("5"), client).unwrap();
+        // Another descriptor of the same client isn't counted twice
+        std::fs::write(fdinfo.join("6"), client).unwrap();
+        assert_eq!(
+            drm_usage(
+                &dir.path().join("proc"),
+                &HashSet::from([42]),
+                "0000:03:00.0"
+            ),
+            (2048, 1500)
+        );
+
+        assert!(
+            validate(
+                &GpuConfig {
+                    fraction: Some(0.25),
+                    ..amd_gpu()
+                },
+                0.25
+            )
+            .is_ok()
+        );
+        assert!(validate(&amd_gpu(), 1.5).is_err());
+        assert!(
+            validate(
+                &GpuConfig {
+                    count: Some(2),
+                    ..amd_gpu()
+                },
+                0.5
+            )
+            .is_err()
+        );
+    }
+
+    fn amd_gpu() -> GpuConfig {
+        serde_json::from_value(serde_json::json!({ "amd": {} })).unwrap()
+    }
+}
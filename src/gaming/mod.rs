@@ -6,10 +6,21 @@ use tracing::{debug, info, warn};
 
 pub mod advanced_optimizations;
 pub mod audio;
+pub mod benchmark;
+pub mod display;
 pub mod display_tech;
+pub mod drivers;
+pub mod fraction;
 pub mod frame_pacing;
+pub mod multi_gpu;
+pub mod prefix;
+pub mod priority;
+pub mod proton;
 pub mod realtime;
+pub mod recovery;
 pub mod rtx_features;
+pub mod thermal;
+pub mod topology;
 pub mod wayland;
 // Gaming commands enum for API usage
 #[derive(Debug, Clone)]
@@ -37,6 +48,52 @@ pub async fn handle_gpu_command(command: GpuCommands) -> Result<()> {
     }
 }
 
+/// GPUs and their partitions, with the containers allocated to each
+#[cfg(feature = "gaming")]
+pub async fn list_allocations(data_dir: &std::path::Path, json: bool) -> Result<()> {
+    use crate::runtime::gpu::allocation;
+
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let units = allocation::report(&runtime, data_dir);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&units)?);
+        return Ok(());
+    }
+    if units.is_empty() {
+        println!("No GPUs bound to nvidia, amdgpu, i915 or xe found");
+        return Ok(());
+    }
+    println!(
+        "{:<22} {:<7} {:<26} {:<10} CONTAINERS",
+        "ID", "VENDOR", "KIND", "MODE"
+    );
+    for report in &units {
+        let mode = match report.holders.first() {
+            Some(grant) => grant.mode.as_str(),
+            None => "-",
+        };
+        let holders: Vec<&str> = report.holders.iter().map(|g| g.holder.as_str()).collect();
+        println!(
+            "{:<22} {:<7} {:<26} {:<10} {}",
+            report.unit.id,
+            format!("{:?}", report.unit.device.vendor).to_lowercase(),
+            report.unit.describe(),
+            mode,
+            if holders.is_empty() {
+                "-".to_string()
+            } else {
+                holders.join(", ")
+            }
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "gaming"))]
+pub async fn list_allocations(_data_dir: &std::path::Path, _json: bool) -> Result<()> {
+    list_gpus().await
+}
+
 pub async fn list_gpus() -> Result<()> {
     info!("🖥️  Listing available GPUs...");
 
@@ -206,12 +263,16 @@ async fn setup_docker_amd_gpu(device_id: u32) -> Result<()> {
     Ok(())
 }
 
-pub async fn setup_wine(proton: Option<&str>, winver: Option<&str>) -> Result<()> {
+pub async fn setup_wine(
+    data_dir: &std::path::Path,
+    proton: Option<&str>,
+    winver: Option<&str>,
+) -> Result<()> {
     info!("🍷 Setting up Wine/Proton...");
 
     if let Some(proton_version) = proton {
         info!("  Proton version: {}", proton_version);
-        setup_proton(proton_version).await?;
+        setup_proton(data_dir, proton_version).await?;
     }
 
     if let Some(windows_version) = winver {
@@ -233,23 +294,17 @@ pub async fn setup_wine(proton: Option<&str>, winver: Option<&str>) -> Result<()
     Ok(())
 }
 
-async fn setup_proton(version: &str) -> Result<()> {
+async fn setup_proton(data_dir: &std::path::Path, version: &str) -> Result<()> {
     info!("🚀 Setting up Proton {}", version);
 
-    // Check for Steam Proton installations
-    let steam_dir = dirs::home_dir()
-        .map(|home| home.join(".steam/steam/steamapps/common"))
-        .unwrap_or_else(|| std::path::PathBuf::from("/home/.steam/steam/steamapps/common"));
-
-    let proton_path = steam_dir.join(format!("Proton {}", version));
-
-    if proton_path.exists() {
-        info!("  ✅ Proton {} found at {:?}", version, proton_path);
-    } else {
-        info!(
+    // GE releases missing from the shared cache are downloaded now
+    let spec = proton::Spec::parse(version)?;
+    match proton::ensure(data_dir, &spec).await? {
+        Some(tool) => info!("  ✅ {} {} at {:?}", tool.flavor, tool.tag, tool.path),
+        None => info!(
             "  📥 Proton {} not found, will use container-based Proton",
             version
-        );
+        ),
     }
 
     info!("✅ Proton configuration ready");
@@ -601,7 +656,7 @@ pub async fn apply_realtime_optimizations(enable: bool) -> Result<()> {
     Ok(())
 }
 
-pub async fn optimize_game_process(pid: u32) -> Result<()> {
+pub async fn optimize_game_process(pid: u32, hybrid_aware: bool) -> Result<()> {
     info!("🎯 Applying gaming optimizations to process: {}", pid);
 
     let config = realtime::RealtimeGamingConfig::default();
@@ -616,10 +671,75 @@ pub async fn optimize_game_process(pid: u32) -> Result<()> {
             })
         })?;
 
+    if hybrid_aware {
+        optimize_hybrid_placement(pid).await?;
+    }
+
     info!("✅ Gaming optimizations applied to process {}", pid);
     Ok(())
 }
 
+/// Pin a game to P-cores, raise their EPP, then check that every thread's
+/// affinity mask actually excludes the E-cores
+async fn optimize_hybrid_placement(pid: u32) -> Result<()> {
+    use crate::optimizations::hybrid::{
+        self, CorePlacement, CpuTopology, EnergyPerformancePreference,
+    };
+
+    let topology = CpuTopology::detect()?;
+    if !topology.hybrid {
+        warn!("No hybrid (P/E core) CPU detected, skipping hybrid-aware placement");
+        return Ok(());
+    }
+
+    info!(
+        "🧩 Hybrid CPU: P-cores {}, E-cores {}",
+        hybrid::format_cpu_list(&topology.p_cores),
+        hybrid::format_cpu_list(&topology.e_cores)
+    );
+    if topology.itmt_enabled == Some(false) {
+        warn!("ITMT is disabled (kernel.sched_itmt_enabled=0); the scheduler won't favour P-cores");
+    }
+
+    let p_cores = topology.cpus_for(CorePlacement::PCores);
+    hybrid::pin_process(pid, &p_cores).await?;
+
+    let preference = EnergyPerformancePreference::Performance;
+    match hybrid::set_energy_performance_preference(&p_cores, preference) {
+        Ok(updated) => debug!("EPP set to performance on {} P-cores", updated),
+        Err(e) => warn!("Could not set EPP on P-cores: {}", e),
+    }
+
+    let check = hybrid::check_affinity(pid, &p_cores)?;
+    if !check.is_valid() {
+        for thread in &check.violations {
+            warn!(
+                "  Thread {} may run on CPUs {}",
+                thread.tid,
+                hybrid::format_cpu_list(&thread.allowed)
+            );
+        }
+        return Err(BoltError::Gaming(
+            crate::error::GamingError::OptimizationFailed {
+                reason: format!(
+                    "{} of {} threads of process {} are not confined to P-cores",
+                    check.violations.len(),
+                    check.threads,
+                    pid
+                ),
+            },
+        ));
+    }
+
+    info!(
+        "📌 All {} threads of process {} confined to P-cores {}",
+        check.threads,
+        pid,
+        hybrid::format_cpu_list(&p_cores)
+    );
+    Ok(())
+}
+
 pub async fn get_gaming_performance_report() -> Result<()> {
     info!("📊 Generating gaming performance report");
 
@@ -0,0 +1,464 @@
+// Multi-GPU AI mode
+//
+// `[services.<name>.gaming.gpu.ai]` prepares a container for NCCL (or RCCL)
+// training across its GPUs:
+//
+// - peer-to-peer: NCCL_P2P_LEVEL is set to the furthest link between the
+//   container's GPUs, so NCCL moves data over NVLink or PCIe instead of
+//   staging it through host memory; `p2p = false` sets NCCL_P2P_DISABLE
+// - GPUDirect RDMA: with InfiniBand present, /dev/infiniband is passed in
+//   and, when the nvidia_peermem module is loaded, NCCL_NET_GDR_LEVEL set
+// - IPC: NCCL's shared memory transport needs far more than the 64MB
+//   /dev/shm default, so it gets 1GB per GPU (at least 2GB), memlock is
+//   unlimited and `ipc = "host"` lets processes in different containers meet
+// - preflight: `nvidia-smi topo -p2p r` must report P2P between every pair
+//   of GPUs, and with the CUDA samples' p2pBandwidthLatencyTest on the PATH
+//   the bandwidth between them is measured against min_bandwidth_gbps
+//
+// The GPUs are the placed ones (`gpu.count`, `gpu.topology`), or every GPU
+// of the configured vendor without a placement. What was used is kept in
+// <data_dir>/gpu/p2p/<container>.json for `bolt gaming gpu p2p`.
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use super::recovery::GpuVendor;
+use super::topology::{self, GpuTopology, Link, Placement, TopologyGpu};
+use crate::config::{GpuAiConfig, GpuConfig};
+
+/// Bandwidth test from the CUDA samples
+const BANDWIDTH_TEST: &str = "p2pBandwidthLatencyTest";
+const BANDWIDTH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What a container was set up with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiReport {
+    pub container: String,
+    /// PCI addresses of the container's GPUs
+    pub gpus: Vec<String>,
+    /// The furthest link between any two of them
+    pub link: Link,
+    pub p2p: bool,
+    /// nvidia_peermem is loaded, so NICs can reach GPU memory directly
+    pub peermem: bool,
+    pub infiniband: bool,
+    pub ipc: String,
+    pub shm_size: Option<String>,
+    /// `p2p_supported[a][b]`, indexed like `gpus`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2p_supported: Option<Vec<Vec<bool>>>,
+    /// Unidirectional P2P bandwidth in GB/s, indexed like `gpus`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_gbps: Option<Vec<Vec<f64>>>,
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AiReport {
+    /// Arguments for `podman/docker run`
+    pub fn run_args(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("--ipc={}", self.ipc),
+            "--ulimit".to_string(),
+            "memlock=-1:-1".to_string(),
+        ];
+        if let Some(ref size) = self.shm_size {
+            args.push(format!("--shm-size={}", size));
+        }
+        if self.infiniband {
+            args.extend(["--device".to_string(), "/dev/infiniband".to_string()]);
+        }
+        for (key, value) in &self.env {
+            args.extend(["-e".to_string(), format!("{}={}", key, value)]);
+        }
+        args
+    }
+
+    /// The slowest measured link between two different GPUs
+    pub fn min_bandwidth(&self) -> Option<f64> {
+        let matrix = self.bandwidth_gbps.as_ref()?;
+        matrix
+            .iter()
+            .enumerate()
+            .flat_map(|(a, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(move |(b, _)| *b != a)
+                    .map(|(_, bw)| *bw)
+            })
+            .reduce(f64::min)
+    }
+}
+
+/// NCCL_P2P_LEVEL allowing P2P across `link`
+fn nccl_p2p_level(link: Link) -> &'static str {
+    match link {
+        Link::Same | Link::NvLink => "NVL",
+        Link::PcieSwitch => "PXB",
+        Link::HostBridge | Link::Numa => "PHB",
+        Link::System => "SYS",
+    }
+}
+
+/// Bolt's NCCL defaults, with the service's `nccl_env` on top
+pub fn environment(
+    ai: &GpuAiConfig,
+    link: Link,
+    peermem: bool,
+    infiniband: bool,
+) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    let mut set = |key: &str, value: &str| env.insert(key.to_string(), value.to_string());
+    // Number GPUs like nvidia-smi and NVIDIA_VISIBLE_DEVICES do
+    set("CUDA_DEVICE_ORDER", "PCI_BUS_ID");
+    set("NCCL_DEBUG", "WARN");
+    if ai.p2p {
+        set("NCCL_P2P_LEVEL", nccl_p2p_level(link));
+    } else {
+        set("NCCL_P2P_DISABLE", "1");
+    }
+    if !infiniband {
+        set("NCCL_IB_DISABLE", "1");
+    } else if peermem {
+        set("NCCL_NET_GDR_LEVEL", "PHB");
+    }
+    env.extend(ai.nccl_env.clone());
+    env
+}
+
+/// /dev/shm for `gpus` GPUs; None leaves it to the IPC namespace's owner
+fn shm_size(ai: &GpuAiConfig, gpus: usize) -> Option<String> {
+    if ai.ipc == "host" {
+        return None;
+    }
+    match ai.shm_size.as_str() {
+        "auto" => Some(format!("{}g", gpus.max(2))),
+        size => Some(size.to_string()),
+    }
+}
+
+/// `nvidia-smi topo -p2p r`: whether each pair of GPUs supports P2P reads
+pub fn parse_p2p_matrix(output: &str) -> Vec<Vec<bool>> {
+    topology::matrix_cells(output)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|c| matches!(c.as_deref(), Some("X" | "OK")))
+                .collect()
+        })
+        .collect()
+}
+
+/// The "Unidirectional P2P=Enabled Bandwidth" matrix of
+/// p2pBandwidthLatencyTest, in GB/s
+pub fn parse_bandwidth(output: &str) -> Option<Vec<Vec<f64>>> {
+    let mut lines = output
+        .lines()
+        .skip_while(|l| !l.contains("Unidirectional P2P=Enabled Bandwidth"))
+        .skip(1);
+    // "   D\D     0      1"
+    lines.next()?;
+    let matrix: Vec<Vec<f64>> = lines
+        .map(|l| l.split_whitespace().collect::<Vec<_>>())
+        .take_while(|cells| cells.len() > 1 && cells[0].parse::<u32>().is_ok())
+        .map(|cells| cells[1..].iter().filter_map(|c| c.parse().ok()).collect())
+        .collect();
+    (!matrix.is_empty()).then_some(matrix)
+}
+
+/// Set a container up for multi-GPU training and record what it got
+pub async fn prepare(
+    data_dir: &Path,
+    container: &str,
+    gpu: &GpuConfig,
+    ai: &GpuAiConfig,
+    placement: Option<&Placement>,
+) -> Result<AiReport> {
+    if !matches!(ai.ipc.as_str(), "private" | "shareable" | "host") {
+        bail!("Invalid gpu.ai.ipc {} (private, shareable or host)", ai.ipc);
+    }
+    let topology = GpuTopology::detect().await?;
+    let gpus: Vec<TopologyGpu> = match placement {
+        Some(placement) => placement.gpus.clone(),
+        None => {
+            let vendor = if gpu.amd.is_some() && gpu.nvidia.is_none() {
+                GpuVendor::Amd
+            } else {
+                GpuVendor::Nvidia
+            };
+            topology
+                .gpus
+                .iter()
+                .filter(|g| g.vendor == vendor)
+                .cloned()
+                .collect()
+        }
+    };
+    let indices: Vec<usize> = gpus
+        .iter()
+        .filter_map(|g| {
+            topology
+                .gpus
+                .iter()
+                .position(|t| t.pci_bus_id == g.pci_bus_id)
+        })
+        .collect();
+    let link = indices
+        .iter()
+        .flat_map(|&a| indices.iter().map(move |&b| (a, b)))
+        .map(|(a, b)| topology.links[a][b])
+        .max()
+        .unwrap_or(Link::Same);
+
+    let peermem = Path::new("/sys/module/nvidia_peermem").exists();
+    let infiniband = std::fs::read_dir("/sys/class/infiniband")
+        .is_ok_and(|mut entries| entries.next().is_some());
+    let mut notes = Vec::new();
+    if infiniband && !peermem {
+        notes.push(
+            "InfiniBand without nvidia_peermem: no GPUDirect RDMA (modprobe nvidia_peermem)"
+                .to_string(),
+        );
+    }
+
+    let mut report = AiReport {
+        container: container.to_string(),
+        gpus: gpus.iter().map(|g| g.pci_bus_id.clone()).collect(),
+        link,
+        p2p: ai.p2p,
+        peermem,
+        infiniband,
+        ipc: ai.ipc.clone(),
+        shm_size: shm_size(ai, gpus.len()),
+        p2p_supported: None,
+        bandwidth_gbps: None,
+        env: environment(ai, link, peermem, infiniband),
+        notes,
+        created_at: Utc::now(),
+    };
+
+    let nvidia: Vec<u32> = gpus.iter().filter_map(|g| g.nvidia_index).collect();
+    if ai.p2p && ai.preflight && nvidia.len() > 1 {
+        preflight(ai, &nvidia, &mut report).await?;
+    } else if ai.min_bandwidth_gbps.is_some() && nvidia.len() > 1 {
+        bail!("gpu.ai.min_bandwidth_gbps needs p2p and preflight enabled");
+    }
+
+    info!(
+        "🧠 AI mode: {} GPU(s), {} ({}), P2P {}",
+        report.gpus.len(),
+        link.label(),
+        report
+            .env
+            .get("NCCL_P2P_LEVEL")
+            .map_or("off", |l| l.as_str()),
+        match report.min_bandwidth() {
+            Some(bw) => format!("≥ {:.1} GB/s", bw),
+            None if ai.p2p => "unmeasured".to_string(),
+            None => "disabled".to_string(),
+        }
+    );
+    for note in &report.notes {
+        warn!("  {}", note);
+    }
+
+    let path = report_path(data_dir, container);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(&report)?)?;
+    Ok(report)
+}
+
+/// Check P2P support between the GPUs and measure it when the bandwidth
+/// test is installed
+async fn preflight(ai: &GpuAiConfig, nvidia: &[u32], report: &mut AiReport) -> Result<()> {
+    let output = AsyncCommand::new("nvidia-smi")
+        .args(["topo", "-p2p", "r"])
+        .output()
+        .await
+        .context("Failed to run nvidia-smi topo -p2p r")?;
+    if output.status.success() {
+        let matrix = parse_p2p_matrix(&String::from_utf8_lossy(&output.stdout));
+        let supported: Vec<Vec<bool>> = nvidia
+            .iter()
+            .map(|&a| {
+                nvidia
+                    .iter()
+                    .map(|&b| {
+                        matrix
+                            .get(a as usize)
+                            .and_then(|row| row.get(b as usize))
+                            .copied()
+                            .unwrap_or(false)
+                    })
+                    .collect()
+            })
+            .collect();
+        if supported.iter().flatten().any(|ok| !ok) {
+            report.notes.push(
+                "nvidia-smi reports no P2P between some GPUs; NCCL will stage through host memory"
+                    .to_string(),
+            );
+        }
+        report.p2p_supported = Some(supported);
+    } else {
+        report.notes.push(format!(
+            "nvidia-smi topo -p2p failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let visible: Vec<String> = nvidia.iter().map(|i| i.to_string()).collect();
+    let measured = tokio::time::timeout(
+        BANDWIDTH_TIMEOUT,
+        AsyncCommand::new(BANDWIDTH_TEST)
+            .env("CUDA_DEVICE_ORDER", "PCI_BUS_ID")
+            .env("CUDA_VISIBLE_DEVICES", visible.join(","))
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await;
+    match measured {
+        Ok(Ok(output)) if output.status.success() => {
+            report.bandwidth_gbps = parse_bandwidth(&String::from_utf8_lossy(&output.stdout));
+        }
+        Ok(Ok(output)) => report.notes.push(format!(
+            "{} failed: {}",
+            BANDWIDTH_TEST,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Ok(Err(_)) => report.notes.push(format!(
+            "{} (CUDA samples) not found; P2P bandwidth unmeasured",
+            BANDWIDTH_TEST
+        )),
+        Err(_) => report.notes.push(format!(
+            "{} timed out after {}s",
+            BANDWIDTH_TEST,
+            BANDWIDTH_TIMEOUT.as_secs()
+        )),
+    }
+
+    if let Some(required) = ai.min_bandwidth_gbps {
+        let measured = report.min_bandwidth().ok_or_else(|| {
+            anyhow!(
+                "gpu.ai.min_bandwidth_gbps is set but P2P bandwidth couldn't be measured: {}",
+                report.notes.join("; ")
+            )
+        })?;
+        if measured < required {
+            bail!(
+                "P2P bandwidth between the GPUs is {:.1} GB/s, below gpu.ai.min_bandwidth_gbps = {:.1}",
+                measured,
+                required
+            );
+        }
+    }
+    Ok(())
+}
+
+fn report_path(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir
+        .join("gpu")
+        .join("p2p")
+        .join(format!("{}.json", container))
+}
+
+/// What each AI-mode container was set up with, by container name
+pub fn reports(data_dir: &Path) -> Vec<AiReport> {
+    let Ok(entries) = std::fs::read_dir(data_dir.join("gpu").join("p2p")) else {
+        return Vec::new();
+    };
+    let mut reports: Vec<AiReport> = entries
+        .flatten()
+        .filter_map(|e| std::fs::read(e.path()).ok())
+        .filter_map(|json| serde_json::from_slice(&json).ok())
+        .collect();
+    reports.sort_by(|a, b| a.container.cmp(&b.container));
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nccl_follows_the_link_and_preflight_output_parses() {
+        let ai = GpuAiConfig::default();
+        let env = environment(&ai, Link::PcieSwitch, false, false);
+        assert_eq!(env["NCCL_P2P_LEVEL"], "PXB");
+        assert_eq!(env["NCCL_IB_DISABLE"], "1");
+        assert!(!env.contains_key("NCCL_NET_GDR_LEVEL"));
+
+        let mut custom = GpuAiConfig {
+            p2p: false,
+            ..Default::default()
+        };
+        custom
+            .nccl_env
+            .insert("NCCL_DEBUG".to_string(), "INFO".to_string());
+        let env = environment(&custom, Link::NvLink, true, true);
+        assert_eq!(env["NCCL_P2P_DISABLE"], "1");
+        assert_eq!(env["NCCL_NET_GDR_LEVEL"], "PHB");
+        assert_eq!(env["NCCL_DEBUG"], "INFO");
+
+        assert_eq!(shm_size(&ai, 4).as_deref(), Some("4g"));
+        assert_eq!(shm_size(&ai, 1).as_deref(), Some("2g"));
+        let host = GpuAiConfig {
+            ipc: "host".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(shm_size(&host, 8), None);
+
+        let p2p = " \tGPU0\tGPU1\tGPU2\t\n GPU0\tX\tOK\tNS\t\n GPU1\tOK\tX\tNS\t\n GPU2\tNS\tNS\tX\t\n\nLegend:\n\n  X    = Self\n  OK   = Status Ok\n";
+        assert_eq!(
+            parse_p2p_matrix(p2p),
+            vec![
+                vec![true, true, false],
+                vec![true, true, false],
+                vec![false, false, true]
+            ]
+        );
+
+        let bandwidth = "\
+Unidirectional P2P=Disabled Bandwidth Matrix (GB/s)
+   D\\D     0      1
+     0 769.01  11.21
+     1  11.30 772.27
+Unidirectional P2P=Enabled Bandwidth (P2P Writes) Matrix (GB/s)
+   D\\D     0      1
+     0 769.01  48.40
+     1  48.47 772.27
+Bidirectional P2P=Disabled Bandwidth Matrix (GB/s)
+";
+        let matrix = parse_bandwidth(bandwidth).unwrap();
+        assert_eq!(matrix, vec![vec![769.01, 48.40], vec![48.47, 772.27]]);
+
+        let report = AiReport {
+            container: "trainer".to_string(),
+            gpus: vec!["0000:01:00.0".to_string(), "0000:02:00.0".to_string()],
+            link: Link::PcieSwitch,
+            p2p: true,
+            peermem: false,
+            infiniband: false,
+            ipc: "private".to_string(),
+            shm_size: Some("2g".to_string()),
+            p2p_supported: None,
+            bandwidth_gbps: Some(matrix),
+            env: BTreeMap::new(),
+            notes: Vec::new(),
+            created_at: Utc::now(),
+        };
+        assert_eq!(report.min_bandwidth(), Some(48.40));
+        let args = report.run_args();
+        assert!(args.contains(&"--ipc=private".to_string()));
+        assert!(args.contains(&"--shm-size=2g".to_string()));
+    }
+}
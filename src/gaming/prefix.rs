@@ -0,0 +1,690 @@
+// Local caches of Wine prefixes on network storage
+//
+// A prefix on a NAS makes every DLL load and every shader cache lookup a
+// network round trip, and games read thousands of small files while
+// loading. With `prefix_cache = true` in a service's `[gaming.wine]`, the
+// prefix named by `prefix` stays authoritative on the share, but the game
+// runs on a copy on local storage:
+//
+// 1. When the service starts, the copy under the data dir is brought up to
+//    date with the share and mounted at /wine (WINEPREFIX). The first start
+//    copies the whole prefix; later ones only what changed on the share.
+// 2. When the session ends (the container exits, or `surge down` takes it
+//    away), what the game changed is written back. Detached services get a
+//    `bolt prefix-watch` process that does this once the container stops.
+// 3. `bolt gaming prefix sync` syncs at any other time, e.g. to save
+//    progress mid-session or after the share was unreachable.
+//
+// Syncing is three-way: both sides are compared, by size and modification
+// time, with what they held after the last sync. A file changed on one side
+// is copied to the other, a file deleted on one side is deleted on the other.
+// A file changed on both sides, say by a session on another machine, is a
+// conflict: both versions are left alone until a sync with `--prefer local`
+// or `--prefer remote` picks one. A share that is missing or suddenly empty
+// is taken as unmounted, never as everything having been deleted.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::config::{BoltConfig, WineConfig};
+use crate::runtime::watcher::{self, Watcher};
+
+/// Where the local copy is mounted in the container
+pub const MOUNT: &str = "/wine";
+pub const LABEL: &str = "bolt.wine-prefix";
+/// How often `bolt prefix-watch` checks whether the container still runs
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Which side wins a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Prefer {
+    Local,
+    Remote,
+}
+
+/// What a path is, as far as syncing is concerned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Stamp {
+    File { size: u64, mtime_ns: u64 },
+    Dir,
+    Symlink { target: String },
+}
+
+/// A path's stamps on both sides after it was last synced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Synced {
+    local: Stamp,
+    remote: Stamp,
+}
+
+type Base = BTreeMap<String, Synced>;
+
+/// A service's cached prefix
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixState {
+    pub service: String,
+    pub container: String,
+    /// The authoritative prefix on the share
+    pub remote: PathBuf,
+    /// The copy the container runs on
+    pub local: PathBuf,
+    pub synced_at: Option<DateTime<Utc>>,
+    /// Paths changed on both sides since the last sync
+    pub conflicts: Vec<String>,
+    pub watcher_pid: Option<u32>,
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PrefixState {
+    /// Whether a session's write-back is still pending
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+/// What one sync did
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub container: String,
+    /// Paths copied or deleted from the share to the local copy
+    pub pulled: usize,
+    /// Paths copied or deleted from the local copy to the share
+    pub pushed: usize,
+    pub bytes: u64,
+    pub conflicts: Vec<String>,
+}
+
+/// Whether a service runs on a local copy of its prefix, and where the
+/// prefix is
+pub fn cached_prefix(wine: &WineConfig) -> Option<PathBuf> {
+    if wine.prefix_cache != Some(true) {
+        return None;
+    }
+    wine.prefix.as_ref().map(PathBuf::from)
+}
+
+const WATCHER: Watcher = Watcher::new("prefix-watch");
+
+fn prefix_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("wine-prefix")
+}
+
+fn container_dir(data_dir: &Path, container: &str) -> PathBuf {
+    prefix_dir(data_dir).join(container)
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    container_dir(data_dir, container).join("state.json")
+}
+
+fn base_path(data_dir: &Path, container: &str) -> PathBuf {
+    container_dir(data_dir, container).join("base.json")
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    container_dir(data_dir, container).join("watch.log")
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<PrefixState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every cached prefix, by container
+pub fn list(data_dir: &Path) -> Vec<PrefixState> {
+    let mut states: Vec<PrefixState> = std::fs::read_dir(prefix_dir(data_dir))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| std::fs::read(e.path().join("state.json")).ok())
+                .filter_map(|b| serde_json::from_slice(&b).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &PrefixState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+fn load_base(data_dir: &Path, container: &str) -> Base {
+    std::fs::read(base_path(data_dir, container))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+fn save_base(data_dir: &Path, container: &str, base: &Base) -> Result<()> {
+    let path = base_path(data_dir, container);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(base)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Serializes syncs of one prefix; released when dropped
+fn lock(data_dir: &Path, container: &str) -> Result<File> {
+    let path = container_dir(data_dir, container).join("sync.lock");
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // SAFETY: flock(2) on a descriptor we own
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {}", path.display()));
+    }
+    Ok(file)
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = std::fs::symlink_metadata(path).ok()?;
+    let kind = meta.file_type();
+    if kind.is_symlink() {
+        let target = std::fs::read_link(path).ok()?;
+        Some(Stamp::Symlink {
+            target: target.to_string_lossy().to_string(),
+        })
+    } else if kind.is_dir() {
+        Some(Stamp::Dir)
+    } else if kind.is_file() {
+        let mtime_ns = meta
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Some(Stamp::File {
+            size: meta.len(),
+            mtime_ns,
+        })
+    } else {
+        // Sockets and FIFOs don't belong in a prefix
+        None
+    }
+}
+
+/// Every path under `root`, relative to it. Symlinks aren't followed;
+/// dosdevices/z: points at the root of the filesystem.
+fn scan(root: &Path) -> Result<BTreeMap<String, Stamp>> {
+    let mut stamps = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root).min_depth(1).follow_links(false) {
+        let entry = entry.with_context(|| format!("Failed to read {}", root.display()))?;
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let Some(relative) = relative.to_str() else {
+            warn!("Skipping {}: its name isn't UTF-8", entry.path().display());
+            continue;
+        };
+        if let Some(stamp) = stamp(entry.path()) {
+            stamps.insert(relative.to_string(), stamp);
+        }
+    }
+    Ok(stamps)
+}
+
+fn same_content(a: &Path, b: &Path, sa: &Stamp, sb: &Stamp) -> bool {
+    match (sa, sb) {
+        (Stamp::File { size: x, .. }, Stamp::File { size: y, .. }) => {
+            x == y
+                && match (std::fs::read(a), std::fs::read(b)) {
+                    (Ok(x), Ok(y)) => x == y,
+                    _ => false,
+                }
+        }
+        _ => sa == sb,
+    }
+}
+
+/// Make `to` what `from` is; None deletes it. Returns the bytes copied.
+fn apply(from: &Path, to: &Path, stamp: Option<&Stamp>) -> Result<u64> {
+    let existing = std::fs::symlink_metadata(to).ok();
+    let is_dir = existing.as_ref().is_some_and(|m| m.is_dir());
+    match stamp {
+        None => {
+            if is_dir {
+                // Emptied directories go once their contents are synced
+                let _ = std::fs::remove_dir(to);
+            } else if existing.is_some() {
+                std::fs::remove_file(to)
+                    .with_context(|| format!("Failed to remove {}", to.display()))?;
+            }
+            Ok(0)
+        }
+        Some(Stamp::Dir) => {
+            if existing.is_some() && !is_dir {
+                std::fs::remove_file(to)?;
+            }
+            std::fs::create_dir_all(to)
+                .with_context(|| format!("Failed to create {}", to.display()))?;
+            Ok(0)
+        }
+        Some(stamp) => {
+            if is_dir {
+                std::fs::remove_dir_all(to)
+                    .with_context(|| format!("Failed to remove {}", to.display()))?;
+            }
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            // Written next to the target and renamed over it, so an
+            // interrupted sync never leaves a truncated file behind
+            let name = to.file_name().unwrap_or_default().to_string_lossy();
+            let tmp = to.with_file_name(format!(".{}.bolt-sync", name));
+            let _ = std::fs::remove_file(&tmp);
+            let bytes = match stamp {
+                Stamp::Symlink { target } => {
+                    std::os::unix::fs::symlink(target, &tmp)
+                        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+                    0
+                }
+                _ => std::fs::copy(from, &tmp)
+                    .with_context(|| format!("Failed to copy {}", from.display()))?,
+            };
+            std::fs::rename(&tmp, to)
+                .with_context(|| format!("Failed to replace {}", to.display()))?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Bring `local` and `remote` in line with each other from what they held
+/// at the last sync
+fn reconcile(
+    local: &Path,
+    remote: &Path,
+    base: &mut Base,
+    prefer: Option<Prefer>,
+) -> Result<SyncReport> {
+    let local_stamps = scan(local)?;
+    let remote_stamps = scan(remote)?;
+    if remote_stamps.is_empty() && !base.is_empty() {
+        return Err(anyhow!(
+            "{} is empty; is the share mounted?",
+            remote.display()
+        ));
+    }
+    if local_stamps.is_empty() {
+        // A wiped cache is filled again rather than emptying the share
+        base.clear();
+    }
+
+    let paths: BTreeSet<&String> = local_stamps
+        .keys()
+        .chain(remote_stamps.keys())
+        .chain(base.keys())
+        .collect();
+    let mut report = SyncReport::default();
+    let mut settled = Vec::new();
+    let mut removed_dirs = Vec::new();
+    for path in paths {
+        let l = local_stamps.get(path);
+        let r = remote_stamps.get(path);
+        let b = base.get(path);
+        let local_changed = l != b.map(|b| &b.local);
+        let remote_changed = r != b.map(|b| &b.remote);
+        let (lp, rp) = (local.join(path), remote.join(path));
+
+        let push = match (local_changed, remote_changed) {
+            (false, false) => continue,
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => {
+                let same = match (l, r) {
+                    (None, None) => true,
+                    (Some(l), Some(r)) => same_content(&lp, &rp, l, r),
+                    _ => false,
+                };
+                if same {
+                    settled.push(path.clone());
+                    continue;
+                }
+                match prefer {
+                    Some(Prefer::Local) => true,
+                    Some(Prefer::Remote) => false,
+                    None => {
+                        report.conflicts.push(path.clone());
+                        continue;
+                    }
+                }
+            }
+        };
+        let (from, to, stamp) = if push { (&lp, &rp, l) } else { (&rp, &lp, r) };
+        if stamp.is_none() && std::fs::symlink_metadata(to).is_ok_and(|m| m.is_dir()) {
+            removed_dirs.push(to.clone());
+        } else {
+            report.bytes += apply(from, to, stamp)?;
+        }
+        if push {
+            report.pushed += 1;
+        } else {
+            report.pulled += 1;
+        }
+        settled.push(path.clone());
+    }
+    // Deepest first, so parents are empty by the time they're removed
+    for dir in removed_dirs.iter().rev() {
+        let _ = std::fs::remove_dir(dir);
+    }
+
+    for path in settled {
+        match (stamp(&local.join(&path)), stamp(&remote.join(&path))) {
+            (Some(local), Some(remote)) => {
+                base.insert(path, Synced { local, remote });
+            }
+            _ => {
+                base.remove(&path);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Sync a container's prefix with the share, holding its lock
+fn sync_blocking(data_dir: &Path, container: &str, prefer: Option<Prefer>) -> Result<SyncReport> {
+    let mut state =
+        state(data_dir, container).ok_or_else(|| anyhow!("{} has no cached prefix", container))?;
+    let _lock = lock(data_dir, container)?;
+    let result = if state.remote.is_dir() {
+        std::fs::create_dir_all(&state.local)
+            .with_context(|| format!("Failed to create {}", state.local.display()))?;
+        let mut base = load_base(data_dir, container);
+        let result = reconcile(&state.local, &state.remote, &mut base, prefer);
+        save_base(data_dir, container, &base)?;
+        result
+    } else {
+        Err(anyhow!(
+            "{} is unreachable; is the share mounted?",
+            state.remote.display()
+        ))
+    };
+
+    // The watcher may have been replaced meanwhile
+    if let Some(current) = self::state(data_dir, container) {
+        state.watcher_pid = current.watcher_pid;
+    }
+    state.updated_at = Utc::now();
+    match &result {
+        Ok(report) => {
+            state.synced_at = Some(state.updated_at);
+            state.conflicts = report.conflicts.clone();
+            state.error = None;
+        }
+        Err(e) => state.error = Some(e.to_string()),
+    }
+    save(data_dir, &state)?;
+    result.map(|mut report| {
+        report.container = container.to_string();
+        report
+    })
+}
+
+async fn sync_container(
+    data_dir: &Path,
+    container: &str,
+    prefer: Option<Prefer>,
+) -> Result<SyncReport> {
+    let data_dir = data_dir.to_path_buf();
+    let container = container.to_string();
+    tokio::task::spawn_blocking(move || sync_blocking(&data_dir, &container, prefer)).await?
+}
+
+fn log_report(report: &SyncReport) {
+    info!(
+        "🍷 Synced the prefix of {}: {} pulled, {} pushed ({:.1} MB)",
+        report.container,
+        report.pulled,
+        report.pushed,
+        report.bytes as f64 / 1_000_000.0
+    );
+    if !report.conflicts.is_empty() {
+        warn!(
+            "⚠️  {} path(s) of {} changed both locally and on the share and were left alone; run `bolt gaming prefix sync --prefer local|remote`: {}",
+            report.conflicts.len(),
+            report.container,
+            report.conflicts.join(", ")
+        );
+    }
+}
+
+/// Bring the local copy of a service's prefix up to date before the
+/// container starts, and return the arguments mounting it
+pub async fn prepare(
+    data_dir: &Path,
+    service: &str,
+    container: &str,
+    remote: &Path,
+) -> Result<Vec<String>> {
+    stop_watcher(data_dir, container);
+    let local = container_dir(data_dir, container).join("prefix");
+    let mut state = state(data_dir, container).unwrap_or_else(|| PrefixState {
+        service: service.to_string(),
+        container: container.to_string(),
+        remote: remote.to_path_buf(),
+        local: local.clone(),
+        synced_at: None,
+        conflicts: Vec::new(),
+        watcher_pid: None,
+        error: None,
+        updated_at: Utc::now(),
+    });
+    if state.remote != remote {
+        // A different prefix: the old copy and its history don't apply
+        info!("🍷 Prefix of {} moved to {}", container, remote.display());
+        let _ = std::fs::remove_dir_all(&local);
+        let _ = std::fs::remove_file(base_path(data_dir, container));
+        state.remote = remote.to_path_buf();
+        state.synced_at = None;
+        state.conflicts.clear();
+    }
+    state.watcher_pid = None;
+    save(data_dir, &state)?;
+
+    info!(
+        "🍷 Syncing the prefix of {} from {}",
+        container,
+        remote.display()
+    );
+    match sync_container(data_dir, container, None).await {
+        Ok(report) => log_report(&report),
+        Err(e) if state.synced_at.is_some() => {
+            warn!(
+                "⚠️  Could not sync the prefix of {}, running on the local copy: {}",
+                container, e
+            );
+        }
+        Err(e) => {
+            return Err(e.context(format!("Cannot cache the prefix of {}", container)));
+        }
+    }
+
+    Ok(vec![
+        "--label".to_string(),
+        format!("{}={}", LABEL, remote.display()),
+        "-v".to_string(),
+        format!("{}:{}", local.display(), MOUNT),
+        "-e".to_string(),
+        format!("WINEPREFIX={}", MOUNT),
+    ])
+}
+
+/// Write the prefix back once a detached container stops
+pub fn start(data_dir: &Path, container: &str) -> Result<()> {
+    let Some(mut state) = state(data_dir, container) else {
+        return Ok(());
+    };
+    state.watcher_pid =
+        Some(WATCHER.spawn(["prefix-watch", container], &log_path(data_dir, container))?);
+    state.updated_at = Utc::now();
+    save(data_dir, &state)
+}
+
+fn stop_watcher(data_dir: &Path, container: &str) {
+    if let Some(pid) = state(data_dir, container).and_then(|s| s.watcher_pid) {
+        WATCHER.stop(pid);
+    }
+}
+
+/// Write back what the session changed. Called once the container is gone;
+/// the local copy is kept for the next session.
+pub async fn end_session(data_dir: &Path, container: &str) {
+    if state(data_dir, container).is_none() {
+        return;
+    }
+    stop_watcher(data_dir, container);
+    match sync_container(data_dir, container, None).await {
+        Ok(report) => log_report(&report),
+        Err(e) => warn!(
+            "⚠️  Could not write the prefix of {} back; run `bolt gaming prefix sync` once the share is reachable: {}",
+            container, e
+        ),
+    }
+}
+
+/// Wait for the container to stop, then write its prefix back. Run by
+/// `bolt prefix-watch`; a SIGTERM means someone else writes it back.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+            _ = terminate.recv() => return Ok(()),
+        }
+        if state(data_dir, container).is_none() {
+            debug!("{} no longer has a cached prefix, stopping", container);
+            return Ok(());
+        }
+        if !crate::runtime::healthcheck::status(&runtime, container)
+            .await
+            .is_some_and(|s| s.running())
+        {
+            break;
+        }
+    }
+    info!("{} stopped, writing its prefix back", container);
+    let report = sync_container(data_dir, container, None).await?;
+    log_report(&report);
+    Ok(())
+}
+
+/// Sync the cached prefixes of a service, or all of them
+pub async fn sync(
+    config: &BoltConfig,
+    service: Option<&str>,
+    prefer: Option<Prefer>,
+) -> Result<Vec<SyncReport>> {
+    let states: Vec<PrefixState> = list(&config.data_dir)
+        .into_iter()
+        .filter(|s| service.is_none_or(|name| s.service == name || s.container == name))
+        .collect();
+    if states.is_empty() {
+        return Err(match service {
+            Some(name) => anyhow!("{} has no cached prefix", name),
+            None => anyhow!("No service has a cached prefix"),
+        });
+    }
+
+    let mut reports = Vec::new();
+    for state in states {
+        let report = sync_container(&config.data_dir, &state.container, prefer)
+            .await
+            .map_err(|e| e.context(format!("Cannot sync the prefix of {}", state.container)))?;
+        log_report(&report);
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn read(root: &Path, path: &str) -> Option<String> {
+        std::fs::read_to_string(root.join(path)).ok()
+    }
+
+    #[test]
+    fn reconciles_both_ways_and_detects_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (local, remote) = (dir.path().join("local"), dir.path().join("remote"));
+        std::fs::create_dir_all(&local).unwrap();
+        write(&remote, "drive_c/windows/system32/d3d11.dll", "dxvk");
+        write(&remote, "drive_c/users/steamuser/save.dat", "level 1");
+        write(&remote, "user.reg", "[Software]");
+        std::fs::create_dir_all(remote.join("dosdevices")).unwrap();
+        std::os::unix::fs::symlink("/", remote.join("dosdevices/z:")).unwrap();
+
+        // First sync copies the whole prefix, symlinks as symlinks
+        let mut base = Base::new();
+        let report = reconcile(&local, &remote, &mut base, None).unwrap();
+        assert_eq!(report.pushed, 0);
+        assert!(report.pulled >= 4);
+        assert_eq!(read(&local, "user.reg").as_deref(), Some("[Software]"));
+        assert_eq!(
+            std::fs::read_link(local.join("dosdevices/z:")).unwrap(),
+            Path::new("/")
+        );
+        let report = reconcile(&local, &remote, &mut base, None).unwrap();
+        assert_eq!((report.pulled, report.pushed), (0, 0));
+
+        // The session's changes go back, deletions included
+        write(&local, "drive_c/users/steamuser/save.dat", "level 20");
+        std::fs::remove_file(local.join("drive_c/windows/system32/d3d11.dll")).unwrap();
+        let report = reconcile(&local, &remote, &mut base, None).unwrap();
+        assert_eq!(report.pushed, 2);
+        assert_eq!(
+            read(&remote, "drive_c/users/steamuser/save.dat").as_deref(),
+            Some("level 20")
+        );
+        assert!(!remote.join("drive_c/windows/system32/d3d11.dll").exists());
+
+        // Changed on both sides: left alone until a side is preferred
+        write(&local, "user.reg", "[Local]");
+        write(&remote, "user.reg", "[Remote, longer]");
+        let report = reconcile(&local, &remote, &mut base, None).unwrap();
+        assert_eq!(report.conflicts, vec!["user.reg".to_string()]);
+        assert_eq!(read(&local, "user.reg").as_deref(), Some("[Local]"));
+        assert_eq!(
+            read(&remote, "user.reg").as_deref(),
+            Some("[Remote, longer]")
+        );
+        let report = reconcile(&local, &remote, &mut base, Some(Prefer::Remote)).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(
+            read(&local, "user.reg").as_deref(),
+            Some("[Remote, longer]")
+        );
+
+        // An empty share looks unmounted, and the local copy is kept
+        std::fs::remove_dir_all(&remote).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        assert!(reconcile(&local, &remote, &mut base, None).is_err());
+        assert_eq!(
+            read(&local, "user.reg").as_deref(),
+            Some("[Remote, longer]")
+        );
+    }
+}
@@ -0,0 +1,713 @@
+// GPU priority between games and batch jobs
+//
+// A game sharing a GPU with an AI job gets time slices of it like any other
+// client, and a long training kernel makes it miss frames. Every GPU
+// service has a `gpu.priority`: "interactive" (games, streaming), "batch"
+// (training, inference queues) or "normal". Unset, services with `gpu.ai`
+// or `gpu.aiml` are batch and those with `gpu.gaming` interactive. The
+// priority is kept in the container's `bolt.gpu-priority` label.
+//
+// Interactive beats batch:
+//
+// - Placement: an interactive service that finds no free GPU may share one
+//   that only batch containers hold; batch services only take free GPUs.
+// - NVIDIA: containers get CUDA_MPS_CLIENT_PRIORITY (1, below normal, for
+//   batch), which MPS honours when its control daemon runs. While a GPU is
+//   contended its compute time slice is set to short (`nvidia-smi
+//   compute-policy --set-timeslice`), so batch work is preempted sooner,
+//   and back to the default afterwards.
+// - AMD: the amdgpu scheduler priority of the contexts a container's
+//   processes hold on the GPU is overridden, as SteamVR does for its
+//   compositor (AMDGPU_SCHED_OP_PROCESS_PRIORITY_OVERRIDE, which needs
+//   CAP_SYS_NICE): high for interactive, low for batch. The override only
+//   reaches contexts that exist, so it is repeated while the GPU is
+//   contended.
+//
+// A GPU is contended while an interactive and a batch container both hold
+// it; containers not placed on GPUs count against every GPU. `bolt
+// gpu-arbiter`, started by surge with the first interactive or batch
+// container and exiting once none is left, enforces this every few seconds
+// and records it for `bolt gaming gpu ls --assignments`. It also samples and
+// releases fractional GPU slices (see `fraction.rs`) while any are left.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::recovery::GpuVendor;
+use super::topology::{self, GpuTopology, TopologyGpu};
+use crate::config::GpuConfig;
+use crate::runtime::watcher::{self, Watcher};
+
+/// Label holding a container's GPU priority
+pub const LABEL: &str = "bolt.gpu-priority";
+
+/// How often the arbiter re-applies priorities
+const ARBITRATE_INTERVAL: Duration = Duration::from_secs(5);
+
+// include/uapi/drm/amdgpu_drm.h: DRM_IOW(DRM_COMMAND_BASE + DRM_AMDGPU_SCHED,
+// union drm_amdgpu_sched)
+const DRM_IOCTL_AMDGPU_SCHED: libc::c_ulong = 0x4010_6455;
+const AMDGPU_SCHED_OP_PROCESS_PRIORITY_OVERRIDE: u32 = 1;
+const AMDGPU_CTX_PRIORITY_LOW: i32 = -512;
+const AMDGPU_CTX_PRIORITY_NORMAL: i32 = 0;
+const AMDGPU_CTX_PRIORITY_HIGH: i32 = 512;
+
+/// struct drm_amdgpu_sched_in
+#[repr(C)]
+struct AmdgpuSchedIn {
+    op: u32,
+    fd: u32,
+    priority: i32,
+    ctx_id: u32,
+}
+
+/// Who wins a shared GPU, lowest first
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuPriority {
+    Batch,
+    #[default]
+    Normal,
+    Interactive,
+}
+
+impl GpuPriority {
+    /// The service's priority, or the one its GPU settings suggest
+    pub fn of(gpu: &GpuConfig) -> Self {
+        gpu.priority
+            .unwrap_or(if gpu.ai.is_some() || gpu.aiml.is_some() {
+                GpuPriority::Batch
+            } else if gpu.gaming.is_some() {
+                GpuPriority::Interactive
+            } else {
+                GpuPriority::Normal
+            })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GpuPriority::Batch => "batch",
+            GpuPriority::Normal => "normal",
+            GpuPriority::Interactive => "interactive",
+        }
+    }
+
+    fn parse(label: &str) -> Option<Self> {
+        match label.trim() {
+            "batch" => Some(GpuPriority::Batch),
+            "normal" => Some(GpuPriority::Normal),
+            "interactive" => Some(GpuPriority::Interactive),
+            _ => None,
+        }
+    }
+
+    /// The amdgpu context priority on a contended GPU
+    fn amdgpu_priority(self) -> i32 {
+        match self {
+            GpuPriority::Batch => AMDGPU_CTX_PRIORITY_LOW,
+            GpuPriority::Normal => AMDGPU_CTX_PRIORITY_NORMAL,
+            GpuPriority::Interactive => AMDGPU_CTX_PRIORITY_HIGH,
+        }
+    }
+}
+
+fn amdgpu_priority_name(priority: i32) -> &'static str {
+    match priority {
+        AMDGPU_CTX_PRIORITY_LOW => "low",
+        AMDGPU_CTX_PRIORITY_HIGH => "high",
+        _ => "normal",
+    }
+}
+
+/// Arguments for `podman/docker run` of a GPU service
+pub fn run_args(gpu: &GpuConfig) -> Vec<String> {
+    let priority = GpuPriority::of(gpu);
+    let mut args = vec![
+        "--label".to_string(),
+        format!("{}={}", LABEL, priority.as_str()),
+    ];
+    if (gpu.amd.is_none() && gpu.intel.is_none()) || gpu.nvidia.is_some() {
+        let mps = if priority == GpuPriority::Batch { 1 } else { 0 };
+        args.push("-e".to_string());
+        args.push(format!("CUDA_MPS_CLIENT_PRIORITY={}", mps));
+    }
+    args
+}
+
+/// A running container with GPUs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holder {
+    pub container: String,
+    pub pid: u32,
+    /// PCI addresses it was placed on; None when it wasn't placed and may
+    /// use every GPU
+    pub gpus: Option<Vec<String>>,
+    pub priority: GpuPriority,
+}
+
+/// Running containers placed on GPUs or given a GPU priority
+pub async fn holders(runtime: &str) -> Vec<Holder> {
+    let mut ids = Vec::new();
+    for label in [topology::LABEL, LABEL] {
+        if let Ok(output) = AsyncCommand::new(runtime)
+            .args(["ps", "-q", "--filter", &format!("label={}", label)])
+            .output()
+            .await
+        {
+            ids.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .map(str::to_string),
+            );
+        }
+    }
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let format = format!(
+        "{{{{.Name}}}}\t{{{{.State.Pid}}}}\t{{{{index .Config.Labels \"{}\"}}}}\t{{{{index .Config.Labels \"{}\"}}}}",
+        topology::LABEL,
+        LABEL
+    );
+    match AsyncCommand::new(runtime)
+        .args(["inspect", "--format", &format])
+        .args(&ids)
+        .output()
+        .await
+    {
+        Ok(output) => parse_holders(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            debug!("Failed to inspect GPU containers: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Parse `<name>\t<pid>\t<bolt.gpus>\t<bolt.gpu-priority>` lines
+fn parse_holders(output: &str) -> Vec<Holder> {
+    let label = |value: &str| {
+        let value = value.trim();
+        (!value.is_empty() && value != "<no value>").then(|| value.to_string())
+    };
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let container = fields.next()?.trim().trim_start_matches('/').to_string();
+            let pid = fields.next()?.trim().parse().ok()?;
+            let gpus = fields
+                .next()
+                .and_then(label)
+                .map(|gpus| gpus.split(',').map(str::to_string).collect());
+            let priority = fields
+                .next()
+                .and_then(label)
+                .and_then(|p| GpuPriority::parse(&p))
+                .unwrap_or_default();
+            Some(Holder {
+                container,
+                pid,
+                gpus,
+                priority,
+            })
+        })
+        .collect()
+}
+
+/// PCI address of each GPU placed containers hold, with a container's name
+pub fn placed(holders: &[Holder]) -> HashMap<String, String> {
+    holders
+        .iter()
+        .flat_map(|h| {
+            h.gpus
+                .iter()
+                .flatten()
+                .map(|gpu| (gpu.clone(), h.container.clone()))
+        })
+        .collect()
+}
+
+/// GPUs a container of `priority` may share when none is free: those only
+/// batch containers hold, for an interactive one
+pub fn shareable(holders: &[Holder], priority: GpuPriority) -> HashSet<String> {
+    if priority != GpuPriority::Interactive {
+        return HashSet::new();
+    }
+    let mut held: BTreeMap<&str, Vec<GpuPriority>> = BTreeMap::new();
+    for holder in holders {
+        for gpu in holder.gpus.iter().flatten() {
+            held.entry(gpu).or_default().push(holder.priority);
+        }
+    }
+    held.into_iter()
+        .filter(|(_, priorities)| priorities.iter().all(|p| *p == GpuPriority::Batch))
+        .map(|(gpu, _)| gpu.to_string())
+        .collect()
+}
+
+/// A container on a GPU and what is enforced for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assigned {
+    pub container: String,
+    pub priority: GpuPriority,
+    #[serde(default)]
+    pub enforced: Vec<String>,
+}
+
+/// The containers on a GPU
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuAssignment {
+    pub pci_bus_id: String,
+    pub vendor: GpuVendor,
+    pub containers: Vec<Assigned>,
+    /// An interactive and a batch container share the GPU
+    pub contended: bool,
+    /// NVIDIA compute time slice the arbiter set
+    pub timeslice: Option<String>,
+}
+
+/// Who is on each GPU
+pub fn assign(gpus: &[TopologyGpu], holders: &[Holder]) -> Vec<GpuAssignment> {
+    gpus.iter()
+        .map(|gpu| {
+            let containers: Vec<Assigned> = holders
+                .iter()
+                .filter(|h| h.gpus.as_ref().is_none_or(|g| g.contains(&gpu.pci_bus_id)))
+                .map(|h| Assigned {
+                    container: h.container.clone(),
+                    priority: h.priority,
+                    enforced: Vec::new(),
+                })
+                .collect();
+            let has = |p| containers.iter().any(|c| c.priority == p);
+            GpuAssignment {
+                pci_bus_id: gpu.pci_bus_id.clone(),
+                vendor: gpu.vendor,
+                contended: has(GpuPriority::Interactive) && has(GpuPriority::Batch),
+                containers,
+                timeslice: None,
+            }
+        })
+        .collect()
+}
+
+/// What the arbiter last enforced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArbiterState {
+    pub pid: Option<u32>,
+    pub gpus: Vec<GpuAssignment>,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ArbiterState {
+    pub fn running(&self) -> bool {
+        self.pid.is_some_and(|pid| ARBITER.alive(pid))
+    }
+}
+
+const ARBITER: Watcher = Watcher::new("gpu-arbiter");
+
+fn priority_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu-priority")
+}
+
+fn state_path(data_dir: &Path) -> PathBuf {
+    priority_dir(data_dir).join("arbiter.json")
+}
+
+pub fn state(data_dir: &Path) -> Option<ArbiterState> {
+    watcher::load(&state_path(data_dir))
+}
+
+fn save(data_dir: &Path, state: &ArbiterState) -> Result<()> {
+    watcher::save(&state_path(data_dir), state)
+}
+
+/// Start the arbiter unless it runs
+pub fn ensure_arbiter(data_dir: &Path) -> Result<()> {
+    if state(data_dir).is_some_and(|s| s.running()) {
+        return Ok(());
+    }
+    let pid = ARBITER.spawn(["gpu-arbiter"], &priority_dir(data_dir).join("arbiter.log"))?;
+    save(
+        data_dir,
+        &ArbiterState {
+            pid: Some(pid),
+            gpus: Vec::new(),
+            errors: Vec::new(),
+            updated_at: Utc::now(),
+        },
+    )?;
+    info!("⚖️  Arbitrating GPU priorities");
+    Ok(())
+}
+
+/// Who holds each GPU at what priority, with what the arbiter enforces
+pub async fn report(data_dir: &Path) -> Result<Vec<GpuAssignment>> {
+    let topology = GpuTopology::detect().await?;
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let mut assignments = assign(&topology.gpus, &holders(&runtime).await);
+    if let Some(state) = state(data_dir).filter(|s| s.running()) {
+        for assignment in &mut assignments {
+            let Some(enforced) = state
+                .gpus
+                .iter()
+                .find(|g| g.pci_bus_id == assignment.pci_bus_id)
+            else {
+                continue;
+            };
+            assignment.timeslice = enforced.timeslice.clone();
+            for container in &mut assignment.containers {
+                if let Some(known) = enforced
+                    .containers
+                    .iter()
+                    .find(|c| c.container == container.container)
+                {
+                    container.enforced = known.enforced.clone();
+                }
+            }
+        }
+    }
+    Ok(assignments)
+}
+
+/// Enforce priorities on shared GPUs until no interactive or batch
+/// container is left, then put the GPUs back. Run by `bolt gpu-arbiter`.
+pub async fn arbitrate(data_dir: &Path) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let topology = GpuTopology::detect().await?;
+    let mut arbiter = Arbiter::default();
+    let result = tokio::select! {
+        result = arbiter.run(data_dir, &topology) => result,
+        _ = terminate.recv() => Ok(()),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    arbiter.release(&topology).await;
+    if let Some(mut state) = state(data_dir) {
+        state.pid = None;
+        save(data_dir, &state)?;
+    }
+    result
+}
+
+#[derive(Default)]
+struct Arbiter {
+    /// NVIDIA GPUs whose time slice was set, and whether to short
+    timeslices: HashMap<String, bool>,
+    /// AMD containers whose contexts were overridden
+    overridden: HashMap<String, Holder>,
+    sampler: super::fraction::Sampler,
+}
+
+impl Arbiter {
+    async fn run(&mut self, data_dir: &Path, topology: &GpuTopology) -> Result<()> {
+        let runtime = crate::runtime::detect_container_runtime().await?;
+        loop {
+            let holders = holders(&runtime).await;
+            let (slices, slice_errors) =
+                super::fraction::sweep(&runtime, data_dir, &mut self.sampler).await;
+            if slices == 0 && holders.iter().all(|h| h.priority == GpuPriority::Normal) {
+                info!("No interactive, batch or fractional GPU containers left, stopping");
+                return Ok(());
+            }
+            let mut assignments = assign(&topology.gpus, &holders);
+            let mut errors = self.enforce(topology, &mut assignments, &holders).await;
+            errors.extend(slice_errors);
+            for error in &errors {
+                warn!("{}", error);
+            }
+            save(
+                data_dir,
+                &ArbiterState {
+                    pid: Some(std::process::id()),
+                    gpus: assignments,
+                    errors,
+                    updated_at: Utc::now(),
+                },
+            )?;
+            tokio::time::sleep(ARBITRATE_INTERVAL).await;
+        }
+    }
+
+    async fn enforce(
+        &mut self,
+        topology: &GpuTopology,
+        assignments: &mut [GpuAssignment],
+        holders: &[Holder],
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (gpu, assignment) in topology.gpus.iter().zip(assignments.iter_mut()) {
+            match gpu.vendor {
+                GpuVendor::Nvidia => {
+                    let short = assignment.contended;
+                    if self.timeslices.get(&gpu.pci_bus_id) != Some(&short)
+                        && (short || self.timeslices.contains_key(&gpu.pci_bus_id))
+                    {
+                        match set_timeslice(&gpu.pci_bus_id, short).await {
+                            Ok(()) => {
+                                info!(
+                                    "⚖️  GPU {} time slice: {}",
+                                    gpu.pci_bus_id,
+                                    if short { "short" } else { "default" }
+                                );
+                                self.timeslices.insert(gpu.pci_bus_id.clone(), short);
+                            }
+                            Err(e) => errors.push(format!("GPU {}: {:#}", gpu.pci_bus_id, e)),
+                        }
+                    }
+                    assignment.timeslice = self
+                        .timeslices
+                        .get(&gpu.pci_bus_id)
+                        .map(|short| if *short { "short" } else { "default" }.to_string());
+                    for container in &mut assignment.containers {
+                        if container.priority == GpuPriority::Batch {
+                            container.enforced.push("MPS below normal".to_string());
+                        }
+                        if short {
+                            container.enforced.push("time slice short".to_string());
+                        }
+                    }
+                }
+                GpuVendor::Amd => {
+                    for container in &mut assignment.containers {
+                        let Some(holder) =
+                            holders.iter().find(|h| h.container == container.container)
+                        else {
+                            continue;
+                        };
+                        let priority = if assignment.contended {
+                            container.priority.amdgpu_priority()
+                        } else {
+                            AMDGPU_CTX_PRIORITY_NORMAL
+                        };
+                        if priority == AMDGPU_CTX_PRIORITY_NORMAL
+                            && !self.overridden.contains_key(&holder.container)
+                        {
+                            continue;
+                        }
+                        match override_amdgpu(gpu, holder.pid, priority) {
+                            Ok(0) => {}
+                            Ok(_) => {
+                                container
+                                    .enforced
+                                    .push(format!("amdgpu {}", amdgpu_priority_name(priority)));
+                                if priority == AMDGPU_CTX_PRIORITY_NORMAL {
+                                    self.overridden.remove(&holder.container);
+                                } else {
+                                    self.overridden
+                                        .insert(holder.container.clone(), holder.clone());
+                                }
+                            }
+                            Err(e) => errors.push(format!(
+                                "GPU {} / {}: {:#}",
+                                gpu.pci_bus_id, holder.container, e
+                            )),
+                        }
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Put time slices and context priorities back to normal
+    async fn release(&mut self, topology: &GpuTopology) {
+        for (gpu, short) in std::mem::take(&mut self.timeslices) {
+            if short {
+                if let Err(e) = set_timeslice(&gpu, false).await {
+                    warn!("Failed to restore the time slice of GPU {}: {:#}", gpu, e);
+                }
+            }
+        }
+        for holder in std::mem::take(&mut self.overridden).into_values() {
+            for gpu in topology.gpus.iter().filter(|g| g.vendor == GpuVendor::Amd) {
+                let _ = override_amdgpu(gpu, holder.pid, AMDGPU_CTX_PRIORITY_NORMAL);
+            }
+        }
+    }
+}
+
+/// Set an NVIDIA GPU's compute time slice to short, or back to the default
+async fn set_timeslice(pci_bus_id: &str, short: bool) -> Result<()> {
+    let output = AsyncCommand::new("nvidia-smi")
+        .args([
+            "compute-policy",
+            "-i",
+            pci_bus_id,
+            "--set-timeslice",
+            if short { "1" } else { "0" },
+        ])
+        .output()
+        .await
+        .context("Failed to run nvidia-smi")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-smi compute-policy failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Override the amdgpu priority of every context the container's processes
+/// hold on `gpu`; how many DRM files were overridden
+fn override_amdgpu(gpu: &TopologyGpu, container_pid: u32, priority: i32) -> Result<usize> {
+    let Some(render_node) = gpu.devices.iter().find(|d| d.contains("renderD")) else {
+        return Err(anyhow!("no render node"));
+    };
+    let rdevs: Vec<u64> = gpu
+        .devices
+        .iter()
+        .filter_map(|d| std::fs::metadata(d).ok().map(|m| m.rdev()))
+        .collect();
+    let device = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(render_node)
+        .with_context(|| format!("Failed to open {}", render_node))?;
+    let procs = crate::runtime::cgroups::ContainerCgroup::of_process(container_pid)
+        .map(|cgroup| cgroup.procs())
+        .filter(|procs| !procs.is_empty())
+        .unwrap_or_else(|| vec![container_pid]);
+
+    let mut overridden = 0;
+    let mut last_error = None;
+    for pid in procs {
+        let Ok(entries) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        let drm_fds: Vec<i32> = entries
+            .flatten()
+            .filter(|e| std::fs::metadata(e.path()).is_ok_and(|m| rdevs.contains(&m.rdev())))
+            .filter_map(|e| e.file_name().to_str()?.parse().ok())
+            .collect();
+        if drm_fds.is_empty() {
+            continue;
+        }
+        // SAFETY: pidfd_open(2) takes a pid and flags and returns a new fd
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::c_int, 0) };
+        if pidfd < 0 {
+            last_error = Some(std::io::Error::last_os_error());
+            continue;
+        }
+        for fd in drm_fds {
+            // SAFETY: pidfd_getfd(2) duplicates the target's fd into this
+            // process; the copy is closed below
+            let local = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd, fd, 0) };
+            if local < 0 {
+                last_error = Some(std::io::Error::last_os_error());
+                continue;
+            }
+            let args = AmdgpuSchedIn {
+                op: AMDGPU_SCHED_OP_PROCESS_PRIORITY_OVERRIDE,
+                fd: local as u32,
+                priority,
+                ctx_id: 0,
+            };
+            // SAFETY: DRM_IOCTL_AMDGPU_SCHED reads a drm_amdgpu_sched_in,
+            // which `args` matches and outlives the call
+            let result = unsafe {
+                libc::ioctl(
+                    device.as_raw_fd(),
+                    DRM_IOCTL_AMDGPU_SCHED as _,
+                    &args as *const AmdgpuSchedIn,
+                )
+            };
+            if result == 0 {
+                overridden += 1;
+            } else {
+                last_error = Some(std::io::Error::last_os_error());
+            }
+            // SAFETY: `local` is an fd this process owns
+            unsafe { libc::close(local as libc::c_int) };
+        }
+        // SAFETY: `pidfd` is an fd this process owns
+        unsafe { libc::close(pidfd as libc::c_int) };
+    }
+    match (overridden, last_error) {
+        (0, Some(e)) => Err(anyhow!("Failed to override context priorities: {}", e)),
+        (overridden, _) => Ok(overridden),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interactive_beats_batch_on_shared_gpus() {
+        let holders = parse_holders(
+            "/arena_server\t4242\t0000:01:00.0\tinteractive\n\
+             /lab_trainer\t5151\t0000:02:00.0\tbatch\n\
+             /lab_eval\t6161\t<no value>\tbatch\n\
+             /old_placed\t7171\t0000:03:00.0\t\n\
+             /exited\t0\t0000:04:00.0\tbatch\n",
+        );
+        assert_eq!(holders.len(), 5);
+        assert_eq!(holders[0].container, "arena_server");
+        assert_eq!(holders[2].gpus, None);
+        assert_eq!(holders[3].priority, GpuPriority::Normal);
+
+        let config: GpuConfig = toml::from_str("[ai]\n").unwrap();
+        assert_eq!(GpuPriority::of(&config), GpuPriority::Batch);
+        let config: GpuConfig = toml::from_str("priority = \"interactive\"\n[aiml]\n").unwrap();
+        assert_eq!(GpuPriority::of(&config), GpuPriority::Interactive);
+        assert_eq!(
+            run_args(&toml::from_str("[ai]\n").unwrap()),
+            vec![
+                "--label",
+                "bolt.gpu-priority=batch",
+                "-e",
+                "CUDA_MPS_CLIENT_PRIORITY=1"
+            ]
+        );
+
+        // A game may share the GPUs only batch jobs hold, never the reverse
+        let shared = shareable(&holders, GpuPriority::Interactive);
+        assert!(shared.contains("0000:02:00.0"));
+        assert!(shared.contains("0000:04:00.0"));
+        assert!(!shared.contains("0000:01:00.0"));
+        assert!(!shared.contains("0000:03:00.0"));
+        assert!(shareable(&holders, GpuPriority::Batch).is_empty());
+        assert_eq!(placed(&holders)["0000:01:00.0"], "arena_server");
+
+        let gpu = |pci: &str, vendor| TopologyGpu {
+            pci_bus_id: pci.to_string(),
+            vendor,
+            numa_node: None,
+            nvidia_index: None,
+            devices: Vec::new(),
+        };
+        let assignments = assign(
+            &[
+                gpu("0000:01:00.0", GpuVendor::Nvidia),
+                gpu("0000:03:00.0", GpuVendor::Amd),
+            ],
+            &holders,
+        );
+        // lab_eval isn't placed, so it counts against every GPU
+        let names: Vec<&str> = assignments[0]
+            .containers
+            .iter()
+            .map(|c| c.container.as_str())
+            .collect();
+        assert_eq!(names, vec!["arena_server", "lab_eval"]);
+        assert!(assignments[0].contended);
+        assert!(!assignments[1].contended);
+    }
+}
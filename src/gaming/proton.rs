@@ -0,0 +1,602 @@
+// Proton-GE and Wine-GE versions
+//
+// A service's `gaming.wine.proton` picks the compatibility tool its game
+// runs on:
+//
+// - `GE-Proton9-20`, `proton-ge:latest` or `latest`: a Proton-GE release
+// - `wine-ge:GE-Proton8-26` or `wine-ge:latest`: a Wine-GE (Lutris) build
+// - `8.0`, `9.0-beta` and other plain versions: Valve's Proton, as Steam
+//   installed it on the host
+//
+// GE releases are downloaded from GitHub on first use, verified against the
+// release's `.sha512sum` and unpacked into
+// `<data_dir>/compat-tools/<flavor>/<tag>`. That cache is shared by every
+// service: a container gets its version mounted read-only under
+// /opt/bolt/compat, with PROTONPATH (as umu-launcher reads it) or WINE and
+// WINESERVER pointing into it. Containers are labelled with their version,
+// and a version some container still uses isn't removed.
+//
+// Downloads unpack into a scratch directory next to the cache and are moved
+// into place whole, so services starting together never see half a tool.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+/// Where a container's tool is mounted
+pub const MOUNT: &str = "/opt/bolt/compat";
+/// `<flavor>:<tag>` of the tool a container has mounted
+pub const LABEL: &str = "bolt.compat-tool";
+const RELEASES_PER_PAGE: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flavor {
+    ProtonGe,
+    WineGe,
+    /// Valve's Proton, installed through Steam
+    Steam,
+}
+
+impl Flavor {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Flavor::ProtonGe => "proton-ge",
+            Flavor::WineGe => "wine-ge",
+            Flavor::Steam => "steam",
+        }
+    }
+
+    fn repository(self) -> Option<&'static str> {
+        match self {
+            Flavor::ProtonGe => Some("GloriousEggroll/proton-ge-custom"),
+            Flavor::WineGe => Some("GloriousEggroll/wine-ge-custom"),
+            Flavor::Steam => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Flavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A version as written in `gaming.wine.proton`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    pub flavor: Flavor,
+    /// `None` for the newest release
+    pub tag: Option<String>,
+}
+
+impl Spec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (flavor, tag) = match spec.split_once(':') {
+            Some(("proton-ge", tag)) => (Flavor::ProtonGe, tag),
+            Some(("wine-ge", tag)) => (Flavor::WineGe, tag),
+            Some(("steam", tag)) => (Flavor::Steam, tag),
+            Some(_) => {
+                return Err(anyhow!(
+                    "Unknown Proton version '{}': expected GE-Proton<N>-<N>, latest, \
+                     wine-ge:<tag> or a Steam Proton version like 8.0",
+                    spec
+                ));
+            }
+            None if spec == "latest" || spec.starts_with("GE-Proton") => (Flavor::ProtonGe, spec),
+            None => (Flavor::Steam, spec),
+        };
+        if tag.is_empty() || tag.contains(['/', '\\']) || tag.starts_with('.') {
+            return Err(anyhow!("Invalid Proton version '{}'", spec));
+        }
+        if flavor == Flavor::Steam && tag == "latest" {
+            return Err(anyhow!(
+                "Steam's Proton has no 'latest'; name the version, e.g. 9.0"
+            ));
+        }
+        Ok(Self {
+            flavor,
+            tag: (tag != "latest").then(|| tag.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.flavor,
+            self.tag.as_deref().unwrap_or("latest")
+        )
+    }
+}
+
+/// A release on GitHub
+#[derive(Debug, Clone, Serialize)]
+pub struct Release {
+    pub flavor: Flavor,
+    pub tag: String,
+    pub published_at: Option<DateTime<Utc>>,
+    pub prerelease: bool,
+    /// Size of the archive
+    pub size: u64,
+    pub installed: bool,
+    #[serde(skip)]
+    archive: Asset,
+    #[serde(skip)]
+    checksum: Option<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    #[serde(default)]
+    published_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    #[serde(default)]
+    size: u64,
+}
+
+/// The release's tarball and the `.sha512sum` next to it. Wine-GE also
+/// ships League of Legends builds (`-lol-`), which are skipped.
+fn pick_assets(assets: &[Asset]) -> Option<(Asset, Option<Asset>)> {
+    let archive = assets.iter().find(|a| {
+        (a.name.ends_with(".tar.gz") || a.name.ends_with(".tar.xz"))
+            && !a.name.to_lowercase().contains("-lol-")
+    })?;
+    let stem = archive
+        .name
+        .trim_end_matches(".tar.gz")
+        .trim_end_matches(".tar.xz");
+    let wanted = format!("{}.sha512sum", stem);
+    let checksum = assets.iter().find(|a| a.name == wanted).cloned();
+    Some((archive.clone(), checksum))
+}
+
+/// The digest `sha512sum` printed for `file`
+fn expected_digest(sums: &str, file: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim().trim_start_matches('*');
+        let name = name.rsplit('/').next().unwrap_or(name);
+        (name == file && digest.len() == 128).then(|| digest.to_ascii_lowercase())
+    })
+}
+
+fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("bolt/", env!("CARGO_PKG_VERSION")))
+        .build()?)
+}
+
+/// A GitHub request; `GITHUB_TOKEN` lifts the anonymous rate limit
+fn github(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    let request = client.get(url);
+    match std::env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => request.bearer_auth(token),
+        _ => request,
+    }
+}
+
+/// The newest releases of a GE flavor, newest first
+pub async fn available(data_dir: &Path, flavor: Flavor) -> Result<Vec<Release>> {
+    let repository = flavor
+        .repository()
+        .ok_or_else(|| anyhow!("Valve's Proton is installed through Steam, not by Bolt"))?;
+    let url = format!(
+        "https://api.github.com/repos/{}/releases?per_page={}",
+        repository, RELEASES_PER_PAGE
+    );
+    let response = github(&client()?, &url)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .with_context(|| format!("Failed to list {} releases", flavor))?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to list {} releases: GitHub answered {}",
+            flavor,
+            response.status()
+        ));
+    }
+    let releases: Vec<GithubRelease> = response.json().await?;
+    let installed = installed(data_dir);
+    Ok(releases
+        .into_iter()
+        .filter(|r| !r.draft)
+        .filter_map(|r| {
+            let (archive, checksum) = pick_assets(&r.assets)?;
+            Some(Release {
+                flavor,
+                installed: installed
+                    .iter()
+                    .any(|i| i.flavor == flavor && i.tag == r.tag_name),
+                tag: r.tag_name,
+                published_at: r.published_at,
+                prerelease: r.prerelease,
+                size: archive.size,
+                archive,
+                checksum,
+            })
+        })
+        .collect())
+}
+
+/// A version in the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Installed {
+    pub flavor: Flavor,
+    pub tag: String,
+    /// SHA-512 of the archive it was unpacked from
+    pub sha512: String,
+    pub installed_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+fn cache_dir(data_dir: &Path, flavor: Flavor) -> PathBuf {
+    data_dir.join("compat-tools").join(flavor.as_str())
+}
+
+fn metadata_path(data_dir: &Path, flavor: Flavor, tag: &str) -> PathBuf {
+    cache_dir(data_dir, flavor).join(format!("{}.json", tag))
+}
+
+/// Every version in the cache, by flavor and then tag
+pub fn installed(data_dir: &Path) -> Vec<Installed> {
+    let mut tools = Vec::new();
+    for flavor in [Flavor::ProtonGe, Flavor::WineGe] {
+        let Ok(entries) = std::fs::read_dir(cache_dir(data_dir, flavor)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let tool = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<Installed>(&data)?));
+            match tool {
+                Ok(mut tool) => {
+                    tool.path = cache_dir(data_dir, flavor).join(&tool.tag);
+                    if tool.path.is_dir() {
+                        tools.push(tool);
+                    }
+                }
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+    }
+    tools.sort_by(|a, b| (a.flavor.as_str(), &a.tag).cmp(&(b.flavor.as_str(), &b.tag)));
+    tools
+}
+
+fn find_installed(data_dir: &Path, flavor: Flavor, tag: &str) -> Option<Installed> {
+    installed(data_dir)
+        .into_iter()
+        .find(|i| i.flavor == flavor && i.tag == tag)
+}
+
+/// Download, verify and unpack a GE release into the cache, unless it's
+/// already there
+pub async fn install(data_dir: &Path, spec: &Spec) -> Result<Installed> {
+    if spec.flavor == Flavor::Steam {
+        return Err(anyhow!(
+            "Valve's Proton is installed through Steam; use a GE-Proton version to have Bolt download it"
+        ));
+    }
+    if let Some(ref tag) = spec.tag
+        && let Some(tool) = find_installed(data_dir, spec.flavor, tag)
+    {
+        return Ok(tool);
+    }
+
+    let releases = available(data_dir, spec.flavor).await?;
+    let release = match spec.tag {
+        Some(ref tag) => releases.into_iter().find(|r| &r.tag == tag),
+        None => releases.into_iter().find(|r| !r.prerelease),
+    }
+    .ok_or_else(|| anyhow!("No release {} on GitHub", spec))?;
+    if let Some(tool) = find_installed(data_dir, spec.flavor, &release.tag) {
+        return Ok(tool);
+    }
+    let checksum = release.checksum.as_ref().ok_or_else(|| {
+        anyhow!(
+            "{} {} publishes no .sha512sum to verify the download against",
+            spec.flavor,
+            release.tag
+        )
+    })?;
+
+    info!(
+        "📥 Downloading {} {} ({:.1} MB)",
+        spec.flavor,
+        release.tag,
+        release.size as f64 / 1_000_000.0
+    );
+    let client = client()?;
+    let sums = github(&client, &checksum.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let expected = expected_digest(&sums, &release.archive.name).ok_or_else(|| {
+        anyhow!(
+            "{} doesn't list a SHA-512 for {}",
+            checksum.name,
+            release.archive.name
+        )
+    })?;
+
+    let root = cache_dir(data_dir, spec.flavor);
+    std::fs::create_dir_all(&root)?;
+    let mut archive = tempfile::Builder::new()
+        .prefix(".download-")
+        .tempfile_in(&root)?;
+    let mut response = github(&client, &release.archive.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut hasher = Sha512::new();
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+        archive.write_all(&chunk)?;
+    }
+    archive.flush()?;
+    let digest = format!("{:x}", hasher.finalize());
+    if digest != expected {
+        return Err(anyhow!(
+            "{} failed verification: SHA-512 {} instead of {}",
+            release.archive.name,
+            digest,
+            expected
+        ));
+    }
+    info!("  ✅ SHA-512 verified");
+
+    let scratch = tempfile::Builder::new()
+        .prefix(".unpack-")
+        .tempdir_in(&root)?;
+    let output = AsyncCommand::new("tar")
+        .arg("-xf")
+        .arg(archive.path())
+        .arg("-C")
+        .arg(scratch.path())
+        .output()
+        .await
+        .context("Failed to run tar")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to unpack {}: {}",
+            release.archive.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // The archives hold one directory, e.g. GE-Proton9-20/
+    let mut top = std::fs::read_dir(scratch.path())?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir());
+    let unpacked = match (top.next(), top.next()) {
+        (Some(dir), None) => dir,
+        _ => scratch.path().to_path_buf(),
+    };
+
+    let target = root.join(&release.tag);
+    if let Err(e) = std::fs::rename(&unpacked, &target) {
+        // Another service finished the same download first
+        if !target.is_dir() {
+            return Err(e.into());
+        }
+    }
+    let tool = Installed {
+        flavor: spec.flavor,
+        tag: release.tag.clone(),
+        sha512: digest,
+        installed_at: Utc::now(),
+        path: target,
+    };
+    std::fs::write(
+        metadata_path(data_dir, spec.flavor, &tool.tag),
+        serde_json::to_vec_pretty(&tool)?,
+    )?;
+    info!("✅ {} {} installed", spec.flavor, tool.tag);
+    Ok(tool)
+}
+
+/// Containers, running or not, that have a version mounted
+async fn users(runtime: &str, flavor: Flavor, tag: &str) -> Result<Vec<String>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["ps", "-a", "--format", "{{.Names}}", "--filter"])
+        .arg(format!("label={}={}:{}", LABEL, flavor, tag))
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to list containers: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Remove a version from the cache, unless a container uses it
+pub async fn remove(data_dir: &Path, spec: &Spec, runtime: Option<&str>) -> Result<()> {
+    let tag = spec
+        .tag
+        .as_deref()
+        .ok_or_else(|| anyhow!("Name the version to remove, not latest"))?;
+    let tool = find_installed(data_dir, spec.flavor, tag)
+        .ok_or_else(|| anyhow!("{} isn't installed", spec))?;
+    if let Some(runtime) = runtime {
+        let users = users(runtime, spec.flavor, tag).await?;
+        if !users.is_empty() {
+            return Err(anyhow!(
+                "{} is used by {}; remove those containers first",
+                spec,
+                users.join(", ")
+            ));
+        }
+    }
+    std::fs::remove_dir_all(&tool.path)?;
+    std::fs::remove_file(metadata_path(data_dir, spec.flavor, tag))?;
+    info!("🗑️  Removed {}", spec);
+    Ok(())
+}
+
+/// Valve's Proton as Steam installed it
+fn steam_install(version: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    [".steam/steam", ".local/share/Steam"]
+        .iter()
+        .map(|steam| {
+            home.join(steam)
+                .join("steamapps/common")
+                .join(format!("Proton {}", version))
+        })
+        .find(|path| path.is_dir())
+}
+
+/// A tool ready to be mounted into a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tool {
+    pub flavor: Flavor,
+    pub tag: String,
+    pub path: PathBuf,
+}
+
+impl Tool {
+    pub fn target(&self) -> String {
+        format!("{}/{}", MOUNT, self.tag)
+    }
+
+    pub fn run_args(&self) -> Vec<String> {
+        let target = self.target();
+        let mut args = vec![
+            "-v".to_string(),
+            format!("{}:{}:ro", self.path.display(), target),
+            "--label".to_string(),
+            format!("{}={}:{}", LABEL, self.flavor, self.tag),
+        ];
+        match self.flavor {
+            Flavor::ProtonGe | Flavor::Steam => {
+                args.extend(["-e".to_string(), format!("PROTONPATH={}", target)]);
+            }
+            Flavor::WineGe => {
+                args.extend([
+                    "-e".to_string(),
+                    format!("WINE={}/bin/wine", target),
+                    "-e".to_string(),
+                    format!("WINESERVER={}/bin/wineserver", target),
+                ]);
+            }
+        }
+        args
+    }
+}
+
+/// The tool a service asked for, downloading GE releases that aren't in the
+/// cache yet. A Steam version Steam hasn't installed is only warned about.
+pub async fn ensure(data_dir: &Path, spec: &Spec) -> Result<Option<Tool>> {
+    if spec.flavor == Flavor::Steam {
+        let version = spec.tag.as_deref().unwrap_or_default();
+        return Ok(match steam_install(version) {
+            Some(path) => Some(Tool {
+                flavor: Flavor::Steam,
+                tag: version.to_string(),
+                path,
+            }),
+            None => {
+                warn!(
+                    "Proton {} isn't installed through Steam; the image's own Wine is used",
+                    version
+                );
+                None
+            }
+        });
+    }
+    let tool = install(data_dir, spec).await?;
+    Ok(Some(Tool {
+        flavor: tool.flavor,
+        tag: tool.tag,
+        path: tool.path,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> Asset {
+        Asset {
+            name: name.to_string(),
+            browser_download_url: format!("https://example.com/{}", name),
+            size: 1,
+        }
+    }
+
+    #[test]
+    fn parses_versions_and_picks_release_assets() {
+        let spec = Spec::parse("GE-Proton9-20").unwrap();
+        assert_eq!(spec.flavor, Flavor::ProtonGe);
+        assert_eq!(spec.tag.as_deref(), Some("GE-Proton9-20"));
+        assert_eq!(Spec::parse("latest").unwrap().tag, None);
+        let wine = Spec::parse("wine-ge:GE-Proton8-26").unwrap();
+        assert_eq!(wine.flavor, Flavor::WineGe);
+        assert_eq!(wine.to_string(), "wine-ge:GE-Proton8-26");
+        assert_eq!(Spec::parse("8.0").unwrap().flavor, Flavor::Steam);
+        assert!(Spec::parse("steam:latest").is_err());
+        assert!(Spec::parse("wine-ge:../etc").is_err());
+        assert!(Spec::parse("lutris:1").is_err());
+
+        let (archive, checksum) = pick_assets(&[
+            asset("wine-lutris-ge-lol-8.0-x86_64.tar.xz"),
+            asset("wine-lutris-GE-Proton8-26-x86_64.sha512sum"),
+            asset("wine-lutris-GE-Proton8-26-x86_64.tar.xz"),
+        ])
+        .unwrap();
+        assert_eq!(archive.name, "wine-lutris-GE-Proton8-26-x86_64.tar.xz");
+        assert_eq!(
+            checksum.unwrap().name,
+            "wine-lutris-GE-Proton8-26-x86_64.sha512sum"
+        );
+        let (_, checksum) = pick_assets(&[asset("GE-Proton9-20.tar.gz")]).unwrap();
+        assert!(checksum.is_none());
+
+        let digest = "a".repeat(128);
+        let sums = format!("{}  GE-Proton9-20/GE-Proton9-20.tar.gz\n", digest);
+        assert_eq!(expected_digest(&sums, "GE-Proton9-20.tar.gz"), Some(digest));
+        assert_eq!(expected_digest(&sums, "other.tar.gz"), None);
+
+        let tool = Tool {
+            flavor: Flavor::WineGe,
+            tag: "GE-Proton8-26".to_string(),
+            path: PathBuf::from("/var/lib/bolt/compat-tools/wine-ge/GE-Proton8-26"),
+        };
+        let args = tool.run_args();
+        assert!(args.contains(&"bolt.compat-tool=wine-ge:GE-Proton8-26".to_string()));
+        assert!(args.contains(&"WINE=/opt/bolt/compat/GE-Proton8-26/bin/wine".to_string()));
+    }
+}
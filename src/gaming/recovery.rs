@@ -0,0 +1,856 @@
+// GPU fault detection and recovery
+//
+// A GPU that hangs or falls off the bus takes every later GPU container down
+// with it. `bolt gaming gpu watch` follows the kernel log for NVIDIA Xid
+// errors and amdgpu ring timeouts and recovery failures. When a fault leaves
+// the GPU unusable and nothing has it open anymore, the device is reset:
+// `nvidia-smi --gpu-reset` (or a PCI remove/rescan once it fell off the bus)
+// for NVIDIA, the driver's own recovery or the PCI reset, which vendor-reset
+// hooks into, for AMD. A GPU whose reset fails is quarantined, and surge
+// refuses to start GPU services while no healthy GPU is left, until
+// `bolt gaming gpu unquarantine`.
+//
+// Faults, resets and quarantines are appended to
+// `<data_dir>/gpu/recovery-events.jsonl` and raised through `[alerts]`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, error, info, warn};
+
+use crate::config::BoltConfig;
+use crate::monitoring::alerts::{Alert, AlertSeverity, AlertingEngine};
+
+const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+}
+
+impl GpuVendor {
+    fn from_pci_id(id: &str) -> Option<Self> {
+        match id.trim() {
+            "0x10de" => Some(Self::Nvidia),
+            "0x1002" => Some(Self::Amd),
+            _ => None,
+        }
+    }
+}
+
+/// What a fault means for the GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FaultKind {
+    /// The application crashed; the GPU itself is fine
+    Application,
+    /// The GPU hung and needs a reset
+    Hang,
+    /// The GPU fell off the bus
+    Lost,
+    /// The driver reset the GPU on its own
+    Recovered,
+}
+
+impl FaultKind {
+    pub fn needs_reset(&self) -> bool {
+        matches!(self, Self::Hang | Self::Lost)
+    }
+}
+
+/// A GPU fault reported in the kernel log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuFault {
+    /// Timestamp as printed by the kernel log
+    pub logged_at: String,
+    pub vendor: GpuVendor,
+    /// PCI address (`0000:01:00.0`), when the message names one
+    pub pci_bus_id: Option<String>,
+    /// NVIDIA Xid code
+    pub xid: Option<u32>,
+    pub kind: FaultKind,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub message: String,
+}
+
+/// Fatal Xids: double-bit ECC errors, internal microcontroller halts, the
+/// GPU falling off the bus and GSP timeouts. Anything else (13, 31, 43, ...)
+/// is an application fault the GPU survives.
+fn classify_xid(xid: u32) -> FaultKind {
+    match xid {
+        79 => FaultKind::Lost,
+        48 | 61 | 62 | 64 | 74 | 92 | 94 | 95 | 119 | 120 | 140 => FaultKind::Hang,
+        _ => FaultKind::Application,
+    }
+}
+
+/// Parse one kernel log line (`dmesg --time-format iso` or
+/// `journalctl -k -o short-iso-precise`)
+pub fn parse_kernel_line(line: &str) -> Option<GpuFault> {
+    let logged_at = line
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    if let Some(rest) = line.split("NVRM: Xid (PCI:").nth(1) {
+        let (address, rest) = rest.split_once("):")?;
+        let mut fields = rest.split(',').map(str::trim);
+        let xid: u32 = fields.next()?.parse().ok()?;
+        let mut pid = None;
+        let mut process = None;
+        let mut message = Vec::new();
+        for field in fields {
+            if let Some(value) = field.strip_prefix("pid=") {
+                pid = value.trim_matches('\'').parse().ok();
+            } else if let Some(value) = field.strip_prefix("name=") {
+                process = Some(value.to_string()).filter(|v| v != "<unknown>");
+            } else {
+                message.push(field);
+            }
+        }
+        return Some(GpuFault {
+            logged_at,
+            vendor: GpuVendor::Nvidia,
+            pci_bus_id: Some(normalize_pci(address)),
+            xid: Some(xid),
+            kind: classify_xid(xid),
+            pid,
+            process,
+            message: format!("Xid {}: {}", xid, message.join(", ")),
+        });
+    }
+
+    if !line.contains("amdgpu") {
+        return None;
+    }
+    let lower = line.to_lowercase();
+    let kind = if lower.contains("ring") && lower.contains("timeout") {
+        FaultKind::Hang
+    } else if lower.contains("gpu reset") && lower.contains("succeeded") {
+        FaultKind::Recovered
+    } else if lower.contains("gpu recovery failed")
+        || (lower.contains("gpu reset") && lower.contains("failed"))
+    {
+        FaultKind::Hang
+    } else {
+        return None;
+    };
+    // "amdgpu 0000:03:00.0: amdgpu: GPU reset(2) succeeded!"
+    let pci_bus_id = line
+        .split_whitespace()
+        .map(|w| w.trim_end_matches(':'))
+        .find(|w| w.len() == 12 && w.matches(':').count() == 2 && w.contains('.'))
+        .map(str::to_string);
+    let message = line
+        .split("*ERROR* ")
+        .nth(1)
+        .or_else(|| line.split("amdgpu: ").last())
+        .unwrap_or(line)
+        .trim()
+        .to_string();
+    Some(GpuFault {
+        logged_at,
+        vendor: GpuVendor::Amd,
+        pci_bus_id,
+        xid: None,
+        kind,
+        pid: None,
+        process: None,
+        message,
+    })
+}
+
+/// Faults in kernel log lines. amdgpu names the guilty process on a line of
+/// its own after the timeout, which is folded into the preceding fault.
+pub fn parse_kernel_log(lines: &[String]) -> Vec<GpuFault> {
+    let mut faults: Vec<GpuFault> = Vec::new();
+    for line in lines {
+        if let Some(fault) = parse_kernel_line(line) {
+            faults.push(fault);
+        } else if let Some(info) = line.split("Process information: process ").nth(1)
+            && let Some(last) = faults.last_mut()
+            && last.vendor == GpuVendor::Amd
+        {
+            // "Process information: process game.exe pid 4242 thread ..."
+            let mut words = info.split_whitespace();
+            last.process = words.next().map(str::to_string);
+            last.pid = words.nth(1).and_then(|p| p.parse().ok());
+        }
+    }
+    faults
+}
+
+/// "0000:01:00" (NVIDIA drops the function) -> "0000:01:00.0"
+fn normalize_pci(address: &str) -> String {
+    let address = address.trim().to_lowercase();
+    if address.contains('.') {
+        address
+    } else {
+        format!("{}.0", address)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuDevice {
+    pub pci_bus_id: String,
+    pub vendor: GpuVendor,
+}
+
+/// NVIDIA and AMD display controllers on the PCI bus
+pub fn list_gpus() -> Vec<GpuDevice> {
+    let mut gpus = Vec::new();
+    let Ok(entries) = std::fs::read_dir(PCI_DEVICES) else {
+        return gpus;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_display =
+            std::fs::read_to_string(path.join("class")).is_ok_and(|c| c.trim().starts_with("0x03"));
+        let vendor = std::fs::read_to_string(path.join("vendor"))
+            .ok()
+            .and_then(|v| GpuVendor::from_pci_id(&v));
+        if let (true, Some(vendor)) = (is_display, vendor) {
+            gpus.push(GpuDevice {
+                pci_bus_id: entry.file_name().to_string_lossy().to_string(),
+                vendor,
+            });
+        }
+    }
+    gpus.sort_by(|a, b| a.pci_bus_id.cmp(&b.pci_bus_id));
+    gpus
+}
+
+/// The kernel log, from dmesg or, without access to it, the journal
+pub async fn kernel_log() -> Result<Vec<String>> {
+    let dmesg = AsyncCommand::new("dmesg")
+        .args(["--time-format", "iso"])
+        .output()
+        .await;
+    if let Ok(ref output) = dmesg
+        && output.status.success()
+    {
+        return Ok(lines(&output.stdout));
+    }
+    let output = AsyncCommand::new("journalctl")
+        .args(["-k", "-b", "--no-pager", "-o", "short-iso-precise"])
+        .output()
+        .await
+        .context("Failed to read the kernel log (dmesg and journalctl are unavailable)")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to read the kernel log: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(lines(&output.stdout))
+}
+
+fn lines(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// `/dev` nodes that belong to one GPU
+fn device_nodes(gpu: &GpuDevice) -> Vec<PathBuf> {
+    match gpu.vendor {
+        GpuVendor::Nvidia => {
+            let info = format!("/proc/driver/nvidia/gpus/{}/information", gpu.pci_bus_id);
+            std::fs::read_to_string(info)
+                .ok()
+                .and_then(|info| {
+                    info.lines()
+                        .find_map(|l| l.strip_prefix("Device Minor:"))
+                        .map(|m| PathBuf::from(format!("/dev/nvidia{}", m.trim())))
+                })
+                .into_iter()
+                .collect()
+        }
+        GpuVendor::Amd => {
+            std::fs::read_dir(Path::new(PCI_DEVICES).join(&gpu.pci_bus_id).join("drm"))
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|e| Path::new("/dev/dri").join(e.file_name()))
+                        .filter(|p| p.exists())
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Processes that hold one of the GPU's device nodes open
+fn processes_using(gpu: &GpuDevice) -> Vec<(u32, String)> {
+    let nodes: HashSet<PathBuf> = device_nodes(gpu).into_iter().collect();
+    let mut users = Vec::new();
+    if nodes.is_empty() {
+        return users;
+    }
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return users;
+    };
+    for entry in procs.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let holds = fds
+            .flatten()
+            .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|target| nodes.contains(&target)));
+        if holds {
+            let comm = std::fs::read_to_string(entry.path().join("comm"))
+                .map(|c| c.trim().to_string())
+                .unwrap_or_default();
+            if comm != "nvidia-persistenced" {
+                users.push((pid, comm));
+            }
+        }
+    }
+    users
+}
+
+/// Container a process runs in, from its cgroup path
+async fn container_of(runtime: &str, pid: u32) -> Option<String> {
+    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let id = cgroup
+        .split(['/', '-', '.'])
+        .find(|part| part.len() == 64 && part.chars().all(|c| c.is_ascii_hexdigit()))?;
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.Name}}", id])
+        .output()
+        .await
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .trim_start_matches('/')
+        .to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Reset a GPU. Unless `force`, refuses while any process has it open.
+pub async fn reset(gpu: &GpuDevice, kind: FaultKind, force: bool) -> Result<()> {
+    let users = processes_using(gpu);
+    if !force && !users.is_empty() {
+        let names: Vec<String> = users
+            .iter()
+            .map(|(pid, comm)| format!("{} ({})", comm, pid))
+            .collect();
+        return Err(anyhow!(
+            "{} is still in use by {}",
+            gpu.pci_bus_id,
+            names.join(", ")
+        ));
+    }
+
+    info!("🔁 Resetting GPU {}", gpu.pci_bus_id);
+    match gpu.vendor {
+        GpuVendor::Nvidia => {
+            let output = AsyncCommand::new("nvidia-smi")
+                .args(["--gpu-reset", "-i", &gpu.pci_bus_id])
+                .output()
+                .await;
+            match output {
+                Ok(o) if o.status.success() => {}
+                result => {
+                    let reason = match result {
+                        Ok(o) => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    if kind != FaultKind::Lost {
+                        return Err(anyhow!("nvidia-smi --gpu-reset failed: {}", reason));
+                    }
+                    // A GPU that fell off the bus only comes back through the PCI core
+                    debug!(
+                        "nvidia-smi --gpu-reset failed ({}); rescanning the bus",
+                        reason
+                    );
+                    pci_remove_rescan(&gpu.pci_bus_id)?;
+                }
+            }
+        }
+        GpuVendor::Amd => {
+            let recover = amdgpu_debugfs(&gpu.pci_bus_id).map(|d| d.join("amdgpu_gpu_recover"));
+            match recover.filter(|p| p.exists()) {
+                Some(path) => {
+                    // Reading the file runs the driver's recovery
+                    std::fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?;
+                }
+                None => {
+                    if !Path::new("/sys/module/vendor_reset").exists() {
+                        debug!("vendor-reset is not loaded; using the kernel's PCI reset");
+                    }
+                    let path = Path::new(PCI_DEVICES).join(&gpu.pci_bus_id).join("reset");
+                    std::fs::write(&path, "1")
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                }
+            }
+        }
+    }
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    if !responds(gpu).await {
+        return Err(anyhow!(
+            "{} did not come back after the reset",
+            gpu.pci_bus_id
+        ));
+    }
+    info!("✅ GPU {} reset", gpu.pci_bus_id);
+    Ok(())
+}
+
+fn pci_remove_rescan(pci_bus_id: &str) -> Result<()> {
+    let device = Path::new(PCI_DEVICES).join(pci_bus_id);
+    if device.exists() {
+        std::fs::write(device.join("remove"), "1")
+            .with_context(|| format!("Failed to remove {} from the PCI bus", pci_bus_id))?;
+    }
+    std::fs::write("/sys/bus/pci/rescan", "1").context("Failed to rescan the PCI bus")?;
+    Ok(())
+}
+
+/// `/sys/kernel/debug/dri/<minor>` of an AMD GPU
+fn amdgpu_debugfs(pci_bus_id: &str) -> Option<PathBuf> {
+    std::fs::read_dir(Path::new(PCI_DEVICES).join(pci_bus_id).join("drm"))
+        .ok()?
+        .flatten()
+        .find_map(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .strip_prefix("card")
+                .map(|minor| PathBuf::from(format!("/sys/kernel/debug/dri/{}", minor)))
+        })
+}
+
+/// Whether the driver can talk to the GPU
+async fn responds(gpu: &GpuDevice) -> bool {
+    match gpu.vendor {
+        GpuVendor::Nvidia => AsyncCommand::new("nvidia-smi")
+            .args([
+                "-i",
+                &gpu.pci_bus_id,
+                "--query-gpu=pci.bus_id",
+                "--format=csv,noheader",
+            ])
+            .output()
+            .await
+            .is_ok_and(|o| o.status.success()),
+        GpuVendor::Amd => Path::new(PCI_DEVICES)
+            .join(&gpu.pci_bus_id)
+            .join("drm")
+            .exists(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub pci_bus_id: String,
+    pub vendor: GpuVendor,
+    pub reason: String,
+    /// Seconds since the Unix epoch
+    pub since: u64,
+}
+
+fn quarantine_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("quarantine.json")
+}
+
+pub fn quarantined(data_dir: &Path) -> Vec<QuarantineEntry> {
+    std::fs::read(quarantine_path(data_dir))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+fn save_quarantine(data_dir: &Path, entries: &[QuarantineEntry]) -> Result<()> {
+    let path = quarantine_path(data_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(entries)?)?;
+    Ok(())
+}
+
+fn quarantine(data_dir: &Path, gpu: &GpuDevice, reason: &str) -> Result<()> {
+    let mut entries = quarantined(data_dir);
+    entries.retain(|e| e.pci_bus_id != gpu.pci_bus_id);
+    entries.push(QuarantineEntry {
+        pci_bus_id: gpu.pci_bus_id.clone(),
+        vendor: gpu.vendor,
+        reason: reason.to_string(),
+        since: now(),
+    });
+    save_quarantine(data_dir, &entries)
+}
+
+/// Return a GPU to service; false if it wasn't quarantined
+pub fn unquarantine(data_dir: &Path, pci_bus_id: &str) -> Result<bool> {
+    let pci_bus_id = normalize_pci(pci_bus_id);
+    let mut entries = quarantined(data_dir);
+    let before = entries.len();
+    entries.retain(|e| e.pci_bus_id != pci_bus_id);
+    if entries.len() == before {
+        return Ok(false);
+    }
+    save_quarantine(data_dir, &entries)?;
+    record_event(
+        data_dir,
+        &RecoveryEvent::new(&pci_bus_id, "unquarantine", "returned to service"),
+    )?;
+    info!("✅ GPU {} returned to service", pci_bus_id);
+    Ok(true)
+}
+
+/// Refuse to start a GPU service when every GPU is quarantined
+pub fn check_quarantine(data_dir: &Path, service: &str) -> Result<()> {
+    let entries = quarantined(data_dir);
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let quarantined: HashSet<&str> = entries.iter().map(|e| e.pci_bus_id.as_str()).collect();
+    let healthy = list_gpus()
+        .into_iter()
+        .filter(|g| !quarantined.contains(g.pci_bus_id.as_str()))
+        .count();
+    let ids: Vec<&str> = quarantined.into_iter().collect();
+    if healthy == 0 {
+        return Err(anyhow!(
+            "Not starting {}: GPU {} is quarantined after a failed reset (see `bolt gaming gpu health`)",
+            service,
+            ids.join(", ")
+        ));
+    }
+    warn!(
+        "GPU {} is quarantined; make sure {} doesn't use it",
+        ids.join(", "),
+        service
+    );
+    Ok(())
+}
+
+/// One entry of the recovery event trail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryEvent {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub gpu: String,
+    /// fault, reset, reset-failed, quarantine or unquarantine
+    pub action: String,
+    pub xid: Option<u32>,
+    pub container: Option<String>,
+    pub process: Option<String>,
+    pub message: String,
+}
+
+impl RecoveryEvent {
+    fn new(gpu: &str, action: &str, message: impl Into<String>) -> Self {
+        Self {
+            timestamp: now(),
+            gpu: gpu.to_string(),
+            action: action.to_string(),
+            xid: None,
+            container: None,
+            process: None,
+            message: message.into(),
+        }
+    }
+}
+
+pub fn events_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("recovery-events.jsonl")
+}
+
+fn record_event(data_dir: &Path, event: &RecoveryEvent) -> Result<()> {
+    let path = events_path(data_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(event)?)?;
+    Ok(())
+}
+
+/// The event trail, oldest first
+pub fn read_events(data_dir: &Path) -> Result<Vec<RecoveryEvent>> {
+    let path = events_path(data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuHealth {
+    pub pci_bus_id: String,
+    pub vendor: GpuVendor,
+    pub responding: bool,
+    pub quarantined: Option<QuarantineEntry>,
+    /// Faults in the kernel log since boot
+    pub faults: Vec<GpuFault>,
+}
+
+/// State of every GPU, with the faults the kernel logged for it since boot
+pub async fn health(config: &BoltConfig) -> Result<Vec<GpuHealth>> {
+    let faults = match kernel_log().await {
+        Ok(lines) => parse_kernel_log(&lines),
+        Err(e) => {
+            warn!("{}", e);
+            Vec::new()
+        }
+    };
+    let gpus = list_gpus();
+    let quarantined = quarantined(&config.data_dir);
+    let mut report = Vec::new();
+    for gpu in &gpus {
+        report.push(GpuHealth {
+            pci_bus_id: gpu.pci_bus_id.clone(),
+            vendor: gpu.vendor,
+            responding: responds(gpu).await,
+            quarantined: quarantined
+                .iter()
+                .find(|q| q.pci_bus_id == gpu.pci_bus_id)
+                .cloned(),
+            faults: faults
+                .iter()
+                .filter(|f| fault_device(f, &gpus).is_some_and(|d| d.pci_bus_id == gpu.pci_bus_id))
+                .cloned()
+                .collect(),
+        });
+    }
+    Ok(report)
+}
+
+/// The GPU a fault is about; amdgpu's timeout lines don't name one, which is
+/// only unambiguous with a single AMD GPU
+fn fault_device<'a>(fault: &GpuFault, gpus: &'a [GpuDevice]) -> Option<&'a GpuDevice> {
+    match fault.pci_bus_id {
+        Some(ref id) => gpus.iter().find(|g| &g.pci_bus_id == id),
+        None => {
+            let mut same_vendor = gpus.iter().filter(|g| g.vendor == fault.vendor);
+            let only = same_vendor.next();
+            same_vendor.next().is_none().then_some(only).flatten()
+        }
+    }
+}
+
+/// Reset a GPU by hand, quarantining it if the reset fails
+pub async fn recover(config: &BoltConfig, pci_bus_id: &str, force: bool) -> Result<()> {
+    let pci_bus_id = normalize_pci(pci_bus_id);
+    let gpu = list_gpus()
+        .into_iter()
+        .find(|g| g.pci_bus_id == pci_bus_id)
+        .ok_or_else(|| anyhow!("No GPU at {}", pci_bus_id))?;
+    let alerts = AlertingEngine::new(&config.data_dir, config.alerts.clone());
+    recover_device(config, &alerts, &gpu, FaultKind::Hang, force).await
+}
+
+async fn recover_device(
+    config: &BoltConfig,
+    alerts: &AlertingEngine,
+    gpu: &GpuDevice,
+    kind: FaultKind,
+    force: bool,
+) -> Result<()> {
+    let key = format!("gpu-recovery:{}", gpu.pci_bus_id);
+    match reset(gpu, kind, force).await {
+        Ok(()) => {
+            record_event(
+                &config.data_dir,
+                &RecoveryEvent::new(&gpu.pci_bus_id, "reset", "GPU reset and responding"),
+            )?;
+            alerts.resolve(&key)?;
+            Ok(())
+        }
+        Err(e) if e.to_string().contains("still in use") => Err(e),
+        Err(e) => {
+            error!("GPU {} reset failed: {}", gpu.pci_bus_id, e);
+            record_event(
+                &config.data_dir,
+                &RecoveryEvent::new(&gpu.pci_bus_id, "reset-failed", e.to_string()),
+            )?;
+            quarantine(&config.data_dir, gpu, &e.to_string())?;
+            record_event(
+                &config.data_dir,
+                &RecoveryEvent::new(&gpu.pci_bus_id, "quarantine", e.to_string()),
+            )?;
+            alerts
+                .fire(&Alert::new(
+                    &key,
+                    AlertSeverity::Critical,
+                    format!(
+                        "GPU {} could not be reset and is quarantined: {}",
+                        gpu.pci_bus_id, e
+                    ),
+                ))
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Follow the kernel log for GPU faults until interrupted, resetting GPUs
+/// that need it as soon as nothing holds them open anymore
+pub async fn watch(config: &BoltConfig, interval: Duration) -> Result<()> {
+    let runtime = crate::runtime::detect_container_runtime().await.ok();
+    let alerts = AlertingEngine::new(&config.data_dir, config.alerts.clone());
+    if let Err(e) = super::drivers::check_at_startup(config).await {
+        warn!("Could not check the GPU driver version: {}", e);
+    }
+
+    // Faults from before the watch started are history, not news
+    let mut seen: HashSet<String> = kernel_log().await?.into_iter().collect();
+    let mut pending: HashMap<String, (GpuDevice, FaultKind)> = HashMap::new();
+    info!(
+        "🩺 Watching the kernel log for GPU faults every {}s",
+        interval.as_secs_f64()
+    );
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+
+        let lines = kernel_log().await?;
+        let new: Vec<String> = lines
+            .iter()
+            .filter(|l| !seen.contains(*l))
+            .cloned()
+            .collect();
+        seen = lines.into_iter().collect();
+
+        let gpus = list_gpus();
+        for fault in parse_kernel_log(&new) {
+            let gpu = fault_device(&fault, &gpus).cloned();
+            let gpu_id = gpu
+                .as_ref()
+                .map(|g| g.pci_bus_id.clone())
+                .or_else(|| fault.pci_bus_id.clone())
+                .unwrap_or_else(|| "unknown".to_string());
+            let container = match (runtime.as_deref(), fault.pid) {
+                (Some(runtime), Some(pid)) => container_of(runtime, pid).await,
+                _ => None,
+            };
+
+            let culprit = match (&container, &fault.process) {
+                (Some(c), _) => format!(" in container {}", c),
+                (None, Some(p)) => format!(" in {}", p),
+                _ => String::new(),
+            };
+            let message = format!("GPU {}: {}{}", gpu_id, fault.message, culprit);
+            warn!("💥 {}", message);
+            record_event(
+                &config.data_dir,
+                &RecoveryEvent {
+                    xid: fault.xid,
+                    container: container.clone(),
+                    process: fault.process.clone(),
+                    ..RecoveryEvent::new(&gpu_id, "fault", fault.message.clone())
+                },
+            )?;
+
+            let severity = if fault.kind.needs_reset() {
+                AlertSeverity::Critical
+            } else {
+                AlertSeverity::Warning
+            };
+            alerts
+                .fire(&Alert::new(
+                    format!("gpu-fault:{}:{}", gpu_id, fault.logged_at),
+                    severity,
+                    message,
+                ))
+                .await?;
+
+            if let (true, Some(gpu)) = (fault.kind.needs_reset(), gpu) {
+                pending.insert(gpu.pci_bus_id.clone(), (gpu, fault.kind));
+            }
+        }
+
+        let mut done = Vec::new();
+        for (id, (gpu, kind)) in &pending {
+            if !processes_using(gpu).is_empty() {
+                debug!("GPU {} still in use; reset deferred", id);
+                continue;
+            }
+            // A failed reset quarantines the GPU; either way it's handled
+            let _ = recover_device(config, &alerts, gpu, *kind, false).await;
+            done.push(id.clone());
+        }
+        for id in done {
+            pending.remove(&id);
+        }
+    }
+
+    if !pending.is_empty() {
+        let ids: Vec<&String> = pending.keys().collect();
+        warn!(
+            "GPU reset still pending for {:?}; run `bolt gaming gpu reset` once it's free",
+            ids
+        );
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nvidia_xids_and_amdgpu_hangs() {
+        let fault = parse_kernel_line(
+            "2026-10-16T21:04:11,512344+02:00 NVRM: Xid (PCI:0000:01:00): 79, pid='<unknown>', name=<unknown>, GPU has fallen off the bus.",
+        )
+        .unwrap();
+        assert_eq!(fault.pci_bus_id.as_deref(), Some("0000:01:00.0"));
+        assert_eq!(fault.kind, FaultKind::Lost);
+        assert_eq!(fault.process, None);
+
+        let fault = parse_kernel_line(
+            "2026-10-16T21:02:40,000001+02:00 NVRM: Xid (PCI:0000:01:00): 13, pid=4242, name=game.exe, Graphics SM Warp Exception",
+        )
+        .unwrap();
+        assert_eq!(fault.kind, FaultKind::Application);
+        assert_eq!(fault.pid, Some(4242));
+        assert_eq!(fault.process.as_deref(), Some("game.exe"));
+
+        let log = vec![
+            "2026-10-16T22:00:00,1+02:00 [drm:amdgpu_job_timedout [amdgpu]] *ERROR* ring gfx_0.0.0 timeout, signaled seq=1, emitted seq=3".to_string(),
+            "2026-10-16T22:00:00,2+02:00 [drm:amdgpu_job_timedout [amdgpu]] *ERROR* Process information: process game.exe pid 777 thread game.exe:cs0 pid 780".to_string(),
+            "2026-10-16T22:00:01,3+02:00 amdgpu 0000:03:00.0: amdgpu: GPU reset(2) succeeded!".to_string(),
+            "2026-10-16T22:00:02,4+02:00 usb 1-1: new high-speed USB device".to_string(),
+        ];
+        let faults = parse_kernel_log(&log);
+        assert_eq!(faults.len(), 2);
+        assert_eq!(faults[0].kind, FaultKind::Hang);
+        assert_eq!(faults[0].pid, Some(777));
+        assert_eq!(faults[1].kind, FaultKind::Recovered);
+        assert_eq!(faults[1].pci_bus_id.as_deref(), Some("0000:03:00.0"));
+    }
+}
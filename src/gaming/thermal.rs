@@ -0,0 +1,511 @@
+// GPU thermal and power protection
+//
+// `bolt gaming thermal watch` samples the GPU metrics of services with a
+// `[services.<name>.thermal]` policy. When the temperature or power draw stays
+// above its limit for `sustain`, the policy's action is taken:
+//
+// - `pause`: freeze the container until the GPU cools down
+// - `frame-limit`: cap the frame rate through the MangoHud config Bolt
+//   mounts into the container, which MangoHud reloads on change
+// - `notify`: raise an alert through the `[alerts]` sinks
+//
+// The action is undone once the GPU has been below the limit minus the
+// hysteresis for `sustain` as well. Every action is appended to
+// `<data_dir>/gpu/thermal-events.jsonl`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::{BoltConfig, Service, ThermalConfig};
+use crate::monitoring::alerts::{Alert, AlertSeverity, AlertingEngine};
+use crate::monitoring::{GPUMetrics, MetricsCollector};
+
+/// Where the MangoHud config of a frame-limited container is mounted
+const MANGOHUD_DIR: &str = "/run/bolt/thermal";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThermalAction {
+    Pause,
+    FrameLimit,
+    Notify,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThermalPolicy {
+    pub max_temperature: Option<f64>,
+    pub max_power: Option<f64>,
+    pub sustain: Duration,
+    pub temperature_hysteresis: f64,
+    pub power_hysteresis: f64,
+    pub action: ThermalAction,
+    pub frame_limit: u32,
+    pub interval: Duration,
+}
+
+impl ThermalPolicy {
+    pub fn from_config(config: &ThermalConfig) -> Result<Self> {
+        if config.max_temperature.is_none() && config.max_power.is_none() {
+            return Err(anyhow!("set max_temperature, max_power or both"));
+        }
+        if config.action == ThermalAction::FrameLimit && config.frame_limit == 0 {
+            return Err(anyhow!("frame_limit must be at least 1"));
+        }
+        let interval = parse_duration(&config.interval)?;
+        if interval.is_zero() {
+            return Err(anyhow!("interval must be greater than zero"));
+        }
+
+        Ok(Self {
+            max_temperature: config.max_temperature.map(f64::from),
+            max_power: config.max_power.map(f64::from),
+            sustain: parse_duration(&config.sustain)?,
+            temperature_hysteresis: config.temperature_hysteresis.into(),
+            power_hysteresis: config.power_hysteresis.into(),
+            action: config.action,
+            frame_limit: config.frame_limit,
+            interval,
+        })
+    }
+
+    /// Why a sample exceeds the limits, if it does
+    fn exceeded(&self, temperature: f64, power: f64) -> Option<String> {
+        let mut reasons = Vec::new();
+        if let Some(max) = self.max_temperature
+            && temperature > max
+        {
+            reasons.push(format!("{:.0}°C > {:.0}°C", temperature, max));
+        }
+        if let Some(max) = self.max_power
+            && power > max
+        {
+            reasons.push(format!("{:.0}W > {:.0}W", power, max));
+        }
+        (!reasons.is_empty()).then(|| reasons.join(", "))
+    }
+
+    /// Whether a sample is far enough below the limits to restore
+    fn cleared(&self, temperature: f64, power: f64) -> bool {
+        self.max_temperature
+            .is_none_or(|max| temperature <= max - self.temperature_hysteresis)
+            && self
+                .max_power
+                .is_none_or(|max| power <= max - self.power_hysteresis)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThermalTransition {
+    Throttle(String),
+    Restore,
+}
+
+/// Turns GPU samples into throttle/restore transitions, with hysteresis and
+/// the `sustain` delay in both directions
+#[derive(Debug, Clone)]
+pub struct ThermalGuard {
+    policy: ThermalPolicy,
+    throttled: bool,
+    since: Option<Instant>,
+}
+
+impl ThermalGuard {
+    pub fn new(policy: ThermalPolicy) -> Self {
+        Self {
+            policy,
+            throttled: false,
+            since: None,
+        }
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    pub fn observe(
+        &mut self,
+        temperature: f64,
+        power: f64,
+        now: Instant,
+    ) -> Option<ThermalTransition> {
+        let transition = if self.throttled {
+            self.policy
+                .cleared(temperature, power)
+                .then_some(ThermalTransition::Restore)
+        } else {
+            self.policy
+                .exceeded(temperature, power)
+                .map(ThermalTransition::Throttle)
+        };
+
+        let Some(transition) = transition else {
+            self.since = None;
+            return None;
+        };
+        let since = *self.since.get_or_insert(now);
+        if now.duration_since(since) < self.policy.sustain {
+            return None;
+        }
+
+        self.since = None;
+        self.throttled = !self.throttled;
+        Some(match transition {
+            ThermalTransition::Throttle(reason) => ThermalTransition::Throttle(format!(
+                "{} for {}s",
+                reason,
+                self.policy.sustain.as_secs()
+            )),
+            restore => restore,
+        })
+    }
+}
+
+/// One entry of the thermal event trail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThermalEvent {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub service: String,
+    pub container: String,
+    pub gpu: String,
+    /// pause, resume, frame-limit, frame-unlimit, notify or resolved
+    pub action: String,
+    pub reason: String,
+    pub temperature_celsius: f64,
+    pub power_watts: f64,
+}
+
+pub fn events_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("thermal-events.jsonl")
+}
+
+fn record_event(data_dir: &Path, event: &ThermalEvent) -> Result<()> {
+    let path = events_path(data_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(event)?)?;
+    Ok(())
+}
+
+/// The event trail, oldest first, optionally for one service
+pub fn read_events(data_dir: &Path, service: Option<&str>) -> Result<Vec<ThermalEvent>> {
+    let path = events_path(data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<ThermalEvent>(line).ok())
+        .filter(|e| service.is_none_or(|s| e.service == s))
+        .collect())
+}
+
+fn mangohud_dir(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("gpu").join("thermal").join(container)
+}
+
+fn write_frame_limit(data_dir: &Path, container: &str, fps: u32) -> Result<()> {
+    let dir = mangohud_dir(data_dir, container);
+    std::fs::create_dir_all(&dir)?;
+    // fps_limit=0 means unlimited
+    std::fs::write(
+        dir.join("MangoHud.conf"),
+        format!("no_display\nfps_limit={}\n", fps),
+    )?;
+    Ok(())
+}
+
+/// `run` arguments a thermal policy needs: the `frame-limit` action mounts a
+/// MangoHud config that it rewrites while throttling
+pub fn run_args(
+    config: &BoltConfig,
+    container: &str,
+    thermal: &ThermalConfig,
+) -> Result<Vec<String>> {
+    if thermal.action != ThermalAction::FrameLimit {
+        return Ok(Vec::new());
+    }
+    write_frame_limit(&config.data_dir, container, 0)?;
+    Ok(vec![
+        "-v".to_string(),
+        format!(
+            "{}:{}:ro",
+            mangohud_dir(&config.data_dir, container).display(),
+            MANGOHUD_DIR
+        ),
+        "-e".to_string(),
+        "MANGOHUD=1".to_string(),
+        "-e".to_string(),
+        format!("MANGOHUD_CONFIGFILE={}/MangoHud.conf", MANGOHUD_DIR),
+    ])
+}
+
+struct Watched {
+    service: String,
+    container: String,
+    device: Option<u32>,
+    guard: ThermalGuard,
+    last: Option<GPUMetrics>,
+}
+
+/// Watch the GPUs of every service (or the named ones) with a thermal policy
+/// until interrupted, restoring throttled services on exit
+pub async fn watch(config: &BoltConfig, services: &[String]) -> Result<()> {
+    let boltfile = config.load_boltfile()?;
+    let mut watched = Vec::new();
+    for (name, service) in &boltfile.services {
+        if !services.is_empty() && !services.contains(name) {
+            continue;
+        }
+        let Some(ref thermal) = service.thermal else {
+            continue;
+        };
+        watched.push(Watched {
+            service: name.clone(),
+            container: format!("{}_{}", boltfile.project, name),
+            device: gpu_device(service),
+            guard: ThermalGuard::new(ThermalPolicy::from_config(thermal)?),
+            last: None,
+        });
+    }
+    if watched.is_empty() {
+        return Err(anyhow!(
+            "No services with a [services.<name>.thermal] policy to watch"
+        ));
+    }
+
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let collector = MetricsCollector::new().await?;
+    let alerts = AlertingEngine::new(&config.data_dir, config.alerts.clone());
+    let period = watched
+        .iter()
+        .map(|w| w.guard.policy.interval)
+        .min()
+        .unwrap_or(Duration::from_secs(5));
+    info!(
+        "🌡️  Watching GPU thermals for {} service(s) every {}s",
+        watched.len(),
+        period.as_secs_f64()
+    );
+
+    let mut interval = tokio::time::interval(period);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+
+        let samples = match collector.sample_gpus().await {
+            Ok(samples) if !samples.is_empty() => samples,
+            Ok(_) => {
+                warn!("No GPU metrics available");
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to sample GPU metrics: {}", e);
+                continue;
+            }
+        };
+
+        for w in &mut watched {
+            let Some(sample) = select_gpu(&samples, w.device) else {
+                continue;
+            };
+            debug!(
+                "{}: {} at {:.0}°C, {:.0}W",
+                w.container, sample.gpu_id, sample.temperature_celsius, sample.power_usage_watts
+            );
+            let transition = w.guard.observe(
+                sample.temperature_celsius,
+                sample.power_usage_watts,
+                Instant::now(),
+            );
+            w.last = Some(sample.clone());
+            if let Some(transition) = transition
+                && let Err(e) = apply(config, &runtime, &alerts, w, sample, transition).await
+            {
+                warn!("Thermal action for {} failed: {}", w.container, e);
+            }
+        }
+    }
+
+    // Don't leave a service paused or limited behind
+    for w in watched.iter().filter(|w| w.guard.is_throttled()) {
+        let Some(ref sample) = w.last else {
+            continue;
+        };
+        if let Err(e) = apply(
+            config,
+            &runtime,
+            &alerts,
+            w,
+            sample,
+            ThermalTransition::Restore,
+        )
+        .await
+        {
+            warn!("Failed to restore {}: {}", w.container, e);
+        }
+    }
+    info!("🌡️  Thermal watch stopped");
+    Ok(())
+}
+
+/// The GPU index a service is pinned to, if any
+fn gpu_device(service: &Service) -> Option<u32> {
+    let gpu = service.gaming.as_ref()?.gpu.as_ref()?;
+    gpu.nvidia
+        .as_ref()
+        .and_then(|n| n.device)
+        .or_else(|| gpu.amd.as_ref().and_then(|a| a.device))
+}
+
+/// The service's GPU, or the hottest one when it isn't pinned
+fn select_gpu(samples: &[GPUMetrics], device: Option<u32>) -> Option<&GPUMetrics> {
+    match device {
+        Some(index) => samples.iter().find(|s| {
+            s.gpu_id == format!("gpu-{}", index) || s.gpu_id == format!("amd-gpu-{}", index)
+        }),
+        None => samples
+            .iter()
+            .max_by(|a, b| a.temperature_celsius.total_cmp(&b.temperature_celsius)),
+    }
+}
+
+async fn apply(
+    config: &BoltConfig,
+    runtime: &str,
+    alerts: &AlertingEngine,
+    watched: &Watched,
+    sample: &GPUMetrics,
+    transition: ThermalTransition,
+) -> Result<()> {
+    let container = &watched.container;
+    let throttle = matches!(transition, ThermalTransition::Throttle(_));
+    let reason = match transition {
+        ThermalTransition::Throttle(reason) => reason,
+        ThermalTransition::Restore => "back within limits".to_string(),
+    };
+    let alert_key = format!("gpu.thermal.{}", container);
+
+    let action = match (watched.guard.policy.action, throttle) {
+        (ThermalAction::Pause, true) => {
+            runtime_command(runtime, "pause", container).await?;
+            warn!("🔥 Paused {}: {}", container, reason);
+            "pause"
+        }
+        (ThermalAction::Pause, false) => {
+            runtime_command(runtime, "unpause", container).await?;
+            info!("❄️  Resumed {}: {}", container, reason);
+            "resume"
+        }
+        (ThermalAction::FrameLimit, true) => {
+            let fps = watched.guard.policy.frame_limit;
+            write_frame_limit(&config.data_dir, container, fps)?;
+            warn!("🔥 Limited {} to {} FPS: {}", container, fps, reason);
+            "frame-limit"
+        }
+        (ThermalAction::FrameLimit, false) => {
+            write_frame_limit(&config.data_dir, container, 0)?;
+            info!("❄️  Lifted frame limit of {}: {}", container, reason);
+            "frame-unlimit"
+        }
+        (ThermalAction::Notify, true) => {
+            let message = format!("GPU of {} is running hot: {}", container, reason);
+            alerts
+                .fire(&Alert::new(&alert_key, AlertSeverity::Critical, message))
+                .await?;
+            "notify"
+        }
+        (ThermalAction::Notify, false) => {
+            alerts.resolve(&alert_key)?;
+            info!("❄️  GPU of {} {}", container, reason);
+            "resolved"
+        }
+    };
+
+    record_event(
+        &config.data_dir,
+        &ThermalEvent {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            service: watched.service.clone(),
+            container: container.clone(),
+            gpu: sample.gpu_id.clone(),
+            action: action.to_string(),
+            reason,
+            temperature_celsius: sample.temperature_celsius,
+            power_watts: sample.power_usage_watts,
+        },
+    )
+}
+
+async fn runtime_command(runtime: &str, command: &str, container: &str) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .args([command, container])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            command,
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_waits_for_sustain_and_applies_hysteresis() {
+        let config = ThermalConfig {
+            max_temperature: Some(85),
+            sustain: "10s".to_string(),
+            temperature_hysteresis: 5,
+            action: ThermalAction::Pause,
+            ..Default::default()
+        };
+        let mut guard = ThermalGuard::new(ThermalPolicy::from_config(&config).unwrap());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A short spike does not trip
+        assert_eq!(guard.observe(90.0, 0.0, at(0)), None);
+        assert_eq!(guard.observe(80.0, 0.0, at(5)), None);
+        assert_eq!(guard.observe(90.0, 0.0, at(6)), None);
+        assert!(matches!(
+            guard.observe(91.0, 0.0, at(16)),
+            Some(ThermalTransition::Throttle(_))
+        ));
+
+        // Below the limit but inside the hysteresis band stays throttled
+        assert_eq!(guard.observe(82.0, 0.0, at(20)), None);
+        assert_eq!(guard.observe(82.0, 0.0, at(40)), None);
+        assert_eq!(guard.observe(79.0, 0.0, at(41)), None);
+        assert_eq!(
+            guard.observe(78.0, 0.0, at(51)),
+            Some(ThermalTransition::Restore)
+        );
+        assert!(!guard.is_throttled());
+
+        assert!(ThermalPolicy::from_config(&ThermalConfig::default()).is_err());
+    }
+}
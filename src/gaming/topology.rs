@@ -0,0 +1,627 @@
+// GPU topology and placement
+//
+// On multi-GPU hosts it matters which GPUs a workload gets: a pair joined by
+// NVLink, or sharing a PCIe switch, moves data between them far faster than
+// two GPUs on different sockets. The topology is read from `nvidia-smi topo
+// -m` for NVIDIA GPUs and from the PCI hierarchy and `numa_node` in sysfs
+// otherwise. Links, closest first:
+//
+//   NV    NVLink
+//   PXB   behind the same PCIe bridge or switch
+//   PHB   same PCIe host bridge
+//   NODE  same NUMA node
+//   SYS   across NUMA nodes
+//
+// A GPU service with `gpu.count` or `gpu.topology` ("nvlink-pair",
+// "same-pcie-switch", "same-numa") is placed on the closest set of free GPUs
+// that satisfies the hint, and only those devices are passed in. GPUs are
+// free when no running container carries them in its `bolt.gpus` label and
+// they aren't quarantined. With none free, an interactive service may share
+// GPUs that only batch containers hold (see `priority`).
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use super::priority::{self, GpuPriority};
+use super::recovery::{self, GpuVendor};
+use crate::config::GpuConfig;
+
+/// Label listing the PCI addresses of the GPUs a container was placed on
+pub const LABEL: &str = "bolt.gpus";
+
+/// How two GPUs are connected, closest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Link {
+    Same,
+    NvLink,
+    PcieSwitch,
+    HostBridge,
+    Numa,
+    System,
+}
+
+impl Link {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Link::Same => "X",
+            Link::NvLink => "NV",
+            Link::PcieSwitch => "PXB",
+            Link::HostBridge => "PHB",
+            Link::Numa => "NODE",
+            Link::System => "SYS",
+        }
+    }
+
+    /// A cell of `nvidia-smi topo -m`
+    fn from_nvidia(cell: &str) -> Option<Self> {
+        match cell {
+            "X" => Some(Link::Same),
+            "PIX" | "PXB" => Some(Link::PcieSwitch),
+            "PHB" => Some(Link::HostBridge),
+            "NODE" => Some(Link::Numa),
+            "SYS" | "SOC" => Some(Link::System),
+            nv if nv.starts_with("NV") => Some(Link::NvLink),
+            _ => None,
+        }
+    }
+}
+
+/// `gpu.topology` placement hint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TopologyHint {
+    #[default]
+    Any,
+    /// Two GPUs joined by NVLink
+    NvlinkPair,
+    /// GPUs behind the same PCIe switch
+    SamePcieSwitch,
+    /// GPUs attached to the same NUMA node
+    SameNuma,
+}
+
+impl TopologyHint {
+    /// The furthest link allowed between any two placed GPUs
+    fn max_link(&self) -> Link {
+        match self {
+            TopologyHint::Any => Link::System,
+            TopologyHint::NvlinkPair => Link::NvLink,
+            TopologyHint::SamePcieSwitch => Link::PcieSwitch,
+            TopologyHint::SameNuma => Link::Numa,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            TopologyHint::Any => "",
+            TopologyHint::NvlinkPair => " joined by NVLink",
+            TopologyHint::SamePcieSwitch => " behind one PCIe switch",
+            TopologyHint::SameNuma => " on one NUMA node",
+        }
+    }
+}
+
+/// How many GPUs a service asks to be placed on, or None when it leaves the
+/// choice to the runtime
+pub fn requested_count(count: Option<u32>, hint: Option<TopologyHint>) -> Result<Option<usize>> {
+    match (count, hint) {
+        (Some(0), _) => Err(anyhow!("gpu.count must be at least 1")),
+        (Some(count), Some(TopologyHint::NvlinkPair)) if count != 2 => Err(anyhow!(
+            "gpu.topology = \"nvlink-pair\" places exactly 2 GPUs, but gpu.count is {}",
+            count
+        )),
+        (Some(count), _) => Ok(Some(count as usize)),
+        (None, Some(TopologyHint::NvlinkPair)) => Ok(Some(2)),
+        (None, Some(_)) => Ok(Some(1)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopologyGpu {
+    pub pci_bus_id: String,
+    pub vendor: GpuVendor,
+    pub numa_node: Option<u32>,
+    /// Index among NVIDIA GPUs, as used by nvidia-smi and NVIDIA_VISIBLE_DEVICES
+    pub nvidia_index: Option<u32>,
+    /// Device nodes to pass into a container using this GPU
+    pub devices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuTopology {
+    pub gpus: Vec<TopologyGpu>,
+    /// `links[a][b]`, indexed like `gpus`
+    pub links: Vec<Vec<Link>>,
+}
+
+impl GpuTopology {
+    pub async fn detect() -> Result<Self> {
+        let mut gpus = Vec::new();
+        let mut bridges = Vec::new();
+        let mut nvidia = 0;
+        for gpu in recovery::list_gpus() {
+            let sysfs = Path::new("/sys/bus/pci/devices").join(&gpu.pci_bus_id);
+            let numa_node = std::fs::read_to_string(sysfs.join("numa_node"))
+                .ok()
+                .and_then(|n| n.trim().parse().ok());
+            let (nvidia_index, devices) = match gpu.vendor {
+                GpuVendor::Nvidia => {
+                    nvidia += 1;
+                    (Some(nvidia - 1), vec![format!("/dev/nvidia{}", nvidia - 1)])
+                }
+                GpuVendor::Amd => (None, drm_devices(&sysfs)),
+            };
+            bridges.push(pci_path(&sysfs));
+            gpus.push(TopologyGpu {
+                pci_bus_id: gpu.pci_bus_id,
+                vendor: gpu.vendor,
+                numa_node,
+                nvidia_index,
+                devices,
+            });
+        }
+
+        let mut links = vec![vec![Link::System; gpus.len()]; gpus.len()];
+        for a in 0..gpus.len() {
+            for b in 0..gpus.len() {
+                links[a][b] = if a == b {
+                    Link::Same
+                } else {
+                    sysfs_link(
+                        &bridges[a],
+                        &bridges[b],
+                        gpus[a].numa_node,
+                        gpus[b].numa_node,
+                    )
+                };
+            }
+        }
+
+        if nvidia > 1 {
+            match nvidia_matrix().await {
+                Ok(matrix) => {
+                    let by_index: HashMap<u32, usize> = gpus
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, g)| g.nvidia_index.map(|n| (n, i)))
+                        .collect();
+                    for (a, row) in matrix.iter().enumerate() {
+                        for (b, link) in row.iter().enumerate() {
+                            if let (Some(&a), Some(&b)) =
+                                (by_index.get(&(a as u32)), by_index.get(&(b as u32)))
+                            {
+                                links[a][b] = *link;
+                            }
+                        }
+                    }
+                }
+                Err(e) => debug!("nvidia-smi topology unavailable, using sysfs: {}", e),
+            }
+        }
+
+        Ok(Self { gpus, links })
+    }
+
+    /// The closest `count` GPUs among `candidates` meeting `hint`
+    fn select(&self, candidates: &[usize], count: usize, hint: TopologyHint) -> Option<Vec<usize>> {
+        let mut best: Option<(usize, Vec<usize>)> = None;
+        let mut chosen = Vec::new();
+        self.search(candidates, count, hint, &mut chosen, &mut best);
+        best.map(|(_, set)| set)
+    }
+
+    fn search(
+        &self,
+        candidates: &[usize],
+        count: usize,
+        hint: TopologyHint,
+        chosen: &mut Vec<usize>,
+        best: &mut Option<(usize, Vec<usize>)>,
+    ) {
+        if chosen.len() == count {
+            let cost = self.cost(chosen);
+            if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                *best = Some((cost, chosen.clone()));
+            }
+            return;
+        }
+        for (i, &gpu) in candidates.iter().enumerate() {
+            if chosen
+                .iter()
+                .all(|&other| self.compatible(gpu, other, hint))
+            {
+                chosen.push(gpu);
+                self.search(&candidates[i + 1..], count, hint, chosen, best);
+                chosen.pop();
+            }
+        }
+    }
+
+    fn compatible(&self, a: usize, b: usize, hint: TopologyHint) -> bool {
+        let same_node = match (self.gpus[a].numa_node, self.gpus[b].numa_node) {
+            (Some(x), Some(y)) => x == y,
+            _ => true,
+        };
+        self.links[a][b] <= hint.max_link() && (hint != TopologyHint::SameNuma || same_node)
+    }
+
+    fn cost(&self, set: &[usize]) -> usize {
+        let mut cost = 0;
+        for (i, &a) in set.iter().enumerate() {
+            for &b in &set[i + 1..] {
+                cost += self.links[a][b] as usize;
+            }
+        }
+        cost
+    }
+}
+
+/// The GPUs a container was placed on
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub gpus: Vec<TopologyGpu>,
+}
+
+impl Placement {
+    /// Arguments for `podman/docker run`
+    pub fn run_args(&self) -> Vec<String> {
+        let ids: Vec<&str> = self.gpus.iter().map(|g| g.pci_bus_id.as_str()).collect();
+        let mut args = vec![
+            "--label".to_string(),
+            format!("{}={}", LABEL, ids.join(",")),
+        ];
+        for device in self.gpus.iter().flat_map(|g| &g.devices) {
+            args.push("--device".to_string());
+            args.push(device.clone());
+        }
+        let nvidia: Vec<String> = self
+            .gpus
+            .iter()
+            .filter_map(|g| g.nvidia_index.map(|i| i.to_string()))
+            .collect();
+        if !nvidia.is_empty() {
+            args.push("-e".to_string());
+            args.push(format!("NVIDIA_VISIBLE_DEVICES={}", nvidia.join(",")));
+        }
+        args
+    }
+}
+
+/// Whether `device` gives access to one or all GPUs rather than to a shared
+/// control node, so placement can replace it with the placed GPUs' devices
+pub fn is_gpu_device(device: &str) -> bool {
+    device == "/dev/dri"
+        || device.starts_with("/dev/dri/")
+        || device
+            .strip_prefix("/dev/nvidia")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Place a container on GPUs according to `gpu.count` and `gpu.topology`;
+/// None when the service sets neither
+pub async fn place(runtime: &str, data_dir: &Path, gpu: &GpuConfig) -> Result<Option<Placement>> {
+    let hint = gpu.topology.unwrap_or_default();
+    let Some(count) = requested_count(gpu.count, gpu.topology)? else {
+        return Ok(None);
+    };
+    let vendor = match (&gpu.nvidia, &gpu.amd) {
+        (Some(_), None) => Some(GpuVendor::Nvidia),
+        (None, Some(_)) => Some(GpuVendor::Amd),
+        _ => None,
+    };
+
+    let topology = GpuTopology::detect().await?;
+    let holders = priority::holders(runtime).await;
+    let in_use = priority::placed(&holders);
+    let quarantined: Vec<String> = recovery::quarantined(data_dir)
+        .into_iter()
+        .map(|q| q.pci_bus_id)
+        .collect();
+    let candidates: Vec<usize> = topology
+        .gpus
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| vendor.is_none_or(|v| v == g.vendor))
+        .filter(|(_, g)| {
+            !in_use.contains_key(&g.pci_bus_id) && !quarantined.contains(&g.pci_bus_id)
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut set = topology.select(&candidates, count, hint);
+    if set.is_none() {
+        let shareable = priority::shareable(&holders, GpuPriority::of(gpu));
+        let widened: Vec<usize> = topology
+            .gpus
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| vendor.is_none_or(|v| v == g.vendor))
+            .filter(|(_, g)| !quarantined.contains(&g.pci_bus_id))
+            .filter(|(_, g)| {
+                !in_use.contains_key(&g.pci_bus_id) || shareable.contains(&g.pci_bus_id)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if widened.len() > candidates.len() {
+            set = topology.select(&widened, count, hint);
+            for &i in set.iter().flatten() {
+                if let Some(holder) = in_use.get(&topology.gpus[i].pci_bus_id) {
+                    info!(
+                        "⚖️  Sharing GPU {} with batch container {}, which yields to this one",
+                        topology.gpus[i].pci_bus_id, holder
+                    );
+                }
+            }
+        }
+    }
+    let Some(set) = set else {
+        return Err(anyhow!(
+            "No {} free GPU(s){}: {} of {} GPUs are free (see `bolt gaming gpu topology`)",
+            count,
+            hint.describe(),
+            candidates.len(),
+            topology.gpus.len()
+        ));
+    };
+    let placement = Placement {
+        gpus: set.iter().map(|&i| topology.gpus[i].clone()).collect(),
+    };
+    let ids: Vec<&str> = placement
+        .gpus
+        .iter()
+        .map(|g| g.pci_bus_id.as_str())
+        .collect();
+    let link = set
+        .iter()
+        .flat_map(|&a| set.iter().map(move |&b| (a, b)))
+        .map(|(a, b)| topology.links[a][b])
+        .max()
+        .unwrap_or(Link::Same);
+    info!("🧭 Placed on GPU {} ({})", ids.join(", "), link.label());
+    Ok(Some(placement))
+}
+
+/// PCI address of each GPU held by a running container, with its name
+pub async fn in_use(runtime: &str) -> HashMap<String, String> {
+    let mut held = HashMap::new();
+    let Ok(output) = AsyncCommand::new(runtime)
+        .args(["ps", "-q", "--filter", &format!("label={}", LABEL)])
+        .output()
+        .await
+    else {
+        return held;
+    };
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if ids.is_empty() {
+        return held;
+    }
+    let format = format!("{{{{.Name}}}} {{{{index .Config.Labels \"{}\"}}}}", LABEL);
+    let Ok(output) = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", &format])
+        .args(&ids)
+        .output()
+        .await
+    else {
+        return held;
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some((name, gpus)) = line.split_once(' ') {
+            for gpu in gpus.split(',').filter(|g| !g.is_empty()) {
+                held.insert(gpu.to_string(), name.trim_start_matches('/').to_string());
+            }
+        }
+    }
+    held
+}
+
+/// The bridges between the PCI root and the device, root first
+fn pci_path(sysfs: &Path) -> Vec<String> {
+    let Ok(path) = std::fs::canonicalize(sysfs) else {
+        return Vec::new();
+    };
+    let mut parts: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .skip_while(|c| !c.starts_with("pci"))
+        .collect();
+    parts.pop();
+    parts
+}
+
+fn sysfs_link(a: &[String], b: &[String], numa_a: Option<u32>, numa_b: Option<u32>) -> Link {
+    let shared = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    match shared {
+        // Something below the root port, i.e. a switch
+        2.. => Link::PcieSwitch,
+        1 => Link::HostBridge,
+        _ if numa_a.is_some() && numa_a == numa_b => Link::Numa,
+        _ => Link::System,
+    }
+}
+
+fn drm_devices(sysfs: &Path) -> Vec<String> {
+    let mut devices: Vec<String> = std::fs::read_dir(sysfs.join("drm"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| n.starts_with("card") || n.starts_with("renderD"))
+                .map(|n| format!("/dev/dri/{}", n))
+                .collect()
+        })
+        .unwrap_or_default();
+    devices.sort();
+    devices
+}
+
+async fn nvidia_matrix() -> Result<Vec<Vec<Link>>> {
+    let output = AsyncCommand::new("nvidia-smi")
+        .args(["topo", "-m"])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-smi topo failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_nvidia_matrix(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Parse the GPU-to-GPU part of `nvidia-smi topo -m`
+pub fn parse_nvidia_matrix(output: &str) -> Vec<Vec<Link>> {
+    matrix_cells(output)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|c| {
+                    c.as_deref()
+                        .and_then(Link::from_nvidia)
+                        .unwrap_or(Link::System)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The GPU-by-GPU cells of an `nvidia-smi topo` matrix, None where a row is
+/// missing
+pub(crate) fn matrix_cells(output: &str) -> Vec<Vec<Option<String>>> {
+    let lines: Vec<String> = output.lines().map(strip_escapes).collect();
+    let gpus = lines
+        .iter()
+        .find(|l| l.split_whitespace().next() == Some("GPU0"))
+        .map(|header| {
+            header
+                .split_whitespace()
+                .take_while(|t| t.starts_with("GPU"))
+                .count()
+        })
+        .unwrap_or(0);
+    (0..gpus)
+        .map(|row| {
+            let name = format!("GPU{}", row);
+            lines
+                .iter()
+                .map(|l| l.split_whitespace().collect::<Vec<_>>())
+                .find(|cells| {
+                    // The header starts with GPU0 too
+                    cells.first() == Some(&name.as_str())
+                        && cells.len() > gpus
+                        && !cells[1].starts_with("GPU")
+                })
+                .map(|cells| {
+                    cells[1..=gpus]
+                        .iter()
+                        .map(|c| Some(c.to_string()))
+                        .collect()
+                })
+                .unwrap_or_else(|| vec![None; gpus])
+        })
+        .collect()
+}
+
+/// nvidia-smi underlines the header with terminal escapes
+fn strip_escapes(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_topology_and_places_on_the_closest_gpus() {
+        let output = "\u{1b}[4mGPU0\tGPU1\tGPU2\tGPU3\tCPU Affinity\tNUMA Affinity\u{1b}[0m\n\
+GPU0\t X \tNV12\tPXB\tSYS\t0-31\t0\n\
+GPU1\tNV12\t X \tPXB\tSYS\t0-31\t0\n\
+GPU2\tPXB\tPXB\t X \tSYS\t0-31\t0\n\
+GPU3\tSYS\tSYS\tSYS\t X \t32-63\t1\n\
+\n\
+Legend:\n\
+  X    = Self\n";
+        let links = parse_nvidia_matrix(output);
+        assert_eq!(links.len(), 4);
+        assert_eq!(links[0][1], Link::NvLink);
+        assert_eq!(links[2][0], Link::PcieSwitch);
+        assert_eq!(links[3][3], Link::Same);
+
+        let gpu = |id: &str, numa: u32, index: u32| TopologyGpu {
+            pci_bus_id: id.to_string(),
+            vendor: GpuVendor::Nvidia,
+            numa_node: Some(numa),
+            nvidia_index: Some(index),
+            devices: vec![format!("/dev/nvidia{}", index)],
+        };
+        let topology = GpuTopology {
+            gpus: vec![
+                gpu("0000:01:00.0", 0, 0),
+                gpu("0000:02:00.0", 0, 1),
+                gpu("0000:03:00.0", 0, 2),
+                gpu("0000:81:00.0", 1, 3),
+            ],
+            links,
+        };
+        let all = [0, 1, 2, 3];
+        assert_eq!(
+            topology.select(&all, 2, TopologyHint::NvlinkPair),
+            Some(vec![0, 1])
+        );
+        assert_eq!(
+            topology.select(&[1, 2, 3], 2, TopologyHint::NvlinkPair),
+            None
+        );
+        assert_eq!(
+            topology.select(&[1, 2, 3], 2, TopologyHint::SamePcieSwitch),
+            Some(vec![1, 2])
+        );
+        assert_eq!(topology.select(&[0, 3], 2, TopologyHint::SameNuma), None);
+        assert_eq!(
+            topology.select(&[0, 3], 2, TopologyHint::Any),
+            Some(vec![0, 3])
+        );
+
+        assert_eq!(
+            requested_count(None, Some(TopologyHint::NvlinkPair)).unwrap(),
+            Some(2)
+        );
+        assert!(requested_count(Some(3), Some(TopologyHint::NvlinkPair)).is_err());
+        assert_eq!(requested_count(None, None).unwrap(), None);
+
+        let placement = Placement {
+            gpus: vec![topology.gpus[0].clone(), topology.gpus[1].clone()],
+        };
+        let args = placement.run_args();
+        assert_eq!(args[1], "bolt.gpus=0000:01:00.0,0000:02:00.0");
+        assert!(args.contains(&"/dev/nvidia1".to_string()));
+        assert!(args.contains(&"NVIDIA_VISIBLE_DEVICES=0,1".to_string()));
+
+        assert!(is_gpu_device("/dev/nvidia3"));
+        assert!(is_gpu_device("/dev/dri"));
+        assert!(!is_gpu_device("/dev/nvidiactl"));
+        assert!(!is_gpu_device("/dev/nvidia-uvm"));
+    }
+}
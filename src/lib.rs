@@ -25,12 +25,14 @@ pub mod plugins;
 pub mod profiles;
 pub mod registry;
 pub mod runtime;
+pub mod secrets;
 pub mod surge;
 pub mod types;
 pub mod volume;
 
+pub use bolt_client as client;
 pub use config::*;
-pub use error::{BoltError, Result};
+pub use error::{BoltError, ErrorCategory, ErrorCode, ErrorPayload, Result};
 
 // Export main types at root level
 pub use types::{ContainerInfo, NetworkInfo, ServiceInfo, SurgeStatus};
@@ -96,6 +98,7 @@ impl BoltFileBuilder {
             networks: None,
             volumes: None,
             snapshots: None,
+            maintenance: None,
         }
     }
 }
@@ -103,20 +106,44 @@ impl BoltFileBuilder {
 /// Core Bolt API for container management
 #[derive(Clone)]
 pub struct BoltRuntime {
-    config: BoltConfig,
+    /// Swapped as a whole by `reload_config`; shared by every clone
+    config: std::sync::Arc<std::sync::RwLock<std::sync::Arc<BoltConfig>>>,
+    /// Shared by every clone of the runtime, created on first use
+    volumes: std::sync::Arc<tokio::sync::OnceCell<volume::VolumeManager>>,
+    networks: std::sync::Arc<tokio::sync::OnceCell<networking::NetworkManager>>,
 }
 
 impl BoltRuntime {
     /// Create a new Bolt runtime instance
     pub fn new() -> Result<Self> {
-        Ok(Self {
-            config: BoltConfig::load()?,
-        })
+        Ok(Self::with_config(BoltConfig::load()?))
     }
 
     /// Create a new Bolt runtime instance with custom config
     pub fn with_config(config: BoltConfig) -> Self {
-        Self { config }
+        Self {
+            config: std::sync::Arc::new(std::sync::RwLock::new(std::sync::Arc::new(config))),
+            volumes: Default::default(),
+            networks: Default::default(),
+        }
+    }
+
+    /// The volume manager, shared with concurrent callers of this runtime
+    pub async fn volumes(&self) -> Result<&volume::VolumeManager> {
+        Ok(self
+            .volumes
+            .get_or_try_init(|| async { volume::VolumeManager::new() })
+            .await?)
+    }
+
+    /// The network manager, shared with concurrent callers of this runtime
+    pub async fn networks(&self) -> Result<&networking::NetworkManager> {
+        Ok(self
+            .networks
+            .get_or_try_init(|| {
+                networking::NetworkManager::new(networking::NetworkConfig::default())
+            })
+            .await?)
     }
 
     /// Run a container
@@ -129,42 +156,934 @@ impl BoltRuntime {
         volumes: &[String],
         detach: bool,
     ) -> Result<()> {
-        runtime::run_container(image, name, ports, env, volumes, detach).await
+        let timer = runtime::startup::StartupTimer::new(image);
+        self.run_container_timed(
+            image,
+            name,
+            ports,
+            env,
+            volumes,
+            detach,
+            &Default::default(),
+            timer,
+        )
+        .await
+    }
+
+    /// Run a container, continuing `timer` (which may already hold the image
+    /// resolve phase) and recording the startup timings once it has started
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_container_timed(
+        &self,
+        image: &str,
+        name: Option<&str>,
+        ports: &[String],
+        env: &[String],
+        volumes: &[String],
+        detach: bool,
+        options: &runtime::run_options::RunOptions,
+        mut timer: runtime::startup::StartupTimer,
+    ) -> Result<()> {
+        if image.starts_with("bolt://") {
+            if !options.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Restart, healthcheck, label, user, network, capability, workdir, hostname and GPU options only apply to OCI images"
+                )
+                .into());
+            }
+            if let Some(ref workspace) = self.config().workspace {
+                return Err(anyhow::anyhow!(
+                    "Capsules can't run in workspace {}: only OCI images are admitted",
+                    workspace
+                )
+                .into());
+            }
+            return runtime::run_container(image, name, ports, env, volumes, detach).await;
+        }
+
+        // Every way of starting a container ends here, so API and
+        // docker-compat runs can't skip the policy that `bolt run` checks
+        runtime::trust::enforce(&self.config(), image, runtime::trust::Stage::Run).await?;
+
+        timer.phase(runtime::startup::Phase::Spec);
+        let (name, volumes, mut args) = match self.config().workspace {
+            Some(ref workspace) => {
+                let name = name.map(|n| runtime::workspace::scoped(workspace, n));
+                let volumes: Vec<String> = volumes
+                    .iter()
+                    .map(|v| runtime::workspace::scoped_volume(workspace, v))
+                    .collect();
+                let args = runtime::workspace::admit(
+                    &runtime::detect_container_runtime().await?,
+                    &self.config().data_dir,
+                    workspace,
+                    name.as_deref(),
+                    &Default::default(),
+                )
+                .await?;
+                (name, volumes, args)
+            }
+            None => (name.map(String::from), volumes.to_vec(), Vec::new()),
+        };
+        args.extend(
+            runtime::emulation::run_args(
+                &runtime::detect_container_runtime().await?,
+                image,
+                self.config().platform.setup_emulation,
+            )
+            .await?,
+        );
+        args.extend(options.run_args(
+            &runtime::detect_container_runtime().await?,
+            &self.config().data_dir,
+        )?);
+
+        let container = runtime::run_oci_container_timed(
+            image,
+            name.as_deref(),
+            ports,
+            env,
+            &volumes,
+            detach,
+            &args,
+            &mut timer,
+        )
+        .await?;
+
+        if let Some(container) = container {
+            options.apply(&self.config().data_dir, &container, detach)?;
+            runtime::startup::complete(&self.config().data_dir, timer, &container);
+        }
+        Ok(())
+    }
+
+    /// The latest startup timings of a container
+    pub fn startup_timings(&self, container: &str) -> Result<runtime::startup::StartupTimings> {
+        let container = self.scoped_name(container);
+        runtime::startup::load(&self.config().data_dir, &container)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No startup timings recorded for {} (they are recorded by `bolt run`)",
+                container
+            )
+            .into()
+        })
+    }
+
+    /// p50/p95 of every startup phase across recent container starts
+    pub fn startup_stats(&self) -> Vec<runtime::startup::PhaseStats> {
+        runtime::startup::aggregate(&runtime::startup::history(&self.config().data_dir))
+    }
+
+    /// The container runtime's inspect output for a container
+    pub async fn inspect_container(&self, container: &str) -> Result<serde_json::Value> {
+        runtime::inspect_container(&self.scoped_name(container)).await
+    }
+
+    /// The OCI annotations of a container's runtime spec
+    pub async fn container_annotations(
+        &self,
+        container: &str,
+    ) -> Result<std::collections::BTreeMap<String, String>> {
+        let inspect = self.inspect_container(container).await?;
+        Ok(runtime::annotations::of_container(&inspect))
+    }
+
+    /// An image's manifest annotations, from Bolt's store when it was pulled
+    /// there and from podman otherwise
+    pub async fn image_annotations(
+        &self,
+        image: &str,
+    ) -> Result<std::collections::BTreeMap<String, String>> {
+        let storage =
+            runtime::storage::StorageManager::new(self.config().data_dir.join("storage"))?;
+        let tagged = format!("{}:latest", image);
+        if let Some(stored) = storage
+            .list_images()
+            .into_iter()
+            .find(|i| i.id == image || i.id == tagged)
+        {
+            return Ok(stored.annotations.clone().into_iter().collect());
+        }
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::annotations::of_image(&runtime, image).await?)
+    }
+
+    /// A container's CPU, memory, network and block I/O, sampled from its
+    /// cgroup every `interval` until it is removed
+    pub fn container_stats(
+        &self,
+        container: &str,
+        interval: std::time::Duration,
+    ) -> impl futures::Stream<Item = Result<monitoring::ContainerMetrics>> + use<> {
+        use futures::StreamExt;
+
+        monitoring::stats::stream(self.scoped_name(container), interval)
+            .map(|metrics| metrics.map_err(Into::into))
+    }
+
+    /// A container, network or volume name inside the user's workspace
+    pub fn scoped_name(&self, name: &str) -> String {
+        match self.config().workspace {
+            Some(ref workspace) => runtime::workspace::scoped(workspace, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Whether a name belongs to the user's workspace; always true outside one
+    pub fn in_workspace(&self, name: &str) -> bool {
+        self.config()
+            .workspace
+            .as_deref()
+            .is_none_or(|workspace| runtime::workspace::is_scoped(workspace, name))
+    }
+
+    /// Build an image, reusing the cached image when the build context is unchanged
+    pub async fn build_image(
+        &self,
+        path: &str,
+        tag: Option<&str>,
+        dockerfile: &str,
+    ) -> Result<builds::cache::BuildStats> {
+        self.build_image_on(
+            builds::builders::DEFAULT_BUILDER,
+            path,
+            tag,
+            dockerfile,
+            &[],
+            &[],
+            &[],
+            false,
+        )
+        .await
+    }
+
+    /// Build an image on a named builder, within its limits and cache, with
+    /// the shared package manager caches in `cache_volumes` mounted,
+    /// `annotations` (`key=value`) on its manifest and `build_args`
+    /// (`KEY=VALUE`) passed to the Dockerfile. `no_cache` builds every step
+    /// again.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_image_on(
+        &self,
+        builder: &str,
+        path: &str,
+        tag: Option<&str>,
+        dockerfile: &str,
+        cache_volumes: &[String],
+        annotations: &[String],
+        build_args: &[String],
+        no_cache: bool,
+    ) -> Result<builds::cache::BuildStats> {
+        let annotations = runtime::annotations::parse(annotations)?;
+        let build_args = builds::reproducible::parse_build_args(build_args)?;
+        let config = self.config();
+        let builder = builds::builders::BuilderStore::load(&config.data_dir)?.get(builder)?;
+        runtime::trust::enforce_build(&config, std::path::Path::new(path), dockerfile).await?;
+        let mut cache = self.build_cache(&builder)?;
+        let caches = builds::artifacts::resolve(&config.data_dir, cache_volumes)?;
+        let _slot = builds::builders::acquire_slot(&config.data_dir, &builder).await?;
+        let lease = builds::artifacts::lease(&config.data_dir, &caches)?;
+        let stats = runtime::build_image_cached(
+            path,
+            tag,
+            dockerfile,
+            &builder,
+            &mut cache,
+            &caches,
+            &annotations,
+            &builds::reproducible::build_arg_flags(&build_args),
+            no_cache,
+        )
+        .await?;
+        if !caches.is_empty() {
+            drop(lease);
+            builds::artifacts::enforce(&config, &runtime::detect_container_runtime().await?).await;
+        }
+        Ok(stats)
+    }
+
+    /// Build an image reproducibly and record its provenance: pinned
+    /// SOURCE_DATE_EPOCH and base images, and layers and config rewritten
+    /// deterministically under `tag`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_image_reproducible(
+        &self,
+        builder: &str,
+        path: &str,
+        tag: &str,
+        dockerfile: &str,
+        annotations: &[String],
+        build_args: &[String],
+        source_date_epoch: Option<i64>,
+    ) -> Result<builds::reproducible::Provenance> {
+        use builds::reproducible;
+
+        let annotations = runtime::annotations::parse(annotations)?;
+        let build_args = reproducible::parse_build_args(build_args)?;
+        let config = self.config();
+        let builder = builds::builders::BuilderStore::load(&config.data_dir)?.get(builder)?;
+        runtime::trust::enforce_build(&config, std::path::Path::new(path), dockerfile).await?;
+        let runtime_bin = runtime::detect_container_runtime().await?;
+        let (context, dockerfile_path) = reproducible::locate(path, dockerfile)?;
+        let inputs = reproducible::resolve_inputs(
+            &runtime_bin,
+            &context,
+            &dockerfile_path,
+            build_args,
+            source_date_epoch,
+        )
+        .await?;
+        tracing::info!("📌 SOURCE_DATE_EPOCH={}", inputs.source_date_epoch);
+
+        let mut cache = self.build_cache(&builder)?;
+        let _slot = builds::builders::acquire_slot(&config.data_dir, &builder).await?;
+        let stats = runtime::build_image_cached(
+            path,
+            None,
+            dockerfile,
+            &builder,
+            &mut cache,
+            &[],
+            &annotations,
+            &inputs.build_flags(&runtime_bin),
+            false,
+        )
+        .await?;
+        let normalized = reproducible::normalize(
+            &runtime_bin,
+            &stats.image_id,
+            tag,
+            inputs.source_date_epoch,
+            &config.data_dir.join("builds").join("tmp"),
+        )
+        .await?;
+
+        let provenance = reproducible::Provenance {
+            image: tag.to_string(),
+            digest: normalized.digest,
+            layers: normalized.layers,
+            context_digest: builds::cache::context_key(&context, &dockerfile_path)?,
+            context,
+            dockerfile: dockerfile_path,
+            inputs,
+            annotations,
+            builder: builder.name.clone(),
+            runtime: reproducible::runtime_version(&runtime_bin).await,
+            built_at: chrono::Utc::now(),
+        };
+        provenance.save(&config.data_dir)?;
+        Ok(provenance)
+    }
+
+    /// Rebuild the recorded inputs of a reproducible build without any
+    /// cache and compare the result layer by layer. The rebuilt image is
+    /// removed unless `keep` is set.
+    pub async fn verify_reproducible(
+        &self,
+        image: &str,
+        keep: bool,
+    ) -> Result<builds::reproducible::Verification> {
+        use builds::reproducible;
+
+        let config = self.config();
+        let provenance = reproducible::Provenance::find(&config.data_dir, image)?;
+        let runtime_bin = runtime::detect_container_runtime().await?;
+        let builder =
+            builds::builders::BuilderStore::load(&config.data_dir)?.get(&provenance.builder)?;
+        let changed_inputs = reproducible::changed_inputs(&runtime_bin, &provenance).await;
+        for input in &changed_inputs {
+            tracing::warn!("Changed since the build: {}", input);
+        }
+
+        let context = provenance.context.to_string_lossy();
+        let dockerfile = provenance.dockerfile.to_string_lossy();
+        let rebuilt = {
+            let _slot = builds::builders::acquire_slot(&config.data_dir, &builder).await?;
+            tracing::info!("🔁 Rebuilding {} without cache", provenance.image);
+            runtime::build_image_uncached(
+                &context,
+                &dockerfile,
+                &builder,
+                &provenance.annotations,
+                &provenance.inputs.build_flags(&runtime_bin),
+            )
+            .await?
+        };
+        let tag = format!(
+            "localhost/bolt-verify:{}",
+            &provenance.digest.trim_start_matches("sha256:")[..12]
+        );
+        let normalized = reproducible::normalize(
+            &runtime_bin,
+            &rebuilt,
+            &tag,
+            provenance.inputs.source_date_epoch,
+            &config.data_dir.join("builds").join("tmp"),
+        )
+        .await?;
+
+        if !keep {
+            for image in [tag.as_str(), rebuilt.as_str()] {
+                let _ = tokio::process::Command::new(&runtime_bin)
+                    .args(["rmi", image])
+                    .output()
+                    .await;
+            }
+        }
+        Ok(reproducible::Verification {
+            image: provenance.image.clone(),
+            layers: reproducible::compare(&provenance.layers, &normalized.layers),
+            expected: provenance.digest,
+            actual: normalized.digest,
+            changed_inputs,
+            rebuilt: keep.then_some(tag),
+        })
+    }
+
+    /// Prune a builder's layer cache down to `keep_storage` bytes, first
+    /// dropping layers unused for longer than `until`. Built images are kept.
+    /// Returns the bytes reclaimed.
+    pub async fn prune_build_cache(
+        &self,
+        builder: &str,
+        keep_storage: u64,
+        until: Option<std::time::Duration>,
+    ) -> Result<u64> {
+        let builder =
+            builds::builders::BuilderStore::load(&self.config().data_dir)?.get(builder)?;
+        runtime::prune_build_cache(&builder, keep_storage, until).await
+    }
+
+    /// A builder's build cache index
+    fn build_cache(
+        &self,
+        builder: &builds::builders::Builder,
+    ) -> Result<builds::cache::BuildCache> {
+        let max_size = match builder.storage {
+            Some(storage) => storage,
+            None => builds::cache::parse_size(&self.config().build_cache.max_size)?,
+        };
+        Ok(builds::cache::BuildCache::open(
+            builder.cache_dir(&self.config().data_dir),
+            max_size,
+        )?)
+    }
+
+    /// Create a named builder with its own cache and limits
+    pub async fn create_builder(
+        &self,
+        name: &str,
+        limits: builds::builders::BuilderLimits,
+    ) -> Result<builds::builders::Builder> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(builds::builders::create(&runtime, &self.config().data_dir, name, limits).await?)
+    }
+
+    /// Builders with the size of their layer caches
+    pub async fn list_builders(&self) -> Result<Vec<(builds::builders::Builder, u64)>> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let store = builds::builders::BuilderStore::load(&self.config().data_dir)?;
+        let mut builders = Vec::new();
+        for builder in store.list() {
+            let size = builds::builders::layer_cache_size(&runtime, &builder)
+                .await
+                .unwrap_or(0);
+            builders.push((builder, size));
+        }
+        Ok(builders)
+    }
+
+    /// Remove a builder; with Docker its BuildKit instance and layer cache
+    /// go with it. Images it built are kept.
+    pub async fn remove_builder(&self, name: &str) -> Result<builds::builders::Builder> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(builds::builders::remove(&runtime, &self.config().data_dir, name).await?)
+    }
+
+    /// Import an image built from a Nix flake output (e.g. `./flake#image`)
+    /// and load it into the container runtime
+    pub async fn import_nix_image(
+        &self,
+        flake_ref: &str,
+        tag: Option<&str>,
+    ) -> Result<builds::BuildResult> {
+        let mut build_system = builds::BuildSystem::new(self.config().data_dir.join("builds"))?;
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(build_system
+            .import_nix_image(flake_ref, &runtime, tag)
+            .await?)
+    }
+
+    /// Diff two Nix-built images at the file level
+    pub async fn diff_nix_images(
+        &self,
+        old_ref: &str,
+        new_ref: &str,
+    ) -> Result<builds::nix::ImageDiff> {
+        let build_system = builds::BuildSystem::new(self.config().data_dir.join("builds"))?;
+        Ok(build_system.diff_nix_images(old_ref, new_ref).await?)
+    }
+
+    /// Squash, strip and zstd-recompress an image under a new tag
+    pub async fn optimize_image(
+        &self,
+        image: &str,
+        options: &builds::optimize::OptimizeOptions,
+    ) -> Result<builds::optimize::OptimizeReport> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(builds::optimize::optimize(
+            &runtime,
+            image,
+            options,
+            &self.config().data_dir.join("builds").join("tmp"),
+        )
+        .await?)
     }
 
-    /// Build an image
-    pub async fn build_image(&self, path: &str, tag: Option<&str>, dockerfile: &str) -> Result<()> {
-        runtime::build_image(path, tag, dockerfile).await
+    /// Pull an image allowed by the trust policy, decrypting its layers with
+    /// the configured keys
+    pub async fn pull_image(&self, image: &str, platform: Option<&str>) -> Result<()> {
+        runtime::trust::enforce(&self.config(), image, runtime::trust::Stage::Pull).await?;
+        let choice = self.platform_choice(platform);
+        let config = self.config();
+        let keys = runtime::encryption::DecryptionKeys::load(&config)?;
+        if keys.is_empty() && !config.p2p.enabled {
+            return runtime::pull_image(image, &choice).await;
+        }
+        let runtime = runtime::detect_container_runtime().await?;
+        let selected = runtime::platform::resolve(&runtime, image, &choice).await?;
+        runtime::pull::pull(&config, &runtime, image, selected.as_ref(), &keys).await?;
+        Ok(runtime::platform::verify_local(&runtime, image, selected.as_ref(), &choice).await?)
+    }
+
+    /// Pull an image with Bolt's own registry client, without podman or
+    /// docker, and unpack its rootfs under `<data_dir>/storage`
+    pub async fn pull_rootfs(
+        &self,
+        image: &str,
+        platform: Option<&str>,
+    ) -> Result<std::path::PathBuf> {
+        runtime::trust::enforce(&self.config(), image, runtime::trust::Stage::Pull).await?;
+        let platform = platform
+            .map(runtime::platform::Platform::parse)
+            .transpose()?
+            .unwrap_or_else(runtime::platform::Platform::host);
+        let mut storage =
+            runtime::storage::StorageManager::new(self.config().data_dir.join("storage"))?;
+        let image_id = storage.pull_registry_image(image, &platform).await?;
+        Ok(storage.export_rootfs(&image_id)?)
+    }
+
+    /// Share the layers in Bolt's store with Bolt nodes on the LAN until
+    /// interrupted
+    #[cfg(feature = "quic-networking")]
+    pub async fn serve_layers(&self) -> Result<()> {
+        Ok(runtime::storage::p2p::serve(&self.config()).await?)
     }
 
-    /// Pull an image
-    pub async fn pull_image(&self, image: &str) -> Result<()> {
-        runtime::pull_image(image).await
+    /// Bolt nodes on the LAN sharing layers
+    #[cfg(feature = "quic-networking")]
+    pub async fn layer_peers(&self) -> Result<Vec<runtime::storage::p2p::Peer>> {
+        Ok(runtime::storage::p2p::peers().await?)
+    }
+
+    /// `platform` (the host's when `None`) under `[platform]` settings
+    pub fn platform_choice(&self, platform: Option<&str>) -> runtime::platform::PlatformChoice {
+        runtime::platform::PlatformChoice::new(platform, self.config().platform.auto_select)
+    }
+
+    /// Make an image allowed by the trust policy available according to a
+    /// pull policy, verifying pinned digests
+    pub async fn ensure_image(
+        &self,
+        image: &str,
+        policy: Option<runtime::pull::PullPolicy>,
+        platform: Option<&str>,
+    ) -> Result<runtime::pull::ImageRecord> {
+        runtime::trust::enforce(&self.config(), image, runtime::trust::Stage::Pull).await?;
+        let runtime = runtime::detect_container_runtime().await?;
+        let keys = runtime::encryption::DecryptionKeys::load(&self.config())?;
+        let choice = self.platform_choice(platform);
+        Ok(
+            runtime::pull::ensure_image(&self.config(), &runtime, image, policy, &choice, &keys)
+                .await?,
+        )
     }
 
-    /// Push an image
+    /// Push an image, encrypted for the `[encryption]` recipients if any
     pub async fn push_image(&self, image: &str) -> Result<()> {
-        runtime::push_image(image).await
+        self.push_image_tagged(image, &[], &[]).await
+    }
+
+    /// Push an image under its own tag and `extra_tags`, adding
+    /// `annotations` (`key=value`) to its manifest
+    pub async fn push_image_tagged(
+        &self,
+        image: &str,
+        extra_tags: &[String],
+        annotations: &[String],
+    ) -> Result<()> {
+        let annotations = runtime::annotations::parse(annotations)?;
+        let recipients = self.config().encryption.recipients.clone();
+        if recipients.is_empty() {
+            return runtime::push_image_tagged(
+                image,
+                extra_tags,
+                &annotations,
+                &self.config().data_dir.join("tmp"),
+            )
+            .await;
+        }
+        if !extra_tags.is_empty() || !annotations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Extra tags and annotations aren't supported for encrypted pushes; tag the image locally and push each tag"
+            )
+            .into());
+        }
+        self.push_image_encrypted(image, &recipients, &[]).await
+    }
+
+    /// Push an image with its layers encrypted for `recipients`; `layers`
+    /// picks which (negative counts from the top), all when empty
+    pub async fn push_image_encrypted(
+        &self,
+        image: &str,
+        recipients: &[String],
+        layers: &[i32],
+    ) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::encryption::push(&runtime, image, recipients, layers).await?)
     }
 
     /// List containers
     pub async fn list_containers(&self, all: bool) -> Result<Vec<ContainerInfo>> {
-        runtime::list_containers_info(all).await
+        let mut containers = runtime::list_containers_info(all).await?;
+        containers.retain(|c| self.in_workspace(&c.name));
+        Ok(containers)
+    }
+
+    /// Names of the containers a bulk selector matches, as the user sees
+    /// them inside their workspace; `stopped` also looks at stopped ones
+    pub async fn select_containers(
+        &self,
+        selector: &runtime::bulk::Selector,
+        stopped: bool,
+    ) -> Result<Vec<String>> {
+        if selector.is_literal() {
+            return Ok(selector.names.clone());
+        }
+        let workspace = self.config().workspace.clone();
+        let containers: Vec<_> = self
+            .list_containers(stopped)
+            .await?
+            .into_iter()
+            .map(|c| {
+                let name = match workspace {
+                    Some(ref workspace) => c
+                        .name
+                        .strip_prefix(&format!("{}.", workspace))
+                        .unwrap_or(&c.name)
+                        .to_string(),
+                    None => c.name.clone(),
+                };
+                (name, c)
+            })
+            .collect();
+        Ok(selector.resolve(&containers))
+    }
+
+    /// List collected crash dumps, newest first
+    pub fn crash_reports(&self, container: Option<&str>) -> Result<Vec<runtime::crash::CrashReport>> {
+        let collector = runtime::crash::CrashCollector::new(
+            self.config().data_dir.clone(),
+            self.config().crash_dumps.clone(),
+        )?;
+        Ok(collector.list(container)?)
+    }
+
+    /// Get a crash dump by id, or the latest one for a container
+    pub fn crash_report(&self, container_or_id: &str) -> Result<Option<runtime::crash::CrashReport>> {
+        let collector = runtime::crash::CrashCollector::new(
+            self.config().data_dir.clone(),
+            self.config().crash_dumps.clone(),
+        )?;
+        Ok(collector.find(container_or_id)?)
+    }
+
+    /// Interactive shell in a running container, using a toolbox of static
+    /// binaries when the image has no shell
+    pub async fn debug_shell(
+        &self,
+        container: &str,
+        options: &runtime::debug_shell::DebugShellOptions,
+    ) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(
+            runtime::debug_shell::run(&runtime, container, &self.config().data_dir, options)
+                .await?,
+        )
+    }
+
+    /// Shell, or `command`, in the dev environment of the repository
+    /// containing `cwd`; returns the exit code
+    pub async fn dev_enter(
+        &self,
+        cwd: &std::path::Path,
+        command: &[String],
+        rebuild: bool,
+    ) -> Result<i32> {
+        let env = runtime::dev_env::DevEnvironment::find(cwd)?;
+        let runtime = runtime::detect_container_runtime().await?;
+        let code = runtime::dev_env::enter(
+            &runtime,
+            &self.config().data_dir,
+            &env,
+            cwd,
+            command,
+            rebuild,
+        )
+        .await?;
+        if !env.cache_volumes.is_empty() {
+            builds::artifacts::enforce(&self.config(), &runtime).await;
+        }
+        Ok(code)
+    }
+
+    pub async fn dev_environments(&self) -> Result<Vec<runtime::dev_env::DevStatus>> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::dev_env::list(&runtime, &self.config().data_dir).await)
+    }
+
+    /// Name of the dev environment: `name`, or the one of the repository
+    /// containing `cwd`
+    pub fn dev_environment_name(
+        &self,
+        name: Option<&str>,
+        cwd: &std::path::Path,
+    ) -> Result<String> {
+        match name {
+            Some(name) => Ok(name.to_string()),
+            None => Ok(runtime::dev_env::DevEnvironment::find(cwd)?.name),
+        }
+    }
+
+    pub async fn dev_stop(&self, name: &str) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::dev_env::stop(&runtime, &self.config().data_dir, name).await?)
+    }
+
+    pub async fn dev_remove(&self, name: &str, snapshot: bool) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::dev_env::remove(&runtime, &self.config().data_dir, name, snapshot).await?)
+    }
+
+    fn restic_repository(&self) -> Result<runtime::storage::restic::ResticRepository> {
+        let config = self.config();
+        let settings = config.backup.restic.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("No [backup.restic] repository configured in config.toml")
+        })?;
+        Ok(runtime::storage::restic::ResticRepository::from_settings(
+            settings,
+            &secrets::SecretStore::new(&config.config_dir),
+        )?)
+    }
+
+    /// Create the configured restic repository
+    pub async fn restic_init(&self) -> Result<()> {
+        Ok(self.restic_repository()?.init().await?)
+    }
+
+    /// Back up volumes and capsule snapshots to the restic repository
+    pub async fn restic_backup(
+        &self,
+        volumes: &[String],
+        snapshots: &[String],
+    ) -> Result<Vec<runtime::storage::restic::ResticBackupSummary>> {
+        let repo = self.restic_repository()?;
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::storage::restic::backup(
+            &repo,
+            &runtime,
+            &self.capsules_root(),
+            &self.config().data_dir.join("backup").join("staging"),
+            volumes,
+            snapshots,
+        )
+        .await?)
+    }
+
+    /// Bolt's backups in the restic repository
+    pub async fn restic_snapshots(&self) -> Result<Vec<runtime::storage::restic::ResticSnapshot>> {
+        Ok(self.restic_repository()?.snapshots().await?)
+    }
+
+    /// Restore a backup from the restic repository
+    pub async fn restic_restore(
+        &self,
+        snapshot_id: &str,
+        target: Option<&std::path::Path>,
+    ) -> Result<runtime::storage::restic::ResticRestoreSummary> {
+        let repo = self.restic_repository()?;
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::storage::restic::restore(
+            &repo,
+            &runtime,
+            &self.capsules_root(),
+            &self.config().data_dir.join("backup").join("staging"),
+            snapshot_id,
+            target,
+        )
+        .await?)
     }
 
     /// Stop a container
     pub async fn stop_container(&self, container: &str) -> Result<()> {
-        runtime::stop_container(container).await
+        let container = self.scoped_name(container);
+        runtime::supervisor::stopping(&self.config().data_dir, &container);
+        let result = runtime::stop_container(&container).await;
+        runtime::workspace::stopped(&self.config().data_dir, &container);
+        optimizations::hybrid::forget(&self.config().data_dir, &container);
+        result
     }
 
     /// Remove a container
     pub async fn remove_container(&self, container: &str, force: bool) -> Result<()> {
-        runtime::remove_container(container, force).await
+        let container = self.scoped_name(container);
+        let result = runtime::remove_container(&container, force).await;
+        runtime::workspace::stopped(&self.config().data_dir, &container);
+        optimizations::hybrid::forget(&self.config().data_dir, &container);
+        result
     }
 
     /// Restart a container
     pub async fn restart_container(&self, container: &str, timeout: u64) -> Result<()> {
-        runtime::restart_container(container, timeout).await
+        let container = self.scoped_name(container);
+        runtime::restart_container(&container, timeout).await?;
+        // A new network namespace comes without the egress rules
+        let runtime = runtime::detect_container_runtime().await?;
+        networking::egress::restore(&runtime, &self.config().data_dir, &container).await;
+        Ok(())
+    }
+
+    /// Change a running container's resource limits in place
+    pub async fn update_container(
+        &self,
+        container: &str,
+        limits: &runtime::oci::ResourceLimits,
+    ) -> Result<()> {
+        runtime::update_container(
+            &self.scoped_name(container),
+            limits,
+            &self.config().data_dir,
+        )
+        .await
+    }
+
+    /// Run a command in a running container, returning its exit code
+    pub async fn exec_container(
+        &self,
+        container: &str,
+        command: &[String],
+        interactive: bool,
+        tty: bool,
+    ) -> Result<i32> {
+        let options = runtime::ExecOptions {
+            interactive,
+            tty,
+            ..Default::default()
+        };
+        self.exec_container_with(container, command, &options).await
+    }
+
+    /// Run a command in a running container with environment, working
+    /// directory or user overrides
+    pub async fn exec_container_with(
+        &self,
+        container: &str,
+        command: &[String],
+        options: &runtime::ExecOptions,
+    ) -> Result<i32> {
+        runtime::exec_container(&self.scoped_name(container), command, options).await
+    }
+
+    /// Run a command in a running container without stdin, returning its
+    /// exit code and output instead of printing it
+    pub async fn exec_container_output(
+        &self,
+        container: &str,
+        command: &[String],
+        options: &runtime::ExecOptions,
+    ) -> Result<runtime::ExecOutput> {
+        runtime::exec_container_output(&self.scoped_name(container), command, options).await
+    }
+
+    /// Copy a host file or directory into a container, or a tar stream from
+    /// stdin when `src` is `-`. Returns the bytes copied.
+    pub async fn copy_to_container(
+        &self,
+        src: &std::path::Path,
+        container: &str,
+        dest: &str,
+        options: runtime::copy::CopyOptions,
+    ) -> Result<u64> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::copy::copy_to_container(
+            &runtime,
+            src,
+            &self.scoped_name(container),
+            dest,
+            options,
+        )
+        .await?)
+    }
+
+    /// Copy a file or directory out of a container, or to stdout as a tar
+    /// stream when `dest` is `-`. Returns the bytes copied.
+    pub async fn copy_from_container(
+        &self,
+        container: &str,
+        src: &str,
+        dest: &std::path::Path,
+        options: runtime::copy::CopyOptions,
+    ) -> Result<u64> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::copy::copy_from_container(
+            &runtime,
+            &self.scoped_name(container),
+            src,
+            dest,
+            options,
+        )
+        .await?)
+    }
+
+    /// Checkpoint a running container into `dir` with CRIU
+    pub async fn checkpoint_container(
+        &self,
+        container: &str,
+        dir: &std::path::Path,
+        options: runtime::checkpoint::CheckpointOptions,
+    ) -> Result<runtime::checkpoint::CheckpointManifest> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(
+            runtime::checkpoint::checkpoint(&runtime, &self.scoped_name(container), dir, options)
+                .await?,
+        )
+    }
+
+    /// Restore a checkpoint, as `name` or the container it was taken from;
+    /// returns the container's name
+    pub async fn restore_container(
+        &self,
+        dir: &std::path::Path,
+        name: Option<&str>,
+    ) -> Result<String> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let name = name.map(|n| self.scoped_name(n));
+        Ok(
+            runtime::checkpoint::restore(&runtime, &self.config().data_dir, dir, name.as_deref())
+                .await?,
+        )
     }
 
     /// Start Surge orchestration
@@ -174,27 +1093,82 @@ impl BoltRuntime {
         detach: bool,
         force_recreate: bool,
     ) -> Result<()> {
-        surge::up(&self.config, services, detach, force_recreate).await
+        surge::up(&self.config(), services, detach, force_recreate, None).await
+    }
+
+    /// Start Surge services, overriding each service's `pull_policy`
+    pub async fn surge_up_with_pull(
+        &self,
+        services: &[String],
+        detach: bool,
+        force_recreate: bool,
+        pull: Option<runtime::pull::PullPolicy>,
+    ) -> Result<()> {
+        surge::up(&self.config(), services, detach, force_recreate, pull).await
+    }
+
+    /// Start Surge services and report what happened to each, continuing
+    /// past failures with `on_failure = continue`
+    pub async fn surge_up_with(
+        &self,
+        services: &[String],
+        options: &surge::UpOptions,
+    ) -> Result<surge::report::UpSummary> {
+        surge::up_with(&self.config(), services, options).await
+    }
+
+    /// Show what `surge up` would change
+    pub async fn surge_plan(&self, services: &[String]) -> Result<Vec<surge::plan::ServicePlan>> {
+        surge::plan::plan(&self.config(), services).await
+    }
+
+    /// Whether the services' reservations fit on this host together
+    pub async fn surge_resources(
+        &self,
+        services: &[String],
+    ) -> Result<surge::resources::Feasibility> {
+        surge::resources::check(&self.config(), services).await
+    }
+
+    /// Start Surge services and keep them in sync with their files until
+    /// interrupted
+    pub async fn surge_dev(
+        &self,
+        services: &[String],
+        interval: std::time::Duration,
+        start: bool,
+    ) -> Result<()> {
+        surge::dev::dev(&self.config(), services, interval, start).await
     }
 
     /// Stop Surge services
     pub async fn surge_down(&self, services: &[String], volumes: bool) -> Result<()> {
-        surge::down(&self.config, services, volumes).await
+        surge::down(&self.config(), services, volumes).await
+    }
+
+    /// Stop Surge services dependents first, optionally removing orphan
+    /// containers, and report what was removed
+    pub async fn surge_down_with(
+        &self,
+        services: &[String],
+        options: &surge::teardown::DownOptions,
+    ) -> Result<surge::teardown::DownSummary> {
+        surge::down_with(&self.config(), services, options).await
     }
 
     /// Get Surge status
     pub async fn surge_status(&self) -> Result<SurgeStatus> {
-        surge::status_api::status_info(&self.config).await
+        surge::status_api::status_info(&self.config()).await
     }
 
     /// Scale Surge services
     pub async fn surge_scale(&self, services: &[String]) -> Result<()> {
-        surge::scale(&self.config, services).await
+        surge::scale(&self.config(), services).await
     }
 
     /// Setup gaming environment
     pub async fn setup_gaming(&self, proton: Option<&str>, winver: Option<&str>) -> Result<()> {
-        gaming::setup_wine(proton, winver).await
+        gaming::setup_wine(&self.config().data_dir, proton, winver).await
     }
 
     /// Launch a game
@@ -209,30 +1183,684 @@ impl BoltRuntime {
         driver: &str,
         subnet: Option<&str>,
     ) -> Result<()> {
-        network::create_network(name, driver, subnet).await
+        network::create_network(&self.scoped_name(name), driver, subnet).await
     }
 
     /// List networks
     pub async fn list_networks(&self) -> Result<Vec<NetworkInfo>> {
-        network::list_networks_info().await
+        let mut networks = network::list_networks_info().await?;
+        networks.retain(|n| self.in_workspace(&n.name));
+        Ok(networks)
     }
 
     /// Remove a network
     pub async fn remove_network(&self, name: &str) -> Result<()> {
-        network::remove_network(name).await
+        network::remove_network(&self.scoped_name(name)).await
+    }
+
+    /// Create a volume
+    pub async fn create_volume(
+        &self,
+        name: &str,
+        options: volume::VolumeCreateOptions,
+    ) -> Result<volume::Volume> {
+        Ok(self
+            .volumes()
+            .await?
+            .create_volume(&self.scoped_name(name), options)?)
+    }
+
+    /// List volumes
+    pub async fn list_volumes(&self) -> Result<Vec<volume::VolumeInfo>> {
+        let mut volumes = self.volumes().await?.list_volumes();
+        volumes.retain(|v| self.in_workspace(&v.name));
+        Ok(volumes)
+    }
+
+    /// Inspect a volume
+    pub async fn inspect_volume(&self, name: &str) -> Result<volume::Volume> {
+        Ok(self
+            .volumes()
+            .await?
+            .inspect_volume(&self.scoped_name(name))?)
+    }
+
+    /// Remove a volume
+    pub async fn remove_volume(&self, name: &str, force: bool) -> Result<()> {
+        Ok(self
+            .volumes()
+            .await?
+            .remove_volume(&self.scoped_name(name), force)?)
+    }
+
+    /// Remove unused volumes, returning their names
+    pub async fn prune_volumes(&self, force: bool) -> Result<Vec<String>> {
+        Ok(self.volumes().await?.prune_volumes(force)?)
+    }
+
+    /// Create a workspace, or change an existing workspace's quota
+    pub fn create_workspace(
+        &self,
+        name: &str,
+        quota: runtime::workspace::Quota,
+    ) -> Result<runtime::workspace::Workspace> {
+        Ok(runtime::workspace::create(
+            &self.config().data_dir,
+            name,
+            quota,
+        )?)
+    }
+
+    /// Assign a user to a workspace, or remove them from theirs with `None`
+    pub fn assign_workspace(&self, user: &str, workspace: Option<&str>) -> Result<()> {
+        Ok(runtime::workspace::assign(
+            &self.config().data_dir,
+            user,
+            workspace,
+        )?)
+    }
+
+    /// Usage and quota of a workspace, by default the user's own
+    pub async fn workspace_usage(
+        &self,
+        workspace: Option<&str>,
+    ) -> Result<runtime::workspace::WorkspaceUsage> {
+        let config = self.config();
+        let workspace = match (workspace, config.workspace.as_deref()) {
+            (Some(requested), Some(own)) if requested != own => {
+                return Err(anyhow::anyhow!(
+                    "You can only see the usage of your own workspace ({})",
+                    own
+                )
+                .into());
+            }
+            (Some(workspace), None) => {
+                runtime::workspace::require_root("see the usage of other workspaces")?;
+                workspace
+            }
+            (Some(workspace), Some(_)) | (None, Some(workspace)) => workspace,
+            (None, None) => {
+                return Err(anyhow::anyhow!(
+                    "You are not in a workspace; name one to see its usage"
+                )
+                .into());
+            }
+        };
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::workspace::usage(&runtime, &config.data_dir, workspace).await?)
+    }
+
+    /// A container's egress allowlist and the destinations it was denied
+    pub async fn egress_report(&self, container: &str) -> Result<networking::egress::EgressReport> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(networking::egress::report(&runtime, &self.config().data_dir, container).await?)
+    }
+
+    /// Cache tuners of containers in the workspace
+    pub fn volume_cache_states(&self) -> Vec<volume::cache::CacheState> {
+        let mut states = volume::cache::list(&self.config().data_dir);
+        states.retain(|s| self.in_workspace(&s.container));
+        states
+    }
+
+    /// Names external DNS providers hold for containers in the workspace
+    pub fn dns_records(&self) -> Vec<networking::external_dns::DnsState> {
+        let mut states = networking::external_dns::list(&self.config().data_dir);
+        states.retain(|s| self.in_workspace(&s.container));
+        states
+    }
+
+    /// Remove the DNS records of containers that are gone, follow address
+    /// changes and retry failed records
+    pub async fn sync_dns(&self) -> Result<Vec<networking::external_dns::DnsState>> {
+        let runtime = runtime::detect_container_runtime().await?;
+        networking::external_dns::sync(&runtime, &self.config()).await?;
+        Ok(self.dns_records())
+    }
+
+    /// What the router and STUN see, and how `port_forward` containers are reachable
+    pub async fn nat_report(&self) -> networking::nat::NatReport {
+        networking::nat::report(&self.config()).await
+    }
+
+    /// The host's networks, containers, port mappings, tunnels and egress
+    /// policies as a graph
+    pub async fn network_topology(&self) -> Result<networking::topology::Topology> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let config = self.config();
+        let peers = networking::topology::discover_peers(&config).await;
+        Ok(networking::topology::collect(&runtime, &config, &peers).await?)
+    }
+
+    /// Stream topology changes over a websocket until interrupted
+    pub async fn serve_network_topology(&self, listen: std::net::SocketAddr) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(networking::topology::serve_live(&runtime, &self.config(), listen).await?)
+    }
+
+    /// Publish ports for hosts behind NAT until interrupted
+    #[cfg(feature = "quic-networking")]
+    pub async fn serve_relay(&self) -> Result<()> {
+        Ok(networking::relay::serve(&self.config()).await?)
+    }
+
+    /// Certificates in `[acme]` and when they expire
+    #[cfg(feature = "quic-networking")]
+    pub fn certificates(&self) -> Vec<networking::acme::CertStatus> {
+        networking::acme::status(&self.config())
+    }
+
+    /// Obtain missing `[acme]` certificates and renew those due, or the
+    /// named ones
+    #[cfg(feature = "quic-networking")]
+    pub async fn renew_certificates(
+        &self,
+        names: &[String],
+        force: bool,
+    ) -> Result<Vec<(String, anyhow::Result<bool>)>> {
+        Ok(networking::acme::renew(&self.config(), names, force).await?)
+    }
+
+    /// Keep `[acme]` certificates renewed until interrupted
+    #[cfg(feature = "quic-networking")]
+    pub async fn watch_certificates(&self) -> Result<()> {
+        Ok(networking::acme::watch(&self.config()).await?)
+    }
+
+    /// Compile-time features and host support, so callers can hide what won't work
+    pub async fn capabilities(&self) -> Result<runtime::capabilities::Capabilities> {
+        Ok(runtime::capabilities::Capabilities::detect().await)
+    }
+
+    /// Syscalls and writes the hardened sandbox blocked for a container
+    pub async fn sandbox_report(
+        &self,
+        container: &str,
+    ) -> Result<runtime::sandbox::CompatibilityReport> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::sandbox::compatibility_report(&runtime, container).await?)
+    }
+
+    /// Platforms of Boltfile services that need a binfmt handler this host
+    /// doesn't have yet
+    pub fn emulation_missing(&self) -> Result<Vec<runtime::platform::Platform>> {
+        use runtime::emulation::Support;
+
+        let mut missing: Vec<runtime::platform::Platform> = Vec::new();
+        for (_, platform) in runtime::emulation::foreign_services(&self.config().load_boltfile()?) {
+            let support = runtime::emulation::support(&platform);
+            if matches!(support, Support::Missing | Support::Unusable(_))
+                && !missing.contains(&platform)
+            {
+                missing.push(platform);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Register qemu-user-static handlers so containers for `platforms` run
+    /// under emulation
+    pub async fn setup_emulation(&self, platforms: &[runtime::platform::Platform]) -> Result<()> {
+        let runtime = runtime::detect_container_runtime().await?;
+        Ok(runtime::emulation::register(&runtime, platforms).await?)
+    }
+
+    /// Host preflight checks, including hardware networking from the Boltfile
+    pub async fn doctor(&self) -> Result<runtime::doctor::DoctorReport> {
+        Ok(runtime::doctor::run(&self.config()).await)
+    }
+
+    /// Benchmark container starts, pulls, proxy transports, volume IO and GPU
+    /// passthrough on this host
+    pub async fn bench(
+        &self,
+        options: &runtime::bench::BenchOptions,
+    ) -> Result<runtime::bench::Scorecard> {
+        Ok(runtime::bench::run(&self.config().data_dir, options).await?)
+    }
+
+    /// A saved benchmark baseline, if there is one by that name
+    pub fn bench_baseline(&self, name: &str) -> Result<Option<runtime::bench::Scorecard>> {
+        Ok(runtime::bench::load_baseline(
+            &self.config().data_dir,
+            name,
+        )?)
+    }
+
+    /// Save a benchmark run as a named baseline for later runs to compare with
+    pub fn save_bench_baseline(
+        &self,
+        name: &str,
+        scorecard: &runtime::bench::Scorecard,
+    ) -> Result<std::path::PathBuf> {
+        Ok(runtime::bench::save_baseline(
+            &self.config().data_dir,
+            name,
+            scorecard,
+        )?)
+    }
+
+    /// Roll daemon, storage, network and surge project health on this node
+    /// up into one status with the reasons it isn't healthy
+    pub async fn health(&self) -> Result<runtime::health::NodeHealth> {
+        Ok(runtime::health::check(&self.config()).await)
+    }
+
+    /// Compare the host with the recommended QUIC network tuning
+    pub async fn network_tuning(&self) -> Result<Vec<networking::tuning::TuningItem>> {
+        let boltfile = self.config().load_boltfile().ok();
+        Ok(networking::tuning::inspect(boltfile.as_ref()).await)
+    }
+
+    /// Apply the recommended QUIC network tuning, recording each changed
+    /// setting's new value
+    pub async fn apply_network_tuning(
+        &self,
+        items: &mut [networking::tuning::TuningItem],
+    ) -> Result<()> {
+        Ok(networking::tuning::apply(items).await?)
+    }
+
+    /// Record current storage usage and forecast when each store fills up,
+    /// alerting on anything due within `[capacity] alert_days`
+    pub async fn capacity_report(&self) -> Result<monitoring::capacity::CapacityReport> {
+        Ok(monitoring::capacity::run(&self.config()).await?)
+    }
+
+    /// The filesystem snapshot manager for the Boltfile's `[snapshots]`
+    pub async fn snapshots(&self) -> Result<runtime::snapshots::SnapshotManager> {
+        let settings = if self.config().boltfile_path.exists() {
+            self.config().load_boltfile()?.snapshots
+        } else {
+            None
+        };
+        Ok(
+            runtime::snapshots::SnapshotManager::open(&self.config().data_dir, settings.as_ref())
+                .await?,
+        )
+    }
+
+    /// Take a filesystem snapshot of the snapshot root
+    pub async fn create_snapshot(
+        &self,
+        name: Option<&str>,
+        description: Option<&str>,
+        kind: runtime::snapshots::SnapshotKind,
+    ) -> Result<runtime::snapshots::Snapshot> {
+        Ok(self.snapshots().await?.create(name, description, kind).await?)
+    }
+
+    /// Filesystem snapshots, oldest first
+    pub async fn list_snapshots(
+        &self,
+        kind: Option<runtime::snapshots::SnapshotKind>,
+    ) -> Result<Vec<runtime::snapshots::Snapshot>> {
+        Ok(self.snapshots().await?.list(kind)?)
+    }
+
+    /// A filesystem snapshot by ID, name or unique ID prefix
+    pub async fn snapshot(&self, snapshot: &str) -> Result<runtime::snapshots::Snapshot> {
+        Ok(self.snapshots().await?.find(snapshot)?)
+    }
+
+    /// Roll the snapshot root back to a snapshot
+    pub async fn rollback_snapshot(
+        &self,
+        snapshot: &str,
+    ) -> Result<runtime::snapshots::RollbackOutcome> {
+        Ok(self.snapshots().await?.rollback(snapshot).await?)
+    }
+
+    /// Delete a filesystem snapshot
+    pub async fn delete_snapshot(&self, snapshot: &str) -> Result<runtime::snapshots::Snapshot> {
+        Ok(self.snapshots().await?.delete(snapshot).await?)
+    }
+
+    /// Apply the snapshot retention policy
+    pub async fn cleanup_snapshots(
+        &self,
+        dry_run: bool,
+    ) -> Result<Vec<runtime::snapshots::Snapshot>> {
+        Ok(self.snapshots().await?.cleanup(dry_run).await?)
+    }
+
+    /// Start or stop taking the Boltfile's timeline snapshots in the background
+    pub fn set_auto_snapshots(
+        &self,
+        enabled: bool,
+    ) -> Result<Option<runtime::snapshots::AutoState>> {
+        if enabled {
+            Ok(Some(runtime::snapshots::auto_enable(
+                &self.config().data_dir,
+                &self.config().boltfile_path,
+            )?))
+        } else {
+            runtime::snapshots::auto_disable(&self.config().data_dir);
+            Ok(None)
+        }
+    }
+
+    /// The running automatic snapshot watcher
+    pub fn auto_snapshots(&self) -> Option<runtime::snapshots::AutoState> {
+        runtime::snapshots::auto_status(&self.config().data_dir)
+    }
+
+    /// What applying a tuning preset would change on this host and in the
+    /// named containers' cgroups; `[optimize] preset` when `preset` is None
+    pub async fn optimize_plan(
+        &self,
+        preset: Option<optimizations::tuning::Preset>,
+        containers: &[String],
+    ) -> Result<optimizations::tuning::Plan> {
+        let config = self.config();
+        let preset = preset.or(config.optimize.preset).unwrap_or_default();
+        let containers: Vec<String> = containers.iter().map(|c| self.scoped_name(c)).collect();
+        let cgroups = if containers.is_empty() {
+            Vec::new()
+        } else {
+            let runtime = runtime::detect_container_runtime().await?;
+            optimizations::tuning::container_cgroups(&runtime, &containers).await?
+        };
+        Ok(optimizations::tuning::plan(
+            &optimizations::tuning::Host::default(),
+            preset,
+            &config.optimize,
+            &cgroups,
+        )?)
     }
 
-    /// Get the runtime configuration
-    pub fn config(&self) -> &BoltConfig {
-        &self.config
+    /// Apply a tuning plan, recording the previous values for `revert_optimize`
+    pub fn apply_optimize(
+        &self,
+        plan: &optimizations::tuning::Plan,
+    ) -> Result<optimizations::tuning::Outcome> {
+        Ok(optimizations::tuning::apply(&self.config().data_dir, plan)?)
+    }
+
+    /// Restore what the host was set to before `bolt optimize apply`
+    pub fn revert_optimize(&self) -> Result<optimizations::tuning::Outcome> {
+        Ok(optimizations::tuning::revert(&self.config().data_dir)?)
+    }
+
+    /// The maintenance window, the queue and the last run
+    pub fn maintenance_status(&self) -> Result<surge::maintenance::MaintenanceStatus> {
+        Ok(surge::maintenance::status(&self.config())?)
+    }
+
+    /// Queue a maintenance operation for the next window; false if it
+    /// already was
+    pub fn queue_maintenance(&self, operation: surge::maintenance::Operation) -> Result<bool> {
+        Ok(surge::maintenance::queue(
+            &self.config().data_dir,
+            operation,
+        )?)
+    }
+
+    /// Drop a queued maintenance operation; false if it wasn't queued
+    pub fn unqueue_maintenance(&self, operation: surge::maintenance::Operation) -> Result<bool> {
+        Ok(surge::maintenance::unqueue(
+            &self.config().data_dir,
+            operation,
+        )?)
+    }
+
+    /// Queue operations and run the maintenance queue now
+    pub async fn run_maintenance(
+        &self,
+        operations: &[surge::maintenance::Operation],
+    ) -> Result<surge::maintenance::RunReport> {
+        let config = self.config();
+        for operation in operations {
+            surge::maintenance::queue(&config.data_dir, *operation)?;
+        }
+        Ok(surge::maintenance::run(&config, None).await?)
+    }
+
+    /// Start or stop running maintenance windows in the background
+    pub fn set_maintenance(&self, enabled: bool) -> Result<Option<surge::maintenance::WatchState>> {
+        if enabled {
+            Ok(Some(surge::maintenance::enable(
+                &self.config().data_dir,
+                &self.config().boltfile_path,
+            )?))
+        } else {
+            surge::maintenance::disable(&self.config().data_dir);
+            Ok(None)
+        }
+    }
+
+    /// Built-in and user capsule templates
+    pub fn capsule_templates(&self) -> Vec<capsules::CapsuleTemplate> {
+        let mut templates = capsules::templates::builtin_templates();
+        templates.extend(capsules::templates::load_user_templates(
+            &self.capsules_root(),
+        ));
+        templates
+    }
+
+    pub fn capsule_template(&self, name: &str) -> Result<capsules::CapsuleTemplate> {
+        Ok(capsules::templates::find(&self.capsules_root(), name)?)
+    }
+
+    /// Store a user template derived from another one
+    pub fn create_capsule_template(
+        &self,
+        name: &str,
+        from: &str,
+        overrides: &capsules::templates::TemplateOverrides,
+        force: bool,
+    ) -> Result<capsules::CapsuleTemplate> {
+        let mut template = self.capsule_template(from)?;
+        template.name = name.to_string();
+        overrides.apply(&mut template);
+        capsules::templates::save(&self.capsules_root(), &template, force)?;
+        Ok(template)
+    }
+
+    /// Validate a template file and store it, renamed to `name` if given
+    pub fn import_capsule_template(
+        &self,
+        path: &std::path::Path,
+        name: Option<&str>,
+        force: bool,
+    ) -> Result<capsules::CapsuleTemplate> {
+        let text = std::fs::read_to_string(path)?;
+        let mut template = capsules::templates::parse(&text)?;
+        if let Some(name) = name {
+            template.name = name.to_string();
+        }
+        capsules::templates::save(&self.capsules_root(), &template, force)?;
+        Ok(template)
+    }
+
+    pub fn remove_capsule_template(&self, name: &str) -> Result<()> {
+        Ok(capsules::templates::remove(&self.capsules_root(), name)?)
+    }
+
+    /// Run a capsule from a template
+    pub async fn create_capsule(
+        &self,
+        name: &str,
+        template: &str,
+        overrides: &capsules::templates::TemplateOverrides,
+    ) -> Result<capsules::CapsuleConfig> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let mut template = self.capsule_template(template)?;
+        overrides.apply(&mut template);
+        let name = self.scoped_name(name);
+        let config =
+            capsules::templates::instantiate(&runtime, &self.capsules_root(), &name, &template)
+                .await?;
+        optimizations::hybrid::apply_to_container(
+            &runtime,
+            &self.config().data_dir,
+            &name,
+            config.resources.core_placement,
+            config.resources.energy_performance_preference,
+        )
+        .await?;
+        Ok(config)
+    }
+
+    /// Show a capsule's boot output and follow its console until Ctrl-C, or
+    /// with `shell` open a login shell in it; returns the exit code
+    pub async fn capsule_console(&self, name: &str, lines: usize, shell: bool) -> Result<i32> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let name = self.scoped_name(name);
+        Ok(if shell {
+            capsules::console::shell(&runtime, &self.capsules_root(), &name, "/bin/sh").await?
+        } else {
+            capsules::console::attach(&runtime, &self.capsules_root(), &name, lines).await?
+        })
+    }
+
+    /// The last `lines` lines of a capsule's boot output, captured again first
+    /// when the capsule still exists
+    pub async fn capsule_console_log(&self, name: &str, lines: usize) -> Result<Vec<String>> {
+        let name = self.scoped_name(name);
+        let root = self.capsules_root();
+        let runtime = runtime::detect_container_runtime().await?;
+        if let Err(e) = capsules::console::capture(&runtime, &root, &name).await {
+            tracing::debug!("Showing the last captured boot output of {}: {}", name, e);
+        }
+        Ok(capsules::console::tail(&root, &name, lines)?)
+    }
+
+    /// Snapshot a running capsule with its memory: a CRIU checkpoint that
+    /// leaves it running, stored compressed and deduplicated against the
+    /// capsule's earlier snapshots
+    pub async fn snapshot_capsule(
+        &self,
+        name: &str,
+        snapshot_name: Option<String>,
+        description: Option<&str>,
+    ) -> Result<capsules::SnapshotMetadata> {
+        let runtime = runtime::detect_container_runtime().await?;
+        let name = self.scoped_name(name);
+        let root = self.capsules_root();
+        let mut manager = capsules::CapsuleManager::new(root.clone())?;
+        manager.load_instance(&name, capsules::CapsuleStatus::Running)?;
+
+        // The checkpoint is only kept as the memory blocks it's stored as
+        let work = tempfile::tempdir_in(&root)?;
+        let checkpoint = work.path().join("checkpoint");
+        runtime::checkpoint::checkpoint(
+            &runtime,
+            &name,
+            &checkpoint,
+            runtime::checkpoint::CheckpointOptions {
+                leave_running: true,
+                tcp_established: false,
+            },
+        )
+        .await?;
+        let image = work.path().join("memory.img");
+        runtime::checkpoint::memory_image(&checkpoint, &image)?;
+
+        let description =
+            description.map_or_else(|| format!("Memory snapshot of {}", name), str::to_string);
+        Ok(manager.create_memory_snapshot(&name, &image, snapshot_name, &description)?)
+    }
+
+    /// Write a capsule snapshot and its memory blocks to a portable bundle
+    pub fn export_snapshot(
+        &self,
+        snapshot: &str,
+        output: &std::path::Path,
+    ) -> Result<capsules::export::ExportSummary> {
+        Ok(capsules::export::export_snapshot(
+            &self.capsules_root(),
+            snapshot,
+            output,
+        )?)
+    }
+
+    /// Import a snapshot bundle, optionally checking its SHA-256 first
+    pub fn import_snapshot(
+        &self,
+        bundle: &std::path::Path,
+        sha256: Option<&str>,
+    ) -> Result<capsules::export::SnapshotRecord> {
+        Ok(capsules::export::import_snapshot(
+            &self.capsules_root(),
+            bundle,
+            sha256,
+        )?)
+    }
+
+    /// Ship a snapshot directly to another host running `bolt snapshot receive`
+    #[cfg(feature = "quic-networking")]
+    pub async fn send_snapshot(
+        &self,
+        snapshot: &str,
+        to: &str,
+        fingerprint: Option<&str>,
+        retries: u32,
+    ) -> Result<capsules::transfer::SendSummary> {
+        Ok(self
+            .snapshot_transfer()?
+            .send(snapshot, to, fingerprint, retries)
+            .await?)
+    }
+
+    /// Accept snapshots from authorized senders until interrupted
+    #[cfg(feature = "quic-networking")]
+    pub async fn receive_snapshots(
+        &self,
+        listen: std::net::SocketAddr,
+        allow_senders: &[String],
+        max_size: u64,
+    ) -> Result<()> {
+        Ok(self
+            .snapshot_transfer()?
+            .receive(listen, allow_senders, max_size)
+            .await?)
+    }
+
+    /// Certificate fingerprint a receiving host must authorize to accept
+    /// snapshots from this one
+    #[cfg(feature = "quic-networking")]
+    pub fn snapshot_sender_fingerprint(&self) -> Result<String> {
+        Ok(self.snapshot_transfer()?.sender_fingerprint()?)
+    }
+
+    #[cfg(feature = "quic-networking")]
+    fn snapshot_transfer(&self) -> Result<capsules::transfer::SnapshotTransfer> {
+        Ok(capsules::transfer::SnapshotTransfer::new(
+            self.capsules_root(),
+            self.config().data_dir.join("transfers"),
+        )?)
+    }
+
+    fn capsules_root(&self) -> std::path::PathBuf {
+        self.config().data_dir.join("capsules")
+    }
+
+    /// Get the runtime configuration. A reload doesn't change a config
+    /// already handed out; call this again to see it.
+    pub fn config(&self) -> std::sync::Arc<BoltConfig> {
+        self.config
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Reread `<config_dir>/config.toml`, validate it and swap it in for
+    /// every clone of this runtime, applying the new log levels
+    pub fn reload_config(&self) -> Result<config::reload::ConfigReload> {
+        let (config, reload) = self.config().reloaded()?;
+        monitoring::logging::set_levels(&config)?;
+        *self
+            .config
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = std::sync::Arc::new(config);
+        Ok(reload)
     }
 }
 
 impl Default for BoltRuntime {
     fn default() -> Self {
-        Self::new().unwrap_or_else(|_| Self {
-            config: BoltConfig::default(),
-        })
+        Self::new().unwrap_or_else(|_| Self::with_config(BoltConfig::default()))
     }
 }
 
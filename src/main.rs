@@ -1,31 +1,64 @@
 mod cli;
 
 use anyhow::Result;
-use bolt::{BoltConfig, BoltRuntime, gaming, network, surge};
-use clap::Parser;
-use cli::{Cli, Commands, GamingCommands, NetworkCommands, SurgeCommands, VolumeCommands, compat};
+use bolt::config::template::TemplateOptions;
+use bolt::{BoltConfig, BoltRuntime, gaming, monitoring::logging, network, surge};
+use clap::{CommandFactory, FromArgMatches};
+use cli::{
+    BackupCommands, BuildCommands, BuilderCommands, CapsuleCommands, CapsuleSshCommands,
+    CapsuleTemplateCommands, CertCommands, Cli, Commands, ConfigCommands, CrashCommands,
+    DebugCommands, DesktopCommands, DevCommands, GamingCommands, HooksCommands, IdleCommands,
+    ImageCommands, MaintenanceCommands, MetricsCommands, NetworkCommands, OptimizeCommands,
+    PrefixCommands, ProtonCommands, ReportCommands, ResticCommands, SandboxCommands,
+    SecretCommands, SurgeCommands, ThermalCommands, VolumeCommands, WorkspaceCommands, compat,
+};
 use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
-
-    // Initialize logging
-    let level = if cli.verbose { "debug" } else { "info" };
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env().add_directive(level.parse()?),
-        )
-        .init();
-
-    info!("🚀 Bolt starting up...");
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
 
     // Create BoltConfig from CLI config path
     let mut bolt_config = BoltConfig::load()?;
     bolt_config.boltfile_path = std::path::PathBuf::from(&cli.config);
     bolt_config.verbose = cli.verbose;
+    bolt_config.template = TemplateOptions::from_args(&cli.vars, cli.profile.clone())?;
+    bolt_config.trust.override_violations = cli.override_trust;
+    if let Commands::Surge {
+        command:
+            SurgeCommands::Up {
+                remap_ports: true, ..
+            }
+            | SurgeCommands::Plan {
+                remap_ports: true, ..
+            },
+    } = cli.command
+    {
+        bolt_config.ports.remap = true;
+    }
+    if let Commands::Surge {
+        command: SurgeCommands::Up { ref set, .. } | SurgeCommands::Plan { ref set, .. },
+    } = cli.command
+    {
+        bolt_config.overrides = set.clone();
+    }
 
-    let runtime = BoltRuntime::new()?;
+    // An SSH session's stdout belongs to the capsule: sftp and `ssh host
+    // <command>` output would be corrupted by log lines
+    if let Commands::SshExec { ref capsule } = cli.command {
+        let code = bolt::runtime::ssh::exec_session(&bolt_config.data_dir, capsule).await?;
+        std::process::exit(code);
+    }
+
+    // Initialize logging
+    let log_format = cli.log_format.as_deref().map(str::parse).transpose()?;
+    let _log_guard = logging::init(&bolt_config, log_format)?;
+    let _request_span = logging::request_span(matches.subcommand_name().unwrap_or("bolt")).entered();
+
+    info!("🚀 Bolt starting up...");
+
+    let runtime = BoltRuntime::with_config(bolt_config.clone());
 
     match cli.command {
         Commands::Run {
@@ -37,6 +70,25 @@ async fn main() -> Result<()> {
             detach,
             runtime: gpu_runtime,
             gpu,
+            gpu_shared,
+            crash_dump,
+            pull,
+            platform,
+            restart,
+            health_cmd,
+            health_interval,
+            health_timeout,
+            health_retries,
+            health_start_period,
+            no_healthcheck,
+            labels,
+            annotations,
+            user,
+            network,
+            cap_add,
+            cap_drop,
+            workdir,
+            hostname,
         } => {
             info!("Running container: {}", image);
             if let Some(ref runtime_type) = gpu_runtime {
@@ -45,28 +97,626 @@ async fn main() -> Result<()> {
             if let Some(ref gpu_devices) = gpu {
                 info!("  GPU devices: {}", gpu_devices);
             }
-            runtime
-                .run_container(&image, name.as_deref(), &ports, &env, &volumes, detach)
-                .await?;
+            // Crash dumps are looked up by name once the container has exited
+            let name = if crash_dump && name.is_none() {
+                Some(format!("bolt-{}", &uuid::Uuid::new_v4().to_string()[..8]))
+            } else {
+                name
+            };
+
+            let healthcheck = match (health_cmd, no_healthcheck) {
+                (Some(command), _) => Some(bolt::config::HealthcheckConfig {
+                    test: vec!["CMD-SHELL".to_string(), command],
+                    interval: health_interval,
+                    timeout: health_timeout,
+                    retries: health_retries,
+                    start_period: health_start_period,
+                    disable: false,
+                }),
+                (None, true) => Some(bolt::config::HealthcheckConfig {
+                    test: vec!["NONE".to_string()],
+                    interval: None,
+                    timeout: None,
+                    retries: None,
+                    start_period: None,
+                    disable: true,
+                }),
+                (None, false) => None,
+            };
+            let options = bolt::runtime::run_options::RunOptions {
+                restart,
+                healthcheck,
+                labels,
+                annotations,
+                user,
+                network,
+                cap_add,
+                cap_drop,
+                workdir,
+                hostname,
+                gpus: gpu,
+                gpu_shared,
+            };
+            // Check the options, and reserve the GPUs, before anything is pulled
+            options.run_args(
+                &bolt::runtime::detect_container_runtime().await?,
+                &bolt_config.data_dir,
+            )?;
+
+            // Bolt pulls missing images itself so encrypted layers can be
+            // decrypted, the trust policy checked and the image's platform
+            // verified before the container starts
+            let mut timer = bolt::runtime::startup::StartupTimer::new(&image);
+            if !image.starts_with("bolt://") {
+                let policy = pull.unwrap_or(bolt::runtime::pull::PullPolicy::IfNotPresent);
+                timer.phase(bolt::runtime::startup::Phase::ImageResolve);
+                runtime
+                    .ensure_image(&image, Some(policy), platform.as_deref())
+                    .await?;
+            }
+
+            // The PID, cgroup and process status are gone once the container
+            // exits, so they are sampled while it runs
+            let watch = match (crash_dump, detach, &name) {
+                (true, false, Some(container)) => Some(bolt::runtime::crash::CrashWatch::start(
+                    &bolt::runtime::detect_container_runtime().await?,
+                    container,
+                )),
+                _ => None,
+            };
+            let result = runtime
+                .run_container_timed(
+                    &image,
+                    name.as_deref(),
+                    &ports,
+                    &env,
+                    &volumes,
+                    detach,
+                    &options,
+                    timer,
+                )
+                .await;
+
+            let live = watch.map(|w| w.finish());
+            if let (Err(_), Some(live), Some(container)) = (&result, live, &name) {
+                match bolt::runtime::collect_crash_dump(
+                    container,
+                    bolt_config.crash_dumps.clone(),
+                    live,
+                )
+                .await
+                {
+                    Ok(Some(report)) => info!(
+                        "💥 Crash dump {} collected (bolt debug crashes show {})",
+                        report.id, container
+                    ),
+                    Ok(None) => {}
+                    Err(e) => info!("Could not collect crash dump: {}", e),
+                }
+            }
+            result?;
+        }
+
+        Commands::Build {
+            command: Some(BuildCommands::VerifyReproducible { image, keep, json }),
+            ..
+        } => {
+            let verification = runtime.verify_reproducible(&image, keep).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&verification)?);
+            } else {
+                println!("Expected: {}", verification.expected);
+                println!("Rebuilt:  {}", verification.actual);
+                for layer in &verification.layers {
+                    println!(
+                        "{} layer {:<3} {}",
+                        if layer.matches() { "✅" } else { "❌" },
+                        layer.index,
+                        layer.created_by.as_deref().unwrap_or("-")
+                    );
+                    if !layer.matches() {
+                        println!(
+                            "      expected {}",
+                            layer.expected.as_deref().unwrap_or("none")
+                        );
+                        println!(
+                            "      rebuilt  {}",
+                            layer.actual.as_deref().unwrap_or("none")
+                        );
+                    }
+                }
+                for input in &verification.changed_inputs {
+                    println!("⚠️  Changed since the build: {}", input);
+                }
+                if let Some(ref rebuilt) = verification.rebuilt {
+                    println!("Rebuilt image kept as {}", rebuilt);
+                }
+                if verification.reproducible() {
+                    println!("✅ {} is reproducible", verification.image);
+                } else {
+                    println!("❌ {} did not rebuild identically", verification.image);
+                }
+            }
+            if !verification.reproducible() {
+                std::process::exit(1);
+            }
         }
 
-        Commands::Build { path, tag, file } => {
-            info!("Building image from: {}", path);
-            runtime.build_image(&path, tag.as_deref(), &file).await?;
+        Commands::Build {
+            path,
+            tag,
+            file,
+            from_nix,
+            diff,
+            builder,
+            cache_volumes,
+            annotations,
+            build_args,
+            reproducible,
+            source_date_epoch,
+            no_cache,
+            command: None,
+        } => {
+            if reproducible {
+                let tag = tag.unwrap_or_default();
+                info!("Building {} reproducibly from: {}", tag, path);
+                let provenance = runtime
+                    .build_image_reproducible(
+                        &builder,
+                        &path,
+                        &tag,
+                        &file,
+                        &annotations,
+                        &build_args,
+                        source_date_epoch,
+                    )
+                    .await?;
+                println!("{}", provenance.digest);
+                info!(
+                    "📜 Provenance: {}",
+                    bolt::builds::reproducible::provenance_dir(&bolt_config.data_dir)
+                        .join(format!(
+                            "{}.json",
+                            provenance.digest.trim_start_matches("sha256:")
+                        ))
+                        .display()
+                );
+            } else if let Some(flake_ref) = from_nix {
+                if let Some(other_ref) = diff {
+                    let image_diff = runtime.diff_nix_images(&other_ref, &flake_ref).await?;
+                    for path in &image_diff.added {
+                        println!("+ {}", path);
+                    }
+                    for path in &image_diff.removed {
+                        println!("- {}", path);
+                    }
+                    for path in &image_diff.modified {
+                        println!("~ {}", path);
+                    }
+                    info!(
+                        "{} added, {} removed, {} modified",
+                        image_diff.added.len(),
+                        image_diff.removed.len(),
+                        image_diff.modified.len()
+                    );
+                } else {
+                    info!("Importing Nix image: {}", flake_ref);
+                    let result = runtime
+                        .import_nix_image(&flake_ref, tag.as_deref())
+                        .await?;
+                    println!("{}", result.build_hash);
+                    if !result.reproducible {
+                        info!("⚠️  Image digest changed since the previous import");
+                    }
+                }
+            } else {
+                info!("Building image from: {}", path);
+                let stats = runtime
+                    .build_image_on(
+                        &builder,
+                        &path,
+                        tag.as_deref(),
+                        &file,
+                        &cache_volumes,
+                        &annotations,
+                        &build_args,
+                        no_cache,
+                    )
+                    .await?;
+                info!("📊 Build cache: {}", stats);
+            }
         }
 
-        Commands::Pull { image } => {
+        Commands::Builder { command } => match command {
+            BuilderCommands::Create {
+                name,
+                concurrency,
+                cpus,
+                memory,
+                storage,
+            } => {
+                let limits = bolt::builds::builders::BuilderLimits {
+                    concurrency,
+                    cpus,
+                    memory: memory
+                        .as_deref()
+                        .map(bolt::builds::cache::parse_size)
+                        .transpose()?,
+                    storage: storage
+                        .as_deref()
+                        .map(bolt::builds::cache::parse_size)
+                        .transpose()?,
+                };
+                runtime.create_builder(&name, limits).await?;
+            }
+            BuilderCommands::Ls { json } => {
+                use bolt::builds::cache::format_size;
+
+                let builders = runtime.list_builders().await?;
+                if json {
+                    let builders: Vec<serde_json::Value> = builders
+                        .iter()
+                        .map(|(builder, size)| {
+                            serde_json::json!({ "builder": builder, "cache_size": size })
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&builders)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:<20} {:>11} {:>6} {:>10} {:>21}",
+                    "NAME", "CONCURRENCY", "CPUS", "MEMORY", "CACHE"
+                );
+                let limit = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+                for (builder, size) in &builders {
+                    let quota = match builder.storage {
+                        Some(storage) => format_size(storage),
+                        None => runtime.config().build_cache.max_size.clone(),
+                    };
+                    println!(
+                        "{:<20} {:>11} {:>6} {:>10} {:>21}",
+                        builder.name,
+                        limit((!builder.is_default()).then(|| builder.concurrency.to_string())),
+                        limit(builder.cpus.map(|c| c.to_string())),
+                        limit(builder.memory.map(format_size)),
+                        format!("{} / {}", format_size(*size), quota)
+                    );
+                }
+            }
+            BuilderCommands::Rm { name } => {
+                runtime.remove_builder(&name).await?;
+                info!("✅ Removed builder {}", name);
+            }
+            BuilderCommands::Prune {
+                keep_storage,
+                filter,
+                builder,
+            } => {
+                let mut until = None;
+                for f in &filter {
+                    match f.split_once('=') {
+                        Some(("until", value)) => {
+                            until = Some(bolt::builds::cache::parse_duration(value)?)
+                        }
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "Unsupported filter '{}' (expected until=<duration>)",
+                                f
+                            ));
+                        }
+                    }
+                }
+
+                // With neither option, prune everything
+                let keep_storage = match keep_storage {
+                    Some(size) => bolt::builds::cache::parse_size(&size)?,
+                    None if until.is_some() => u64::MAX,
+                    None => 0,
+                };
+
+                let reclaimed = runtime.prune_build_cache(&builder, keep_storage, until).await?;
+                info!(
+                    "✅ Pruned the layer cache, reclaimed {}",
+                    bolt::builds::cache::format_size(reclaimed)
+                );
+            }
+            BuilderCommands::Caches {
+                prune,
+                keep_storage,
+                clear,
+                json,
+            } => {
+                use bolt::builds::artifacts;
+                use bolt::builds::cache::{format_size, parse_size};
+
+                let config = runtime.config();
+                let container_runtime = bolt::runtime::detect_container_runtime().await.ok();
+                if let Some(name) = clear {
+                    let reclaimed = artifacts::clear(&config.data_dir, &name)?;
+                    info!(
+                        "✅ Removed cache volume {} ({})",
+                        name,
+                        format_size(reclaimed)
+                    );
+                    return Ok(());
+                }
+                if prune {
+                    let max_size = parse_size(
+                        keep_storage
+                            .as_deref()
+                            .unwrap_or(&config.build_cache.artifacts_max_size),
+                    )?;
+                    let report =
+                        artifacts::prune(&config.data_dir, container_runtime.as_deref(), max_size)
+                            .await?;
+                    for name in &report.busy {
+                        info!("⏳ Skipped cache volume {}: a build is using it", name);
+                    }
+                    info!(
+                        "✅ Evicted {} cache volume entries, reclaimed {} ({} / {} used)",
+                        report.removed,
+                        format_size(report.reclaimed),
+                        format_size(report.size),
+                        format_size(max_size)
+                    );
+                    return Ok(());
+                }
+
+                let caches = artifacts::usage(&config.data_dir, container_runtime.as_deref()).await;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&caches)?);
+                    return Ok(());
+                }
+                println!("{:<16} {:>10} {:<20} IN USE", "NAME", "SIZE", "LAST USED");
+                for cache in &caches {
+                    println!(
+                        "{:<16} {:>10} {:<20} {}",
+                        cache.name,
+                        format_size(cache.size),
+                        cache
+                            .last_used
+                            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                        if cache.in_use { "yes" } else { "no" }
+                    );
+                }
+                let total: u64 = caches.iter().map(|c| c.size).sum();
+                println!(
+                    "\n{} / {} (built in: {})",
+                    format_size(total),
+                    config.build_cache.artifacts_max_size,
+                    artifacts::known_names().join(", ")
+                );
+            }
+        },
+
+        Commands::Image { command } => match command {
+            ImageCommands::Optimize {
+                image,
+                tag,
+                strip,
+                level,
+                json,
+            } => {
+                use bolt::builds::cache::format_size;
+                use bolt::builds::optimize::{OptimizeOptions, StripProfile};
+
+                let profiles = strip
+                    .iter()
+                    .filter(|s| s.as_str() != "none")
+                    .map(|s| StripProfile::parse(s))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let options = OptimizeOptions {
+                    tag,
+                    profiles,
+                    level,
+                };
+                let report = runtime.optimize_image(&image, &options).await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    println!(
+                        "{:<20} {:>10} {:>8} {:>10} {:>10} {:>10}",
+                        "LAYER", "SIZE", "FILES", "SHADOWED", "STRIPPED", "KEPT"
+                    );
+                    for layer in &report.layers {
+                        let digest = layer.digest.trim_start_matches("sha256:");
+                        println!(
+                            "{:<20} {:>10} {:>8} {:>10} {:>10} {:>10}",
+                            &digest[..digest.len().min(19)],
+                            format_size(layer.size_bytes),
+                            layer.files,
+                            format_size(layer.shadowed_bytes),
+                            format_size(layer.stripped_bytes),
+                            format_size(layer.kept_bytes)
+                        );
+                    }
+                    println!(
+                        "\n{} layers ({}) squashed to 1 layer of {} ({} with zstd), {} files stripped",
+                        report.layers.len(),
+                        format_size(report.original_bytes),
+                        format_size(report.squashed_bytes),
+                        format_size(report.compressed_bytes),
+                        report.stripped_files
+                    );
+                    println!("✅ Tagged {}", report.tag);
+                }
+            }
+            #[cfg(feature = "quic-networking")]
+            ImageCommands::Serve => runtime.serve_layers().await?,
+            #[cfg(feature = "quic-networking")]
+            ImageCommands::Peers { json } => {
+                let peers = runtime.layer_peers().await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&peers)?);
+                } else if peers.is_empty() {
+                    info!("No Bolt nodes on the LAN are sharing layers");
+                } else {
+                    println!("{:<34} {:<40} {:>8}", "NODE", "ADDRESS", "BLOBS");
+                    for peer in peers {
+                        println!(
+                            "{:<34} {:<40} {:>8}",
+                            peer.node,
+                            peer.address.to_string(),
+                            peer.blobs
+                        );
+                    }
+                }
+            }
+            #[cfg(not(feature = "quic-networking"))]
+            ImageCommands::Serve | ImageCommands::Peers { .. } => {
+                return Err(anyhow::anyhow!(
+                    "Sharing layers requires the quic-networking feature"
+                ));
+            }
+            ImageCommands::Annotations { image, json } => {
+                let annotations = runtime.image_annotations(&image).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&annotations)?);
+                } else {
+                    for (key, value) in annotations {
+                        println!("{}={}", key, value);
+                    }
+                }
+            }
+        },
+
+        Commands::Pull {
+            image,
+            platform,
+            rootfs,
+        } => {
             info!("Pulling image: {}", image);
-            runtime.pull_image(&image).await?;
+            if rootfs {
+                let path = runtime.pull_rootfs(&image, platform.as_deref()).await?;
+                println!("{}", path.display());
+            } else {
+                runtime.pull_image(&image, platform.as_deref()).await?;
+            }
         }
 
-        Commands::Push { image } => {
+        Commands::Push {
+            image,
+            encrypt,
+            encrypt_layer,
+            tags,
+            annotations,
+        } => {
             info!("Pushing image: {}", image);
-            runtime.push_image(&image).await?;
+            if encrypt.is_empty() {
+                runtime
+                    .push_image_tagged(&image, &tags, &annotations)
+                    .await?;
+            } else {
+                runtime
+                    .push_image_encrypted(&image, &encrypt, &encrypt_layer)
+                    .await?;
+            }
+        }
+
+        Commands::Stats {
+            containers,
+            no_stream,
+            json,
+            interval,
+        } => {
+            use futures::StreamExt;
+
+            let interval = bolt::builds::cache::parse_duration(&interval)?;
+            let containers = if containers.is_empty() {
+                runtime
+                    .list_containers(false)
+                    .await?
+                    .into_iter()
+                    .map(|c| c.name)
+                    .collect()
+            } else {
+                containers
+            };
+            if containers.is_empty() {
+                info!("No running containers");
+                return Ok(());
+            }
+
+            let mut latest = std::collections::BTreeMap::new();
+            if no_stream {
+                // Two samples, as CPU usage needs something to compare against
+                let samples = futures::future::join_all(containers.iter().map(|container| {
+                    runtime
+                        .container_stats(container, interval)
+                        .take(2)
+                        .collect::<Vec<_>>()
+                }))
+                .await;
+                for (container, samples) in containers.iter().zip(samples) {
+                    match samples.into_iter().last() {
+                        Some(Ok(metrics)) => {
+                            latest.insert(container.clone(), metrics);
+                        }
+                        Some(Err(e)) => eprintln!("❌ {}: {}", container, e),
+                        None => {}
+                    }
+                }
+                if json {
+                    let all: Vec<_> = latest.values().collect();
+                    println!("{}", serde_json::to_string_pretty(&all)?);
+                } else {
+                    print_stats(&latest);
+                }
+                return Ok(());
+            }
+
+            let mut samples = futures::stream::select_all(containers.iter().map(|container| {
+                let container = container.clone();
+                runtime
+                    .container_stats(&container, interval)
+                    .map(move |metrics| (container.clone(), metrics))
+                    .boxed()
+            }));
+            let mut redraw = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    sample = samples.next() => match sample {
+                        Some((container, Ok(metrics))) => {
+                            if json {
+                                println!("{}", serde_json::to_string(&metrics)?);
+                            }
+                            latest.insert(container, metrics);
+                        }
+                        Some((container, Err(e))) => {
+                            latest.remove(&container);
+                            if json {
+                                eprintln!("❌ {}: {}", container, e);
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = redraw.tick(), if !json => {
+                        // Clear the screen and redraw from the top left
+                        print!("\x1b[2J\x1b[H");
+                        print_stats(&latest);
+                    }
+                }
+            }
         }
 
-        Commands::Ps { all } => {
-            let containers = runtime.list_containers(all).await?;
+        Commands::Ps { all, filters } => {
+            let mut containers = runtime.list_containers(all).await?;
+            if !filters.is_empty() {
+                let filters = filters
+                    .iter()
+                    .map(|f| bolt::runtime::annotations::Filter::parse(f))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let mut matching = Vec::new();
+                for container in containers {
+                    let annotations = runtime.container_annotations(&container.name).await?;
+                    if filters.iter().all(|f| f.matches(&annotations)) {
+                        matching.push(container);
+                    }
+                }
+                containers = matching;
+            }
 
             if containers.is_empty() {
                 info!("No containers found");
@@ -102,6 +752,9 @@ async fn main() -> Result<()> {
 
                 // Enhanced status with runtime info
                 let status_display = match container.runtime.as_deref() {
+                    _ if container.labels.contains_key(bolt::runtime::emulation::LABEL) => {
+                        format!("🐢 {}", container.status)
+                    }
                     Some("nvbind") => format!("🚀 {}", container.status),
                     Some("docker") => format!("🐳 {}", container.status),
                     _ => container.status.clone(),
@@ -127,6 +780,16 @@ async fn main() -> Result<()> {
             }
 
             println!();
+            for container in &containers {
+                if let Some(platform) = container.labels.get(bolt::runtime::emulation::LABEL) {
+                    println!(
+                        "🐢 {} runs {} under emulation on this {} host: expect it to be several times slower",
+                        container.name,
+                        platform,
+                        bolt::runtime::platform::Platform::host()
+                    );
+                }
+            }
             info!(
                 "Found {} containers (showing all: {})",
                 containers.len(),
@@ -134,46 +797,356 @@ async fn main() -> Result<()> {
             );
         }
 
-        Commands::Stop { containers } => {
-            for container in containers {
-                info!("Stopping container: {}", container);
-                runtime.stop_container(&container).await?;
-            }
+        Commands::Stop {
+            containers,
+            filter,
+            all,
+            parallel,
+        } => {
+            let selector = bolt::runtime::bulk::Selector::new(containers, &filter, all)?;
+            let containers = runtime.select_containers(&selector, false).await?;
+            let summary = bolt::runtime::bulk::run("Stopped", containers, parallel, |container| {
+                let runtime = &runtime;
+                async move { Ok(runtime.stop_container(&container).await?) }
+            })
+            .await;
+            finish_bulk(&summary);
         }
 
-        Commands::Rm { containers, force } => {
-            for container in containers {
-                info!("Removing container: {}", container);
-                runtime.remove_container(&container, force).await?;
+        Commands::Rm {
+            containers,
+            force,
+            filter,
+            all,
+            parallel,
+        } => {
+            let selector = bolt::runtime::bulk::Selector::new(containers, &filter, all)?;
+            let containers = runtime.select_containers(&selector, true).await?;
+            let summary = bolt::runtime::bulk::run("Removed", containers, parallel, |container| {
+                let runtime = &runtime;
+                async move { Ok(runtime.remove_container(&container, force).await?) }
+            })
+            .await;
+            finish_bulk(&summary);
+        }
+
+        Commands::Update {
+            containers,
+            filter,
+            all,
+            parallel,
+            memory,
+            cpus,
+            pids_limit,
+            cpu_shares,
+            blkio_weight,
+            iops,
+        } => {
+            let limits = bolt::runtime::oci::ResourceLimits {
+                memory_limit: memory
+                    .as_deref()
+                    .map(bolt::builds::cache::parse_size)
+                    .transpose()?,
+                cpu_limit: cpus,
+                pids_limit,
+                blkio_weight,
+                cpu_shares,
+                io_limit: iops,
+            };
+            if limits.is_empty() {
+                anyhow::bail!(
+                    "Nothing to update; pass --memory, --cpus, --pids-limit, --cpu-shares, --blkio-weight or --iops"
+                );
             }
+            let selector = bolt::runtime::bulk::Selector::new(containers, &filter, all)?;
+            let containers = runtime.select_containers(&selector, false).await?;
+            let summary = bolt::runtime::bulk::run("Updated", containers, parallel, |container| {
+                let (runtime, limits) = (&runtime, &limits);
+                async move { Ok(runtime.update_container(&container, limits).await?) }
+            })
+            .await;
+            finish_bulk(&summary);
         }
 
         Commands::Restart {
             containers,
             timeout,
+            filter,
+            all,
+            parallel,
         } => {
-            for container in containers {
-                info!(
-                    "Restarting container: {} (timeout: {}s)",
-                    container, timeout
-                );
-                runtime.restart_container(&container, timeout).await?;
+            let selector = bolt::runtime::bulk::Selector::new(containers, &filter, all)?;
+            let containers = runtime.select_containers(&selector, false).await?;
+            let summary =
+                bolt::runtime::bulk::run("Restarted", containers, parallel, |container| {
+                    let runtime = &runtime;
+                    async move { Ok(runtime.restart_container(&container, timeout).await?) }
+                })
+                .await;
+            finish_bulk(&summary);
+        }
+
+        Commands::Inspect {
+            container,
+            timings,
+            json,
+            annotations,
+        } => {
+            if timings {
+                let timings = runtime.startup_timings(&container)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&timings)?);
+                } else {
+                    timings.print();
+                }
+            } else if annotations {
+                for (key, value) in runtime.container_annotations(&container).await? {
+                    println!("{}={}", key, value);
+                }
+            } else {
+                let inspect = runtime.inspect_container(&container).await?;
+                println!("{}", serde_json::to_string_pretty(&inspect)?);
+            }
+        }
+
+        Commands::Exec {
+            interactive,
+            tty,
+            env,
+            workdir,
+            user,
+            container,
+            command,
+        } => {
+            let options = bolt::runtime::ExecOptions {
+                interactive,
+                tty,
+                env,
+                workdir,
+                user,
+            };
+            let code = runtime
+                .exec_container_with(&container, &command, &options)
+                .await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+
+        Commands::Cp {
+            follow_link,
+            archive,
+            src,
+            dest,
+        } => {
+            use bolt::runtime::copy::{CopyOptions, Endpoint};
+
+            let options = CopyOptions {
+                follow_link,
+                archive,
+            };
+            let host = |endpoint: Endpoint| match endpoint {
+                Endpoint::Host(path) => Some(path),
+                Endpoint::Stream => Some(std::path::PathBuf::from("-")),
+                Endpoint::Container { .. } => None,
+            };
+            match (Endpoint::parse(&src), Endpoint::parse(&dest)) {
+                (Endpoint::Container { container, path }, to) => {
+                    let to = host(to).ok_or_else(|| {
+                        anyhow::anyhow!("Copying between containers is not supported")
+                    })?;
+                    let copied = runtime
+                        .copy_from_container(&container, &path, &to, options)
+                        .await?;
+                    // stdout carries the archive
+                    if to != std::path::Path::new("-") {
+                        info!(
+                            "✅ Copied {} from {}:{} to {}",
+                            bolt::builds::cache::format_size(copied),
+                            container,
+                            path,
+                            to.display()
+                        );
+                    }
+                }
+                (from, Endpoint::Container { container, path }) => {
+                    let from = host(from).expect("not a container");
+                    let copied = runtime
+                        .copy_to_container(&from, &container, &path, options)
+                        .await?;
+                    info!(
+                        "✅ Copied {} to {}:{}",
+                        bolt::builds::cache::format_size(copied),
+                        container,
+                        path
+                    );
+                }
+                _ => {
+                    return Err(anyhow::anyhow!("One of the paths must be CONTAINER:PATH"));
+                }
             }
         }
 
+        Commands::Checkpoint {
+            container,
+            dir,
+            leave_running,
+            tcp_established,
+        } => {
+            let options = bolt::runtime::checkpoint::CheckpointOptions {
+                leave_running,
+                tcp_established,
+            };
+            runtime
+                .checkpoint_container(&container, &dir, options)
+                .await?;
+            info!("Restore with: bolt restore {}", dir.display());
+        }
+
+        Commands::Restore { dir, name } => {
+            runtime.restore_container(&dir, name.as_deref()).await?;
+        }
+
         Commands::Surge { command } => match command {
             SurgeCommands::Up {
                 services,
                 detach,
                 force_recreate,
+                pull,
+                on_failure,
+                summary,
+                save_profile,
+                ..
             } => {
                 info!("Starting surge orchestration...");
-                runtime.surge_up(&services, detach, force_recreate).await?;
+                let options = surge::UpOptions {
+                    detach,
+                    force_recreate,
+                    pull,
+                    on_failure,
+                };
+                let outcome = runtime.surge_up_with(&services, &options).await?;
+                match summary.as_deref() {
+                    Some(path) if path == std::path::Path::new("-") => {
+                        println!("{}", serde_json::to_string_pretty(&outcome)?);
+                    }
+                    Some(path) => {
+                        std::fs::write(path, serde_json::to_vec_pretty(&outcome)?)?;
+                        outcome.print();
+                    }
+                    None => outcome.print(),
+                }
+                if outcome.exit_code != 0 {
+                    std::process::exit(outcome.exit_code);
+                }
+                if let Some(ref profile) = save_profile {
+                    let config = runtime.config();
+                    let path = bolt::config::overrides::save_profile(
+                        &bolt::config::template::resolve_path(&config.boltfile_path),
+                        profile,
+                        &config.overrides,
+                    )?;
+                    println!(
+                        "💾 Saved {} override(s) to profile '{}' in {}",
+                        config.overrides.len(),
+                        profile,
+                        path.display()
+                    );
+                }
+            }
+
+            SurgeCommands::Plan {
+                services,
+                resources,
+                json,
+                ..
+            } => {
+                if resources {
+                    let feasibility = runtime.surge_resources(&services).await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&feasibility)?);
+                    } else {
+                        feasibility.print();
+                    }
+                    return Ok(());
+                }
+                let plans = runtime.surge_plan(&services).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&plans)?);
+                    return Ok(());
+                }
+                for project_override in runtime
+                    .config()
+                    .active_overrides()?
+                    .iter()
+                    .filter(|o| !o.path.starts_with("services."))
+                {
+                    println!("✏️  overridden: {}", project_override);
+                }
+                println!("{:<20} {:<10} {:<40} DETAILS", "SERVICE", "ACTION", "IMAGE");
+                for plan in plans {
+                    let details = match plan.action {
+                        surge::plan::PlanAction::Recreate(ref reason)
+                        | surge::plan::PlanAction::Update(ref reason) => reason.clone(),
+                        _ => plan
+                            .current_digest
+                            .as_deref()
+                            .map(surge::plan::short_digest)
+                            .unwrap_or("")
+                            .to_string(),
+                    };
+                    println!(
+                        "{:<20} {:<10} {:<40} {}",
+                        plan.service,
+                        plan.action.as_str(),
+                        plan.image.as_deref().unwrap_or("-"),
+                        details
+                    );
+                    for violation in &plan.trust {
+                        println!("  ⛔ untrusted: {}", violation);
+                    }
+                    for conflict in &plan.ports {
+                        println!("  🔀 port: {}", conflict);
+                    }
+                    for setting in &plan.overridden {
+                        println!("  ✏️  overridden: {}", setting);
+                    }
+                }
+                println!();
+                runtime.surge_resources(&services).await?.print();
+            }
+
+            SurgeCommands::Dev {
+                services,
+                interval,
+                no_start,
+            } => {
+                runtime
+                    .surge_dev(
+                        &services,
+                        std::time::Duration::from_millis(interval.max(50)),
+                        !no_start,
+                    )
+                    .await?;
             }
 
-            SurgeCommands::Down { services, volumes } => {
+            SurgeCommands::Down {
+                services,
+                volumes,
+                remove_orphans,
+                json,
+            } => {
                 info!("Stopping surge services...");
-                runtime.surge_down(&services, volumes).await?;
+                let options = surge::teardown::DownOptions {
+                    remove_volumes: volumes,
+                    remove_orphans,
+                };
+                let summary = runtime.surge_down_with(&services, &options).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                } else {
+                    summary.print();
+                }
             }
 
             SurgeCommands::Status => {
@@ -185,6 +1158,14 @@ async fn main() -> Result<()> {
                         service.name, service.status, service.replicas
                     );
                 }
+                if let Some(dirty) = status.dirty {
+                    println!();
+                    println!(
+                        "⚠️  Boltfile changed since the services were started (noticed {})",
+                        dirty.since.format("%Y-%m-%d %H:%M:%S")
+                    );
+                    println!("   `bolt surge plan` shows what `bolt surge up` would change");
+                }
             }
 
             SurgeCommands::Logs {
@@ -198,28 +1179,532 @@ async fn main() -> Result<()> {
             SurgeCommands::Scale { services } => {
                 surge::scale(&bolt_config, &services).await?;
             }
-        },
 
-        Commands::Gaming { command } => match command {
-            GamingCommands::Gpu { command } => {
-                let gaming_command = match command {
-                    cli::GpuCommands::List => gaming::GpuCommands::List,
-                    cli::GpuCommands::Nvidia {
-                        device,
-                        dlss,
-                        raytracing,
-                    } => gaming::GpuCommands::Nvidia {
-                        device,
-                        dlss,
-                        raytracing,
-                    },
-                    cli::GpuCommands::Amd { device } => gaming::GpuCommands::Amd { device },
-                    cli::GpuCommands::Nvbind {
-                        devices,
-                        driver,
-                        performance,
-                        wsl2,
-                    } => {
+            SurgeCommands::Idle { command } => match command {
+                IdleCommands::Status { json, prometheus } => {
+                    let states = surge::idle_states(&bolt_config)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&states)?);
+                    } else if prometheus {
+                        print!("{}", bolt::runtime::idle::prometheus(&states));
+                    } else if states.is_empty() {
+                        println!("No services with idle settings are running");
+                    } else {
+                        println!(
+                            "{:<28} {:<14} {:<10} {:>6} {:>6} {:>12}",
+                            "CONTAINER", "ACTION", "STATE", "SLEPT", "WOKEN", "SUSPENDED"
+                        );
+                        for state in &states {
+                            let status = match state.status {
+                                bolt::runtime::idle::IdleStatus::Active => "active",
+                                bolt::runtime::idle::IdleStatus::Suspended => "suspended",
+                            };
+                            let total = state.suspended_total();
+                            println!(
+                                "{:<28} {:<14} {:<10} {:>6} {:>6} {:>12}",
+                                state.container,
+                                state.policy.action.as_str(),
+                                if state.watching() { status } else { "unwatched" },
+                                state.suspensions,
+                                state.wakes,
+                                format!("{}h{:02}m", total / 3600, total % 3600 / 60)
+                            );
+                        }
+                    }
+                }
+                IdleCommands::Wake { service } => {
+                    surge::wake(&bolt_config, &service)?;
+                }
+            },
+
+            SurgeCommands::Desktop { command } => match command {
+                DesktopCommands::Status { json } => {
+                    let sessions = surge::desktop_sessions(&bolt_config)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&sessions)?);
+                    } else if sessions.is_empty() {
+                        println!("No services with desktop integration are running");
+                    } else {
+                        println!(
+                            "{:<28} {:<9} {:<13} {:>7} {:>7} {:>7}  TRANSFER",
+                            "CONTAINER", "SESSION", "CLIPBOARD", "TO HOST", "TO APP", "REFUSED"
+                        );
+                        for session in &sessions {
+                            println!(
+                                "{:<28} {:<9} {:<13} {:>7} {:>7} {:>7}  {}",
+                                session.container,
+                                if session.running() { "running" } else { "stopped" },
+                                if session.policy.clipboard {
+                                    session.policy.direction.as_str()
+                                } else {
+                                    "off"
+                                },
+                                session.copied_to_host,
+                                session.copied_to_container,
+                                session.refused,
+                                session.transfer_dir.display()
+                            );
+                        }
+                    }
+                }
+                DesktopCommands::Send { service, files } => {
+                    for path in surge::desktop_send(&bolt_config, &service, &files)? {
+                        println!("📂 {}", path.display());
+                    }
+                }
+            },
+
+            SurgeCommands::Hooks { command } => match command {
+                HooksCommands::Status { json, prometheus } => {
+                    let states = surge::log_hook_states(&bolt_config)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&states)?);
+                    } else if prometheus {
+                        print!("{}", bolt::runtime::log_hooks::prometheus(&states));
+                    } else if states.is_empty() {
+                        println!("No services with log hooks are running");
+                    } else {
+                        for state in &states {
+                            let gauges: Vec<String> = state
+                                .gauges
+                                .iter()
+                                .map(|(gauge, value)| format!("{}={}", gauge, value))
+                                .collect();
+                            println!(
+                                "{} ({})",
+                                state.container,
+                                if state.watching() { "watching" } else { "unwatched" }
+                            );
+                            println!("  gauges: {}", gauges.join(" "));
+                            for (event, count) in &state.events {
+                                println!("  {}: {} line(s)", event, count);
+                            }
+                            for (name, automation) in &state.automations {
+                                let last = automation
+                                    .last_run
+                                    .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                                    .unwrap_or_else(|| "never".to_string());
+                                println!(
+                                    "  ⚙️  {}: {} run(s), last {}{}",
+                                    name,
+                                    automation.runs,
+                                    last,
+                                    automation
+                                        .last_error
+                                        .as_ref()
+                                        .map(|e| format!(" (failed: {})", e))
+                                        .unwrap_or_default()
+                                );
+                            }
+                        }
+                    }
+                }
+                HooksCommands::Events { service, json } => {
+                    let events = surge::log_hook_events(&bolt_config, &service)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&events)?);
+                    } else if events.is_empty() {
+                        println!("No log hook events recorded for {}", service);
+                    } else {
+                        for event in &events {
+                            let mut fields: Vec<String> = event
+                                .attributes
+                                .iter()
+                                .filter(|(key, _)| *key != "source" && *key != "trace_id")
+                                .map(|(key, value)| format!("{}={}", key, value))
+                                .collect();
+                            fields.sort();
+                            println!(
+                                "{}  {:<24} {:<16} {}",
+                                event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                                event.container,
+                                event.action,
+                                fields.join(" ")
+                            );
+                        }
+                    }
+                }
+            },
+
+            SurgeCommands::Slo { json, prometheus } => {
+                let reports = surge::slo_reports(&bolt_config)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&reports)?);
+                } else if prometheus {
+                    print!("{}", bolt::monitoring::slo::prometheus(&reports));
+                } else if reports.is_empty() {
+                    println!("No services with an slo are running");
+                } else {
+                    let ms = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("≤{}ms", v));
+                    for report in &reports {
+                        let summary = &report.summary;
+                        println!(
+                            "{} ({}): {} probe(s), {} failed, p50 {} p90 {} p99 {}",
+                            report.container,
+                            if report.watching { "probing" } else { "not probing" },
+                            summary.probes,
+                            summary.failures,
+                            ms(summary.p50_ms),
+                            ms(summary.p90_ms),
+                            ms(summary.p99_ms)
+                        );
+                        for objective in &report.objectives {
+                            let burning = objective.burning(report.spec.burn_rate);
+                            println!(
+                                "  {} {:<13} target {:>7.3}%  actual {:>8}  budget left {:>7.1}%  burn {:.1}x/1h {:.1}x/5m",
+                                if burning { "🔥" } else { "  " },
+                                objective.indicator.as_str(),
+                                objective.target,
+                                objective
+                                    .actual
+                                    .map_or("-".to_string(), |a| format!("{:.3}%", a)),
+                                objective.budget_remaining,
+                                objective.burn_rate_long,
+                                objective.burn_rate_short
+                            );
+                        }
+                    }
+                }
+            }
+        },
+
+        Commands::Gaming { command } => match command {
+            GamingCommands::Gpu { command } => {
+                let gaming_command = match command {
+                    cli::GpuCommands::Health { json } => {
+                        let report = gaming::recovery::health(&bolt_config).await?;
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&report)?);
+                        } else if report.is_empty() {
+                            println!("No NVIDIA or AMD GPUs found");
+                        } else {
+                            println!(
+                                "{:<14} {:<8} {:<12} {:>7}  LAST FAULT",
+                                "GPU", "VENDOR", "STATE", "FAULTS"
+                            );
+                            for gpu in &report {
+                                let state = if gpu.quarantined.is_some() {
+                                    "quarantined"
+                                } else if gpu.responding {
+                                    "ok"
+                                } else {
+                                    "unresponsive"
+                                };
+                                let vendor = match gpu.vendor {
+                                    gaming::recovery::GpuVendor::Nvidia => "nvidia",
+                                    gaming::recovery::GpuVendor::Amd => "amd",
+                                };
+                                println!(
+                                    "{:<14} {:<8} {:<12} {:>7}  {}",
+                                    gpu.pci_bus_id,
+                                    vendor,
+                                    state,
+                                    gpu.faults.len(),
+                                    gpu.faults
+                                        .last()
+                                        .map(|f| f.message.as_str())
+                                        .unwrap_or("-")
+                                );
+                            }
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Watch { interval } => {
+                        let interval = bolt::builds::cache::parse_duration(&interval)?;
+                        gaming::recovery::watch(&bolt_config, interval).await?;
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Reset { device, force } => {
+                        gaming::recovery::recover(&bolt_config, &device, force).await?;
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Unquarantine { device } => {
+                        if !gaming::recovery::unquarantine(&bolt_config.data_dir, &device)? {
+                            println!("GPU {} is not quarantined", device);
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Events { json } => {
+                        let events = gaming::recovery::read_events(&bolt_config.data_dir)?;
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&events)?);
+                        } else if events.is_empty() {
+                            println!("No GPU recovery events recorded");
+                        } else {
+                            println!(
+                                "{:<20} {:<14} {:<13} {:<20}  MESSAGE",
+                                "TIME", "GPU", "ACTION", "CONTAINER"
+                            );
+                            for event in &events {
+                                let time =
+                                    chrono::DateTime::from_timestamp(event.timestamp as i64, 0)
+                                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                                        .unwrap_or_default();
+                                println!(
+                                    "{:<20} {:<14} {:<13} {:<20}  {}",
+                                    time,
+                                    event.gpu,
+                                    event.action,
+                                    event.container.as_deref().unwrap_or("-"),
+                                    event.message
+                                );
+                            }
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Topology { json } => {
+                        let topology = gaming::topology::GpuTopology::detect().await?;
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&topology)?);
+                        } else if topology.gpus.is_empty() {
+                            println!("No NVIDIA or AMD GPUs found");
+                        } else {
+                            let runtime = bolt::runtime::detect_container_runtime().await?;
+                            let in_use = gaming::topology::in_use(&runtime).await;
+                            print!("{:<6}", "");
+                            for i in 0..topology.gpus.len() {
+                                print!(" {:<5}", format!("GPU{}", i));
+                            }
+                            println!(" {:<14} {:<5} USED BY", "PCI", "NUMA");
+                            for (i, gpu) in topology.gpus.iter().enumerate() {
+                                print!("{:<6}", format!("GPU{}", i));
+                                for link in &topology.links[i] {
+                                    print!(" {:<5}", link.label());
+                                }
+                                println!(
+                                    " {:<14} {:<5} {}",
+                                    gpu.pci_bus_id,
+                                    gpu.numa_node
+                                        .map(|n| n.to_string())
+                                        .unwrap_or_else(|| "-".to_string()),
+                                    in_use
+                                        .get(&gpu.pci_bus_id)
+                                        .map(|c| c.as_str())
+                                        .unwrap_or("-")
+                                );
+                            }
+                            println!();
+                            println!(
+                                "NV = NVLink, PXB = same PCIe switch, PHB = same host bridge, NODE = same NUMA node, SYS = across NUMA nodes"
+                            );
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::P2p { container, json } => {
+                        let mut reports = gaming::multi_gpu::reports(&bolt_config.data_dir);
+                        if let Some(ref container) = container {
+                            reports.retain(|r| &r.container == container);
+                        }
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&reports)?);
+                        } else if reports.is_empty() {
+                            println!("No containers use gpu.ai");
+                        }
+                        for report in reports.iter().filter(|_| !json) {
+                            println!(
+                                "🧠 {} - {} GPU(s): {}",
+                                report.container,
+                                report.gpus.len(),
+                                report.gpus.join(", ")
+                            );
+                            println!(
+                                "   Link: {}, P2P: {}, GPUDirect RDMA: {}",
+                                report.link.label(),
+                                if report.p2p { "on" } else { "off" },
+                                match (report.infiniband, report.peermem) {
+                                    (false, _) => "no InfiniBand",
+                                    (true, true) => "on",
+                                    (true, false) => "off (nvidia_peermem not loaded)",
+                                }
+                            );
+                            println!(
+                                "   IPC: {}, /dev/shm: {}",
+                                report.ipc,
+                                report.shm_size.as_deref().unwrap_or("host")
+                            );
+                            if let Some(ref matrix) = report.bandwidth_gbps {
+                                println!("   P2P bandwidth (GB/s):");
+                                for row in matrix {
+                                    let cells: Vec<String> =
+                                        row.iter().map(|bw| format!("{:>8.1}", bw)).collect();
+                                    println!("   {}", cells.join(""));
+                                }
+                            }
+                            for (key, value) in &report.env {
+                                println!("   {}={}", key, value);
+                            }
+                            for note in &report.notes {
+                                println!("   ⚠️  {}", note);
+                            }
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Refresh { dry_run, yes, json } => {
+                        use bolt::gaming::drivers;
+
+                        drivers::check_driver(&bolt_config).await?;
+                        let stale = drivers::stale_containers(&bolt_config.data_dir).await?;
+                        let plan = drivers::plan(&bolt_config.load_boltfile()?, stale);
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&plan)?);
+                        } else if plan.containers.is_empty() && plan.skipped.is_empty() {
+                            println!("✅ Every GPU container runs on the current driver");
+                        } else {
+                            for container in &plan.containers {
+                                println!("🔄 {:<28} {}", container.container, container.reason);
+                            }
+                            for (container, why) in &plan.skipped {
+                                println!(
+                                    "⏭️  {:<28} {}, skipped: {}",
+                                    container.container, container.reason, why
+                                );
+                            }
+                        }
+                        if dry_run || plan.services.is_empty() {
+                            return Ok(());
+                        }
+
+                        let confirmed = yes || {
+                            use std::io::Write;
+                            eprint!(
+                                "Recreate {}, dependencies first? [y/N] ",
+                                plan.services.join(", ")
+                            );
+                            std::io::stderr().flush()?;
+                            let mut line = String::new();
+                            std::io::stdin().read_line(&mut line)?;
+                            matches!(line.trim(), "y" | "Y" | "yes")
+                        };
+                        if confirmed {
+                            let refreshed = drivers::refresh(&bolt_config, &plan).await?;
+                            println!(
+                                "✅ Recreated {} on the current GPU driver",
+                                refreshed.join(", ")
+                            );
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::List { assignments, json } => {
+                        if !assignments {
+                            gaming::list_allocations(&bolt_config.data_dir, json).await?;
+                            return Ok(());
+                        } else {
+                            let assignments =
+                                gaming::priority::report(&bolt_config.data_dir).await?;
+                            if json {
+                                println!("{}", serde_json::to_string_pretty(&assignments)?);
+                            } else if assignments.is_empty() {
+                                println!("No NVIDIA or AMD GPUs found");
+                            } else {
+                                println!(
+                                    "GPU    PCI            VENDOR  CONTAINER                  PRIORITY     ENFORCED"
+                                );
+                                for (i, gpu) in assignments.iter().enumerate() {
+                                    let vendor = match gpu.vendor {
+                                        gaming::recovery::GpuVendor::Nvidia => "nvidia",
+                                        gaming::recovery::GpuVendor::Amd => "amd",
+                                    };
+                                    let name =
+                                        format!("GPU{}{}", i, if gpu.contended { "*" } else { "" });
+                                    if gpu.containers.is_empty() {
+                                        println!(
+                                            "{:<6} {:<14} {:<7} -",
+                                            name, gpu.pci_bus_id, vendor
+                                        );
+                                    }
+                                    for (n, container) in gpu.containers.iter().enumerate() {
+                                        let (name, pci, vendor) = if n == 0 {
+                                            (name.as_str(), gpu.pci_bus_id.as_str(), vendor)
+                                        } else {
+                                            ("", "", "")
+                                        };
+                                        println!(
+                                            "{:<6} {:<14} {:<7} {:<26} {:<12} {}",
+                                            name,
+                                            pci,
+                                            vendor,
+                                            container.container,
+                                            container.priority.as_str(),
+                                            if container.enforced.is_empty() {
+                                                "-".to_string()
+                                            } else {
+                                                container.enforced.join(", ")
+                                            }
+                                        );
+                                    }
+                                }
+                                if assignments.iter().any(|g| g.contended) {
+                                    println!();
+                                    println!(
+                                        "* contended: interactive containers take precedence over batch ones"
+                                    );
+                                }
+                                if let Some(state) = gaming::priority::state(&bolt_config.data_dir)
+                                    .filter(|s| s.running())
+                                {
+                                    for error in &state.errors {
+                                        println!("⚠️  {}", error);
+                                    }
+                                }
+                            }
+                            return Ok(());
+                        }
+                    }
+                    cli::GpuCommands::Slices { json } => {
+                        let slices = gaming::fraction::slices(&bolt_config.data_dir);
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&slices)?);
+                        } else if slices.is_empty() {
+                            println!("No fractional GPU slices");
+                        } else {
+                            println!(
+                                "CONTAINER                  GPU            MECHANISM   SHARE  MEMORY (MiB)     UTIL"
+                            );
+                            for slice in &slices {
+                                let usage = slice.usage.as_ref();
+                                let memory = format!(
+                                    "{}/{}",
+                                    usage.map_or("-".to_string(), |u| u.memory_used_mb.to_string()),
+                                    slice
+                                        .memory_limit_mb
+                                        .map_or("-".to_string(), |m| m.to_string())
+                                );
+                                println!(
+                                    "{:<26} {:<14} {:<11} {:<6} {:<16} {}{}",
+                                    slice.container,
+                                    slice.gpu.pci_bus_id,
+                                    slice.mechanism.name(),
+                                    format!("{:.0}%", slice.fraction * 100.0),
+                                    memory,
+                                    usage
+                                        .and_then(|u| u.utilization_percent)
+                                        .map_or("-".to_string(), |u| format!("{:.0}%", u)),
+                                    if slice.over_memory() {
+                                        "  ⚠️ over memory"
+                                    } else {
+                                        ""
+                                    }
+                                );
+                            }
+                        }
+                        return Ok(());
+                    }
+                    cli::GpuCommands::Nvidia {
+                        device,
+                        dlss,
+                        raytracing,
+                    } => gaming::GpuCommands::Nvidia {
+                        device,
+                        dlss,
+                        raytracing,
+                    },
+                    cli::GpuCommands::Amd { device } => gaming::GpuCommands::Amd { device },
+                    cli::GpuCommands::Nvbind {
+                        devices,
+                        driver,
+                        performance,
+                        wsl2,
+                    } => {
                         info!("nvbind GPU configuration:");
                         info!("  • Devices: {:?}", devices);
                         info!("  • Driver: {}", driver);
@@ -231,16 +1716,73 @@ async fn main() -> Result<()> {
                         info!("Checking nvbind runtime compatibility...");
                         gaming::GpuCommands::List // For now, just list GPUs
                     }
-                    cli::GpuCommands::Benchmark => {
-                        info!("Running GPU runtime performance comparison...");
-                        gaming::GpuCommands::List // For now, just list GPUs
+                    cli::GpuCommands::Benchmark {
+                        workload,
+                        duration,
+                        image,
+                        json,
+                    } => {
+                        use gaming::benchmark::{self, BenchmarkOptions};
+
+                        let mut options = BenchmarkOptions {
+                            workload: workload.parse()?,
+                            duration_secs: duration.max(1),
+                            ..Default::default()
+                        };
+                        if let Some(image) = image {
+                            options.image = image;
+                        }
+                        let report = benchmark::run(&options).await?;
+                        if json {
+                            println!("{}", serde_json::to_string_pretty(&report)?);
+                            return Ok(());
+                        }
+                        println!(
+                            "{} benchmark on {} via {}, {}s per scene",
+                            report.workload.as_str(),
+                            report.gpus.join(", "),
+                            report.container_runtime,
+                            report.duration_secs
+                        );
+                        println!();
+                        println!(
+                            "RUNTIME    FPS       FRAME TIME  WORST      STARTUP    GPU UTIL  SCORE"
+                        );
+                        for run in &report.runs {
+                            if let Some(reason) = &run.skipped {
+                                println!("{:<10} skipped: {}", run.runtime, reason);
+                                continue;
+                            }
+                            println!(
+                                "{:<10} {:<9.1} {:<11} {:<10} {:<10} {:<9} {}",
+                                run.runtime,
+                                run.mean_fps,
+                                format!("{:.3} ms", run.mean_frame_time_ms),
+                                format!("{:.3} ms", run.worst_frame_time_ms),
+                                format!("{:.0} ms", run.startup_ms),
+                                run.gpu_utilization_percent
+                                    .map_or("-".to_string(), |u| format!("{:.0}%", u)),
+                                run.score.map_or("-".to_string(), |s| s.to_string())
+                            );
+                        }
+                        if let Some(comparison) = report.comparison() {
+                            println!();
+                            println!(
+                                "nvbind vs standard: {:+.1}% FPS, {:+.1}% frame time, {:+.0} ms startup",
+                                comparison.fps_percent,
+                                comparison.frame_time_percent,
+                                comparison.startup_ms
+                            );
+                        }
+                        return Ok(());
                     }
                 };
                 gaming::handle_gpu_command(gaming_command).await?;
             }
 
             GamingCommands::Wine { proton, winver } => {
-                gaming::setup_wine(proton.as_deref(), winver.as_deref()).await?;
+                gaming::setup_wine(&bolt_config.data_dir, proton.as_deref(), winver.as_deref())
+                    .await?;
             }
 
             GamingCommands::Audio { system } => {
@@ -256,17 +1798,216 @@ async fn main() -> Result<()> {
                 info!("Wayland gaming session started: {}", session_id);
             }
 
+            GamingCommands::Display { json } => {
+                use gaming::display::{HostDisplay, Passthrough};
+
+                let host = HostDisplay::from_env();
+                let passthrough = Passthrough::resolve(&host, Default::default()).ok();
+                let sessions = gaming::display::gamescope_sessions(&bolt_config.data_dir);
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&serde_json::json!({
+                            "host": host,
+                            "passthrough": passthrough,
+                            "gamescope": sessions,
+                        }))?
+                    );
+                    return Ok(());
+                }
+                let passthrough = passthrough.unwrap_or_default();
+                match passthrough.wayland {
+                    Some(ref socket) => println!("Wayland:    {}", socket.display()),
+                    None => println!("Wayland:    none"),
+                }
+                match passthrough.x11 {
+                    Some((number, ref socket)) => {
+                        println!("X11:        :{} ({})", number, socket.display())
+                    }
+                    None => println!("X11:        none"),
+                }
+                if let Some(ref xauthority) = host.xauthority {
+                    println!("Xauthority: {}", xauthority.display());
+                }
+                if !sessions.is_empty() {
+                    println!();
+                    println!("CONTAINER                  PID      DISPLAY  WAYLAND        STATUS");
+                    for session in &sessions {
+                        println!(
+                            "{:<26} {:<8} {:<8} {:<14} {}",
+                            session.container,
+                            session.pid,
+                            session.display.as_deref().unwrap_or("-"),
+                            session.wayland_display.as_deref().unwrap_or("-"),
+                            if session.running() { "running" } else { "exited" }
+                        );
+                    }
+                }
+            }
+
             GamingCommands::Realtime { enable } => {
                 gaming::apply_realtime_optimizations(enable).await?;
             }
 
-            GamingCommands::Optimize { pid } => {
-                gaming::optimize_game_process(pid).await?;
+            GamingCommands::Optimize { pid, hybrid_aware } => {
+                gaming::optimize_game_process(pid, hybrid_aware).await?;
             }
 
             GamingCommands::Performance => {
                 gaming::get_gaming_performance_report().await?;
             }
+
+            GamingCommands::Thermal { command } => match command {
+                ThermalCommands::Watch { services } => {
+                    gaming::thermal::watch(&bolt_config, &services).await?;
+                }
+                ThermalCommands::Events { service, json } => {
+                    let events =
+                        gaming::thermal::read_events(&bolt_config.data_dir, service.as_deref())?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&events)?);
+                    } else if events.is_empty() {
+                        println!("No thermal events recorded");
+                    } else {
+                        println!(
+                            "{:<20} {:<20} {:<10} {:<14} {:>6} {:>6}  REASON",
+                            "TIME", "CONTAINER", "GPU", "ACTION", "TEMP", "POWER"
+                        );
+                        for event in &events {
+                            let time = chrono::DateTime::from_timestamp(event.timestamp as i64, 0)
+                                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                                .unwrap_or_default();
+                            println!(
+                                "{:<20} {:<20} {:<10} {:<14} {:>5.0}C {:>5.0}W  {}",
+                                time,
+                                event.container,
+                                event.gpu,
+                                event.action,
+                                event.temperature_celsius,
+                                event.power_watts,
+                                event.reason
+                            );
+                        }
+                    }
+                }
+            },
+            GamingCommands::Prefix { command } => match command {
+                PrefixCommands::Sync {
+                    service,
+                    prefer,
+                    json,
+                } => {
+                    let reports =
+                        gaming::prefix::sync(&bolt_config, service.as_deref(), prefer).await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&reports)?);
+                    }
+                }
+                PrefixCommands::List { json } => {
+                    let states = gaming::prefix::list(&bolt_config.data_dir);
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&states)?);
+                    } else if states.is_empty() {
+                        println!("No cached Wine prefixes");
+                    } else {
+                        println!(
+                            "{:<28} {:<36} {:<20} {:<10}  CONFLICTS",
+                            "CONTAINER", "PREFIX", "SYNCED", "SESSION"
+                        );
+                        for state in &states {
+                            let synced = state
+                                .synced_at
+                                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                                .unwrap_or_else(|| "never".to_string());
+                            let session = if state.watching() { "running" } else { "-" };
+                            println!(
+                                "{:<28} {:<36} {:<20} {:<10}  {}",
+                                state.container,
+                                state.remote.display(),
+                                synced,
+                                session,
+                                state.conflicts.len()
+                            );
+                            if let Some(ref error) = state.error {
+                                println!("  ⚠️  {}", error);
+                            }
+                            for path in &state.conflicts {
+                                println!("  ⚡ {}", path);
+                            }
+                        }
+                    }
+                }
+            },
+            GamingCommands::Proton { command } => match command {
+                ProtonCommands::List {
+                    available,
+                    flavor,
+                    json,
+                } if available => {
+                    let releases = gaming::proton::available(&bolt_config.data_dir, flavor).await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&releases)?);
+                    } else {
+                        println!(
+                            "{:<28} {:<12} {:>10}  INSTALLED",
+                            "RELEASE", "PUBLISHED", "SIZE"
+                        );
+                        for release in &releases {
+                            let published = release
+                                .published_at
+                                .map(|t| t.format("%Y-%m-%d").to_string())
+                                .unwrap_or_else(|| "-".to_string());
+                            let tag = if release.prerelease {
+                                format!("{} (pre)", release.tag)
+                            } else {
+                                release.tag.clone()
+                            };
+                            println!(
+                                "{:<28} {:<12} {:>7.1} MB  {}",
+                                tag,
+                                published,
+                                release.size as f64 / 1_000_000.0,
+                                if release.installed { "yes" } else { "-" }
+                            );
+                        }
+                    }
+                }
+                ProtonCommands::List { json, .. } => {
+                    let tools = gaming::proton::installed(&bolt_config.data_dir);
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&tools)?);
+                    } else if tools.is_empty() {
+                        println!(
+                            "No Proton or Wine versions cached; see `bolt gaming proton install`"
+                        );
+                    } else {
+                        println!(
+                            "{:<10} {:<28} {:<20}  SHA-512",
+                            "FLAVOR", "VERSION", "INSTALLED"
+                        );
+                        for tool in &tools {
+                            println!(
+                                "{:<10} {:<28} {:<20}  {}",
+                                tool.flavor,
+                                tool.tag,
+                                tool.installed_at.format("%Y-%m-%d %H:%M:%S"),
+                                &tool.sha512[..16]
+                            );
+                        }
+                    }
+                }
+                ProtonCommands::Install { version } => {
+                    let spec = gaming::proton::Spec::parse(&version)?;
+                    let tool = gaming::proton::install(&bolt_config.data_dir, &spec).await?;
+                    println!("{}", tool.path.display());
+                }
+                ProtonCommands::Remove { version } => {
+                    let spec = gaming::proton::Spec::parse(&version)?;
+                    let runtime = bolt::runtime::detect_container_runtime().await.ok();
+                    gaming::proton::remove(&bolt_config.data_dir, &spec, runtime.as_deref())
+                        .await?;
+                }
+            },
         },
 
         Commands::Network { command } => match command {
@@ -299,11 +2040,9 @@ async fn main() -> Result<()> {
                 }
 
                 // Create network with enhanced implementation
-                let mut network_manager = bolt::networking::NetworkManager::new(
-                    bolt::networking::NetworkConfig::default(),
-                )
-                .await?;
-                network_manager
+                runtime
+                    .networks()
+                    .await?
                     .create_bolt_network(&name, &driver, subnet.as_deref())
                     .await?;
                 info!("✅ Network '{}' created successfully", name);
@@ -320,11 +2059,7 @@ async fn main() -> Result<()> {
                 println!("{}", "─".repeat(90));
 
                 // Get actual network data with enhanced features
-                let network_manager = bolt::networking::NetworkManager::new(
-                    bolt::networking::NetworkConfig::default(),
-                )
-                .await?;
-                let networks = network_manager.list_bolt_networks().await?;
+                let networks = runtime.networks().await?.list_bolt_networks().await?;
 
                 for network in &networks {
                     println!(
@@ -374,6 +2109,174 @@ async fn main() -> Result<()> {
                 network::remove_network(&name).await?;
                 info!("✅ Network '{}' removed successfully", name);
             }
+
+            NetworkCommands::Egress { container, json } => {
+                let report = runtime.egress_report(&container).await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    println!(
+                        "Egress policy for {} ({}{})",
+                        report.container,
+                        report.mode.as_str(),
+                        if report.syncing { ", resolving domains" } else { "" }
+                    );
+                    for network in &report.networks {
+                        println!("  allow   {}", network);
+                    }
+                    for (domain, addrs) in &report.resolved {
+                        let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                        println!("  allow   {} ({})", domain, addrs.join(", "));
+                    }
+                    if report.attempts.is_empty() {
+                        println!("No connections outside the allowlist");
+                    } else {
+                        let verb = match report.mode {
+                            bolt::networking::egress::EgressMode::Enforce => "Blocked",
+                            bolt::networking::egress::EgressMode::Audit => "Would block",
+                        };
+                        println!("{}:", verb);
+                        for attempt in &report.attempts {
+                            println!(
+                                "  {:<40} {}/{}",
+                                attempt.address, attempt.protocol, attempt.port
+                            );
+                        }
+                        if report.mode == bolt::networking::egress::EgressMode::Audit {
+                            println!(
+                                "Add the destinations the service needs to egress.allow, then set mode = \"enforce\""
+                            );
+                        }
+                    }
+                }
+            }
+            NetworkCommands::Nat { json } => {
+                let report = runtime.nat_report().await;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    let probe = &report.probe;
+                    let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+                    println!(
+                        "Gateway:         {}{}",
+                        show(probe.gateway.map(|g| g.to_string())),
+                        probe
+                            .protocol
+                            .map(|p| format!(" ({})", p.as_str()))
+                            .unwrap_or_default()
+                    );
+                    println!(
+                        "This host:       {}",
+                        show(probe.local_address.map(|a| a.to_string()))
+                    );
+                    println!(
+                        "Router address:  {}",
+                        show(probe.router_address.map(|a| a.to_string()))
+                    );
+                    println!(
+                        "Public address:  {}",
+                        show(probe.public_address.map(|a| a.to_string()))
+                    );
+                    println!("NAT:             {}", probe.nat.as_str());
+                    for error in &probe.errors {
+                        println!("  ⚠️  {}", error);
+                    }
+                    if probe.nat == bolt::networking::nat::NatKind::Cgnat {
+                        println!(
+                            "Router mappings can't be reached from outside; set [nat] relay to publish through a relay"
+                        );
+                    }
+
+                    if !report.containers.is_empty() {
+                        println!();
+                        println!(
+                            "CONTAINER                    PORT         REACHABLE AT                 VIA      EXPIRES"
+                        );
+                    }
+                    for state in &report.containers {
+                        if !state.watching() {
+                            println!("{:<28} not forwarding (watcher stopped)", state.container);
+                            continue;
+                        }
+                        for mapping in &state.mappings {
+                            println!(
+                                "{:<28} {:<12} {:<28} {:<8} {}",
+                                state.container,
+                                mapping.port.to_string(),
+                                mapping.public.to_string(),
+                                mapping.via.as_str(),
+                                mapping
+                                    .expires_at
+                                    .map(|at| at.format("%H:%M:%S").to_string())
+                                    .unwrap_or_else(|| "-".to_string())
+                            );
+                        }
+                        if let Some(ref error) = state.error {
+                            println!("{:<28} ⚠️  {}", state.container, error);
+                        } else if state.mappings.is_empty() {
+                            println!("{:<28} mapping...", state.container);
+                        }
+                    }
+                }
+            }
+            NetworkCommands::Dns { sync, json } => {
+                let states = if sync {
+                    runtime.sync_dns().await?
+                } else {
+                    runtime.dns_records()
+                };
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&states)?);
+                } else if states.is_empty() {
+                    println!("No external DNS records");
+                } else {
+                    println!(
+                        "CONTAINER                    NAME                                PROVIDER     ADDRESSES"
+                    );
+                    for state in &states {
+                        for record in &state.records {
+                            let addresses: Vec<String> =
+                                record.addresses.iter().map(|a| a.to_string()).collect();
+                            println!(
+                                "{:<28} {:<35} {:<12} {}",
+                                state.container,
+                                record.name,
+                                record.provider,
+                                if addresses.is_empty() {
+                                    "-".to_string()
+                                } else {
+                                    addresses.join(", ")
+                                }
+                            );
+                            if let Some(ref error) = record.error {
+                                println!("  ⚠️  {}", error);
+                            }
+                        }
+                    }
+                }
+            }
+            NetworkCommands::Topo {
+                format,
+                live,
+                listen,
+            } => {
+                if live {
+                    runtime.serve_network_topology(listen).await?;
+                } else {
+                    let format: bolt::networking::topology::Format = format.parse()?;
+                    let topology = runtime.network_topology().await?;
+                    print!("{}", bolt::networking::topology::render(&topology, format)?);
+                }
+            }
+            #[cfg(feature = "quic-networking")]
+            NetworkCommands::Relay => runtime.serve_relay().await?,
+            #[cfg(not(feature = "quic-networking"))]
+            NetworkCommands::Relay => {
+                return Err(anyhow::anyhow!(
+                    "The relay requires the quic-networking feature"
+                ));
+            }
         },
 
         Commands::Volume { command } => match command {
@@ -383,6 +2286,7 @@ async fn main() -> Result<()> {
                 size,
                 opt,
             } => {
+                let name = runtime.scoped_name(&name);
                 info!("Creating volume: {} (driver: {})", name, driver);
                 if let Some(ref size_str) = size {
                     info!("  Size: {}", size_str);
@@ -390,8 +2294,6 @@ async fn main() -> Result<()> {
                 if !opt.is_empty() {
                     info!("  Options: {:?}", opt);
                 }
-                // Create volume with real implementation
-                let mut volume_manager = bolt::volume::VolumeManager::new()?;
                 let options = bolt::volume::VolumeCreateOptions {
                     driver: "local".to_string(),
                     size: size.clone(),
@@ -408,15 +2310,13 @@ async fn main() -> Result<()> {
                         })
                         .collect(),
                 };
-                volume_manager.create_volume(&name, options)?;
+                runtime.create_volume(&name, options).await?;
                 info!("✅ Volume '{}' created successfully", name);
             }
 
             VolumeCommands::List => {
                 info!("📋 Listing volumes...");
-                // List volumes with real implementation
-                let volume_manager = bolt::volume::VolumeManager::new()?;
-                let volumes = volume_manager.list_volumes();
+                let volumes = runtime.list_volumes().await?;
 
                 println!("VOLUME NAME    DRIVER    SIZE      CREATED");
                 println!("─────────────────────────────────────────────");
@@ -430,18 +2330,16 @@ async fn main() -> Result<()> {
             }
 
             VolumeCommands::Remove { name, force } => {
+                let name = runtime.scoped_name(&name);
                 info!("Removing volume: {} (force: {})", name, force);
-                // Remove volume with real implementation
-                let mut volume_manager = bolt::volume::VolumeManager::new()?;
-                volume_manager.remove_volume(&name, force)?;
+                runtime.remove_volume(&name, force).await?;
                 info!("✅ Volume '{}' removed successfully", name);
             }
 
             VolumeCommands::Inspect { name } => {
+                let name = runtime.scoped_name(&name);
                 info!("Inspecting volume: {}", name);
-                // Inspect volume with real implementation
-                let volume_manager = bolt::volume::VolumeManager::new()?;
-                let volume = volume_manager.inspect_volume(&name)?;
+                let volume = runtime.inspect_volume(&name).await?;
 
                 println!("Volume details for '{}':", name);
                 println!("  Driver: {}", volume.driver);
@@ -463,15 +2361,77 @@ async fn main() -> Result<()> {
 
             VolumeCommands::Prune { force } => {
                 info!("Pruning unused volumes (force: {})", force);
-                // Prune volumes with real implementation
-                let mut volume_manager = bolt::volume::VolumeManager::new()?;
-                let removed_volumes = volume_manager.prune_volumes(force)?;
+                let removed_volumes = runtime.prune_volumes(force).await?;
                 info!(
                     "✅ Pruned {} unused volumes: {:?}",
                     removed_volumes.len(),
                     removed_volumes
                 );
             }
+
+            VolumeCommands::Cache { json } => {
+                let states = runtime.volume_cache_states();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&states)?);
+                } else if states.is_empty() {
+                    println!(
+                        "No volumes are tuned; set cache_profile or hot_paths under [volumes.<name>]"
+                    );
+                } else {
+                    println!(
+                        "CONTAINER                    VOLUME           PROFILE    READ-AHEAD  HOT PATH                 RESIDENT"
+                    );
+                    for state in &states {
+                        if !state.watching() {
+                            println!("{:<28} not tuned (tuner stopped)", state.container);
+                            continue;
+                        }
+                        for volume in &state.volumes {
+                            let read_ahead =
+                                bolt::runtime::cgroups::block_device(&volume.mountpoint)
+                                    .and_then(|(major, minor)| {
+                                        let device = format!("{}:{}", major, minor);
+                                        state.devices.iter().find(|d| d.device == device)
+                                    })
+                                    .map(|d| format!("{} KB", d.read_ahead_kb))
+                                    .unwrap_or_else(|| "-".to_string());
+                            let hot: Vec<_> = state
+                                .hot_paths
+                                .iter()
+                                .filter(|h| h.volume == volume.name)
+                                .collect();
+                            if hot.is_empty() {
+                                println!(
+                                    "{:<28} {:<16} {:<10} {:<11} -",
+                                    state.container,
+                                    volume.name,
+                                    volume.profile.as_str(),
+                                    read_ahead
+                                );
+                            }
+                            for path in hot {
+                                let percent = match path.bytes {
+                                    0 => 100.0,
+                                    bytes => path.resident_bytes as f64 / bytes as f64 * 100.0,
+                                };
+                                println!(
+                                    "{:<28} {:<16} {:<10} {:<11} {:<24} {:.0}% of {}",
+                                    state.container,
+                                    volume.name,
+                                    volume.profile.as_str(),
+                                    read_ahead,
+                                    path.path,
+                                    percent,
+                                    bolt::builds::cache::format_size(path.bytes)
+                                );
+                            }
+                        }
+                        if let Some(ref error) = state.error {
+                            println!("  ⚠️  {}", error);
+                        }
+                    }
+                }
+            }
         },
 
         Commands::Snapshot { command } => {
@@ -481,74 +2441,1482 @@ async fn main() -> Result<()> {
                     description,
                     snapshot_type,
                 } => {
-                    let snapshot_name = name.unwrap_or_else(|| {
-                        format!(
-                            "snapshot-{}",
-                            std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs()
-                        )
-                    });
-                    info!("Creating {} snapshot '{}'", snapshot_type, snapshot_name);
-                    if let Some(desc) = description {
-                        info!("Description: {}", desc);
-                    }
-                    // TODO: Implement snapshot creation
-                    info!("✅ Snapshot '{}' created successfully", snapshot_name);
+                    let kind: bolt::runtime::snapshots::SnapshotKind = snapshot_type.parse()?;
+                    let snapshot = runtime
+                        .create_snapshot(name.as_deref(), description.as_deref(), kind)
+                        .await?;
+                    println!("{}", snapshot.id);
                 }
                 cli::SnapshotCommands::List {
                     verbose,
                     filter_type,
                 } => {
-                    info!("Listing snapshots");
-                    if let Some(filter) = filter_type {
-                        info!("Filtering by type: {}", filter);
-                    }
-                    // TODO: Implement snapshot listing
-                    if verbose {
-                        println!("No snapshots found (verbose mode)");
-                    } else {
+                    let kind = filter_type.map(|t| t.parse()).transpose()?;
+                    let snapshots = runtime.list_snapshots(kind).await?;
+                    if snapshots.is_empty() {
                         println!("No snapshots found");
+                    } else {
+                        println!(
+                            "{:<10} {:<20} {:<13} {:<20} {:>10}",
+                            "ID", "NAME", "TYPE", "CREATED", "SIZE"
+                        );
+                        for snapshot in &snapshots {
+                            println!(
+                                "{:<10} {:<20} {:<13} {:<20} {:>10}",
+                                snapshot.id,
+                                snapshot.name.as_deref().unwrap_or("-"),
+                                snapshot.kind,
+                                snapshot
+                                    .created_at
+                                    .with_timezone(&chrono::Local)
+                                    .format("%Y-%m-%d %H:%M:%S"),
+                                snapshot
+                                    .size_bytes
+                                    .map(|b| format!("{:.1} MiB", b as f64 / 1048576.0))
+                                    .unwrap_or_else(|| "-".to_string())
+                            );
+                            if verbose {
+                                println!("           {}", snapshot.location);
+                                if let Some(ref description) = snapshot.description {
+                                    println!("           {}", description);
+                                }
+                            }
+                        }
                     }
                 }
                 cli::SnapshotCommands::Show { snapshot } => {
-                    info!("Showing snapshot details for: {}", snapshot);
-                    // TODO: Implement snapshot details
-                    println!("Snapshot '{}' not found", snapshot);
+                    let snapshot = runtime.snapshot(&snapshot).await?;
+                    println!("{}", serde_json::to_string_pretty(&snapshot)?);
                 }
                 cli::SnapshotCommands::Rollback { snapshot, force } => {
-                    info!("Rolling back to snapshot '{}' (force: {})", snapshot, force);
-                    // TODO: Implement snapshot rollback
-                    info!("✅ Rolled back to snapshot '{}' successfully", snapshot);
+                    let manager = runtime.snapshots().await?;
+                    let target = manager.find(&snapshot)?;
+                    if !force {
+                        let discarded = manager.rollback_discards(&target.id)?;
+                        if !discarded.is_empty() {
+                            eprintln!(
+                                "This destroys {} newer snapshot(s): {}",
+                                discarded.len(),
+                                discarded
+                                    .iter()
+                                    .map(|s| s.label())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            );
+                        }
+                        eprint!(
+                            "Roll {} back to snapshot {} from {}? [y/N] ",
+                            target.root.display(),
+                            target.label(),
+                            target.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                        );
+                        std::io::Write::flush(&mut std::io::stderr())?;
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        if !matches!(line.trim(), "y" | "Y" | "yes") {
+                            println!("Rollback cancelled");
+                            return Ok(());
+                        }
+                    }
+                    let outcome = runtime.rollback_snapshot(&target.id).await?;
+                    if let Some(ref pre_rollback) = outcome.pre_rollback {
+                        println!(
+                            "Previous state saved as snapshot {} (undo with: bolt snapshot rollback {})",
+                            pre_rollback.id, pre_rollback.id
+                        );
+                    }
+                    if outcome.reboot_required {
+                        println!("Reboot to finish rolling back {}", target.root.display());
+                    }
                 }
                 cli::SnapshotCommands::Delete { snapshot, force } => {
-                    info!("Deleting snapshot '{}' (force: {})", snapshot, force);
-                    // TODO: Implement snapshot deletion
-                    info!("✅ Snapshot '{}' deleted successfully", snapshot);
+                    let target = runtime.snapshot(&snapshot).await?;
+                    if !force {
+                        eprint!("Delete snapshot {}? [y/N] ", target.label());
+                        std::io::Write::flush(&mut std::io::stderr())?;
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        if !matches!(line.trim(), "y" | "Y" | "yes") {
+                            println!("Deletion cancelled");
+                            return Ok(());
+                        }
+                    }
+                    runtime.delete_snapshot(&target.id).await?;
+                }
+                cli::SnapshotCommands::Cleanup { dry_run, force } => {
+                    let planned = runtime.cleanup_snapshots(true).await?;
+                    if planned.is_empty() {
+                        println!("Nothing to clean up");
+                        return Ok(());
+                    }
+                    for snapshot in &planned {
+                        println!(
+                            "{} {} ({}, {})",
+                            if dry_run { "Would remove" } else { "Removing" },
+                            snapshot.label(),
+                            snapshot.kind,
+                            snapshot.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                        );
+                    }
+                    if dry_run {
+                        return Ok(());
+                    }
+                    if !force {
+                        eprint!("Remove {} snapshot(s)? [y/N] ", planned.len());
+                        std::io::Write::flush(&mut std::io::stderr())?;
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        if !matches!(line.trim(), "y" | "Y" | "yes") {
+                            println!("Cleanup cancelled");
+                            return Ok(());
+                        }
+                    }
+                    let removed = runtime.cleanup_snapshots(false).await?;
+                    info!("✅ Removed {} snapshot(s)", removed.len());
+                }
+                cli::SnapshotCommands::Config { verbose } => {
+                    let manager = runtime.snapshots().await?;
+                    let settings = manager.settings();
+                    if verbose {
+                        println!("{}", serde_json::to_string_pretty(settings)?);
+                    } else {
+                        println!("Enabled:    {}", settings.enabled);
+                        println!("Backend:    {}", settings.backend);
+                        println!("Root:       {}", settings.root.display());
+                        if let Some(ref store) = settings.store {
+                            println!("Stored in:  {}", store.display());
+                        }
+                        match settings.retention {
+                            Some(ref r) => println!(
+                                "Retention:  hourly {}, daily {}, weekly {}, monthly {}, yearly {}, max {}",
+                                r.keep_hourly.map_or("-".to_string(), |n| n.to_string()),
+                                r.keep_daily.map_or("-".to_string(), |n| n.to_string()),
+                                r.keep_weekly.map_or("-".to_string(), |n| n.to_string()),
+                                r.keep_monthly.map_or("-".to_string(), |n| n.to_string()),
+                                r.keep_yearly.map_or("-".to_string(), |n| n.to_string()),
+                                r.max_total.map_or("-".to_string(), |n| n.to_string())
+                            ),
+                            None => println!("Retention:  none (snapshots are kept until deleted)"),
+                        }
+                        println!(
+                            "Named:      {}",
+                            settings
+                                .named
+                                .iter()
+                                .map(|n| n.name.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                }
+                cli::SnapshotCommands::Auto { action } => match action {
+                    cli::AutoAction::Enable => {
+                        let state = runtime.set_auto_snapshots(true)?.expect("watcher state");
+                        info!(
+                            "✅ Automatic snapshots enabled (watcher pid {})",
+                            state.watcher_pid
+                        );
+                    }
+                    cli::AutoAction::Disable => {
+                        runtime.set_auto_snapshots(false)?;
+                        info!("✅ Automatic snapshots disabled");
+                    }
+                    cli::AutoAction::Status => match runtime.auto_snapshots() {
+                        Some(state) => println!(
+                            "enabled (watcher pid {}, since {})",
+                            state.watcher_pid,
+                            state.since.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                        ),
+                        None => println!("disabled"),
+                    },
+                },
+                cli::SnapshotCommands::Export { snapshot, output } => {
+                    let summary =
+                        runtime.export_snapshot(&snapshot, std::path::Path::new(&output))?;
+                    println!("{}", summary.path.display());
+                    println!("sha256:{}", summary.sha256);
+                }
+                cli::SnapshotCommands::Import { file, sha256 } => {
+                    let record =
+                        runtime.import_snapshot(std::path::Path::new(&file), sha256.as_deref())?;
+                    println!(
+                        "{} ({})",
+                        record.snapshot.id,
+                        record.snapshot.name.as_deref().unwrap_or(&record.capsule_name)
+                    );
+                }
+                #[cfg(feature = "quic-networking")]
+                cli::SnapshotCommands::Send {
+                    snapshot,
+                    to,
+                    fingerprint,
+                    retries,
+                } => {
+                    let summary = runtime
+                        .send_snapshot(&snapshot, &to, fingerprint.as_deref(), retries)
+                        .await?;
+                    if summary.already_present {
+                        info!("{} already has snapshot {}", to, summary.snapshot_id);
+                    }
                 }
-                cli::SnapshotCommands::Cleanup { .. } => {
-                    info!("Cleaning up old snapshots");
-                    // TODO: Implement snapshot cleanup
-                    info!("✅ Snapshot cleanup completed");
+                #[cfg(feature = "quic-networking")]
+                cli::SnapshotCommands::Receive {
+                    listen,
+                    allow_sender,
+                    max_size,
+                } => {
+                    let listen = listen
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("Invalid listen address {}: {}", listen, e))?;
+                    let max_size = bolt::builds::cache::parse_size(&max_size)?;
+                    runtime
+                        .receive_snapshots(listen, &allow_sender, max_size)
+                        .await?;
                 }
-                cli::SnapshotCommands::Config { .. } => {
-                    info!("Managing snapshot configuration");
-                    // TODO: Implement snapshot configuration
-                    info!("✅ Snapshot configuration updated");
+                #[cfg(feature = "quic-networking")]
+                cli::SnapshotCommands::Fingerprint => {
+                    println!("{}", runtime.snapshot_sender_fingerprint()?);
                 }
-                cli::SnapshotCommands::Auto { .. } => {
-                    info!("Managing automatic snapshots");
-                    // TODO: Implement automatic snapshots
-                    info!("✅ Automatic snapshot settings configured");
+                #[cfg(not(feature = "quic-networking"))]
+                cli::SnapshotCommands::Send { .. }
+                | cli::SnapshotCommands::Receive { .. }
+                | cli::SnapshotCommands::Fingerprint => {
+                    return Err(anyhow::anyhow!(
+                        "Snapshot shipping requires the quic-networking feature"
+                    ));
                 }
             }
         }
 
-        Commands::Compat { command } => {
-            compat::handle_compat_command(compat::CompatArgs { command }, runtime).await?;
+        Commands::Debug { command } => match command {
+            DebugCommands::Crashes { command } => match command {
+                CrashCommands::Ls { container } => {
+                    let reports = runtime.crash_reports(container.as_deref())?;
+                    if reports.is_empty() {
+                        info!("No crash dumps found");
+                        return Ok(());
+                    }
+
+                    println!(
+                        "{:<28} {:<25} {:<6} {:<5} {:<20}",
+                        "CRASH ID", "CONTAINER", "EXIT", "OOM", "COLLECTED"
+                    );
+                    for report in reports {
+                        println!(
+                            "{:<28} {:<25} {:<6} {:<5} {:<20}",
+                            report.id,
+                            report.container,
+                            report.exit_code,
+                            if report.oom_killed { "yes" } else { "no" },
+                            report.collected_at.format("%Y-%m-%d %H:%M:%S")
+                        );
+                    }
+                }
+
+                CrashCommands::Show { container } => match runtime.crash_report(&container)? {
+                    Some(report) => println!("{}", serde_json::to_string_pretty(&report)?),
+                    None => println!("No crash dumps found for '{}'", container),
+                },
+            },
+
+            DebugCommands::Shell {
+                container,
+                toolbox,
+                sidecar,
+                user,
+            } => {
+                let options = bolt::runtime::debug_shell::DebugShellOptions {
+                    toolbox,
+                    sidecar,
+                    user,
+                };
+                runtime.debug_shell(&container, &options).await?;
+            }
+        },
+
+        Commands::Info { capabilities, json } => {
+            let caps = runtime.capabilities().await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&caps)?);
+                return Ok(());
+            }
+
+            println!("Bolt {}", caps.version);
+            println!(
+                "  Container runtime: {}",
+                caps.container_runtime.as_deref().unwrap_or("none")
+            );
+            println!(
+                "  Kernel:            {}",
+                caps.kernel_version.as_deref().unwrap_or("unknown")
+            );
+            println!("  Data directory:    {}", runtime.config().data_dir.display());
+
+            if capabilities {
+                println!();
+                println!("{:<20} {:<10} {:<6} NOTES", "CAPABILITY", "COMPILED", "HOST");
+                for (name, cap) in &caps.capabilities {
+                    println!(
+                        "{:<20} {:<10} {:<6} {}",
+                        name,
+                        if cap.compiled { "yes" } else { "no" },
+                        if cap.host_supported { "yes" } else { "no" },
+                        cap.reason.as_deref().unwrap_or("")
+                    );
+                }
+            }
         }
-    }
 
-    Ok(())
+        Commands::Sandbox { command } => match command {
+            SandboxCommands::Report { container, json } => {
+                let report = runtime.sandbox_report(&container).await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                    return Ok(());
+                }
+
+                println!(
+                    "🛡️  {} (sandbox: {}{})",
+                    report.container,
+                    report.tier,
+                    if report.syscall_interception { ", gVisor" } else { "" }
+                );
+                if report.blocked_syscalls.is_empty() {
+                    println!("No blocked syscalls recorded");
+                } else {
+                    println!("{:<24} {:<20} {:>6}", "SYSCALL", "PROCESS", "COUNT");
+                    for blocked in &report.blocked_syscalls {
+                        println!(
+                            "{:<24} {:<20} {:>6}",
+                            blocked.syscall, blocked.process, blocked.count
+                        );
+                    }
+                }
+                if !report.log_findings.is_empty() {
+                    println!("\nFrom container logs:");
+                    for line in &report.log_findings {
+                        println!("  {}", line);
+                    }
+                }
+                for hint in &report.hints {
+                    println!("💡 {}", hint);
+                }
+            }
+            SandboxCommands::Profile => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&bolt::runtime::sandbox::seccomp_profile())?
+                );
+            }
+        },
+
+        Commands::Doctor {
+            json,
+            network,
+            apply,
+            setup_emulation,
+            yes,
+        } => {
+            if setup_emulation {
+                let missing = runtime.emulation_missing()?;
+                if missing.is_empty() {
+                    info!("🧩 Every Boltfile platform already runs on this host");
+                } else {
+                    let listed: Vec<String> = missing.iter().map(|p| p.to_string()).collect();
+                    let confirmed = yes || {
+                        use std::io::Write;
+                        eprint!(
+                            "Register binfmt handlers for {} with a privileged container? [y/N] ",
+                            listed.join(", ")
+                        );
+                        std::io::stderr().flush()?;
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        matches!(line.trim(), "y" | "Y" | "yes")
+                    };
+                    if confirmed {
+                        runtime.setup_emulation(&missing).await?;
+                    }
+                }
+            }
+
+            let mut report = runtime.doctor().await?;
+
+            if network || apply {
+                let mut tuning = runtime.network_tuning().await?;
+                let pending = tuning.iter().filter(|i| i.needs_change()).count();
+                if apply && pending > 0 {
+                    let confirmed = yes || {
+                        use std::io::Write;
+                        eprint!("Change {} network setting(s)? [y/N] ", pending);
+                        std::io::stderr().flush()?;
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line)?;
+                        matches!(line.trim(), "y" | "Y" | "yes")
+                    };
+                    if confirmed {
+                        runtime.apply_network_tuning(&mut tuning).await?;
+                    }
+                }
+                report
+                    .checks
+                    .extend(bolt::networking::tuning::checks(&tuning));
+            }
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                for check in &report.checks {
+                    println!(
+                        "{} {:<16} {:<24} {}",
+                        check.status.symbol(),
+                        check.area,
+                        check.name,
+                        check.detail
+                    );
+                    if let Some(ref hint) = check.hint {
+                        println!("   {:<16} {:<24} → {}", "", "", hint);
+                    }
+                }
+            }
+
+            if report.has_failures() {
+                return Err(anyhow::anyhow!("bolt doctor found failing checks"));
+            }
+        }
+
+        Commands::Bench {
+            only,
+            iterations,
+            image,
+            baseline,
+            save,
+            threshold,
+            json,
+        } => {
+            use bolt::runtime::bench::{self, BenchOptions, Suite};
+
+            let mut options = BenchOptions {
+                image,
+                iterations: iterations.max(1),
+                ..Default::default()
+            };
+            if !only.is_empty() {
+                options.suites = only
+                    .iter()
+                    .map(|s| s.parse::<Suite>())
+                    .collect::<anyhow::Result<_>>()?;
+            }
+
+            let scorecard = runtime.bench(&options).await?;
+            let previous = runtime.bench_baseline(&baseline)?;
+            let comparisons = bench::compare(&scorecard, previous.as_ref(), threshold);
+            let regressions = comparisons.iter().filter(|c| c.regressed).count();
+
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "scorecard": scorecard,
+                        "baseline": previous.as_ref().map(|_| &baseline),
+                        "comparisons": comparisons,
+                    }))?
+                );
+            } else {
+                println!(
+                    "🏁 {} · {} · bolt {} · kernel {}",
+                    scorecard.host, scorecard.runtime, scorecard.bolt_version, scorecard.kernel
+                );
+                match previous {
+                    Some(ref previous) => println!(
+                        "   compared with baseline '{}' (bolt {}, {})",
+                        baseline,
+                        previous.bolt_version,
+                        previous.taken_at.format("%Y-%m-%d %H:%M")
+                    ),
+                    None => println!(
+                        "   no baseline '{}' yet; save one with `bolt bench --save {}`",
+                        baseline, baseline
+                    ),
+                }
+                println!();
+                for comparison in &comparisons {
+                    let metric = &comparison.metric;
+                    let delta = match (comparison.baseline, comparison.worse_by_pct) {
+                        (Some(base), Some(worse)) => format!(
+                            "{:.1}% {} than {:.1}",
+                            worse.abs(),
+                            if worse > 0.0 { "worse" } else { "better" },
+                            base
+                        ),
+                        _ => String::new(),
+                    };
+                    println!(
+                        "{} {:<20} {:>10.1} {:<5} {}",
+                        if comparison.regressed {
+                            "⚠️ "
+                        } else {
+                            "✅"
+                        },
+                        metric.name,
+                        metric.value,
+                        metric.unit,
+                        delta
+                    );
+                }
+                for skipped in &scorecard.skipped {
+                    println!(
+                        "⏭️  {:<20} skipped: {}",
+                        skipped.suite.as_str(),
+                        skipped.reason
+                    );
+                }
+            }
+
+            if let Some(name) = save {
+                let path = runtime.save_bench_baseline(&name, &scorecard)?;
+                info!("💾 Saved baseline '{}' to {}", name, path.display());
+            }
+
+            if regressions > 0 {
+                return Err(anyhow::anyhow!(
+                    "bolt bench found {} regression(s) against baseline '{}'",
+                    regressions,
+                    baseline
+                ));
+            }
+        }
+
+        Commands::Health { json } => {
+            use bolt::runtime::health::HealthStatus;
+
+            let health = runtime.health().await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&health)?);
+            } else {
+                println!("{} node {}", health.status.symbol(), health.status);
+                for (name, component) in [
+                    ("daemon", &health.daemon),
+                    ("storage", &health.storage),
+                    ("network", &health.network),
+                ] {
+                    println!("{} {:<24} {}", component.status.symbol(), name, component.status);
+                    for reason in &component.reasons {
+                        println!("   {:<24} → {}", "", reason);
+                    }
+                }
+                for project in &health.projects {
+                    println!();
+                    println!("{} project {}", project.status.symbol(), project.project);
+                    for service in &project.services {
+                        println!(
+                            "{} {:<24} {}",
+                            service.status.symbol(),
+                            service.name,
+                            service.state
+                        );
+                        for reason in &service.reasons {
+                            println!("   {:<24} → {}", "", reason);
+                        }
+                    }
+                }
+            }
+
+            match health.status {
+                HealthStatus::Healthy => {}
+                HealthStatus::Degraded => std::process::exit(1),
+                HealthStatus::Unhealthy => std::process::exit(2),
+            }
+        }
+
+        Commands::Metrics { command } => match command {
+            MetricsCommands::Serve => {
+                let config = runtime.config();
+                if !config.monitoring.enabled {
+                    return Err(anyhow::anyhow!(
+                        "The metrics endpoint is disabled; set `enabled = true` under [monitoring] in config.toml"
+                    ));
+                }
+                let _monitoring = bolt::monitoring::MonitoringSystem::new(&config).await?;
+                tokio::signal::ctrl_c().await?;
+                info!("🛑 Stopping metrics endpoint");
+            }
+            MetricsCommands::Show => {
+                let config = runtime.config();
+                let collector = bolt::monitoring::MetricsCollector::with_config(&config).await?;
+                collector.collect_once().await;
+                let exporter = bolt::monitoring::prometheus::PrometheusExporter::new(
+                    std::sync::Arc::new(collector),
+                    config.monitoring.clone(),
+                )
+                .await?;
+                print!("{}", exporter.generate_metrics().await);
+            }
+        },
+
+        Commands::Optimize { command } => match command {
+            OptimizeCommands::Apply {
+                preset,
+                containers,
+                dry_run,
+                json,
+            } => {
+                let plan = runtime.optimize_plan(preset, &containers).await?;
+                if dry_run {
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&plan)?);
+                    } else if plan.changes.is_empty() {
+                        println!(
+                            "Nothing to change for the {} preset",
+                            plan.preset.as_str()
+                        );
+                    } else {
+                        println!("The {} preset would change:", plan.preset.as_str());
+                        for change in &plan.changes {
+                            println!(
+                                "  {:<36} {} -> {}",
+                                change.knob.to_string(),
+                                change.from,
+                                change.to
+                            );
+                        }
+                    }
+                    return Ok(());
+                }
+                let outcome = runtime.apply_optimize(&plan)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&outcome)?);
+                    return Ok(());
+                }
+                println!(
+                    "⚡ Applied the {} preset: {} changed",
+                    plan.preset.as_str(),
+                    outcome.changed.len()
+                );
+                for change in &outcome.changed {
+                    println!(
+                        "  {:<36} {} -> {}",
+                        change.knob.to_string(),
+                        change.from,
+                        change.to
+                    );
+                }
+                for skipped in &outcome.skipped {
+                    println!("  ⚠️  {}: {}", skipped.change.knob, skipped.error);
+                }
+                if !outcome.changed.is_empty() {
+                    println!("Undo with `bolt optimize revert`");
+                }
+            }
+            OptimizeCommands::Revert { json } => {
+                let outcome = runtime.revert_optimize()?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&outcome)?);
+                    return Ok(());
+                }
+                println!("↩️  Restored {} settings", outcome.changed.len());
+                for change in &outcome.changed {
+                    println!(
+                        "  {:<36} {} -> {}",
+                        change.knob.to_string(),
+                        change.to,
+                        change.from
+                    );
+                }
+                for skipped in &outcome.skipped {
+                    println!("  ⚠️  {}: {}", skipped.change.knob, skipped.error);
+                }
+            }
+        },
+
+        Commands::Maintenance { command } => match command {
+            MaintenanceCommands::Status { json } => {
+                let status = runtime.maintenance_status()?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&status)?);
+                    return Ok(());
+                }
+
+                let time = |t: chrono::NaiveDateTime| t.format("%a %Y-%m-%d %H:%M").to_string();
+                match status.window {
+                    Some(ref window) => println!("🔧 Window: {}", window),
+                    None => println!("🔧 Window: none (set `maintenance` in the Boltfile)"),
+                }
+                if let Some(end) = status.open_until {
+                    println!("   Open until {}", time(end));
+                } else if let Some(next) = status.next_window {
+                    println!("   Next: {}", time(next));
+                }
+                match status.watcher {
+                    Some(ref watcher) => println!(
+                        "   Watcher: pid {}, since {}",
+                        watcher.watcher_pid,
+                        watcher
+                            .since
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M")
+                    ),
+                    None => println!("   Watcher: disabled (bolt maintenance enable)"),
+                }
+
+                if status.state.queue.is_empty() {
+                    println!("\nQueue: empty");
+                } else {
+                    println!("\nQueue:");
+                    for queued in &status.state.queue {
+                        print!("  {}", queued.operation);
+                        if queued.manual {
+                            print!(" (queued by hand)");
+                        }
+                        if let Some(ref reason) = queued.postponed {
+                            print!(" - {}", reason);
+                        }
+                        println!();
+                    }
+                }
+
+                if let Some(ref run) = status.state.last_run {
+                    println!(
+                        "\nLast run {}:",
+                        run.started_at
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M")
+                    );
+                    for op in &run.operations {
+                        let mark = match (op.ok, op.postponed) {
+                            (false, _) => "❌",
+                            (true, true) => "⏸️ ",
+                            (true, false) => "✅",
+                        };
+                        println!("  {} {}: {}", mark, op.operation, op.summary);
+                    }
+                }
+            }
+            MaintenanceCommands::Queue { operation } => {
+                if runtime.queue_maintenance(operation)? {
+                    info!("✅ Queued {} for the next maintenance window", operation);
+                } else {
+                    info!("{} is already queued", operation);
+                }
+            }
+            MaintenanceCommands::Unqueue { operation } => {
+                if runtime.unqueue_maintenance(operation)? {
+                    info!("✅ Dropped {} from the maintenance queue", operation);
+                } else {
+                    info!("{} wasn't queued", operation);
+                }
+            }
+            MaintenanceCommands::Run { operations, json } => {
+                let report = runtime.run_maintenance(&operations).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else if report.operations.is_empty() {
+                    println!("Nothing queued");
+                }
+                if report.failed() {
+                    std::process::exit(1);
+                }
+            }
+            MaintenanceCommands::Enable => {
+                let state = runtime.set_maintenance(true)?.expect("watcher state");
+                info!(
+                    "✅ Maintenance windows enabled (watcher pid {})",
+                    state.watcher_pid
+                );
+            }
+            MaintenanceCommands::Disable => {
+                runtime.set_maintenance(false)?;
+                info!("✅ Maintenance windows disabled");
+            }
+        },
+
+        Commands::Report { command } => match command {
+            ReportCommands::Capacity { json } => {
+                use bolt::builds::cache::format_size;
+                use bolt::monitoring::capacity::CapacityStatus;
+
+                let report = runtime.capacity_report().await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    println!(
+                        "{:<10} {:>10} {:>12} {:>12} {:>10}",
+                        "SERIES", "CURRENT", "THRESHOLD", "GROWTH/DAY", "FULL IN"
+                    );
+                    for series in &report.series {
+                        let symbol = match series.status {
+                            CapacityStatus::Ok => "✅",
+                            CapacityStatus::Warning => "⚠️",
+                            CapacityStatus::Critical => "❌",
+                            CapacityStatus::Unknown => "❔",
+                        };
+                        println!(
+                            "{:<10} {:>10} {:>12} {:>12} {:>10} {}",
+                            series.name,
+                            format_size(series.current_bytes),
+                            series.threshold_bytes.map(format_size).unwrap_or_else(|| "-".to_string()),
+                            series
+                                .growth_bytes_per_day
+                                .map(|g| if g < 0.0 { format!("-{}", format_size(-g as u64)) } else { format_size(g as u64) })
+                                .unwrap_or_else(|| "-".to_string()),
+                            series
+                                .days_until_full
+                                .map(|d| format!("{:.1}d", d))
+                                .unwrap_or_else(|| "-".to_string()),
+                            symbol
+                        );
+                    }
+                    println!(
+                        "\n{} samples over {}; alerting {} days ahead",
+                        report.samples, report.window, report.alert_days
+                    );
+                    if report.series.iter().any(|s| s.status == CapacityStatus::Unknown) {
+                        println!("   Run this regularly (e.g. daily from a timer) to build up history");
+                    }
+                }
+            }
+            ReportCommands::Startup { json, prometheus } => {
+                let stats = runtime.startup_stats();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                } else if prometheus {
+                    print!("{}", bolt::runtime::startup::prometheus(&stats));
+                } else if stats.is_empty() {
+                    println!("No container starts recorded yet");
+                } else {
+                    println!("{:<14} {:>8} {:>10} {:>10}", "PHASE", "STARTS", "P50", "P95");
+                    for stat in &stats {
+                        println!(
+                            "{:<14} {:>8} {:>8.1}ms {:>8.1}ms",
+                            stat.name(),
+                            stat.samples,
+                            stat.p50_ms,
+                            stat.p95_ms
+                        );
+                    }
+                    // The phase with the worst tail is where startups lose the most time
+                    if let Some(phase) = stats
+                        .iter()
+                        .filter_map(|s| s.phase.map(|p| (p, s.p95_ms)))
+                        .max_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(p, _)| p)
+                    {
+                        println!("💡 {}: {}", phase, phase.hint());
+                    }
+                }
+            }
+        },
+
+        Commands::Config { command } => match command {
+            ConfigCommands::Render { output } => {
+                let rendered = bolt_config.render_boltfile()?;
+                match output {
+                    Some(path) => {
+                        std::fs::write(&path, rendered)?;
+                        info!("📝 Rendered Boltfile written to {}", path);
+                    }
+                    None => print!("{}", rendered),
+                }
+            }
+        },
+
+        Commands::Backup { command } => match command {
+            BackupCommands::Restic { command } => match command {
+                ResticCommands::Init => {
+                    runtime.restic_init().await?;
+                }
+
+                ResticCommands::Backup {
+                    volumes,
+                    snapshots,
+                    json,
+                } => {
+                    use bolt::builds::cache::format_size;
+
+                    let summaries = runtime.restic_backup(&volumes, &snapshots).await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&summaries)?);
+                        return Ok(());
+                    }
+                    println!("{:<30} {:<10} {:>12} {:>12}", "TARGET", "SNAPSHOT", "ADDED", "TOTAL");
+                    for summary in &summaries {
+                        println!(
+                            "{:<30} {:<10} {:>12} {:>12}",
+                            summary.target.to_string(),
+                            &summary.snapshot_id[..summary.snapshot_id.len().min(8)],
+                            format_size(summary.data_added),
+                            format_size(summary.total_bytes)
+                        );
+                    }
+                }
+
+                ResticCommands::Restore { id, target } => {
+                    let summary = runtime
+                        .restic_restore(&id, target.as_deref().map(std::path::Path::new))
+                        .await?;
+                    println!("✅ Restored {} to {}", summary.target, summary.restored_to);
+                }
+
+                ResticCommands::Snapshots { json } => {
+                    let snapshots = runtime.restic_snapshots().await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&snapshots)?);
+                        return Ok(());
+                    }
+                    if snapshots.is_empty() {
+                        info!("No Bolt backups in the repository");
+                        return Ok(());
+                    }
+                    println!("{:<10} {:<26} {:<20} {:<30}", "ID", "TIME", "HOST", "TARGET");
+                    for snapshot in &snapshots {
+                        println!(
+                            "{:<10} {:<26} {:<20} {:<30}",
+                            snapshot.short_id(),
+                            snapshot.time.chars().take(19).collect::<String>().replace('T', " "),
+                            snapshot.hostname,
+                            snapshot
+                                .target()
+                                .map(|t| t.to_string())
+                                .unwrap_or_else(|| "-".to_string())
+                        );
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "quic-networking")]
+        Commands::Cert { command } => match command {
+            CertCommands::Ls { json } => {
+                let certificates = runtime.certificates();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&certificates)?);
+                } else if certificates.is_empty() {
+                    println!("No certificates in [acme]");
+                } else {
+                    let now = chrono::Utc::now();
+                    println!(
+                        "{:<20} {:<18} {:>9}  DOMAINS",
+                        "NAME", "EXPIRES", "DAYS LEFT"
+                    );
+                    for certificate in certificates {
+                        let (expires, left) = match certificate.not_after {
+                            Some(expiry) => (
+                                expiry.format("%Y-%m-%d %H:%M").to_string(),
+                                (expiry - now).num_days().to_string(),
+                            ),
+                            None => ("not issued".to_string(), "-".to_string()),
+                        };
+                        println!(
+                            "{:<20} {:<18} {:>9}  {}",
+                            certificate.name,
+                            expires,
+                            left,
+                            certificate.domains.join(", ")
+                        );
+                    }
+                }
+            }
+            CertCommands::Renew { names, force } => {
+                let mut failed = 0;
+                for (name, result) in runtime.renew_certificates(&names, force).await? {
+                    match result {
+                        Ok(true) => info!("✅ Issued certificate {}", name),
+                        Ok(false) => info!("Certificate {} is not due for renewal", name),
+                        Err(e) => {
+                            failed += 1;
+                            eprintln!("❌ Certificate {}: {:#}", name, e);
+                        }
+                    }
+                }
+                if failed > 0 {
+                    return Err(anyhow::anyhow!("{} certificate(s) failed", failed));
+                }
+            }
+            CertCommands::Watch => runtime.watch_certificates().await?,
+        },
+        #[cfg(not(feature = "quic-networking"))]
+        Commands::Cert { .. } => {
+            return Err(anyhow::anyhow!(
+                "ACME certificates require the quic-networking feature"
+            ));
+        }
+
+        Commands::Secret { command } => {
+            let store = bolt::secrets::SecretStore::new(&bolt_config.config_dir);
+            match command {
+                SecretCommands::Set { name, from_file } => {
+                    use std::io::{IsTerminal, Read};
+
+                    let value = match from_file {
+                        Some(path) => std::fs::read(&path)?,
+                        None if std::io::stdin().is_terminal() => {
+                            eprint!("Value for {}: ", name);
+                            let mut line = String::new();
+                            std::io::stdin().read_line(&mut line)?;
+                            line.into_bytes()
+                        }
+                        None => {
+                            let mut value = Vec::new();
+                            std::io::stdin().read_to_end(&mut value)?;
+                            value
+                        }
+                    };
+                    store.set(&name, &value)?;
+                }
+                SecretCommands::Ls => {
+                    for name in store.list()? {
+                        println!("{}", name);
+                    }
+                }
+                SecretCommands::Rm { name } => {
+                    if store.remove(&name)? {
+                        info!("🗑️  Removed secret {}", name);
+                    } else {
+                        info!("No secret named {}", name);
+                    }
+                }
+            }
+        }
+
+        Commands::Workspace { command } => match command {
+            WorkspaceCommands::Create {
+                name,
+                cpus,
+                memory,
+                gpu_hours,
+                disk,
+            } => {
+                use bolt::builds::cache::parse_size;
+
+                let quota = bolt::runtime::workspace::Quota {
+                    cpus,
+                    memory: memory.as_deref().map(parse_size).transpose()?,
+                    gpu_hours,
+                    disk: disk.as_deref().map(parse_size).transpose()?,
+                };
+                runtime.create_workspace(&name, quota)?;
+            }
+            WorkspaceCommands::Assign {
+                user,
+                workspace,
+                remove: _,
+            } => {
+                runtime.assign_workspace(&user, workspace.as_deref())?;
+            }
+            WorkspaceCommands::Usage { workspace, json } => {
+                use bolt::builds::cache::format_size;
+
+                let usage = runtime.workspace_usage(workspace.as_deref()).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&usage)?);
+                } else {
+                    let limit = |quota: Option<String>| quota.unwrap_or_else(|| "-".to_string());
+                    println!("Workspace {} ({})", usage.workspace, usage.users.join(", "));
+                    println!("{:<10} {:>12} {:>12}", "RESOURCE", "USED", "QUOTA");
+                    println!(
+                        "{:<10} {:>12.2} {:>12}",
+                        "cpus",
+                        usage.cpus,
+                        limit(usage.quota.cpus.map(|c| c.to_string()))
+                    );
+                    println!(
+                        "{:<10} {:>12} {:>12}",
+                        "memory",
+                        format_size(usage.memory),
+                        limit(usage.quota.memory.map(format_size))
+                    );
+                    println!(
+                        "{:<10} {:>12.1} {:>12}",
+                        "gpu-hours",
+                        usage.gpu_hours,
+                        limit(usage.quota.gpu_hours.map(|h| h.to_string()))
+                    );
+                    println!(
+                        "{:<10} {:>12} {:>12}",
+                        "disk",
+                        format_size(usage.disk),
+                        limit(usage.quota.disk.map(format_size))
+                    );
+                    println!("{} running container(s)", usage.containers.len());
+                }
+            }
+        },
+
+        Commands::Dev { command } => {
+            let cwd = std::env::current_dir()?;
+            match command {
+                DevCommands::Enter { rebuild, command } => {
+                    let code = runtime.dev_enter(&cwd, &command, rebuild).await?;
+                    std::process::exit(code);
+                }
+                DevCommands::List { json } => {
+                    let environments = runtime.dev_environments().await?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&environments)?);
+                    } else if environments.is_empty() {
+                        println!("No dev environments");
+                    } else {
+                        println!("{:<20} {:<10} {:<20} REPOSITORY", "NAME", "STATUS", "ENTERED");
+                        for env in environments {
+                            println!(
+                                "{:<20} {:<10} {:<20} {}",
+                                env.record.name,
+                                env.status.as_deref().unwrap_or("removed"),
+                                env.record.last_entered.format("%Y-%m-%d %H:%M"),
+                                env.record.root.display()
+                            );
+                        }
+                    }
+                }
+                DevCommands::Stop { name } => {
+                    let name = runtime.dev_environment_name(name.as_deref(), &cwd)?;
+                    runtime.dev_stop(&name).await?;
+                }
+                DevCommands::Rm { name, snapshot } => {
+                    let name = runtime.dev_environment_name(name.as_deref(), &cwd)?;
+                    runtime.dev_remove(&name, snapshot).await?;
+                }
+            }
+        }
+
+        Commands::Capsule {
+            command: CapsuleCommands::Ssh { command },
+        } => {
+            use bolt::runtime::ssh;
+
+            let data_dir = &runtime.config().data_dir;
+            match command {
+                CapsuleSshCommands::Enable {
+                    capsule,
+                    address,
+                    port,
+                    shell,
+                } => {
+                    let options = ssh::SshOptions {
+                        address,
+                        port,
+                        shell,
+                    };
+                    let endpoint = ssh::enable(data_dir, &capsule, &options).await?;
+                    println!("Connect with: bolt ssh {}", capsule);
+                    println!(
+                        "Or from an IDE: ssh -p {} {}@<this host>",
+                        endpoint.port, endpoint.user
+                    );
+                }
+                CapsuleSshCommands::Disable { capsule } => {
+                    ssh::stop(data_dir, &capsule);
+                    info!("🔒 Stopped the SSH endpoint for {}", capsule);
+                }
+                CapsuleSshCommands::AddKey { capsule, key } => {
+                    ssh::add_key(data_dir, &capsule, &key)?;
+                }
+                CapsuleSshCommands::RemoveKey { capsule, key } => {
+                    ssh::remove_key(data_dir, &capsule, &key)?;
+                }
+                CapsuleSshCommands::Keys { capsule } => {
+                    let keys = ssh::keys(data_dir, &capsule);
+                    if keys.is_empty() {
+                        println!("No keys authorized for {}", capsule);
+                    }
+                    for key in keys {
+                        println!("{:<12} {} {}", key.kind, key.fingerprint(), key.comment);
+                    }
+                }
+                CapsuleSshCommands::Status { json } => {
+                    let endpoints = ssh::list(data_dir);
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&endpoints)?);
+                    } else if endpoints.is_empty() {
+                        println!("No SSH endpoints");
+                    } else {
+                        println!(
+                            "{:<20} {:<22} {:<10} {:<5} HOST KEY",
+                            "CAPSULE", "LISTEN", "STATUS", "KEYS"
+                        );
+                        for endpoint in endpoints {
+                            println!(
+                                "{:<20} {:<22} {:<10} {:<5} {}",
+                                endpoint.capsule,
+                                format!("{}:{}", endpoint.address, endpoint.port),
+                                if endpoint.running() { "running" } else { "stopped" },
+                                ssh::keys(data_dir, &endpoint.capsule).len(),
+                                endpoint.host_key
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Commands::Capsule {
+            command:
+                CapsuleCommands::Console {
+                    name,
+                    lines,
+                    log,
+                    shell,
+                },
+        } => {
+            if log {
+                for line in runtime.capsule_console_log(&name, lines).await? {
+                    println!("{}", line);
+                }
+            } else {
+                let code = runtime.capsule_console(&name, lines, shell).await?;
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+        }
+
+        Commands::Capsule {
+            command:
+                CapsuleCommands::Create {
+                    name,
+                    template,
+                    image,
+                    memory_mb,
+                    vcpus,
+                },
+        } => {
+            let overrides = bolt::capsules::templates::TemplateOverrides {
+                description: None,
+                image,
+                memory_mb,
+                vcpus,
+            };
+            runtime.create_capsule(&name, &template, &overrides).await?;
+        }
+
+        Commands::Capsule {
+            command:
+                CapsuleCommands::Snapshot {
+                    name,
+                    snapshot_name,
+                    description,
+                },
+        } => {
+            let snapshot = runtime
+                .snapshot_capsule(&name, snapshot_name, description.as_deref())
+                .await?;
+            println!("📸 Snapshot {} of capsule {}", snapshot.id, name);
+            if let Some(stats) = &snapshot.memory_stats {
+                println!(
+                    "   {} of memory stored in {} ({:.1}% saved: {} zero blocks, {} deduplicated)",
+                    bolt::builds::cache::format_size(stats.original_bytes),
+                    bolt::builds::cache::format_size(stats.stored_bytes),
+                    stats.savings_percent(),
+                    stats.zero_blocks,
+                    stats.deduplicated_blocks
+                );
+            }
+        }
+
+        Commands::Capsule {
+            command: CapsuleCommands::Template { command },
+        } => match command {
+            CapsuleTemplateCommands::Create {
+                name,
+                from,
+                description,
+                image,
+                memory_mb,
+                vcpus,
+                force,
+            } => {
+                let overrides = bolt::capsules::templates::TemplateOverrides {
+                    description,
+                    image,
+                    memory_mb,
+                    vcpus,
+                };
+                runtime.create_capsule_template(&name, &from, &overrides, force)?;
+            }
+            CapsuleTemplateCommands::List { json } => {
+                let templates = runtime.capsule_templates();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&templates)?);
+                } else {
+                    println!(
+                        "{:<20} {:<16} {:<44} {:>7} {:>5}  DESCRIPTION",
+                        "NAME", "TYPE", "IMAGE", "MEMORY", "VCPUS"
+                    );
+                    for template in &templates {
+                        let config = &template.base_config;
+                        println!(
+                            "{:<20} {:<16} {:<44} {:>5}MB {:>5}  {}",
+                            template.name,
+                            format!("{:?}", template.capsule_type),
+                            config.image,
+                            config.resources.memory_mb,
+                            config.resources.vcpus,
+                            template.description
+                        );
+                    }
+                }
+            }
+            CapsuleTemplateCommands::Export { name, output } => {
+                let text = bolt::capsules::templates::to_toml(&runtime.capsule_template(&name)?)?;
+                match output {
+                    Some(path) => {
+                        std::fs::write(&path, text)?;
+                        info!("📤 Template {} exported to {}", name, path.display());
+                    }
+                    None => print!("{}", text),
+                }
+            }
+            CapsuleTemplateCommands::Import { file, name, force } => {
+                let template = runtime.import_capsule_template(&file, name.as_deref(), force)?;
+                info!("📥 Template {} imported", template.name);
+            }
+            CapsuleTemplateCommands::Rm { name } => {
+                runtime.remove_capsule_template(&name)?;
+                info!("🗑️  Template {} removed", name);
+            }
+        },
+
+        Commands::Ssh { capsule, args } => {
+            let mut ssh_args = bolt::runtime::ssh::ssh_args(&runtime.config().data_dir, &capsule)?;
+            ssh_args.extend(args);
+            let status = tokio::process::Command::new("ssh")
+                .args(&ssh_args)
+                .status()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to run ssh: {}", e))?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        Commands::Compat { command } => {
+            compat::handle_compat_command(compat::CompatArgs { command }, runtime).await?;
+        }
+
+        Commands::IdleWatch { container } => {
+            bolt::runtime::idle::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::SloWatch { container } => {
+            bolt::monitoring::slo::watch(&runtime.config(), &container).await?;
+        }
+
+        Commands::SnapshotWatch => {
+            bolt::runtime::snapshots::watch(&runtime.config()).await?;
+        }
+
+        Commands::MaintenanceWatch => {
+            bolt::surge::maintenance::watch(&runtime.config()).await?;
+        }
+
+        Commands::LogWatch { container } => {
+            bolt::runtime::log_hooks::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::NatWatch { container } => {
+            bolt::networking::nat::watch(&runtime.config(), &container).await?;
+        }
+
+        Commands::CacheTune { container } => {
+            bolt::volume::cache::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::GpuArbiter => {
+            bolt::gaming::priority::arbitrate(&runtime.config().data_dir).await?;
+        }
+
+        Commands::PrefixWatch { container } => {
+            bolt::gaming::prefix::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::HealthWatch { container } => {
+            bolt::runtime::healthcheck::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::Supervise { container } => {
+            bolt::runtime::supervisor::watch(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::DesktopBridge { container } => {
+            bolt::runtime::desktop::bridge(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::EgressSync { container } => {
+            bolt::networking::egress::sync(&runtime.config().data_dir, &container).await?;
+        }
+
+        Commands::SshExec { .. } => unreachable!("handled before logging starts"),
+
+        Commands::WasmExec { spec } => {
+            let code = bolt::runtime::wasm::exec(&spec).await?;
+            std::process::exit(code);
+        }
+    }
+
+    Ok(())
+}
+
+/// `docker stats`-style table of the latest sample per container
+fn print_stats(stats: &std::collections::BTreeMap<String, bolt::monitoring::ContainerMetrics>) {
+    use bolt::builds::cache::format_size;
+
+    println!(
+        "{:<12} {:<24} {:>7} {:>21} {:>6} {:>21} {:>21} {:>8}",
+        "CONTAINER ID",
+        "NAME",
+        "CPU %",
+        "MEM USAGE / LIMIT",
+        "MEM %",
+        "NET I/O",
+        "BLOCK I/O",
+        "RESTARTS"
+    );
+    for metrics in stats.values() {
+        let limit = match metrics.memory_limit_bytes {
+            0 => "-".to_string(),
+            limit => format_size(limit),
+        };
+        let memory_percent = match metrics.memory_limit_bytes {
+            0 => "-".to_string(),
+            limit => format!(
+                "{:.1}%",
+                metrics.memory_usage_bytes as f64 / limit as f64 * 100.0
+            ),
+        };
+        println!(
+            "{:<12} {:<24} {:>6.1}% {:>21} {:>6} {:>21} {:>21} {:>8}",
+            metrics.container_id.chars().take(12).collect::<String>(),
+            metrics.name,
+            metrics.cpu_usage_percent,
+            format!("{} / {}", format_size(metrics.memory_usage_bytes), limit),
+            memory_percent,
+            format!(
+                "{} / {}",
+                format_size(metrics.network_rx_bytes),
+                format_size(metrics.network_tx_bytes)
+            ),
+            format!(
+                "{} / {}",
+                format_size(metrics.disk_read_bytes),
+                format_size(metrics.disk_write_bytes)
+            ),
+            metrics.restart_count,
+        );
+    }
+}
+
+/// Print a bulk operation's results; exits 1 when some containers failed
+/// and 2 when all of them did
+fn finish_bulk(summary: &bolt::runtime::bulk::Summary) {
+    if summary.outcomes.is_empty() {
+        info!("No containers matched");
+        return;
+    }
+    summary.print();
+    let code = summary.exit_code();
+    if code != 0 {
+        std::process::exit(code);
+    }
 }
@@ -0,0 +1,176 @@
+// Alerting engine
+//
+// Alerts are logged, appended to `<data_dir>/alerts.jsonl` and, when
+// configured in `[alerts]`, POSTed to a webhook and handed to a command.
+// An alert with the same key is not repeated within `repeat_interval`.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::process::Command as AsyncCommand;
+use tracing::{error, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::AlertSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    /// Something worth knowing that needs no action, like planned maintenance
+    Info,
+    Warning,
+    Critical,
+}
+
+impl AlertSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    /// Stable identifier used to suppress repeats, e.g. `capacity.volumes`
+    pub key: String,
+    pub severity: AlertSeverity,
+    pub message: String,
+    /// Seconds since the Unix epoch
+    pub fired_at: u64,
+}
+
+impl Alert {
+    pub fn new(
+        key: impl Into<String>,
+        severity: AlertSeverity,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            severity,
+            message: message.into(),
+            fired_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+pub struct AlertingEngine {
+    settings: AlertSettings,
+    log_path: PathBuf,
+    state_path: PathBuf,
+}
+
+impl AlertingEngine {
+    pub fn new(data_dir: &Path, settings: AlertSettings) -> Self {
+        Self {
+            settings,
+            log_path: data_dir.join("alerts.jsonl"),
+            state_path: data_dir.join("alerts-state.json"),
+        }
+    }
+
+    /// Deliver an alert unless the same key fired within `repeat_interval`.
+    /// Returns whether it was delivered.
+    pub async fn fire(&self, alert: &Alert) -> Result<bool> {
+        let repeat = parse_duration(&self.settings.repeat_interval)?.as_secs();
+        let mut state = self.load_state();
+        if let Some(last) = state.get(&alert.key)
+            && alert.fired_at.saturating_sub(*last) < repeat
+        {
+            return Ok(false);
+        }
+
+        self.deliver(alert).await?;
+        state.insert(alert.key.clone(), alert.fired_at);
+        std::fs::write(&self.state_path, serde_json::to_vec(&state)?)?;
+        Ok(true)
+    }
+
+    /// Deliver a one-off notice, like planned maintenance, without
+    /// `repeat_interval` applying to it
+    pub async fn notify(&self, alert: &Alert) -> Result<()> {
+        self.deliver(alert).await
+    }
+
+    async fn deliver(&self, alert: &Alert) -> Result<()> {
+        match alert.severity {
+            AlertSeverity::Info => info!("🔔 {}", alert.message),
+            AlertSeverity::Warning => warn!("🔔 {}", alert.message),
+            AlertSeverity::Critical => error!("🚨 {}", alert.message),
+        }
+
+        if let Some(parent) = self.log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)?;
+        writeln!(log, "{}", serde_json::to_string(alert)?)?;
+
+        // A broken sink shouldn't stop the others or the caller
+        if let Some(webhook) = &self.settings.webhook
+            && let Err(e) = self.send_webhook(webhook, alert).await
+        {
+            warn!("Alert webhook {} failed: {}", webhook, e);
+        }
+        if let Some(command) = &self.settings.command
+            && let Err(e) = self.run_command(command, alert).await
+        {
+            warn!("Alert command failed: {}", e);
+        }
+
+        Ok(())
+    }
+
+    /// Forget that an alert fired, so it is delivered again as soon as the
+    /// condition returns
+    pub fn resolve(&self, key: &str) -> Result<()> {
+        let mut state = self.load_state();
+        if state.remove(key).is_some() {
+            std::fs::write(&self.state_path, serde_json::to_vec(&state)?)?;
+            info!("✅ Alert resolved: {}", key);
+        }
+        Ok(())
+    }
+
+    async fn send_webhook(&self, url: &str, alert: &Alert) -> Result<()> {
+        reqwest::Client::new()
+            .post(url)
+            .json(alert)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn run_command(&self, command: &str, alert: &Alert) -> Result<()> {
+        let status = AsyncCommand::new("sh")
+            .args(["-c", command])
+            .env("BOLT_ALERT_KEY", &alert.key)
+            .env("BOLT_ALERT_SEVERITY", alert.severity.as_str())
+            .env("BOLT_ALERT_MESSAGE", &alert.message)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("`{}` exited with {}", command, status));
+        }
+        Ok(())
+    }
+
+    fn load_state(&self) -> HashMap<String, u64> {
+        std::fs::read(&self.state_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,376 @@
+// Capacity forecasting for the data dir, volumes and image store
+//
+// Each run records the current size of every series into the metrics
+// history, fits a linear growth rate over the configured window and projects
+// when the series reaches its threshold: the configured `*_max`, or the
+// point where the filesystem it lives on crosses `disk_threshold_percent`,
+// whichever comes first.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::alerts::{Alert, AlertSeverity, AlertingEngine};
+use super::history::{MetricsHistory, Sample};
+use crate::builds::cache::{format_size, parse_duration, parse_size};
+use crate::config::{BoltConfig, CapacitySettings};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+/// Shorter histories give wildly unstable growth rates
+const MIN_HISTORY_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapacityStatus {
+    Ok,
+    /// Threshold forecast to be hit within `alert_days`
+    Warning,
+    /// Already at or past the threshold
+    Critical,
+    /// Not enough history to estimate growth yet
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesForecast {
+    pub name: String,
+    pub path: PathBuf,
+    pub current_bytes: u64,
+    pub threshold_bytes: Option<u64>,
+    /// Where the threshold comes from: "configured" or "disk"
+    pub threshold_source: Option<String>,
+    pub growth_bytes_per_day: Option<f64>,
+    pub days_until_full: Option<f64>,
+    pub status: CapacityStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapacityReport {
+    pub window: String,
+    pub samples: usize,
+    pub alert_days: u32,
+    pub series: Vec<SeriesForecast>,
+}
+
+/// Current size of one series and the filesystem it lives on
+#[derive(Debug, Clone)]
+pub struct SeriesUsage {
+    pub name: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub configured_max: Option<u64>,
+    /// (size, used) of the containing filesystem
+    pub filesystem: Option<(u64, u64)>,
+}
+
+/// Measure, record, forecast and alert
+pub async fn run(config: &BoltConfig) -> Result<CapacityReport> {
+    let settings = &config.capacity;
+    let window = parse_duration(&settings.window)?;
+    let retention = parse_duration(&settings.retention)?;
+
+    let usage = collect(config).await?;
+    let history = MetricsHistory::open(&config.data_dir, "capacity")?;
+    history.record(
+        usage
+            .iter()
+            .map(|u| (u.name.clone(), u.bytes))
+            .collect::<BTreeMap<_, _>>(),
+    )?;
+    let pruned = history.prune(retention)?;
+    if pruned > 0 {
+        debug!("Pruned {} capacity samples", pruned);
+    }
+
+    let samples = history.samples(window)?;
+    let report = forecast(&usage, &samples, settings);
+
+    let alerts = AlertingEngine::new(&config.data_dir, config.alerts.clone());
+    for series in &report.series {
+        let key = format!("capacity.{}", series.name);
+        let severity = match series.status {
+            CapacityStatus::Critical => AlertSeverity::Critical,
+            CapacityStatus::Warning => AlertSeverity::Warning,
+            CapacityStatus::Ok => {
+                alerts.resolve(&key)?;
+                continue;
+            }
+            CapacityStatus::Unknown => continue,
+        };
+        alerts
+            .fire(&Alert::new(key, severity, describe(series)))
+            .await?;
+    }
+
+    Ok(report)
+}
+
+/// Current size of the data dir, volumes and image store
+pub async fn collect(config: &BoltConfig) -> Result<Vec<SeriesUsage>> {
+    let settings = &config.capacity;
+    let max = |value: &Option<String>| value.as_deref().map(parse_size).transpose();
+
+    let mut usage = vec![SeriesUsage {
+        name: "data_dir".to_string(),
+        path: config.data_dir.clone(),
+        bytes: dir_size(&config.data_dir),
+        configured_max: max(&settings.data_dir_max)?,
+        filesystem: filesystem_usage(&config.data_dir).await,
+    }];
+
+    let volumes = crate::runtime::rootless::storage_root().join("volumes");
+    if volumes.exists() {
+        usage.push(SeriesUsage {
+            name: "volumes".to_string(),
+            bytes: dir_size(&volumes),
+            configured_max: max(&settings.volumes_max)?,
+            filesystem: filesystem_usage(&volumes).await,
+            path: volumes,
+        });
+    }
+
+    match image_store().await {
+        Ok((root, bytes)) => usage.push(SeriesUsage {
+            name: "images".to_string(),
+            bytes,
+            configured_max: max(&settings.images_max)?,
+            filesystem: filesystem_usage(&root).await,
+            path: root,
+        }),
+        Err(e) => warn!("Skipping image store: {}", e),
+    }
+
+    Ok(usage)
+}
+
+/// Project each series against its threshold using the recorded samples
+pub fn forecast(
+    usage: &[SeriesUsage],
+    samples: &[Sample],
+    settings: &CapacitySettings,
+) -> CapacityReport {
+    let series = usage
+        .iter()
+        .map(|u| {
+            // Room left before the filesystem crosses the usage threshold,
+            // expressed as a size for this series
+            let disk_limit = u.filesystem.map(|(size, used)| {
+                let limit = size as f64 * settings.disk_threshold_percent as f64 / 100.0;
+                u.bytes + (limit as u64).saturating_sub(used)
+            });
+            let (threshold, source) = match (u.configured_max, disk_limit) {
+                (Some(max), Some(disk)) if disk < max => (Some(disk), Some("disk")),
+                (Some(max), _) => (Some(max), Some("configured")),
+                (None, Some(disk)) => (Some(disk), Some("disk")),
+                (None, None) => (None, None),
+            };
+
+            let points: Vec<(u64, u64)> = samples
+                .iter()
+                .filter_map(|s| s.series.get(&u.name).map(|b| (s.timestamp, *b)))
+                .collect();
+            let growth = growth_per_second(&points).map(|g| g * SECONDS_PER_DAY);
+
+            let days_until_full = match (threshold, growth) {
+                (Some(threshold), _) if u.bytes >= threshold => Some(0.0),
+                (Some(threshold), Some(growth)) if growth > 0.0 => {
+                    Some((threshold - u.bytes) as f64 / growth)
+                }
+                _ => None,
+            };
+            let status = match (threshold, days_until_full) {
+                (Some(_), Some(days)) if days <= 0.0 => CapacityStatus::Critical,
+                (Some(_), Some(days)) if days <= settings.alert_days as f64 => {
+                    CapacityStatus::Warning
+                }
+                (Some(_), _) if growth.is_none() => CapacityStatus::Unknown,
+                _ => CapacityStatus::Ok,
+            };
+
+            SeriesForecast {
+                name: u.name.clone(),
+                path: u.path.clone(),
+                current_bytes: u.bytes,
+                threshold_bytes: threshold,
+                threshold_source: source.map(str::to_string),
+                growth_bytes_per_day: growth,
+                days_until_full,
+                status,
+            }
+        })
+        .collect();
+
+    CapacityReport {
+        window: settings.window.clone(),
+        samples: samples.len(),
+        alert_days: settings.alert_days,
+        series,
+    }
+}
+
+/// Human-readable summary of a forecast, used for alerts
+pub fn describe(series: &SeriesForecast) -> String {
+    let threshold = series.threshold_bytes.map(format_size).unwrap_or_default();
+    match series.days_until_full {
+        Some(days) if days <= 0.0 => format!(
+            "{} ({}) is at {} and past its {} threshold of {}",
+            series.name,
+            series.path.display(),
+            format_size(series.current_bytes),
+            series.threshold_source.as_deref().unwrap_or(""),
+            threshold
+        ),
+        Some(days) => format!(
+            "{} ({}) is at {} and will reach its {} threshold of {} in {:.1} days",
+            series.name,
+            series.path.display(),
+            format_size(series.current_bytes),
+            series.threshold_source.as_deref().unwrap_or(""),
+            threshold,
+            days
+        ),
+        None => format!(
+            "{} ({}) is at {}",
+            series.name,
+            series.path.display(),
+            format_size(series.current_bytes)
+        ),
+    }
+}
+
+/// Least-squares slope in bytes per second, or None if the history is too
+/// short to say anything
+fn growth_per_second(points: &[(u64, u64)]) -> Option<f64> {
+    let (first, last) = (points.first()?.0, points.last()?.0);
+    if points.len() < 2 || last - first < MIN_HISTORY_SECS {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    // Offset timestamps so the squares stay well within f64 precision
+    let xs: Vec<f64> = points.iter().map(|(t, _)| (t - first) as f64).collect();
+    let ys: Vec<f64> = points.iter().map(|(_, b)| *b as f64).collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (x, y) in xs.iter().zip(&ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var += (x - mean_x).powi(2);
+    }
+    if var == 0.0 {
+        return None;
+    }
+    Some(cov / var)
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// (size, used) in bytes of the filesystem holding `path`
+pub(crate) async fn filesystem_usage(path: &Path) -> Option<(u64, u64)> {
+    let output = AsyncCommand::new("df")
+        .args(["-B1", "--output=size,used"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.lines().nth(1)?.split_whitespace();
+    Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+}
+
+/// Storage root and total size of all images. Layers shared between images
+/// are counted once per image, which overstates the store somewhat but grows
+/// at the same rate.
+async fn image_store() -> Result<(PathBuf, u64)> {
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let root_format = if runtime == "podman" {
+        "{{.Store.GraphRoot}}"
+    } else {
+        "{{.DockerRootDir}}"
+    };
+    let root = AsyncCommand::new(&runtime)
+        .args(["info", "--format", root_format])
+        .output()
+        .await?;
+    if !root.status.success() {
+        return Err(anyhow!(
+            "{} info failed: {}",
+            runtime,
+            String::from_utf8_lossy(&root.stderr).trim()
+        ));
+    }
+    let root = PathBuf::from(String::from_utf8_lossy(&root.stdout).trim());
+
+    let ids = AsyncCommand::new(&runtime)
+        .args(["images", "-q", "--no-trunc"])
+        .output()
+        .await?;
+    let ids: Vec<String> = String::from_utf8_lossy(&ids.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if ids.is_empty() {
+        return Ok((root, 0));
+    }
+
+    let sizes = AsyncCommand::new(&runtime)
+        .args(["image", "inspect", "--format", "{{.Size}}"])
+        .args(&ids)
+        .output()
+        .await?;
+    let total = String::from_utf8_lossy(&sizes.stdout)
+        .lines()
+        .filter_map(|l| l.trim().parse::<u64>().ok())
+        .sum();
+    info!("📦 {} images using {}", ids.len(), format_size(total));
+    Ok((root, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forecasts_days_until_configured_threshold() {
+        let gb = 1024u64.pow(3);
+        // 1 GB/day growth over four days, now at 10 GB of a 17 GB limit
+        let samples: Vec<Sample> = (0..5)
+            .map(|day| Sample {
+                timestamp: 1_700_000_000 + day * 86400,
+                series: BTreeMap::from([("volumes".to_string(), (6 + day) * gb)]),
+            })
+            .collect();
+        let usage = [SeriesUsage {
+            name: "volumes".to_string(),
+            path: PathBuf::from("/var/lib/bolt/volumes"),
+            bytes: 10 * gb,
+            configured_max: Some(17 * gb),
+            filesystem: Some((1000 * gb, 100 * gb)),
+        }];
+
+        let report = forecast(&usage, &samples, &CapacitySettings::default());
+        let series = &report.series[0];
+        assert_eq!(series.threshold_source.as_deref(), Some("configured"));
+        assert!((series.days_until_full.unwrap() - 7.0).abs() < 1e-6);
+        assert_eq!(series.status, CapacityStatus::Warning);
+
+        // Without history there's nothing to project
+        let report = forecast(&usage, &samples[..1], &CapacitySettings::default());
+        assert_eq!(report.series[0].status, CapacityStatus::Unknown);
+    }
+}
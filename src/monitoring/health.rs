@@ -395,18 +395,23 @@ impl HealthChecker {
     async fn check_storage_health(&self) -> Result<(HealthStatus, String)> {
         let mut issues = Vec::new();
 
+        let storage = crate::runtime::rootless::storage_root();
+
         // Check volume directory
-        if !std::path::Path::new("/var/lib/bolt/volumes").exists() {
+        if !storage.join("volumes").exists() {
             issues.push("Volume directory missing".to_string());
         }
 
         // Check image storage
-        if !std::path::Path::new("/var/lib/bolt/images").exists() {
+        if !storage.join("images").exists() {
             issues.push("Image storage directory missing".to_string());
         }
 
         // Check for read-only filesystems
-        if self.check_readonly_filesystem("/var/lib/bolt").await? {
+        if self
+            .check_readonly_filesystem(&storage.to_string_lossy())
+            .await?
+        {
             issues.push("Storage filesystem is read-only".to_string());
         }
 
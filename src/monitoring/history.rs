@@ -0,0 +1,95 @@
+// Embedded metrics history
+//
+// Samples are appended to a JSONL file under the data dir so trends survive
+// restarts without an external time-series database. Each sample holds a set
+// of named series; readers pick the series they care about.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub series: BTreeMap<String, u64>,
+}
+
+pub struct MetricsHistory {
+    path: PathBuf,
+}
+
+impl MetricsHistory {
+    /// History stored as `<data_dir>/metrics/<name>.jsonl`
+    pub fn open(data_dir: &Path, name: &str) -> Result<Self> {
+        let dir = data_dir.join("metrics");
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            path: dir.join(format!("{}.jsonl", name)),
+        })
+    }
+
+    pub fn record(&self, series: BTreeMap<String, u64>) -> Result<Sample> {
+        let sample = Sample {
+            timestamp: now(),
+            series,
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&sample)?)?;
+        Ok(sample)
+    }
+
+    /// Samples no older than `window`, oldest first
+    pub fn samples(&self, window: Duration) -> Result<Vec<Sample>> {
+        let since = now().saturating_sub(window.as_secs());
+        Ok(self
+            .read_all()?
+            .into_iter()
+            .filter(|s| s.timestamp >= since)
+            .collect())
+    }
+
+    /// Drop samples older than `retention`
+    pub fn prune(&self, retention: Duration) -> Result<usize> {
+        let samples = self.read_all()?;
+        let kept = self.samples(retention)?;
+        let removed = samples.len() - kept.len();
+        if removed > 0 {
+            let mut data = String::new();
+            for sample in &kept {
+                data.push_str(&serde_json::to_string(sample)?);
+                data.push('\n');
+            }
+            std::fs::write(&self.path, data)?;
+        }
+        Ok(removed)
+    }
+
+    fn read_all(&self) -> Result<Vec<Sample>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut samples: Vec<Sample> = BufReader::new(file)
+            .lines()
+            .map_while(|l| l.ok())
+            // A crash mid-write leaves a truncated last line; skip it
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect();
+        samples.sort_by_key(|s| s.timestamp);
+        Ok(samples)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tracing::{Level, Span, info_span, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::{FilterExt, Targets, filter_fn};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::layer::Filter;
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+use crate::config::{BoltConfig, LogFormat};
+
+/// Tracing target for container stdout/stderr, routed apart from daemon logs
+pub const CONTAINER_LOG_TARGET: &str = "bolt::container";
+
+static TRACE_ID: OnceLock<String> = OnceLock::new();
+
+/// Swaps the daemon log filter when the config is reloaded
+static DAEMON_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Identifier attached to everything logged while handling one CLI request.
+/// Callers such as Nova can pass their own through `BOLT_TRACE_ID`.
+pub fn trace_id() -> &'static str {
+    TRACE_ID.get_or_init(|| {
+        std::env::var("BOLT_TRACE_ID")
+            .ok()
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+    })
+}
+
+/// Root span for a CLI request; every event logged inside it carries the trace ID
+pub fn request_span(command: &str) -> Span {
+    info_span!("request", trace_id = %trace_id(), command = %command)
+}
+
+/// Flushes the container log writer when dropped; hold it for the life of the process
+pub struct LoggingGuard {
+    _container_writer: WorkerGuard,
+}
+
+/// Install the global subscriber.
+///
+/// Daemon logs go to stdout in text mode and to stderr in JSON mode so they
+/// don't interleave with command output. Container output is written to
+/// `containers.log` in the container log directory instead.
+pub fn init(config: &BoltConfig, format_override: Option<LogFormat>) -> Result<LoggingGuard> {
+    let format = format_override.unwrap_or(config.logging.format);
+    let (env_filter, filter_handle) = reload::Layer::new(env_filter(config)?);
+    let daemon_writer = match format {
+        LogFormat::Text => BoxMakeWriter::new(std::io::stdout),
+        LogFormat::Json => BoxMakeWriter::new(std::io::stderr),
+    };
+
+    let log_dir = config.container_log_dir();
+    std::fs::create_dir_all(&log_dir)
+        .with_context(|| format!("Failed to create container log directory {:?}", log_dir))?;
+    let (container_writer, guard) = tracing_appender::non_blocking(
+        tracing_appender::rolling::never(&log_dir, "containers.log"),
+    );
+
+    Registry::default()
+        .with(split_layers(
+            format,
+            env_filter,
+            daemon_writer,
+            container_writer,
+        ))
+        .try_init()
+        .context("Logging already initialized")?;
+    let _ = DAEMON_FILTER.set(filter_handle);
+
+    Ok(LoggingGuard {
+        _container_writer: guard,
+    })
+}
+
+/// A daemon layer for what `levels` lets through, except container output,
+/// and a container layer for all container output
+fn split_layers<F, D, C>(
+    format: LogFormat,
+    levels: F,
+    daemon_writer: D,
+    container_writer: C,
+) -> Vec<Box<dyn Layer<Registry> + Send + Sync>>
+where
+    F: Filter<Registry> + Send + Sync + 'static,
+    D: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    C: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let daemon_filter = levels.and(filter_fn(|meta| meta.target() != CONTAINER_LOG_TARGET));
+    let container_filter = Targets::new().with_target(CONTAINER_LOG_TARGET, Level::TRACE);
+
+    match format {
+        LogFormat::Text => vec![
+            fmt::layer()
+                .with_writer(daemon_writer)
+                .with_filter(daemon_filter)
+                .boxed(),
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(container_writer)
+                .with_filter(container_filter)
+                .boxed(),
+        ],
+        LogFormat::Json => vec![
+            fmt::layer()
+                .json()
+                .with_writer(daemon_writer)
+                .with_filter(daemon_filter)
+                .boxed(),
+            fmt::layer()
+                .json()
+                .with_writer(container_writer)
+                .with_filter(container_filter)
+                .boxed(),
+        ],
+    }
+}
+
+/// Filter for daemon logs: the configured levels, then `RUST_LOG`
+fn env_filter(config: &BoltConfig) -> Result<EnvFilter> {
+    let mut directives = config.logging.filter_directives(config.verbose);
+    if let Ok(env) = std::env::var("RUST_LOG") {
+        directives.extend(env.split(',').filter(|d| !d.is_empty()).map(String::from));
+    }
+    EnvFilter::try_new(directives.join(",")).context("Invalid logging level configuration")
+}
+
+/// Apply the log levels of a reloaded config. Does nothing when logging
+/// wasn't set up by `init`.
+pub fn set_levels(config: &BoltConfig) -> Result<()> {
+    let filter = env_filter(config)?;
+    if let Some(handle) = DAEMON_FILTER.get() {
+        handle
+            .reload(filter)
+            .context("Failed to apply the new log levels")?;
+    }
+    Ok(())
+}
+
+/// Open (append) the file that receives a container's raw output. Open it
+/// before entering the container's mount namespace; the handle stays valid after.
+pub fn open_container_log(log_dir: &Path, container: &str) -> Result<(PathBuf, std::fs::File)> {
+    std::fs::create_dir_all(log_dir)?;
+    let path = log_dir.join(format!("{}.log", container));
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open container log {:?}", path))?;
+    Ok((path, file))
+}
+
+/// Forward one of a container's output streams, line by line, to its log
+/// file and to the container log target
+pub async fn forward_container_output<R>(
+    container: String,
+    stream: &'static str,
+    reader: R,
+    log_file: Option<std::fs::File>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut file = log_file.map(tokio::fs::File::from_std);
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::info!(target: CONTAINER_LOG_TARGET, container = %container, stream, "{}", line);
+
+        if let Some(ref mut f) = file {
+            if let Err(e) = f.write_all(format!("{}\n", line).as_bytes()).await {
+                warn!("Stopped writing log file for {}: {}", container, e);
+                file = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn builds_subsystem_directives() {
+        let mut config = BoltConfig::default();
+        config.logging.level = "warn".to_string();
+        config.logging.subsystems = HashMap::from([
+            ("networking".to_string(), "debug".to_string()),
+            ("bolt::volume::cache".to_string(), "trace".to_string()),
+        ]);
+        assert_eq!(
+            config.logging.filter_directives(false),
+            [
+                "warn",
+                "bolt::volume::cache=trace",
+                "bolt::network=debug",
+                "bolt::networking=debug",
+            ]
+        );
+        assert_eq!(config.logging.filter_directives(true)[0], "debug");
+        EnvFilter::try_new(config.logging.filter_directives(false).join(",")).unwrap();
+    }
+
+    #[test]
+    fn routes_container_output_apart_from_daemon_logs() {
+        for format in [LogFormat::Text, LogFormat::Json] {
+            let daemon = Buffer::default();
+            let container = Buffer::default();
+            let subscriber = Registry::default().with(split_layers(
+                format,
+                EnvFilter::new("info"),
+                {
+                    let daemon = daemon.clone();
+                    move || daemon.clone()
+                },
+                {
+                    let container = container.clone();
+                    move || container.clone()
+                },
+            ));
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!("daemon started");
+                tracing::debug!("below the daemon level");
+                tracing::debug!(target: CONTAINER_LOG_TARGET, container = "web", "hello from web");
+            });
+
+            let (daemon, container) = (daemon.text(), container.text());
+            assert!(daemon.contains("daemon started"));
+            assert!(!daemon.contains("below the daemon level"));
+            assert!(!daemon.contains("hello from web"));
+            assert!(container.contains("hello from web"));
+            assert!(!container.contains("daemon started"));
+        }
+    }
+}
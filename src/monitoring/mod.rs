@@ -1,13 +1,23 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+use crate::config::{BoltConfig, MonitoringSettings};
+use prometheus::MetricFamily;
+
+pub mod alerts;
+pub mod capacity;
 pub mod health;
+pub mod history;
+pub mod logging;
 pub mod prometheus;
+pub mod slo;
+pub mod stats;
 pub mod tracing_setup;
 
 /// Comprehensive monitoring system for Bolt
@@ -27,6 +37,13 @@ pub struct MetricsCollector {
     storage_metrics: Arc<RwLock<HashMap<String, StorageMetrics>>>,
     system_metrics: Arc<RwLock<SystemMetrics>>,
     runtime_metrics: Arc<RwLock<RuntimeMetrics>>,
+    gaming_metrics: Arc<RwLock<GamingMetrics>>,
+    /// Last cgroup sample of every running container, to compute CPU usage from
+    container_samples: Arc<RwLock<HashMap<String, stats::Sample>>>,
+    settings: MonitoringSettings,
+    /// Where thermal events and multi-GPU reports are read from; the
+    /// default data directory when unset
+    data_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +61,9 @@ pub struct ContainerMetrics {
     pub uptime_seconds: u64,
     pub restart_count: u32,
     pub exit_code: Option<i32>,
+    /// Labels of the container, exported when listed in `[monitoring] container_labels`
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
     pub last_updated: SystemTime,
 }
 
@@ -62,6 +82,19 @@ pub struct GPUMetrics {
     pub last_updated: SystemTime,
 }
 
+/// GPU state kept by Bolt's gaming and AI features
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GamingMetrics {
+    /// How often each thermal action was taken
+    pub thermal_actions: BTreeMap<String, u64>,
+    /// Containers a thermal policy currently holds back, with the action taken
+    pub throttled: BTreeMap<String, String>,
+    /// Containers started in GPU AI mode
+    pub multi_gpu: Vec<crate::gaming::multi_gpu::AiReport>,
+    /// Fractional GPU slices, as last sampled by the GPU arbiter
+    pub slices: Vec<crate::gaming::fraction::Slice>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkMetrics {
     pub interface_name: String,
@@ -128,23 +161,36 @@ pub struct RuntimeMetrics {
     pub ebpf_programs_loaded: u32,
     pub gpu_containers: u32,
     pub average_startup_time_ms: f64,
+    /// p50/p95 per startup phase across recent container starts
+    #[serde(default)]
+    pub startup_phases: Vec<crate::runtime::startup::PhaseStats>,
     pub api_requests_total: u64,
     pub api_requests_failed: u64,
     pub last_updated: SystemTime,
 }
 
 impl MonitoringSystem {
-    /// Create new monitoring system
-    pub async fn new() -> Result<Self> {
+    /// Create new monitoring system, configured by `[monitoring]`
+    pub async fn new(config: &BoltConfig) -> Result<Self> {
         info!("🔍 Initializing comprehensive monitoring system");
 
-        let metrics_collector = Arc::new(MetricsCollector::new().await?);
+        let metrics_collector = Arc::new(MetricsCollector::with_config(config).await?);
         let health_checker = Arc::new(health::HealthChecker::new().await?);
         let tracing_config = tracing_setup::TracingConfig::default();
 
         // Initialize Prometheus exporter
-        let prometheus_exporter =
-            Some(prometheus::PrometheusExporter::new(Arc::clone(&metrics_collector)).await?);
+        let prometheus_exporter = if config.monitoring.enabled {
+            Some(
+                prometheus::PrometheusExporter::new(
+                    Arc::clone(&metrics_collector),
+                    config.monitoring.clone(),
+                )
+                .await?,
+            )
+        } else {
+            info!("📊 Prometheus endpoint disabled in [monitoring]");
+            None
+        };
 
         let system = Self {
             metrics_collector,
@@ -238,6 +284,20 @@ impl MetricsCollector {
             storage_metrics: Arc::new(RwLock::new(HashMap::new())),
             system_metrics: Arc::new(RwLock::new(SystemMetrics::default())),
             runtime_metrics: Arc::new(RwLock::new(RuntimeMetrics::default())),
+            gaming_metrics: Arc::new(RwLock::new(GamingMetrics::default())),
+            container_samples: Arc::new(RwLock::new(HashMap::new())),
+            settings: MonitoringSettings::default(),
+            data_dir: None,
+        })
+    }
+
+    /// Create a metrics collector that only collects the families enabled
+    /// in `[monitoring] collectors`
+    pub async fn with_config(config: &BoltConfig) -> Result<Self> {
+        Ok(Self {
+            settings: config.monitoring.clone(),
+            data_dir: Some(config.data_dir.clone()),
+            ..Self::new().await?
         })
     }
 
@@ -249,33 +309,72 @@ impl MetricsCollector {
 
         loop {
             interval.tick().await;
+            self.collect_once().await;
+        }
+    }
 
-            // Collect system metrics
-            if let Err(e) = self.collect_system_metrics().await {
-                warn!("Failed to collect system metrics: {}", e);
-            }
+    /// Run one collection cycle over the enabled metric families
+    pub async fn collect_once(&self) {
+        // Collect system metrics
+        if self.settings.collects(MetricFamily::System)
+            && let Err(e) = self.collect_system_metrics().await
+        {
+            warn!("Failed to collect system metrics: {}", e);
+        }
 
-            // Collect container metrics
-            if let Err(e) = self.collect_container_metrics().await {
-                warn!("Failed to collect container metrics: {}", e);
-            }
+        // Collect container metrics
+        if self.settings.collects(MetricFamily::Containers)
+            && let Err(e) = self.collect_container_metrics().await
+        {
+            warn!("Failed to collect container metrics: {}", e);
+        }
 
-            // Collect GPU metrics
+        // Collect GPU metrics and which containers hold them
+        if self.settings.collects(MetricFamily::Gpu) {
             if let Err(e) = self.collect_gpu_metrics().await {
                 warn!("Failed to collect GPU metrics: {}", e);
             }
-
-            // Collect network metrics
-            if let Err(e) = self.collect_network_metrics().await {
-                warn!("Failed to collect network metrics: {}", e);
+            if let Err(e) = self.collect_gpu_assignments().await {
+                debug!("Failed to collect GPU assignments: {}", e);
             }
+        }
 
-            // Collect storage metrics
-            if let Err(e) = self.collect_storage_metrics().await {
-                warn!("Failed to collect storage metrics: {}", e);
-            }
+        // Collect thermal and multi-GPU state
+        if self.settings.collects(MetricFamily::Gaming)
+            && let Err(e) = self.collect_gaming_metrics().await
+        {
+            warn!("Failed to collect gaming metrics: {}", e);
+        }
 
-            debug!("📈 Metrics collection cycle completed");
+        // Collect network metrics
+        if self.settings.collects(MetricFamily::Network)
+            && let Err(e) = self.collect_network_metrics().await
+        {
+            warn!("Failed to collect network metrics: {}", e);
+        }
+
+        // Collect storage metrics
+        if self.settings.collects(MetricFamily::Storage)
+            && let Err(e) = self.collect_storage_metrics().await
+        {
+            warn!("Failed to collect storage metrics: {}", e);
+        }
+
+        // Collect container startup timings
+        if self.settings.collects(MetricFamily::Runtime)
+            && let Err(e) = self.collect_startup_metrics().await
+        {
+            warn!("Failed to collect startup metrics: {}", e);
+        }
+
+        debug!("📈 Metrics collection cycle completed");
+    }
+
+    /// The data directory given at creation, or the default one
+    fn data_dir(&self) -> Result<PathBuf> {
+        match self.data_dir {
+            Some(ref data_dir) => Ok(data_dir.clone()),
+            None => Ok(BoltConfig::load()?.data_dir),
         }
     }
 
@@ -317,13 +416,117 @@ impl MetricsCollector {
 
     /// Collect container metrics
     async fn collect_container_metrics(&self) -> Result<()> {
-        // In a real implementation, this would:
-        // 1. List all running containers
-        // 2. Read cgroup stats for each container
-        // 3. Calculate CPU/memory/network usage
-        // 4. Update container metrics
-
         debug!("Collecting container metrics");
+
+        let mut seen = HashSet::new();
+        let mut sampled = HashSet::new();
+        let mut samples = self.container_samples.write().await;
+        match crate::runtime::list_containers_info(false).await {
+            Ok(containers) => {
+                for container in containers {
+                    // `docker ps` has no full ID in its JSON, only the name
+                    let reference = if container.id.is_empty() {
+                        &container.name
+                    } else {
+                        &container.id
+                    };
+                    let previous = samples.get(reference);
+                    match stats::snapshot(reference, previous).await {
+                        Ok((metric, sample)) => {
+                            match sample {
+                                Some(sample) => samples.insert(reference.clone(), sample),
+                                None => samples.remove(reference),
+                            };
+                            sampled.insert(reference.clone());
+                            seen.insert(metric.container_id.clone());
+                            self.record_container_metric(metric).await;
+                        }
+                        Err(e) => debug!("Failed to sample {}: {}", reference, e),
+                    }
+                }
+            }
+            Err(e) => debug!("No containers to collect metrics from: {}", e),
+        }
+
+        // WASM workloads are plain processes that Bolt supervises itself
+        let data_dir = self.data_dir()?;
+        for state in crate::runtime::wasm::list(&data_dir) {
+            if let Some(metric) = crate::runtime::wasm::metrics(&data_dir, &state) {
+                seen.insert(metric.container_id.clone());
+                self.record_container_metric(metric).await;
+            }
+        }
+
+        // Containers that are gone stop being exported
+        samples.retain(|reference, _| sampled.contains(reference));
+        self.container_metrics
+            .write()
+            .await
+            .retain(|id, _| seen.contains(id));
+        Ok(())
+    }
+
+    /// Fill in which running container holds each GPU
+    async fn collect_gpu_assignments(&self) -> Result<()> {
+        use crate::gaming::topology::{self, GpuTopology};
+
+        let runtime = crate::runtime::detect_container_runtime().await?;
+        let held = topology::in_use(&runtime).await;
+        let topology = if held.is_empty() {
+            GpuTopology::default()
+        } else {
+            GpuTopology::detect().await?
+        };
+
+        for metric in self.gpu_metrics.write().await.values_mut() {
+            metric.container_assignments = gpu_pci_address(&metric.gpu_id, &topology)
+                .and_then(|address| held.get(&address).cloned())
+                .into_iter()
+                .collect();
+        }
+        self.runtime_metrics.write().await.gpu_containers =
+            held.values().collect::<HashSet<_>>().len() as u32;
+        Ok(())
+    }
+
+    /// Collect thermal actions and multi-GPU reports from the data directory
+    async fn collect_gaming_metrics(&self) -> Result<()> {
+        let data_dir = self.data_dir()?;
+        let mut gaming = GamingMetrics::default();
+        for event in crate::gaming::thermal::read_events(&data_dir, None)? {
+            *gaming
+                .thermal_actions
+                .entry(event.action.clone())
+                .or_default() += 1;
+            match event.action.as_str() {
+                "pause" | "frame-limit" | "notify" => {
+                    gaming.throttled.insert(event.container, event.action);
+                }
+                _ => {
+                    gaming.throttled.remove(&event.container);
+                }
+            }
+        }
+        gaming.multi_gpu = crate::gaming::multi_gpu::reports(&data_dir);
+        gaming.slices = crate::gaming::fraction::slices(&data_dir);
+
+        *self.gaming_metrics.write().await = gaming;
+        Ok(())
+    }
+
+    /// Aggregate recorded container startups into p50/p95 per phase
+    async fn collect_startup_metrics(&self) -> Result<()> {
+        let config = crate::config::BoltConfig::load()?;
+        let history = crate::runtime::startup::history(&config.data_dir);
+        let phases = crate::runtime::startup::aggregate(&history);
+
+        let mut runtime_metrics = self.runtime_metrics.write().await;
+        runtime_metrics.average_startup_time_ms = if history.is_empty() {
+            0.0
+        } else {
+            history.iter().map(|t| t.total_ms).sum::<f64>() / history.len() as f64
+        };
+        runtime_metrics.startup_phases = phases;
         Ok(())
     }
 
@@ -366,9 +569,81 @@ impl MetricsCollector {
             }
         }
 
+        // Without NVML, fall back to nvidia-smi
+        #[cfg(not(feature = "nvidia-support"))]
+        self.collect_nvidia_smi_metrics().await?;
+
         // Collect AMD GPU metrics via sysfs
         self.collect_amd_gpu_metrics().await?;
 
+        #[cfg(feature = "gaming")]
+        self.collect_intel_gpu_metrics().await;
+
+        Ok(())
+    }
+
+    /// Collect Intel GPU metrics from sysfs, sampled over a quarter second
+    #[cfg(feature = "gaming")]
+    async fn collect_intel_gpu_metrics(&self) {
+        let samples = crate::runtime::gpu::intel::sample(Duration::from_millis(250)).await;
+        for (i, sample) in samples.into_iter().enumerate() {
+            self.record_gpu_metric(GPUMetrics {
+                gpu_id: format!("intel-gpu-{}", i),
+                gpu_name: format!("Intel GPU {} ({})", sample.pci_bus_id, sample.driver),
+                gpu_vendor: "Intel".to_string(),
+                utilization_percent: sample.busy_percent.unwrap_or(0.0),
+                memory_used_bytes: 0, // Integrated GPUs share system memory
+                memory_total_bytes: 0,
+                temperature_celsius: sample.temperature_celsius.unwrap_or(0.0),
+                power_usage_watts: sample.power_watts.unwrap_or(0.0),
+                fan_speed_percent: 0.0,
+                container_assignments: Vec::new(),
+                last_updated: SystemTime::now(),
+            })
+            .await;
+        }
+    }
+
+    /// Collect NVIDIA GPU metrics from nvidia-smi
+    #[cfg(not(feature = "nvidia-support"))]
+    async fn collect_nvidia_smi_metrics(&self) -> Result<()> {
+        let output = match tokio::process::Command::new("nvidia-smi")
+            .args([
+                "--query-gpu=index,name,utilization.gpu,memory.used,memory.total,temperature.gpu,power.draw,fan.speed",
+                "--format=csv,noheader,nounits",
+            ])
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => output,
+            _ => {
+                debug!("nvidia-smi not available, skipping NVIDIA GPU metrics");
+                return Ok(());
+            }
+        };
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            if fields.len() < 8 {
+                continue;
+            }
+            let number = |i: usize| fields[i].parse::<f64>().unwrap_or(0.0);
+            self.record_gpu_metric(GPUMetrics {
+                gpu_id: format!("gpu-{}", fields[0]),
+                gpu_name: fields[1].to_string(),
+                gpu_vendor: "NVIDIA".to_string(),
+                utilization_percent: number(2),
+                memory_used_bytes: (number(3) * 1024.0 * 1024.0) as u64,
+                memory_total_bytes: (number(4) * 1024.0 * 1024.0) as u64,
+                temperature_celsius: number(5),
+                power_usage_watts: number(6),
+                fan_speed_percent: number(7),
+                container_assignments: Vec::new(),
+                last_updated: SystemTime::now(),
+            })
+            .await;
+        }
+
         Ok(())
     }
 
@@ -380,7 +655,7 @@ impl MetricsCollector {
         let amd_gpu_paths = ["/sys/class/drm/card0/device", "/sys/class/drm/card1/device"];
 
         for (i, path) in amd_gpu_paths.iter().enumerate() {
-            if std::path::Path::new(path).exists() {
+            if std::path::Path::new(path).exists() && self.read_amd_gpu_name(path).await.is_some() {
                 let gpu_metrics = GPUMetrics {
                     gpu_id: format!("amd-gpu-{}", i),
                     gpu_name: self
@@ -687,15 +962,52 @@ impl MetricsCollector {
         Some(0)
     }
 
-    async fn read_amd_gpu_temperature(&self, _path: &str) -> Option<f64> {
-        Some(0.0)
+    async fn read_amd_gpu_temperature(&self, path: &str) -> Option<f64> {
+        // Edge temperature in millidegrees
+        read_hwmon(path, "temp1_input").map(|t| t / 1000.0)
     }
 
-    async fn read_amd_gpu_power(&self, _path: &str) -> Option<f64> {
-        Some(0.0)
+    async fn read_amd_gpu_power(&self, path: &str) -> Option<f64> {
+        // Average board power in microwatts
+        read_hwmon(path, "power1_average")
+            .or_else(|| read_hwmon(path, "power1_input"))
+            .map(|p| p / 1_000_000.0)
+    }
+
+    /// Collect GPU metrics now and return the latest sample of every GPU
+    pub async fn sample_gpus(&self) -> Result<Vec<GPUMetrics>> {
+        self.collect_gpu_metrics().await?;
+        Ok(self.gpu_metrics.read().await.values().cloned().collect())
     }
 }
 
+/// PCI address of a GPU by its metrics ID: `gpu-<n>` is the n-th NVIDIA GPU
+/// and `amd-gpu-<n>` is DRM card n
+fn gpu_pci_address(
+    gpu_id: &str,
+    topology: &crate::gaming::topology::GpuTopology,
+) -> Option<String> {
+    if let Some(card) = gpu_id.strip_prefix("amd-gpu-") {
+        let device = std::fs::canonicalize(format!("/sys/class/drm/card{}/device", card)).ok()?;
+        return Some(device.file_name()?.to_string_lossy().to_string());
+    }
+    let index: u32 = gpu_id.strip_prefix("gpu-")?.parse().ok()?;
+    topology
+        .gpus
+        .iter()
+        .find(|gpu| gpu.nvidia_index == Some(index))
+        .map(|gpu| gpu.pci_bus_id.clone())
+}
+
+/// Read a value from the hwmon directory of a DRM device
+fn read_hwmon(device: &str, file: &str) -> Option<f64> {
+    std::fs::read_dir(format!("{}/hwmon", device))
+        .ok()?
+        .flatten()
+        .find_map(|hwmon| std::fs::read_to_string(hwmon.path().join(file)).ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct SystemStatus {
     pub overall_health: String,
@@ -772,6 +1084,7 @@ impl Default for RuntimeMetrics {
             ebpf_programs_loaded: 0,
             gpu_containers: 0,
             average_startup_time_ms: 0.0,
+            startup_phases: Vec::new(),
             api_requests_total: 0,
             api_requests_failed: 0,
             last_updated: UNIX_EPOCH,
@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -8,17 +9,51 @@ use tracing::{info, warn};
 use warp::Filter;
 
 use super::{
-    ContainerMetrics, GPUMetrics, MetricsCollector, NetworkMetrics, RuntimeMetrics, StorageMetrics,
-    SystemMetrics,
+    ContainerMetrics, GPUMetrics, GamingMetrics, MetricsCollector, NetworkMetrics, RuntimeMetrics,
+    StorageMetrics, SystemMetrics,
 };
+use crate::config::MonitoringSettings;
 
 /// Prometheus metrics exporter for Bolt
 pub struct PrometheusExporter {
     metrics_collector: Arc<MetricsCollector>,
-    server_addr: String,
+    settings: Arc<MonitoringSettings>,
     custom_metrics: Arc<RwLock<HashMap<String, CustomMetric>>>,
 }
 
+/// A group of metrics that can be turned on or off in `[monitoring] collectors`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricFamily {
+    /// Host uptime, load, CPU, memory, swap and disk
+    System,
+    /// Per-container CPU, memory, network and uptime
+    Containers,
+    /// Per-GPU utilization, memory, temperature, power, fans and assignments
+    Gpu,
+    /// Thermal actions and multi-GPU P2P state of gaming and AI containers
+    Gaming,
+    Network,
+    Storage,
+    /// Bolt's own counts and container startup timings
+    Runtime,
+    /// Metrics recorded through `add_custom_metric`
+    Custom,
+}
+
+impl MetricFamily {
+    pub const ALL: [MetricFamily; 8] = [
+        MetricFamily::System,
+        MetricFamily::Containers,
+        MetricFamily::Gpu,
+        MetricFamily::Gaming,
+        MetricFamily::Network,
+        MetricFamily::Storage,
+        MetricFamily::Runtime,
+        MetricFamily::Custom,
+    ];
+}
+
 #[derive(Debug, Clone)]
 pub struct CustomMetric {
     pub name: String,
@@ -38,32 +73,43 @@ pub enum MetricType {
 
 impl PrometheusExporter {
     /// Create new Prometheus exporter
-    pub async fn new(metrics_collector: Arc<MetricsCollector>) -> Result<Self> {
+    pub async fn new(
+        metrics_collector: Arc<MetricsCollector>,
+        settings: MonitoringSettings,
+    ) -> Result<Self> {
         info!("📊 Initializing Prometheus metrics exporter");
 
         Ok(Self {
             metrics_collector,
-            server_addr: "0.0.0.0:9090".to_string(),
+            settings: Arc::new(settings),
             custom_metrics: Arc::new(RwLock::new(HashMap::new())),
         })
     }
 
     /// Start Prometheus metrics server
     pub async fn start_server(&self) -> Result<()> {
-        info!(
-            "🚀 Starting Prometheus metrics server on {}",
-            self.server_addr
-        );
+        let bind_addr = self.settings.bind_addr()?;
+        let tls = self
+            .settings
+            .tls_cert
+            .clone()
+            .zip(self.settings.tls_key.clone());
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        info!("🚀 Starting Prometheus metrics server on {}", bind_addr);
 
         let metrics_collector = Arc::clone(&self.metrics_collector);
         let custom_metrics = Arc::clone(&self.custom_metrics);
+        let settings = Arc::clone(&self.settings);
 
         // Create metrics endpoint
         let metrics_route = warp::path("metrics").and(warp::get()).and_then(move || {
             let metrics_collector = Arc::clone(&metrics_collector);
             let custom_metrics = Arc::clone(&custom_metrics);
+            let settings = Arc::clone(&settings);
             async move {
-                match generate_prometheus_metrics(metrics_collector, custom_metrics).await {
+                match generate_prometheus_metrics(metrics_collector, custom_metrics, &settings)
+                    .await
+                {
                     Ok(metrics) => Ok(warp::reply::with_header(
                         metrics,
                         "content-type",
@@ -100,19 +146,35 @@ impl PrometheusExporter {
 
         let routes = metrics_route.or(health_route).or(info_route);
 
-        // Parse bind address
-        let bind_addr: std::net::SocketAddr = self.server_addr.parse()?;
+        // warp panics inside the server task when it can't bind, so find
+        // out here while the error can still be returned
+        drop(
+            TcpListener::bind(bind_addr)
+                .await
+                .with_context(|| format!("Failed to listen on {}", bind_addr))?,
+        );
 
-        tokio::spawn(async move {
-            warp::serve(routes).run(bind_addr).await;
-        });
+        match tls {
+            Some((cert, key)) => {
+                tokio::spawn(
+                    warp::serve(routes)
+                        .tls()
+                        .cert_path(cert)
+                        .key_path(key)
+                        .run(bind_addr),
+                );
+            }
+            None => {
+                tokio::spawn(warp::serve(routes).run(bind_addr));
+            }
+        }
 
         info!("✅ Prometheus metrics server started successfully");
         info!(
-            "📊 Metrics available at: http://{}/metrics",
-            self.server_addr
+            "📊 Metrics available at: {}://{}/metrics",
+            scheme, bind_addr
         );
-        info!("❤️ Health check at: http://{}/health", self.server_addr);
+        info!("❤️ Health check at: {}://{}/health", scheme, bind_addr);
 
         Ok(())
     }
@@ -122,6 +184,7 @@ impl PrometheusExporter {
         match generate_prometheus_metrics(
             Arc::clone(&self.metrics_collector),
             Arc::clone(&self.custom_metrics),
+            &self.settings,
         )
         .await
         {
@@ -220,6 +283,7 @@ impl PrometheusExporter {
 async fn generate_prometheus_metrics(
     metrics_collector: Arc<MetricsCollector>,
     custom_metrics: Arc<RwLock<HashMap<String, CustomMetric>>>,
+    settings: &MonitoringSettings,
 ) -> Result<String> {
     let mut output = String::new();
 
@@ -234,27 +298,23 @@ async fn generate_prometheus_metrics(
     ));
     output.push('\n');
 
-    // System metrics
-    let system_metrics = metrics_collector.get_system_metrics().await;
-    output.push_str(&format_system_metrics(&system_metrics));
-
-    // Container metrics
-    output.push_str(&format_container_metrics(&metrics_collector).await);
-
-    // GPU metrics
-    output.push_str(&format_gpu_metrics(&metrics_collector).await);
-
-    // Network metrics
-    output.push_str(&format_network_metrics(&metrics_collector).await);
-
-    // Storage metrics
-    output.push_str(&format_storage_metrics(&metrics_collector).await);
-
-    // Runtime metrics
-    output.push_str(&format_runtime_metrics(&metrics_collector).await);
-
-    // Custom metrics
-    output.push_str(&format_custom_metrics(&custom_metrics).await);
+    for family in &settings.collectors {
+        let metrics = match family {
+            MetricFamily::System => {
+                format_system_metrics(&metrics_collector.get_system_metrics().await)
+            }
+            MetricFamily::Containers => {
+                format_container_metrics(&metrics_collector, &settings.container_labels).await
+            }
+            MetricFamily::Gpu => format_gpu_metrics(&metrics_collector).await,
+            MetricFamily::Gaming => format_gaming_metrics(&metrics_collector).await,
+            MetricFamily::Network => format_network_metrics(&metrics_collector).await,
+            MetricFamily::Storage => format_storage_metrics(&metrics_collector).await,
+            MetricFamily::Runtime => format_runtime_metrics(&metrics_collector).await,
+            MetricFamily::Custom => format_custom_metrics(&custom_metrics).await,
+        };
+        output.push_str(&metrics);
+    }
 
     Ok(output)
 }
@@ -368,18 +428,27 @@ fn format_system_metrics(metrics: &SystemMetrics) -> String {
 }
 
 /// Format container metrics for Prometheus
-async fn format_container_metrics(metrics_collector: &Arc<MetricsCollector>) -> String {
+async fn format_container_metrics(
+    metrics_collector: &Arc<MetricsCollector>,
+    allowed_labels: &[String],
+) -> String {
     let mut output = String::new();
     let container_metrics = metrics_collector.container_metrics.read().await;
 
     if !container_metrics.is_empty() {
+        let labels: HashMap<&str, String> = container_metrics
+            .values()
+            .map(|m| (m.container_id.as_str(), container_labels(m, allowed_labels)))
+            .collect();
+
         // Container CPU usage
         output.push_str("# HELP bolt_container_cpu_usage_percent Container CPU usage percentage\n");
         output.push_str("# TYPE bolt_container_cpu_usage_percent gauge\n");
-        for (_, metric) in container_metrics.iter() {
+        for (id, metric) in container_metrics.iter() {
             output.push_str(&format!(
-                "bolt_container_cpu_usage_percent{{container_id=\"{}\",name=\"{}\"}} {}\n",
-                metric.container_id, metric.name, metric.cpu_usage_percent
+                "bolt_container_cpu_usage_percent{{{}}} {}\n",
+                labels[id.as_str()],
+                metric.cpu_usage_percent
             ));
         }
         output.push('\n');
@@ -387,14 +456,16 @@ async fn format_container_metrics(metrics_collector: &Arc<MetricsCollector>) ->
         // Container memory usage
         output.push_str("# HELP bolt_container_memory_bytes Container memory usage in bytes\n");
         output.push_str("# TYPE bolt_container_memory_bytes gauge\n");
-        for (_, metric) in container_metrics.iter() {
+        for (id, metric) in container_metrics.iter() {
             output.push_str(&format!(
-                "bolt_container_memory_bytes{{container_id=\"{}\",name=\"{}\",type=\"used\"}} {}\n",
-                metric.container_id, metric.name, metric.memory_usage_bytes
+                "bolt_container_memory_bytes{{{},type=\"used\"}} {}\n",
+                labels[id.as_str()],
+                metric.memory_usage_bytes
             ));
             output.push_str(&format!(
-                "bolt_container_memory_bytes{{container_id=\"{}\",name=\"{}\",type=\"limit\"}} {}\n",
-                metric.container_id, metric.name, metric.memory_limit_bytes
+                "bolt_container_memory_bytes{{{},type=\"limit\"}} {}\n",
+                labels[id.as_str()],
+                metric.memory_limit_bytes
             ));
         }
         output.push('\n');
@@ -402,14 +473,33 @@ async fn format_container_metrics(metrics_collector: &Arc<MetricsCollector>) ->
         // Container network metrics
         output.push_str("# HELP bolt_container_network_bytes Container network traffic in bytes\n");
         output.push_str("# TYPE bolt_container_network_bytes counter\n");
-        for (_, metric) in container_metrics.iter() {
+        for (id, metric) in container_metrics.iter() {
+            output.push_str(&format!(
+                "bolt_container_network_bytes{{{},direction=\"rx\"}} {}\n",
+                labels[id.as_str()],
+                metric.network_rx_bytes
+            ));
+            output.push_str(&format!(
+                "bolt_container_network_bytes{{{},direction=\"tx\"}} {}\n",
+                labels[id.as_str()],
+                metric.network_tx_bytes
+            ));
+        }
+        output.push('\n');
+
+        // Container disk I/O
+        output.push_str("# HELP bolt_container_disk_bytes Container block I/O in bytes\n");
+        output.push_str("# TYPE bolt_container_disk_bytes counter\n");
+        for (id, metric) in container_metrics.iter() {
             output.push_str(&format!(
-                "bolt_container_network_bytes{{container_id=\"{}\",name=\"{}\",direction=\"rx\"}} {}\n",
-                metric.container_id, metric.name, metric.network_rx_bytes
+                "bolt_container_disk_bytes{{{},operation=\"read\"}} {}\n",
+                labels[id.as_str()],
+                metric.disk_read_bytes
             ));
             output.push_str(&format!(
-                "bolt_container_network_bytes{{container_id=\"{}\",name=\"{}\",direction=\"tx\"}} {}\n",
-                metric.container_id, metric.name, metric.network_tx_bytes
+                "bolt_container_disk_bytes{{{},operation=\"write\"}} {}\n",
+                labels[id.as_str()],
+                metric.disk_write_bytes
             ));
         }
         output.push('\n');
@@ -417,10 +507,23 @@ async fn format_container_metrics(metrics_collector: &Arc<MetricsCollector>) ->
         // Container uptime
         output.push_str("# HELP bolt_container_uptime_seconds Container uptime in seconds\n");
         output.push_str("# TYPE bolt_container_uptime_seconds gauge\n");
-        for (_, metric) in container_metrics.iter() {
+        for (id, metric) in container_metrics.iter() {
+            output.push_str(&format!(
+                "bolt_container_uptime_seconds{{{}}} {}\n",
+                labels[id.as_str()],
+                metric.uptime_seconds
+            ));
+        }
+        output.push('\n');
+
+        // Container restarts
+        output.push_str("# HELP bolt_container_restarts_total Times the container was restarted\n");
+        output.push_str("# TYPE bolt_container_restarts_total counter\n");
+        for (id, metric) in container_metrics.iter() {
             output.push_str(&format!(
-                "bolt_container_uptime_seconds{{container_id=\"{}\",name=\"{}\"}} {}\n",
-                metric.container_id, metric.name, metric.uptime_seconds
+                "bolt_container_restarts_total{{{}}} {}\n",
+                labels[id.as_str()],
+                metric.restart_count
             ));
         }
         output.push('\n');
@@ -429,6 +532,39 @@ async fn format_container_metrics(metrics_collector: &Arc<MetricsCollector>) ->
     output
 }
 
+/// `container_id` and `name`, followed by the allowed container labels the
+/// container has, e.g. `com.example.team` as `label_com_example_team`
+fn container_labels(metric: &ContainerMetrics, allowed: &[String]) -> String {
+    let mut labels = format!(
+        "container_id=\"{}\",name=\"{}\"",
+        escape(&metric.container_id),
+        escape(&metric.name)
+    );
+    for key in allowed {
+        if let Some(value) = metric.labels.get(key) {
+            labels.push_str(&format!(",{}=\"{}\"", label_name(key), escape(value)));
+        }
+    }
+    labels
+}
+
+/// A container label key as a Prometheus label name
+fn label_name(key: &str) -> String {
+    let sanitized: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("label_{}", sanitized)
+}
+
+/// Escape a label value for the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 /// Format GPU metrics for Prometheus
 async fn format_gpu_metrics(metrics_collector: &Arc<MetricsCollector>) -> String {
     let mut output = String::new();
@@ -482,6 +618,154 @@ async fn format_gpu_metrics(metrics_collector: &Arc<MetricsCollector>) -> String
             ));
         }
         output.push('\n');
+
+        // GPU fan speed
+        output.push_str("# HELP bolt_gpu_fan_speed_percent GPU fan speed percentage\n");
+        output.push_str("# TYPE bolt_gpu_fan_speed_percent gauge\n");
+        for (_, metric) in gpu_metrics.iter() {
+            output.push_str(&format!(
+                "bolt_gpu_fan_speed_percent{{gpu_id=\"{}\",name=\"{}\"}} {}\n",
+                metric.gpu_id, metric.gpu_name, metric.fan_speed_percent
+            ));
+        }
+        output.push('\n');
+
+        // Which container holds which GPU
+        output.push_str(
+            "# HELP bolt_gpu_container_assignment GPUs placed into a running container\n",
+        );
+        output.push_str("# TYPE bolt_gpu_container_assignment gauge\n");
+        for (_, metric) in gpu_metrics.iter() {
+            for container in &metric.container_assignments {
+                output.push_str(&format!(
+                    "bolt_gpu_container_assignment{{gpu_id=\"{}\",container=\"{}\"}} 1\n",
+                    metric.gpu_id,
+                    escape(container)
+                ));
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Format gaming and AI GPU metrics for Prometheus
+async fn format_gaming_metrics(metrics_collector: &Arc<MetricsCollector>) -> String {
+    let gaming = metrics_collector.gaming_metrics.read().await;
+    render_gaming_metrics(&gaming)
+}
+
+/// Reads one `bolt_gpu_slice_*` value off a slice
+type SliceGauge = fn(&crate::gaming::fraction::Slice) -> Option<f64>;
+
+fn render_gaming_metrics(gaming: &GamingMetrics) -> String {
+    let mut output = String::new();
+
+    if !gaming.thermal_actions.is_empty() {
+        output.push_str(
+            "# HELP bolt_gpu_thermal_actions_total Actions taken by GPU thermal policies\n",
+        );
+        output.push_str("# TYPE bolt_gpu_thermal_actions_total counter\n");
+        for (action, count) in &gaming.thermal_actions {
+            output.push_str(&format!(
+                "bolt_gpu_thermal_actions_total{{action=\"{}\"}} {}\n",
+                action, count
+            ));
+        }
+        output.push('\n');
+
+        output.push_str(
+            "# HELP bolt_gpu_thermal_throttled Containers a thermal policy is holding back\n",
+        );
+        output.push_str("# TYPE bolt_gpu_thermal_throttled gauge\n");
+        for (container, action) in &gaming.throttled {
+            output.push_str(&format!(
+                "bolt_gpu_thermal_throttled{{container=\"{}\",action=\"{}\"}} 1\n",
+                escape(container),
+                action
+            ));
+        }
+        output.push('\n');
+    }
+
+    if !gaming.multi_gpu.is_empty() {
+        output.push_str(
+            "# HELP bolt_gpu_p2p_enabled Whether a multi-GPU container uses peer-to-peer\n",
+        );
+        output.push_str("# TYPE bolt_gpu_p2p_enabled gauge\n");
+        for report in &gaming.multi_gpu {
+            output.push_str(&format!(
+                "bolt_gpu_p2p_enabled{{container=\"{}\",link=\"{}\",gpus=\"{}\"}} {}\n",
+                escape(&report.container),
+                report.link.label(),
+                report.gpus.len(),
+                u8::from(report.p2p)
+            ));
+        }
+        output.push('\n');
+
+        output.push_str(
+            "# HELP bolt_gpu_p2p_min_bandwidth_gbps Slowest GPU pair measured at preflight\n",
+        );
+        output.push_str("# TYPE bolt_gpu_p2p_min_bandwidth_gbps gauge\n");
+        for report in &gaming.multi_gpu {
+            if let Some(bandwidth) = report.min_bandwidth() {
+                output.push_str(&format!(
+                    "bolt_gpu_p2p_min_bandwidth_gbps{{container=\"{}\"}} {}\n",
+                    escape(&report.container),
+                    bandwidth
+                ));
+            }
+        }
+        output.push('\n');
+    }
+
+    if !gaming.slices.is_empty() {
+        let families: [(&str, &str, SliceGauge); 5] = [
+            ("fraction", "Share of its GPU a container was given", |s| {
+                Some(s.fraction)
+            }),
+            ("memory_limit_bytes", "GPU memory a slice may use", |s| {
+                s.memory_limit_mb.map(|mb| (mb * 1024 * 1024) as f64)
+            }),
+            (
+                "memory_used_bytes",
+                "GPU memory a slice's container uses",
+                |s| {
+                    s.usage
+                        .as_ref()
+                        .map(|u| (u.memory_used_mb * 1024 * 1024) as f64)
+                },
+            ),
+            (
+                "utilization_percent",
+                "How busy a slice's container keeps its GPU",
+                |s| s.usage.as_ref().and_then(|u| u.utilization_percent),
+            ),
+            (
+                "over_memory",
+                "Whether a container uses more GPU memory than its share",
+                |s| Some(f64::from(u8::from(s.over_memory()))),
+            ),
+        ];
+        for (name, help, value) in families {
+            output.push_str(&format!("# HELP bolt_gpu_slice_{} {}\n", name, help));
+            output.push_str(&format!("# TYPE bolt_gpu_slice_{} gauge\n", name));
+            for slice in &gaming.slices {
+                if let Some(value) = value(slice) {
+                    output.push_str(&format!(
+                        "bolt_gpu_slice_{}{{container=\"{}\",gpu=\"{}\",mechanism=\"{}\"}} {}\n",
+                        name,
+                        escape(&slice.container),
+                        slice.gpu.pci_bus_id,
+                        slice.mechanism.name(),
+                        value
+                    ));
+                }
+            }
+            output.push('\n');
+        }
     }
 
     output
@@ -655,6 +939,24 @@ async fn format_runtime_metrics(metrics_collector: &Arc<MetricsCollector>) -> St
     ));
     output.push('\n');
 
+    // Container startup phases
+    if !runtime_metrics.startup_phases.is_empty() {
+        output.push_str(&crate::runtime::startup::prometheus(
+            &runtime_metrics.startup_phases,
+        ));
+        output.push('\n');
+    }
+
+    // Expiry of the `[acme]` certificates
+    #[cfg(feature = "quic-networking")]
+    if let Ok(config) = crate::config::BoltConfig::load() {
+        let certificates = crate::networking::acme::status(&config);
+        if !certificates.is_empty() {
+            output.push_str(&crate::networking::acme::prometheus(&certificates));
+            output.push('\n');
+        }
+    }
+
     output
 }
 
@@ -702,3 +1004,71 @@ async fn format_custom_metrics(
 struct MetricsError;
 
 impl warp::reject::Reject for MetricsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exports_enabled_families_with_allowed_container_labels() {
+        let collector = Arc::new(MetricsCollector::new().await.unwrap());
+        collector
+            .record_container_metric(ContainerMetrics {
+                container_id: "abc".to_string(),
+                name: "web".to_string(),
+                status: "running".to_string(),
+                cpu_usage_percent: 12.5,
+                memory_usage_bytes: 1024,
+                memory_limit_bytes: 0,
+                network_rx_bytes: 0,
+                network_tx_bytes: 0,
+                disk_read_bytes: 0,
+                disk_write_bytes: 0,
+                uptime_seconds: 60,
+                restart_count: 0,
+                exit_code: None,
+                labels: HashMap::from([
+                    ("com.example.team".to_string(), "game \"ops\"".to_string()),
+                    ("secret-ish".to_string(), "hidden".to_string()),
+                ]),
+                last_updated: SystemTime::now(),
+            })
+            .await;
+        collector
+            .gaming_metrics
+            .write()
+            .await
+            .throttled
+            .insert("arena".to_string(), "frame-limit".to_string());
+        collector
+            .gaming_metrics
+            .write()
+            .await
+            .thermal_actions
+            .insert("frame-limit".to_string(), 2);
+
+        let settings = MonitoringSettings {
+            collectors: vec![MetricFamily::Containers, MetricFamily::Gaming],
+            container_labels: vec!["com.example.team".to_string()],
+            ..Default::default()
+        };
+        let custom = Arc::new(RwLock::new(HashMap::new()));
+        let output = generate_prometheus_metrics(collector, custom, &settings)
+            .await
+            .unwrap();
+
+        assert!(output.contains(
+            "bolt_container_cpu_usage_percent{container_id=\"abc\",name=\"web\",label_com_example_team=\"game \\\"ops\\\"\"} 12.5"
+        ));
+        assert!(!output.contains("hidden"));
+        assert!(output.contains("bolt_gpu_thermal_actions_total{action=\"frame-limit\"} 2"));
+        assert!(
+            output.contains(
+                "bolt_gpu_thermal_throttled{container=\"arena\",action=\"frame-limit\"} 1"
+            )
+        );
+        // Families that aren't enabled are left out
+        assert!(!output.contains("bolt_system_uptime_seconds"));
+        assert!(!output.contains("bolt_version_info"));
+    }
+}
@@ -0,0 +1,751 @@
+// Service level objectives
+//
+// A service with `slo = { availability = "99.9%", p99_ms = 50 }` gets a
+// `bolt slo-watch` process per container that probes its published TCP
+// ports every `interval`: a connect, or an HTTP request for `http_path`
+// (5xx answers fail). Each minute's probes are counted into a bucket, with a
+// latency histogram, and appended to `<data_dir>/slo/<container>.jsonl`,
+// which keeps the rolling `window` and survives redeploys. Time the watcher
+// doesn't run, like between `surge down` and `surge up`, isn't counted.
+//
+// From the buckets:
+//
+// - availability: the share of probes that succeeded
+// - latency: the share of successful probes faster than `p99_ms`, which must
+//   be at least 99%
+// - error budget: the bad probes each objective allows over the window, and
+//   how much of it is left
+// - burn rate: how fast the budget goes, where 1 spends it exactly over the
+//   window. When it's above `burn_rate` over both the last hour and the last
+//   5 minutes, an alert fires; the short window clears it soon after the
+//   service recovers. Running out of budget raises a warning.
+//
+// `bolt surge slo` shows all of it, also as JSON or Prometheus metrics.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+use super::alerts::{Alert, AlertSeverity, AlertingEngine};
+use crate::builds::cache::parse_duration;
+use crate::config::{BoltConfig, SloConfig};
+use crate::runtime::idle::PublishedPort;
+use crate::runtime::watcher::{self, Watcher};
+
+const DEFAULT_WINDOW: &str = "30d";
+const DEFAULT_INTERVAL: &str = "10s";
+/// Spends a 30 day budget in about two days
+const DEFAULT_BURN_RATE: f64 = 14.4;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Minutes the burn rate is measured over
+const LONG_BURN_WINDOW: i64 = 60;
+const SHORT_BURN_WINDOW: i64 = 5;
+/// How often the history file is rewritten without the buckets that left
+/// the window
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Upper bounds of the latency histogram, in milliseconds; one more bucket
+/// counts the probes slower than all of them
+const LATENCY_BOUNDS_MS: [f64; 18] = [
+    1.0, 2.0, 3.0, 5.0, 7.5, 10.0, 15.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 250.0, 500.0,
+    1000.0, 2500.0, 5000.0,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloSpec {
+    /// Share of probes that must succeed, in percent
+    pub availability: Option<f64>,
+    pub p99_ms: Option<u64>,
+    pub window_secs: u64,
+    pub interval_secs: u64,
+    pub http_path: Option<String>,
+    pub burn_rate: f64,
+    /// TCP ports probed
+    pub ports: Vec<PublishedPort>,
+}
+
+impl SloSpec {
+    pub fn from_config(config: &SloConfig, ports: &[String]) -> Result<Self> {
+        let availability = config
+            .availability
+            .as_deref()
+            .map(parse_percent)
+            .transpose()?;
+        if availability.is_none() && config.p99_ms.is_none() {
+            return Err(anyhow!("slo needs availability, p99_ms or both"));
+        }
+        if config.p99_ms == Some(0) {
+            return Err(anyhow!("slo.p99_ms must be above 0"));
+        }
+        let window = parse_duration(config.window.as_deref().unwrap_or(DEFAULT_WINDOW))?;
+        if window.as_secs() < 3600 {
+            return Err(anyhow!("slo.window must be at least 1h"));
+        }
+        let interval = parse_duration(config.interval.as_deref().unwrap_or(DEFAULT_INTERVAL))?;
+        if interval.is_zero() || interval.as_secs() > 60 {
+            return Err(anyhow!("slo.interval must be between 1s and 60s"));
+        }
+        if let Some(path) = &config.http_path
+            && !path.starts_with('/')
+        {
+            return Err(anyhow!("slo.http_path must start with '/'"));
+        }
+        let burn_rate = config.burn_rate.unwrap_or(DEFAULT_BURN_RATE);
+        if !(burn_rate > 0.0 && burn_rate.is_finite()) {
+            return Err(anyhow!("slo.burn_rate must be above 0"));
+        }
+        Ok(Self {
+            availability,
+            p99_ms: config.p99_ms,
+            window_secs: window.as_secs(),
+            interval_secs: interval.as_secs(),
+            http_path: config.http_path.clone(),
+            burn_rate,
+            ports: ports
+                .iter()
+                .filter_map(|p| PublishedPort::parse(p))
+                .filter(|p| !p.udp)
+                .collect(),
+        })
+    }
+
+    fn window_minutes(&self) -> i64 {
+        (self.window_secs / 60) as i64
+    }
+}
+
+/// "99.9%" or "99.9"
+fn parse_percent(value: &str) -> Result<f64> {
+    let percent: f64 = value
+        .trim()
+        .trim_end_matches('%')
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid slo.availability '{}'", value))?;
+    if !(percent > 0.0 && percent < 100.0) {
+        return Err(anyhow!(
+            "slo.availability must be between 0% and 100%, exclusive"
+        ));
+    }
+    Ok(percent)
+}
+
+/// The probes of one minute
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    /// Start of the minute, in minutes since the Unix epoch
+    pub minute: i64,
+    pub probes: u64,
+    pub failures: u64,
+    /// Successful probes slower than `p99_ms`
+    pub slow: u64,
+    /// Successful probes per `LATENCY_BOUNDS_MS` bucket
+    pub latency: Vec<u64>,
+}
+
+impl Bucket {
+    fn new(minute: i64) -> Self {
+        Self {
+            minute,
+            latency: vec![0; LATENCY_BOUNDS_MS.len() + 1],
+            ..Self::default()
+        }
+    }
+
+    /// Count a probe: its latency in milliseconds, or None when it failed
+    fn record(&mut self, latency_ms: Option<f64>, p99_ms: Option<u64>) {
+        self.probes += 1;
+        let Some(ms) = latency_ms else {
+            self.failures += 1;
+            return;
+        };
+        if p99_ms.is_some_and(|limit| ms > limit as f64) {
+            self.slow += 1;
+        }
+        let index = LATENCY_BOUNDS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BOUNDS_MS.len());
+        self.latency.resize(LATENCY_BOUNDS_MS.len() + 1, 0);
+        self.latency[index] += 1;
+    }
+}
+
+fn current_minute() -> i64 {
+    Utc::now().timestamp().div_euclid(60)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Indicator {
+    Availability,
+    Latency,
+}
+
+impl Indicator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Indicator::Availability => "availability",
+            Indicator::Latency => "latency",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Objective {
+    pub indicator: Indicator,
+    /// Share of probes that must be good, in percent
+    pub target: f64,
+    /// Share of good probes over the window, in percent; None before the
+    /// first probe
+    pub actual: Option<f64>,
+    /// Share of the error budget left, in percent; negative once overspent
+    pub budget_remaining: f64,
+    /// Over the last hour and the last 5 minutes
+    pub burn_rate_long: f64,
+    pub burn_rate_short: f64,
+}
+
+impl Objective {
+    /// Whether the budget burns too fast for `threshold`
+    pub fn burning(&self, threshold: f64) -> bool {
+        self.burn_rate_long > threshold && self.burn_rate_short > threshold
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub probes: u64,
+    pub failures: u64,
+    /// Upper bounds of the histogram buckets the percentiles fall into
+    pub p50_ms: Option<f64>,
+    pub p90_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+/// Good and bad probes of one objective over the buckets since `from`
+fn counts(buckets: &[Bucket], from: i64, indicator: Indicator) -> (u64, u64) {
+    buckets
+        .iter()
+        .filter(|b| b.minute >= from)
+        .fold((0, 0), |(total, bad), b| match indicator {
+            Indicator::Availability => (total + b.probes, bad + b.failures),
+            Indicator::Latency => (total + b.probes - b.failures, bad + b.slow),
+        })
+}
+
+/// Share of the budget a stretch of probes burns per share of the window
+fn burn_rate(total: u64, bad: u64, allowed: f64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    bad as f64 / total as f64 / allowed
+}
+
+fn percentile(histogram: &[u64], quantile: f64) -> Option<f64> {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = (quantile * total as f64).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (index, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return Some(
+                LATENCY_BOUNDS_MS
+                    .get(index)
+                    .copied()
+                    .unwrap_or(f64::INFINITY),
+            );
+        }
+    }
+    None
+}
+
+/// Objectives and latency percentiles of the buckets within the window
+/// ending at `now` (in minutes since the Unix epoch)
+pub fn evaluate(spec: &SloSpec, buckets: &[Bucket], now: i64) -> (Summary, Vec<Objective>) {
+    let start = now - spec.window_minutes();
+    let in_window: Vec<Bucket> = buckets
+        .iter()
+        .filter(|b| b.minute >= start)
+        .cloned()
+        .collect();
+
+    let mut histogram = vec![0u64; LATENCY_BOUNDS_MS.len() + 1];
+    for bucket in &in_window {
+        for (total, count) in histogram.iter_mut().zip(&bucket.latency) {
+            *total += count;
+        }
+    }
+    let summary = Summary {
+        probes: in_window.iter().map(|b| b.probes).sum(),
+        failures: in_window.iter().map(|b| b.failures).sum(),
+        p50_ms: percentile(&histogram, 0.5),
+        p90_ms: percentile(&histogram, 0.9),
+        p99_ms: percentile(&histogram, 0.99),
+    };
+
+    let mut targets = Vec::new();
+    if let Some(availability) = spec.availability {
+        targets.push((Indicator::Availability, availability));
+    }
+    if spec.p99_ms.is_some() {
+        targets.push((Indicator::Latency, 99.0));
+    }
+    let objectives = targets
+        .into_iter()
+        .map(|(indicator, target)| {
+            let allowed = 1.0 - target / 100.0;
+            let (total, bad) = counts(&in_window, start, indicator);
+            let (long_total, long_bad) = counts(&in_window, now - LONG_BURN_WINDOW, indicator);
+            let (short_total, short_bad) = counts(&in_window, now - SHORT_BURN_WINDOW, indicator);
+            Objective {
+                indicator,
+                target,
+                actual: (total > 0).then(|| 100.0 * (total - bad) as f64 / total as f64),
+                budget_remaining: if total == 0 {
+                    100.0
+                } else {
+                    100.0 * (1.0 - bad as f64 / (allowed * total as f64))
+                },
+                burn_rate_long: burn_rate(long_total, long_bad, allowed),
+                burn_rate_short: burn_rate(short_total, short_bad, allowed),
+            }
+        })
+        .collect();
+    (summary, objectives)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloState {
+    pub container: String,
+    pub spec: SloSpec,
+    pub watcher_pid: Option<u32>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl SloState {
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+/// A container's objectives as of now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloReport {
+    pub container: String,
+    pub watching: bool,
+    pub spec: SloSpec,
+    pub summary: Summary,
+    pub objectives: Vec<Objective>,
+}
+
+const WATCHER: Watcher = Watcher::new("slo-watch");
+
+fn slo_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("slo")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    slo_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn history_path(data_dir: &Path, container: &str) -> PathBuf {
+    slo_dir(data_dir).join(format!("{}.jsonl", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    slo_dir(data_dir).join(format!("{}.log", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<SloState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+fn history(data_dir: &Path, container: &str) -> Vec<Bucket> {
+    std::fs::read_to_string(history_path(data_dir, container))
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The objectives of every container with an slo, by name
+pub fn reports(data_dir: &Path) -> Vec<SloReport> {
+    let now = current_minute();
+    let mut reports: Vec<SloReport> = watcher::list::<SloState>(&slo_dir(data_dir))
+        .into_iter()
+        .map(|state| {
+            let buckets = history(data_dir, &state.container);
+            let (summary, objectives) = evaluate(&state.spec, &buckets, now);
+            SloReport {
+                watching: state.watching(),
+                container: state.container,
+                spec: state.spec,
+                summary,
+                objectives,
+            }
+        })
+        .collect();
+    reports.sort_by(|a, b| a.container.cmp(&b.container));
+    reports
+}
+
+fn save(data_dir: &Path, state: &SloState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Start probing a running container for its objectives
+pub fn start(data_dir: &Path, container: &str, spec: &SloSpec) -> Result<()> {
+    forget(data_dir, container);
+    let mut state = SloState {
+        container: container.to_string(),
+        spec: spec.clone(),
+        watcher_pid: None,
+        started_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["slo-watch", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    debug!(
+        "Probing {} port(s) of {} for its SLO",
+        spec.ports.len(),
+        container
+    );
+    Ok(())
+}
+
+/// Stop probing a container. Its history stays, so a redeployed service
+/// keeps its error budget.
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Latency of one probe in milliseconds, or None when it failed
+async fn probe(
+    client: &reqwest::Client,
+    port: &PublishedPort,
+    http_path: Option<&str>,
+) -> Option<f64> {
+    let started = Instant::now();
+    let addr = port.local_addr();
+    let ok = match http_path {
+        Some(path) => client
+            .get(format!("http://{}{}", addr, path))
+            .send()
+            .await
+            .is_ok_and(|response| !response.status().is_server_error()),
+        None => tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .is_ok_and(|connected| connected.is_ok()),
+    };
+    ok.then(|| started.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn append(data_dir: &Path, container: &str, bucket: &Bucket) -> Result<()> {
+    let path = history_path(data_dir, container);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(bucket)?)?;
+    Ok(())
+}
+
+fn rewrite(data_dir: &Path, container: &str, buckets: &VecDeque<Bucket>) -> Result<()> {
+    let path = history_path(data_dir, container);
+    let mut content = String::new();
+    for bucket in buckets {
+        content.push_str(&serde_json::to_string(bucket)?);
+        content.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// Fire or resolve the alerts of each objective
+async fn check_alerts(
+    alerts: &AlertingEngine,
+    container: &str,
+    spec: &SloSpec,
+    objectives: &[Objective],
+) {
+    for objective in objectives {
+        let key = format!("slo.{}.{}", container, objective.indicator.as_str());
+        let result = if objective.burning(spec.burn_rate) {
+            let message = format!(
+                "{} burns its {} error budget {:.1}x too fast ({:.1}x over the last hour, {:.1}x over 5 minutes)",
+                container,
+                objective.indicator.as_str(),
+                objective.burn_rate_short.min(objective.burn_rate_long),
+                objective.burn_rate_long,
+                objective.burn_rate_short
+            );
+            alerts
+                .fire(&Alert::new(key, AlertSeverity::Critical, message))
+                .await
+                .map(|_| ())
+        } else {
+            alerts.resolve(&key)
+        };
+        if let Err(e) = result {
+            warn!("Could not deliver the SLO alert of {}: {}", container, e);
+        }
+
+        let key = format!("slo.{}.{}.budget", container, objective.indicator.as_str());
+        let result = if objective.budget_remaining <= 0.0 {
+            let message = format!(
+                "{} spent its {} error budget: {:.3}% over the window against a {}% objective",
+                container,
+                objective.indicator.as_str(),
+                objective.actual.unwrap_or_default(),
+                objective.target
+            );
+            alerts
+                .fire(&Alert::new(key, AlertSeverity::Warning, message))
+                .await
+                .map(|_| ())
+        } else {
+            alerts.resolve(&key)
+        };
+        if let Err(e) = result {
+            warn!("Could not deliver the SLO alert of {}: {}", container, e);
+        }
+    }
+}
+
+/// Probe the container's published ports and keep its history and alerts.
+/// Run by `bolt slo-watch`.
+pub async fn watch(config: &BoltConfig, container: &str) -> Result<()> {
+    let data_dir = &config.data_dir;
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let Some(state) = state(data_dir, container) else {
+        debug!("No SLO for {}, stopping", container);
+        return Ok(());
+    };
+    let spec = state.spec;
+    let alerts = AlertingEngine::new(data_dir, config.alerts.clone());
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build()?;
+
+    let mut buckets: VecDeque<Bucket> = history(data_dir, container).into();
+    let mut bucket = Bucket::new(current_minute());
+    let mut pruned: Option<Instant> = None;
+    let mut interval = tokio::time::interval(Duration::from_secs(spec.interval_secs));
+    loop {
+        interval.tick().await;
+        if !state_path(data_dir, container).exists() {
+            debug!("SLO of {} removed, stopping", container);
+            return Ok(());
+        }
+        if crate::runtime::healthcheck::status(&runtime, container)
+            .await
+            .is_none()
+        {
+            info!("{} no longer exists, no longer probing it", container);
+            return Ok(());
+        }
+
+        for port in &spec.ports {
+            let latency = probe(&client, port, spec.http_path.as_deref()).await;
+            bucket.record(latency, spec.p99_ms);
+        }
+
+        let now = current_minute();
+        if now == bucket.minute {
+            continue;
+        }
+        let finished = std::mem::replace(&mut bucket, Bucket::new(now));
+        if let Err(e) = append(data_dir, container, &finished) {
+            warn!("Could not record the probes of {}: {}", container, e);
+        }
+        buckets.push_back(finished);
+        while buckets
+            .front()
+            .is_some_and(|b| b.minute < now - spec.window_minutes())
+        {
+            buckets.pop_front();
+        }
+        if pruned.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL) {
+            pruned = Some(Instant::now());
+            if let Err(e) = rewrite(data_dir, container, &buckets) {
+                warn!("Could not prune the probe history of {}: {}", container, e);
+            }
+        }
+
+        let (_, objectives) = evaluate(&spec, buckets.make_contiguous(), now);
+        check_alerts(&alerts, container, &spec, &objectives).await;
+    }
+}
+
+/// Objectives in the Prometheus text format
+pub fn prometheus(reports: &[SloReport]) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(&Objective) -> f64| {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for report in reports {
+            for objective in &report.objectives {
+                out.push_str(&format!(
+                    "{}{{container=\"{}\",indicator=\"{}\"}} {}\n",
+                    name,
+                    report.container,
+                    objective.indicator.as_str(),
+                    value(objective)
+                ));
+            }
+        }
+    };
+    metric(
+        "bolt_slo_target_ratio",
+        "gauge",
+        "Share of probes that must be good",
+        &|o| o.target / 100.0,
+    );
+    metric(
+        "bolt_slo_good_ratio",
+        "gauge",
+        "Share of good probes over the window",
+        &|o| o.actual.map_or(1.0, |a| a / 100.0),
+    );
+    metric(
+        "bolt_slo_error_budget_remaining_ratio",
+        "gauge",
+        "Share of the error budget left",
+        &|o| o.budget_remaining / 100.0,
+    );
+    metric(
+        "bolt_slo_burn_rate_1h",
+        "gauge",
+        "How fast the error budget burns over the last hour",
+        &|o| o.burn_rate_long,
+    );
+    metric(
+        "bolt_slo_burn_rate_5m",
+        "gauge",
+        "How fast the error budget burns over the last 5 minutes",
+        &|o| o.burn_rate_short,
+    );
+
+    out.push_str("# HELP bolt_slo_probes_total Probes over the window\n");
+    out.push_str("# TYPE bolt_slo_probes_total gauge\n");
+    for report in reports {
+        out.push_str(&format!(
+            "bolt_slo_probes_total{{container=\"{}\",result=\"success\"}} {}\n",
+            report.container,
+            report.summary.probes - report.summary.failures
+        ));
+        out.push_str(&format!(
+            "bolt_slo_probes_total{{container=\"{}\",result=\"failure\"}} {}\n",
+            report.container, report.summary.failures
+        ));
+    }
+    out.push_str("# HELP bolt_slo_latency_ms Probe latency percentiles over the window\n");
+    out.push_str("# TYPE bolt_slo_latency_ms gauge\n");
+    for report in reports {
+        let percentiles = [
+            ("0.5", report.summary.p50_ms),
+            ("0.9", report.summary.p90_ms),
+            ("0.99", report.summary.p99_ms),
+        ];
+        for (quantile, value) in percentiles {
+            if let Some(value) = value {
+                out.push_str(&format!(
+                    "bolt_slo_latency_ms{{container=\"{}\",quantile=\"{}\"}} {}\n",
+                    report.container, quantile, value
+                ));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_budgets_burn_rates_and_percentiles() {
+        let config: SloConfig =
+            toml::from_str("availability = \"99%\"\np99_ms = 50\nwindow = \"1d\"\n").unwrap();
+        let spec = SloSpec::from_config(&config, &["8080:80".to_string(), "53:53/udp".to_string()])
+            .unwrap();
+        assert_eq!(spec.availability, Some(99.0));
+        assert_eq!(spec.ports.len(), 1);
+        assert_eq!(spec.window_secs, 86400);
+
+        let now = 1_000_000;
+        let mut buckets = Vec::new();
+        // A day ago and out of the window: everything failed
+        let mut old = Bucket::new(now - 2000);
+        for _ in 0..100 {
+            old.record(None, spec.p99_ms);
+        }
+        buckets.push(old);
+        // 1000 probes earlier today, 5 failed and 20 slow
+        let mut earlier = Bucket::new(now - 30);
+        for i in 0..1000 {
+            let latency = match i {
+                0..5 => None,
+                5..25 => Some(80.0),
+                _ => Some(4.0),
+            };
+            earlier.record(latency, spec.p99_ms);
+        }
+        buckets.push(earlier);
+        // The last minutes: half the probes fail
+        let mut recent = Bucket::new(now - 1);
+        recent.record(None, spec.p99_ms);
+        recent.record(Some(4.0), spec.p99_ms);
+        buckets.push(recent);
+
+        let (summary, objectives) = evaluate(&spec, &buckets, now);
+        assert_eq!(summary.probes, 1002);
+        assert_eq!(summary.failures, 6);
+        assert_eq!(summary.p50_ms, Some(5.0));
+        assert_eq!(summary.p99_ms, Some(100.0));
+
+        let availability = &objectives[0];
+        assert_eq!(availability.indicator, Indicator::Availability);
+        assert!((availability.actual.unwrap() - 100.0 * 996.0 / 1002.0).abs() < 1e-9);
+        // 6 of the ~10 bad probes allowed
+        assert!((availability.budget_remaining - 100.0 * (1.0 - 6.0 / 10.02)).abs() < 1e-9);
+        // Half of the last 5 minutes' probes failed against 1% allowed
+        assert!((availability.burn_rate_short - 50.0).abs() < 1e-9);
+        assert!(availability.burn_rate_long < 1.0);
+        assert!(!availability.burning(spec.burn_rate));
+
+        let latency = &objectives[1];
+        assert_eq!(latency.indicator, Indicator::Latency);
+        assert_eq!(latency.target, 99.0);
+        // 20 of 996 successful probes were slower than 50ms: over budget
+        assert!(latency.budget_remaining < 0.0);
+
+        assert!(SloSpec::from_config(&toml::from_str("window = \"1d\"").unwrap(), &[]).is_err());
+        assert!(
+            SloSpec::from_config(&toml::from_str("availability = \"100%\"").unwrap(), &[]).is_err()
+        );
+        assert!(
+            SloSpec::from_config(
+                &toml::from_str("p99_ms = 20\nhttp_path = \"health\"").unwrap(),
+                &[]
+            )
+            .is_err()
+        );
+    }
+}
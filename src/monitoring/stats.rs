@@ -0,0 +1,257 @@
+// Live container stats
+//
+// `bolt stats` samples a container's cgroup (CPU time, memory usage and
+// limit, bytes read and written, through runtime::cgroups on v1 or v2) and
+// its network namespace (/proc/<pid>/net/dev, loopback excluded) every
+// interval. CPU is the cgroup's CPU time over the wall time between two
+// samples, so 100% is one full core.
+use anyhow::Result;
+use futures::Stream;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::monitoring::ContainerMetrics;
+use crate::runtime;
+
+/// Counters read from a container's cgroup and network namespace
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub at: Instant,
+    pub cpu_usec: u64,
+    pub memory_bytes: u64,
+    /// 0 when unlimited
+    pub memory_limit_bytes: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+/// The container a stream follows, re-inspected on every sample
+#[derive(Debug, Clone)]
+struct Target {
+    id: String,
+    name: String,
+    status: String,
+    /// 0 when not running
+    pid: u32,
+    restart_count: u32,
+    exit_code: Option<i32>,
+    labels: HashMap<String, String>,
+}
+
+async fn inspect(container: &str) -> Result<Target> {
+    let inspect = runtime::inspect_container(container).await?;
+    let state = &inspect["State"];
+    let status = state["Status"].as_str().unwrap_or("unknown").to_string();
+    Ok(Target {
+        id: inspect["Id"].as_str().unwrap_or(container).to_string(),
+        name: inspect["Name"]
+            .as_str()
+            .unwrap_or(container)
+            .trim_start_matches('/')
+            .to_string(),
+        pid: state["Pid"].as_u64().unwrap_or(0) as u32,
+        restart_count: inspect["RestartCount"].as_u64().unwrap_or(0) as u32,
+        exit_code: (status == "exited")
+            .then(|| state["ExitCode"].as_i64().map(|c| c as i32))
+            .flatten(),
+        labels: inspect["Config"]["Labels"]
+            .as_object()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        status,
+    })
+}
+
+/// Read a running process's container counters
+pub fn sample(pid: u32) -> Option<Sample> {
+    let cgroup = runtime::cgroups::ContainerCgroup::of_process(pid)?;
+    let cpu_usec = cgroup.cpu_usec()?;
+    let usage = cgroup.usage();
+    let net = std::fs::read_to_string(format!("/proc/{}/net/dev", pid)).unwrap_or_default();
+    let (rx_bytes, tx_bytes) = parse_net_dev(&net);
+
+    Some(Sample {
+        at: Instant::now(),
+        cpu_usec,
+        memory_bytes: usage.memory_bytes,
+        memory_limit_bytes: usage.memory_limit_bytes.unwrap_or(0),
+        rx_bytes,
+        tx_bytes,
+        read_bytes: usage.read_bytes,
+        write_bytes: usage.write_bytes,
+    })
+}
+
+/// Bytes received and sent on every interface but loopback
+fn parse_net_dev(net_dev: &str) -> (u64, u64) {
+    // Two header lines, then "<iface>: <rx bytes> ... (8 rx fields) <tx bytes> ..."
+    net_dev
+        .lines()
+        .skip(2)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(iface, _)| iface.trim() != "lo")
+        .fold((0, 0), |(rx, tx), (_, counters)| {
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .map(|f| f.parse().unwrap_or(0))
+                .collect();
+            (
+                rx + fields.first().copied().unwrap_or(0),
+                tx + fields.get(8).copied().unwrap_or(0),
+            )
+        })
+}
+
+fn metrics(
+    target: &Target,
+    previous: Option<&Sample>,
+    current: Option<&Sample>,
+) -> ContainerMetrics {
+    let cpu_usage_percent = match (previous, current) {
+        (Some(previous), Some(current)) => {
+            let wall = current.at.duration_since(previous.at).as_micros().max(1) as f64;
+            current.cpu_usec.saturating_sub(previous.cpu_usec) as f64 / wall * 100.0
+        }
+        _ => 0.0,
+    };
+    let uptime_seconds = (target.pid != 0)
+        .then(|| process_uptime(target.pid))
+        .flatten()
+        .unwrap_or(0);
+    ContainerMetrics {
+        container_id: target.id.clone(),
+        name: target.name.clone(),
+        status: target.status.clone(),
+        cpu_usage_percent,
+        memory_usage_bytes: current.map_or(0, |s| s.memory_bytes),
+        memory_limit_bytes: current.map_or(0, |s| s.memory_limit_bytes),
+        network_rx_bytes: current.map_or(0, |s| s.rx_bytes),
+        network_tx_bytes: current.map_or(0, |s| s.tx_bytes),
+        disk_read_bytes: current.map_or(0, |s| s.read_bytes),
+        disk_write_bytes: current.map_or(0, |s| s.write_bytes),
+        uptime_seconds,
+        restart_count: target.restart_count,
+        exit_code: target.exit_code,
+        labels: target.labels.clone(),
+        last_updated: SystemTime::now(),
+    }
+}
+
+/// Seconds since a process started, from its start time in /proc/<pid>/stat
+fn process_uptime(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // starttime is the 22nd field; the 3rd follows the parenthesised command
+    let started_ticks: u64 = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()?;
+    let boot_uptime: f64 = std::fs::read_to_string(Path::new("/proc/uptime"))
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    // SAFETY: sysconf has no memory safety requirements
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    Some((boot_uptime - started_ticks as f64 / ticks_per_sec).max(0.0) as u64)
+}
+
+/// Metrics of a container now, with CPU relative to `previous`, and the
+/// sample to pass as `previous` next time
+pub async fn snapshot(
+    container: &str,
+    previous: Option<&Sample>,
+) -> Result<(ContainerMetrics, Option<Sample>)> {
+    let target = inspect(container).await?;
+    let current = (target.pid != 0).then(|| sample(target.pid)).flatten();
+    Ok((metrics(&target, previous, current.as_ref()), current))
+}
+
+/// Sample a container every `interval`. The first item comes right away,
+/// with CPU at 0% as there is nothing to compare against yet; the stream
+/// ends with an error once the container is gone.
+pub fn stream(
+    container: String,
+    interval: Duration,
+) -> impl Stream<Item = Result<ContainerMetrics>> {
+    futures::stream::unfold(
+        (Some(container), None::<Sample>, true),
+        move |(container, previous, first)| async move {
+            let container = container?;
+            if !first {
+                tokio::time::sleep(interval).await;
+            }
+            match snapshot(&container, previous.as_ref()).await {
+                Ok((metrics, current)) => Some((Ok(metrics), (Some(container), current, false))),
+                Err(e) => Some((Err(e), (None, None, false))),
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_parse_and_cpu_is_relative_to_wall_time() {
+        let net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    1000      10    0    0    0     0          0         0     1000      10    0    0    0     0       0          0
+  eth0:    2048      20    0    0    0     0          0         0      512       5    0    0    0     0       0          0
+  eth1:     100       1    0    0    0     0          0         0      100       1    0    0    0     0       0          0
+";
+        assert_eq!(parse_net_dev(net_dev), (2148, 612));
+
+        let io_stat = "8:0 rbytes=4096 wbytes=1024 rios=1 wios=1 dbytes=0 dios=0\n\
+                       259:0 rbytes=100 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n";
+        assert_eq!(runtime::cgroups::parse_io_stat(io_stat), (4196, 1024));
+
+        let target = Target {
+            id: "abc".to_string(),
+            name: "web".to_string(),
+            status: "running".to_string(),
+            pid: 0,
+            restart_count: 2,
+            exit_code: None,
+            labels: HashMap::new(),
+        };
+        let at = Instant::now();
+        let previous = Sample {
+            at,
+            cpu_usec: 1_000_000,
+            memory_bytes: 0,
+            memory_limit_bytes: 0,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            read_bytes: 0,
+            write_bytes: 0,
+        };
+        let current = Sample {
+            at: at + Duration::from_secs(2),
+            cpu_usec: 2_000_000,
+            memory_bytes: 64 << 20,
+            ..previous.clone()
+        };
+        let m = metrics(&target, Some(&previous), Some(&current));
+        assert!((m.cpu_usage_percent - 50.0).abs() < 0.01);
+        assert_eq!(m.memory_usage_bytes, 64 << 20);
+        assert_eq!(m.restart_count, 2);
+        assert_eq!(
+            metrics(&target, None, Some(&current)).cpu_usage_percent,
+            0.0
+        );
+    }
+}
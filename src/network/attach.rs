@@ -0,0 +1,221 @@
+// Attaching services to their Boltfile networks
+//
+// Every network a service lists is joined when its container is created,
+// with its aliases registered in the runtime's DNS (aardvark-dns for Podman,
+// the embedded DNS server for Docker) and its static addresses applied.
+// Podman takes all attachments on `run`; Docker only takes the first one, so
+// the rest are connected right after the container starts.
+use anyhow::{Result, anyhow};
+use std::net::IpAddr;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use crate::config::{BoltFile, ServiceNetworkConfig, ServiceNetworks};
+
+/// One network a container joins, with its runtime-level name resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub network: String,
+    pub config: ServiceNetworkConfig,
+}
+
+/// Resolve a service's networks to runtime network names, creating any that
+/// the Boltfile declares but that don't exist yet
+pub async fn prepare(
+    runtime: &str,
+    boltfile: &BoltFile,
+    networks: &ServiceNetworks,
+) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    for (key, config) in networks.attachments() {
+        let declared = boltfile.networks.as_ref().and_then(|n| n.get(key));
+        let network = declared
+            .and_then(|n| n.name.clone())
+            .unwrap_or_else(|| key.to_string());
+
+        if let Some(declared) = declared
+            && !declared.external.unwrap_or(false)
+            && !exists(runtime, &network).await
+        {
+            let subnet = declared
+                .ipam
+                .as_ref()
+                .and_then(|ipam| ipam.config.as_ref())
+                .and_then(|pools| pools.first())
+                .and_then(|pool| pool.subnet.as_deref());
+            crate::network::create_network(&network, "bridge", subnet).await?;
+        }
+
+        attachments.push(Attachment { network, config });
+    }
+    Ok(attachments)
+}
+
+/// `run` arguments for the attachments the runtime accepts at creation,
+/// and the attachments left to connect once the container exists
+pub fn run_args(runtime: &str, attachments: &[Attachment]) -> (Vec<String>, Vec<Attachment>) {
+    let mut args = Vec::new();
+    if runtime == "podman" {
+        for attachment in attachments {
+            let mut options = Vec::new();
+            for alias in attachment.config.aliases.iter().flatten() {
+                options.push(format!("alias={}", alias));
+            }
+            for ip in [
+                &attachment.config.ipv4_address,
+                &attachment.config.ipv6_address,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                options.push(format!("ip={}", ip));
+            }
+            args.push("--network".to_string());
+            args.push(if options.is_empty() {
+                attachment.network.clone()
+            } else {
+                format!("{}:{}", attachment.network, options.join(","))
+            });
+        }
+        return (args, Vec::new());
+    }
+
+    let Some((first, rest)) = attachments.split_first() else {
+        return (args, Vec::new());
+    };
+    args.extend(["--network".to_string(), first.network.clone()]);
+    for alias in first.config.aliases.iter().flatten() {
+        args.extend(["--network-alias".to_string(), alias.clone()]);
+    }
+    if let Some(ref ip) = first.config.ipv4_address {
+        args.extend(["--ip".to_string(), ip.clone()]);
+    }
+    if let Some(ref ip) = first.config.ipv6_address {
+        args.extend(["--ip6".to_string(), ip.clone()]);
+    }
+    (args, rest.to_vec())
+}
+
+/// Connect a running container to networks it couldn't join at creation
+pub async fn connect(runtime: &str, container: &str, attachments: &[Attachment]) -> Result<()> {
+    for attachment in attachments {
+        let mut cmd = AsyncCommand::new(runtime);
+        cmd.args(["network", "connect"]);
+        for alias in attachment.config.aliases.iter().flatten() {
+            cmd.arg("--alias").arg(alias);
+        }
+        if let Some(ref ip) = attachment.config.ipv4_address {
+            cmd.arg("--ip").arg(ip);
+        }
+        if let Some(ref ip) = attachment.config.ipv6_address {
+            cmd.arg("--ip6").arg(ip);
+        }
+        cmd.arg(&attachment.network).arg(container);
+        debug!("Connecting: {:?}", cmd);
+
+        let output = cmd.output().await?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to connect {} to network {}: {}",
+                container,
+                attachment.network,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        info!("🔌 {} joined network {}", container, attachment.network);
+    }
+    Ok(())
+}
+
+pub(crate) async fn exists(runtime: &str, network: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["network", "inspect", network])
+        .output()
+        .await
+        .is_ok_and(|o| o.status.success())
+}
+
+/// Whether `ip` lies inside a CIDR subnet such as `172.20.0.0/16`
+pub fn subnet_contains(subnet: &str, ip: IpAddr) -> Result<bool> {
+    let (network, prefix) = subnet
+        .split_once('/')
+        .ok_or_else(|| anyhow!("Subnet '{}' must be in CIDR notation", subnet))?;
+    let network: IpAddr = network
+        .parse()
+        .map_err(|_| anyhow!("Invalid subnet address in '{}'", subnet))?;
+    let prefix: u32 = prefix
+        .parse()
+        .map_err(|_| anyhow!("Invalid prefix length in '{}'", subnet))?;
+
+    let (network, ip, bits) = match (network, ip) {
+        (IpAddr::V4(n), IpAddr::V4(i)) => (u32::from(n) as u128, u32::from(i) as u128, 32),
+        (IpAddr::V6(n), IpAddr::V6(i)) => (u128::from(n), u128::from(i), 128),
+        _ => return Ok(false),
+    };
+    if prefix > bits {
+        return Err(anyhow!("Invalid prefix length in '{}'", subnet));
+    }
+    let host_bits = bits - prefix;
+    let mask = if host_bits >= 128 {
+        0
+    } else {
+        !0u128 << host_bits
+    };
+    Ok(network & mask == ip & mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn podman_takes_all_networks_docker_connects_the_rest() {
+        let attachments = vec![
+            Attachment {
+                network: "frontend".to_string(),
+                config: ServiceNetworkConfig {
+                    aliases: Some(vec!["api".to_string(), "api-v2".to_string()]),
+                    ipv4_address: Some("172.20.0.10".to_string()),
+                    ipv6_address: None,
+                },
+            },
+            Attachment {
+                network: "backend".to_string(),
+                config: ServiceNetworkConfig::default(),
+            },
+        ];
+
+        let (args, rest) = run_args("podman", &attachments);
+        assert_eq!(
+            args,
+            [
+                "--network",
+                "frontend:alias=api,alias=api-v2,ip=172.20.0.10",
+                "--network",
+                "backend"
+            ]
+        );
+        assert!(rest.is_empty());
+
+        let (args, rest) = run_args("docker", &attachments);
+        assert_eq!(
+            args,
+            [
+                "--network",
+                "frontend",
+                "--network-alias",
+                "api",
+                "--network-alias",
+                "api-v2",
+                "--ip",
+                "172.20.0.10"
+            ]
+        );
+        assert_eq!(rest, attachments[1..]);
+
+        let ip = "172.20.0.10".parse().unwrap();
+        assert!(subnet_contains("172.20.0.0/16", ip).unwrap());
+        assert!(!subnet_contains("172.21.0.0/16", ip).unwrap());
+        assert!(subnet_contains("0.0.0.0/0", ip).unwrap());
+    }
+}
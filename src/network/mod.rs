@@ -5,6 +5,8 @@ use anyhow::anyhow;
 use tokio::process::Command as AsyncCommand;
 use tracing::{debug, info, warn};
 
+pub mod attach;
+
 pub async fn create_network(name: &str, driver: &str, subnet: Option<&str>) -> Result<()> {
     info!("🌐 Creating network: {}", name);
     debug!("Driver: {}", driver);
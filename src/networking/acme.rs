@@ -0,0 +1,995 @@
+// ACME certificates
+//
+// `[[acme.certificates]]` in config.toml lists the TLS certificates Bolt
+// obtains from an ACME CA, Let's Encrypt unless `[acme] directory` names
+// another one. Each certificate is validated with one challenge:
+//
+// - http-01: Bolt answers `/.well-known/acme-challenge/<token>` itself on
+//   `http_address:http_port` while the order is validated; an ingress
+//   already holding port 80 forwards that path there
+// - dns-01: the `[acme.dns]` provider publishes the `_acme-challenge` TXT
+//   record and removes it afterwards; wildcards need this one
+//
+// The account key and each certificate's chain and key are kept in the
+// secret store as `acme-account.key`, `tls-<name>.crt` and `tls-<name>.key`.
+// `bolt cert renew` obtains missing certificates and renews those expiring
+// within `renew_before`; `bolt cert watch` does so every `check_interval`
+// and alerts on certificates close to expiring. The QUIC proxy rereads its
+// certificate when it changes, so new handshakes get the renewed one while
+// open connections are left alone.
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+use warp::Filter;
+
+use crate::builds::cache::parse_duration;
+use crate::config::{
+    AcmeSettings, BoltConfig, CertificateSettings, ChallengeKind, DnsProviderSettings,
+};
+use crate::monitoring::alerts::{Alert, AlertSeverity, AlertingEngine};
+use crate::secrets::{self, SecretStore};
+
+const ACCOUNT_KEY: &str = "acme-account.key";
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+/// Certificates expiring within this many days alert as critical
+const CRITICAL_DAYS: i64 = 7;
+const POLL_ATTEMPTS: u32 = 30;
+
+/// A certificate from `[acme]` and when it expires
+#[derive(Debug, Clone, Serialize)]
+pub struct CertStatus {
+    pub name: String,
+    pub domains: Vec<String>,
+    /// None until the certificate has been obtained
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+pub fn cert_secret(name: &str) -> String {
+    format!("tls-{}.crt", name)
+}
+
+pub fn key_secret(name: &str) -> String {
+    format!("tls-{}.key", name)
+}
+
+/// PEM chain and key of a stored certificate
+pub fn load(store: &SecretStore, name: &str) -> Option<(String, String)> {
+    Some((
+        store.get(&cert_secret(name)).ok()?,
+        store.get(&key_secret(name)).ok()?,
+    ))
+}
+
+pub fn status(config: &BoltConfig) -> Vec<CertStatus> {
+    let store = SecretStore::new(&config.config_dir);
+    config
+        .acme
+        .certificates
+        .iter()
+        .map(|certificate| certificate_status(&store, certificate))
+        .collect()
+}
+
+fn certificate_status(store: &SecretStore, certificate: &CertificateSettings) -> CertStatus {
+    CertStatus {
+        name: certificate.name.clone(),
+        domains: certificate.domains.clone(),
+        not_after: store
+            .get(&cert_secret(&certificate.name))
+            .ok()
+            .and_then(|pem| not_after(&pem)),
+    }
+}
+
+/// Whether a certificate is missing or expires within `renew_before`
+fn due(status: &CertStatus, renew_before: Duration, now: DateTime<Utc>) -> bool {
+    status
+        .not_after
+        .is_none_or(|expiry| (expiry - now).to_std().unwrap_or_default() < renew_before)
+}
+
+/// Expiry of the first certificate in a PEM chain
+pub fn not_after(pem: &str) -> Option<DateTime<Utc>> {
+    let body: String = pem
+        .lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN CERTIFICATE-----"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END"))
+        .collect();
+    let der = STANDARD.decode(body.trim()).ok()?;
+
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version
+    // (optional), serialNumber, signature, issuer, validity, ... } ... }
+    let (_, certificate, _) = der_element(&der)?;
+    let (_, tbs, _) = der_element(certificate)?;
+    let (tag, _, mut rest) = der_element(tbs)?;
+    if tag != 0xa0 {
+        rest = tbs;
+    }
+    for _ in 0..3 {
+        rest = der_element(rest)?.2;
+    }
+    let (_, validity, _) = der_element(rest)?;
+    let (_, _, validity) = der_element(validity)?;
+    let (tag, time, _) = der_element(validity)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let time = match tag {
+        // UTCTime: YYMMDDHHMMSSZ, with years 50-99 in the 1900s
+        0x17 if time.len() == 13 => {
+            let century = if &time[..2] < "50" { "20" } else { "19" };
+            format!("{}{}", century, time)
+        }
+        // GeneralizedTime: YYYYMMDDHHMMSSZ
+        0x18 => time.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&time, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Tag, contents and what follows of the DER element at the start of `data`
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (length, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = data
+            .get(2..2 + count)?
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + count)
+    };
+    let contents = data.get(header..header + length)?;
+    Some((tag, contents, &data[header + length..]))
+}
+
+fn b64(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+/// The ES256 key the ACME account is registered with
+struct AccountKey {
+    pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    fn load_or_create(store: &SecretStore) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = match store.get(ACCOUNT_KEY) {
+            Ok(encoded) => STANDARD
+                .decode(encoded.trim())
+                .context("Invalid ACME account key")?,
+            Err(_) => {
+                let document = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                    .map_err(|_| anyhow!("Failed to generate an ACME account key"))?;
+                store.set(ACCOUNT_KEY, STANDARD.encode(document.as_ref()).as_bytes())?;
+                document.as_ref().to_vec()
+            }
+        };
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+            .map_err(|e| anyhow!("Invalid ACME account key: {}", e))?;
+        Ok(Self { pair, rng })
+    }
+
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04, then 32 bytes each of x and y
+        let point = self.pair.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": b64(&point[1..33]),
+            "y": b64(&point[33..65]),
+        })
+    }
+
+    /// RFC 7638 thumbprint: the JWK's required members, in order, unspaced
+    fn thumbprint(&self) -> String {
+        let jwk = self.jwk();
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":{},"y":{}}}"#,
+            jwk["x"], jwk["y"]
+        );
+        b64(&Sha256::digest(canonical.as_bytes()))
+    }
+
+    /// Flattened JWS of an already encoded payload
+    fn sign(&self, protected: &Value, payload: &str) -> Result<Value> {
+        let protected = b64(serde_json::to_string(protected)?.as_bytes());
+        let signature = self
+            .pair
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| anyhow!("Failed to sign an ACME request"))?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": b64(signature.as_ref()),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    identifier: Identifier,
+    status: String,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+}
+
+/// How the challenges of an order are answered
+pub struct Challenges<'a> {
+    pub http: &'a Http01Responder,
+    pub dns: Option<&'a dyn DnsProvider>,
+    /// Wait after publishing TXT records, before asking the CA to look
+    pub propagation: Duration,
+}
+
+/// A TXT record published for a dns-01 challenge
+struct Published {
+    domain: String,
+    record: String,
+    value: String,
+}
+
+pub struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    key: AccountKey,
+    /// Account URL, the `kid` of every request after registration
+    account: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// Fetch the directory and register the account, or find the existing
+    /// one for the stored key
+    pub async fn connect(settings: &AcmeSettings, store: &SecretStore) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let directory = http
+            .get(&settings.directory)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to reach ACME directory {}", settings.directory))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid ACME directory {}", settings.directory))?;
+        let mut client = Self {
+            http,
+            directory,
+            key: AccountKey::load_or_create(store)?,
+            account: None,
+            nonce: None,
+        };
+
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(ref email) = settings.email {
+            account["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let url = client.directory.new_account.clone();
+        let response = client.post(&url, Some(&account)).await?;
+        client.account = Some(location(&response)?);
+        Ok(client)
+    }
+
+    async fn nonce(&mut self) -> Result<String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await?
+            .error_for_status()?;
+        replay_nonce(&response).ok_or_else(|| anyhow!("ACME server sent no nonce"))
+    }
+
+    /// POST a signed request; no payload makes it a POST-as-GET
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<reqwest::Response> {
+        let payload = match payload {
+            Some(payload) => b64(serde_json::to_string(payload)?.as_bytes()),
+            None => String::new(),
+        };
+        let mut retried = false;
+        loop {
+            let mut protected = json!({
+                "alg": "ES256",
+                "nonce": self.nonce().await?,
+                "url": url,
+            });
+            match self.account {
+                Some(ref account) => protected["kid"] = json!(account),
+                None => protected["jwk"] = self.key.jwk(),
+            }
+            let body = self.key.sign(&protected, &payload)?;
+            let response = self
+                .http
+                .post(url)
+                .header("content-type", "application/jose+json")
+                .body(serde_json::to_vec(&body)?)
+                .send()
+                .await
+                .with_context(|| format!("ACME request to {} failed", url))?;
+            self.nonce = replay_nonce(&response);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let problem: Value = response.json().await.unwrap_or_default();
+            // A stale nonce is retried once, with the fresh one the error carried
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(anyhow!(
+                "ACME request to {} failed: {}",
+                url,
+                problem["detail"].as_str().unwrap_or("unknown error")
+            ));
+        }
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(&mut self, url: &str) -> Result<T> {
+        Ok(self.post(url, None).await?.json().await?)
+    }
+
+    /// Poll an order or authorization until it is valid
+    async fn poll(&mut self, url: &str, what: &str) -> Result<Value> {
+        let mut delay = Duration::from_secs(1);
+        for _ in 0..POLL_ATTEMPTS {
+            let value: Value = self.fetch(url).await?;
+            match value["status"].as_str() {
+                Some("valid") => return Ok(value),
+                Some("pending" | "ready" | "processing") => {
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(Duration::from_secs(10));
+                }
+                status => {
+                    let detail = value["error"]["detail"]
+                        .as_str()
+                        .or_else(|| {
+                            value["challenges"].as_array().and_then(|challenges| {
+                                challenges
+                                    .iter()
+                                    .find_map(|c| c["error"]["detail"].as_str())
+                            })
+                        })
+                        .unwrap_or("no reason given");
+                    return Err(anyhow!(
+                        "{} is {}: {}",
+                        what,
+                        status.unwrap_or("unknown"),
+                        detail
+                    ));
+                }
+            }
+        }
+        Err(anyhow!("{} is still pending", what))
+    }
+
+    /// Obtain a certificate, returning its PEM chain and key
+    pub async fn issue(
+        &mut self,
+        certificate: &CertificateSettings,
+        challenges: &Challenges<'_>,
+    ) -> Result<(String, String)> {
+        let identifiers: Vec<Value> = certificate
+            .domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect();
+        let url = self.directory.new_order.clone();
+        let response = self
+            .post(&url, Some(&json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = location(&response)?;
+        let order: Order = response.json().await?;
+
+        let mut published = Vec::new();
+        let authorized = self
+            .authorize(&order, certificate.challenge, challenges, &mut published)
+            .await;
+        if let Some(dns) = challenges.dns {
+            for record in published {
+                if let Err(e) = dns
+                    .cleanup(&record.domain, &record.record, &record.value)
+                    .await
+                {
+                    warn!("Failed to remove TXT record {}: {}", record.record, e);
+                }
+            }
+        }
+        authorized?;
+
+        let mut params = rcgen::CertificateParams::new(certificate.domains.clone());
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let key = rcgen::Certificate::from_params(params)?;
+        let csr = key.serialize_request_der()?;
+        self.post(&order.finalize, Some(&json!({ "csr": b64(&csr) })))
+            .await?;
+
+        let order: Order = serde_json::from_value(
+            self.poll(&order_url, &format!("Order for {}", certificate.name))
+                .await?,
+        )?;
+        let chain_url = order
+            .certificate
+            .ok_or_else(|| anyhow!("Valid order for {} has no certificate", certificate.name))?;
+        let chain = self.post(&chain_url, None).await?.text().await?;
+        Ok((chain, key.serialize_private_key_pem()))
+    }
+
+    async fn authorize(
+        &mut self,
+        order: &Order,
+        kind: ChallengeKind,
+        challenges: &Challenges<'_>,
+        published: &mut Vec<Published>,
+    ) -> Result<()> {
+        let kind_name = match kind {
+            ChallengeKind::Http01 => "http-01",
+            ChallengeKind::Dns01 => "dns-01",
+        };
+
+        // Every record is published before the CA is asked to look, so
+        // DNS propagation is only waited for once
+        let mut pending = Vec::new();
+        for url in &order.authorizations {
+            let authorization: Authorization = self.fetch(url).await?;
+            if authorization.status == "valid" {
+                continue;
+            }
+            let domain = authorization.identifier.value;
+            let challenge = authorization
+                .challenges
+                .into_iter()
+                .find(|c| c.kind == kind_name)
+                .ok_or_else(|| anyhow!("{} can't be validated with {}", domain, kind_name))?;
+            let key_authorization = format!("{}.{}", challenge.token, self.key.thumbprint());
+
+            match kind {
+                ChallengeKind::Http01 => {
+                    challenges.http.insert(&challenge.token, &key_authorization)
+                }
+                ChallengeKind::Dns01 => {
+                    let dns = challenges
+                        .dns
+                        .ok_or_else(|| anyhow!("dns-01 needs an [acme.dns] provider"))?;
+                    let record = format!("_acme-challenge.{}", domain);
+                    let value = b64(&Sha256::digest(key_authorization.as_bytes()));
+                    info!("📝 Publishing TXT record {}", record);
+                    dns.present(&domain, &record, &value).await?;
+                    published.push(Published {
+                        domain: domain.clone(),
+                        record,
+                        value,
+                    });
+                }
+            }
+            pending.push((url.clone(), challenge.url, domain));
+        }
+
+        if !published.is_empty() {
+            tokio::time::sleep(challenges.propagation).await;
+        }
+        for (url, challenge_url, domain) in pending {
+            self.post(&challenge_url, Some(&json!({}))).await?;
+            self.poll(&url, &format!("Authorization for {}", domain))
+                .await?;
+            info!("✅ Validated {}", domain);
+        }
+        Ok(())
+    }
+}
+
+fn location(response: &reqwest::Response) -> Result<String> {
+    response
+        .headers()
+        .get("location")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("ACME server sent no Location header"))
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("replay-nonce")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Answers http-01 challenges with the key authorization of their token
+#[derive(Debug, Clone, Default)]
+pub struct Http01Responder {
+    tokens: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Http01Responder {
+    pub fn insert(&self, token: &str, key_authorization: &str) {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token.to_string(), key_authorization.to_string());
+    }
+
+    pub fn answer(&self, token: &str) -> Option<String> {
+        self.tokens.lock().unwrap().get(token).cloned()
+    }
+
+    /// Serve `/.well-known/acme-challenge/<token>` on `addr` until the
+    /// returned task is aborted
+    pub fn serve(&self, addr: SocketAddr) -> Result<tokio::task::JoinHandle<()>> {
+        let responder = self.clone();
+        let route =
+            warp::path!(".well-known" / "acme-challenge" / String).map(move |token: String| {
+                match responder.answer(&token) {
+                    Some(key_authorization) => {
+                        warp::reply::with_status(key_authorization, warp::http::StatusCode::OK)
+                    }
+                    None => {
+                        warp::reply::with_status(String::new(), warp::http::StatusCode::NOT_FOUND)
+                    }
+                }
+            });
+        let (bound, server) = warp::serve(route)
+            .try_bind_ephemeral(addr)
+            .with_context(|| format!("Failed to listen for http-01 challenges on {}", addr))?;
+        info!("🌐 Answering http-01 challenges on {}", bound);
+        Ok(tokio::spawn(server))
+    }
+}
+
+/// Publishes the TXT records of dns-01 challenges
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    /// Publish `value` at `record`, `_acme-challenge.<domain>`
+    async fn present(&self, domain: &str, record: &str, value: &str) -> Result<()>;
+
+    /// Remove the record published by `present`
+    async fn cleanup(&self, domain: &str, record: &str, value: &str) -> Result<()>;
+}
+
+pub fn dns_provider(
+    settings: &DnsProviderSettings,
+    store: &SecretStore,
+) -> Result<Box<dyn DnsProvider>> {
+    Ok(match settings {
+        DnsProviderSettings::Command { command } => Box::new(CommandDns {
+            command: command.clone(),
+        }),
+        DnsProviderSettings::Cloudflare { api_token } => {
+            let token = if secrets::is_reference(api_token) {
+                store
+                    .resolve(api_token)
+                    .context("Failed to resolve [acme.dns] api_token")?
+            } else {
+                api_token.clone()
+            };
+            Box::new(CloudflareDns {
+                http: reqwest::Client::new(),
+                token,
+            })
+        }
+    })
+}
+
+/// Hands records to a user command, for DNS hosts without a built-in provider
+struct CommandDns {
+    command: String,
+}
+
+impl CommandDns {
+    async fn run(&self, action: &str, domain: &str, record: &str, value: &str) -> Result<()> {
+        let status = AsyncCommand::new("sh")
+            .args(["-c", &self.command])
+            .env("BOLT_ACME_ACTION", action)
+            .env("BOLT_ACME_DOMAIN", domain)
+            .env("BOLT_ACME_RECORD", record)
+            .env("BOLT_ACME_VALUE", value)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("`{}` exited with {}", self.command, status));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DnsProvider for CommandDns {
+    async fn present(&self, domain: &str, record: &str, value: &str) -> Result<()> {
+        self.run("present", domain, record, value).await
+    }
+
+    async fn cleanup(&self, domain: &str, record: &str, value: &str) -> Result<()> {
+        self.run("cleanup", domain, record, value).await
+    }
+}
+
+struct CloudflareDns {
+    http: reqwest::Client,
+    token: String,
+}
+
+impl CloudflareDns {
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Value> {
+        let response: Value = self
+            .http
+            .get(format!("{}{}", CLOUDFLARE_API, path))
+            .query(query)
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["result"].clone())
+    }
+
+    /// The zone holding `domain`: the longest of its suffixes Cloudflare has
+    async fn zone(&self, domain: &str) -> Result<String> {
+        let mut name = domain;
+        loop {
+            let zones = self.get("/zones", &[("name", name)]).await?;
+            if let Some(id) = zones[0]["id"].as_str() {
+                return Ok(id.to_string());
+            }
+            name = match name.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => parent,
+                _ => return Err(anyhow!("No Cloudflare zone holds {}", domain)),
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl DnsProvider for CloudflareDns {
+    async fn present(&self, domain: &str, record: &str, value: &str) -> Result<()> {
+        let zone = self.zone(domain).await?;
+        self.http
+            .post(format!("{}/zones/{}/dns_records", CLOUDFLARE_API, zone))
+            .bearer_auth(&self.token)
+            .json(&json!({ "type": "TXT", "name": record, "content": value, "ttl": 120 }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn cleanup(&self, domain: &str, record: &str, value: &str) -> Result<()> {
+        let zone = self.zone(domain).await?;
+        let path = format!("/zones/{}/dns_records", zone);
+        let records = self
+            .get(
+                &path,
+                &[("type", "TXT"), ("name", record), ("content", value)],
+            )
+            .await?;
+        for id in records
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r["id"].as_str())
+        {
+            self.http
+                .delete(format!("{}{}/{}", CLOUDFLARE_API, path, id))
+                .bearer_auth(&self.token)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Obtain the named certificates, or all of them, that are missing or due
+/// for renewal. Each gets `Ok(true)` when it was issued, `Ok(false)` when it
+/// wasn't due, or the reason it failed.
+pub async fn renew(
+    config: &BoltConfig,
+    names: &[String],
+    force: bool,
+) -> Result<Vec<(String, Result<bool>)>> {
+    let settings = &config.acme;
+    let renew_before = parse_duration(&settings.renew_before)?;
+    let store = SecretStore::new(&config.config_dir);
+    let selected = if names.is_empty() {
+        settings.certificates.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                settings
+                    .certificates
+                    .iter()
+                    .find(|c| &c.name == name)
+                    .ok_or_else(|| anyhow!("No certificate named {} in [acme]", name))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let responder = Http01Responder::default();
+    let mut server = None;
+    let mut client = None;
+    let dns = match settings.dns {
+        Some(ref dns) => Some(dns_provider(dns, &store)?),
+        None => None,
+    };
+    let challenges = Challenges {
+        http: &responder,
+        dns: dns.as_deref(),
+        propagation: parse_duration(&settings.dns_propagation)?,
+    };
+
+    let now = Utc::now();
+    let mut outcomes = Vec::new();
+    for certificate in selected {
+        if !force && !due(&certificate_status(&store, certificate), renew_before, now) {
+            outcomes.push((certificate.name.clone(), Ok(false)));
+            continue;
+        }
+        if client.is_none() {
+            client = Some(AcmeClient::connect(settings, &store).await?);
+        }
+        if certificate.challenge == ChallengeKind::Http01 && server.is_none() {
+            let ip: std::net::IpAddr = settings
+                .http_address
+                .parse()
+                .map_err(|_| anyhow!("[acme] http_address is not an IP address"))?;
+            server = Some(responder.serve(SocketAddr::new(ip, settings.http_port))?);
+        }
+
+        info!(
+            "🔐 Requesting certificate {} for {}",
+            certificate.name,
+            certificate.domains.join(", ")
+        );
+        let client = client.as_mut().unwrap();
+        let result = match client.issue(certificate, &challenges).await {
+            Ok((chain, key)) => store
+                .set(&key_secret(&certificate.name), key.as_bytes())
+                .and_then(|_| store.set(&cert_secret(&certificate.name), chain.as_bytes()))
+                .map(|_| true),
+            Err(e) => Err(e),
+        };
+        outcomes.push((certificate.name.clone(), result));
+    }
+
+    if let Some(server) = server {
+        server.abort();
+    }
+    Ok(outcomes)
+}
+
+/// The alert for a certificate that is missing or close to expiring: a
+/// warning within `renew_before`, which means renewing failed, and critical
+/// within a week
+fn expiry_alert(status: &CertStatus, renew_before: Duration, now: DateTime<Utc>) -> Option<Alert> {
+    let key = format!("tls.{}", status.name);
+    let Some(expiry) = status.not_after else {
+        return Some(Alert::new(
+            key,
+            AlertSeverity::Warning,
+            format!("Certificate {} has not been issued", status.name),
+        ));
+    };
+    let left = expiry - now;
+    let severity = if left < chrono::Duration::days(CRITICAL_DAYS) {
+        AlertSeverity::Critical
+    } else if due(status, renew_before, now) {
+        AlertSeverity::Warning
+    } else {
+        return None;
+    };
+    let message = if left <= chrono::Duration::zero() {
+        format!("Certificate {} expired at {}", status.name, expiry)
+    } else {
+        format!(
+            "Certificate {} expires in {} days ({})",
+            status.name,
+            left.num_days(),
+            expiry
+        )
+    };
+    Some(Alert::new(key, severity, message))
+}
+
+/// Fire or resolve the expiry alert of every certificate
+pub async fn check_expiry(config: &BoltConfig, alerts: &AlertingEngine) -> Result<()> {
+    let renew_before = parse_duration(&config.acme.renew_before)?;
+    let now = Utc::now();
+    for status in status(config) {
+        match expiry_alert(&status, renew_before, now) {
+            Some(alert) => {
+                alerts.fire(&alert).await?;
+            }
+            None => alerts.resolve(&format!("tls.{}", status.name))?,
+        }
+    }
+    Ok(())
+}
+
+/// Renew due certificates every `check_interval` and alert on those close
+/// to expiring, until interrupted
+pub async fn watch(config: &BoltConfig) -> Result<()> {
+    let interval = parse_duration(&config.acme.check_interval)?;
+    let alerts = AlertingEngine::new(&config.data_dir, config.alerts.clone());
+    info!(
+        "👀 Watching {} certificate(s), checking every {}",
+        config.acme.certificates.len(),
+        config.acme.check_interval
+    );
+    loop {
+        match renew(config, &[], false).await {
+            Ok(outcomes) => {
+                for (name, result) in outcomes {
+                    match result {
+                        Ok(true) => info!("✅ Renewed certificate {}", name),
+                        Ok(false) => {}
+                        Err(e) => warn!("Failed to renew certificate {}: {:#}", name, e),
+                    }
+                }
+            }
+            Err(e) => warn!("Certificate renewal failed: {:#}", e),
+        }
+        check_expiry(config, &alerts).await?;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Expiry gauges for the Prometheus endpoint
+pub fn prometheus(certificates: &[CertStatus]) -> String {
+    let mut output = String::new();
+    output.push_str(
+        "# HELP bolt_tls_certificate_expiry_timestamp_seconds When an [acme] certificate expires\n",
+    );
+    output.push_str("# TYPE bolt_tls_certificate_expiry_timestamp_seconds gauge\n");
+    for certificate in certificates {
+        if let Some(expiry) = certificate.not_after {
+            output.push_str(&format!(
+                "bolt_tls_certificate_expiry_timestamp_seconds{{certificate=\"{}\"}} {}\n",
+                certificate.name,
+                expiry.timestamp()
+            ));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+
+    #[test]
+    fn signs_requests_and_tracks_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::new(dir.path());
+
+        // The account key is created once and reused
+        let key = AccountKey::load_or_create(&store).unwrap();
+        let again = AccountKey::load_or_create(&store).unwrap();
+        assert_eq!(key.jwk(), again.jwk());
+        assert_eq!(key.thumbprint(), again.thumbprint());
+        assert_eq!(key.thumbprint().len(), 43);
+
+        let jws = key
+            .sign(
+                &json!({ "alg": "ES256", "url": "https://ca/new-order" }),
+                "e30",
+            )
+            .unwrap();
+        let signed = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key.pair.public_key().as_ref())
+            .verify(signed.as_bytes(), &signature)
+            .unwrap();
+
+        // UTCTime before 2050 and GeneralizedTime after
+        for (year, month, day) in [(2031, 2, 3), (2051, 6, 7)] {
+            let mut params = rcgen::CertificateParams::new(vec!["example.com".to_string()]);
+            params.not_after = rcgen::date_time_ymd(year, month, day);
+            let pem = rcgen::Certificate::from_params(params)
+                .unwrap()
+                .serialize_pem()
+                .unwrap();
+            assert_eq!(
+                not_after(&pem),
+                Some(
+                    Utc.with_ymd_and_hms(year, month as u32, day as u32, 0, 0, 0)
+                        .unwrap()
+                )
+            );
+        }
+        assert_eq!(not_after("not a certificate"), None);
+
+        let now = Utc.with_ymd_and_hms(2031, 1, 1, 0, 0, 0).unwrap();
+        let renew_before = Duration::from_secs(30 * 86400);
+        let status = |days| CertStatus {
+            name: "web".to_string(),
+            domains: vec!["example.com".to_string()],
+            not_after: Some(now + chrono::Duration::days(days)),
+        };
+        assert!(!due(&status(60), renew_before, now));
+        assert!(expiry_alert(&status(60), renew_before, now).is_none());
+        assert!(due(&status(20), renew_before, now));
+        let warning = expiry_alert(&status(20), renew_before, now).unwrap();
+        assert_eq!(warning.severity, AlertSeverity::Warning);
+        assert_eq!(warning.key, "tls.web");
+        assert_eq!(
+            expiry_alert(&status(3), renew_before, now)
+                .unwrap()
+                .severity,
+            AlertSeverity::Critical
+        );
+        assert!(prometheus(&[status(60)]).contains(&format!(
+            "bolt_tls_certificate_expiry_timestamp_seconds{{certificate=\"web\"}} {}",
+            (now + chrono::Duration::days(60)).timestamp()
+        )));
+
+        let responder = Http01Responder::default();
+        responder.insert("token", "token.thumbprint");
+        assert_eq!(
+            responder.answer("token").as_deref(),
+            Some("token.thumbprint")
+        );
+        assert_eq!(responder.answer("other"), None);
+    }
+}
@@ -0,0 +1,770 @@
+// Egress control
+//
+// `[services.<name>.egress]` limits where a container can connect to. The
+// allowlist takes domain names, addresses and CIDR ranges. Bolt loads an
+// nftables table into the container's network namespace whose output chain
+// accepts loopback, replies, DNS to the container's own resolvers (its
+// resolv.conf nameservers and default gateway) and the allowed addresses.
+// Port 53 elsewhere is not an exception to the allowlist. Anything else is
+// dropped, or with `mode = "audit"` let through, and in both cases its
+// destination is recorded in a set that `bolt egress report` reads. Running
+// in audit mode first shows what a service needs before it is locked down.
+//
+// The rules can only be loaded once the container's network namespace
+// exists, so a detached service's entrypoint is a gate that holds its
+// command back until they are. Bolt opens the gate by writing the start time
+// of the container's init to `/run/bolt/egress/ready`; a restarted container
+// has a new start time and waits again, for `surge idle` or the restart
+// supervisor to reload the rules.
+//
+// Domains are resolved on the host, and again every `refresh` by a
+// `bolt egress-sync` process per container that exits with the container.
+// An address a domain stops resolving to stays allowed for `ADDRESS_GRACE`,
+// so connections opened from a cached DNS answer keep working.
+//
+// Needs root, `nsenter` and `nft`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::EgressConfig;
+use crate::runtime::watcher::{self, Watcher};
+
+const SYNC: Watcher = Watcher::new("egress-sync");
+
+const TABLE: &str = "bolt_egress";
+/// Where the gate and its ready marker are mounted in the container
+const GATE_DIR: &str = "/run/bolt/egress";
+const DEFAULT_REFRESH: Duration = Duration::from_secs(30);
+/// How long an address stays allowed after its domain stopped resolving to it
+const ADDRESS_GRACE: Duration = Duration::from_secs(600);
+/// Destinations recorded per address family
+const ATTEMPTS_MAX: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EgressMode {
+    #[default]
+    Enforce,
+    Audit,
+}
+
+impl EgressMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EgressMode::Enforce => "enforce",
+            EgressMode::Audit => "audit",
+        }
+    }
+}
+
+/// A service's egress settings, checked and split by kind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EgressPolicy {
+    pub mode: EgressMode,
+    /// Addresses and CIDR ranges, normalised to `addr/prefix`
+    pub networks: Vec<String>,
+    pub domains: Vec<String>,
+    pub dns: bool,
+    pub refresh_secs: u64,
+}
+
+impl EgressPolicy {
+    pub fn from_config(config: &EgressConfig) -> Result<Self> {
+        let mut networks = Vec::new();
+        let mut domains = Vec::new();
+        for entry in &config.allow {
+            match parse_network(entry)? {
+                Some((addr, prefix)) => networks.push(format!("{}/{}", addr, prefix)),
+                None => domains.push(parse_domain(entry)?),
+            }
+        }
+        let refresh = match config.refresh {
+            Some(ref refresh) => parse_duration(refresh)?,
+            None => DEFAULT_REFRESH,
+        };
+        if refresh.is_zero() {
+            return Err(anyhow!("egress refresh must be at least 1s"));
+        }
+        Ok(Self {
+            mode: config.mode,
+            networks,
+            domains,
+            dns: config.dns.unwrap_or(true),
+            refresh_secs: refresh.as_secs(),
+        })
+    }
+}
+
+/// `10.0.0.0/8` or `1.2.3.4` as an address and prefix length; `None` for
+/// anything that isn't an address
+fn parse_network(entry: &str) -> Result<Option<(IpAddr, u8)>> {
+    let entry = entry.trim();
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+    let Ok(addr) = addr.parse::<IpAddr>() else {
+        if prefix.is_some() {
+            return Err(anyhow!("Invalid CIDR range in egress allowlist: {}", entry));
+        }
+        return Ok(None);
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|p| *p <= max)
+            .ok_or_else(|| anyhow!("Invalid prefix length in egress allowlist: {}", entry))?,
+        None => max,
+    };
+    Ok(Some((addr, prefix)))
+}
+
+fn parse_domain(entry: &str) -> Result<String> {
+    let domain = entry.trim().trim_end_matches('.').to_ascii_lowercase();
+    if domain.contains('*') {
+        return Err(anyhow!(
+            "Wildcard domains can't be resolved; list each host instead of '{}'",
+            entry
+        ));
+    }
+    let valid = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        return Err(anyhow!(
+            "'{}' is not a domain name, address or CIDR range",
+            entry
+        ));
+    }
+    Ok(domain)
+}
+
+/// Policy of a container and the addresses its domains resolve to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EgressState {
+    pub container: String,
+    pub policy: EgressPolicy,
+    /// Pid of the container's init process when the rules were loaded
+    pub pid: u32,
+    /// Each domain's addresses and when it last resolved to them
+    pub resolved: BTreeMap<String, BTreeMap<IpAddr, DateTime<Utc>>>,
+    pub sync_pid: Option<u32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl EgressState {
+    /// Every allowed address or range, split into IPv4 and IPv6
+    fn allowed(&self) -> (Vec<String>, Vec<String>) {
+        let resolved =
+            self.resolved
+                .values()
+                .flat_map(|addrs| addrs.keys())
+                .map(|addr| match addr {
+                    IpAddr::V4(_) => format!("{}/32", addr),
+                    IpAddr::V6(_) => format!("{}/128", addr),
+                });
+        let all: BTreeSet<String> = self
+            .policy
+            .networks
+            .iter()
+            .cloned()
+            .chain(resolved)
+            .collect();
+        all.into_iter().partition(|net| !net.contains(':'))
+    }
+}
+
+/// A destination the container tried to reach that isn't allowed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EgressAttempt {
+    pub address: IpAddr,
+    pub protocol: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EgressReport {
+    pub container: String,
+    pub mode: EgressMode,
+    pub networks: Vec<String>,
+    pub resolved: BTreeMap<String, Vec<IpAddr>>,
+    /// Blocked, or in audit mode would have been blocked
+    pub attempts: Vec<EgressAttempt>,
+    pub syncing: bool,
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("egress").join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("egress").join(format!("{}.log", container))
+}
+
+fn gate_dir(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("egress").join(format!("{}.gate", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<EgressState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+fn save(data_dir: &Path, state: &EgressState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Load the policy into a running container and, when it names domains,
+/// start the process that keeps their addresses current
+pub async fn apply(
+    runtime: &str,
+    data_dir: &Path,
+    container: &str,
+    policy: &EgressPolicy,
+) -> Result<()> {
+    let previous = state(data_dir, container).and_then(|s| s.sync_pid);
+    SYNC.forget(&state_path(data_dir, container), previous);
+    let pid = container_pid(runtime, container)
+        .await
+        .ok_or_else(|| anyhow!("Container {} is not running", container))?;
+
+    let mut state = EgressState {
+        container: container.to_string(),
+        policy: policy.clone(),
+        pid,
+        resolved: BTreeMap::new(),
+        sync_pid: None,
+        updated_at: Utc::now(),
+    };
+    resolve(&mut state).await;
+    let (allowed4, allowed6) = state.allowed();
+    let resolvers = container_resolvers(pid);
+    if policy.dns && resolvers.is_empty() {
+        warn!(
+            "⚠️  {} has no nameserver or gateway to allow DNS to; only allowed addresses resolve",
+            container
+        );
+    }
+    nft(pid, &ruleset(policy, &allowed4, &allowed6, &resolvers))
+        .await
+        .with_context(|| format!("Failed to load the egress policy into {}", container))?;
+
+    if !policy.domains.is_empty() {
+        state.sync_pid =
+            Some(SYNC.spawn(["egress-sync", container], &log_path(data_dir, container))?);
+    }
+    save(data_dir, &state)?;
+    open_gate(data_dir, container, pid)?;
+    info!(
+        "🧱 Egress policy for {} loaded ({}, {} network(s), {} domain(s))",
+        container,
+        policy.mode.as_str(),
+        policy.networks.len(),
+        policy.domains.len()
+    );
+    Ok(())
+}
+
+/// Reload a container's recorded policy after it was started again in a new
+/// network namespace, which also lets its gate open
+pub async fn restore(runtime: &str, data_dir: &Path, container: &str) {
+    let Some(egress) = state(data_dir, container) else {
+        return;
+    };
+    if let Err(e) = apply(runtime, data_dir, container, &egress.policy).await {
+        warn!(
+            "Could not restore the egress policy of {}: {}",
+            container, e
+        );
+    }
+}
+
+/// The entrypoint of a gated container: waits until the policy is loaded
+/// for this start of the container, then runs `command`
+pub fn gate_script(command: &[String]) -> String {
+    let argv: Vec<String> = command
+        .iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
+        .collect();
+    format!(
+        "#!/bin/sh\n\
+         # Held back until bolt has loaded the egress policy\n\
+         start=$(sed 's/.*) //' /proc/1/stat | cut -d ' ' -f 20)\n\
+         until [ \"$(cat {dir}/ready 2>/dev/null)\" = \"$start\" ]; do\n\
+         \x20 sleep 0.2 2>/dev/null || sleep 1\n\
+         done\n\
+         exec {}\n",
+        argv.join(" "),
+        dir = GATE_DIR
+    )
+}
+
+/// Write the container's gate; returns the run arguments that mount it and
+/// make it the entrypoint in front of `command`
+pub fn gate(data_dir: &Path, container: &str, command: &[String]) -> Result<Vec<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if command.is_empty() {
+        return Err(anyhow!(
+            "{} has no command to hold back until its egress policy is loaded; set the service's command",
+            container
+        ));
+    }
+    let dir = gate_dir(data_dir, container);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let _ = std::fs::remove_file(dir.join("ready"));
+    let script = dir.join("gate");
+    std::fs::write(&script, gate_script(command))
+        .with_context(|| format!("Failed to write {}", script.display()))?;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+    Ok(vec![
+        "-v".to_string(),
+        format!("{}:{}:ro", dir.display(), GATE_DIR),
+        "--entrypoint".to_string(),
+        format!("{}/gate", GATE_DIR),
+    ])
+}
+
+/// Let a gated container's command run; `pid` is its init on the host
+fn open_gate(data_dir: &Path, container: &str, pid: u32) -> Result<()> {
+    let dir = gate_dir(data_dir, container);
+    if !dir.join("gate").exists() {
+        return Ok(());
+    }
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .with_context(|| format!("Failed to read the start time of {}", container))?;
+    let start = start_time(&stat).ok_or_else(|| anyhow!("Unexpected /proc/{}/stat format", pid))?;
+    let tmp = dir.join("ready.tmp");
+    std::fs::write(&tmp, format!("{}\n", start))?;
+    std::fs::rename(&tmp, dir.join("ready"))?;
+    Ok(())
+}
+
+/// Field 22 of `/proc/<pid>/stat`, the process's start time; the command
+/// name before it may contain spaces and parentheses
+fn start_time(stat: &str) -> Option<&str> {
+    stat.rsplit_once(") ")?.1.split(' ').nth(19)
+}
+
+/// Resolve the container's domains every `refresh` until it stops. Run by
+/// `bolt egress-sync`.
+pub async fn sync(data_dir: &Path, container: &str) -> Result<()> {
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    loop {
+        let Some(mut state) = state(data_dir, container) else {
+            debug!("No egress policy for {}, stopping", container);
+            return Ok(());
+        };
+        tokio::time::sleep(Duration::from_secs(state.policy.refresh_secs)).await;
+
+        if container_pid(&runtime, container).await != Some(state.pid) {
+            info!("{} stopped, no longer syncing its egress policy", container);
+            return Ok(());
+        }
+        let before = state.allowed();
+        resolve(&mut state).await;
+        let (allowed4, allowed6) = state.allowed();
+        if (&allowed4, &allowed6) != (&before.0, &before.1) {
+            let update = format!(
+                "flush set inet {table} allowed4\nflush set inet {table} allowed6\n{}{}",
+                add_elements("allowed4", &allowed4),
+                add_elements("allowed6", &allowed6),
+                table = TABLE
+            );
+            if let Err(e) = nft(state.pid, &update).await {
+                warn!("Could not update egress sets of {}: {}", container, e);
+                continue;
+            }
+            info!(
+                "🔄 Egress allowlist of {} now has {} address(es)",
+                container,
+                allowed4.len() + allowed6.len()
+            );
+        }
+        state.updated_at = Utc::now();
+        watcher::update(&state_path(data_dir, container), &state)?;
+    }
+}
+
+/// Resolve every domain, keeping addresses seen within `ADDRESS_GRACE`
+async fn resolve(state: &mut EgressState) {
+    let now = Utc::now();
+    let grace = chrono::Duration::from_std(ADDRESS_GRACE).unwrap_or_default();
+    for domain in &state.policy.domains {
+        let seen = state.resolved.entry(domain.clone()).or_default();
+        match tokio::net::lookup_host((domain.as_str(), 0)).await {
+            Ok(addrs) => {
+                for addr in addrs {
+                    seen.insert(addr.ip(), now);
+                }
+            }
+            Err(e) => warn!("Could not resolve {}: {}", domain, e),
+        }
+        seen.retain(|_, last| now - *last < grace);
+    }
+}
+
+fn add_elements(set: &str, elements: &[String]) -> String {
+    if elements.is_empty() {
+        return String::new();
+    }
+    format!(
+        "add element inet {} {} {{ {} }}\n",
+        TABLE,
+        set,
+        elements.join(", ")
+    )
+}
+
+/// The DNS servers a container uses: the nameservers of its resolv.conf and
+/// its default gateway, which podman and docker networks resolve on
+fn container_resolvers(pid: u32) -> Vec<IpAddr> {
+    let mut resolvers = std::fs::read_to_string(format!("/proc/{}/root/etc/resolv.conf", pid))
+        .map(|conf| parse_nameservers(&conf))
+        .unwrap_or_default();
+    if let Some(gateway) = std::fs::read_to_string(format!("/proc/{}/net/route", pid))
+        .ok()
+        .and_then(|routes| parse_default_gateway(&routes))
+        && !resolvers.contains(&gateway)
+    {
+        resolvers.push(gateway);
+    }
+    resolvers
+}
+
+fn parse_nameservers(conf: &str) -> Vec<IpAddr> {
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        // Scoped IPv6 servers (fe80::1%eth0) are matched without the scope
+        .filter_map(|server| server.trim().split('%').next()?.parse().ok())
+        .filter(|ip: &IpAddr| !ip.is_loopback())
+        .collect()
+}
+
+/// The gateway of the default route in `/proc/net/route`, whose addresses
+/// are little-endian hex
+fn parse_default_gateway(routes: &str) -> Option<IpAddr> {
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        (gateway != 0).then(|| IpAddr::from(gateway.to_le_bytes()))
+    })
+}
+
+/// The nftables table for a policy, replacing any earlier one
+fn ruleset(
+    policy: &EgressPolicy,
+    allowed4: &[String],
+    allowed6: &[String],
+    resolvers: &[IpAddr],
+) -> String {
+    let verdict = match policy.mode {
+        EgressMode::Enforce => "drop",
+        EgressMode::Audit => "accept",
+    };
+    let mut dns = String::new();
+    if policy.dns {
+        for (family, ipv6) in [("ip", false), ("ip6", true)] {
+            let servers: Vec<String> = resolvers
+                .iter()
+                .filter(|ip| ip.is_ipv6() == ipv6)
+                .map(|ip| ip.to_string())
+                .collect();
+            if servers.is_empty() {
+                continue;
+            }
+            for protocol in ["udp", "tcp"] {
+                dns.push_str(&format!(
+                    "        {} daddr {{ {} }} {} dport 53 accept\n",
+                    family,
+                    servers.join(", "),
+                    protocol
+                ));
+            }
+        }
+    }
+    format!(
+        "table inet {table}
+delete table inet {table}
+table inet {table} {{
+    set allowed4 {{ type ipv4_addr; flags interval; auto-merge; }}
+    set allowed6 {{ type ipv6_addr; flags interval; auto-merge; }}
+    set attempts4 {{ type ipv4_addr . inet_proto . inet_service; flags dynamic; size {max}; }}
+    set attempts6 {{ type ipv6_addr . inet_proto . inet_service; flags dynamic; size {max}; }}
+    chain output {{
+        type filter hook output priority 0; policy accept;
+        oifname \"lo\" accept
+        ct state established,related accept
+        icmpv6 type {{ nd-neighbor-solicit, nd-neighbor-advert, nd-router-solicit }} accept
+{dns}        ip daddr @allowed4 accept
+        ip6 daddr @allowed6 accept
+        meta l4proto {{ tcp, udp }} add @attempts4 {{ ip daddr . meta l4proto . th dport }}
+        meta l4proto {{ tcp, udp }} add @attempts6 {{ ip6 daddr . meta l4proto . th dport }}
+        {verdict}
+    }}
+}}
+{}{}",
+        add_elements("allowed4", allowed4),
+        add_elements("allowed6", allowed6),
+        table = TABLE,
+        max = ATTEMPTS_MAX,
+        dns = dns,
+        verdict = verdict,
+    )
+}
+
+/// Run an nft script in the network namespace of `pid`
+async fn nft(pid: u32, script: &str) -> Result<String> {
+    let mut child = AsyncCommand::new("nsenter")
+        .args(["-t", &pid.to_string(), "-n", "nft", "-f", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("Failed to run nsenter (egress control needs root, nsenter and nft)")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nft failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn container_pid(runtime: &str, container: &str) -> Option<u32> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Pid}}", container])
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| *pid > 0)
+}
+
+/// The container's policy and the destinations it was denied
+pub async fn report(runtime: &str, data_dir: &Path, container: &str) -> Result<EgressReport> {
+    let state =
+        state(data_dir, container).ok_or_else(|| anyhow!("{} has no egress policy", container))?;
+    let pid = container_pid(runtime, container)
+        .await
+        .filter(|pid| *pid == state.pid)
+        .ok_or_else(|| anyhow!("{} is not running", container))?;
+
+    let mut attempts = Vec::new();
+    for set in ["attempts4", "attempts6"] {
+        let listing = nft(pid, &format!("list set inet {} {}\n", TABLE, set)).await?;
+        attempts.extend(parse_attempts(&listing));
+    }
+    attempts.sort_by_key(|a| (a.address, a.port));
+
+    Ok(EgressReport {
+        container: container.to_string(),
+        mode: state.policy.mode,
+        networks: state.policy.networks.clone(),
+        resolved: state
+            .resolved
+            .iter()
+            .map(|(domain, addrs)| (domain.clone(), addrs.keys().copied().collect()))
+            .collect(),
+        attempts,
+        syncing: state.sync_pid.is_some_and(|pid| SYNC.alive(pid)),
+    })
+}
+
+/// Elements of an `nft list set` listing, `1.2.3.4 . tcp . 443`
+fn parse_attempts(listing: &str) -> Vec<EgressAttempt> {
+    let Some(start) = listing.find("elements = {") else {
+        return Vec::new();
+    };
+    let body = &listing[start + "elements = {".len()..];
+    let body = &body[..body.find('}').unwrap_or(body.len())];
+    body.split(',')
+        .filter_map(|element| {
+            let mut parts = element.split(" . ").map(str::trim);
+            let address = parts.next()?.parse().ok()?;
+            let protocol = parts.next()?.to_string();
+            let port = parts.next()?.parse().ok()?;
+            Some(EgressAttempt {
+                address,
+                protocol,
+                port,
+            })
+        })
+        .collect()
+}
+
+/// Stop syncing a container's domains and drop its policy record and gate.
+/// The nftables table goes away with the container's network namespace.
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.sync_pid);
+    SYNC.forget(&state_path(data_dir, container), pid);
+    let _ = std::fs::remove_dir_all(gate_dir(data_dir, container));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_policy_rules_and_reads_attempts() {
+        let config = EgressConfig {
+            mode: EgressMode::Audit,
+            allow: vec![
+                "Master.Example.com.".to_string(),
+                "10.0.0.0/8".to_string(),
+                "203.0.113.7".to_string(),
+                "2001:db8::/32".to_string(),
+            ],
+            dns: None,
+            refresh: Some("1m".to_string()),
+        };
+        let policy = EgressPolicy::from_config(&config).unwrap();
+        assert_eq!(policy.domains, vec!["master.example.com"]);
+        assert_eq!(
+            policy.networks,
+            vec!["10.0.0.0/8", "203.0.113.7/32", "2001:db8::/32"]
+        );
+        assert!(policy.dns);
+        assert_eq!(policy.refresh_secs, 60);
+
+        for bad in ["*.example.com", "10.0.0.0/33", "not a host", "localhost"] {
+            let config = EgressConfig {
+                allow: vec![bad.to_string()],
+                ..Default::default()
+            };
+            assert!(EgressPolicy::from_config(&config).is_err(), "{}", bad);
+        }
+
+        let mut state = EgressState {
+            container: "arena_server".to_string(),
+            policy: policy.clone(),
+            pid: 1,
+            resolved: BTreeMap::new(),
+            sync_pid: None,
+            updated_at: Utc::now(),
+        };
+        state
+            .resolved
+            .entry("master.example.com".to_string())
+            .or_default()
+            .insert("198.51.100.1".parse().unwrap(), Utc::now());
+        let (allowed4, allowed6) = state.allowed();
+        assert_eq!(
+            allowed4,
+            vec!["10.0.0.0/8", "198.51.100.1/32", "203.0.113.7/32"]
+        );
+        assert_eq!(allowed6, vec!["2001:db8::/32"]);
+
+        let conf = "# Generated\nsearch dns.podman\nnameserver 10.89.0.1\nnameserver 127.0.0.11\nnameserver fe80::1%eth0\n";
+        let resolvers = parse_nameservers(conf);
+        assert_eq!(
+            resolvers,
+            vec![
+                "10.89.0.1".parse::<IpAddr>().unwrap(),
+                "fe80::1".parse().unwrap()
+            ]
+        );
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                      eth0\t00000000\t0158590A\t0003\t0\t0\t0\t00000000\n\
+                      eth0\t0058590A\t00000000\t0001\t0\t0\t0\t00FFFFFF\n";
+        assert_eq!(
+            parse_default_gateway(routes),
+            Some("10.89.88.1".parse().unwrap())
+        );
+        assert_eq!(parse_default_gateway("Iface\tDestination\n"), None);
+
+        let rules = ruleset(&policy, &allowed4, &allowed6, &resolvers);
+        assert!(rules.starts_with("table inet bolt_egress\ndelete table inet bolt_egress\n"));
+        // DNS only reaches the container's resolvers, never port 53 anywhere
+        assert!(rules.contains("ip daddr { 10.89.0.1 } udp dport 53 accept"));
+        assert!(rules.contains("ip daddr { 10.89.0.1 } tcp dport 53 accept"));
+        assert!(rules.contains("ip6 daddr { fe80::1 } udp dport 53 accept"));
+        assert!(!rules.lines().any(|l| l.trim().starts_with("udp dport 53")));
+        assert!(!rules.lines().any(|l| l.trim().starts_with("tcp dport 53")));
+        assert!(!ruleset(&policy, &allowed4, &allowed6, &[]).contains("dport 53"));
+        assert!(rules.contains("add element inet bolt_egress allowed6 { 2001:db8::/32 }"));
+        assert!(rules.trim_end().lines().any(|l| l.trim() == "accept"));
+
+        let listing = "table inet bolt_egress {\n\tset attempts4 {\n\t\ttype ipv4_addr . inet_proto . inet_service\n\t\tsize 4096\n\t\tflags dynamic\n\t\telements = { 1.1.1.1 . tcp . 443, 8.8.4.4 . udp . 123 }\n\t}\n}\n";
+        assert_eq!(
+            parse_attempts(listing),
+            vec![
+                EgressAttempt {
+                    address: "1.1.1.1".parse().unwrap(),
+                    protocol: "tcp".to_string(),
+                    port: 443
+                },
+                EgressAttempt {
+                    address: "8.8.4.4".parse().unwrap(),
+                    protocol: "udp".to_string(),
+                    port: 123
+                },
+            ]
+        );
+        assert!(parse_attempts("table inet bolt_egress { set attempts6 { } }").is_empty());
+    }
+
+    #[test]
+    fn gates_the_command_until_the_policy_is_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = vec!["/srv/server".to_string(), "it's".to_string()];
+        let args = gate(dir.path(), "arena_server", &command).unwrap();
+        assert_eq!(args[2..], ["--entrypoint", "/run/bolt/egress/gate"]);
+        assert!(args[1].ends_with(":/run/bolt/egress:ro"));
+
+        let script = std::fs::read_to_string(
+            dir.path()
+                .join("egress")
+                .join("arena_server.gate")
+                .join("gate"),
+        )
+        .unwrap();
+        assert!(script.contains("cut -d ' ' -f 20"));
+        assert!(script.contains("cat /run/bolt/egress/ready"));
+        assert!(script.ends_with("exec '/srv/server' 'it'\\''s'\n"));
+        assert!(gate(dir.path(), "arena_server", &[]).is_err());
+
+        let stat = "4242 (my (odd) app) S 1 4242 4242 0 -1 4194560 300 0 0 0 1 2 0 0 20 0 1 0 987654 1000 10";
+        assert_eq!(start_time(stat), Some("987654"));
+
+        // The gate opens for this process, whose stat start time it records
+        open_gate(dir.path(), "arena_server", std::process::id()).unwrap();
+        let own = std::fs::read_to_string(format!("/proc/{}/stat", std::process::id())).unwrap();
+        let ready = dir
+            .path()
+            .join("egress")
+            .join("arena_server.gate")
+            .join("ready");
+        assert_eq!(
+            std::fs::read_to_string(&ready).unwrap().trim(),
+            start_time(&own).unwrap()
+        );
+
+        forget(dir.path(), "arena_server");
+        assert!(!ready.exists());
+    }
+}
@@ -0,0 +1,1157 @@
+// External DNS
+//
+// A homelab's names usually live in Pi-hole, PowerDNS or the firewall, not
+// in the container runtime. Bolt registers containers there:
+//
+// - `[networks.<name>.dns]` points `<container>.<zone>` at the container's
+//   address on that network
+// - `[dns.published]` points `<container>.<zone>` at this host for
+//   services that publish ports to other machines
+//
+// Providers are declared under `[dns.providers.<name>]` in config.toml:
+// the PowerDNS HTTP API, Pi-hole 6 local records, dynamic updates (RFC
+// 2136) through `nsupdate`, and OPNsense Unbound host overrides. Bolt only
+// changes names it owns. PowerDNS and RFC 2136 keep a TXT record
+// `heritage=bolt,bolt/owner=<owner>,bolt/container=<container>` next to the
+// addresses, OPNsense keeps the same text as the override's description, and
+// for Pi-hole, which has neither, a name is Bolt's while it only points
+// where Bolt last pointed it.
+//
+// Records are kept in `<data_dir>/dns/<container>.json`. They are removed
+// when surge takes the container away; `bolt network dns --sync` removes
+// those of containers that are gone otherwise, follows address changes and
+// retries records that failed.
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::config::{BoltConfig, BoltFile, ExternalDnsProvider, Service};
+use crate::secrets::{self, SecretStore};
+
+/// Where a record's addresses come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RecordSource {
+    /// The container's address on a runtime network
+    Network { network: String },
+    /// This host, for a service publishing ports
+    Published,
+}
+
+/// A name Bolt registered for a container
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredRecord {
+    pub provider: String,
+    pub name: String,
+    pub zone: String,
+    pub source: RecordSource,
+    pub addresses: Vec<IpAddr>,
+    pub updated_at: DateTime<Utc>,
+    /// Why the provider last refused or couldn't be reached
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsState {
+    pub container: String,
+    pub records: Vec<RegisteredRecord>,
+}
+
+/// What a provider is asked to publish
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Fully qualified, without the trailing dot
+    pub name: String,
+    pub zone: String,
+    pub addresses: Vec<IpAddr>,
+    /// Where Bolt last pointed the name, for providers without ownership
+    /// records
+    pub previous: Vec<IpAddr>,
+    pub ttl: u32,
+    /// Marks the name as Bolt's
+    pub ownership: String,
+}
+
+/// Who holds a name at a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    Free,
+    Ours,
+    Taken,
+}
+
+/// Creates and removes the records of containers
+#[async_trait]
+pub trait DnsRegistry: Send + Sync {
+    /// Point the record's name at its addresses, unless someone else holds it
+    async fn upsert(&self, record: &Record) -> Result<()>;
+
+    /// Remove what `upsert` created; a name someone else took over is left
+    async fn remove(&self, record: &Record) -> Result<()>;
+}
+
+pub fn ownership(owner: &str, container: &str) -> String {
+    format!(
+        "heritage=bolt,bolt/owner={},bolt/container={}",
+        owner, container
+    )
+}
+
+/// `<container>.<zone>`, the container name made a valid DNS label
+pub fn record_name(container: &str, zone: &str) -> String {
+    format!(
+        "{}.{}",
+        crate::runtime::identity::default_hostname(container),
+        zone.trim_end_matches('.')
+    )
+}
+
+fn owner(config: &BoltConfig) -> String {
+    config.dns.owner.clone().unwrap_or_else(|| {
+        hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "bolt".to_string())
+    })
+}
+
+fn secret(store: &SecretStore, value: &str, what: &str) -> Result<String> {
+    if secrets::is_reference(value) {
+        store
+            .resolve(value)
+            .with_context(|| format!("Failed to resolve {}", what))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// The provider `[dns.providers.<name>]` declares
+pub fn provider(config: &BoltConfig, name: &str) -> Result<Box<dyn DnsRegistry>> {
+    let settings = config
+        .dns
+        .providers
+        .get(name)
+        .ok_or_else(|| anyhow!("DNS provider '{}' isn't defined in [dns.providers]", name))?;
+    let store = SecretStore::new(&config.config_dir);
+    let what = |key: &str| format!("[dns.providers.{}] {}", name, key);
+    Ok(match settings {
+        ExternalDnsProvider::Powerdns {
+            url,
+            api_key,
+            server,
+        } => Box::new(PowerDns {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key: secret(&store, api_key, &what("api_key"))?,
+            server: server.clone(),
+        }),
+        ExternalDnsProvider::Pihole { url, password } => Box::new(PiHole {
+            http: reqwest::Client::new(),
+            url: reqwest::Url::parse(url).with_context(|| what("url"))?,
+            password: secret(&store, password, &what("password"))?,
+        }),
+        ExternalDnsProvider::Rfc2136 {
+            server,
+            port,
+            key_file,
+        } => Box::new(Rfc2136 {
+            server: server.clone(),
+            port: *port,
+            key_file: key_file.clone(),
+        }),
+        ExternalDnsProvider::Opnsense {
+            url,
+            api_key,
+            api_secret,
+            ca_cert,
+        } => {
+            let mut http = reqwest::Client::builder();
+            if let Some(path) = ca_cert {
+                let pem = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                http = http.add_root_certificate(
+                    reqwest::Certificate::from_pem(&pem).with_context(|| what("ca_cert"))?,
+                );
+            }
+            Box::new(Opnsense {
+                http: http.build()?,
+                url: url.trim_end_matches('/').to_string(),
+                api_key: secret(&store, api_key, &what("api_key"))?,
+                api_secret: secret(&store, api_secret, &what("api_secret"))?,
+            })
+        }
+    })
+}
+
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text)
+}
+
+struct PowerDns {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+    server: String,
+}
+
+impl PowerDns {
+    fn zone_url(&self, zone: &str) -> String {
+        format!(
+            "{}/api/v1/servers/{}/zones/{}",
+            self.url,
+            self.server,
+            canonical(zone)
+        )
+    }
+
+    async fn rrsets(&self, zone: &str) -> Result<Vec<Value>> {
+        let zone: Value = self
+            .http
+            .get(self.zone_url(zone))
+            .header("X-API-Key", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("PowerDNS has no zone {}", zone))?
+            .json()
+            .await?;
+        Ok(zone["rrsets"].as_array().cloned().unwrap_or_default())
+    }
+
+    async fn patch(&self, zone: &str, rrsets: Vec<Value>) -> Result<()> {
+        self.http
+            .patch(self.zone_url(zone))
+            .header("X-API-Key", &self.api_key)
+            .json(&json!({ "rrsets": rrsets }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Who holds `name` among a PowerDNS zone's rrsets
+pub fn powerdns_claim(rrsets: &[Value], name: &str, ownership: &str) -> Claim {
+    let name = canonical(name);
+    let at_name: Vec<&Value> = rrsets
+        .iter()
+        .filter(|r| r["name"].as_str() == Some(name.as_str()))
+        .collect();
+    if at_name.is_empty() {
+        return Claim::Free;
+    }
+    let owned = at_name.iter().any(|r| {
+        r["type"] == "TXT"
+            && r["records"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|record| record["content"].as_str() == Some(quoted(ownership).as_str()))
+    });
+    if owned { Claim::Ours } else { Claim::Taken }
+}
+
+#[async_trait]
+impl DnsRegistry for PowerDns {
+    async fn upsert(&self, record: &Record) -> Result<()> {
+        let rrsets = self.rrsets(&record.zone).await?;
+        if powerdns_claim(&rrsets, &record.name, &record.ownership) == Claim::Taken {
+            return Err(anyhow!(
+                "{} already exists in PowerDNS and isn't Bolt's",
+                record.name
+            ));
+        }
+        let name = canonical(&record.name);
+        let mut changes = Vec::new();
+        for (kind, v6) in [("A", false), ("AAAA", true)] {
+            let addresses: Vec<Value> = record
+                .addresses
+                .iter()
+                .filter(|a| a.is_ipv6() == v6)
+                .map(|a| json!({ "content": a.to_string(), "disabled": false }))
+                .collect();
+            changes.push(if addresses.is_empty() {
+                json!({ "name": name, "type": kind, "changetype": "DELETE" })
+            } else {
+                json!({
+                    "name": name, "type": kind, "ttl": record.ttl,
+                    "changetype": "REPLACE", "records": addresses,
+                })
+            });
+        }
+        changes.push(json!({
+            "name": name, "type": "TXT", "ttl": record.ttl, "changetype": "REPLACE",
+            "records": [{ "content": quoted(&record.ownership), "disabled": false }],
+        }));
+        self.patch(&record.zone, changes).await
+    }
+
+    async fn remove(&self, record: &Record) -> Result<()> {
+        let rrsets = self.rrsets(&record.zone).await?;
+        if powerdns_claim(&rrsets, &record.name, &record.ownership) != Claim::Ours {
+            debug!("{} isn't Bolt's in PowerDNS, leaving it", record.name);
+            return Ok(());
+        }
+        let name = canonical(&record.name);
+        let changes = ["A", "AAAA", "TXT"]
+            .iter()
+            .map(|kind| json!({ "name": name, "type": kind, "changetype": "DELETE" }))
+            .collect();
+        self.patch(&record.zone, changes).await
+    }
+}
+
+struct PiHole {
+    http: reqwest::Client,
+    url: reqwest::Url,
+    password: String,
+}
+
+impl PiHole {
+    fn api(&self, path: &[&str]) -> Result<reqwest::Url> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid Pi-hole url {}", self.url))?
+            .pop_if_empty()
+            .push("api")
+            .extend(path);
+        Ok(url)
+    }
+
+    /// A session id; None when Pi-hole has no password
+    async fn login(&self) -> Result<Option<String>> {
+        let response: Value = self
+            .http
+            .post(self.api(&["auth"])?)
+            .json(&json!({ "password": self.password }))
+            .send()
+            .await?
+            .error_for_status()
+            .context("Pi-hole refused the password")?
+            .json()
+            .await?;
+        Ok(response["session"]["sid"].as_str().map(str::to_string))
+    }
+
+    async fn logout(&self, sid: &Option<String>) {
+        if let (Some(sid), Ok(url)) = (sid, self.api(&["auth"])) {
+            let _ = self.http.delete(url).header("X-FTL-SID", sid).send().await;
+        }
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: reqwest::Url,
+        sid: &Option<String>,
+    ) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, url);
+        match sid {
+            Some(sid) => request.header("X-FTL-SID", sid),
+            None => request,
+        }
+    }
+
+    async fn hosts(&self, sid: &Option<String>) -> Result<Vec<String>> {
+        let response: Value = self
+            .request(
+                reqwest::Method::GET,
+                self.api(&["config", "dns", "hosts"])?,
+                sid,
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["config"]["dns"]["hosts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|h| h.as_str().map(str::to_string))
+            .collect())
+    }
+
+    async fn change(
+        &self,
+        sid: &Option<String>,
+        method: reqwest::Method,
+        entry: &str,
+    ) -> Result<()> {
+        self.request(method, self.api(&["config", "dns", "hosts", entry])?, sid)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Pi-hole refused to change '{}'", entry))?;
+        Ok(())
+    }
+
+    /// Point the name at `addresses` in a session, logging out afterwards
+    /// so sessions don't pile up. False when the name isn't Bolt's.
+    async fn update(&self, record: &Record, addresses: &[IpAddr]) -> Result<bool> {
+        let sid = self.login().await?;
+        let result: Result<bool> = async {
+            let hosts = self.hosts(&sid).await?;
+            let (claim, existing) = pihole_claim(&hosts, &record.name, &record.previous);
+            if claim == Claim::Taken {
+                return Ok(false);
+            }
+            for (ip, entry) in &existing {
+                if !addresses.contains(ip) {
+                    self.change(&sid, reqwest::Method::DELETE, entry).await?;
+                }
+            }
+            for ip in addresses {
+                if !existing.iter().any(|(existing, _)| existing == ip) {
+                    let entry = format!("{} {}", ip, record.name);
+                    self.change(&sid, reqwest::Method::PUT, &entry).await?;
+                }
+            }
+            Ok(true)
+        }
+        .await;
+        self.logout(&sid).await;
+        result
+    }
+}
+
+/// Who holds `name` among Pi-hole's `ip host...` entries, and the entries
+/// pointing it somewhere. It is Bolt's while it only points at `previous`.
+pub fn pihole_claim(
+    hosts: &[String],
+    name: &str,
+    previous: &[IpAddr],
+) -> (Claim, Vec<(IpAddr, String)>) {
+    let mut existing = Vec::new();
+    let mut foreign = false;
+    for entry in hosts {
+        let mut fields = entry.split_whitespace();
+        let ip = fields.next().and_then(|ip| ip.parse::<IpAddr>().ok());
+        let names: Vec<&str> = fields.collect();
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        match ip {
+            Some(ip) if names.len() == 1 && previous.contains(&ip) => {
+                existing.push((ip, entry.clone()))
+            }
+            _ => foreign = true,
+        }
+    }
+    let claim = if foreign {
+        Claim::Taken
+    } else if existing.is_empty() {
+        Claim::Free
+    } else {
+        Claim::Ours
+    };
+    (claim, existing)
+}
+
+#[async_trait]
+impl DnsRegistry for PiHole {
+    async fn upsert(&self, record: &Record) -> Result<()> {
+        if !self.update(record, &record.addresses).await? {
+            return Err(anyhow!(
+                "{} already is a Pi-hole local record that isn't Bolt's",
+                record.name
+            ));
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, record: &Record) -> Result<()> {
+        if !self.update(record, &[]).await? {
+            debug!("{} isn't Bolt's in Pi-hole, leaving it", record.name);
+        }
+        Ok(())
+    }
+}
+
+struct Rfc2136 {
+    server: String,
+    port: u16,
+    key_file: Option<PathBuf>,
+}
+
+/// An `nsupdate` script applying `updates` to `zone` when `prereq` holds
+pub fn nsupdate_script(
+    server: &str,
+    port: u16,
+    zone: &str,
+    prereq: &str,
+    updates: &[String],
+) -> String {
+    let mut script = format!(
+        "server {} {}\nzone {}\nprereq {}\n",
+        server,
+        port,
+        canonical(zone),
+        prereq
+    );
+    for update in updates {
+        script.push_str(&format!("update {}\n", update));
+    }
+    script.push_str("send\n");
+    script
+}
+
+impl Rfc2136 {
+    /// Send a script; Ok(false) when the server refused it because a
+    /// prerequisite didn't hold
+    async fn send(&self, script: &str) -> Result<bool> {
+        let mut command = AsyncCommand::new("nsupdate");
+        if let Some(ref key_file) = self.key_file {
+            command.arg("-k").arg(key_file);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run nsupdate; is it installed (bind-utils/dnsutils)?")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if output.status.success() {
+            return Ok(true);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if ["NXDOMAIN", "YXDOMAIN", "NXRRSET", "YXRRSET"]
+            .iter()
+            .any(|rcode| stderr.contains(&format!("update failed: {}", rcode)))
+        {
+            return Ok(false);
+        }
+        Err(anyhow!(
+            "nsupdate to {} failed: {}",
+            self.server,
+            stderr.trim()
+        ))
+    }
+
+    fn script(&self, record: &Record, prereq: &str, updates: &[String]) -> String {
+        nsupdate_script(&self.server, self.port, &record.zone, prereq, updates)
+    }
+}
+
+#[async_trait]
+impl DnsRegistry for Rfc2136 {
+    async fn upsert(&self, record: &Record) -> Result<()> {
+        let name = canonical(&record.name);
+        let addresses: Vec<String> = record
+            .addresses
+            .iter()
+            .map(|ip| {
+                let kind = if ip.is_ipv6() { "AAAA" } else { "A" };
+                format!("add {} {} {} {}", name, record.ttl, kind, ip)
+            })
+            .collect();
+        let owned = format!("yxrrset {} TXT {}", name, quoted(&record.ownership));
+
+        // A new name gets the addresses and the ownership record at once
+        let mut create = addresses.clone();
+        create.push(format!(
+            "add {} {} TXT {}",
+            name,
+            record.ttl,
+            quoted(&record.ownership)
+        ));
+        if self
+            .send(&self.script(record, &format!("nxdomain {}", name), &create))
+            .await?
+        {
+            return Ok(());
+        }
+
+        // One that exists is only replaced while its ownership record is ours
+        let mut replace = vec![
+            format!("delete {} A", name),
+            format!("delete {} AAAA", name),
+        ];
+        replace.extend(addresses);
+        if self.send(&self.script(record, &owned, &replace)).await? {
+            return Ok(());
+        }
+        Err(anyhow!("{} already exists and isn't Bolt's", record.name))
+    }
+
+    async fn remove(&self, record: &Record) -> Result<()> {
+        let name = canonical(&record.name);
+        let updates: Vec<String> = ["A", "AAAA", "TXT"]
+            .iter()
+            .map(|kind| format!("delete {} {}", name, kind))
+            .collect();
+        let owned = format!("yxrrset {} TXT {}", name, quoted(&record.ownership));
+        if !self.send(&self.script(record, &owned, &updates)).await? {
+            debug!(
+                "{} isn't Bolt's on {}, leaving it",
+                record.name, self.server
+            );
+        }
+        Ok(())
+    }
+}
+
+struct Opnsense {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+    api_secret: String,
+}
+
+impl Opnsense {
+    async fn call(&self, path: &str, body: Option<Value>) -> Result<Value> {
+        let url = format!("{}/api/unbound/{}", self.url, path);
+        let request = match body {
+            Some(body) => self.http.post(url).json(&body),
+            None => self.http.get(url),
+        };
+        Ok(request
+            .basic_auth(&self.api_key, Some(&self.api_secret))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("OPNsense refused {}", path))?
+            .json()
+            .await?)
+    }
+
+    /// Point the name at `addresses`; false when it isn't Bolt's
+    async fn update(&self, record: &Record, addresses: &[IpAddr]) -> Result<bool> {
+        let (hostname, domain) = record
+            .name
+            .split_once('.')
+            .ok_or_else(|| anyhow!("{} has no domain", record.name))?;
+        let overrides = self
+            .call(
+                "settings/searchHostOverride",
+                Some(json!({ "rowCount": -1 })),
+            )
+            .await?;
+        let rows = overrides["rows"].as_array().cloned().unwrap_or_default();
+        let (claim, ours) = opnsense_claim(&rows, hostname, domain, &record.ownership);
+        if claim == Claim::Taken {
+            return Ok(false);
+        }
+        for uuid in &ours {
+            self.call(
+                &format!("settings/delHostOverride/{}", uuid),
+                Some(json!({})),
+            )
+            .await?;
+        }
+        for ip in addresses {
+            let result = self
+                .call(
+                    "settings/addHostOverride",
+                    Some(json!({ "host": {
+                        "enabled": "1",
+                        "hostname": hostname,
+                        "domain": domain,
+                        "rr": if ip.is_ipv6() { "AAAA" } else { "A" },
+                        "server": ip.to_string(),
+                        "description": record.ownership,
+                    }})),
+                )
+                .await?;
+            if result["result"] != "saved" {
+                return Err(anyhow!(
+                    "OPNsense didn't save {} -> {}: {}",
+                    record.name,
+                    ip,
+                    result["validations"]
+                ));
+            }
+        }
+        if !ours.is_empty() || !addresses.is_empty() {
+            self.call("service/reconfigure", Some(json!({}))).await?;
+        }
+        Ok(true)
+    }
+}
+
+/// Who holds `hostname.domain` among OPNsense's host overrides, and the
+/// uuids of Bolt's
+pub fn opnsense_claim(
+    rows: &[Value],
+    hostname: &str,
+    domain: &str,
+    ownership: &str,
+) -> (Claim, Vec<String>) {
+    let mut ours = Vec::new();
+    let mut foreign = false;
+    for row in rows {
+        if row["hostname"].as_str() != Some(hostname) || row["domain"].as_str() != Some(domain) {
+            continue;
+        }
+        match row["uuid"].as_str() {
+            Some(uuid) if row["description"].as_str() == Some(ownership) => {
+                ours.push(uuid.to_string())
+            }
+            _ => foreign = true,
+        }
+    }
+    let claim = if foreign {
+        Claim::Taken
+    } else if ours.is_empty() {
+        Claim::Free
+    } else {
+        Claim::Ours
+    };
+    (claim, ours)
+}
+
+#[async_trait]
+impl DnsRegistry for Opnsense {
+    async fn upsert(&self, record: &Record) -> Result<()> {
+        if !self.update(record, &record.addresses).await? {
+            return Err(anyhow!(
+                "{} already is an OPNsense host override that isn't Bolt's",
+                record.name
+            ));
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, record: &Record) -> Result<()> {
+        if !self.update(record, &[]).await? {
+            debug!("{} isn't Bolt's in OPNsense, leaving it", record.name);
+        }
+        Ok(())
+    }
+}
+
+fn dns_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("dns")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    dns_dir(data_dir).join(format!("{}.json", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<DnsState> {
+    std::fs::read(state_path(data_dir, container))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+/// Every container with external DNS records, by name
+pub fn list(data_dir: &Path) -> Vec<DnsState> {
+    let mut states: Vec<DnsState> = std::fs::read_dir(dns_dir(data_dir))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .filter_map(|e| std::fs::read(e.path()).ok())
+                .filter_map(|b| serde_json::from_slice(&b).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &DnsState) -> Result<()> {
+    let path = state_path(data_dir, &state.container);
+    if state.records.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(state)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// A container's addresses per network, from `inspect`
+pub fn parse_network_addresses(networks: &Value) -> HashMap<String, Vec<IpAddr>> {
+    networks
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, endpoint)| {
+            let addresses = ["IPAddress", "GlobalIPv6Address"]
+                .iter()
+                .filter_map(|key| endpoint[key].as_str())
+                .filter_map(|ip| ip.parse().ok())
+                .collect();
+            (name.clone(), addresses)
+        })
+        .collect()
+}
+
+/// None when the container doesn't exist
+async fn network_addresses(
+    runtime: &str,
+    container: &str,
+) -> Result<Option<HashMap<String, Vec<IpAddr>>>> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            "{{json .NetworkSettings.Networks}}",
+            container,
+        ])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let networks: Value = serde_json::from_slice(&output.stdout).unwrap_or(Value::Null);
+    Ok(Some(parse_network_addresses(&networks)))
+}
+
+/// The address `[dns.published]` records point at
+fn host_address(config: &BoltConfig) -> Result<IpAddr> {
+    if let Some(address) = config.dns.published.as_ref().and_then(|p| p.address) {
+        return Ok(address);
+    }
+    let gateway = super::nat::default_gateway()
+        .ok_or_else(|| anyhow!("No default route; set [dns.published] address"))?;
+    Ok(IpAddr::V4(super::nat::local_address(gateway)?))
+}
+
+/// The addresses a record should point at now
+fn current_addresses(
+    config: &BoltConfig,
+    source: &RecordSource,
+    networks: &HashMap<String, Vec<IpAddr>>,
+) -> Result<Vec<IpAddr>> {
+    let mut addresses = match source {
+        RecordSource::Network { network } => networks.get(network).cloned().unwrap_or_default(),
+        RecordSource::Published => vec![host_address(config)?],
+    };
+    addresses.sort();
+    if addresses.is_empty() {
+        return Err(anyhow!("The container has no address to register"));
+    }
+    Ok(addresses)
+}
+
+/// Publish one record and note the outcome on it
+async fn publish(
+    config: &BoltConfig,
+    container: &str,
+    record: &mut RegisteredRecord,
+    addresses: Result<Vec<IpAddr>>,
+) {
+    let previous = record.addresses.clone();
+    let result = async {
+        let addresses = addresses?;
+        let request = Record {
+            name: record.name.clone(),
+            zone: record.zone.clone(),
+            addresses: addresses.clone(),
+            previous,
+            ttl: config.dns.ttl,
+            ownership: ownership(&owner(config), container),
+        };
+        provider(config, &record.provider)?.upsert(&request).await?;
+        Ok::<_, anyhow::Error>(addresses)
+    }
+    .await;
+    record.updated_at = Utc::now();
+    match result {
+        Ok(addresses) => {
+            info!(
+                "📇 {} -> {} ({})",
+                record.name,
+                addresses
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                record.provider
+            );
+            record.addresses = addresses;
+            record.error = None;
+        }
+        Err(e) => {
+            warn!(
+                "Could not register {} with {}: {:#}",
+                record.name, record.provider, e
+            );
+            record.error = Some(format!("{:#}", e));
+        }
+    }
+}
+
+async fn unpublish(config: &BoltConfig, container: &str, record: &RegisteredRecord) -> Result<()> {
+    let request = Record {
+        name: record.name.clone(),
+        zone: record.zone.clone(),
+        addresses: Vec::new(),
+        previous: record.addresses.clone(),
+        ttl: config.dns.ttl,
+        ownership: ownership(&owner(config), container),
+    };
+    provider(config, &record.provider)?.remove(&request).await?;
+    info!("📇 Removed {} from {}", record.name, record.provider);
+    Ok(())
+}
+
+/// The records a service's container should have
+pub fn wanted(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service: &Service,
+    container: &str,
+) -> Vec<RegisteredRecord> {
+    let mut records = Vec::new();
+    let mut add = |provider: &str, zone: &str, source: RecordSource| {
+        records.push(RegisteredRecord {
+            provider: provider.to_string(),
+            name: record_name(container, zone),
+            zone: zone.trim_end_matches('.').to_string(),
+            source,
+            addresses: Vec::new(),
+            updated_at: Utc::now(),
+            error: None,
+        });
+    };
+    for (key, _) in service.networks.iter().flat_map(|n| n.attachments()) {
+        let Some(declared) = boltfile.networks.as_ref().and_then(|n| n.get(key)) else {
+            continue;
+        };
+        if let Some(ref dns) = declared.dns {
+            let network = declared.name.clone().unwrap_or_else(|| key.to_string());
+            add(&dns.provider, &dns.zone, RecordSource::Network { network });
+        }
+    }
+    if let Some(ref published) = config.dns.published
+        && !super::nat::published_ports(service.ports.as_deref().unwrap_or_default()).is_empty()
+    {
+        add(
+            &published.provider,
+            &published.zone,
+            RecordSource::Published,
+        );
+    }
+    records
+}
+
+fn same_record(a: &RegisteredRecord, b: &RegisteredRecord) -> bool {
+    a.provider == b.provider && a.name == b.name && a.source == b.source
+}
+
+/// Register a started container's names, and remove those its service no
+/// longer asks for. A provider that fails is reported and retried by
+/// `bolt network dns --sync`; it doesn't fail the service.
+pub async fn register(
+    runtime: &str,
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service: &Service,
+    container: &str,
+) -> Result<()> {
+    let mut records = wanted(config, boltfile, service, container);
+    let previous = state(&config.data_dir, container)
+        .map(|s| s.records)
+        .unwrap_or_default();
+    if records.is_empty() && previous.is_empty() {
+        return Ok(());
+    }
+
+    let networks = network_addresses(runtime, container)
+        .await?
+        .ok_or_else(|| anyhow!("Container {} doesn't exist", container))?;
+    let mut kept = Vec::new();
+    for old in previous {
+        match records.iter_mut().find(|r| same_record(r, &old)) {
+            // Carried over so providers know where Bolt pointed the name
+            Some(record) => record.addresses = old.addresses,
+            None => {
+                if let Err(e) = unpublish(config, container, &old).await {
+                    warn!(
+                        "Could not remove {} from {}: {:#}",
+                        old.name, old.provider, e
+                    );
+                    kept.push(RegisteredRecord {
+                        error: Some(format!("{:#}", e)),
+                        ..old
+                    });
+                }
+            }
+        }
+    }
+    for record in &mut records {
+        let addresses = current_addresses(config, &record.source, &networks);
+        publish(config, container, record, addresses).await;
+    }
+    records.extend(kept);
+    save(
+        &config.data_dir,
+        &DnsState {
+            container: container.to_string(),
+            records,
+        },
+    )
+}
+
+/// Remove a container's records. Those a provider fails to remove are kept
+/// for `bolt network dns --sync`.
+pub async fn release(config: &BoltConfig, container: &str) {
+    let Some(state) = state(&config.data_dir, container) else {
+        return;
+    };
+    let mut kept = Vec::new();
+    for record in state.records {
+        if let Err(e) = unpublish(config, container, &record).await {
+            warn!(
+                "Could not remove {} from {}: {:#}",
+                record.name, record.provider, e
+            );
+            kept.push(RegisteredRecord {
+                error: Some(format!("{:#}", e)),
+                ..record
+            });
+        }
+    }
+    let state = DnsState {
+        container: container.to_string(),
+        records: kept,
+    };
+    if let Err(e) = save(&config.data_dir, &state) {
+        warn!("{:#}", e);
+    }
+}
+
+/// Remove the records of containers that are gone, follow address changes
+/// and retry records that failed
+pub async fn sync(runtime: &str, config: &BoltConfig) -> Result<Vec<DnsState>> {
+    for mut state in list(&config.data_dir) {
+        let Some(networks) = network_addresses(runtime, &state.container).await? else {
+            release(config, &state.container).await;
+            continue;
+        };
+        for record in &mut state.records {
+            let addresses = current_addresses(config, &record.source, &networks);
+            let changed = match addresses {
+                Ok(ref addresses) => *addresses != record.addresses,
+                // A stopped container keeps its names until it is removed
+                Err(_) => false,
+            };
+            if changed || record.error.is_some() {
+                publish(config, &state.container, record, addresses).await;
+            }
+        }
+        save(&config.data_dir, &state)?;
+    }
+    Ok(list(&config.data_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_names_only_when_bolt_owns_them() {
+        let own = ownership("node1", "app_web");
+        assert_eq!(own, "heritage=bolt,bolt/owner=node1,bolt/container=app_web");
+        assert_eq!(
+            record_name("app_web_2", "lab.example."),
+            "app-web-2.lab.example"
+        );
+
+        // PowerDNS: the ownership TXT record decides
+        let rrsets = vec![
+            json!({ "name": "app-web.lab.example.", "type": "A", "records": [{ "content": "10.0.0.5" }] }),
+            json!({ "name": "app-web.lab.example.", "type": "TXT", "records": [{ "content": quoted(&own) }] }),
+            json!({ "name": "nas.lab.example.", "type": "A", "records": [{ "content": "10.0.0.2" }] }),
+        ];
+        assert_eq!(
+            powerdns_claim(&rrsets, "app-web.lab.example", &own),
+            Claim::Ours
+        );
+        assert_eq!(
+            powerdns_claim(&rrsets, "nas.lab.example", &own),
+            Claim::Taken
+        );
+        assert_eq!(powerdns_claim(&rrsets, "db.lab.example", &own), Claim::Free);
+        let other = ownership("node2", "app_web");
+        assert_eq!(
+            powerdns_claim(&rrsets, "app-web.lab.example", &other),
+            Claim::Taken
+        );
+
+        // Pi-hole: Bolt's while the name only points where Bolt left it
+        let hosts = vec![
+            "10.0.0.5 app-web.lab.example".to_string(),
+            "10.0.0.2 nas.lab.example nas".to_string(),
+        ];
+        let previous: Vec<IpAddr> = vec!["10.0.0.5".parse().unwrap()];
+        let (claim, existing) = pihole_claim(&hosts, "app-web.lab.example", &previous);
+        assert_eq!(claim, Claim::Ours);
+        assert_eq!(existing, vec![(previous[0], hosts[0].clone())]);
+        assert_eq!(
+            pihole_claim(&hosts, "app-web.lab.example", &[]).0,
+            Claim::Taken
+        );
+        assert_eq!(pihole_claim(&hosts, "nas", &previous).0, Claim::Taken);
+        assert_eq!(pihole_claim(&hosts, "db.lab.example", &[]).0, Claim::Free);
+
+        // OPNsense: the override's description
+        let rows = vec![
+            json!({ "uuid": "a1", "hostname": "app-web", "domain": "lab.example", "description": own }),
+            json!({ "uuid": "b2", "hostname": "nas", "domain": "lab.example", "description": "" }),
+        ];
+        assert_eq!(
+            opnsense_claim(&rows, "app-web", "lab.example", &own),
+            (Claim::Ours, vec!["a1".to_string()])
+        );
+        assert_eq!(
+            opnsense_claim(&rows, "nas", "lab.example", &own).0,
+            Claim::Taken
+        );
+        assert_eq!(
+            opnsense_claim(&rows, "db", "lab.example", &own).0,
+            Claim::Free
+        );
+    }
+
+    #[test]
+    fn builds_nsupdate_scripts_and_reads_container_addresses() {
+        let script = nsupdate_script(
+            "10.0.0.1",
+            53,
+            "lab.example",
+            "nxdomain app-web.lab.example.",
+            &["add app-web.lab.example. 300 A 10.0.0.5".to_string()],
+        );
+        assert_eq!(
+            script,
+            "server 10.0.0.1 53\nzone lab.example.\nprereq nxdomain app-web.lab.example.\n\
+             update add app-web.lab.example. 300 A 10.0.0.5\nsend\n"
+        );
+
+        let networks = json!({
+            "lan": { "IPAddress": "10.0.0.5", "GlobalIPv6Address": "fd00::5" },
+            "backend": { "IPAddress": "", "GlobalIPv6Address": "" },
+        });
+        let addresses = parse_network_addresses(&networks);
+        assert_eq!(
+            addresses["lan"],
+            vec![
+                "10.0.0.5".parse::<IpAddr>().unwrap(),
+                "fd00::5".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert!(addresses["backend"].is_empty());
+    }
+}
@@ -0,0 +1,680 @@
+// Physical NIC features for Bolt networks: bonded/teamed uplinks on the
+// network bridge and SR-IOV virtual functions moved straight into containers.
+//
+// Everything here drives iproute2/teamd and sysfs directly and needs root.
+use anyhow::{Context, Result, anyhow};
+use sha2::{Digest, Sha256};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use crate::config::{NetworkHardware, SriovConfig, UplinkConfig, UplinkKind};
+use crate::runtime::doctor::DoctorCheck;
+
+const BOND_MODES: &[&str] = &[
+    "balance-rr",
+    "active-backup",
+    "balance-xor",
+    "broadcast",
+    "802.3ad",
+    "balance-tlb",
+    "balance-alb",
+];
+
+/// Kernel limit on interface names (IFNAMSIZ - 1)
+const IFNAME_MAX: usize = 15;
+
+/// Create the network's uplink and VFs and attach the uplink to its bridge
+pub async fn prepare_network(
+    runtime: &str,
+    network: &str,
+    hardware: &NetworkHardware,
+    subnet: Option<&str>,
+) -> Result<()> {
+    if !network_exists(runtime, network).await {
+        crate::network::create_network(network, "bridge", subnet).await?;
+    }
+
+    if let Some(ref uplink) = hardware.uplink {
+        create_uplink(uplink).await?;
+        let bridge = bridge_interface(runtime, network).await?;
+        ip(&["link", "set", "dev", &uplink.name, "master", &bridge]).await?;
+        info!(
+            "🔗 Uplink {} attached to bridge {} ({})",
+            uplink.name, bridge, network
+        );
+    }
+
+    if let Some(ref sriov) = hardware.sriov {
+        ensure_vfs(sriov)?;
+    }
+
+    Ok(())
+}
+
+async fn network_exists(runtime: &str, network: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["network", "inspect", network])
+        .output()
+        .await
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Host-side Linux bridge backing a podman/docker bridge network
+pub async fn bridge_interface(runtime: &str, network: &str) -> Result<String> {
+    let format = if runtime == "podman" {
+        "{{.NetworkInterface}}"
+    } else {
+        "{{index .Options \"com.docker.network.bridge.name\"}} {{.Id}}"
+    };
+    let output = AsyncCommand::new(runtime)
+        .args(["network", "inspect", "--format", format, network])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to inspect network {}: {}",
+            network,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = stdout.split_whitespace().collect();
+    match fields.as_slice() {
+        [name] if runtime == "podman" => Ok(name.to_string()),
+        [name, _id] => Ok(name.to_string()),
+        // Docker only sets the option for custom bridge names ("<no value>")
+        [.., id] if runtime != "podman" && id.len() >= 12 => Ok(format!("br-{}", &id[..12])),
+        _ => Err(anyhow!("Network {} has no bridge interface", network)),
+    }
+}
+
+async fn create_uplink(uplink: &UplinkConfig) -> Result<()> {
+    if Path::new("/sys/class/net").join(&uplink.name).exists() {
+        debug!("Uplink {} already exists", uplink.name);
+        return Ok(());
+    }
+
+    match uplink.kind {
+        UplinkKind::Bond => {
+            let mode = uplink.mode.as_deref().unwrap_or("active-backup");
+            ip(&[
+                "link",
+                "add",
+                "name",
+                &uplink.name,
+                "type",
+                "bond",
+                "mode",
+                mode,
+                "miimon",
+                "100",
+            ])
+            .await?;
+            for member in &uplink.interfaces {
+                // Members must be down before they can be enslaved
+                ip(&["link", "set", "dev", member, "down"]).await?;
+                ip(&["link", "set", "dev", member, "master", &uplink.name]).await?;
+            }
+        }
+        UplinkKind::Team => {
+            let runner = uplink.mode.as_deref().unwrap_or("activebackup");
+            let config = serde_json::json!({ "runner": { "name": runner } }).to_string();
+            run("teamd", &["-d", "-t", &uplink.name, "-c", &config]).await?;
+            for member in &uplink.interfaces {
+                ip(&["link", "set", "dev", member, "down"]).await?;
+                run("teamdctl", &[&uplink.name, "port", "add", member]).await?;
+            }
+        }
+    }
+
+    if let Some(mtu) = uplink.mtu {
+        ip(&["link", "set", "dev", &uplink.name, "mtu", &mtu.to_string()]).await?;
+    }
+    ip(&["link", "set", "dev", &uplink.name, "up"]).await?;
+
+    info!(
+        "🔗 Created {:?} uplink {} over {}",
+        uplink.kind,
+        uplink.name,
+        uplink.interfaces.join(", ")
+    );
+    Ok(())
+}
+
+fn pf_device(sriov: &SriovConfig) -> PathBuf {
+    Path::new("/sys/class/net")
+        .join(&sriov.physical_function)
+        .join("device")
+}
+
+fn read_sysfs_u32(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Make sure the physical function has the configured number of VFs, bound
+/// to the configured driver
+fn ensure_vfs(sriov: &SriovConfig) -> Result<()> {
+    let device = pf_device(sriov);
+    let total = read_sysfs_u32(&device.join("sriov_totalvfs"))
+        .ok_or_else(|| anyhow!("{} does not support SR-IOV", sriov.physical_function))?;
+    if sriov.num_vfs > total {
+        return Err(anyhow!(
+            "{} supports at most {} VFs, {} requested",
+            sriov.physical_function,
+            total,
+            sriov.num_vfs
+        ));
+    }
+
+    let current = read_sysfs_u32(&device.join("sriov_numvfs")).unwrap_or(0);
+    if current != sriov.num_vfs {
+        // The kernel refuses to change a non-zero VF count directly, and going
+        // through 0 pulls every VF out of whatever holds it
+        if current != 0 {
+            let busy = busy_vfs(&device, current);
+            if !busy.is_empty() {
+                let busy: Vec<String> = busy.iter().map(|i| i.to_string()).collect();
+                return Err(anyhow!(
+                    "{} has {} VFs, not {}, and VF(s) {} are in use; stop the containers or VMs holding them, or reset the count by hand with `echo 0 > {}`",
+                    sriov.physical_function,
+                    current,
+                    sriov.num_vfs,
+                    busy.join(", "),
+                    device.join("sriov_numvfs").display()
+                ));
+            }
+            std::fs::write(device.join("sriov_numvfs"), "0")?;
+        }
+        std::fs::write(device.join("sriov_numvfs"), sriov.num_vfs.to_string())
+            .with_context(|| format!("Failed to create VFs on {}", sriov.physical_function))?;
+        info!(
+            "🧩 Created {} VFs on {}",
+            sriov.num_vfs, sriov.physical_function
+        );
+    }
+
+    if let Some(ref driver) = sriov.driver {
+        for index in 0..sriov.num_vfs {
+            bind_vf_driver(&vf_pci_address(sriov, index)?, driver)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// VFs bound to a driver but without a netdev in the host namespace: moved
+/// into a container, or held by vfio-pci for a VM we can't see
+fn busy_vfs(device: &Path, count: u32) -> Vec<u32> {
+    (0..count)
+        .filter(|index| {
+            let vf = device.join(format!("virtfn{}", index));
+            let bound = vf.join("driver").exists();
+            let on_host =
+                std::fs::read_dir(vf.join("net")).is_ok_and(|mut entries| entries.next().is_some());
+            bound && !on_host
+        })
+        .collect()
+}
+
+fn vf_pci_address(sriov: &SriovConfig, index: u32) -> Result<String> {
+    let link = std::fs::read_link(pf_device(sriov).join(format!("virtfn{}", index)))
+        .with_context(|| format!("VF {} of {} not found", index, sriov.physical_function))?;
+    link.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("Invalid VF link for {}", sriov.physical_function))
+}
+
+fn bind_vf_driver(pci_address: &str, driver: &str) -> Result<()> {
+    let device = Path::new("/sys/bus/pci/devices").join(pci_address);
+    let current = std::fs::read_link(device.join("driver"))
+        .ok()
+        .and_then(|d| d.file_name().map(|n| n.to_string_lossy().to_string()));
+    if current.as_deref() == Some(driver) {
+        return Ok(());
+    }
+
+    std::fs::write(device.join("driver_override"), driver)?;
+    if current.is_some() {
+        std::fs::write(device.join("driver/unbind"), pci_address)?;
+    }
+    std::fs::write("/sys/bus/pci/drivers_probe", pci_address)
+        .with_context(|| format!("Failed to bind {} to {}", pci_address, driver))?;
+    debug!("Bound VF {} to {}", pci_address, driver);
+    Ok(())
+}
+
+/// Netdev name of a VF while it is still in the host namespace
+fn vf_netdev(pci_address: &str) -> Option<String> {
+    std::fs::read_dir(
+        Path::new("/sys/bus/pci/devices")
+            .join(pci_address)
+            .join("net"),
+    )
+    .ok()?
+    .flatten()
+    .next()
+    .map(|e| e.file_name().to_string_lossy().to_string())
+}
+
+/// Locally administered MAC derived from the network and container names, so
+/// a recreated container keeps its address
+pub fn vf_mac(network: &str, container: &str) -> String {
+    let digest = Sha256::digest(format!("{}/{}", network, container).as_bytes());
+    format!(
+        "02:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        digest[0], digest[1], digest[2], digest[3], digest[4]
+    )
+}
+
+/// Move a free VF into a running container's network namespace
+pub async fn attach_vf(
+    runtime: &str,
+    container: &str,
+    network: &str,
+    sriov: &SriovConfig,
+) -> Result<String> {
+    if sriov.driver.as_deref() == Some("vfio-pci") {
+        return Err(anyhow!(
+            "VFs on {} are bound to vfio-pci and can only be passed to VM capsules",
+            sriov.physical_function
+        ));
+    }
+
+    // Two containers starting at once must not pick the same VF
+    let _lock = lock_vfs(&sriov.physical_function).await?;
+
+    // VFs already handed to a container are no longer visible on the host
+    let (index, netdev) = (0..sriov.num_vfs)
+        .find_map(|index| {
+            let address = vf_pci_address(sriov, index).ok()?;
+            vf_netdev(&address).map(|netdev| (index, netdev))
+        })
+        .ok_or_else(|| anyhow!("No free VFs left on {}", sriov.physical_function))?;
+
+    let mac = vf_mac(network, container);
+    let index = index.to_string();
+    let mut args = vec![
+        "link",
+        "set",
+        "dev",
+        &sriov.physical_function,
+        "vf",
+        &index,
+        "mac",
+        &mac,
+    ];
+    let vlan = sriov.vlan.map(|v| v.to_string());
+    if let Some(ref vlan) = vlan {
+        args.extend(["vlan", vlan.as_str()]);
+    }
+    if sriov.trust {
+        args.extend(["trust", "on"]);
+    }
+    ip(&args).await?;
+
+    let pid = container_pid(runtime, container).await?;
+    ip(&["link", "set", "dev", &netdev, "netns", &pid]).await?;
+
+    let mut ifname = format!("net-{}", network);
+    ifname.truncate(IFNAME_MAX);
+    run(
+        "nsenter",
+        &[
+            "-t", &pid, "-n", "ip", "link", "set", "dev", &netdev, "name", &ifname,
+        ],
+    )
+    .await?;
+    run(
+        "nsenter",
+        &["-t", &pid, "-n", "ip", "link", "set", "dev", &ifname, "up"],
+    )
+    .await?;
+
+    info!(
+        "🧩 VF {} of {} attached to {} as {} ({})",
+        index, sriov.physical_function, container, ifname, mac
+    );
+    Ok(ifname)
+}
+
+/// Serializes VF assignment on one physical function across bolt
+/// processes; released when dropped
+async fn lock_vfs(physical_function: &str) -> Result<std::fs::File> {
+    let path = PathBuf::from(format!("/run/lock/bolt-sriov-{}.lock", physical_function));
+    tokio::task::spawn_blocking(move || {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        // SAFETY: flock(2) on a descriptor we own
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to lock {}", path.display()));
+        }
+        Ok(file)
+    })
+    .await?
+}
+
+async fn container_pid(runtime: &str, container: &str) -> Result<String> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Pid}}", container])
+        .output()
+        .await?;
+    let pid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || pid.is_empty() || pid == "0" {
+        return Err(anyhow!("Container {} is not running", container));
+    }
+    Ok(pid)
+}
+
+async fn ip(args: &[&str]) -> Result<()> {
+    run("ip", args).await
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = AsyncCommand::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Preflight checks for `bolt doctor`
+pub async fn preflight(network: &str, hardware: &NetworkHardware) -> Vec<DoctorCheck> {
+    let area = format!("network/{}", network);
+    let area = area.as_str();
+    let mut checks = Vec::new();
+
+    checks.push(
+        if crate::runtime::capabilities::effective_uid() == Some(0) {
+            DoctorCheck::pass(area, "privileges", "running as root")
+        } else {
+            DoctorCheck::fail(
+                area,
+                "privileges",
+                "hardware networking needs root",
+                "run `bolt surge up` with sudo or as a rootful service",
+            )
+        },
+    );
+    for tool in ["ip", "nsenter"] {
+        if !in_path(tool) {
+            checks.push(DoctorCheck::fail(
+                area,
+                tool,
+                format!("{} not found in PATH", tool),
+                "install iproute2 and util-linux",
+            ));
+        }
+    }
+
+    if let Some(ref uplink) = hardware.uplink {
+        checks.extend(uplink_checks(area, uplink));
+    }
+    if let Some(ref sriov) = hardware.sriov {
+        checks.extend(sriov_checks(area, sriov));
+    }
+
+    checks
+}
+
+fn uplink_checks(area: &str, uplink: &UplinkConfig) -> Vec<DoctorCheck> {
+    let mut checks = Vec::new();
+    let name = format!("uplink {}", uplink.name);
+
+    match uplink.kind {
+        UplinkKind::Bond => {
+            if !Path::new("/sys/module/bonding").exists() {
+                checks.push(DoctorCheck::warn(
+                    area,
+                    &name,
+                    "bonding module not loaded",
+                    "modprobe bonding",
+                ));
+            }
+            if let Some(ref mode) = uplink.mode {
+                if !BOND_MODES.contains(&mode.as_str()) {
+                    checks.push(DoctorCheck::fail(
+                        area,
+                        &name,
+                        format!("unknown bond mode '{}'", mode),
+                        format!("use one of: {}", BOND_MODES.join(", ")),
+                    ));
+                }
+            }
+        }
+        UplinkKind::Team => {
+            if !in_path("teamd") || !in_path("teamdctl") {
+                checks.push(DoctorCheck::fail(
+                    area,
+                    &name,
+                    "teamd not found in PATH",
+                    "install libteam or switch to kind = \"bond\"",
+                ));
+            }
+        }
+    }
+
+    if uplink.interfaces.is_empty() {
+        checks.push(DoctorCheck::fail(
+            area,
+            &name,
+            "no member interfaces",
+            "list NICs in `interfaces`",
+        ));
+    }
+
+    let mut speeds = Vec::new();
+    for member in &uplink.interfaces {
+        let sysfs = Path::new("/sys/class/net").join(member);
+        if !sysfs.exists() {
+            checks.push(DoctorCheck::fail(
+                area,
+                &name,
+                format!("interface {} not found", member),
+                "check `ip link` for NIC names",
+            ));
+            continue;
+        }
+        if let Some(master) = std::fs::read_link(sysfs.join("master"))
+            .ok()
+            .and_then(|m| m.file_name().map(|n| n.to_string_lossy().to_string()))
+        {
+            if master != uplink.name {
+                checks.push(DoctorCheck::fail(
+                    area,
+                    &name,
+                    format!("{} is already enslaved to {}", member, master),
+                    format!("release it with `ip link set {} nomaster`", member),
+                ));
+            }
+        }
+        // Speed reads as -1 (or fails) while the link is down
+        if let Some(speed) = std::fs::read_to_string(sysfs.join("speed"))
+            .ok()
+            .and_then(|s| s.trim().parse::<i64>().ok())
+            .filter(|s| *s > 0)
+        {
+            speeds.push((member.as_str(), speed));
+        }
+    }
+
+    if speeds.windows(2).any(|w| w[0].1 != w[1].1) {
+        let detail = speeds
+            .iter()
+            .map(|(member, speed)| format!("{}={}Mb/s", member, speed))
+            .collect::<Vec<_>>()
+            .join(", ");
+        checks.push(DoctorCheck::warn(
+            area,
+            &name,
+            format!("member link speeds differ ({})", detail),
+            "mismatched members reduce 802.3ad throughput",
+        ));
+    }
+
+    if checks.is_empty() {
+        checks.push(DoctorCheck::pass(
+            area,
+            &name,
+            format!("{} members ready", uplink.interfaces.len()),
+        ));
+    }
+    checks
+}
+
+fn sriov_checks(area: &str, sriov: &SriovConfig) -> Vec<DoctorCheck> {
+    let name = format!("sr-iov {}", sriov.physical_function);
+    let device = pf_device(sriov);
+
+    if !Path::new("/sys/class/net")
+        .join(&sriov.physical_function)
+        .exists()
+    {
+        return vec![DoctorCheck::fail(
+            area,
+            &name,
+            "physical function not found",
+            "check `ip link` for NIC names",
+        )];
+    }
+    let Some(total) = read_sysfs_u32(&device.join("sriov_totalvfs")) else {
+        return vec![DoctorCheck::fail(
+            area,
+            &name,
+            "NIC does not support SR-IOV",
+            "enable SR-IOV in firmware/BIOS or use a capable NIC",
+        )];
+    };
+
+    let mut checks = Vec::new();
+    if sriov.num_vfs == 0 || sriov.num_vfs > total {
+        checks.push(DoctorCheck::fail(
+            area,
+            &name,
+            format!(
+                "num_vfs = {} but the NIC supports 1-{}",
+                sriov.num_vfs, total
+            ),
+            "lower num_vfs",
+        ));
+    }
+
+    let iommu_groups = std::fs::read_dir("/sys/kernel/iommu_groups")
+        .map(|d| d.count())
+        .unwrap_or(0);
+    if iommu_groups == 0 {
+        let vfio = sriov.driver.as_deref() == Some("vfio-pci");
+        let hint = "enable VT-d/AMD-Vi and boot with intel_iommu=on iommu=pt";
+        checks.push(if vfio {
+            DoctorCheck::fail(area, &name, "IOMMU disabled, vfio-pci cannot bind", hint)
+        } else {
+            DoctorCheck::warn(area, &name, "IOMMU disabled", hint)
+        });
+    }
+
+    if let Some(ref driver) = sriov.driver {
+        if !Path::new("/sys/bus/pci/drivers").join(driver).exists() {
+            checks.push(DoctorCheck::fail(
+                area,
+                &name,
+                format!("driver {} not loaded", driver),
+                format!("modprobe {}", driver),
+            ));
+        }
+    }
+
+    if sriov.trust && sriov.vlan.is_none() {
+        checks.push(DoctorCheck::warn(
+            area,
+            &name,
+            "trusted VFs without a VLAN see all untagged traffic",
+            "set `vlan` or disable `trust`",
+        ));
+    }
+
+    if checks.is_empty() {
+        checks.push(DoctorCheck::pass(
+            area,
+            &name,
+            format!("{} of {} VFs available", sriov.num_vfs, total),
+        ));
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vf_macs_are_stable_local_unicast_addresses() {
+        let mac = vf_mac("fabric", "myapp_worker");
+        assert_eq!(mac, vf_mac("fabric", "myapp_worker"));
+        assert_ne!(mac, vf_mac("fabric", "myapp_worker_2"));
+        assert_ne!(mac, vf_mac("storage", "myapp_worker"));
+
+        let octets: Vec<u8> = mac
+            .split(':')
+            .map(|o| u8::from_str_radix(o, 16).unwrap())
+            .collect();
+        assert_eq!(octets.len(), 6);
+        // Locally administered, not multicast
+        assert_eq!(octets[0] & 0b11, 0b10);
+    }
+
+    #[test]
+    fn vfs_bound_without_a_host_netdev_are_busy() {
+        let dir = tempfile::tempdir().unwrap();
+        let device = dir.path();
+        let drivers = device.join("drivers");
+        for driver in ["iavf", "vfio-pci"] {
+            std::fs::create_dir_all(drivers.join(driver)).unwrap();
+        }
+        let vf = |index: u32, driver: Option<&str>, netdev: Option<&str>| {
+            let vf = device.join(format!("virtfn{}", index));
+            std::fs::create_dir_all(vf.join("net")).unwrap();
+            if let Some(driver) = driver {
+                std::os::unix::fs::symlink(drivers.join(driver), vf.join("driver")).unwrap();
+            }
+            if let Some(netdev) = netdev {
+                std::fs::create_dir_all(vf.join("net").join(netdev)).unwrap();
+            }
+        };
+        vf(0, Some("iavf"), Some("enp5s0f0v0"));
+        // Moved into a container's namespace
+        vf(1, Some("iavf"), None);
+        // Held by vfio-pci
+        vf(2, Some("vfio-pci"), None);
+        // Unbound
+        vf(3, None, None);
+
+        assert_eq!(busy_vfs(device, 4), vec![1, 2]);
+        assert!(busy_vfs(device, 1).is_empty());
+    }
+}
@@ -3,16 +3,27 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, info, warn};
 
+#[cfg(feature = "quic-networking")]
+pub mod acme;
 pub mod advanced_networking;
 pub mod bridge;
 pub mod ebpf;
+pub mod egress;
+pub mod external_dns;
 pub mod firewall_advanced;
+pub mod hardware;
+pub mod nat;
 pub mod quic;
 pub mod quic_proxy;
 pub mod quic_real;
+#[cfg(feature = "quic-networking")]
+pub mod relay;
+pub mod rootless;
+pub mod topology;
+pub mod tuning;
 
 // Re-export main networking types
 pub use advanced_networking::BoltAdvancedNetworking;
@@ -47,6 +58,8 @@ pub struct NetworkInterface {
     pub mac_address: String,
     pub mtu: u16,
     pub namespace: String,
+    /// Network the interface is attached to
+    pub network: String,
 }
 
 /// Network performance metrics
@@ -61,9 +74,16 @@ pub struct NetworkMetrics {
 }
 
 /// Bolt Network Manager - handles container networking with QUIC optimization
+///
+/// Clones share state, so one manager can serve concurrent commands. Creating
+/// and removing the same network is serialized by a per-network lock.
+#[derive(Clone)]
 pub struct NetworkManager {
     interfaces: Arc<RwLock<HashMap<String, NetworkInterface>>>,
     metrics: Arc<RwLock<HashMap<String, NetworkMetrics>>>,
+    /// Networks created through this manager, by name
+    networks: Arc<RwLock<HashMap<String, BoltNetworkInfo>>>,
+    network_locks: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>,
     config: NetworkConfig,
     quic_server: Option<Arc<quic::QUICServer>>,
     ebpf_manager: Option<Arc<ebpf::EBPFManager>>,
@@ -121,6 +141,8 @@ impl NetworkManager {
         Ok(Self {
             interfaces: Arc::new(RwLock::new(HashMap::new())),
             metrics: Arc::new(RwLock::new(HashMap::new())),
+            networks: Arc::new(RwLock::new(HashMap::new())),
+            network_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
             config,
             quic_server,
             ebpf_manager,
@@ -213,6 +235,7 @@ impl NetworkManager {
             mac_address,
             mtu,
             namespace,
+            network: network_name.to_string(),
         })
     }
 
@@ -384,7 +407,7 @@ impl NetworkManager {
 
     /// Create Bolt network with enhanced features
     pub async fn create_bolt_network(
-        &mut self,
+        &self,
         name: &str,
         driver: &str,
         subnet: Option<&str>,
@@ -395,33 +418,107 @@ impl NetworkManager {
         if name.is_empty() {
             return Err(anyhow::anyhow!("Network name cannot be empty"));
         }
+        let interface = network_interface_name(name, driver)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported network driver: {}", driver))?;
+
+        let lock = self.network_lock(name);
+        let _guard = lock.lock().await;
+
+        if self.networks.read().await.contains_key(name) || interface_exists(&interface) {
+            return Err(anyhow::anyhow!("Network '{}' already exists", name));
+        }
 
         // Set default subnet if not provided
         let subnet = subnet.unwrap_or("172.20.0.0/16");
+        let gateway = self.calculate_gateway_ip(subnet)?;
 
         // Create network based on driver type
-        match driver {
-            "bolt" => {
-                self.create_bolt_bridge_network(name, subnet).await?;
-            }
-            "bridge" => {
-                self.create_traditional_bridge_network(name, subnet).await?;
-            }
-            "overlay" => {
-                self.create_overlay_network(name, subnet).await?;
-            }
-            "macvlan" => {
-                self.create_macvlan_network(name, subnet).await?;
-            }
-            _ => {
-                return Err(anyhow::anyhow!("Unsupported network driver: {}", driver));
+        let created = match driver {
+            "bolt" => self.create_bolt_bridge_network(name, subnet).await,
+            "bridge" => self.create_traditional_bridge_network(name, subnet).await,
+            "overlay" => self.create_overlay_network(name, subnet).await,
+            _ => self.create_macvlan_network(name, subnet).await,
+        };
+        if let Err(e) = created {
+            // Don't leave a half-configured interface behind
+            if interface_exists(&interface) {
+                delete_interface(&interface);
             }
+            return Err(e);
         }
 
+        let info = BoltNetworkInfo {
+            id: network_id(&interface),
+            name: name.to_string(),
+            driver: driver.to_string(),
+            scope: "local".to_string(),
+            subnet: subnet.to_string(),
+            gateway: gateway
+                .split('/')
+                .next()
+                .unwrap_or(&gateway)
+                .to_string(),
+        };
+        self.networks.write().await.insert(name.to_string(), info);
+
         info!("✅ Network '{}' created successfully", name);
         Ok(())
     }
 
+    /// Remove a network created with [`Self::create_bolt_network`]
+    pub async fn remove_bolt_network(&self, name: &str) -> Result<()> {
+        info!("🗑️  Removing Bolt network: {}", name);
+
+        let lock = self.network_lock(name);
+        let _guard = lock.lock().await;
+
+        let known = self.networks.read().await.get(name).cloned();
+        let interface = match known {
+            Some(ref network) => network_interface_name(name, &network.driver),
+            None => ["bolt", "overlay", "macvlan"]
+                .iter()
+                .filter_map(|driver| network_interface_name(name, driver))
+                .find(|interface| interface_exists(interface)),
+        }
+        .ok_or_else(|| anyhow::anyhow!("Network '{}' not found", name))?;
+
+        let interfaces = self.interfaces.read().await;
+        let attached: Vec<&String> = interfaces
+            .iter()
+            .filter(|(_, iface)| iface.network == name)
+            .map(|(container, _)| container)
+            .collect();
+        if !attached.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Network '{}' is in use by containers: {:?}",
+                name,
+                attached
+            ));
+        }
+        drop(interfaces);
+
+        if interface_exists(&interface) && !delete_interface(&interface) {
+            return Err(anyhow::anyhow!(
+                "Failed to delete interface {} of network '{}'",
+                interface,
+                name
+            ));
+        }
+        self.networks.write().await.remove(name);
+
+        info!("✅ Network '{}' removed", name);
+        Ok(())
+    }
+
+    fn network_lock(&self, name: &str) -> Arc<Mutex<()>> {
+        self.network_locks
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
     /// Create Bolt bridge network with QUIC support
     async fn create_bolt_bridge_network(&self, name: &str, subnet: &str) -> Result<()> {
         info!("🌉 Creating Bolt bridge network with QUIC support");
@@ -724,12 +821,7 @@ impl NetworkManager {
                                         bridge_name.strip_prefix("br-").unwrap_or(bridge_name);
 
                                     networks.push(BoltNetworkInfo {
-                                        id: format!(
-                                            "{:x}",
-                                            bridge_name.as_bytes().iter().fold(0u64, |acc, &b| acc
-                                                .wrapping_mul(31)
-                                                .wrapping_add(b as u64))
-                                        ),
+                                        id: network_id(bridge_name),
                                         name: network_name.to_string(),
                                         driver: "bolt".to_string(),
                                         scope: "local".to_string(),
@@ -745,10 +837,48 @@ impl NetworkManager {
             Err(_) => {}
         }
 
+        // Networks created here carry their real settings
+        let known = self.networks.read().await;
+        networks.retain(|n| !known.contains_key(&n.name));
+        networks.extend(known.values().cloned());
+        networks.sort_by(|a, b| a.name.cmp(&b.name));
+
         Ok(networks)
     }
 }
 
+/// Host interface backing a network
+fn network_interface_name(name: &str, driver: &str) -> Option<String> {
+    let prefix = match driver {
+        "bolt" | "bridge" => "br",
+        "overlay" => "vx",
+        "macvlan" => "mv",
+        _ => return None,
+    };
+    Some(format!("{}-{}", prefix, name))
+}
+
+fn interface_exists(interface: &str) -> bool {
+    std::path::Path::new("/sys/class/net").join(interface).exists()
+}
+
+fn delete_interface(interface: &str) -> bool {
+    std::process::Command::new("ip")
+        .args(["link", "del", interface])
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+fn network_id(interface: &str) -> String {
+    format!(
+        "{:x}",
+        interface
+            .as_bytes()
+            .iter()
+            .fold(0u64, |acc, &b| acc.wrapping_mul(31).wrapping_add(b as u64))
+    )
+}
+
 /// Bolt network information
 #[derive(Debug, Clone)]
 pub struct BoltNetworkInfo {
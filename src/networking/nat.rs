@@ -0,0 +1,1441 @@
+// NAT traversal for published ports
+//
+// Homelab game servers sit behind consumer routers. A detached service with
+// `port_forward = true` gets a `bolt nat-watch` process per container that
+// makes its published ports reachable from the internet:
+//
+// 1. It asks the router to forward each published port to this host, over
+//    NAT-PMP (RFC 6886) or UPnP IGD as `[nat] protocol` says, for `[nat]
+//    lease`. Mappings are renewed halfway through the lease the router
+//    granted, and removed when the container stops or `surge down` takes it
+//    away.
+// 2. A STUN binding request (RFC 5389) to the `[nat] stun` servers tells the
+//    address the internet sees. When the router's external address is a
+//    shared (100.64.0.0/10) or private one, or differs from what STUN saw,
+//    the router sits behind carrier-grade NAT and its mappings can't be
+//    reached from outside, so they are removed again.
+// 3. Behind CGNAT, or when the router refuses, the ports are published
+//    through the `[nat] relay`, a node with a public address running `bolt
+//    network relay` (see relay.rs).
+//
+// `bolt network nat` shows what the router and STUN report, and how each
+// container's ports are reachable.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::{BoltConfig, NatSettings};
+use crate::runtime::watcher::{self, Watcher};
+
+/// Shortest `[nat] lease`; routers may grant less
+pub const MIN_LEASE: Duration = Duration::from_secs(120);
+/// How often the watcher checks the container and the relay connection
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long the watcher waits before trying again after a failure
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+const NATPMP_PORT: u16 = 5351;
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const IGD_DEVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:2",
+];
+const WAN_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:",
+    "urn:schemas-upnp-org:service:WANPPPConnection:",
+];
+const STUN_MAGIC: u32 = 0x2112_A442;
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_RESPONSE: u16 = 0x0101;
+const STUN_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// How port mappings are requested from the router
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingProtocol {
+    /// NAT-PMP, then UPnP
+    #[default]
+    Auto,
+    Natpmp,
+    Upnp,
+    /// Never ask the router; only the relay publishes ports
+    Off,
+}
+
+/// What a mapping goes through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingVia {
+    Natpmp,
+    Upnp,
+    Relay,
+}
+
+impl MappingVia {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MappingVia::Natpmp => "nat-pmp",
+            MappingVia::Upnp => "upnp",
+            MappingVia::Relay => "relay",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        }
+    }
+
+    fn natpmp_opcode(&self) -> u8 {
+        match self {
+            Transport::Udp => 1,
+            Transport::Tcp => 2,
+        }
+    }
+}
+
+/// A host port a container publishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PublishedPort {
+    pub port: u16,
+    pub transport: Transport,
+}
+
+impl std::fmt::Display for PublishedPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.port, self.transport.as_str())
+    }
+}
+
+/// Host ports published to other machines by `ports` mappings such as
+/// "25565:25565", "27015-27020:27015-27020/udp" or "0.0.0.0:8080:80".
+/// Mappings bound to loopback, and container-only ports, stay local.
+pub fn published_ports(mappings: &[String]) -> Vec<PublishedPort> {
+    let mut ports = Vec::new();
+    for mapping in mappings {
+        let (mapping, transport) = match mapping.rsplit_once('/') {
+            Some((mapping, "udp")) => (mapping, Transport::Udp),
+            Some((mapping, _)) => (mapping, Transport::Tcp),
+            None => (mapping.as_str(), Transport::Tcp),
+        };
+        let mut parts = mapping.rsplitn(3, ':');
+        let _container = parts.next();
+        let Some(host) = parts.next() else {
+            continue;
+        };
+        if let Some(ip) = parts.next() {
+            let ip = ip.trim_start_matches('[').trim_end_matches(']');
+            if ip.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()) {
+                continue;
+            }
+        }
+        let Ok(range) = PortRange::parse(host) else {
+            continue;
+        };
+        for port in range.start..=range.end {
+            let port = PublishedPort { port, transport };
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+    }
+    ports
+}
+
+/// Inclusive range of ports, e.g. "20000-29999" or "25565"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    pub fn parse(range: &str) -> Result<Self> {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let parse = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|port| *port > 0)
+                .ok_or_else(|| anyhow!("'{}' is not a port", port.trim()))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(anyhow!("{} starts after it ends", range));
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+/// What sits between this host and the internet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NatKind {
+    /// The host has a public address
+    None,
+    /// A router with a public address
+    Nat,
+    /// A router that is itself behind the carrier's NAT
+    Cgnat,
+    #[default]
+    Unknown,
+}
+
+impl NatKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NatKind::None => "none (public address)",
+            NatKind::Nat => "router with a public address",
+            NatKind::Cgnat => "carrier-grade NAT",
+            NatKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Tell the kind of NAT from the host's address, the router's external
+/// address and the address STUN saw
+pub fn classify(local: Option<IpAddr>, router: Option<IpAddr>, public: Option<IpAddr>) -> NatKind {
+    if local.is_some() && local == public {
+        return NatKind::None;
+    }
+    match router {
+        Some(router) if !is_global(router) => NatKind::Cgnat,
+        Some(router) if public.is_some_and(|public| public != router) => NatKind::Cgnat,
+        Some(_) => NatKind::Nat,
+        None => NatKind::Unknown,
+    }
+}
+
+/// Not private, shared (100.64.0.0/10), loopback or link-local
+fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+            !(ip.is_private()
+                || shared
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified())
+        }
+        IpAddr::V6(ip) => {
+            let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+            let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+            !(unique_local || link_local || ip.is_loopback() || ip.is_unspecified())
+        }
+    }
+}
+
+/// What `bolt network nat` found out about the network
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NatProbe {
+    pub gateway: Option<Ipv4Addr>,
+    pub local_address: Option<Ipv4Addr>,
+    /// How the router took mapping requests
+    pub protocol: Option<MappingVia>,
+    /// External address the router reported
+    pub router_address: Option<IpAddr>,
+    /// Address the STUN servers saw
+    pub public_address: Option<IpAddr>,
+    pub nat: NatKind,
+    pub errors: Vec<String>,
+}
+
+/// Ask the router and the STUN servers what they see
+pub async fn probe(settings: &NatSettings) -> NatProbe {
+    let mut probe = NatProbe {
+        gateway: settings.gateway.or_else(default_gateway),
+        ..Default::default()
+    };
+    probe.local_address = probe
+        .gateway
+        .and_then(|gateway| local_address(gateway).ok());
+    match Router::find(settings).await {
+        Ok(router) => {
+            probe.protocol = Some(router.via());
+            match router.external_address().await {
+                Ok(address) => probe.router_address = Some(address),
+                Err(e) => probe.errors.push(format!("{:#}", e)),
+            }
+        }
+        Err(e) => probe.errors.push(format!("{:#}", e)),
+    }
+    match public_address(settings).await {
+        Ok(address) => probe.public_address = Some(address),
+        Err(e) => probe.errors.push(format!("{:#}", e)),
+    }
+    probe.nat = classify(
+        probe.local_address.map(IpAddr::V4),
+        probe.router_address,
+        probe.public_address,
+    );
+    probe
+}
+
+/// What `bolt network nat` shows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatReport {
+    pub probe: NatProbe,
+    pub containers: Vec<NatState>,
+}
+
+pub async fn report(config: &BoltConfig) -> NatReport {
+    NatReport {
+        probe: probe(&config.nat).await,
+        containers: list(&config.data_dir),
+    }
+}
+
+/// The default route's gateway, from /proc/net/route
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    parse_route_table(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+fn parse_route_table(table: &str) -> Option<Ipv4Addr> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        // Printed in host byte order
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        (gateway != 0).then(|| Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+/// This host's address on the gateway's network
+pub fn local_address(gateway: Ipv4Addr) -> Result<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((gateway, 9))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(ip) => Err(anyhow!(
+            "Unexpected IPv6 address {} towards {}",
+            ip,
+            gateway
+        )),
+    }
+}
+
+/// The router a watcher maps ports on
+struct Router {
+    gateway: Ipv4Addr,
+    local: Ipv4Addr,
+    /// Where the gateway takes NAT-PMP requests
+    natpmp_port: u16,
+    kind: RouterKind,
+}
+
+enum RouterKind {
+    NatPmp,
+    Upnp(Igd),
+}
+
+impl Router {
+    async fn find(settings: &NatSettings) -> Result<Router> {
+        let gateway = settings
+            .gateway
+            .or_else(default_gateway)
+            .ok_or_else(|| anyhow!("No default gateway to ask for port mappings"))?;
+        let local = local_address(gateway)?;
+        let mut errors = Vec::new();
+        if matches!(
+            settings.protocol,
+            MappingProtocol::Auto | MappingProtocol::Natpmp
+        ) {
+            match natpmp_external(gateway, NATPMP_PORT).await {
+                Ok(_) => {
+                    return Ok(Router {
+                        gateway,
+                        local,
+                        natpmp_port: NATPMP_PORT,
+                        kind: RouterKind::NatPmp,
+                    });
+                }
+                Err(e) => errors.push(format!("NAT-PMP: {:#}", e)),
+            }
+        }
+        if matches!(
+            settings.protocol,
+            MappingProtocol::Auto | MappingProtocol::Upnp
+        ) {
+            match Igd::discover(gateway).await {
+                Ok(igd) => {
+                    return Ok(Router {
+                        gateway,
+                        local,
+                        natpmp_port: NATPMP_PORT,
+                        kind: RouterKind::Upnp(igd),
+                    });
+                }
+                Err(e) => errors.push(format!("UPnP: {:#}", e)),
+            }
+        }
+        if errors.is_empty() {
+            return Err(anyhow!("Port mapping is off ([nat] protocol)"));
+        }
+        Err(anyhow!(
+            "Router {} takes no port mappings ({})",
+            gateway,
+            errors.join("; ")
+        ))
+    }
+
+    fn via(&self) -> MappingVia {
+        match self.kind {
+            RouterKind::NatPmp => MappingVia::Natpmp,
+            RouterKind::Upnp(_) => MappingVia::Upnp,
+        }
+    }
+
+    async fn external_address(&self) -> Result<IpAddr> {
+        match self.kind {
+            RouterKind::NatPmp => Ok(IpAddr::V4(
+                natpmp_external(self.gateway, self.natpmp_port).await?,
+            )),
+            RouterKind::Upnp(ref igd) => {
+                let response = igd.soap("GetExternalIPAddress", &[]).await?;
+                let address = tag(&response, "NewExternalIPAddress")
+                    .ok_or_else(|| anyhow!("The router did not say its external address"))?;
+                address
+                    .parse()
+                    .map_err(|_| anyhow!("The router's external address is '{}'", address))
+            }
+        }
+    }
+
+    /// Forward `port` to this host; the external port and the lease granted
+    /// in seconds, 0 when the mapping is permanent
+    async fn map(&self, port: PublishedPort, lease: u32, description: &str) -> Result<(u16, u32)> {
+        match self.kind {
+            RouterKind::NatPmp => {
+                let request = natpmp_map_request(port, port.port, lease);
+                let response = natpmp_request(self.gateway, self.natpmp_port, &request).await?;
+                parse_natpmp_map(&response)
+            }
+            RouterKind::Upnp(ref igd) => {
+                let granted = igd.add(self.local, port, lease, description).await?;
+                Ok((port.port, granted))
+            }
+        }
+    }
+
+    async fn unmap(&self, port: PublishedPort, external: u16) -> Result<()> {
+        match self.kind {
+            RouterKind::NatPmp => {
+                // Deleting asks for no external port and no lifetime
+                let request = natpmp_map_request(port, 0, 0);
+                let response = natpmp_request(self.gateway, self.natpmp_port, &request).await?;
+                parse_natpmp_map(&response).map(|_| ())
+            }
+            RouterKind::Upnp(ref igd) => {
+                igd.soap(
+                    "DeletePortMapping",
+                    &[
+                        ("NewRemoteHost", String::new()),
+                        ("NewExternalPort", external.to_string()),
+                        ("NewProtocol", port.transport.as_str().to_uppercase()),
+                    ],
+                )
+                .await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Send a NAT-PMP request, retrying with the RFC's doubling timeout
+async fn natpmp_request(gateway: Ipv4Addr, port: u16, request: &[u8]) -> Result<Vec<u8>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect((gateway, port)).await?;
+    let mut wait = Duration::from_millis(250);
+    let mut buf = [0u8; 16];
+    for _ in 0..4 {
+        socket.send(request).await?;
+        if let Ok(received) = tokio::time::timeout(wait, socket.recv(&mut buf)).await {
+            let len = received.with_context(|| format!("{} refused NAT-PMP", gateway))?;
+            if len >= 4 && buf[1] == request[1] + 128 {
+                let code = u16::from_be_bytes([buf[2], buf[3]]);
+                if code != 0 {
+                    return Err(anyhow!("{} answered: {}", gateway, natpmp_error(code)));
+                }
+                return Ok(buf[..len].to_vec());
+            }
+        }
+        wait *= 2;
+    }
+    Err(anyhow!("{} did not answer", gateway))
+}
+
+fn natpmp_error(code: u16) -> &'static str {
+    match code {
+        1 => "unsupported version",
+        2 => "not authorized",
+        3 => "network failure",
+        4 => "out of resources",
+        5 => "unsupported opcode",
+        _ => "unknown error",
+    }
+}
+
+async fn natpmp_external(gateway: Ipv4Addr, port: u16) -> Result<Ipv4Addr> {
+    let response = natpmp_request(gateway, port, &[0, 0]).await?;
+    let octets: [u8; 4] = response
+        .get(8..12)
+        .and_then(|octets| octets.try_into().ok())
+        .ok_or_else(|| anyhow!("Short NAT-PMP answer from {}", gateway))?;
+    Ok(Ipv4Addr::from(octets))
+}
+
+fn natpmp_map_request(port: PublishedPort, external: u16, lifetime: u32) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[1] = port.transport.natpmp_opcode();
+    request[4..6].copy_from_slice(&port.port.to_be_bytes());
+    request[6..8].copy_from_slice(&external.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// External port and lifetime of a mapping response
+fn parse_natpmp_map(response: &[u8]) -> Result<(u16, u32)> {
+    if response.len() < 16 {
+        return Err(anyhow!("Short NAT-PMP mapping answer"));
+    }
+    let external = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+    Ok((external, lifetime))
+}
+
+/// A UPnP Internet Gateway Device's WAN connection service
+struct Igd {
+    service: String,
+    control_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{action} failed: UPnP error {code} ({description})")]
+struct UpnpFault {
+    action: String,
+    code: u16,
+    description: String,
+}
+
+impl Igd {
+    /// Search for the gateway's IGD over SSDP and read its description
+    async fn discover(gateway: Ipv4Addr) -> Result<Igd> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        for device in IGD_DEVICES {
+            let search = format!(
+                "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+                SSDP_ADDR, device
+            );
+            socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(3);
+        let mut buf = vec![0u8; 2048];
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+        {
+            let (len, from) = received?;
+            if from.ip() != IpAddr::V4(gateway) {
+                continue;
+            }
+            let response = String::from_utf8_lossy(&buf[..len]);
+            let Some(location) = header(&response, "location") else {
+                continue;
+            };
+            match Igd::describe(client.clone(), location).await {
+                Ok(igd) => return Ok(igd),
+                Err(e) => debug!("Skipping UPnP device at {}: {:#}", location, e),
+            }
+        }
+        Err(anyhow!("No UPnP gateway answered at {}", gateway))
+    }
+
+    async fn describe(client: reqwest::Client, location: &str) -> Result<Igd> {
+        let description = client
+            .get(location)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let (service, control) = control_url(&description)
+            .ok_or_else(|| anyhow!("No WAN connection service described"))?;
+        let base = tag(&description, "URLBase").unwrap_or(location);
+        let control_url = reqwest::Url::parse(base)?.join(&control)?.to_string();
+        Ok(Igd {
+            service,
+            control_url,
+            client,
+        })
+    }
+
+    async fn soap(&self, action: &str, args: &[(&str, String)]) -> Result<String> {
+        let args: String = args
+            .iter()
+            .map(|(name, value)| format!("<{0}>{1}</{0}>", name, value))
+            .collect();
+        let envelope = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body></s:Envelope>",
+            action = action,
+            service = self.service,
+            args = args
+        );
+        let response = self
+            .client
+            .post(&self.control_url)
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{}#{}\"", self.service, action))
+            .body(envelope)
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(UpnpFault {
+                action: action.to_string(),
+                code: tag(&body, "errorCode")
+                    .and_then(|code| code.parse().ok())
+                    .unwrap_or_default(),
+                description: tag(&body, "errorDescription")
+                    .unwrap_or(status.as_str())
+                    .to_string(),
+            }
+            .into());
+        }
+        Ok(body)
+    }
+
+    /// Add a mapping to `local`; the lease granted, 0 when the router only
+    /// takes permanent mappings
+    async fn add(
+        &self,
+        local: Ipv4Addr,
+        port: PublishedPort,
+        lease: u32,
+        description: &str,
+    ) -> Result<u32> {
+        let args = |lease: u32| {
+            [
+                ("NewRemoteHost", String::new()),
+                ("NewExternalPort", port.port.to_string()),
+                ("NewProtocol", port.transport.as_str().to_uppercase()),
+                ("NewInternalPort", port.port.to_string()),
+                ("NewInternalClient", local.to_string()),
+                ("NewEnabled", "1".to_string()),
+                ("NewPortMappingDescription", description.to_string()),
+                ("NewLeaseDuration", lease.to_string()),
+            ]
+        };
+        match self.soap("AddPortMapping", &args(lease)).await {
+            Ok(_) => Ok(lease),
+            // OnlyPermanentLeasesSupported
+            Err(e) if e.downcast_ref::<UpnpFault>().is_some_and(|f| f.code == 725) => {
+                self.soap("AddPortMapping", &args(0)).await?;
+                Ok(0)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// Text of the first `<name>` element
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{}>", name))? + start;
+    Some(xml[start..end].trim())
+}
+
+/// Type and control URL of the first WAN connection service
+fn control_url(description: &str) -> Option<(String, String)> {
+    description.split("<service>").skip(1).find_map(|block| {
+        let service = tag(block, "serviceType")?;
+        if !WAN_SERVICES.iter().any(|wan| service.starts_with(wan)) {
+            return None;
+        }
+        Some((service.to_string(), tag(block, "controlURL")?.to_string()))
+    })
+}
+
+/// Address the first answering `[nat] stun` server saw
+async fn public_address(settings: &NatSettings) -> Result<IpAddr> {
+    let mut errors = Vec::new();
+    for server in &settings.stun {
+        match stun_lookup(server).await {
+            Ok(address) => return Ok(address.ip()),
+            Err(e) => errors.push(format!("{}: {:#}", server, e)),
+        }
+    }
+    if errors.is_empty() {
+        return Err(anyhow!(
+            "No [nat] stun servers to ask for the public address"
+        ));
+    }
+    Err(anyhow!("No STUN server answered ({})", errors.join("; ")))
+}
+
+/// Ask a STUN server which address and port our requests come from
+pub async fn stun_lookup(server: &str) -> Result<SocketAddr> {
+    let target = tokio::net::lookup_host(server)
+        .await
+        .with_context(|| format!("Failed to resolve {}", server))?
+        .find(|address| address.is_ipv4())
+        .ok_or_else(|| anyhow!("No IPv4 address for {}", server))?;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let transaction: [u8; 12] = rand::random();
+    let request = binding_request(&transaction);
+    let mut buf = [0u8; 512];
+    let mut wait = Duration::from_millis(500);
+    for _ in 0..3 {
+        socket.send_to(&request, target).await?;
+        if let Ok(received) = tokio::time::timeout(wait, socket.recv_from(&mut buf)).await {
+            let (len, from) = received?;
+            if from == target
+                && let Some(mapped) = parse_binding_response(&buf[..len], &transaction)
+            {
+                return Ok(mapped);
+            }
+        }
+        wait *= 2;
+    }
+    Err(anyhow!("no answer"))
+}
+
+fn stun_header(kind: u16, length: usize, transaction: &[u8; 12]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(20 + length);
+    message.extend_from_slice(&kind.to_be_bytes());
+    message.extend_from_slice(&(length as u16).to_be_bytes());
+    message.extend_from_slice(&STUN_MAGIC.to_be_bytes());
+    message.extend_from_slice(transaction);
+    message
+}
+
+pub fn binding_request(transaction: &[u8; 12]) -> Vec<u8> {
+    stun_header(STUN_BINDING_REQUEST, 0, transaction)
+}
+
+/// Transaction id of a binding request
+pub fn parse_binding_request(message: &[u8]) -> Option<[u8; 12]> {
+    if message.len() < 20
+        || message[0..2] != STUN_BINDING_REQUEST.to_be_bytes()
+        || message[4..8] != STUN_MAGIC.to_be_bytes()
+    {
+        return None;
+    }
+    message[8..20].try_into().ok()
+}
+
+/// Answer to a binding request from `mapped`
+pub fn binding_response(transaction: &[u8; 12], mapped: SocketAddr) -> Vec<u8> {
+    let key = xor_key(transaction);
+    let mut value = vec![0, if mapped.is_ipv4() { 1 } else { 2 }];
+    value.extend_from_slice(&(mapped.port() ^ u16::from_be_bytes([key[0], key[1]])).to_be_bytes());
+    let octets = match mapped.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    value.extend(octets.iter().zip(key).map(|(octet, key)| octet ^ key));
+
+    let mut message = stun_header(STUN_BINDING_RESPONSE, 4 + value.len(), transaction);
+    message.extend_from_slice(&STUN_XOR_MAPPED_ADDRESS.to_be_bytes());
+    message.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    message.extend_from_slice(&value);
+    message
+}
+
+/// The mapped address of a binding response to `transaction`
+pub fn parse_binding_response(message: &[u8], transaction: &[u8; 12]) -> Option<SocketAddr> {
+    if message.len() < 20
+        || message[0..2] != STUN_BINDING_RESPONSE.to_be_bytes()
+        || message[4..8] != STUN_MAGIC.to_be_bytes()
+        || message[8..20] != transaction[..]
+    {
+        return None;
+    }
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    let mut attributes = message.get(20..20 + length)?;
+    let mut mapped = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + len)?;
+        match kind {
+            STUN_XOR_MAPPED_ADDRESS => return decode_address(value, &xor_key(transaction)),
+            STUN_MAPPED_ADDRESS => mapped = decode_address(value, &[0; 16]),
+            _ => {}
+        }
+        // Attributes are padded to 4 bytes
+        attributes = attributes
+            .get(4 + len.div_ceil(4) * 4..)
+            .unwrap_or_default();
+    }
+    mapped
+}
+
+/// The magic cookie followed by the transaction id
+fn xor_key(transaction: &[u8; 12]) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..4].copy_from_slice(&STUN_MAGIC.to_be_bytes());
+    key[4..].copy_from_slice(transaction);
+    key
+}
+
+fn decode_address(value: &[u8], key: &[u8; 16]) -> Option<SocketAddr> {
+    let port = u16::from_be_bytes([value.get(2)? ^ key[0], value.get(3)? ^ key[1]]);
+    let ip = match value.get(1)? {
+        1 => {
+            let octets: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+            IpAddr::V4(Ipv4Addr::from(std::array::from_fn::<u8, 4, _>(|i| {
+                octets[i] ^ key[i]
+            })))
+        }
+        2 => {
+            let octets: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+            IpAddr::V6(Ipv6Addr::from(std::array::from_fn::<u8, 16, _>(|i| {
+                octets[i] ^ key[i]
+            })))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// How one published port is reachable from the internet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub port: PublishedPort,
+    /// Where friends connect, e.g. "203.0.113.7:25565"
+    pub public: SocketAddr,
+    pub via: MappingVia,
+    /// When the router drops the mapping unless it is renewed
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A `port_forward` container and how its ports are reachable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatState {
+    pub container: String,
+    pub ports: Vec<PublishedPort>,
+    pub watcher_pid: Option<u32>,
+    pub nat: NatKind,
+    pub mappings: Vec<PortMapping>,
+    /// Why the ports aren't reachable
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NatState {
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+const WATCHER: Watcher = Watcher::new("nat-watch");
+
+fn nat_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("nat")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    nat_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    nat_dir(data_dir).join(format!("{}.log", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<NatState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every `port_forward` container, by name
+pub fn list(data_dir: &Path) -> Vec<NatState> {
+    let mut states: Vec<NatState> = watcher::list(&nat_dir(data_dir));
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &NatState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Start making a running container's published ports reachable
+pub fn start(data_dir: &Path, container: &str, ports: Vec<PublishedPort>) -> Result<()> {
+    forget(data_dir, container);
+    let mut state = NatState {
+        container: container.to_string(),
+        ports,
+        watcher_pid: None,
+        nat: NatKind::Unknown,
+        mappings: Vec::new(),
+        error: None,
+        updated_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["nat-watch", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    let ports: Vec<String> = state.ports.iter().map(|p| p.to_string()).collect();
+    info!("🌐 Forwarding {} of {}", ports.join(", "), container);
+    Ok(())
+}
+
+/// Stop forwarding a container's ports and drop its record; the watcher
+/// removes its mappings on the way out
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Keep the container's ports mapped until it stops or the watcher is
+/// told to, then remove the mappings. Run by `bolt nat-watch`.
+pub async fn watch(config: &BoltConfig, container: &str) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut session = Session::default();
+    let result = tokio::select! {
+        result = keep_reachable(config, container, &mut session) => result,
+        _ = terminate.recv() => Ok(()),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    session.close(container).await;
+    result
+}
+
+async fn keep_reachable(config: &BoltConfig, container: &str, session: &mut Session) -> Result<()> {
+    let data_dir = &config.data_dir;
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    loop {
+        let Some(mut state) = state(data_dir, container) else {
+            debug!("{} no longer forwards ports, stopping", container);
+            return Ok(());
+        };
+        if !crate::runtime::healthcheck::status(&runtime, container)
+            .await
+            .is_some_and(|s| s.running())
+        {
+            info!("{} stopped, removing its port mappings", container);
+            return Ok(());
+        }
+
+        state.error = match session.refresh(config, container, &state.ports).await {
+            Ok(()) => None,
+            Err(e) => {
+                warn!("Ports of {} are not reachable: {:#}", container, e);
+                Some(format!("{:#}", e))
+            }
+        };
+        state.nat = session.nat;
+        state.mappings = session.mappings.clone();
+        state.updated_at = Utc::now();
+        // The record may have been removed by `surge down` meanwhile
+        watcher::update(&state_path(data_dir, container), &state)?;
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+/// The mappings a watcher holds for one container
+#[derive(Default)]
+struct Session {
+    router: Option<Router>,
+    nat: NatKind,
+    mappings: Vec<PortMapping>,
+    renew_at: Option<Instant>,
+    #[cfg(feature = "quic-networking")]
+    relay: Option<super::relay::RelayClient>,
+}
+
+impl Session {
+    /// Map the ports if they aren't, renew mappings that are due, and fall
+    /// back to the relay when the router can't help
+    async fn refresh(
+        &mut self,
+        config: &BoltConfig,
+        container: &str,
+        ports: &[PublishedPort],
+    ) -> Result<()> {
+        #[cfg(feature = "quic-networking")]
+        if let Some(ref relay) = self.relay {
+            if relay.is_alive() {
+                return Ok(());
+            }
+            warn!("Lost the relay for {}, reconnecting", container);
+            self.relay = None;
+            self.mappings.clear();
+            self.renew_at = None;
+        }
+        if self.renew_at.is_some_and(|at| Instant::now() < at) {
+            return Ok(());
+        }
+
+        let settings = &config.nat;
+        let lease = parse_duration(&settings.lease)?;
+        let mapped = match settings.protocol {
+            MappingProtocol::Off => Err(anyhow!("Port mapping is off ([nat] protocol)")),
+            _ => self.map(settings, container, ports, lease).await,
+        };
+        let error = match mapped {
+            Ok(renew_in) => {
+                self.renew_at = Some(Instant::now() + renew_in);
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        self.unmap(container).await;
+        self.router = None;
+        self.renew_at = Some(Instant::now() + RETRY_INTERVAL);
+        if settings.relay.is_none() {
+            return Err(error.context("no [nat] relay to publish through instead"));
+        }
+        info!("{:#}; publishing {} through the relay", error, container);
+        self.relay(config, container, ports).await
+    }
+
+    /// Map every port on the router; how long until they need renewing
+    async fn map(
+        &mut self,
+        settings: &NatSettings,
+        container: &str,
+        ports: &[PublishedPort],
+        lease: Duration,
+    ) -> Result<Duration> {
+        if self.router.is_none() {
+            self.router = Some(Router::find(settings).await?);
+        }
+        let Some(ref router) = self.router else {
+            unreachable!("router found above");
+        };
+        let external = router.external_address().await?;
+        let public = public_address(settings).await;
+        if let Err(ref e) = public {
+            debug!("Cannot tell CGNAT apart: {:#}", e);
+        }
+        self.nat = classify(Some(IpAddr::V4(router.local)), Some(external), public.ok());
+        if self.nat == NatKind::Cgnat {
+            return Err(anyhow!(
+                "The router's external address {} is behind carrier-grade NAT",
+                external
+            ));
+        }
+
+        let fresh = self.mappings.is_empty();
+        let description = format!("bolt {}", container);
+        let mut renew_in = lease / 2;
+        for port in ports {
+            let (external_port, granted) = router
+                .map(*port, lease.as_secs() as u32, &description)
+                .await
+                .with_context(|| format!("Failed to map {}", port))?;
+            if granted > 0 {
+                renew_in = renew_in.min(Duration::from_secs(granted as u64 / 2));
+            }
+            self.mappings.retain(|m| m.port != *port);
+            self.mappings.push(PortMapping {
+                port: *port,
+                public: SocketAddr::new(external, external_port),
+                via: router.via(),
+                expires_at: (granted > 0)
+                    .then(|| Utc::now() + chrono::Duration::seconds(granted as i64)),
+            });
+        }
+        if fresh {
+            for mapping in &self.mappings {
+                info!(
+                    "🌐 {} of {} is reachable at {} ({})",
+                    mapping.port,
+                    container,
+                    mapping.public,
+                    mapping.via.as_str()
+                );
+            }
+        }
+        Ok(renew_in.max(Duration::from_secs(30)))
+    }
+
+    /// Remove the mappings made on the router
+    async fn unmap(&mut self, container: &str) {
+        let Some(ref router) = self.router else {
+            self.mappings.clear();
+            return;
+        };
+        for mapping in self.mappings.drain(..) {
+            if mapping.via == MappingVia::Relay {
+                continue;
+            }
+            if let Err(e) = router.unmap(mapping.port, mapping.public.port()).await {
+                warn!(
+                    "Could not remove the mapping of {} for {}: {:#}",
+                    mapping.port, container, e
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "quic-networking")]
+    async fn relay(
+        &mut self,
+        config: &BoltConfig,
+        container: &str,
+        ports: &[PublishedPort],
+    ) -> Result<()> {
+        let relay = super::relay::RelayClient::connect(config, ports).await?;
+        self.mappings = relay.mappings();
+        for mapping in &self.mappings {
+            info!(
+                "🌐 {} of {} is reachable at {} (relay)",
+                mapping.port, container, mapping.public
+            );
+        }
+        self.relay = Some(relay);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "quic-networking"))]
+    async fn relay(
+        &mut self,
+        _config: &BoltConfig,
+        _container: &str,
+        _ports: &[PublishedPort],
+    ) -> Result<()> {
+        Err(anyhow!(
+            "Publishing through a relay requires the quic-networking feature"
+        ))
+    }
+
+    async fn close(&mut self, container: &str) {
+        #[cfg(feature = "quic-networking")]
+        if let Some(relay) = self.relay.take() {
+            relay.close();
+        }
+        self.unmap(container).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn reads_published_ports_and_speaks_stun_and_natpmp() {
+        let mappings = [
+            "25565:25565",
+            "27015-27016:27015-27016/udp",
+            "127.0.0.1:8080:80",
+            "0.0.0.0:9000:9000/tcp",
+            "5432",
+        ]
+        .map(String::from);
+        let ports: Vec<String> = published_ports(&mappings)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(ports, ["25565/tcp", "27015/udp", "27016/udp", "9000/tcp"]);
+
+        let transaction = [7u8; 12];
+        for mapped in ["203.0.113.7:40123", "[2001:db8::1]:3478"] {
+            let mapped: SocketAddr = mapped.parse().unwrap();
+            let request = binding_request(&transaction);
+            assert_eq!(parse_binding_request(&request), Some(transaction));
+            let response = binding_response(&transaction, mapped);
+            assert_eq!(
+                parse_binding_response(&response, &transaction),
+                Some(mapped)
+            );
+            assert_eq!(parse_binding_response(&response, &[8u8; 12]), None);
+        }
+
+        let port = PublishedPort {
+            port: 25565,
+            transport: Transport::Udp,
+        };
+        let request = natpmp_map_request(port, 25565, 3600);
+        assert_eq!(request[..4], [0, 1, 0, 0]);
+        let mut response = [0u8; 16];
+        response[1] = 129;
+        response[10..12].copy_from_slice(&25570u16.to_be_bytes());
+        response[12..16].copy_from_slice(&1800u32.to_be_bytes());
+        assert_eq!(parse_natpmp_map(&response).unwrap(), (25570, 1800));
+
+        let table = "Iface\tDestination\tGateway\tFlags\n\
+                     eth0\t0000A8C0\t00000000\t0001\n\
+                     eth0\t00000000\t0101A8C0\t0003\n";
+        assert_eq!(
+            parse_route_table(table),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+        let local = ip("192.168.1.20");
+        assert_eq!(
+            classify(local, ip("203.0.113.7"), ip("203.0.113.7")),
+            NatKind::Nat
+        );
+        assert_eq!(
+            classify(local, ip("100.72.3.4"), ip("198.51.100.9")),
+            NatKind::Cgnat
+        );
+        assert_eq!(
+            classify(local, ip("203.0.113.7"), ip("198.51.100.9")),
+            NatKind::Cgnat
+        );
+        assert_eq!(
+            classify(ip("203.0.113.7"), None, ip("203.0.113.7")),
+            NatKind::None
+        );
+        assert_eq!(classify(local, None, ip("203.0.113.7")), NatKind::Unknown);
+
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+            <controlURL>/l3f</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+            <controlURL>/ctl/IPConn</controlURL></service></serviceList></device></root>";
+        assert_eq!(
+            control_url(description),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:1".to_string(),
+                "/ctl/IPConn".to_string()
+            ))
+        );
+    }
+
+    /// A NAT-PMP gateway on loopback that grants at most two minutes; the
+    /// port it listens on and the requests it got
+    async fn fake_natpmp() -> (u16, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 16];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                let request = buf[..len].to_vec();
+                let mut response = vec![0, request[1] + 128, 0, 0, 0, 0, 0, 1];
+                if request[1] == 0 {
+                    response.extend([203, 0, 113, 7]);
+                } else {
+                    let internal = u16::from_be_bytes([request[4], request[5]]);
+                    let lifetime = u32::from_be_bytes(request[8..12].try_into().unwrap()).min(120);
+                    let external = if lifetime == 0 { 0 } else { internal + 1000 };
+                    response.extend(internal.to_be_bytes());
+                    response.extend(external.to_be_bytes());
+                    response.extend(lifetime.to_be_bytes());
+                }
+                seen.lock().unwrap().push(request);
+                socket.send_to(&response, from).await.unwrap();
+            }
+        });
+        (port, requests)
+    }
+
+    /// SOAP actions a fake gateway got, with their arguments
+    type Actions = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// A UPnP gateway on loopback that only takes permanent mappings; where
+    /// it describes itself and the actions it got
+    fn fake_igd() -> (String, Actions) {
+        use warp::Filter;
+
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let seen = actions.clone();
+        let description = warp::path!("igd.xml").map(|| {
+            "<root><device><serviceList><service>\
+             <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+             <controlURL>/ctl</controlURL></service></serviceList></device></root>"
+        });
+        let control = warp::path!("ctl")
+            .and(warp::header::<String>("soapaction"))
+            .and(warp::body::bytes())
+            .map(move |action: String, body: bytes::Bytes| {
+                let action = action.trim_matches('"').rsplit('#').next().unwrap();
+                let body = String::from_utf8_lossy(&body).to_string();
+                let leased = tag(&body, "NewLeaseDuration").is_some_and(|lease| lease != "0");
+                seen.lock().unwrap().push((action.to_string(), body));
+                let (status, reply) = match action {
+                    "GetExternalIPAddress" => (
+                        warp::http::StatusCode::OK,
+                        "<NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>",
+                    ),
+                    "AddPortMapping" if leased => (
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        "<errorCode>725</errorCode>\
+                         <errorDescription>OnlyPermanentLeasesSupported</errorDescription>",
+                    ),
+                    _ => (warp::http::StatusCode::OK, ""),
+                };
+                warp::reply::with_status(reply, status)
+            });
+        let (address, server) =
+            warp::serve(description.or(control)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/igd.xml", address), actions)
+    }
+
+    fn config(lease: &str) -> BoltConfig {
+        let mut config = BoltConfig::default();
+        config.nat.lease = lease.to_string();
+        // The router's address is all there is to tell CGNAT by
+        config.nat.stun.clear();
+        config
+    }
+
+    #[tokio::test]
+    async fn renews_natpmp_leases_halfway_and_removes_them_on_close() {
+        let (natpmp_port, requests) = fake_natpmp().await;
+        let mapped = |requests: &Mutex<Vec<Vec<u8>>>| -> Vec<u32> {
+            requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|request| request[1] != 0)
+                .map(|request| u32::from_be_bytes(request[8..12].try_into().unwrap()))
+                .collect()
+        };
+        let config = config("10m");
+        let port = PublishedPort {
+            port: 25565,
+            transport: Transport::Udp,
+        };
+        let mut session = Session {
+            router: Some(Router {
+                gateway: Ipv4Addr::LOCALHOST,
+                local: Ipv4Addr::LOCALHOST,
+                natpmp_port,
+                kind: RouterKind::NatPmp,
+            }),
+            ..Default::default()
+        };
+
+        session.refresh(&config, "mc", &[port]).await.unwrap();
+        assert_eq!(session.nat, NatKind::Nat);
+        assert_eq!(mapped(&requests), [600]);
+        let first = session.mappings.clone();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].public, "203.0.113.7:26565".parse().unwrap());
+        assert_eq!(first[0].via, MappingVia::Natpmp);
+        let expires_in = first[0].expires_at.unwrap() - Utc::now();
+        assert!(expires_in <= chrono::Duration::seconds(120));
+        assert!(expires_in > chrono::Duration::seconds(110));
+        // Halfway through the two minutes granted, not the ten asked for
+        let renew_in = session.renew_at.unwrap() - Instant::now();
+        assert!(renew_in <= Duration::from_secs(60) && renew_in > Duration::from_secs(50));
+
+        session.refresh(&config, "mc", &[port]).await.unwrap();
+        assert_eq!(mapped(&requests), [600]);
+
+        session.renew_at = Some(Instant::now());
+        session.refresh(&config, "mc", &[port]).await.unwrap();
+        assert_eq!(mapped(&requests), [600, 600]);
+        assert_eq!(session.mappings.len(), 1);
+        assert!(session.mappings[0].expires_at >= first[0].expires_at);
+
+        session.close("mc").await;
+        assert!(session.mappings.is_empty());
+        assert_eq!(mapped(&requests), [600, 600, 0]);
+        assert_eq!(
+            requests.lock().unwrap().last().unwrap()[..],
+            natpmp_map_request(port, 0, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_permanently_over_upnp_when_leases_are_refused_and_removes_on_close() {
+        let (location, actions) = fake_igd();
+        let igd = Igd::describe(reqwest::Client::new(), &location)
+            .await
+            .unwrap();
+        let config = config("10m");
+        let port = PublishedPort {
+            port: 27015,
+            transport: Transport::Tcp,
+        };
+        let mut session = Session {
+            router: Some(Router {
+                gateway: Ipv4Addr::LOCALHOST,
+                local: Ipv4Addr::LOCALHOST,
+                natpmp_port: NATPMP_PORT,
+                kind: RouterKind::Upnp(igd),
+            }),
+            ..Default::default()
+        };
+        let calls = |actions: &Mutex<Vec<(String, String)>>| -> Vec<String> {
+            actions
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(action, body)| match tag(body, "NewLeaseDuration") {
+                    Some(lease) => format!("{} {}", action, lease),
+                    None => action.clone(),
+                })
+                .collect()
+        };
+
+        session.refresh(&config, "cs2", &[port]).await.unwrap();
+        assert_eq!(
+            session.mappings,
+            [PortMapping {
+                port,
+                public: "203.0.113.7:27015".parse().unwrap(),
+                via: MappingVia::Upnp,
+                expires_at: None,
+            }]
+        );
+        assert_eq!(
+            calls(&actions),
+            [
+                "GetExternalIPAddress",
+                "AddPortMapping 600",
+                "AddPortMapping 0"
+            ]
+        );
+        // Permanent mappings are still re-added halfway through the lease
+        // asked for, in case the router forgot them
+        let renew_in = session.renew_at.unwrap() - Instant::now();
+        assert!(renew_in <= Duration::from_secs(300) && renew_in > Duration::from_secs(290));
+
+        session.close("cs2").await;
+        assert!(session.mappings.is_empty());
+        let (action, body) = actions.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(action, "DeletePortMapping");
+        assert_eq!(tag(&body, "NewExternalPort"), Some("27015"));
+        assert_eq!(tag(&body, "NewProtocol"), Some("TCP"));
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use bytes::{BufMut, Bytes, BytesMut};
 use futures::stream::{FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -12,6 +12,7 @@ use tokio::sync::{RwLock, Semaphore, mpsc};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::networking::NetworkConfig;
+use crate::runtime::readiness::{ProbeTarget, ReadinessProbe, ReadinessTracker};
 
 /// High-performance QUIC-based socket proxy for container networking
 /// Provides TCP/UDP proxying over QUIC with gaming optimizations
@@ -21,6 +22,11 @@ pub struct QUICSocketProxy {
     server_endpoints: Arc<RwLock<HashMap<String, QUICServerEndpoint>>>,
     client_connections: Arc<RwLock<HashMap<String, QUICClientConnection>>>,
     proxy_rules: Arc<RwLock<HashMap<String, ProxyRule>>>,
+    /// Rules whose readiness probe isn't passing; traffic for them is dropped
+    not_ready: Arc<RwLock<HashSet<String>>>,
+    /// Rules whose target is being drained; only clients that already have
+    /// a session still reach it
+    draining: Arc<RwLock<HashSet<String>>>,
     stats: Arc<RwLock<ProxyStats>>,
     connection_pool: Arc<Semaphore>,
     shutdown_tx: Option<mpsc::Sender<()>>,
@@ -54,6 +60,11 @@ pub struct QUICProxyConfig {
     pub congestion_control: CongestionControl,
     pub enable_gso: bool, // Generic Segmentation Offload
     pub enable_gro: bool, // Generic Receive Offload
+
+    /// `[[acme.certificates]]` name of the certificate the QUIC endpoint
+    /// serves; renewals are picked up without dropping connections
+    #[serde(default)]
+    pub tls_certificate: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +108,9 @@ pub struct ProxyRule {
     pub protocol: ProxyProtocol,
     pub load_balancing: LoadBalancingStrategy,
     pub health_check: Option<HealthCheck>,
+    /// Only route to the target once this probe passes
+    #[serde(default)]
+    pub readiness: Option<ReadinessProbe>,
     pub traffic_shaping: Option<TrafficShaping>,
     pub gaming_optimizations: Option<GamingOptimizations>,
 }
@@ -226,6 +240,8 @@ impl QUICSocketProxy {
             server_endpoints: Arc::new(RwLock::new(HashMap::new())),
             client_connections: Arc::new(RwLock::new(HashMap::new())),
             proxy_rules: Arc::new(RwLock::new(HashMap::new())),
+            not_ready: Arc::new(RwLock::new(HashSet::new())),
+            draining: Arc::new(RwLock::new(HashSet::new())),
             stats: Arc::new(RwLock::new(stats)),
             connection_pool,
             shutdown_tx: None,
@@ -252,6 +268,15 @@ impl QUICSocketProxy {
         // Start health check monitoring
         let health_check_task = self.start_health_check_monitor();
 
+        // Start readiness probes
+        let readiness_task = self.start_readiness_monitor();
+
+        // Follow replicas being drained
+        let drain_task = self.start_drain_monitor();
+
+        // Pick up renewed certificates
+        let certificate_task = self.start_certificate_monitor();
+
         // Start statistics collection
         let stats_task = self.start_stats_collector();
 
@@ -272,6 +297,15 @@ impl QUICSocketProxy {
             result = health_check_task => {
                 error!("Health check task completed unexpectedly: {:?}", result);
             }
+            result = readiness_task => {
+                error!("Readiness monitor completed unexpectedly: {:?}", result);
+            }
+            result = drain_task => {
+                error!("Drain monitor completed unexpectedly: {:?}", result);
+            }
+            result = certificate_task => {
+                error!("Certificate monitor completed unexpectedly: {:?}", result);
+            }
             result = stats_task => {
                 error!("Stats collector task completed unexpectedly: {:?}", result);
             }
@@ -288,6 +322,11 @@ impl QUICSocketProxy {
             rule.name, rule.listen_port, rule.protocol, rule.target_address
         );
 
+        // Rules with a readiness probe start out not routable
+        if rule.readiness.is_some() {
+            self.not_ready.write().await.insert(rule.name.clone());
+        }
+
         let mut rules = self.proxy_rules.write().await;
         rules.insert(rule.name.clone(), rule);
 
@@ -300,6 +339,8 @@ impl QUICSocketProxy {
 
         let mut rules = self.proxy_rules.write().await;
         rules.remove(name);
+        self.not_ready.write().await.remove(name);
+        self.draining.write().await.remove(name);
 
         Ok(())
     }
@@ -379,6 +420,8 @@ impl QUICSocketProxy {
         let stats = Arc::clone(&self.stats);
         let connection_pool = Arc::clone(&self.connection_pool);
         let config = self.config.clone();
+        let not_ready = Arc::clone(&self.not_ready);
+        let draining = Arc::clone(&self.draining);
 
         let task = tokio::spawn(async move {
             loop {
@@ -389,6 +432,17 @@ impl QUICSocketProxy {
                             client_addr, rule.name
                         );
 
+                        if not_ready.read().await.contains(&rule.name)
+                            || draining.read().await.contains(&rule.name)
+                        {
+                            debug!(
+                                "Target of {} not ready, refusing {}",
+                                rule.name, client_addr
+                            );
+                            drop(stream);
+                            continue;
+                        }
+
                         // Acquire connection slot
                         let _permit = connection_pool.acquire().await?;
 
@@ -610,6 +664,8 @@ impl QUICSocketProxy {
 
         let stats = Arc::clone(&self.stats);
         let config = self.config.clone();
+        let not_ready = Arc::clone(&self.not_ready);
+        let draining = Arc::clone(&self.draining);
 
         let client_sessions = Arc::new(RwLock::new(HashMap::<SocketAddr, Arc<UdpSocket>>::new()));
 
@@ -621,6 +677,20 @@ impl QUICSocketProxy {
                     Ok((size, client_addr)) => {
                         debug!("📦 UDP packet from {} ({} bytes)", client_addr, size);
 
+                        if not_ready.read().await.contains(&rule.name) {
+                            debug!("Target of {} not ready, dropping packet", rule.name);
+                            continue;
+                        }
+                        if draining.read().await.contains(&rule.name)
+                            && !client_sessions.read().await.contains_key(&client_addr)
+                        {
+                            debug!(
+                                "Target of {} is draining, dropping new client {}",
+                                rule.name, client_addr
+                            );
+                            continue;
+                        }
+
                         // Get or create session socket for this client
                         let session_socket = {
                             // Check if session exists
@@ -807,6 +877,141 @@ impl QUICSocketProxy {
         }
     }
 
+    /// Probe each rule's target and mark it routable or not as its readiness
+    /// changes
+    async fn start_readiness_monitor(&self) -> Result<()> {
+        let probes: Vec<(String, IpAddr, ReadinessProbe)> = self
+            .proxy_rules
+            .read()
+            .await
+            .values()
+            .filter_map(|rule| {
+                rule.readiness
+                    .clone()
+                    .map(|probe| (rule.name.clone(), rule.target_address.ip(), probe))
+            })
+            .collect();
+        if probes.is_empty() {
+            return futures::future::pending().await;
+        }
+        info!("🚦 Starting readiness probes for {} rules", probes.len());
+
+        let mut monitors = FuturesUnordered::new();
+        for (name, ip, probe) in probes {
+            let not_ready = Arc::clone(&self.not_ready);
+            monitors.push(async move {
+                tokio::time::sleep(probe.initial_delay).await;
+                let target = ProbeTarget::Address(ip);
+                let mut tracker = ReadinessTracker::new(&probe);
+                let mut interval = tokio::time::interval(probe.interval);
+                loop {
+                    interval.tick().await;
+                    match tracker.observe(probe.probe(&target).await) {
+                        Some(true) => {
+                            info!("✅ {} is ready, routing traffic", name);
+                            not_ready.write().await.remove(&name);
+                        }
+                        Some(false) => {
+                            warn!("⚠️ {} is no longer ready, holding traffic", name);
+                            not_ready.write().await.insert(name.clone());
+                        }
+                        None => {}
+                    }
+                }
+            });
+        }
+        monitors.next().await;
+        Ok(())
+    }
+
+    /// Stop sending new clients to targets that `surge` is draining before
+    /// it stops them
+    async fn start_drain_monitor(&self) -> Result<()> {
+        let data_dir = match crate::config::BoltConfig::load() {
+            Ok(config) => config.data_dir,
+            Err(e) => {
+                warn!("Not following drained replicas: {}", e);
+                return futures::future::pending().await;
+            }
+        };
+
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let addresses = crate::runtime::drain::draining_addresses(&data_dir);
+            let now_draining: HashSet<String> = self
+                .proxy_rules
+                .read()
+                .await
+                .values()
+                .filter(|rule| addresses.contains(&rule.target_address.ip()))
+                .map(|rule| rule.name.clone())
+                .collect();
+
+            let mut draining = self.draining.write().await;
+            for name in now_draining.difference(&draining) {
+                info!("🚰 {} is draining, no new clients", name);
+            }
+            for name in draining.difference(&now_draining) {
+                debug!("{} is no longer draining", name);
+            }
+            *draining = now_draining;
+        }
+    }
+
+    /// Serve the `[acme]` certificate named by `tls_certificate`, rereading
+    /// it after `bolt cert` renews it. Only new handshakes get the renewed
+    /// certificate; open connections keep theirs.
+    async fn start_certificate_monitor(&self) -> Result<()> {
+        #[cfg(feature = "quic-networking")]
+        if let Some(ref name) = self.config.tls_certificate {
+            let store = match crate::config::BoltConfig::load() {
+                Ok(config) => crate::secrets::SecretStore::new(&config.config_dir),
+                Err(e) => {
+                    warn!("Not serving certificate {}: {}", name, e);
+                    return futures::future::pending().await;
+                }
+            };
+
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let Some((chain, key)) = crate::networking::acme::load(&store, name) else {
+                    debug!("Certificate {} has not been issued yet", name);
+                    continue;
+                };
+                let mut endpoints = self.server_endpoints.write().await;
+                let endpoint =
+                    endpoints
+                        .entry(name.clone())
+                        .or_insert_with(|| QUICServerEndpoint {
+                            endpoint_id: name.clone(),
+                            bind_address: self.config.bind_address,
+                            cert_chain: Vec::new(),
+                            private_key: Vec::new(),
+                            active_connections: 0,
+                            total_connections: 0,
+                            bytes_sent: 0,
+                            bytes_received: 0,
+                            last_activity: Instant::now(),
+                        });
+                if endpoint.cert_chain != chain.as_bytes() {
+                    if endpoint.cert_chain.is_empty() {
+                        info!("🔐 Serving certificate {}", name);
+                    } else {
+                        info!(
+                            "🔐 Reloaded certificate {}; open connections keep the previous one",
+                            name
+                        );
+                    }
+                    endpoint.cert_chain = chain.into_bytes();
+                    endpoint.private_key = key.into_bytes();
+                }
+            }
+        }
+        futures::future::pending().await
+    }
+
     /// Start statistics collector
     async fn start_stats_collector(&self) -> Result<()> {
         info!("📊 Starting statistics collector");
@@ -920,6 +1125,7 @@ impl Default for QUICProxyConfig {
             congestion_control: CongestionControl::BBR,
             enable_gso: true,
             enable_gro: true,
+            tls_certificate: None,
         }
     }
 }
@@ -963,6 +1169,7 @@ mod tests {
             protocol: ProxyProtocol::TCP,
             load_balancing: LoadBalancingStrategy::RoundRobin,
             health_check: None,
+            readiness: None,
             traffic_shaping: None,
             gaming_optimizations: None,
         };
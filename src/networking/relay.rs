@@ -0,0 +1,834 @@
+// Relay for hosts behind NAT
+//
+// When the router can't forward a `port_forward` service's ports, typically
+// behind carrier-grade NAT, `[nat] relay` names a node with a public address
+// running `bolt network relay`. The host's `nat-watch` keeps a QUIC
+// connection to it, and the relay listens on the host's published port
+// numbers, or the first free ones in its `relay_ports`:
+//
+// - each TCP connection the relay accepts is carried to the host in a QUIC
+//   stream, and the host connects it to the published port
+// - each UDP datagram goes to the host as a QUIC datagram tagged with the
+//   port and the sender, and the host answers through a socket per sender
+//
+// Friends connect to the relay's address like to any server, with no client
+// of their own; the game server sees their traffic come from the host
+// itself. The relay also answers STUN binding requests on `stun_port`, so it
+// can be the hosts' `[nat] stun` server.
+//
+// A relay with a `relay_token` only publishes for hosts presenting the same
+// token. Hosts pin the relay's certificate by `relay_fingerprint`, or trust
+// it on first use.
+use anyhow::{Context, Result, anyhow};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use super::nat::{MappingVia, PortMapping, PortRange, PublishedPort, Transport};
+use crate::config::BoltConfig;
+
+const SERVER_NAME: &str = "bolt-relay";
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024;
+/// How long a UDP sender's socket on the host lives without answers
+const UDP_FLOW_IDLE: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Message {
+    /// Host to relay, on the first stream
+    Publish {
+        token: Option<String>,
+        ports: Vec<PublishedPort>,
+    },
+    Published {
+        ports: Vec<RelayedPort>,
+        /// Ports the relay could not listen on, and why
+        refused: Vec<String>,
+    },
+    Refused {
+        reason: String,
+    },
+    /// Relay to host, opening the stream of each TCP connection
+    Connection {
+        port: u16,
+        peer: SocketAddr,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct RelayedPort {
+    port: PublishedPort,
+    /// Port the relay listens on for it
+    public: u16,
+}
+
+fn relay_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("nat").join("relay")
+}
+
+/// Publish ports for hosts behind NAT and answer STUN until stopped. Run by
+/// `bolt network relay`.
+pub async fn serve(config: &BoltConfig) -> Result<()> {
+    let settings = &config.nat;
+    let range = PortRange::parse(&settings.relay_ports).context("Invalid [nat] relay_ports")?;
+
+    let (cert, key) = identity(&relay_dir(&config.data_dir))?;
+    let fingerprint = hex::encode(Sha256::digest(&cert));
+    let mut server_config = quinn::ServerConfig::with_single_cert(
+        vec![quinn_rustls::Certificate(cert)],
+        quinn_rustls::PrivateKey(key),
+    )?;
+    server_config.transport_config(transport_config()?);
+    let listen = SocketAddr::from((Ipv4Addr::UNSPECIFIED, settings.relay_port));
+    let endpoint = quinn::Endpoint::server(server_config, listen)
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    let stun = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, settings.stun_port))
+        .await
+        .with_context(|| format!("Failed to listen for STUN on port {}", settings.stun_port))?;
+
+    info!(
+        "🛰️  Relaying for hosts behind NAT on port {}, STUN on port {}",
+        settings.relay_port, settings.stun_port
+    );
+    info!("  • Certificate fingerprint: {}", fingerprint);
+    if settings.relay_token.is_none() {
+        warn!("No [nat] relay_token: any host can publish ports through this relay");
+    }
+
+    let answer = answer_stun(&stun);
+    let accept = async {
+        while let Some(connecting) = endpoint.accept().await {
+            let token = settings.relay_token.clone();
+            tokio::spawn(async move {
+                let remote = connecting.remote_address();
+                if let Err(e) = serve_host(connecting, token, range).await {
+                    warn!("Relaying for {} ended: {:#}", remote, e);
+                }
+            });
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    tokio::try_join!(answer, accept)?;
+    Ok(())
+}
+
+/// Tell each binding request's sender the address it came from
+async fn answer_stun(socket: &UdpSocket) -> Result<()> {
+    let mut buf = [0u8; 512];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        if let Some(transaction) = super::nat::parse_binding_request(&buf[..len]) {
+            let response = super::nat::binding_response(&transaction, from);
+            if let Err(e) = socket.send_to(&response, from).await {
+                debug!("Could not answer STUN from {}: {}", from, e);
+            }
+        }
+    }
+}
+
+/// Listen on the ports a host asks for and carry their traffic to it until
+/// the host goes away
+async fn serve_host(
+    connecting: quinn::Connecting,
+    token: Option<String>,
+    range: PortRange,
+) -> Result<()> {
+    let connection = connecting.await?;
+    let remote = connection.remote_address();
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let Message::Publish {
+        token: presented,
+        ports,
+    } = read_message(&mut recv).await?
+    else {
+        return Err(anyhow!("{} did not say what to publish", remote));
+    };
+    if token.is_some() && presented != token {
+        let reason = "wrong relay token".to_string();
+        write_message(&mut send, &Message::Refused { reason }).await?;
+        send.finish().await?;
+        return Err(anyhow!("{} presented the wrong relay token", remote));
+    }
+
+    // Listeners close when the tasks are dropped with the host's connection
+    let mut tasks = JoinSet::new();
+    let mut udp: HashMap<u16, Arc<UdpSocket>> = HashMap::new();
+    let mut published = Vec::new();
+    let mut refused = Vec::new();
+    for port in ports {
+        match listen(port, range).await {
+            Ok((Listener::Tcp(listener), public)) => {
+                tasks.spawn(relay_tcp(listener, port.port, connection.clone()));
+                published.push(RelayedPort { port, public });
+            }
+            Ok((Listener::Udp(socket), public)) => {
+                let socket = Arc::new(socket);
+                udp.insert(port.port, socket.clone());
+                tasks.spawn(relay_udp(socket, port.port, connection.clone()));
+                published.push(RelayedPort { port, public });
+            }
+            Err(e) => refused.push(format!("{}: {:#}", port, e)),
+        }
+    }
+    let summary: Vec<String> = published
+        .iter()
+        .map(|p| format!("{} on {}", p.port, p.public))
+        .collect();
+    write_message(
+        &mut send,
+        &Message::Published {
+            ports: published,
+            refused,
+        },
+    )
+    .await?;
+    send.finish().await?;
+    info!("🛰️  Relaying {} for {}", summary.join(", "), remote);
+
+    // What the host sends back to the senders of UDP datagrams
+    let error = loop {
+        let datagram = match connection.read_datagram().await {
+            Ok(datagram) => datagram,
+            Err(e) => break e,
+        };
+        let Some((port, peer, payload)) = decode_datagram(&datagram) else {
+            continue;
+        };
+        if let Some(socket) = udp.get(&port)
+            && let Err(e) = socket.send_to(payload, peer).await
+        {
+            debug!("Could not relay a datagram to {}: {}", peer, e);
+        }
+    };
+    info!("Stopped relaying for {} ({})", remote, error);
+    Ok(())
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+}
+
+/// Listen on the host's port number when `range` allows it and it's free,
+/// otherwise on the first free port of `range`
+async fn listen(port: PublishedPort, range: PortRange) -> Result<(Listener, u16)> {
+    let same = Some(port.port).filter(|p| range.contains(*p));
+    for candidate in same.into_iter().chain(range.start..=range.end) {
+        let address = SocketAddr::from((Ipv4Addr::UNSPECIFIED, candidate));
+        let bound = match port.transport {
+            Transport::Tcp => TcpListener::bind(address).await.map(Listener::Tcp),
+            Transport::Udp => UdpSocket::bind(address).await.map(Listener::Udp),
+        };
+        if let Ok(listener) = bound {
+            return Ok((listener, candidate));
+        }
+    }
+    Err(anyhow!("no free port in {}-{}", range.start, range.end))
+}
+
+async fn relay_tcp(listener: TcpListener, port: u16, connection: quinn::Connection) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(
+                    "Stopped accepting relayed connections for port {}: {}",
+                    port, e
+                );
+                return;
+            }
+        };
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            let carried = async {
+                let (mut send, recv) = connection.open_bi().await?;
+                write_message(&mut send, &Message::Connection { port, peer }).await?;
+                splice(stream, send, recv).await
+            };
+            if let Err(e) = carried.await {
+                debug!("Relayed connection from {} ended: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn relay_udp(socket: Arc<UdpSocket>, port: u16, connection: quinn::Connection) {
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Stopped relaying datagrams for port {}: {}", port, e);
+                return;
+            }
+        };
+        if let Err(e) = connection.send_datagram(encode_datagram(port, peer, &buf[..len])) {
+            if connection.close_reason().is_some() {
+                return;
+            }
+            debug!("Dropped a datagram from {}: {}", peer, e);
+        }
+    }
+}
+
+/// Copy both ways between a TCP connection and a QUIC stream
+async fn splice(
+    stream: TcpStream,
+    mut send: quinn::SendStream,
+    mut recv: quinn::RecvStream,
+) -> Result<()> {
+    let (mut read, mut write) = stream.into_split();
+    let outbound = async {
+        tokio::io::copy(&mut read, &mut send).await?;
+        send.finish().await?;
+        Ok::<(), anyhow::Error>(())
+    };
+    let inbound = async {
+        tokio::io::copy(&mut recv, &mut write).await?;
+        write.shutdown().await?;
+        Ok::<(), anyhow::Error>(())
+    };
+    tokio::try_join!(outbound, inbound)?;
+    Ok(())
+}
+
+/// A host's connection to its relay
+pub struct RelayClient {
+    connection: quinn::Connection,
+    mappings: Vec<PortMapping>,
+    // Dropping these stops carrying traffic
+    _tasks: JoinSet<()>,
+    _endpoint: quinn::Endpoint,
+}
+
+impl RelayClient {
+    /// Ask the `[nat] relay` to publish `ports` and start carrying their
+    /// traffic to this host
+    pub async fn connect(config: &BoltConfig, ports: &[PublishedPort]) -> Result<Self> {
+        let settings = &config.nat;
+        let relay = settings
+            .relay
+            .as_deref()
+            .ok_or_else(|| anyhow!("No [nat] relay configured"))?;
+        let remote = tokio::net::lookup_host(relay)
+            .await
+            .with_context(|| format!("Failed to resolve {}", relay))?
+            .next()
+            .ok_or_else(|| anyhow!("No address found for {}", relay))?;
+        let pinned = match settings.relay_fingerprint {
+            Some(ref fingerprint) => Some(fingerprint.to_lowercase()),
+            None => known_relays(&config.data_dir).remove(relay),
+        };
+        let verifier = Arc::new(FingerprintVerifier {
+            expected: pinned.clone(),
+            seen: Mutex::new(None),
+        });
+
+        let crypto = quinn_rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(verifier.clone())
+            .with_no_client_auth();
+        let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+        client_config.transport_config(transport_config()?);
+        let bind = match remote {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let mut endpoint = quinn::Endpoint::client(bind)?;
+        endpoint.set_default_client_config(client_config);
+        let connection = endpoint
+            .connect(remote, SERVER_NAME)?
+            .await
+            .with_context(|| format!("Failed to connect to relay {}", relay))?;
+        if pinned.is_none()
+            && let Some(seen) = verifier.seen.lock().unwrap().clone()
+        {
+            warn!(
+                "🔑 Trusting relay {} on first use (certificate sha256:{})",
+                relay, seen
+            );
+            remember_relay(&config.data_dir, relay, &seen)?;
+        }
+
+        let (mut send, mut recv) = connection.open_bi().await?;
+        write_message(
+            &mut send,
+            &Message::Publish {
+                token: settings.relay_token.clone(),
+                ports: ports.to_vec(),
+            },
+        )
+        .await?;
+        send.finish().await?;
+        let published = match read_message(&mut recv).await? {
+            Message::Published { ports, refused } => {
+                for refusal in refused {
+                    warn!("Relay {} cannot publish {}", relay, refusal);
+                }
+                ports
+            }
+            Message::Refused { reason } => {
+                return Err(anyhow!("Relay {} refused: {}", relay, reason));
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unexpected answer from relay {}: {:?}",
+                    relay,
+                    other
+                ));
+            }
+        };
+        if published.is_empty() {
+            return Err(anyhow!("Relay {} published none of the ports", relay));
+        }
+
+        let allowed = |transport: Transport| -> HashSet<u16> {
+            published
+                .iter()
+                .filter(|p| p.port.transport == transport)
+                .map(|p| p.port.port)
+                .collect()
+        };
+        let mut tasks = JoinSet::new();
+        tasks.spawn(accept_streams(connection.clone(), allowed(Transport::Tcp)));
+        tasks.spawn(forward_datagrams(
+            connection.clone(),
+            allowed(Transport::Udp),
+        ));
+        let mappings = published
+            .iter()
+            .map(|p| PortMapping {
+                port: p.port,
+                public: SocketAddr::new(remote.ip(), p.public),
+                via: MappingVia::Relay,
+                expires_at: None,
+            })
+            .collect();
+        Ok(Self {
+            connection,
+            mappings,
+            _tasks: tasks,
+            _endpoint: endpoint,
+        })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.connection.close_reason().is_none()
+    }
+
+    pub fn mappings(&self) -> Vec<PortMapping> {
+        self.mappings.clone()
+    }
+
+    /// Tell the relay to stop listening for this host
+    pub fn close(self) {
+        self.connection.close(0u32.into(), b"done");
+    }
+}
+
+/// Connect each relayed TCP connection to its published port
+async fn accept_streams(connection: quinn::Connection, allowed: HashSet<u16>) {
+    while let Ok((send, mut recv)) = connection.accept_bi().await {
+        let allowed = allowed.clone();
+        tokio::spawn(async move {
+            let carried = async {
+                let Message::Connection { port, peer } = read_message(&mut recv).await? else {
+                    return Err(anyhow!("Expected a relayed connection"));
+                };
+                // The relay only reaches the ports it publishes
+                if !allowed.contains(&port) {
+                    return Err(anyhow!("Port {}/tcp is not published", port));
+                }
+                debug!("Relaying a connection from {} to port {}", peer, port);
+                let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+                    .await
+                    .with_context(|| format!("Nothing accepts on port {}", port))?;
+                splice(stream, send, recv).await
+            };
+            if let Err(e) = carried.await {
+                debug!("Relayed connection ended: {:#}", e);
+            }
+        });
+    }
+}
+
+type Flows = Arc<Mutex<HashMap<(u16, SocketAddr), Arc<UdpSocket>>>>;
+
+/// Send each relayed datagram to its published port, from a socket per
+/// sender whose answers go back through the relay
+async fn forward_datagrams(connection: quinn::Connection, allowed: HashSet<u16>) {
+    let flows: Flows = Default::default();
+    while let Ok(datagram) = connection.read_datagram().await {
+        let Some((port, peer, payload)) = decode_datagram(&datagram) else {
+            continue;
+        };
+        if !allowed.contains(&port) {
+            continue;
+        }
+        let socket = match flow(&flows, port, peer, &connection).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                debug!("Cannot relay datagrams from {}: {:#}", peer, e);
+                continue;
+            }
+        };
+        if let Err(e) = socket.send(payload).await {
+            debug!("Could not deliver a datagram from {}: {}", peer, e);
+        }
+    }
+}
+
+async fn flow(
+    flows: &Flows,
+    port: u16,
+    peer: SocketAddr,
+    connection: &quinn::Connection,
+) -> Result<Arc<UdpSocket>> {
+    if let Some(socket) = flows.lock().unwrap().get(&(port, peer)) {
+        return Ok(socket.clone());
+    }
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    socket.connect((Ipv4Addr::LOCALHOST, port)).await?;
+    let socket = Arc::new(socket);
+    flows.lock().unwrap().insert((port, peer), socket.clone());
+
+    // Answers go back to the sender until the flow goes quiet
+    let (flows, answers, connection) = (flows.clone(), socket.clone(), connection.clone());
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 65536];
+        while let Ok(Ok(len)) = tokio::time::timeout(UDP_FLOW_IDLE, answers.recv(&mut buf)).await {
+            if connection
+                .send_datagram(encode_datagram(port, peer, &buf[..len]))
+                .is_err()
+                && connection.close_reason().is_some()
+            {
+                break;
+            }
+        }
+        flows.lock().unwrap().remove(&(port, peer));
+    });
+    Ok(socket)
+}
+
+/// The published port, the sender's address and the payload
+fn encode_datagram(port: u16, peer: SocketAddr, payload: &[u8]) -> Bytes {
+    let mut datagram = Vec::with_capacity(21 + payload.len());
+    datagram.extend_from_slice(&port.to_be_bytes());
+    match peer.ip() {
+        IpAddr::V4(ip) => {
+            datagram.push(4);
+            datagram.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            datagram.push(6);
+            datagram.extend_from_slice(&ip.octets());
+        }
+    }
+    datagram.extend_from_slice(&peer.port().to_be_bytes());
+    datagram.extend_from_slice(payload);
+    Bytes::from(datagram)
+}
+
+fn decode_datagram(datagram: &[u8]) -> Option<(u16, SocketAddr, &[u8])> {
+    let port = u16::from_be_bytes([*datagram.first()?, *datagram.get(1)?]);
+    let (ip, rest) = match datagram.get(2)? {
+        4 => {
+            let octets: [u8; 4] = datagram.get(3..7)?.try_into().ok()?;
+            (IpAddr::from(octets), datagram.get(7..)?)
+        }
+        6 => {
+            let octets: [u8; 16] = datagram.get(3..19)?.try_into().ok()?;
+            (IpAddr::from(octets), datagram.get(19..)?)
+        }
+        _ => return None,
+    };
+    let peer_port = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]);
+    Some((port, SocketAddr::new(ip, peer_port), rest.get(2..)?))
+}
+
+/// Self-signed certificate, generated once so its fingerprint stays stable
+fn identity(dir: &Path) -> Result<(Vec<u8>, Vec<u8>)> {
+    let cert_path = dir.join("relay.crt.der");
+    let key_path = dir.join("relay.key.der");
+    if cert_path.exists() && key_path.exists() {
+        return Ok((std::fs::read(&cert_path)?, std::fs::read(&key_path)?));
+    }
+    std::fs::create_dir_all(dir)?;
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+    let cert_der = cert.serialize_der()?;
+    let key_der = cert.serialize_private_key_der();
+    std::fs::write(&cert_path, &cert_der)?;
+    std::fs::write(&key_path, &key_der)?;
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))?;
+    Ok((cert_der, key_der))
+}
+
+fn known_relays_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("nat").join("known_relays.json")
+}
+
+fn known_relays(data_dir: &Path) -> HashMap<String, String> {
+    std::fs::read(known_relays_path(data_dir))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+fn remember_relay(data_dir: &Path, relay: &str, fingerprint: &str) -> Result<()> {
+    let mut relays = known_relays(data_dir);
+    relays.insert(relay.to_string(), fingerprint.to_string());
+    let path = known_relays_path(data_dir);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(&relays)?)?;
+    Ok(())
+}
+
+/// Accepts the relay's self-signed certificate if it matches the pinned
+/// fingerprint, or records it when nothing is pinned yet
+#[derive(Debug)]
+struct FingerprintVerifier {
+    expected: Option<String>,
+    seen: Mutex<Option<String>>,
+}
+
+impl quinn_rustls::client::ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &quinn_rustls::Certificate,
+        _intermediates: &[quinn_rustls::Certificate],
+        _server_name: &quinn_rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<quinn_rustls::client::ServerCertVerified, quinn_rustls::Error> {
+        let fingerprint = hex::encode(Sha256::digest(&end_entity.0));
+        if let Some(ref expected) = self.expected
+            && !fingerprint.eq_ignore_ascii_case(expected)
+        {
+            return Err(quinn_rustls::Error::General(format!(
+                "relay certificate sha256:{} does not match pinned sha256:{}",
+                fingerprint, expected
+            )));
+        }
+        *self.seen.lock().unwrap() = Some(fingerprint);
+        Ok(quinn_rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+fn transport_config() -> Result<Arc<quinn::TransportConfig>> {
+    let mut transport = quinn::TransportConfig::default();
+    transport.max_idle_timeout(Some(Duration::from_secs(60).try_into()?));
+    transport.keep_alive_interval(Some(Duration::from_secs(10)));
+    Ok(Arc::new(transport))
+}
+
+async fn write_message(send: &mut quinn::SendStream, message: &Message) -> Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    send.write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    send.write_all(&payload).await?;
+    Ok(())
+}
+
+async fn read_message(recv: &mut quinn::RecvStream) -> Result<Message> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(anyhow!("Relay message too large ({} bytes)", len));
+    }
+    let mut payload = vec![0u8; len as usize];
+    recv.read_exact(&mut payload).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NatSettings;
+
+    #[test]
+    fn tags_datagrams_with_port_and_sender() {
+        for peer in ["198.51.100.4:50000", "[2001:db8::7]:61000"] {
+            let peer: SocketAddr = peer.parse().unwrap();
+            let datagram = encode_datagram(27015, peer, b"\xffTSource Engine Query");
+            assert_eq!(
+                decode_datagram(&datagram),
+                Some((27015, peer, &b"\xffTSource Engine Query"[..]))
+            );
+        }
+        assert_eq!(decode_datagram(&[0x69, 0x87, 5, 1, 2]), None);
+        assert_eq!(decode_datagram(&[0x69, 0x87, 4, 1, 2]), None);
+    }
+
+    fn tcp(port: u16) -> PublishedPort {
+        PublishedPort {
+            port,
+            transport: Transport::Tcp,
+        }
+    }
+
+    /// The first of `count` consecutive ports free for TCP and UDP
+    fn free_ports(count: u16) -> u16 {
+        loop {
+            let probe = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+            let start = probe.local_addr().unwrap().port();
+            drop(probe);
+            let free = (start..start.saturating_add(count)).all(|port| {
+                std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).is_ok()
+                    && std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).is_ok()
+            });
+            if free && start.checked_add(count).is_some() {
+                return start;
+            }
+        }
+    }
+
+    /// Run a relay in `dir`; its `[nat] relay` address
+    fn spawn_relay(dir: &Path, token: Option<&str>, relay_ports: String) -> String {
+        let relay_port = free_ports(2);
+        let config = BoltConfig {
+            data_dir: dir.join("relay"),
+            nat: NatSettings {
+                relay_token: token.map(String::from),
+                relay_port,
+                stun_port: relay_port + 1,
+                relay_ports,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let relay = format!("127.0.0.1:{}", relay_port);
+        tokio::spawn(async move { serve(&config).await });
+        relay
+    }
+
+    fn host_config(dir: &Path, relay: &str, token: Option<&str>) -> BoltConfig {
+        BoltConfig {
+            data_dir: dir.join("host"),
+            nat: NatSettings {
+                relay: Some(relay.to_string()),
+                relay_token: token.map(String::from),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn allocates_public_ports_from_relay_ports() {
+        let start = free_ports(2);
+        let range = PortRange {
+            start,
+            end: start + 1,
+        };
+
+        // Ports outside the range get the first free one of it
+        let (first, public) = listen(tcp(1), range).await.unwrap();
+        assert_eq!(public, start);
+        let (second, public) = listen(tcp(2), range).await.unwrap();
+        assert_eq!(public, start + 1);
+        let error = listen(tcp(start), range).await.err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("no free port in {}-{}", start, start + 1)
+        );
+        drop((first, second));
+
+        // The host's own port number wins when the range has it
+        let (_same, public) = listen(tcp(start + 1), range).await.unwrap();
+        assert_eq!(public, start + 1);
+        let udp = PublishedPort {
+            port: start + 1,
+            transport: Transport::Udp,
+        };
+        let (_udp, public) = listen(udp, range).await.unwrap();
+        assert_eq!(public, start + 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_hosts_without_the_relay_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let relay = spawn_relay(dir.path(), Some("hunter2"), free_ports(1).to_string());
+        for token in [None, Some("guess")] {
+            let config = host_config(dir.path(), &relay, token);
+            let error = RelayClient::connect(&config, &[tcp(25565)])
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(
+                error.to_string(),
+                format!("Relay {} refused: wrong relay token", relay)
+            );
+        }
+        // The certificate is still trusted on first use
+        assert!(known_relays(&dir.path().join("host")).contains_key(&relay));
+    }
+
+    #[tokio::test]
+    async fn forwards_tcp_connections_over_quic() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        // What the container publishes on the host
+        let echo = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = tcp(echo.local_addr().unwrap().port());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = echo.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    tokio::io::copy(&mut read, &mut write).await.unwrap();
+                });
+            }
+        });
+        let public = free_ports(1);
+        let relay = spawn_relay(dir.path(), Some("hunter2"), public.to_string());
+        let config = host_config(dir.path(), &relay, Some("hunter2"));
+        let client = RelayClient::connect(&config, &[port]).await.unwrap();
+        assert_eq!(
+            client.mappings(),
+            [PortMapping {
+                port,
+                public: SocketAddr::from((Ipv4Addr::LOCALHOST, public)),
+                via: MappingVia::Relay,
+                expires_at: None,
+            }]
+        );
+
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, public))
+            .await
+            .unwrap();
+        stream.write_all(b"hello from a friend").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut echoed = Vec::new();
+        stream.read_to_end(&mut echoed).await.unwrap();
+        assert_eq!(echoed, b"hello from a friend");
+
+        // The relay stops listening once the host goes away
+        client.close();
+        let stopped = async {
+            while TcpStream::connect((Ipv4Addr::LOCALHOST, public))
+                .await
+                .is_ok()
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), stopped)
+            .await
+            .unwrap();
+    }
+}
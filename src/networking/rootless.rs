@@ -0,0 +1,246 @@
+// Rootless container networking
+//
+// Without root Bolt can't create bridges or veth pairs, so the network
+// namespace of a rootless container is connected to the host by a user-mode
+// network stack: pasta (from passt) when it is installed, else slirp4netns.
+// Both give the container outbound access and forward its published ports.
+// pasta is preferred: it is faster and keeps the clients' source addresses,
+// which game servers use for bans and matchmaking.
+//
+// Containers run by rootless podman get the same stack through `--network`
+// when their service joins no Boltfile network, so the choice doesn't depend
+// on the podman version's default; slirp4netns uses its own port handler,
+// which keeps source addresses too.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command as AsyncCommand};
+use tracing::{debug, info};
+
+use crate::runtime::oci::PortMapping;
+
+/// How long slirp4netns gets to open its API socket
+const API_SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A user-mode network stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Pasta,
+    Slirp4netns,
+}
+
+impl Backend {
+    /// pasta when installed, else slirp4netns
+    pub fn detect() -> Option<Self> {
+        [Backend::Pasta, Backend::Slirp4netns]
+            .into_iter()
+            .find(|b| in_path(b.as_str()))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Pasta => "pasta",
+            Backend::Slirp4netns => "slirp4netns",
+        }
+    }
+
+    /// `--network` for a rootless podman container
+    pub fn podman_network(&self) -> &'static str {
+        match self {
+            Backend::Pasta => "pasta",
+            Backend::Slirp4netns => "slirp4netns:port_handler=slirp4netns",
+        }
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Arguments for `podman run` of a service that joins no Boltfile network,
+/// when Bolt runs without root
+pub fn run_args(runtime: &str) -> Vec<String> {
+    if runtime != "podman" || !crate::runtime::rootless::active() {
+        return Vec::new();
+    }
+    match Backend::detect() {
+        Some(backend) => vec![
+            "--network".to_string(),
+            backend.podman_network().to_string(),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// A user-mode network stack serving a container's network namespace; it
+/// stops when dropped
+pub struct RootlessNetwork {
+    pub backend: Backend,
+    child: Child,
+    api_socket: Option<PathBuf>,
+}
+
+impl RootlessNetwork {
+    /// Connect the network namespace of `pid` to the host and forward
+    /// `ports` to it. slirp4netns's API socket is created in `run_dir`.
+    pub async fn attach(
+        backend: Backend,
+        pid: u32,
+        ports: &[PortMapping],
+        run_dir: &Path,
+    ) -> Result<Self> {
+        let network = match backend {
+            Backend::Pasta => {
+                let mut cmd = AsyncCommand::new("pasta");
+                cmd.args(["--config-net", "--foreground", "--quiet"]);
+                for port in ports {
+                    cmd.args(pasta_forward(port));
+                }
+                let child = cmd
+                    .arg(pid.to_string())
+                    .stdin(std::process::Stdio::null())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .context("Failed to start pasta")?;
+                Self {
+                    backend,
+                    child,
+                    api_socket: None,
+                }
+            }
+            Backend::Slirp4netns => {
+                let socket = run_dir.join(format!("slirp4netns-{}.sock", pid));
+                let _ = std::fs::remove_file(&socket);
+                let child = AsyncCommand::new("slirp4netns")
+                    .args(["--configure", "--mtu=65520", "--disable-host-loopback"])
+                    .arg(format!("--userns-path=/proc/{}/ns/user", pid))
+                    .arg(format!("--api-socket={}", socket.display()))
+                    .args([pid.to_string().as_str(), "tap0"])
+                    .stdin(std::process::Stdio::null())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .context("Failed to start slirp4netns")?;
+                let network = Self {
+                    backend,
+                    child,
+                    api_socket: Some(socket.clone()),
+                };
+                let deadline = tokio::time::Instant::now() + API_SOCKET_TIMEOUT;
+                while !socket.exists() {
+                    if tokio::time::Instant::now() > deadline {
+                        return Err(anyhow!("slirp4netns didn't open {}", socket.display()));
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                for port in ports {
+                    add_hostfwd(&socket, port).await?;
+                }
+                network
+            }
+        };
+        info!(
+            "🌐 Rootless network through {} ({} port(s) forwarded)",
+            backend.as_str(),
+            ports.len()
+        );
+        Ok(network)
+    }
+
+    /// Stop the network stack
+    pub async fn stop(mut self) {
+        let _ = self.child.kill().await;
+        if let Some(ref socket) = self.api_socket {
+            let _ = std::fs::remove_file(socket);
+        }
+    }
+}
+
+fn is_udp(port: &PortMapping) -> bool {
+    matches!(port.protocol.as_str(), "udp" | "quic")
+}
+
+/// `-t`/`-u` and `[address/]host:container` for pasta
+fn pasta_forward(port: &PortMapping) -> [String; 2] {
+    let flag = if is_udp(port) { "-u" } else { "-t" };
+    let spec = match port.host_ip {
+        Some(ref ip) => format!("{}/{}:{}", ip, port.host_port, port.container_port),
+        None => format!("{}:{}", port.host_port, port.container_port),
+    };
+    [flag.to_string(), spec]
+}
+
+/// The slirp4netns API request forwarding `port`
+fn hostfwd_request(port: &PortMapping) -> serde_json::Value {
+    serde_json::json!({
+        "execute": "add_hostfwd",
+        "arguments": {
+            "proto": if is_udp(port) { "udp" } else { "tcp" },
+            "host_addr": port.host_ip.as_deref().unwrap_or("0.0.0.0"),
+            "host_port": port.host_port,
+            "guest_port": port.container_port,
+        }
+    })
+}
+
+async fn add_hostfwd(socket: &Path, port: &PortMapping) -> Result<()> {
+    let mut stream = tokio::net::UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+    stream
+        .write_all(hostfwd_request(port).to_string().as_bytes())
+        .await?;
+    stream.shutdown().await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let response: serde_json::Value =
+        serde_json::from_str(&response).context("Invalid slirp4netns API response")?;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!(
+            "slirp4netns can't forward port {}: {}",
+            port.host_port,
+            error
+        ));
+    }
+    debug!("slirp4netns forwards port {}", port.host_port);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_ports_for_either_backend() {
+        let game = PortMapping {
+            host_port: 27015,
+            container_port: 27015,
+            protocol: "udp".to_string(),
+            host_ip: None,
+        };
+        let admin = PortMapping {
+            host_port: 8080,
+            container_port: 80,
+            protocol: "tcp".to_string(),
+            host_ip: Some("127.0.0.1".to_string()),
+        };
+        assert_eq!(pasta_forward(&game), ["-u", "27015:27015"]);
+        assert_eq!(pasta_forward(&admin), ["-t", "127.0.0.1/8080:80"]);
+
+        let request = hostfwd_request(&admin);
+        assert_eq!(request["execute"], "add_hostfwd");
+        assert_eq!(request["arguments"]["proto"], "tcp");
+        assert_eq!(request["arguments"]["host_addr"], "127.0.0.1");
+        assert_eq!(request["arguments"]["guest_port"], 80);
+        assert_eq!(hostfwd_request(&game)["arguments"]["host_addr"], "0.0.0.0");
+
+        assert_eq!(
+            Backend::Slirp4netns.podman_network(),
+            "slirp4netns:port_handler=slirp4netns"
+        );
+    }
+}
@@ -0,0 +1,948 @@
+// Network topology export
+//
+// `bolt network topo` draws how this host's networking fits together, as a
+// graph for Graphviz (`dot`), Mermaid or anything reading JSON:
+//
+// - the host, the runtime's networks (bridges, and QUIC fabric networks
+//   created with the `bolt` and `gquic` drivers) and the running containers
+//   attached to them, with their addresses and aliases
+// - published ports, from the host into each container
+// - how `port_forward` services are reachable from the internet: router
+//   mappings over NAT-PMP or UPnP, or the `[nat] relay` and the QUIC tunnel
+//   to it (see nat.rs and relay.rs)
+// - egress allowlists, as an edge from the container to the internet
+// - with `[p2p] enabled`, the Bolt nodes on the LAN sharing image layers
+//
+// `--live` serves the graph over a websocket for the Nova GUI's network
+// view: a `snapshot` message when a client connects, then a `change` message
+// with the nodes and edges added and removed each time the graph changes.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, info, warn};
+use warp::Filter;
+use warp::ws::{Message, WebSocket};
+
+use super::nat::MappingVia;
+use crate::config::BoltConfig;
+use crate::runtime::workspace;
+
+/// How often `--live` collects the graph again
+pub const LIVE_INTERVAL: Duration = Duration::from_secs(2);
+/// Peer discovery waits for answers, so `--live` only repeats it this often
+#[cfg(feature = "quic-networking")]
+const PEER_INTERVAL: Duration = Duration::from_secs(30);
+const HOST: &str = "host";
+const INTERNET: &str = "internet";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Host,
+    Network,
+    Container,
+    Relay,
+    Peer,
+    Internet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// A network on the host
+    Network,
+    /// A container on a network, or on the host's own network stack
+    Attached,
+    /// A host port forwarded into a container
+    Publish,
+    /// A public address forwarded by the router or the relay
+    Mapping,
+    /// The QUIC connection to the relay
+    Tunnel,
+    /// What a container may reach
+    Egress,
+    /// A LAN node sharing image layers over QUIC
+    Peer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Node {
+    /// Unique within the graph, e.g. "ctr:web" or "net:bolt0"
+    pub id: String,
+    pub kind: NodeKind,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Topology {
+    pub generated_at: DateTime<Utc>,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// What changed between two graphs. A node whose attributes changed is in
+/// `nodes_added` again; a changed edge is removed and added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopologyChange {
+    pub nodes_added: Vec<Node>,
+    pub nodes_removed: Vec<String>,
+    pub edges_added: Vec<Edge>,
+    pub edges_removed: Vec<Edge>,
+}
+
+impl TopologyChange {
+    pub fn is_empty(&self) -> bool {
+        self.nodes_added.is_empty()
+            && self.nodes_removed.is_empty()
+            && self.edges_added.is_empty()
+            && self.edges_removed.is_empty()
+    }
+}
+
+/// Messages on the `--live` websocket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LiveMessage {
+    Snapshot(Topology),
+    Change {
+        generated_at: DateTime<Utc>,
+        #[serde(flatten)]
+        change: TopologyChange,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dot,
+    Json,
+    Mermaid,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" | "graphviz" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            "mermaid" => Ok(Format::Mermaid),
+            other => Err(anyhow!(
+                "Unknown topology format '{}': use dot, json or mermaid",
+                other
+            )),
+        }
+    }
+}
+
+/// A LAN node sharing image layers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanPeer {
+    pub node: String,
+    pub address: SocketAddr,
+    pub blobs: usize,
+}
+
+/// Graph under construction; nodes and edges are kept sorted and unique
+#[derive(Default)]
+struct Builder {
+    nodes: BTreeMap<String, Node>,
+    edges: BTreeSet<Edge>,
+}
+
+impl Builder {
+    fn node(&mut self, id: &str, kind: NodeKind, label: &str) -> &mut Node {
+        self.nodes.entry(id.to_string()).or_insert_with(|| Node {
+            id: id.to_string(),
+            kind,
+            label: label.to_string(),
+            attributes: BTreeMap::new(),
+        })
+    }
+
+    fn edge(&mut self, from: &str, to: &str, kind: EdgeKind, label: String) {
+        self.edge_with(from, to, kind, label, BTreeMap::new());
+    }
+
+    fn edge_with(
+        &mut self,
+        from: &str,
+        to: &str,
+        kind: EdgeKind,
+        label: String,
+        attributes: BTreeMap<String, String>,
+    ) {
+        self.edges.insert(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind,
+            label,
+            attributes,
+        });
+    }
+
+    fn finish(self) -> Topology {
+        Topology {
+            generated_at: Utc::now(),
+            nodes: self.nodes.into_values().collect(),
+            edges: self.edges.into_iter().collect(),
+        }
+    }
+}
+
+/// A network as `network inspect` reports it, docker or podman
+#[derive(Debug, Default)]
+struct NetworkView {
+    name: String,
+    driver: String,
+    subnets: Vec<String>,
+    internal: bool,
+    quic: bool,
+}
+
+/// A running container as `inspect` reports it
+#[derive(Debug, Default)]
+struct ContainerView {
+    name: String,
+    image: String,
+    network_mode: String,
+    /// Network name, address and aliases
+    networks: Vec<(String, Option<String>, Vec<String>)>,
+    /// Host address and port, container port ("80/tcp")
+    ports: Vec<(String, String)>,
+}
+
+fn network_id(name: &str) -> String {
+    format!("net:{}", name)
+}
+
+fn container_id(name: &str) -> String {
+    format!("ctr:{}", name)
+}
+
+/// Looks up the first of `keys`, since docker and podman disagree on case
+fn field<'a>(value: &'a serde_json::Value, keys: &[&str]) -> Option<&'a serde_json::Value> {
+    keys.iter()
+        .find_map(|k| value.get(*k))
+        .filter(|v| !v.is_null())
+}
+
+fn string(value: &serde_json::Value, keys: &[&str]) -> String {
+    field(value, keys)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn parse_network(value: &serde_json::Value) -> NetworkView {
+    let mut subnets: Vec<String> = field(value, &["IPAM"])
+        .and_then(|ipam| field(ipam, &["Config"]))
+        .or_else(|| field(value, &["subnets"]))
+        .and_then(|c| c.as_array())
+        .map(|configs| {
+            configs
+                .iter()
+                .map(|c| string(c, &["Subnet", "subnet"]))
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    subnets.sort();
+    let quic = field(value, &["Labels", "labels"])
+        .and_then(|l| l.get("bolt.quic"))
+        .and_then(|v| v.as_str())
+        == Some("enabled");
+    NetworkView {
+        name: string(value, &["Name", "name"]),
+        driver: string(value, &["Driver", "driver"]),
+        subnets,
+        internal: field(value, &["Internal", "internal"])
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+        quic,
+    }
+}
+
+fn parse_container(value: &serde_json::Value) -> ContainerView {
+    let settings = field(value, &["NetworkSettings"]);
+    let mut networks: Vec<(String, Option<String>, Vec<String>)> = settings
+        .and_then(|s| field(s, &["Networks"]))
+        .and_then(|n| n.as_object())
+        .map(|networks| {
+            networks
+                .iter()
+                .map(|(name, endpoint)| {
+                    let address = string(endpoint, &["IPAddress"]);
+                    let aliases = field(endpoint, &["Aliases"])
+                        .and_then(|a| a.as_array())
+                        .map(|a| {
+                            a.iter()
+                                .filter_map(|v| v.as_str())
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    (
+                        name.clone(),
+                        Some(address).filter(|a| !a.is_empty()),
+                        aliases,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    networks.sort();
+
+    let mut ports: Vec<(String, String)> = settings
+        .and_then(|s| field(s, &["Ports"]))
+        .and_then(|p| p.as_object())
+        .map(|ports| {
+            ports
+                .iter()
+                .flat_map(|(container_port, bindings)| {
+                    bindings.as_array().into_iter().flatten().map(move |b| {
+                        let ip = string(b, &["HostIp"]);
+                        let ip = if ip.is_empty() {
+                            "0.0.0.0".to_string()
+                        } else {
+                            ip
+                        };
+                        (
+                            format!("{}:{}", ip, string(b, &["HostPort"])),
+                            container_port.clone(),
+                        )
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    ports.sort();
+    ports.dedup();
+
+    let config = field(value, &["Config"]);
+    ContainerView {
+        name: string(value, &["Name"]).trim_start_matches('/').to_string(),
+        image: config
+            .map(|c| string(c, &["Image"]))
+            .filter(|i| !i.is_empty())
+            .unwrap_or_else(|| string(value, &["ImageName", "Image"])),
+        network_mode: field(value, &["HostConfig"])
+            .map(|h| string(h, &["NetworkMode"]))
+            .unwrap_or_default(),
+        networks,
+        ports,
+    }
+}
+
+async fn inspect_all(
+    runtime: &str,
+    list: &[&str],
+    inspect: &[&str],
+) -> Result<Vec<serde_json::Value>> {
+    let output = AsyncCommand::new(runtime)
+        .args(list)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            runtime,
+            list.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let output = AsyncCommand::new(runtime)
+        .args(inspect)
+        .args(&ids)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", runtime))?;
+    // Something can go away between the two commands; inspect still prints
+    // what it found
+    if !output.status.success() {
+        debug!(
+            "{} {} failed: {}",
+            runtime,
+            inspect.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(serde_json::from_slice(&output.stdout).unwrap_or_default())
+}
+
+/// Bolt nodes on the LAN, when `[p2p] enabled`; empty otherwise
+pub async fn discover_peers(config: &BoltConfig) -> Vec<LanPeer> {
+    #[cfg(feature = "quic-networking")]
+    if config.p2p.enabled {
+        match crate::runtime::storage::p2p::peers().await {
+            Ok(peers) => {
+                return peers
+                    .into_iter()
+                    .map(|p| LanPeer {
+                        node: p.node,
+                        address: p.address,
+                        blobs: p.blobs,
+                    })
+                    .collect();
+            }
+            Err(e) => warn!("⚠️  Peer discovery failed: {}", e),
+        }
+    }
+    #[cfg(not(feature = "quic-networking"))]
+    let _ = config;
+    Vec::new()
+}
+
+/// The current graph of this host's networking. Containers outside the
+/// workspace are left out.
+pub async fn collect(runtime: &str, config: &BoltConfig, peers: &[LanPeer]) -> Result<Topology> {
+    let networks = inspect_all(runtime, &["network", "ls", "-q"], &["network", "inspect"])
+        .await?
+        .iter()
+        .map(parse_network)
+        .collect::<Vec<_>>();
+    let containers = inspect_all(runtime, &["ps", "-q"], &["inspect"])
+        .await?
+        .iter()
+        .map(parse_container)
+        .filter(|c| {
+            config
+                .workspace
+                .as_deref()
+                .is_none_or(|ws| workspace::is_scoped(ws, &c.name))
+        })
+        .collect::<Vec<_>>();
+
+    let mut graph = Builder::default();
+    let host = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| HOST.to_string());
+    graph
+        .node(HOST, NodeKind::Host, &host)
+        .attributes
+        .insert("runtime".to_string(), runtime.to_string());
+
+    for network in &networks {
+        // Pseudo networks are drawn as the container's mode instead
+        if network.name.is_empty() || matches!(network.driver.as_str(), "null" | "host") {
+            continue;
+        }
+        let id = network_id(&network.name);
+        let node = graph.node(&id, NodeKind::Network, &network.name);
+        node.attributes
+            .insert("driver".to_string(), network.driver.clone());
+        if !network.subnets.is_empty() {
+            node.attributes
+                .insert("subnet".to_string(), network.subnets.join(", "));
+        }
+        if network.internal {
+            node.attributes
+                .insert("internal".to_string(), "true".to_string());
+        }
+        if network.quic {
+            node.attributes
+                .insert("fabric".to_string(), "quic".to_string());
+        }
+        let label = if network.quic {
+            "quic fabric"
+        } else {
+            network.driver.as_str()
+        };
+        graph.edge(HOST, &id, EdgeKind::Network, label.to_string());
+    }
+
+    for container in &containers {
+        let id = container_id(&container.name);
+        let node = graph.node(&id, NodeKind::Container, &container.name);
+        node.attributes
+            .insert("image".to_string(), container.image.clone());
+
+        let mut attached = false;
+        for (network, address, aliases) in &container.networks {
+            let net = network_id(network);
+            if !graph.nodes.contains_key(&net) {
+                continue;
+            }
+            let mut attributes = BTreeMap::new();
+            if !aliases.is_empty() {
+                attributes.insert("aliases".to_string(), aliases.join(", "));
+            }
+            let label = address.clone().unwrap_or_default();
+            graph.edge_with(&net, &id, EdgeKind::Attached, label, attributes);
+            attached = true;
+        }
+        // Host networking, and rootless slirp4netns or pasta, share the
+        // host's addresses
+        if !attached && !container.network_mode.is_empty() && container.network_mode != "none" {
+            graph.edge(
+                HOST,
+                &id,
+                EdgeKind::Attached,
+                container.network_mode.clone(),
+            );
+        }
+
+        for (host_port, container_port) in &container.ports {
+            graph.edge(
+                HOST,
+                &id,
+                EdgeKind::Publish,
+                format!("{} → {}", host_port, container_port),
+            );
+        }
+
+        if let Some(state) = super::egress::state(&config.data_dir, &container.name) {
+            let policy = &state.policy;
+            let mut attributes = BTreeMap::new();
+            if !policy.networks.is_empty() {
+                attributes.insert("networks".to_string(), policy.networks.join(", "));
+            }
+            if !policy.domains.is_empty() {
+                attributes.insert("domains".to_string(), policy.domains.join(", "));
+            }
+            attributes.insert("dns".to_string(), policy.dns.to_string());
+            graph.node(INTERNET, NodeKind::Internet, "internet");
+            graph.edge_with(
+                &id,
+                INTERNET,
+                EdgeKind::Egress,
+                format!(
+                    "egress {}: {} networks, {} domains",
+                    policy.mode.as_str(),
+                    policy.networks.len(),
+                    policy.domains.len()
+                ),
+                attributes,
+            );
+        }
+    }
+
+    for state in super::nat::list(&config.data_dir) {
+        let id = container_id(&state.container);
+        if !graph.nodes.contains_key(&id) {
+            continue;
+        }
+        for mapping in &state.mappings {
+            graph.node(INTERNET, NodeKind::Internet, "internet");
+            let label = format!("{} → {}", mapping.public, mapping.port);
+            match mapping.via {
+                MappingVia::Relay => {
+                    let relay = config
+                        .nat
+                        .relay
+                        .clone()
+                        .unwrap_or_else(|| mapping.public.ip().to_string());
+                    let relay_id = format!("relay:{}", relay);
+                    graph.node(&relay_id, NodeKind::Relay, &relay);
+                    graph.edge(INTERNET, &relay_id, EdgeKind::Mapping, label);
+                    graph.edge(&relay_id, HOST, EdgeKind::Tunnel, "quic".to_string());
+                }
+                MappingVia::Natpmp | MappingVia::Upnp => {
+                    graph.edge(
+                        INTERNET,
+                        HOST,
+                        EdgeKind::Mapping,
+                        format!("{} ({})", label, mapping.via.as_str()),
+                    );
+                }
+            }
+        }
+    }
+
+    for peer in peers {
+        let id = format!("peer:{}", peer.node);
+        let node = graph.node(&id, NodeKind::Peer, &peer.node);
+        node.attributes
+            .insert("address".to_string(), peer.address.to_string());
+        node.attributes
+            .insert("blobs".to_string(), peer.blobs.to_string());
+        graph.edge(HOST, &id, EdgeKind::Peer, "p2p layers".to_string());
+    }
+
+    Ok(graph.finish())
+}
+
+/// What changed from `old` to `new`
+pub fn diff(old: &Topology, new: &Topology) -> TopologyChange {
+    let old_nodes: BTreeMap<&str, &Node> = old.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let new_ids: BTreeSet<&str> = new.nodes.iter().map(|n| n.id.as_str()).collect();
+    let old_edges: BTreeSet<&Edge> = old.edges.iter().collect();
+    let new_edges: BTreeSet<&Edge> = new.edges.iter().collect();
+    TopologyChange {
+        nodes_added: new
+            .nodes
+            .iter()
+            .filter(|n| old_nodes.get(n.id.as_str()) != Some(n))
+            .cloned()
+            .collect(),
+        nodes_removed: old_nodes
+            .keys()
+            .filter(|id| !new_ids.contains(*id))
+            .map(|id| id.to_string())
+            .collect(),
+        edges_added: new_edges
+            .difference(&old_edges)
+            .map(|e| (*e).clone())
+            .collect(),
+        edges_removed: old_edges
+            .difference(&new_edges)
+            .map(|e| (*e).clone())
+            .collect(),
+    }
+}
+
+pub fn render(topology: &Topology, format: Format) -> Result<String> {
+    Ok(match format {
+        Format::Dot => to_dot(topology),
+        Format::Json => serde_json::to_string_pretty(topology)? + "\n",
+        Format::Mermaid => to_mermaid(topology),
+    })
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Graphviz, e.g. `bolt network topo | dot -Tsvg > net.svg`
+pub fn to_dot(topology: &Topology) -> String {
+    let mut out = String::from(
+        "digraph bolt {\n    rankdir=LR;\n    node [fontname=\"sans-serif\"];\n    edge [fontname=\"sans-serif\", fontsize=10];\n",
+    );
+    for node in &topology.nodes {
+        let shape = match node.kind {
+            NodeKind::Host => "house",
+            NodeKind::Network => "hexagon",
+            NodeKind::Container => "box",
+            NodeKind::Relay => "diamond",
+            NodeKind::Peer => "component",
+            NodeKind::Internet => "doublecircle",
+        };
+        let mut label = node.label.clone();
+        for (key, value) in &node.attributes {
+            label.push_str(&format!("\n{}: {}", key, value));
+        }
+        out.push_str(&format!(
+            "    \"{}\" [shape={}, label=\"{}\"];\n",
+            dot_escape(&node.id),
+            shape,
+            dot_escape(&label)
+        ));
+    }
+    for edge in &topology.edges {
+        let style = match edge.kind {
+            EdgeKind::Tunnel | EdgeKind::Peer => ", style=dashed",
+            EdgeKind::Egress => ", style=dotted, color=red",
+            EdgeKind::Mapping => ", style=bold",
+            EdgeKind::Network | EdgeKind::Attached | EdgeKind::Publish => "",
+        };
+        out.push_str(&format!(
+            "    \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+            dot_escape(&edge.from),
+            dot_escape(&edge.to),
+            dot_escape(&edge.label),
+            style
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+/// A Mermaid flowchart, which GitHub and most wikis render inline
+pub fn to_mermaid(topology: &Topology) -> String {
+    // Node ids like "ctr:web" aren't valid Mermaid ids
+    let ids: BTreeMap<&str, String> = topology
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), format!("n{}", i)))
+        .collect();
+    let mut out = String::from("flowchart LR\n");
+    for node in &topology.nodes {
+        let mut label = mermaid_escape(&node.label);
+        if let Some(detail) = node
+            .attributes
+            .get("subnet")
+            .or_else(|| node.attributes.get("image"))
+            .or_else(|| node.attributes.get("address"))
+        {
+            label.push_str(&format!("<br/><small>{}</small>", mermaid_escape(detail)));
+        }
+        let (open, close) = match node.kind {
+            NodeKind::Host => ("[[", "]]"),
+            NodeKind::Network => ("{{", "}}"),
+            NodeKind::Container => ("[", "]"),
+            NodeKind::Relay => ("{", "}"),
+            NodeKind::Peer => ("[/", "/]"),
+            NodeKind::Internet => ("((", "))"),
+        };
+        out.push_str(&format!(
+            "    {}{}\"{}\"{}\n",
+            ids[node.id.as_str()],
+            open,
+            label,
+            close
+        ));
+    }
+    for edge in &topology.edges {
+        let (Some(from), Some(to)) = (ids.get(edge.from.as_str()), ids.get(edge.to.as_str()))
+        else {
+            continue;
+        };
+        let arrow = match edge.kind {
+            EdgeKind::Tunnel | EdgeKind::Peer | EdgeKind::Egress => "-.->",
+            EdgeKind::Mapping => "==>",
+            EdgeKind::Network | EdgeKind::Attached | EdgeKind::Publish => "-->",
+        };
+        if edge.label.is_empty() {
+            out.push_str(&format!("    {} {} {}\n", from, arrow, to));
+        } else {
+            out.push_str(&format!(
+                "    {} {}|\"{}\"| {}\n",
+                from,
+                arrow,
+                mermaid_escape(&edge.label),
+                to
+            ));
+        }
+    }
+    out
+}
+
+/// Serves the graph on `ws://<listen>/topology` until interrupted
+pub async fn serve_live(runtime: &str, config: &BoltConfig, listen: SocketAddr) -> Result<()> {
+    #[cfg(feature = "quic-networking")]
+    let mut peers_at = tokio::time::Instant::now();
+    let mut peers = discover_peers(config).await;
+    let mut current = collect(runtime, config, &peers).await?;
+
+    let snapshot = serde_json::to_string(&LiveMessage::Snapshot(current.clone()))?;
+    let (snapshot_tx, snapshot_rx) = watch::channel(snapshot);
+    let (change_tx, _) = broadcast::channel::<String>(64);
+
+    let changes = change_tx.clone();
+    let route = warp::path("topology")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let snapshot = snapshot_rx.clone();
+            let changes = changes.subscribe();
+            ws.on_upgrade(move |socket| stream_to(socket, snapshot, changes))
+        });
+    let (bound, server) = warp::serve(route)
+        .try_bind_ephemeral(listen)
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    info!("🕸️  Streaming topology changes on ws://{}/topology", bound);
+    let server = tokio::spawn(server);
+
+    let mut ticker = tokio::time::interval(LIVE_INTERVAL);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        #[cfg(feature = "quic-networking")]
+        if peers_at.elapsed() >= PEER_INTERVAL {
+            peers = discover_peers(config).await;
+            peers_at = tokio::time::Instant::now();
+        }
+        let next = match collect(runtime, config, &peers).await {
+            Ok(next) => next,
+            Err(e) => {
+                warn!("⚠️  Failed to collect the topology: {}", e);
+                continue;
+            }
+        };
+        let change = diff(&current, &next);
+        if change.is_empty() {
+            continue;
+        }
+        debug!(
+            "Topology changed: +{}/-{} nodes, +{}/-{} edges",
+            change.nodes_added.len(),
+            change.nodes_removed.len(),
+            change.edges_added.len(),
+            change.edges_removed.len()
+        );
+        let message = LiveMessage::Change {
+            generated_at: next.generated_at,
+            change,
+        };
+        snapshot_tx.send_replace(serde_json::to_string(&LiveMessage::Snapshot(next.clone()))?);
+        // Nobody listening is fine
+        let _ = change_tx.send(serde_json::to_string(&message)?);
+        current = next;
+    }
+
+    server.abort();
+    #[cfg(not(feature = "quic-networking"))]
+    let _ = &mut peers;
+    Ok(())
+}
+
+async fn stream_to(
+    socket: WebSocket,
+    snapshot: watch::Receiver<String>,
+    mut changes: broadcast::Receiver<String>,
+) {
+    let (mut tx, mut rx) = socket.split();
+    let first = snapshot.borrow().clone();
+    if tx.send(Message::text(first)).await.is_err() {
+        return;
+    }
+    loop {
+        let message = tokio::select! {
+            change = changes.recv() => match change {
+                Ok(change) => change,
+                // A client too slow for the changes starts over from the
+                // current graph
+                Err(broadcast::error::RecvError::Lagged(_)) => snapshot.borrow().clone(),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            incoming = rx.next() => match incoming {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            },
+        };
+        if tx.send(Message::text(message)).await.is_err() {
+            break;
+        }
+    }
+    let _ = tx.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, kind: NodeKind, label: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            kind,
+            label: label.to_string(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    fn edge(from: &str, to: &str, kind: EdgeKind, label: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind,
+            label: label.to_string(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn inspect_renders_and_diffs() {
+        let network = parse_network(&serde_json::json!({
+            "name": "game-net",
+            "driver": "bridge",
+            "subnets": [{"subnet": "10.89.0.0/24", "gateway": "10.89.0.1"}],
+            "labels": {"bolt.quic": "enabled"},
+            "internal": false
+        }));
+        assert_eq!(network.name, "game-net");
+        assert_eq!(network.subnets, vec!["10.89.0.0/24"]);
+        assert!(network.quic);
+
+        let container = parse_container(&serde_json::json!({
+            "Name": "/minecraft",
+            "Config": {"Image": "itzg/minecraft-server"},
+            "HostConfig": {"NetworkMode": "game-net"},
+            "NetworkSettings": {
+                "Networks": {"game-net": {"IPAddress": "10.89.0.5", "Aliases": ["mc"]}},
+                "Ports": {
+                    "25565/tcp": [{"HostIp": "", "HostPort": "25565"}],
+                    "25575/tcp": null
+                }
+            }
+        }));
+        assert_eq!(container.name, "minecraft");
+        assert_eq!(
+            container.networks,
+            vec![(
+                "game-net".to_string(),
+                Some("10.89.0.5".to_string()),
+                vec!["mc".to_string()]
+            )]
+        );
+        assert_eq!(
+            container.ports,
+            vec![("0.0.0.0:25565".to_string(), "25565/tcp".to_string())]
+        );
+
+        let old = Topology {
+            generated_at: Utc::now(),
+            nodes: vec![
+                node(HOST, NodeKind::Host, "box \"a\""),
+                node("net:game-net", NodeKind::Network, "game-net"),
+            ],
+            edges: vec![edge(HOST, "net:game-net", EdgeKind::Network, "quic fabric")],
+        };
+        let dot = to_dot(&old);
+        assert!(dot.starts_with("digraph bolt {"));
+        assert!(dot.contains("\"host\" [shape=house, label=\"box \\\"a\\\"\"];"));
+        assert!(dot.contains("\"host\" -> \"net:game-net\" [label=\"quic fabric\"];"));
+        let mermaid = to_mermaid(&old);
+        assert!(mermaid.contains("n0[[\"box #quot;a#quot;\"]]"));
+        assert!(mermaid.contains("n0 -->|\"quic fabric\"| n1"));
+
+        let mut new = old.clone();
+        new.nodes
+            .push(node("ctr:minecraft", NodeKind::Container, "minecraft"));
+        new.edges.push(edge(
+            "net:game-net",
+            "ctr:minecraft",
+            EdgeKind::Attached,
+            "10.89.0.5",
+        ));
+        new.edges.remove(0);
+        let change = diff(&old, &new);
+        assert_eq!(change.nodes_added.len(), 1);
+        assert_eq!(change.nodes_added[0].id, "ctr:minecraft");
+        assert!(change.nodes_removed.is_empty());
+        assert_eq!(change.edges_added.len(), 1);
+        assert_eq!(change.edges_removed[0].kind, EdgeKind::Network);
+        assert!(diff(&new, &new).is_empty());
+
+        let message = serde_json::to_value(LiveMessage::Change {
+            generated_at: new.generated_at,
+            change,
+        })
+        .unwrap();
+        assert_eq!(message["type"], "change");
+        assert_eq!(message["nodes_removed"], serde_json::json!([]));
+    }
+}
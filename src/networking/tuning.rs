@@ -0,0 +1,379 @@
+// Host tuning for QUIC
+//
+// QUIC runs over UDP, so its throughput depends on host settings few users
+// know about: the kernel's default socket buffer limits are far below what
+// a busy QUIC connection needs, UDP segmentation offload is often off on
+// the uplinks, and latency-sensitive games profit from busy polling.
+// `bolt doctor --network` compares the host with these recommendations and,
+// with `--apply`, changes what differs and reports before and after.
+//
+// Busy polling is only recommended when a service in the Boltfile uses the
+// competitive gaming profile, since it trades CPU time for latency. Applied
+// sysctls are also written to `SYSCTL_FILE` so they survive a reboot;
+// offload settings last until the interface is reset. Needs root and
+// `ethtool`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::process::Command as AsyncCommand;
+use tracing::info;
+
+use crate::config::BoltFile;
+use crate::runtime::doctor::DoctorCheck;
+
+/// Enough for the send and receive windows of a fast QUIC connection
+const UDP_BUFFER_MAX: u64 = 7_500_000;
+/// Microseconds a socket read busy-polls the device before sleeping
+const BUSY_POLL_USECS: u64 = 50;
+const SYSCTL_FILE: &str = "/etc/sysctl.d/60-bolt-quic.conf";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum Setting {
+    /// A numeric sysctl that should be at least the recommended value
+    Sysctl { name: String },
+    /// An ethtool feature of an interface
+    Offload { interface: String, feature: String },
+}
+
+impl std::fmt::Display for Setting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Setting::Sysctl { name } => write!(f, "{}", name),
+            Setting::Offload { interface, feature } => write!(f, "{} {}", interface, feature),
+        }
+    }
+}
+
+/// One setting, its recommended value and what the host has
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuningItem {
+    pub setting: Setting,
+    pub reason: String,
+    pub recommended: String,
+    pub before: Option<String>,
+    /// Value after `apply`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// The device doesn't let the setting be changed
+    #[serde(default)]
+    pub fixed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TuningItem {
+    fn new(setting: Setting, recommended: impl ToString, reason: &str) -> Self {
+        Self {
+            setting,
+            reason: reason.to_string(),
+            recommended: recommended.to_string(),
+            before: None,
+            after: None,
+            fixed: false,
+            error: None,
+        }
+    }
+
+    fn satisfied_by(&self, value: Option<&str>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        match self.setting {
+            Setting::Sysctl { .. } => {
+                match (value.parse::<u64>(), self.recommended.parse::<u64>()) {
+                    (Ok(value), Ok(recommended)) => value >= recommended,
+                    _ => value == self.recommended,
+                }
+            }
+            Setting::Offload { .. } => value == self.recommended,
+        }
+    }
+
+    /// Whether the host already matches the recommendation
+    pub fn is_tuned(&self) -> bool {
+        self.satisfied_by(self.after.as_deref().or(self.before.as_deref()))
+    }
+
+    /// Whether `apply` would change the setting
+    pub fn needs_change(&self) -> bool {
+        !self.is_tuned() && !self.fixed && self.before.is_some()
+    }
+}
+
+/// Settings to check, without the host's values
+fn recommendations(competitive: bool, interfaces: &[String]) -> Vec<TuningItem> {
+    let sysctl = |name: &str| Setting::Sysctl {
+        name: name.to_string(),
+    };
+    let mut items = vec![
+        TuningItem::new(
+            sysctl("net.core.rmem_max"),
+            UDP_BUFFER_MAX,
+            "QUIC receive buffers are capped at this size",
+        ),
+        TuningItem::new(
+            sysctl("net.core.wmem_max"),
+            UDP_BUFFER_MAX,
+            "QUIC send buffers are capped at this size",
+        ),
+    ];
+    if competitive {
+        for name in ["net.core.busy_poll", "net.core.busy_read"] {
+            items.push(TuningItem::new(
+                sysctl(name),
+                BUSY_POLL_USECS,
+                "busy polling cuts wake-up latency for competitive services",
+            ));
+        }
+    }
+    for interface in interfaces {
+        let offload = |feature: &str| Setting::Offload {
+            interface: interface.clone(),
+            feature: feature.to_string(),
+        };
+        items.push(TuningItem::new(
+            offload("tx-udp-segmentation"),
+            "on",
+            "UDP GSO sends a batch of QUIC packets in one call",
+        ));
+        items.push(TuningItem::new(
+            offload("rx-udp-gro-forwarding"),
+            "on",
+            "UDP GRO batches packets forwarded to container bridges",
+        ));
+    }
+    items
+}
+
+/// Compare the host with the recommended settings
+pub async fn inspect(boltfile: Option<&BoltFile>) -> Vec<TuningItem> {
+    let competitive = boltfile.is_some_and(|b| {
+        b.services.values().any(|s| {
+            s.gaming
+                .as_ref()
+                .and_then(|g| g.performance_profile.as_deref())
+                .is_some_and(|p| p.eq_ignore_ascii_case("competitive"))
+        })
+    });
+
+    let mut interfaces: Vec<String> = boltfile
+        .and_then(|b| b.networks.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|(_, n)| n.hardware.as_ref()?.uplink.as_ref())
+        .map(|uplink| uplink.name.clone())
+        .collect();
+    if let Some(interface) = default_route_interface().filter(|i| !interfaces.contains(i)) {
+        interfaces.insert(0, interface);
+    }
+
+    let mut features = HashMap::new();
+    for interface in &interfaces {
+        features.insert(interface.clone(), read_features(interface).await);
+    }
+    let mut items = recommendations(competitive, &interfaces);
+    for item in &mut items {
+        match item.setting {
+            Setting::Sysctl { ref name } => item.before = read_sysctl(name),
+            Setting::Offload {
+                ref interface,
+                ref feature,
+            } => {
+                if let Some((value, fixed)) = features[interface].get(feature) {
+                    item.before = Some(value.clone());
+                    item.fixed = *fixed;
+                }
+            }
+        }
+    }
+    items
+}
+
+/// Change every setting that differs from the recommendation, recording
+/// each one's value afterwards
+pub async fn apply(items: &mut [TuningItem]) -> Result<()> {
+    let mut persisted = Vec::new();
+    for item in items.iter_mut().filter(|i| i.needs_change()) {
+        let result = match item.setting {
+            Setting::Sysctl { ref name } => {
+                let result = std::fs::write(sysctl_path(name), &item.recommended)
+                    .with_context(|| format!("Failed to set {}", name));
+                item.after = read_sysctl(name);
+                if result.is_ok() {
+                    persisted.push(format!("{} = {}", name, item.recommended));
+                }
+                result
+            }
+            Setting::Offload {
+                ref interface,
+                ref feature,
+            } => {
+                let result = ethtool(&["-K", interface, feature, &item.recommended]).await;
+                item.after = read_features(interface)
+                    .await
+                    .get(feature)
+                    .map(|(value, _)| value.clone());
+                result.map(|_| ())
+            }
+        };
+        match result {
+            Ok(()) => info!(
+                "🔧 {}: {} → {}",
+                item.setting,
+                item.before.as_deref().unwrap_or("?"),
+                item.after.as_deref().unwrap_or("?")
+            ),
+            Err(e) => item.error = Some(e.to_string()),
+        }
+    }
+
+    if !persisted.is_empty() {
+        let contents = format!(
+            "# Written by `bolt doctor --network --apply`\n{}\n",
+            persisted.join("\n")
+        );
+        std::fs::write(SYSCTL_FILE, contents)
+            .with_context(|| format!("Failed to write {}", SYSCTL_FILE))?;
+    }
+    Ok(())
+}
+
+/// Doctor checks for tuning items, showing before and after once applied
+pub fn checks(items: &[TuningItem]) -> Vec<DoctorCheck> {
+    const AREA: &str = "network/tuning";
+    items
+        .iter()
+        .map(|item| {
+            let name = item.setting.to_string();
+            let before = item.before.as_deref().unwrap_or("-");
+            if let Some(ref error) = item.error {
+                return DoctorCheck::fail(AREA, name, error.clone(), "run as root");
+            }
+            if let Some(ref after) = item.after {
+                let detail = format!("{} → {}", before, after);
+                return if item.is_tuned() {
+                    DoctorCheck::pass(AREA, name, detail)
+                } else {
+                    DoctorCheck::warn(AREA, name, detail, "the kernel or driver kept its value")
+                };
+            }
+            if item.is_tuned() {
+                DoctorCheck::pass(AREA, name, before)
+            } else if item.before.is_none() {
+                DoctorCheck::warn(AREA, name, "not available", item.reason.clone())
+            } else if item.fixed {
+                DoctorCheck::warn(
+                    AREA,
+                    name,
+                    format!("{} (fixed by the driver)", before),
+                    item.reason.clone(),
+                )
+            } else {
+                DoctorCheck::warn(
+                    AREA,
+                    name,
+                    format!("{}, recommended {}", before, item.recommended),
+                    format!("{}; run `bolt doctor --network --apply`", item.reason),
+                )
+            }
+        })
+        .collect()
+}
+
+fn sysctl_path(name: &str) -> String {
+    format!("/proc/sys/{}", name.replace('.', "/"))
+}
+
+fn read_sysctl(name: &str) -> Option<String> {
+    std::fs::read_to_string(sysctl_path(name))
+        .ok()
+        .map(|v| v.trim().to_string())
+}
+
+/// Interface of the IPv4 default route
+pub(crate) fn default_route_interface() -> Option<String> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.get(1) == Some(&"00000000")).then(|| fields[0].to_string())
+    })
+}
+
+async fn ethtool(args: &[&str]) -> Result<String> {
+    let output = AsyncCommand::new("ethtool")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to run ethtool")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ethtool {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn read_features(interface: &str) -> HashMap<String, (String, bool)> {
+    ethtool(&["-k", interface])
+        .await
+        .map(|output| parse_features(&output))
+        .unwrap_or_default()
+}
+
+/// `ethtool -k` output as feature -> (value, fixed)
+fn parse_features(output: &str) -> HashMap<String, (String, bool)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (feature, state) = line.trim().split_once(": ")?;
+            let mut words = state.split_whitespace();
+            let value = words.next()?.to_string();
+            let fixed = words.any(|w| w == "[fixed]");
+            Some((feature.to_string(), (value, fixed)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::doctor::CheckStatus;
+
+    #[test]
+    fn compares_host_settings_with_recommendations() {
+        let features = parse_features(
+            "Features for eth0:\nrx-checksumming: on\ntx-udp-segmentation: off [fixed]\nrx-udp-gro-forwarding: off\n",
+        );
+        assert_eq!(features["tx-udp-segmentation"], ("off".to_string(), true));
+        assert_eq!(
+            features["rx-udp-gro-forwarding"],
+            ("off".to_string(), false)
+        );
+
+        let mut items = recommendations(false, &["eth0".to_string()]);
+        assert!(!items.iter().any(|i| i.setting.to_string().contains("busy")));
+        assert_eq!(recommendations(true, &[]).len(), 4);
+
+        items[0].before = Some("212992".to_string());
+        items[1].before = Some("16777216".to_string());
+        items[2].before = Some("off".to_string());
+        items[2].fixed = true;
+        items[3].before = Some("off".to_string());
+        assert!(items[0].needs_change());
+        assert!(items[1].is_tuned() && !items[1].needs_change());
+        assert!(!items[2].needs_change());
+        assert!(items[3].needs_change());
+
+        items[0].after = Some("7500000".to_string());
+        let checks = checks(&items);
+        assert_eq!(checks[0].status, CheckStatus::Pass);
+        assert_eq!(checks[0].detail, "212992 → 7500000");
+        assert_eq!(checks[2].name, "eth0 tx-udp-segmentation");
+        assert!(checks[2].detail.contains("fixed"));
+        assert_eq!(checks[3].status, CheckStatus::Warn);
+        assert!(checks[3].hint.as_deref().unwrap().contains("--apply"));
+    }
+}
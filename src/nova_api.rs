@@ -114,16 +114,17 @@ impl BoltNovaRuntime {
             vec![]
         };
 
-        // Run the container
-        runtime::run_container(
-            &config.capsule,
-            Some(name),
-            &[], // ports - will be configured via network
-            &env_vars,
-            &volumes,
-            true, // detach for background running
-        )
-        .await?;
+        // Run the container; through BoltRuntime so the trust policy applies
+        crate::BoltRuntime::new()?
+            .run_container(
+                &config.capsule,
+                Some(name),
+                &[], // ports - will be configured via network
+                &env_vars,
+                &volumes,
+                true, // detach for background running
+            )
+            .await?;
 
         // Get container info
         let containers = runtime::list_containers_info(false).await?;
@@ -163,6 +164,11 @@ impl BoltNovaRuntime {
         Ok(())
     }
 
+    /// What this Bolt build and host support, for hiding unsupported actions in the UI
+    pub async fn capabilities(&self) -> Result<runtime::capabilities::Capabilities> {
+        Ok(runtime::capabilities::Capabilities::detect().await)
+    }
+
     /// List all capsules
     pub async fn list_capsules(&self) -> Result<Vec<CapsuleHandle>> {
         let containers = runtime::list_containers_info(true).await?;
@@ -218,11 +224,13 @@ impl BoltNovaRuntime {
         })
     }
 
-    /// Get logs for a capsule
-    pub async fn get_capsule_logs(&self, _name: &str, _lines: usize) -> Result<Vec<String>> {
-        // This would integrate with log collection
-        // For now, return empty
-        Ok(Vec::new())
+    /// The last `lines` lines of a capsule's console output, for Nova's
+    /// console view
+    pub async fn get_capsule_logs(&self, name: &str, lines: usize) -> Result<Vec<String>> {
+        let root = self.inner.read().await.capsule_manager.root_path.clone();
+        let runtime = runtime::detect_container_runtime().await?;
+        crate::capsules::console::capture(&runtime, &root, name).await?;
+        crate::capsules::console::tail(&root, name, lines)
     }
 
     /// Restart a capsule
@@ -287,6 +295,8 @@ impl BoltNovaRuntime {
             memory_balloon: false,
             cpu_hotplug: false,
             numa_topology: None,
+            core_placement: Default::default(),
+            energy_performance_preference: None,
         };
 
         let networking = crate::capsules::CapsuleNetworking {
@@ -332,6 +342,7 @@ impl BoltNovaRuntime {
                 interval_minutes: 60,
                 max_snapshots: 10,
                 compress_snapshots: true,
+                memory_compression: Default::default(),
             },
         };
 
@@ -0,0 +1,594 @@
+// Hybrid CPU (P-core/E-core) topology, placement and energy preference
+//
+// Intel hybrid parts expose their core types as separate perf PMUs
+// (`cpu_core` and `cpu_atom`); ARM big.LITTLE systems report a per-CPU
+// `cpu_capacity` instead. Everything else is treated as homogeneous.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+const CPU_SYSFS: &str = "/sys/devices/system/cpu";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoreType {
+    Performance,
+    Efficiency,
+}
+
+/// Where a workload's threads may run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorePlacement {
+    #[default]
+    Any,
+    /// Latency-sensitive work such as games
+    PCores,
+    /// Background services that shouldn't compete with P-core work
+    ECores,
+}
+
+/// Values accepted by `cpufreq/energy_performance_preference`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnergyPerformancePreference {
+    Default,
+    Performance,
+    BalancePerformance,
+    BalancePower,
+    Power,
+}
+
+impl EnergyPerformancePreference {
+    pub fn to_kernel_name(&self) -> &'static str {
+        match self {
+            EnergyPerformancePreference::Default => "default",
+            EnergyPerformancePreference::Performance => "performance",
+            EnergyPerformancePreference::BalancePerformance => "balance_performance",
+            EnergyPerformancePreference::BalancePower => "balance_power",
+            EnergyPerformancePreference::Power => "power",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuTopology {
+    pub p_cores: Vec<u32>,
+    pub e_cores: Vec<u32>,
+    /// True when the CPU mixes performance and efficiency cores
+    pub hybrid: bool,
+    /// Whether the scheduler's ITMT (Thread Director) core ranking is enabled
+    pub itmt_enabled: Option<bool>,
+}
+
+impl CpuTopology {
+    pub fn detect() -> Result<Self> {
+        let online = read_cpu_list(&Path::new(CPU_SYSFS).join("online"))
+            .context("Failed to read online CPUs")?;
+        let itmt_enabled = std::fs::read_to_string("/proc/sys/kernel/sched_itmt_enabled")
+            .ok()
+            .map(|v| v.trim() == "1");
+
+        // Intel hybrid: one PMU per core type
+        if let (Ok(p_cores), Ok(e_cores)) = (
+            read_cpu_list(Path::new("/sys/devices/cpu_core/cpus")),
+            read_cpu_list(Path::new("/sys/devices/cpu_atom/cpus")),
+        ) {
+            let online: BTreeSet<_> = online.iter().copied().collect();
+            return Ok(Self::new(
+                p_cores.into_iter().filter(|c| online.contains(c)).collect(),
+                e_cores.into_iter().filter(|c| online.contains(c)).collect(),
+                itmt_enabled,
+            ));
+        }
+
+        // Asymmetric capacity (ARM big.LITTLE and similar)
+        let capacities: Vec<(u32, u64)> = online
+            .iter()
+            .filter_map(|cpu| {
+                std::fs::read_to_string(format!("{}/cpu{}/cpu_capacity", CPU_SYSFS, cpu))
+                    .ok()
+                    .and_then(|c| c.trim().parse().ok())
+                    .map(|c| (*cpu, c))
+            })
+            .collect();
+        if capacities.len() == online.len()
+            && let Some(max) = capacities.iter().map(|(_, c)| *c).max()
+        {
+            let (p, e): (Vec<_>, Vec<_>) = capacities.iter().partition(|(_, c)| *c == max);
+            return Ok(Self::new(
+                p.into_iter().map(|(cpu, _)| cpu).collect(),
+                e.into_iter().map(|(cpu, _)| cpu).collect(),
+                itmt_enabled,
+            ));
+        }
+
+        Ok(Self::new(online, Vec::new(), itmt_enabled))
+    }
+
+    fn new(p_cores: Vec<u32>, e_cores: Vec<u32>, itmt_enabled: Option<bool>) -> Self {
+        let hybrid = !p_cores.is_empty() && !e_cores.is_empty();
+        Self {
+            p_cores,
+            e_cores,
+            hybrid,
+            itmt_enabled,
+        }
+    }
+
+    pub fn core_type(&self, cpu: u32) -> Option<CoreType> {
+        if self.p_cores.contains(&cpu) {
+            Some(CoreType::Performance)
+        } else if self.e_cores.contains(&cpu) {
+            Some(CoreType::Efficiency)
+        } else {
+            None
+        }
+    }
+
+    /// CPUs a workload with this placement may use. On non-hybrid CPUs every
+    /// placement resolves to all online CPUs.
+    pub fn cpus_for(&self, placement: CorePlacement) -> Vec<u32> {
+        let mut cpus = match placement {
+            CorePlacement::PCores if self.hybrid => self.p_cores.clone(),
+            CorePlacement::ECores if self.hybrid => self.e_cores.clone(),
+            _ => self.p_cores.iter().chain(&self.e_cores).copied().collect(),
+        };
+        cpus.sort_unstable();
+        cpus
+    }
+}
+
+/// Parse a kernel CPU list such as `0-3,8,10-11`
+pub fn parse_cpu_list(list: &str) -> Result<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: u32 = start
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid CPU list: {}", list))?;
+                let end: u32 = end
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid CPU list: {}", list))?;
+                if start > end {
+                    return Err(anyhow!("Invalid CPU list: {} ({} is reversed)", list, part));
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(
+                part.parse()
+                    .map_err(|_| anyhow!("Invalid CPU list: {}", list))?,
+            ),
+        }
+    }
+    Ok(cpus)
+}
+
+/// Format CPUs as a kernel CPU list, collapsing runs into ranges
+pub fn format_cpu_list(cpus: &[u32]) -> String {
+    let sorted: BTreeSet<_> = cpus.iter().copied().collect();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for cpu in sorted {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == cpu => *end = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn read_cpu_list(path: &Path) -> Result<Vec<u32>> {
+    parse_cpu_list(&std::fs::read_to_string(path)?)
+}
+
+/// Set the energy/performance preference of the given CPUs. Returns how many
+/// were updated; CPUs without EPP support (or locked by the `performance`
+/// governor) are skipped with a warning.
+pub fn set_energy_performance_preference(
+    cpus: &[u32],
+    preference: EnergyPerformancePreference,
+) -> Result<usize> {
+    write_epp(Path::new(CPU_SYSFS), cpus, preference.to_kernel_name())
+}
+
+fn write_epp(sysfs: &Path, cpus: &[u32], value: &str) -> Result<usize> {
+    let mut updated = 0;
+
+    for cpu in cpus {
+        let cpufreq = sysfs.join(format!("cpu{}", cpu)).join("cpufreq");
+        let path = cpufreq.join("energy_performance_preference");
+        if !path.exists() {
+            debug!("CPU {} has no energy_performance_preference", cpu);
+            continue;
+        }
+
+        if let Ok(available) =
+            std::fs::read_to_string(cpufreq.join("energy_performance_available_preferences"))
+            && value != "default"
+            && !available.split_whitespace().any(|p| p == value)
+        {
+            return Err(anyhow!(
+                "CPU {} does not support EPP '{}' (available: {})",
+                cpu,
+                value,
+                available.trim()
+            ));
+        }
+
+        match std::fs::write(&path, value) {
+            Ok(()) => updated += 1,
+            Err(e) => warn!("Could not set EPP '{}' on CPU {}: {}", value, cpu, e),
+        }
+    }
+
+    Ok(updated)
+}
+
+/// EPP is host-wide, so every change made for a container is recorded with
+/// the CPU's previous value and put back once no container claims it
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EppLedger {
+    cpus: BTreeMap<u32, EppClaim>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EppClaim {
+    original: String,
+    owners: BTreeSet<String>,
+}
+
+impl EppLedger {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if self.cpus.is_empty() {
+            let _ = std::fs::remove_file(path);
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn ledger_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("hybrid").join("epp.json")
+}
+
+/// Set the EPP of `cpus` for `owner`, remembering what each CPU had before
+/// so [`forget`] can restore it
+pub fn claim_energy_performance_preference(
+    data_dir: &Path,
+    owner: &str,
+    cpus: &[u32],
+    preference: EnergyPerformancePreference,
+) -> Result<usize> {
+    claim_epp(
+        Path::new(CPU_SYSFS),
+        &ledger_path(data_dir),
+        owner,
+        cpus,
+        preference,
+    )
+}
+
+fn claim_epp(
+    sysfs: &Path,
+    ledger_path: &Path,
+    owner: &str,
+    cpus: &[u32],
+    preference: EnergyPerformancePreference,
+) -> Result<usize> {
+    let mut ledger = EppLedger::load(ledger_path)?;
+    for cpu in cpus {
+        if let Some(claim) = ledger.cpus.get_mut(cpu) {
+            claim.owners.insert(owner.to_string());
+            continue;
+        }
+        let path = sysfs
+            .join(format!("cpu{}", cpu))
+            .join("cpufreq/energy_performance_preference");
+        let Ok(original) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        ledger.cpus.insert(
+            *cpu,
+            EppClaim {
+                original: original.trim().to_string(),
+                owners: BTreeSet::from([owner.to_string()]),
+            },
+        );
+    }
+    // Recorded before writing, so a partial update is still undone
+    ledger.save(ledger_path)?;
+    write_epp(sysfs, cpus, preference.to_kernel_name())
+}
+
+/// Drop `owner`'s EPP claims and restore the CPUs nothing else claims.
+/// Returns how many were restored.
+fn release_epp(sysfs: &Path, ledger_path: &Path, owner: &str) -> Result<usize> {
+    let mut ledger = EppLedger::load(ledger_path)?;
+    let mut restored = 0;
+    ledger.cpus.retain(|cpu, claim| {
+        if !claim.owners.remove(owner) || !claim.owners.is_empty() {
+            return true;
+        }
+        let path = sysfs
+            .join(format!("cpu{}", cpu))
+            .join("cpufreq/energy_performance_preference");
+        match std::fs::write(path, &claim.original) {
+            Ok(()) => restored += 1,
+            Err(e) => warn!(
+                "Could not restore EPP '{}' on CPU {}: {}",
+                claim.original, cpu, e
+            ),
+        }
+        false
+    });
+    ledger.save(ledger_path)?;
+    Ok(restored)
+}
+
+/// Restore the EPP a stopped or removed container claimed
+pub fn forget(data_dir: &Path, container: &str) {
+    match release_epp(Path::new(CPU_SYSFS), &ledger_path(data_dir), container) {
+        Ok(0) => {}
+        Ok(restored) => info!("⚡ EPP restored on {} CPUs after {}", restored, container),
+        Err(e) => warn!("Could not restore the EPP claimed by {}: {}", container, e),
+    }
+}
+
+/// Pin a started container to a core type and claim the EPP of those cores
+/// for it until [`forget`]
+pub async fn apply_to_container(
+    runtime: &str,
+    data_dir: &Path,
+    container: &str,
+    placement: CorePlacement,
+    preference: Option<EnergyPerformancePreference>,
+) -> Result<()> {
+    if placement == CorePlacement::Any && preference.is_none() {
+        return Ok(());
+    }
+
+    let topology = CpuTopology::detect()?;
+    let cpus = topology.cpus_for(placement);
+
+    if placement != CorePlacement::Any {
+        if topology.hybrid {
+            pin_container(runtime, container, &cpus).await?;
+        } else {
+            warn!(
+                "No hybrid CPU detected; ignoring {:?} placement for {}",
+                placement, container
+            );
+        }
+    }
+
+    if let Some(preference) = preference {
+        let updated = claim_energy_performance_preference(data_dir, container, &cpus, preference)?;
+        info!(
+            "⚡ EPP '{}' set on {} CPUs for {}",
+            preference.to_kernel_name(),
+            updated,
+            container
+        );
+    }
+
+    Ok(())
+}
+
+/// Threads of a process whose allowed CPUs are outside the expected set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AffinityCheck {
+    pub pid: u32,
+    pub expected: Vec<u32>,
+    pub threads: usize,
+    pub violations: Vec<ThreadAffinity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadAffinity {
+    pub tid: u32,
+    pub allowed: Vec<u32>,
+}
+
+impl AffinityCheck {
+    pub fn is_valid(&self) -> bool {
+        self.threads > 0 && self.violations.is_empty()
+    }
+}
+
+/// Compare every thread's `Cpus_allowed_list` with the expected CPUs
+pub fn check_affinity(pid: u32, expected: &[u32]) -> Result<AffinityCheck> {
+    let expected_set: BTreeSet<_> = expected.iter().copied().collect();
+    let task_dir = format!("/proc/{}/task", pid);
+    let mut threads = 0;
+    let mut violations = Vec::new();
+
+    for entry in
+        std::fs::read_dir(&task_dir).with_context(|| format!("No such process: {}", pid))?
+    {
+        let entry = entry?;
+        let Ok(tid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        // Threads can exit while we walk the list
+        let Ok(status) = std::fs::read_to_string(entry.path().join("status")) else {
+            continue;
+        };
+        let Some(allowed) = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Cpus_allowed_list:"))
+        else {
+            continue;
+        };
+
+        threads += 1;
+        let allowed = parse_cpu_list(allowed)?;
+        if allowed.is_empty() || !allowed.iter().all(|c| expected_set.contains(c)) {
+            violations.push(ThreadAffinity { tid, allowed });
+        }
+    }
+
+    Ok(AffinityCheck {
+        pid,
+        expected: expected.to_vec(),
+        threads,
+        violations,
+    })
+}
+
+/// Restrict every thread of a process to the given CPUs
+pub async fn pin_process(pid: u32, cpus: &[u32]) -> Result<()> {
+    let output = AsyncCommand::new("taskset")
+        .args(["--all-tasks", "--pid", "--cpu-list"])
+        .arg(format_cpu_list(cpus))
+        .arg(pid.to_string())
+        .output()
+        .await
+        .context("Failed to run taskset (util-linux)")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "taskset failed for process {}: {}",
+            pid,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Restrict a running podman/docker container to the given CPUs
+pub async fn pin_container(runtime: &str, container: &str, cpus: &[u32]) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .arg("update")
+        .arg("--cpuset-cpus")
+        .arg(format_cpu_list(cpus))
+        .arg(container)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to set CPU placement of {}: {}",
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    info!("📌 {} pinned to CPUs {}", container, format_cpu_list(cpus));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists_round_trip() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(parse_cpu_list("").unwrap(), Vec::<u32>::new());
+        assert_eq!(format_cpu_list(&[11, 0, 2, 1, 3, 8, 10, 3]), "0-3,8,10-11");
+        assert_eq!(format_cpu_list(&[]), "");
+
+        for list in ["0", "0-15", "0-7,16-23", "1,3,5", "0-1,4,6-7"] {
+            assert_eq!(format_cpu_list(&parse_cpu_list(list).unwrap()), list);
+        }
+
+        assert!(parse_cpu_list("5-3").is_err());
+        assert!(parse_cpu_list("0-").is_err());
+        assert!(parse_cpu_list("a,1").is_err());
+    }
+
+    #[test]
+    fn placement_falls_back_to_all_cpus_without_hybrid_cores() {
+        let homogeneous = CpuTopology::new(vec![0, 1, 2, 3], Vec::new(), None);
+        assert!(!homogeneous.hybrid);
+        for placement in [
+            CorePlacement::Any,
+            CorePlacement::PCores,
+            CorePlacement::ECores,
+        ] {
+            assert_eq!(homogeneous.cpus_for(placement), vec![0, 1, 2, 3]);
+        }
+
+        let hybrid = CpuTopology::new(vec![0, 1, 2, 3], vec![4, 5], Some(true));
+        assert!(hybrid.hybrid);
+        assert_eq!(hybrid.cpus_for(CorePlacement::PCores), vec![0, 1, 2, 3]);
+        assert_eq!(hybrid.cpus_for(CorePlacement::ECores), vec![4, 5]);
+        assert_eq!(hybrid.cpus_for(CorePlacement::Any), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(hybrid.core_type(4), Some(CoreType::Efficiency));
+        assert_eq!(hybrid.core_type(9), None);
+    }
+
+    #[test]
+    fn epp_is_restored_when_the_last_claim_is_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let sysfs = dir.path().join("cpu");
+        for (cpu, epp) in [(0, "balance_power"), (1, "power")] {
+            let cpufreq = sysfs.join(format!("cpu{}", cpu)).join("cpufreq");
+            std::fs::create_dir_all(&cpufreq).unwrap();
+            std::fs::write(cpufreq.join("energy_performance_preference"), epp).unwrap();
+        }
+        let epp = |cpu: u32| {
+            std::fs::read_to_string(
+                sysfs.join(format!("cpu{}/cpufreq/energy_performance_preference", cpu)),
+            )
+            .unwrap()
+        };
+        let ledger = dir.path().join("epp.json");
+        let performance = EnergyPerformancePreference::Performance;
+
+        // CPU 2 has no cpufreq directory and is skipped
+        assert_eq!(
+            claim_epp(&sysfs, &ledger, "game", &[0, 1, 2], performance).unwrap(),
+            2
+        );
+        assert_eq!(
+            claim_epp(&sysfs, &ledger, "stream", &[1], performance).unwrap(),
+            1
+        );
+        assert_eq!(
+            (epp(0).as_str(), epp(1).as_str()),
+            ("performance", "performance")
+        );
+
+        assert_eq!(release_epp(&sysfs, &ledger, "game").unwrap(), 1);
+        assert_eq!(
+            (epp(0).as_str(), epp(1).as_str()),
+            ("balance_power", "performance")
+        );
+
+        assert_eq!(release_epp(&sysfs, &ledger, "stream").unwrap(), 1);
+        assert_eq!(epp(1), "power");
+        assert!(!ledger.exists());
+        assert_eq!(release_epp(&sysfs, &ledger, "stream").unwrap(), 0);
+    }
+}
@@ -6,9 +6,11 @@ use tokio::sync::RwLock;
 
 pub mod cpu;
 pub mod gpu;
+pub mod hybrid;
 pub mod memory;
 pub mod network;
 pub mod storage;
+pub mod tuning;
 
 use crate::plugins::{OptimizationContext, OptimizationPlugin, PluginManager};
 
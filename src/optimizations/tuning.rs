@@ -0,0 +1,612 @@
+// Host tuning presets
+//
+// `bolt optimize apply --preset latency|throughput|balanced` sets the kernel
+// knobs a workload class cares about:
+//
+// - sysctls under /proc/sys (swappiness, dirty ratios, busy polling, NUMA
+//   balancing, socket buffers)
+// - the cpufreq governor of every CPU
+// - device IRQ affinity: steered to a few CPUs for latency, spread over all
+//   of them for throughput
+// - transparent huge pages (`enabled` and `defrag`)
+// - cpu.weight of the cgroups of the containers named with `--container`
+//
+// A plan only lists knobs whose current value differs from the preset's, and
+// `--dry-run` stops there. Applying first records every knob's current value
+// in `<data_dir>/optimize/undo.json`, then writes the new values; if a write
+// fails, the ones already written are put back and nothing is left half
+// applied. IRQs the kernel manages itself refuse new affinities, so those are
+// skipped rather than failing the whole plan. Applying again keeps the values
+// from before the first apply, and `bolt optimize revert` writes them back.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::warn;
+
+use super::hybrid::{format_cpu_list, parse_cpu_list};
+use crate::config::OptimizeSettings;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Interactive work: games, audio, request/response services
+    Latency,
+    /// Batch work: builds, training, bulk transfers
+    Throughput,
+    #[default]
+    Balanced,
+}
+
+impl Preset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Preset::Latency => "latency",
+            Preset::Throughput => "throughput",
+            Preset::Balanced => "balanced",
+        }
+    }
+
+    fn sysctls(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::Latency => &[
+                ("vm.swappiness", "10"),
+                ("vm.stat_interval", "10"),
+                ("kernel.numa_balancing", "0"),
+                ("net.core.busy_poll", "50"),
+                ("net.core.busy_read", "50"),
+            ],
+            Preset::Throughput => &[
+                ("vm.dirty_ratio", "40"),
+                ("vm.dirty_background_ratio", "10"),
+                ("kernel.numa_balancing", "1"),
+                ("net.core.netdev_max_backlog", "16384"),
+                ("net.core.rmem_max", "16777216"),
+                ("net.core.wmem_max", "16777216"),
+            ],
+            Preset::Balanced => &[
+                ("vm.swappiness", "30"),
+                ("vm.dirty_ratio", "20"),
+                ("vm.dirty_background_ratio", "10"),
+                ("net.core.netdev_max_backlog", "4096"),
+            ],
+        }
+    }
+
+    fn governor(&self) -> &'static str {
+        match self {
+            Preset::Latency | Preset::Throughput => "performance",
+            Preset::Balanced => "schedutil",
+        }
+    }
+
+    /// THP `enabled` and `defrag`
+    fn thp(&self) -> (&'static str, &'static str) {
+        match self {
+            // Compaction stalls show up as latency spikes
+            Preset::Latency => ("never", "never"),
+            Preset::Throughput => ("always", "madvise"),
+            Preset::Balanced => ("madvise", "madvise"),
+        }
+    }
+
+    /// cpu.weight of the named containers (default 100)
+    fn cpu_weight(&self) -> Option<u64> {
+        match self {
+            Preset::Latency => Some(1000),
+            Preset::Throughput => Some(100),
+            Preset::Balanced => None,
+        }
+    }
+}
+
+/// A setting a preset changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Knob {
+    Sysctl {
+        name: String,
+    },
+    Governor {
+        cpu: u32,
+    },
+    Irq {
+        irq: u32,
+    },
+    /// `enabled` or `defrag` under /sys/kernel/mm/transparent_hugepage
+    Thp {
+        setting: String,
+    },
+    CpuWeight {
+        container: String,
+    },
+}
+
+impl Knob {
+    /// What `[optimize] exclude` matches besides sysctl names
+    fn class(&self) -> &'static str {
+        match self {
+            Knob::Sysctl { .. } => "sysctl",
+            Knob::Governor { .. } => "governor",
+            Knob::Irq { .. } => "irq",
+            Knob::Thp { .. } => "thp",
+            Knob::CpuWeight { .. } => "cpu_weight",
+        }
+    }
+
+    fn excluded(&self, exclude: &[String]) -> bool {
+        exclude
+            .iter()
+            .any(|e| e == self.class() || matches!(self, Knob::Sysctl { name } if name == e))
+    }
+
+    /// Writes the kernel may refuse without the plan being wrong
+    fn best_effort(&self) -> bool {
+        matches!(self, Knob::Irq { .. })
+    }
+}
+
+impl std::fmt::Display for Knob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Knob::Sysctl { name } => write!(f, "sysctl {}", name),
+            Knob::Governor { cpu } => write!(f, "cpu{} governor", cpu),
+            Knob::Irq { irq } => write!(f, "irq {} affinity", irq),
+            Knob::Thp { setting } => write!(f, "thp {}", setting),
+            Knob::CpuWeight { container } => write!(f, "{} cpu.weight", container),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub knob: Knob,
+    pub path: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub preset: Preset,
+    pub changes: Vec<Change>,
+}
+
+/// Saved before a plan is applied, to revert it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Undo {
+    pub preset: Preset,
+    pub applied_at: DateTime<Utc>,
+    /// Values from before the first apply
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Skipped {
+    pub change: Change,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Outcome {
+    pub changed: Vec<Change>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// The files tuning reads and writes, under `root` (`/` outside tests)
+#[derive(Debug, Clone)]
+pub struct Host {
+    root: PathBuf,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::new(Path::new("/"))
+    }
+}
+
+impl Host {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn cpu_dir(&self) -> PathBuf {
+        self.root.join("sys/devices/system/cpu")
+    }
+
+    fn sysctl(&self, name: &str) -> PathBuf {
+        self.root.join("proc/sys").join(name.replace('.', "/"))
+    }
+
+    fn thp(&self, setting: &str) -> PathBuf {
+        self.root
+            .join("sys/kernel/mm/transparent_hugepage")
+            .join(setting)
+    }
+
+    /// CPUs with a cpufreq governor
+    fn governed_cpus(&self) -> Vec<u32> {
+        let mut cpus: Vec<u32> = std::fs::read_dir(self.cpu_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .strip_prefix("cpu")?
+                    .parse()
+                    .ok()
+            })
+            .filter(|cpu| self.governor(*cpu).is_file())
+            .collect();
+        cpus.sort_unstable();
+        cpus
+    }
+
+    fn governor(&self, cpu: u32) -> PathBuf {
+        self.cpu_dir()
+            .join(format!("cpu{}/cpufreq/scaling_governor", cpu))
+    }
+
+    fn online_cpus(&self) -> Option<Vec<u32>> {
+        let online = std::fs::read_to_string(self.cpu_dir().join("online")).ok()?;
+        parse_cpu_list(&online).ok()
+    }
+
+    /// Device IRQs, without the timer
+    fn irqs(&self) -> Vec<u32> {
+        let mut irqs: Vec<u32> = std::fs::read_dir(self.root.join("proc/irq"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|e| e.file_name().to_string_lossy().parse().ok())
+            .filter(|irq| *irq != 0 && self.irq(*irq).is_file())
+            .collect();
+        irqs.sort_unstable();
+        irqs
+    }
+
+    fn irq(&self, irq: u32) -> PathBuf {
+        self.root
+            .join(format!("proc/irq/{}/smp_affinity_list", irq))
+    }
+}
+
+/// A knob's current value: whitespace collapsed, and the selected word of
+/// files like THP's `always [madvise] never`
+fn current(path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let value = match (content.find('['), content.find(']')) {
+        (Some(start), Some(end)) if start < end => content[start + 1..end].to_string(),
+        _ => content.split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+    Some(value)
+}
+
+/// What applying `preset` would change on `host`. `containers` are the
+/// containers to weight and their cgroup directories.
+pub fn plan(
+    host: &Host,
+    preset: Preset,
+    settings: &OptimizeSettings,
+    containers: &[(String, PathBuf)],
+) -> Result<Plan> {
+    let mut wanted: Vec<(Knob, PathBuf, String)> = Vec::new();
+
+    let mut sysctls: BTreeMap<String, String> = preset
+        .sysctls()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    sysctls.extend(settings.sysctls.clone());
+    for (name, value) in sysctls {
+        let path = host.sysctl(&name);
+        wanted.push((Knob::Sysctl { name }, path, value));
+    }
+
+    for cpu in host.governed_cpus() {
+        let path = host.governor(cpu);
+        let available = path.with_file_name("scaling_available_governors");
+        let offered = std::fs::read_to_string(&available)
+            .is_ok_and(|a| a.split_whitespace().any(|g| g == preset.governor()));
+        if offered {
+            wanted.push((Knob::Governor { cpu }, path, preset.governor().to_string()));
+        }
+    }
+
+    let irq_cpus = match preset {
+        Preset::Latency => Some(match settings.irq_cpus {
+            Some(ref cpus) => parse_cpu_list(cpus).context("Invalid [optimize] irq_cpus")?,
+            None => vec![0],
+        }),
+        Preset::Throughput => host.online_cpus(),
+        Preset::Balanced => None,
+    };
+    if let Some(mut cpus) = irq_cpus {
+        cpus.sort_unstable();
+        cpus.dedup();
+        for irq in host.irqs() {
+            let path = host.irq(irq);
+            let same = current(&path)
+                .and_then(|c| parse_cpu_list(&c).ok())
+                .is_some_and(|mut c| {
+                    c.sort_unstable();
+                    c == cpus
+                });
+            if !same {
+                wanted.push((Knob::Irq { irq }, path, format_cpu_list(&cpus)));
+            }
+        }
+    }
+
+    let (enabled, defrag) = preset.thp();
+    for (setting, value) in [("enabled", enabled), ("defrag", defrag)] {
+        wanted.push((
+            Knob::Thp {
+                setting: setting.to_string(),
+            },
+            host.thp(setting),
+            value.to_string(),
+        ));
+    }
+
+    if let Some(weight) = preset.cpu_weight() {
+        for (container, cgroup) in containers {
+            wanted.push((
+                Knob::CpuWeight {
+                    container: container.clone(),
+                },
+                cgroup.join("cpu.weight"),
+                weight.to_string(),
+            ));
+        }
+    }
+
+    let changes = wanted
+        .into_iter()
+        .filter(|(knob, _, _)| !knob.excluded(&settings.exclude))
+        // Knobs this kernel doesn't have are left out
+        .filter_map(|(knob, path, to)| {
+            let from = current(&path)?;
+            (from != to).then_some(Change {
+                knob,
+                path,
+                from,
+                to,
+            })
+        })
+        .collect();
+    Ok(Plan { preset, changes })
+}
+
+/// cgroup directories of running containers
+pub async fn container_cgroups(
+    runtime: &str,
+    containers: &[String],
+) -> Result<Vec<(String, PathBuf)>> {
+    let mut cgroups = Vec::new();
+    for container in containers {
+        let output = AsyncCommand::new(runtime)
+            .args(["inspect", "--format", "{{.State.Pid}}", container])
+            .output()
+            .await?;
+        let pid: u32 = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()
+            .filter(|pid| *pid > 0)
+            .ok_or_else(|| anyhow!("Container {} is not running", container))?;
+        let cgroup = crate::runtime::cgroups::unified_dir(pid)
+            .ok_or_else(|| anyhow!("cpu.weight needs cgroup v2"))?;
+        cgroups.push((container.clone(), cgroup));
+    }
+    Ok(cgroups)
+}
+
+fn undo_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("optimize").join("undo.json")
+}
+
+/// What `bolt optimize revert` would restore
+pub fn undo(data_dir: &Path) -> Option<Undo> {
+    std::fs::read(undo_path(data_dir))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+fn save(data_dir: &Path, undo: Option<&Undo>) -> Result<()> {
+    let path = undo_path(data_dir);
+    match undo {
+        Some(undo) if !undo.changes.is_empty() => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, serde_json::to_vec_pretty(undo)?)
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+        _ => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn write(change: &Change, value: &str) -> Result<()> {
+    std::fs::write(&change.path, value)
+        .with_context(|| format!("Failed to set {} to {}", change.knob, value))
+}
+
+/// Apply `plan`, all of it or none of it
+pub fn apply(data_dir: &Path, plan: &Plan) -> Result<Outcome> {
+    let previous = undo(data_dir);
+    let mut record = Undo {
+        preset: plan.preset,
+        applied_at: Utc::now(),
+        changes: previous
+            .as_ref()
+            .map(|u| u.changes.clone())
+            .unwrap_or_default(),
+    };
+    for change in &plan.changes {
+        if !record.changes.iter().any(|c| c.path == change.path) {
+            record.changes.push(change.clone());
+        }
+    }
+    // Recorded first, so even a crash halfway leaves a way back
+    save(data_dir, Some(&record))?;
+
+    let mut outcome = Outcome::default();
+    for change in &plan.changes {
+        match write(change, &change.to) {
+            Ok(()) => outcome.changed.push(change.clone()),
+            Err(e) if change.knob.best_effort() => outcome.skipped.push(Skipped {
+                change: change.clone(),
+                error: format!("{:#}", e),
+            }),
+            Err(e) => {
+                for done in outcome.changed.iter().rev() {
+                    if let Err(e) = write(done, &done.from) {
+                        warn!("Failed to roll back: {:#}", e);
+                    }
+                }
+                save(data_dir, previous.as_ref())?;
+                return Err(e.context("Nothing was changed"));
+            }
+        }
+    }
+
+    let recorded_before = |c: &Change| {
+        previous
+            .as_ref()
+            .is_some_and(|p| p.changes.iter().any(|o| o.path == c.path))
+    };
+    record
+        .changes
+        .retain(|c| recorded_before(c) || !outcome.skipped.iter().any(|s| s.change.path == c.path));
+    save(data_dir, Some(&record))?;
+    Ok(outcome)
+}
+
+/// Put back every value from before the first apply. Values that can't be
+/// written stay in the undo file for the next revert; containers that are
+/// gone are dropped.
+pub fn revert(data_dir: &Path) -> Result<Outcome> {
+    let mut record = undo(data_dir).ok_or_else(|| anyhow!("No optimizations to revert"))?;
+    let mut outcome = Outcome::default();
+    let mut failed = Vec::new();
+    for change in record.changes.iter().rev() {
+        if !change.path.exists() {
+            outcome.skipped.push(Skipped {
+                change: change.clone(),
+                error: "gone".to_string(),
+            });
+            continue;
+        }
+        match write(change, &change.from) {
+            Ok(()) => outcome.changed.push(change.clone()),
+            Err(e) => {
+                failed.push(change.clone());
+                outcome.skipped.push(Skipped {
+                    change: change.clone(),
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+    }
+    failed.reverse();
+    record.changes = failed;
+    save(data_dir, Some(&record))?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host() -> (tempfile::TempDir, Host) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for (file, content) in [
+            ("proc/sys/vm/swappiness", "60\n"),
+            ("proc/sys/vm/stat_interval", "10\n"),
+            ("proc/sys/kernel/numa_balancing", "1\n"),
+            ("sys/devices/system/cpu/online", "0-1\n"),
+            (
+                "sys/devices/system/cpu/cpu0/cpufreq/scaling_governor",
+                "powersave\n",
+            ),
+            (
+                "sys/devices/system/cpu/cpu0/cpufreq/scaling_available_governors",
+                "performance powersave\n",
+            ),
+            (
+                "sys/kernel/mm/transparent_hugepage/enabled",
+                "always [madvise] never\n",
+            ),
+            (
+                "sys/kernel/mm/transparent_hugepage/defrag",
+                "always defer [madvise] never\n",
+            ),
+            ("proc/irq/0/smp_affinity_list", "0-1\n"),
+            ("proc/irq/24/smp_affinity_list", "0-1\n"),
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let host = Host::new(root);
+        (dir, host)
+    }
+
+    #[test]
+    fn plans_only_differing_knobs_and_reverts_to_the_first_values() {
+        let (dir, host) = host();
+        let settings = OptimizeSettings {
+            exclude: vec!["kernel.numa_balancing".to_string()],
+            ..Default::default()
+        };
+        let plan = plan(&host, Preset::Latency, &settings, &[]).unwrap();
+        let knobs: Vec<String> = plan.changes.iter().map(|c| c.knob.to_string()).collect();
+        // stat_interval already matches, busy_poll doesn't exist, irq 0 is the
+        // timer and numa_balancing is excluded
+        assert_eq!(
+            knobs,
+            [
+                "sysctl vm.swappiness",
+                "cpu0 governor",
+                "irq 24 affinity",
+                "thp enabled",
+                "thp defrag"
+            ]
+        );
+        assert_eq!(plan.changes[3].from, "madvise");
+
+        let data_dir = dir.path().join("data");
+        let outcome = apply(&data_dir, &plan).unwrap();
+        assert_eq!(outcome.changed.len(), 5);
+        assert_eq!(current(&host.sysctl("vm.swappiness")).unwrap(), "10");
+        assert_eq!(current(&host.irq(24)).unwrap(), "0");
+
+        // A second preset on top keeps the original values
+        let plan = super::plan(&host, Preset::Throughput, &settings, &[]).unwrap();
+        apply(&data_dir, &plan).unwrap();
+        let record = undo(&data_dir).unwrap();
+        assert_eq!(record.preset, Preset::Throughput);
+        assert!(
+            record
+                .changes
+                .iter()
+                .any(|c| c.knob.to_string() == "thp enabled" && c.from == "madvise")
+        );
+
+        revert(&data_dir).unwrap();
+        assert_eq!(current(&host.sysctl("vm.swappiness")).unwrap(), "60");
+        assert_eq!(current(&host.governor(0)).unwrap(), "powersave");
+        assert_eq!(current(&host.irq(24)).unwrap(), "0-1");
+        assert!(undo(&data_dir).is_none());
+    }
+}
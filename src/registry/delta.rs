@@ -0,0 +1,314 @@
+// Delta layer updates
+//
+// Game-server images change a few files per release, yet every new layer
+// digest means downloading the whole layer again. When a Drift registry
+// advertises `delta_updates`, it keeps binary diffs between layer versions,
+// made with `zstd --patch-from=<old layer> <new layer>`:
+//
+// - `GET /v2/<name>/deltas/<digest>` lists the diffs that produce the layer
+//   `<digest>`: `{"deltas": [{"from": "sha256:…", "digest": "sha256:…",
+//   "size": 1234}]}`. 404 means there are none.
+// - each diff is an ordinary blob, fetched from `/v2/<name>/blobs/<digest>`.
+//
+// Bolt picks a diff whose `from` layer it already has, preferring the layer
+// at the same position in the version pulled last, then the smallest diff.
+// The patched layer must hash to the manifest's digest before it enters the
+// layer store; if no diff applies or verification fails, the layer is
+// downloaded in full.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::drift_integration::PackageManifest;
+
+/// Largest back-reference a patched layer may use: 2 GiB, as `--long=31`
+const DELTA_WINDOW_LOG_MAX: u32 = 31;
+
+/// Diffs the registry has for one layer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeltaIndex {
+    #[serde(default)]
+    pub deltas: Vec<DeltaEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeltaEntry {
+    /// Layer the diff applies to
+    pub from: String,
+    /// Blob holding the diff
+    pub digest: String,
+    pub size: u64,
+}
+
+impl DeltaIndex {
+    /// Diff to apply given the layers in `store`, preferring one from
+    /// `preferred`, the layer this one replaces
+    pub fn choose(&self, store: &LayerStore, preferred: Option<&str>) -> Option<&DeltaEntry> {
+        let usable = || self.deltas.iter().filter(|d| store.contains(&d.from));
+        preferred
+            .and_then(|p| usable().find(|d| d.from == p))
+            .or_else(|| usable().min_by_key(|d| d.size))
+    }
+}
+
+/// How one layer got into the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerFetch {
+    /// Already present
+    Cached,
+    /// Patched from a local layer; bytes of the diff downloaded
+    Delta { downloaded: u64, size: u64 },
+    /// Downloaded in full
+    Full { downloaded: u64 },
+}
+
+/// Totals of a pull, for the summary line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchSummary {
+    pub cached: u32,
+    pub delta: u32,
+    pub full: u32,
+    pub downloaded_bytes: u64,
+    /// Layer bytes that diffs saved downloading
+    pub saved_bytes: u64,
+}
+
+impl FetchSummary {
+    pub fn add(&mut self, fetch: LayerFetch) {
+        match fetch {
+            LayerFetch::Cached => self.cached += 1,
+            LayerFetch::Delta { downloaded, size } => {
+                self.delta += 1;
+                self.downloaded_bytes += downloaded;
+                self.saved_bytes += size.saturating_sub(downloaded);
+            }
+            LayerFetch::Full { downloaded } => {
+                self.full += 1;
+                self.downloaded_bytes += downloaded;
+            }
+        }
+    }
+}
+
+/// Content-addressed layers, plus the manifest each package was last pulled at
+#[derive(Debug, Clone)]
+pub struct LayerStore {
+    root: PathBuf,
+}
+
+impl LayerStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn blob_path(&self, digest: &str) -> PathBuf {
+        let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+        self.root.join("blobs").join(algorithm).join(hex)
+    }
+
+    pub fn contains(&self, digest: &str) -> bool {
+        self.blob_path(digest).is_file()
+    }
+
+    /// Scratch file next to the blobs, so importing is a rename
+    pub fn temp_path(&self, digest: &str) -> Result<PathBuf> {
+        let dir = self.root.join("tmp");
+        std::fs::create_dir_all(&dir)?;
+        let name = digest.replace(':', "-");
+        Ok(dir.join(format!("{}.{}", name, std::process::id())))
+    }
+
+    /// Move a verified file into the store under `digest`
+    pub fn import(&self, path: &Path, digest: &str) -> Result<PathBuf> {
+        let target = self.blob_path(digest);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(path, &target)
+            .with_context(|| format!("Failed to store layer {}", digest))?;
+        Ok(target)
+    }
+
+    fn manifest_path(&self, package: &str) -> PathBuf {
+        self.root
+            .join("manifests")
+            .join(format!("{}.json", package.replace('/', "_")))
+    }
+
+    /// Manifest of the version of `package` pulled last
+    pub fn previous_manifest(&self, package: &str) -> Option<PackageManifest> {
+        std::fs::read(self.manifest_path(package))
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+    }
+
+    pub fn save_manifest(&self, package: &str, manifest: &PackageManifest) -> Result<()> {
+        let path = self.manifest_path(package);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_vec_pretty(manifest)?)?;
+        Ok(())
+    }
+}
+
+/// Writer that hashes what passes through it
+pub struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    /// Flush, then return `sha256:<hex>` and the byte count
+    pub fn finish(mut self) -> Result<(String, u64)> {
+        self.inner.flush()?;
+        Ok((
+            format!("sha256:{}", hex::encode(self.hasher.finalize())),
+            self.written,
+        ))
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Fail unless `actual` is the digest the manifest promised
+pub fn verify(expected: &str, actual: &str) -> Result<()> {
+    if expected != actual {
+        return Err(anyhow!(
+            "Digest mismatch: expected {}, got {}",
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
+
+/// Patch `base` with the zstd diff at `delta`, writing the result to `out`,
+/// and check it hashes to `expected`. Returns the patched layer's size.
+pub fn apply(base: &Path, delta: &Path, out: &Path, expected: &str) -> Result<u64> {
+    let prefix = std::fs::read(base).with_context(|| format!("Failed to read {:?}", base))?;
+    let reader = BufReader::new(File::open(delta)?);
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(reader, &prefix)?;
+    decoder.window_log_max(DELTA_WINDOW_LOG_MAX)?;
+
+    let mut writer = DigestWriter::new(BufWriter::new(File::create(out)?));
+    std::io::copy(&mut decoder, &mut writer).context("Failed to apply layer delta")?;
+    let (digest, size) = writer.finish()?;
+    verify(expected, &digest)?;
+    Ok(size)
+}
+
+/// `sha256:<hex>` of a file
+pub fn file_digest(path: &Path) -> Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_delta_against_stored_layer_and_verifies_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LayerStore::new(dir.path());
+
+        let old: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut new = old.clone();
+        new[1000..1010].copy_from_slice(b"patched!!!");
+        new.extend_from_slice(b"new map data");
+
+        let old_path = dir.path().join("old");
+        std::fs::write(&old_path, &old).unwrap();
+        let old_digest = file_digest(&old_path).unwrap();
+        store.import(&old_path, &old_digest).unwrap();
+
+        let mut encoder =
+            zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, &old).unwrap();
+        encoder.write_all(&new).unwrap();
+        let delta = encoder.finish().unwrap();
+        assert!(delta.len() < new.len() / 100);
+        let delta_path = dir.path().join("delta");
+        std::fs::write(&delta_path, &delta).unwrap();
+        let new_digest = format!("sha256:{}", hex::encode(Sha256::digest(&new)));
+
+        let index = DeltaIndex {
+            deltas: vec![
+                DeltaEntry {
+                    from: "sha256:missing".to_string(),
+                    digest: "sha256:d1".to_string(),
+                    size: 10,
+                },
+                DeltaEntry {
+                    from: old_digest.clone(),
+                    digest: "sha256:d2".to_string(),
+                    size: delta.len() as u64,
+                },
+            ],
+        };
+        let chosen = index.choose(&store, None).unwrap();
+        assert_eq!(chosen.from, old_digest);
+
+        let out = store.temp_path(&new_digest).unwrap();
+        let size = apply(
+            &store.blob_path(&old_digest),
+            &delta_path,
+            &out,
+            &new_digest,
+        )
+        .unwrap();
+        assert_eq!(size, new.len() as u64);
+        assert!(
+            apply(
+                &store.blob_path(&old_digest),
+                &delta_path,
+                &out,
+                &old_digest
+            )
+            .is_err()
+        );
+
+        let mut summary = FetchSummary::default();
+        summary.add(LayerFetch::Delta {
+            downloaded: delta.len() as u64,
+            size,
+        });
+        summary.add(LayerFetch::Cached);
+        assert_eq!((summary.delta, summary.cached), (1, 1));
+        assert_eq!(summary.saved_bytes, size - delta.len() as u64);
+    }
+}
@@ -7,6 +7,7 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+use super::delta::{self, DeltaIndex, FetchSummary, LayerFetch, LayerStore};
 use crate::runtime::storage::ghostbay::GhostbayClient;
 
 /// Enhanced Drift registry integration for Bolt ecosystem
@@ -19,6 +20,8 @@ pub struct DriftRegistryClient {
     pub cache: Arc<RwLock<PackageCache>>,
     pub features: DriftFeatures,
     pub gaming_config: GamingPackageConfig,
+    /// Layers pulled so far, the bases for delta updates
+    pub layer_store: LayerStore,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +32,9 @@ pub struct DriftFeatures {
     pub p2p_distribution: bool,
     pub ghostwire_integration: bool,
     pub multi_arch_support: bool,
+    /// Serves binary diffs between layer versions (see `delta.rs`)
+    #[serde(default)]
+    pub delta_updates: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -183,15 +189,19 @@ pub struct PackageCache {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PackageManifest {
     pub schema_version: u32,
+    #[serde(default)]
     pub media_type: String,
     pub config: BlobDescriptor,
     pub layers: Vec<LayerDescriptor>,
+    #[serde(default)]
     pub annotations: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct BlobDescriptor {
     pub media_type: String,
     pub size: u64,
@@ -199,6 +209,7 @@ pub struct BlobDescriptor {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct LayerDescriptor {
     pub media_type: String,
     pub size: u64,
@@ -207,9 +218,13 @@ pub struct LayerDescriptor {
     pub annotations: Option<HashMap<String, String>>,
 
     // Bolt-specific layer metadata
+    #[serde(default)]
     pub gaming_assets: bool,
+    #[serde(default)]
     pub system_libraries: bool,
+    #[serde(default)]
     pub user_data: bool,
+    #[serde(default)]
     pub cacheable: bool,
 }
 
@@ -282,9 +297,21 @@ impl DriftRegistryClient {
                 auto_optimization: true,
                 ghostforge_sync: true,
             },
+            layer_store: LayerStore::new(
+                dirs::data_dir()
+                    .unwrap_or_else(|| std::path::PathBuf::from("."))
+                    .join("bolt")
+                    .join("drift"),
+            ),
         })
     }
 
+    /// Keep pulled layers under `root` instead of `<data_dir>/drift`
+    pub fn with_layer_store(mut self, root: impl Into<std::path::PathBuf>) -> Self {
+        self.layer_store = LayerStore::new(root);
+        self
+    }
+
     /// Detect registry features by querying the API
     async fn detect_registry_features(client: &Client, endpoint: &str) -> Result<DriftFeatures> {
         debug!("🔍 Detecting registry features");
@@ -309,6 +336,7 @@ impl DriftRegistryClient {
                         p2p_distribution: false,
                         ghostwire_integration: false,
                         multi_arch_support: true,
+                        delta_updates: false,
                     })
                 }
             }
@@ -321,6 +349,7 @@ impl DriftRegistryClient {
                     p2p_distribution: false,
                     ghostwire_integration: false,
                     multi_arch_support: true,
+                    delta_updates: false,
                 })
             }
         }
@@ -446,18 +475,23 @@ impl DriftRegistryClient {
     async fn pull_from_registry(&self, package_ref: &str) -> Result<String> {
         debug!("🌊 Pulling from registry: {}", package_ref);
 
-        // Get package manifest
+        // Get package manifest, and the one pulled last as delta bases
         let manifest = self.get_package_manifest(package_ref).await?;
+        let (name, _) = split_ref(package_ref);
+        let previous = self.layer_store.previous_manifest(name);
 
         // If Ghostbay is available, use optimized download
-        if let Some(ref ghostbay) = self.ghostbay_client {
-            return self
-                .pull_via_ghostbay(package_ref, &manifest, ghostbay)
-                .await;
-        }
+        let path = if let Some(ref ghostbay) = self.ghostbay_client {
+            self.pull_via_ghostbay(package_ref, &manifest, previous.as_ref(), ghostbay)
+                .await?
+        } else {
+            // Standard registry pull
+            self.pull_standard(package_ref, &manifest, previous.as_ref())
+                .await?
+        };
 
-        // Standard registry pull
-        self.pull_standard(package_ref, &manifest).await
+        self.layer_store.save_manifest(name, &manifest)?;
+        Ok(path)
     }
 
     /// Optimized pull using Ghostbay storage
@@ -465,6 +499,7 @@ impl DriftRegistryClient {
         &self,
         package_ref: &str,
         manifest: &PackageManifest,
+        previous: Option<&PackageManifest>,
         ghostbay: &GhostbayClient,
     ) -> Result<String> {
         debug!("👻 Using Ghostbay optimized pull");
@@ -473,11 +508,11 @@ impl DriftRegistryClient {
         let mut cached_layers = Vec::new();
         let mut missing_layers = Vec::new();
 
-        for layer in &manifest.layers {
+        for (index, layer) in manifest.layers.iter().enumerate() {
             if ghostbay.blob_exists(&layer.digest).await? {
                 cached_layers.push(layer);
             } else {
-                missing_layers.push(layer);
+                missing_layers.push((index, layer));
             }
         }
 
@@ -487,9 +522,11 @@ impl DriftRegistryClient {
             missing_layers.len()
         );
 
-        // Download missing layers in parallel
+        // Download missing layers, patching from older versions where possible
         if !missing_layers.is_empty() {
-            self.download_missing_layers(&missing_layers).await?;
+            let (name, _) = split_ref(package_ref);
+            self.download_missing_layers(name, &missing_layers, previous)
+                .await?;
         }
 
         // Assemble final image
@@ -501,13 +538,19 @@ impl DriftRegistryClient {
     }
 
     /// Standard registry pull without Ghostbay
-    async fn pull_standard(&self, package_ref: &str, manifest: &PackageManifest) -> Result<String> {
+    async fn pull_standard(
+        &self,
+        package_ref: &str,
+        manifest: &PackageManifest,
+        previous: Option<&PackageManifest>,
+    ) -> Result<String> {
         debug!("📦 Standard registry pull");
 
-        // Download all layers
-        for layer in &manifest.layers {
-            self.download_layer(&layer.digest).await?;
-        }
+        // Download all layers not in the layer store yet
+        let (name, _) = split_ref(package_ref);
+        let layers: Vec<(usize, &LayerDescriptor)> = manifest.layers.iter().enumerate().collect();
+        self.download_missing_layers(name, &layers, previous)
+            .await?;
 
         // Assemble image
         let image_path = self
@@ -583,19 +626,35 @@ impl DriftRegistryClient {
         Ok("/tmp/package".to_string())
     }
 
-    async fn get_package_manifest(&self, _package_ref: &str) -> Result<PackageManifest> {
-        // Fetch manifest from registry
-        Ok(PackageManifest {
-            schema_version: 2,
-            media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
-            config: BlobDescriptor {
-                media_type: "application/vnd.oci.image.config.v1+json".to_string(),
-                size: 1024,
-                digest: "sha256:abcd1234".to_string(),
-            },
-            layers: vec![],
-            annotations: HashMap::new(),
-        })
+    async fn get_package_manifest(&self, package_ref: &str) -> Result<PackageManifest> {
+        let (name, reference) = split_ref(package_ref);
+        let url = format!("{}/v2/{}/manifests/{}", self.endpoint, name, reference);
+
+        let response = self
+            .client
+            .get(&url)
+            .header(reqwest::header::ACCEPT, super::media_types::OCI_MANIFEST)
+            .send()
+            .await
+            .context("Failed to fetch package manifest")?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch manifest for {}: {}",
+                package_ref,
+                response.status()
+            ));
+        }
+
+        let manifest: PackageManifest = response
+            .json()
+            .await
+            .context("Failed to parse package manifest")?;
+        self.cache
+            .write()
+            .await
+            .manifests
+            .insert(package_ref.to_string(), manifest.clone());
+        Ok(manifest)
     }
 
     async fn share_via_p2p(&self, _package_ref: &str, _path: &str) -> Result<()> {
@@ -603,8 +662,31 @@ impl DriftRegistryClient {
         Ok(())
     }
 
-    async fn download_missing_layers(&self, _layers: &[&LayerDescriptor]) -> Result<()> {
-        // Download layers that aren't in Ghostbay
+    /// Download layers by their position in the manifest, so each can be
+    /// patched from the layer at the same position in `previous`
+    async fn download_missing_layers(
+        &self,
+        package: &str,
+        layers: &[(usize, &LayerDescriptor)],
+        previous: Option<&PackageManifest>,
+    ) -> Result<()> {
+        let mut summary = FetchSummary::default();
+        for (index, layer) in layers {
+            let replaces = previous
+                .and_then(|p| p.layers.get(*index))
+                .map(|l| l.digest.as_str())
+                .filter(|digest| *digest != layer.digest);
+            summary.add(self.download_layer(package, layer, replaces).await?);
+        }
+
+        info!(
+            "📊 Layers: {} cached, {} patched, {} full; downloaded {} (saved {})",
+            summary.cached,
+            summary.delta,
+            summary.full,
+            crate::builds::cache::format_size(summary.downloaded_bytes),
+            crate::builds::cache::format_size(summary.saved_bytes)
+        );
         Ok(())
     }
 
@@ -617,9 +699,127 @@ impl DriftRegistryClient {
         Ok("/var/lib/bolt/images/package".to_string())
     }
 
-    async fn download_layer(&self, _digest: &str) -> Result<()> {
-        // Download individual layer
-        Ok(())
+    /// Fetch one layer into the layer store: patched from an older layer
+    /// when the registry has a usable delta, in full otherwise
+    async fn download_layer(
+        &self,
+        package: &str,
+        layer: &LayerDescriptor,
+        replaces: Option<&str>,
+    ) -> Result<LayerFetch> {
+        if self.layer_store.contains(&layer.digest) {
+            return Ok(LayerFetch::Cached);
+        }
+
+        if self.features.delta_updates {
+            match self.download_delta(package, layer, replaces).await {
+                Ok(Some(fetch)) => return Ok(fetch),
+                Ok(None) => debug!("No usable delta for {}", layer.digest),
+                Err(e) => warn!(
+                    "Delta update of {} failed, pulling the full layer: {}",
+                    layer.digest, e
+                ),
+            }
+        }
+
+        let (path, downloaded) = self.download_blob(package, &layer.digest).await?;
+        self.layer_store.import(&path, &layer.digest)?;
+        Ok(LayerFetch::Full { downloaded })
+    }
+
+    /// Patch a layer from one already in the store, if the registry has a
+    /// delta from one
+    async fn download_delta(
+        &self,
+        package: &str,
+        layer: &LayerDescriptor,
+        replaces: Option<&str>,
+    ) -> Result<Option<LayerFetch>> {
+        let url = format!("{}/v2/{}/deltas/{}", self.endpoint, package, layer.digest);
+        let response = self.client.get(&url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("delta index: {}", response.status()));
+        }
+        let index: DeltaIndex = response.json().await.context("Invalid delta index")?;
+        let Some(entry) = index.choose(&self.layer_store, replaces).cloned() else {
+            return Ok(None);
+        };
+
+        debug!(
+            "🧩 Patching {} from {} ({} byte delta)",
+            layer.digest, entry.from, entry.size
+        );
+        let (patch, downloaded) = self.download_blob(package, &entry.digest).await?;
+        let base = self.layer_store.blob_path(&entry.from);
+        let out = self.layer_store.temp_path(&layer.digest)?;
+        let expected = layer.digest.clone();
+        let applied = {
+            let (patch, out) = (patch.clone(), out.clone());
+            tokio::task::spawn_blocking(move || delta::apply(&base, &patch, &out, &expected))
+                .await?
+        };
+        let _ = std::fs::remove_file(&patch);
+        let size = match applied {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = std::fs::remove_file(&out);
+                return Err(e);
+            }
+        };
+
+        self.layer_store.import(&out, &layer.digest)?;
+        Ok(Some(LayerFetch::Delta { downloaded, size }))
+    }
+
+    /// Download a blob to a scratch file in the layer store, checking its
+    /// digest on the way
+    async fn download_blob(
+        &self,
+        package: &str,
+        digest: &str,
+    ) -> Result<(std::path::PathBuf, u64)> {
+        use futures_util::StreamExt;
+        use std::io::Write;
+
+        let url = format!("{}/v2/{}/blobs/{}", self.endpoint, package, digest);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to download {}", digest))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to download {}: {}",
+                digest,
+                response.status()
+            ));
+        }
+
+        let path = self.layer_store.temp_path(digest)?;
+        let written = async {
+            let mut writer =
+                delta::DigestWriter::new(std::io::BufWriter::new(std::fs::File::create(&path)?));
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                writer.write_all(&chunk?)?;
+            }
+            let (actual, size) = writer.finish()?;
+            delta::verify(digest, &actual)?;
+            Ok::<_, anyhow::Error>(size)
+        }
+        .await;
+
+        match written {
+            Ok(size) => Ok((path, size)),
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                Err(e)
+            }
+        }
     }
 
     async fn create_enhanced_manifest(
@@ -666,6 +866,17 @@ impl DriftRegistryClient {
     }
 }
 
+/// Package name and tag or digest of a `name:tag` reference
+fn split_ref(package_ref: &str) -> (&str, &str) {
+    if let Some((name, digest)) = package_ref.split_once('@') {
+        return (name, digest);
+    }
+    match package_ref.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (package_ref, "latest"),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GamingSearchFilter {
     pub games_only: bool,
@@ -693,6 +904,7 @@ impl Default for DriftFeatures {
             p2p_distribution: true,
             ghostwire_integration: true,
             multi_arch_support: true,
+            delta_updates: true,
         }
     }
 }
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub mod client;
+pub mod delta;
 pub mod drift_integration;
 pub mod types;
 
@@ -0,0 +1,189 @@
+// OCI annotations
+//
+// Annotations are `key=value` metadata of the OCI specs, next to labels but
+// meant for tooling rather than people: hooks, CDI and the like read them.
+//
+// - images: `bolt build --annotation` hands them to the builder, `bolt push
+//   --annotation` adds them to the pushed manifest, and the registry client
+//   keeps a pulled manifest's annotations in the image's metadata
+// - containers: `bolt run --annotation` and a service's `annotations` table
+//   end up in the `annotations` of the container's runtime spec
+//
+// `bolt inspect --annotations` and `bolt image annotations` show them, and
+// `bolt ps --filter annotation=key[=value]` selects containers by them.
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use tokio::process::Command as AsyncCommand;
+
+/// `key=value` pairs as a map; keys must be unique
+pub fn parse(pairs: &[String]) -> Result<BTreeMap<String, String>> {
+    let mut annotations = BTreeMap::new();
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid annotation '{}': expected key=value", pair))?;
+        validate_key(key)?;
+        if annotations
+            .insert(key.to_string(), value.to_string())
+            .is_some()
+        {
+            return Err(anyhow!("Annotation '{}' is given more than once", key));
+        }
+    }
+    Ok(annotations)
+}
+
+pub fn validate_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.chars().any(|c| c.is_whitespace() || c == '=') {
+        return Err(anyhow!("Invalid annotation key '{}'", key));
+    }
+    Ok(())
+}
+
+/// `--annotation key=value` for each annotation, in key order. `run` and
+/// `build` take the same flag under podman and docker.
+pub fn args<'a>(annotations: impl IntoIterator<Item = (&'a String, &'a String)>) -> Vec<String> {
+    let sorted: BTreeMap<&String, &String> = annotations.into_iter().collect();
+    sorted
+        .into_iter()
+        .flat_map(|(key, value)| ["--annotation".to_string(), format!("{}={}", key, value)])
+        .collect()
+}
+
+fn string_map(value: Option<&serde_json::Value>) -> BTreeMap<String, String> {
+    value
+        .and_then(|v| v.as_object())
+        .map(|map| {
+            map.iter()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A container's annotations from the runtime's `inspect`: podman keeps them
+/// in `Config.Annotations`, docker in `HostConfig.Annotations`. Podman's own
+/// `io.podman.annotations.*` bookkeeping is left out.
+pub fn of_container(inspect: &serde_json::Value) -> BTreeMap<String, String> {
+    let mut annotations = string_map(inspect.pointer("/HostConfig/Annotations"));
+    annotations.extend(string_map(inspect.pointer("/Config/Annotations")));
+    annotations.retain(|key, _| !key.starts_with("io.podman.annotations."));
+    annotations
+}
+
+/// A local image's manifest annotations. Docker doesn't keep them, so this
+/// is empty there.
+pub async fn of_image(runtime: &str, image: &str) -> Result<BTreeMap<String, String>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["image", "inspect"])
+        .arg(image)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to inspect image {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let inspect: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let inspect = inspect.get(0).unwrap_or(&inspect);
+    Ok(string_map(inspect.get("Annotations")))
+}
+
+/// `annotation=key` or `annotation=key=value`, as given to `ps --filter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Self> {
+        let spec = filter.strip_prefix("annotation=").ok_or_else(|| {
+            anyhow!(
+                "Unsupported filter '{}': expected annotation=key[=value]",
+                filter
+            )
+        })?;
+        let (key, value) = match spec.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (spec, None),
+        };
+        validate_key(key)?;
+        Ok(Self {
+            key: key.to_string(),
+            value,
+        })
+    }
+
+    pub fn matches(&self, annotations: &BTreeMap<String, String>) -> bool {
+        annotations
+            .get(&self.key)
+            .is_some_and(|value| self.value.as_ref().is_none_or(|wanted| wanted == value))
+    }
+}
+
+/// Annotations of a Boltfile service or `bolt run`, checked
+pub fn validate(annotations: &HashMap<String, String>) -> Result<()> {
+    annotations.keys().try_for_each(|key| validate_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_filters_and_reads_annotations() {
+        let parsed = parse(&[
+            "org.opencontainers.image.source=https://git.example/app".to_string(),
+            "cdi.k8s.io/gpu=nvidia.com/gpu=0".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(parsed["cdi.k8s.io/gpu"], "nvidia.com/gpu=0");
+        assert_eq!(
+            args(&parsed),
+            [
+                "--annotation",
+                "cdi.k8s.io/gpu=nvidia.com/gpu=0",
+                "--annotation",
+                "org.opencontainers.image.source=https://git.example/app",
+            ]
+        );
+        assert!(parse(&["novalue".to_string()]).is_err());
+        assert!(parse(&["=x".to_string()]).is_err());
+        assert!(parse(&["a=1".to_string(), "a=2".to_string()]).is_err());
+
+        let podman = serde_json::json!({
+            "Config": { "Annotations": {
+                "io.podman.annotations.autoremove": "FALSE",
+                "team": "games",
+            }},
+        });
+        let docker = serde_json::json!({
+            "Config": {},
+            "HostConfig": { "Annotations": { "team": "games" } },
+        });
+        for inspect in [podman, docker] {
+            let annotations = of_container(&inspect);
+            assert_eq!(annotations.len(), 1);
+            assert!(
+                Filter::parse("annotation=team")
+                    .unwrap()
+                    .matches(&annotations)
+            );
+            assert!(
+                Filter::parse("annotation=team=games")
+                    .unwrap()
+                    .matches(&annotations)
+            );
+            assert!(
+                !Filter::parse("annotation=team=ops")
+                    .unwrap()
+                    .matches(&annotations)
+            );
+        }
+        assert!(Filter::parse("label=team").is_err());
+        assert!(Filter::parse("annotation=").is_err());
+    }
+}
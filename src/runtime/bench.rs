@@ -0,0 +1,679 @@
+// Host benchmarks
+//
+// `bolt bench` measures what Bolt actually does on this host, so a slower
+// kernel, driver or Bolt upgrade shows up as numbers rather than a feeling:
+//
+// - start: cold start (`run --rm` of a new container) and warm start
+//   (`start` of an existing one), median of the iterations
+// - pull: registry download and layer extraction throughput with Bolt's own
+//   registry client, into a scratch store
+// - proxy: round trip of a small request over loopback TCP and QUIC, the two
+//   transports the proxy carries traffic over
+// - volume: sequential write (fsync'd) and read throughput through a bind
+//   mounted volume, as dd inside a container reports it
+// - gpu: how much GPU passthrough adds to a container start
+//
+// A run is a scorecard. Saved as a named baseline under `<data_dir>/bench/`,
+// later runs are compared with it and metrics that got worse by more than the
+// threshold are flagged as regressions.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+pub const DEFAULT_IMAGE: &str = "docker.io/library/alpine:latest";
+pub const DEFAULT_BASELINE: &str = "default";
+const WARM_CONTAINER: &str = "bolt-bench-warm";
+const VOLUME_MB: u32 = 128;
+const PROXY_ROUND_TRIPS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Suite {
+    Start,
+    Pull,
+    Proxy,
+    Volume,
+    Gpu,
+}
+
+impl Suite {
+    pub const ALL: [Suite; 5] = [
+        Suite::Start,
+        Suite::Pull,
+        Suite::Proxy,
+        Suite::Volume,
+        Suite::Gpu,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Suite::Start => "start",
+            Suite::Pull => "pull",
+            Suite::Proxy => "proxy",
+            Suite::Volume => "volume",
+            Suite::Gpu => "gpu",
+        }
+    }
+}
+
+impl std::str::FromStr for Suite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Suite::ALL
+            .into_iter()
+            .find(|suite| suite.as_str() == s)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown benchmark '{}': use start, pull, proxy, volume or gpu",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub suites: Vec<Suite>,
+    pub image: String,
+    pub iterations: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            suites: Suite::ALL.to_vec(),
+            image: DEFAULT_IMAGE.to_string(),
+            iterations: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Better {
+    Lower,
+    Higher,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    /// e.g. "start.cold"
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+    pub better: Better,
+}
+
+impl Metric {
+    fn ms(name: &str, value: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            unit: "ms".to_string(),
+            better: Better::Lower,
+        }
+    }
+
+    fn us(name: &str, value: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            unit: "µs".to_string(),
+            better: Better::Lower,
+        }
+    }
+
+    fn mb_per_s(name: &str, value: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            unit: "MB/s".to_string(),
+            better: Better::Higher,
+        }
+    }
+}
+
+/// A suite that couldn't run here, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Skipped {
+    pub suite: Suite,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scorecard {
+    pub taken_at: DateTime<Utc>,
+    pub host: String,
+    pub bolt_version: String,
+    pub runtime: String,
+    pub kernel: String,
+    pub image: String,
+    pub metrics: Vec<Metric>,
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+}
+
+/// A metric next to its baseline value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparison {
+    pub metric: Metric,
+    pub baseline: Option<f64>,
+    /// Change against the baseline in percent, positive when worse
+    pub worse_by_pct: Option<f64>,
+    pub regressed: bool,
+}
+
+/// Compare a run with a baseline; a metric regressed when it's worse by
+/// more than `threshold_pct`
+pub fn compare(
+    current: &Scorecard,
+    baseline: Option<&Scorecard>,
+    threshold_pct: f64,
+) -> Vec<Comparison> {
+    current
+        .metrics
+        .iter()
+        .map(|metric| {
+            let base = baseline
+                .and_then(|b| b.metrics.iter().find(|m| m.name == metric.name))
+                .map(|m| m.value);
+            let worse_by_pct = base.filter(|b| *b > 0.0).map(|b| {
+                let change = (metric.value - b) / b * 100.0;
+                match metric.better {
+                    Better::Lower => change,
+                    Better::Higher => -change,
+                }
+            });
+            Comparison {
+                metric: metric.clone(),
+                baseline: base,
+                worse_by_pct,
+                regressed: worse_by_pct.is_some_and(|w| w > threshold_pct),
+            }
+        })
+        .collect()
+}
+
+fn bench_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("bench")
+}
+
+fn baseline_path(data_dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        || name.starts_with('.')
+    {
+        return Err(anyhow!("Invalid baseline name '{}'", name));
+    }
+    Ok(bench_dir(data_dir).join(format!("{}.json", name)))
+}
+
+pub fn save_baseline(data_dir: &Path, name: &str, scorecard: &Scorecard) -> Result<PathBuf> {
+    let path = baseline_path(data_dir, name)?;
+    std::fs::create_dir_all(bench_dir(data_dir))?;
+    std::fs::write(&path, serde_json::to_vec_pretty(scorecard)?)?;
+    Ok(path)
+}
+
+pub fn load_baseline(data_dir: &Path, name: &str) -> Result<Option<Scorecard>> {
+    let path = baseline_path(data_dir, name)?;
+    match std::fs::read(&path) {
+        Ok(data) => {
+            Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                format!("Failed to parse {}", path.display())
+            })?))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Names of the saved baselines
+pub fn list_baselines(data_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(bench_dir(data_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".json")
+                .filter(|n| *n != "last")
+                .map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+fn median(mut samples: Vec<f64>) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let mid = samples.len() / 2;
+    if samples.len() % 2 == 0 {
+        (samples[mid - 1] + samples[mid]) / 2.0
+    } else {
+        samples[mid]
+    }
+}
+
+/// Run a runtime command to completion and return how long it took
+async fn timed(runtime: &str, args: &[&str]) -> Result<f64> {
+    let started = Instant::now();
+    let output = AsyncCommand::new(runtime).args(args).output().await?;
+    let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`{} {}` failed: {}",
+            runtime,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(elapsed)
+}
+
+/// Run the selected suites and collect a scorecard
+pub async fn run(data_dir: &Path, options: &BenchOptions) -> Result<Scorecard> {
+    let runtime = super::detect_container_runtime().await?;
+    let mut scorecard = Scorecard {
+        taken_at: Utc::now(),
+        host: hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        bolt_version: env!("CARGO_PKG_VERSION").to_string(),
+        runtime: runtime.clone(),
+        kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .map(|k| k.trim().to_string())
+            .unwrap_or_default(),
+        image: options.image.clone(),
+        metrics: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let scratch = data_dir.join("tmp");
+    std::fs::create_dir_all(&scratch)?;
+
+    // Every container suite needs the image locally
+    let needs_image = options
+        .suites
+        .iter()
+        .any(|s| matches!(s, Suite::Start | Suite::Volume | Suite::Gpu));
+    if needs_image {
+        timed(&runtime, &["pull", "-q", &options.image]).await?;
+    }
+
+    for suite in &options.suites {
+        info!("⏱️  Benchmarking {}", suite.as_str());
+        let result = match suite {
+            Suite::Start => start(&runtime, options).await,
+            Suite::Pull => pull(&scratch, &options.image).await,
+            Suite::Proxy => proxy().await,
+            Suite::Volume => volume(&runtime, &scratch, &options.image).await,
+            Suite::Gpu => gpu(&runtime, options).await,
+        };
+        match result {
+            Ok(metrics) => scorecard.metrics.extend(metrics),
+            Err(e) => {
+                warn!("⚠️  Skipping the {} benchmark: {:#}", suite.as_str(), e);
+                scorecard.skipped.push(Skipped {
+                    suite: *suite,
+                    reason: format!("{:#}", e),
+                });
+            }
+        }
+    }
+
+    std::fs::create_dir_all(bench_dir(data_dir))?;
+    std::fs::write(
+        bench_dir(data_dir).join("last.json"),
+        serde_json::to_vec_pretty(&scorecard)?,
+    )?;
+    Ok(scorecard)
+}
+
+async fn start(runtime: &str, options: &BenchOptions) -> Result<Vec<Metric>> {
+    let mut cold = Vec::with_capacity(options.iterations);
+    for _ in 0..options.iterations {
+        cold.push(timed(runtime, &["run", "--rm", &options.image, "true"]).await?);
+    }
+
+    let _ = timed(runtime, &["rm", "-f", WARM_CONTAINER]).await;
+    timed(
+        runtime,
+        &["create", "--name", WARM_CONTAINER, &options.image, "true"],
+    )
+    .await?;
+    let mut warm = Vec::with_capacity(options.iterations);
+    let result = async {
+        for _ in 0..options.iterations {
+            warm.push(timed(runtime, &["start", "-a", WARM_CONTAINER]).await?);
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    let _ = timed(runtime, &["rm", "-f", WARM_CONTAINER]).await;
+    result?;
+
+    Ok(vec![
+        Metric::ms("start.cold", median(cold)),
+        Metric::ms("start.warm", median(warm)),
+    ])
+}
+
+async fn pull(scratch: &Path, image: &str) -> Result<Vec<Metric>> {
+    use super::storage::registry::{self, Reference, RegistryClient};
+
+    let store = tempfile::Builder::new()
+        .prefix("bolt-bench-")
+        .tempdir_in(scratch)?;
+    let mut client = RegistryClient::new(Reference::parse(image)?)?;
+    let reference = Reference::parse(image)?.manifest_reference().to_string();
+    let (mut manifest, _) = client.manifest(&reference).await?;
+    if let Some(ref entries) = manifest.manifests {
+        let digest = registry::select_manifest(image, entries, &super::platform::Platform::host())?
+            .digest
+            .clone();
+        (manifest, _) = client.manifest(&digest).await?;
+    }
+
+    let started = Instant::now();
+    let mut layers = Vec::with_capacity(manifest.layers.len());
+    for layer in &manifest.layers {
+        layers.push((layer, client.blob(layer, store.path()).await?));
+    }
+    let download_secs = started.elapsed().as_secs_f64();
+    let compressed: u64 = manifest.layers.iter().map(|l| l.size).sum();
+
+    let rootfs = store.path().join("rootfs");
+    std::fs::create_dir_all(&rootfs)?;
+    let started = Instant::now();
+    for (layer, path) in &layers {
+        registry::unpack_layer(path, &layer.media_type, &rootfs)?;
+    }
+    let extract_secs = started.elapsed().as_secs_f64();
+
+    let mb = compressed as f64 / 1_000_000.0;
+    Ok(vec![
+        Metric::mb_per_s("pull.download", mb / download_secs.max(1e-6)),
+        Metric::mb_per_s("pull.extract", mb / extract_secs.max(1e-6)),
+    ])
+}
+
+async fn proxy() -> Result<Vec<Metric>> {
+    let mut metrics = vec![Metric::us("proxy.tcp_rtt", tcp_rtt().await?)];
+    #[cfg(feature = "quic-networking")]
+    metrics.push(Metric::us("proxy.quic_rtt", quic_rtt().await?));
+    Ok(metrics)
+}
+
+/// Median round trip of a 64-byte echo over loopback TCP, in microseconds
+async fn tcp_rtt() -> Result<f64> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        stream.set_nodelay(true)?;
+        let mut buf = [0u8; 64];
+        for _ in 0..PROXY_ROUND_TRIPS {
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+    let mut buf = [7u8; 64];
+    let mut samples = Vec::with_capacity(PROXY_ROUND_TRIPS);
+    for _ in 0..PROXY_ROUND_TRIPS {
+        let started = Instant::now();
+        stream.write_all(&buf).await?;
+        stream.read_exact(&mut buf).await?;
+        samples.push(started.elapsed().as_secs_f64() * 1_000_000.0);
+    }
+    server.await??;
+    Ok(median(samples))
+}
+
+/// Accepts exactly the certificate the benchmark's server generated
+#[cfg(feature = "quic-networking")]
+struct PinnedCert(Vec<u8>);
+
+#[cfg(feature = "quic-networking")]
+impl quinn_rustls::client::ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &quinn_rustls::Certificate,
+        _intermediates: &[quinn_rustls::Certificate],
+        _server_name: &quinn_rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<quinn_rustls::client::ServerCertVerified, quinn_rustls::Error> {
+        if end_entity.0 == self.0 {
+            Ok(quinn_rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(quinn_rustls::Error::General(
+                "unexpected certificate".to_string(),
+            ))
+        }
+    }
+}
+
+/// Median round trip of a 64-byte echo over one loopback QUIC stream, in
+/// microseconds
+#[cfg(feature = "quic-networking")]
+async fn quic_rtt() -> Result<f64> {
+    use std::sync::Arc;
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let der = cert.serialize_der()?;
+    let key = cert.serialize_private_key_der();
+    let server_config = quinn::ServerConfig::with_single_cert(
+        vec![quinn_rustls::Certificate(der.clone())],
+        quinn_rustls::PrivateKey(key),
+    )?;
+    let server = quinn::Endpoint::server(server_config, "127.0.0.1:0".parse()?)?;
+    let addr = server.local_addr()?;
+    let echo = tokio::spawn(async move {
+        let connection = server
+            .accept()
+            .await
+            .ok_or_else(|| anyhow!("no QUIC connection"))?
+            .await?;
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let mut buf = [0u8; 64];
+        for _ in 0..PROXY_ROUND_TRIPS {
+            recv.read_exact(&mut buf).await?;
+            send.write_all(&buf).await?;
+        }
+        send.finish().await?;
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let crypto = quinn_rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCert(der)))
+        .with_no_client_auth();
+    let mut client = quinn::Endpoint::client("127.0.0.1:0".parse()?)?;
+    client.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    let connection = client.connect(addr, "localhost")?.await?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let mut buf = [7u8; 64];
+    let mut samples = Vec::with_capacity(PROXY_ROUND_TRIPS);
+    for _ in 0..PROXY_ROUND_TRIPS {
+        let started = Instant::now();
+        send.write_all(&buf).await?;
+        recv.read_exact(&mut buf).await?;
+        samples.push(started.elapsed().as_secs_f64() * 1_000_000.0);
+    }
+    echo.await??;
+    connection.close(quinn::VarInt::from_u32(0), b"done");
+    client.wait_idle().await;
+    Ok(median(samples))
+}
+
+/// Seconds dd reports for a copy: "... copied, 0.123 s, ..." (GNU) or
+/// "... copied, 0.123 seconds, ..." (busybox)
+pub fn dd_seconds(output: &str) -> Option<f64> {
+    let after = output.rsplit_once("copied, ")?.1;
+    after.split_whitespace().next()?.parse().ok()
+}
+
+async fn volume(runtime: &str, scratch: &Path, image: &str) -> Result<Vec<Metric>> {
+    let dir = tempfile::Builder::new()
+        .prefix("bolt-bench-volume-")
+        .tempdir_in(scratch)?;
+    let mount = format!("{}:/bench:Z", dir.path().display());
+    let count = format!("count={}", VOLUME_MB);
+
+    let dd = |script: Vec<String>| {
+        let mount = mount.clone();
+        async move {
+            let mut args = vec!["run", "--rm", "-v", &mount, image, "dd"];
+            args.extend(script.iter().map(String::as_str));
+            let output = AsyncCommand::new(runtime).args(&args).output().await?;
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            if !output.status.success() {
+                return Err(anyhow!("dd failed: {}", stderr.trim()));
+            }
+            dd_seconds(&stderr).ok_or_else(|| anyhow!("Unexpected dd output: {}", stderr.trim()))
+        }
+    };
+
+    let write = dd(vec![
+        "if=/dev/zero".to_string(),
+        "of=/bench/data".to_string(),
+        "bs=1M".to_string(),
+        count.clone(),
+        "conv=fsync".to_string(),
+    ])
+    .await?;
+    let read = dd(vec![
+        "if=/bench/data".to_string(),
+        "of=/dev/null".to_string(),
+        "bs=1M".to_string(),
+    ])
+    .await?;
+    let mb = VOLUME_MB as f64 * 1.048576;
+    Ok(vec![
+        Metric::mb_per_s("volume.write", mb / write.max(1e-6)),
+        Metric::mb_per_s("volume.read", mb / read.max(1e-6)),
+    ])
+}
+
+/// Run arguments that pass this host's GPUs through, if it has any
+#[cfg(feature = "gaming")]
+pub(crate) fn gpu_args(runtime: &str) -> Option<Vec<String>> {
+    use super::gpu::plugin;
+    let devices = plugin::discover();
+    (!devices.is_empty()).then(|| plugin::run_args(runtime, &devices))
+}
+
+#[cfg(not(feature = "gaming"))]
+pub(crate) fn gpu_args(runtime: &str) -> Option<Vec<String>> {
+    if Path::new("/dev/nvidia0").exists() {
+        return Some(if runtime == "docker" {
+            vec!["--gpus".to_string(), "all".to_string()]
+        } else {
+            vec!["--device".to_string(), "nvidia.com/gpu=all".to_string()]
+        });
+    }
+    Path::new("/dev/dri")
+        .exists()
+        .then(|| vec!["--device".to_string(), "/dev/dri".to_string()])
+}
+
+async fn gpu(runtime: &str, options: &BenchOptions) -> Result<Vec<Metric>> {
+    let passthrough = gpu_args(runtime).ok_or_else(|| anyhow!("no GPU on this host"))?;
+    let mut plain = Vec::with_capacity(options.iterations);
+    let mut with_gpu = Vec::with_capacity(options.iterations);
+    for _ in 0..options.iterations {
+        plain.push(timed(runtime, &["run", "--rm", &options.image, "true"]).await?);
+        let mut args = vec!["run", "--rm"];
+        args.extend(passthrough.iter().map(String::as_str));
+        args.extend([options.image.as_str(), "true"]);
+        with_gpu.push(timed(runtime, &args).await?);
+    }
+    Ok(vec![Metric::ms(
+        "gpu.start_overhead",
+        (median(with_gpu) - median(plain)).max(0.0),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_regressions_in_the_direction_that_is_worse() {
+        let card = |metrics: Vec<Metric>| Scorecard {
+            taken_at: Utc::now(),
+            host: "h".to_string(),
+            bolt_version: "0".to_string(),
+            runtime: "podman".to_string(),
+            kernel: String::new(),
+            image: DEFAULT_IMAGE.to_string(),
+            metrics,
+            skipped: Vec::new(),
+        };
+        let baseline = card(vec![
+            Metric::ms("start.cold", 400.0),
+            Metric::mb_per_s("pull.download", 100.0),
+            Metric::mb_per_s("volume.write", 500.0),
+        ]);
+        let current = card(vec![
+            Metric::ms("start.cold", 480.0),
+            Metric::mb_per_s("pull.download", 120.0),
+            Metric::mb_per_s("volume.write", 420.0),
+            Metric::us("proxy.tcp_rtt", 30.0),
+        ]);
+        let compared = compare(&current, Some(&baseline), 10.0);
+        let regressed: Vec<&str> = compared
+            .iter()
+            .filter(|c| c.regressed)
+            .map(|c| c.metric.name.as_str())
+            .collect();
+        assert_eq!(regressed, vec!["start.cold", "volume.write"]);
+        assert_eq!(compared[0].worse_by_pct.map(|w| w.round()), Some(20.0));
+        assert_eq!(compared[1].worse_by_pct.map(|w| w.round()), Some(-20.0));
+        assert!(compared[3].baseline.is_none() && !compared[3].regressed);
+
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), 2.5);
+        assert_eq!(
+            dd_seconds("134217728 bytes (134 MB, 128 MiB) copied, 0.25 s, 537 MB/s"),
+            Some(0.25)
+        );
+        assert_eq!(
+            dd_seconds(
+                "128+0 records in\n128+0 records out\n134217728 bytes (128.0MB) copied, 0.5 seconds, 256.0MB/s"
+            ),
+            Some(0.5)
+        );
+        assert!("disk".parse::<Suite>().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        save_baseline(dir.path(), "before-upgrade", &baseline).unwrap();
+        assert!(save_baseline(dir.path(), "../x", &baseline).is_err());
+        assert_eq!(list_baselines(dir.path()), vec!["before-upgrade"]);
+        assert_eq!(
+            load_baseline(dir.path(), "before-upgrade")
+                .unwrap()
+                .unwrap()
+                .metrics,
+            baseline.metrics
+        );
+        assert!(load_baseline(dir.path(), "missing").unwrap().is_none());
+    }
+}
@@ -0,0 +1,318 @@
+// Bulk container operations
+//
+// `bolt stop/rm/restart/update` take names, name globs (`web-*`), repeated
+// `--filter label=k[=v]` / `--filter name=<glob>` / `--filter status=<s>`
+// and `--all`; the matching containers are handled by a bounded pool of
+// concurrent operations and every outcome is reported, so one failing
+// container doesn't stop the rest.
+use anyhow::{Result, anyhow};
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::surge::dev::wildcard_match;
+use crate::types::ContainerInfo;
+
+pub const DEFAULT_PARALLEL: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `label=key` or `label=key=value`
+    Label(String, Option<String>),
+    /// `name=<glob>`
+    Name(String),
+    /// `status=<state>`, matched against the start of the runtime's status
+    Status(String),
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid filter '{}' (expected label=<key>[=<value>], name=<glob> or status=<state>)",
+                filter
+            )
+        };
+        let (kind, value) = filter.split_once('=').ok_or_else(invalid)?;
+        if value.is_empty() {
+            return Err(invalid());
+        }
+        match kind {
+            "label" => Ok(match value.split_once('=') {
+                Some((key, value)) => Filter::Label(key.to_string(), Some(value.to_string())),
+                None => Filter::Label(value.to_string(), None),
+            }),
+            "name" => Ok(Filter::Name(value.to_string())),
+            "status" => Ok(Filter::Status(value.to_lowercase())),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn matches(&self, container: &ContainerInfo, name: &str) -> bool {
+        match self {
+            Filter::Label(key, value) => container
+                .labels
+                .get(key)
+                .is_some_and(|v| value.as_ref().is_none_or(|value| v == value)),
+            Filter::Name(pattern) => wildcard_match(pattern, name),
+            Filter::Status(status) => container.status.to_lowercase().starts_with(status),
+        }
+    }
+}
+
+/// Which containers a bulk command applies to
+#[derive(Debug, Clone)]
+pub struct Selector {
+    /// Names, IDs or name globs
+    pub names: Vec<String>,
+    pub filters: Vec<Filter>,
+    pub all: bool,
+}
+
+impl Selector {
+    /// Selecting every container takes `all`; without names or filters
+    /// nothing is selected and this is an error
+    pub fn new(names: Vec<String>, filters: &[String], all: bool) -> Result<Self> {
+        if names.is_empty() && filters.is_empty() && !all {
+            return Err(anyhow!(
+                "No containers selected; give names, --filter or --all"
+            ));
+        }
+        Ok(Self {
+            names,
+            filters: filters
+                .iter()
+                .map(|f| Filter::parse(f))
+                .collect::<Result<_>>()?,
+            all,
+        })
+    }
+
+    /// Only plain names and IDs; nothing to look up
+    pub fn is_literal(&self) -> bool {
+        !self.all && self.filters.is_empty() && !self.names.iter().any(|n| is_glob(n))
+    }
+
+    /// The names selected from `containers`, given as (name shown to the
+    /// user, container) pairs. Plain names that aren't listed are kept so
+    /// the runtime reports them.
+    pub fn resolve(&self, containers: &[(String, ContainerInfo)]) -> Vec<String> {
+        let mut selected = Vec::new();
+        let every = self.all || (self.names.is_empty() && !self.filters.is_empty());
+        for (name, container) in containers {
+            let named = every
+                || self.names.iter().any(|n| {
+                    if is_glob(n) {
+                        wildcard_match(n, name)
+                    } else {
+                        n == name || (n.len() >= 3 && container.id.starts_with(n.as_str()))
+                    }
+                });
+            if named
+                && self.filters.iter().all(|f| f.matches(container, name))
+                && !selected.contains(name)
+            {
+                selected.push(name.clone());
+            }
+        }
+        if self.filters.is_empty() {
+            for name in &self.names {
+                let listed = containers.iter().any(|(n, c)| {
+                    n == name || (name.len() >= 3 && c.id.starts_with(name.as_str()))
+                });
+                if !is_glob(name) && !listed && !selected.contains(name) {
+                    selected.push(name.clone());
+                }
+            }
+        }
+        selected
+    }
+}
+
+fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub container: String,
+    pub result: Result<()>,
+    pub elapsed: Duration,
+}
+
+/// Per-container results of a bulk operation, in selection order
+#[derive(Debug)]
+pub struct Summary {
+    pub action: String,
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Summary {
+    pub fn failed(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_err()).count()
+    }
+
+    pub fn print(&self) {
+        println!("{:<30} {:<8} {:>8}", "CONTAINER", "RESULT", "TIME");
+        for outcome in &self.outcomes {
+            let result = match outcome.result {
+                Ok(()) => "ok",
+                Err(_) => "failed",
+            };
+            println!(
+                "{:<30} {:<8} {:>8}",
+                outcome.container,
+                result,
+                format!("{:.1}s", outcome.elapsed.as_secs_f64())
+            );
+            if let Err(ref e) = outcome.result {
+                println!("  ❌ {:#}", e);
+            }
+        }
+        let failed = self.failed();
+        println!(
+            "{} {}: {} succeeded, {} failed",
+            self.action,
+            self.outcomes.len(),
+            self.outcomes.len() - failed,
+            failed
+        );
+    }
+
+    /// 0 when every container succeeded, 1 when some failed and 2 when all did
+    pub fn exit_code(&self) -> i32 {
+        match self.failed() {
+            0 => 0,
+            n if n == self.outcomes.len() => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Apply `op` to each container, at most `parallel` at a time
+pub async fn run<F, Fut>(action: &str, containers: Vec<String>, parallel: usize, op: F) -> Summary
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut outcomes: Vec<(usize, Outcome)> = stream::iter(containers.into_iter().enumerate())
+        .map(|(i, container)| {
+            let started = Instant::now();
+            let op = op(container.clone());
+            async move {
+                let result = op.await;
+                (
+                    i,
+                    Outcome {
+                        container,
+                        result,
+                        elapsed: started.elapsed(),
+                    },
+                )
+            }
+        })
+        .buffer_unordered(parallel.max(1))
+        .collect()
+        .await;
+    outcomes.sort_by_key(|(i, _)| *i);
+    Summary {
+        action: action.to_string(),
+        outcomes: outcomes.into_iter().map(|(_, o)| o).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn container(
+        name: &str,
+        id: &str,
+        status: &str,
+        labels: &[(&str, &str)],
+    ) -> (String, ContainerInfo) {
+        let info = ContainerInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            names: vec![name.to_string()],
+            image: "nginx".to_string(),
+            image_id: String::new(),
+            command: String::new(),
+            created: String::new(),
+            status: status.to_string(),
+            ports: Vec::new(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            uptime: None,
+            runtime: None,
+        };
+        (name.to_string(), info)
+    }
+
+    #[tokio::test]
+    async fn selects_and_reports_containers() {
+        let containers = vec![
+            container("web-1", "aaa111", "Up 2 minutes", &[("project", "x")]),
+            container("web-2", "bbb222", "Exited (0)", &[("project", "y")]),
+            container("db", "ccc333", "Up 1 hour", &[("project", "x")]),
+        ];
+
+        let glob = Selector::new(vec!["web-*".to_string()], &[], false).unwrap();
+        assert!(!glob.is_literal());
+        assert_eq!(glob.resolve(&containers), ["web-1", "web-2"]);
+
+        let label = Selector::new(Vec::new(), &["label=project=x".to_string()], false).unwrap();
+        assert_eq!(label.resolve(&containers), ["web-1", "db"]);
+
+        let both = Selector::new(
+            vec!["web-*".to_string()],
+            &["label=project".to_string(), "status=up".to_string()],
+            false,
+        )
+        .unwrap();
+        assert_eq!(both.resolve(&containers), ["web-1"]);
+
+        let all = Selector::new(Vec::new(), &[], true).unwrap();
+        assert_eq!(all.resolve(&containers).len(), 3);
+        assert!(Selector::new(Vec::new(), &[], false).is_err());
+        let empty = Selector {
+            names: Vec::new(),
+            filters: Vec::new(),
+            all: false,
+        };
+        assert!(empty.resolve(&containers).is_empty());
+
+        let literal =
+            Selector::new(vec!["ccc333".to_string(), "gone".to_string()], &[], false).unwrap();
+        assert!(literal.is_literal());
+        assert_eq!(literal.resolve(&containers), ["db", "gone"]);
+
+        assert!(Filter::parse("label=").is_err());
+        assert!(Filter::parse("image=nginx").is_err());
+
+        let summary = run(
+            "Stopped",
+            vec!["a".into(), "b".into(), "c".into()],
+            2,
+            |name| async move {
+                if name == "b" {
+                    Err(anyhow!("no such container"))
+                } else {
+                    Ok(())
+                }
+            },
+        )
+        .await;
+        let names: Vec<_> = summary
+            .outcomes
+            .iter()
+            .map(|o| o.container.as_str())
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(summary.exit_code(), 1);
+    }
+}
@@ -0,0 +1,352 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::process::Command as AsyncCommand;
+
+/// Whether a single capability can be used with this build on this host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capability {
+    /// Support was compiled into this build
+    pub compiled: bool,
+    /// The host provides what the feature needs
+    pub host_supported: bool,
+    /// Why the capability is unavailable, when it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Capability {
+    fn new(compiled: bool, host: Result<(), String>) -> Self {
+        let host_supported = host.is_ok();
+        let reason = if !compiled {
+            Some("not compiled into this build".to_string())
+        } else {
+            host.err()
+        };
+
+        Self {
+            compiled,
+            host_supported,
+            reason,
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        self.compiled && self.host_supported
+    }
+}
+
+/// What this Bolt binary supports, for GUIs and scripts to adapt to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub version: String,
+    /// Cargo features enabled at build time
+    pub features: BTreeMap<String, bool>,
+    /// Container backend used for podman/docker operations, if any
+    pub container_runtime: Option<String>,
+    pub kernel_version: Option<String>,
+    pub capabilities: BTreeMap<String, Capability>,
+}
+
+impl Capabilities {
+    pub fn is_available(&self, name: &str) -> bool {
+        self.capabilities.get(name).is_some_and(|c| c.available())
+    }
+
+    /// Detect compile-time features and probe the host
+    pub async fn detect() -> Self {
+        let features = BTreeMap::from([
+            ("gaming".to_string(), cfg!(feature = "gaming")),
+            (
+                "quic-networking".to_string(),
+                cfg!(feature = "quic-networking"),
+            ),
+            ("oci-runtime".to_string(), cfg!(feature = "oci-runtime")),
+            (
+                "nvidia-support".to_string(),
+                cfg!(feature = "nvidia-support"),
+            ),
+            ("amd-support".to_string(), cfg!(feature = "amd-support")),
+            (
+                "nvbind-support".to_string(),
+                cfg!(feature = "nvbind-support"),
+            ),
+            ("wasm-runtime".to_string(), cfg!(feature = "wasm-runtime")),
+        ]);
+
+        let container_runtime = super::detect_container_runtime().await.ok();
+        let kernel_version = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .ok()
+            .map(|v| v.trim().to_string());
+
+        let mut capabilities = BTreeMap::new();
+        capabilities.insert(
+            "container_runtime".to_string(),
+            Capability::new(
+                true,
+                container_runtime
+                    .as_ref()
+                    .map(|_| ())
+                    .ok_or_else(|| "neither podman nor docker is installed".to_string()),
+            ),
+        );
+        capabilities.insert(
+            "oci_runtime".to_string(),
+            Capability::new(cfg!(feature = "oci-runtime"), check_cgroup_v2()),
+        );
+        capabilities.insert(
+            "quic".to_string(),
+            Capability::new(cfg!(feature = "quic-networking"), Ok(())),
+        );
+        capabilities.insert("ebpf".to_string(), Capability::new(true, check_ebpf()));
+        capabilities.insert(
+            "nvbind".to_string(),
+            Capability::new(cfg!(feature = "nvbind-support"), check_nvidia_gpu()),
+        );
+        capabilities.insert(
+            "nvidia_gpu".to_string(),
+            Capability::new(true, check_nvidia_gpu()),
+        );
+        capabilities.insert(
+            "amd_gpu".to_string(),
+            Capability::new(true, check_amd_gpu()),
+        );
+        // Capsules are containers; there is no VM backend to offer yet
+        capabilities.insert(
+            "vm_capsules".to_string(),
+            Capability::new(false, check_kvm()),
+        );
+        capabilities.insert(
+            "rootless".to_string(),
+            Capability::new(true, check_user_namespaces()),
+        );
+        capabilities.insert("kvm".to_string(), Capability::new(true, check_kvm()));
+        capabilities.insert(
+            "gaming".to_string(),
+            Capability::new(cfg!(feature = "gaming"), check_wayland_or_x11()),
+        );
+        // Bolt has no io_uring I/O path yet; the host probe is still reported
+        // so tooling can tell the two apart
+        capabilities.insert(
+            "io_uring".to_string(),
+            Capability::new(false, check_io_uring(kernel_version.as_deref())),
+        );
+        // WASI modules run in-process under wasmtime
+        capabilities.insert(
+            "wasm".to_string(),
+            Capability::new(cfg!(feature = "wasm-runtime"), check_wasm()),
+        );
+        capabilities.insert(
+            "nix_images".to_string(),
+            Capability::new(true, check_binary("nix").await),
+        );
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features,
+            container_runtime,
+            kernel_version,
+            capabilities,
+        }
+    }
+}
+
+pub(crate) fn effective_uid() -> Option<u32> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|l| l.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().nth(1))
+        .and_then(|uid| uid.parse().ok())
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn check_cgroup_v2() -> Result<(), String> {
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        Ok(())
+    } else {
+        Err("cgroup v2 unified hierarchy not mounted".to_string())
+    }
+}
+
+fn check_ebpf() -> Result<(), String> {
+    if !Path::new("/sys/fs/bpf").exists() {
+        return Err("bpffs not mounted at /sys/fs/bpf".to_string());
+    }
+    if effective_uid() == Some(0) {
+        return Ok(());
+    }
+    match read_trimmed("/proc/sys/kernel/unprivileged_bpf_disabled").as_deref() {
+        Some("0") => Ok(()),
+        _ => Err("requires root (unprivileged BPF is disabled)".to_string()),
+    }
+}
+
+fn check_nvidia_gpu() -> Result<(), String> {
+    if Path::new("/dev/nvidiactl").exists() {
+        Ok(())
+    } else {
+        Err("no NVIDIA driver device nodes (/dev/nvidiactl)".to_string())
+    }
+}
+
+fn check_amd_gpu() -> Result<(), String> {
+    if Path::new("/dev/kfd").exists() {
+        Ok(())
+    } else {
+        Err("no AMD compute device (/dev/kfd)".to_string())
+    }
+}
+
+fn check_user_namespaces() -> Result<(), String> {
+    match read_trimmed("/proc/sys/user/max_user_namespaces").and_then(|n| n.parse::<u64>().ok()) {
+        Some(0) => Err("user namespaces are disabled".to_string()),
+        Some(_) => Ok(()),
+        None => Err("cannot determine user namespace support".to_string()),
+    }
+}
+
+fn check_kvm() -> Result<(), String> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/kvm")
+        .map(|_| ())
+        .map_err(|e| format!("/dev/kvm not accessible: {}", e))
+}
+
+fn check_wayland_or_x11() -> Result<(), String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_some() {
+        Ok(())
+    } else {
+        Err("no Wayland or X11 display session".to_string())
+    }
+}
+
+fn check_wasm() -> Result<(), String> {
+    if cfg!(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64",
+        target_arch = "s390x"
+    )) {
+        Ok(())
+    } else {
+        Err(format!(
+            "wasmtime has no compiler for {}",
+            std::env::consts::ARCH
+        ))
+    }
+}
+
+fn check_io_uring(kernel_version: Option<&str>) -> Result<(), String> {
+    if matches!(
+        read_trimmed("/proc/sys/kernel/io_uring_disabled").as_deref(),
+        Some("2")
+    ) {
+        return Err("io_uring disabled by kernel.io_uring_disabled".to_string());
+    }
+
+    let (major, minor) = kernel_version
+        .and_then(|v| {
+            let mut parts = v.split(|c: char| !c.is_ascii_digit());
+            Some((
+                parts.next()?.parse::<u32>().ok()?,
+                parts.next()?.parse::<u32>().ok()?,
+            ))
+        })
+        .ok_or_else(|| "unknown kernel version".to_string())?;
+
+    if (major, minor) >= (5, 6) {
+        Ok(())
+    } else {
+        Err(format!("kernel {}.{} is older than 5.6", major, minor))
+    }
+}
+
+async fn check_binary(name: &str) -> Result<(), String> {
+    match AsyncCommand::new(name).arg("--version").output().await {
+        Ok(output) if output.status.success() => Ok(()),
+        _ => Err(format!("{} not found in PATH", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_why_a_capability_is_unavailable() {
+        let usable = Capability::new(true, Ok(()));
+        assert!(usable.available());
+        assert_eq!(
+            serde_json::to_value(&usable).unwrap(),
+            serde_json::json!({"compiled": true, "host_supported": true})
+        );
+
+        let missing_host = Capability::new(true, Err("no /dev/kvm".to_string()));
+        assert!(!missing_host.available());
+        assert_eq!(missing_host.reason.as_deref(), Some("no /dev/kvm"));
+
+        // Not being compiled in is the reason, whatever the host supports
+        let not_compiled = Capability::new(false, Err("no /dev/kvm".to_string()));
+        assert!(!not_compiled.host_supported);
+        assert_eq!(
+            serde_json::to_value(&not_compiled).unwrap(),
+            serde_json::json!({
+                "compiled": false,
+                "host_supported": false,
+                "reason": "not compiled into this build",
+            })
+        );
+
+        if read_trimmed("/proc/sys/kernel/io_uring_disabled").as_deref() != Some("2") {
+            assert!(check_io_uring(Some("6.8.0-45-generic")).is_ok());
+            assert_eq!(
+                check_io_uring(Some("5.4.0")).unwrap_err(),
+                "kernel 5.4 is older than 5.6"
+            );
+            assert!(check_io_uring(None).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn detects_build_features_and_round_trips() {
+        let detected = Capabilities::detect().await;
+        assert_eq!(detected.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(detected.features["gaming"], cfg!(feature = "gaming"));
+        assert_eq!(
+            detected.features["wasm-runtime"],
+            cfg!(feature = "wasm-runtime")
+        );
+        assert_eq!(
+            detected.capabilities["wasm"].compiled,
+            cfg!(feature = "wasm-runtime")
+        );
+        assert_eq!(
+            detected.features["oci-runtime"],
+            cfg!(feature = "oci-runtime")
+        );
+        assert!(!detected.capabilities["io_uring"].compiled);
+        assert!(!detected.is_available("io_uring"));
+        assert!(!detected.is_available("vm_capsules"));
+        assert!(!detected.is_available("no-such-capability"));
+
+        let json = serde_json::to_string(&detected).unwrap();
+        let parsed: Capabilities = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.features, detected.features);
+        assert_eq!(
+            parsed.capabilities.keys().collect::<Vec<_>>(),
+            detected.capabilities.keys().collect::<Vec<_>>()
+        );
+        for (name, capability) in &parsed.capabilities {
+            assert_eq!(capability.available(), detected.is_available(name));
+            assert_eq!(capability.reason.is_none(), capability.available());
+        }
+    }
+}
@@ -0,0 +1,852 @@
+// cgroup v1 compatibility
+//
+// Resource limits were written straight to the cgroup v2 unified hierarchy.
+// Older distros still boot with legacy (v1 only) or hybrid (v1 controllers
+// plus an empty v2 mount at /sys/fs/cgroup/unified) cgroups, where those
+// files don't exist and limits went nowhere. The mode is detected from
+// /proc/self/mountinfo and every limit goes to the v2 file or its v1
+// equivalent:
+//
+// - memory: memory.max -> memory.limit_in_bytes
+// - cpu: cpu.max -> cpu.cfs_quota_us + cpu.cfs_period_us, cpu.weight -> cpu.shares
+// - pids: pids.max on both
+// - io: io.weight -> blkio.weight, io.max -> blkio.throttle.*_iops_device
+// - devices: rules go to devices.allow on v1 (v2 needs an eBPF program)
+// - freezer: cgroup.freeze -> freezer.state
+//
+// What v1 can't do, such as pressure stall information, is reported once as
+// a warning rather than dropped quietly.
+//
+// Usage is read back the same way (memory.current or memory.usage_in_bytes,
+// and so on) for the metrics collector, from a container's cgroup or the
+// cgroup of any process.
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, warn};
+
+use crate::runtime::oci::ResourceLimits;
+
+/// Parent of every container cgroup, relative to each hierarchy's root
+const BOLT_CGROUP: &str = "bolt";
+
+/// Limits at or above this are "unlimited": v1 reports no memory limit as
+/// i64::MAX rounded down to a page
+const UNLIMITED: u64 = 1 << 62;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CgroupMode {
+    /// cgroup v2 only
+    Unified,
+    /// v1 controllers with a controller-less v2 mount next to them
+    Hybrid,
+    /// cgroup v1 only
+    Legacy,
+    /// No cgroup filesystem mounted
+    #[default]
+    Unavailable,
+}
+
+impl std::fmt::Display for CgroupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CgroupMode::Unified => "v2 (unified)",
+            CgroupMode::Hybrid => "hybrid (v1 controllers)",
+            CgroupMode::Legacy => "v1 (legacy)",
+            CgroupMode::Unavailable => "not mounted",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Controller {
+    Cpu,
+    /// CPU accounting; v2 folds it into the cpu controller
+    Cpuacct,
+    Memory,
+    Pids,
+    Blkio,
+    Devices,
+    Freezer,
+}
+
+impl Controller {
+    pub const ALL: [Controller; 7] = [
+        Controller::Cpu,
+        Controller::Cpuacct,
+        Controller::Memory,
+        Controller::Pids,
+        Controller::Blkio,
+        Controller::Devices,
+        Controller::Freezer,
+    ];
+
+    /// Name of the controller in v1 mount options
+    pub fn v1_name(self) -> &'static str {
+        match self {
+            Controller::Cpu => "cpu",
+            Controller::Cpuacct => "cpuacct",
+            Controller::Memory => "memory",
+            Controller::Pids => "pids",
+            Controller::Blkio => "blkio",
+            Controller::Devices => "devices",
+            Controller::Freezer => "freezer",
+        }
+    }
+
+    /// Name to enable in cgroup.subtree_control; devices and freezer are
+    /// built into v2 and cannot be enabled
+    pub fn v2_name(self) -> Option<&'static str> {
+        match self {
+            Controller::Cpu | Controller::Cpuacct => Some("cpu"),
+            Controller::Memory => Some("memory"),
+            Controller::Pids => Some("pids"),
+            Controller::Blkio => Some("io"),
+            Controller::Devices | Controller::Freezer => None,
+        }
+    }
+
+    /// The limits lost when a v1 host doesn't mount the controller
+    fn limits(self) -> &'static str {
+        match self {
+            Controller::Cpu => "CPU limits and weights",
+            Controller::Cpuacct => "CPU usage accounting",
+            Controller::Memory => "memory limits",
+            Controller::Pids => "process count limits",
+            Controller::Blkio => "I/O weights",
+            Controller::Devices => "device rules",
+            Controller::Freezer => "freezing",
+        }
+    }
+}
+
+/// Where the cgroup hierarchies are mounted on this host
+#[derive(Debug, Clone, Default)]
+pub struct CgroupLayout {
+    pub mode: CgroupMode,
+    /// Mount point of the v2 hierarchy, if any
+    pub unified: Option<PathBuf>,
+    /// Mount point of each mounted v1 controller
+    pub v1: HashMap<Controller, PathBuf>,
+}
+
+impl CgroupLayout {
+    /// The host's layout, read from /proc/self/mountinfo once
+    pub fn current() -> &'static CgroupLayout {
+        static LAYOUT: OnceLock<CgroupLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            std::fs::read_to_string("/proc/self/mountinfo")
+                .map(|mountinfo| Self::parse(&mountinfo))
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn parse(mountinfo: &str) -> Self {
+        let mut layout = Self::default();
+        for line in mountinfo.lines() {
+            // <id> <parent> <dev> <root> <mount point> <options> [tags] - <fs> <source> <super options>
+            let Some((mount, fs)) = line.split_once(" - ") else {
+                continue;
+            };
+            let Some(point) = mount.split_whitespace().nth(4) else {
+                continue;
+            };
+            let mut fs = fs.split_whitespace();
+            match (fs.next(), fs.nth(1)) {
+                (Some("cgroup2"), _) => {
+                    layout.unified.get_or_insert_with(|| PathBuf::from(point));
+                }
+                (Some("cgroup"), Some(options)) => {
+                    for option in options.split(',') {
+                        if let Some(controller) =
+                            Controller::ALL.into_iter().find(|c| c.v1_name() == option)
+                        {
+                            layout
+                                .v1
+                                .entry(controller)
+                                .or_insert_with(|| PathBuf::from(point));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        layout.mode = match (layout.unified.is_some(), layout.v1.is_empty()) {
+            (true, true) => CgroupMode::Unified,
+            (true, false) => CgroupMode::Hybrid,
+            (false, false) => CgroupMode::Legacy,
+            (false, true) => CgroupMode::Unavailable,
+        };
+        layout
+    }
+
+    pub fn is_v1(&self) -> bool {
+        matches!(self.mode, CgroupMode::Hybrid | CgroupMode::Legacy)
+    }
+
+    /// Features this host can't provide, one line each
+    pub fn degradations(&self) -> Vec<String> {
+        match self.mode {
+            CgroupMode::Unified => Vec::new(),
+            CgroupMode::Unavailable => {
+                vec!["no cgroup hierarchy is mounted, resource limits are not applied".to_string()]
+            }
+            CgroupMode::Hybrid | CgroupMode::Legacy => {
+                let mut degradations = vec![
+                    "no pressure stall information (PSI), CPU/memory/I/O pressure is not reported"
+                        .to_string(),
+                    "no memory.high, memory limits are hard limits only".to_string(),
+                    "I/O weights use blkio.weight, which only the BFQ scheduler honours"
+                        .to_string(),
+                ];
+                degradations.extend(
+                    Controller::ALL
+                        .into_iter()
+                        .filter(|c| !self.v1.contains_key(c))
+                        .map(|c| {
+                            format!("{} controller not mounted, no {}", c.v1_name(), c.limits())
+                        }),
+                );
+                degradations
+            }
+        }
+    }
+
+    /// Log the degradations once per process
+    pub fn warn_degradations(&self) {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| {
+            for degradation in self.degradations() {
+                warn!("⚠️  cgroup {}: {}", self.mode, degradation);
+            }
+        });
+    }
+}
+
+/// A container's cgroup: one v2 directory, or one directory per v1 controller
+#[derive(Debug, Clone, Default)]
+pub struct ContainerCgroup {
+    v2: Option<PathBuf>,
+    v1: HashMap<Controller, PathBuf>,
+}
+
+impl ContainerCgroup {
+    /// Paths of the container's cgroup under `bolt/<id>`; nothing is created
+    pub fn new(layout: &CgroupLayout, id: &str) -> Self {
+        let relative = Path::new(BOLT_CGROUP).join(id);
+        match layout.mode {
+            CgroupMode::Unified => Self {
+                v2: layout.unified.as_ref().map(|root| root.join(&relative)),
+                v1: HashMap::new(),
+            },
+            CgroupMode::Hybrid | CgroupMode::Legacy => Self {
+                v2: None,
+                v1: layout
+                    .v1
+                    .iter()
+                    .map(|(controller, root)| (*controller, root.join(&relative)))
+                    .collect(),
+            },
+            CgroupMode::Unavailable => Self::default(),
+        }
+    }
+
+    /// The cgroup a process is in, whether or not bolt created it
+    pub fn of_process(pid: u32) -> Option<Self> {
+        let layout = CgroupLayout::current();
+        if !layout.is_v1() {
+            return Some(Self {
+                v2: Some(unified_dir(pid)?),
+                v1: HashMap::new(),
+            });
+        }
+        let membership = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+        let mut v1 = HashMap::new();
+        // <hierarchy id>:<controllers>:<path>
+        for line in membership.lines() {
+            let mut fields = line.splitn(3, ':');
+            let (Some(controllers), Some(path)) = (fields.nth(1), fields.next()) else {
+                continue;
+            };
+            for name in controllers.split(',') {
+                if let Some((controller, root)) =
+                    layout.v1.iter().find(|(c, _)| c.v1_name() == name)
+                {
+                    v1.insert(*controller, root.join(path.trim().trim_start_matches('/')));
+                }
+            }
+        }
+        (!v1.is_empty()).then_some(Self { v2: None, v1 })
+    }
+
+    /// Whether the cgroup has been created
+    pub fn exists(&self) -> bool {
+        let dirs = self.dirs();
+        !dirs.is_empty() && dirs.iter().all(|dir| dir.is_dir())
+    }
+
+    /// Directory holding the controller's files
+    pub fn path(&self, controller: Controller) -> Option<&Path> {
+        self.v2
+            .as_deref()
+            .or_else(|| self.v1.get(&controller).map(PathBuf::as_path))
+    }
+
+    /// Every directory of the cgroup; co-mounted v1 controllers share one
+    fn dirs(&self) -> Vec<&PathBuf> {
+        let mut dirs: Vec<&PathBuf> = self.v2.iter().chain(self.v1.values()).collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Create the cgroup. On v2 the controllers are delegated to it through
+    /// the parent's subtree_control; on v1 every mounted controller gets a
+    /// directory.
+    pub fn create(&self, controllers: &[Controller]) -> Result<()> {
+        if let Some(dir) = &self.v2 {
+            let parent = dir.parent().unwrap_or(dir);
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+            let mut enable: Vec<String> = controllers
+                .iter()
+                .filter_map(|c| c.v2_name())
+                .map(|name| format!("+{}", name))
+                .collect();
+            enable.dedup();
+            if !enable.is_empty() {
+                let subtree = parent.join("cgroup.subtree_control");
+                if let Err(e) = std::fs::write(&subtree, enable.join(" ")) {
+                    warn!(
+                        "Failed to enable cgroup controllers {}: {}",
+                        enable.join(" "),
+                        e
+                    );
+                }
+            }
+        }
+        for dir in self.dirs() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cgroup {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, controller: Controller, file: &str, value: &str) -> Result<()> {
+        let dir = self.path(controller).ok_or_else(|| {
+            anyhow::anyhow!("{} controller is not available", controller.v1_name())
+        })?;
+        let path = dir.join(file);
+        std::fs::write(&path, value).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn read(&self, controller: Controller, file: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(controller)?.join(file)).ok()
+    }
+
+    fn read_number(&self, controller: Controller, file: &str) -> Option<u64> {
+        self.read(controller, file)?.trim().parse().ok()
+    }
+
+    /// Write every limit that is set and leave the others as they are.
+    /// `io_device` is the disk `io_limit` applies to. A failing limit doesn't
+    /// stop the rest; the failures are reported together.
+    pub fn apply(&self, limits: &ResourceLimits, io_device: Option<(u64, u64)>) -> Result<()> {
+        let mut results: Vec<(&str, Result<()>)> = Vec::new();
+        if let Some(bytes) = limits.memory_limit {
+            results.push(("memory", self.set_memory_limit(bytes)));
+        }
+        if let Some(cores) = limits.cpu_limit {
+            results.push(("cpu", self.set_cpu_limit(cores)));
+        }
+        if let Some(max) = limits.pids_limit {
+            results.push(("pids", self.set_pids_limit(max)));
+        }
+        if let Some(shares) = limits.cpu_shares {
+            results.push(("cpu shares", self.set_cpu_shares(shares.into())));
+        }
+        if let Some(weight) = limits.blkio_weight {
+            results.push(("blkio weight", self.set_blkio_weight(weight.into())));
+        }
+        if let Some(iops) = limits.io_limit {
+            let result = match io_device {
+                Some(device) => self.set_iops_limit(device, iops),
+                None => Err(anyhow::anyhow!("no block device to limit")),
+            };
+            results.push(("iops", result));
+        }
+
+        let failed: Vec<String> = results
+            .into_iter()
+            .filter_map(|(limit, result)| result.err().map(|e| format!("{}: {:#}", limit, e)))
+            .collect();
+        if !failed.is_empty() {
+            anyhow::bail!("Failed to apply limits to {}: {}", self, failed.join("; "));
+        }
+        Ok(())
+    }
+
+    pub fn set_memory_limit(&self, bytes: u64) -> Result<()> {
+        let file = if self.v2.is_some() {
+            "memory.max"
+        } else {
+            "memory.limit_in_bytes"
+        };
+        self.write(Controller::Memory, file, &bytes.to_string())
+    }
+
+    pub fn set_cpu_limit(&self, cores: f64) -> Result<()> {
+        let period_us = 100_000u64;
+        let quota_us = (cores * period_us as f64) as u64;
+        if self.v2.is_some() {
+            self.write(
+                Controller::Cpu,
+                "cpu.max",
+                &format!("{} {}", quota_us, period_us),
+            )
+        } else {
+            self.write(Controller::Cpu, "cpu.cfs_period_us", &period_us.to_string())?;
+            self.write(Controller::Cpu, "cpu.cfs_quota_us", &quota_us.to_string())
+        }
+    }
+
+    pub fn set_pids_limit(&self, max: u32) -> Result<()> {
+        self.write(Controller::Pids, "pids.max", &max.to_string())
+    }
+
+    /// Relative CPU weight on the v2 scale (1-10000, default 100)
+    pub fn set_cpu_weight(&self, weight: u64) -> Result<()> {
+        if self.v2.is_some() {
+            self.write(Controller::Cpu, "cpu.weight", &weight.to_string())
+        } else {
+            let shares = weight_to_shares(weight);
+            self.write(Controller::Cpu, "cpu.shares", &shares.to_string())
+        }
+    }
+
+    /// Relative I/O weight on the v2 scale (1-10000, default 100)
+    pub fn set_io_weight(&self, weight: u64) -> Result<()> {
+        if self.v2.is_some() {
+            self.write(Controller::Blkio, "io.weight", &weight.to_string())
+        } else {
+            let blkio = weight_to_blkio(weight);
+            self.write(Controller::Blkio, "blkio.weight", &blkio.to_string())
+        }
+    }
+
+    /// Relative CPU weight on the v1 cpu.shares scale (2-262144, default 1024)
+    pub fn set_cpu_shares(&self, shares: u64) -> Result<()> {
+        if self.v2.is_some() {
+            let weight = shares_to_weight(shares);
+            self.write(Controller::Cpu, "cpu.weight", &weight.to_string())
+        } else {
+            let shares = shares.clamp(2, 262_144);
+            self.write(Controller::Cpu, "cpu.shares", &shares.to_string())
+        }
+    }
+
+    /// Relative I/O weight on the v1 blkio.weight scale (10-1000, default 500)
+    pub fn set_blkio_weight(&self, weight: u64) -> Result<()> {
+        if self.v2.is_some() {
+            let weight = blkio_to_weight(weight);
+            self.write(Controller::Blkio, "io.weight", &weight.to_string())
+        } else {
+            let weight = weight.clamp(10, 1000);
+            self.write(Controller::Blkio, "blkio.weight", &weight.to_string())
+        }
+    }
+
+    /// Cap read and write operations per second on a (major, minor) block
+    /// device; 0 lifts the cap
+    pub fn set_iops_limit(&self, device: (u64, u64), iops: u32) -> Result<()> {
+        let (major, minor) = device;
+        if self.v2.is_some() {
+            let iops = match iops {
+                0 => "max".to_string(),
+                iops => iops.to_string(),
+            };
+            self.write(
+                Controller::Blkio,
+                "io.max",
+                &format!("{}:{} riops={} wiops={}", major, minor, iops, iops),
+            )
+        } else {
+            let rule = format!("{}:{} {}", major, minor, iops);
+            self.write(Controller::Blkio, "blkio.throttle.read_iops_device", &rule)?;
+            self.write(Controller::Blkio, "blkio.throttle.write_iops_device", &rule)
+        }
+    }
+
+    /// Allow device access rules such as `c 226:* rwm`. v2 has no device
+    /// files, so the rules are left to the device mounts there.
+    pub fn allow_devices(&self, rules: &[&str]) -> Result<()> {
+        if self.v2.is_some() {
+            debug!("cgroup v2 device rules need an eBPF program, relying on device mounts");
+            return Ok(());
+        }
+        for rule in rules {
+            self.write(Controller::Devices, "devices.allow", rule)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_frozen(&self, frozen: bool) -> Result<()> {
+        if self.v2.is_some() {
+            self.write(
+                Controller::Freezer,
+                "cgroup.freeze",
+                if frozen { "1" } else { "0" },
+            )
+        } else {
+            let state = if frozen { "FROZEN" } else { "THAWED" };
+            self.write(Controller::Freezer, "freezer.state", state)
+        }
+    }
+
+    /// Move a process into the cgroup, in every v1 hierarchy
+    pub fn add_pid(&self, pid: u32) -> Result<()> {
+        let dirs = self.dirs();
+        if dirs.is_empty() {
+            anyhow::bail!("no cgroup hierarchy is mounted");
+        }
+        for dir in dirs {
+            let procs = dir.join("cgroup.procs");
+            std::fs::write(&procs, pid.to_string())
+                .with_context(|| format!("Failed to write {}", procs.display()))?;
+        }
+        Ok(())
+    }
+
+    /// CPU time used by the cgroup, in microseconds
+    pub fn cpu_usec(&self) -> Option<u64> {
+        if self.v2.is_some() {
+            keyed(&self.read(Controller::Cpu, "cpu.stat")?, "usage_usec")
+        } else {
+            self.read_number(Controller::Cpuacct, "cpuacct.usage")
+                .map(|ns| ns / 1000)
+        }
+    }
+
+    /// Current usage and limits; counters the host doesn't have read as 0
+    pub fn usage(&self) -> CgroupUsage {
+        let limit = |controller, file| {
+            self.read_number(controller, file)
+                .filter(|&limit| limit < UNLIMITED)
+        };
+        let mut usage = CgroupUsage {
+            cpu_usec: self.cpu_usec().unwrap_or(0),
+            pids: self
+                .read_number(Controller::Pids, "pids.current")
+                .unwrap_or(0),
+            pids_limit: limit(Controller::Pids, "pids.max"),
+            ..Default::default()
+        };
+        if self.v2.is_some() {
+            usage.memory_bytes = self
+                .read_number(Controller::Memory, "memory.current")
+                .unwrap_or(0);
+            usage.memory_limit_bytes = limit(Controller::Memory, "memory.max");
+            (usage.read_bytes, usage.write_bytes) =
+                parse_io_stat(&self.read(Controller::Blkio, "io.stat").unwrap_or_default());
+            usage.oom_kills = self
+                .read(Controller::Memory, "memory.events")
+                .and_then(|events| keyed(&events, "oom_kill"))
+                .unwrap_or(0);
+        } else {
+            usage.memory_bytes = self
+                .read_number(Controller::Memory, "memory.usage_in_bytes")
+                .unwrap_or(0);
+            usage.memory_limit_bytes = limit(Controller::Memory, "memory.limit_in_bytes");
+            (usage.read_bytes, usage.write_bytes) = parse_blkio_service_bytes(
+                &self
+                    .read(Controller::Blkio, "blkio.throttle.io_service_bytes")
+                    .unwrap_or_default(),
+            );
+            // oom_kill is in memory.oom_control since Linux 4.13
+            usage.oom_kills = self
+                .read(Controller::Memory, "memory.oom_control")
+                .and_then(|control| keyed(&control, "oom_kill"))
+                .unwrap_or(0);
+        }
+        usage
+    }
+
+    /// Processes in the cgroup
+    pub fn procs(&self) -> Vec<u32> {
+        self.dirs()
+            .first()
+            .and_then(|dir| std::fs::read_to_string(dir.join("cgroup.procs")).ok())
+            .map(|procs| {
+                procs
+                    .lines()
+                    .filter_map(|l| l.trim().parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove the (empty) cgroup directories
+    pub fn remove(&self) {
+        for dir in self.dirs() {
+            if dir.exists() {
+                if let Err(e) = std::fs::remove_dir(dir) {
+                    debug!("Failed to remove cgroup {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerCgroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.v2, self.v1.get(&Controller::Memory)) {
+            (Some(dir), _) => write!(f, "{}", dir.display()),
+            (None, Some(dir)) => write!(f, "{} (+{} v1 controllers)", dir.display(), self.v1.len()),
+            (None, None) => f.write_str("none"),
+        }
+    }
+}
+
+/// Resource usage and limits read back from a cgroup
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CgroupUsage {
+    pub memory_bytes: u64,
+    /// None when unlimited
+    pub memory_limit_bytes: Option<u64>,
+    pub cpu_usec: u64,
+    pub pids: u64,
+    /// None when unlimited
+    pub pids_limit: Option<u64>,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// Processes killed for running out of memory
+    pub oom_kills: u64,
+}
+
+/// The value of `key` in a flat-keyed file such as cpu.stat or memory.events
+fn keyed(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        (k == key).then(|| v.trim().parse().ok()).flatten()
+    })
+}
+
+/// Bytes read and written across every device in a cgroup v2 io.stat
+pub(crate) fn parse_io_stat(io_stat: &str) -> (u64, u64) {
+    // "<major>:<minor> rbytes=... wbytes=... rios=... wios=... dbytes=... dios=..."
+    io_stat
+        .split_whitespace()
+        .fold((0, 0), |(read, write), field| match field.split_once('=') {
+            Some(("rbytes", v)) => (read + v.parse().unwrap_or(0), write),
+            Some(("wbytes", v)) => (read, write + v.parse().unwrap_or(0)),
+            _ => (read, write),
+        })
+}
+
+/// Bytes read and written across every device in a v1
+/// blkio.throttle.io_service_bytes
+fn parse_blkio_service_bytes(service_bytes: &str) -> (u64, u64) {
+    // "<major>:<minor> Read <bytes>" ... "Total <bytes>"
+    service_bytes.lines().fold((0, 0), |(read, write), line| {
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [_, "Read", bytes] => (read + bytes.parse().unwrap_or(0), write),
+            [_, "Write", bytes] => (read, write + bytes.parse().unwrap_or(0)),
+            _ => (read, write),
+        }
+    })
+}
+
+/// The disk holding `path` as (major, minor), for I/O limits. Partitions
+/// resolve to their disk since io.max only takes whole devices; filesystems
+/// without a block device (tmpfs, overlayfs) give None.
+pub fn block_device(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let (major, minor) = (libc::major(dev) as u64, libc::minor(dev) as u64);
+    if major == 0 {
+        return None;
+    }
+    let sys = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    if !sys.join("partition").exists() {
+        return Some((major, minor));
+    }
+    // The partition's sysfs directory sits inside its disk's
+    let disk = std::fs::canonicalize(&sys).ok()?;
+    let number = std::fs::read_to_string(disk.parent()?.join("dev")).ok()?;
+    let (major, minor) = number.trim().split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// v2 cpu.weight (1-10000) to v1 cpu.shares (2-262144), the inverse of the
+/// conversion runc uses
+fn weight_to_shares(weight: u64) -> u64 {
+    let weight = weight.clamp(1, 10_000);
+    2 + (weight - 1) * 262_142 / 9_999
+}
+
+/// v1 cpu.shares (2-262144) to v2 cpu.weight (1-10000), as runc converts it
+fn shares_to_weight(shares: u64) -> u64 {
+    let shares = shares.clamp(2, 262_144);
+    1 + (shares - 2) * 9_999 / 262_142
+}
+
+/// v1 blkio.weight (10-1000) to v2 io.weight (1-10000), as runc converts it
+fn blkio_to_weight(weight: u64) -> u64 {
+    let weight = weight.clamp(10, 1000);
+    1 + (weight - 10) * 9_999 / 990
+}
+
+/// v2 io.weight (1-10000) to v1 blkio.weight (10-1000)
+fn weight_to_blkio(weight: u64) -> u64 {
+    let weight = weight.clamp(1, 10_000);
+    10 + (weight - 1) * 990 / 9_999
+}
+
+/// A process's cgroup in the v2 hierarchy; None on legacy cgroups
+pub fn unified_dir(pid: u32) -> Option<PathBuf> {
+    let membership = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let path = membership.lines().find_map(|l| l.strip_prefix("0::"))?;
+    let root = CgroupLayout::current().unified.as_deref()?;
+    Some(root.join(path.trim().trim_start_matches('/')))
+}
+
+/// CPU time used by a process's cgroup, in microseconds
+pub fn cpu_usage_usec(pid: u32) -> Option<u64> {
+    ContainerCgroup::of_process(pid)?.cpu_usec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_modes_and_writes_v1_equivalents() {
+        let unified =
+            "29 23 0:26 / /sys/fs/cgroup rw,nosuid shared:4 - cgroup2 cgroup2 rw,nsdelegate\n";
+        let layout = CgroupLayout::parse(unified);
+        assert_eq!(layout.mode, CgroupMode::Unified);
+        assert!(layout.degradations().is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let hybrid = format!(
+            "25 24 0:22 / {root}/unified rw - cgroup2 cgroup2 rw\n\
+             26 24 0:23 / {root}/cpu,cpuacct rw shared:7 - cgroup cgroup rw,cpu,cpuacct\n\
+             27 24 0:24 / {root}/memory rw shared:8 - cgroup cgroup rw,memory\n\
+             28 24 0:25 / {root}/freezer rw shared:9 - cgroup cgroup rw,freezer\n\
+             30 23 0:5 / /proc rw - proc proc rw\n",
+            root = root.display()
+        );
+        let layout = CgroupLayout::parse(&hybrid);
+        assert_eq!(layout.mode, CgroupMode::Hybrid);
+        assert_eq!(layout.v1[&Controller::Cpuacct], root.join("cpu,cpuacct"));
+        let degradations = layout.degradations();
+        assert!(degradations.iter().any(|d| d.contains("PSI")));
+        assert!(
+            degradations
+                .iter()
+                .any(|d| d.starts_with("pids controller not mounted"))
+        );
+        assert!(
+            !degradations
+                .iter()
+                .any(|d| d.starts_with("memory controller"))
+        );
+
+        let cgroup = ContainerCgroup::new(&layout, "web");
+        cgroup
+            .create(&[Controller::Cpu, Controller::Memory])
+            .unwrap();
+        cgroup.set_memory_limit(512 * 1024 * 1024).unwrap();
+        cgroup.set_cpu_limit(1.5).unwrap();
+        cgroup.set_cpu_weight(100).unwrap();
+        cgroup.set_frozen(true).unwrap();
+        assert!(cgroup.set_pids_limit(64).is_err());
+
+        let read = |path: &str| std::fs::read_to_string(root.join(path)).unwrap();
+        assert_eq!(read("memory/bolt/web/memory.limit_in_bytes"), "536870912");
+        assert_eq!(read("cpu,cpuacct/bolt/web/cpu.cfs_quota_us"), "150000");
+        assert_eq!(read("cpu,cpuacct/bolt/web/cpu.cfs_period_us"), "100000");
+        assert_eq!(read("cpu,cpuacct/bolt/web/cpu.shares"), "2597");
+        assert_eq!(read("freezer/bolt/web/freezer.state"), "FROZEN");
+        assert_eq!(weight_to_blkio(10_000), 1000);
+        assert_eq!(weight_to_shares(1), 2);
+    }
+
+    #[test]
+    fn applies_limits_and_reads_usage_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let layout = CgroupLayout::parse(&format!(
+            "29 23 0:26 / {} rw - cgroup2 cgroup2 rw\n",
+            root.display()
+        ));
+        let cgroup = ContainerCgroup::new(&layout, "game");
+        assert!(!cgroup.exists());
+        cgroup
+            .create(&[Controller::Memory, Controller::Cpu])
+            .unwrap();
+        assert!(cgroup.exists());
+
+        let limits = ResourceLimits {
+            memory_limit: Some(1 << 30),
+            cpu_limit: Some(2.0),
+            pids_limit: None,
+            blkio_weight: Some(500),
+            cpu_shares: Some(1024),
+            io_limit: Some(300),
+        };
+        cgroup.apply(&limits, Some((259, 0))).unwrap();
+        let read = |file: &str| std::fs::read_to_string(root.join("bolt/game").join(file)).unwrap();
+        assert_eq!(read("memory.max"), "1073741824");
+        assert_eq!(read("cpu.max"), "200000 100000");
+        assert_eq!(read("cpu.weight"), "39");
+        assert_eq!(read("io.weight"), "4950");
+        assert_eq!(read("io.max"), "259:0 riops=300 wiops=300");
+        assert!(!root.join("bolt/game/pids.max").exists());
+
+        let mut update = limits.clone();
+        update.memory_limit = Some(2 << 30);
+        update.io_limit = Some(0);
+        cgroup.apply(&update, Some((259, 0))).unwrap();
+        assert_eq!(read("memory.max"), "2147483648");
+        assert_eq!(read("io.max"), "259:0 riops=max wiops=max");
+        let err = cgroup.apply(&update, None).unwrap_err().to_string();
+        assert!(err.contains("iops: no block device"), "{}", err);
+
+        let write = |file: &str, contents: &str| {
+            std::fs::write(root.join("bolt/game").join(file), contents).unwrap()
+        };
+        write("memory.current", "52428800\n");
+        write("memory.max", "max\n");
+        write("cpu.stat", "usage_usec 1500000\nuser_usec 1000000\n");
+        write("pids.current", "12\n");
+        write("pids.max", "64\n");
+        write(
+            "io.stat",
+            "259:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n",
+        );
+        write("memory.events", "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n");
+        assert_eq!(
+            cgroup.usage(),
+            CgroupUsage {
+                memory_bytes: 50 << 20,
+                memory_limit_bytes: None,
+                cpu_usec: 1_500_000,
+                pids: 12,
+                pids_limit: Some(64),
+                read_bytes: 4096,
+                write_bytes: 8192,
+                oom_kills: 1,
+            }
+        );
+        assert_eq!(
+            parse_blkio_service_bytes("8:0 Read 100\n8:0 Write 50\n8:0 Total 150\nTotal 150\n"),
+            (100, 50)
+        );
+        assert_eq!(shares_to_weight(262_144), 10_000);
+    }
+}
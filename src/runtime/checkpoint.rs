@@ -0,0 +1,514 @@
+// Container checkpoint and restore with CRIU
+//
+// `bolt checkpoint` freezes a running container and has CRIU write its
+// processes (memory, open files and sockets, file locks) into a directory;
+// `bolt restore` brings them back from it, on this host or another. The
+// runtime drives CRIU:
+//
+// - podman exports a self-contained archive, `checkpoint.tar.gz`, holding
+//   the CRIU images, the container's config and its filesystem changes, so
+//   it restores as a new container on any host with the image
+// - docker writes the CRIU images to `<dir>/criu` (it needs
+//   `"experimental": true` in daemon.json) and restores them into the same,
+//   stopped container
+//
+// Next to them `checkpoint.json` records what was checkpointed, which is
+// what capsule migration ships between hosts. CRIU can't save GPU state and
+// needs root, so GPU containers and rootless runtimes are refused up front.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+pub const MANIFEST_FILE: &str = "checkpoint.json";
+/// podman's exported checkpoint
+const ARCHIVE_FILE: &str = "checkpoint.tar.gz";
+/// docker's checkpoint name, a directory under `--checkpoint-dir`
+const DOCKER_CHECKPOINT: &str = "criu";
+
+/// Device paths whose state CRIU can't save
+const GPU_DEVICES: &[&str] = &["/dev/nvidia", "/dev/dri", "/dev/kfd"];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckpointOptions {
+    /// Keep the container running after the checkpoint
+    pub leave_running: bool,
+    /// Save established TCP connections; they only survive a restore with
+    /// the same IP address
+    pub tcp_established: bool,
+}
+
+/// `checkpoint.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointManifest {
+    pub container: String,
+    pub image: String,
+    /// podman or docker; a checkpoint restores only with the runtime that made it
+    pub runtime: String,
+    pub host: String,
+    pub created_at: DateTime<Utc>,
+    pub criu_version: Option<String>,
+    pub leave_running: bool,
+    pub tcp_established: bool,
+    /// Bytes on disk
+    pub size: u64,
+}
+
+impl CheckpointManifest {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let content = std::fs::read(&path).with_context(|| {
+            format!(
+                "No checkpoint in {} ({} missing)",
+                dir.display(),
+                MANIFEST_FILE
+            )
+        })?;
+        serde_json::from_slice(&content).with_context(|| format!("Invalid {}", path.display()))
+    }
+}
+
+/// `criu --version`, also telling whether CRIU is installed
+async fn criu_version() -> Option<String> {
+    let output = AsyncCommand::new("criu")
+        .arg("--version")
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("Version:"))
+        .map(|v| v.trim().to_string())
+}
+
+/// The CRIU arguments for the runtime's `checkpoint` command
+fn checkpoint_args(
+    runtime: &str,
+    container: &str,
+    dir: &Path,
+    options: CheckpointOptions,
+) -> Vec<String> {
+    let mut args: Vec<String> = match runtime {
+        "podman" => vec![
+            "container".to_string(),
+            "checkpoint".to_string(),
+            "--export".to_string(),
+            dir.join(ARCHIVE_FILE).display().to_string(),
+            "--file-locks".to_string(),
+        ],
+        _ => vec![
+            "checkpoint".to_string(),
+            "create".to_string(),
+            "--checkpoint-dir".to_string(),
+            dir.display().to_string(),
+        ],
+    };
+    if options.leave_running {
+        args.push("--leave-running".to_string());
+    }
+    if options.tcp_established && runtime == "podman" {
+        args.push("--tcp-established".to_string());
+    }
+    args.push(container.to_string());
+    if runtime != "podman" {
+        args.push(DOCKER_CHECKPOINT.to_string());
+    }
+    args
+}
+
+/// The runtime's arguments to restore `manifest` from `dir`, as `name`
+fn restore_args(manifest: &CheckpointManifest, dir: &Path, name: &str) -> Vec<String> {
+    if manifest.runtime != "podman" {
+        return vec![
+            "start".to_string(),
+            "--checkpoint".to_string(),
+            DOCKER_CHECKPOINT.to_string(),
+            "--checkpoint-dir".to_string(),
+            dir.display().to_string(),
+            name.to_string(),
+        ];
+    }
+    let mut args = vec![
+        "container".to_string(),
+        "restore".to_string(),
+        "--import".to_string(),
+        dir.join(ARCHIVE_FILE).display().to_string(),
+        "--file-locks".to_string(),
+        "--name".to_string(),
+        name.to_string(),
+    ];
+    if manifest.tcp_established {
+        args.push("--tcp-established".to_string());
+    }
+    args
+}
+
+/// (running, image, host device paths) of a container
+async fn describe(runtime: &str, container: &str) -> Result<(bool, String, Vec<String>)> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "container",
+            "inspect",
+            "--format",
+            "{{.State.Running}}|{{.Config.Image}}|{{range .HostConfig.Devices}}{{.PathOnHost}} {{end}}",
+            container,
+        ])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!("No container named {}", container));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.trim().splitn(3, '|');
+    let running = fields.next() == Some("true");
+    let image = fields.next().unwrap_or_default().to_string();
+    let devices = fields
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    Ok((running, image, devices))
+}
+
+/// Refuse what CRIU can't checkpoint before freezing anything
+fn preflight(runtime: &str, container: &str, running: bool, devices: &[String]) -> Result<()> {
+    if !running {
+        return Err(anyhow!("{} is not running", container));
+    }
+    if crate::runtime::capabilities::effective_uid() != Some(0) {
+        return Err(anyhow!(
+            "Checkpointing needs root: CRIU can't dump rootless containers"
+        ));
+    }
+    if !matches!(runtime, "podman" | "docker") {
+        return Err(anyhow!("{} can't checkpoint containers", runtime));
+    }
+    if let Some(device) = devices
+        .iter()
+        .find(|d| GPU_DEVICES.iter().any(|gpu| d.starts_with(gpu)))
+    {
+        return Err(anyhow!(
+            "{} uses {}; CRIU can't save GPU state",
+            container,
+            device
+        ));
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Checkpoint a running container into `dir`, which must be empty or new
+pub async fn checkpoint(
+    runtime: &str,
+    container: &str,
+    dir: &Path,
+    options: CheckpointOptions,
+) -> Result<CheckpointManifest> {
+    let (running, image, devices) = describe(runtime, container).await?;
+    preflight(runtime, container, running, &devices)?;
+    let criu_version = criu_version()
+        .await
+        .ok_or_else(|| anyhow!("CRIU is not installed (criu --version failed)"))?;
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(anyhow!("{} is not empty", dir.display()));
+    }
+    std::fs::create_dir_all(dir)?;
+
+    info!("🧊 Checkpointing {} into {}", container, dir.display());
+    let started = std::time::Instant::now();
+    let args = checkpoint_args(runtime, container, dir, options);
+    debug!("{} {}", runtime, args.join(" "));
+    let output = AsyncCommand::new(runtime).args(&args).output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let hint = if stderr.contains("experimental") {
+            " (docker checkpoints need \"experimental\": true in daemon.json)"
+        } else {
+            ""
+        };
+        return Err(anyhow!(
+            "Failed to checkpoint {}: {}{}",
+            container,
+            stderr.trim(),
+            hint
+        ));
+    }
+    if options.tcp_established && runtime != "podman" {
+        warn!("docker can't checkpoint established TCP connections; they were dropped");
+    }
+
+    let manifest = CheckpointManifest {
+        container: container.to_string(),
+        image,
+        runtime: runtime.to_string(),
+        host: std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|h| h.trim().to_string())
+            .unwrap_or_default(),
+        created_at: Utc::now(),
+        criu_version: Some(criu_version),
+        leave_running: options.leave_running,
+        tcp_established: options.tcp_established && runtime == "podman",
+        size: dir_size(dir),
+    };
+    std::fs::write(
+        dir.join(MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    info!(
+        "✅ Checkpointed {} in {:.1}s ({})",
+        container,
+        started.elapsed().as_secs_f64(),
+        crate::builds::cache::format_size(manifest.size)
+    );
+    Ok(manifest)
+}
+
+/// Write the checkpoint in `dir` to `output` as one uncompressed tar, the
+/// memory image of a capsule snapshot. Its CRIU page images stay
+/// uncompressed, so zero pages and pages unchanged since the last snapshot
+/// can be found.
+pub fn memory_image(dir: &Path, output: &Path) -> Result<()> {
+    let mut file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let archive = dir.join(ARCHIVE_FILE);
+    if archive.exists() {
+        let mut decoder =
+            flate2::read::GzDecoder::new(std::io::BufReader::new(std::fs::File::open(&archive)?));
+        std::io::copy(&mut decoder, &mut file)
+            .with_context(|| format!("Failed to unpack {}", archive.display()))?;
+    } else {
+        let mut tar = tar::Builder::new(file);
+        tar.append_dir_all(DOCKER_CHECKPOINT, dir.join(DOCKER_CHECKPOINT))
+            .with_context(|| format!("No CRIU images in {}", dir.display()))?;
+        tar.finish()?;
+    }
+    Ok(())
+}
+
+/// Restore the checkpoint in `dir`, as `name` or the container it was taken
+/// from. Returns the container's name.
+pub async fn restore(
+    runtime: &str,
+    data_dir: &Path,
+    dir: &Path,
+    name: Option<&str>,
+) -> Result<String> {
+    let manifest = CheckpointManifest::load(dir)?;
+    if manifest.runtime != runtime {
+        return Err(anyhow!(
+            "{} was checkpointed with {}; it can't be restored with {}",
+            manifest.container,
+            manifest.runtime,
+            runtime
+        ));
+    }
+    if crate::runtime::capabilities::effective_uid() != Some(0) {
+        return Err(anyhow!(
+            "Restoring needs root: CRIU can't restore rootless containers"
+        ));
+    }
+    let name = name.unwrap_or(&manifest.container).to_string();
+    let existing = describe(runtime, &name).await.ok();
+    match existing {
+        Some((true, _, _)) => {
+            return Err(anyhow!(
+                "{} is running; stop it or restore under another name",
+                name
+            ));
+        }
+        // podman imports a new container; the stopped one left by the
+        // checkpoint would take its name
+        Some((false, _, _)) if runtime == "podman" => {
+            let output = AsyncCommand::new(runtime)
+                .args(["rm", &name])
+                .output()
+                .await?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Failed to remove the stopped {}: {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+        None if runtime != "podman" => {
+            return Err(anyhow!(
+                "docker restores checkpoints into the container they came from; {} doesn't exist",
+                name
+            ));
+        }
+        _ => {}
+    }
+
+    info!("♻️  Restoring {} from {}", name, dir.display());
+    let started = std::time::Instant::now();
+    let output = AsyncCommand::new(runtime)
+        .args(restore_args(&manifest, dir, &name))
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to restore {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // A new network namespace comes without the egress rules
+    if let Some(egress) = crate::networking::egress::state(data_dir, &manifest.container) {
+        if let Err(e) =
+            crate::networking::egress::apply(runtime, data_dir, &name, &egress.policy).await
+        {
+            warn!("Could not restore the egress policy of {}: {}", name, e);
+        }
+    }
+    info!(
+        "✅ Restored {} in {:.1}s",
+        name,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drives_criu_through_the_runtime() {
+        let dir = Path::new("/srv/ckpt");
+        let options = CheckpointOptions {
+            leave_running: true,
+            tcp_established: true,
+        };
+        assert_eq!(
+            checkpoint_args("podman", "db", dir, options),
+            [
+                "container",
+                "checkpoint",
+                "--export",
+                "/srv/ckpt/checkpoint.tar.gz",
+                "--file-locks",
+                "--leave-running",
+                "--tcp-established",
+                "db",
+            ]
+        );
+        assert_eq!(
+            checkpoint_args("docker", "db", dir, CheckpointOptions::default()),
+            [
+                "checkpoint",
+                "create",
+                "--checkpoint-dir",
+                "/srv/ckpt",
+                "db",
+                "criu"
+            ]
+        );
+
+        let mut manifest = CheckpointManifest {
+            container: "db".to_string(),
+            image: "postgres:16".to_string(),
+            runtime: "podman".to_string(),
+            host: "node-a".to_string(),
+            created_at: Utc::now(),
+            criu_version: Some("3.19".to_string()),
+            leave_running: false,
+            tcp_established: true,
+            size: 0,
+        };
+        assert_eq!(
+            restore_args(&manifest, dir, "db-2"),
+            [
+                "container",
+                "restore",
+                "--import",
+                "/srv/ckpt/checkpoint.tar.gz",
+                "--file-locks",
+                "--name",
+                "db-2",
+                "--tcp-established",
+            ]
+        );
+        manifest.runtime = "docker".to_string();
+        assert_eq!(
+            restore_args(&manifest, dir, "db"),
+            [
+                "start",
+                "--checkpoint",
+                "criu",
+                "--checkpoint-dir",
+                "/srv/ckpt",
+                "db"
+            ]
+        );
+
+        assert!(preflight("podman", "db", false, &[]).is_err());
+    }
+
+    #[test]
+    fn memory_images_are_uncompressed_tars() {
+        use std::io::{Read, Write};
+
+        let entries = |image: &Path| -> Vec<String> {
+            tar::Archive::new(std::fs::File::open(image).unwrap())
+                .entries()
+                .unwrap()
+                .map(|e| e.unwrap().path().unwrap().display().to_string())
+                .collect()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        // docker: the CRIU images themselves
+        let docker = dir.path().join("docker");
+        std::fs::create_dir_all(docker.join(DOCKER_CHECKPOINT)).unwrap();
+        std::fs::write(docker.join("criu/pages-1.img"), vec![0u8; 8192]).unwrap();
+        let image = dir.path().join("docker.img");
+        memory_image(&docker, &image).unwrap();
+        assert!(entries(&image).contains(&"criu/pages-1.img".to_string()));
+
+        // podman: its archive, unpacked
+        let podman = dir.path().join("podman");
+        std::fs::create_dir_all(&podman).unwrap();
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4096);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "checkpoint/pages-1.img", &[7u8; 4096][..])
+            .unwrap();
+        let tar = tar.into_inner().unwrap();
+        let mut gz = flate2::write::GzEncoder::new(
+            std::fs::File::create(podman.join(ARCHIVE_FILE)).unwrap(),
+            flate2::Compression::default(),
+        );
+        gz.write_all(&tar).unwrap();
+        gz.finish().unwrap();
+        let image = dir.path().join("podman.img");
+        memory_image(&podman, &image).unwrap();
+        let mut unpacked = Vec::new();
+        std::fs::File::open(&image)
+            .unwrap()
+            .read_to_end(&mut unpacked)
+            .unwrap();
+        assert_eq!(unpacked, tar);
+
+        assert!(memory_image(&dir.path().join("empty"), &dir.path().join("none.img")).is_err());
+    }
+}
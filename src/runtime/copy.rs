@@ -0,0 +1,498 @@
+// Copying files between the host and containers (`bolt cp`)
+//
+// Everything crosses as a tar stream: `<runtime> cp - <container>:<dir>`
+// extracts one in the container's mount namespace, so volumes and other
+// mounts are written through, and `<runtime> cp <container>:<path> -`
+// produces one. Bolt builds and unpacks the archives itself, which is what
+// lets it follow `docker cp`'s rules for the destination (copy into an
+// existing directory, else create or replace the path, `dir/.` copies the
+// directory's contents) and decide whose files they are: copied into a
+// container they belong to root unless `--archive` keeps the host's
+// ownership, copied out they belong to the caller.
+//
+// `-` as the source or destination is a tar stream on stdin or stdout, as
+// with Docker. Stopped containers can be copied to and from as well.
+use anyhow::{Context, Result, anyhow};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+/// One side of `bolt cp`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Host(PathBuf),
+    Container {
+        container: String,
+        path: String,
+    },
+    /// A tar stream on stdin or stdout
+    Stream,
+}
+
+impl Endpoint {
+    /// `-`, `container:path`, or a host path. Host paths containing a colon
+    /// need to start with `/` or `.`.
+    pub fn parse(arg: &str) -> Self {
+        if arg == "-" {
+            return Endpoint::Stream;
+        }
+        if arg.starts_with('/') || arg.starts_with('.') {
+            return Endpoint::Host(PathBuf::from(arg));
+        }
+        match arg.split_once(':') {
+            Some((container, path)) if !container.is_empty() => Endpoint::Container {
+                container: container.to_string(),
+                path: path.to_string(),
+            },
+            _ => Endpoint::Host(PathBuf::from(arg)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions {
+    /// Copy what a symlink source points at, not the link
+    pub follow_link: bool,
+    /// Keep the files' owners
+    pub archive: bool,
+}
+
+/// What is at the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    Other,
+}
+
+/// Where a copy lands: extracted into `dir`, the source's top entry named
+/// `name`, or only the entries under it when `name` is None
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placement {
+    dir: String,
+    name: Option<String>,
+}
+
+fn basename(path: &str) -> Option<String> {
+    Path::new(path.trim_end_matches('/'))
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+fn parent(path: &str) -> String {
+    match Path::new(path.trim_end_matches('/')).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ if path.starts_with('/') => "/".to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+/// `docker cp`'s rules for where `src` ends up at `dest`
+fn place(src: &str, src_is_dir: bool, dest: &str, existing: Option<Kind>) -> Result<Placement> {
+    let renamed = || -> Result<Placement> {
+        Ok(Placement {
+            dir: parent(dest),
+            name: Some(basename(dest).ok_or_else(|| anyhow!("Invalid destination {}", dest))?),
+        })
+    };
+    match existing {
+        Some(Kind::Dir) if src_is_dir && (src.ends_with("/.") || src == ".") => Ok(Placement {
+            dir: dest.to_string(),
+            name: None,
+        }),
+        Some(Kind::Dir) => Ok(Placement {
+            dir: dest.to_string(),
+            name: Some(basename(src).ok_or_else(|| anyhow!("Invalid source {}", src))?),
+        }),
+        Some(Kind::Other) if src_is_dir => Err(anyhow!(
+            "Cannot copy a directory to {}, which is not a directory",
+            dest
+        )),
+        None if dest.ends_with('/') && !src_is_dir => {
+            Err(anyhow!("Destination directory {} does not exist", dest))
+        }
+        Some(Kind::Other) | None => renamed(),
+    }
+}
+
+/// Container paths are relative to the container's root
+fn absolute(path: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+fn failure(runtime: &str, stderr: &[u8]) -> anyhow::Error {
+    anyhow!(
+        "{} cp failed: {}",
+        runtime,
+        String::from_utf8_lossy(stderr).trim()
+    )
+}
+
+/// What is at `path` in the container, from the first entry of its archive
+fn stat(runtime: &str, container: &str, path: &str) -> Option<Kind> {
+    let mut child = Command::new(runtime)
+        .args(["cp", &format!("{}:{}", container, path), "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let kind = child.stdout.take().and_then(|stdout| {
+        let mut archive = tar::Archive::new(stdout);
+        let entry = archive.entries().ok()?.next()?.ok()?;
+        Some(if entry.header().entry_type().is_dir() {
+            Kind::Dir
+        } else {
+            Kind::Other
+        })
+    });
+    // Only the first header is needed; don't stream whole directories
+    let _ = child.kill();
+    let _ = child.wait();
+    kind
+}
+
+/// Copy `src` on the host, or a tar stream from stdin when it is `-`, to
+/// `dest` in the container. Returns the bytes copied.
+pub async fn copy_to_container(
+    runtime: &str,
+    src: &Path,
+    container: &str,
+    dest: &str,
+    options: CopyOptions,
+) -> Result<u64> {
+    let (runtime, src, container, dest) = (
+        runtime.to_string(),
+        src.to_path_buf(),
+        container.to_string(),
+        absolute(dest),
+    );
+    tokio::task::spawn_blocking(move || to_container(&runtime, &src, &container, &dest, options))
+        .await?
+}
+
+fn to_container(
+    runtime: &str,
+    src: &Path,
+    container: &str,
+    dest: &str,
+    options: CopyOptions,
+) -> Result<u64> {
+    if src == Path::new("-") {
+        // The stream is extracted into `dest`, which has to be a directory
+        let mut child = Command::new(runtime)
+            .args(["cp", "-", &format!("{}:{}", container, dest)])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {} cp", runtime))?;
+        let copied = match child.stdin.take() {
+            Some(mut stdin) => std::io::copy(&mut std::io::stdin().lock(), &mut stdin)?,
+            None => 0,
+        };
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(failure(runtime, &output.stderr));
+        }
+        return Ok(copied);
+    }
+
+    let metadata = if options.follow_link {
+        std::fs::metadata(src)
+    } else {
+        std::fs::symlink_metadata(src)
+    }
+    .with_context(|| format!("Cannot copy {}", src.display()))?;
+    let placement = place(
+        &src.to_string_lossy(),
+        metadata.is_dir(),
+        dest,
+        stat(runtime, container, dest),
+    )?;
+    debug!(
+        "Copying {} into {}:{} as {:?}",
+        src.display(),
+        container,
+        placement.dir,
+        placement.name
+    );
+
+    let mut child = Command::new(runtime)
+        .args(["cp", "-", &format!("{}:{}", container, placement.dir)])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {} cp", runtime))?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("{} cp has no stdin", runtime))?;
+    let written = write_archive(stdin, src, placement.name.as_deref(), options);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failure(runtime, &output.stderr));
+    }
+    written
+}
+
+/// Archive `src`, its top entry named `name` or left out when None, into
+/// `out`. Returns the bytes of file data written.
+fn write_archive(
+    out: impl Write,
+    src: &Path,
+    name: Option<&str>,
+    options: CopyOptions,
+) -> Result<u64> {
+    let mut builder = tar::Builder::new(out);
+    let mut copied = 0;
+    let walker = walkdir::WalkDir::new(src)
+        .follow_links(false)
+        .follow_root_links(options.follow_link)
+        .sort_by_file_name();
+    for entry in walker {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(src)?;
+        let path = match (name, rel.as_os_str().is_empty()) {
+            (None, true) => continue,
+            (None, false) => rel.to_path_buf(),
+            (Some(name), _) => Path::new(name).join(rel),
+        };
+        let metadata = entry.metadata()?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata_in_mode(&metadata, tar::HeaderMode::Complete);
+        if !options.archive {
+            header.set_uid(0);
+            header.set_gid(0);
+        }
+        let file_type = entry.file_type();
+        if file_type.is_file() {
+            builder.append_data(&mut header, &path, std::fs::File::open(entry.path())?)?;
+            copied += metadata.len();
+        } else if file_type.is_dir() {
+            header.set_size(0);
+            builder.append_data(&mut header, &path, std::io::empty())?;
+        } else if file_type.is_symlink() {
+            header.set_size(0);
+            builder.append_link(&mut header, &path, std::fs::read_link(entry.path())?)?;
+        } else {
+            warn!(
+                "Skipping {}: not a file, directory or symlink",
+                entry.path().display()
+            );
+        }
+    }
+    builder.into_inner()?.flush()?;
+    Ok(copied)
+}
+
+/// Copy `src` in the container to `dest` on the host, or to stdout as a tar
+/// stream when it is `-`. Returns the bytes copied.
+pub async fn copy_from_container(
+    runtime: &str,
+    container: &str,
+    src: &str,
+    dest: &Path,
+    options: CopyOptions,
+) -> Result<u64> {
+    let (runtime, container, src, dest) = (
+        runtime.to_string(),
+        container.to_string(),
+        absolute(src),
+        dest.to_path_buf(),
+    );
+    tokio::task::spawn_blocking(move || from_container(&runtime, &container, &src, &dest, options))
+        .await?
+}
+
+fn from_container(
+    runtime: &str,
+    container: &str,
+    src: &str,
+    dest: &Path,
+    options: CopyOptions,
+) -> Result<u64> {
+    let mut cmd = Command::new(runtime);
+    cmd.arg("cp");
+    if options.follow_link {
+        cmd.arg("-L");
+    }
+    let mut child = cmd
+        .arg(format!("{}:{}", container, src))
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {} cp", runtime))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("{} cp has no stdout", runtime))?;
+
+    let copied = if dest == Path::new("-") {
+        let mut stdout = stdout;
+        std::io::copy(&mut stdout, &mut std::io::stdout().lock())
+    } else {
+        unpack(stdout, src, dest, options)
+    };
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failure(runtime, &output.stderr));
+    }
+    Ok(copied?)
+}
+
+/// Unpack an archive of `src` at `dest` following `place`, refusing entries
+/// that would land outside the destination
+fn unpack(input: impl Read, src: &str, dest: &Path, options: CopyOptions) -> std::io::Result<u64> {
+    let error = |e: anyhow::Error| std::io::Error::other(e.to_string());
+    let mut archive = tar::Archive::new(input);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_ownerships(options.archive);
+    archive.set_overwrite(true);
+    let mut entries = archive.entries()?;
+    let Some(first) = entries.next() else {
+        return Ok(0);
+    };
+    let first = first?;
+
+    let existing = std::fs::metadata(dest)
+        .ok()
+        .map(|m| if m.is_dir() { Kind::Dir } else { Kind::Other });
+    let placement = place(
+        src,
+        first.header().entry_type().is_dir(),
+        &dest.to_string_lossy(),
+        existing,
+    )
+    .map_err(error)?;
+    let base = PathBuf::from(&placement.dir);
+    let root = base.canonicalize().map_err(|_| {
+        error(anyhow!(
+            "Destination directory {} does not exist",
+            base.display()
+        ))
+    })?;
+    // Entries are named after the source; swap that for where it lands
+    let target = |path: &Path| -> std::io::Result<Option<PathBuf>> {
+        let mut components = path.components().skip(1).peekable();
+        if components.peek().is_none() && placement.name.is_none() {
+            return Ok(None);
+        }
+        let mut target = match placement.name {
+            Some(ref name) => base.join(name),
+            None => base.clone(),
+        };
+        for component in components {
+            match component {
+                Component::Normal(part) => target.push(part),
+                Component::CurDir => {}
+                _ => {
+                    return Err(error(anyhow!("Refusing archive entry {}", path.display())));
+                }
+            }
+        }
+        Ok(Some(target))
+    };
+
+    let mut copied = 0;
+    for entry in std::iter::once(Ok(first)).chain(entries) {
+        let mut entry = entry?;
+        let Some(path) = target(&entry.path()?)? else {
+            continue;
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+            // A symlink unpacked earlier mustn't lead outside the destination
+            if !parent.canonicalize()?.starts_with(&root) {
+                return Err(error(anyhow!(
+                    "Refusing {}: it leads outside {}",
+                    path.display(),
+                    root.display()
+                )));
+            }
+        }
+        if entry.header().entry_type().is_hard_link() {
+            let link = entry
+                .link_name()?
+                .ok_or_else(|| error(anyhow!("Hard link without a target")))?;
+            let Some(link) = target(&link)? else {
+                continue;
+            };
+            let _ = std::fs::remove_file(&path);
+            std::fs::hard_link(link, &path)?;
+            continue;
+        }
+        if entry.header().entry_type().is_file() {
+            copied += entry.header().size()?;
+        }
+        entry.unpack(&path)?;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_copies_like_docker_cp() {
+        let dir = Some(Kind::Dir);
+        let file = Some(Kind::Other);
+        let at = |dir: &str, name: Option<&str>| Placement {
+            dir: dir.to_string(),
+            name: name.map(str::to_string),
+        };
+
+        // Into an existing directory, or at a new path
+        assert_eq!(
+            place("app.conf", false, "/etc/app", dir).unwrap(),
+            at("/etc/app", Some("app.conf"))
+        );
+        assert_eq!(
+            place("app.conf", false, "/etc/app.conf", None).unwrap(),
+            at("/etc", Some("app.conf"))
+        );
+        assert_eq!(
+            place("app.conf", false, "/etc/app.conf", file).unwrap(),
+            at("/etc", Some("app.conf"))
+        );
+        assert_eq!(
+            place("./site", true, "/srv/www", dir).unwrap(),
+            at("/srv/www", Some("site"))
+        );
+        assert_eq!(
+            place("./site", true, "/srv/www", None).unwrap(),
+            at("/srv", Some("www"))
+        );
+        // Only the contents
+        assert_eq!(
+            place("./site/.", true, "/srv/www", dir).unwrap(),
+            at("/srv/www", None)
+        );
+        assert_eq!(
+            place("site/.", true, "out", None).unwrap(),
+            at(".", Some("out"))
+        );
+
+        assert!(place("./site", true, "/etc/passwd", file).is_err());
+        assert!(place("app.conf", false, "/missing/", None).is_err());
+
+        assert_eq!(
+            Endpoint::parse("web:/var/log"),
+            Endpoint::Container {
+                container: "web".to_string(),
+                path: "/var/log".to_string()
+            }
+        );
+        assert_eq!(
+            Endpoint::parse("./a:b"),
+            Endpoint::Host(PathBuf::from("./a:b"))
+        );
+        assert_eq!(Endpoint::parse("-"), Endpoint::Stream);
+    }
+}
@@ -0,0 +1,608 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::events::{ContainerEvent, EventLog};
+
+/// Crash-dump collection settings, the `[crash_dumps]` section of
+/// `<config_dir>/config.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashDumpConfig {
+    pub enabled: bool,
+    /// How much of the tail of the container logs to keep
+    pub log_tail_kb: u64,
+    /// Copy the core dump when ulimit/core_pattern produced one
+    pub collect_core: bool,
+    /// Dumps kept per container; older ones are removed first
+    pub max_dumps_per_container: usize,
+    /// Dumps older than this are removed regardless of count
+    pub max_age_days: u32,
+}
+
+impl Default for CrashDumpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            log_tail_kb: 64,
+            collect_core: true,
+            max_dumps_per_container: 5,
+            max_age_days: 14,
+        }
+    }
+}
+
+/// Where to read the failed container's output from
+#[derive(Debug, Clone)]
+pub enum LogSource {
+    /// A log file written by the runtime
+    File(PathBuf),
+    /// `podman logs` / `docker logs`
+    Runtime {
+        runtime: String,
+    },
+    None,
+}
+
+/// Everything known about a container at the moment it exited
+#[derive(Debug, Clone)]
+pub struct CrashContext {
+    pub container: String,
+    pub image: Option<String>,
+    pub exit_code: i32,
+    pub pid: Option<u32>,
+    pub logs: LogSource,
+    pub cgroup_path: Option<PathBuf>,
+    pub rootfs_path: Option<PathBuf>,
+    /// `/proc/<pid>/status` as last read while the process ran
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub container: String,
+    pub image: Option<String>,
+    pub exit_code: i32,
+    /// Signal number for exit codes of the form 128 + N
+    pub signal: Option<i32>,
+    pub pid: Option<u32>,
+    pub collected_at: chrono::DateTime<chrono::Utc>,
+    pub oom_killed: bool,
+    pub log_file: Option<PathBuf>,
+    pub core_file: Option<PathBuf>,
+    pub memory_events_file: Option<PathBuf>,
+    #[serde(default)]
+    pub status_file: Option<PathBuf>,
+    /// Diagnostics that could not be captured and why
+    pub notes: Vec<String>,
+}
+
+impl CrashReport {
+    pub fn dir(&self, crashes_dir: &Path) -> PathBuf {
+        crashes_dir.join(&self.container).join(&self.id)
+    }
+}
+
+/// The parts of a [`CrashContext`] that only exist while the container runs
+#[derive(Debug, Clone, Default)]
+pub struct LiveState {
+    pub pid: Option<u32>,
+    pub cgroup_path: Option<PathBuf>,
+    /// The container's writable layer, which outlives its processes
+    pub rootfs_path: Option<PathBuf>,
+    pub status: Option<String>,
+}
+
+/// Samples a container's [`LiveState`] while it runs in the foreground, so
+/// a crash dump taken after it exits still has its PID, cgroup and status
+pub struct CrashWatch {
+    state: Arc<Mutex<LiveState>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+impl CrashWatch {
+    pub fn start(runtime: &str, container: &str) -> Self {
+        let state = Arc::new(Mutex::new(LiveState::default()));
+        let task = tokio::spawn(sample(
+            runtime.to_string(),
+            container.to_string(),
+            state.clone(),
+        ));
+        Self { state, task }
+    }
+
+    /// Stop sampling and return what was seen
+    pub fn finish(self) -> LiveState {
+        self.task.abort();
+        self.state.lock().unwrap().clone()
+    }
+}
+
+async fn sample(runtime: String, container: String, state: Arc<Mutex<LiveState>>) {
+    loop {
+        let pid = state.lock().unwrap().pid;
+        match pid {
+            None => {
+                let output = AsyncCommand::new(&runtime)
+                    .args([
+                        "inspect",
+                        "--format",
+                        "{{.State.Pid}} {{.GraphDriver.Data.UpperDir}}",
+                    ])
+                    .arg(&container)
+                    .output()
+                    .await;
+                if let Ok(output) = output
+                    && output.status.success()
+                {
+                    let inspect = String::from_utf8_lossy(&output.stdout).to_string();
+                    let mut fields = inspect.split_whitespace();
+                    if let Some(pid) = fields.next().and_then(|p| p.parse::<u32>().ok())
+                        && pid > 0
+                    {
+                        let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))
+                            .ok()
+                            .and_then(|c| cgroup_dir(&c));
+                        let mut state = state.lock().unwrap();
+                        state.pid = Some(pid);
+                        state.cgroup_path = cgroup;
+                        state.rootfs_path = fields
+                            .next()
+                            .filter(|dir| dir.starts_with('/'))
+                            .map(PathBuf::from);
+                    }
+                }
+            }
+            Some(pid) => {
+                if let Ok(status) = std::fs::read_to_string(format!("/proc/{}/status", pid)) {
+                    state.lock().unwrap().status = Some(status);
+                }
+            }
+        }
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+/// The cgroup directory of a process from its `/proc/<pid>/cgroup`: the
+/// unified hierarchy, or the v1 memory controller
+fn cgroup_dir(cgroup: &str) -> Option<PathBuf> {
+    let root = Path::new("/sys/fs/cgroup");
+    cgroup.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (id, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let path = path.trim_start_matches('/');
+        if id == "0" && controllers.is_empty() {
+            Some(root.join(path))
+        } else if controllers.split(',').any(|c| c == "memory") {
+            Some(root.join("memory").join(path))
+        } else {
+            None
+        }
+    })
+}
+
+/// Collects diagnostics for containers that exited non-zero
+#[derive(Debug, Clone)]
+pub struct CrashCollector {
+    pub crashes_dir: PathBuf,
+    pub config: CrashDumpConfig,
+    events: EventLog,
+}
+
+impl CrashCollector {
+    pub fn new(data_dir: PathBuf, config: CrashDumpConfig) -> Result<Self> {
+        let crashes_dir = data_dir.join("crashes");
+        std::fs::create_dir_all(&crashes_dir).context("Failed to create crashes directory")?;
+
+        Ok(Self {
+            crashes_dir,
+            config,
+            events: EventLog::new(data_dir)?,
+        })
+    }
+
+    /// Capture a crash bundle if the exit code indicates failure
+    pub async fn collect(&self, ctx: &CrashContext) -> Result<Option<CrashReport>> {
+        if !self.config.enabled || ctx.exit_code == 0 {
+            return Ok(None);
+        }
+
+        info!(
+            "💥 Container {} exited with code {}, collecting crash dump",
+            ctx.container, ctx.exit_code
+        );
+
+        let id = format!(
+            "{}-{}",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            &uuid::Uuid::new_v4().to_string()[..8]
+        );
+        let dir = self.crashes_dir.join(&ctx.container).join(&id);
+        std::fs::create_dir_all(&dir)?;
+
+        let mut report = CrashReport {
+            id,
+            container: ctx.container.clone(),
+            image: ctx.image.clone(),
+            exit_code: ctx.exit_code,
+            signal: (ctx.exit_code > 128).then(|| ctx.exit_code - 128),
+            pid: ctx.pid,
+            collected_at: chrono::Utc::now(),
+            oom_killed: false,
+            log_file: None,
+            core_file: None,
+            memory_events_file: None,
+            status_file: None,
+            notes: Vec::new(),
+        };
+
+        match self.capture_logs(ctx).await {
+            Ok(Some(logs)) => {
+                let path = dir.join("logs.txt");
+                std::fs::write(&path, logs)?;
+                report.log_file = Some(path);
+            }
+            Ok(None) => report.notes.push("no log source available".to_string()),
+            Err(e) => report.notes.push(format!("log capture failed: {}", e)),
+        }
+
+        // The process is usually gone by now; fall back to reading it live
+        // for callers that collect before the container is reaped
+        let status = ctx.status.clone().or_else(|| {
+            ctx.pid
+                .and_then(|pid| std::fs::read_to_string(format!("/proc/{}/status", pid)).ok())
+        });
+        match status {
+            Some(status) => {
+                let path = dir.join("status");
+                std::fs::write(&path, status)?;
+                report.status_file = Some(path);
+            }
+            None => report.notes.push("process status unavailable".to_string()),
+        }
+
+        self.capture_oom_info(ctx, &dir, &mut report).await;
+
+        if self.config.collect_core {
+            if let Err(e) = self.capture_core(ctx, &dir, &mut report).await {
+                report.notes.push(format!("core dump not collected: {}", e));
+            }
+        }
+
+        std::fs::write(
+            dir.join("report.json"),
+            serde_json::to_string_pretty(&report)?,
+        )?;
+
+        self.events.record(
+            &ContainerEvent::new(&ctx.container, "crash")
+                .with_attribute("crash_id", report.id.clone())
+                .with_attribute("exit_code", report.exit_code.to_string())
+                .with_attribute("oom_killed", report.oom_killed.to_string()),
+        )?;
+
+        if let Err(e) = self.apply_retention(&ctx.container) {
+            warn!("Failed to apply crash dump retention: {}", e);
+        }
+
+        info!("✅ Crash dump {} saved to {:?}", report.id, dir);
+        Ok(Some(report))
+    }
+
+    async fn capture_logs(&self, ctx: &CrashContext) -> Result<Option<Vec<u8>>> {
+        let limit = (self.config.log_tail_kb * 1024) as usize;
+
+        let logs = match &ctx.logs {
+            LogSource::File(path) => std::fs::read(path)?,
+            LogSource::Runtime { runtime } => {
+                let output = AsyncCommand::new(runtime)
+                    .arg("logs")
+                    .arg(&ctx.container)
+                    .output()
+                    .await?;
+                let mut combined = output.stdout;
+                combined.extend_from_slice(&output.stderr);
+                combined
+            }
+            LogSource::None => return Ok(None),
+        };
+
+        let start = logs.len().saturating_sub(limit);
+        Ok(Some(logs[start..].to_vec()))
+    }
+
+    async fn capture_oom_info(&self, ctx: &CrashContext, dir: &Path, report: &mut CrashReport) {
+        if let Some(ref cgroup) = ctx.cgroup_path {
+            // cgroup v2 memory.events, or the v1 memory.oom_control (oom_kill since 4.13)
+            for file in ["memory.events", "memory.oom_control"] {
+                if let Ok(events) = std::fs::read_to_string(cgroup.join(file)) {
+                    report.oom_killed = events.lines().any(|line| {
+                        line.strip_prefix("oom_kill ")
+                            .and_then(|n| n.trim().parse::<u64>().ok())
+                            .is_some_and(|n| n > 0)
+                    });
+                    let path = dir.join(file);
+                    if std::fs::write(&path, events).is_ok() {
+                        report.memory_events_file = Some(path);
+                    }
+                    return;
+                }
+            }
+        }
+
+        // Fall back to the runtime's own view of the container state
+        if let LogSource::Runtime { runtime } = &ctx.logs {
+            if let Ok(output) = AsyncCommand::new(runtime)
+                .args(["inspect", "--format", "{{.State.OOMKilled}}"])
+                .arg(&ctx.container)
+                .output()
+                .await
+            {
+                report.oom_killed = String::from_utf8_lossy(&output.stdout).trim() == "true";
+                return;
+            }
+        }
+
+        report.notes.push("OOM information unavailable".to_string());
+    }
+
+    async fn capture_core(
+        &self,
+        ctx: &CrashContext,
+        dir: &Path,
+        report: &mut CrashReport,
+    ) -> Result<()> {
+        let pid = ctx
+            .pid
+            .ok_or_else(|| anyhow::anyhow!("container PID unknown"))?;
+
+        let limits = std::fs::read_to_string("/proc/self/limits").unwrap_or_default();
+        if limits
+            .lines()
+            .find(|l| l.starts_with("Max core file size"))
+            .and_then(|l| l.split_whitespace().nth(4))
+            == Some("0")
+        {
+            return Err(anyhow::anyhow!("core file size ulimit is 0"));
+        }
+
+        let pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern")
+            .context("cannot read core_pattern")?;
+        let pattern = pattern.trim();
+        let core_dest = dir.join("core");
+
+        if pattern.starts_with('|') {
+            if !pattern.contains("systemd-coredump") {
+                return Err(anyhow::anyhow!("core_pattern pipes to {}", pattern));
+            }
+
+            let output = AsyncCommand::new("coredumpctl")
+                .args(["dump", &pid.to_string(), "--output"])
+                .arg(&core_dest)
+                .output()
+                .await
+                .context("coredumpctl not available")?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "coredumpctl: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        } else {
+            let name = pattern.replace("%p", &pid.to_string());
+            let base = if name.starts_with('/') {
+                PathBuf::new()
+            } else {
+                ctx.rootfs_path
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("relative core_pattern and no rootfs"))?
+            };
+            let candidate = base.join(name.trim_start_matches('/'));
+            if name.contains('%') || !candidate.exists() {
+                return Err(anyhow::anyhow!(
+                    "no core file found for pattern {}",
+                    pattern
+                ));
+            }
+            std::fs::copy(&candidate, &core_dest)?;
+        }
+
+        debug!(
+            "Collected core dump for {} at {:?}",
+            ctx.container, core_dest
+        );
+        report.core_file = Some(core_dest);
+        Ok(())
+    }
+
+    /// Crash reports, newest first, optionally filtered to one container
+    pub fn list(&self, container: Option<&str>) -> Result<Vec<CrashReport>> {
+        let mut reports = Vec::new();
+
+        for container_entry in std::fs::read_dir(&self.crashes_dir)? {
+            let container_entry = container_entry?;
+            let name = container_entry.file_name().to_string_lossy().to_string();
+            if container.is_some_and(|c| c != name) || !container_entry.path().is_dir() {
+                continue;
+            }
+
+            for dump in std::fs::read_dir(container_entry.path())? {
+                let report_path = dump?.path().join("report.json");
+                if let Ok(content) = std::fs::read_to_string(&report_path) {
+                    if let Ok(report) = serde_json::from_str::<CrashReport>(&content) {
+                        reports.push(report);
+                    }
+                }
+            }
+        }
+
+        reports.sort_by_key(|r| std::cmp::Reverse(r.collected_at));
+        Ok(reports)
+    }
+
+    /// Look up a crash by id, or the latest crash of a container
+    pub fn find(&self, container_or_id: &str) -> Result<Option<CrashReport>> {
+        let reports = self.list(None)?;
+        Ok(reports
+            .iter()
+            .find(|r| r.id == container_or_id)
+            .or_else(|| reports.iter().find(|r| r.container == container_or_id))
+            .cloned())
+    }
+
+    pub fn apply_retention(&self, container: &str) -> Result<()> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(self.config.max_age_days as i64);
+
+        for (index, report) in self.list(Some(container))?.iter().enumerate() {
+            if index >= self.config.max_dumps_per_container || report.collected_at < cutoff {
+                debug!("Removing expired crash dump {}", report.id);
+                std::fs::remove_dir_all(report.dir(&self.crashes_dir))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(dir: &Path, exit_code: i32) -> CrashContext {
+        CrashContext {
+            container: "worker".to_string(),
+            image: Some("worker:latest".to_string()),
+            exit_code,
+            pid: None,
+            logs: LogSource::File(dir.join("worker.log")),
+            cgroup_path: Some(dir.join("cgroup")),
+            rootfs_path: None,
+            status: Some("Name:\tworker\nState:\tZ (zombie)\n".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_a_bundle_with_the_log_tail_and_oom_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let log: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(dir.path().join("worker.log"), &log).unwrap();
+        std::fs::create_dir_all(dir.path().join("cgroup")).unwrap();
+        std::fs::write(
+            dir.path().join("cgroup").join("memory.events"),
+            "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n",
+        )
+        .unwrap();
+        let config = CrashDumpConfig {
+            log_tail_kb: 1,
+            collect_core: false,
+            ..Default::default()
+        };
+        let collector = CrashCollector::new(dir.path().join("data"), config).unwrap();
+
+        assert!(
+            collector
+                .collect(&context(dir.path(), 0))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let report = collector
+            .collect(&context(dir.path(), 137))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.signal, Some(9));
+        assert!(report.oom_killed);
+
+        let bundle = report.dir(&collector.crashes_dir);
+        let tail = std::fs::read_to_string(bundle.join("logs.txt")).unwrap();
+        assert_eq!(tail.len(), 1024);
+        assert!(log.ends_with(&tail));
+        assert!(bundle.join("memory.events").is_file());
+        assert_eq!(report.status_file, Some(bundle.join("status")));
+        assert!(
+            std::fs::read_to_string(bundle.join("status"))
+                .unwrap()
+                .starts_with("Name:\tworker")
+        );
+        let saved: CrashReport =
+            serde_json::from_slice(&std::fs::read(bundle.join("report.json")).unwrap()).unwrap();
+        assert_eq!(saved.id, report.id);
+        assert_eq!(saved.exit_code, 137);
+        assert_eq!(saved.image.as_deref(), Some("worker:latest"));
+        assert_eq!(saved.log_file, Some(bundle.join("logs.txt")));
+
+        let events = EventLog::new(dir.path().join("data"))
+            .unwrap()
+            .history("worker")
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, "crash");
+        assert_eq!(collector.find("worker").unwrap().unwrap().id, report.id);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_dumps_within_count_and_age() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("worker.log"), "boom\n").unwrap();
+        let config = CrashDumpConfig {
+            collect_core: false,
+            max_dumps_per_container: 2,
+            ..Default::default()
+        };
+        let collector = CrashCollector::new(dir.path().join("data"), config).unwrap();
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let report = collector
+                .collect(&context(dir.path(), 1))
+                .await
+                .unwrap()
+                .unwrap();
+            ids.push(report.id);
+        }
+        let kept: Vec<String> = collector
+            .list(Some("worker"))
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(kept, vec![ids[2].clone(), ids[1].clone()]);
+
+        // Too old to keep even though under the count
+        let mut old = collector.find(&ids[1]).unwrap().unwrap();
+        old.collected_at = chrono::Utc::now() - chrono::Duration::days(15);
+        std::fs::write(
+            old.dir(&collector.crashes_dir).join("report.json"),
+            serde_json::to_string(&old).unwrap(),
+        )
+        .unwrap();
+        collector.apply_retention("worker").unwrap();
+        let kept = collector.list(Some("worker")).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].id, ids[2]);
+        assert!(!old.dir(&collector.crashes_dir).exists());
+    }
+
+    #[test]
+    fn finds_the_cgroup_of_a_process() {
+        assert_eq!(
+            cgroup_dir("0::/machine.slice/libpod-abc.scope/container\n"),
+            Some(PathBuf::from(
+                "/sys/fs/cgroup/machine.slice/libpod-abc.scope/container"
+            ))
+        );
+        assert_eq!(
+            cgroup_dir("12:pids:/docker/abc\n4:memory:/docker/abc\n1:name=systemd:/docker/abc\n"),
+            Some(PathBuf::from("/sys/fs/cgroup/memory/docker/abc"))
+        );
+        assert_eq!(cgroup_dir("3:cpu,cpuacct:/docker/abc\n"), None);
+    }
+}
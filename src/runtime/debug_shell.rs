@@ -0,0 +1,377 @@
+// Debug shells for containers without one
+//
+// `bolt debug shell` copies a toolbox of static binaries (busybox from
+// `busybox:musl` by default, or any directory such as a Nix `pkgsStatic`
+// build) into the running container at `/.bolt-debug`, execs a shell from it
+// inside the container's namespaces and removes the toolbox again when the
+// shell exits. The image is never modified.
+//
+// Containers whose root filesystem is read-only get an ephemeral sidecar
+// instead, like `kubectl debug`: a container sharing the target's PID and
+// network namespaces, where the target's filesystem is at `/proc/1/root`.
+use anyhow::{Context, Result, anyhow};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_TOOLBOX_IMAGE: &str = "docker.io/library/busybox:musl";
+
+/// Where the toolbox lives inside the container
+const TOOLBOX_MOUNT: &str = "/.bolt-debug";
+
+#[derive(Debug, Clone, Default)]
+pub struct DebugShellOptions {
+    /// A toolbox directory, or an image whose static `/bin/busybox` to use
+    pub toolbox: Option<String>,
+    /// Always use a sidecar container instead of copying the toolbox in
+    pub sidecar: bool,
+    /// User to run the shell as (default: the container's user)
+    pub user: Option<String>,
+}
+
+/// Open an interactive shell in `container`, cleaning up when it exits
+pub async fn run(
+    runtime: &str,
+    container: &str,
+    data_dir: &Path,
+    options: &DebugShellOptions,
+) -> Result<()> {
+    if inspect(runtime, container, "{{.State.Running}}").await? != "true" {
+        return Err(anyhow!("{} is not running", container));
+    }
+    let toolbox = prepare_toolbox(runtime, data_dir, options.toolbox.as_deref()).await?;
+
+    if !options.sidecar {
+        match inject(runtime, container, &toolbox).await {
+            Ok(()) => {
+                let result = exec_shell(runtime, container, options).await;
+                remove_toolbox(runtime, container).await;
+                return result;
+            }
+            Err(e) => {
+                warn!(
+                    "Could not copy the toolbox into {} ({}); using a sidecar container",
+                    container, e
+                );
+            }
+        }
+    }
+    sidecar_shell(runtime, container, &toolbox, options).await
+}
+
+/// Resolve the toolbox directory, extracting busybox from an image on first use
+async fn prepare_toolbox(runtime: &str, data_dir: &Path, toolbox: Option<&str>) -> Result<PathBuf> {
+    if let Some(dir) = toolbox.map(Path::new).filter(|p| p.is_dir()) {
+        if !dir.join("bin").join("sh").exists() {
+            return Err(anyhow!(
+                "Toolbox {} has no bin/sh; it must contain static binaries under bin/",
+                dir.display()
+            ));
+        }
+        return Ok(dir.to_path_buf());
+    }
+
+    let image = toolbox.unwrap_or(DEFAULT_TOOLBOX_IMAGE);
+    let dir = data_dir
+        .join("debug")
+        .join("toolbox")
+        .join(image.replace(['/', ':', '@'], "_"));
+    if dir.join("bin").join("sh").exists() {
+        return Ok(dir);
+    }
+
+    info!("🧰 Extracting toolbox from {}", image);
+    let staging = dir.with_extension("tmp");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(staging.join("bin"))?;
+
+    let name = format!("bolt-toolbox-{}", std::process::id());
+    let output = AsyncCommand::new(runtime)
+        .args(["create", "--name", &name, image])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to create toolbox container from {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let busybox = staging.join("bin").join("busybox");
+    let copied = AsyncCommand::new(runtime)
+        .arg("cp")
+        .arg(format!("{}:/bin/busybox", name))
+        .arg(&busybox)
+        .output()
+        .await;
+    let _ = AsyncCommand::new(runtime)
+        .args(["rm", "-f", &name])
+        .output()
+        .await;
+    if !copied.is_ok_and(|o| o.status.success()) {
+        return Err(anyhow!("{} has no /bin/busybox to use as a toolbox", image));
+    }
+
+    // One symlink per applet, so the shell finds `ls`, `ps`, `wget`, ...
+    let applets = AsyncCommand::new(&busybox)
+        .arg("--list")
+        .output()
+        .await
+        .with_context(|| format!("{} does not run on this host", busybox.display()))?;
+    for applet in String::from_utf8_lossy(&applets.stdout).lines() {
+        let applet = applet.trim();
+        if !applet.is_empty() && applet != "busybox" {
+            std::os::unix::fs::symlink("busybox", staging.join("bin").join(applet))?;
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::rename(&staging, &dir)?;
+    Ok(dir)
+}
+
+/// Copy the toolbox into the container's filesystem
+async fn inject(runtime: &str, container: &str, toolbox: &Path) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .arg("cp")
+        .arg(format!("{}/.", toolbox.display()))
+        .arg(format!("{}:{}", container, TOOLBOX_MOUNT))
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    debug!("Toolbox copied to {}:{}", container, TOOLBOX_MOUNT);
+    Ok(())
+}
+
+async fn exec_shell(runtime: &str, container: &str, options: &DebugShellOptions) -> Result<()> {
+    let env = inspect(
+        runtime,
+        container,
+        "{{range .Config.Env}}{{println .}}{{end}}",
+    )
+    .await?;
+    let path = env
+        .lines()
+        .find_map(|l| l.strip_prefix("PATH="))
+        .unwrap_or("/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin");
+
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.arg("exec");
+    if std::io::stdin().is_terminal() {
+        cmd.arg("-it");
+    } else {
+        cmd.arg("-i");
+    }
+    if let Some(ref user) = options.user {
+        cmd.args(["--user", user]);
+    }
+    cmd.args(["-e", &format!("PATH={}/bin:{}", TOOLBOX_MOUNT, path)])
+        .args(["-e", "PS1=[bolt debug] \\w # "])
+        .arg(container)
+        .arg(format!("{}/bin/sh", TOOLBOX_MOUNT));
+
+    info!("🐚 Shell in {} (toolbox in {})", container, TOOLBOX_MOUNT);
+    let status = cmd.status().await.context("Failed to start the shell")?;
+    debug!("Debug shell exited with {}", status);
+    Ok(())
+}
+
+async fn remove_toolbox(runtime: &str, container: &str) {
+    let removed = AsyncCommand::new(runtime)
+        .args(["exec", "--user", "0", container])
+        .arg(format!("{}/bin/rm", TOOLBOX_MOUNT))
+        .args(["-rf", TOOLBOX_MOUNT])
+        .output()
+        .await;
+    match removed {
+        Ok(o) if o.status.success() => info!("🧹 Removed toolbox from {}", container),
+        _ => warn!(
+            "Could not remove {} from {}; it goes away when the container is recreated",
+            TOOLBOX_MOUNT, container
+        ),
+    }
+}
+
+/// Shell in an ephemeral container sharing the target's PID and network
+/// namespaces, built from the target's own image with the toolbox mounted
+async fn sidecar_shell(
+    runtime: &str,
+    container: &str,
+    toolbox: &Path,
+    options: &DebugShellOptions,
+) -> Result<()> {
+    let image = inspect(runtime, container, "{{.Config.Image}}").await?;
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.args(["run", "--rm"]);
+    if std::io::stdin().is_terminal() {
+        cmd.arg("-it");
+    } else {
+        cmd.arg("-i");
+    }
+    if let Some(ref user) = options.user {
+        cmd.args(["--user", user]);
+    }
+    cmd.args([
+        "--name",
+        &format!("{}-debug-{}", container, std::process::id()),
+    ])
+    .args(["--pid", &format!("container:{}", container)])
+    .args(["--network", &format!("container:{}", container)])
+    .args(["--cap-add", "SYS_PTRACE"])
+    .args(["-v", &format!("{}:{}:ro", toolbox.display(), TOOLBOX_MOUNT)])
+    .args(["-e", &format!("PATH={}/bin", TOOLBOX_MOUNT)])
+    .args(["-e", "PS1=[bolt debug] \\w # "])
+    .args(["--entrypoint", &format!("{}/bin/sh", TOOLBOX_MOUNT)])
+    .arg(&image);
+
+    info!(
+        "🐚 Sidecar shell for {}; its filesystem is at /proc/1/root",
+        container
+    );
+    let status = cmd
+        .status()
+        .await
+        .context("Failed to start the debug sidecar")?;
+    debug!("Debug sidecar exited with {}", status);
+    Ok(())
+}
+
+async fn inspect(runtime: &str, container: &str, format: &str) -> Result<String> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", format, container])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "No such container: {} ({})",
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// A fake podman that records its arguments; `cp` fails once the
+    /// container is marked read-only
+    fn fake_runtime(dir: &Path) -> String {
+        let script = dir.join("podman");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\n\
+             here=$(dirname \"$0\")\n\
+             printf '%s\\n' \"$*\" >> \"$here/calls\"\n\
+             case \"$1 $3\" in\n\
+             \x20 'inspect {{.State.Running}}') [ -e \"$here/stopped\" ] && echo false || echo true ;;\n\
+             \x20 'inspect {{.Config.Image}}') echo app:latest ;;\n\
+             \x20 inspect*) echo PATH=/usr/bin ;;\n\
+             \x20 cp*) [ -e \"$here/readonly\" ] && { echo 'read-only file system' >&2; exit 1; } ;;\n\
+             esac\n\
+             exit 0\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script.display().to_string()
+    }
+
+    fn toolbox(dir: &Path) -> String {
+        let bin = dir.join("toolbox").join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("sh"), "").unwrap();
+        dir.join("toolbox").display().to_string()
+    }
+
+    fn calls(dir: &Path) -> Vec<String> {
+        std::fs::read_to_string(dir.join("calls"))
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn copies_the_toolbox_in_and_removes_it_after() {
+        let dir = TempDir::new().unwrap();
+        let runtime = fake_runtime(dir.path());
+        let options = DebugShellOptions {
+            toolbox: Some(toolbox(dir.path())),
+            ..Default::default()
+        };
+
+        run(&runtime, "app", dir.path(), &options).await.unwrap();
+
+        let calls = calls(dir.path());
+        assert_eq!(
+            calls[1],
+            format!(
+                "cp {}/. app:/.bolt-debug",
+                options.toolbox.as_ref().unwrap()
+            )
+        );
+        assert!(calls.contains(
+            &"exec -i -e PATH=/.bolt-debug/bin:/usr/bin -e PS1=[bolt debug] \\w #  app /.bolt-debug/bin/sh"
+                .to_string()
+        ));
+        assert_eq!(
+            calls.last().unwrap(),
+            "exec --user 0 app /.bolt-debug/bin/rm -rf /.bolt-debug"
+        );
+        assert!(!calls.iter().any(|c| c.starts_with("run ")));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_a_sidecar_on_read_only_containers() {
+        let dir = TempDir::new().unwrap();
+        let runtime = fake_runtime(dir.path());
+        std::fs::write(dir.path().join("readonly"), "").unwrap();
+        let toolbox = toolbox(dir.path());
+        let options = DebugShellOptions {
+            toolbox: Some(toolbox.clone()),
+            ..Default::default()
+        };
+
+        run(&runtime, "app", dir.path(), &options).await.unwrap();
+
+        let calls = calls(dir.path());
+        assert!(!calls.iter().any(|c| c.starts_with("exec ")));
+        let sidecar = calls.last().unwrap();
+        assert!(sidecar.starts_with("run --rm -i --name app-debug-"));
+        assert!(sidecar.contains("--pid container:app --network container:app"));
+        assert!(sidecar.contains(&format!("-v {}:/.bolt-debug:ro", toolbox)));
+        assert!(sidecar.ends_with("--entrypoint /.bolt-debug/bin/sh app:latest"));
+    }
+
+    #[tokio::test]
+    async fn rejects_stopped_containers_and_toolboxes_without_a_shell() {
+        let dir = TempDir::new().unwrap();
+        let runtime = fake_runtime(dir.path());
+        let empty = dir.path().join("empty");
+        std::fs::create_dir(&empty).unwrap();
+        let options = DebugShellOptions {
+            toolbox: Some(empty.display().to_string()),
+            ..Default::default()
+        };
+        let err = run(&runtime, "app", dir.path(), &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no bin/sh"));
+
+        std::fs::write(dir.path().join("stopped"), "").unwrap();
+        let err = run(&runtime, "app", dir.path(), &DebugShellOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "app is not running");
+        assert_eq!(calls(dir.path()).len(), 2);
+    }
+}
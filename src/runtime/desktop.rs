@@ -0,0 +1,642 @@
+// Desktop integration
+//
+// GUI services (launchers, modding tools) with a `[desktop_integration]`
+// table don't get the host's Wayland socket. Each container gets its own
+// session: a nested weston that shows up as one window on the host desktop,
+// with its socket in `<data_dir>/desktop/<container>/run` mounted into the
+// container. Nothing the app puts on its clipboard reaches the host, and the
+// app can't read the host's clipboard, unless the bridge is enabled:
+//
+//   clipboard = true                  share the clipboard (off by default)
+//   clipboard_direction = "both"      or "to-host" / "to-container"
+//   clipboard_types = [...]           text/* and image/* types that may cross
+//   clipboard_max_size = "8MB"        larger content stays where it is
+//
+// Files cross through a transfer directory shared with the container
+// (`transfer_dir`, mounted at `transfer_target`, /transfer by default): drop
+// them there from the host's file manager, or send them with
+// `bolt surge desktop send`, and drag them into the app from inside. Files
+// the app saves there show up on the host. The directory is kept when the
+// service goes down.
+//
+// `bolt desktop-bridge`, started by surge for each container, runs the nested
+// compositor and copies clipboard contents between the two sessions with
+// wl-clipboard, counting what it copied and refused in
+// `<data_dir>/desktop/<container>/session.json`.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::watcher::{self, Watcher};
+use crate::builds::cache::{format_size, parse_size};
+use crate::config::DesktopIntegrationConfig;
+
+const SOCKET: &str = "wayland-0";
+/// Where the session's runtime directory is mounted in the container
+const CONTAINER_RUNTIME_DIR: &str = "/run/bolt/desktop";
+const DEFAULT_TRANSFER_TARGET: &str = "/transfer";
+const DEFAULT_MAX_SIZE: u64 = 8 * 1024 * 1024;
+const DEFAULT_TYPES: [&str; 2] = ["text/plain", "image/png"];
+const CLIPBOARD_POLL: Duration = Duration::from_secs(1);
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardDirection {
+    #[default]
+    Both,
+    ToHost,
+    ToContainer,
+}
+
+impl ClipboardDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClipboardDirection::Both => "both",
+            ClipboardDirection::ToHost => "to-host",
+            ClipboardDirection::ToContainer => "to-container",
+        }
+    }
+
+    fn allows(&self, from: Side) -> bool {
+        match self {
+            ClipboardDirection::Both => true,
+            ClipboardDirection::ToHost => from == Side::Container,
+            ClipboardDirection::ToContainer => from == Side::Host,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesktopPolicy {
+    pub clipboard: bool,
+    pub direction: ClipboardDirection,
+    /// MIME types that may cross, most preferred first
+    pub types: Vec<String>,
+    pub max_size: u64,
+    /// Host side of the transfer directory; under the session's directory
+    /// when unset
+    pub transfer_dir: Option<PathBuf>,
+    pub transfer_target: String,
+}
+
+impl DesktopPolicy {
+    /// `project_dir` is where a relative `transfer_dir` starts from
+    pub fn from_config(config: &DesktopIntegrationConfig, project_dir: &Path) -> Result<Self> {
+        let types = match config.clipboard_types {
+            Some(ref types) if types.is_empty() => {
+                return Err(anyhow!("clipboard_types can't be empty"));
+            }
+            Some(ref types) => types.clone(),
+            None => DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+        };
+        for mime in &types {
+            let valid = mime
+                .split_once('/')
+                .is_some_and(|(kind, sub)| (kind == "text" || kind == "image") && !sub.is_empty());
+            if !valid {
+                return Err(anyhow!(
+                    "Clipboard type '{}' is not allowed: only text/* and image/* types can cross",
+                    mime
+                ));
+            }
+        }
+        let max_size = match config.clipboard_max_size {
+            Some(ref size) => parse_size(size)?,
+            None => DEFAULT_MAX_SIZE,
+        };
+        if max_size == 0 {
+            return Err(anyhow!("clipboard_max_size must be above 0"));
+        }
+        let transfer_target = config
+            .transfer_target
+            .clone()
+            .unwrap_or_else(|| DEFAULT_TRANSFER_TARGET.to_string());
+        if !transfer_target.starts_with('/') {
+            return Err(anyhow!(
+                "transfer_target must be an absolute path, not '{}'",
+                transfer_target
+            ));
+        }
+        Ok(Self {
+            clipboard: config.clipboard.unwrap_or(false),
+            direction: config.clipboard_direction.unwrap_or_default(),
+            types,
+            max_size,
+            transfer_dir: config.transfer_dir.as_ref().map(|d| project_dir.join(d)),
+            transfer_target,
+        })
+    }
+
+    /// The first allowed type `offered`, by our preference. A type without
+    /// parameters also allows it with parameters (text/plain takes
+    /// text/plain;charset=utf-8).
+    fn pick_type<'a>(&self, offered: &'a [String]) -> Option<&'a str> {
+        self.types.iter().find_map(|allowed| {
+            offered
+                .iter()
+                .find(|o| {
+                    *o == allowed
+                        || (!allowed.contains(';')
+                            && o.split(';')
+                                .next()
+                                .is_some_and(|base| base.trim() == allowed))
+                })
+                .map(String::as_str)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesktopSession {
+    pub container: String,
+    pub policy: DesktopPolicy,
+    pub transfer_dir: PathBuf,
+    pub bridge_pid: Option<u32>,
+    pub copied_to_host: u64,
+    pub copied_to_container: u64,
+    /// Contents that were too large or of no allowed type
+    pub refused: u64,
+}
+
+impl DesktopSession {
+    pub fn running(&self) -> bool {
+        self.bridge_pid.is_some_and(|pid| BRIDGE.alive(pid))
+    }
+
+    /// Mount the session and the transfer directory into the container
+    pub fn run_args(&self, data_dir: &Path) -> Vec<String> {
+        let run = runtime_dir(data_dir, &self.container);
+        vec![
+            "-v".to_string(),
+            format!("{}:{}", run.display(), CONTAINER_RUNTIME_DIR),
+            "-e".to_string(),
+            format!("XDG_RUNTIME_DIR={}", CONTAINER_RUNTIME_DIR),
+            "-e".to_string(),
+            format!("WAYLAND_DISPLAY={}", SOCKET),
+            "-e".to_string(),
+            "XDG_SESSION_TYPE=wayland".to_string(),
+            "-e".to_string(),
+            "GDK_BACKEND=wayland".to_string(),
+            "-e".to_string(),
+            "QT_QPA_PLATFORM=wayland".to_string(),
+            "-e".to_string(),
+            "SDL_VIDEODRIVER=wayland".to_string(),
+            "-v".to_string(),
+            format!(
+                "{}:{}",
+                self.transfer_dir.display(),
+                self.policy.transfer_target
+            ),
+            "-e".to_string(),
+            format!("BOLT_TRANSFER_DIR={}", self.policy.transfer_target),
+        ]
+    }
+}
+
+/// The compositor runs in the bridge's process group and stops with it
+const BRIDGE: Watcher = Watcher::group("desktop-bridge");
+
+fn session_dir(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("desktop").join(container)
+}
+
+fn runtime_dir(data_dir: &Path, container: &str) -> PathBuf {
+    session_dir(data_dir, container).join("run")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    session_dir(data_dir, container).join("session.json")
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    session_dir(data_dir, container).join("bridge.log")
+}
+
+pub fn session(data_dir: &Path, container: &str) -> Option<DesktopSession> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every container with a desktop session, by name
+pub fn list(data_dir: &Path) -> Vec<DesktopSession> {
+    let mut sessions: Vec<DesktopSession> = std::fs::read_dir(data_dir.join("desktop"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| session(data_dir, &e.file_name().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+    sessions.sort_by(|a, b| a.container.cmp(&b.container));
+    sessions
+}
+
+fn save(data_dir: &Path, session: &DesktopSession) -> Result<()> {
+    watcher::save(&state_path(data_dir, &session.container), session)
+}
+
+/// Save the bridge's counters unless `surge down` removed the session meanwhile
+fn update(data_dir: &Path, session: &DesktopSession) -> Result<()> {
+    watcher::update(&state_path(data_dir, &session.container), session)
+}
+
+/// Start the container's Wayland session and clipboard bridge, and wait for
+/// its socket so the app finds it when it starts
+pub async fn start(
+    data_dir: &Path,
+    container: &str,
+    policy: &DesktopPolicy,
+) -> Result<DesktopSession> {
+    forget(data_dir, container);
+    let run = runtime_dir(data_dir, container);
+    std::fs::create_dir_all(&run).with_context(|| format!("Failed to create {}", run.display()))?;
+    // Wayland wants its runtime directory private
+    std::fs::set_permissions(&run, std::fs::Permissions::from_mode(0o700))?;
+    let _ = std::fs::remove_file(run.join(SOCKET));
+
+    let transfer_dir = policy
+        .transfer_dir
+        .clone()
+        .unwrap_or_else(|| session_dir(data_dir, container).join("transfer"));
+    std::fs::create_dir_all(&transfer_dir)
+        .with_context(|| format!("Failed to create {}", transfer_dir.display()))?;
+
+    let mut session = DesktopSession {
+        container: container.to_string(),
+        policy: policy.clone(),
+        transfer_dir,
+        bridge_pid: None,
+        copied_to_host: 0,
+        copied_to_container: 0,
+        refused: 0,
+    };
+    save(data_dir, &session)?;
+    session.bridge_pid = Some(BRIDGE.spawn(
+        ["desktop-bridge", container],
+        &log_path(data_dir, container),
+    )?);
+    save(data_dir, &session)?;
+
+    let socket = run.join(SOCKET);
+    let started = std::time::Instant::now();
+    while !socket.exists() {
+        if !session.running() || started.elapsed() > SOCKET_TIMEOUT {
+            forget(data_dir, container);
+            return Err(anyhow!(
+                "The Wayland session for {} didn't start; see {}",
+                container,
+                log_path(data_dir, container).display()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    info!(
+        "🖥️  Desktop session for {}: clipboard {}, transfers in {}",
+        container,
+        if policy.clipboard {
+            policy.direction.as_str()
+        } else {
+            "off"
+        },
+        session.transfer_dir.display()
+    );
+    Ok(session)
+}
+
+/// Close the container's session and drop its record; the transfer
+/// directory is kept
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = session(data_dir, container).and_then(|s| s.bridge_pid);
+    BRIDGE.forget(&state_path(data_dir, container), pid);
+    let _ = std::fs::remove_dir_all(runtime_dir(data_dir, container));
+}
+
+/// Copy files into the container's transfer directory
+pub fn send(data_dir: &Path, container: &str, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let session = session(data_dir, container)
+        .ok_or_else(|| anyhow!("{} has no desktop session", container))?;
+    let mut sent = Vec::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", file.display()))?;
+        let target = session.transfer_dir.join(name);
+        std::fs::copy(file, &target)
+            .with_context(|| format!("Failed to copy {}", file.display()))?;
+        sent.push(Path::new(&session.policy.transfer_target).join(name));
+    }
+    Ok(sent)
+}
+
+/// Run the container's Wayland session and bridge its clipboard until the
+/// compositor exits. Run by `bolt desktop-bridge`.
+pub async fn bridge(data_dir: &Path, container: &str) -> Result<()> {
+    let session = session(data_dir, container)
+        .ok_or_else(|| anyhow!("{} has no desktop session", container))?;
+    let run = runtime_dir(data_dir, container);
+    let mut compositor = AsyncCommand::new("weston")
+        .arg("--backend=wayland")
+        .arg(format!("--socket={}", SOCKET))
+        .env("XDG_RUNTIME_DIR", &run)
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("Desktop integration needs weston on the host: {}", e))?;
+
+    if !session.policy.clipboard {
+        compositor.wait().await?;
+        return Ok(());
+    }
+    let host = Clipboard::host();
+    let nested = Clipboard::nested(&run);
+    tokio::select! {
+        status = compositor.wait() => {
+            info!("Wayland session for {} ended: {}", container, status?);
+            Ok(())
+        }
+        result = sync_clipboards(data_dir, session, &host, &nested) => result,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Host,
+    Container,
+}
+
+/// wl-clipboard pointed at one of the two sessions
+struct Clipboard {
+    env: Vec<(&'static str, String)>,
+}
+
+impl Clipboard {
+    fn host() -> Self {
+        // The bridge inherited the session surge was started from
+        Self { env: Vec::new() }
+    }
+
+    fn nested(runtime_dir: &Path) -> Self {
+        Self {
+            env: vec![
+                ("XDG_RUNTIME_DIR", runtime_dir.display().to_string()),
+                ("WAYLAND_DISPLAY", SOCKET.to_string()),
+            ],
+        }
+    }
+
+    fn command(&self, program: &str) -> AsyncCommand {
+        let mut cmd = AsyncCommand::new(program);
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null());
+        cmd
+    }
+
+    async fn types(&self) -> Vec<String> {
+        match self.command("wl-paste").arg("--list-types").output().await {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Read the content as `mime`; None when it's larger than `max_size`
+    async fn read(&self, mime: &str, max_size: u64) -> Result<Option<Vec<u8>>> {
+        let mut child = self
+            .command("wl-paste")
+            .args(["--no-newline", "--type", mime])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("Clipboard bridging needs wl-clipboard on the host: {}", e))?;
+        let mut data = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            stdout.take(max_size + 1).read_to_end(&mut data).await?;
+        }
+        if data.len() as u64 > max_size {
+            return Ok(None);
+        }
+        child.wait().await?;
+        Ok(Some(data))
+    }
+
+    async fn write(&self, mime: &str, data: &[u8]) -> Result<()> {
+        let mut child = self
+            .command("wl-copy")
+            .args(["--type", mime])
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(data).await?;
+        }
+        // wl-copy keeps serving the content in the background
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(anyhow!("wl-copy failed: {}", status));
+        }
+        Ok(())
+    }
+}
+
+/// What a clipboard held when last looked at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seen {
+    Empty,
+    /// Content of a type we don't copy, or too large
+    Refused,
+    Content(u64),
+}
+
+/// Spots new clipboard content on either side
+#[derive(Debug, Default)]
+struct Tracker {
+    /// Nothing crosses until both sides were looked at once, so the host
+    /// clipboard from before the session isn't handed to the app
+    last: Option<(Seen, Seen)>,
+}
+
+impl Tracker {
+    /// The side whose content is new and should be copied to the other
+    fn changed(&mut self, direction: ClipboardDirection, host: Seen, nested: Seen) -> Option<Side> {
+        let last = self.last.replace((host, nested));
+        let (last_host, last_nested) = last?;
+        let source = if host != last_host && host != nested {
+            Some((Side::Host, host))
+        } else if nested != last_nested && nested != host {
+            Some((Side::Container, nested))
+        } else {
+            None
+        };
+        match source {
+            Some((side, Seen::Content(_))) if direction.allows(side) => Some(side),
+            _ => None,
+        }
+    }
+
+    /// The other side now holds the copied content too
+    fn copied(&mut self, seen: Seen) {
+        self.last = Some((seen, seen));
+    }
+}
+
+async fn look(
+    clipboard: &Clipboard,
+    policy: &DesktopPolicy,
+) -> Result<(Seen, Option<(String, Vec<u8>)>)> {
+    let offered = clipboard.types().await;
+    if offered.is_empty() {
+        return Ok((Seen::Empty, None));
+    }
+    let Some(mime) = policy.pick_type(&offered) else {
+        return Ok((Seen::Refused, None));
+    };
+    match clipboard.read(mime, policy.max_size).await? {
+        Some(data) => {
+            let mut hasher = DefaultHasher::new();
+            mime.hash(&mut hasher);
+            data.hash(&mut hasher);
+            Ok((
+                Seen::Content(hasher.finish()),
+                Some((mime.to_string(), data)),
+            ))
+        }
+        None => Ok((Seen::Refused, None)),
+    }
+}
+
+async fn sync_clipboards(
+    data_dir: &Path,
+    mut session: DesktopSession,
+    host: &Clipboard,
+    nested: &Clipboard,
+) -> Result<()> {
+    let policy = session.policy.clone();
+    let mut tracker = Tracker::default();
+    let mut last_refused = (false, false);
+    loop {
+        tokio::time::sleep(CLIPBOARD_POLL).await;
+        let (host_seen, host_content) = look(host, &policy).await?;
+        let (nested_seen, nested_content) = look(nested, &policy).await?;
+
+        // Count refusals once per content, from the side that may send
+        let refused = (
+            host_seen == Seen::Refused && policy.direction.allows(Side::Host),
+            nested_seen == Seen::Refused && policy.direction.allows(Side::Container),
+        );
+        if (refused.0 && !last_refused.0) || (refused.1 && !last_refused.1) {
+            debug!(
+                "Clipboard content for {} is not allowed or above {}",
+                session.container,
+                format_size(policy.max_size)
+            );
+            session.refused += 1;
+            update(data_dir, &session)?;
+        }
+        last_refused = refused;
+
+        let Some(source) = tracker.changed(policy.direction, host_seen, nested_seen) else {
+            continue;
+        };
+        let (target, content, seen) = match source {
+            Side::Host => (nested, host_content, host_seen),
+            Side::Container => (host, nested_content, nested_seen),
+        };
+        let Some((mime, data)) = content else {
+            continue;
+        };
+        if let Err(e) = target.write(&mime, &data).await {
+            warn!("Could not copy clipboard for {}: {}", session.container, e);
+            continue;
+        }
+        tracker.copied(seen);
+        match source {
+            Side::Host => session.copied_to_container += 1,
+            Side::Container => session.copied_to_host += 1,
+        }
+        debug!(
+            "📋 Copied {} ({} bytes) for {}",
+            mime,
+            data.len(),
+            session.container
+        );
+        update(data_dir, &session)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_policy_and_copies_only_new_allowed_content() {
+        let config = DesktopIntegrationConfig {
+            clipboard: Some(true),
+            clipboard_direction: Some(ClipboardDirection::ToHost),
+            clipboard_types: None,
+            clipboard_max_size: Some("1MB".to_string()),
+            transfer_dir: Some("transfer".to_string()),
+            transfer_target: None,
+        };
+        let policy = DesktopPolicy::from_config(&config, Path::new("/srv/mods")).unwrap();
+        assert_eq!(policy.max_size, 1024 * 1024);
+        assert_eq!(
+            policy.transfer_dir,
+            Some(PathBuf::from("/srv/mods/transfer"))
+        );
+        assert_eq!(policy.transfer_target, "/transfer");
+
+        let offered = vec![
+            "text/html".to_string(),
+            "text/plain;charset=utf-8".to_string(),
+            "image/png".to_string(),
+        ];
+        assert_eq!(policy.pick_type(&offered), Some("text/plain;charset=utf-8"));
+        assert_eq!(policy.pick_type(&["application/x-kde".to_string()]), None);
+
+        let bad = DesktopIntegrationConfig {
+            clipboard_types: Some(vec!["application/octet-stream".to_string()]),
+            ..config.clone()
+        };
+        assert!(DesktopPolicy::from_config(&bad, Path::new("/")).is_err());
+        let bad = DesktopIntegrationConfig {
+            transfer_target: Some("transfer".to_string()),
+            ..config
+        };
+        assert!(DesktopPolicy::from_config(&bad, Path::new("/")).is_err());
+
+        let mut tracker = Tracker::default();
+        let both = ClipboardDirection::Both;
+        // What was there before the session stays put
+        assert_eq!(tracker.changed(both, Seen::Content(1), Seen::Empty), None);
+        // The app copies something
+        assert_eq!(
+            tracker.changed(both, Seen::Content(1), Seen::Content(2)),
+            Some(Side::Container)
+        );
+        tracker.copied(Seen::Content(2));
+        assert_eq!(
+            tracker.changed(both, Seen::Content(2), Seen::Content(2)),
+            None
+        );
+        // The host copies something, but only the app may send
+        assert_eq!(
+            tracker.changed(
+                ClipboardDirection::ToHost,
+                Seen::Content(3),
+                Seen::Content(2)
+            ),
+            None
+        );
+        assert_eq!(tracker.changed(both, Seen::Refused, Seen::Content(2)), None);
+    }
+}
@@ -0,0 +1,721 @@
+// Instant dev environments
+//
+// `bolt dev enter` reads `.bolt/dev.toml` from the repository, found by
+// walking up from the current directory, and drops into a shell in a
+// long-lived development container:
+//
+// - the first run starts the template image, runs the setup commands and
+//   commits the result as a warm snapshot, `localhost/bolt-dev/<name>:<key>`,
+//   keyed by a hash of the image and the setup
+// - the container is created from the snapshot with the repository mounted
+//   at `workdir` and the ports published, runs `sleep infinity` and is kept
+//   between sessions
+// - later runs exec a shell in it, starting it first if it was stopped, so
+//   entering takes well under a second
+//
+// Changing the image or the setup builds a new snapshot; changing ports,
+// env, volumes, cache volumes or workdir recreates the container from the
+// existing one.
+// The SSH agent is reached through `<data_dir>/dev/<name>/agent.sock`, which
+// Bolt relays to the current `SSH_AUTH_SOCK` while a session is open, so a
+// new agent path after logging in again doesn't mean a new container.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::capsules::templates::dev_template;
+
+pub const DEV_FILE: &str = ".bolt/dev.toml";
+
+/// Where the relayed SSH agent socket appears inside the container
+const SSH_AGENT_DIR: &str = "/run/bolt-ssh";
+
+/// `.bolt/dev.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DevSpec {
+    /// Environment name (default: the repository's directory name)
+    pub name: Option<String>,
+    /// Built-in template: base, rust, node, python or go
+    pub template: Option<String>,
+    /// Base image, instead of or overriding the template's
+    pub image: Option<String>,
+    /// Commands baked into the warm snapshot, after the template's
+    #[serde(default)]
+    pub setup: Vec<String>,
+    /// Where the repository is mounted (default: /workspace)
+    pub workdir: Option<String>,
+    pub shell: Option<String>,
+    /// Published ports, `host:container` or a single port
+    #[serde(default)]
+    pub ports: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Extra mounts, e.g. a named volume for a package cache
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// Shared package manager caches, e.g. ["cargo"], also used by the setup
+    #[serde(default)]
+    pub cache_volumes: Vec<String>,
+    /// Forward the host's SSH agent (default: true)
+    pub ssh_agent: Option<bool>,
+}
+
+/// A dev environment resolved from its spec and template
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevEnvironment {
+    pub name: String,
+    /// Repository root, the directory holding `.bolt/`
+    pub root: PathBuf,
+    pub image: String,
+    pub setup: Vec<String>,
+    pub workdir: String,
+    pub shell: String,
+    pub ports: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub volumes: Vec<String>,
+    pub cache_volumes: Vec<String>,
+    pub ssh_agent: bool,
+}
+
+impl DevEnvironment {
+    /// Environment of the repository containing `dir`
+    pub fn find(dir: &Path) -> Result<Self> {
+        let root = dir
+            .ancestors()
+            .find(|d| d.join(DEV_FILE).is_file())
+            .ok_or_else(|| anyhow!("No {} in {:?} or its parents", DEV_FILE, dir))?;
+        let path = root.join(DEV_FILE);
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let spec: DevSpec =
+            toml::from_str(&content).with_context(|| format!("Invalid {:?}", path))?;
+        Self::from_spec(spec, root)
+    }
+
+    pub fn from_spec(spec: DevSpec, root: &Path) -> Result<Self> {
+        let template = match spec.template.as_deref() {
+            Some(name) => Some(dev_template(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown dev template '{}' (available: base, rust, node, python, go)",
+                    name
+                )
+            })?),
+            None => None,
+        };
+        let image = spec
+            .image
+            .or_else(|| template.map(|t| t.image.to_string()))
+            .ok_or_else(|| anyhow!("{} needs an image or a template", DEV_FILE))?;
+        let name = spec
+            .name
+            .or_else(|| root.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .map(|n| sanitize(&n))
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| anyhow!("{} needs a name", DEV_FILE))?;
+        let workdir = spec.workdir.unwrap_or_else(|| "/workspace".to_string());
+        if !workdir.starts_with('/') {
+            return Err(anyhow!(
+                "workdir must be an absolute path, not '{}'",
+                workdir
+            ));
+        }
+
+        let mut setup: Vec<String> = template
+            .map(|t| t.setup.iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default();
+        setup.extend(spec.setup);
+
+        Ok(Self {
+            name,
+            root: root.to_path_buf(),
+            image,
+            setup,
+            workdir,
+            shell: spec
+                .shell
+                .or_else(|| template.map(|t| t.shell.to_string()))
+                .unwrap_or_else(|| "/bin/sh".to_string()),
+            ports: spec.ports,
+            env: spec.env,
+            volumes: spec.volumes,
+            cache_volumes: spec.cache_volumes,
+            ssh_agent: spec.ssh_agent.unwrap_or(true),
+        })
+    }
+
+    pub fn container(&self) -> String {
+        format!("bolt-dev-{}", self.name)
+    }
+
+    /// Image the container is created from: the warm snapshot, or the base
+    /// image itself when there is nothing to set up
+    pub fn snapshot(&self) -> String {
+        if self.setup.is_empty() {
+            return self.image.clone();
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(self.image.as_bytes());
+        for command in &self.setup {
+            hasher.update([0]);
+            hasher.update(command.as_bytes());
+        }
+        let key = hex::encode(hasher.finalize());
+        format!("localhost/bolt-dev/{}:{}", self.name, &key[..12])
+    }
+
+    /// Hash of everything the container is created with, stored as its
+    /// `bolt.dev.config` label
+    pub fn config_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut field = |value: &str| {
+            hasher.update(value.as_bytes());
+            hasher.update([0]);
+        };
+        field(&self.snapshot());
+        field(&self.root.to_string_lossy());
+        field(&self.workdir);
+        field(if self.ssh_agent { "ssh" } else { "" });
+        for port in &self.ports {
+            field(port);
+        }
+        field("");
+        for volume in &self.volumes {
+            field(volume);
+        }
+        field("");
+        for cache in &self.cache_volumes {
+            field(cache);
+        }
+        field("");
+        for (key, value) in &self.env {
+            field(&format!("{}={}", key, value));
+        }
+        hex::encode(hasher.finalize())[..16].to_string()
+    }
+
+    /// Directory inside the container matching `cwd` on the host
+    pub fn container_dir(&self, cwd: &Path) -> String {
+        match cwd.strip_prefix(&self.root) {
+            Ok(rel) if !rel.as_os_str().is_empty() => {
+                format!("{}/{}", self.workdir.trim_end_matches('/'), rel.display())
+            }
+            _ => self.workdir.clone(),
+        }
+    }
+}
+
+/// Lowercase letters, digits and dashes, as container and image names allow
+fn sanitize(name: &str) -> String {
+    let mapped: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    mapped.trim_matches('-').to_string()
+}
+
+/// What Bolt remembers about an environment it created
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DevRecord {
+    pub name: String,
+    pub root: PathBuf,
+    pub container: String,
+    pub snapshot: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_entered: chrono::DateTime<chrono::Utc>,
+}
+
+/// A recorded environment and its container's state
+#[derive(Debug, Clone, Serialize)]
+pub struct DevStatus {
+    #[serde(flatten)]
+    pub record: DevRecord,
+    /// Container status, e.g. running or exited; None once it is gone
+    pub status: Option<String>,
+}
+
+fn dev_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("dev")
+}
+
+fn record_path(data_dir: &Path, name: &str) -> PathBuf {
+    dev_dir(data_dir).join(format!("{}.json", name))
+}
+
+fn agent_dir(data_dir: &Path, name: &str) -> PathBuf {
+    dev_dir(data_dir).join(name)
+}
+
+pub fn record(data_dir: &Path, name: &str) -> Option<DevRecord> {
+    std::fs::read(record_path(data_dir, name))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+fn save(data_dir: &Path, record: &DevRecord) -> Result<()> {
+    std::fs::create_dir_all(dev_dir(data_dir))?;
+    std::fs::write(
+        record_path(data_dir, &record.name),
+        serde_json::to_vec_pretty(record)?,
+    )?;
+    Ok(())
+}
+
+/// Open a shell, or run `command`, in the environment's container, creating
+/// it and its warm snapshot as needed. Returns the exit code.
+pub async fn enter(
+    runtime: &str,
+    data_dir: &Path,
+    env: &DevEnvironment,
+    cwd: &Path,
+    command: &[String],
+    rebuild: bool,
+) -> Result<i32> {
+    let started = Instant::now();
+    let container = env.container();
+    let snapshot = env.snapshot();
+    let config_key = env.config_key();
+
+    let existing = inspect(
+        runtime,
+        &container,
+        "{{index .Config.Labels \"bolt.dev.config\"}} {{.State.Status}}",
+    )
+    .await;
+    let ready = match existing.as_deref().and_then(|s| s.split_once(' ')) {
+        Some((key, status)) if key == config_key && !rebuild => {
+            resume(runtime, &container, status).await?;
+            true
+        }
+        Some(_) => {
+            if !rebuild {
+                info!("🔁 {} changed; recreating {}", DEV_FILE, container);
+            }
+            remove_container(runtime, &container).await;
+            false
+        }
+        None => false,
+    };
+
+    let mut created_at = chrono::Utc::now();
+    if ready {
+        if let Some(previous) = record(data_dir, &env.name) {
+            created_at = previous.created_at;
+        }
+    } else {
+        if rebuild && snapshot != env.image {
+            remove_image(runtime, &snapshot).await;
+        }
+        if !crate::runtime::pull::image_exists(runtime, &snapshot).await {
+            build_snapshot(runtime, data_dir, env, &snapshot).await?;
+        }
+        create(runtime, data_dir, env, &snapshot, &config_key).await?;
+    }
+    save(
+        data_dir,
+        &DevRecord {
+            name: env.name.clone(),
+            root: env.root.clone(),
+            container: container.clone(),
+            snapshot: snapshot.clone(),
+            created_at,
+            last_entered: chrono::Utc::now(),
+        },
+    )?;
+
+    let relay = match std::env::var_os("SSH_AUTH_SOCK") {
+        Some(agent) if env.ssh_agent => {
+            relay_agent(&agent_dir(data_dir, &env.name), PathBuf::from(agent)).await
+        }
+        _ => None,
+    };
+
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.arg("exec");
+    if std::io::stdin().is_terminal() {
+        cmd.arg("-it");
+    } else {
+        cmd.arg("-i");
+    }
+    cmd.args(["-w", &env.container_dir(cwd)]);
+    if let Ok(term) = std::env::var("TERM") {
+        cmd.args(["-e", &format!("TERM={}", term)]);
+    }
+    cmd.arg(&container);
+    if command.is_empty() {
+        cmd.arg(&env.shell);
+    } else {
+        cmd.args(command);
+    }
+
+    info!(
+        "⚡ Entering {} ({} ms)",
+        env.name,
+        started.elapsed().as_millis()
+    );
+    let status = cmd
+        .status()
+        .await
+        .context("Failed to exec in the dev container");
+    if let Some(relay) = relay {
+        relay.stop();
+    }
+    let status = status?;
+    debug!("Dev session exited with {}", status);
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Bring a kept container back to running
+async fn resume(runtime: &str, container: &str, status: &str) -> Result<()> {
+    let action = match status {
+        "running" => return Ok(()),
+        "paused" => "unpause",
+        _ => "start",
+    };
+    let output = AsyncCommand::new(runtime)
+        .args([action, container])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to {} {}: {}",
+            action,
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Run the setup on the base image and commit the result as `snapshot`
+async fn build_snapshot(
+    runtime: &str,
+    data_dir: &Path,
+    env: &DevEnvironment,
+    snapshot: &str,
+) -> Result<()> {
+    let started = Instant::now();
+    let builder = format!("{}-warmup", env.container());
+    remove_container(runtime, &builder).await;
+    info!(
+        "🧊 Building warm snapshot for {} from {} ({} setup steps)",
+        env.name,
+        env.image,
+        env.setup.len()
+    );
+
+    // Mounted caches aren't committed, so the setup can fill them freely
+    let caches = crate::builds::artifacts::resolve(data_dir, &env.cache_volumes)?;
+    let _lease = crate::builds::artifacts::lease(data_dir, &caches)?;
+    let status = AsyncCommand::new(runtime)
+        .args(["run", "--name", &builder])
+        .args(crate::builds::artifacts::run_args(&caches))
+        .args(["--entrypoint", "/bin/sh"])
+        .arg(&env.image)
+        .arg("-c")
+        .arg(env.setup.join(" && "))
+        .status()
+        .await
+        .context("Failed to start the setup container")?;
+    if !status.success() {
+        remove_container(runtime, &builder).await;
+        return Err(anyhow!("Setup of dev environment {} failed", env.name));
+    }
+
+    let output = AsyncCommand::new(runtime)
+        .args(["commit", &builder, snapshot])
+        .output()
+        .await?;
+    remove_container(runtime, &builder).await;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to commit warm snapshot {}: {}",
+            snapshot,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!(
+        "✅ Warm snapshot {} ready in {:.1}s",
+        snapshot,
+        started.elapsed().as_secs_f64()
+    );
+
+    // Earlier snapshots of this environment are no longer used
+    let repository = snapshot.rsplit_once(':').map_or(snapshot, |(r, _)| r);
+    for stale in snapshots(runtime, repository).await {
+        if stale != snapshot {
+            remove_image(runtime, &stale).await;
+        }
+    }
+    Ok(())
+}
+
+async fn create(
+    runtime: &str,
+    data_dir: &Path,
+    env: &DevEnvironment,
+    snapshot: &str,
+    config_key: &str,
+) -> Result<()> {
+    let container = env.container();
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.args(["run", "-d", "--name", &container, "--hostname", &env.name])
+        .args(["--label", &format!("bolt.dev={}", env.name)])
+        .args(["--label", &format!("bolt.dev.config={}", config_key)])
+        .args(["-v", &format!("{}:{}", env.root.display(), env.workdir)])
+        .args(["-w", &env.workdir]);
+    if env.ssh_agent {
+        let dir = agent_dir(data_dir, &env.name);
+        std::fs::create_dir_all(&dir)?;
+        cmd.args(["-v", &format!("{}:{}", dir.display(), SSH_AGENT_DIR)])
+            .args(["-e", &format!("SSH_AUTH_SOCK={}/agent.sock", SSH_AGENT_DIR)]);
+    }
+    for port in &env.ports {
+        cmd.args(["-p", port]);
+    }
+    for volume in &env.volumes {
+        cmd.args(["-v", volume]);
+    }
+    cmd.args(crate::builds::artifacts::run_args(
+        &crate::builds::artifacts::resolve(data_dir, &env.cache_volumes)?,
+    ));
+    for (key, value) in &env.env {
+        cmd.args(["-e", &format!("{}={}", key, value)]);
+    }
+    cmd.args(["--entrypoint", "sleep", snapshot, "infinity"]);
+
+    let output = cmd
+        .output()
+        .await
+        .context("Failed to create dev container")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to create {}: {}",
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!("📦 Created {} from {}", container, snapshot);
+    Ok(())
+}
+
+/// Relays connections on `<dir>/agent.sock` to the host's SSH agent
+struct AgentRelay {
+    socket: PathBuf,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl AgentRelay {
+    fn stop(self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Start relaying, unless another session already does
+async fn relay_agent(dir: &Path, agent: PathBuf) -> Option<AgentRelay> {
+    let socket = dir.join("agent.sock");
+    if UnixStream::connect(&socket).await.is_ok() {
+        return None;
+    }
+    let _ = std::fs::remove_file(&socket);
+    let listener = match std::fs::create_dir_all(dir).and_then(|()| UnixListener::bind(&socket)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Could not forward the SSH agent: {}", e);
+            return None;
+        }
+    };
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&socket, std::fs::Permissions::from_mode(0o600));
+    }
+
+    let task = tokio::spawn(async move {
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                match UnixStream::connect(&agent).await {
+                    Ok(mut outbound) => {
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                    }
+                    Err(e) => debug!("SSH agent at {:?} unreachable: {}", agent, e),
+                }
+            });
+        }
+    });
+    Some(AgentRelay { socket, task })
+}
+
+/// Recorded environments with their containers' state
+pub async fn list(runtime: &str, data_dir: &Path) -> Vec<DevStatus> {
+    let mut records: Vec<DevRecord> = std::fs::read_dir(dev_dir(data_dir))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .filter_map(|e| std::fs::read(e.path()).ok())
+                .filter_map(|b| serde_json::from_slice(&b).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut statuses = Vec::new();
+    for record in records {
+        let status = inspect(runtime, &record.container, "{{.State.Status}}").await;
+        statuses.push(DevStatus { record, status });
+    }
+    statuses
+}
+
+/// Stop an environment's container, keeping it for the next `enter`
+pub async fn stop(runtime: &str, data_dir: &Path, name: &str) -> Result<()> {
+    let record =
+        record(data_dir, name).ok_or_else(|| anyhow!("No dev environment named {}", name))?;
+    let output = AsyncCommand::new(runtime)
+        .args(["stop", "--time", "1", &record.container])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to stop {}: {}",
+            record.container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!("⏹️  Stopped dev environment {}", name);
+    Ok(())
+}
+
+/// Remove an environment's container and record, and its warm snapshot with
+/// `snapshot`. The repository is untouched.
+pub async fn remove(runtime: &str, data_dir: &Path, name: &str, snapshot: bool) -> Result<()> {
+    let record =
+        record(data_dir, name).ok_or_else(|| anyhow!("No dev environment named {}", name))?;
+    remove_container(runtime, &record.container).await;
+    if snapshot && record.snapshot.starts_with("localhost/bolt-dev/") {
+        remove_image(runtime, &record.snapshot).await;
+    }
+    let _ = std::fs::remove_file(record_path(data_dir, name));
+    let _ = std::fs::remove_dir_all(agent_dir(data_dir, name));
+    info!("🗑️  Removed dev environment {}", name);
+    Ok(())
+}
+
+async fn inspect(runtime: &str, container: &str, format: &str) -> Option<String> {
+    let output = AsyncCommand::new(runtime)
+        .args(["container", "inspect", "--format", format, container])
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+async fn remove_container(runtime: &str, container: &str) {
+    let _ = AsyncCommand::new(runtime)
+        .args(["rm", "-f", container])
+        .output()
+        .await;
+}
+
+async fn remove_image(runtime: &str, image: &str) {
+    let _ = AsyncCommand::new(runtime)
+        .args(["rmi", image])
+        .output()
+        .await;
+}
+
+/// Tags of `repository`, as `<repository>:<tag>`
+async fn snapshots(runtime: &str, repository: &str) -> Vec<String> {
+    let Ok(output) = AsyncCommand::new(runtime)
+        .args(["images", "--format", "{{.Repository}}:{{.Tag}}"])
+        .output()
+        .await
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| {
+            l.strip_prefix(repository)
+                .is_some_and(|t| t.starts_with(':'))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_dev_toml_and_keys_snapshot_separately_from_container() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("My Repo");
+        std::fs::create_dir_all(root.join(".bolt")).unwrap();
+        std::fs::create_dir_all(root.join("crates/core")).unwrap();
+        std::fs::write(
+            root.join(DEV_FILE),
+            r#"
+template = "rust"
+setup = ["cargo install cargo-watch"]
+ports = ["3000:3000"]
+"#,
+        )
+        .unwrap();
+
+        let env = DevEnvironment::find(&root.join("crates/core")).unwrap();
+        assert_eq!(env.name, "my-repo");
+        assert_eq!(env.container(), "bolt-dev-my-repo");
+        assert_eq!(env.image, "docker.io/library/rust:1-bookworm");
+        assert_eq!(env.shell, "/bin/bash");
+        assert_eq!(env.setup.last().unwrap(), "cargo install cargo-watch");
+        assert!(env.ssh_agent);
+        assert!(env.snapshot().starts_with("localhost/bolt-dev/my-repo:"));
+        assert_eq!(
+            env.container_dir(&root.join("crates/core")),
+            "/workspace/crates/core"
+        );
+        assert_eq!(env.container_dir(&root), "/workspace");
+
+        // Ports only recreate the container; setup needs a new snapshot
+        let mut ported = env.clone();
+        ported.ports.push("8080:8080".to_string());
+        assert_eq!(ported.snapshot(), env.snapshot());
+        assert_ne!(ported.config_key(), env.config_key());
+        let mut extended = env.clone();
+        extended.setup.push("apt-get install -y jq".to_string());
+        assert_ne!(extended.snapshot(), env.snapshot());
+
+        let bare = DevEnvironment::from_spec(
+            DevSpec {
+                image: Some("alpine".to_string()),
+                ..Default::default()
+            },
+            &root,
+        )
+        .unwrap();
+        assert_eq!(bare.snapshot(), "alpine");
+        assert!(
+            DevEnvironment::from_spec(
+                DevSpec {
+                    template: Some("cobol".to_string()),
+                    ..Default::default()
+                },
+                &root
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::BoltConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "✅",
+            CheckStatus::Warn => "⚠️ ",
+            CheckStatus::Fail => "❌",
+        }
+    }
+}
+
+/// One preflight check result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorCheck {
+    /// Subsystem the check belongs to, e.g. "runtime" or "network/lan"
+    pub area: String,
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl DoctorCheck {
+    pub fn pass(area: &str, name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(area, name, CheckStatus::Pass, detail, None)
+    }
+
+    pub fn warn(
+        area: &str,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self::new(area, name, CheckStatus::Warn, detail, Some(hint.into()))
+    }
+
+    pub fn fail(
+        area: &str,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self::new(area, name, CheckStatus::Fail, detail, Some(hint.into()))
+    }
+
+    fn new(
+        area: &str,
+        name: impl Into<String>,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        hint: Option<String>,
+    ) -> Self {
+        Self {
+            area: area.to_string(),
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            hint,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|c| c.status == CheckStatus::Fail)
+    }
+}
+
+/// Run host preflight checks, including those for hardware configured in the Boltfile
+pub async fn run(config: &BoltConfig) -> DoctorReport {
+    let mut checks = Vec::new();
+
+    match super::detect_container_runtime().await {
+        Ok(runtime) => {
+            if runtime == "podman" {
+                checks.push(keyring_check());
+            }
+            checks.push(DoctorCheck::pass("runtime", "container runtime", runtime));
+        }
+        Err(e) => checks.push(DoctorCheck::fail(
+            "runtime",
+            "container runtime",
+            e.to_string(),
+            "install podman (preferred) or docker",
+        )),
+    }
+
+    let cgroups = super::cgroups::CgroupLayout::current();
+    checks.push(match cgroups.mode {
+        super::cgroups::CgroupMode::Unified => {
+            DoctorCheck::pass("runtime", "cgroups", "v2 unified hierarchy mounted")
+        }
+        super::cgroups::CgroupMode::Unavailable => DoctorCheck::warn(
+            "runtime",
+            "cgroups",
+            "no cgroup hierarchy mounted, resource limits are not applied",
+            "boot with systemd.unified_cgroup_hierarchy=1 for resource limits",
+        ),
+        mode => DoctorCheck::warn(
+            "runtime",
+            "cgroups",
+            format!(
+                "{}: limits use the v1 fallback; {}",
+                mode,
+                cgroups.degradations().join("; ")
+            ),
+            "boot with systemd.unified_cgroup_hierarchy=1 for PSI and full resource control",
+        ),
+    });
+
+    checks.push(match std::fs::create_dir_all(&config.data_dir) {
+        Ok(()) => DoctorCheck::pass(
+            "runtime",
+            "data directory",
+            config.data_dir.display().to_string(),
+        ),
+        Err(e) => DoctorCheck::fail(
+            "runtime",
+            "data directory",
+            format!("{}: {}", config.data_dir.display(), e),
+            "check permissions or set XDG_DATA_HOME",
+        ),
+    });
+
+    if super::rootless::active() {
+        checks.extend(super::rootless::doctor_checks());
+    }
+
+    let boltfile_path = crate::config::template::resolve_path(&config.boltfile_path);
+    if boltfile_path.exists() {
+        match config.load_boltfile() {
+            Ok(boltfile) => {
+                for (name, network) in boltfile.networks.iter().flatten() {
+                    if let Some(ref hardware) = network.hardware {
+                        checks.extend(crate::networking::hardware::preflight(name, hardware).await);
+                    }
+                }
+                checks.extend(emulation_checks(&boltfile));
+                #[cfg(feature = "gaming")]
+                checks.extend(gaming_checks(&boltfile).await);
+            }
+            Err(e) => checks.push(DoctorCheck::fail(
+                "boltfile",
+                "parse",
+                e.to_string(),
+                format!("fix {}", boltfile_path.display()),
+            )),
+        }
+    }
+
+    DoctorReport { checks }
+}
+
+/// Podman gives each container its own session keyring unless
+/// containers.conf turns that off
+fn keyring_check() -> DoctorCheck {
+    match super::keyring::podman_keyring_disabled() {
+        Some(file) => DoctorCheck::warn(
+            "runtime",
+            "container keyrings",
+            format!(
+                "keyring = false in {}: containers share podman's session keyring",
+                file.display()
+            ),
+            format!("remove `keyring = false` from {}", file.display()),
+        ),
+        None => DoctorCheck::pass(
+            "runtime",
+            "container keyrings",
+            "each container gets its own session keyring",
+        ),
+    }
+}
+
+/// A usable binfmt handler for every service `platform` this host can't run
+/// natively
+fn emulation_checks(boltfile: &crate::config::BoltFile) -> Vec<DoctorCheck> {
+    use super::emulation::{self, Support};
+
+    emulation::foreign_services(boltfile)
+        .into_iter()
+        .map(|(name, platform)| {
+            let check = format!("emulation {}", name);
+            match emulation::support(&platform) {
+                Support::Emulated(handler) => DoctorCheck::warn(
+                    "platform",
+                    check,
+                    format!(
+                        "{} runs under {}, several times slower than natively",
+                        platform,
+                        handler.interpreter.display()
+                    ),
+                    "use an image built for this host's architecture when performance matters",
+                ),
+                Support::Unsupported => DoctorCheck::fail(
+                    "platform",
+                    check,
+                    format!("{} can't run on a Linux host", platform),
+                    "use a Linux image",
+                ),
+                support => DoctorCheck::fail(
+                    "platform",
+                    check,
+                    format!("no usable binfmt handler for {}", platform),
+                    emulation::hint(&platform, &support),
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Vulkan inside running GPU services, and the nvidia-container-toolkit
+/// setup when a service uses it
+#[cfg(feature = "gaming")]
+async fn gaming_checks(boltfile: &crate::config::BoltFile) -> Vec<DoctorCheck> {
+    let mut checks = Vec::new();
+    let Ok(runtime) = super::detect_container_runtime().await else {
+        return checks;
+    };
+    let running: Vec<String> = super::list_containers_info(false)
+        .await
+        .map(|containers| containers.into_iter().map(|c| c.name).collect())
+        .unwrap_or_default();
+
+    let uses_toolkit = boltfile.services.values().any(|s| {
+        s.gaming
+            .as_ref()
+            .and_then(|g| g.gpu.as_ref())
+            .is_some_and(|gpu| gpu.runtime.as_deref() == Some("nvidia-ctk"))
+    });
+    if uses_toolkit {
+        checks.push(nvidia_ctk_check().await);
+    }
+
+    for (name, service) in &boltfile.services {
+        if service.gaming.as_ref().is_none_or(|g| g.gpu.is_none()) {
+            continue;
+        }
+        let container = format!("{}_{}", boltfile.project, name);
+        if !running.contains(&container) {
+            checks.push(DoctorCheck::warn(
+                "gaming",
+                format!("vulkan {}", name),
+                "service not running",
+                "start it with `bolt surge up -d` to check Vulkan inside the container",
+            ));
+            continue;
+        }
+        checks.push(
+            match super::gpu::icd::verify_vulkan(&runtime, &container).await {
+                Ok(devices) => {
+                    DoctorCheck::pass("gaming", format!("vulkan {}", name), devices.join(", "))
+                }
+                Err(e) => DoctorCheck::fail(
+                    "gaming",
+                    format!("vulkan {}", name),
+                    e.to_string(),
+                    "check the GPU section of the service and `bolt surge logs`",
+                ),
+            },
+        );
+    }
+    checks
+}
+
+/// Which toolkit path GPU services will use, and whether the CDI spec still
+/// matches the installed driver
+#[cfg(feature = "gaming")]
+async fn nvidia_ctk_check() -> DoctorCheck {
+    use super::gpu::nvidia_ctk::{self, Interop};
+
+    let Some(interop) = Interop::detect() else {
+        return DoctorCheck::fail(
+            "gaming",
+            "nvidia-ctk",
+            "no CDI spec and no nvidia-container-runtime-hook",
+            "run `nvidia-ctk cdi generate --output=/etc/cdi/nvidia.yaml`",
+        );
+    };
+    let Interop::Cdi { ref path, ref spec } = interop else {
+        return DoctorCheck::pass("gaming", "nvidia-ctk", interop.describe());
+    };
+    let all = vec!["all".to_string()];
+    let (cdi, listing) = match (
+        nvidia_ctk::cdi_injection(spec, &all),
+        nvidia_ctk::hook_listing(&all).await,
+    ) {
+        (Ok(cdi), Ok(listing)) => (cdi, listing),
+        // Without nvidia-container-cli there is nothing to compare against
+        _ => return DoctorCheck::pass("gaming", "nvidia-ctk", interop.describe()),
+    };
+    let missing = nvidia_ctk::missing_from_spec(&cdi, &nvidia_ctk::hook_injection(&all, &listing));
+    if missing.is_empty() {
+        DoctorCheck::pass("gaming", "nvidia-ctk", interop.describe())
+    } else {
+        DoctorCheck::warn(
+            "gaming",
+            "nvidia-ctk",
+            format!(
+                "{} is stale: {} driver file(s) missing, e.g. {}",
+                path.display(),
+                missing.len(),
+                missing[0]
+            ),
+            format!("run `nvidia-ctk cdi generate --output={}`", path.display()),
+        )
+    }
+}
@@ -0,0 +1,290 @@
+// Connection draining
+//
+// Before a replica of a service with `[services.<name>.drain]` is stopped,
+// by `surge down`, a scale down or a rolling `surge up --force-recreate`, it
+// is taken out of rotation: a marker under `<data_dir>/drain` makes the
+// QUIC proxy stop sending new clients to its addresses while existing
+// sessions carry on. Bolt then waits until no inbound TCP connections are
+// left in the container's network namespace and the `idle_check` passes, or
+// until `timeout`, and only then stops it.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::DrainConfig;
+
+/// Markers are ignored this long past their timeout, in case the process
+/// that wrote them died mid-drain
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct DrainPolicy {
+    pub timeout: Duration,
+    pub interval: Duration,
+    pub ports: Vec<u16>,
+    pub command: Option<Vec<String>>,
+    pub idle_check: Option<Vec<String>>,
+}
+
+impl DrainPolicy {
+    pub fn from_config(config: &DrainConfig) -> Result<Self> {
+        if config.command.as_ref().is_some_and(|c| c.is_empty())
+            || config.idle_check.as_ref().is_some_and(|c| c.is_empty())
+        {
+            return Err(anyhow!("drain commands must not be empty"));
+        }
+        Ok(Self {
+            timeout: parse_duration(&config.timeout)?,
+            interval: parse_duration(&config.interval)?.max(Duration::from_millis(100)),
+            ports: config.ports.clone(),
+            command: config.command.clone(),
+            idle_check: config.idle_check.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DrainMarker {
+    container: String,
+    addresses: Vec<IpAddr>,
+    started_at: chrono::DateTime<chrono::Utc>,
+    timeout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrainOutcome {
+    pub elapsed: Duration,
+    /// Inbound connections still open when draining ended
+    pub remaining: usize,
+    pub timed_out: bool,
+}
+
+fn marker_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("drain")
+}
+
+/// Take `container` out of rotation and wait for its sessions to finish.
+/// Call [`finish`] once the container is stopped.
+pub async fn drain(
+    runtime: &str,
+    data_dir: &Path,
+    container: &str,
+    policy: &DrainPolicy,
+) -> Result<DrainOutcome> {
+    let started = Instant::now();
+    let marker = DrainMarker {
+        container: container.to_string(),
+        addresses: container_addresses(runtime, container).await,
+        started_at: chrono::Utc::now(),
+        timeout_secs: policy.timeout.as_secs(),
+    };
+    let dir = marker_dir(data_dir);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        dir.join(format!("{}.json", container)),
+        serde_json::to_vec(&marker)?,
+    )?;
+    info!(
+        "🚰 Draining {} (up to {}s)",
+        container,
+        policy.timeout.as_secs()
+    );
+
+    if let Some(ref argv) = policy.command {
+        let status = AsyncCommand::new(runtime)
+            .arg("exec")
+            .arg(container)
+            .args(argv)
+            .status()
+            .await
+            .context("Failed to run the drain command")?;
+        if !status.success() {
+            warn!("Drain command in {} exited with {}", container, status);
+        }
+    }
+
+    loop {
+        let Some(pid) = running_pid(runtime, container).await else {
+            debug!("{} exited while draining", container);
+            break;
+        };
+        let remaining = count_inbound(&read_tcp_tables(pid), &policy.ports);
+        let idle = match policy.idle_check {
+            Some(ref argv) => idle_check(runtime, container, argv).await,
+            None => true,
+        };
+        if remaining == 0 && idle {
+            break;
+        }
+        if started.elapsed() >= policy.timeout {
+            warn!(
+                "⏱️ {} still had {} connection(s){} after {}s; stopping it anyway",
+                container,
+                remaining,
+                if idle { "" } else { " and active sessions" },
+                policy.timeout.as_secs()
+            );
+            return Ok(DrainOutcome {
+                elapsed: started.elapsed(),
+                remaining,
+                timed_out: true,
+            });
+        }
+        debug!("{}: {} inbound connection(s) left", container, remaining);
+        tokio::time::sleep(policy.interval).await;
+    }
+
+    info!(
+        "✅ {} drained in {:.1}s",
+        container,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(DrainOutcome {
+        elapsed: started.elapsed(),
+        remaining: 0,
+        timed_out: false,
+    })
+}
+
+/// Remove the drain marker of a stopped container
+pub fn finish(data_dir: &Path, container: &str) {
+    let _ = std::fs::remove_file(marker_dir(data_dir).join(format!("{}.json", container)));
+}
+
+/// Addresses of replicas being drained, which must not get new clients
+pub fn draining_addresses(data_dir: &Path) -> HashSet<IpAddr> {
+    let mut addresses = HashSet::new();
+    let Ok(entries) = std::fs::read_dir(marker_dir(data_dir)) else {
+        return addresses;
+    };
+    let now = chrono::Utc::now();
+    for entry in entries.flatten() {
+        let Some(marker) = std::fs::read(entry.path())
+            .ok()
+            .and_then(|b| serde_json::from_slice::<DrainMarker>(&b).ok())
+        else {
+            continue;
+        };
+        let expires = marker.started_at
+            + chrono::Duration::seconds((marker.timeout_secs + STALE_AFTER.as_secs()) as i64);
+        if now < expires {
+            addresses.extend(marker.addresses);
+        }
+    }
+    addresses
+}
+
+/// Count established TCP connections whose local port is one the container
+/// listens on (or one of `ports`), i.e. inbound connections, from
+/// `/proc/<pid>/net/tcp{,6}` contents
+fn count_inbound(tables: &[String], ports: &[u16]) -> usize {
+    const ESTABLISHED: &str = "01";
+    const LISTEN: &str = "0A";
+
+    let sockets: Vec<(u16, &str)> = tables
+        .iter()
+        .flat_map(|t| t.lines().skip(1))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let port = fields.get(1)?.rsplit(':').next()?;
+            Some((u16::from_str_radix(port, 16).ok()?, *fields.get(3)?))
+        })
+        .collect();
+
+    let inbound: HashSet<u16> = if ports.is_empty() {
+        sockets
+            .iter()
+            .filter(|(_, state)| *state == LISTEN)
+            .map(|(port, _)| *port)
+            .collect()
+    } else {
+        ports.iter().copied().collect()
+    };
+    sockets
+        .iter()
+        .filter(|(port, state)| *state == ESTABLISHED && inbound.contains(port))
+        .count()
+}
+
+fn read_tcp_tables(pid: u32) -> Vec<String> {
+    ["tcp", "tcp6"]
+        .iter()
+        .filter_map(|t| std::fs::read_to_string(format!("/proc/{}/net/{}", pid, t)).ok())
+        .collect()
+}
+
+async fn running_pid(runtime: &str, container: &str) -> Option<u32> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Pid}}", container])
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| *pid > 0)
+}
+
+async fn container_addresses(runtime: &str, container: &str) -> Vec<IpAddr> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            "{{range .NetworkSettings.Networks}}{{.IPAddress}} {{.GlobalIPv6Address}} {{end}}",
+            container,
+        ])
+        .output()
+        .await;
+    output
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .split_whitespace()
+                .filter_map(|ip| ip.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn idle_check(runtime: &str, container: &str, argv: &[String]) -> bool {
+    AsyncCommand::new(runtime)
+        .arg("exec")
+        .arg(container)
+        .args(argv)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_only_inbound_connections() {
+        // :8080 listening with two clients, plus an outbound connection to :5432
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1 1 0 100 0 0 10 0
+   1: 0200000A:1F90 0100000A:D431 01 00000000:00000000 00:00000000 00000000     0        0 2 1 0 20 4 30 10 -1
+   2: 0200000A:1F90 0100000A:D432 01 00000000:00000000 00:00000000 00000000     0        0 3 1 0 20 4 30 10 -1
+   3: 0200000A:9C40 0300000A:1538 01 00000000:00000000 00:00000000 00000000     0        0 4 1 0 20 4 30 10 -1
+"
+        .to_string();
+        assert_eq!(count_inbound(std::slice::from_ref(&tcp), &[]), 2);
+        assert_eq!(count_inbound(&[tcp], &[9000]), 0);
+
+        let config = DrainConfig {
+            timeout: "soon".to_string(),
+            ..Default::default()
+        };
+        assert!(DrainPolicy::from_config(&config).is_err());
+    }
+}
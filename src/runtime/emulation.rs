@@ -0,0 +1,285 @@
+// Foreign-architecture containers
+//
+// An arm64-only image runs on an x86_64 host when the kernel has a binfmt_misc
+// handler for arm64 binaries, normally qemu-user-static. Containers need the
+// handler registered with the F (fix binary) flag: the interpreter is then
+// opened at registration time, so it works inside the container's mount
+// namespace where /usr/bin/qemu-aarch64-static doesn't exist.
+//
+// Before a container for another platform starts, Bolt checks the handler.
+// A missing one is an error with a hint, or is registered by running
+// tonistiigi/binfmt when `[platform] setup_emulation = true`. Emulated
+// containers are labelled `bolt.platform=<platform>`, which `bolt ps` uses to
+// mark them: everything they execute is translated instruction by instruction
+// and runs several times slower than natively.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use super::platform::{self, Platform};
+
+/// Label carrying the platform of an emulated container
+pub const LABEL: &str = "bolt.platform";
+
+/// Image that registers qemu-user-static handlers with the F flag
+pub const BINFMT_IMAGE: &str = "docker.io/tonistiigi/binfmt:latest";
+
+const BINFMT_MISC: &str = "/proc/sys/fs/binfmt_misc";
+
+/// A registered binfmt_misc handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handler {
+    pub enabled: bool,
+    pub interpreter: PathBuf,
+    pub flags: String,
+}
+
+impl Handler {
+    /// Parse `/proc/sys/fs/binfmt_misc/<name>`
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut enabled = false;
+        let mut interpreter = None;
+        let mut flags = String::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line == "enabled" {
+                enabled = true;
+            } else if let Some(path) = line.strip_prefix("interpreter ") {
+                interpreter = Some(PathBuf::from(path.trim()));
+            } else if let Some(value) = line.strip_prefix("flags:") {
+                flags = value.trim().to_string();
+            }
+        }
+        Some(Self {
+            enabled,
+            interpreter: interpreter?,
+            flags,
+        })
+    }
+
+    /// Whether the interpreter was opened at registration, so containers
+    /// can use it
+    pub fn fix_binary(&self) -> bool {
+        self.flags.contains('F')
+    }
+}
+
+/// Whether this host can run a platform's binaries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Support {
+    Native,
+    Emulated(Handler),
+    /// A handler exists but is disabled or lacks the F flag
+    Unusable(Handler),
+    Missing,
+    /// Not a Linux platform; nothing emulates it
+    Unsupported,
+}
+
+impl Support {
+    pub fn usable(&self) -> bool {
+        matches!(self, Support::Native | Support::Emulated(_))
+    }
+}
+
+/// qemu's name for a platform's architecture
+pub fn qemu_arch(platform: &Platform) -> String {
+    match platform.architecture.as_str() {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "386" => "i386",
+        other => other,
+    }
+    .to_string()
+}
+
+/// The handler for `platform` under a binfmt_misc mount
+pub fn handler_in(root: &Path, platform: &Platform) -> Option<Handler> {
+    let arch = qemu_arch(platform);
+    [format!("qemu-{}", arch), format!("qemu-{}-static", arch)]
+        .iter()
+        .find_map(|name| Handler::parse(&std::fs::read_to_string(root.join(name)).ok()?))
+}
+
+/// Whether this host runs `platform` natively, under emulation, or not at all
+pub fn support(platform: &Platform) -> Support {
+    let host = Platform::host();
+    // x86_64 kernels run i386 binaries themselves
+    if platform.serves(&host)
+        || (platform.os == "linux"
+            && host.architecture == "amd64"
+            && platform.architecture == "386")
+    {
+        return Support::Native;
+    }
+    if platform.os != "linux" {
+        return Support::Unsupported;
+    }
+    let globally_enabled = std::fs::read_to_string(Path::new(BINFMT_MISC).join("status"))
+        .is_ok_and(|s| s.trim() == "enabled");
+    match handler_in(Path::new(BINFMT_MISC), platform) {
+        Some(handler) if globally_enabled && handler.enabled && handler.fix_binary() => {
+            Support::Emulated(handler)
+        }
+        Some(handler) => Support::Unusable(handler),
+        None => Support::Missing,
+    }
+}
+
+/// Boltfile services whose `platform` this host doesn't run natively
+pub fn foreign_services(boltfile: &crate::config::BoltFile) -> Vec<(String, Platform)> {
+    let mut services: Vec<(String, Platform)> = boltfile
+        .services
+        .iter()
+        .filter_map(|(name, service)| {
+            let platform = Platform::parse(service.platform.as_deref()?).ok()?;
+            (support(&platform) != Support::Native).then(|| (name.clone(), platform))
+        })
+        .collect();
+    services.sort_by(|a, b| a.0.cmp(&b.0));
+    services
+}
+
+/// Register qemu-user-static handlers for `platforms` with tonistiigi/binfmt
+pub async fn register(runtime: &str, platforms: &[Platform]) -> Result<()> {
+    let arches: Vec<String> = platforms.iter().map(|p| p.architecture.clone()).collect();
+    info!("🧩 Registering binfmt handlers for {}", arches.join(", "));
+    let output = AsyncCommand::new(runtime)
+        .args(["run", "--rm", "--privileged", BINFMT_IMAGE, "--install"])
+        .arg(arches.join(","))
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to register binfmt handlers: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    for platform in platforms {
+        if !support(platform).usable() {
+            return Err(anyhow!(
+                "{} registered no usable handler for {}",
+                BINFMT_IMAGE,
+                platform
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// How to make `platform` runnable when `support` says it isn't
+pub fn hint(platform: &Platform, support: &Support) -> String {
+    let arch = qemu_arch(platform);
+    match support {
+        Support::Unusable(handler) if !handler.enabled => format!(
+            "the qemu-{} handler is disabled; run `echo 1 | sudo tee {}/qemu-{}`",
+            arch, BINFMT_MISC, arch
+        ),
+        Support::Unusable(_) => format!(
+            "the qemu-{} handler lacks the F flag, so containers can't use it; run \
+             `bolt doctor --setup-emulation` to re-register it",
+            arch
+        ),
+        _ => format!(
+            "install qemu-user-static, or run `bolt doctor --setup-emulation`, or set \
+             `[platform] setup_emulation = true` in config.toml to register qemu-{} \
+             automatically",
+            arch
+        ),
+    }
+}
+
+/// Make sure a container for `platform` can start here, registering the
+/// handler when `setup` is set
+pub async fn ensure(runtime: &str, platform: &Platform, setup: bool) -> Result<()> {
+    let found = support(platform);
+    if found.usable() {
+        return Ok(());
+    }
+    if found == Support::Unsupported {
+        return Err(anyhow!(
+            "{} can't run on this {} host",
+            platform,
+            Platform::host()
+        ));
+    }
+    if setup {
+        return register(runtime, std::slice::from_ref(platform)).await;
+    }
+    Err(anyhow!(
+        "No binfmt handler to run {} on this {} host: {}",
+        platform,
+        Platform::host(),
+        hint(platform, &found)
+    ))
+}
+
+/// Run arguments for a container of `image`: `--platform` and the emulation
+/// label when the local image is for another architecture
+pub async fn run_args(runtime: &str, image: &str, setup: bool) -> Result<Vec<String>> {
+    let Some(image_platform) = platform::local_platform(runtime, image).await else {
+        return Ok(Vec::new());
+    };
+    if support(&image_platform) == Support::Native {
+        return Ok(Vec::new());
+    }
+    ensure(runtime, &image_platform, setup).await?;
+    warn!(
+        "🐢 {} is {}, emulated on this {} host: expect it to run several times slower",
+        image,
+        image_platform,
+        Platform::host()
+    );
+    Ok(vec![
+        "--platform".to_string(),
+        image_platform.to_string(),
+        "--label".to_string(),
+        format!("{}={}", LABEL, image_platform),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_binfmt_handlers_and_requires_the_fix_binary_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("qemu-aarch64"),
+            "enabled\ninterpreter /usr/bin/qemu-aarch64-static\nflags: POCF\noffset 0\n\
+             magic 7f454c460201010000000000000000000200b700\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("qemu-riscv64-static"),
+            "disabled\ninterpreter /usr/bin/qemu-riscv64\nflags: \n",
+        )
+        .unwrap();
+
+        let arm64 = Platform::parse("linux/arm64").unwrap();
+        let handler = handler_in(dir.path(), &arm64).unwrap();
+        assert!(handler.enabled && handler.fix_binary());
+        assert_eq!(
+            handler.interpreter,
+            PathBuf::from("/usr/bin/qemu-aarch64-static")
+        );
+
+        let riscv = Platform::parse("linux/riscv64").unwrap();
+        let handler = handler_in(dir.path(), &riscv).unwrap();
+        assert!(!handler.enabled && !handler.fix_binary());
+        assert!(hint(&riscv, &Support::Unusable(handler)).contains("disabled"));
+        assert!(handler_in(dir.path(), &Platform::parse("linux/s390x").unwrap()).is_none());
+        assert_eq!(
+            qemu_arch(&Platform::parse("linux/amd64").unwrap()),
+            "x86_64"
+        );
+        assert_eq!(support(&Platform::host()), Support::Native);
+        assert_eq!(
+            support(&Platform::parse("windows/amd64").unwrap()),
+            Support::Unsupported
+        );
+    }
+}
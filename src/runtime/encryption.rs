@@ -0,0 +1,237 @@
+// Encrypted images
+//
+// Images can be pushed with encrypted layers (the OCI `+encrypted` media
+// types of ocicrypt, as used by podman, skopeo and containerd's imgcrypt), so
+// proprietary server builds don't sit in plaintext on shared registries.
+// Layers are encrypted for one or more recipients:
+//
+//   jwe:<public key .pem>   pkcs7:<x509 certificate .pem>   pgp:<email>
+//
+// and decrypted on pull with the private keys listed under `[encryption]
+// decryption_keys` in config.toml. Those are secret references, usually
+// `secret:<name>` holding a PEM key, and are only written to a private
+// temporary directory while a pull runs.
+//
+// Podman encrypts and decrypts by itself. Docker can't, so images go through
+// skopeo between the Docker daemon and the registry. An image that can't be
+// decrypted is never started: its pull fails with IMAGE_DECRYPTION_FAILED.
+use anyhow::{Context, Result, anyhow};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use tokio::process::Command as AsyncCommand;
+use tracing::info;
+
+use super::platform::Platform;
+use crate::config::BoltConfig;
+use crate::error::{BoltError, RuntimeError};
+
+const RECIPIENT_SCHEMES: [&str; 4] = ["jwe:", "pkcs7:", "pgp:", "provider:"];
+
+/// Private keys for decrypting pulled images, on disk until dropped
+#[derive(Debug, Default)]
+pub struct DecryptionKeys {
+    _dir: Option<tempfile::TempDir>,
+    paths: Vec<PathBuf>,
+}
+
+impl DecryptionKeys {
+    /// Resolve `[encryption] decryption_keys`; empty when none are configured
+    pub fn load(config: &BoltConfig) -> Result<Self> {
+        let references = &config.encryption.decryption_keys;
+        if references.is_empty() {
+            return Ok(Self::default());
+        }
+        let store = crate::secrets::SecretStore::new(&config.config_dir);
+        let dir = tempfile::Builder::new().prefix("bolt-keys-").tempdir()?;
+        let mut paths = Vec::new();
+        for (i, reference) in references.iter().enumerate() {
+            let key = store
+                .resolve(reference)
+                .with_context(|| format!("Cannot read decryption key {}", reference))?;
+            let path = dir.path().join(format!("key-{}.pem", i));
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)?;
+            file.write_all(key.as_bytes())?;
+            paths.push(path);
+        }
+        Ok(Self {
+            _dir: Some(dir),
+            paths,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    fn args(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .flat_map(|p| ["--decryption-key".to_string(), p.display().to_string()])
+            .collect()
+    }
+}
+
+pub fn validate_recipient(recipient: &str) -> Result<()> {
+    let scheme = RECIPIENT_SCHEMES
+        .iter()
+        .find(|scheme| recipient.starts_with(**scheme));
+    match scheme {
+        Some(scheme) if recipient.len() > scheme.len() => Ok(()),
+        _ => Err(anyhow!(
+            "Invalid encryption recipient '{}': use jwe:<public key>, pkcs7:<certificate> or pgp:<email>",
+            recipient
+        )),
+    }
+}
+
+/// Pull `image`, decrypting its layers with `keys`
+pub async fn pull(
+    runtime: &str,
+    image: &str,
+    platform: Option<&Platform>,
+    keys: &DecryptionKeys,
+) -> Result<()> {
+    info!("🔐 Pulling encrypted image: {}", image);
+    let mut cmd = if runtime == "podman" {
+        let mut cmd = AsyncCommand::new("podman");
+        cmd.arg("pull").args(keys.args());
+        if let Some(platform) = platform {
+            cmd.arg("--platform").arg(platform.to_string());
+        }
+        cmd.arg(image);
+        cmd
+    } else {
+        let mut cmd = AsyncCommand::new("skopeo");
+        cmd.arg("copy");
+        if let Some(platform) = platform {
+            cmd.args(["--override-os", &platform.os])
+                .args(["--override-arch", &platform.architecture]);
+            if let Some(ref variant) = platform.variant {
+                cmd.args(["--override-variant", variant]);
+            }
+        }
+        cmd.args(keys.args())
+            .arg(format!("docker://{}", image))
+            .arg(format!("docker-daemon:{}", daemon_reference(image)?));
+        cmd
+    };
+    run(&mut cmd, image, "pull").await?;
+    info!("✅ Image pulled and decrypted: {}", image);
+    Ok(())
+}
+
+/// Push `image` with its layers encrypted for `recipients`; `layers` picks
+/// which (0 is the first, -1 the last), all when empty
+pub async fn push(runtime: &str, image: &str, recipients: &[String], layers: &[i32]) -> Result<()> {
+    for recipient in recipients {
+        validate_recipient(recipient)?;
+    }
+    let mut args = Vec::new();
+    for recipient in recipients {
+        args.push("--encryption-key".to_string());
+        args.push(recipient.clone());
+    }
+    for layer in layers {
+        args.push("--encrypt-layer".to_string());
+        args.push(layer.to_string());
+    }
+
+    info!(
+        "🔐 Pushing {} encrypted for {} recipient(s)",
+        image,
+        recipients.len()
+    );
+    let mut cmd = if runtime == "podman" {
+        let mut cmd = AsyncCommand::new("podman");
+        cmd.arg("push").args(&args).arg(image);
+        cmd
+    } else {
+        let mut cmd = AsyncCommand::new("skopeo");
+        cmd.arg("copy")
+            .args(&args)
+            .arg(format!("docker-daemon:{}", daemon_reference(image)?))
+            .arg(format!("docker://{}", image));
+        cmd
+    };
+    run(&mut cmd, image, "push").await?;
+    info!("✅ Encrypted image pushed: {}", image);
+    Ok(())
+}
+
+async fn run(cmd: &mut AsyncCommand, image: &str, action: &str) -> Result<()> {
+    let output = cmd.output().await.map_err(|e| {
+        anyhow!(
+            "Encrypted images need podman, or skopeo next to Docker: {}",
+            e
+        )
+    })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let fallback = RuntimeError::OciError {
+        message: format!("Failed to {} {}: {}", action, image, stderr.trim()),
+    };
+    Err(BoltError::Runtime(RuntimeError::classify(image, &stderr, fallback)).into())
+}
+
+/// Docker daemon references need a tag and can't be digests
+pub(crate) fn daemon_reference(image: &str) -> Result<String> {
+    if image.contains('@') {
+        return Err(anyhow!(
+            "Encrypted images can't be referenced by digest with Docker; use a tag for {}",
+            image
+        ));
+    }
+    let name = image.rsplit('/').next().unwrap_or(image);
+    Ok(if name.contains(':') {
+        image.to_string()
+    } else {
+        format!("{}:latest", image)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn checks_recipients_and_classifies_decryption_failures() {
+        assert!(validate_recipient("jwe:/etc/bolt/keys/partner.pub.pem").is_ok());
+        assert!(validate_recipient("pgp:ops@example.com").is_ok());
+        assert!(validate_recipient("jwe:").is_err());
+        assert!(validate_recipient("/etc/bolt/keys/partner.pub.pem").is_err());
+
+        assert_eq!(
+            daemon_reference("registry.local:5000/arena").unwrap(),
+            "registry.local:5000/arena:latest"
+        );
+        assert_eq!(daemon_reference("arena:1.2").unwrap(), "arena:1.2");
+        assert!(daemon_reference("arena@sha256:abc").is_err());
+
+        let keys = DecryptionKeys {
+            _dir: None,
+            paths: vec![PathBuf::from("/tmp/k.pem")],
+        };
+        assert_eq!(keys.args(), vec!["--decryption-key", "/tmp/k.pem"]);
+
+        let fallback = RuntimeError::OciError {
+            message: String::new(),
+        };
+        let error = RuntimeError::classify(
+            "arena:1.2",
+            "Error: writing blob: decrypting layer: missing private key needed for decryption",
+            fallback,
+        );
+        assert_eq!(
+            BoltError::Runtime(error).code(),
+            ErrorCode::ImageDecryptionFailed
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// A lifecycle event recorded for a container
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerEvent {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub container: String,
+    pub action: String,
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+}
+
+impl ContainerEvent {
+    pub fn new(container: &str, action: &str) -> Self {
+        Self {
+            timestamp: chrono::Utc::now(),
+            container: container.to_string(),
+            action: action.to_string(),
+            attributes: HashMap::from([(
+                "trace_id".to_string(),
+                crate::monitoring::logging::trace_id().to_string(),
+            )]),
+        }
+    }
+
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// Append-only per-container event history, one JSON object per line
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    pub events_dir: PathBuf,
+}
+
+impl EventLog {
+    pub fn new(data_dir: PathBuf) -> Result<Self> {
+        let events_dir = data_dir.join("events");
+        std::fs::create_dir_all(&events_dir).context("Failed to create events directory")?;
+        Ok(Self { events_dir })
+    }
+
+    pub fn record(&self, event: &ContainerEvent) -> Result<()> {
+        let path = self.events_dir.join(format!("{}.jsonl", event.container));
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open event log {:?}", path))?;
+
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+        debug!("Recorded {} event for {}", event.action, event.container);
+        Ok(())
+    }
+
+    /// Event history for a container, oldest first
+    pub fn history(&self, container: &str) -> Result<Vec<ContainerEvent>> {
+        let path = self.events_dir.join(format!("{}.jsonl", container));
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        Ok(content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect())
+    }
+}
@@ -0,0 +1,530 @@
+// GPU allocation across containers
+//
+// The unit of allocation is a whole GPU, an SR-IOV virtual function (its own
+// PCI device, with the physical GPU as parent) or an NVIDIA MIG instance
+// (named by its `MIG-<uuid>`, with its GPU as parent). A container holds
+// units through its `bolt.gpus` label, as surge placement already records
+// them (see `gaming/topology.rs`), and `bolt.gpu-mode` says whether it
+// holds them exclusively (the default, and what a container without the
+// label is taken to do) or shares them.
+//
+// Two holders of the same unit, or of a GPU and one of its partitions,
+// conflict unless both share. `bolt run --gpu` checks its request against
+// the running containers under a lock and records it as a reservation of
+// its own process until the container, and with it the label, exists; a
+// reservation ends with the process, so a failed or finished run leaves
+// nothing behind.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+use super::GPUVendor;
+use super::plugin::{self, Device};
+
+/// Label saying how a container holds its GPUs
+pub const MODE_LABEL: &str = "bolt.gpu-mode";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Exclusive,
+    Shared,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Exclusive => "exclusive",
+            Mode::Shared => "shared",
+        }
+    }
+
+    fn parse(label: &str) -> Self {
+        match label.trim() {
+            "shared" => Mode::Shared,
+            _ => Mode::Exclusive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum UnitKind {
+    Gpu,
+    VirtualFunction,
+    Mig { profile: String },
+}
+
+/// Something a container can be given
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unit {
+    /// PCI address, or the MIG instance's UUID
+    pub id: String,
+    pub kind: UnitKind,
+    /// The GPU or virtual function; a MIG instance's GPU
+    pub device: Device,
+    /// Physical GPU of a virtual function or MIG instance
+    pub parent: Option<String>,
+}
+
+impl Unit {
+    pub fn describe(&self) -> String {
+        match (&self.kind, &self.parent) {
+            (UnitKind::Mig { profile }, _) => format!("mig {}", profile),
+            (UnitKind::VirtualFunction, Some(parent)) => format!("vf of {}", parent),
+            _ => "gpu".to_string(),
+        }
+    }
+}
+
+/// The GPUs under `pci_root` and their partitions; `mig` is the output of
+/// `nvidia-smi -L`
+pub fn inventory_in(pci_root: &Path, devices: &[Device], mig: &str) -> Vec<Unit> {
+    let mut units: Vec<Unit> = devices
+        .iter()
+        .map(|device| {
+            let parent = std::fs::read_link(pci_root.join(&device.pci_bus_id).join("physfn"))
+                .ok()
+                .and_then(|link| Some(link.file_name()?.to_string_lossy().to_string()));
+            Unit {
+                id: device.pci_bus_id.clone(),
+                kind: if parent.is_some() {
+                    UnitKind::VirtualFunction
+                } else {
+                    UnitKind::Gpu
+                },
+                device: device.clone(),
+                parent,
+            }
+        })
+        .collect();
+
+    // nvidia-smi numbers GPUs by PCI address, as the plugin does
+    let mut gpu = None;
+    for line in mig.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("GPU ") {
+            let index = rest
+                .split(':')
+                .next()
+                .and_then(|i| i.trim().parse::<u32>().ok());
+            gpu = devices
+                .iter()
+                .find(|d| d.vendor == GPUVendor::NVIDIA && Some(d.index) == index);
+        } else if let (Some(rest), Some(device)) = (line.strip_prefix("MIG "), gpu) {
+            let profile = rest.split_whitespace().next().unwrap_or_default();
+            let uuid = rest
+                .split("UUID: ")
+                .nth(1)
+                .map(|u| u.trim_end_matches(')').trim());
+            if let Some(uuid) = uuid {
+                units.push(Unit {
+                    id: uuid.to_string(),
+                    kind: UnitKind::Mig {
+                        profile: profile.to_string(),
+                    },
+                    device: device.clone(),
+                    parent: Some(device.pci_bus_id.clone()),
+                });
+            }
+        }
+    }
+    units
+}
+
+/// Every allocatable unit on this host
+pub fn inventory() -> Vec<Unit> {
+    let devices = plugin::discover();
+    let mig = if devices.iter().any(|d| d.vendor == GPUVendor::NVIDIA) {
+        Command::new("nvidia-smi")
+            .arg("-L")
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    inventory_in(Path::new("/sys/bus/pci/devices"), &devices, &mig)
+}
+
+/// The units `selector` names: what `plugin::select` takes, plus MIG
+/// instances by UUID
+pub fn select(units: &[Unit], selector: &str) -> Result<Vec<Unit>> {
+    let (mig, rest): (Vec<&str>, Vec<&str>) = selector
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .partition(|p| p.starts_with("MIG-"));
+    let mut selected = Vec::new();
+    if !rest.is_empty() {
+        let devices: Vec<Device> = units
+            .iter()
+            .filter(|u| !matches!(u.kind, UnitKind::Mig { .. }))
+            .map(|u| u.device.clone())
+            .collect();
+        for device in plugin::select(&devices, &rest.join(","))? {
+            if let Some(unit) = units.iter().find(|u| u.id == device.pci_bus_id) {
+                selected.push(unit.clone());
+            }
+        }
+    }
+    for uuid in mig {
+        let unit = units
+            .iter()
+            .find(|u| u.id == uuid)
+            .ok_or_else(|| anyhow!("No MIG instance {} (see `bolt gaming gpu list`)", uuid))?;
+        if !selected.contains(unit) {
+            selected.push(unit.clone());
+        }
+    }
+    if selected.is_empty() {
+        return Err(anyhow!("No GPUs selected by '{}'", selector));
+    }
+    Ok(selected)
+}
+
+/// Units held by a container, or reserved by a `bolt run` still starting one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    pub holder: String,
+    pub units: Vec<String>,
+    pub mode: Mode,
+}
+
+/// A reservation file's contents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reservation {
+    pid: u32,
+    units: Vec<String>,
+    mode: Mode,
+}
+
+fn allocation_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("gpu").join("allocations")
+}
+
+/// Running containers with GPUs
+pub fn container_grants(runtime: &str) -> Vec<Grant> {
+    let Ok(output) = Command::new(runtime)
+        .args([
+            "ps",
+            "-q",
+            "--filter",
+            &format!("label={}", crate::gaming::topology::LABEL),
+        ])
+        .output()
+    else {
+        return Vec::new();
+    };
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let format = format!(
+        "{{{{.Name}}}}\t{{{{index .Config.Labels \"{}\"}}}}\t{{{{index .Config.Labels \"{}\"}}}}",
+        crate::gaming::topology::LABEL,
+        MODE_LABEL
+    );
+    match Command::new(runtime)
+        .args(["inspect", "--format", &format])
+        .args(&ids)
+        .output()
+    {
+        Ok(output) => parse_grants(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            debug!("Failed to inspect GPU containers: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Parse `<name>\t<bolt.gpus>\t<bolt.gpu-mode>` lines
+fn parse_grants(output: &str) -> Vec<Grant> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let holder = fields.next()?.trim().trim_start_matches('/').to_string();
+            let units: Vec<String> = fields
+                .next()?
+                .split(',')
+                .map(str::trim)
+                .filter(|u| !u.is_empty() && *u != "<no value>")
+                .map(str::to_string)
+                .collect();
+            let mode = Mode::parse(fields.next().unwrap_or_default());
+            (!units.is_empty()).then_some(Grant {
+                holder,
+                units,
+                mode,
+            })
+        })
+        .collect()
+}
+
+/// Reservations of live `bolt run` processes other than this one; those
+/// of exited processes are removed
+fn reservations(data_dir: &Path) -> Vec<Grant> {
+    let Ok(entries) = std::fs::read_dir(allocation_dir(data_dir)) else {
+        return Vec::new();
+    };
+    let mut grants = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(reservation) = std::fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice::<Reservation>(&b).ok())
+        else {
+            continue;
+        };
+        if reservation.pid == std::process::id() {
+            continue;
+        }
+        if !Path::new(&format!("/proc/{}", reservation.pid)).exists() {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        grants.push(Grant {
+            holder: format!("bolt run (pid {})", reservation.pid),
+            units: reservation.units,
+            mode: reservation.mode,
+        });
+    }
+    grants
+}
+
+/// Whether two units are the same or one partitions the other
+fn overlaps(units: &[Unit], a: &str, b: &str) -> bool {
+    let parent = |id: &str| {
+        units
+            .iter()
+            .find(|u| u.id == id)
+            .and_then(|u| u.parent.clone())
+    };
+    a == b || parent(a).as_deref() == Some(b) || parent(b).as_deref() == Some(a)
+}
+
+/// (requested unit, holder) pairs that keep `request` from being granted
+pub fn conflicts(
+    units: &[Unit],
+    grants: &[Grant],
+    request: &[String],
+    mode: Mode,
+) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for id in request {
+        for grant in grants {
+            let both_shared = mode == Mode::Shared && grant.mode == Mode::Shared;
+            if !both_shared && grant.units.iter().any(|held| overlaps(units, id, held)) {
+                found.push((
+                    id.clone(),
+                    format!("{} ({})", grant.holder, grant.mode.as_str()),
+                ));
+            }
+        }
+    }
+    found
+}
+
+/// Check `request` against every holder and reserve it for this process
+pub fn claim(
+    runtime: &str,
+    data_dir: &Path,
+    units: &[Unit],
+    request: &[Unit],
+    mode: Mode,
+) -> Result<()> {
+    let dir = allocation_dir(data_dir);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let lock_path = dir.join(".lock");
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    // SAFETY: flock(2) on a descriptor we own
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {}", lock_path.display()));
+    }
+
+    let mut grants = container_grants(runtime);
+    grants.extend(reservations(data_dir));
+    let ids: Vec<String> = request.iter().map(|u| u.id.clone()).collect();
+    let conflicts = conflicts(units, &grants, &ids, mode);
+    if !conflicts.is_empty() {
+        let held: Vec<String> = conflicts
+            .iter()
+            .map(|(unit, holder)| format!("{} is held by {}", unit, holder))
+            .collect();
+        return Err(anyhow!(
+            "GPU already allocated: {}{}",
+            held.join(", "),
+            if mode == Mode::Shared {
+                ""
+            } else {
+                "; pass --gpu-shared to share it with other shared containers"
+            }
+        ));
+    }
+
+    let reservation = Reservation {
+        pid: std::process::id(),
+        units: ids,
+        mode,
+    };
+    let path = dir.join(format!("{}.json", reservation.pid));
+    std::fs::write(&path, serde_json::to_vec(&reservation)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `run` arguments giving a container `request`, labeled with the units
+/// and mode it holds them in
+pub fn run_args(runtime: &str, request: &[Unit], mode: Mode) -> Vec<String> {
+    let ids: Vec<&str> = request.iter().map(|u| u.id.as_str()).collect();
+    let mut args = vec![
+        "--label".to_string(),
+        format!("{}={}", crate::gaming::topology::LABEL, ids.join(",")),
+        "--label".to_string(),
+        format!("{}={}", MODE_LABEL, mode.as_str()),
+    ];
+    let (mig, whole): (Vec<&Unit>, Vec<&Unit>) = request
+        .iter()
+        .partition(|u| matches!(u.kind, UnitKind::Mig { .. }));
+    let devices: Vec<Device> = whole.iter().map(|u| u.device.clone()).collect();
+    args.extend(plugin::device_args(runtime, &devices));
+    if !mig.is_empty() {
+        // The instances' GPUs' nodes, with CUDA limited to the instances
+        let mut parents: Vec<Device> = Vec::new();
+        for unit in &mig {
+            if !parents.contains(&unit.device) && !devices.contains(&unit.device) {
+                parents.push(unit.device.clone());
+            }
+        }
+        args.extend(
+            plugin::allocate(&parents)
+                .nodes
+                .into_iter()
+                .flat_map(|node| ["--device".to_string(), node]),
+        );
+        let uuids: Vec<&str> = mig.iter().map(|u| u.id.as_str()).collect();
+        for var in ["NVIDIA_VISIBLE_DEVICES", "CUDA_VISIBLE_DEVICES"] {
+            args.extend(["-e".to_string(), format!("{}={}", var, uuids.join(","))]);
+        }
+    }
+    args
+}
+
+/// A unit with whoever holds it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitReport {
+    pub unit: Unit,
+    pub holders: Vec<Grant>,
+}
+
+/// Every unit on this host with its holders
+pub fn report(runtime: &str, data_dir: &Path) -> Vec<UnitReport> {
+    let mut grants = container_grants(runtime);
+    grants.extend(reservations(data_dir));
+    inventory()
+        .into_iter()
+        .map(|unit| UnitReport {
+            holders: grants
+                .iter()
+                .filter(|g| g.units.contains(&unit.id))
+                .cloned()
+                .collect(),
+            unit,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(index: u32, pci: &str) -> Device {
+        Device {
+            vendor: GPUVendor::NVIDIA,
+            index,
+            pci_bus_id: pci.to_string(),
+            driver: "nvidia".to_string(),
+            nodes: vec![format!("/dev/nvidia{}", index)],
+        }
+    }
+
+    #[test]
+    fn finds_partitions_and_rejects_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let pf = "0000:01:00.0";
+        let vf = "0000:01:00.4";
+        std::fs::create_dir_all(dir.path().join(pf)).unwrap();
+        std::fs::create_dir_all(dir.path().join(vf)).unwrap();
+        std::os::unix::fs::symlink(format!("../{}", pf), dir.path().join(vf).join("physfn"))
+            .unwrap();
+        let devices = vec![device(0, pf), device(1, vf), device(2, "0000:02:00.0")];
+        let mig = "GPU 0: NVIDIA A100-SXM4-40GB (UUID: GPU-5d5b)\n  MIG 1g.5gb      Device  0: (UUID: MIG-c6d4)\nGPU 2: NVIDIA A100 (UUID: GPU-77aa)\n";
+        let units = inventory_in(dir.path(), &devices, mig);
+        assert_eq!(units.len(), 4);
+        assert_eq!(units[1].kind, UnitKind::VirtualFunction);
+        assert_eq!(units[1].parent.as_deref(), Some(pf));
+        assert_eq!(units[3].id, "MIG-c6d4");
+        assert_eq!(units[3].describe(), "mig 1g.5gb");
+        assert_eq!(units[3].parent.as_deref(), Some(pf));
+
+        let picked = select(&units, "2,MIG-c6d4").unwrap();
+        assert_eq!(picked[0].id, "0000:02:00.0");
+        assert_eq!(picked[1].id, "MIG-c6d4");
+        assert!(select(&units, "MIG-nope").is_err());
+
+        let grants = parse_grants(&format!(
+            "/web\t{}\t<no value>\n/render\tMIG-c6d4\tshared\n/idle\t<no value>\t\n",
+            vf
+        ));
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[0].mode, Mode::Exclusive);
+
+        // The physical GPU overlaps its exclusively held VF and its MIG instance
+        let request = vec![pf.to_string()];
+        assert_eq!(conflicts(&units, &grants, &request, Mode::Shared).len(), 1);
+        assert_eq!(
+            conflicts(&units, &grants, &request, Mode::Exclusive).len(),
+            2
+        );
+        // Shared holders of the same instance coexist
+        let request = vec!["MIG-c6d4".to_string()];
+        assert!(conflicts(&units, &grants, &request, Mode::Shared).is_empty());
+        assert!(
+            conflicts(
+                &units,
+                &grants,
+                &["0000:02:00.0".to_string()],
+                Mode::Exclusive
+            )
+            .is_empty()
+        );
+
+        let args = run_args("docker", &picked, Mode::Shared);
+        assert_eq!(
+            args[..4],
+            [
+                "--label",
+                "bolt.gpus=0000:02:00.0,MIG-c6d4",
+                "--label",
+                "bolt.gpu-mode=shared"
+            ]
+        );
+        assert!(
+            args.windows(2)
+                .any(|w| w == ["-e", "CUDA_VISIBLE_DEVICES=MIG-c6d4"])
+        );
+    }
+}
@@ -0,0 +1,484 @@
+// Base-OS detection and per-distro GPU library placement
+//
+// Host driver libraries have to land where the image's dynamic loader looks
+// for them: Debian/Ubuntu use a multiarch directory, Fedora uses lib64, Arch
+// and Alpine use plain /usr/lib, and NixOS images have no FHS library path at
+// all and pick drivers up from /run/opengl-driver. Driver libraries are built
+// against glibc, so injecting them into a musl image is refused up front
+// instead of failing with a loader error inside the game.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use super::icd::IcdManager;
+use crate::config::VulkanConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistroFamily {
+    Debian,
+    Fedora,
+    Arch,
+    Alpine,
+    NixOS,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Libc {
+    Glibc,
+    Musl,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaseOs {
+    pub family: DistroFamily,
+    /// `ID` from os-release, e.g. "ubuntu"
+    pub id: String,
+    pub pretty_name: Option<String>,
+    pub libc: Libc,
+}
+
+/// Where a distro keeps GPU userspace files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryLayout {
+    pub lib_dir: PathBuf,
+    pub dri_dir: PathBuf,
+    /// Root of the `vulkan/` and `glvnd/` manifest directories
+    pub share_dir: PathBuf,
+    /// Environment needed for the loader to find the libraries
+    pub env: Vec<(String, String)>,
+}
+
+impl LibraryLayout {
+    pub fn vulkan_icd_dir(&self) -> PathBuf {
+        self.share_dir.join("vulkan/icd.d")
+    }
+
+    pub fn explicit_layer_dir(&self) -> PathBuf {
+        self.share_dir.join("vulkan/explicit_layer.d")
+    }
+
+    pub fn implicit_layer_dir(&self) -> PathBuf {
+        self.share_dir.join("vulkan/implicit_layer.d")
+    }
+
+    pub fn egl_vendor_dir(&self) -> PathBuf {
+        self.share_dir.join("glvnd/egl_vendor.d")
+    }
+}
+
+/// Files of the loader consulted to tell libcs apart
+const MUSL_LOADERS: &[&str] = &["/lib/ld-musl-x86_64.so.1", "/lib/ld-musl-aarch64.so.1"];
+const GLIBC_LOADERS: &[&str] = &[
+    "/lib64/ld-linux-x86-64.so.2",
+    "/lib/ld-linux-aarch64.so.1",
+    "/lib/x86_64-linux-gnu/libc.so.6",
+    "/usr/lib/libc.so.6",
+];
+
+const NIXOS_DRIVER_DIR: &str = "/run/opengl-driver";
+
+impl BaseOs {
+    /// Classify from os-release contents and which loaders exist
+    pub fn from_os_release(
+        os_release: &str,
+        has_musl_loader: bool,
+        has_glibc_loader: bool,
+        has_nix_store: bool,
+    ) -> Self {
+        let fields: HashMap<&str, String> = os_release
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.trim(), v.trim().trim_matches('"').to_string()))
+            .collect();
+        let id = fields.get("ID").cloned().unwrap_or_default();
+        let id_like = fields.get("ID_LIKE").cloned().unwrap_or_default();
+        let matches = |names: &[&str]| {
+            names.contains(&id.as_str()) || id_like.split_whitespace().any(|l| names.contains(&l))
+        };
+
+        let family = if id == "nixos" || (id.is_empty() && has_nix_store) {
+            DistroFamily::NixOS
+        } else if matches(&["alpine"]) {
+            DistroFamily::Alpine
+        } else if matches(&["arch", "archlinux"]) {
+            DistroFamily::Arch
+        } else if matches(&["fedora", "rhel", "centos"]) {
+            DistroFamily::Fedora
+        } else if matches(&["debian", "ubuntu"]) {
+            DistroFamily::Debian
+        } else {
+            DistroFamily::Unknown
+        };
+
+        let libc = if has_musl_loader {
+            Libc::Musl
+        } else if has_glibc_loader || family == DistroFamily::NixOS {
+            Libc::Glibc
+        } else if family == DistroFamily::Alpine {
+            Libc::Musl
+        } else {
+            Libc::Unknown
+        };
+
+        Self {
+            family,
+            id,
+            pretty_name: fields.get("PRETTY_NAME").cloned(),
+            libc,
+        }
+    }
+
+    pub fn detect_host() -> Self {
+        let os_release = std::fs::read_to_string("/etc/os-release")
+            .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+            .unwrap_or_default();
+        Self::from_os_release(
+            &os_release,
+            MUSL_LOADERS.iter().any(|p| Path::new(p).exists()),
+            GLIBC_LOADERS.iter().any(|p| Path::new(p).exists()),
+            Path::new("/nix/store").exists(),
+        )
+    }
+
+    pub fn display_name(&self) -> String {
+        let name = self
+            .pretty_name
+            .clone()
+            .unwrap_or_else(|| format!("{:?}", self.family));
+        match self.libc {
+            Libc::Musl => format!("{} (musl)", name),
+            _ => name,
+        }
+    }
+
+    pub fn layout(&self) -> LibraryLayout {
+        let lib_dir = match self.family {
+            DistroFamily::Fedora => PathBuf::from("/usr/lib64"),
+            DistroFamily::Arch | DistroFamily::Alpine => PathBuf::from("/usr/lib"),
+            DistroFamily::NixOS => Path::new(NIXOS_DRIVER_DIR).join("lib"),
+            // Debian-style multiarch is the most common layout for other images
+            DistroFamily::Debian | DistroFamily::Unknown => {
+                PathBuf::from(format!("/usr/lib/{}-linux-gnu", std::env::consts::ARCH))
+            }
+        };
+        let share_dir = if self.family == DistroFamily::NixOS {
+            Path::new(NIXOS_DRIVER_DIR).join("share")
+        } else {
+            PathBuf::from("/usr/share")
+        };
+        let env = if self.family == DistroFamily::NixOS {
+            vec![
+                ("LD_LIBRARY_PATH".to_string(), lib_dir.display().to_string()),
+                (
+                    "__EGL_VENDOR_LIBRARY_DIRS".to_string(),
+                    share_dir.join("glvnd/egl_vendor.d").display().to_string(),
+                ),
+            ]
+        } else {
+            Vec::new()
+        };
+
+        LibraryLayout {
+            dri_dir: lib_dir.join("dri"),
+            lib_dir,
+            share_dir,
+            env,
+        }
+    }
+}
+
+/// Detect the base OS of an image without running it (distroless and Nix
+/// images have no shell), by copying files out of a created container
+pub async fn detect_image(runtime: &str, image: &str) -> Result<BaseOs> {
+    let output = AsyncCommand::new(runtime)
+        .args(["create", image])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to inspect image {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let container = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let result = async {
+        let os_release = match read_file(runtime, &container, "/etc/os-release").await {
+            Some(content) => content,
+            None => read_file(runtime, &container, "/usr/lib/os-release")
+                .await
+                .unwrap_or_default(),
+        };
+        let mut has_musl = false;
+        for loader in MUSL_LOADERS {
+            has_musl |= path_exists(runtime, &container, loader).await;
+        }
+        let mut has_glibc = false;
+        for loader in GLIBC_LOADERS {
+            has_glibc |= path_exists(runtime, &container, loader).await;
+        }
+        let has_nix_store = path_exists(runtime, &container, "/nix/store").await;
+        BaseOs::from_os_release(&os_release, has_musl, has_glibc, has_nix_store)
+    }
+    .await;
+
+    let _ = AsyncCommand::new(runtime)
+        .args(["rm", "-f", &container])
+        .output()
+        .await;
+
+    debug!("Image {} base OS: {:?}", image, result);
+    Ok(result)
+}
+
+async fn read_file(runtime: &str, container: &str, path: &str) -> Option<String> {
+    // `cp` to stdout produces a tar stream with a single entry
+    let output = AsyncCommand::new(runtime)
+        .args(["cp", "-L", &format!("{}:{}", container, path), "-"])
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())?;
+    let mut archive = tar::Archive::new(output.stdout.as_slice());
+    let mut entry = archive.entries().ok()?.next()?.ok()?;
+    let mut content = String::new();
+    std::io::Read::read_to_string(&mut entry, &mut content).ok()?;
+    Some(content)
+}
+
+async fn path_exists(runtime: &str, container: &str, path: &str) -> bool {
+    use tokio::io::AsyncReadExt;
+
+    // Only the first tar header is needed; don't stream whole directories
+    let Ok(mut child) = AsyncCommand::new(runtime)
+        .args(["cp", &format!("{}:{}", container, path), "-"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let mut header = [0u8; 512];
+    let exists = match child.stdout.as_mut() {
+        Some(stdout) => stdout.read_exact(&mut header).await.is_ok(),
+        None => false,
+    };
+    let _ = child.kill().await;
+    exists
+}
+
+/// Which host driver stack to inject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuDriverStack {
+    Nvidia,
+    /// AMD/Intel Vulkan drivers
+    Mesa,
+}
+
+impl GpuDriverStack {
+    /// File name prefixes of the libraries that make up the stack
+    fn library_prefixes(&self) -> &'static [&'static str] {
+        match self {
+            GpuDriverStack::Nvidia => &[
+                "libnvidia-",
+                "libcuda.so",
+                "libnvcuvid.so",
+                "libGLX_nvidia.so",
+                "libEGL_nvidia.so",
+                "libGLESv2_nvidia.so",
+                "libvulkan.so.1",
+            ],
+            GpuDriverStack::Mesa => &[
+                "libvulkan_radeon.so",
+                "libvulkan_intel.so",
+                "libvulkan.so.1",
+            ],
+        }
+    }
+
+    pub(crate) fn icd_prefixes(&self) -> &'static [&'static str] {
+        match self {
+            GpuDriverStack::Nvidia => &["nvidia_icd"],
+            GpuDriverStack::Mesa => &["radeon_icd", "intel_icd"],
+        }
+    }
+
+    fn devices(&self) -> Vec<String> {
+        let candidates: Vec<String> = match self {
+            GpuDriverStack::Nvidia => {
+                let mut devices: Vec<String> = [
+                    "/dev/nvidiactl",
+                    "/dev/nvidia-uvm",
+                    "/dev/nvidia-uvm-tools",
+                    "/dev/nvidia-modeset",
+                ]
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+                devices.extend((0..16).map(|i| format!("/dev/nvidia{}", i)));
+                devices
+            }
+            GpuDriverStack::Mesa => vec!["/dev/dri".to_string()],
+        };
+        candidates
+            .into_iter()
+            .filter(|d| Path::new(d).exists())
+            .collect()
+    }
+}
+
+/// Bind mounts, devices and environment that expose the host GPU stack
+#[derive(Debug, Clone, Default)]
+pub struct GpuInjection {
+    /// (host path, container path)
+    pub mounts: Vec<(PathBuf, PathBuf)>,
+    pub devices: Vec<String>,
+    pub env: Vec<String>,
+}
+
+impl GpuInjection {
+    /// Arguments for `podman/docker run`
+    pub fn run_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (host, container) in &self.mounts {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}:ro", host.display(), container.display()));
+        }
+        for device in &self.devices {
+            args.push("--device".to_string());
+            args.push(device.clone());
+        }
+        for env in &self.env {
+            args.push("-e".to_string());
+            args.push(env.clone());
+        }
+        args
+    }
+}
+
+/// Map the host's driver libraries, Vulkan ICDs and requested layers into the
+/// image's layout. `generated_dir` receives the manifests written for the image.
+pub fn plan_injection(
+    host: &BaseOs,
+    image: &BaseOs,
+    stack: GpuDriverStack,
+    vulkan: &VulkanConfig,
+    generated_dir: &Path,
+) -> Result<GpuInjection> {
+    check_libc_compatible(host, image)?;
+
+    let host_layout = host.layout();
+    let image_layout = image.layout();
+    let mut injection = GpuInjection {
+        devices: stack.devices(),
+        ..Default::default()
+    };
+
+    let entries = std::fs::read_dir(&host_layout.lib_dir).map_err(|e| {
+        anyhow!(
+            "Cannot read host GPU libraries in {}: {}",
+            host_layout.lib_dir.display(),
+            e
+        )
+    })?;
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| {
+            stack
+                .library_prefixes()
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .collect();
+    names.sort();
+    for name in names {
+        injection.mounts.push((
+            host_layout.lib_dir.join(&name),
+            image_layout.lib_dir.join(&name),
+        ));
+    }
+    if injection.mounts.is_empty() {
+        return Err(anyhow!(
+            "No {:?} driver libraries found in {}",
+            stack,
+            host_layout.lib_dir.display()
+        ));
+    }
+
+    let icds = IcdManager::new(generated_dir, &host_layout, &image_layout);
+    icds.add_driver_manifests(stack, &mut injection)?;
+    icds.add_layers(vulkan, &mut injection)?;
+
+    injection.env.extend(
+        image_layout
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value)),
+    );
+    if stack == GpuDriverStack::Nvidia {
+        injection
+            .env
+            .push("NVIDIA_DRIVER_CAPABILITIES=all".to_string());
+    }
+
+    info!(
+        "📚 Injecting {} {:?} files for {} into {}",
+        injection.mounts.len(),
+        stack,
+        image.display_name(),
+        image_layout.lib_dir.display()
+    );
+    Ok(injection)
+}
+
+/// Host driver libraries only load in an image with the same libc
+pub fn check_libc_compatible(host: &BaseOs, image: &BaseOs) -> Result<()> {
+    match (host.libc, image.libc) {
+        (Libc::Glibc, Libc::Musl) => Err(anyhow!(
+            "{} uses musl libc, but the host GPU drivers are built against glibc and \
+             would crash the container when loaded. Use a glibc-based image (e.g. \
+             Debian, Fedora or Arch) or install the distro's own Mesa/Vulkan packages \
+             in the image and disable GPU library injection",
+            image.display_name()
+        )),
+        (Libc::Musl, Libc::Glibc) => Err(anyhow!(
+            "The host uses musl libc; its GPU drivers cannot be loaded by the \
+             glibc-based image {}",
+            image.display_name()
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_distro_families() {
+        let alpine = BaseOs::from_os_release("ID=alpine\nVERSION_ID=3.19\n", true, false, false);
+        assert_eq!(alpine.family, DistroFamily::Alpine);
+        assert_eq!(alpine.libc, Libc::Musl);
+
+        let rocky = BaseOs::from_os_release(
+            "ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n",
+            false,
+            true,
+            false,
+        );
+        assert_eq!(rocky.family, DistroFamily::Fedora);
+        assert_eq!(rocky.layout().lib_dir, PathBuf::from("/usr/lib64"));
+
+        let nix = BaseOs::from_os_release("", false, false, true);
+        assert_eq!(nix.family, DistroFamily::NixOS);
+        assert!(check_libc_compatible(&rocky, &alpine).is_err());
+        assert!(check_libc_compatible(&rocky, &nix).is_ok());
+    }
+}
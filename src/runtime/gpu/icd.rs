@@ -0,0 +1,451 @@
+// Vulkan ICD, EGL vendor and Vulkan layer manifests for GPU containers
+//
+// Manifests shipped by the image rarely match the driver mounted from the
+// host: they point at libraries that aren't there or at a different driver
+// version. Instead of trusting them, Bolt writes manifests for the host
+// driver into a per-image directory and bind-mounts them over the image's
+// icd.d / egl_vendor.d / layer directories.
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::distro::{GpuDriverStack, GpuInjection, LibraryLayout};
+use crate::config::VulkanConfig;
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+pub struct IcdManager<'a> {
+    output_dir: PathBuf,
+    host: &'a LibraryLayout,
+    image: &'a LibraryLayout,
+}
+
+impl<'a> IcdManager<'a> {
+    pub fn new(output_dir: &Path, host: &'a LibraryLayout, image: &'a LibraryLayout) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            host,
+            image,
+        }
+    }
+
+    /// Vulkan ICD and EGL vendor manifests for the host driver
+    pub fn add_driver_manifests(
+        &self,
+        stack: GpuDriverStack,
+        injection: &mut GpuInjection,
+    ) -> Result<()> {
+        match stack {
+            GpuDriverStack::Nvidia => self.add_nvidia_manifests(injection),
+            GpuDriverStack::Mesa => self.add_mesa_manifests(injection),
+        }
+    }
+
+    fn add_nvidia_manifests(&self, injection: &mut GpuInjection) -> Result<()> {
+        // Userspace libraries carry the driver version in their file name and
+        // only work with the matching kernel module
+        if let Some(version) = nvidia_kernel_version() {
+            let glcore = format!("libnvidia-glcore.so.{}", version);
+            if !self.host.lib_dir.join(&glcore).exists() {
+                return Err(anyhow!(
+                    "NVIDIA kernel module is {} but {} has no {}; reboot or reinstall the \
+                     driver so userspace and kernel versions match",
+                    version,
+                    self.host.lib_dir.display(),
+                    glcore
+                ));
+            }
+            debug!("NVIDIA driver {}", version);
+        }
+
+        let api_version = self
+            .host_manifests(&self.host.vulkan_icd_dir(), &["nvidia_icd"])
+            .iter()
+            .find_map(|(_, manifest)| manifest.pointer("/ICD/api_version").cloned())
+            .unwrap_or_else(|| json!("1.3.0"));
+
+        let icd = json!({
+            "file_format_version": "1.0.1",
+            "ICD": {
+                "library_path": self.image.lib_dir.join("libGLX_nvidia.so.0"),
+                "api_version": api_version,
+            }
+        });
+        self.write(
+            "nvidia_icd.json",
+            &icd,
+            self.image.vulkan_icd_dir(),
+            injection,
+        )?;
+
+        let egl = json!({
+            "file_format_version": "1.0.0",
+            "ICD": { "library_path": "libEGL_nvidia.so.0" }
+        });
+        self.write(
+            "10_nvidia.json",
+            &egl,
+            self.image.egl_vendor_dir(),
+            injection,
+        )?;
+
+        injection
+            .env
+            .push("__GLX_VENDOR_LIBRARY_NAME=nvidia".to_string());
+        Ok(())
+    }
+
+    fn add_mesa_manifests(&self, injection: &mut GpuInjection) -> Result<()> {
+        let manifests = self.host_manifests(
+            &self.host.vulkan_icd_dir(),
+            GpuDriverStack::Mesa.icd_prefixes(),
+        );
+        if manifests.is_empty() {
+            warn!(
+                "No Mesa Vulkan ICDs found in {}",
+                self.host.vulkan_icd_dir().display()
+            );
+        }
+        for (name, mut manifest) in manifests {
+            self.relocate_library(&mut manifest, "/ICD/library_path", injection)?;
+            self.write(&name, &manifest, self.image.vulkan_icd_dir(), injection)?;
+        }
+        Ok(())
+    }
+
+    /// Validation and implicit layers requested for the service
+    pub fn add_layers(&self, vulkan: &VulkanConfig, injection: &mut GpuInjection) -> Result<()> {
+        if vulkan.validation {
+            let (name, mut manifest) = self
+                .host_manifests(
+                    &self.host.explicit_layer_dir(),
+                    &["VkLayer_khronos_validation"],
+                )
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Vulkan validation requested but {} is not installed on the host \
+                         (install the Vulkan validation layers package)",
+                        VALIDATION_LAYER
+                    )
+                })?;
+            self.relocate_library(&mut manifest, "/layer/library_path", injection)?;
+            self.write(&name, &manifest, self.image.explicit_layer_dir(), injection)?;
+            injection
+                .env
+                .push(format!("VK_INSTANCE_LAYERS={}", VALIDATION_LAYER));
+        }
+
+        for layer in &vulkan.implicit_layers {
+            let wanted = layer.to_lowercase();
+            let manifests: Vec<_> = self
+                .host_manifests(&self.host.implicit_layer_dir(), &[])
+                .into_iter()
+                .filter(|(name, _)| name.to_lowercase().contains(&wanted))
+                .collect();
+            if manifests.is_empty() {
+                return Err(anyhow!(
+                    "Implicit Vulkan layer '{}' not found in {}",
+                    layer,
+                    self.host.implicit_layer_dir().display()
+                ));
+            }
+
+            for (name, mut manifest) in manifests {
+                self.relocate_library(&mut manifest, "/layer/library_path", injection)?;
+                // Implicit layers only load when their enable variable is set
+                if let Some(enable) = manifest
+                    .pointer("/layer/enable_environment")
+                    .and_then(|e| e.as_object())
+                {
+                    for (key, value) in enable {
+                        injection
+                            .env
+                            .push(format!("{}={}", key, value.as_str().unwrap_or("1")));
+                    }
+                }
+                self.write(&name, &manifest, self.image.implicit_layer_dir(), injection)?;
+            }
+            info!("🧩 Vulkan layer enabled: {}", layer);
+        }
+
+        Ok(())
+    }
+
+    /// `*.json` manifests in a host directory whose name starts with a prefix
+    fn host_manifests(&self, dir: &Path, prefixes: &[&str]) -> Vec<(String, Value)> {
+        let mut dirs = vec![dir.to_path_buf()];
+        // Drivers installed by hand (e.g. the NVIDIA .run installer) use /etc
+        if let Ok(relative) = dir.strip_prefix(&self.host.share_dir) {
+            dirs.push(Path::new("/etc").join(relative));
+        }
+
+        let mut manifests = Vec::new();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.ends_with(".json")
+                    || !(prefixes.is_empty() || prefixes.iter().any(|p| name.starts_with(p)))
+                {
+                    continue;
+                }
+                match std::fs::read(entry.path())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|data| Ok(serde_json::from_slice(&data)?))
+                {
+                    Ok(manifest) => manifests.push((name, manifest)),
+                    Err(e) => warn!("Skipping invalid manifest {}: {}", name, e),
+                }
+            }
+        }
+        manifests.sort_by(|a, b| a.0.cmp(&b.0));
+        manifests
+    }
+
+    /// Point a manifest's library at the image's library directory, mounting
+    /// the host library there if the driver mounts don't already cover it
+    fn relocate_library(
+        &self,
+        manifest: &mut Value,
+        pointer: &str,
+        injection: &mut GpuInjection,
+    ) -> Result<()> {
+        let Some(library) = manifest.pointer(pointer).and_then(|p| p.as_str()) else {
+            return Ok(());
+        };
+        let library = PathBuf::from(library);
+        let host_path = if library.is_absolute() {
+            library.clone()
+        } else {
+            // Bare names resolve through the loader path, or a subdirectory
+            // such as /usr/lib/mangohud
+            std::iter::once(self.host.lib_dir.join(&library))
+                .chain(
+                    std::fs::read_dir(&self.host.lib_dir)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .map(|e| e.path().join(&library)),
+                )
+                .find(|p| p.is_file())
+                .ok_or_else(|| anyhow!("Layer library {} not found on host", library.display()))?
+        };
+        let file_name = host_path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid library path {}", host_path.display()))?;
+        let container_path = self.image.lib_dir.join(file_name);
+
+        if !injection.mounts.iter().any(|(_, c)| *c == container_path) {
+            injection.mounts.push((host_path, container_path.clone()));
+        }
+        if let Some(slot) = manifest.pointer_mut(pointer) {
+            *slot = Value::String(container_path.display().to_string());
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        name: &str,
+        manifest: &Value,
+        container_dir: PathBuf,
+        injection: &mut GpuInjection,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.output_dir)?;
+        let path = self.output_dir.join(name);
+        std::fs::write(&path, serde_json::to_vec_pretty(manifest)?)?;
+        injection.mounts.push((path, container_dir.join(name)));
+        Ok(())
+    }
+}
+
+/// Version of the loaded NVIDIA kernel module
+pub fn nvidia_kernel_version() -> Option<String> {
+    std::fs::read_to_string("/sys/module/nvidia/version")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Run `vulkaninfo --summary` in a running container and return the GPUs it
+/// reports
+pub async fn verify_vulkan(runtime: &str, container: &str) -> Result<Vec<String>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["exec", container, "vulkaninfo", "--summary"])
+        .output()
+        .await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("executable file not found") || stderr.contains("not found in $PATH") {
+            return Err(anyhow!(
+                "vulkaninfo is not installed in {} (install vulkan-tools in the image)",
+                container
+            ));
+        }
+        return Err(anyhow!(
+            "vulkaninfo failed in {}: {}",
+            container,
+            stderr
+                .lines()
+                .chain(stdout.lines())
+                .last()
+                .unwrap_or("")
+                .trim()
+        ));
+    }
+
+    let devices = vulkan_devices(&stdout);
+    if devices.is_empty() {
+        return Err(anyhow!(
+            "vulkaninfo in {} found no Vulkan devices",
+            container
+        ));
+    }
+    Ok(devices)
+}
+
+/// `deviceName` entries of `vulkaninfo --summary` output
+fn vulkan_devices(summary: &str) -> Vec<String> {
+    summary
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("deviceName"))
+        .map(|l| l.trim_start_matches([' ', '=']).trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(root: &Path) -> LibraryLayout {
+        LibraryLayout {
+            lib_dir: root.join("lib"),
+            dri_dir: root.join("lib/dri"),
+            share_dir: root.join("share"),
+            env: Vec::new(),
+        }
+    }
+
+    fn put(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn read_manifest(path: &Path) -> Value {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rewrites_mesa_icds_for_the_image_layout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let host = layout(&tmp.path().join("host"));
+        let image = LibraryLayout {
+            lib_dir: PathBuf::from("/usr/lib64"),
+            dri_dir: PathBuf::from("/usr/lib64/dri"),
+            share_dir: PathBuf::from("/usr/share"),
+            env: Vec::new(),
+        };
+        let driver = host.lib_dir.join("libvulkan_radeon.so");
+        put(&driver, "");
+        put(
+            &host.vulkan_icd_dir().join("radeon_icd.x86_64.json"),
+            &json!({
+                "file_format_version": "1.0.0",
+                "ICD": { "library_path": driver, "api_version": "1.3.274" }
+            })
+            .to_string(),
+        );
+        put(&host.vulkan_icd_dir().join("lvp_icd.x86_64.json"), "{}");
+
+        let output = tmp.path().join("manifests");
+        let mut injection = GpuInjection::default();
+        IcdManager::new(&output, &host, &image)
+            .add_driver_manifests(GpuDriverStack::Mesa, &mut injection)
+            .unwrap();
+
+        let manifest = read_manifest(&output.join("radeon_icd.x86_64.json"));
+        assert_eq!(
+            manifest["ICD"]["library_path"],
+            "/usr/lib64/libvulkan_radeon.so"
+        );
+        assert_eq!(manifest["ICD"]["api_version"], "1.3.274");
+        assert!(!output.join("lvp_icd.x86_64.json").exists());
+        assert!(
+            injection
+                .mounts
+                .contains(&(driver, PathBuf::from("/usr/lib64/libvulkan_radeon.so")))
+        );
+        assert!(injection.mounts.contains(&(
+            output.join("radeon_icd.x86_64.json"),
+            PathBuf::from("/usr/share/vulkan/icd.d/radeon_icd.x86_64.json")
+        )));
+    }
+
+    #[test]
+    fn enables_implicit_layers_from_subdirectories() {
+        let tmp = tempfile::tempdir().unwrap();
+        let host = layout(&tmp.path().join("host"));
+        let image = layout(Path::new("/usr"));
+        put(&host.lib_dir.join("mangohud/libMangoHud.so"), "");
+        put(
+            &host.implicit_layer_dir().join("MangoHud.x86_64.json"),
+            &json!({
+                "file_format_version": "1.0.0",
+                "layer": {
+                    "name": "VK_LAYER_MANGOHUD_overlay_x86_64",
+                    "library_path": "libMangoHud.so",
+                    "enable_environment": { "MANGOHUD": "1" }
+                }
+            })
+            .to_string(),
+        );
+
+        let output = tmp.path().join("manifests");
+        let manager = IcdManager::new(&output, &host, &image);
+        let mut injection = GpuInjection::default();
+        let vulkan = VulkanConfig {
+            validation: false,
+            implicit_layers: vec!["mangohud".to_string()],
+        };
+        manager.add_layers(&vulkan, &mut injection).unwrap();
+
+        let manifest = read_manifest(&output.join("MangoHud.x86_64.json"));
+        assert_eq!(manifest["layer"]["library_path"], "/usr/lib/libMangoHud.so");
+        assert_eq!(injection.env, vec!["MANGOHUD=1".to_string()]);
+        assert!(injection.mounts.contains(&(
+            host.lib_dir.join("mangohud/libMangoHud.so"),
+            PathBuf::from("/usr/lib/libMangoHud.so")
+        )));
+
+        let missing = VulkanConfig {
+            validation: false,
+            implicit_layers: vec!["vkbasalt".to_string()],
+        };
+        let err = manager
+            .add_layers(&missing, &mut GpuInjection::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("'vkbasalt' not found"));
+    }
+
+    #[test]
+    fn parses_vulkaninfo_device_names() {
+        let summary = "Devices:\n========\nGPU0:\n\tapiVersion         = 1.3.277\n\
+                       \tdeviceName         = AMD Radeon RX 7900 XTX (RADV NAVI31)\n\
+                       GPU1:\n\tdeviceName         = llvmpipe (LLVM 17.0.6, 256 bits)\n";
+        assert_eq!(
+            vulkan_devices(summary),
+            vec![
+                "AMD Radeon RX 7900 XTX (RADV NAVI31)".to_string(),
+                "llvmpipe (LLVM 17.0.6, 256 bits)".to_string(),
+            ]
+        );
+        assert!(vulkan_devices("ERROR: [Loader Message] no ICDs found").is_empty());
+    }
+}
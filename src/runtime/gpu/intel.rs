@@ -0,0 +1,414 @@
+// Intel GPUs: QuickSync media and Level Zero compute
+//
+// A service with `gpu.intel` gets its GPU's own `/dev/dri` card and render
+// nodes rather than the whole directory, plus the host's media stack mapped
+// into the image's layout the way `distro.rs` maps Vulkan drivers: libva and
+// the VA-API driver (iHD, or i965 on older GPUs) with oneVPL/Media SDK for
+// QuickSync, and optionally the Level Zero loader and compute runtime.
+//
+// Metrics come from sysfs as intel_gpu_top reads them without perf access:
+// the actual GT frequency, busy time as the share of the interval the GT
+// spent outside RC6, and power and temperature from the hwmon of discrete
+// cards. i915 and xe lay these files out differently.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::distro::{GpuInjection, LibraryLayout};
+use super::plugin::{Device, DevicePlugin, Intel};
+use crate::config::IntelConfig;
+
+/// libva and the gmm library the iHD driver links against
+const VAAPI_LIBRARIES: &[&str] = &["libva.so", "libva-drm.so", "libigdgmm.so"];
+/// VA-API drivers in the `dri` directory, preferred first
+const VAAPI_DRIVERS: &[(&str, &str)] =
+    &[("iHD", "iHD_drv_video.so"), ("i965", "i965_drv_video.so")];
+/// oneVPL dispatcher and runtimes, and the older Media SDK
+const QUICKSYNC_LIBRARIES: &[&str] = &[
+    "libvpl.so",
+    "libmfx-gen.so",
+    "libmfxhw64.so",
+    "libmfx.so",
+    "libigfxcmrt.so",
+];
+const LEVEL_ZERO_LIBRARIES: &[&str] = &[
+    "libze_loader.so",
+    "libze_intel_gpu.so",
+    "libigc.so",
+    "libigdfcl.so",
+    "libigdgmm.so",
+];
+
+/// The Intel GPU `config` asks for among `devices`
+pub fn select(config: &IntelConfig, devices: &[Device]) -> Result<Device> {
+    let index = config.device.unwrap_or(0);
+    devices
+        .iter()
+        .find(|d| d.index == index)
+        .cloned()
+        .ok_or_else(|| match devices.len() {
+            0 => anyhow!("No Intel GPU bound to i915 or xe found"),
+            n => anyhow!("No Intel GPU {} (this host has {}: 0-{})", index, n, n - 1),
+        })
+}
+
+/// Device nodes, libraries and environment for `device`, with the host's
+/// files under `host` mapped into `image`
+pub fn plan(
+    config: &IntelConfig,
+    device: &Device,
+    host: &LibraryLayout,
+    image: &LibraryLayout,
+) -> Result<GpuInjection> {
+    let mut injection = GpuInjection {
+        devices: device.nodes.clone(),
+        ..Default::default()
+    };
+    if injection.devices.is_empty() {
+        return Err(anyhow!(
+            "Intel GPU {} has no /dev/dri nodes",
+            device.pci_bus_id
+        ));
+    }
+
+    let names = library_names(&host.lib_dir);
+    let libraries = |prefixes: &[&str], injection: &mut GpuInjection| {
+        let mut found = false;
+        for name in names
+            .iter()
+            .filter(|n| prefixes.iter().any(|p| n.starts_with(p)))
+        {
+            let mount = (host.lib_dir.join(name), image.lib_dir.join(name));
+            if !injection.mounts.contains(&mount) {
+                injection.mounts.push(mount);
+            }
+            found = true;
+        }
+        found
+    };
+
+    if config.quicksync != Some(false) {
+        let (driver, file) = VAAPI_DRIVERS
+            .iter()
+            .find(|(_, file)| host.dri_dir.join(file).exists())
+            .ok_or_else(|| {
+                anyhow!(
+                    "No VA-API driver (iHD_drv_video.so or i965_drv_video.so) in {}; install intel-media-driver",
+                    host.dri_dir.display()
+                )
+            })?;
+        if !libraries(VAAPI_LIBRARIES, &mut injection) {
+            return Err(anyhow!("No libva found in {}", host.lib_dir.display()));
+        }
+        libraries(QUICKSYNC_LIBRARIES, &mut injection);
+        injection
+            .mounts
+            .push((host.dri_dir.join(file), image.dri_dir.join(file)));
+        injection.env.extend([
+            format!("LIBVA_DRIVER_NAME={}", driver),
+            format!("LIBVA_DRIVERS_PATH={}", image.dri_dir.display()),
+        ]);
+    }
+
+    if config.level_zero == Some(true) {
+        if !libraries(LEVEL_ZERO_LIBRARIES, &mut injection)
+            || !names.iter().any(|n| n.starts_with("libze_intel_gpu.so"))
+        {
+            return Err(anyhow!(
+                "gpu.intel.level_zero is set but libze_intel_gpu.so is not in {}; install the Intel compute runtime",
+                host.lib_dir.display()
+            ));
+        }
+        // Lets tools in the container read frequency and power through Sysman
+        injection.env.push("ZES_ENABLE_SYSMAN=1".to_string());
+    }
+
+    injection.env.extend(
+        image
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value)),
+    );
+    Ok(injection)
+}
+
+/// Give the service its Intel GPU: its own nodes in place of `/dev/dri`
+/// and the media stack on top of the Mesa injection
+pub fn apply(
+    config: &IntelConfig,
+    host: &LibraryLayout,
+    image: &LibraryLayout,
+    injection: &mut GpuInjection,
+) -> Result<()> {
+    let device = select(config, &Intel.discover())?;
+    let intel = plan(config, &device, host, image)?;
+    injection.devices.retain(|d| d != "/dev/dri");
+    injection.devices.extend(intel.devices);
+    for mount in intel.mounts {
+        if !injection.mounts.contains(&mount) {
+            injection.mounts.push(mount);
+        }
+    }
+    for env in intel.env {
+        if !injection.env.contains(&env) {
+            injection.env.push(env);
+        }
+    }
+    Ok(())
+}
+
+fn library_names(lib_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(lib_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// One sample of an Intel GPU
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntelMetrics {
+    pub pci_bus_id: String,
+    pub driver: String,
+    pub frequency_mhz: Option<u32>,
+    pub max_frequency_mhz: Option<u32>,
+    /// Share of the interval the GT was out of RC6
+    pub busy_percent: Option<f64>,
+    /// Discrete cards only
+    pub power_watts: Option<f64>,
+    pub temperature_celsius: Option<f64>,
+}
+
+/// Cumulative counters read at both ends of a sample
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub idle_ms: Option<u64>,
+    pub energy_uj: Option<u64>,
+}
+
+/// Files of one GPU under its PCI sysfs directory
+#[derive(Debug, Clone)]
+pub struct Sysfs {
+    device: PathBuf,
+    xe: bool,
+}
+
+impl Sysfs {
+    pub fn new(pci_root: &Path, device: &Device) -> Self {
+        Self {
+            device: pci_root.join(&device.pci_bus_id),
+            xe: device.driver == "xe",
+        }
+    }
+
+    fn card(&self) -> Option<PathBuf> {
+        std::fs::read_dir(self.device.join("drm"))
+            .ok()?
+            .flatten()
+            .map(|e| e.path())
+            .find(|p| {
+                p.file_name()
+                    .is_some_and(|n| n.to_string_lossy().starts_with("card"))
+            })
+    }
+
+    fn hwmon(&self, file: &str) -> Option<u64> {
+        std::fs::read_dir(self.device.join("hwmon"))
+            .ok()?
+            .flatten()
+            .find_map(|e| read_number(&e.path().join(file)))
+    }
+
+    fn frequency(&self, name: &str) -> Option<u32> {
+        let value = if self.xe {
+            read_number(&self.device.join(format!("tile0/gt0/freq0/{}_freq", name)))
+        } else {
+            read_number(&self.card()?.join(format!("gt_{}_freq_mhz", name)))
+        };
+        value.map(|v| v as u32)
+    }
+
+    pub fn counters(&self) -> Counters {
+        let idle_ms = if self.xe {
+            read_number(&self.device.join("tile0/gt0/gtidle/idle_residency_ms"))
+        } else {
+            self.card().and_then(|card| {
+                read_number(&card.join("gt/gt0/rc6_residency_ms"))
+                    .or_else(|| read_number(&card.join("power/rc6_residency_ms")))
+            })
+        };
+        Counters {
+            idle_ms,
+            energy_uj: self.hwmon("energy1_input"),
+        }
+    }
+
+    /// Metrics over `elapsed`, from counters read at its start and end
+    pub fn metrics(
+        &self,
+        device: &Device,
+        before: Counters,
+        after: Counters,
+        elapsed: Duration,
+    ) -> IntelMetrics {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let busy_percent = match (before.idle_ms, after.idle_ms) {
+            (Some(before), Some(after)) if elapsed_ms > 0.0 => {
+                let idle = after.saturating_sub(before) as f64;
+                Some((100.0 * (1.0 - idle / elapsed_ms)).clamp(0.0, 100.0))
+            }
+            _ => None,
+        };
+        let power_watts = match (before.energy_uj, after.energy_uj) {
+            (Some(before), Some(after)) if elapsed_ms > 0.0 => {
+                Some(after.saturating_sub(before) as f64 / 1000.0 / elapsed_ms)
+            }
+            _ => None,
+        };
+        IntelMetrics {
+            pci_bus_id: device.pci_bus_id.clone(),
+            driver: device.driver.clone(),
+            frequency_mhz: self.frequency("act"),
+            max_frequency_mhz: self.frequency("max"),
+            busy_percent,
+            power_watts,
+            temperature_celsius: self.hwmon("temp1_input").map(|t| t as f64 / 1000.0),
+        }
+    }
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Sample every Intel GPU over `interval`
+pub async fn sample(interval: Duration) -> Vec<IntelMetrics> {
+    let pci_root = Path::new("/sys/bus/pci/devices");
+    let devices: Vec<(Device, Sysfs)> = Intel
+        .discover()
+        .into_iter()
+        .map(|d| {
+            let sysfs = Sysfs::new(pci_root, &d);
+            (d, sysfs)
+        })
+        .collect();
+    if devices.is_empty() {
+        return Vec::new();
+    }
+    let before: Vec<Counters> = devices.iter().map(|(_, s)| s.counters()).collect();
+    let started = std::time::Instant::now();
+    tokio::time::sleep(interval).await;
+    let elapsed = started.elapsed();
+    devices
+        .iter()
+        .zip(before)
+        .map(|((device, sysfs), before)| sysfs.metrics(device, before, sysfs.counters(), elapsed))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::gpu::GPUVendor;
+
+    #[test]
+    fn plans_media_stack_and_reads_sysfs_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib_dir = dir.path().join("lib");
+        std::fs::create_dir_all(lib_dir.join("dri")).unwrap();
+        for file in [
+            "libva.so.2",
+            "libva-drm.so.2",
+            "libigdgmm.so.12",
+            "libvpl.so.2",
+            "libmfx-gen.so.1.2",
+            "libvulkan_intel.so",
+            "dri/iHD_drv_video.so",
+        ] {
+            std::fs::write(lib_dir.join(file), "").unwrap();
+        }
+        let host = LibraryLayout {
+            lib_dir: lib_dir.clone(),
+            dri_dir: lib_dir.join("dri"),
+            share_dir: dir.path().join("share"),
+            env: Vec::new(),
+        };
+        let image = LibraryLayout {
+            lib_dir: PathBuf::from("/usr/lib64"),
+            dri_dir: PathBuf::from("/usr/lib64/dri"),
+            share_dir: PathBuf::from("/usr/share"),
+            env: Vec::new(),
+        };
+        let device = Device {
+            vendor: GPUVendor::Intel,
+            index: 0,
+            pci_bus_id: "0000:00:02.0".to_string(),
+            driver: "i915".to_string(),
+            nodes: vec![
+                "/dev/dri/card1".to_string(),
+                "/dev/dri/renderD128".to_string(),
+            ],
+        };
+        let config = IntelConfig {
+            device: None,
+            quicksync: None,
+            level_zero: None,
+        };
+
+        assert_eq!(
+            select(&config, std::slice::from_ref(&device)).unwrap(),
+            device
+        );
+        assert!(
+            select(
+                &IntelConfig {
+                    device: Some(1),
+                    ..config.clone()
+                },
+                std::slice::from_ref(&device)
+            )
+            .is_err()
+        );
+
+        let injection = plan(&config, &device, &host, &image).unwrap();
+        assert_eq!(injection.devices, device.nodes);
+        let targets: Vec<_> = injection.mounts.iter().map(|(_, c)| c.clone()).collect();
+        assert!(targets.contains(&PathBuf::from("/usr/lib64/libva.so.2")));
+        assert!(targets.contains(&PathBuf::from("/usr/lib64/libmfx-gen.so.1.2")));
+        assert!(targets.contains(&PathBuf::from("/usr/lib64/dri/iHD_drv_video.so")));
+        assert!(!targets.contains(&PathBuf::from("/usr/lib64/libvulkan_intel.so")));
+        assert!(injection.env.contains(&"LIBVA_DRIVER_NAME=iHD".to_string()));
+        let level_zero = IntelConfig {
+            level_zero: Some(true),
+            ..config.clone()
+        };
+        assert!(plan(&level_zero, &device, &host, &image).is_err());
+
+        let pci = dir.path().join("pci");
+        let card = pci.join("0000:00:02.0/drm/card1");
+        std::fs::create_dir_all(card.join("gt/gt0")).unwrap();
+        std::fs::write(card.join("gt_act_freq_mhz"), "1100\n").unwrap();
+        std::fs::write(card.join("gt_max_freq_mhz"), "1450\n").unwrap();
+        std::fs::write(card.join("gt/gt0/rc6_residency_ms"), "1000\n").unwrap();
+        let hwmon = pci.join("0000:00:02.0/hwmon/hwmon3");
+        std::fs::create_dir_all(&hwmon).unwrap();
+        std::fs::write(hwmon.join("energy1_input"), "5000000\n").unwrap();
+
+        let sysfs = Sysfs::new(&pci, &device);
+        let before = sysfs.counters();
+        std::fs::write(card.join("gt/gt0/rc6_residency_ms"), "1250\n").unwrap();
+        std::fs::write(hwmon.join("energy1_input"), "7000000\n").unwrap();
+        let metrics = sysfs.metrics(&device, before, sysfs.counters(), Duration::from_secs(1));
+        assert_eq!(metrics.frequency_mhz, Some(1100));
+        assert_eq!(metrics.max_frequency_mhz, Some(1450));
+        assert_eq!(metrics.busy_percent, Some(75.0));
+        assert_eq!(metrics.power_watts, Some(2.0));
+        assert_eq!(metrics.temperature_celsius, None);
+    }
+}
@@ -5,9 +5,15 @@ use std::path::Path;
 use std::process::Command;
 use tracing::{debug, info, warn};
 
+pub mod allocation;
 pub mod amd;
+pub mod distro;
+pub mod icd;
+pub mod intel;
 pub mod nvbind;
 pub mod nvidia;
+pub mod nvidia_ctk;
+pub mod plugin;
 pub mod velocity;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,7 +33,7 @@ pub struct GPUInfo {
     pub device_paths: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GPUVendor {
     NVIDIA,
     AMD,
@@ -66,6 +72,19 @@ impl GPUManager {
     ) -> Result<()> {
         info!("🚀 Setting up GPU access for container: {}", container_id);
 
+        // An existing nvidia-container-toolkit install, asked for or standing in for nvbind
+        let runtime = gpu_config.runtime.as_deref();
+        if runtime == Some("nvidia-ctk") || (runtime == Some("nvbind") && self.nvbind.is_none()) {
+            if let Some(interop) = nvidia_ctk::Interop::detect() {
+                info!("  Using nvidia-container-toolkit: {}", interop.describe());
+                return Ok(());
+            }
+            warn!(
+                "⚠️ nvidia-container-toolkit not found (no CDI spec in {} and no prestart hook)",
+                nvidia_ctk::CDI_DIRS.join(" or ")
+            );
+        }
+
         // Check if nvbind runtime is preferred
         if let Some(ref runtime) = gpu_config.runtime {
             if runtime == "nvbind" {
@@ -237,14 +256,15 @@ impl GPUManager {
         info!("📦 Mounting Vulkan drivers for container: {}", container_id);
 
         // Mount Vulkan ICD files
+        let layout = distro::BaseOs::detect_host().layout();
         let vulkan_paths = [
-            "/usr/share/vulkan",
-            "/usr/lib/x86_64-linux-gnu/libvulkan.so.1",
+            std::path::PathBuf::from("/usr/share/vulkan"),
+            layout.lib_dir.join("libvulkan.so.1"),
         ];
 
         for path in &vulkan_paths {
-            if Path::new(path).exists() {
-                debug!("  Mounting: {}", path);
+            if path.exists() {
+                debug!("  Mounting: {}", path.display());
                 // Would bind-mount these paths into container
             }
         }
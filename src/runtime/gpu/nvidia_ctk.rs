@@ -0,0 +1,473 @@
+// nvidia-container-toolkit interop
+//
+// `gpu.runtime = "nvidia-ctk"` hands NVIDIA GPU setup to an existing
+// nvidia-container-toolkit install instead of nvbind or Bolt's own driver
+// injection. It is also used when `gpu.runtime = "nvbind"` but nvbind isn't
+// installed. Two paths, in order of preference:
+//
+// - CDI: the spec `nvidia-ctk cdi generate` wrote to /etc/cdi or
+//   /var/run/cdi is resolved for the requested devices into device nodes,
+//   bind mounts and environment. Its hooks (library symlinks, ldcache) run
+//   from a generated OCI hooks directory under podman; docker can't run
+//   them.
+// - Prestart hook: without a CDI spec, nvidia-container-runtime-hook does
+//   the injection from NVIDIA_VISIBLE_DEVICES when the container starts,
+//   through a hooks directory under podman and `--gpus` under docker.
+//
+// Both paths give the container the same devices, files and environment:
+// the hook mounts what `nvidia-container-cli list` reports, and a CDI spec
+// generated for the same driver lists the same paths. NVIDIA_VISIBLE_DEVICES
+// is set to the selected devices on both, rather than the `void` CDI specs
+// use to keep the hook out of the way.
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::distro::GpuInjection;
+use crate::config::GpuConfig;
+use crate::runtime::oci::cdi::{CDIContainerEdits, CDIHook, CDISpec};
+
+/// Where nvidia-ctk writes CDI specs
+pub const CDI_DIRS: [&str; 2] = ["/etc/cdi", "/var/run/cdi"];
+pub const CDI_KIND: &str = "nvidia.com/gpu";
+const HOOK_BINARIES: [&str; 2] = ["nvidia-container-runtime-hook", "nvidia-container-toolkit"];
+/// Capabilities requested on both paths; gaming needs graphics and display
+const DRIVER_CAPABILITIES: &str = "all";
+
+/// How the toolkit injects the GPUs
+#[derive(Debug, Clone)]
+pub enum Interop {
+    Cdi { path: PathBuf, spec: CDISpec },
+    PrestartHook { hook: PathBuf },
+}
+
+impl Interop {
+    /// A CDI spec for NVIDIA GPUs, otherwise the prestart hook
+    pub fn detect() -> Option<Self> {
+        let dirs: Vec<&Path> = CDI_DIRS.iter().map(Path::new).collect();
+        if let Some((path, spec)) = find_spec(&dirs) {
+            return Some(Interop::Cdi { path, spec });
+        }
+        find_hook().map(|hook| Interop::PrestartHook { hook })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Interop::Cdi { path, spec } => format!(
+                "CDI spec {} ({} devices)",
+                path.display(),
+                spec.devices.len()
+            ),
+            Interop::PrestartHook { hook } => format!("prestart hook {}", hook.display()),
+        }
+    }
+}
+
+/// The first spec in `dirs` for NVIDIA GPUs
+pub fn find_spec(dirs: &[&Path]) -> Option<(PathBuf, CDISpec)> {
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|ext| ext == "yaml" || ext == "yml" || ext == "json")
+            })
+            .collect();
+        paths.sort();
+        for path in paths {
+            match parse_spec(&path) {
+                Ok(spec) if spec.kind == CDI_KIND => return Some((path, spec)),
+                Ok(_) => {}
+                Err(e) => debug!("Skipping CDI spec {}: {}", path.display(), e),
+            }
+        }
+    }
+    None
+}
+
+/// Parse a CDI spec (YAML, or JSON, which YAML accepts)
+pub fn parse_spec(path: &Path) -> Result<CDISpec> {
+    let text = std::fs::read_to_string(path)?;
+    serde_yaml::from_str(&text).with_context(|| format!("Invalid CDI spec {}", path.display()))
+}
+
+fn find_hook() -> Option<PathBuf> {
+    let path = std::env::var("PATH").unwrap_or_default();
+    HOOK_BINARIES.iter().find_map(|name| {
+        std::env::split_paths(&path)
+            .chain([PathBuf::from("/usr/bin"), PathBuf::from("/usr/local/bin")])
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.exists())
+    })
+}
+
+/// Device names to request: the placed GPUs, `nvbind.devices`
+/// ("gpu:0", "gpu:all"), `nvidia.device`, or all of them
+pub fn selection(gpu: &GpuConfig, placed: &[u32]) -> Vec<String> {
+    if !placed.is_empty() {
+        return placed.iter().map(|i| i.to_string()).collect();
+    }
+    if let Some(devices) = gpu.nvbind.as_ref().and_then(|n| n.devices.as_ref()) {
+        let names: Vec<String> = devices
+            .iter()
+            .map(|d| d.strip_prefix("gpu:").unwrap_or(d).to_string())
+            .collect();
+        if !names.is_empty() {
+            return names;
+        }
+    }
+    match gpu.nvidia.as_ref().and_then(|n| n.device) {
+        Some(device) => vec![device.to_string()],
+        None => vec!["all".to_string()],
+    }
+}
+
+fn selection_env(selection: &[String]) -> Vec<String> {
+    vec![
+        format!("NVIDIA_VISIBLE_DEVICES={}", selection.join(",")),
+        format!("NVIDIA_DRIVER_CAPABILITIES={}", DRIVER_CAPABILITIES),
+    ]
+}
+
+/// The spec's edits for the selected devices, merged
+pub fn cdi_edits(spec: &CDISpec, selection: &[String]) -> Result<CDIContainerEdits> {
+    let mut edits = spec.container_edits.clone().unwrap_or_default();
+    for name in selection {
+        let devices: Vec<_> = if name == "all" && !spec.devices.iter().any(|d| d.name == "all") {
+            spec.devices.iter().collect()
+        } else {
+            spec.devices.iter().filter(|d| &d.name == name).collect()
+        };
+        if devices.is_empty() {
+            let known: Vec<&str> = spec.devices.iter().map(|d| d.name.as_str()).collect();
+            return Err(anyhow!(
+                "CDI spec has no device {}={} (has: {})",
+                spec.kind,
+                name,
+                known.join(", ")
+            ));
+        }
+        for device in devices {
+            let device = &device.container_edits;
+            edits
+                .device_nodes
+                .extend(device.device_nodes.iter().cloned());
+            edits.mounts.extend(device.mounts.iter().cloned());
+            edits.env.extend(device.env.iter().cloned());
+            edits.hooks.extend(device.hooks.iter().cloned());
+        }
+    }
+    Ok(edits)
+}
+
+/// What the container gets from the CDI path
+pub fn cdi_injection(spec: &CDISpec, selection: &[String]) -> Result<GpuInjection> {
+    let edits = cdi_edits(spec, selection)?;
+    let mut injection = GpuInjection::default();
+    let mut seen = BTreeSet::new();
+    for node in &edits.device_nodes {
+        let host = node.host_path.as_deref().unwrap_or(&node.path);
+        let device = if host == node.path {
+            node.path.clone()
+        } else {
+            format!("{}:{}", host, node.path)
+        };
+        if seen.insert(device.clone()) {
+            injection.devices.push(device);
+        }
+    }
+    for mount in &edits.mounts {
+        let mount = (
+            PathBuf::from(&mount.host_path),
+            PathBuf::from(&mount.container_path),
+        );
+        if !injection.mounts.contains(&mount) {
+            injection.mounts.push(mount);
+        }
+    }
+    injection.env = merge_env(&edits.env, &selection_env(selection));
+    Ok(injection)
+}
+
+/// What the prestart hook mounts, from `nvidia-container-cli list` output:
+/// device nodes, then libraries, binaries and IPC sockets at their host paths
+pub fn hook_injection(selection: &[String], cli_list: &str) -> GpuInjection {
+    let mut injection = GpuInjection {
+        env: selection_env(selection),
+        ..Default::default()
+    };
+    for path in cli_list
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with('/'))
+    {
+        if path.starts_with("/dev/") {
+            injection.devices.push(path.to_string());
+        } else {
+            injection
+                .mounts
+                .push((PathBuf::from(path), PathBuf::from(path)));
+        }
+    }
+    injection
+}
+
+/// `overrides` replace same-named variables in `env`
+fn merge_env(env: &[String], overrides: &[String]) -> Vec<String> {
+    let name = |var: &String| var.split('=').next().unwrap_or_default().to_string();
+    let replaced: BTreeSet<String> = overrides.iter().map(name).collect();
+    let mut merged: Vec<String> = env
+        .iter()
+        .filter(|var| !replaced.contains(&name(var)))
+        .cloned()
+        .collect();
+    merged.extend(overrides.iter().cloned());
+    merged
+}
+
+/// Ask the toolkit what the prestart hook would mount for `selection`
+pub async fn hook_listing(selection: &[String]) -> Result<String> {
+    let mut cmd = AsyncCommand::new("nvidia-container-cli");
+    cmd.arg("list");
+    if selection != ["all"] {
+        cmd.arg(format!("--device={}", selection.join(",")));
+    }
+    cmd.args([
+        "--compute",
+        "--utility",
+        "--graphics",
+        "--video",
+        "--display",
+    ]);
+    let output = cmd
+        .output()
+        .await
+        .context("Failed to run nvidia-container-cli")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "nvidia-container-cli list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Paths the prestart hook would inject that the CDI spec doesn't cover,
+/// e.g. after a driver upgrade without regenerating the spec
+pub fn missing_from_spec(cdi: &GpuInjection, hook: &GpuInjection) -> Vec<String> {
+    let covered: BTreeSet<String> = cdi
+        .devices
+        .iter()
+        .map(|d| d.rsplit(':').next().unwrap_or(d).to_string())
+        .chain(cdi.mounts.iter().map(|(_, c)| c.display().to_string()))
+        .collect();
+    hook.devices
+        .iter()
+        .cloned()
+        .chain(hook.mounts.iter().map(|(_, c)| c.display().to_string()))
+        .filter(|path| !covered.contains(path))
+        .collect()
+}
+
+/// `podman/docker run` arguments that let the toolkit inject the selected
+/// GPUs. Hook directories for podman are written under `hooks_root`. With
+/// `placed`, the topology placement passes the GPUs' own device nodes.
+pub fn run_args(
+    interop: &Interop,
+    runtime: &str,
+    selection: &[String],
+    placed: bool,
+    hooks_root: &Path,
+) -> Result<Vec<String>> {
+    let podman = runtime.ends_with("podman");
+    match interop {
+        Interop::Cdi { path, spec } => {
+            info!(
+                "🟢 Injecting GPUs {} from CDI spec {}",
+                selection.join(","),
+                path.display()
+            );
+            let mut injection = cdi_injection(spec, selection)?;
+            if placed {
+                injection
+                    .devices
+                    .retain(|d| !crate::gaming::topology::is_gpu_device(d));
+            }
+            let mut args = injection.run_args();
+            let hooks = cdi_edits(spec, selection)?.hooks;
+            if !hooks.is_empty() {
+                if podman {
+                    let dir = hooks_root.join("cdi");
+                    write_hooks(&dir, &hooks)?;
+                    args.push("--hooks-dir".to_string());
+                    args.push(dir.display().to_string());
+                } else {
+                    warn!(
+                        "⚠️  {} can't run the CDI spec's {} hook(s); libraries are mounted but symlinks and the ldcache are not updated",
+                        runtime,
+                        hooks.len()
+                    );
+                }
+            }
+            Ok(args)
+        }
+        Interop::PrestartHook { hook } => {
+            info!(
+                "🟢 Injecting GPUs {} with {}",
+                selection.join(","),
+                hook.display()
+            );
+            let mut args = Vec::new();
+            if podman {
+                let dir = hooks_root.join("prestart");
+                write_hooks(
+                    &dir,
+                    &[CDIHook {
+                        hook_name: "prestart".to_string(),
+                        path: hook.display().to_string(),
+                        args: vec![
+                            hook.file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                .unwrap_or_default(),
+                            "prestart".to_string(),
+                        ],
+                        env: Vec::new(),
+                    }],
+                )?;
+                args.push("--hooks-dir".to_string());
+                args.push(dir.display().to_string());
+            } else {
+                args.push("--gpus".to_string());
+                args.push(if selection == ["all"] {
+                    "all".to_string()
+                } else {
+                    format!("\"device={}\"", selection.join(","))
+                });
+            }
+            for env in selection_env(selection) {
+                args.push("-e".to_string());
+                args.push(env);
+            }
+            Ok(args)
+        }
+    }
+}
+
+/// Write OCI hooks (podman's hooks.d format), one file per hook in run order
+fn write_hooks(dir: &Path, hooks: &[CDIHook]) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let _ = std::fs::remove_file(entry.path());
+    }
+    for (i, hook) in hooks.iter().enumerate() {
+        let mut definition = serde_json::json!({
+            "path": hook.path,
+            "args": hook.args,
+        });
+        if !hook.env.is_empty() {
+            definition["env"] = serde_json::json!(hook.env);
+        }
+        let file = serde_json::json!({
+            "version": "1.0.0",
+            "hook": definition,
+            "when": { "always": true },
+            "stages": [hook.hook_name],
+        });
+        let path = dir.join(format!("{:02}-nvidia.json", i));
+        std::fs::write(&path, serde_json::to_vec_pretty(&file)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trimmed from `nvidia-ctk cdi generate` and `nvidia-container-cli list`
+    // for the same driver
+    const SPEC: &str = r#"
+cdiVersion: 0.5.0
+kind: nvidia.com/gpu
+devices:
+- name: "0"
+  containerEdits:
+    deviceNodes:
+    - path: /dev/nvidia0
+- name: "1"
+  containerEdits:
+    deviceNodes:
+    - path: /dev/nvidia1
+- name: all
+  containerEdits:
+    deviceNodes:
+    - path: /dev/nvidia0
+    - path: /dev/nvidia1
+containerEdits:
+  env:
+  - NVIDIA_VISIBLE_DEVICES=void
+  deviceNodes:
+  - path: /dev/nvidiactl
+  - path: /dev/nvidia-uvm
+  hooks:
+  - hookName: createContainer
+    path: /usr/bin/nvidia-ctk
+    args: [nvidia-ctk, hook, update-ldcache, --folder, /usr/lib/x86_64-linux-gnu]
+  mounts:
+  - hostPath: /usr/lib/x86_64-linux-gnu/libcuda.so.550.54.14
+    containerPath: /usr/lib/x86_64-linux-gnu/libcuda.so.550.54.14
+    options: [ro, nosuid, nodev, bind]
+  - hostPath: /usr/bin/nvidia-smi
+    containerPath: /usr/bin/nvidia-smi
+    options: [ro, nosuid, nodev, bind]
+"#;
+
+    const CLI_LIST: &str = "/dev/nvidiactl
+/dev/nvidia-uvm
+/dev/nvidia1
+/usr/bin/nvidia-smi
+/usr/lib/x86_64-linux-gnu/libcuda.so.550.54.14
+";
+
+    #[test]
+    fn cdi_and_prestart_hook_paths_inject_the_same_environment_and_files() {
+        let spec: CDISpec = serde_yaml::from_str(SPEC).unwrap();
+        let selection = vec!["1".to_string()];
+
+        let cdi = cdi_injection(&spec, &selection).unwrap();
+        let hook = hook_injection(&selection, CLI_LIST);
+
+        let sorted = |mut v: Vec<String>| {
+            v.sort();
+            v
+        };
+        assert_eq!(sorted(cdi.env.clone()), sorted(hook.env.clone()));
+        assert!(cdi.env.contains(&"NVIDIA_VISIBLE_DEVICES=1".to_string()));
+        assert_eq!(sorted(cdi.devices.clone()), sorted(hook.devices.clone()));
+        let mut cdi_mounts = cdi.mounts.clone();
+        let mut hook_mounts = hook.mounts.clone();
+        cdi_mounts.sort();
+        hook_mounts.sort();
+        assert_eq!(cdi_mounts, hook_mounts);
+        assert!(missing_from_spec(&cdi, &hook).is_empty());
+        assert_eq!(cdi_edits(&spec, &selection).unwrap().hooks.len(), 1);
+
+        let all = cdi_injection(&spec, &["all".to_string()]).unwrap();
+        assert!(all.devices.contains(&"/dev/nvidia0".to_string()));
+        assert!(cdi_injection(&spec, &["7".to_string()]).is_err());
+
+        // A driver upgrade without `nvidia-ctk cdi generate` leaves the spec stale
+        let upgraded = CLI_LIST.replace("550.54.14", "555.42.02");
+        let stale = missing_from_spec(&cdi, &hook_injection(&selection, &upgraded));
+        assert_eq!(
+            stale,
+            vec!["/usr/lib/x86_64-linux-gnu/libcuda.so.555.42.02".to_string()]
+        );
+    }
+}
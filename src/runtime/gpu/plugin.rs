@@ -0,0 +1,495 @@
+// GPU device plugins
+//
+// One plugin per vendor knows how to find its GPUs and what a container
+// needs to use some of them, so `bolt run --gpu`, capsules and the native
+// OCI runtime hand out GPUs the same way:
+//
+// - NVIDIA: `/dev/nvidiaN` per GPU plus the shared control nodes. With
+//   nvidia-container-toolkit installed the GPUs are requested through it
+//   (CDI names under podman, `--gpus` under docker), which also injects the
+//   driver libraries; nvbind and Bolt's own injection (see `distro.rs`)
+//   bring the libraries otherwise.
+// - AMD: the GPU's DRI card and render nodes, plus `/dev/kfd` for ROCm.
+// - Intel: the GPU's DRI card and render nodes under i915 or xe.
+//
+// GPUs are found on the PCI bus: display controllers of the plugin's
+// vendor bound to one of its kernel drivers. A container's GPUs are recorded
+// in its `bolt.gpus` label, as for surge placement (see
+// `gaming/topology.rs`), so they count as held and end with the container.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::GPUVendor;
+use crate::runtime::oci::cdi::{CDIContainerEdits, CDIDevice, CDIDeviceNode, CDISpec};
+
+const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+const CDI_VERSION: &str = "0.5.0";
+
+/// A GPU on this host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    pub vendor: GPUVendor,
+    /// Position among the vendor's GPUs by PCI address, as
+    /// NVIDIA_VISIBLE_DEVICES and CDI device names count them
+    pub index: u32,
+    pub pci_bus_id: String,
+    /// Kernel driver, e.g. nvidia, amdgpu, i915 or xe
+    pub driver: String,
+    /// Device nodes of this GPU alone
+    pub nodes: Vec<String>,
+}
+
+/// What a container gets for a set of GPUs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Allocation {
+    pub devices: Vec<Device>,
+    /// The GPUs' nodes and the vendors' shared ones present on this host
+    pub nodes: Vec<String>,
+    pub env: Vec<String>,
+}
+
+impl Allocation {
+    fn merge(&mut self, other: Allocation) {
+        self.devices.extend(other.devices);
+        for node in other.nodes {
+            if !self.nodes.contains(&node) {
+                self.nodes.push(node);
+            }
+        }
+        self.env.extend(other.env);
+    }
+
+    /// `run` arguments passing the device nodes straight in
+    pub fn device_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for node in &self.nodes {
+            args.extend(["--device".to_string(), node.clone()]);
+        }
+        for env in &self.env {
+            args.extend(["-e".to_string(), env.clone()]);
+        }
+        args
+    }
+}
+
+pub trait DevicePlugin: Send + Sync {
+    fn vendor(&self) -> GPUVendor;
+
+    /// PCI vendor ID, e.g. "0x10de"
+    fn pci_vendor(&self) -> &'static str;
+
+    /// Kernel drivers a usable GPU is bound to
+    fn drivers(&self) -> &'static [&'static str];
+
+    /// CDI kind of the vendor's devices, e.g. "nvidia.com/gpu"
+    fn cdi_kind(&self) -> &'static str;
+
+    /// Device nodes of one GPU, from its sysfs directory
+    fn gpu_nodes(&self, index: u32, sysfs: &Path) -> Vec<String>;
+
+    /// Nodes every container using the vendor's GPUs needs
+    fn shared_nodes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Environment telling the vendor's libraries which GPUs they got
+    fn env(&self, _devices: &[Device]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The vendor's GPUs under `pci_root`, by PCI address
+    fn discover_in(&self, pci_root: &Path) -> Vec<Device> {
+        let Ok(entries) = std::fs::read_dir(pci_root) else {
+            return Vec::new();
+        };
+        let mut gpus: Vec<(String, String)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let read = |file: &str| std::fs::read_to_string(path.join(file)).ok();
+                let is_display = read("class").is_some_and(|c| c.trim().starts_with("0x03"));
+                let vendor = read("vendor").is_some_and(|v| v.trim() == self.pci_vendor());
+                let driver = std::fs::read_link(path.join("driver"))
+                    .ok()?
+                    .file_name()?
+                    .to_string_lossy()
+                    .to_string();
+                (is_display && vendor && self.drivers().contains(&driver.as_str()))
+                    .then(|| (entry.file_name().to_string_lossy().to_string(), driver))
+            })
+            .collect();
+        gpus.sort();
+        gpus.into_iter()
+            .enumerate()
+            .map(|(index, (pci_bus_id, driver))| Device {
+                vendor: self.vendor(),
+                index: index as u32,
+                nodes: self.gpu_nodes(index as u32, &pci_root.join(&pci_bus_id)),
+                pci_bus_id,
+                driver,
+            })
+            .collect()
+    }
+
+    /// The vendor's GPUs on this host
+    fn discover(&self) -> Vec<Device> {
+        self.discover_in(Path::new(PCI_DEVICES))
+    }
+
+    /// Device nodes and environment for `devices`
+    fn allocate(&self, devices: &[Device]) -> Allocation {
+        let mut nodes: Vec<String> = devices.iter().flat_map(|d| d.nodes.clone()).collect();
+        nodes.extend(
+            self.shared_nodes()
+                .iter()
+                .filter(|node| Path::new(node).exists())
+                .map(|node| node.to_string()),
+        );
+        Allocation {
+            devices: devices.to_vec(),
+            nodes,
+            env: self.env(devices),
+        }
+    }
+
+    /// `run` arguments requesting `devices` through the vendor's container
+    /// toolkit, when it's installed and works with `runtime`
+    fn toolkit_args(&self, _runtime: &str, _devices: &[Device]) -> Option<Vec<String>> {
+        None
+    }
+
+    /// A CDI spec for `devices`, one CDI device per GPU named by its index
+    fn cdi_spec(&self, devices: &[Device]) -> CDISpec {
+        let node = |path: &str| CDIDeviceNode {
+            path: path.to_string(),
+            host_path: None,
+            device_type: "c".to_string(),
+            major: 0,
+            minor: 0,
+        };
+        let shared = self.allocate(&[]);
+        CDISpec {
+            cdi_version: CDI_VERSION.to_string(),
+            kind: self.cdi_kind().to_string(),
+            devices: devices
+                .iter()
+                .map(|device| CDIDevice {
+                    name: device.index.to_string(),
+                    container_edits: CDIContainerEdits {
+                        device_nodes: device.nodes.iter().map(|n| node(n)).collect(),
+                        env: self.env(std::slice::from_ref(device)),
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+            container_edits: (!shared.nodes.is_empty()).then(|| CDIContainerEdits {
+                device_nodes: shared.nodes.iter().map(|n| node(n)).collect(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Give back what `allocate` set up on the host. The GPUs themselves
+    /// are free again once the container carrying their label is gone.
+    fn release(&self, _allocation: &Allocation) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The DRI card and render nodes of a GPU
+fn drm_nodes(sysfs: &Path) -> Vec<String> {
+    let mut nodes: Vec<String> = std::fs::read_dir(sysfs.join("drm"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| n.starts_with("card") || n.starts_with("renderD"))
+                .map(|n| format!("/dev/dri/{}", n))
+                .collect()
+        })
+        .unwrap_or_default();
+    nodes.sort();
+    nodes
+}
+
+pub struct Nvidia;
+
+impl DevicePlugin for Nvidia {
+    fn vendor(&self) -> GPUVendor {
+        GPUVendor::NVIDIA
+    }
+
+    fn pci_vendor(&self) -> &'static str {
+        "0x10de"
+    }
+
+    fn drivers(&self) -> &'static [&'static str] {
+        &["nvidia"]
+    }
+
+    fn cdi_kind(&self) -> &'static str {
+        super::nvidia_ctk::CDI_KIND
+    }
+
+    fn gpu_nodes(&self, index: u32, _sysfs: &Path) -> Vec<String> {
+        vec![format!("/dev/nvidia{}", index)]
+    }
+
+    fn shared_nodes(&self) -> &'static [&'static str] {
+        &[
+            "/dev/nvidiactl",
+            "/dev/nvidia-uvm",
+            "/dev/nvidia-uvm-tools",
+            "/dev/nvidia-modeset",
+        ]
+    }
+
+    fn env(&self, devices: &[Device]) -> Vec<String> {
+        let indexes: Vec<String> = devices.iter().map(|d| d.index.to_string()).collect();
+        vec![
+            format!("NVIDIA_VISIBLE_DEVICES={}", indexes.join(",")),
+            "NVIDIA_DRIVER_CAPABILITIES=all".to_string(),
+        ]
+    }
+
+    fn toolkit_args(&self, runtime: &str, devices: &[Device]) -> Option<Vec<String>> {
+        use super::nvidia_ctk::Interop;
+
+        let indexes: Vec<String> = devices.iter().map(|d| d.index.to_string()).collect();
+        match Interop::detect()? {
+            Interop::Cdi { .. } if runtime == "podman" => Some(
+                indexes
+                    .iter()
+                    .flat_map(|i| ["--device".to_string(), format!("{}={}", self.cdi_kind(), i)])
+                    .collect(),
+            ),
+            _ if runtime == "docker" => Some(vec![
+                "--gpus".to_string(),
+                format!("\"device={}\"", indexes.join(",")),
+            ]),
+            _ => None,
+        }
+    }
+}
+
+pub struct Amd;
+
+impl DevicePlugin for Amd {
+    fn vendor(&self) -> GPUVendor {
+        GPUVendor::AMD
+    }
+
+    fn pci_vendor(&self) -> &'static str {
+        "0x1002"
+    }
+
+    fn drivers(&self) -> &'static [&'static str] {
+        &["amdgpu"]
+    }
+
+    fn cdi_kind(&self) -> &'static str {
+        "amd.com/gpu"
+    }
+
+    fn gpu_nodes(&self, _index: u32, sysfs: &Path) -> Vec<String> {
+        drm_nodes(sysfs)
+    }
+
+    fn shared_nodes(&self) -> &'static [&'static str] {
+        &["/dev/kfd"]
+    }
+}
+
+pub struct Intel;
+
+impl DevicePlugin for Intel {
+    fn vendor(&self) -> GPUVendor {
+        GPUVendor::Intel
+    }
+
+    fn pci_vendor(&self) -> &'static str {
+        "0x8086"
+    }
+
+    fn drivers(&self) -> &'static [&'static str] {
+        &["i915", "xe"]
+    }
+
+    fn cdi_kind(&self) -> &'static str {
+        "intel.com/gpu"
+    }
+
+    fn gpu_nodes(&self, _index: u32, sysfs: &Path) -> Vec<String> {
+        drm_nodes(sysfs)
+    }
+}
+
+pub fn plugins() -> Vec<Box<dyn DevicePlugin>> {
+    vec![Box::new(Nvidia), Box::new(Amd), Box::new(Intel)]
+}
+
+pub fn plugin(vendor: &GPUVendor) -> Box<dyn DevicePlugin> {
+    match vendor {
+        GPUVendor::NVIDIA => Box::new(Nvidia),
+        GPUVendor::AMD => Box::new(Amd),
+        GPUVendor::Intel => Box::new(Intel),
+    }
+}
+
+/// Every vendor's GPUs on this host, NVIDIA first
+pub fn discover() -> Vec<Device> {
+    plugins().iter().flat_map(|p| p.discover()).collect()
+}
+
+/// The GPUs `selector` names among `devices`: "all", or a comma-separated
+/// list of positions in `devices` or PCI addresses
+pub fn select(devices: &[Device], selector: &str) -> Result<Vec<Device>> {
+    if devices.is_empty() {
+        return Err(anyhow!("No GPUs found on this host"));
+    }
+    if selector.trim() == "all" {
+        return Ok(devices.to_vec());
+    }
+    let mut selected: Vec<Device> = Vec::new();
+    for part in selector.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let device = match part.parse::<usize>() {
+            Ok(position) => devices.get(position),
+            Err(_) => devices.iter().find(|d| d.pci_bus_id == part),
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "No GPU {} (this host has {}: 0-{} or their PCI addresses)",
+                part,
+                devices.len(),
+                devices.len() - 1
+            )
+        })?;
+        if !selected.contains(device) {
+            selected.push(device.clone());
+        }
+    }
+    if selected.is_empty() {
+        return Err(anyhow!("No GPUs selected by '{}'", selector));
+    }
+    Ok(selected)
+}
+
+/// `devices` split by vendor, each with its plugin
+fn by_vendor(devices: &[Device]) -> Vec<(Box<dyn DevicePlugin>, Vec<Device>)> {
+    plugins()
+        .into_iter()
+        .filter_map(|plugin| {
+            let group: Vec<Device> = devices
+                .iter()
+                .filter(|d| d.vendor == plugin.vendor())
+                .cloned()
+                .collect();
+            (!group.is_empty()).then_some((plugin, group))
+        })
+        .collect()
+}
+
+/// Device nodes and environment for GPUs of any vendors
+pub fn allocate(devices: &[Device]) -> Allocation {
+    let mut allocation = Allocation::default();
+    for (plugin, group) in by_vendor(devices) {
+        allocation.merge(plugin.allocate(&group));
+    }
+    allocation
+}
+
+/// `run` arguments giving a container `devices`: through the vendors'
+/// toolkits where installed, as device nodes otherwise, and labeled with
+/// the GPUs it holds
+pub fn run_args(runtime: &str, devices: &[Device]) -> Vec<String> {
+    let ids: Vec<&str> = devices.iter().map(|d| d.pci_bus_id.as_str()).collect();
+    let mut args = vec![
+        "--label".to_string(),
+        format!("{}={}", crate::gaming::topology::LABEL, ids.join(",")),
+    ];
+    args.extend(device_args(runtime, devices));
+    args
+}
+
+/// `run` arguments passing `devices` in, without the label
+pub fn device_args(runtime: &str, devices: &[Device]) -> Vec<String> {
+    let mut args = Vec::new();
+    for (plugin, group) in by_vendor(devices) {
+        match plugin.toolkit_args(runtime, &group) {
+            Some(toolkit) => args.extend(toolkit),
+            None => args.extend(plugin.allocate(&group).device_args()),
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_gpu(root: &Path, bus: &str, vendor: &str, driver: &str, drm: &[&str]) {
+        let dir = root.join("devices").join(bus);
+        std::fs::create_dir_all(dir.join("drm")).unwrap();
+        std::fs::write(dir.join("class"), "0x030000\n").unwrap();
+        std::fs::write(dir.join("vendor"), format!("{}\n", vendor)).unwrap();
+        std::fs::create_dir_all(root.join("drivers").join(driver)).unwrap();
+        std::os::unix::fs::symlink(root.join("drivers").join(driver), dir.join("driver")).unwrap();
+        for node in drm {
+            std::fs::create_dir_all(dir.join("drm").join(node)).unwrap();
+        }
+    }
+
+    #[test]
+    fn discovers_and_allocates_per_vendor() {
+        let root = tempfile::tempdir().unwrap();
+        let pci = root.path().join("devices");
+        fake_gpu(root.path(), "0000:03:00.0", "0x10de", "nvidia", &[]);
+        fake_gpu(root.path(), "0000:01:00.0", "0x10de", "nvidia", &[]);
+        fake_gpu(root.path(), "0000:05:00.0", "0x10de", "nouveau", &[]);
+        fake_gpu(
+            root.path(),
+            "0000:0a:00.0",
+            "0x1002",
+            "amdgpu",
+            &["card1", "renderD129"],
+        );
+        fake_gpu(root.path(), "0000:00:02.0", "0x8086", "xe", &["card0"]);
+
+        let nvidia = Nvidia.discover_in(&pci);
+        assert_eq!(nvidia.len(), 2, "nouveau GPUs are skipped");
+        assert_eq!(nvidia[0].pci_bus_id, "0000:01:00.0");
+        assert_eq!(nvidia[1].nodes, ["/dev/nvidia1"]);
+        let amd = Amd.discover_in(&pci);
+        assert_eq!(amd[0].nodes, ["/dev/dri/card1", "/dev/dri/renderD129"]);
+        let intel = Intel.discover_in(&pci);
+        assert_eq!(intel[0].driver, "xe");
+
+        let mut devices = nvidia.clone();
+        devices.extend(amd);
+        devices.extend(intel);
+        let selected = select(&devices, "1,0000:0a:00.0").unwrap();
+        assert_eq!(selected.len(), 2);
+        assert!(select(&devices, "7").is_err());
+
+        let allocation = allocate(&selected);
+        for node in ["/dev/nvidia1", "/dev/dri/card1", "/dev/dri/renderD129"] {
+            assert!(allocation.nodes.contains(&node.to_string()));
+        }
+        assert!(!allocation.nodes.contains(&"/dev/nvidia0".to_string()));
+        assert!(
+            allocation
+                .env
+                .contains(&"NVIDIA_VISIBLE_DEVICES=1".to_string())
+        );
+
+        let spec = Nvidia.cdi_spec(&nvidia);
+        assert_eq!(spec.kind, "nvidia.com/gpu");
+        let names: Vec<&str> = spec.devices.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["0", "1"]);
+        assert_eq!(
+            spec.devices[1].container_edits.device_nodes[0].path,
+            "/dev/nvidia1"
+        );
+    }
+}
@@ -0,0 +1,539 @@
+// Node health
+//
+// `bolt health` and the API server's `/healthz` roll everything that keeps
+// this node serving into one answer:
+//
+// - daemon: the container runtime is installed and answers `info`
+// - storage: the data dir is writable, its disk is below
+//   `[capacity] disk_threshold_percent`, and Podman's storage check finds no
+//   damaged images, layers or containers
+// - network: the host has a default route, the networks of every surge
+//   project exist and the uplinks of their hardware networks are up
+// - projects: every service `surge up` started on this node, across all
+//   projects, is running and passes its healthcheck. Services suspended for
+//   idleness count as healthy.
+//
+// Each part is healthy, degraded or unhealthy, with the reasons it isn't
+// healthy. The node takes the worst of daemon, storage and network. A failing
+// project makes it degraded at most: the node itself still serves, so load
+// balancers keep it in rotation while uptime monitors see the reasons.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use crate::config::BoltConfig;
+use crate::surge::projects::{self, ProjectRecord, RecordedService};
+
+/// How long one runtime command may take before its check fails
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Podman's storage check reads every layer, even with `--quick`
+const STORAGE_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Disk usage, in percent, at which storage is unhealthy rather than degraded
+const DISK_FULL_PERCENT: u64 = 98;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+impl HealthStatus {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            HealthStatus::Healthy => "✅",
+            HealthStatus::Degraded => "⚠️ ",
+            HealthStatus::Unhealthy => "❌",
+        }
+    }
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Unhealthy => "unhealthy",
+        })
+    }
+}
+
+/// Health of one part of the node and why it isn't healthy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+impl ComponentHealth {
+    fn report(&mut self, status: HealthStatus, reason: impl Into<String>) {
+        self.status = self.status.max(status);
+        self.reasons.push(reason.into());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceHealth {
+    pub name: String,
+    pub container: String,
+    pub status: HealthStatus,
+    /// What the service is doing: "running", "suspended", "2/3 running"
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectHealth {
+    pub project: String,
+    pub status: HealthStatus,
+    pub services: Vec<ServiceHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeHealth {
+    pub status: HealthStatus,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    /// Every reason below, prefixed with where it comes from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    pub daemon: ComponentHealth,
+    pub storage: ComponentHealth,
+    pub network: ComponentHealth,
+    pub projects: Vec<ProjectHealth>,
+}
+
+impl NodeHealth {
+    fn rollup(
+        daemon: ComponentHealth,
+        storage: ComponentHealth,
+        network: ComponentHealth,
+        projects: Vec<ProjectHealth>,
+    ) -> Self {
+        let worst_project = projects.iter().map(|p| p.status).max().unwrap_or_default();
+        let status = daemon
+            .status
+            .max(storage.status)
+            .max(network.status)
+            .max(worst_project.min(HealthStatus::Degraded));
+
+        let mut reasons = Vec::new();
+        for (name, component) in [
+            ("daemon", &daemon),
+            ("storage", &storage),
+            ("network", &network),
+        ] {
+            reasons.extend(component.reasons.iter().map(|r| format!("{}: {}", name, r)));
+        }
+        for project in &projects {
+            for service in &project.services {
+                reasons.extend(
+                    service
+                        .reasons
+                        .iter()
+                        .map(|r| format!("{}/{}: {}", project.project, service.name, r)),
+                );
+            }
+        }
+
+        Self {
+            status,
+            checked_at: chrono::Utc::now(),
+            reasons,
+            daemon,
+            storage,
+            network,
+            projects,
+        }
+    }
+}
+
+/// Check the node and every surge project deployed on it
+pub async fn check(config: &BoltConfig) -> NodeHealth {
+    info!("❤️ Checking node health");
+
+    let (runtime, daemon) = check_daemon().await;
+    let storage = check_storage(config, runtime.as_deref()).await;
+    let records = projects::list(&config.data_dir);
+    let network = check_network(runtime.as_deref(), &records).await;
+
+    let instances = match runtime {
+        Some(ref runtime) => container_states(runtime, &records).await,
+        None => Err("container runtime unavailable".to_string()),
+    };
+    let projects = records
+        .iter()
+        .map(|record| {
+            let services: Vec<ServiceHealth> = record
+                .services
+                .iter()
+                .map(|(name, service)| {
+                    check_service(&config.data_dir, name, service, instances.as_ref())
+                })
+                .collect();
+            ProjectHealth {
+                project: record.project.clone(),
+                status: services.iter().map(|s| s.status).max().unwrap_or_default(),
+                services,
+            }
+        })
+        .collect();
+
+    let health = NodeHealth::rollup(daemon, storage, network, projects);
+    debug!(
+        "Node health: {} ({} reasons)",
+        health.status,
+        health.reasons.len()
+    );
+    health
+}
+
+/// Run a runtime command, failing with its stderr or on timeout
+async fn run(program: &str, args: &[&str], timeout: Duration) -> Result<String, String> {
+    let output = tokio::time::timeout(timeout, AsyncCommand::new(program).args(args).output())
+        .await
+        .map_err(|_| format!("{} {} timed out", program, args.join(" ")))?
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn check_daemon() -> (Option<String>, ComponentHealth) {
+    let mut health = ComponentHealth::default();
+    let runtime = match super::detect_container_runtime().await {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            health.report(HealthStatus::Unhealthy, e.to_string());
+            return (None, health);
+        }
+    };
+    if let Err(e) = run(&runtime, &["info"], COMMAND_TIMEOUT).await {
+        health.report(HealthStatus::Unhealthy, e);
+        return (None, health);
+    }
+    (Some(runtime), health)
+}
+
+async fn check_storage(config: &BoltConfig, runtime: Option<&str>) -> ComponentHealth {
+    let mut health = ComponentHealth::default();
+
+    let probe = config.data_dir.join(".health-probe");
+    let writable = std::fs::create_dir_all(&config.data_dir)
+        .and_then(|()| std::fs::write(&probe, b"ok"))
+        .and_then(|()| std::fs::remove_file(&probe));
+    if let Err(e) = writable {
+        health.report(
+            HealthStatus::Unhealthy,
+            format!(
+                "data dir {} is not writable: {}",
+                config.data_dir.display(),
+                e
+            ),
+        );
+    }
+
+    if let Some((size, used)) =
+        crate::monitoring::capacity::filesystem_usage(&config.data_dir).await
+        && size > 0
+    {
+        let percent = used * 100 / size;
+        let threshold = config.capacity.disk_threshold_percent as u64;
+        if percent >= DISK_FULL_PERCENT {
+            health.report(
+                HealthStatus::Unhealthy,
+                format!("data dir disk is {}% full", percent),
+            );
+        } else if percent >= threshold {
+            health.report(
+                HealthStatus::Degraded,
+                format!(
+                    "data dir disk is {}% full (threshold {}%)",
+                    percent, threshold
+                ),
+            );
+        }
+    }
+
+    if runtime == Some("podman") {
+        match run(
+            "podman",
+            &["system", "check", "--quick"],
+            STORAGE_CHECK_TIMEOUT,
+        )
+        .await
+        {
+            Ok(report) => {
+                for line in report.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    health.report(HealthStatus::Degraded, format!("storage check: {}", line));
+                }
+            }
+            // Podman before 5.0 has no storage check
+            Err(e) if e.contains("unrecognized command") => {}
+            Err(e) => health.report(HealthStatus::Degraded, e),
+        }
+    }
+
+    health
+}
+
+async fn check_network(runtime: Option<&str>, records: &[ProjectRecord]) -> ComponentHealth {
+    let mut health = ComponentHealth::default();
+
+    if crate::networking::tuning::default_route_interface().is_none() {
+        health.report(HealthStatus::Degraded, "no IPv4 default route");
+    }
+
+    for record in records {
+        if let Some(runtime) = runtime {
+            for network in &record.networks {
+                if !crate::network::attach::exists(runtime, network).await {
+                    health.report(
+                        HealthStatus::Degraded,
+                        format!("network {} of {} is missing", network, record.project),
+                    );
+                }
+            }
+        }
+        for uplink in &record.uplinks {
+            let state = std::fs::read_to_string(format!("/sys/class/net/{}/operstate", uplink))
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| "missing".to_string());
+            if state != "up" {
+                health.report(
+                    HealthStatus::Degraded,
+                    format!("uplink {} of {} is {}", uplink, record.project, state),
+                );
+            }
+        }
+    }
+
+    health
+}
+
+/// Runtime state of a container and its healthcheck status, if it has one
+#[derive(Debug, Clone, PartialEq)]
+struct InstanceState {
+    state: String,
+    health: Option<String>,
+}
+
+/// State of every instance of every recorded OCI service, by container name
+async fn container_states(
+    runtime: &str,
+    records: &[ProjectRecord],
+) -> Result<HashMap<String, InstanceState>, String> {
+    let names = run(
+        runtime,
+        &["ps", "-a", "--format", "{{.Names}}"],
+        COMMAND_TIMEOUT,
+    )
+    .await?;
+    let wanted: Vec<&str> = names
+        .lines()
+        .map(str::trim)
+        .filter(|name| {
+            records
+                .iter()
+                .flat_map(|r| r.services.values())
+                .any(|s| !s.wasm && instance_of(&s.container, name))
+        })
+        .collect();
+    if wanted.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut args = vec![
+        "inspect",
+        "--format",
+        "{{.Name}}|{{.State.Status}}|{{if .State.Health}}{{.State.Health.Status}}{{end}}",
+    ];
+    args.extend(&wanted);
+    let output = run(runtime, &args, COMMAND_TIMEOUT).await?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().splitn(3, '|');
+            let name = fields.next()?.trim_start_matches('/').to_string();
+            let state = fields.next()?.to_string();
+            let health = fields.next().filter(|h| !h.is_empty()).map(str::to_string);
+            Some((name, InstanceState { state, health }))
+        })
+        .collect())
+}
+
+/// Whether `name` is the service's container or one of its scaled instances
+fn instance_of(container: &str, name: &str) -> bool {
+    name == container
+        || name
+            .strip_prefix(container)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn check_service(
+    data_dir: &Path,
+    name: &str,
+    service: &RecordedService,
+    instances: Result<&HashMap<String, InstanceState>, &String>,
+) -> ServiceHealth {
+    let mut health = ComponentHealth::default();
+    let state = if service.wasm {
+        match super::wasm::state(data_dir, &service.container) {
+            Some(state) if state.is_running() => "running".to_string(),
+            Some(state) => {
+                let described = state.describe();
+                health.report(HealthStatus::Unhealthy, format!("module {}", described));
+                described
+            }
+            None => {
+                health.report(HealthStatus::Unhealthy, "module is not running");
+                "missing".to_string()
+            }
+        }
+    } else {
+        match instances {
+            Ok(instances) => {
+                let suspended = super::idle::state(data_dir, &service.container)
+                    .is_some_and(|s| s.status == super::idle::IdleStatus::Suspended);
+                oci_service_state(&service.container, instances, suspended, &mut health)
+            }
+            Err(e) => {
+                health.report(HealthStatus::Unhealthy, format!("not checked: {}", e));
+                "unknown".to_string()
+            }
+        }
+    };
+
+    ServiceHealth {
+        name: name.to_string(),
+        container: service.container.clone(),
+        status: health.status,
+        state,
+        reasons: health.reasons,
+    }
+}
+
+/// Roll the service's instances up: all failing is unhealthy, some failing
+/// or a healthcheck still starting is degraded
+fn oci_service_state(
+    container: &str,
+    instances: &HashMap<String, InstanceState>,
+    suspended: bool,
+    health: &mut ComponentHealth,
+) -> String {
+    let mut mine: Vec<(&String, &InstanceState)> = instances
+        .iter()
+        .filter(|(name, _)| instance_of(container, name))
+        .collect();
+    mine.sort_by(|a, b| a.0.cmp(b.0));
+
+    if mine.is_empty() {
+        health.report(HealthStatus::Unhealthy, "container is missing");
+        return "missing".to_string();
+    }
+
+    let mut serving = 0;
+    let mut failures = Vec::new();
+    for (name, instance) in &mine {
+        match (instance.state.as_str(), instance.health.as_deref()) {
+            ("running", None | Some("healthy")) => serving += 1,
+            ("running", Some("starting")) => {
+                serving += 1;
+                health.report(
+                    HealthStatus::Degraded,
+                    format!("{} healthcheck is starting", name),
+                );
+            }
+            ("running", Some(status)) => failures.push(format!("{} is {}", name, status)),
+            // Idle suspension pauses or stops the first instance on purpose
+            (_, _) if suspended && *name == container => serving += 1,
+            (state, _) => failures.push(format!("{} is {}", name, state)),
+        }
+    }
+
+    if serving == 0 {
+        health.status = HealthStatus::Unhealthy;
+    } else if !failures.is_empty() {
+        health.status = health.status.max(HealthStatus::Degraded);
+    }
+    health.reasons.extend(failures);
+
+    if suspended && mine.len() == 1 {
+        "suspended".to_string()
+    } else if mine.len() == 1 {
+        mine[0].1.state.clone()
+    } else {
+        format!("{}/{} running", serving, mine.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(state: &str, health: Option<&str>) -> InstanceState {
+        InstanceState {
+            state: state.to_string(),
+            health: health.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn rolls_instances_and_projects_up_without_failing_the_node() {
+        let instances = HashMap::from([
+            ("shop_web".to_string(), instance("running", Some("healthy"))),
+            ("shop_web_2".to_string(), instance("exited", None)),
+            ("shop_web_old".to_string(), instance("exited", None)),
+            ("shop_db".to_string(), instance("paused", None)),
+        ]);
+
+        let mut web = ComponentHealth::default();
+        let state = oci_service_state("shop_web", &instances, false, &mut web);
+        assert_eq!(state, "1/2 running");
+        assert_eq!(web.status, HealthStatus::Degraded);
+        assert_eq!(web.reasons, vec!["shop_web_2 is exited".to_string()]);
+
+        let mut db = ComponentHealth::default();
+        oci_service_state("shop_db", &instances, false, &mut db);
+        assert_eq!(db.status, HealthStatus::Unhealthy);
+        let mut idle_db = ComponentHealth::default();
+        let state = oci_service_state("shop_db", &instances, true, &mut idle_db);
+        assert_eq!(
+            (state.as_str(), idle_db.status),
+            ("suspended", HealthStatus::Healthy)
+        );
+
+        let project = ProjectHealth {
+            project: "shop".to_string(),
+            status: HealthStatus::Unhealthy,
+            services: vec![ServiceHealth {
+                name: "db".to_string(),
+                container: "shop_db".to_string(),
+                status: HealthStatus::Unhealthy,
+                state: "paused".to_string(),
+                reasons: db.reasons,
+            }],
+        };
+        let node = NodeHealth::rollup(
+            ComponentHealth::default(),
+            ComponentHealth::default(),
+            ComponentHealth::default(),
+            vec![project],
+        );
+        assert_eq!(node.status, HealthStatus::Degraded);
+        assert_eq!(node.reasons, vec!["shop/db: shop_db is paused".to_string()]);
+    }
+}
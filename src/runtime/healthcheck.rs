@@ -0,0 +1,497 @@
+// Service healthchecks
+//
+// A service's `[healthcheck]` becomes the runtime's own healthcheck
+// (`--health-cmd` and friends): podman or docker run the command inside the
+// container every `interval`, and `inspect` reports the container as
+// starting, healthy, or unhealthy once `retries` checks in a row failed.
+// Failures during `start_period` don't count. Surge builds on that:
+//
+// - a service whose `depends_on` says `condition = "service_healthy"` starts
+//   only once its dependency's check passes
+// - `surge status` shows the health of each service
+// - Bolt restarts a container that exits according to `restart` (see
+//   supervisor.rs), but that doesn't cover one that is merely unhealthy.
+//   `bolt health-watch`, started by surge for detached services with both a
+//   healthcheck and a restart policy other than `no`, does, and counts the
+//   restarts in `<data_dir>/health/<container>.json`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::events::{ContainerEvent, EventLog};
+use super::watcher::{self, Watcher};
+use crate::builds::cache::parse_duration;
+use crate::config::{HealthcheckConfig, RestartPolicy};
+
+const WAIT_POLL: Duration = Duration::from_secs(1);
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+/// Seconds an unhealthy container gets to stop before it's killed
+const RESTART_STOP_TIMEOUT: u64 = 10;
+/// Extra time to wait for a dependency beyond its checks' own timing
+const WAIT_SLACK: Duration = Duration::from_secs(30);
+
+/// Health the runtime reports for a container with a healthcheck
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl Health {
+    fn parse(status: &str) -> Option<Self> {
+        match status {
+            "starting" => Some(Health::Starting),
+            "healthy" => Some(Health::Healthy),
+            "unhealthy" => Some(Health::Unhealthy),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Health::Starting => "starting",
+            Health::Healthy => "healthy",
+            Health::Unhealthy => "unhealthy",
+        })
+    }
+}
+
+/// A `[healthcheck]` in the runtime's terms
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthcheckSpec {
+    /// Shell command to run, None when the check is disabled
+    pub command: Option<String>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub start_period: Duration,
+}
+
+impl HealthcheckSpec {
+    pub fn from_config(config: &HealthcheckConfig) -> Result<Self> {
+        let duration = |value: &Option<String>, default: u64, key: &str| match value {
+            Some(value) => {
+                parse_duration(value).with_context(|| format!("Invalid healthcheck {}", key))
+            }
+            None => Ok(Duration::from_secs(default)),
+        };
+        let interval = duration(&config.interval, 30, "interval")?;
+        let timeout = duration(&config.timeout, 30, "timeout")?;
+        if interval.is_zero() || timeout.is_zero() {
+            return Err(anyhow!(
+                "healthcheck interval and timeout must be at least 1s"
+            ));
+        }
+        let retries = config.retries.unwrap_or(3);
+        if retries == 0 {
+            return Err(anyhow!("healthcheck retries must be at least 1"));
+        }
+
+        let command = match config.test.split_first() {
+            _ if config.disable => None,
+            Some((kind, _)) if kind == "NONE" => None,
+            Some((kind, argv)) if kind == "CMD" => Some(shell_join(argv)),
+            Some((kind, script)) if kind == "CMD-SHELL" => Some(script.join(" ")),
+            Some(_) => Some(shell_join(&config.test)),
+            None => None,
+        };
+        if command.as_ref().is_some_and(|c| c.trim().is_empty())
+            || (command.is_none() && !config.disable && config.test.is_empty())
+        {
+            return Err(anyhow!("healthcheck needs a command to run"));
+        }
+
+        Ok(Self {
+            command,
+            interval,
+            timeout,
+            retries,
+            start_period: duration(&config.start_period, 0, "start_period")?,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.command.is_some()
+    }
+
+    /// Options for `podman run`/`docker run`
+    pub fn run_args(&self) -> Vec<String> {
+        let Some(ref command) = self.command else {
+            return vec!["--no-healthcheck".to_string()];
+        };
+        vec![
+            "--health-cmd".to_string(),
+            command.clone(),
+            "--health-interval".to_string(),
+            format!("{}s", self.interval.as_secs()),
+            "--health-timeout".to_string(),
+            format!("{}s", self.timeout.as_secs()),
+            "--health-retries".to_string(),
+            self.retries.to_string(),
+            "--health-start-period".to_string(),
+            format!("{}s", self.start_period.as_secs()),
+        ]
+    }
+
+    /// How long a container can take to become healthy before it's certain
+    /// something is wrong
+    pub fn start_timeout(&self) -> Duration {
+        self.start_period + (self.interval + self.timeout) * (self.retries + 1) + WAIT_SLACK
+    }
+}
+
+/// Quote an argv for `sh -c`
+fn shell_join(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c))
+            {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A service's `restart`: no, always, on-failure[:max-retries] or
+/// unless-stopped
+pub fn restart_policy(restart: &str) -> Result<RestartPolicy> {
+    Ok(super::supervisor::RestartSpec::parse(restart)?.policy)
+}
+
+/// A container's runtime state and, if it has a healthcheck, its health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerHealth {
+    pub state: String,
+    pub health: Option<Health>,
+}
+
+impl ContainerHealth {
+    pub fn running(&self) -> bool {
+        self.state == "running"
+    }
+
+    /// `running (healthy)`, `exited`, ...
+    pub fn describe(&self) -> String {
+        match self.health {
+            Some(health) if self.running() => format!("{} ({})", self.state, health),
+            _ => self.state.clone(),
+        }
+    }
+}
+
+/// None once the container doesn't exist
+pub async fn status(runtime: &str, container: &str) -> Option<ContainerHealth> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            "{{.State.Status}}|{{if .State.Health}}{{.State.Health.Status}}{{end}}",
+            container,
+        ])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (state, health) = stdout.trim().split_once('|')?;
+    Some(ContainerHealth {
+        state: state.to_string(),
+        health: Health::parse(health),
+    })
+}
+
+/// Wait until a container's healthcheck passes, failing as soon as it's
+/// unhealthy or no longer running
+pub async fn wait_healthy(
+    runtime: &str,
+    container: &str,
+    spec: &HealthcheckSpec,
+) -> Result<Duration> {
+    info!("⏳ Waiting for {} to become healthy", container);
+    let started = Instant::now();
+    let deadline = spec.start_timeout();
+    loop {
+        let current = status(runtime, container)
+            .await
+            .ok_or_else(|| anyhow!("{} does not exist", container))?;
+        match current.health {
+            _ if !current.running() && current.state != "created" => {
+                return Err(anyhow!("{} is {}", container, current.state));
+            }
+            Some(Health::Healthy) => {
+                let elapsed = started.elapsed();
+                info!(
+                    "💚 {} healthy after {:.1}s",
+                    container,
+                    elapsed.as_secs_f64()
+                );
+                return Ok(elapsed);
+            }
+            Some(Health::Unhealthy) => {
+                return Err(anyhow!("{} is unhealthy", container));
+            }
+            None if current.running() => {
+                return Err(anyhow!(
+                    "{} has no healthcheck; recreate it with `bolt surge up --force-recreate`",
+                    container
+                ));
+            }
+            _ => {}
+        }
+        if started.elapsed() >= deadline {
+            return Err(anyhow!(
+                "{} was not healthy within {}s; podman runs healthchecks from systemd timers, check that they run",
+                container,
+                deadline.as_secs()
+            ));
+        }
+        tokio::time::sleep(WAIT_POLL).await;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthWatchState {
+    pub container: String,
+    pub watcher_pid: Option<u32>,
+    /// Last health seen
+    pub health: Option<Health>,
+    /// Restarts because the container was unhealthy
+    pub restarts: u64,
+    pub last_restart: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const WATCHER: Watcher = Watcher::new("health-watch");
+
+fn health_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("health")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    health_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    health_dir(data_dir).join(format!("{}.log", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<HealthWatchState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+fn save(data_dir: &Path, state: &HealthWatchState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Save the watcher's changes unless `surge down` removed the record meanwhile
+fn update(data_dir: &Path, state: &HealthWatchState) -> Result<()> {
+    watcher::update(&state_path(data_dir, &state.container), state)
+}
+
+/// Start restarting a running container whenever it turns unhealthy
+pub fn start(data_dir: &Path, container: &str) -> Result<()> {
+    forget(data_dir, container);
+    let mut state = HealthWatchState {
+        container: container.to_string(),
+        watcher_pid: None,
+        health: None,
+        restarts: 0,
+        last_restart: None,
+        updated_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["health-watch", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    debug!("Restarting {} when it turns unhealthy", container);
+    Ok(())
+}
+
+/// Stop watching a container's health and drop its record
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Restart the container each time its healthcheck fails it. Run by `bolt
+/// health-watch`.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    let runtime = super::detect_container_runtime().await?;
+    let Some(mut state) = state(data_dir, container) else {
+        debug!("No health watch for {}, stopping", container);
+        return Ok(());
+    };
+    let events = EventLog::new(data_dir.to_path_buf())?;
+
+    loop {
+        if !state_path(data_dir, container).exists() {
+            debug!("Health watch of {} removed, stopping", container);
+            return Ok(());
+        }
+        let Some(current) = status(&runtime, container).await else {
+            info!("{} no longer exists, no longer watching it", container);
+            return Ok(());
+        };
+        // A stopped container is the runtime's restart policy's business
+        let health = current.health.filter(|_| current.running());
+        if health != state.health {
+            state.health = health;
+            state.updated_at = Utc::now();
+            update(data_dir, &state)?;
+        }
+
+        if health == Some(Health::Unhealthy) {
+            warn!("💔 {} is unhealthy, restarting it", container);
+            let event = ContainerEvent::new(container, "health_restart")
+                .with_attribute("restarts", (state.restarts + 1).to_string());
+            if let Err(e) = events.record(&event) {
+                debug!("Could not record the restart of {}: {}", container, e);
+            }
+            match super::restart_container(container, RESTART_STOP_TIMEOUT).await {
+                Ok(()) => {
+                    // A new network namespace comes without the egress rules
+                    crate::networking::egress::restore(&runtime, data_dir, container).await;
+                    state.restarts += 1;
+                    state.last_restart = Some(Utc::now());
+                    state.health = None;
+                    state.updated_at = Utc::now();
+                    update(data_dir, &state)?;
+                }
+                Err(e) => warn!("Could not restart {}: {}", container, e),
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(test: &[&str]) -> HealthcheckConfig {
+        HealthcheckConfig {
+            test: test.iter().map(|s| s.to_string()).collect(),
+            interval: Some("10s".to_string()),
+            timeout: None,
+            retries: Some(2),
+            start_period: Some("1m".to_string()),
+            disable: false,
+        }
+    }
+
+    #[test]
+    fn translates_healthchecks_and_restart_policies() {
+        let spec = HealthcheckSpec::from_config(&config(&[
+            "CMD",
+            "curl",
+            "-f",
+            "http://localhost/it's ok",
+        ]))
+        .unwrap();
+        assert_eq!(
+            spec.command.as_deref(),
+            Some(r"curl -f 'http://localhost/it'\''s ok'")
+        );
+        assert_eq!(
+            spec.run_args(),
+            [
+                "--health-cmd",
+                r"curl -f 'http://localhost/it'\''s ok'",
+                "--health-interval",
+                "10s",
+                "--health-timeout",
+                "30s",
+                "--health-retries",
+                "2",
+                "--health-start-period",
+                "60s",
+            ]
+        );
+        assert_eq!(spec.start_timeout(), Duration::from_secs(60 + 40 * 3 + 30));
+
+        let shell =
+            HealthcheckSpec::from_config(&config(&["CMD-SHELL", "pg_isready || exit 1"])).unwrap();
+        assert_eq!(shell.command.as_deref(), Some("pg_isready || exit 1"));
+        let plain = HealthcheckSpec::from_config(&config(&["redis-cli", "ping"])).unwrap();
+        assert_eq!(plain.command.as_deref(), Some("redis-cli ping"));
+
+        let none = HealthcheckSpec::from_config(&config(&["NONE"])).unwrap();
+        assert!(!none.enabled());
+        assert_eq!(none.run_args(), ["--no-healthcheck"]);
+        let disabled = HealthcheckConfig {
+            disable: true,
+            ..config(&["CMD", "true"])
+        };
+        assert!(!HealthcheckSpec::from_config(&disabled).unwrap().enabled());
+
+        assert!(HealthcheckSpec::from_config(&config(&[])).is_err());
+        assert!(HealthcheckSpec::from_config(&config(&["CMD-SHELL", " "])).is_err());
+        let zero = HealthcheckConfig {
+            interval: Some("0s".to_string()),
+            ..config(&["true"])
+        };
+        assert!(HealthcheckSpec::from_config(&zero).is_err());
+
+        assert_eq!(restart_policy("always").unwrap(), RestartPolicy::Always);
+        assert_eq!(
+            restart_policy("on-failure:3").unwrap(),
+            RestartPolicy::OnFailure
+        );
+        assert!(restart_policy("sometimes").is_err());
+        assert!(restart_policy("always:3").is_err());
+        assert!(restart_policy("on-failure:x").is_err());
+
+        let running = ContainerHealth {
+            state: "running".to_string(),
+            health: Some(Health::Unhealthy),
+        };
+        assert_eq!(running.describe(), "running (unhealthy)");
+        let exited = ContainerHealth {
+            state: "exited".to_string(),
+            health: Some(Health::Unhealthy),
+        };
+        assert_eq!(exited.describe(), "exited");
+    }
+
+    #[test]
+    fn depends_on_waits_only_for_services_with_a_healthcheck() {
+        use crate::config::{BoltFile, DependencyCondition};
+
+        let boltfile = |db: &str| -> BoltFile {
+            toml::from_str(&format!(
+                "project = \"shop\"\n\n[services.web]\nimage = \"nginx\"\n\n\
+                 [services.web.depends_on.db]\ncondition = \"service_healthy\"\n\n\
+                 [services.web.depends_on.cache]\n\n\
+                 [services.cache]\nimage = \"redis\"\n\n\
+                 [services.db]\nimage = \"postgres\"\nrestart = \"on-failure:3\"\n{}",
+                db
+            ))
+            .unwrap()
+        };
+        let checked =
+            boltfile("\n[services.db.healthcheck]\ncommand = [\"CMD-SHELL\", \"pg_isready\"]\n");
+        checked.validate().unwrap();
+        let deps = checked.services["web"].depends_on.as_ref().unwrap();
+        assert_eq!(deps.names().collect::<Vec<_>>(), ["cache", "db"]);
+        assert_eq!(deps.condition("db"), DependencyCondition::ServiceHealthy);
+        assert_eq!(deps.condition("cache"), DependencyCondition::ServiceStarted);
+
+        assert!(boltfile("").validate().is_err());
+    }
+}
@@ -0,0 +1,262 @@
+// Container identity
+//
+// Containers otherwise get a random hostname and whatever machine-id the
+// image ships with, both of which change when a container is recreated.
+// Game launchers that tie logins to the machine and systemd-based images
+// need them to stay put, so a container gets:
+//
+// - the service's `hostname` (default: the container name) and `domainname`
+//   in its own UTS namespace, unless `uts = "host"` shares the host's
+// - a machine-id generated on first start and kept under
+//   `<data_dir>/identity/<container>/machine-id`, mounted read-only at
+//   `/etc/machine-id`; `machine_id = "none"` keeps the image's and a 32-digit
+//   hex value pins it
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::config::Service;
+
+/// Longest hostname the kernel accepts (`HOST_NAME_MAX`)
+const HOST_NAME_MAX: usize = 64;
+
+/// Identity settings as written in a Boltfile or container config
+#[derive(Debug, Clone, Default)]
+pub struct IdentitySpec {
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
+    pub uts: Option<String>,
+    pub machine_id: Option<String>,
+    /// The container uses the host's network stack, where Docker and Podman
+    /// default to the host's hostname
+    pub host_network: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MachineId {
+    Generated,
+    Image,
+    Fixed(String),
+}
+
+/// What a container is started with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerIdentity {
+    pub hostname: Option<String>,
+    pub domainname: Option<String>,
+    pub share_host_uts: bool,
+    /// Host file to mount at `/etc/machine-id`
+    pub machine_id_file: Option<PathBuf>,
+}
+
+impl IdentitySpec {
+    pub fn from_service(service: &Service) -> Self {
+        Self {
+            hostname: service.hostname.clone(),
+            domainname: service.domainname.clone(),
+            uts: service.uts.clone(),
+            machine_id: service.machine_id.clone(),
+            host_network: service.network_mode.as_deref() == Some("host"),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.share_host_uts()?;
+        self.machine_id()?;
+        if let Some(ref hostname) = self.hostname {
+            validate_name("hostname", hostname)?;
+        }
+        if let Some(ref domainname) = self.domainname {
+            validate_name("domainname", domainname)?;
+        }
+        if self.share_host_uts()? && (self.hostname.is_some() || self.domainname.is_some()) {
+            return Err(anyhow!(
+                "hostname and domainname cannot be set with uts = \"host\""
+            ));
+        }
+        Ok(())
+    }
+
+    fn share_host_uts(&self) -> Result<bool> {
+        match self.uts.as_deref() {
+            None | Some("private") => Ok(false),
+            Some("host") => Ok(true),
+            Some(other) => Err(anyhow!(
+                "Invalid uts '{}': use \"private\" or \"host\"",
+                other
+            )),
+        }
+    }
+
+    fn machine_id(&self) -> Result<MachineId> {
+        match self.machine_id.as_deref() {
+            None | Some("auto") => Ok(MachineId::Generated),
+            Some("none") => Ok(MachineId::Image),
+            Some(id) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Ok(MachineId::Fixed(id.to_ascii_lowercase()))
+            }
+            Some(other) => Err(anyhow!(
+                "Invalid machine_id '{}': use \"auto\", \"none\" or 32 hex digits",
+                other
+            )),
+        }
+    }
+
+    /// Resolve the identity of `container`, creating its machine-id on first use
+    pub fn resolve(&self, data_dir: &Path, container: &str) -> Result<ContainerIdentity> {
+        self.validate()?;
+        let share_host_uts = self.share_host_uts()?;
+
+        let hostname = match self.hostname {
+            Some(ref hostname) => Some(hostname.clone()),
+            None if share_host_uts || self.host_network => None,
+            None => Some(default_hostname(container)),
+        };
+
+        let machine_id_file = match self.machine_id()? {
+            MachineId::Image => None,
+            MachineId::Generated => Some(persist_machine_id(data_dir, container, None)?),
+            MachineId::Fixed(id) => Some(persist_machine_id(data_dir, container, Some(&id))?),
+        };
+
+        Ok(ContainerIdentity {
+            hostname,
+            domainname: self.domainname.clone(),
+            share_host_uts,
+            machine_id_file,
+        })
+    }
+}
+
+impl ContainerIdentity {
+    /// `docker`/`podman run` arguments
+    pub fn run_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.share_host_uts {
+            args.push("--uts=host".to_string());
+        }
+        if let Some(ref hostname) = self.hostname {
+            args.push(format!("--hostname={}", hostname));
+        }
+        if let Some(ref domainname) = self.domainname {
+            args.push(format!("--domainname={}", domainname));
+        }
+        if let Some(ref file) = self.machine_id_file {
+            args.push("-v".to_string());
+            args.push(format!("{}:/etc/machine-id:ro", file.display()));
+        }
+        args
+    }
+}
+
+fn identity_dir(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("identity").join(container)
+}
+
+/// Write (or keep) the container's machine-id file and return its path
+fn persist_machine_id(data_dir: &Path, container: &str, fixed: Option<&str>) -> Result<PathBuf> {
+    let dir = identity_dir(data_dir, container);
+    let path = dir.join("machine-id");
+    let current = std::fs::read_to_string(&path)
+        .ok()
+        .map(|id| id.trim().to_string());
+
+    let id = match (fixed, current) {
+        (Some(fixed), Some(current)) if fixed == current => return Ok(path),
+        (Some(fixed), _) => fixed.to_string(),
+        (None, Some(current)) if !current.is_empty() => return Ok(path),
+        (None, _) => uuid::Uuid::new_v4().simple().to_string(),
+    };
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    std::fs::write(&path, format!("{}\n", id))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    debug!("machine-id of {} is {}", container, id);
+    Ok(path)
+}
+
+/// Forget a container's machine-id, e.g. when its volumes are removed
+pub fn remove(data_dir: &Path, container: &str) {
+    let _ = std::fs::remove_dir_all(identity_dir(data_dir, container));
+}
+
+/// A valid hostname derived from the container name: `myapp_web_2` -> `myapp-web-2`
+pub fn default_hostname(container: &str) -> String {
+    let name: String = container
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(HOST_NAME_MAX - 1)
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        "bolt".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn validate_name(field: &str, name: &str) -> Result<()> {
+    let labels_valid = name.split('.').all(|label| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    if name.len() < HOST_NAME_MAX && labels_valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid {} '{}': use up to {} letters, digits, '-' and '.'",
+            field,
+            name,
+            HOST_NAME_MAX - 1
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_id_persists_and_hostname_defaults_to_container() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = IdentitySpec {
+            domainname: Some("lan".to_string()),
+            ..Default::default()
+        };
+
+        let first = spec.resolve(dir.path(), "myapp_web_1").unwrap();
+        assert_eq!(first.hostname.as_deref(), Some("myapp-web-1"));
+        let id = std::fs::read_to_string(first.machine_id_file.as_ref().unwrap()).unwrap();
+        assert_eq!(id.trim().len(), 32);
+
+        let again = spec.resolve(dir.path(), "myapp_web_1").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(again.machine_id_file.unwrap()).unwrap(),
+            id
+        );
+        assert!(first.run_args().contains(&"--domainname=lan".to_string()));
+
+        let host = IdentitySpec {
+            uts: Some("host".to_string()),
+            machine_id: Some("none".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            host.resolve(dir.path(), "myapp_web_1").unwrap().run_args(),
+            vec!["--uts=host"]
+        );
+
+        let invalid = IdentitySpec {
+            hostname: Some("bad_name".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = IdentitySpec {
+            machine_id: Some("1234".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,721 @@
+// Idle suspension
+//
+// Services with an `[idle]` table are suspended while nobody uses them: no
+// network traffic and CPU below `cpu_percent` for `after`. The `action` says
+// how:
+//
+// - `pause`: freeze the container's processes; memory stays allocated
+// - `checkpoint`: save the processes to disk with CRIU and stop (podman only)
+// - `scale-to-zero`: stop the container; it starts from scratch on wake
+//
+// With `wake` (the default) the service resumes on the next connection. A
+// paused container keeps its ports and the kernel still queues connections
+// for it, so new connections in its network namespace wake it. A stopped
+// container releases its ports: bolt listens on the published ports instead,
+// and on the first connection closes them, resumes the container and hands
+// that connection over. A UDP datagram wakes the service too but is dropped;
+// clients resend. `bolt surge idle wake` resumes a service by hand.
+//
+// `bolt idle-watch`, started by surge for each container, does all of this
+// and counts suspensions, wakes and time spent suspended in
+// `<data_dir>/idle/<container>.json`.
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::process::Command as AsyncCommand;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use super::watcher::{self, Watcher};
+use crate::builds::cache::parse_duration;
+use crate::config::IdleConfig;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+const WAKE_POLL: Duration = Duration::from_secs(1);
+/// How long a handed over connection waits for the resumed service to listen
+const HAND_OVER_TIMEOUT: Duration = Duration::from_secs(120);
+/// Traffic per sample that still counts as idle: ARP, neighbour discovery and
+/// the like
+const BACKGROUND_BYTES: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdleAction {
+    #[default]
+    Pause,
+    Checkpoint,
+    ScaleToZero,
+}
+
+impl IdleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdleAction::Pause => "pause",
+            IdleAction::Checkpoint => "checkpoint",
+            IdleAction::ScaleToZero => "scale-to-zero",
+        }
+    }
+}
+
+/// A host port the service publishes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedPort {
+    pub address: IpAddr,
+    pub port: u16,
+    pub udp: bool,
+}
+
+impl PublishedPort {
+    /// `[ip:]host:container[/proto]`; None for ports without a fixed host
+    /// port and for ranges
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (spec, udp) = match spec.rsplit_once('/') {
+            Some((spec, proto)) => (spec, proto.eq_ignore_ascii_case("udp")),
+            None => (spec, false),
+        };
+        let (address, rest) = match spec.strip_prefix('[') {
+            Some(v6) => {
+                let (address, rest) = v6.split_once("]:")?;
+                (address.parse().ok()?, rest)
+            }
+            None => {
+                let parts: Vec<&str> = spec.split(':').collect();
+                match parts.len() {
+                    2 => (IpAddr::V4(Ipv4Addr::UNSPECIFIED), spec),
+                    3 => (parts[0].parse().ok()?, &spec[parts[0].len() + 1..]),
+                    _ => return None,
+                }
+            }
+        };
+        let (host, _) = rest.split_once(':')?;
+        Some(Self {
+            address,
+            port: host.parse().ok()?,
+            udp,
+        })
+    }
+
+    fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// Where to reach the service once it's back
+    pub fn local_addr(&self) -> SocketAddr {
+        let address = if self.address.is_unspecified() {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            self.address
+        };
+        SocketAddr::new(address, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdlePolicy {
+    pub after_secs: u64,
+    /// Percent of one core
+    pub cpu_percent: f64,
+    pub action: IdleAction,
+    pub wake: bool,
+    pub ports: Vec<PublishedPort>,
+}
+
+impl IdlePolicy {
+    pub fn from_config(config: &IdleConfig, ports: &[String]) -> Result<Self> {
+        let after = parse_duration(&config.after)?;
+        if after < SAMPLE_INTERVAL {
+            return Err(anyhow!(
+                "idle.after must be at least {}s",
+                SAMPLE_INTERVAL.as_secs()
+            ));
+        }
+        let cpu_percent = config.cpu_percent.unwrap_or(2.0);
+        if !(cpu_percent > 0.0 && cpu_percent.is_finite()) {
+            return Err(anyhow!("idle.cpu_percent must be above 0"));
+        }
+        Ok(Self {
+            after_secs: after.as_secs(),
+            cpu_percent,
+            action: config.action,
+            wake: config.wake.unwrap_or(true),
+            ports: ports
+                .iter()
+                .filter_map(|p| PublishedPort::parse(p))
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleStatus {
+    Active,
+    Suspended,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdleState {
+    pub container: String,
+    pub policy: IdlePolicy,
+    pub watcher_pid: Option<u32>,
+    pub status: IdleStatus,
+    /// When the container became active or was suspended
+    pub since: DateTime<Utc>,
+    pub suspensions: u64,
+    pub wakes: u64,
+    /// Time spent suspended, not counting the current suspension
+    pub suspended_seconds: u64,
+    /// From the wake-up to the resumed container, in milliseconds
+    pub last_wake_ms: Option<u64>,
+}
+
+impl IdleState {
+    /// Time spent suspended, including the current suspension
+    pub fn suspended_total(&self) -> u64 {
+        let current = match self.status {
+            IdleStatus::Suspended => (Utc::now() - self.since).num_seconds().max(0) as u64,
+            IdleStatus::Active => 0,
+        };
+        self.suspended_seconds + current
+    }
+
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+const WATCHER: Watcher = Watcher::new("idle-watch");
+
+fn idle_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("idle")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    idle_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    idle_dir(data_dir).join(format!("{}.log", container))
+}
+
+fn wake_path(data_dir: &Path, container: &str) -> PathBuf {
+    idle_dir(data_dir).join(format!("{}.wake", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<IdleState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every container with an idle policy, by name
+pub fn list(data_dir: &Path) -> Vec<IdleState> {
+    let mut states: Vec<IdleState> = watcher::list(&idle_dir(data_dir));
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &IdleState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Save the watcher's changes unless `surge down` removed the record meanwhile
+fn update(data_dir: &Path, state: &IdleState) -> Result<()> {
+    watcher::update(&state_path(data_dir, &state.container), state)
+}
+
+/// Start watching a running container for idleness
+pub fn start(data_dir: &Path, container: &str, policy: &IdlePolicy) -> Result<()> {
+    forget(data_dir, container);
+    let mut state = IdleState {
+        container: container.to_string(),
+        policy: policy.clone(),
+        watcher_pid: None,
+        status: IdleStatus::Active,
+        since: Utc::now(),
+        suspensions: 0,
+        wakes: 0,
+        suspended_seconds: 0,
+        last_wake_ms: None,
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["idle-watch", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    info!(
+        "💤 {} will {} after {}s idle",
+        container,
+        policy.action.as_str(),
+        policy.after_secs
+    );
+    Ok(())
+}
+
+/// Stop watching a container and drop its record
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+    let _ = std::fs::remove_file(wake_path(data_dir, container));
+}
+
+/// Ask the watcher to resume a suspended container; false if it isn't suspended
+pub fn request_wake(data_dir: &Path, container: &str) -> Result<bool> {
+    let state =
+        state(data_dir, container).ok_or_else(|| anyhow!("{} has no idle policy", container))?;
+    if state.status != IdleStatus::Suspended {
+        return Ok(false);
+    }
+    if !state.watching() {
+        return Err(anyhow!(
+            "Nothing is watching {}; resume it with `bolt surge up`",
+            container
+        ));
+    }
+    std::fs::write(wake_path(data_dir, container), b"")?;
+    Ok(true)
+}
+
+/// Suspend the container whenever it's idle and resume it on demand. Run by
+/// `bolt idle-watch`.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    let runtime = super::detect_container_runtime().await?;
+    let mut last: Option<Sample> = None;
+    let mut idle_since: Option<Instant> = None;
+    loop {
+        let Some(state) = state(data_dir, container) else {
+            debug!("No idle policy for {}, stopping", container);
+            return Ok(());
+        };
+        if state.status == IdleStatus::Suspended {
+            resume_on_demand(&runtime, data_dir, state).await?;
+            last = None;
+            idle_since = None;
+            continue;
+        }
+
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let Some(sample) = Sample::take(&runtime, container).await else {
+            info!("{} is not running, no longer watching it", container);
+            return Ok(());
+        };
+        match last {
+            Some(ref last) if !last.busy(&sample, state.policy.cpu_percent) => {
+                // An idle sample covers the interval before it
+                idle_since.get_or_insert(last.at);
+            }
+            _ => idle_since = None,
+        }
+        last = Some(sample);
+        if idle_since.is_some_and(|since| since.elapsed().as_secs() >= state.policy.after_secs) {
+            if let Err(e) = suspend(&runtime, data_dir, state).await {
+                warn!("Could not suspend {}: {}", container, e);
+            }
+            idle_since = None;
+        }
+    }
+}
+
+async fn suspend(runtime: &str, data_dir: &Path, mut state: IdleState) -> Result<()> {
+    let container = state.container.clone();
+    match state.policy.action {
+        IdleAction::Pause => run(runtime, &["pause", &container]).await?,
+        IdleAction::Checkpoint if runtime != "podman" => {
+            return Err(anyhow!("idle.action = \"checkpoint\" needs podman"));
+        }
+        IdleAction::Checkpoint => run(runtime, &["container", "checkpoint", &container]).await?,
+        IdleAction::ScaleToZero => run(runtime, &["stop", &container]).await?,
+    }
+    state.status = IdleStatus::Suspended;
+    state.since = Utc::now();
+    state.suspensions += 1;
+    update(data_dir, &state)?;
+    info!(
+        "💤 {} idle for {}s: {}",
+        container,
+        state.policy.after_secs,
+        state.policy.action.as_str()
+    );
+    Ok(())
+}
+
+async fn resume_on_demand(runtime: &str, data_dir: &Path, mut state: IdleState) -> Result<()> {
+    let container = state.container.clone();
+    let held = match state.policy.action {
+        IdleAction::Pause => {
+            wait_for_traffic(runtime, data_dir, &state).await;
+            None
+        }
+        IdleAction::Checkpoint | IdleAction::ScaleToZero => {
+            wait_for_connection(data_dir, &state).await
+        }
+    };
+
+    let started = Instant::now();
+    match state.policy.action {
+        IdleAction::Pause => run(runtime, &["unpause", &container]).await?,
+        IdleAction::Checkpoint => run(runtime, &["container", "restore", &container]).await?,
+        IdleAction::ScaleToZero => run(runtime, &["start", &container]).await?,
+    }
+    if state.policy.action != IdleAction::Pause {
+        // A new network namespace comes without the egress rules
+        crate::networking::egress::restore(runtime, data_dir, &container).await;
+    }
+
+    state.suspended_seconds = state.suspended_total();
+    state.status = IdleStatus::Active;
+    state.since = Utc::now();
+    state.wakes += 1;
+    state.last_wake_ms = Some(started.elapsed().as_millis() as u64);
+    update(data_dir, &state)?;
+    info!(
+        "⏰ {} resumed in {}ms",
+        container,
+        started.elapsed().as_millis()
+    );
+
+    if let Some((stream, port)) = held {
+        tokio::spawn(hand_over(stream, port));
+    }
+    Ok(())
+}
+
+/// Wait until a paused container gets a connection or a datagram, or a wake
+/// is requested
+async fn wait_for_traffic(runtime: &str, data_dir: &Path, state: &IdleState) {
+    let pid = container_pid(runtime, &state.container).await;
+    let baseline = pid.map(connections).unwrap_or_default();
+    loop {
+        tokio::time::sleep(WAKE_POLL).await;
+        if take_wake_request(data_dir, &state.container) {
+            return;
+        }
+        let Some(pid) = pid.filter(|_| state.policy.wake) else {
+            continue;
+        };
+        if !connections(pid).is_subset(&baseline) || udp_queued(pid) {
+            info!("📨 Traffic for {}, resuming", state.container);
+            return;
+        }
+    }
+}
+
+/// Listen on the published ports of a stopped container until a client
+/// connects or a wake is requested. Returns the TCP connection to hand over.
+async fn wait_for_connection(
+    data_dir: &Path,
+    state: &IdleState,
+) -> Option<(TcpStream, PublishedPort)> {
+    let (tx, mut rx) = mpsc::channel(1);
+    let mut listeners = Vec::new();
+    if state.policy.wake {
+        for port in &state.policy.ports {
+            let tx = tx.clone();
+            let port = port.clone();
+            if port.udp {
+                match UdpSocket::bind(port.socket_addr()).await {
+                    Ok(socket) => listeners.push(tokio::spawn(async move {
+                        let mut buf = [0u8; 1];
+                        if socket.recv_from(&mut buf).await.is_ok() {
+                            let _ = tx.send(None).await;
+                        }
+                    })),
+                    Err(e) => warn!("Cannot listen on udp {}: {}", port.socket_addr(), e),
+                }
+            } else {
+                match TcpListener::bind(port.socket_addr()).await {
+                    Ok(listener) => listeners.push(tokio::spawn(async move {
+                        if let Ok((stream, _)) = listener.accept().await {
+                            let _ = tx.send(Some((stream, port))).await;
+                        }
+                    })),
+                    Err(e) => warn!("Cannot listen on tcp {}: {}", port.socket_addr(), e),
+                }
+            }
+        }
+    }
+    drop(tx);
+
+    let held = loop {
+        tokio::select! {
+            Some(held) = rx.recv() => {
+                info!("📨 Connection for {}, resuming", state.container);
+                break held;
+            }
+            _ = tokio::time::sleep(WAKE_POLL) => {
+                if take_wake_request(data_dir, &state.container) {
+                    break None;
+                }
+            }
+        }
+    };
+    // The container needs the ports back
+    for listener in listeners {
+        listener.abort();
+        let _ = listener.await;
+    }
+    held
+}
+
+/// Pass a connection accepted while the service was stopped on to it
+async fn hand_over(mut client: TcpStream, port: PublishedPort) {
+    let deadline = Instant::now() + HAND_OVER_TIMEOUT;
+    let mut upstream = loop {
+        match TcpStream::connect(port.local_addr()).await {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => {
+                tokio::time::sleep(Duration::from_millis(250)).await
+            }
+            Err(e) => {
+                warn!("Resumed service never listened on {}: {}", port.port, e);
+                return;
+            }
+        }
+    };
+    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+}
+
+fn take_wake_request(data_dir: &Path, container: &str) -> bool {
+    std::fs::remove_file(wake_path(data_dir, container)).is_ok()
+}
+
+async fn run(runtime: &str, args: &[&str]) -> Result<()> {
+    let output = AsyncCommand::new(runtime).args(args).output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            runtime,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+async fn container_pid(runtime: &str, container: &str) -> Option<u32> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Pid}}", container])
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| *pid > 0)
+}
+
+/// Network and CPU counters of a running container
+struct Sample {
+    pid: u32,
+    at: Instant,
+    network_bytes: u64,
+    cpu_usec: Option<u64>,
+}
+
+impl Sample {
+    async fn take(runtime: &str, container: &str) -> Option<Self> {
+        let pid = container_pid(runtime, container).await?;
+        let network_bytes = std::fs::read_to_string(format!("/proc/{}/net/dev", pid))
+            .ok()
+            .map(|dev| network_bytes(&dev))?;
+        Some(Self {
+            pid,
+            at: Instant::now(),
+            network_bytes,
+            cpu_usec: super::cgroups::cpu_usage_usec(pid),
+        })
+    }
+
+    fn busy(&self, now: &Sample, cpu_percent: f64) -> bool {
+        if self.pid != now.pid {
+            return true;
+        }
+        if now.network_bytes.saturating_sub(self.network_bytes) > BACKGROUND_BYTES {
+            return true;
+        }
+        let elapsed = now.at.duration_since(self.at).as_micros() as f64;
+        match (self.cpu_usec, now.cpu_usec) {
+            (Some(before), Some(after)) if elapsed > 0.0 => {
+                after.saturating_sub(before) as f64 / elapsed * 100.0 > cpu_percent
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Bytes received and sent on every interface but loopback, from /proc/<pid>/net/dev
+fn network_bytes(dev: &str) -> u64 {
+    dev.lines()
+        .skip(2)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim() != "lo")
+        .map(|(_, counters)| {
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            fields.first().copied().unwrap_or(0) + fields.get(8).copied().unwrap_or(0)
+        })
+        .sum()
+}
+
+/// Established and half-open TCP connections in the container's namespace.
+/// The kernel accepts them for a paused container's listening sockets.
+fn connections(pid: u32) -> HashSet<String> {
+    ["tcp", "tcp6"]
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(format!("/proc/{}/net/{}", pid, table)).ok())
+        .flat_map(|listing| parse_connections(&listing))
+        .collect()
+}
+
+fn parse_connections(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // 01 ESTABLISHED, 03 SYN_RECV
+            match fields.get(3) {
+                Some(&"01") | Some(&"03") => Some(format!("{} {}", fields[1], fields[2])),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether a UDP socket in the container's namespace has datagrams waiting
+fn udp_queued(pid: u32) -> bool {
+    ["udp", "udp6"].iter().any(|table| {
+        std::fs::read_to_string(format!("/proc/{}/net/{}", pid, table))
+            .is_ok_and(|listing| parse_udp_queued(&listing))
+    })
+}
+
+fn parse_udp_queued(listing: &str) -> bool {
+    listing.lines().skip(1).any(|line| {
+        line.split_whitespace()
+            .nth(4)
+            .and_then(|queues| queues.split_once(':'))
+            .and_then(|(_, rx)| u64::from_str_radix(rx, 16).ok())
+            .is_some_and(|rx| rx > 0)
+    })
+}
+
+/// Suspension counters in the Prometheus text format
+pub fn prometheus(states: &[IdleState]) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(&IdleState) -> u64| {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for state in states {
+            out.push_str(&format!(
+                "{}{{container=\"{}\",action=\"{}\"}} {}\n",
+                name,
+                state.container,
+                state.policy.action.as_str(),
+                value(state)
+            ));
+        }
+    };
+    metric(
+        "bolt_idle_suspended",
+        "gauge",
+        "Whether the container is suspended for being idle",
+        &|s| (s.status == IdleStatus::Suspended) as u64,
+    );
+    metric(
+        "bolt_idle_suspensions_total",
+        "counter",
+        "Times the container was suspended for being idle",
+        &|s| s.suspensions,
+    );
+    metric(
+        "bolt_idle_wakes_total",
+        "counter",
+        "Times the container was resumed",
+        &|s| s.wakes,
+    );
+    metric(
+        "bolt_idle_suspended_seconds_total",
+        "counter",
+        "Time the container spent suspended",
+        &|s| s.suspended_total(),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ports_and_spots_idle_and_incoming_traffic() {
+        assert_eq!(
+            PublishedPort::parse("25565:25565"),
+            Some(PublishedPort {
+                address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                port: 25565,
+                udp: false,
+            })
+        );
+        let udp = PublishedPort::parse("127.0.0.1:27015:27015/udp").unwrap();
+        assert_eq!(udp.address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert!(udp.udp);
+        assert_eq!(PublishedPort::parse("[::1]:8080:80").unwrap().port, 8080);
+        assert_eq!(PublishedPort::parse("80"), None);
+        assert_eq!(PublishedPort::parse("8000-8010:8000-8010"), None);
+
+        let dev = "Inter-|   Receive                                                |  Transmit\n \
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n    \
+lo:  500     5    0    0    0     0          0         0      500     5    0    0    0     0       0          0\n  \
+eth0: 1000    10    0    0    0     0          0         0      2000    20    0    0    0     0       0          0\n";
+        assert_eq!(network_bytes(dev), 3000);
+
+        let sample = |network_bytes, cpu_usec| Sample {
+            pid: 42,
+            at: Instant::now(),
+            network_bytes,
+            cpu_usec: Some(cpu_usec),
+        };
+        let before = Sample {
+            at: Instant::now() - Duration::from_secs(15),
+            ..sample(3000, 0)
+        };
+        assert!(!before.busy(&sample(3100, 100_000), 2.0));
+        assert!(before.busy(&sample(90_000, 100_000), 2.0));
+        assert!(before.busy(&sample(3100, 1_000_000), 2.0));
+
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+0: 00000000:63DD 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1 1 0000000000000000 100 0 0 10 0\n   \
+1: 0200000A:63DD 0100000A:D431 01 00000000:00000000 00:00000000 00000000  1000        0 2 1 0000000000000000 20 4 30 10 -1\n";
+        assert_eq!(
+            parse_connections(tcp),
+            vec!["0200000A:63DD 0100000A:D431".to_string()]
+        );
+        let udp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n  \
+1: 00000000:6987 00000000:0000 07 00000000:00000040 00:00000000 00000000  1000        0 3 2 0000000000000000 0\n";
+        assert!(parse_udp_queued(udp));
+
+        let config = IdleConfig {
+            after: "30m".to_string(),
+            cpu_percent: None,
+            action: IdleAction::ScaleToZero,
+            wake: None,
+        };
+        let policy = IdlePolicy::from_config(&config, &["25565:25565".to_string()]).unwrap();
+        assert_eq!(policy.after_secs, 1800);
+        assert!(policy.wake);
+        assert_eq!(policy.ports.len(), 1);
+        let config = IdleConfig {
+            after: "5s".to_string(),
+            ..config
+        };
+        assert!(IdlePolicy::from_config(&config, &[]).is_err());
+    }
+}
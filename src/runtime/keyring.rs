@@ -0,0 +1,321 @@
+// Container keyrings
+//
+// A process inherits its parent's session keyring, so a container started by
+// the native runtime would otherwise see, and could change, the keys in
+// Bolt's own session: the credentials of whoever started it, or what another
+// container's anti-cheat or DRM client stored there. Each container instead
+// joins a new anonymous session keyring right before its command runs.
+// An anonymous keyring has no name another process could join it by, and
+// only its possessors, the container's own processes, may search or read it.
+//
+// Keys a container needs from the host (`expose`) are copied into its keyring
+// rather than linked. The copy only grants view, read and search, so the
+// container can use the key but can't change it or its permissions, and
+// nothing it does reaches the host's key.
+//
+// runc and crun give every container a session keyring of its own already,
+// unless podman's containers.conf sets `keyring = false`; `bolt doctor`
+// reports that.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::path::PathBuf;
+use tracing::debug;
+
+const KEY_SPEC_SESSION_KEYRING: libc::c_long = -3;
+const KEY_SPEC_USER_KEYRING: libc::c_long = -4;
+
+const KEYCTL_GET_KEYRING_ID: libc::c_long = 0;
+const KEYCTL_JOIN_SESSION_KEYRING: libc::c_long = 1;
+const KEYCTL_SETPERM: libc::c_long = 5;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+
+const KEY_POS_VIEW: u32 = 0x0100_0000;
+const KEY_POS_READ: u32 = 0x0200_0000;
+const KEY_POS_SEARCH: u32 = 0x0800_0000;
+const KEY_POS_ALL: u32 = 0x3f00_0000;
+const KEY_USR_VIEW: u32 = 0x0001_0000;
+
+/// The container's session keyring: everything for its processes, and only
+/// its attributes for anyone else with the same uid
+const SESSION_PERM: u32 = KEY_POS_ALL | KEY_USR_VIEW;
+
+/// An exposed key: usable, but not writable, by the container
+const EXPOSED_PERM: u32 = KEY_POS_VIEW | KEY_POS_READ | KEY_POS_SEARCH | KEY_USR_VIEW;
+
+/// Key types whose payload userspace can't read, so they can't be copied
+const UNREADABLE_TYPES: &[&str] = &["logon", "big_key", "trusted", "encrypted", "asymmetric"];
+
+/// What a container's keyring holds besides its own keys
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyringConfig {
+    /// Host `user` keys copied read-only into the container's keyring, by
+    /// description (`steam:token`, or `user:steam:token`)
+    #[serde(default)]
+    pub expose: Vec<String>,
+}
+
+/// The session keyring a container joins before its command runs
+#[derive(Debug, Default)]
+pub struct ContainerKeyring {
+    /// Keyrings aren't available to Bolt, so there's nothing to isolate
+    disabled: bool,
+    keys: Vec<(CString, Vec<u8>)>,
+}
+
+fn keyctl(
+    operation: libc::c_long,
+    arg2: libc::c_long,
+    arg3: libc::c_long,
+    arg4: libc::c_long,
+    arg5: libc::c_long,
+) -> std::io::Result<libc::c_long> {
+    // SAFETY: callers pass pointers that outlive the call, as keyctl(2)
+    // requires for the operation
+    let result = unsafe { libc::syscall(libc::SYS_keyctl, operation, arg2, arg3, arg4, arg5) };
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn add_user_key(
+    description: &CString,
+    payload: &[u8],
+    keyring: libc::c_long,
+) -> std::io::Result<libc::c_long> {
+    // SAFETY: the type, description and payload outlive the call
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_add_key,
+            c"user".as_ptr(),
+            description.as_ptr(),
+            payload.as_ptr(),
+            payload.len(),
+            keyring,
+        )
+    };
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Whether this process can use keyrings at all; seccomp profiles such as
+/// Docker's default block them
+pub fn supported() -> bool {
+    keyctl(KEYCTL_GET_KEYRING_ID, KEY_SPEC_SESSION_KEYRING, 0, 0, 0).is_ok()
+}
+
+/// The description of an exposed `user` key
+pub fn key_description(name: &str) -> Result<String> {
+    if let Some((kind, _)) = name.split_once(':')
+        && UNREADABLE_TYPES.contains(&kind)
+    {
+        return Err(anyhow!(
+            "Key '{}' can't be exposed: {} keys can't be read back to copy them, only user keys can",
+            name,
+            kind
+        ));
+    }
+    let description = name.strip_prefix("user:").unwrap_or(name);
+    if description.is_empty() || description.contains('\0') {
+        return Err(anyhow!("Invalid key name '{}'", name));
+    }
+    Ok(description.to_string())
+}
+
+/// Find a `user` key in Bolt's session or user keyring
+fn search(description: &CString) -> Result<libc::c_long> {
+    [KEY_SPEC_SESSION_KEYRING, KEY_SPEC_USER_KEYRING]
+        .into_iter()
+        .find_map(|keyring| {
+            keyctl(
+                KEYCTL_SEARCH,
+                keyring,
+                c"user".as_ptr() as libc::c_long,
+                description.as_ptr() as libc::c_long,
+                0,
+            )
+            .ok()
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "Key user:{} is in neither Bolt's session nor its user keyring",
+                description.to_string_lossy()
+            )
+        })
+}
+
+/// A key's payload
+fn read(key: libc::c_long) -> std::io::Result<Vec<u8>> {
+    loop {
+        let size = keyctl(KEYCTL_READ, key, 0, 0, 0)? as usize;
+        let mut payload = vec![0u8; size];
+        let read = keyctl(
+            KEYCTL_READ,
+            key,
+            payload.as_mut_ptr() as libc::c_long,
+            size as libc::c_long,
+            0,
+        )? as usize;
+        // The key may have been updated in between
+        if read <= size {
+            payload.truncate(read);
+            return Ok(payload);
+        }
+    }
+}
+
+impl ContainerKeyring {
+    /// Read the keys to expose from Bolt's keyrings
+    pub fn prepare(config: Option<&KeyringConfig>) -> Result<Self> {
+        let expose = config.map(|c| c.expose.as_slice()).unwrap_or_default();
+        if !supported() {
+            if let Some(name) = expose.first() {
+                return Err(anyhow!(
+                    "Can't expose key '{}': keyrings aren't available to Bolt",
+                    name
+                ));
+            }
+            debug!("Keyrings aren't available; containers have none to share");
+            return Ok(Self {
+                disabled: true,
+                keys: Vec::new(),
+            });
+        }
+
+        let mut keys = Vec::with_capacity(expose.len());
+        for name in expose {
+            let description = CString::new(key_description(name)?)?;
+            let payload = read(search(&description)?)
+                .map_err(|e| anyhow!("Failed to read key '{}': {}", name, e))?;
+            debug!(
+                "Exposing key user:{} read-only",
+                description.to_string_lossy()
+            );
+            keys.push((description, payload));
+        }
+        Ok(Self {
+            disabled: false,
+            keys,
+        })
+    }
+
+    /// Join a new anonymous session keyring and copy the exposed keys into
+    /// it. Runs in the container's process between fork and exec, so it only
+    /// makes syscalls.
+    pub fn enter(&self) -> std::io::Result<()> {
+        if self.disabled {
+            return Ok(());
+        }
+        let session = keyctl(KEYCTL_JOIN_SESSION_KEYRING, 0, 0, 0, 0)?;
+        keyctl(KEYCTL_SETPERM, session, SESSION_PERM as libc::c_long, 0, 0)?;
+        for (description, payload) in &self.keys {
+            let key = add_user_key(description, payload, KEY_SPEC_SESSION_KEYRING)?;
+            keyctl(KEYCTL_SETPERM, key, EXPOSED_PERM as libc::c_long, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// `keyring` under `[containers]` in a containers.conf
+fn containers_conf_keyring(conf: &str) -> Option<bool> {
+    conf.parse::<toml::Value>()
+        .ok()?
+        .get("containers")?
+        .get("keyring")?
+        .as_bool()
+}
+
+/// The containers.conf that stops podman from giving containers their own
+/// keyring, if the one that takes effect does
+pub fn podman_keyring_disabled() -> Option<PathBuf> {
+    let mut files = vec![
+        PathBuf::from("/usr/share/containers/containers.conf"),
+        PathBuf::from("/etc/containers/containers.conf"),
+    ];
+    files.extend(dirs::config_dir().map(|dir| dir.join("containers/containers.conf")));
+    files.extend(std::env::var_os("CONTAINERS_CONF").map(PathBuf::from));
+
+    let (file, keyring) = files.into_iter().rev().find_map(|file| {
+        let setting = containers_conf_keyring(&std::fs::read_to_string(&file).ok()?)?;
+        Some((file, setting))
+    })?;
+    (!keyring).then_some(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposes_keys_read_only_and_keeps_containers_apart() {
+        assert_eq!(key_description("user:steam:token").unwrap(), "steam:token");
+        assert_eq!(key_description("steam:token").unwrap(), "steam:token");
+        assert!(key_description("logon:cifs:a").is_err());
+        assert!(key_description("user:").is_err());
+        assert_eq!(
+            containers_conf_keyring("[containers]\nkeyring = false\n"),
+            Some(false)
+        );
+        assert_eq!(
+            containers_conf_keyring("[engine]\nevents_logger = \"file\"\n"),
+            None
+        );
+
+        if !supported() {
+            return;
+        }
+        // Session keyrings are per thread, so each thread plays one process
+        // and the test runner's own session keyring is left alone
+        let host = std::thread::spawn(|| {
+            keyctl(KEYCTL_JOIN_SESSION_KEYRING, 0, 0, 0, 0).unwrap();
+            let description = CString::new("bolt-test:token").unwrap();
+            add_user_key(&description, b"secret", KEY_SPEC_SESSION_KEYRING).unwrap();
+            let config = KeyringConfig {
+                expose: vec!["user:bolt-test:token".to_string()],
+            };
+            ContainerKeyring::prepare(Some(&config)).unwrap()
+        })
+        .join()
+        .unwrap();
+
+        let (exposed, private) = std::thread::spawn(move || {
+            host.enter().unwrap();
+            let token = search(&CString::new("bolt-test:token").unwrap()).unwrap();
+            let exposed = read(token).unwrap();
+            // Read-only: updating the copy is refused
+            assert!(
+                add_user_key(
+                    &CString::new("bolt-test:token").unwrap(),
+                    b"changed",
+                    KEY_SPEC_SESSION_KEYRING
+                )
+                .is_err()
+            );
+            let private = add_user_key(
+                &CString::new("bolt-test:a").unwrap(),
+                b"a",
+                KEY_SPEC_SESSION_KEYRING,
+            )
+            .unwrap();
+            (exposed, private)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(exposed, b"secret");
+
+        std::thread::spawn(move || {
+            ContainerKeyring::prepare(None).unwrap().enter().unwrap();
+            assert!(search(&CString::new("bolt-test:token").unwrap()).is_err());
+            assert!(search(&CString::new("bolt-test:a").unwrap()).is_err());
+            assert!(read(private).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+}
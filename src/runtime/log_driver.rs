@@ -0,0 +1,357 @@
+// Per-service log drivers
+//
+// A service's `logging` block picks where its output goes:
+//
+// - `journald`: the runtime's journald driver, tagged with the container name
+//   so entries carry CONTAINER_NAME and SYSLOG_IDENTIFIER
+// - `syslog`: a remote syslog server (Docker only; Podman has no syslog driver)
+// - `file`: a local file rotated by size and, optionally, age
+// - `none`: output is discarded
+//
+// `bolt surge logs` reads back from whichever driver the service uses.
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::{parse_duration, parse_size};
+use crate::config::LoggingConfig;
+
+const DEFAULT_MAX_SIZE: &str = "10m";
+const DEFAULT_MAX_FILES: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogDriver {
+    Journald,
+    Syslog,
+    File,
+    None,
+    /// Any other runtime driver, passed through as is (e.g. from a compose file)
+    Runtime(String),
+}
+
+impl LogDriver {
+    pub fn parse(driver: &str) -> Self {
+        match driver {
+            "journald" => LogDriver::Journald,
+            "syslog" => LogDriver::Syslog,
+            // Compose files name the runtimes' own file drivers
+            "file" | "json-file" | "k8s-file" | "local" => LogDriver::File,
+            "none" => LogDriver::None,
+            other => LogDriver::Runtime(other.to_string()),
+        }
+    }
+
+    pub fn of(logging: Option<&LoggingConfig>) -> Self {
+        logging
+            .filter(|l| !l.driver.is_empty())
+            .map(|l| Self::parse(&l.driver))
+            .unwrap_or(LogDriver::Runtime(String::new()))
+    }
+}
+
+/// Rotation limits of the `file` driver
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileRotation {
+    pub max_size: u64,
+    pub max_files: u32,
+    pub max_age: Option<std::time::Duration>,
+}
+
+impl FileRotation {
+    fn from_options(options: &HashMap<String, String>) -> Result<Self> {
+        let max_size = options
+            .get("max-size")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_MAX_SIZE);
+        Ok(Self {
+            max_size: parse_size(max_size)?,
+            max_files: options
+                .get("max-files")
+                .or_else(|| options.get("max-file"))
+                .map(|n| n.parse())
+                .transpose()
+                .map_err(|_| anyhow!("max-files must be a number"))?
+                .unwrap_or(DEFAULT_MAX_FILES),
+            max_age: options
+                .get("max-age")
+                .map(|age| parse_duration(age))
+                .transpose()?,
+        })
+    }
+}
+
+/// `run` arguments selecting the service's log driver
+pub fn run_args(
+    runtime: &str,
+    container: &str,
+    logging: &LoggingConfig,
+    log_dir: &Path,
+) -> Result<Vec<String>> {
+    let options = &logging.options;
+    let mut args = Vec::new();
+
+    match LogDriver::parse(&logging.driver) {
+        LogDriver::Journald => {
+            args.extend(["--log-driver".to_string(), "journald".to_string()]);
+            let tag = options.get("tag").map(String::as_str).unwrap_or(container);
+            opt(&mut args, "tag", tag);
+            // Docker can copy container labels into journal fields
+            if runtime == "docker"
+                && let Some(labels) = options.get("labels")
+            {
+                opt(&mut args, "labels", labels);
+            }
+        }
+        LogDriver::Syslog => {
+            if runtime != "docker" {
+                return Err(anyhow!(
+                    "{} has no syslog log driver; use driver = \"journald\" and forward \
+                     the journal to your syslog server instead",
+                    runtime
+                ));
+            }
+            let address = options.get("address").ok_or_else(|| {
+                anyhow!("syslog logging needs an address, e.g. udp://logs.example.com:514")
+            })?;
+            args.extend(["--log-driver".to_string(), "syslog".to_string()]);
+            opt(&mut args, "syslog-address", address);
+            opt(
+                &mut args,
+                "syslog-facility",
+                options
+                    .get("facility")
+                    .map(String::as_str)
+                    .unwrap_or("daemon"),
+            );
+            opt(
+                &mut args,
+                "syslog-format",
+                options
+                    .get("format")
+                    .map(String::as_str)
+                    .unwrap_or("rfc5424"),
+            );
+            opt(
+                &mut args,
+                "tag",
+                options.get("tag").map(String::as_str).unwrap_or(container),
+            );
+        }
+        LogDriver::File => {
+            let rotation = FileRotation::from_options(options)?;
+            if runtime == "podman" {
+                // conmon writes the file; Bolt rotates extra files and by age
+                let path = file_log_path(log_dir, container);
+                args.extend(["--log-driver".to_string(), "k8s-file".to_string()]);
+                opt(&mut args, "path", &path.display().to_string());
+            } else {
+                args.extend(["--log-driver".to_string(), "json-file".to_string()]);
+                opt(&mut args, "max-file", &rotation.max_files.to_string());
+            }
+            opt(&mut args, "max-size", &rotation.max_size.to_string());
+        }
+        LogDriver::None => {
+            args.extend(["--log-driver".to_string(), "none".to_string()]);
+        }
+        LogDriver::Runtime(driver) => {
+            if !driver.is_empty() {
+                args.extend(["--log-driver".to_string(), driver]);
+            }
+            for (key, value) in options {
+                opt(&mut args, key, value);
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+fn opt(args: &mut Vec<String>, key: &str, value: &str) {
+    args.push("--log-opt".to_string());
+    args.push(format!("{}={}", key, value));
+}
+
+/// Where the `file` driver writes under Podman
+pub fn file_log_path(log_dir: &Path, container: &str) -> PathBuf {
+    log_dir.join(format!("{}.log", container))
+}
+
+/// Rotate a `file` driver log that has outgrown its size or age limit.
+///
+/// The log is copied to `<log>.1` and truncated in place, since the runtime
+/// keeps it open. The time of the last rotation is kept in `<log>.rotated`.
+pub async fn rotate(
+    runtime: &str,
+    container: &str,
+    logging: &LoggingConfig,
+    log_dir: &Path,
+) -> Result<bool> {
+    if LogDriver::parse(&logging.driver) != LogDriver::File {
+        return Ok(false);
+    }
+    let rotation = FileRotation::from_options(&logging.options)?;
+    // Docker rotates by size itself, so only age matters there
+    if runtime != "podman" && rotation.max_age.is_none() {
+        return Ok(false);
+    }
+
+    let Some(path) = runtime_log_path(runtime, container, log_dir).await else {
+        return Ok(false);
+    };
+    let Ok(metadata) = std::fs::metadata(&path) else {
+        return Ok(false);
+    };
+
+    let marker = path.with_extension("log.rotated");
+    let last_rotation = std::fs::metadata(&marker).and_then(|m| m.modified()).ok();
+    if last_rotation.is_none() {
+        // Start the age clock when Bolt first sees the file
+        std::fs::write(&marker, b"")?;
+    }
+    let too_old = match (rotation.max_age, last_rotation) {
+        (Some(max_age), Some(at)) => SystemTime::now()
+            .duration_since(at)
+            .map(|age| age >= max_age)
+            .unwrap_or(false),
+        _ => false,
+    };
+    let too_big = runtime == "podman" && metadata.len() >= rotation.max_size;
+    if (!too_old && !too_big) || metadata.len() == 0 {
+        return Ok(false);
+    }
+
+    let rotated = |n: u32| PathBuf::from(format!("{}.{}", path.display(), n));
+    let _ = std::fs::remove_file(rotated(rotation.max_files.max(1)));
+    for n in (1..rotation.max_files.max(1)).rev() {
+        let _ = std::fs::rename(rotated(n), rotated(n + 1));
+    }
+    std::fs::copy(&path, rotated(1))
+        .with_context(|| format!("Failed to rotate {}", path.display()))?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(&path)?;
+    std::fs::write(&marker, b"")?;
+
+    info!(
+        "🔄 Rotated log for {} ({})",
+        container,
+        if too_old { "age" } else { "size" }
+    );
+    Ok(true)
+}
+
+async fn runtime_log_path(runtime: &str, container: &str, log_dir: &Path) -> Option<PathBuf> {
+    if runtime == "podman" {
+        return Some(file_log_path(log_dir, container));
+    }
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.LogPath}}", container])
+        .output()
+        .await
+        .ok()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Print a container's logs from wherever its driver sends them
+pub async fn show_logs(
+    runtime: &str,
+    container: &str,
+    logging: Option<&LoggingConfig>,
+    follow: bool,
+    tail: Option<usize>,
+) -> Result<()> {
+    let mut cmd = match LogDriver::of(logging) {
+        LogDriver::None => {
+            return Err(anyhow!(
+                "{} uses the none log driver; its output is not kept",
+                container
+            ));
+        }
+        LogDriver::Journald => {
+            let mut cmd = AsyncCommand::new("journalctl");
+            cmd.arg(format!("CONTAINER_NAME={}", container)).args([
+                "--output",
+                "cat",
+                "--no-pager",
+            ]);
+            if follow {
+                cmd.arg("--follow");
+            }
+            if let Some(tail) = tail {
+                cmd.arg("--lines").arg(tail.to_string());
+            }
+            cmd
+        }
+        // Docker keeps a local copy of syslog output ("dual logging"), and
+        // both runtimes read their own file drivers
+        LogDriver::Syslog | LogDriver::File | LogDriver::Runtime(_) => {
+            let mut cmd = AsyncCommand::new(runtime);
+            cmd.arg("logs");
+            if follow {
+                cmd.arg("--follow");
+            }
+            if let Some(tail) = tail {
+                cmd.arg("--tail").arg(tail.to_string());
+            }
+            cmd.arg(container);
+            cmd
+        }
+    };
+    debug!("Reading logs: {:?}", cmd);
+
+    let status = cmd
+        .status()
+        .await
+        .with_context(|| format!("Failed to read logs for {}", container))?;
+    if !status.success() {
+        warn!("Reading logs for {} exited with {}", container, status);
+        return Err(anyhow!("Failed to get logs for {}", container));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logging(driver: &str, options: &[(&str, &str)]) -> LoggingConfig {
+        LoggingConfig {
+            driver: driver.to_string(),
+            options: options
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn maps_drivers_to_runtime_flags() {
+        let log_dir = Path::new("/var/log/bolt");
+
+        let args = run_args("podman", "app_web", &logging("journald", &[]), log_dir).unwrap();
+        assert_eq!(
+            args,
+            ["--log-driver", "journald", "--log-opt", "tag=app_web"]
+        );
+
+        let file = logging("file", &[("max-size", "50MB"), ("max-age", "7d")]);
+        let args = run_args("podman", "app_web", &file, log_dir).unwrap();
+        assert!(args.contains(&"path=/var/log/bolt/app_web.log".to_string()));
+        assert!(args.contains(&format!("max-size={}", 50 * 1024 * 1024)));
+
+        let args = run_args("docker", "app_web", &file, log_dir).unwrap();
+        assert!(args.contains(&"json-file".to_string()));
+        assert!(args.contains(&"max-file=3".to_string()));
+
+        assert!(run_args("podman", "app_web", &logging("syslog", &[]), log_dir).is_err());
+        assert!(run_args("docker", "app_web", &logging("syslog", &[]), log_dir).is_err());
+        let syslog = logging("syslog", &[("address", "udp://logs:514")]);
+        let args = run_args("docker", "app_web", &syslog, log_dir).unwrap();
+        assert!(args.contains(&"syslog-address=udp://logs:514".to_string()));
+    }
+}
@@ -0,0 +1,809 @@
+// Log hooks
+//
+// Game servers tell the world what happens in them through their logs:
+// "Steve joined the game", "Saving world". A service's `[log_hooks]` turn
+// such lines into state Bolt can act on:
+//
+// - each rule is a regex; a matching line records an event named by the rule
+//   in the container's event log, with the regex's named groups as fields
+// - rules update gauges such as `player_count`: add to them, set them to a
+//   number or to a named group's value. Gauges start at 0 and go back to 0
+//   when the container restarts.
+// - automation runs a command inside the container or on the host once a
+//   condition on a gauge has held for a while, e.g. `player_count == 0` for
+//   10 minutes. It runs once, and again only after the condition stopped
+//   holding in between.
+//
+// `bolt log-watch`, started by surge for each container, follows the logs,
+// keeps the gauges and automation state in
+// `<data_dir>/log_hooks/<container>.json` and records each change of the
+// gauges in the metrics history `gauges-<container>`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::events::{ContainerEvent, EventLog};
+use super::watcher::{self, Watcher};
+use crate::builds::cache::parse_duration;
+use crate::config::LogHooksConfig;
+use crate::monitoring::history::MetricsHistory;
+
+/// How often automation conditions are checked
+const EVAL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a stopped container is checked for having started again
+const RESTART_POLL: Duration = Duration::from_secs(5);
+const HISTORY_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// What a matching line does to a gauge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GaugeUpdate {
+    Add(i64),
+    Set(u64),
+    /// Set to the value of a named group
+    Capture(String),
+}
+
+impl GaugeUpdate {
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if let Some(group) = spec.strip_prefix('$') {
+            return Ok(GaugeUpdate::Capture(group.to_string()));
+        }
+        if spec.starts_with(['+', '-']) {
+            return spec
+                .parse()
+                .map(GaugeUpdate::Add)
+                .map_err(|_| anyhow!("Invalid gauge update '{}'", spec));
+        }
+        spec.parse().map(GaugeUpdate::Set).map_err(|_| {
+            anyhow!(
+                "Invalid gauge update '{}': use \"+N\", \"-N\", a number or \"$group\"",
+                spec
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+/// `<gauge> <op> <value>`, e.g. "player_count == 0"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub gauge: String,
+    pub op: Comparison,
+    pub value: u64,
+}
+
+impl Condition {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let [gauge, op, value] = parts[..] else {
+            return Err(anyhow!(
+                "Invalid condition '{}': use \"<gauge> <op> <number>\"",
+                spec
+            ));
+        };
+        let op = match op {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            other => return Err(anyhow!("Unknown comparison '{}' in '{}'", other, spec)),
+        };
+        Ok(Self {
+            gauge: gauge.to_string(),
+            op,
+            value: value
+                .parse()
+                .map_err(|_| anyhow!("Invalid number '{}' in '{}'", value, spec))?,
+        })
+    }
+
+    pub fn holds(&self, gauges: &BTreeMap<String, u64>) -> bool {
+        let current = gauges.get(&self.gauge).copied().unwrap_or(0);
+        match self.op {
+            Comparison::Eq => current == self.value,
+            Comparison::Ne => current != self.value,
+            Comparison::Lt => current < self.value,
+            Comparison::Le => current <= self.value,
+            Comparison::Gt => current > self.value,
+            Comparison::Ge => current >= self.value,
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.gauge, self.op.as_str(), self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    event: String,
+    regex: Regex,
+    gauges: Vec<(String, GaugeUpdate)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Automation {
+    pub name: String,
+    pub condition: Condition,
+    pub hold: Duration,
+    exec: Option<Vec<String>>,
+    run: Option<Vec<String>>,
+}
+
+/// A line a rule matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMatch {
+    pub event: String,
+    /// Named groups that matched
+    pub fields: BTreeMap<String, String>,
+}
+
+/// A service's compiled `[log_hooks]`
+#[derive(Debug, Clone)]
+pub struct LogHooks {
+    rules: Vec<Rule>,
+    pub automations: Vec<Automation>,
+}
+
+impl LogHooks {
+    pub fn from_config(config: &LogHooksConfig) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            if rule.event.trim().is_empty() {
+                return Err(anyhow!("Every log_hooks rule needs an event name"));
+            }
+            let regex = Regex::new(&rule.pattern)
+                .with_context(|| format!("Invalid pattern for event '{}'", rule.event))?;
+            let mut gauges = Vec::new();
+            for (gauge, spec) in &rule.gauges {
+                validate_gauge_name(gauge)?;
+                let update = GaugeUpdate::parse(spec)?;
+                if let GaugeUpdate::Capture(ref group) = update {
+                    if !regex.capture_names().flatten().any(|name| name == group) {
+                        return Err(anyhow!(
+                            "Gauge '{}' of event '{}' reads group '{}', which the pattern doesn't name",
+                            gauge,
+                            rule.event,
+                            group
+                        ));
+                    }
+                }
+                gauges.push((gauge.clone(), update));
+            }
+            rules.push(Rule {
+                event: rule.event.clone(),
+                regex,
+                gauges,
+            });
+        }
+
+        let mut automations: Vec<Automation> = Vec::new();
+        for automation in &config.automation {
+            if automation.name.trim().is_empty() {
+                return Err(anyhow!("Every log_hooks automation needs a name"));
+            }
+            if automations.iter().any(|a| a.name == automation.name) {
+                return Err(anyhow!(
+                    "Duplicate log_hooks automation '{}'",
+                    automation.name
+                ));
+            }
+            let condition = Condition::parse(&automation.when)?;
+            if !rules
+                .iter()
+                .any(|r| r.gauges.iter().any(|(g, _)| *g == condition.gauge))
+            {
+                return Err(anyhow!(
+                    "Automation '{}' watches gauge '{}', which no rule updates",
+                    automation.name,
+                    condition.gauge
+                ));
+            }
+            let has_command = |c: &Option<Vec<String>>| c.as_ref().is_some_and(|c| !c.is_empty());
+            if !has_command(&automation.exec) && !has_command(&automation.run) {
+                return Err(anyhow!(
+                    "Automation '{}' needs an exec or run command",
+                    automation.name
+                ));
+            }
+            automations.push(Automation {
+                name: automation.name.clone(),
+                condition,
+                hold: automation
+                    .hold
+                    .as_deref()
+                    .map(parse_duration)
+                    .transpose()?
+                    .unwrap_or_default(),
+                exec: automation.exec.clone().filter(|c| !c.is_empty()),
+                run: automation.run.clone().filter(|c| !c.is_empty()),
+            });
+        }
+        Ok(Self { rules, automations })
+    }
+
+    /// Gauges the rules update, all at 0
+    pub fn initial_gauges(&self) -> BTreeMap<String, u64> {
+        self.rules
+            .iter()
+            .flat_map(|r| r.gauges.iter())
+            .map(|(gauge, _)| (gauge.clone(), 0))
+            .collect()
+    }
+
+    /// Match a log line against every rule and update the gauges
+    pub fn process(&self, line: &str, gauges: &mut BTreeMap<String, u64>) -> Vec<LogMatch> {
+        let mut matches = Vec::new();
+        for rule in &self.rules {
+            let Some(captures) = rule.regex.captures(line) else {
+                continue;
+            };
+            let fields: BTreeMap<String, String> = rule
+                .regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().into())))
+                .collect();
+            for (gauge, update) in &rule.gauges {
+                let value = gauges.entry(gauge.clone()).or_default();
+                match update {
+                    GaugeUpdate::Add(delta) => *value = value.saturating_add_signed(*delta),
+                    GaugeUpdate::Set(n) => *value = *n,
+                    GaugeUpdate::Capture(group) => match fields.get(group).map(|v| v.parse()) {
+                        Some(Ok(n)) => *value = n,
+                        _ => debug!("'{}' set no number for gauge {}", rule.event, gauge),
+                    },
+                }
+            }
+            matches.push(LogMatch {
+                event: rule.event.clone(),
+                fields,
+            });
+        }
+        matches
+    }
+
+    /// Automations whose condition has now held long enough. Conditions that
+    /// stopped holding re-arm their automation.
+    pub fn due(
+        &self,
+        gauges: &BTreeMap<String, u64>,
+        states: &mut BTreeMap<String, AutomationState>,
+        now: DateTime<Utc>,
+    ) -> Vec<&Automation> {
+        let mut due = Vec::new();
+        for automation in &self.automations {
+            let state = states.entry(automation.name.clone()).or_default();
+            if !automation.condition.holds(gauges) {
+                state.holding_since = None;
+                state.fired = false;
+                continue;
+            }
+            let since = *state.holding_since.get_or_insert(now);
+            let held = (now - since).to_std().unwrap_or_default();
+            if !state.fired && held >= automation.hold {
+                state.fired = true;
+                due.push(automation);
+            }
+        }
+        due
+    }
+}
+
+fn validate_gauge_name(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid gauge name '{}': use lowercase letters, digits and '_'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+impl Automation {
+    /// Run the exec command in the container, then the run command on the host
+    async fn run(
+        &self,
+        runtime: &str,
+        container: &str,
+        gauges: &BTreeMap<String, u64>,
+    ) -> Result<()> {
+        if let Some(ref exec) = self.exec {
+            let mut command = AsyncCommand::new(runtime);
+            command.arg("exec").arg(container).args(exec);
+            output_of(command, &exec[0]).await?;
+        }
+        if let Some(ref run) = self.run {
+            let mut command = AsyncCommand::new(&run[0]);
+            command
+                .args(&run[1..])
+                .env("BOLT_CONTAINER", container)
+                .env("BOLT_AUTOMATION", &self.name);
+            for (gauge, value) in gauges {
+                command.env(
+                    format!("BOLT_GAUGE_{}", gauge.to_ascii_uppercase()),
+                    value.to_string(),
+                );
+            }
+            output_of(command, &run[0]).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn output_of(mut command: AsyncCommand, program: &str) -> Result<()> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} exited with {}: {}",
+        program,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutomationState {
+    /// Since when the condition holds
+    pub holding_since: Option<DateTime<Utc>>,
+    /// Whether it ran since the condition last started holding
+    pub fired: bool,
+    pub runs: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogHookState {
+    pub container: String,
+    pub config: LogHooksConfig,
+    pub watcher_pid: Option<u32>,
+    pub gauges: BTreeMap<String, u64>,
+    /// Lines matched per event
+    pub events: BTreeMap<String, u64>,
+    pub automations: BTreeMap<String, AutomationState>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl LogHookState {
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+const WATCHER: Watcher = Watcher::new("log-watch");
+
+fn hooks_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("log_hooks")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    hooks_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    hooks_dir(data_dir).join(format!("{}.log", container))
+}
+
+fn history_name(container: &str) -> String {
+    format!("gauges-{}", container)
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<LogHookState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every container with log hooks, by name
+pub fn list(data_dir: &Path) -> Vec<LogHookState> {
+    let mut states: Vec<LogHookState> = watcher::list(&hooks_dir(data_dir));
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &LogHookState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Save the watcher's changes unless `surge down` removed the record meanwhile
+fn update(data_dir: &Path, state: &LogHookState) -> Result<()> {
+    watcher::update(&state_path(data_dir, &state.container), state)
+}
+
+/// Start following a running container's logs
+pub fn start(data_dir: &Path, container: &str, config: &LogHooksConfig) -> Result<()> {
+    let hooks = LogHooks::from_config(config)?;
+    forget(data_dir, container);
+    let mut state = LogHookState {
+        container: container.to_string(),
+        config: config.clone(),
+        watcher_pid: None,
+        gauges: hooks.initial_gauges(),
+        events: BTreeMap::new(),
+        automations: BTreeMap::new(),
+        updated_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["log-watch", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    info!(
+        "🪝 Watching {} logs with {} rule(s) and {} automation(s)",
+        container,
+        config.rules.len(),
+        config.automation.len()
+    );
+    Ok(())
+}
+
+/// Stop following a container's logs and drop its record
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Follow the container's logs, keep its gauges and run its automation. Run
+/// by `bolt log-watch`.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    let runtime = super::detect_container_runtime().await?;
+    let Some(mut state) = state(data_dir, container) else {
+        debug!("No log hooks for {}, stopping", container);
+        return Ok(());
+    };
+    let hooks = LogHooks::from_config(&state.config)?;
+    let events = EventLog::new(data_dir.to_path_buf())?;
+    let history = MetricsHistory::open(data_dir, &history_name(container))?;
+    if let Err(e) = history.prune(HISTORY_RETENTION) {
+        debug!("Could not prune the gauge history of {}: {}", container, e);
+    }
+
+    let mut first = true;
+    loop {
+        if !state_path(data_dir, container).exists() {
+            debug!("Log hooks of {} removed, stopping", container);
+            return Ok(());
+        }
+        match container_running(&runtime, container).await {
+            None => {
+                info!("{} no longer exists, no longer watching it", container);
+                return Ok(());
+            }
+            Some(false) => {
+                tokio::time::sleep(RESTART_POLL).await;
+                continue;
+            }
+            Some(true) => {}
+        }
+        if !first {
+            // A restarted server starts over
+            state.gauges = hooks.initial_gauges();
+            record_gauges(&history, &state);
+            update(data_dir, &state)?;
+        }
+        first = false;
+
+        follow(&runtime, &hooks, &events, &history, data_dir, &mut state).await?;
+        debug!("Log stream of {} ended", container);
+    }
+}
+
+/// Process the container's output until it stops
+async fn follow(
+    runtime: &str,
+    hooks: &LogHooks,
+    events: &EventLog,
+    history: &MetricsHistory,
+    data_dir: &Path,
+    state: &mut LogHookState,
+) -> Result<()> {
+    let container = state.container.clone();
+    let mut child = AsyncCommand::new(runtime)
+        .args(["logs", "--follow", "--tail", "0", &container])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to follow the logs of {}", container))?;
+    let mut stdout = child.stdout.take().map(|s| BufReader::new(s).lines());
+    let mut stderr = child.stderr.take().map(|s| BufReader::new(s).lines());
+    let mut tick = tokio::time::interval(EVAL_INTERVAL);
+
+    while stdout.is_some() || stderr.is_some() {
+        let line = tokio::select! {
+            line = next_line(&mut stdout), if stdout.is_some() => line,
+            line = next_line(&mut stderr), if stderr.is_some() => line,
+            _ = tick.tick() => {
+                run_automations(runtime, hooks, events, state).await;
+                update(data_dir, state)?;
+                continue;
+            }
+        };
+        let Some(line) = line else {
+            continue;
+        };
+
+        let before = state.gauges.clone();
+        let matches = hooks.process(&line, &mut state.gauges);
+        if matches.is_empty() {
+            continue;
+        }
+        for found in matches {
+            *state.events.entry(found.event.clone()).or_default() += 1;
+            let mut event =
+                ContainerEvent::new(&container, &found.event).with_attribute("source", "log-hook");
+            for (field, value) in found.fields {
+                event = event.with_attribute(&field, value);
+            }
+            if let Err(e) = events.record(&event) {
+                warn!(
+                    "Could not record {} event of {}: {}",
+                    found.event, container, e
+                );
+            }
+        }
+        if state.gauges != before {
+            record_gauges(history, state);
+        }
+        state.updated_at = Utc::now();
+        update(data_dir, state)?;
+    }
+    let _ = child.wait().await;
+    Ok(())
+}
+
+/// Next line of a stream, closing it on its end
+async fn next_line<R>(lines: &mut Option<tokio::io::Lines<R>>) -> Option<String>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    let stream = lines.as_mut()?;
+    match stream.next_line().await {
+        Ok(Some(line)) => Some(line),
+        Ok(None) | Err(_) => {
+            *lines = None;
+            None
+        }
+    }
+}
+
+async fn run_automations(
+    runtime: &str,
+    hooks: &LogHooks,
+    events: &EventLog,
+    state: &mut LogHookState,
+) {
+    let container = state.container.clone();
+    let now = Utc::now();
+    for automation in hooks.due(&state.gauges, &mut state.automations, now) {
+        info!(
+            "⚙️  {}: {} for {}s, running {}",
+            container,
+            automation.condition,
+            automation.hold.as_secs(),
+            automation.name
+        );
+        let result = automation.run(runtime, &container, &state.gauges).await;
+        let entry = state
+            .automations
+            .entry(automation.name.clone())
+            .or_default();
+        entry.runs += 1;
+        entry.last_run = Some(now);
+        entry.last_error = result.as_ref().err().map(|e| e.to_string());
+        let status = match result {
+            Ok(()) => "ok",
+            Err(ref e) => {
+                warn!(
+                    "Automation {} of {} failed: {}",
+                    automation.name, container, e
+                );
+                "failed"
+            }
+        };
+        let event = ContainerEvent::new(&container, "automation")
+            .with_attribute("source", "log-hook")
+            .with_attribute("automation", automation.name.as_str())
+            .with_attribute("status", status);
+        if let Err(e) = events.record(&event) {
+            warn!("Could not record automation event of {}: {}", container, e);
+        }
+    }
+}
+
+fn record_gauges(history: &MetricsHistory, state: &LogHookState) {
+    if let Err(e) = history.record(state.gauges.clone()) {
+        debug!("Could not record gauges of {}: {}", state.container, e);
+    }
+}
+
+/// `Some(running)` for an existing container, `None` once it's gone
+async fn container_running(runtime: &str, container: &str) -> Option<bool> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Running}}", container])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+/// Events recorded by log hooks, oldest first
+pub fn events(data_dir: &Path, container: &str) -> Result<Vec<ContainerEvent>> {
+    Ok(EventLog::new(data_dir.to_path_buf())?
+        .history(container)?
+        .into_iter()
+        .filter(|e| e.attributes.get("source").map(String::as_str) == Some("log-hook"))
+        .collect())
+}
+
+/// Gauges and matched events in the Prometheus text format. Each gauge is
+/// its own metric, e.g. `bolt_service_player_count`.
+pub fn prometheus(states: &[LogHookState]) -> String {
+    let mut out = String::new();
+    let mut gauges: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for state in states {
+        for (gauge, value) in &state.gauges {
+            gauges
+                .entry(gauge)
+                .or_default()
+                .push((&state.container, *value));
+        }
+    }
+    for (gauge, values) in gauges {
+        let name = format!("bolt_service_{}", gauge);
+        out.push_str(&format!(
+            "# HELP {} Gauge {} set by the service's log hooks\n# TYPE {} gauge\n",
+            name, gauge, name
+        ));
+        for (container, value) in values {
+            out.push_str(&format!(
+                "{}{{container=\"{}\"}} {}\n",
+                name, container, value
+            ));
+        }
+    }
+    out.push_str(
+        "# HELP bolt_log_events_total Log lines matched by the service's log hooks\n\
+         # TYPE bolt_log_events_total counter\n",
+    );
+    for state in states {
+        for (event, count) in &state.events {
+            out.push_str(&format!(
+                "bolt_log_events_total{{container=\"{}\",event=\"{}\"}} {}\n",
+                state.container, event, count
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_players_from_log_lines_and_fires_automation_once_held() {
+        let config: LogHooksConfig = toml::from_str(
+            r#"
+[[rules]]
+event = "player-joined"
+pattern = '(?P<player>\w+) joined the game'
+gauges = { player_count = "+1" }
+
+[[rules]]
+event = "player-left"
+pattern = '(?P<player>\w+) left the game'
+gauges = { player_count = "-1" }
+
+[[rules]]
+event = "player-list"
+pattern = 'There are (?P<online>\d+) of a max of \d+ players online'
+gauges = { player_count = "$online" }
+
+[[automation]]
+name = "snapshot-world"
+when = "player_count == 0"
+for = "10m"
+exec = ["rcon-cli", "save-all"]
+"#,
+        )
+        .unwrap();
+        let hooks = LogHooks::from_config(&config).unwrap();
+        let mut gauges = hooks.initial_gauges();
+        assert_eq!(gauges["player_count"], 0);
+
+        let found = hooks.process(
+            "[12:00:01] [Server thread/INFO]: Steve joined the game",
+            &mut gauges,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].event, "player-joined");
+        assert_eq!(found[0].fields["player"], "Steve");
+        hooks.process("Alex joined the game", &mut gauges);
+        assert_eq!(gauges["player_count"], 2);
+        hooks.process("There are 5 of a max of 20 players online", &mut gauges);
+        assert_eq!(gauges["player_count"], 5);
+        assert!(hooks.process("Saving chunks", &mut gauges).is_empty());
+        gauges.insert("player_count".to_string(), 1);
+        hooks.process("Alex left the game", &mut gauges);
+        hooks.process("Alex left the game", &mut gauges);
+        assert_eq!(gauges["player_count"], 0);
+
+        let mut states = BTreeMap::new();
+        let start = Utc::now();
+        let at = |minutes| start + chrono::Duration::minutes(minutes);
+        assert!(hooks.due(&gauges, &mut states, at(0)).is_empty());
+        assert!(hooks.due(&gauges, &mut states, at(9)).is_empty());
+        assert_eq!(hooks.due(&gauges, &mut states, at(10)).len(), 1);
+        assert!(hooks.due(&gauges, &mut states, at(30)).is_empty());
+        gauges.insert("player_count".to_string(), 1);
+        assert!(hooks.due(&gauges, &mut states, at(31)).is_empty());
+        gauges.insert("player_count".to_string(), 0);
+        assert!(hooks.due(&gauges, &mut states, at(32)).is_empty());
+        assert_eq!(hooks.due(&gauges, &mut states, at(42)).len(), 1);
+
+        let bad = |extra: &str| {
+            let config: LogHooksConfig = toml::from_str(extra).unwrap();
+            LogHooks::from_config(&config).is_err()
+        };
+        assert!(bad("[[rules]]\nevent = \"x\"\npattern = \"(\""));
+        assert!(bad(
+            "[[rules]]\nevent = \"x\"\npattern = \"x\"\ngauges = { n = \"$missing\" }"
+        ));
+        assert!(bad(
+            "[[rules]]\nevent = \"x\"\npattern = \"x\"\ngauges = { n = \"+1\" }\n\
+             [[automation]]\nname = \"a\"\nwhen = \"other == 0\"\nrun = [\"true\"]"
+        ));
+        assert!(bad(
+            "[[rules]]\nevent = \"x\"\npattern = \"x\"\ngauges = { n = \"+1\" }\n\
+             [[automation]]\nname = \"a\"\nwhen = \"n == 0\""
+        ));
+    }
+}
@@ -1,13 +1,51 @@
+use crate::builds::cache::{BuildCache, BuildStats};
 use crate::{BoltError, Result};
 use std::collections::HashMap;
 use tokio::process::Command as AsyncCommand;
 use tracing::{debug, info, warn};
 
+pub mod annotations;
+pub mod bench;
+pub mod bulk;
+pub mod capabilities;
+pub mod cgroups;
+pub mod checkpoint;
+pub mod copy;
+pub mod crash;
+pub mod debug_shell;
+pub mod desktop;
+pub mod dev_env;
+pub mod doctor;
+pub mod drain;
+pub mod emulation;
+pub mod encryption;
 pub mod environment;
+pub mod events;
+pub mod health;
+pub mod healthcheck;
+pub mod identity;
+pub mod idle;
 pub mod input;
+pub mod keyring;
+pub mod log_driver;
+pub mod log_hooks;
 pub mod nvbind;
 pub mod oci;
+pub mod platform;
+pub mod pull;
+pub mod readiness;
+pub mod rootless;
+pub mod run_options;
+pub mod sandbox;
+pub mod snapshots;
+pub mod ssh;
+pub mod startup;
 pub mod storage;
+pub mod supervisor;
+pub mod trust;
+pub mod wasm;
+pub mod watcher;
+pub mod workspace;
 
 #[cfg(feature = "gaming")]
 pub mod gpu;
@@ -34,9 +72,11 @@ pub async fn detect_container_runtime() -> Result<String> {
         return Ok("docker".to_string());
     }
 
-    Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-        message: "No container runtime found (podman or docker required)".to_string(),
-    }))
+    Err(BoltError::Runtime(
+        crate::error::RuntimeError::RuntimeUnavailable {
+            reason: "No container runtime found (podman or docker required)".to_string(),
+        },
+    ))
 }
 
 pub async fn run_container(
@@ -86,7 +126,45 @@ pub async fn run_oci_container(
     volumes: &[String],
     detach: bool,
 ) -> Result<()> {
+    run_oci_container_with_args(image, name, ports, env, volumes, detach, &[]).await
+}
+
+/// Like [`run_oci_container`], passing `extra_args` to `run` before the image
+/// (used for sandbox options)
+pub async fn run_oci_container_with_args(
+    image: &str,
+    name: Option<&str>,
+    ports: &[String],
+    env: &[String],
+    volumes: &[String],
+    detach: bool,
+    extra_args: &[String],
+) -> Result<()> {
+    let mut timer = startup::StartupTimer::new(image);
+    run_oci_container_timed(
+        image, name, ports, env, volumes, detach, extra_args, &mut timer,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Like [`run_oci_container_with_args`], timing the spec and exec phases.
+/// Returns the container's name, or its ID when detached without one. The
+/// exec phase of an attached container is not timed, since `run` only
+/// returns when it exits.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_oci_container_timed(
+    image: &str,
+    name: Option<&str>,
+    ports: &[String],
+    env: &[String],
+    volumes: &[String],
+    detach: bool,
+    extra_args: &[String],
+    timer: &mut startup::StartupTimer,
+) -> Result<Option<String>> {
     info!("🐳 Starting OCI container: {}", image);
+    timer.phase(startup::Phase::Spec);
 
     debug!("Container config:");
     debug!("  Image: {}", image);
@@ -126,95 +204,456 @@ pub async fn run_oci_container(
         cmd.arg("-v").arg(volume);
     }
 
+    cmd.args(extra_args);
     cmd.arg(image);
 
+    if detach {
+        timer.phase(startup::Phase::Exec);
+    } else {
+        timer.end();
+    }
     let output = cmd.output().await?;
+    timer.end();
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            image,
+            &stderr,
             crate::error::RuntimeError::StartFailed {
                 reason: format!("Failed to run container: {}", stderr),
             },
-        ));
+        )));
     }
 
     let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
     info!("✅ Container started: {}", container_id);
 
-    Ok(())
+    Ok(match name {
+        Some(name) => Some(name.to_string()),
+        None if detach => Some(container_id.chars().take(12).collect()),
+        None => None,
+    })
+}
+
+/// Collect a crash dump for a podman/docker managed container that has
+/// exited; `live` is what a [`crash::CrashWatch`] saw while it ran
+pub async fn collect_crash_dump(
+    container: &str,
+    config: crash::CrashDumpConfig,
+    live: crash::LiveState,
+) -> Result<Option<crash::CrashReport>> {
+    let runtime = detect_container_runtime().await?;
+
+    let output = AsyncCommand::new(&runtime)
+        .args(["inspect", "--format", "{{.State.ExitCode}} {{.Config.Image}}"])
+        .arg(container)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(BoltError::Runtime(
+            crate::error::RuntimeError::ContainerNotFound {
+                name: container.to_string(),
+            },
+        ));
+    }
+
+    let inspect = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let (exit_code, image) = inspect.split_once(' ').unwrap_or((inspect.as_str(), ""));
+
+    let ctx = crash::CrashContext {
+        container: container.to_string(),
+        image: (!image.is_empty()).then(|| image.to_string()),
+        exit_code: exit_code.parse().unwrap_or(-1),
+        pid: live.pid,
+        logs: crash::LogSource::Runtime { runtime },
+        cgroup_path: live.cgroup_path,
+        rootfs_path: live.rootfs_path,
+        status: live.status,
+    };
+
+    let collector = crash::CrashCollector::new(crate::config::BoltConfig::load()?.data_dir, config)?;
+    Ok(collector.collect(&ctx).await?)
 }
 
 pub async fn build_image(path: &str, tag: Option<&str>, dockerfile: &str) -> Result<()> {
+    build_image_with_caches(path, tag, dockerfile, &[]).await
+}
+
+/// Build an image with shared package manager caches mounted (Podman only)
+pub async fn build_image_with_caches(
+    path: &str,
+    tag: Option<&str>,
+    dockerfile: &str,
+    caches: &[crate::builds::artifacts::CacheVolume],
+) -> Result<()> {
+    let runtime = detect_container_runtime().await?;
+    let builder = crate::builds::builders::Builder::default_builder();
+    let annotations = std::collections::BTreeMap::new();
+    run_build(
+        &runtime,
+        &builder,
+        path,
+        tag,
+        dockerfile,
+        caches,
+        &annotations,
+        &[],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Build an image through Bolt's build cache. An unchanged context, built
+/// from the same base images, reuses the image from the last build unless
+/// `no_cache` is set; afterwards the builder's layer cache is pruned back
+/// to the cache's size limit. `extra_args` go to the builder as they are,
+/// e.g. `--build-arg`s.
+#[allow(clippy::too_many_arguments)]
+pub async fn build_image_cached(
+    path: &str,
+    tag: Option<&str>,
+    dockerfile: &str,
+    builder: &crate::builds::builders::Builder,
+    cache: &mut BuildCache,
+    caches: &[crate::builds::artifacts::CacheVolume],
+    annotations: &std::collections::BTreeMap<String, String>,
+    extra_args: &[String],
+    no_cache: bool,
+) -> Result<BuildStats> {
+    use sha2::{Digest, Sha256};
+
+    let runtime = detect_container_runtime().await?;
+
+    // Like the builders, a relative Dockerfile path is tried against the working directory first
+    let dockerfile_path = if std::path::Path::new(dockerfile).exists() {
+        std::path::PathBuf::from(dockerfile)
+    } else {
+        std::path::Path::new(path).join(dockerfile)
+    };
+    // The same context built with other annotations, arguments or base
+    // images is another image
+    let mut hasher = Sha256::new();
+    hasher.update(
+        crate::builds::cache::context_key(std::path::Path::new(path), &dockerfile_path)?.as_bytes(),
+    );
+    for arg in annotations::args(annotations).iter().chain(extra_args) {
+        hasher.update(b"\0");
+        hasher.update(arg.as_bytes());
+    }
+    let build_args: std::collections::BTreeMap<String, String> = extra_args
+        .windows(2)
+        .filter(|pair| pair[0] == "--build-arg")
+        .filter_map(|pair| pair[1].split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let text = std::fs::read_to_string(&dockerfile_path)?;
+    for reference in crate::builds::reproducible::base_references(&text, &build_args) {
+        let base = crate::builds::reproducible::resolve_base(&runtime, &reference).await?;
+        hasher.update(b"\0");
+        hasher.update(format!("{}={}", base.reference, base.image_id).as_bytes());
+    }
+    let key = format!("{:x}", hasher.finalize());
+
+    if !no_cache && let Some(entry) = cache.get(&key).cloned() {
+        if image_exists(&runtime, &entry.image_id).await {
+            info!("♻️  Build context unchanged, reusing image {}", entry.image_id);
+            if let Some(tag) = tag {
+                tag_image(&runtime, &entry.image_id, tag).await?;
+            }
+            cache.record_hit(&key, tag);
+            cache.save()?;
+
+            return Ok(BuildStats {
+                image_id: entry.image_id,
+                cache_hit: true,
+                steps_total: 0,
+                steps_cached: 0,
+                pruned: 0,
+                cache_size: crate::builds::builders::layer_cache_size(&runtime, builder)
+                    .await
+                    .unwrap_or(0),
+                cache_limit: cache.max_size(),
+                counters: cache.counters().clone(),
+            });
+        }
+        cache.remove(&key);
+    }
+
+    cache.record_miss();
+    let mut args = Vec::new();
+    if no_cache {
+        args.push("--no-cache".to_string());
+    }
+    args.extend_from_slice(extra_args);
+    let (image_id, output) = run_build(
+        &runtime,
+        builder,
+        path,
+        tag,
+        dockerfile,
+        caches,
+        annotations,
+        &args,
+    )
+    .await?;
+    let (steps_total, steps_cached) = crate::builds::cache::parse_step_stats(&output);
+
+    let size = image_size(&runtime, &image_id).await.unwrap_or_else(|e| {
+        warn!("Could not determine size of image {}: {}", image_id, e);
+        0
+    });
+    cache.insert(key, image_id.clone(), tag, size);
+    cache.save()?;
+
+    let mut cache_size = crate::builds::builders::layer_cache_size(&runtime, builder)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Could not determine the layer cache size: {}", e);
+            0
+        });
+    let mut pruned = 0;
+    if cache_size > cache.max_size() {
+        match crate::builds::builders::prune_layer_cache(&runtime, builder, cache.max_size(), None)
+            .await
+        {
+            Ok(reclaimed) => {
+                pruned = reclaimed;
+                cache_size = cache_size.saturating_sub(reclaimed);
+            }
+            Err(e) => warn!("Could not prune the layer cache: {}", e),
+        }
+    }
+
+    Ok(BuildStats {
+        image_id,
+        cache_hit: false,
+        steps_total,
+        steps_cached,
+        pruned,
+        cache_size,
+        cache_limit: cache.max_size(),
+        counters: cache.counters().clone(),
+    })
+}
+
+/// Prune a builder's layer cache, first what went unused for longer than
+/// `until`, then the oldest layers until at most `keep_storage` bytes
+/// remain. Images builds produced are never removed. Returns the bytes
+/// reclaimed.
+pub async fn prune_build_cache(
+    builder: &crate::builds::builders::Builder,
+    keep_storage: u64,
+    until: Option<std::time::Duration>,
+) -> Result<u64> {
+    let runtime = detect_container_runtime().await?;
+    Ok(crate::builds::builders::prune_layer_cache(&runtime, builder, keep_storage, until).await?)
+}
+
+/// Build an image without Bolt's build cache or the builder's layer cache;
+/// returns the image ID
+pub async fn build_image_uncached(
+    path: &str,
+    dockerfile: &str,
+    builder: &crate::builds::builders::Builder,
+    annotations: &std::collections::BTreeMap<String, String>,
+    extra_args: &[String],
+) -> Result<String> {
+    let runtime = detect_container_runtime().await?;
+    let mut args = vec!["--no-cache".to_string()];
+    args.extend_from_slice(extra_args);
+    let (image_id, _) = run_build(
+        &runtime,
+        builder,
+        path,
+        None,
+        dockerfile,
+        &[],
+        annotations,
+        &args,
+    )
+    .await?;
+    Ok(image_id)
+}
+
+/// Run the builder; returns the image ID and the combined build output
+#[allow(clippy::too_many_arguments)]
+async fn run_build(
+    runtime: &str,
+    builder: &crate::builds::builders::Builder,
+    path: &str,
+    tag: Option<&str>,
+    dockerfile: &str,
+    caches: &[crate::builds::artifacts::CacheVolume],
+    annotations: &std::collections::BTreeMap<String, String>,
+    extra_args: &[String],
+) -> Result<(String, String)> {
     info!("🔨 Building image from path: {}", path);
     debug!("Dockerfile: {}", dockerfile);
+    if !builder.is_default() {
+        info!("  🏗️  Builder: {}", builder.name);
+    }
     if let Some(tag) = tag {
         debug!("Tag: {}", tag);
     }
 
-    let runtime = detect_container_runtime().await?;
-    let mut cmd = AsyncCommand::new(&runtime);
-    cmd.arg("build");
+    let iidfile = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = AsyncCommand::new(runtime);
+    cmd.args(builder.build_args(runtime));
+    cmd.args(crate::builds::artifacts::build_args(runtime, caches));
+    cmd.args(annotations::args(annotations));
+    cmd.args(extra_args);
 
     if let Some(tag) = tag {
         cmd.arg("-t").arg(tag);
     }
 
+    cmd.arg("--iidfile").arg(iidfile.path());
     cmd.arg("-f").arg(dockerfile);
     cmd.arg(path);
 
     let output = cmd.output().await?;
 
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            path,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to build image: {}", stderr),
+            },
+        )));
+    }
+
+    let image_id = std::fs::read_to_string(iidfile.path())?.trim().to_string();
+    let build_output = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    info!("✅ Image built successfully");
+    Ok((image_id, build_output))
+}
+
+async fn image_exists(runtime: &str, image: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["image", "inspect", "--format", "{{.Id}}"])
+        .arg(image)
+        .output()
+        .await
+        .is_ok_and(|o| o.status.success())
+}
+
+async fn image_size(runtime: &str, image: &str) -> Result<u64> {
+    let output = AsyncCommand::new(runtime)
+        .args(["image", "inspect", "--format", "{{.Size}}"])
+        .arg(image)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(BoltError::Runtime(
+            crate::error::RuntimeError::ImageNotFound {
+                image: image.to_string(),
+            },
+        ));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|e| BoltError::Other(anyhow::anyhow!("Unexpected image size output: {}", e)))
+}
+
+async fn tag_image(runtime: &str, image: &str, tag: &str) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .arg("tag")
+        .arg(image)
+        .arg(tag)
+        .output()
+        .await?;
+
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-            message: format!("Failed to build image: {}", stderr),
+            message: format!("Failed to tag image {} as {}: {}", image, tag, stderr),
         }));
     }
 
-    info!("✅ Image built successfully");
     Ok(())
 }
 
-pub async fn pull_image(image: &str) -> Result<()> {
+pub async fn pull_image(image: &str, choice: &platform::PlatformChoice) -> Result<()> {
     info!("⬇️  Pulling image: {}", image);
 
     let runtime = detect_container_runtime().await?;
+    let selected = platform::resolve(&runtime, image, choice).await?;
     let mut cmd = AsyncCommand::new(&runtime);
-    cmd.arg("pull").arg(image);
+    cmd.arg("pull");
+    if let Some(ref selected) = selected {
+        cmd.arg("--platform").arg(selected.to_string());
+    }
+    cmd.arg(image);
 
     let output = cmd.output().await?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            image,
+            &stderr,
             crate::error::RuntimeError::ImagePullFailed {
                 image: format!("Failed to pull image: {}", stderr),
             },
-        ));
+        )));
     }
 
+    platform::verify_local(&runtime, image, selected.as_ref(), choice).await?;
+
     info!("✅ Image pulled successfully: {}", image);
     Ok(())
 }
 
 pub async fn push_image(image: &str) -> Result<()> {
+    push_image_tagged(
+        image,
+        &[],
+        &std::collections::BTreeMap::new(),
+        &std::env::temp_dir(),
+    )
+    .await
+}
+
+/// Push a local image with Bolt's registry client, also under `extra_tags`,
+/// exporting it as an OCI layout in a scratch directory under `scratch` first.
+/// `annotations` are added to the pushed manifest.
+pub async fn push_image_tagged(
+    image: &str,
+    extra_tags: &[String],
+    annotations: &std::collections::BTreeMap<String, String>,
+    scratch: &std::path::Path,
+) -> Result<()> {
     info!("⬆️  Pushing image: {}", image);
 
     let runtime = detect_container_runtime().await?;
-    let mut cmd = AsyncCommand::new(&runtime);
-    cmd.arg("push").arg(image);
-
-    let output = cmd.output().await?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-            message: format!("Failed to push image: {}", stderr),
-        }));
-    }
-
-    info!("✅ Image pushed successfully: {}", image);
+    std::fs::create_dir_all(scratch)?;
+    let export = tempfile::Builder::new()
+        .prefix("bolt-push-")
+        .tempdir_in(scratch)?;
+    let layout = storage::registry::export_layout(&runtime, image, export.path()).await?;
+    storage::registry::annotate_layout(&layout, annotations)?;
+    let mut client =
+        storage::registry::RegistryClient::new(storage::registry::Reference::parse(image)?)?;
+    let digest = client.push_layout(&layout, extra_tags).await.map_err(|e| {
+        BoltError::Runtime(crate::error::RuntimeError::OciError {
+            message: format!("Failed to push image {}: {:#}", image, e),
+        })
+    })?;
+
+    info!("✅ Image pushed successfully: {} ({})", image, digest);
     Ok(())
 }
 
@@ -280,6 +719,8 @@ pub async fn list_containers_info(all: bool) -> Result<Vec<ContainerInfo>> {
         }
 
         if let Ok(value) = serde_json::from_str::<serde_json::Value>(line) {
+            let labels = container_labels(value.get("Labels"));
+            let runtime = labels.get("bolt.runtime").cloned();
             let container = ContainerInfo {
                 id: value
                     .get("Id")
@@ -308,8 +749,8 @@ pub async fn list_containers_info(all: bool) -> Result<Vec<ContainerInfo>> {
                     .and_then(|v| v.as_str())
                     .unwrap_or("")
                     .to_string(),
-                labels: HashMap::new(), // TODO: Parse from container labels
-                uptime: None,           // TODO: Calculate uptime
+                labels,
+                uptime: None, // TODO: Calculate uptime
                 command: value
                     .get("Command")
                     .and_then(|v| v.as_str())
@@ -332,11 +773,7 @@ pub async fn list_containers_info(all: bool) -> Result<Vec<ContainerInfo>> {
                     .split(',')
                     .map(|s| s.trim().to_string())
                     .collect(),
-                runtime: value
-                    .get("Labels")
-                    .and_then(|labels| labels.get("bolt.runtime"))
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string()),
+                runtime,
             };
             containers.push(container);
         }
@@ -345,6 +782,51 @@ pub async fn list_containers_info(all: bool) -> Result<Vec<ContainerInfo>> {
     Ok(containers)
 }
 
+/// Labels in `ps --format json`: an object under podman, a comma-separated
+/// `key=value` string under docker
+fn container_labels(value: Option<&serde_json::Value>) -> HashMap<String, String> {
+    match value {
+        Some(serde_json::Value::Object(map)) => map
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+            .collect(),
+        Some(serde_json::Value::String(joined)) => joined
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// The runtime's `inspect` output for a container
+pub async fn inspect_container(container: &str) -> Result<serde_json::Value> {
+    let runtime = detect_container_runtime().await?;
+    let output = AsyncCommand::new(&runtime)
+        .args(["container", "inspect"])
+        .arg(container)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::ContainerNotFound {
+                name: container.to_string(),
+            },
+        )));
+    }
+
+    let mut inspect: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    // Both runtimes wrap the result in a one-element array
+    Ok(match inspect.as_array_mut() {
+        Some(items) if items.len() == 1 => items.remove(0),
+        _ => inspect,
+    })
+}
+
 pub async fn stop_container(container: &str) -> Result<()> {
     info!("🛑 Stopping container: {}", container);
 
@@ -356,15 +838,65 @@ pub async fn stop_container(container: &str) -> Result<()> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-            message: format!("Failed to stop container: {}", stderr),
-        }));
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to stop container: {}", stderr),
+            },
+        )));
     }
 
     info!("✅ Container stopped: {}", container);
     Ok(())
 }
 
+/// Stop a container, giving it `timeout` to exit after SIGTERM before it is
+/// killed. Returns whether it had to be killed.
+pub async fn stop_container_within(container: &str, timeout: std::time::Duration) -> Result<bool> {
+    info!(
+        "🛑 Stopping container: {} (grace period {}s)",
+        container,
+        timeout.as_secs()
+    );
+
+    let runtime = detect_container_runtime().await?;
+    let started = std::time::Instant::now();
+    let output = AsyncCommand::new(&runtime)
+        .args(["stop", "-t", &timeout.as_secs().to_string(), container])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to stop container: {}", stderr),
+            },
+        )));
+    }
+
+    // SIGKILL shows as exit code 137; only count it when the grace period ran out
+    let killed = started.elapsed() >= timeout
+        && AsyncCommand::new(&runtime)
+            .args(["inspect", "--format", "{{.State.ExitCode}}", container])
+            .output()
+            .await
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "137");
+    if killed {
+        warn!(
+            "⏱️  {} did not exit within {}s and was killed",
+            container,
+            timeout.as_secs()
+        );
+    } else {
+        info!("✅ Container stopped: {}", container);
+    }
+    Ok(killed)
+}
+
 pub async fn remove_container(container: &str, force: bool) -> Result<()> {
     info!("🗑️  Removing container: {} (force: {})", container, force);
 
@@ -382,9 +914,13 @@ pub async fn remove_container(container: &str, force: bool) -> Result<()> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-            message: format!("Failed to remove container: {}", stderr),
-        }));
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to remove container: {}", stderr),
+            },
+        )));
     }
 
     info!("✅ Container removed: {}", container);
@@ -408,11 +944,215 @@ pub async fn restart_container(container: &str, timeout: u64) -> Result<()> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
-            message: format!("Failed to restart container: {}", stderr),
-        }));
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to restart container: {}", stderr),
+            },
+        )));
     }
 
     info!("✅ Container restarted: {}", container);
     Ok(())
 }
+
+/// Change a running container's resource limits without restarting it.
+/// Containers with a bolt cgroup are updated in place, I/O limits applying to
+/// the disk holding `io_path`; others go through `<runtime> update`.
+pub async fn update_container(
+    container: &str,
+    limits: &oci::ResourceLimits,
+    io_path: &std::path::Path,
+) -> Result<()> {
+    info!("📊 Updating resources of container: {}", container);
+
+    let cgroup = cgroups::ContainerCgroup::new(cgroups::CgroupLayout::current(), container);
+    if cgroup.exists() {
+        let io_device =
+            match limits.io_limit {
+                Some(_) => Some(cgroups::block_device(io_path).ok_or_else(|| {
+                    anyhow::anyhow!("No block device behind {}", io_path.display())
+                })?),
+                None => None,
+            };
+        cgroup.apply(limits, io_device)?;
+        info!("✅ Container updated: {} ({})", container, cgroup);
+        return Ok(());
+    }
+
+    let runtime = detect_container_runtime().await?;
+    if limits.io_limit.is_some() {
+        return Err(BoltError::Other(anyhow::anyhow!(
+            "{} update cannot limit IOPS; only containers with a bolt cgroup can",
+            runtime
+        )));
+    }
+    let mut cmd = AsyncCommand::new(&runtime);
+    cmd.arg("update");
+    if let Some(bytes) = limits.memory_limit {
+        // docker refuses a memory limit above the current swap limit
+        cmd.arg("--memory").arg(bytes.to_string());
+        cmd.arg("--memory-swap").arg("-1");
+    }
+    if let Some(cores) = limits.cpu_limit {
+        cmd.arg("--cpus").arg(cores.to_string());
+    }
+    if let Some(max) = limits.pids_limit {
+        cmd.arg("--pids-limit").arg(max.to_string());
+    }
+    if let Some(shares) = limits.cpu_shares {
+        cmd.arg("--cpu-shares").arg(shares.to_string());
+    }
+    if let Some(weight) = limits.blkio_weight {
+        cmd.arg("--blkio-weight").arg(weight.to_string());
+    }
+    cmd.arg(container);
+
+    let output = cmd.output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::OciError {
+                message: format!("Failed to update container: {}", stderr),
+            },
+        )));
+    }
+
+    info!("✅ Container updated: {}", container);
+    Ok(())
+}
+
+/// How `exec_container` runs a command
+#[derive(Debug, Clone, Default)]
+pub struct ExecOptions {
+    /// Keep stdin open and stream it to the command
+    pub interactive: bool,
+    /// Allocate a pseudo-terminal for the command
+    pub tty: bool,
+    /// Extra environment, as KEY=VALUE
+    pub env: Vec<String>,
+    pub workdir: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Run a command in a running container with this process's stdin, stdout
+/// and stderr, returning the command's exit code
+pub async fn exec_container(
+    container: &str,
+    command: &[String],
+    options: &ExecOptions,
+) -> Result<i32> {
+    let mut cmd = exec_command(container, command, options).await?;
+    Ok(exit_code(cmd.status().await?))
+}
+
+/// What a command run in a container printed
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExecOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run a command in a running container without stdin or a TTY, capturing
+/// its output
+pub async fn exec_container_output(
+    container: &str,
+    command: &[String],
+    options: &ExecOptions,
+) -> Result<ExecOutput> {
+    let options = ExecOptions {
+        interactive: false,
+        tty: false,
+        ..options.clone()
+    };
+    let output = exec_command(container, command, &options)
+        .await?
+        .output()
+        .await?;
+    Ok(ExecOutput {
+        exit_code: exit_code(output.status),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+/// The runtime's `exec` of `command` in `container`, once it is running
+async fn exec_command(
+    container: &str,
+    command: &[String],
+    options: &ExecOptions,
+) -> Result<AsyncCommand> {
+    use std::io::IsTerminal;
+
+    if command.is_empty() {
+        return Err(BoltError::Other(anyhow::anyhow!(
+            "No command given to run in {}",
+            container
+        )));
+    }
+
+    let runtime = detect_container_runtime().await?;
+    let state = AsyncCommand::new(&runtime)
+        .args(["container", "inspect", "--format", "{{.State.Running}}"])
+        .arg(container)
+        .output()
+        .await?;
+    if !state.status.success() {
+        let stderr = String::from_utf8_lossy(&state.stderr);
+        return Err(BoltError::Runtime(crate::error::RuntimeError::classify(
+            container,
+            &stderr,
+            crate::error::RuntimeError::ContainerNotFound {
+                name: container.to_string(),
+            },
+        )));
+    }
+    if String::from_utf8_lossy(&state.stdout).trim() != "true" {
+        return Err(BoltError::Runtime(crate::error::RuntimeError::OciError {
+            message: format!("Container {} is not running", container),
+        }));
+    }
+
+    debug!("Executing in {}: {:?}", container, command);
+    let mut cmd = AsyncCommand::new(&runtime);
+    cmd.arg("exec");
+    if options.interactive {
+        cmd.arg("-i");
+    } else {
+        cmd.stdin(std::process::Stdio::null());
+    }
+    if options.tty {
+        // The runtime refuses a TTY over piped input
+        if options.interactive && !std::io::stdin().is_terminal() {
+            warn!("stdin is not a terminal, running without a TTY");
+        } else {
+            cmd.arg("-t");
+        }
+    }
+    for var in &options.env {
+        cmd.arg("-e").arg(var);
+    }
+    if let Some(workdir) = &options.workdir {
+        cmd.arg("-w").arg(workdir);
+    }
+    if let Some(user) = &options.user {
+        cmd.arg("-u").arg(user);
+    }
+    cmd.arg(container).args(command);
+    Ok(cmd)
+}
+
+/// A command's exit code; killed by a signal, reported the way a shell would
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
@@ -8,6 +8,13 @@ pub struct CDISpec {
     pub cdi_version: String,
     pub kind: String,
     pub devices: Vec<CDIDevice>,
+    /// Edits applied whenever any of the spec's devices is requested
+    #[serde(
+        rename = "containerEdits",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub container_edits: Option<CDIContainerEdits>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,20 +24,29 @@ pub struct CDIDevice {
     pub container_edits: CDIContainerEdits,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CDIContainerEdits {
-    #[serde(rename = "deviceNodes")]
+    #[serde(rename = "deviceNodes", default)]
     pub device_nodes: Vec<CDIDeviceNode>,
+    #[serde(default)]
     pub mounts: Vec<CDIMount>,
+    #[serde(default)]
     pub env: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<CDIHook>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CDIDeviceNode {
     pub path: String,
-    #[serde(rename = "type")]
+    /// Host node when it differs from `path`
+    #[serde(rename = "hostPath", default, skip_serializing_if = "Option::is_none")]
+    pub host_path: Option<String>,
+    #[serde(rename = "type", default)]
     pub device_type: String,
+    #[serde(default)]
     pub major: u32,
+    #[serde(default)]
     pub minor: u32,
 }
 
@@ -40,9 +56,23 @@ pub struct CDIMount {
     pub host_path: String,
     #[serde(rename = "containerPath")]
     pub container_path: String,
+    #[serde(default)]
     pub options: Vec<String>,
 }
 
+/// OCI hook run at a container lifecycle stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CDIHook {
+    /// OCI stage, e.g. "createContainer"
+    #[serde(rename = "hookName")]
+    pub hook_name: String,
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+}
+
 /// AMD GPU information structure
 #[derive(Debug, Clone)]
 pub struct AMDGPUInfo {
@@ -11,25 +11,43 @@ use tracing::{debug, error, info, warn};
 use super::{ContainerState, ResourceLimits, ContainerConfig};
 use crate::runtime::nvbind::{NvbindRuntime, NvbindConfig, GpuRequest, create_nvbind_config_for_gaming};
 use crate::config::{Service, GamingConfig};
+use crate::runtime::cgroups::{self, CgroupLayout, CgroupMode, ContainerCgroup, Controller};
+use crate::runtime::rootless;
+use crate::runtime::startup::{Phase, StartupTimer};
 use nix::libc;
 
-pub async fn execute_container(state: &ContainerState, spec: &Spec) -> Result<u32> {
+pub async fn execute_container(
+    state: &ContainerState,
+    spec: &Spec,
+    timer: &mut StartupTimer,
+) -> Result<u32> {
     info!("🚀 Executing container: {}", state.id);
 
     // Create the container rootfs from image layers
+    timer.phase(Phase::Rootfs);
     create_container_rootfs(&state.id, &state.bundle_path, spec).await?;
 
     // Check for gaming configuration and nvbind GPU runtime
     if let Some(ref gaming_config) = state.config.gaming_config {
         if gaming_config.gpu.is_some() && is_nvbind_runtime(&state.config) {
             info!("🎮 Using nvbind GPU runtime for gaming container");
+            timer.phase(Phase::Gpu);
             return execute_nvbind_container(state, spec, gaming_config).await;
         }
     }
 
     // Setup the execution environment in proper order
+    timer.phase(Phase::Network);
     let namespaces = setup_namespaces(state, spec).await?;
-    setup_mounts(state, spec).await?;
+    timer.phase(Phase::Rootfs);
+    if state.config.privileged || nix::unistd::getuid().is_root() {
+        setup_mounts(state, spec).await?;
+    } else {
+        // Made in the container's own mount namespace, which Bolt can't
+        // mount into without root
+        create_essential_dirs(&state.bundle_path.join("rootfs")).await?;
+    }
+    timer.phase(Phase::Exec);
     setup_cgroups(state).await?;
     setup_security_profile(state).await?;
 
@@ -120,10 +138,34 @@ async fn execute_with_namespaces(
     // Unshare into new namespaces
     unshare(clone_flags).context("Failed to unshare namespaces")?;
 
+    if namespaces.uts_ns {
+        set_uts_names(spec)?;
+    }
+
     info!("✅ Namespaces created successfully");
 
     // Now execute the container process in the new namespaces
-    execute_container_process(state, spec).await
+    execute_container_process(state, spec, None).await
+}
+
+/// Apply the spec's hostname and domainname inside the new UTS namespace
+fn set_uts_names(spec: &Spec) -> Result<()> {
+    if let Some(hostname) = spec.hostname() {
+        // SAFETY: the pointer and length describe a live byte slice
+        let rc = unsafe { libc::sethostname(hostname.as_ptr().cast(), hostname.len()) };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set container hostname");
+        }
+        info!("  🏠 Hostname: {}", hostname);
+    }
+    if let Some(domainname) = spec.domainname() {
+        // SAFETY: as above
+        let rc = unsafe { libc::setdomainname(domainname.as_ptr().cast(), domainname.len()) };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set container domainname");
+        }
+    }
+    Ok(())
 }
 
 async fn execute_simple_container(
@@ -185,7 +227,11 @@ async fn execute_simple_container(
     Ok(pid)
 }
 
-async fn execute_container_process(state: &ContainerState, spec: &Spec) -> Result<u32> {
+async fn execute_container_process(
+    state: &ContainerState,
+    spec: &Spec,
+    rootless: Option<RootlessProcess>,
+) -> Result<u32> {
     info!("🏃 Executing container process in namespaces");
 
     let process = spec.process().as_ref().unwrap();
@@ -195,10 +241,26 @@ async fn execute_container_process(state: &ContainerState, spec: &Spec) -> Resul
 
     let rootfs_path = state.bundle_path.join("rootfs");
 
-    // Change root filesystem using pivot_root for proper isolation
-    setup_pivot_root(&rootfs_path).await?;
+    // Container output is kept out of the daemon log; open its log file while host paths still resolve
+    let container_log = match &state.log_dir {
+        Some(dir) => crate::monitoring::logging::open_container_log(dir, &state.id)
+            .map_err(|e| warn!("Output of {} will not be persisted: {}", state.id, e))
+            .ok(),
+        None => {
+            info!(
+                "Output of {} will not be persisted: the runtime has no container log directory",
+                state.id
+            );
+            None
+        }
+    };
 
-    info!("✅ Changed root to container filesystem with pivot_root");
+    // Change root filesystem using pivot_root for proper isolation; a
+    // rootless process pivots itself once inside its user namespace
+    if rootless.is_none() {
+        setup_pivot_root(&rootfs_path).await?;
+        info!("✅ Changed root to container filesystem with pivot_root");
+    }
 
     // Apply process-level security before exec
     apply_process_security(state, spec).await?;
@@ -207,8 +269,8 @@ async fn execute_container_process(state: &ContainerState, spec: &Spec) -> Resul
     let mut cmd = Command::new(command);
     cmd.args(command_args)
         .stdin(Stdio::null())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit());
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
 
     // Set environment variables from spec
     cmd.env_clear(); // Start with clean environment
@@ -242,24 +304,73 @@ async fn execute_container_process(state: &ContainerState, spec: &Spec) -> Resul
         // GID setting is handled by the process spawning
     }
 
+    // Keys in Bolt's own session keyring must not reach the container
+    let keyring =
+        crate::runtime::keyring::ContainerKeyring::prepare(state.config.keyring.as_ref())?;
+    let (entry, network) = rootless.map(|r| (r.entry, r.network)).unzip();
+    let forks = entry.as_ref().is_some_and(rootless::NamespaceEntry::forks);
+    // SAFETY: enter() only makes syscalls, which is safe between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(ref entry) = entry {
+                entry.enter()?;
+            }
+            keyring.enter()
+        });
+    }
+
     // Add container to its cgroup before exec
     add_process_to_cgroup(state).await?;
 
     // Spawn the process
-    let child = cmd.spawn().context("Failed to spawn container process")?;
+    let mut child = cmd.spawn().context("Failed to spawn container process")?;
     let pid = child.id().context("Failed to get child PID")?;
 
     // Write PID to cgroup.procs for resource management
     write_pid_to_cgroup(state, pid).await?;
+    // The rootless PID 1 was forked before its parent joined the cgroup
+    if forks && let Some(init) = rootless::namespace_init(pid) {
+        write_pid_to_cgroup(state, init).await?;
+    }
+
+    let log_file = container_log.map(|(_, file)| file);
+    let forwarders = forward_output(&mut child, &state.id, log_file);
+
+    let network = match network.flatten() {
+        Some(backend) => match crate::networking::rootless::RootlessNetwork::attach(
+            backend,
+            pid,
+            &state.config.ports,
+            &state.bundle_path,
+        )
+        .await
+        {
+            Ok(network) => Some(network),
+            Err(e) => {
+                warn!("⚠️  {} has no network: {:#}", state.id, e);
+                None
+            }
+        },
+        None => None,
+    };
 
     info!("✅ Container process started with PID: {} (fully isolated)", pid);
 
     // Store child process for monitoring
     let container_id = state.id.clone();
     tokio::spawn(async move {
-        match child.wait_with_output().await {
-            Ok(output) => {
-                let code = output.status.code().unwrap_or(-1);
+        let status = child.wait().await;
+        if let Some(network) = network {
+            network.stop().await;
+        }
+        // Drain remaining output so the log is complete
+        for forwarder in forwarders {
+            let _ = forwarder.await;
+        }
+
+        match status {
+            Ok(status) => {
+                let code = status.code().unwrap_or(-1);
                 info!("Container {} (PID {}) exited with code: {}", container_id, pid, code);
 
                 // Clean up resources when container exits
@@ -277,79 +388,125 @@ async fn execute_container_process(state: &ContainerState, spec: &Spec) -> Resul
 async fn execute_rootless_container(
     state: &ContainerState,
     spec: &Spec,
-    mut namespaces: NamespaceConfig,
+    namespaces: NamespaceConfig,
 ) -> Result<u32> {
     info!("🔒 Executing rootless container: {}", state.id);
 
-    // Enable user namespace for rootless execution
-    namespaces.user_ns = true;
-
-    // Setup user namespace mappings
-    setup_user_namespace_mappings().await?;
+    validate_rootless_prerequisites(nix::unistd::getuid(), nix::unistd::getgid()).await?;
 
     // Apply rootless-specific security measures
     setup_rootless_security(state).await?;
 
-    // Use regular namespace execution with rootless configuration
-    execute_with_namespaces(state, spec, &namespaces).await
-}
-
-async fn setup_user_namespace_mappings() -> Result<()> {
-    info!("👤 Setting up user namespace mappings for rootless container");
-
-    let current_uid = nix::unistd::getuid();
-    let current_gid = nix::unistd::getgid();
-
-    // Validate that we can create user namespace mappings
-    validate_rootless_prerequisites(current_uid, current_gid).await?;
-
-    // Check for existing mappings (might already be in a user namespace)
-    if let Ok(existing_uid_map) = std::fs::read_to_string("/proc/self/uid_map") {
-        if !existing_uid_map.trim().is_empty() && existing_uid_map != format!("0 {} 1", current_uid) {
-            info!("⚠️  Already in user namespace, using existing mappings");
-            return Ok(());
+    // The container process joins this namespace and unshares the others
+    let userns = rootless::UserNamespace::create()?;
+    let mut flags = 0;
+    for (enabled, flag) in [
+        (namespaces.pid_ns, libc::CLONE_NEWPID),
+        (namespaces.net_ns, libc::CLONE_NEWNET),
+        (namespaces.mount_ns, libc::CLONE_NEWNS),
+        (namespaces.ipc_ns, libc::CLONE_NEWIPC),
+        (namespaces.uts_ns, libc::CLONE_NEWUTS),
+    ] {
+        if enabled {
+            flags |= flag;
         }
     }
+    let entry = userns.entry(
+        flags,
+        &state.bundle_path.join("rootfs"),
+        &rootless_mounts(state, spec, &namespaces),
+        spec.hostname().as_deref(),
+    )?;
+    let network = if namespaces.net_ns {
+        let backend = crate::networking::rootless::Backend::detect();
+        if backend.is_none() {
+            warn!(
+                "⚠️  Neither pasta nor slirp4netns is installed, {} has no network",
+                state.id
+            );
+        }
+        backend
+    } else {
+        None
+    };
 
-    // Map current user to root inside container (standard rootless pattern)
-    let uid_map = format!("0 {} 1", current_uid);
-    let gid_map = format!("0 {} 1", current_gid);
-
-    info!("Setting UID mapping: {}", uid_map);
-    info!("Setting GID mapping: {}", gid_map);
+    execute_container_process(state, spec, Some(RootlessProcess { entry, network })).await
+}
 
-    // Write UID mapping with validation
-    if let Err(e) = std::fs::write("/proc/self/uid_map", &uid_map) {
-        return Err(anyhow::anyhow!(
-            "Failed to write uid_map for rootless container: {}. This may indicate insufficient privileges or kernel restrictions.",
-            e
-        ));
-    }
+/// How a rootless container process enters its namespaces
+struct RootlessProcess {
+    entry: rootless::NamespaceEntry,
+    network: Option<crate::networking::rootless::Backend>,
+}
 
-    // Deny setgroups (required for rootless) - this must be done before GID mapping
-    if let Err(e) = std::fs::write("/proc/self/setgroups", "deny") {
-        return Err(anyhow::anyhow!(
-            "Failed to deny setgroups for rootless container: {}. This is required for rootless operation.",
-            e
-        ));
+/// The spec's mounts and the gaming devices, made inside the rootless
+/// container's mount namespace. procfs and sysfs can only be mounted fresh
+/// in the container's own PID and network namespaces; without them the
+/// host's are bind mounted.
+fn rootless_mounts(
+    state: &ContainerState,
+    spec: &Spec,
+    namespaces: &NamespaceConfig,
+) -> Vec<rootless::RootlessMount> {
+    use rootless::RootlessMount;
+
+    let mut mounts = Vec::new();
+    for mount in spec.mounts().iter().flatten() {
+        let target = mount.destination().clone();
+        match mount.typ().as_deref().unwrap_or("bind") {
+            "proc" if namespaces.pid_ns => mounts.push(RootlessMount::proc(target)),
+            "proc" => mounts.push(RootlessMount::bind("/proc", target)),
+            "sysfs" if namespaces.net_ns => mounts.push(RootlessMount::sysfs(target)),
+            "sysfs" => mounts.push(RootlessMount::bind("/sys", target)),
+            "tmpfs" => mounts.push(RootlessMount::tmpfs(target)),
+            "bind" => match mount.source() {
+                Some(source) if source.exists() => mounts.push(RootlessMount::bind(source, target)),
+                _ => warn!("Bind mount source of {} does not exist", target.display()),
+            },
+            other => debug!(
+                "Skipping the {} mount at {} in a rootless container",
+                other,
+                target.display()
+            ),
+        }
     }
 
-    // Write GID mapping with validation
-    if let Err(e) = std::fs::write("/proc/self/gid_map", &gid_map) {
-        return Err(anyhow::anyhow!(
-            "Failed to write gid_map for rootless container: {}. Check that setgroups was properly denied.",
-            e
-        ));
+    if let Some(ref gaming) = state.config.gaming_config {
+        let mut devices = Vec::new();
+        if gaming
+            .gpu
+            .as_ref()
+            .is_some_and(|gpu| gpu.passthrough == Some(true))
+        {
+            devices.push(PathBuf::from("/dev/dri"));
+            devices.extend(
+                fs::read_dir("/dev")
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|e| e.file_name().to_string_lossy().starts_with("nvidia"))
+                    .map(|e| e.path()),
+            );
+        }
+        if gaming.audio.is_some() {
+            devices.push(PathBuf::from("/dev/snd"));
+        }
+        devices.push(PathBuf::from("/tmp/.X11-unix"));
+        if let Ok(display) = std::env::var("WAYLAND_DISPLAY") {
+            devices.push(PathBuf::from(format!(
+                "/run/user/{}/{}",
+                nix::unistd::getuid(),
+                display
+            )));
+        }
+        mounts.extend(
+            devices
+                .into_iter()
+                .filter(|path| path.exists())
+                .map(|path| RootlessMount::bind(path.clone(), path)),
+        );
     }
-
-    // Verify the mappings were applied correctly
-    verify_user_namespace_mappings(current_uid, current_gid).await?;
-
-    info!(
-        "✅ User namespace mappings configured and verified: uid {} -> 0, gid {} -> 0",
-        current_uid, current_gid
-    );
-    Ok(())
+    mounts
 }
 
 async fn setup_rootless_security(state: &ContainerState) -> Result<()> {
@@ -799,116 +956,67 @@ async fn mount_display_sockets(rootfs_path: &std::path::Path) -> Result<()> {
 }
 
 async fn setup_cgroups(state: &ContainerState) -> Result<()> {
-    info!("📊 Setting up cgroups v2 for container: {}", state.id);
-
-    let limits = &state.config.resource_limits;
-    let cgroup_path = format!("/sys/fs/cgroup/bolt/{}", state.id);
+    let layout = CgroupLayout::current();
+    info!("📊 Setting up cgroups {} for container: {}", layout.mode, state.id);
 
-    // Check if cgroups v2 is available
-    if !std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
-        warn!("Cgroups v2 not available, skipping resource limits");
+    if layout.mode == CgroupMode::Unavailable {
+        warn!("No cgroup hierarchy mounted, skipping resource limits");
         return Ok(());
     }
+    layout.warn_degradations();
 
-    // Ensure parent bolt cgroup exists
-    let bolt_cgroup = "/sys/fs/cgroup/bolt";
-    if !std::path::Path::new(bolt_cgroup).exists() {
-        fs::create_dir_all(bolt_cgroup)?;
-
-        // Enable necessary controllers for bolt cgroup
-        if let Err(e) = enable_cgroup_controllers(bolt_cgroup, &["cpu", "memory", "pids"]).await {
-            warn!("Failed to enable controllers for bolt cgroup: {}", e);
-        }
-    }
+    let limits = &state.config.resource_limits;
+    let cgroup = ContainerCgroup::new(layout, &state.id);
 
-    // Create container-specific cgroup directory
-    if let Err(e) = fs::create_dir_all(&cgroup_path) {
-        warn!("Failed to create cgroup directory: {} - {}", cgroup_path, e);
+    // Create the container cgroup with the controllers its limits need
+    let controllers = determine_required_controllers(limits, &state.config);
+    if let Err(e) = cgroup.create(&controllers) {
+        warn!("Failed to create cgroup for {}: {}", state.id, e);
         return Ok(()); // Don't fail container creation if cgroups fail
     }
 
-    info!("✅ Created cgroup: {}", cgroup_path);
-
-    // Enable controllers for this container
-    let controllers_to_enable = determine_required_controllers(limits, &state.config);
-    if let Err(e) = enable_cgroup_controllers(&cgroup_path, &controllers_to_enable).await {
-        warn!("Failed to enable cgroup controllers: {}", e);
-    }
+    info!("✅ Created cgroup: {}", cgroup);
 
-    // Set resource limits with validation
-    if let Some(memory_limit) = limits.memory_limit {
-        if let Err(e) = set_memory_limit(&cgroup_path, memory_limit).await {
-            warn!("Failed to set memory limit: {}", e);
-        }
-    }
-
-    if let Some(cpu_limit) = limits.cpu_limit {
-        if let Err(e) = set_cpu_limit(&cgroup_path, cpu_limit).await {
-            warn!("Failed to set CPU limit: {}", e);
-        }
-    }
-
-    if let Some(pids_limit) = limits.pids_limit {
-        if let Err(e) = set_pids_limit(&cgroup_path, pids_limit).await {
-            warn!("Failed to set PIDs limit: {}", e);
-        }
+    // Set resource limits; one that fails doesn't stop the others
+    log_limits(limits);
+    let io_device = limits
+        .io_limit
+        .and_then(|_| cgroups::block_device(&state.bundle_path));
+    if let Err(e) = cgroup.apply(limits, io_device) {
+        warn!("{:#}", e);
     }
 
     // Gaming-specific optimizations
     if let Some(ref gaming) = state.config.gaming_config {
-        if let Err(e) = setup_gaming_cgroups(&cgroup_path, gaming).await {
+        if let Err(e) = setup_gaming_cgroups(&cgroup, gaming).await {
             warn!("Failed to set up gaming cgroups: {}", e);
         }
     }
 
-    // Set up I/O limits if supported
-    setup_io_limits(&cgroup_path, limits).await?;
-
-    info!("✅ Cgroups v2 configured successfully");
+    info!("✅ Cgroups {} configured successfully", layout.mode);
     Ok(())
 }
 
-async fn set_memory_limit(cgroup_path: &str, limit_bytes: u64) -> Result<()> {
-    info!(
-        "💾 Setting memory limit: {:.1} MB",
-        limit_bytes as f64 / 1024.0 / 1024.0
-    );
-
-    let memory_max_path = format!("{}/memory.max", cgroup_path);
-    fs::write(&memory_max_path, limit_bytes.to_string())
-        .with_context(|| format!("Failed to set memory limit: {}", memory_max_path))?;
-
-    Ok(())
-}
-
-async fn set_cpu_limit(cgroup_path: &str, cpu_cores: f64) -> Result<()> {
-    info!("⚙️  Setting CPU limit: {:.2} cores", cpu_cores);
-
-    // Convert cores to microseconds (1 core = 100000 microseconds per 100ms period)
-    let quota_us = (cpu_cores * 100000.0) as u64;
-    let period_us = 100000u64; // 100ms period
-
-    let cpu_max_path = format!("{}/cpu.max", cgroup_path);
-    let cpu_limit_str = format!("{} {}", quota_us, period_us);
-
-    fs::write(&cpu_max_path, &cpu_limit_str)
-        .with_context(|| format!("Failed to set CPU limit: {}", cpu_max_path))?;
-
-    Ok(())
-}
-
-async fn set_pids_limit(cgroup_path: &str, max_pids: u32) -> Result<()> {
-    info!("🏃 Setting PIDs limit: {}", max_pids);
-
-    let pids_max_path = format!("{}/pids.max", cgroup_path);
-    fs::write(&pids_max_path, max_pids.to_string())
-        .with_context(|| format!("Failed to set PIDs limit: {}", pids_max_path))?;
-
-    Ok(())
+fn log_limits(limits: &ResourceLimits) {
+    if let Some(limit_bytes) = limits.memory_limit {
+        info!(
+            "💾 Setting memory limit: {:.1} MB",
+            limit_bytes as f64 / 1024.0 / 1024.0
+        );
+    }
+    if let Some(cpu_cores) = limits.cpu_limit {
+        info!("⚙️  Setting CPU limit: {:.2} cores", cpu_cores);
+    }
+    if let Some(max_pids) = limits.pids_limit {
+        info!("🏃 Setting PIDs limit: {}", max_pids);
+    }
+    if let Some(io_limit) = limits.io_limit {
+        info!("💿 Setting I/O limit: {} IOPS", io_limit);
+    }
 }
 
 async fn setup_gaming_cgroups(
-    cgroup_path: &str,
+    cgroup: &ContainerCgroup,
     gaming: &crate::config::GamingConfig,
 ) -> Result<()> {
     info!("🎮 Setting up gaming cgroup optimizations");
@@ -917,23 +1025,24 @@ async fn setup_gaming_cgroups(
     // 1. Higher CPU priority/weight
     // 2. I/O priority boost
 
-    // Set CPU weight higher for gaming
-    let cpu_weight_path = format!("{}/cpu.weight", cgroup_path);
-    if std::path::Path::new(&cpu_weight_path).exists() {
-        if let Err(e) = fs::write(&cpu_weight_path, "200") {
-            debug!("Failed to set gaming CPU weight: {}", e);
-        } else {
-            info!("✅ Set gaming CPU weight: 200");
-        }
+    // Set CPU weight higher for gaming (cpu.shares on cgroup v1)
+    if let Err(e) = cgroup.set_cpu_weight(200) {
+        debug!("Failed to set gaming CPU weight: {}", e);
+    } else {
+        info!("✅ Set gaming CPU weight: 200");
     }
 
-    // I/O priority for gaming
-    let io_weight_path = format!("{}/io.weight", cgroup_path);
-    if std::path::Path::new(&io_weight_path).exists() {
-        if let Err(e) = fs::write(&io_weight_path, "200") {
-            debug!("Failed to set gaming I/O weight: {}", e);
-        } else {
-            info!("✅ Set gaming I/O weight: 200");
+    // I/O priority for gaming (blkio.weight on cgroup v1)
+    if let Err(e) = cgroup.set_io_weight(200) {
+        debug!("Failed to set gaming I/O weight: {}", e);
+    } else {
+        info!("✅ Set gaming I/O weight: 200");
+    }
+
+    // cgroup v1 device rules for GPU and audio passthrough
+    if gaming.gpu.as_ref().and_then(|gpu| gpu.passthrough) == Some(true) {
+        if let Err(e) = cgroup.allow_devices(GAMING_DEVICE_RULES) {
+            debug!("Failed to allow gaming devices: {}", e);
         }
     }
 
@@ -995,65 +1104,33 @@ async fn setup_gaming_security(state: &ContainerState) -> Result<()> {
     Ok(())
 }
 
-async fn enable_cgroup_controllers(cgroup_path: &str, controllers: &[&str]) -> Result<()> {
-    let subtree_control = format!("{}/cgroup.subtree_control", cgroup_path);
+/// DRM, NVIDIA, NVIDIA control/UVM and ALSA character devices
+const GAMING_DEVICE_RULES: &[&str] = &["c 226:* rwm", "c 195:* rwm", "c 234:* rwm", "c 116:* rwm"];
 
-    if std::path::Path::new(&subtree_control).exists() {
-        let controllers_str = controllers.iter().map(|c| format!("+{}", c)).collect::<Vec<_>>().join(" ");
-
-        if let Err(e) = fs::write(&subtree_control, &controllers_str) {
-            return Err(anyhow::anyhow!("Failed to enable controllers {}: {}", controllers_str, e));
-        }
-
-        info!("✅ Enabled cgroup controllers: {:?}", controllers);
-    }
-
-    Ok(())
-}
-
-fn determine_required_controllers(limits: &ResourceLimits, config: &ContainerConfig) -> Vec<&'static str> {
+fn determine_required_controllers(limits: &ResourceLimits, config: &ContainerConfig) -> Vec<Controller> {
     let mut controllers = Vec::new();
 
     if limits.memory_limit.is_some() {
-        controllers.push("memory");
+        controllers.push(Controller::Memory);
     }
 
-    if limits.cpu_limit.is_some() {
-        controllers.push("cpu");
+    if limits.cpu_limit.is_some() || limits.cpu_shares.is_some() {
+        controllers.push(Controller::Cpu);
     }
 
     if limits.pids_limit.is_some() {
-        controllers.push("pids");
+        controllers.push(Controller::Pids);
     }
 
     // Add I/O controller for storage limits or gaming optimizations
-    if limits.io_limit.is_some() || config.gaming_config.is_some() {
-        controllers.push("io");
+    if limits.io_limit.is_some() || limits.blkio_weight.is_some() || config.gaming_config.is_some()
+    {
+        controllers.push(Controller::Blkio);
     }
 
     controllers
 }
 
-async fn setup_io_limits(cgroup_path: &str, limits: &ResourceLimits) -> Result<()> {
-    if let Some(io_limit) = limits.io_limit {
-        info!("💿 Setting I/O limits: {} IOPS", io_limit);
-
-        // Set I/O weight (priority)
-        let io_weight_path = format!("{}/io.weight", cgroup_path);
-        if std::path::Path::new(&io_weight_path).exists() {
-            // Higher weight for better I/O performance (default is 100, max is 10000)
-            let weight = if io_limit > 1000 { "500" } else { "100" };
-            if let Err(e) = fs::write(&io_weight_path, weight) {
-                debug!("Failed to set I/O weight: {}", e);
-            } else {
-                info!("✅ Set I/O weight: {}", weight);
-            }
-        }
-    }
-
-    Ok(())
-}
-
 async fn validate_rootless_prerequisites(uid: nix::unistd::Uid, gid: nix::unistd::Gid) -> Result<()> {
     info!("🔍 Validating rootless prerequisites");
 
@@ -1097,112 +1174,6 @@ async fn validate_rootless_prerequisites(uid: nix::unistd::Uid, gid: nix::unistd
         }
     }
 
-    // Check subuid/subgid files for advanced mappings
-    check_subid_files(uid).await?;
-
-    Ok(())
-}
-
-async fn check_subid_files(uid: nix::unistd::Uid) -> Result<()> {
-    let username = match nix::unistd::User::from_uid(uid)? {
-        Some(user) => user.name,
-        None => {
-            warn!("⚠️  Could not determine username for UID {}", uid);
-            return Ok(());
-        }
-    };
-
-    // Check /etc/subuid
-    if let Ok(subuid_content) = std::fs::read_to_string("/etc/subuid") {
-        let has_subuid = subuid_content.lines().any(|line| {
-            line.starts_with(&format!("{}:", username)) || line.starts_with(&format!("{}:", uid))
-        });
-
-        if has_subuid {
-            info!("✅ User {} has subuid mappings available", username);
-        } else {
-            info!("⚠️  No subuid mappings for user {} - limited to single UID mapping", username);
-        }
-    } else {
-        info!("⚠️  /etc/subuid not found - limited rootless capabilities");
-    }
-
-    // Check /etc/subgid
-    if let Ok(subgid_content) = std::fs::read_to_string("/etc/subgid") {
-        let has_subgid = subgid_content.lines().any(|line| {
-            line.starts_with(&format!("{}:", username)) || line.starts_with(&format!("{}:", uid))
-        });
-
-        if has_subgid {
-            info!("✅ User {} has subgid mappings available", username);
-        } else {
-            info!("⚠️  No subgid mappings for user {} - limited to single GID mapping", username);
-        }
-    } else {
-        info!("⚠️  /etc/subgid not found - limited rootless capabilities");
-    }
-
-    Ok(())
-}
-
-async fn verify_user_namespace_mappings(original_uid: nix::unistd::Uid, original_gid: nix::unistd::Gid) -> Result<()> {
-    info!("🔍 Verifying user namespace mappings");
-
-    // Read back the UID mapping
-    let uid_map = std::fs::read_to_string("/proc/self/uid_map")
-        .context("Failed to read uid_map after setup")?;
-
-    let expected_uid_map = format!("         0       {} 1", original_uid);
-    if uid_map.trim().contains(&format!("0 {} 1", original_uid)) {
-        info!("✅ UID mapping verified: {}", uid_map.trim());
-    } else {
-        return Err(anyhow::anyhow!(
-            "UID mapping verification failed. Expected containing '0 {} 1', got: '{}'",
-            original_uid,
-            uid_map.trim()
-        ));
-    }
-
-    // Read back the GID mapping
-    let gid_map = std::fs::read_to_string("/proc/self/gid_map")
-        .context("Failed to read gid_map after setup")?;
-
-    if gid_map.trim().contains(&format!("0 {} 1", original_gid)) {
-        info!("✅ GID mapping verified: {}", gid_map.trim());
-    } else {
-        return Err(anyhow::anyhow!(
-            "GID mapping verification failed. Expected containing '0 {} 1', got: '{}'",
-            original_gid,
-            gid_map.trim()
-        ));
-    }
-
-    // Verify setgroups is denied
-    let setgroups = std::fs::read_to_string("/proc/self/setgroups")
-        .context("Failed to read setgroups after setup")?;
-
-    if setgroups.trim() == "deny" {
-        info!("✅ setgroups properly denied");
-    } else {
-        return Err(anyhow::anyhow!(
-            "setgroups verification failed. Expected 'deny', got: '{}'",
-            setgroups.trim()
-        ));
-    }
-
-    // Test effective UID/GID inside namespace
-    let effective_uid = nix::unistd::getuid();
-    let effective_gid = nix::unistd::getgid();
-
-    if effective_uid.is_root() && effective_gid.as_raw() == 0 {
-        info!("✅ Effective UID/GID verified: {} / {}", effective_uid, effective_gid);
-    } else {
-        warn!(
-            "⚠️  Unexpected effective UID/GID: {} / {} (expected 0/0)",
-            effective_uid, effective_gid
-        );
-    }
-
     Ok(())
 }
 
@@ -1293,44 +1264,71 @@ async fn apply_seccomp_profile(profile: &str) -> Result<()> {
 }
 
 async fn add_process_to_cgroup(state: &ContainerState) -> Result<()> {
-    let cgroup_path = format!("/sys/fs/cgroup/bolt/{}", state.id);
+    let layout = CgroupLayout::current();
+    let cgroup = ContainerCgroup::new(layout, &state.id);
 
-    if std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+    if layout.mode != CgroupMode::Unavailable {
         // Ensure cgroup exists
-        if let Err(e) = std::fs::create_dir_all(&cgroup_path) {
+        if let Err(e) = cgroup.create(&[]) {
             debug!("Cgroup directory already exists or creation failed: {}", e);
         }
 
-        info!("✅ Process will be added to cgroup: {}", cgroup_path);
+        info!("✅ Process will be added to cgroup: {}", cgroup);
     }
 
     Ok(())
 }
 
 async fn write_pid_to_cgroup(state: &ContainerState, pid: u32) -> Result<()> {
-    let cgroup_path = format!("/sys/fs/cgroup/bolt/{}", state.id);
-    let cgroup_procs = format!("{}/cgroup.procs", cgroup_path);
+    let cgroup = ContainerCgroup::new(CgroupLayout::current(), &state.id);
 
-    if std::path::Path::new(&cgroup_procs).exists() {
-        if let Err(e) = std::fs::write(&cgroup_procs, pid.to_string()) {
-            debug!("Failed to write PID to cgroup: {}", e);
-        } else {
-            info!("✅ Added PID {} to cgroup: {}", pid, cgroup_path);
-        }
+    if let Err(e) = cgroup.add_pid(pid) {
+        debug!("Failed to write PID to cgroup: {}", e);
+    } else {
+        info!("✅ Added PID {} to cgroup: {}", pid, cgroup);
     }
 
     Ok(())
 }
 
+/// Route the child's stdout/stderr to the container log instead of Bolt's own output
+fn forward_output(
+    child: &mut tokio::process::Child,
+    container_id: &str,
+    log_file: Option<std::fs::File>,
+) -> Vec<tokio::task::JoinHandle<()>> {
+    use crate::monitoring::logging::forward_container_output;
+
+    let stderr_file = log_file.as_ref().and_then(|f| f.try_clone().ok());
+    let mut forwarders = Vec::new();
+
+    if let Some(stdout) = child.stdout.take() {
+        forwarders.push(tokio::spawn(forward_container_output(
+            container_id.to_string(),
+            "stdout",
+            stdout,
+            log_file,
+        )));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forwarders.push(tokio::spawn(forward_container_output(
+            container_id.to_string(),
+            "stderr",
+            stderr,
+            stderr_file,
+        )));
+    }
+
+    forwarders
+}
+
 async fn cleanup_container_resources(container_id: &str) -> Result<()> {
     info!("🧹 Cleaning up resources for container: {}", container_id);
 
     // Clean up cgroup
-    let cgroup_path = format!("/sys/fs/cgroup/bolt/{}", container_id);
-    if std::path::Path::new(&cgroup_path).exists() {
-        let _ = std::fs::remove_dir(&cgroup_path);
-        info!("✅ Cleaned up cgroup: {}", cgroup_path);
-    }
+    let cgroup = ContainerCgroup::new(CgroupLayout::current(), container_id);
+    cgroup.remove();
+    info!("✅ Cleaned up cgroup: {}", cgroup);
 
     // Clean up network resources (would typically call network manager)
     // Clean up mount points
@@ -1437,8 +1435,14 @@ pub async fn create_container_rootfs(
     let rootfs_path = bundle_path.join("rootfs");
     std::fs::create_dir_all(&rootfs_path).context("Failed to create rootfs directory")?;
 
+    // The runtime already assembled the image's layers here
+    if std::fs::read_dir(&rootfs_path)?.next().is_some() {
+        debug!("Using the assembled rootfs at {:?}", rootfs_path);
+        return Ok(());
+    }
+
     // Check if there's an extracted image available
-    let image_storage_path = PathBuf::from("/var/lib/bolt/images");
+    let image_storage_path = rootless::storage_root().join("images");
     let mut image_found = false;
 
     // Try to find and copy the extracted image
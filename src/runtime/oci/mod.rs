@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use oci_spec::runtime::{Linux, Mount, Process, Root, Spec};
+use oci_spec::runtime::{Linux, LinuxNamespaceType, Mount, Process, Root, Spec};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -9,11 +9,14 @@ pub mod cdi;
 pub mod container;
 pub mod executor;
 pub mod namespace;
+pub mod state;
 
 use cdi::*;
+use state::StateStore;
 
 use crate::capsules::CapsuleManager;
-use crate::runtime::storage::StorageManager;
+use crate::runtime::cgroups::{self, CgroupLayout, ContainerCgroup};
+use crate::runtime::storage::{ImageConfig, StorageManager};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerConfig {
@@ -33,6 +36,23 @@ pub struct ContainerConfig {
     pub security_profile: SecurityProfile,
     pub resource_limits: ResourceLimits,
     pub gaming_config: Option<crate::config::GamingConfig>,
+    /// Defaults to the container name
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub domainname: Option<String>,
+    /// UTS namespace: "private" (default) or "host"
+    #[serde(default)]
+    pub uts: Option<String>,
+    /// "auto" (default), "none" or a fixed 32-digit hex machine-id
+    #[serde(default)]
+    pub machine_id: Option<String>,
+    /// Host keys the container's own session keyring gets read-only
+    #[serde(default)]
+    pub keyring: Option<crate::runtime::keyring::KeyringConfig>,
+    /// OCI annotations of the runtime spec
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -101,11 +121,35 @@ impl Default for ResourceLimits {
     }
 }
 
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.memory_limit.is_none()
+            && self.cpu_limit.is_none()
+            && self.pids_limit.is_none()
+            && self.blkio_weight.is_none()
+            && self.cpu_shares.is_none()
+            && self.io_limit.is_none()
+    }
+
+    /// Take every limit `update` sets, keeping the rest
+    pub fn merge(&mut self, update: &ResourceLimits) {
+        self.memory_limit = update.memory_limit.or(self.memory_limit);
+        self.cpu_limit = update.cpu_limit.or(self.cpu_limit);
+        self.pids_limit = update.pids_limit.or(self.pids_limit);
+        self.blkio_weight = update.blkio_weight.or(self.blkio_weight);
+        self.cpu_shares = update.cpu_shares.or(self.cpu_shares);
+        self.io_limit = update.io_limit.or(self.io_limit);
+    }
+}
+
 pub struct OCIRuntime {
     pub storage: StorageManager,
     pub capsule_manager: CapsuleManager,
     pub runtime_dir: PathBuf,
     pub containers: HashMap<String, ContainerState>,
+    state_store: StateStore,
+    data_dir: PathBuf,
+    container_log_dir: PathBuf,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,11 +158,18 @@ pub struct ContainerState {
     pub config: ContainerConfig,
     pub status: ContainerStatus,
     pub pid: Option<u32>,
+    /// Start time of `pid`, to tell it from a later process given the same pid
+    #[serde(default)]
+    pub pid_start: Option<u64>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub started_at: Option<chrono::DateTime<chrono::Utc>>,
     pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
     pub exit_code: Option<i32>,
     pub bundle_path: PathBuf,
+    /// Where the container's output is persisted; None keeps it in the
+    /// daemon's container log only
+    #[serde(default)]
+    pub log_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -131,21 +182,28 @@ pub enum ContainerStatus {
 }
 
 impl OCIRuntime {
-    pub fn new(runtime_dir: PathBuf) -> Result<Self> {
+    pub fn new(runtime_dir: PathBuf, config: &crate::config::BoltConfig) -> Result<Self> {
         std::fs::create_dir_all(&runtime_dir).context("Failed to create runtime directory")?;
 
         let storage = StorageManager::new(runtime_dir.join("storage"))?;
         let capsule_manager = CapsuleManager::new(runtime_dir.join("capsules"))?;
 
+        // Containers started before this process, reconciled with live pids
+        let state_store = StateStore::new(&runtime_dir)?;
+        let containers = state_store.load()?;
+
         Ok(Self {
             storage,
             capsule_manager,
             runtime_dir,
-            containers: HashMap::new(),
+            containers,
+            state_store,
+            data_dir: config.data_dir.clone(),
+            container_log_dir: config.container_log_dir(),
         })
     }
 
-    pub async fn run_container(&mut self, config: ContainerConfig) -> Result<String> {
+    pub async fn run_container(&mut self, mut config: ContainerConfig) -> Result<String> {
         info!("🐳 Starting OCI container: {}", config.image);
 
         // Check if this should be a Bolt Capsule instead
@@ -156,9 +214,11 @@ impl OCIRuntime {
 
         let container_id = config.id.clone();
         debug!("Container config: {:?}", config);
+        let mut timer = crate::runtime::startup::StartupTimer::new(&config.image);
 
         // Gaming optimizations
         if let Some(ref gaming) = config.gaming_config {
+            timer.phase(crate::runtime::startup::Phase::Gpu);
             self.apply_gaming_optimizations(&container_id, gaming)
                 .await?;
         }
@@ -167,11 +227,45 @@ impl OCIRuntime {
         let bundle_path = self.runtime_dir.join("bundles").join(&container_id);
         std::fs::create_dir_all(&bundle_path).context("Failed to create container bundle")?;
 
-        // Pull image if needed
-        self.storage.pull_image(&config.image).await?;
+        // Pull image if needed and assemble its layers as the rootfs
+        timer.phase(crate::runtime::startup::Phase::ImageResolve);
+        let image_id = self.storage.pull_image(&config.image).await?;
+        match self
+            .storage
+            .assemble_rootfs(&image_id, &container_id, &bundle_path.join("rootfs"))
+        {
+            Ok(mount) => debug!(
+                "Rootfs of {} assembled ({:?}, {} layers)",
+                container_id,
+                mount.mode,
+                mount.layers.len()
+            ),
+            Err(e) => warn!(
+                "⚠️  Could not assemble the rootfs of {}: {:#}",
+                container_id, e
+            ),
+        }
+        if let Some(image) = self.storage.get_image(&image_id) {
+            apply_image_defaults(&mut config, &image.config);
+        }
+
+        timer.phase(crate::runtime::startup::Phase::Spec);
+
+        // Hostname and machine-id, which stay the same across restarts
+        let identity = crate::runtime::identity::IdentitySpec {
+            hostname: config.hostname.clone(),
+            domainname: config.domainname.clone(),
+            uts: config.uts.clone(),
+            machine_id: config.machine_id.clone(),
+            host_network: false,
+        }
+        .resolve(
+            &self.runtime_dir,
+            config.name.as_deref().unwrap_or(&container_id),
+        )?;
 
         // Create OCI spec
-        let spec = self.create_oci_spec(&config)?;
+        let spec = self.create_oci_spec(&config, &identity)?;
         let spec_path = bundle_path.join("config.json");
         let spec_json = serde_json::to_string_pretty(&spec)?;
         std::fs::write(&spec_path, spec_json).context("Failed to write OCI spec")?;
@@ -182,22 +276,28 @@ impl OCIRuntime {
             config,
             status: ContainerStatus::Created,
             pid: None,
+            pid_start: None,
             created_at: chrono::Utc::now(),
             started_at: None,
             finished_at: None,
             exit_code: None,
             bundle_path,
+            log_dir: Some(self.container_log_dir.clone()),
         };
 
         // Execute container
-        let pid = executor::execute_container(&state, &spec).await?;
+        let pid = executor::execute_container(&state, &spec, &mut timer).await?;
+
+        crate::runtime::startup::complete(&self.data_dir, timer, &container_id);
 
         // Update state
         let mut updated_state = state;
         updated_state.status = ContainerStatus::Running;
         updated_state.pid = Some(pid);
+        updated_state.pid_start = state::process_start_time(pid);
         updated_state.started_at = Some(chrono::Utc::now());
 
+        self.state_store.save(&updated_state)?;
         self.containers.insert(container_id.clone(), updated_state);
 
         info!("✅ Container {} started with PID {}", container_id, pid);
@@ -284,7 +384,7 @@ impl OCIRuntime {
             info!("🚀 Using nvbind for NVIDIA GPU passthrough");
 
             // Setup CDI (Container Device Interface) spec
-            let cdi_spec = self.generate_nvidia_cdi_spec(container_id, nvidia).await?;
+            let cdi_spec = nvidia_cdi_spec(nvidia)?;
             self.apply_cdi_devices(container_id, &cdi_spec).await?;
 
             // Configure nvbind runtime optimizations
@@ -292,6 +392,7 @@ impl OCIRuntime {
                 runtime: Some("nvbind".to_string()),
                 nvidia: Some(nvidia.clone()),
                 amd: None,
+                intel: None,
                 nvbind: Some(crate::config::NvbindConfig {
                     driver: Some("auto".to_string()),
                     devices: nvidia
@@ -319,6 +420,11 @@ impl OCIRuntime {
                     None
                 },
                 aiml: None,
+                count: None,
+                topology: None,
+                ai: None,
+                priority: None,
+                fraction: None,
             };
 
             nvbind_manager
@@ -436,65 +542,6 @@ impl OCIRuntime {
         Ok(())
     }
 
-    // Complete GPU device passthrough implementation
-    async fn generate_nvidia_cdi_spec(
-        &self,
-        container_id: &str,
-        nvidia: &crate::config::NvidiaConfig,
-    ) -> Result<CDISpec> {
-        info!("📋 Generating CDI spec for NVIDIA GPU");
-
-        let cdi_spec = CDISpec {
-            cdi_version: "0.5.0".to_string(),
-            kind: "nvidia.com/gpu".to_string(),
-            devices: vec![CDIDevice {
-                name: "gpu0".to_string(),
-                container_edits: CDIContainerEdits {
-                    device_nodes: vec![
-                        CDIDeviceNode {
-                            path: "/dev/nvidia0".to_string(),
-                            device_type: "c".to_string(),
-                            major: 195,
-                            minor: 0,
-                        },
-                        CDIDeviceNode {
-                            path: "/dev/nvidiactl".to_string(),
-                            device_type: "c".to_string(),
-                            major: 195,
-                            minor: 255,
-                        },
-                        CDIDeviceNode {
-                            path: "/dev/nvidia-uvm".to_string(),
-                            device_type: "c".to_string(),
-                            major: 510,
-                            minor: 0,
-                        },
-                    ],
-                    mounts: vec![
-                        CDIMount {
-                            host_path: "/usr/lib/x86_64-linux-gnu/libnvidia-ml.so.1".to_string(),
-                            container_path: "/usr/lib/x86_64-linux-gnu/libnvidia-ml.so.1"
-                                .to_string(),
-                            options: vec!["ro".to_string()],
-                        },
-                        CDIMount {
-                            host_path: "/usr/lib/x86_64-linux-gnu/libcuda.so.1".to_string(),
-                            container_path: "/usr/lib/x86_64-linux-gnu/libcuda.so.1".to_string(),
-                            options: vec!["ro".to_string()],
-                        },
-                    ],
-                    env: vec![
-                        "NVIDIA_VISIBLE_DEVICES=0".to_string(),
-                        "NVIDIA_DRIVER_CAPABILITIES=all".to_string(),
-                        "BOLT_GPU_ISOLATION=exclusive".to_string(),
-                    ],
-                },
-            }],
-        };
-
-        Ok(cdi_spec)
-    }
-
     async fn apply_cdi_devices(&self, container_id: &str, cdi_spec: &CDISpec) -> Result<()> {
         info!(
             "🔧 Applying CDI device configuration for container: {}",
@@ -835,7 +882,11 @@ impl OCIRuntime {
         Ok(())
     }
 
-    fn create_oci_spec(&self, config: &ContainerConfig) -> Result<Spec> {
+    fn create_oci_spec(
+        &self,
+        config: &ContainerConfig,
+        identity: &crate::runtime::identity::ContainerIdentity,
+    ) -> Result<Spec> {
         info!("📋 Creating OCI specification");
 
         // Create basic spec with Linux-specific configs
@@ -865,6 +916,10 @@ impl OCIRuntime {
 
         spec.set_process(Some(process));
 
+        // UTS names; the runtime's default hostname is not the container's
+        spec.set_hostname(identity.hostname.clone());
+        spec.set_domainname(identity.domainname.clone());
+
         // Mounts
         let mut mounts = vec![
             // Standard mounts
@@ -912,12 +967,34 @@ impl OCIRuntime {
             mounts.push(mount);
         }
 
+        if let Some(ref machine_id) = identity.machine_id_file {
+            let mut mount = Mount::default();
+            mount.set_destination("/etc/machine-id".into());
+            mount.set_source(Some(machine_id.clone()));
+            mount.set_typ(Some("bind".to_string()));
+            mount.set_options(Some(vec!["bind".to_string(), "ro".to_string()]));
+            mounts.push(mount);
+        }
+
         spec.set_mounts(Some(mounts));
 
         // Linux-specific configuration
-        let linux = Linux::default();
+        let mut linux = Linux::default();
+        if identity.share_host_uts {
+            let namespaces = linux.namespaces().clone().map(|namespaces| {
+                namespaces
+                    .into_iter()
+                    .filter(|ns| ns.typ() != LinuxNamespaceType::Uts)
+                    .collect()
+            });
+            linux.set_namespaces(namespaces);
+        }
         spec.set_linux(Some(linux));
 
+        if !config.annotations.is_empty() {
+            spec.set_annotations(Some(config.annotations.clone()));
+        }
+
         debug!("✅ OCI spec created successfully");
         Ok(spec)
     }
@@ -958,7 +1035,9 @@ impl OCIRuntime {
             }
 
             state.status = ContainerStatus::Stopped;
+            state.pid = None;
             state.finished_at = Some(chrono::Utc::now());
+            self.state_store.save(state)?;
             info!("✅ Container {} stopped", container_id);
         } else {
             return Err(anyhow::anyhow!("Container {} not found", container_id));
@@ -967,6 +1046,49 @@ impl OCIRuntime {
         Ok(())
     }
 
+    /// Remove a container: stop it if it runs, unmount and delete its
+    /// rootfs, and delete its bundle
+    pub async fn remove_container(&mut self, container_id: &str) -> Result<()> {
+        info!("🗑️  Removing container: {}", container_id);
+        let Some(state) = self.containers.get(container_id) else {
+            return Err(anyhow::anyhow!("Container {} not found", container_id));
+        };
+        if state.status == ContainerStatus::Running {
+            self.stop_container(container_id).await?;
+        }
+        let Some(state) = self.containers.remove(container_id) else {
+            return Ok(());
+        };
+
+        self.storage.release_rootfs(container_id)?;
+        if state.bundle_path.exists() {
+            std::fs::remove_dir_all(&state.bundle_path)
+                .with_context(|| format!("Failed to remove bundle {:?}", state.bundle_path))?;
+        }
+        self.state_store.remove(container_id)?;
+        info!("✅ Container {} removed", container_id);
+        Ok(())
+    }
+
+    /// Change a container's resource limits, live in its cgroup when it
+    /// runs. Limits `update` leaves unset keep their value.
+    pub fn update_container(&mut self, container_id: &str, update: &ResourceLimits) -> Result<()> {
+        let Some(state) = self.containers.get_mut(container_id) else {
+            return Err(anyhow::anyhow!("Container {} not found", container_id));
+        };
+        if state.status == ContainerStatus::Running {
+            let cgroup = ContainerCgroup::new(CgroupLayout::current(), container_id);
+            let io_device = update
+                .io_limit
+                .and_then(|_| cgroups::block_device(&state.bundle_path));
+            cgroup.apply(update, io_device)?;
+        }
+        state.config.resource_limits.merge(update);
+        self.state_store.save(state)?;
+        info!("✅ Container {} resources updated", container_id);
+        Ok(())
+    }
+
     pub fn list_containers(&self, all: bool) -> Vec<&ContainerState> {
         self.containers
             .values()
@@ -974,3 +1096,50 @@ impl OCIRuntime {
             .collect()
     }
 }
+
+/// Fill in what the container doesn't set from the image's config, the way
+/// podman does: entrypoint and cmd, env, working directory and user
+fn apply_image_defaults(config: &mut ContainerConfig, image: &ImageConfig) {
+    if config.command.is_empty() {
+        if image.entrypoint.is_empty() {
+            config.command = image.cmd.clone();
+        } else {
+            config.command = image.entrypoint.clone();
+            if config.args.is_empty() {
+                config.args = image.cmd.clone();
+            }
+        }
+    }
+    for var in &image.env {
+        if let Some((key, value)) = var.split_once('=') {
+            config
+                .env
+                .entry(key.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+    if config.working_dir.is_none() && !image.working_dir.is_empty() {
+        config.working_dir = Some(image.working_dir.clone());
+    }
+    if config.user.is_none() && !image.user.is_empty() {
+        config.user = Some(image.user.clone());
+    }
+}
+
+/// A CDI spec for the NVIDIA GPU `nvidia.device` names, or all of them
+fn nvidia_cdi_spec(nvidia: &crate::config::NvidiaConfig) -> Result<CDISpec> {
+    use crate::runtime::gpu::plugin::{self, DevicePlugin};
+
+    let devices = plugin::Nvidia.discover();
+    let selected = match nvidia.device {
+        Some(index) => vec![
+            devices
+                .iter()
+                .find(|d| d.index == index)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No NVIDIA GPU {}", index))?,
+        ],
+        None => devices,
+    };
+    Ok(plugin::Nvidia.cdi_spec(&selected))
+}
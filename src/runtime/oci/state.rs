@@ -0,0 +1,227 @@
+// Container state on disk
+//
+// The native runtime keeps each container's state (config, status, pid and
+// bundle path) in `<runtime_dir>/state/<id>.json`, written whenever it
+// changes, so containers outlive the CLI or daemon that started them.
+//
+// On startup the states are read back and reconciled with the processes that
+// are actually there. A pid alone isn't enough: after a reboot, or once the
+// container exited, the kernel may have handed it to an unrelated process.
+// The state therefore also records the process's start time from
+// `/proc/<pid>/stat`, and a container only stays running while a live process
+// with both the same pid and the same start time exists.
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use super::{ContainerState, ContainerStatus};
+
+const STATE_DIR: &str = "state";
+
+/// Where the native runtime keeps its container states
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+/// Start time (in clock ticks since boot) of a live process, or None if
+/// `pid` has exited or is a zombie
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses; the fields after
+    // it start at the last ')'
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split(' ').collect();
+    if matches!(fields.first(), Some(&"Z") | Some(&"X")) {
+        return None;
+    }
+    // starttime is field 22 of stat, the 20th after the command name
+    fields.get(19)?.parse().ok()
+}
+
+/// Whether the process a state records is still the container's
+fn alive(state: &ContainerState) -> bool {
+    match (state.pid, state.pid_start) {
+        (Some(pid), Some(start)) => process_start_time(pid) == Some(start),
+        // States written before start times were recorded
+        (Some(pid), None) => process_start_time(pid).is_some(),
+        (None, _) => false,
+    }
+}
+
+/// Bring a state loaded from disk in line with the processes running now.
+/// Returns whether it changed.
+pub fn reconcile(state: &mut ContainerState) -> bool {
+    match state.status {
+        ContainerStatus::Running | ContainerStatus::Paused if !alive(state) => {
+            info!(
+                "Container {} (pid {}) is no longer running",
+                state.id,
+                state.pid.unwrap_or_default()
+            );
+            state.status = ContainerStatus::Stopped;
+            state.pid = None;
+            state.finished_at.get_or_insert_with(chrono::Utc::now);
+            true
+        }
+        ContainerStatus::Unknown if alive(state) => {
+            state.status = ContainerStatus::Running;
+            true
+        }
+        _ => false,
+    }
+}
+
+impl StateStore {
+    pub fn new(runtime_dir: &Path) -> Result<Self> {
+        let dir = runtime_dir.join(STATE_DIR);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Write a container's state, replacing the previous one atomically
+    pub fn save(&self, state: &ContainerState) -> Result<()> {
+        let path = self.path(&state.id);
+        let tmp = self.dir.join(format!(".{}.json.tmp", state.id));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn remove(&self, id: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove the state of {}", id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Every saved container, reconciled with the live processes. States
+    /// that changed are written back; unreadable ones are skipped.
+    pub fn load(&self) -> Result<HashMap<String, ContainerState>> {
+        let mut containers = HashMap::new();
+        for entry in std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read {}", self.dir.display()))?
+            .flatten()
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let mut state: ContainerState = match std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!("⚠️  Skipping container state {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if reconcile(&mut state)
+                && let Err(e) = self.save(&state)
+            {
+                warn!("Could not update the state of {}: {}", state.id, e);
+            }
+            containers.insert(state.id.clone(), state);
+        }
+        debug!("Loaded {} container states", containers.len());
+        Ok(containers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::oci::ContainerConfig;
+
+    fn state(
+        id: &str,
+        status: ContainerStatus,
+        pid: u32,
+        pid_start: Option<u64>,
+    ) -> ContainerState {
+        let config = ContainerConfig {
+            id: id.to_string(),
+            name: None,
+            image: "alpine".to_string(),
+            command: Vec::new(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            working_dir: None,
+            user: None,
+            ports: Vec::new(),
+            volumes: Vec::new(),
+            capabilities: Vec::new(),
+            privileged: false,
+            readonly_rootfs: false,
+            security_profile: Default::default(),
+            resource_limits: Default::default(),
+            gaming_config: None,
+            hostname: None,
+            domainname: None,
+            uts: None,
+            machine_id: None,
+            keyring: None,
+            annotations: HashMap::new(),
+        };
+        ContainerState {
+            id: id.to_string(),
+            config,
+            status,
+            pid: Some(pid),
+            pid_start,
+            created_at: chrono::Utc::now(),
+            started_at: Some(chrono::Utc::now()),
+            finished_at: None,
+            exit_code: None,
+            bundle_path: PathBuf::from("/tmp/bundle"),
+            log_dir: None,
+        }
+    }
+
+    #[test]
+    fn reloads_states_and_reconciles_them_with_live_pids() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path()).unwrap();
+        let me = std::process::id();
+        let started = process_start_time(me).unwrap();
+
+        store
+            .save(&state("alive", ContainerStatus::Running, me, Some(started)))
+            .unwrap();
+        // Same pid, but a different process than the one recorded
+        store
+            .save(&state(
+                "reused",
+                ContainerStatus::Running,
+                me,
+                Some(started + 1),
+            ))
+            .unwrap();
+        store
+            .save(&state("done", ContainerStatus::Stopped, me, Some(started)))
+            .unwrap();
+        std::fs::write(dir.path().join("state/broken.json"), "{").unwrap();
+
+        let containers = StateStore::new(dir.path()).unwrap().load().unwrap();
+        assert_eq!(containers.len(), 3);
+        assert_eq!(containers["alive"].status, ContainerStatus::Running);
+        assert_eq!(containers["reused"].status, ContainerStatus::Stopped);
+        assert_eq!(containers["reused"].pid, None);
+        assert!(containers["reused"].finished_at.is_some());
+        assert_eq!(containers["done"].status, ContainerStatus::Stopped);
+
+        // The reconciled state was written back
+        let reloaded = store.load().unwrap();
+        assert_eq!(reloaded["reused"].status, ContainerStatus::Stopped);
+
+        store.remove("alive").unwrap();
+        store.remove("alive").unwrap();
+        assert!(!store.load().unwrap().contains_key("alive"));
+    }
+}
@@ -0,0 +1,508 @@
+// Image platforms
+//
+// A Windows-only or wrong-architecture image used to fail deep in the
+// runtime, with "no matching manifest" or "exec format error" once the
+// container started. Before pulling, Bolt reads the image's manifest (with
+// skopeo, or `docker manifest inspect`) and checks it offers the platform
+// asked for: `--platform`, the service's `platform`, or the host's own.
+// After pulling, the local image is checked too, which also covers images
+// whose manifest couldn't be read.
+//
+// A mismatch is refused with a hint:
+//
+// - Windows images can't run on Linux; a Windows game server's Windows build
+//   runs under Wine/Proton in a Linux image instead
+// - macOS images can't run in Linux containers at all
+// - for another architecture, `--platform` pulls one the image has, which
+//   runs under emulation when a binfmt handler (qemu-user-static) is set up
+//
+// With `[platform] auto_select = true`, an image without a variant for this
+// host's architecture is pulled as linux/amd64 when its manifest list has
+// that variant and the host can emulate it.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, warn};
+
+use crate::error::{BoltError, RuntimeError};
+
+/// `os/architecture[/variant]`, e.g. "linux/arm64/v8"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    pub fn new(os: &str, architecture: &str, variant: Option<&str>) -> Self {
+        let os = match os.to_ascii_lowercase().as_str() {
+            "macos" | "osx" => "darwin".to_string(),
+            other => other.to_string(),
+        };
+        let (architecture, implied_variant) = match architecture.to_ascii_lowercase().as_str() {
+            "x86_64" | "x86-64" => ("amd64".to_string(), None),
+            "aarch64" => ("arm64".to_string(), None),
+            "armhf" => ("arm".to_string(), Some("v7")),
+            "i386" | "i686" | "x86" => ("386".to_string(), None),
+            other => (other.to_string(), None),
+        };
+        Self {
+            os,
+            architecture,
+            variant: variant
+                .or(implied_variant)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string),
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.trim().split('/').collect();
+        match parts[..] {
+            [os, architecture] if !os.is_empty() && !architecture.is_empty() => {
+                Ok(Self::new(os, architecture, None))
+            }
+            [os, architecture, variant] if !os.is_empty() && !architecture.is_empty() => {
+                Ok(Self::new(os, architecture, Some(variant)))
+            }
+            _ => Err(anyhow!(
+                "Invalid platform '{}': use os/architecture[/variant], e.g. linux/amd64",
+                spec
+            )),
+        }
+    }
+
+    /// The platform containers run on natively here
+    pub fn host() -> Self {
+        Self::new("linux", std::env::consts::ARCH, None)
+    }
+
+    /// Whether an image for `self` serves a request for `wanted`
+    pub fn serves(&self, wanted: &Platform) -> bool {
+        let variants_match = match (&self.variant, &wanted.variant) {
+            (Some(a), Some(b)) => a == b || self.architecture == "arm64",
+            _ => true,
+        };
+        self.os == wanted.os && self.architecture == wanted.architecture && variants_match
+    }
+
+    /// Whether a binfmt handler lets this host run the platform's binaries
+    pub fn emulated(&self) -> bool {
+        super::emulation::support(self).usable()
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(ref variant) = self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+/// Platform a pull asks for, and whether another may be picked for it
+#[derive(Debug, Clone, Default)]
+pub struct PlatformChoice {
+    /// `--platform` or the service's `platform`; the host's when unset
+    pub requested: Option<String>,
+    /// `[platform] auto_select`
+    pub auto_select: bool,
+}
+
+impl PlatformChoice {
+    pub fn new(requested: Option<&str>, auto_select: bool) -> Self {
+        Self {
+            requested: requested.map(str::to_string),
+            auto_select,
+        }
+    }
+}
+
+/// Platforms an image's manifest offers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImagePlatforms {
+    /// Whether the manifest is a list with one image per platform
+    pub list: bool,
+    pub platforms: Vec<Platform>,
+}
+
+#[derive(Deserialize)]
+struct RawPlatform {
+    os: String,
+    architecture: String,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+impl RawPlatform {
+    /// Attestation manifests carry `unknown/unknown`
+    fn platform(&self) -> Option<Platform> {
+        (self.os != "unknown" && !self.os.is_empty())
+            .then(|| Platform::new(&self.os, &self.architecture, self.variant.as_deref()))
+    }
+}
+
+#[derive(Deserialize)]
+struct RawDescriptor {
+    #[serde(default)]
+    platform: Option<RawPlatform>,
+}
+
+#[derive(Deserialize)]
+struct RawManifest {
+    #[serde(default)]
+    manifests: Option<Vec<RawDescriptor>>,
+    /// Schema 1 manifests name their architecture directly
+    #[serde(default)]
+    architecture: Option<String>,
+}
+
+/// Platforms of a raw manifest or manifest list. A single image manifest
+/// gives none; its config has them.
+pub fn parse_manifest(json: &str) -> Option<ImagePlatforms> {
+    let manifest: RawManifest = serde_json::from_str(json).ok()?;
+    if let Some(manifests) = manifest.manifests {
+        return Some(ImagePlatforms {
+            list: true,
+            platforms: manifests
+                .iter()
+                .filter_map(|m| m.platform.as_ref()?.platform())
+                .collect(),
+        });
+    }
+    Some(ImagePlatforms {
+        list: false,
+        platforms: manifest
+            .architecture
+            .map(|arch| Platform::new("linux", &arch, None))
+            .into_iter()
+            .collect(),
+    })
+}
+
+/// Platform in an image config (`os`, `architecture`, `variant`)
+pub fn parse_config(json: &str) -> Option<Platform> {
+    serde_json::from_str::<RawPlatform>(json).ok()?.platform()
+}
+
+/// `docker manifest inspect -v`: one `{"Descriptor": {"platform": ...}}`
+/// object per image, in an array for manifest lists
+fn parse_docker_verbose(json: &str) -> Option<ImagePlatforms> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Entry {
+        descriptor: RawDescriptor,
+    }
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    let list = value.is_array();
+    let entries: Vec<Entry> = if list {
+        serde_json::from_value(value).ok()?
+    } else {
+        vec![serde_json::from_value(value).ok()?]
+    };
+    Some(ImagePlatforms {
+        list,
+        platforms: entries
+            .iter()
+            .filter_map(|e| e.descriptor.platform.as_ref()?.platform())
+            .collect(),
+    })
+}
+
+async fn stdout_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = AsyncCommand::new(program).args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Platforms the registry offers for `image`; `None` when that can't be told
+pub async fn remote_platforms(runtime: &str, image: &str) -> Option<ImagePlatforms> {
+    let reference = format!("docker://{}", image);
+    if let Some(raw) = stdout_of("skopeo", &["inspect", "--raw", &reference]).await {
+        let mut found = parse_manifest(&raw)?;
+        if !found.list && found.platforms.is_empty() {
+            let config = stdout_of("skopeo", &["inspect", "--config", &reference]).await?;
+            found.platforms.extend(parse_config(&config));
+        }
+        return Some(found);
+    }
+    if runtime == "docker" {
+        let verbose = stdout_of("docker", &["manifest", "inspect", "-v", image]).await?;
+        return parse_docker_verbose(&verbose);
+    }
+    None
+}
+
+/// Platform of a local image
+pub async fn local_platform(runtime: &str, image: &str) -> Option<Platform> {
+    let out = stdout_of(
+        runtime,
+        &[
+            "image",
+            "inspect",
+            "--format",
+            "{{.Os}}/{{.Architecture}}",
+            image,
+        ],
+    )
+    .await?;
+    Platform::parse(out.trim()).ok()
+}
+
+/// Platform to pull given what the image offers: `None` leaves the choice to
+/// the runtime, `Some` is passed as `--platform`
+pub fn select(
+    image: &str,
+    found: &ImagePlatforms,
+    choice: &PlatformChoice,
+    host: &Platform,
+    emulated: impl Fn(&Platform) -> bool,
+) -> Result<Option<Platform>> {
+    let requested = choice
+        .requested
+        .as_deref()
+        .map(Platform::parse)
+        .transpose()?;
+    let wanted = requested.clone().unwrap_or_else(|| host.clone());
+    if found.platforms.is_empty() || found.platforms.iter().any(|p| p.serves(&wanted)) {
+        return Ok(requested);
+    }
+
+    let amd64 = Platform::new("linux", "amd64", None);
+    if requested.is_none()
+        && choice.auto_select
+        && found.list
+        && found.platforms.iter().any(|p| p.serves(&amd64))
+        && emulated(&amd64)
+    {
+        warn!(
+            "⚠️  {} has no {} variant, using {} under emulation",
+            image, wanted, amd64
+        );
+        return Ok(Some(amd64));
+    }
+    Err(BoltError::Runtime(mismatch(
+        image,
+        &found.platforms,
+        &wanted,
+        choice,
+        &emulated,
+    ))
+    .into())
+}
+
+/// The error for an image that only offers `found`, with what to do instead
+pub fn mismatch(
+    image: &str,
+    found: &[Platform],
+    wanted: &Platform,
+    choice: &PlatformChoice,
+    emulated: &dyn Fn(&Platform) -> bool,
+) -> RuntimeError {
+    let listed = found
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let linux: Vec<&Platform> = found.iter().filter(|p| p.os == "linux").collect();
+
+    let (reason, hint) = if linux.is_empty() && found.iter().any(|p| p.os == "windows") {
+        (
+            format!("it is a Windows image ({})", listed),
+            "Windows containers need a Windows host. For a Windows game server, run its \
+             Windows build under Wine/Proton in a Linux image instead: give the service a \
+             [services.<name>.gaming.wine] table (e.g. proton = \"8.0\"), or see \
+             `bolt gaming wine`."
+                .to_string(),
+        )
+    } else if linux.is_empty() && found.iter().any(|p| p.os == "darwin") {
+        (
+            format!("it is a macOS image ({})", listed),
+            "macOS can't run in Linux containers. Use a Linux build of the software instead."
+                .to_string(),
+        )
+    } else if linux.is_empty() {
+        (
+            format!("it is built for {}", listed),
+            "Only Linux images run in Bolt's containers.".to_string(),
+        )
+    } else {
+        let mut hint = format!(
+            "Pull a platform it has with --platform (or `platform = \"{}\"` in the Boltfile); \
+             it runs under emulation when qemu-user-static registers a binfmt handler for it.",
+            linux[0]
+        );
+        let emulatable: Vec<String> = linux
+            .iter()
+            .filter(|p| emulated(p))
+            .map(|p| p.to_string())
+            .collect();
+        if !emulatable.is_empty() {
+            hint.push_str(&format!(
+                " This host can already emulate {}.",
+                emulatable.join(", ")
+            ));
+        }
+        let amd64 = Platform::new("linux", "amd64", None);
+        if !choice.auto_select
+            && choice.requested.is_none()
+            && linux.iter().any(|p| p.serves(&amd64))
+        {
+            hint.push_str(
+                " With `[platform] auto_select = true` in config.toml, Bolt picks linux/amd64 by itself.",
+            );
+        }
+        (format!("it is built for {}, not {}", listed, wanted), hint)
+    };
+    RuntimeError::PlatformMismatch {
+        image: image.to_string(),
+        reason,
+        hint,
+    }
+}
+
+/// Check what the registry offers for `image` before pulling it and pick
+/// the platform to pull
+pub async fn resolve(
+    runtime: &str,
+    image: &str,
+    choice: &PlatformChoice,
+) -> Result<Option<Platform>> {
+    let Some(found) = remote_platforms(runtime, image).await else {
+        debug!("Could not read the platforms of {}", image);
+        return choice.requested.as_deref().map(Platform::parse).transpose();
+    };
+    debug!(
+        "{} offers {}",
+        image,
+        found
+            .platforms
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    select(image, &found, choice, &Platform::host(), Platform::emulated)
+}
+
+/// Whether a local image is for the platform explicitly asked for; true
+/// when none was or its platform can't be told
+pub async fn local_matches(runtime: &str, image: &str, choice: &PlatformChoice) -> bool {
+    let Some(wanted) = choice
+        .requested
+        .as_deref()
+        .and_then(|p| Platform::parse(p).ok())
+    else {
+        return true;
+    };
+    local_platform(runtime, image)
+        .await
+        .is_none_or(|local| local.serves(&wanted))
+}
+
+/// Check a local image is for `platform`, or the host's when `None`
+pub async fn verify_local(
+    runtime: &str,
+    image: &str,
+    platform: Option<&Platform>,
+    choice: &PlatformChoice,
+) -> Result<()> {
+    let Some(local) = local_platform(runtime, image).await else {
+        return Ok(());
+    };
+    let wanted = platform.cloned().unwrap_or_else(Platform::host);
+    if local.serves(&wanted) {
+        return Ok(());
+    }
+    Err(BoltError::Runtime(mismatch(
+        image,
+        &[local],
+        &wanted,
+        choice,
+        &Platform::emulated,
+    ))
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_windows_and_foreign_images_and_auto_selects_amd64() {
+        let index = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {"digest": "sha256:a", "platform": {"architecture": "amd64", "os": "linux"}},
+                {"digest": "sha256:b", "platform": {"architecture": "arm", "os": "linux", "variant": "v7"}},
+                {"digest": "sha256:c", "platform": {"architecture": "unknown", "os": "unknown"}}
+            ]
+        }"#;
+        let found = parse_manifest(index).unwrap();
+        assert!(found.list);
+        assert_eq!(
+            found.platforms,
+            vec![
+                Platform::parse("linux/amd64").unwrap(),
+                Platform::parse("linux/arm/v7").unwrap()
+            ]
+        );
+
+        let amd64 = Platform::parse("linux/x86_64").unwrap();
+        let arm64 = Platform::parse("linux/aarch64").unwrap();
+        assert_eq!(amd64.to_string(), "linux/amd64");
+        let lenient = PlatformChoice::default();
+        let auto = PlatformChoice::new(None, true);
+        assert_eq!(
+            select("app", &found, &lenient, &amd64, |_| false).unwrap(),
+            None
+        );
+
+        let err = select("app", &found, &lenient, &arm64, |_| false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("linux/amd64, linux/arm/v7, not linux/arm64"));
+        assert!(message.contains("auto_select = true"));
+        assert!(select("app", &found, &auto, &arm64, |_| false).is_err());
+        assert_eq!(
+            select("app", &found, &auto, &arm64, |_| true).unwrap(),
+            Some(Platform::parse("linux/amd64").unwrap())
+        );
+        let explicit = PlatformChoice::new(Some("linux/arm/v7"), false);
+        assert_eq!(
+            select("app", &found, &explicit, &amd64, |_| false).unwrap(),
+            Some(Platform::parse("linux/arm/v7").unwrap())
+        );
+
+        let windows = ImagePlatforms {
+            list: false,
+            platforms: vec![parse_config(r#"{"architecture": "amd64", "os": "windows"}"#).unwrap()],
+        };
+        let message = select(
+            "mcr.microsoft.com/windows/servercore",
+            &windows,
+            &lenient,
+            &amd64,
+            |_| true,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(message.contains("Windows image (windows/amd64)"));
+        assert!(message.contains("Wine/Proton"));
+
+        let verbose = r#"{"Ref": "x", "Descriptor": {"platform": {"architecture": "arm64", "os": "darwin"}}}"#;
+        let mac = parse_docker_verbose(verbose).unwrap();
+        assert!(!mac.list);
+        let message = select("mac", &mac, &lenient, &amd64, |_| true)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("macOS image (darwin/arm64)"));
+        assert!(Platform::parse("linux").is_err());
+    }
+}
@@ -0,0 +1,422 @@
+// Image pull policies
+//
+// A service's `pull_policy` (or `--pull` on `bolt run` / `surge up`) decides
+// when its image is fetched again:
+//
+// - `always`: pull on every start, failing if the registry is unreachable
+// - `if-not-present`: pull only when the image is missing locally
+// - `never`: only use a local image
+// - `digest-pinned`: the image must be pinned (`nginx@sha256:...`); it is
+//   pulled when missing and its digest verified before the container starts
+//
+// Pinned images are verified whatever the policy. Without one, surge pulls on
+// every start but falls back to a local copy when the pull fails. Encrypted
+// layers are decrypted on pull (see encryption.rs). With `[p2p] enabled`,
+// layers come from other Bolt nodes on the LAN where they can (see
+// storage/p2p.rs), and from the registry through the runtime otherwise.
+//
+// Images are checked against the platform asked for before and after the
+// pull (see platform.rs).
+//
+// The digest a container was started from is kept under
+// `<data_dir>/images/<container>.json` so `surge plan` can tell when the tag
+// has moved upstream.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::encryption::DecryptionKeys;
+use super::platform::{Platform, PlatformChoice};
+use crate::config::BoltConfig;
+use crate::error::{BoltError, RuntimeError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullPolicy {
+    Always,
+    /// Also accepted as "missing" and "if_not_present", as in Compose files
+    #[serde(alias = "missing", alias = "if_not_present")]
+    #[value(alias = "missing")]
+    IfNotPresent,
+    Never,
+    DigestPinned,
+}
+
+impl PullPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PullPolicy::Always => "always",
+            PullPolicy::IfNotPresent => "if-not-present",
+            PullPolicy::Never => "never",
+            PullPolicy::DigestPinned => "digest-pinned",
+        }
+    }
+
+    /// Check the policy can be used with `image`
+    pub fn validate(&self, image: &str) -> Result<()> {
+        if *self == PullPolicy::DigestPinned && pinned_digest(image).is_none() {
+            return Err(anyhow!(
+                "pull_policy = \"digest-pinned\" needs an image pinned by digest (e.g. {}@sha256:...), got '{}'",
+                image.split(':').next().unwrap_or(image),
+                image
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PullPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(PullPolicy::Always),
+            "if-not-present" | "if_not_present" | "missing" => Ok(PullPolicy::IfNotPresent),
+            "never" => Ok(PullPolicy::Never),
+            "digest-pinned" => Ok(PullPolicy::DigestPinned),
+            other => Err(anyhow!(
+                "Invalid pull policy '{}': use always, if-not-present, never or digest-pinned",
+                other
+            )),
+        }
+    }
+}
+
+/// The image a container was started from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRecord {
+    pub image: String,
+    /// Registry digest (`sha256:...`), or the local image ID for images that
+    /// were never pushed
+    pub digest: Option<String>,
+    pub pulled: bool,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// `sha256:<hex>` of an image reference pinned by digest
+pub fn pinned_digest(image: &str) -> Option<&str> {
+    let (_, digest) = image.rsplit_once('@')?;
+    let hex = digest.strip_prefix("sha256:")?;
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(digest)
+}
+
+/// Make `image` available according to `policy` and return the digest it
+/// resolved to. `None` keeps surge's lenient default. The image must be for
+/// the platform `platform` asks for. Encrypted layers are decrypted with
+/// `keys`.
+pub async fn ensure_image(
+    config: &BoltConfig,
+    runtime: &str,
+    image: &str,
+    policy: Option<PullPolicy>,
+    platform: &PlatformChoice,
+    keys: &DecryptionKeys,
+) -> Result<ImageRecord> {
+    let pin = pinned_digest(image);
+    let policy = match (policy, pin) {
+        (Some(policy), _) => {
+            policy.validate(image)?;
+            Some(policy)
+        }
+        (None, Some(_)) => Some(PullPolicy::DigestPinned),
+        (None, None) => None,
+    };
+    // A local image for another platform than the one asked for is missing
+    let present = image_exists(runtime, image).await
+        && super::platform::local_matches(runtime, image, platform).await;
+
+    let pulling = match policy {
+        Some(PullPolicy::Never) => false,
+        Some(PullPolicy::IfNotPresent) | Some(PullPolicy::DigestPinned) => !present,
+        Some(PullPolicy::Always) | None => true,
+    };
+    let selected = if pulling {
+        super::platform::resolve(runtime, image, platform).await?
+    } else {
+        platform
+            .requested
+            .as_deref()
+            .map(Platform::parse)
+            .transpose()?
+    };
+    let target = selected.as_ref();
+
+    let pulled = match policy {
+        Some(PullPolicy::Always) => {
+            pull(config, runtime, image, target, keys).await?;
+            true
+        }
+        Some(PullPolicy::Never) if !present => {
+            return Err(anyhow!(
+                "Image {} is not available locally and pull_policy is \"never\"",
+                image
+            ));
+        }
+        Some(PullPolicy::Never) => false,
+        Some(PullPolicy::IfNotPresent) | Some(PullPolicy::DigestPinned) if present => {
+            debug!("Image {} is present, not pulling", image);
+            false
+        }
+        Some(PullPolicy::IfNotPresent) | Some(PullPolicy::DigestPinned) => {
+            pull(config, runtime, image, target, keys).await?;
+            true
+        }
+        None => match pull(config, runtime, image, target, keys).await {
+            Ok(()) => true,
+            Err(e) if present => {
+                warn!(
+                    "Could not pull image {}, using the local image: {}",
+                    image, e
+                );
+                false
+            }
+            Err(e) => return Err(e),
+        },
+    };
+
+    super::platform::verify_local(runtime, image, target, platform).await?;
+
+    let digests = local_digests(runtime, image).await;
+    if let Some(pin) = pin {
+        if !digests.iter().any(|d| d == pin) {
+            return Err(anyhow!(
+                "Image {} does not match its pinned digest (local digests: {})",
+                image,
+                if digests.is_empty() {
+                    "none".to_string()
+                } else {
+                    digests.join(", ")
+                }
+            ));
+        }
+        info!("🔏 Verified {} against its pinned digest", image);
+    }
+
+    let digest = match pin {
+        Some(pin) => Some(pin.to_string()),
+        None => match digests.into_iter().next() {
+            Some(digest) => Some(digest),
+            None => image_id(runtime, image).await,
+        },
+    };
+    Ok(ImageRecord {
+        image: image.to_string(),
+        digest,
+        pulled,
+        recorded_at: chrono::Utc::now(),
+    })
+}
+
+/// Pull `image` for `platform`, from LAN peers when `[p2p]` is enabled
+pub async fn pull(
+    config: &BoltConfig,
+    runtime: &str,
+    image: &str,
+    platform: Option<&Platform>,
+    keys: &DecryptionKeys,
+) -> Result<()> {
+    if !keys.is_empty() {
+        return super::encryption::pull(runtime, image, platform, keys).await;
+    }
+    #[cfg(feature = "quic-networking")]
+    if config.p2p.enabled && pinned_digest(image).is_none() {
+        info!(
+            "⬇️  Pulling image: {} (from LAN peers where possible)",
+            image
+        );
+        let wanted = platform.cloned().unwrap_or_else(Platform::host);
+        match super::storage::p2p::pull(config, runtime, image, &wanted).await {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!(
+                "⚠️  Peer-to-peer pull of {} failed, pulling with {}: {:#}",
+                image, runtime, e
+            ),
+        }
+    }
+    #[cfg(not(feature = "quic-networking"))]
+    let _ = config;
+    info!("⬇️  Pulling image: {}", image);
+    let mut command = AsyncCommand::new(runtime);
+    command.arg("pull");
+    if let Some(platform) = platform {
+        command.arg("--platform").arg(platform.to_string());
+    }
+    let output = command
+        .arg(image)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} pull", runtime))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let fallback = RuntimeError::ImagePullFailed {
+            image: format!("{}: {}", image, stderr.trim()),
+        };
+        return Err(BoltError::Runtime(RuntimeError::classify(image, &stderr, fallback)).into());
+    }
+    info!("✅ Image pulled successfully: {}", image);
+    Ok(())
+}
+
+pub async fn image_exists(runtime: &str, image: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["image", "inspect", image])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+/// Registry digests of a local image, from its `RepoDigests`
+pub async fn local_digests(runtime: &str, image: &str) -> Vec<String> {
+    let Ok(output) = AsyncCommand::new(runtime)
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{json .RepoDigests}}",
+            image,
+        ])
+        .output()
+        .await
+    else {
+        return Vec::new();
+    };
+    parse_repo_digests(&String::from_utf8_lossy(&output.stdout))
+}
+
+async fn image_id(runtime: &str, image: &str) -> Option<String> {
+    let output = AsyncCommand::new(runtime)
+        .args(["image", "inspect", "--format", "{{.Id}}", image])
+        .output()
+        .await
+        .ok()?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || id.is_empty() {
+        return None;
+    }
+    // Podman prints the bare hex ID
+    Some(if id.contains(':') {
+        id
+    } else {
+        format!("sha256:{}", id)
+    })
+}
+
+/// `["docker.io/library/nginx@sha256:..."]` -> `["sha256:..."]`
+fn parse_repo_digests(json: &str) -> Vec<String> {
+    serde_json::from_str::<Option<Vec<String>>>(json.trim())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|d| d.rsplit_once('@').map(|(_, digest)| digest.to_string()))
+        .collect()
+}
+
+/// Digest the registry currently serves for `image`, using skopeo or
+/// `docker buildx imagetools`. `None` when neither can tell.
+pub async fn remote_digest(image: &str) -> Option<String> {
+    if let Some(pin) = pinned_digest(image) {
+        return Some(pin.to_string());
+    }
+    let skopeo = AsyncCommand::new("skopeo")
+        .args(["inspect", "--format", "{{.Digest}}"])
+        .arg(format!("docker://{}", image))
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|d| d.starts_with("sha256:"));
+    if skopeo.is_some() {
+        return skopeo;
+    }
+
+    let output = AsyncCommand::new("docker")
+        .args([
+            "buildx",
+            "imagetools",
+            "inspect",
+            "--format",
+            "{{json .Manifest.Digest}}",
+            image,
+        ])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        debug!("Could not resolve the remote digest of {}", image);
+        return None;
+    }
+    serde_json::from_slice::<String>(&output.stdout)
+        .ok()
+        .filter(|d| d.starts_with("sha256:"))
+}
+
+fn record_path(data_dir: &Path, container: &str) -> PathBuf {
+    data_dir.join("images").join(format!("{}.json", container))
+}
+
+/// Remember which image digest `container` was started from
+pub fn record(data_dir: &Path, container: &str, record: &ImageRecord) -> Result<()> {
+    let path = record_path(data_dir, container);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(record)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn recorded(data_dir: &Path, container: &str) -> Option<ImageRecord> {
+    std::fs::read(record_path(data_dir, container))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+pub fn forget(data_dir: &Path, container: &str) {
+    let _ = std::fs::remove_file(record_path(data_dir, container));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policies_and_pinned_digests() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let pinned = format!("nginx@{}", digest);
+        assert_eq!(pinned_digest(&pinned), Some(digest.as_str()));
+        assert_eq!(pinned_digest("nginx:1.25"), None);
+        assert_eq!(pinned_digest("nginx@sha256:abc"), None);
+
+        assert_eq!(
+            "missing".parse::<PullPolicy>().unwrap(),
+            PullPolicy::IfNotPresent
+        );
+        assert!("sometimes".parse::<PullPolicy>().is_err());
+        let policy: PullPolicy = serde_json::from_str("\"digest-pinned\"").unwrap();
+        assert!(policy.validate(&pinned).is_ok());
+        assert!(policy.validate("nginx:latest").is_err());
+
+        let digests = parse_repo_digests(&format!("[\"docker.io/library/{}\"]\n", pinned));
+        assert_eq!(digests, vec![digest.clone()]);
+        assert!(parse_repo_digests("null").is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let record = ImageRecord {
+            image: pinned.clone(),
+            digest: Some(digest),
+            pulled: true,
+            recorded_at: chrono::Utc::now(),
+        };
+        super::record(dir.path(), "web", &record).unwrap();
+        assert_eq!(recorded(dir.path(), "web"), Some(record));
+        forget(dir.path(), "web");
+        assert!(recorded(dir.path(), "web").is_none());
+    }
+}
@@ -0,0 +1,297 @@
+// Readiness probes
+//
+// Readiness answers "can this replica take traffic yet", separately from the
+// liveness `healthcheck`. Surge waits for a dependency to become ready before
+// starting the services that depend on it, and the proxy only routes to
+// targets whose probe passes.
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use crate::builds::cache::parse_duration;
+use crate::config::ReadinessConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProbeCheck {
+    Tcp { port: u16 },
+    Http { port: u16, path: String },
+    Command { argv: Vec<String> },
+}
+
+/// Where a probe runs: inside a container (ports resolved through the
+/// runtime) or against a plain address, as the proxy does
+#[derive(Debug, Clone)]
+pub enum ProbeTarget {
+    Container { runtime: String, container: String },
+    Address(IpAddr),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadinessProbe {
+    pub check: ProbeCheck,
+    pub initial_delay: Duration,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub success_threshold: u32,
+    pub failure_threshold: u32,
+    pub start_timeout: Duration,
+}
+
+impl ReadinessProbe {
+    pub fn from_config(config: &ReadinessConfig) -> Result<Self> {
+        let check = match (&config.tcp, &config.http, &config.command) {
+            (Some(port), None, None) => ProbeCheck::Tcp { port: *port },
+            (None, Some(path), None) => ProbeCheck::Http {
+                port: config
+                    .port
+                    .ok_or_else(|| anyhow!("an http probe needs a port"))?,
+                path: if path.starts_with('/') {
+                    path.clone()
+                } else {
+                    format!("/{}", path)
+                },
+            },
+            (None, None, Some(argv)) if !argv.is_empty() => {
+                ProbeCheck::Command { argv: argv.clone() }
+            }
+            (None, None, Some(_)) => return Err(anyhow!("the probe command is empty")),
+            _ => return Err(anyhow!("set exactly one of tcp, http or command")),
+        };
+
+        Ok(Self {
+            check,
+            initial_delay: parse_duration(&config.initial_delay)?,
+            interval: parse_duration(&config.interval)?,
+            timeout: parse_duration(&config.timeout)?,
+            success_threshold: config.success_threshold.max(1),
+            failure_threshold: config.failure_threshold.max(1),
+            start_timeout: parse_duration(&config.start_timeout)?,
+        })
+    }
+
+    /// Run the check once
+    pub async fn probe(&self, target: &ProbeTarget) -> bool {
+        match &self.check {
+            ProbeCheck::Tcp { port } => match resolve(target, *port).await {
+                Ok(addr) => tokio::time::timeout(self.timeout, TcpStream::connect(addr))
+                    .await
+                    .is_ok_and(|r| r.is_ok()),
+                Err(e) => {
+                    debug!("Readiness: {}", e);
+                    false
+                }
+            },
+            ProbeCheck::Http { port, path } => match resolve(target, *port).await {
+                Ok(addr) => {
+                    let url = format!("http://{}{}", addr, path);
+                    reqwest::Client::new()
+                        .get(&url)
+                        .timeout(self.timeout)
+                        .send()
+                        .await
+                        .is_ok_and(|r| r.status() == reqwest::StatusCode::OK)
+                }
+                Err(e) => {
+                    debug!("Readiness: {}", e);
+                    false
+                }
+            },
+            ProbeCheck::Command { argv } => {
+                let mut cmd = match target {
+                    ProbeTarget::Container { runtime, container } => {
+                        let mut cmd = AsyncCommand::new(runtime);
+                        cmd.arg("exec").arg(container).args(argv);
+                        cmd
+                    }
+                    ProbeTarget::Address(_) => {
+                        let mut cmd = AsyncCommand::new(&argv[0]);
+                        cmd.args(&argv[1..]);
+                        cmd
+                    }
+                };
+                cmd.stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null());
+                tokio::time::timeout(self.timeout, cmd.status())
+                    .await
+                    .is_ok_and(|r| r.is_ok_and(|s| s.success()))
+            }
+        }
+    }
+
+    /// Probe a freshly started container until it passes `success_threshold`
+    /// times in a row, failing after `start_timeout` or if it exits
+    pub async fn wait_until_ready(&self, runtime: &str, container: &str) -> Result<Duration> {
+        let started = Instant::now();
+        let target = ProbeTarget::Container {
+            runtime: runtime.to_string(),
+            container: container.to_string(),
+        };
+        tokio::time::sleep(self.initial_delay).await;
+
+        let mut tracker = ReadinessTracker::new(self);
+        loop {
+            if tracker.observe(self.probe(&target).await) == Some(true) {
+                return Ok(started.elapsed());
+            }
+            if !is_running(runtime, container).await {
+                return Err(anyhow!(
+                    "{} exited before becoming ready (see `bolt surge logs`)",
+                    container
+                ));
+            }
+            if started.elapsed() >= self.start_timeout {
+                return Err(anyhow!(
+                    "{} did not become ready within {}s",
+                    container,
+                    self.start_timeout.as_secs()
+                ));
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
+
+/// Turns individual probe results into ready/unready transitions
+#[derive(Debug, Clone)]
+pub struct ReadinessTracker {
+    success_threshold: u32,
+    failure_threshold: u32,
+    successes: u32,
+    failures: u32,
+    ready: bool,
+}
+
+impl ReadinessTracker {
+    pub fn new(probe: &ReadinessProbe) -> Self {
+        Self {
+            success_threshold: probe.success_threshold,
+            failure_threshold: probe.failure_threshold,
+            successes: 0,
+            failures: 0,
+            ready: false,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Record a probe result; returns the new state when it changes
+    pub fn observe(&mut self, passed: bool) -> Option<bool> {
+        if passed {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+        }
+
+        let ready = if self.ready {
+            self.failures < self.failure_threshold
+        } else {
+            self.successes >= self.success_threshold
+        };
+        (ready != self.ready).then(|| {
+            self.ready = ready;
+            ready
+        })
+    }
+}
+
+/// Address to probe a container port at: the published host port if there is
+/// one, otherwise the container's own IP
+async fn resolve(target: &ProbeTarget, port: u16) -> Result<SocketAddr> {
+    let (runtime, container) = match target {
+        ProbeTarget::Address(ip) => return Ok(SocketAddr::new(*ip, port)),
+        ProbeTarget::Container { runtime, container } => (runtime, container),
+    };
+
+    let output = AsyncCommand::new(runtime)
+        .args(["port", container, &format!("{}/tcp", port)])
+        .output()
+        .await?;
+    if output.status.success()
+        && let Some(published) = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|l| l.trim().parse::<SocketAddr>().ok())
+    {
+        // Published on all interfaces; loopback reaches it
+        let ip = if published.ip().is_unspecified() {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            published.ip()
+        };
+        return Ok(SocketAddr::new(ip, published.port()));
+    }
+
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            "{{range .NetworkSettings.Networks}}{{.IPAddress}} {{end}}",
+            container,
+        ])
+        .output()
+        .await?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .find_map(|ip| ip.parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, port))
+        .ok_or_else(|| anyhow!("{} has no reachable address for port {}", container, port))
+}
+
+async fn is_running(runtime: &str, container: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["inspect", "--format", "{{.State.Running}}", container])
+        .output()
+        .await
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "true")
+}
+
+/// Wait for a container's readiness, logging how long it took
+pub async fn wait_for(runtime: &str, container: &str, config: &ReadinessConfig) -> Result<()> {
+    let probe = ReadinessProbe::from_config(config)?;
+    info!("⏳ Waiting for {} to become ready", container);
+    let elapsed = probe.wait_until_ready(runtime, container).await?;
+    info!("✅ {} ready after {:.1}s", container, elapsed.as_secs_f64());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_applies_thresholds() {
+        let config = ReadinessConfig {
+            tcp: Some(5432),
+            success_threshold: 2,
+            failure_threshold: 3,
+            ..Default::default()
+        };
+        let probe = ReadinessProbe::from_config(&config).unwrap();
+        let mut tracker = ReadinessTracker::new(&probe);
+
+        assert_eq!(tracker.observe(true), None);
+        assert_eq!(tracker.observe(true), Some(true));
+        assert_eq!(tracker.observe(false), None);
+        assert_eq!(tracker.observe(false), None);
+        assert_eq!(tracker.observe(true), None);
+        assert!(tracker.is_ready());
+        for _ in 0..2 {
+            tracker.observe(false);
+        }
+        assert_eq!(tracker.observe(false), Some(false));
+
+        let both = ReadinessConfig {
+            tcp: Some(80),
+            http: Some("/ready".to_string()),
+            ..Default::default()
+        };
+        assert!(ReadinessProbe::from_config(&both).is_err());
+    }
+}
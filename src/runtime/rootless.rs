@@ -0,0 +1,701 @@
+// Rootless mode
+//
+// Bolt runs as an ordinary user. Its containers then live in a user
+// namespace where that user is root. The user's subordinate ranges in
+// /etc/subuid and /etc/subgid map container IDs 1 and up, so images with
+// files of other owners still work; the setuid newuidmap and newgidmap
+// helpers write those maps. Without ranges or helpers only the user's own
+// ID is mapped, and files of other owners appear as nobody.
+//
+// The namespace is created by a holder process, which has its maps written
+// from outside as the kernel requires; the container process joins it
+// between fork and exec and unshares its other namespaces. Unsharing the
+// PID namespace only places the caller's children in it, so the process
+// forks once more, like `unshare --fork`: the child becomes PID 1, mounts a
+// fresh /proc and /sys and pivots into the rootfs, and the parent stays
+// outside to forward signals to it and exit with its status.
+// Networking goes through pasta or slirp4netns (`networking::rootless`),
+// and volumes and images are kept under $XDG_DATA_HOME/bolt instead of
+// /var/lib/bolt.
+use anyhow::{Context, Result};
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use tracing::{debug, info};
+
+use super::doctor::DoctorCheck;
+
+/// Where Bolt keeps volumes and images when it runs as root
+const SYSTEM_STORAGE: &str = "/var/lib/bolt";
+
+/// The kernel takes at most this many lines in a uid_map or gid_map
+const MAX_MAP_LINES: usize = 340;
+
+/// Whether Bolt runs without root
+pub fn active() -> bool {
+    super::capabilities::effective_uid().is_some_and(|uid| uid != 0)
+}
+
+/// Root of Bolt's volume and image storage: /var/lib/bolt as root,
+/// $XDG_DATA_HOME/bolt (~/.local/share/bolt) otherwise
+pub fn storage_root() -> PathBuf {
+    if !active() {
+        return PathBuf::from(SYSTEM_STORAGE);
+    }
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("bolt")
+}
+
+/// IDs /etc/subuid or /etc/subgid grant a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+/// The ranges `contents` of /etc/subuid or /etc/subgid grant the user,
+/// listed by name or ID
+pub fn subordinate_ranges(contents: &str, user: &str, id: u32) -> Vec<IdRange> {
+    let id = id.to_string();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let owner = fields.next()?;
+            let start = fields.next()?.trim().parse().ok()?;
+            let count = fields.next()?.trim().parse().ok()?;
+            (owner == user || owner == id).then_some(IdRange { start, count })
+        })
+        .filter(|range| range.count > 0)
+        .collect()
+}
+
+/// One line of a uid_map or gid_map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdMap {
+    pub inside: u32,
+    pub outside: u32,
+    pub count: u32,
+}
+
+/// Container root is `own`; container IDs from 1 follow the subordinate
+/// ranges
+pub fn id_maps(own: u32, ranges: &[IdRange]) -> Vec<IdMap> {
+    let mut maps = vec![IdMap {
+        inside: 0,
+        outside: own,
+        count: 1,
+    }];
+    let mut next: u32 = 1;
+    for range in ranges.iter().take(MAX_MAP_LINES - 1) {
+        maps.push(IdMap {
+            inside: next,
+            outside: range.start,
+            count: range.count,
+        });
+        next = next.saturating_add(range.count);
+    }
+    maps
+}
+
+fn map_args(maps: &[IdMap]) -> Vec<String> {
+    maps.iter()
+        .flat_map(|m| [m.inside, m.outside, m.count])
+        .map(|id| id.to_string())
+        .collect()
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn user_name(uid: u32) -> String {
+    nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid))
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .unwrap_or_default()
+}
+
+fn own_ids() -> (u32, u32) {
+    // SAFETY: getuid and getgid can't fail
+    unsafe { (libc::getuid(), libc::getgid()) }
+}
+
+/// Subordinate UIDs and GIDs of the current user
+fn own_ranges() -> (Vec<IdRange>, Vec<IdRange>) {
+    let (uid, _) = own_ids();
+    let user = user_name(uid);
+    let read = |path: &str| {
+        std::fs::read_to_string(path)
+            .map(|contents| subordinate_ranges(&contents, &user, uid))
+            .unwrap_or_default()
+    };
+    (read("/etc/subuid"), read("/etc/subgid"))
+}
+
+/// A user namespace for a rootless container. Dropping it stops the holder
+/// process; the namespace lives on while a container process is in it.
+pub struct UserNamespace {
+    holder: Child,
+    fd: OwnedFd,
+    pub uids: Vec<IdMap>,
+    pub gids: Vec<IdMap>,
+}
+
+impl UserNamespace {
+    pub fn create() -> Result<Self> {
+        let (uid, gid) = own_ids();
+        let (subuids, subgids) = own_ranges();
+
+        // `cat` waits on its stdin until the holder is dropped
+        let mut cmd = Command::new("cat");
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // SAFETY: unshare is a plain syscall, safe between fork and exec
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::unshare(libc::CLONE_NEWUSER) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut holder = cmd.spawn().context(
+            "Failed to create a user namespace; are unprivileged user namespaces enabled?",
+        )?;
+
+        let pid = holder.id();
+        let result = write_map(pid, "uid", uid, &subuids).and_then(|uids| {
+            let gids = write_map(pid, "gid", gid, &subgids)?;
+            let ns = std::fs::File::open(format!("/proc/{}/ns/user", pid))
+                .context("Failed to open the user namespace")?;
+            Ok((uids, gids, OwnedFd::from(ns)))
+        });
+        let (uids, gids, fd) = match result {
+            Ok(namespace) => namespace,
+            Err(e) => {
+                let _ = holder.kill();
+                let _ = holder.wait();
+                return Err(
+                    e.context("Failed to set up the user namespace of a rootless container")
+                );
+            }
+        };
+        info!(
+            "👤 User namespace: {} UIDs and {} GIDs mapped",
+            uids.iter().map(|m| m.count as u64).sum::<u64>(),
+            gids.iter().map(|m| m.count as u64).sum::<u64>()
+        );
+        Ok(Self {
+            holder,
+            fd,
+            uids,
+            gids,
+        })
+    }
+
+    /// Prepare entering the namespace from a forked child: join it, unshare
+    /// `flags`, make `mounts` (relative to `rootfs`) and pivot into `rootfs`
+    pub fn entry(
+        &self,
+        flags: libc::c_int,
+        rootfs: &Path,
+        mounts: &[RootlessMount],
+        hostname: Option<&str>,
+    ) -> Result<NamespaceEntry> {
+        let cstring = |path: &Path| {
+            CString::new(path.as_os_str().as_bytes())
+                .with_context(|| format!("Invalid path {}", path.display()))
+        };
+        let mut prepared = Vec::new();
+        for mount in mounts {
+            let target = rootfs.join(mount.target.strip_prefix("/").unwrap_or(&mount.target));
+            if mount.fstype.is_some() || mount.source.is_dir() {
+                std::fs::create_dir_all(&target)
+                    .with_context(|| format!("Failed to create {}", target.display()))?;
+            } else if !target.exists() {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::File::create(&target)
+                    .with_context(|| format!("Failed to create {}", target.display()))?;
+            }
+            prepared.push(PreparedMount {
+                source: cstring(&mount.source)?,
+                target: cstring(&target)?,
+                fstype: mount.fstype.map(|t| CString::new(t).unwrap_or_default()),
+                flags: mount.flags,
+            });
+        }
+        Ok(NamespaceEntry {
+            fd: self.fd.as_raw_fd(),
+            flags,
+            rootfs: cstring(rootfs)?,
+            mounts: prepared,
+            hostname: hostname.map(|h| h.as_bytes().to_vec()),
+        })
+    }
+}
+
+impl Drop for UserNamespace {
+    fn drop(&mut self) {
+        let _ = self.holder.kill();
+        let _ = self.holder.wait();
+    }
+}
+
+/// Write the uid_map or gid_map of `pid`: through newuidmap/newgidmap
+/// with subordinate ranges, else just the user's own ID
+fn write_map(pid: u32, kind: &str, own: u32, ranges: &[IdRange]) -> Result<Vec<IdMap>> {
+    let helper = format!("new{}map", kind);
+    if !ranges.is_empty() && in_path(&helper) {
+        let maps = id_maps(own, ranges);
+        let output = Command::new(&helper)
+            .arg(pid.to_string())
+            .args(map_args(&maps))
+            .output()
+            .with_context(|| format!("Failed to run {}", helper))?;
+        if output.status.success() {
+            return Ok(maps);
+        }
+        debug!(
+            "{} failed, mapping only the own {}: {}",
+            helper,
+            kind,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // An unprivileged process may map its own ID, its GID only once
+    // setgroups is denied
+    if kind == "gid" {
+        std::fs::write(format!("/proc/{}/setgroups", pid), "deny")
+            .context("Failed to deny setgroups")?;
+    }
+    let maps = id_maps(own, &[]);
+    std::fs::write(
+        format!("/proc/{}/{}_map", pid, kind),
+        format!("0 {} 1\n", own),
+    )
+    .with_context(|| format!("Failed to write the {} map", kind))?;
+    Ok(maps)
+}
+
+/// A mount made inside the container's mount namespace
+#[derive(Debug, Clone)]
+pub struct RootlessMount {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub fstype: Option<&'static str>,
+    pub flags: libc::c_ulong,
+}
+
+impl RootlessMount {
+    pub fn bind(source: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            target: target.into(),
+            fstype: None,
+            flags: libc::MS_BIND | libc::MS_REC,
+        }
+    }
+
+    pub fn tmpfs(target: impl Into<PathBuf>) -> Self {
+        Self {
+            source: PathBuf::from("tmpfs"),
+            target: target.into(),
+            fstype: Some("tmpfs"),
+            flags: libc::MS_NOSUID | libc::MS_NODEV,
+        }
+    }
+
+    /// A procfs of the container's PID namespace
+    pub fn proc(target: impl Into<PathBuf>) -> Self {
+        Self {
+            source: PathBuf::from("proc"),
+            target: target.into(),
+            fstype: Some("proc"),
+            flags: libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+        }
+    }
+
+    /// A read-only sysfs of the container's network namespace
+    pub fn sysfs(target: impl Into<PathBuf>) -> Self {
+        Self {
+            source: PathBuf::from("sysfs"),
+            target: target.into(),
+            fstype: Some("sysfs"),
+            flags: libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC | libc::MS_RDONLY,
+        }
+    }
+}
+
+struct PreparedMount {
+    source: CString,
+    target: CString,
+    fstype: Option<CString>,
+    flags: libc::c_ulong,
+}
+
+/// Everything a forked child needs to enter a rootless container, prepared
+/// beforehand so `enter` makes only syscalls
+pub struct NamespaceEntry {
+    fd: libc::c_int,
+    flags: libc::c_int,
+    rootfs: CString,
+    mounts: Vec<PreparedMount>,
+    hostname: Option<Vec<u8>>,
+}
+
+impl NamespaceEntry {
+    /// Whether entering forks the container's PID 1 off the spawned process
+    pub fn forks(&self) -> bool {
+        self.flags & libc::CLONE_NEWPID != 0
+    }
+
+    /// Join the user namespace and set up the rest; call between fork and
+    /// exec
+    pub fn enter(&self) -> std::io::Result<()> {
+        let check = |rc: libc::c_int| {
+            if rc == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        };
+        // SAFETY: plain syscalls on fds and strings prepared before fork
+        unsafe {
+            check(libc::setns(self.fd, libc::CLONE_NEWUSER))?;
+            check(libc::unshare(self.flags))?;
+            if self.forks() {
+                match libc::fork() {
+                    -1 => return Err(std::io::Error::last_os_error()),
+                    0 => {
+                        // PID 1 of the namespace goes down with its parent
+                        check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL))?;
+                    }
+                    child => supervise(child),
+                }
+            }
+            if self.flags & libc::CLONE_NEWNS == 0 {
+                check(libc::chroot(self.rootfs.as_ptr()))?;
+                return check(libc::chdir(c"/".as_ptr()));
+            }
+            check(libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ))?;
+            // pivot_root needs the new root to be a mount point
+            check(libc::mount(
+                self.rootfs.as_ptr(),
+                self.rootfs.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                std::ptr::null(),
+            ))?;
+            for mount in &self.mounts {
+                check(libc::mount(
+                    mount.source.as_ptr(),
+                    mount.target.as_ptr(),
+                    mount
+                        .fstype
+                        .as_ref()
+                        .map_or(std::ptr::null(), |t| t.as_ptr()),
+                    mount.flags,
+                    std::ptr::null(),
+                ))?;
+            }
+            if let Some(ref hostname) = self.hostname
+                && self.flags & libc::CLONE_NEWUTS != 0
+            {
+                check(libc::sethostname(hostname.as_ptr().cast(), hostname.len()))?;
+            }
+            // Stack the old root on the new one and detach it, so no
+            // directory for it is needed in the rootfs
+            check(libc::chdir(self.rootfs.as_ptr()))?;
+            check(
+                libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int,
+            )?;
+            check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+            check(libc::chdir(c"/".as_ptr()))
+        }
+    }
+}
+
+/// PID of the namespace's PID 1, to which the process outside forwards
+/// signals
+static SUPERVISED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let pid = SUPERVISED.load(Ordering::Relaxed);
+    if pid > 0 {
+        // SAFETY: kill is async-signal-safe
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+/// Wait for the forked PID 1 and exit with its status, forwarding signals
+/// to it meanwhile. Runs in the forked child of a threaded process, so only
+/// async-signal-safe calls are made.
+unsafe fn supervise(child: libc::pid_t) -> ! {
+    SUPERVISED.store(child, Ordering::Relaxed);
+    // SAFETY: plain syscalls; the fds closed are this process's copies
+    unsafe {
+        // Spawning waits for the exec to close its error pipe; only PID 1
+        // may keep it and the container's stdio open
+        if libc::syscall(libc::SYS_close_range, 0, libc::c_uint::MAX, 0) != 0 {
+            for fd in 0..1024 {
+                libc::close(fd);
+            }
+        }
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        for signal in [
+            libc::SIGTERM,
+            libc::SIGINT,
+            libc::SIGHUP,
+            libc::SIGQUIT,
+            libc::SIGUSR1,
+            libc::SIGUSR2,
+            libc::SIGWINCH,
+        ] {
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+        // A handler inherited from Bolt must not run here
+        libc::signal(libc::SIGCHLD, libc::SIG_DFL);
+
+        let mut status = 0;
+        while libc::waitpid(child, &mut status, 0) != child {
+            if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                libc::_exit(1);
+            }
+        }
+        if libc::WIFSIGNALED(status) {
+            let signal = libc::WTERMSIG(status);
+            libc::signal(signal, libc::SIG_DFL);
+            libc::kill(libc::getpid(), signal);
+            libc::_exit(128 + signal);
+        }
+        libc::_exit(libc::WEXITSTATUS(status))
+    }
+}
+
+/// The PID 1 an entry that forks made in its namespace, as seen from Bolt
+pub fn namespace_init(pid: u32) -> Option<u32> {
+    std::fs::read_to_string(format!("/proc/{}/task/{}/children", pid, pid))
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Preflight checks for running without root, for `bolt doctor`
+pub fn doctor_checks() -> Vec<DoctorCheck> {
+    let area = "rootless";
+    let mut checks = Vec::new();
+    let read = |path: &str| std::fs::read_to_string(path).map(|s| s.trim().to_string());
+
+    let userns_disabled = read("/proc/sys/user/max_user_namespaces").is_ok_and(|n| n == "0")
+        || read("/proc/sys/kernel/unprivileged_userns_clone").is_ok_and(|n| n == "0");
+    checks.push(if userns_disabled {
+        DoctorCheck::fail(
+            area,
+            "user namespaces",
+            "unprivileged user namespaces are disabled",
+            "sysctl -w kernel.unprivileged_userns_clone=1 user.max_user_namespaces=28633",
+        )
+    } else if read("/proc/sys/kernel/apparmor_restrict_unprivileged_userns").is_ok_and(|n| n == "1")
+    {
+        DoctorCheck::warn(
+            area,
+            "user namespaces",
+            "AppArmor restricts unprivileged user namespaces",
+            "allow userns in an AppArmor profile for bolt, or sysctl -w kernel.apparmor_restrict_unprivileged_userns=0",
+        )
+    } else {
+        DoctorCheck::pass(area, "user namespaces", "enabled")
+    });
+
+    let (uid, _) = own_ids();
+    let (subuids, subgids) = own_ranges();
+    let helpers = in_path("newuidmap") && in_path("newgidmap");
+    let count = |ranges: &[IdRange]| ranges.iter().map(|r| r.count as u64).sum::<u64>();
+    checks.push(if subuids.is_empty() || subgids.is_empty() {
+        DoctorCheck::warn(
+            area,
+            "subordinate IDs",
+            "no ranges in /etc/subuid and /etc/subgid; containers see only root",
+            format!(
+                "sudo usermod --add-subuids 100000-165535 --add-subgids 100000-165535 {}",
+                user_name(uid)
+            ),
+        )
+    } else if !helpers {
+        DoctorCheck::warn(
+            area,
+            "subordinate IDs",
+            "newuidmap/newgidmap not found; containers see only root",
+            "install the uidmap (shadow-utils) package",
+        )
+    } else {
+        DoctorCheck::pass(
+            area,
+            "subordinate IDs",
+            format!("{} UIDs, {} GIDs", count(&subuids), count(&subgids)),
+        )
+    });
+
+    checks.push(match crate::networking::rootless::Backend::detect() {
+        Some(backend) => DoctorCheck::pass(area, "network", backend.as_str()),
+        None => DoctorCheck::warn(
+            area,
+            "network",
+            "neither pasta nor slirp4netns found; containers have no network",
+            "install passt (for pasta) or slirp4netns",
+        ),
+    });
+
+    let storage = storage_root();
+    checks.push(match std::fs::create_dir_all(&storage) {
+        Ok(()) => DoctorCheck::pass(area, "storage", storage.display().to_string()),
+        Err(e) => DoctorCheck::fail(
+            area,
+            "storage",
+            format!("{}: {}", storage.display(), e),
+            "check permissions or set XDG_DATA_HOME",
+        ),
+    });
+
+    let delegated = read(&format!(
+        "/sys/fs/cgroup/user.slice/user-{0}.slice/user@{0}.service/cgroup.controllers",
+        uid
+    ))
+    .unwrap_or_default();
+    let missing: Vec<&str> = ["cpu", "memory", "pids", "io"]
+        .into_iter()
+        .filter(|c| !delegated.split_whitespace().any(|d| d == *c))
+        .collect();
+    checks.push(if missing.is_empty() {
+        DoctorCheck::pass(area, "cgroup delegation", delegated)
+    } else {
+        DoctorCheck::warn(
+            area,
+            "cgroup delegation",
+            format!("{} not delegated; those limits are not applied", missing.join(", ")),
+            "add Delegate=cpu cpuset io memory pids under [Service] in /etc/systemd/system/user@.service.d/delegate.conf",
+        )
+    });
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_root_to_the_user_and_the_rest_to_subordinate_ranges() {
+        let subuid =
+            "# comment\nalice:100000:65536\nbob:165536:65536\n1000:300000:1000\nalice:bad:1\n";
+        let ranges = subordinate_ranges(subuid, "alice", 1000);
+        assert_eq!(
+            ranges,
+            vec![
+                IdRange {
+                    start: 100000,
+                    count: 65536
+                },
+                IdRange {
+                    start: 300000,
+                    count: 1000
+                }
+            ]
+        );
+        assert!(subordinate_ranges(subuid, "carol", 1002).is_empty());
+
+        let maps = id_maps(1000, &ranges);
+        assert_eq!(
+            maps,
+            vec![
+                IdMap {
+                    inside: 0,
+                    outside: 1000,
+                    count: 1
+                },
+                IdMap {
+                    inside: 1,
+                    outside: 100000,
+                    count: 65536
+                },
+                IdMap {
+                    inside: 65537,
+                    outside: 300000,
+                    count: 1000
+                },
+            ]
+        );
+        assert_eq!(
+            map_args(&maps[..2]),
+            vec!["0", "1000", "1", "1", "100000", "65536"]
+        );
+        assert_eq!(id_maps(1000, &[]).len(), 1);
+    }
+
+    #[test]
+    fn runs_the_container_as_pid_1_of_its_namespaces() {
+        // Needs unprivileged user namespaces
+        let Ok(userns) = UserNamespace::create() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path().join("rootfs");
+        std::fs::create_dir(&rootfs).unwrap();
+        let mut mounts: Vec<RootlessMount> = ["/bin", "/usr", "/lib", "/lib64"]
+            .into_iter()
+            .filter(|path| Path::new(path).exists())
+            .map(|path| RootlessMount::bind(path, path))
+            .collect();
+        mounts.push(RootlessMount::proc("/proc"));
+        let entry = userns
+            .entry(
+                libc::CLONE_NEWPID | libc::CLONE_NEWNS,
+                &rootfs,
+                &mounts,
+                None,
+            )
+            .unwrap();
+
+        let mut cmd = Command::new("/bin/sh");
+        cmd.args(["-c", "echo $$; ls -a /; ls -d /proc/[0-9]* | wc -l; exit 3"]);
+        // SAFETY: enter() only makes syscalls
+        unsafe {
+            cmd.pre_exec(move || entry.enter());
+        }
+        let output = cmd.output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], "1");
+        // Only the rootfs is left, and /proc shows the namespace's processes
+        assert!(lines.contains(&"proc"));
+        assert!(!lines.contains(&"etc"));
+        let processes: usize = lines.last().unwrap().trim().parse().unwrap();
+        assert!(processes <= 3, "{} processes in /proc", processes);
+    }
+}
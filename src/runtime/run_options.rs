@@ -0,0 +1,229 @@
+// `bolt run` options
+//
+// The docker-run flags a one-off container gets on top of image, ports,
+// environment and volumes, with the same behavior as the matching Boltfile
+// service settings: `restart` is enforced by `bolt supervise` rather than
+// the runtime (see `supervisor.rs`), a healthcheck with a restart policy
+// also restarts the container once it turns unhealthy (see
+// `healthcheck.rs`), GPUs are handed out by their vendor's device plugin
+// once no other container holds them (see `gpu/allocation.rs`), and the
+// rest go to `podman run`/`docker run`.
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use tracing::warn;
+
+use crate::config::HealthcheckConfig;
+
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// no, always, on-failure[:N] or unless-stopped
+    pub restart: Option<String>,
+    pub healthcheck: Option<HealthcheckConfig>,
+    /// `key=value`
+    pub labels: Vec<String>,
+    /// `key=value`, into the runtime spec's annotations
+    pub annotations: Vec<String>,
+    pub user: Option<String>,
+    pub network: Option<String>,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub workdir: Option<String>,
+    pub hostname: Option<String>,
+    /// GPUs to pass in: "all", positions or PCI addresses, e.g. "0,1"
+    pub gpus: Option<String>,
+    /// Share the GPUs with other shared containers instead of holding them
+    /// exclusively
+    pub gpu_shared: bool,
+}
+
+impl RunOptions {
+    pub fn is_empty(&self) -> bool {
+        self.restart.is_none()
+            && self.healthcheck.is_none()
+            && self.labels.is_empty()
+            && self.annotations.is_empty()
+            && self.user.is_none()
+            && self.network.is_none()
+            && self.cap_add.is_empty()
+            && self.cap_drop.is_empty()
+            && self.workdir.is_none()
+            && self.hostname.is_none()
+            && self.gpus.is_none()
+            && !self.gpu_shared
+    }
+
+    /// Options for `runtime run`; the restart policy is checked here but
+    /// enforced by [`RunOptions::apply`], and the GPUs are reserved for
+    /// this process
+    pub fn run_args(&self, runtime: &str, data_dir: &Path) -> Result<Vec<String>> {
+        if let Some(ref restart) = self.restart {
+            super::supervisor::RestartSpec::parse(restart)?;
+        }
+        let mut args = Vec::new();
+        if let Some(ref healthcheck) = self.healthcheck {
+            args.extend(super::healthcheck::HealthcheckSpec::from_config(healthcheck)?.run_args());
+        }
+        for label in &self.labels {
+            if label.split_once('=').is_none_or(|(key, _)| key.is_empty()) {
+                return Err(anyhow!("Invalid label '{}': expected key=value", label));
+            }
+            args.extend(["--label".to_string(), label.clone()]);
+        }
+        args.extend(super::annotations::args(&super::annotations::parse(
+            &self.annotations,
+        )?));
+        let single = [
+            ("--user", &self.user),
+            ("--network", &self.network),
+            ("--workdir", &self.workdir),
+            ("--hostname", &self.hostname),
+        ];
+        for (flag, value) in single {
+            if let Some(value) = value {
+                args.extend([flag.to_string(), value.clone()]);
+            }
+        }
+        for cap in &self.cap_add {
+            args.extend(["--cap-add".to_string(), cap.clone()]);
+        }
+        for cap in &self.cap_drop {
+            args.extend(["--cap-drop".to_string(), cap.clone()]);
+        }
+        if let Some(ref selector) = self.gpus {
+            args.extend(gpu_args(runtime, data_dir, selector, self.gpu_shared)?);
+        }
+        Ok(args)
+    }
+
+    /// Enforce the restart policy of a started container, and restart it
+    /// when unhealthy, as surge does for detached services
+    pub fn apply(&self, data_dir: &Path, container: &str, detach: bool) -> Result<()> {
+        let Some(ref restart) = self.restart else {
+            return Ok(());
+        };
+        if !super::supervisor::RestartSpec::parse(restart)?.enabled() {
+            return Ok(());
+        }
+        if !detach {
+            warn!(
+                "--restart {} only applies to detached containers; {} runs once",
+                restart, container
+            );
+            return Ok(());
+        }
+        super::supervisor::start(data_dir, container, restart)?;
+        if let Some(ref healthcheck) = self.healthcheck
+            && super::healthcheck::HealthcheckSpec::from_config(healthcheck)?.enabled()
+        {
+            super::healthcheck::start(data_dir, container)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gaming")]
+fn gpu_args(runtime: &str, data_dir: &Path, selector: &str, shared: bool) -> Result<Vec<String>> {
+    use super::gpu::allocation::{self, Mode};
+    let mode = if shared {
+        Mode::Shared
+    } else {
+        Mode::Exclusive
+    };
+    let units = allocation::inventory();
+    let request = allocation::select(&units, selector)?;
+    allocation::claim(runtime, data_dir, &units, &request, mode)?;
+    Ok(allocation::run_args(runtime, &request, mode))
+}
+
+#[cfg(not(feature = "gaming"))]
+fn gpu_args(
+    _runtime: &str,
+    _data_dir: &Path,
+    _selector: &str,
+    _shared: bool,
+) -> Result<Vec<String>> {
+    Err(anyhow!("--gpu needs Bolt built with the gaming feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_flags_onto_run_args() {
+        let options = RunOptions {
+            restart: Some("on-failure:3".to_string()),
+            healthcheck: Some(HealthcheckConfig {
+                test: vec!["CMD-SHELL".to_string(), "curl -f localhost".to_string()],
+                interval: Some("10s".to_string()),
+                timeout: None,
+                retries: Some(2),
+                start_period: None,
+                disable: false,
+            }),
+            labels: vec!["team=games".to_string()],
+            annotations: vec!["run.oci.keep_original_groups=1".to_string()],
+            user: Some("1000:1000".to_string()),
+            network: Some("backend".to_string()),
+            cap_add: vec!["NET_ADMIN".to_string()],
+            cap_drop: vec!["ALL".to_string()],
+            workdir: Some("/app".to_string()),
+            hostname: Some("box".to_string()),
+            gpus: None,
+            gpu_shared: false,
+        };
+        assert!(!options.is_empty());
+        let args = options
+            .run_args("podman", Path::new("/nonexistent"))
+            .unwrap();
+        assert_eq!(args[..2], ["--health-cmd", "curl -f localhost"]);
+        assert!(args.windows(2).any(|w| w == ["--health-retries", "2"]));
+        let rest = &args[args.iter().position(|a| a == "--label").unwrap()..];
+        assert_eq!(
+            rest,
+            [
+                "--label",
+                "team=games",
+                "--annotation",
+                "run.oci.keep_original_groups=1",
+                "--user",
+                "1000:1000",
+                "--network",
+                "backend",
+                "--workdir",
+                "/app",
+                "--hostname",
+                "box",
+                "--cap-add",
+                "NET_ADMIN",
+                "--cap-drop",
+                "ALL",
+            ]
+        );
+
+        let bad_label = RunOptions {
+            labels: vec!["=x".to_string()],
+            ..Default::default()
+        };
+        assert!(
+            bad_label
+                .run_args("podman", Path::new("/nonexistent"))
+                .is_err()
+        );
+        let bad_restart = RunOptions {
+            restart: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            bad_restart
+                .run_args("podman", Path::new("/nonexistent"))
+                .is_err()
+        );
+        assert!(
+            RunOptions::default()
+                .run_args("podman", Path::new("/nonexistent"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,1061 @@
+// Filesystem snapshots
+//
+// `bolt snapshot` takes point-in-time snapshots of the Boltfile's
+// `[snapshots]` root_path (default "/") and rolls the root back to them. The
+// backend follows the filesystem (`filesystem = "auto"`) or is set
+// explicitly:
+//
+// - btrfs: read-only subvolume snapshots under snapshot_path (default
+//   <root>/.snapshots). Rolling back a mounted root makes a writable copy
+//   the default subvolume, which takes effect on the next boot.
+// - zfs: `<dataset>@bolt-<id>` snapshots of the dataset mounted at the root
+// - copy: reflink copies (`cp --reflink=auto`) for other filesystems,
+//   copy-on-write on XFS and plain copies elsewhere. A rollback copies the
+//   snapshot up next to the root and swaps the two.
+//
+// Metadata lives in <data_dir>/snapshots/index.json. A rollback first takes
+// a pre-rollback snapshot so it can be undone (except on ZFS, where rolling
+// back destroys every newer snapshot). Retention thins timeline snapshots
+// (hourly .. yearly); manual ones only count towards max_total, and named
+// snapshots marked keep_forever are never removed.
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::watcher::{self, Watcher};
+use crate::config::{NamedSnapshot, RetentionPolicy, SnapshotConfig, SnapshotTriggers};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    Btrfs,
+    Zfs,
+    Copy,
+}
+
+impl Backend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Backend::Btrfs => "btrfs",
+            Backend::Zfs => "zfs",
+            Backend::Copy => "copy",
+        }
+    }
+
+    /// The backend named in `filesystem`, `None` for "auto"
+    fn parse(name: &str) -> Result<Option<Self>> {
+        match name {
+            "auto" => Ok(None),
+            "btrfs" => Ok(Some(Backend::Btrfs)),
+            "zfs" => Ok(Some(Backend::Zfs)),
+            "copy" | "overlay" => Ok(Some(Backend::Copy)),
+            other => bail!(
+                "Unknown snapshot filesystem {} (auto, btrfs, zfs, copy)",
+                other
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotKind {
+    Manual,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    /// Taken automatically before a rollback
+    PreRollback,
+}
+
+impl SnapshotKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SnapshotKind::Manual => "manual",
+            SnapshotKind::Hourly => "hourly",
+            SnapshotKind::Daily => "daily",
+            SnapshotKind::Weekly => "weekly",
+            SnapshotKind::Monthly => "monthly",
+            SnapshotKind::Yearly => "yearly",
+            SnapshotKind::PreRollback => "pre-rollback",
+        }
+    }
+
+    /// Taken on a schedule and thinned by the retention policy
+    pub fn is_timeline(self) -> bool {
+        !matches!(self, SnapshotKind::Manual | SnapshotKind::PreRollback)
+    }
+}
+
+impl std::str::FromStr for SnapshotKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "manual" => SnapshotKind::Manual,
+            "hourly" => SnapshotKind::Hourly,
+            "daily" => SnapshotKind::Daily,
+            "weekly" => SnapshotKind::Weekly,
+            "monthly" => SnapshotKind::Monthly,
+            "yearly" => SnapshotKind::Yearly,
+            "pre-rollback" => SnapshotKind::PreRollback,
+            other => bail!(
+                "Unknown snapshot type {} (manual, hourly, daily, weekly, monthly, yearly, pre-rollback)",
+                other
+            ),
+        })
+    }
+}
+
+impl std::fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub kind: SnapshotKind,
+    pub backend: Backend,
+    pub root: PathBuf,
+    /// Snapshot directory, or the ZFS snapshot name
+    pub location: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub keep_forever: bool,
+    /// Known for copy and ZFS snapshots
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+}
+
+impl Snapshot {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// What a rollback did
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackOutcome {
+    pub snapshot: Snapshot,
+    /// Snapshot of the state that was rolled back
+    pub pre_rollback: Option<Snapshot>,
+    /// A mounted btrfs root switches on the next boot
+    pub reboot_required: bool,
+    /// Newer ZFS snapshots destroyed by the rollback
+    pub discarded: Vec<Snapshot>,
+}
+
+/// The effective `[snapshots]` settings
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotSettings {
+    pub enabled: bool,
+    pub backend: Backend,
+    pub root: PathBuf,
+    /// Where btrfs and copy snapshots are kept
+    pub store: Option<PathBuf>,
+    pub retention: Option<RetentionPolicy>,
+    pub triggers: Option<SnapshotTriggers>,
+    pub named: Vec<NamedSnapshot>,
+}
+
+pub struct SnapshotManager {
+    data_dir: PathBuf,
+    settings: SnapshotSettings,
+}
+
+impl SnapshotManager {
+    /// Resolve the settings, detecting the root's filesystem for "auto"
+    pub async fn open(data_dir: &Path, config: Option<&SnapshotConfig>) -> Result<Self> {
+        let root = PathBuf::from(config.and_then(|c| c.root_path.as_deref()).unwrap_or("/"));
+        let backend = match config.and_then(|c| c.filesystem.as_deref()) {
+            Some(name) => Backend::parse(name)?,
+            None => None,
+        };
+        let backend = match backend {
+            Some(backend) => backend,
+            None => detect_backend(&root).await,
+        };
+        let store = match backend {
+            Backend::Zfs => None,
+            Backend::Btrfs | Backend::Copy => Some(
+                config
+                    .and_then(|c| c.snapshot_path.as_deref())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| match backend {
+                        Backend::Btrfs => root.join(".snapshots"),
+                        _ => data_dir.join("snapshots").join("data"),
+                    }),
+            ),
+        };
+        Ok(Self {
+            data_dir: data_dir.to_path_buf(),
+            settings: SnapshotSettings {
+                enabled: config.and_then(|c| c.enabled).unwrap_or(true),
+                backend,
+                root,
+                store,
+                retention: config.and_then(|c| c.retention.clone()),
+                triggers: config.and_then(|c| c.triggers.clone()),
+                named: config
+                    .and_then(|c| c.named_snapshots.clone())
+                    .unwrap_or_default(),
+            },
+        })
+    }
+
+    pub fn settings(&self) -> &SnapshotSettings {
+        &self.settings
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.data_dir.join("snapshots").join("index.json")
+    }
+
+    fn load(&self) -> Result<Vec<Snapshot>> {
+        let path = self.index_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let json = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    fn save(&self, snapshots: &[Snapshot]) -> Result<()> {
+        let path = self.index_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(snapshots)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Snapshots oldest first, optionally of one kind
+    pub fn list(&self, kind: Option<SnapshotKind>) -> Result<Vec<Snapshot>> {
+        let mut snapshots = self.load()?;
+        snapshots.retain(|s| kind.is_none_or(|kind| s.kind == kind));
+        snapshots.sort_by_key(|s| s.created_at);
+        Ok(snapshots)
+    }
+
+    /// A snapshot by ID, name or unique ID prefix
+    pub fn find(&self, query: &str) -> Result<Snapshot> {
+        let snapshots = self.load()?;
+        if let Some(snapshot) = snapshots
+            .iter()
+            .find(|s| s.id == query || s.name.as_deref() == Some(query))
+        {
+            return Ok(snapshot.clone());
+        }
+        let matches: Vec<&Snapshot> = snapshots
+            .iter()
+            .filter(|s| s.id.starts_with(query))
+            .collect();
+        match matches.as_slice() {
+            [snapshot] => Ok((*snapshot).clone()),
+            [] => Err(anyhow!("No snapshot {}", query)),
+            _ => Err(anyhow!("Snapshot ID {} is ambiguous", query)),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        name: Option<&str>,
+        description: Option<&str>,
+        kind: SnapshotKind,
+    ) -> Result<Snapshot> {
+        let settings = &self.settings;
+        if !settings.enabled {
+            bail!("Snapshots are disabled ([snapshots] enabled = false)");
+        }
+        let mut snapshots = self.load()?;
+        if let Some(name) = name {
+            if snapshots.iter().any(|s| s.name.as_deref() == Some(name)) {
+                bail!("A snapshot named {} already exists", name);
+            }
+        }
+        let named = name.and_then(|name| settings.named.iter().find(|n| n.name == name));
+
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let root = &settings.root;
+        let (location, size_bytes) = match settings.backend {
+            Backend::Btrfs => {
+                let store = self.store()?;
+                std::fs::create_dir_all(store)
+                    .with_context(|| format!("Failed to create {}", store.display()))?;
+                let target = store.join(&id);
+                run(
+                    "btrfs",
+                    [
+                        "subvolume".as_ref(),
+                        "snapshot".as_ref(),
+                        "-r".as_ref(),
+                        root.as_os_str(),
+                        target.as_os_str(),
+                    ],
+                )
+                .await?;
+                (target.display().to_string(), None)
+            }
+            Backend::Zfs => {
+                let dataset = zfs_dataset(root).await?;
+                let location = format!("{}@bolt-{}", dataset, id);
+                run("zfs", ["snapshot", location.as_str()]).await?;
+                let used = run(
+                    "zfs",
+                    ["get", "-Hp", "-o", "value", "used", location.as_str()],
+                )
+                .await
+                .ok()
+                .and_then(|used| used.parse().ok());
+                (location, used)
+            }
+            Backend::Copy => {
+                let store = self.store()?;
+                if root == Path::new("/") {
+                    bail!(
+                        "/ is not on btrfs or zfs; copy snapshots need a narrower [snapshots] root_path"
+                    );
+                }
+                if store.starts_with(root) {
+                    bail!(
+                        "snapshot_path {} is inside root_path {}",
+                        store.display(),
+                        root.display()
+                    );
+                }
+                std::fs::create_dir_all(store)
+                    .with_context(|| format!("Failed to create {}", store.display()))?;
+                let target = store.join(&id);
+                reflink_copy(root, &target).await?;
+                (target.display().to_string(), Some(dir_size(&target)))
+            }
+        };
+
+        let snapshot = Snapshot {
+            id,
+            name: name.map(String::from),
+            description: description
+                .map(String::from)
+                .or_else(|| named.and_then(|n| n.description.clone())),
+            kind,
+            backend: settings.backend,
+            root: root.clone(),
+            location,
+            created_at: Utc::now(),
+            keep_forever: named.and_then(|n| n.keep_forever).unwrap_or(false),
+            size_bytes,
+        };
+        snapshots.push(snapshot.clone());
+        self.save(&snapshots)?;
+        info!(
+            "📸 Snapshot {} of {} created ({})",
+            snapshot.label(),
+            root.display(),
+            snapshot.backend
+        );
+        Ok(snapshot)
+    }
+
+    fn store(&self) -> Result<&Path> {
+        self.settings
+            .store
+            .as_deref()
+            .ok_or_else(|| anyhow!("No snapshot_path for {}", self.settings.backend))
+    }
+
+    pub async fn delete(&self, query: &str) -> Result<Snapshot> {
+        let snapshot = self.find(query)?;
+        remove_data(&snapshot).await?;
+        let mut snapshots = self.load()?;
+        snapshots.retain(|s| s.id != snapshot.id);
+        self.save(&snapshots)?;
+        info!("🗑️  Snapshot {} deleted", snapshot.label());
+        Ok(snapshot)
+    }
+
+    /// Snapshots the rollback to `query` would destroy
+    pub fn rollback_discards(&self, query: &str) -> Result<Vec<Snapshot>> {
+        let target = self.find(query)?;
+        if target.backend != Backend::Zfs {
+            return Ok(Vec::new());
+        }
+        let dataset = target.location.split('@').next().unwrap_or_default();
+        Ok(self
+            .list(None)?
+            .into_iter()
+            .filter(|s| {
+                s.backend == Backend::Zfs
+                    && s.created_at > target.created_at
+                    && s.location.split('@').next() == Some(dataset)
+            })
+            .collect())
+    }
+
+    pub async fn rollback(&self, query: &str) -> Result<RollbackOutcome> {
+        let snapshot = self.find(query)?;
+        let root = &self.settings.root;
+        if &snapshot.root != root {
+            bail!(
+                "Snapshot {} is of {}, but root_path is now {}",
+                snapshot.label(),
+                snapshot.root.display(),
+                root.display()
+            );
+        }
+        if snapshot.backend != self.settings.backend {
+            bail!(
+                "Snapshot {} was taken with {}, but the root now uses {}",
+                snapshot.label(),
+                snapshot.backend,
+                self.settings.backend
+            );
+        }
+
+        let description = format!("Before rolling back to {}", snapshot.label());
+        let mut outcome = RollbackOutcome {
+            snapshot: snapshot.clone(),
+            pre_rollback: None,
+            reboot_required: false,
+            discarded: Vec::new(),
+        };
+
+        match snapshot.backend {
+            Backend::Btrfs => {
+                outcome.pre_rollback = Some(
+                    self.create(None, Some(&description), SnapshotKind::PreRollback)
+                        .await?,
+                );
+                if is_mount_point(root) {
+                    // The live root can't be replaced; boot into a writable copy instead
+                    let copy = self.store()?.join(format!("{}-rollback", snapshot.id));
+                    run(
+                        "btrfs",
+                        [
+                            "subvolume".as_ref(),
+                            "snapshot".as_ref(),
+                            snapshot.location.as_ref(),
+                            copy.as_os_str(),
+                        ],
+                    )
+                    .await?;
+                    let show = run(
+                        "btrfs",
+                        ["subvolume".as_ref(), "show".as_ref(), copy.as_os_str()],
+                    )
+                    .await?;
+                    let subvolume_id = show
+                        .lines()
+                        .find_map(|l| l.trim().strip_prefix("Subvolume ID:"))
+                        .map(str::trim)
+                        .ok_or_else(|| {
+                            anyhow!("Cannot find the subvolume ID of {}", copy.display())
+                        })?
+                        .to_string();
+                    run(
+                        "btrfs",
+                        [
+                            "subvolume".as_ref(),
+                            "set-default".as_ref(),
+                            subvolume_id.as_ref(),
+                            root.as_os_str(),
+                        ],
+                    )
+                    .await?;
+                    outcome.reboot_required = true;
+                } else {
+                    run(
+                        "btrfs",
+                        ["subvolume".as_ref(), "delete".as_ref(), root.as_os_str()],
+                    )
+                    .await?;
+                    run(
+                        "btrfs",
+                        [
+                            "subvolume".as_ref(),
+                            "snapshot".as_ref(),
+                            snapshot.location.as_ref(),
+                            root.as_os_str(),
+                        ],
+                    )
+                    .await?;
+                }
+            }
+            Backend::Zfs => {
+                outcome.discarded = self.rollback_discards(&snapshot.id)?;
+                run("zfs", ["rollback", "-r", snapshot.location.as_str()]).await?;
+                let discarded: HashSet<&str> =
+                    outcome.discarded.iter().map(|s| s.id.as_str()).collect();
+                let mut snapshots = self.load()?;
+                snapshots.retain(|s| !discarded.contains(s.id.as_str()));
+                self.save(&snapshots)?;
+            }
+            Backend::Copy => {
+                outcome.pre_rollback = Some(
+                    self.create(None, Some(&description), SnapshotKind::PreRollback)
+                        .await?,
+                );
+                swap_in_copy(Path::new(&snapshot.location), root).await?;
+            }
+        }
+
+        info!(
+            "⏪ Rolled {} back to snapshot {}{}",
+            root.display(),
+            snapshot.label(),
+            if outcome.reboot_required {
+                " (takes effect on the next boot)"
+            } else {
+                ""
+            }
+        );
+        Ok(outcome)
+    }
+
+    /// Apply the retention policy, returning what was (or with `dry_run`,
+    /// would be) removed
+    pub async fn cleanup(&self, dry_run: bool) -> Result<Vec<Snapshot>> {
+        let Some(ref retention) = self.settings.retention else {
+            return Ok(Vec::new());
+        };
+        let snapshots = self.list(None)?;
+        let doomed: HashSet<String> = plan_cleanup(&snapshots, retention).into_iter().collect();
+        let mut removed = Vec::new();
+        for snapshot in snapshots.into_iter().filter(|s| doomed.contains(&s.id)) {
+            if !dry_run {
+                if let Err(e) = self.delete(&snapshot.id).await {
+                    warn!("Failed to remove snapshot {}: {}", snapshot.label(), e);
+                    continue;
+                }
+            }
+            removed.push(snapshot);
+        }
+        Ok(removed)
+    }
+
+    /// Timeline snapshots the triggers call for now
+    pub fn due(&self) -> Result<Vec<SnapshotKind>> {
+        match self.settings.triggers {
+            Some(ref triggers) => due(triggers, &self.list(None)?, Local::now()),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+async fn run<I, S>(program: &str, args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let output = AsyncCommand::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+async fn detect_backend(root: &Path) -> Backend {
+    let filesystem = run(
+        "stat",
+        [
+            "-f".as_ref(),
+            "-c".as_ref(),
+            "%T".as_ref(),
+            root.as_os_str(),
+        ],
+    )
+    .await
+    .unwrap_or_default();
+    debug!("{} is on {}", root.display(), filesystem);
+    match filesystem.as_str() {
+        "btrfs" => Backend::Btrfs,
+        "zfs" => Backend::Zfs,
+        _ => Backend::Copy,
+    }
+}
+
+/// The ZFS dataset mounted at `root`
+async fn zfs_dataset(root: &Path) -> Result<String> {
+    run(
+        "zfs",
+        [
+            "list".as_ref(),
+            "-H".as_ref(),
+            "-o".as_ref(),
+            "name".as_ref(),
+            root.as_os_str(),
+        ],
+    )
+    .await
+    .with_context(|| format!("{} is not a ZFS dataset", root.display()))
+}
+
+fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(parent) = path.parent() else {
+        return true;
+    };
+    match (std::fs::metadata(path), std::fs::metadata(parent)) {
+        (Ok(dir), Ok(parent)) => dir.dev() != parent.dev() || dir.ino() == parent.ino(),
+        _ => false,
+    }
+}
+
+async fn reflink_copy(source: &Path, target: &Path) -> Result<()> {
+    run(
+        "cp",
+        [
+            "-a".as_ref(),
+            "--reflink=auto".as_ref(),
+            source.as_os_str(),
+            target.as_os_str(),
+        ],
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Copy the snapshot up next to the root and swap it in
+async fn swap_in_copy(snapshot: &Path, root: &Path) -> Result<()> {
+    let name = root
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot roll back {}", root.display()))?
+        .to_string_lossy();
+    let staging = root.with_file_name(format!(".{}.bolt-rollback", name));
+    let old = root.with_file_name(format!(".{}.bolt-old", name));
+    for leftover in [&staging, &old] {
+        if leftover.exists() {
+            std::fs::remove_dir_all(leftover)?;
+        }
+    }
+
+    reflink_copy(snapshot, &staging).await?;
+    if let Err(e) = std::fs::rename(root, &old) {
+        let _ = std::fs::remove_dir_all(&staging);
+        bail!(
+            "Cannot move {} aside ({}); if it is a mount point, copy {} back by hand",
+            root.display(),
+            e,
+            snapshot.display()
+        );
+    }
+    if let Err(e) = std::fs::rename(&staging, root) {
+        std::fs::rename(&old, root)?;
+        bail!("Failed to swap in the snapshot: {}", e);
+    }
+    std::fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+async fn remove_data(snapshot: &Snapshot) -> Result<()> {
+    match snapshot.backend {
+        Backend::Btrfs => {
+            if Path::new(&snapshot.location).exists() {
+                run("btrfs", ["subvolume", "delete", snapshot.location.as_str()]).await?;
+            } else {
+                warn!("Snapshot {} data is already gone", snapshot.label());
+            }
+        }
+        Backend::Zfs => {
+            run("zfs", ["destroy", snapshot.location.as_str()]).await?;
+        }
+        Backend::Copy => {
+            let path = Path::new(&snapshot.location);
+            if path.exists() {
+                std::fs::remove_dir_all(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            } else {
+                warn!("Snapshot {} data is already gone", snapshot.label());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| dir_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+/// IDs the retention policy removes. Timeline snapshots survive as the
+/// newest of one of the latest `keep_*` hours, days, weeks, months or years;
+/// then the oldest snapshots go until at most `max_total` remain.
+/// keep_forever snapshots are never removed.
+pub fn plan_cleanup(snapshots: &[Snapshot], retention: &RetentionPolicy) -> Vec<String> {
+    let mut newest_first: Vec<&Snapshot> = snapshots.iter().filter(|s| !s.keep_forever).collect();
+    newest_first.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+
+    let periods = [
+        (retention.keep_hourly, "%Y-%m-%d %H"),
+        (retention.keep_daily, "%Y-%m-%d"),
+        (retention.keep_weekly, "%G-W%V"),
+        (retention.keep_monthly, "%Y-%m"),
+        (retention.keep_yearly, "%Y"),
+    ];
+    let mut doomed: Vec<&Snapshot> = Vec::new();
+    if periods.iter().any(|(keep, _)| keep.is_some()) {
+        let timeline: Vec<&Snapshot> = newest_first
+            .iter()
+            .copied()
+            .filter(|s| s.kind.is_timeline())
+            .collect();
+        let mut kept: HashSet<&str> = HashSet::new();
+        for (keep, format) in periods {
+            let Some(keep) = keep else { continue };
+            let mut buckets = HashSet::new();
+            for snapshot in &timeline {
+                let bucket = snapshot
+                    .created_at
+                    .with_timezone(&Local)
+                    .format(format)
+                    .to_string();
+                if buckets.contains(&bucket) {
+                    continue;
+                }
+                if buckets.len() >= keep as usize {
+                    break;
+                }
+                buckets.insert(bucket);
+                kept.insert(&snapshot.id);
+            }
+        }
+        doomed.extend(
+            timeline
+                .into_iter()
+                .filter(|s| !kept.contains(s.id.as_str())),
+        );
+    }
+
+    if let Some(max_total) = retention.max_total {
+        let forever = snapshots.len() - newest_first.len();
+        let mut remaining: Vec<&Snapshot> = newest_first
+            .iter()
+            .copied()
+            .filter(|s| !doomed.iter().any(|d| d.id == s.id))
+            .collect();
+        while forever + remaining.len() > max_total as usize {
+            match remaining.pop() {
+                Some(oldest) => doomed.push(oldest),
+                None => break,
+            }
+        }
+    }
+
+    doomed.into_iter().map(|s| s.id.clone()).collect()
+}
+
+/// Timeline kinds whose latest scheduled time has passed without a snapshot
+pub fn due(
+    triggers: &SnapshotTriggers,
+    snapshots: &[Snapshot],
+    now: DateTime<Local>,
+) -> Result<Vec<SnapshotKind>> {
+    let mut slots: Vec<(SnapshotKind, DateTime<Local>)> = Vec::new();
+    if triggers.hourly == Some(true) {
+        let hour = now.date_naive().and_hms_opt(now.hour(), 0, 0);
+        if let Some(slot) = hour.and_then(|h| Local.from_local_datetime(&h).earliest()) {
+            slots.push((SnapshotKind::Hourly, slot));
+        }
+    }
+    if let Some(ref daily) = triggers.daily {
+        let time = parse_time(daily)?;
+        let mut slot = at(now.date_naive(), time)?;
+        if slot > now {
+            slot = at(now.date_naive() - chrono::Duration::days(1), time)?;
+        }
+        slots.push((SnapshotKind::Daily, slot));
+    }
+    if let Some(ref weekly) = triggers.weekly {
+        let (day, time) = weekly
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Invalid weekly trigger {} (e.g. sunday@03:00)", weekly))?;
+        let weekday: Weekday = day
+            .parse()
+            .map_err(|_| anyhow!("Invalid weekday in weekly trigger {}", weekly))?;
+        let time = parse_time(time)?;
+        let back = (now.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+        let mut slot = at(now.date_naive() - chrono::Duration::days(back as i64), time)?;
+        if slot > now {
+            slot -= chrono::Duration::days(7);
+        }
+        slots.push((SnapshotKind::Weekly, slot));
+    }
+    if let Some(ref monthly) = triggers.monthly {
+        let (day, time) = monthly
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Invalid monthly trigger {} (e.g. 1@04:00)", monthly))?;
+        let day: u32 = day
+            .parse()
+            .map_err(|_| anyhow!("Invalid day in monthly trigger {}", monthly))?;
+        let time = parse_time(time)?;
+        let this_month = now
+            .date_naive()
+            .with_day(day)
+            .ok_or_else(|| anyhow!("Invalid day in monthly trigger {}", monthly))?;
+        let mut slot = at(this_month, time)?;
+        if slot > now {
+            let last_month = this_month
+                .checked_sub_months(chrono::Months::new(1))
+                .ok_or_else(|| anyhow!("Invalid monthly trigger {}", monthly))?;
+            slot = at(last_month, time)?;
+        }
+        slots.push((SnapshotKind::Monthly, slot));
+    }
+
+    Ok(slots
+        .into_iter()
+        .filter(|(kind, slot)| {
+            !snapshots
+                .iter()
+                .any(|s| s.kind == *kind && s.created_at >= slot.with_timezone(&Utc))
+        })
+        .map(|(kind, _)| kind)
+        .collect())
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| anyhow!("Invalid time {} (HH:MM)", time))
+}
+
+fn at(date: chrono::NaiveDate, time: NaiveTime) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .ok_or_else(|| anyhow!("{} {} does not exist in the local time zone", date, time))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoState {
+    pub watcher_pid: u32,
+    pub since: DateTime<Utc>,
+}
+
+const WATCHER: Watcher = Watcher::new("snapshot-watch");
+
+fn auto_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("snapshots").join("auto.json")
+}
+
+/// The running automatic snapshot watcher
+pub fn auto_status(data_dir: &Path) -> Option<AutoState> {
+    let state: AutoState = watcher::load(&auto_path(data_dir))?;
+    WATCHER.alive(state.watcher_pid).then_some(state)
+}
+
+/// Start taking timeline snapshots in the background
+pub fn auto_enable(data_dir: &Path, boltfile: &Path) -> Result<AutoState> {
+    if let Some(state) = auto_status(data_dir) {
+        return Ok(state);
+    }
+    let boltfile = std::path::absolute(boltfile)?;
+    let log = data_dir.join("snapshots").join("auto.log");
+    let state = AutoState {
+        watcher_pid: WATCHER.spawn(
+            [
+                OsStr::new("--config"),
+                boltfile.as_os_str(),
+                OsStr::new("snapshot-watch"),
+            ],
+            &log,
+        )?,
+        since: Utc::now(),
+    };
+    watcher::save(&auto_path(data_dir), &state)?;
+    Ok(state)
+}
+
+/// Stop automatic snapshots; false if they weren't running
+pub fn auto_disable(data_dir: &Path) -> bool {
+    let running = auto_status(data_dir);
+    WATCHER.forget(
+        &auto_path(data_dir),
+        running.as_ref().map(|s| s.watcher_pid),
+    );
+    running.is_some()
+}
+
+/// Take the timeline snapshots the Boltfile's triggers call for and apply
+/// the retention policy, every minute. Run by `bolt snapshot-watch`.
+pub async fn watch(config: &crate::config::BoltConfig) -> Result<()> {
+    loop {
+        if !auto_path(&config.data_dir).exists() {
+            debug!("Automatic snapshots disabled, stopping");
+            return Ok(());
+        }
+        let snapshots = config.load_boltfile().map(|b| b.snapshots);
+        match snapshots {
+            Ok(snapshots) => {
+                let manager = SnapshotManager::open(&config.data_dir, snapshots.as_ref()).await?;
+                for kind in manager.due().unwrap_or_else(|e| {
+                    warn!("Invalid snapshot triggers: {}", e);
+                    Vec::new()
+                }) {
+                    if let Err(e) = manager.create(None, None, kind).await {
+                        warn!("Failed to take {} snapshot: {}", kind, e);
+                    }
+                }
+                for snapshot in manager.cleanup(false).await? {
+                    info!("🧹 Expired snapshot {}", snapshot.label());
+                }
+            }
+            Err(e) => warn!("Cannot read the Boltfile: {}", e),
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(id: &str, kind: SnapshotKind, hours_ago: i64) -> Snapshot {
+        Snapshot {
+            id: id.to_string(),
+            name: None,
+            description: None,
+            kind,
+            backend: Backend::Copy,
+            root: PathBuf::from("/srv/app"),
+            location: format!("/var/lib/bolt/snapshots/data/{}", id),
+            created_at: Utc::now() - chrono::Duration::hours(hours_ago),
+            keep_forever: false,
+            size_bytes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_snapshots_roll_back_and_retention_thins_the_timeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("app");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("config.toml"), "version = 1").unwrap();
+        let config: SnapshotConfig = toml::from_str(&format!(
+            r#"
+filesystem = "copy"
+root_path = "{}"
+named_snapshots = [{{ name = "stable", keep_forever = true, description = "Known good" }}]
+"#,
+            root.display()
+        ))
+        .unwrap();
+        let manager = SnapshotManager::open(&dir.path().join("data"), Some(&config))
+            .await
+            .unwrap();
+
+        let stable = manager
+            .create(Some("stable"), None, SnapshotKind::Manual)
+            .await
+            .unwrap();
+        assert!(stable.keep_forever);
+        assert_eq!(stable.description.as_deref(), Some("Known good"));
+        assert!(
+            manager
+                .create(Some("stable"), None, SnapshotKind::Manual)
+                .await
+                .is_err()
+        );
+
+        std::fs::write(root.join("config.toml"), "version = 2").unwrap();
+        let outcome = manager.rollback(&stable.id[..6]).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("config.toml")).unwrap(),
+            "version = 1"
+        );
+        let safety = outcome.pre_rollback.unwrap();
+        assert_eq!(safety.kind, SnapshotKind::PreRollback);
+        assert_eq!(
+            std::fs::read_to_string(Path::new(&safety.location).join("config.toml")).unwrap(),
+            "version = 2"
+        );
+        manager.delete(&safety.id).await.unwrap();
+        assert!(!Path::new(&safety.location).exists());
+        assert_eq!(manager.list(None).unwrap().len(), 1);
+
+        let snapshots = vec![
+            snapshot("h0", SnapshotKind::Hourly, 0),
+            snapshot("h1", SnapshotKind::Hourly, 1),
+            snapshot("h2", SnapshotKind::Hourly, 2),
+            snapshot("m1", SnapshotKind::Manual, 30),
+            snapshot("m2", SnapshotKind::Manual, 20),
+            Snapshot {
+                keep_forever: true,
+                ..snapshot("forever", SnapshotKind::Manual, 100)
+            },
+        ];
+        let retention = RetentionPolicy {
+            keep_hourly: Some(2),
+            keep_daily: None,
+            keep_weekly: None,
+            keep_monthly: None,
+            keep_yearly: None,
+            max_total: Some(4),
+            cleanup_frequency: None,
+        };
+        let mut doomed = plan_cleanup(&snapshots, &retention);
+        doomed.sort();
+        assert_eq!(doomed, vec!["h2", "m1"]);
+    }
+
+    #[test]
+    fn triggers_fire_once_per_slot() {
+        let triggers: SnapshotTriggers = toml::from_str(
+            r#"
+hourly = true
+daily = "02:00"
+weekly = "sunday@03:00"
+"#,
+        )
+        .unwrap();
+        let now = Local::now();
+        let kinds = due(&triggers, &[], now).unwrap();
+        assert_eq!(
+            kinds,
+            vec![
+                SnapshotKind::Hourly,
+                SnapshotKind::Daily,
+                SnapshotKind::Weekly
+            ]
+        );
+
+        let taken: Vec<Snapshot> = kinds
+            .iter()
+            .map(|kind| snapshot(kind.as_str(), *kind, 0))
+            .collect();
+        assert!(due(&triggers, &taken, now).unwrap().is_empty());
+
+        let invalid: SnapshotTriggers = toml::from_str(r#"weekly = "someday@03:00""#).unwrap();
+        assert!(due(&invalid, &[], now).is_err());
+    }
+}
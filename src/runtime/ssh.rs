@@ -0,0 +1,572 @@
+// SSH endpoints
+//
+// `bolt capsule ssh enable <capsule>` gives a capsule its own SSH endpoint,
+// mostly for IDE remote development into Development capsules. Bolt runs a
+// private instance of the host's OpenSSH `sshd` for it, as the user running
+// Bolt, with its own host key, config and authorized keys in
+// `<data_dir>/ssh/<capsule>/`. Only key logins as that user are accepted,
+// and every session is forced into the capsule by `bolt ssh-exec`:
+//
+// - a login shell is `<runtime> exec -it <capsule> <shell> -l`
+// - `ssh host <command>` runs the command in the capsule
+// - the sftp subsystem runs the capsule's own `sftp-server`
+//
+// so editors that upload files or start their server over SSH see the
+// capsule's filesystem, not the host's. Forwarding would reach the host's
+// ports rather than the capsule's, so it is turned off. sshd reads
+// `authorized_keys` on each login: `bolt capsule ssh add-key` and
+// `remove-key` apply without a restart.
+//
+// There is deliberately no built-in SSH server. It would have to reimplement
+// terminals, exec, sftp and OpenSSH's hardening, and would fall behind the
+// host's OpenSSH on key types and security fixes.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use super::watcher::{self, Watcher};
+
+/// First port tried when none is given
+const DEFAULT_PORT: u16 = 2222;
+/// `SSH_ORIGINAL_COMMAND` of sftp sessions, from the `Subsystem` line
+const SFTP_MARKER: &str = "bolt-sftp";
+const SFTP_SERVERS: [&str; 4] = [
+    "/usr/lib/openssh/sftp-server",
+    "/usr/libexec/openssh/sftp-server",
+    "/usr/lib/ssh/sftp-server",
+    "/usr/libexec/sftp-server",
+];
+const KEY_TYPES: [&str; 7] = [
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct SshOptions {
+    /// Address to listen on (default: all); a network's gateway address
+    /// keeps the endpoint off the host's other interfaces
+    pub address: Option<String>,
+    /// Port to listen on (default: the first free one from 2222)
+    pub port: Option<u16>,
+    /// Login shell in the capsule (default: /bin/sh)
+    pub shell: Option<String>,
+}
+
+/// A capsule's SSH endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SshEndpoint {
+    pub capsule: String,
+    pub address: String,
+    pub port: u16,
+    /// Account to log in as, the one running the endpoint's sshd
+    pub user: String,
+    pub shell: String,
+    /// `SHA256:...` fingerprint of the endpoint's host key
+    pub host_key: String,
+    pub sshd_pid: Option<u32>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SshEndpoint {
+    pub fn running(&self) -> bool {
+        self.sshd_pid.is_some_and(|pid| SSHD.alive(pid))
+    }
+
+    /// Host to connect to from this machine
+    pub fn host(&self) -> &str {
+        match self.address.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            address => address,
+        }
+    }
+}
+
+/// One line of `authorized_keys`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorizedKey {
+    pub kind: String,
+    pub blob: String,
+    pub comment: String,
+}
+
+impl AuthorizedKey {
+    /// Parse an OpenSSH public key, `<type> <base64> [comment]`
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut parts = line.split_whitespace();
+        let (Some(kind), Some(blob)) = (parts.next(), parts.next()) else {
+            return Err(anyhow!("Not an SSH public key: '{}'", line.trim()));
+        };
+        if !KEY_TYPES.contains(&kind) {
+            return Err(anyhow!(
+                "Unsupported key type '{}' (expected one of {})",
+                kind,
+                KEY_TYPES.join(", ")
+            ));
+        }
+        let decoded =
+            base64_decode(blob).ok_or_else(|| anyhow!("Malformed {} key: bad base64", kind))?;
+        // The blob starts with the key type as an SSH string
+        let embedded = decoded
+            .get(4..)
+            .and_then(|rest| rest.get(..kind.len()))
+            .filter(|_| decoded[..4] == (kind.len() as u32).to_be_bytes());
+        if embedded != Some(kind.as_bytes()) {
+            return Err(anyhow!("Malformed {} key: type doesn't match", kind));
+        }
+        Ok(Self {
+            kind: kind.to_string(),
+            blob: blob.to_string(),
+            comment: parts.collect::<Vec<_>>().join(" "),
+        })
+    }
+
+    /// `SHA256:...`, as `ssh-keygen -l` prints it
+    pub fn fingerprint(&self) -> String {
+        let decoded = base64_decode(&self.blob).unwrap_or_default();
+        format!("SHA256:{}", base64_encode(&Sha256::digest(&decoded)))
+    }
+
+    fn line(&self) -> String {
+        if self.comment.is_empty() {
+            format!("{} {}", self.kind, self.blob)
+        } else {
+            format!("{} {} {}", self.kind, self.blob, self.comment)
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for byte in input.bytes() {
+        let value = BASE64.iter().position(|b| *b == byte)? as u32;
+        acc = (acc << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Unpadded, like OpenSSH fingerprints
+fn base64_encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Recognised by its config path; sessions are in sshd's process group
+const SSHD: Watcher = Watcher::group("sshd_config");
+
+fn endpoint_dir(data_dir: &Path, capsule: &str) -> PathBuf {
+    data_dir.join("ssh").join(capsule)
+}
+
+fn state_path(data_dir: &Path, capsule: &str) -> PathBuf {
+    endpoint_dir(data_dir, capsule).join("endpoint.json")
+}
+
+fn keys_path(data_dir: &Path, capsule: &str) -> PathBuf {
+    endpoint_dir(data_dir, capsule).join("authorized_keys")
+}
+
+fn known_hosts_path(data_dir: &Path, capsule: &str) -> PathBuf {
+    endpoint_dir(data_dir, capsule).join("known_hosts")
+}
+
+pub fn endpoint(data_dir: &Path, capsule: &str) -> Option<SshEndpoint> {
+    watcher::load(&state_path(data_dir, capsule))
+}
+
+/// Every capsule with an SSH endpoint, by name
+pub fn list(data_dir: &Path) -> Vec<SshEndpoint> {
+    let mut endpoints: Vec<SshEndpoint> = std::fs::read_dir(data_dir.join("ssh"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| endpoint(data_dir, &e.file_name().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+    endpoints.sort_by(|a, b| a.capsule.cmp(&b.capsule));
+    endpoints
+}
+
+/// Keys allowed to log in to the capsule
+pub fn keys(data_dir: &Path, capsule: &str) -> Vec<AuthorizedKey> {
+    std::fs::read_to_string(keys_path(data_dir, capsule))
+        .unwrap_or_default()
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| AuthorizedKey::parse(l).ok())
+        .collect()
+}
+
+fn save_keys(data_dir: &Path, capsule: &str, keys: &[AuthorizedKey]) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = endpoint_dir(data_dir, capsule);
+    std::fs::create_dir_all(&dir)?;
+    let path = keys_path(data_dir, capsule);
+    let content: String = keys.iter().map(|k| k.line() + "\n").collect();
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+/// Authorize a public key, given as the key itself or a `.pub` file
+pub fn add_key(data_dir: &Path, capsule: &str, key: &str) -> Result<AuthorizedKey> {
+    let line = if Path::new(key).is_file() {
+        std::fs::read_to_string(key).with_context(|| format!("Failed to read {}", key))?
+    } else {
+        key.to_string()
+    };
+    let key = AuthorizedKey::parse(line.trim())?;
+    let mut keys = keys(data_dir, capsule);
+    if keys.iter().any(|k| k.blob == key.blob) {
+        info!(
+            "Key {} is already authorized for {}",
+            key.fingerprint(),
+            capsule
+        );
+        return Ok(key);
+    }
+    keys.push(key.clone());
+    save_keys(data_dir, capsule, &keys)?;
+    info!(
+        "🔑 Authorized {} {} for {}",
+        key.kind,
+        key.fingerprint(),
+        capsule
+    );
+    Ok(key)
+}
+
+/// Revoke keys matching a fingerprint or comment
+pub fn remove_key(data_dir: &Path, capsule: &str, key: &str) -> Result<Vec<AuthorizedKey>> {
+    let (removed, kept): (Vec<_>, Vec<_>) = keys(data_dir, capsule)
+        .into_iter()
+        .partition(|k| k.fingerprint() == key || k.comment == key);
+    if removed.is_empty() {
+        return Err(anyhow!("No key '{}' is authorized for {}", key, capsule));
+    }
+    save_keys(data_dir, capsule, &kept)?;
+    for key in &removed {
+        info!("🗑️  Revoked {} for {}", key.fingerprint(), capsule);
+    }
+    Ok(removed)
+}
+
+/// sshd_config of a capsule's endpoint
+fn render_config(
+    dir: &Path,
+    exe: &Path,
+    capsule: &str,
+    address: &str,
+    port: u16,
+    user: &str,
+) -> String {
+    format!(
+        "Port {port}
+ListenAddress {address}
+HostKey {dir}/host_ed25519_key
+PidFile none
+AuthorizedKeysFile {dir}/authorized_keys
+PubkeyAuthentication yes
+PasswordAuthentication no
+KbdInteractiveAuthentication no
+UsePAM no
+StrictModes no
+AllowUsers {user}
+PermitRootLogin prohibit-password
+PermitUserEnvironment no
+AllowTcpForwarding no
+AllowStreamLocalForwarding no
+AllowAgentForwarding no
+X11Forwarding no
+PermitTunnel no
+PrintMotd no
+Subsystem sftp {SFTP_MARKER}
+ForceCommand '{exe}' ssh-exec {capsule}
+",
+        dir = dir.display(),
+        exe = exe.display(),
+    )
+}
+
+/// Start the capsule's endpoint, replacing a running one
+pub async fn enable(data_dir: &Path, capsule: &str, options: &SshOptions) -> Result<SshEndpoint> {
+    let sshd = find_sshd().ok_or_else(|| {
+        anyhow!("sshd not found; install the OpenSSH server (openssh-server) on the host")
+    })?;
+    stop(data_dir, capsule);
+
+    let dir = endpoint_dir(data_dir, capsule);
+    std::fs::create_dir_all(&dir)?;
+    let host_key = dir.join("host_ed25519_key");
+    if !host_key.exists() {
+        let output = AsyncCommand::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C"])
+            .arg(format!("bolt-{}", capsule))
+            .arg("-f")
+            .arg(&host_key)
+            .output()
+            .await
+            .context("Failed to run ssh-keygen")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to generate a host key: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+    if !keys_path(data_dir, capsule).exists() {
+        save_keys(data_dir, capsule, &[])?;
+    }
+
+    let address = options
+        .address
+        .clone()
+        .unwrap_or_else(|| "0.0.0.0".to_string());
+    let port = match options.port {
+        Some(port) => port,
+        None => free_port(&address)?,
+    };
+    let user = effective_user()?;
+    let exe = std::env::current_exe().context("Cannot find the bolt executable")?;
+    let config = dir.join("sshd_config");
+    std::fs::write(
+        &config,
+        render_config(&dir, &exe, capsule, &address, port, &user),
+    )?;
+
+    let public = std::fs::read_to_string(host_key.with_extension("pub"))?;
+    let host_key = AuthorizedKey::parse(public.trim())?;
+    let mut endpoint = SshEndpoint {
+        capsule: capsule.to_string(),
+        address,
+        port,
+        user,
+        shell: options
+            .shell
+            .clone()
+            .unwrap_or_else(|| "/bin/sh".to_string()),
+        host_key: host_key.fingerprint(),
+        sshd_pid: None,
+        started_at: chrono::Utc::now(),
+    };
+    std::fs::write(
+        known_hosts_path(data_dir, capsule),
+        format!(
+            "[{}]:{} {} {}\n",
+            endpoint.host(),
+            port,
+            host_key.kind,
+            host_key.blob
+        ),
+    )?;
+
+    // sshd re-executes itself, so it needs its absolute path
+    let args = [
+        OsStr::new("-D"),
+        OsStr::new("-e"),
+        OsStr::new("-f"),
+        config.as_os_str(),
+    ];
+    endpoint.sshd_pid = Some(SSHD.spawn_program(&sshd, args, &dir.join("sshd.log"))?);
+    // sshd exits right away on a bad config or a taken port
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    if !endpoint.running() {
+        let log = std::fs::read_to_string(dir.join("sshd.log")).unwrap_or_default();
+        return Err(anyhow!(
+            "sshd for {} didn't start: {}",
+            capsule,
+            log.lines().last().unwrap_or("no output")
+        ));
+    }
+    watcher::save(&state_path(data_dir, capsule), &endpoint)?;
+
+    if keys(data_dir, capsule).is_empty() {
+        warn!(
+            "No keys are authorized for {} yet; add one with `bolt capsule ssh add-key {} <key>`",
+            capsule, capsule
+        );
+    }
+    info!(
+        "🔐 SSH endpoint for {} on {}:{} (log in as {}, host key {})",
+        capsule, endpoint.address, port, endpoint.user, endpoint.host_key
+    );
+    Ok(endpoint)
+}
+
+/// Stop the capsule's endpoint; its keys and host key are kept
+pub fn stop(data_dir: &Path, capsule: &str) {
+    let pid = endpoint(data_dir, capsule).and_then(|e| e.sshd_pid);
+    SSHD.forget(&state_path(data_dir, capsule), pid);
+}
+
+fn find_sshd() -> Option<PathBuf> {
+    ["/usr/sbin/sshd", "/usr/local/sbin/sshd", "/usr/bin/sshd"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+}
+
+fn free_port(address: &str) -> Result<u16> {
+    (DEFAULT_PORT..=u16::MAX)
+        .find(|port| std::net::TcpListener::bind((address, *port)).is_ok())
+        .ok_or_else(|| anyhow!("No free port on {} from {}", address, DEFAULT_PORT))
+}
+
+/// Name of the account Bolt runs as
+fn effective_user() -> Result<String> {
+    // SAFETY: getpwuid returns null or a pointer to static storage that
+    // stays valid until the next call, and the name is copied out at once
+    unsafe {
+        let passwd = libc::getpwuid(libc::geteuid());
+        if passwd.is_null() {
+            return Err(anyhow!("The current user has no passwd entry"));
+        }
+        Ok(std::ffi::CStr::from_ptr((*passwd).pw_name)
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+/// Run an SSH session in the capsule; sshd's forced command. Returns the
+/// exit code.
+pub async fn exec_session(data_dir: &Path, capsule: &str) -> Result<i32> {
+    let runtime = super::detect_container_runtime().await?;
+    let shell = endpoint(data_dir, capsule)
+        .map(|e| e.shell)
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    let original = std::env::var("SSH_ORIGINAL_COMMAND").ok();
+
+    let mut cmd = AsyncCommand::new(&runtime);
+    cmd.arg("exec");
+    // sshd gives the session a terminal when the client asked for one
+    if std::io::stdin().is_terminal() {
+        cmd.arg("-it");
+    } else {
+        cmd.arg("-i");
+    }
+    if let Ok(term) = std::env::var("TERM") {
+        cmd.args(["-e", &format!("TERM={}", term)]);
+    }
+    cmd.arg(capsule);
+    match original.as_deref() {
+        None => {
+            cmd.args([shell.as_str(), "-l"]);
+        }
+        Some(SFTP_MARKER) => {
+            let script = format!(
+                "for p in {}; do [ -x \"$p\" ] && exec \"$p\"; done; \
+                 echo 'no sftp-server in {}' >&2; exit 1",
+                SFTP_SERVERS.join(" "),
+                capsule
+            );
+            cmd.args(["/bin/sh", "-c", &script]);
+        }
+        Some(command) => {
+            cmd.args([shell.as_str(), "-c", command]);
+        }
+    }
+    let status = cmd
+        .status()
+        .await
+        .with_context(|| format!("Failed to exec in {}", capsule))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// `ssh` arguments that connect to the capsule's endpoint, trusting its
+/// host key without a prompt
+pub fn ssh_args(data_dir: &Path, capsule: &str) -> Result<Vec<String>> {
+    let endpoint = endpoint(data_dir, capsule)
+        .filter(|e| e.running())
+        .ok_or_else(|| {
+            anyhow!(
+                "{} has no SSH endpoint running; start one with `bolt capsule ssh enable {}`",
+                capsule,
+                capsule
+            )
+        })?;
+    Ok(vec![
+        "-p".to_string(),
+        endpoint.port.to_string(),
+        "-o".to_string(),
+        format!(
+            "UserKnownHostsFile={}",
+            known_hosts_path(data_dir, capsule).display()
+        ),
+        format!("{}@{}", endpoint.user, endpoint.host()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_with_openssh_fingerprints_and_renders_forced_config() {
+        // The all-zero ed25519 key; `ssh-keygen -l` prints the same fingerprint
+        let blob = "AAAAC3NzaC1lZDI1NTE5AAAAIAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+        let key = AuthorizedKey::parse(&format!("ssh-ed25519 {} alice@laptop", blob)).unwrap();
+        assert_eq!(key.comment, "alice@laptop");
+        assert_eq!(
+            key.fingerprint(),
+            "SHA256:kmYcvdi2GkPeWxB6XLjrZB8JHsy2Hm8luHMFp9GMvqk"
+        );
+        assert!(AuthorizedKey::parse(&format!("ssh-rsa {}", blob)).is_err());
+        assert!(AuthorizedKey::parse("ssh-dss AAAA").is_err());
+        assert!(AuthorizedKey::parse("ssh-ed25519 not*base64").is_err());
+        assert_eq!(base64_encode(b"bolt"), "Ym9sdA");
+        assert_eq!(base64_decode("Ym9sdA==").unwrap(), b"bolt");
+
+        let dir = tempfile::tempdir().unwrap();
+        add_key(dir.path(), "devbox", &key.line()).unwrap();
+        add_key(dir.path(), "devbox", &key.line()).unwrap();
+        assert_eq!(keys(dir.path(), "devbox").len(), 1);
+        remove_key(dir.path(), "devbox", "alice@laptop").unwrap();
+        assert!(keys(dir.path(), "devbox").is_empty());
+        assert!(remove_key(dir.path(), "devbox", "alice@laptop").is_err());
+
+        let config = render_config(
+            Path::new("/var/lib/bolt/ssh/devbox"),
+            Path::new("/usr/bin/bolt"),
+            "devbox",
+            "0.0.0.0",
+            2222,
+            "alice",
+        );
+        assert!(config.contains("ForceCommand '/usr/bin/bolt' ssh-exec devbox\n"));
+        assert!(config.contains("AllowUsers alice\n"));
+        assert!(config.contains("PasswordAuthentication no\n"));
+        assert!(config.contains("Subsystem sftp bolt-sftp\n"));
+    }
+}
@@ -0,0 +1,399 @@
+// Container startup timings
+//
+// Every container start records how long each phase took, so "fast" can be
+// checked per container instead of taken on faith:
+//
+// - image-resolve: trust policy, platform selection and pulling
+// - rootfs: assembling the root filesystem and its mounts
+// - network: namespace and network setup
+// - gpu: GPU passthrough and gaming device setup
+// - spec: generating the OCI spec or runtime arguments
+// - exec: starting the container process
+//
+// The latest breakdown of each container is kept under `<data_dir>/startup/`
+// with a bounded history that the runtime metrics aggregate into p50/p95 per
+// phase. The slowest phase is flagged with what usually speeds it up.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Starts kept for the p50/p95 aggregates
+const HISTORY_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    ImageResolve,
+    Rootfs,
+    Network,
+    Gpu,
+    Spec,
+    Exec,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::ImageResolve,
+        Phase::Rootfs,
+        Phase::Network,
+        Phase::Gpu,
+        Phase::Spec,
+        Phase::Exec,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::ImageResolve => "image-resolve",
+            Phase::Rootfs => "rootfs",
+            Phase::Network => "network",
+            Phase::Gpu => "gpu",
+            Phase::Spec => "spec",
+            Phase::Exec => "exec",
+        }
+    }
+
+    /// What usually makes the phase faster
+    pub fn hint(self) -> &'static str {
+        match self {
+            Phase::ImageResolve => {
+                "pre-pull the image (`bolt pull`) and run with `--pull missing`; a registry mirror speeds up cold pulls"
+            }
+            Phase::Rootfs => {
+                "squash the image into fewer, smaller layers (`bolt image optimize`) and keep the image store on local SSD"
+            }
+            Phase::Network => {
+                "reuse an existing network instead of creating one per container, or use host networking for latency-critical services"
+            }
+            Phase::Gpu => {
+                "keep the GPU driver initialized (nvidia-persistenced) so device setup doesn't wait for it"
+            }
+            Phase::Spec => "drop volumes, devices and environment the container doesn't need",
+            Phase::Exec => {
+                "check the entrypoint: shell wrappers and init scripts run before the main process"
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub duration_ms: f64,
+}
+
+/// Where a container's startup time went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupTimings {
+    pub container: String,
+    pub image: String,
+    pub started_at: DateTime<Utc>,
+    /// Sum of the phases
+    pub total_ms: f64,
+    /// In the order they ran
+    pub phases: Vec<PhaseTiming>,
+}
+
+impl StartupTimings {
+    pub fn slowest(&self) -> Option<&PhaseTiming> {
+        self.phases
+            .iter()
+            .max_by(|a, b| a.duration_ms.total_cmp(&b.duration_ms))
+    }
+
+    /// Breakdown table with the slowest phase flagged
+    pub fn print(&self) {
+        println!("{} ({})", self.container, self.image);
+        println!("Started: {}", self.started_at.to_rfc3339());
+        println!("{:<14} {:>10} {:>6}", "PHASE", "TIME", "SHARE");
+        let slowest = self.slowest().map(|p| p.phase);
+        for timing in &self.phases {
+            let share = if self.total_ms > 0.0 {
+                timing.duration_ms / self.total_ms * 100.0
+            } else {
+                0.0
+            };
+            println!(
+                "{:<14} {:>8.1}ms {:>5.0}%{}",
+                timing.phase.as_str(),
+                timing.duration_ms,
+                share,
+                if Some(timing.phase) == slowest {
+                    "  ◀ slowest"
+                } else {
+                    ""
+                }
+            );
+        }
+        println!("{:<14} {:>8.1}ms", "total", self.total_ms);
+        if let Some(slowest) = self.slowest() {
+            println!("💡 {}: {}", slowest.phase, slowest.phase.hint());
+        }
+    }
+}
+
+/// Times the phases of one container start
+#[derive(Debug)]
+pub struct StartupTimer {
+    image: String,
+    started_at: DateTime<Utc>,
+    current: Option<(Phase, Instant)>,
+    phases: Vec<PhaseTiming>,
+}
+
+impl StartupTimer {
+    pub fn new(image: &str) -> Self {
+        Self {
+            image: image.to_string(),
+            started_at: Utc::now(),
+            current: None,
+            phases: Vec::new(),
+        }
+    }
+
+    /// End the running phase and start `phase`. A phase entered more than
+    /// once accumulates.
+    pub fn phase(&mut self, phase: Phase) {
+        self.end();
+        self.current = Some((phase, Instant::now()));
+    }
+
+    /// End the running phase; time until the next `phase` isn't counted
+    pub fn end(&mut self) {
+        if let Some((phase, at)) = self.current.take() {
+            self.add(phase, at.elapsed());
+        }
+    }
+
+    fn add(&mut self, phase: Phase, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        match self.phases.iter_mut().find(|t| t.phase == phase) {
+            Some(timing) => timing.duration_ms += ms,
+            None => self.phases.push(PhaseTiming {
+                phase,
+                duration_ms: ms,
+            }),
+        }
+    }
+
+    pub fn finish(mut self, container: &str) -> StartupTimings {
+        self.end();
+        StartupTimings {
+            container: container.to_string(),
+            total_ms: self.phases.iter().map(|t| t.duration_ms).sum(),
+            image: self.image,
+            started_at: self.started_at,
+            phases: self.phases,
+        }
+    }
+}
+
+fn dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("startup")
+}
+
+fn history_path(data_dir: &Path) -> PathBuf {
+    dir(data_dir).join("history.jsonl")
+}
+
+/// Keep the timings as the container's latest and add them to the history
+pub fn record(data_dir: &Path, timings: &StartupTimings) -> Result<()> {
+    let dir = dir(data_dir);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    std::fs::write(
+        dir.join(format!("{}.json", timings.container)),
+        serde_json::to_string_pretty(timings)?,
+    )?;
+
+    let mut lines: Vec<String> = std::fs::read_to_string(history_path(data_dir))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.push(serde_json::to_string(timings)?);
+    let start = lines.len().saturating_sub(HISTORY_LIMIT);
+    let mut history = lines[start..].join("\n");
+    history.push('\n');
+    std::fs::write(history_path(data_dir), history)?;
+    Ok(())
+}
+
+/// Record a finished start and log its slowest phase. Failing to record
+/// never fails the start.
+pub fn complete(data_dir: &Path, timer: StartupTimer, container: &str) -> StartupTimings {
+    let timings = timer.finish(container);
+    if let Some(slowest) = timings.slowest() {
+        info!(
+            "⏱️  {} started in {:.1}ms (slowest phase: {} {:.1}ms)",
+            container, timings.total_ms, slowest.phase, slowest.duration_ms
+        );
+    }
+    if let Err(e) = record(data_dir, &timings) {
+        warn!("Could not record startup timings for {}: {}", container, e);
+    }
+    timings
+}
+
+/// The container's latest startup timings
+pub fn load(data_dir: &Path, container: &str) -> Result<Option<StartupTimings>> {
+    let path = dir(data_dir).join(format!("{}.json", container));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = std::fs::read_to_string(&path)?;
+    Ok(Some(serde_json::from_str(&json).with_context(|| {
+        format!("Invalid startup timings: {}", path.display())
+    })?))
+}
+
+/// Recorded starts, oldest first
+pub fn history(data_dir: &Path) -> Vec<StartupTimings> {
+    std::fs::read_to_string(history_path(data_dir))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// p50/p95 of one phase, or of the total when `phase` is `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub phase: Option<Phase>,
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl PhaseStats {
+    pub fn name(&self) -> &'static str {
+        self.phase.map(Phase::as_str).unwrap_or("total")
+    }
+}
+
+/// p50/p95 per phase that appears in the history, then of the total
+pub fn aggregate(history: &[StartupTimings]) -> Vec<PhaseStats> {
+    let stats = |phase: Option<Phase>, mut samples: Vec<f64>| {
+        samples.sort_by(f64::total_cmp);
+        PhaseStats {
+            phase,
+            samples: samples.len(),
+            p50_ms: percentile(&samples, 0.50),
+            p95_ms: percentile(&samples, 0.95),
+        }
+    };
+
+    let mut aggregates: Vec<PhaseStats> = Phase::ALL
+        .into_iter()
+        .map(|phase| {
+            let samples = history
+                .iter()
+                .flat_map(|t| &t.phases)
+                .filter(|t| t.phase == phase)
+                .map(|t| t.duration_ms)
+                .collect();
+            stats(Some(phase), samples)
+        })
+        .filter(|s| s.samples > 0)
+        .collect();
+    if !history.is_empty() {
+        aggregates.push(stats(None, history.iter().map(|t| t.total_ms).collect()));
+    }
+    aggregates
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Phase quantiles in the Prometheus text format
+pub fn prometheus(stats: &[PhaseStats]) -> String {
+    let name = "bolt_container_startup_phase_milliseconds";
+    let mut out = format!(
+        "# HELP {} Time containers spent in each startup phase\n# TYPE {} summary\n",
+        name, name
+    );
+    for stat in stats {
+        for (quantile, value) in [("0.5", stat.p50_ms), ("0.95", stat.p95_ms)] {
+            out.push_str(&format!(
+                "{}{{phase=\"{}\",quantile=\"{}\"}} {:.3}\n",
+                name,
+                stat.name(),
+                quantile,
+                value
+            ));
+        }
+        out.push_str(&format!(
+            "{}_count{{phase=\"{}\"}} {}\n",
+            name,
+            stat.name(),
+            stat.samples
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_phases_and_aggregates_percentiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let timings = |container: &str, exec_ms: f64| StartupTimings {
+            container: container.to_string(),
+            image: "nginx:alpine".to_string(),
+            started_at: Utc::now(),
+            total_ms: 10.0 + exec_ms,
+            phases: vec![
+                PhaseTiming {
+                    phase: Phase::ImageResolve,
+                    duration_ms: 10.0,
+                },
+                PhaseTiming {
+                    phase: Phase::Exec,
+                    duration_ms: exec_ms,
+                },
+            ],
+        };
+
+        for (i, exec_ms) in [5.0, 80.0, 20.0, 40.0].into_iter().enumerate() {
+            record(dir.path(), &timings(&format!("web-{}", i), exec_ms)).unwrap();
+        }
+        record(dir.path(), &timings("web-1", 30.0)).unwrap();
+
+        let latest = load(dir.path(), "web-1").unwrap().unwrap();
+        assert_eq!(latest.slowest().unwrap().phase, Phase::Exec);
+        assert_eq!(latest.total_ms, 40.0);
+        assert!(load(dir.path(), "db").unwrap().is_none());
+
+        let stats = aggregate(&history(dir.path()));
+        let names: Vec<_> = stats.iter().map(PhaseStats::name).collect();
+        assert_eq!(names, vec!["image-resolve", "exec", "total"]);
+        let exec = &stats[1];
+        assert_eq!((exec.samples, exec.p50_ms, exec.p95_ms), (5, 30.0, 80.0));
+        assert!(prometheus(&stats).contains(
+            "bolt_container_startup_phase_milliseconds{phase=\"exec\",quantile=\"0.95\"} 80.000"
+        ));
+
+        let mut timer = StartupTimer::new("nginx:alpine");
+        timer.phase(Phase::Spec);
+        timer.phase(Phase::Exec);
+        timer.phase(Phase::Spec);
+        let timings = timer.finish("web");
+        let phases: Vec<_> = timings.phases.iter().map(|t| t.phase).collect();
+        assert_eq!(phases, vec![Phase::Spec, Phase::Exec]);
+    }
+}
@@ -8,9 +8,14 @@ use tracing::{debug, info, warn};
 pub mod ghostbay;
 pub mod oci_client;
 pub mod overlay;
+#[cfg(feature = "quic-networking")]
+pub mod p2p;
 pub mod registry;
+pub mod restic;
 pub mod s3;
 
+use crate::runtime::platform;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageManager {
     pub root_path: PathBuf,
@@ -37,6 +42,9 @@ pub struct ImageMetadata {
     pub layers: Vec<String>,
     pub config: ImageConfig,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// The manifest's OCI annotations
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,9 +83,23 @@ impl StorageManager {
                 .with_context(|| format!("Failed to create {} directory", dir))?;
         }
 
+        // Images pulled by the registry client keep their metadata on disk
+        let mut images = HashMap::new();
+        for entry in std::fs::read_dir(root_path.join("images"))?.flatten() {
+            let Ok(data) = std::fs::read(entry.path().join("image.json")) else {
+                continue;
+            };
+            match serde_json::from_slice::<ImageMetadata>(&data) {
+                Ok(image) => {
+                    images.insert(image.id.clone(), image);
+                }
+                Err(e) => warn!("Skipping {}: {}", entry.path().display(), e),
+            }
+        }
+
         Ok(Self {
             root_path,
-            images: HashMap::new(),
+            images,
             layers: HashMap::new(),
             driver: StorageDriver::Overlay2, // Default to overlay2
         })
@@ -102,13 +124,10 @@ impl StorageManager {
                 // Bolt native images - use our registry
                 self.pull_bolt_image(&name, &tag).await
             }
-            "docker.io" | "" => {
-                // Docker Hub images
-                self.pull_docker_image(&name, &tag).await
-            }
             _ => {
-                // Other registries
-                self.pull_oci_image(&registry, &name, &tag).await
+                // Docker Hub and other OCI registries
+                self.pull_registry_image(image_ref, &platform::Platform::host())
+                    .await
             }
         }
     }
@@ -158,6 +177,7 @@ impl StorageManager {
                             volumes: HashMap::new(),
                         },
                         created_at: ghostbay_image.created_at,
+                        annotations: HashMap::new(),
                     };
 
                     self.images.insert(image_id.clone(), image_metadata);
@@ -208,6 +228,7 @@ impl StorageManager {
                 volumes: HashMap::new(),
             },
             created_at: chrono::Utc::now(),
+            annotations: HashMap::new(),
         };
 
         self.images.insert(image_id.clone(), image_metadata);
@@ -216,70 +237,156 @@ impl StorageManager {
         Ok(image_id)
     }
 
-    async fn pull_docker_image(&mut self, name: &str, tag: &str) -> Result<String> {
-        info!("🐳 Pulling Docker image: {}:{}", name, tag);
-
-        // Create temporary directory for image download
-        let temp_dir = self.root_path.join("tmp").join(format!("pull-{}-{}", name.replace("/", "_"), tag));
-        std::fs::create_dir_all(&temp_dir)?;
-
-        // Use OCI client to pull from Docker Hub
-        let oci_metadata = oci_client::pull_from_docker_hub(name, tag, &temp_dir).await?;
-
-        let image_id = self.generate_image_id(name, tag);
-
-        // Move rootfs to permanent location
-        let image_dir = self.root_path.join("images").join(&image_id);
-        std::fs::create_dir_all(&image_dir)?;
+    /// Pull an image for `platform` with the registry client, storing its
+    /// blobs under `content/` and its metadata under `images/<id>`
+    pub async fn pull_registry_image(
+        &mut self,
+        image_ref: &str,
+        platform: &platform::Platform,
+    ) -> Result<String> {
+        let reference = registry::Reference::parse(image_ref)?;
+        let (name, tag) = match reference.registry.as_str() {
+            "docker.io" => (
+                reference
+                    .repository
+                    .strip_prefix("library/")
+                    .unwrap_or(&reference.repository)
+                    .to_string(),
+                reference.manifest_reference().to_string(),
+            ),
+            registry => (
+                format!("{}/{}", registry, reference.repository),
+                reference.manifest_reference().to_string(),
+            ),
+        };
+        let image_id = self.generate_image_id(&name, &tag);
+        let image_dir = self.image_dir(&image_id);
 
-        // Move the extracted rootfs
-        let final_rootfs = image_dir.join("rootfs");
-        if oci_metadata.rootfs_path.exists() {
-            if final_rootfs.exists() {
-                std::fs::remove_dir_all(&final_rootfs)?;
-            }
-            std::fs::rename(&oci_metadata.rootfs_path, &final_rootfs)?;
-        }
+        let mut client = registry::RegistryClient::new(reference)?;
+        let pulled = client
+            .pull(platform, &self.root_path.join("content"), None)
+            .await?;
 
-        // Convert to Bolt metadata format
+        let run = pulled.config.config.clone().unwrap_or_default();
         let image_metadata = ImageMetadata {
             id: image_id.clone(),
-            name: name.to_string(),
-            tag: tag.to_string(),
-            digest: oci_metadata.digest,
-            size: oci_metadata.size,
-            layers: oci_metadata.layers,
+            name,
+            tag,
+            digest: pulled.digest.clone(),
+            size: pulled.size(),
+            layers: pulled.layers.iter().map(|l| l.digest.clone()).collect(),
             config: ImageConfig {
-                architecture: oci_metadata.config.architecture,
-                os: oci_metadata.config.os,
-                env: oci_metadata.config.config.env,
-                cmd: oci_metadata.config.config.cmd,
-                entrypoint: oci_metadata.config.config.entrypoint,
-                working_dir: oci_metadata.config.config.working_dir,
-                user: oci_metadata.config.config.user,
-                exposed_ports: oci_metadata.config.config.exposed_ports,
-                volumes: oci_metadata.config.config.volumes,
+                architecture: pulled.config.architecture.clone(),
+                os: pulled.config.os.clone(),
+                env: run.env.unwrap_or_default(),
+                cmd: run.cmd.unwrap_or_default(),
+                entrypoint: run.entrypoint.unwrap_or_default(),
+                working_dir: run
+                    .working_dir
+                    .filter(|w| !w.is_empty())
+                    .unwrap_or_else(|| "/".to_string()),
+                user: run.user.unwrap_or_default(),
+                exposed_ports: run.exposed_ports.unwrap_or_default(),
+                volumes: run.volumes.unwrap_or_default(),
             },
             created_at: chrono::Utc::now(),
+            annotations: pulled.annotations.clone(),
         };
-
+        std::fs::create_dir_all(&image_dir)?;
+        std::fs::write(
+            image_dir.join("image.json"),
+            serde_json::to_vec_pretty(&image_metadata)?,
+        )?;
+        for layer in &pulled.layers {
+            self.layers.insert(
+                layer.digest.clone(),
+                LayerMetadata {
+                    id: layer.digest.clone(),
+                    digest: layer.digest.clone(),
+                    size: layer.size,
+                    media_type: layer.media_type.clone(),
+                    created_at: chrono::Utc::now(),
+                    parent: None,
+                },
+            );
+        }
         self.images.insert(image_id.clone(), image_metadata);
 
-        // Clean up temporary directory
-        let _ = std::fs::remove_dir_all(&temp_dir);
-
-        info!("✅ Docker image pulled successfully: {}", image_id);
+        info!(
+            "✅ Pulled {} ({}) as {}",
+            pulled.reference, pulled.platform, image_id
+        );
         Ok(image_id)
     }
 
-    async fn pull_oci_image(&mut self, registry: &str, name: &str, tag: &str) -> Result<String> {
-        info!("📋 Pulling OCI image from {}: {}:{}", registry, name, tag);
+    /// The blobs of a pulled image's layers, bottom first
+    pub fn layer_sources(&self, image_id: &str) -> Result<Vec<overlay::LayerSource>> {
+        let image = self
+            .images
+            .get(image_id)
+            .ok_or_else(|| anyhow::anyhow!("Image not found: {}", image_id))?;
+        image
+            .layers
+            .iter()
+            .map(|digest| {
+                let blob = registry::blob_path(&self.root_path.join("content"), digest)?;
+                if !blob.is_file() {
+                    return Err(anyhow::anyhow!(
+                        "Layer {} of {} is missing from the store; pull the image again",
+                        digest,
+                        image_id
+                    ));
+                }
+                Ok(overlay::LayerSource {
+                    digest: digest.clone(),
+                    blob,
+                })
+            })
+            .collect()
+    }
+
+    /// Assemble a container's rootfs from a pulled image at `target`, an
+    /// overlay mount of its layers where the host allows it
+    pub fn assemble_rootfs(
+        &self,
+        image_id: &str,
+        container_id: &str,
+        target: &Path,
+    ) -> Result<overlay::RootfsMount> {
+        let layers = self.layer_sources(image_id)?;
+        let mut driver = overlay::OverlayDriver::new(self.root_path.join("overlay"))?;
+        driver.assemble(container_id, &layers, target)
+    }
 
-        // TODO: Implement generic OCI registry client
-        warn!("Generic OCI registry support not yet implemented");
+    /// Unmount and delete a container's rootfs
+    pub fn release_rootfs(&self, container_id: &str) -> Result<()> {
+        overlay::OverlayDriver::new(self.root_path.join("overlay"))?.release(container_id)
+    }
 
-        let image_id = self.generate_image_id(name, tag);
-        Ok(image_id)
+    /// Unpack a pulled image into a flat rootfs under `images/<id>/rootfs`
+    pub fn export_rootfs(&self, image_id: &str) -> Result<PathBuf> {
+        let rootfs = self.image_dir(image_id).join("rootfs");
+        let layers = self.layer_sources(image_id)?;
+        info!(
+            "📂 Unpacking {} layers into {}",
+            layers.len(),
+            rootfs.display()
+        );
+        if rootfs.exists() {
+            std::fs::remove_dir_all(&rootfs)?;
+        }
+        std::fs::create_dir_all(&rootfs)?;
+        for layer in &layers {
+            registry::unpack_layer(&layer.blob, overlay::media_type(&layer.blob)?, &rootfs)
+                .with_context(|| format!("Failed to unpack layer {}", layer.digest))?;
+        }
+        Ok(rootfs)
+    }
+
+    fn image_dir(&self, image_id: &str) -> PathBuf {
+        self.root_path
+            .join("images")
+            .join(image_id.replace(['/', ':', '@'], "_"))
     }
 
     pub async fn build_image(
@@ -395,6 +502,7 @@ impl StorageManager {
                 volumes: HashMap::new(),
             },
             created_at: chrono::Utc::now(),
+            annotations: HashMap::new(),
         };
 
         self.images.insert(final_image_id.clone(), image_metadata);
@@ -1,173 +1,308 @@
-use anyhow::{Context, Result};
+// Overlay root filesystems
+//
+// Each image layer is unpacked once into `<root>/diff/<digest>` and shared by
+// every image and container that uses it. The layer's OCI whiteouts become
+// what overlayfs understands: `.wh.<name>` a 0/0 character device named
+// `<name>`, and `.wh..wh..opq` the `trusted.overlay.opaque` xattr on its
+// directory.
+//
+// A container's rootfs is an overlay mount of its image's layers, topmost
+// first in `lowerdir`, with an upper and work directory of its own under
+// `<root>/containers/<id>`, mounted where the bundle expects `rootfs`. Its
+// writes land in the upper directory and survive a restart; removing the
+// container unmounts the rootfs and deletes both.
+//
+// Whiteout devices, trusted xattrs and mount(2) need root. Without it, or
+// when the kernel has no overlayfs or the lowerdir chain is longer than
+// mount(2) accepts, the layers are unpacked in order into the rootfs instead,
+// a private copy per container.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
-use tokio::fs;
 use tracing::{debug, info, warn};
 
+use super::registry::{self, OPAQUE_WHITEOUT, WHITEOUT_PREFIX};
+
+const OPAQUE_XATTR: &str = "trusted.overlay.opaque";
+
+/// mount(2) takes at most a page of options
+const MAX_MOUNT_OPTIONS: usize = 4095;
+
 pub struct OverlayDriver {
     pub root_path: PathBuf,
     pub layers: HashMap<String, LayerInfo>,
 }
 
+/// A layer unpacked for overlayfs
 #[derive(Debug, Clone)]
 pub struct LayerInfo {
+    /// The layer's digest
     pub id: String,
     pub diff_path: PathBuf,
-    pub merged_path: PathBuf,
-    pub work_path: PathBuf,
-    pub lower_dirs: Vec<String>,
+}
+
+/// A layer to assemble a rootfs from
+#[derive(Debug, Clone)]
+pub struct LayerSource {
+    pub digest: String,
+    /// The compressed layer blob
+    pub blob: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountMode {
+    Overlay,
+    /// Layers unpacked into a private copy
+    Copy,
+}
+
+/// A container's assembled rootfs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RootfsMount {
+    pub container_id: String,
+    pub mode: MountMode,
+    pub target: PathBuf,
+    /// Layer digests, bottom first
+    pub layers: Vec<String>,
 }
 
 impl OverlayDriver {
     pub fn new(root_path: PathBuf) -> Result<Self> {
-        info!("📂 Initializing overlay storage driver at: {:?}", root_path);
-
-        // Create overlay directory structure
-        let overlay_dirs = ["diff", "merged", "work", "link"];
-        for dir in &overlay_dirs {
+        for dir in ["diff", "containers"] {
             let dir_path = root_path.join(dir);
             std::fs::create_dir_all(&dir_path)
                 .with_context(|| format!("Failed to create overlay directory: {:?}", dir_path))?;
         }
 
-        Ok(Self {
-            root_path,
-            layers: HashMap::new(),
-        })
-    }
-
-    pub async fn create_layer(
-        &mut self,
-        layer_id: &str,
-        parent_layers: &[String],
-    ) -> Result<LayerInfo> {
-        info!("📦 Creating overlay layer: {}", layer_id);
-
-        let diff_path = self.root_path.join("diff").join(layer_id);
-        let merged_path = self.root_path.join("merged").join(layer_id);
-        let work_path = self.root_path.join("work").join(layer_id);
-
-        // Create directories
-        fs::create_dir_all(&diff_path)
-            .await
-            .with_context(|| format!("Failed to create diff directory: {:?}", diff_path))?;
-        fs::create_dir_all(&merged_path)
-            .await
-            .with_context(|| format!("Failed to create merged directory: {:?}", merged_path))?;
-        fs::create_dir_all(&work_path)
-            .await
-            .with_context(|| format!("Failed to create work directory: {:?}", work_path))?;
-
-        let layer_info = LayerInfo {
-            id: layer_id.to_string(),
-            diff_path: diff_path.clone(),
-            merged_path: merged_path.clone(),
-            work_path: work_path.clone(),
-            lower_dirs: parent_layers.to_vec(),
-        };
-
-        // Mount overlay filesystem
-        self.mount_overlay(&layer_info).await?;
-
-        self.layers.insert(layer_id.to_string(), layer_info.clone());
+        let mut layers = HashMap::new();
+        for entry in std::fs::read_dir(root_path.join("diff"))?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".partial") {
+                continue;
+            }
+            let id = name.replacen('-', ":", 1);
+            layers.insert(
+                id.clone(),
+                LayerInfo {
+                    id,
+                    diff_path: entry.path(),
+                },
+            );
+        }
 
-        debug!("✅ Overlay layer created: {}", layer_id);
-        Ok(layer_info)
+        Ok(Self { root_path, layers })
     }
 
-    async fn mount_overlay(&self, layer_info: &LayerInfo) -> Result<()> {
-        debug!("🔗 Mounting overlay for layer: {}", layer_info.id);
+    /// Whether containers can get overlay mounts here
+    pub fn supported() -> bool {
+        // SAFETY: geteuid has no preconditions
+        let root = unsafe { libc::geteuid() } == 0;
+        root && std::fs::read_to_string("/proc/filesystems")
+            .is_ok_and(|f| f.lines().any(|l| l.trim_end().ends_with("\toverlay")))
+    }
 
-        // Build lowerdir string from parent layers
-        let mut lowerdir_parts = Vec::new();
-        for parent_id in &layer_info.lower_dirs {
-            if let Some(parent_layer) = self.layers.get(parent_id) {
-                lowerdir_parts.push(parent_layer.diff_path.to_string_lossy().to_string());
-            }
+    /// Unpack a layer into its diff directory, once
+    pub fn create_layer(&mut self, source: &LayerSource) -> Result<LayerInfo> {
+        if let Some(layer) = self.layers.get(&source.digest) {
+            return Ok(layer.clone());
         }
-
-        let mount_options = if lowerdir_parts.is_empty() {
-            // Base layer - no lowerdir
-            format!(
-                "upperdir={},workdir={}",
-                layer_info.diff_path.display(),
-                layer_info.work_path.display()
-            )
-        } else {
-            // Layer with parents
-            format!(
-                "lowerdir={},upperdir={},workdir={}",
-                lowerdir_parts.join(":"),
-                layer_info.diff_path.display(),
-                layer_info.work_path.display()
-            )
+        info!("📦 Unpacking layer {} for overlay", source.digest);
+
+        let diff_path = self
+            .root_path
+            .join("diff")
+            .join(layer_dir_name(&source.digest)?);
+        let partial = diff_path.with_extension("partial");
+        registry::remove_path(&partial)?;
+        std::fs::create_dir_all(&partial)?;
+        unpack_diff(&source.blob, &partial)
+            .with_context(|| format!("Failed to unpack layer {}", source.digest))?;
+        std::fs::rename(&partial, &diff_path)?;
+
+        let layer = LayerInfo {
+            id: source.digest.clone(),
+            diff_path,
         };
+        self.layers.insert(layer.id.clone(), layer.clone());
+        Ok(layer)
+    }
 
-        debug!("Mount options: {}", mount_options);
-
-        // In a real implementation, we would use mount(2) syscall
-        // For now, we'll simulate the overlay mount
-        info!("✅ Overlay mounted for layer: {}", layer_info.id);
-
+    /// Delete an unpacked layer; containers using it must be gone
+    pub fn remove_layer(&mut self, layer_id: &str) -> Result<()> {
+        info!("🗑️  Removing overlay layer: {}", layer_id);
+        match self.layers.remove(layer_id) {
+            Some(layer) => registry::remove_path(&layer.diff_path)
+                .with_context(|| format!("Failed to remove {:?}", layer.diff_path))?,
+            None => warn!("Layer not found: {}", layer_id),
+        }
         Ok(())
     }
 
-    pub async fn remove_layer(&mut self, layer_id: &str) -> Result<()> {
-        info!("🗑️  Removing overlay layer: {}", layer_id);
+    fn container_dir(&self, container_id: &str) -> Result<PathBuf> {
+        if container_id.is_empty()
+            || container_id.starts_with('.')
+            || container_id.contains(['/', ','])
+        {
+            return Err(anyhow!("Invalid container id '{}'", container_id));
+        }
+        Ok(self.root_path.join("containers").join(container_id))
+    }
 
-        if let Some(layer_info) = self.layers.remove(layer_id) {
-            // Unmount overlay
-            self.unmount_overlay(&layer_info).await?;
-
-            // Remove directories
-            if layer_info.merged_path.exists() {
-                fs::remove_dir_all(&layer_info.merged_path)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to remove merged directory: {:?}",
-                            layer_info.merged_path
-                        )
-                    })?;
+    /// Assemble a container's rootfs from `layers`, bottom first, at
+    /// `target`. A rootfs assembled before is mounted again, keeping the
+    /// container's writes.
+    pub fn assemble(
+        &mut self,
+        container_id: &str,
+        layers: &[LayerSource],
+        target: &Path,
+    ) -> Result<RootfsMount> {
+        let container_dir = self.container_dir(container_id)?;
+        let record_path = container_dir.join("mount.json");
+        if let Some(previous) = std::fs::read(&record_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<RootfsMount>(&data).ok())
+            .filter(|m| m.target == target)
+            && (previous.mode == MountMode::Copy || is_mounted(target))
+        {
+            debug!("Rootfs of {} is already assembled", container_id);
+            return Ok(previous);
+        }
+        std::fs::create_dir_all(&container_dir)?;
+        std::fs::create_dir_all(target)?;
+
+        let mode = if Self::supported() {
+            match self.mount_overlay(&container_dir, layers, target) {
+                Ok(()) => MountMode::Overlay,
+                Err(e) => {
+                    warn!("⚠️  Copying layers for {}: {:#}", container_id, e);
+                    MountMode::Copy
+                }
             }
-
-            if layer_info.diff_path.exists() {
-                fs::remove_dir_all(&layer_info.diff_path)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to remove diff directory: {:?}",
-                            layer_info.diff_path
-                        )
-                    })?;
+        } else {
+            MountMode::Copy
+        };
+        if mode == MountMode::Copy {
+            info!(
+                "📂 Unpacking {} layers into {}",
+                layers.len(),
+                target.display()
+            );
+            for layer in layers {
+                registry::unpack_layer(&layer.blob, media_type(&layer.blob)?, target)
+                    .with_context(|| format!("Failed to unpack layer {}", layer.digest))?;
             }
+        }
 
-            if layer_info.work_path.exists() {
-                fs::remove_dir_all(&layer_info.work_path)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to remove work directory: {:?}",
-                            layer_info.work_path
-                        )
-                    })?;
-            }
+        let mount = RootfsMount {
+            container_id: container_id.to_string(),
+            mode,
+            target: target.to_path_buf(),
+            layers: layers.iter().map(|l| l.digest.clone()).collect(),
+        };
+        std::fs::write(&record_path, serde_json::to_vec_pretty(&mount)?)?;
+        Ok(mount)
+    }
 
-            info!("✅ Overlay layer removed: {}", layer_id);
-        } else {
-            warn!("Layer not found: {}", layer_id);
+    fn mount_overlay(
+        &mut self,
+        container_dir: &Path,
+        layers: &[LayerSource],
+        target: &Path,
+    ) -> Result<()> {
+        let mut lowers = Vec::with_capacity(layers.len());
+        for layer in layers {
+            lowers.push(self.create_layer(layer)?.diff_path);
         }
-
+        // overlayfs needs a lower directory even for an image without layers
+        if lowers.is_empty() {
+            let empty = container_dir.join("empty");
+            std::fs::create_dir_all(&empty)?;
+            lowers.push(empty);
+        }
+        lowers.reverse();
+
+        let upper = container_dir.join("upper");
+        let work = container_dir.join("work");
+        std::fs::create_dir_all(&upper)?;
+        std::fs::create_dir_all(&work)?;
+        let options = mount_options(&lowers, &upper, &work)?;
+        debug!("Mount options: {}", options);
+
+        let source = CString::new("overlay")?;
+        let target_c = CString::new(target.as_os_str().as_bytes())?;
+        let options_c = CString::new(options)?;
+        // SAFETY: every pointer is a valid NUL-terminated string
+        let result = unsafe {
+            libc::mount(
+                source.as_ptr(),
+                target_c.as_ptr(),
+                source.as_ptr(),
+                0,
+                options_c.as_ptr().cast(),
+            )
+        };
+        if result != 0 {
+            return Err(anyhow!(
+                "mount overlay on {}: {}",
+                target.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+        info!(
+            "✅ Overlay mounted at {} ({} layers)",
+            target.display(),
+            layers.len()
+        );
         Ok(())
     }
 
-    async fn unmount_overlay(&self, layer_info: &LayerInfo) -> Result<()> {
-        debug!("🔌 Unmounting overlay for layer: {}", layer_info.id);
+    /// Unmount a container's rootfs and delete it with its upper directory
+    pub fn release(&self, container_id: &str) -> Result<()> {
+        let container_dir = self.container_dir(container_id)?;
+        let Some(mount) = std::fs::read(container_dir.join("mount.json"))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<RootfsMount>(&data).ok())
+        else {
+            return Ok(());
+        };
 
-        // In a real implementation, we would use umount(2) syscall
-        // For now, we'll simulate the overlay unmount
-        debug!("✅ Overlay unmounted for layer: {}", layer_info.id);
+        if is_mounted(&mount.target) {
+            debug!("🔌 Unmounting {}", mount.target.display());
+            let target = CString::new(mount.target.as_os_str().as_bytes())?;
+            // SAFETY: target is a valid NUL-terminated path
+            if unsafe { libc::umount2(target.as_ptr(), 0) } != 0 {
+                let error = std::io::Error::last_os_error();
+                if error.raw_os_error() != Some(libc::EBUSY) {
+                    return Err(anyhow!(
+                        "Failed to unmount {}: {}",
+                        mount.target.display(),
+                        error
+                    ));
+                }
+                warn!(
+                    "{} is busy; detaching it to unmount once unused",
+                    mount.target.display()
+                );
+                // SAFETY: as above
+                if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+                    return Err(anyhow!(
+                        "Failed to unmount {}: {}",
+                        mount.target.display(),
+                        std::io::Error::last_os_error()
+                    ));
+                }
+            }
+        }
 
+        registry::remove_path(&mount.target)?;
+        registry::remove_path(&container_dir)?;
+        info!("🗑️  Released rootfs of {}", container_id);
         Ok(())
     }
 
@@ -205,6 +340,167 @@ pub struct StorageUsage {
     pub driver: String,
 }
 
+/// `sha256:<hex>` as a directory name
+fn layer_dir_name(digest: &str) -> Result<String> {
+    match digest.split_once(':') {
+        Some((algorithm, hex))
+            if !algorithm.is_empty()
+                && !hex.is_empty()
+                && algorithm.chars().all(|c| c.is_ascii_alphanumeric())
+                && hex.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Ok(format!("{}-{}", algorithm, hex))
+        }
+        _ => Err(anyhow!("Invalid layer digest '{}'", digest)),
+    }
+}
+
+/// Options for an overlay mount; `lowers` topmost first
+pub fn mount_options(lowers: &[PathBuf], upper: &Path, work: &Path) -> Result<String> {
+    let all: Vec<&Path> = lowers
+        .iter()
+        .map(PathBuf::as_path)
+        .chain([upper, work])
+        .collect();
+    if let Some(path) = all
+        .iter()
+        .find(|p| p.to_string_lossy().contains([',', ':']))
+    {
+        return Err(anyhow!(
+            "{} can't be used in an overlay mount: it contains ',' or ':'",
+            path.display()
+        ));
+    }
+    let lowerdir: Vec<String> = lowers.iter().map(|p| p.display().to_string()).collect();
+    let options = format!(
+        "lowerdir={},upperdir={},workdir={}",
+        lowerdir.join(":"),
+        upper.display(),
+        work.display()
+    );
+    if options.len() > MAX_MOUNT_OPTIONS {
+        return Err(anyhow!(
+            "{} layers are too many for one overlay mount",
+            lowers.len()
+        ));
+    }
+    Ok(options)
+}
+
+/// The media type of a layer blob, from its magic bytes
+pub fn media_type(blob: &Path) -> Result<&'static str> {
+    let mut magic = [0u8; 4];
+    let read = std::fs::File::open(blob)?.read(&mut magic)?;
+    Ok(match &magic[..read] {
+        [0x1f, 0x8b, ..] => "application/vnd.oci.image.layer.v1.tar+gzip",
+        [0x28, 0xb5, 0x2f, 0xfd] => "application/vnd.oci.image.layer.v1.tar+zstd",
+        _ => "application/vnd.oci.image.layer.v1.tar",
+    })
+}
+
+/// Unpack a layer as an overlayfs diff: files as they are, whiteouts as
+/// whiteout devices and opaque xattrs
+fn unpack_diff(blob: &Path, diff: &Path) -> Result<()> {
+    let mut whiteouts = Vec::new();
+    let mut opaque = Vec::new();
+
+    let mut archive = tar::Archive::new(registry::layer_reader(blob, media_type(blob)?)?);
+    archive.set_preserve_permissions(true);
+    archive.set_unpack_xattrs(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if kind.is_character_special() || kind.is_block_special() {
+            continue;
+        }
+        let entry_path = entry.path()?.into_owned();
+        let parent = entry_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        match entry_path.file_name().and_then(|n| n.to_str()) {
+            Some(OPAQUE_WHITEOUT) => opaque.push(parent),
+            Some(name) if name.starts_with(WHITEOUT_PREFIX) => {
+                whiteouts.push(parent.join(&name[WHITEOUT_PREFIX.len()..]))
+            }
+            _ => {
+                entry.unpack_in(diff)?;
+            }
+        }
+    }
+
+    for dir in opaque {
+        let Some(dir) = registry::inside(diff, &dir) else {
+            continue;
+        };
+        std::fs::create_dir_all(&dir)?;
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let name = CString::new(OPAQUE_XATTR)?;
+        // SAFETY: valid NUL-terminated strings and a one-byte value
+        if unsafe { libc::lsetxattr(path.as_ptr(), name.as_ptr(), b"y".as_ptr().cast(), 1, 0) } != 0
+        {
+            return Err(anyhow!(
+                "Failed to mark {} opaque: {}",
+                dir.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    for hidden in whiteouts {
+        let Some(target) = registry::inside(diff, &hidden) else {
+            continue;
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        registry::remove_path(&target)?;
+        let path = CString::new(target.as_os_str().as_bytes())?;
+        // SAFETY: path is a valid NUL-terminated string
+        if unsafe { libc::mknod(path.as_ptr(), libc::S_IFCHR, libc::makedev(0, 0)) } != 0 {
+            return Err(anyhow!(
+                "Failed to create whiteout {}: {}",
+                target.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Mount points listed in `/proc/self/mountinfo`
+pub fn mount_points(mountinfo: &str) -> Vec<PathBuf> {
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|point| PathBuf::from(unescape_octal(point)))
+        .collect()
+}
+
+/// mountinfo escapes space, tab, newline and backslash as `\ooo`
+fn unescape_octal(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest.get(i + 1..i + 4);
+        match code.and_then(|c| u8::from_str_radix(c, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_mounted(path: &Path) -> bool {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .is_ok_and(|info| mount_points(&info).contains(&path))
+}
+
 fn get_directory_size_sync(path: &Path) -> Result<u64> {
     let mut total_size = 0u64;
 
@@ -225,3 +521,107 @@ fn get_directory_size_sync(path: &Path) -> Result<u64> {
 
     Ok(total_size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(path: &Path, entries: &[(&str, Option<&str>)]) -> LayerSource {
+        let mut builder = tar::Builder::new(std::fs::File::create(path).unwrap());
+        for (entry_path, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            let data = contents.unwrap_or("");
+            header.set_entry_type(if contents.is_some() {
+                tar::EntryType::Regular
+            } else {
+                tar::EntryType::Directory
+            });
+            header.set_size(data.len() as u64);
+            header.set_mode(if contents.is_some() { 0o644 } else { 0o755 });
+            builder
+                .append_data(&mut header, entry_path, data.as_bytes())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        LayerSource {
+            digest: format!("sha256:{:02x}", entries.len()),
+            blob: path.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn assembles_layers_and_builds_mount_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = layer(
+            &dir.path().join("base.tar"),
+            &[
+                ("etc/", None),
+                ("etc/hostname", Some("base")),
+                ("etc/motd", Some("hi")),
+                ("var/cache/", None),
+                ("var/cache/old", Some("x")),
+            ],
+        );
+        let top = layer(
+            &dir.path().join("top.tar"),
+            &[
+                ("etc/.wh.motd", Some("")),
+                ("var/cache/.wh..wh..opq", Some("")),
+                ("var/cache/new", Some("y")),
+            ],
+        );
+
+        // Unpacked as an overlay diff, the whiteouts become overlayfs ones
+        if OverlayDriver::supported() {
+            let diff = dir.path().join("diff");
+            std::fs::create_dir_all(&diff).unwrap();
+            unpack_diff(&top.blob, &diff).unwrap();
+            use std::os::unix::fs::FileTypeExt;
+            let motd = std::fs::symlink_metadata(diff.join("etc/motd")).unwrap();
+            assert!(motd.file_type().is_char_device());
+            assert!(diff.join("var/cache/new").exists());
+        }
+
+        let mut driver = OverlayDriver::new(dir.path().join("overlay")).unwrap();
+        let target = dir.path().join("rootfs");
+        let mount = driver
+            .assemble("c1", &[base.clone(), top.clone()], &target)
+            .unwrap();
+        assert_eq!(mount.layers, vec![base.digest.clone(), top.digest.clone()]);
+        assert_eq!(
+            std::fs::read_to_string(target.join("etc/hostname")).unwrap(),
+            "base"
+        );
+        assert!(!target.join("etc/motd").exists());
+        assert!(!target.join("var/cache/old").exists());
+        assert!(target.join("var/cache/new").exists());
+        // Assembled again, e.g. on restart, it's the same rootfs
+        assert_eq!(driver.assemble("c1", &[base, top], &target).unwrap(), mount);
+        driver.release("c1").unwrap();
+        assert!(!target.exists());
+        assert!(driver.assemble("../c1", &[], &target).is_err());
+
+        let options = mount_options(
+            &[PathBuf::from("/l/top"), PathBuf::from("/l/base")],
+            Path::new("/c/upper"),
+            Path::new("/c/work"),
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            "lowerdir=/l/top:/l/base,upperdir=/c/upper,workdir=/c/work"
+        );
+        assert!(mount_options(&[PathBuf::from("/a,b")], Path::new("/u"), Path::new("/w")).is_err());
+        let many = vec![PathBuf::from(format!("/{}", "x".repeat(100))); 50];
+        assert!(mount_options(&many, Path::new("/u"), Path::new("/w")).is_err());
+
+        assert!(layer_dir_name("sha256:../x").is_err());
+        assert_eq!(layer_dir_name("sha256:ab12").unwrap(), "sha256-ab12");
+        assert_eq!(
+            mount_points(
+                "36 35 98:0 / /mnt/with\\040space rw,noatime master:1 - ext3 /dev/root rw\n"
+            ),
+            vec![PathBuf::from("/mnt/with space")]
+        );
+    }
+}
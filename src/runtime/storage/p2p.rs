@@ -0,0 +1,788 @@
+// Peer-to-peer layer sharing
+//
+// Pulling the same 30GB game image on every machine of a LAN fetches it from
+// the registry once per machine. With `[p2p] enabled`, pulls ask the other
+// Bolt nodes on the LAN for the image's blobs first:
+//
+// 1. The manifest always comes from the registry, so tags resolve and trust
+//    policies apply as usual. The puller then multicasts the digests of the
+//    config and layers to 239.255.77.66:7948; nodes running `bolt image
+//    serve` answer with those they hold in their content store.
+// 2. Each blob is fetched over QUIC from a peer that has it, checked against
+//    its digest and size while it streams and kept in the store like a
+//    registry download. A peer that fails or sends anything else is dropped
+//    for the rest of the pull, and the next one, finally the registry, is
+//    tried.
+// 3. The image is handed to podman (or to docker, through skopeo) as an OCI
+//    layout built from the store. Images pinned by digest and encrypted
+//    images are pulled by the runtime as before.
+//
+// Transfers are limited per peer in both directions: `upload_limit` caps what
+// each peer downloads from this node, `download_limit` what this node
+// downloads from each peer. `bolt image serve` only answers addresses in
+// `allow`, the private and link-local ranges by default. Peer certificates
+// aren't checked since nothing a peer sends is used unverified, but anyone on
+// an allowed network can fetch a blob whose digest they know, including
+// layers of private images.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::UdpSocket;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::registry::{self, BlobSource, Descriptor, Manifest, Reference, RegistryClient};
+use crate::builds::cache::parse_size;
+use crate::config::{BoltConfig, P2pSettings};
+use crate::runtime::platform::Platform;
+
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 66);
+pub const DISCOVERY_PORT: u16 = 7948;
+const SERVER_NAME: &str = "bolt-p2p";
+/// How long a probe waits for answers
+const DISCOVERY_WAIT: Duration = Duration::from_millis(800);
+/// Digests asked about per probe, so that it fits one datagram
+const DIGESTS_PER_PROBE: usize = 256;
+const MAX_DATAGRAM: usize = 65_507;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const CHUNK_SIZE: usize = 1024 * 1024;
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024;
+
+/// Discovery traffic, one JSON object per UDP datagram
+#[derive(Debug, Serialize, Deserialize)]
+enum Datagram {
+    /// Which of these blobs do you have? Empty asks who is there at all.
+    Probe { node: String, digests: Vec<String> },
+    /// The blobs asked about that the node serves on `port`, and how many it
+    /// holds in total
+    Have {
+        node: String,
+        port: u16,
+        digests: Vec<String>,
+        blobs: usize,
+    },
+}
+
+/// Requests and replies on a QUIC stream
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    Get { digest: String },
+    Blob { size: u64 },
+    Missing,
+}
+
+/// A node answering probes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peer {
+    pub node: String,
+    pub address: SocketAddr,
+    /// Blobs in its store
+    pub blobs: usize,
+}
+
+/// Bolt's content store, which pulls fill and `bolt image serve` serves from
+pub fn store_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("storage").join("content")
+}
+
+fn p2p_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("p2p")
+}
+
+fn node_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Keeps a stream of transfers under `rate` bytes per second
+#[derive(Debug)]
+struct Throttle {
+    rate: Option<u64>,
+    started: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+    fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate,
+            started: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// How long to pause after sending or receiving `n` more bytes
+    fn delay(&mut self, n: usize) -> Duration {
+        let Some(rate) = self.rate.filter(|r| *r > 0) else {
+            return Duration::ZERO;
+        };
+        // After an idle spell the budget starts over, rather than allowing a
+        // burst of everything that wasn't used
+        let due = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+        if self.started.elapsed() > due + Duration::from_secs(1) {
+            self.started = Instant::now();
+            self.bytes = 0;
+        }
+        self.bytes += n as u64;
+        Duration::from_secs_f64(self.bytes as f64 / rate as f64)
+            .saturating_sub(self.started.elapsed())
+    }
+}
+
+fn limit(limit: &Option<String>) -> Result<Option<u64>> {
+    limit.as_deref().map(parse_size).transpose()
+}
+
+/// The networks `bolt image serve` answers
+fn allowed_networks(settings: &P2pSettings) -> Result<Vec<ipnet::IpNet>> {
+    let networks: Vec<&str> = if settings.allow.is_empty() {
+        vec![
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "169.254.0.0/16",
+            "127.0.0.0/8",
+            "fc00::/7",
+            "fe80::/10",
+            "::1/128",
+        ]
+    } else {
+        settings.allow.iter().map(String::as_str).collect()
+    };
+    networks
+        .into_iter()
+        .map(|n| {
+            n.parse()
+                .map_err(|_| anyhow!("Invalid [p2p] allow: '{}' is not a CIDR", n))
+        })
+        .collect()
+}
+
+fn allowed(networks: &[ipnet::IpNet], address: IpAddr) -> bool {
+    let address = match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+        v4 => v4,
+    };
+    networks.iter().any(|n| n.contains(&address))
+}
+
+/// Blobs the store can serve, of those asked about
+fn held(store: &Path, digests: &[String]) -> Vec<String> {
+    digests
+        .iter()
+        .filter(|d| registry::blob_path(store, d).is_ok_and(|p| p.is_file()))
+        .cloned()
+        .collect()
+}
+
+fn blob_count(store: &Path) -> usize {
+    std::fs::read_dir(store.join("blobs").join("sha256"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_none())
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Multicast probes for `digests` and collect the answers: which peers have
+/// which blobs
+async fn discover(
+    node: &str,
+    digests: &[String],
+) -> Result<(Vec<Peer>, HashMap<String, Vec<SocketAddr>>)> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    // Probes stay on the LAN
+    socket.set_multicast_ttl_v4(1)?;
+    let group = SocketAddr::from((DISCOVERY_GROUP, DISCOVERY_PORT));
+    let batches: Vec<&[String]> = if digests.is_empty() {
+        vec![&[]]
+    } else {
+        digests.chunks(DIGESTS_PER_PROBE).collect()
+    };
+    for batch in batches {
+        let probe = Datagram::Probe {
+            node: node.to_string(),
+            digests: batch.to_vec(),
+        };
+        socket
+            .send_to(&serde_json::to_vec(&probe)?, group)
+            .await
+            .context("Failed to send a discovery probe")?;
+    }
+
+    let mut peers: HashMap<String, Peer> = HashMap::new();
+    let mut holders: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+    let deadline = tokio::time::Instant::now() + DISCOVERY_WAIT;
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (n, from) = received?;
+        let Ok(Datagram::Have {
+            node: peer,
+            port,
+            digests: have,
+            blobs,
+        }) = serde_json::from_slice(&buf[..n])
+        else {
+            continue;
+        };
+        if peer == node {
+            continue;
+        }
+        let address = SocketAddr::new(from.ip(), port);
+        for digest in have.into_iter().filter(|d| digests.contains(d)) {
+            let holders = holders.entry(digest).or_default();
+            if !holders.contains(&address) {
+                holders.push(address);
+            }
+        }
+        peers.insert(
+            peer.clone(),
+            Peer {
+                node: peer,
+                address,
+                blobs,
+            },
+        );
+    }
+    let mut peers: Vec<Peer> = peers.into_values().collect();
+    peers.sort_by_key(|p| p.address);
+    Ok((peers, holders))
+}
+
+/// Bolt nodes on the LAN serving layers
+pub async fn peers() -> Result<Vec<Peer>> {
+    Ok(discover(&node_id(), &[]).await?.0)
+}
+
+/// The LAN peers as a source of blobs for one pull
+pub struct Swarm {
+    node: String,
+    download_limit: Option<u64>,
+    endpoint: Option<quinn::Endpoint>,
+    connections: HashMap<SocketAddr, quinn::Connection>,
+    /// Peers that have each blob, by digest
+    holders: HashMap<String, Vec<SocketAddr>>,
+    throttles: HashMap<SocketAddr, Throttle>,
+    /// Peers that failed or sent bad data during this pull
+    failed: HashSet<SocketAddr>,
+}
+
+impl Swarm {
+    pub fn new(settings: &P2pSettings) -> Result<Self> {
+        Ok(Self {
+            node: node_id(),
+            download_limit: limit(&settings.download_limit)?,
+            endpoint: None,
+            connections: HashMap::new(),
+            holders: HashMap::new(),
+            throttles: HashMap::new(),
+            failed: HashSet::new(),
+        })
+    }
+
+    async fn connect(&mut self, peer: SocketAddr) -> Result<quinn::Connection> {
+        if let Some(connection) = self.connections.get(&peer)
+            && connection.close_reason().is_none()
+        {
+            return Ok(connection.clone());
+        }
+        if self.endpoint.is_none() {
+            let crypto = quinn_rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(AnyCertificate))
+                .with_no_client_auth();
+            let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+            client_config.transport_config(transport_config()?);
+            let mut endpoint =
+                quinn::Endpoint::client(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))?;
+            endpoint.set_default_client_config(client_config);
+            self.endpoint = Some(endpoint);
+        }
+        let endpoint = self.endpoint.as_ref().expect("endpoint was just created");
+        let connection =
+            tokio::time::timeout(CONNECT_TIMEOUT, endpoint.connect(peer, SERVER_NAME)?)
+                .await
+                .map_err(|_| anyhow!("timed out connecting"))??;
+        self.connections.insert(peer, connection.clone());
+        Ok(connection)
+    }
+
+    async fn fetch_from(
+        &mut self,
+        peer: SocketAddr,
+        descriptor: &Descriptor,
+        partial: &Path,
+    ) -> Result<()> {
+        let connection = self.connect(peer).await?;
+        let (mut send, mut recv) = connection.open_bi().await?;
+        write_message(
+            &mut send,
+            &Message::Get {
+                digest: descriptor.digest.clone(),
+            },
+        )
+        .await?;
+        send.finish().await?;
+        match read_message(&mut recv).await? {
+            Message::Blob { size } if size == descriptor.size => {}
+            Message::Blob { size } => {
+                return Err(anyhow!(
+                    "offered {} bytes, expected {}",
+                    size,
+                    descriptor.size
+                ));
+            }
+            Message::Missing => return Err(anyhow!("no longer has it")),
+            other => return Err(anyhow!("unexpected reply {:?}", other)),
+        }
+
+        let throttle = self
+            .throttles
+            .entry(peer)
+            .or_insert_with(|| Throttle::new(self.download_limit));
+        let mut file = tokio::fs::File::create(partial).await?;
+        let mut hasher = Sha256::new();
+        let mut received = 0u64;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        while let Some(n) = recv.read(&mut buf).await? {
+            received += n as u64;
+            if received > descriptor.size {
+                return Err(anyhow!("sent more than {} bytes", descriptor.size));
+            }
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n]).await?;
+            tokio::time::sleep(throttle.delay(n)).await;
+        }
+        file.flush().await?;
+
+        let digest = format!("sha256:{:x}", hasher.finalize());
+        if digest != descriptor.digest || received != descriptor.size {
+            return Err(anyhow!(
+                "sent {} ({} bytes) instead of {}",
+                digest,
+                received,
+                descriptor.digest
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobSource for Swarm {
+    async fn prepare(&mut self, blobs: &[Descriptor]) {
+        let digests: Vec<String> = blobs.iter().map(|b| b.digest.clone()).collect();
+        match discover(&self.node, &digests).await {
+            Ok((peers, holders)) => {
+                if !peers.is_empty() {
+                    info!(
+                        "🤝 {} peer(s) on the LAN have {} of {} blobs",
+                        peers.len(),
+                        holders.len(),
+                        blobs.len()
+                    );
+                }
+                self.holders = holders;
+            }
+            Err(e) => warn!("⚠️  Could not look for peers on the LAN: {}", e),
+        }
+    }
+
+    async fn fetch(&mut self, descriptor: &Descriptor, partial: &Path) -> bool {
+        let holders = self
+            .holders
+            .get(&descriptor.digest)
+            .cloned()
+            .unwrap_or_default();
+        for peer in holders {
+            if self.failed.contains(&peer) {
+                continue;
+            }
+            let started = Instant::now();
+            match self.fetch_from(peer, descriptor, partial).await {
+                Ok(()) => {
+                    info!(
+                        "  🤝 {} from {} ({:.1} MB in {:.1}s)",
+                        descriptor.digest,
+                        peer,
+                        descriptor.size as f64 / 1_000_000.0,
+                        started.elapsed().as_secs_f64()
+                    );
+                    return true;
+                }
+                Err(e) => {
+                    warn!(
+                        "⚠️  Peer {} failed to send {}: {}; not using it again",
+                        peer, descriptor.digest, e
+                    );
+                    self.failed.insert(peer);
+                    let _ = std::fs::remove_file(partial);
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Pull `image` with Bolt's registry client, its blobs from LAN peers where
+/// possible, and load it into the container runtime
+pub async fn pull(
+    config: &BoltConfig,
+    runtime: &str,
+    image: &str,
+    platform: &Platform,
+) -> Result<()> {
+    let store = store_dir(&config.data_dir);
+    let mut client = RegistryClient::new(Reference::parse(image)?)?
+        .with_source(Box::new(Swarm::new(&config.p2p)?));
+    let pulled = client.pull(platform, &store, None).await?;
+
+    let scratch = p2p_dir(&config.data_dir).join("tmp");
+    std::fs::create_dir_all(&scratch)?;
+    let layout = tempfile::Builder::new()
+        .prefix("bolt-p2p-")
+        .tempdir_in(&scratch)?;
+    write_layout(&store, &pulled.manifest, layout.path())?;
+    load(runtime, layout.path(), image).await?;
+    info!("✅ Image pulled successfully: {}", image);
+    Ok(())
+}
+
+/// An OCI layout holding one image, its blobs linked from the store
+fn write_layout(store: &Path, manifest: &Descriptor, dir: &Path) -> Result<()> {
+    let parsed: Manifest = serde_json::from_slice(&std::fs::read(registry::blob_path(
+        store,
+        &manifest.digest,
+    )?)?)?;
+    let blobs = dir.join("blobs").join("sha256");
+    std::fs::create_dir_all(&blobs)?;
+    for descriptor in std::iter::once(manifest)
+        .chain(parsed.config.as_ref())
+        .chain(&parsed.layers)
+    {
+        let source = registry::blob_path(store, &descriptor.digest)?;
+        let target = registry::blob_path(dir, &descriptor.digest)?;
+        if std::fs::hard_link(&source, &target).is_err() {
+            std::fs::copy(&source, &target)
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+        }
+    }
+    std::fs::write(
+        dir.join("oci-layout"),
+        serde_json::to_vec(&serde_json::json!({ "imageLayoutVersion": "1.0.0" }))?,
+    )?;
+    std::fs::write(
+        dir.join("index.json"),
+        serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": manifest.media_type,
+                "digest": manifest.digest,
+                "size": manifest.size,
+            }],
+        }))?,
+    )?;
+    Ok(())
+}
+
+/// Copy the image in a layout into the runtime's storage as `image`
+async fn load(runtime: &str, layout: &Path, image: &str) -> Result<()> {
+    let source = format!("oci:{}", layout.display());
+    if runtime == "podman" {
+        let id = run(AsyncCommand::new("podman").args(["pull", "-q", &source])).await?;
+        run(AsyncCommand::new("podman").args(["tag", id.trim(), image])).await?;
+    } else {
+        run(AsyncCommand::new("skopeo").args([
+            "copy",
+            &source,
+            &format!(
+                "docker-daemon:{}",
+                super::super::encryption::daemon_reference(image)?
+            ),
+        ]))
+        .await?;
+    }
+    Ok(())
+}
+
+async fn run(command: &mut AsyncCommand) -> Result<String> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let output = command
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Answer probes and serve the content store to the LAN until stopped. Run
+/// by `bolt image serve`.
+pub async fn serve(config: &BoltConfig) -> Result<()> {
+    let store = store_dir(&config.data_dir);
+    std::fs::create_dir_all(&store)?;
+    let networks = Arc::new(allowed_networks(&config.p2p)?);
+    let upload_limit = limit(&config.p2p.upload_limit)?;
+    let node = node_id();
+
+    let (cert, key) = identity(&p2p_dir(&config.data_dir))?;
+    let mut server_config = quinn::ServerConfig::with_single_cert(
+        vec![quinn_rustls::Certificate(cert)],
+        quinn_rustls::PrivateKey(key),
+    )?;
+    server_config.transport_config(transport_config()?);
+    let listen = SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.p2p.port));
+    let endpoint = quinn::Endpoint::server(server_config, listen)
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+
+    let socket = discovery_socket()?;
+    info!(
+        "🤝 Serving {} blobs to the LAN on port {}",
+        blob_count(&store),
+        config.p2p.port
+    );
+
+    let answer = answer_probes(&socket, &node, config.p2p.port, &store, &networks);
+    let accept = async {
+        // Each peer's downloads share one budget, however many streams
+        let throttles: Arc<Mutex<HashMap<IpAddr, Arc<Mutex<Throttle>>>>> = Default::default();
+        while let Some(connecting) = endpoint.accept().await {
+            let remote = connecting.remote_address();
+            if !allowed(&networks, remote.ip()) {
+                debug!("Refusing {}: not in [p2p] allow", remote);
+                tokio::spawn(async move {
+                    if let Ok(connection) = connecting.await {
+                        connection.close(1u32.into(), b"not allowed");
+                    }
+                });
+                continue;
+            }
+            let throttle = throttles
+                .lock()
+                .unwrap()
+                .entry(remote.ip())
+                .or_insert_with(|| Arc::new(Mutex::new(Throttle::new(upload_limit))))
+                .clone();
+            let store = store.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(connecting, &store, throttle).await {
+                    debug!("Serving {} ended: {}", remote, e);
+                }
+            });
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    tokio::try_join!(answer, accept)?;
+    Ok(())
+}
+
+/// The multicast socket probes arrive on
+fn discovery_socket() -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())
+        .with_context(|| format!("Failed to listen for probes on port {}", DISCOVERY_PORT))?;
+    socket.join_multicast_v4(&DISCOVERY_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+async fn answer_probes(
+    socket: &UdpSocket,
+    node: &str,
+    port: u16,
+    store: &Path,
+    networks: &[ipnet::IpNet],
+) -> Result<()> {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let (n, from) = socket.recv_from(&mut buf).await?;
+        if !allowed(networks, from.ip()) {
+            continue;
+        }
+        let Ok(Datagram::Probe {
+            node: prober,
+            digests,
+        }) = serde_json::from_slice(&buf[..n])
+        else {
+            continue;
+        };
+        if prober == node {
+            continue;
+        }
+        let have = Datagram::Have {
+            node: node.to_string(),
+            port,
+            digests: held(store, &digests),
+            blobs: blob_count(store),
+        };
+        if let Err(e) = socket.send_to(&serde_json::to_vec(&have)?, from).await {
+            debug!("Could not answer {}: {}", from, e);
+        }
+    }
+}
+
+async fn serve_connection(
+    connecting: quinn::Connecting,
+    store: &Path,
+    throttle: Arc<Mutex<Throttle>>,
+) -> Result<()> {
+    let connection = connecting.await?;
+    loop {
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let Message::Get { digest } = read_message(&mut recv).await? else {
+            return Err(anyhow!("expected a blob request"));
+        };
+        let path = registry::blob_path(store, &digest)?;
+        let Ok(mut file) = tokio::fs::File::open(&path).await else {
+            write_message(&mut send, &Message::Missing).await?;
+            send.finish().await?;
+            continue;
+        };
+        let size = file.metadata().await?.len();
+        write_message(&mut send, &Message::Blob { size }).await?;
+        debug!("Sending {} to {}", digest, connection.remote_address());
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = tokio::io::AsyncReadExt::read(&mut file, &mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            send.write_all(&buf[..n]).await?;
+            let delay = throttle.lock().unwrap().delay(n);
+            tokio::time::sleep(delay).await;
+        }
+        send.finish().await?;
+    }
+}
+
+/// Self-signed certificate, generated once
+fn identity(dir: &Path) -> Result<(Vec<u8>, Vec<u8>)> {
+    let cert_path = dir.join("server.crt.der");
+    let key_path = dir.join("server.key.der");
+    if cert_path.exists() && key_path.exists() {
+        return Ok((std::fs::read(&cert_path)?, std::fs::read(&key_path)?));
+    }
+    std::fs::create_dir_all(dir)?;
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+    let cert_der = cert.serialize_der()?;
+    let key_der = cert.serialize_private_key_der();
+    std::fs::write(&cert_path, &cert_der)?;
+    std::fs::write(&key_path, &key_der)?;
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))?;
+    Ok((cert_der, key_der))
+}
+
+/// Peers are anonymous: what they send is verified against its digest
+struct AnyCertificate;
+
+impl quinn_rustls::client::ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &quinn_rustls::Certificate,
+        _intermediates: &[quinn_rustls::Certificate],
+        _server_name: &quinn_rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<quinn_rustls::client::ServerCertVerified, quinn_rustls::Error> {
+        Ok(quinn_rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+fn transport_config() -> Result<Arc<quinn::TransportConfig>> {
+    let mut transport = quinn::TransportConfig::default();
+    transport.max_idle_timeout(Some(Duration::from_secs(60).try_into()?));
+    transport.keep_alive_interval(Some(Duration::from_secs(10)));
+    Ok(Arc::new(transport))
+}
+
+async fn write_message(send: &mut quinn::SendStream, message: &Message) -> Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    send.write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    send.write_all(&payload).await?;
+    Ok(())
+}
+
+async fn read_message(recv: &mut quinn::RecvStream) -> Result<Message> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(anyhow!("P2P control message too large ({} bytes)", len));
+    }
+    let mut payload = vec![0u8; len as usize];
+    recv.read_exact(&mut payload).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_per_peer_and_serves_only_allowed_networks() {
+        let mut unlimited = Throttle::new(None);
+        assert_eq!(unlimited.delay(10_000_000), Duration::ZERO);
+
+        // 1 MB/s: 3 MB right away means waiting close to 3s
+        let mut throttle = Throttle::new(Some(1_000_000));
+        throttle.delay(1_000_000);
+        throttle.delay(1_000_000);
+        let delay = throttle.delay(1_000_000);
+        assert!(delay > Duration::from_millis(2900) && delay <= Duration::from_secs(3));
+
+        // An idle budget isn't saved up
+        let mut idle = Throttle::new(Some(1_000_000));
+        idle.started -= Duration::from_secs(60);
+        assert!(idle.delay(1_000_000) > Duration::from_millis(900));
+
+        let defaults = allowed_networks(&P2pSettings::default()).unwrap();
+        assert!(allowed(&defaults, "192.168.1.20".parse().unwrap()));
+        assert!(allowed(&defaults, "::ffff:10.0.0.5".parse().unwrap()));
+        assert!(allowed(&defaults, "fe80::1".parse().unwrap()));
+        assert!(!allowed(&defaults, "203.0.113.7".parse().unwrap()));
+        let lab = P2pSettings {
+            allow: vec!["203.0.113.0/24".to_string()],
+            ..Default::default()
+        };
+        let lab = allowed_networks(&lab).unwrap();
+        assert!(allowed(&lab, "203.0.113.7".parse().unwrap()));
+        assert!(!allowed(&lab, "192.168.1.20".parse().unwrap()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let blob = format!("sha256:{:x}", Sha256::digest(b"layer"));
+        let path = registry::blob_path(dir.path(), &blob).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"layer").unwrap();
+        let missing = format!("sha256:{}", "0".repeat(64));
+        assert_eq!(
+            held(
+                dir.path(),
+                &[blob.clone(), missing, "sha256:../x".to_string()]
+            ),
+            [blob]
+        );
+        assert_eq!(blob_count(dir.path()), 1);
+    }
+}
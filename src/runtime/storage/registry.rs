@@ -1,329 +1,1676 @@
-use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
-use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, HeaderValue};
+// OCI Distribution client
+//
+// Pulls images straight from a registry, without podman or docker:
+//
+// 1. The manifest is requested with every manifest media type Bolt reads
+//    (OCI index and manifest, Docker schema2 list and manifest). A 401 carries
+//    a `WWW-Authenticate` challenge; a Bearer challenge is answered by fetching
+//    a token from its realm, which covers Docker Hub, GHCR, Quay and most
+//    self-hosted registries, and a Basic one with the login. Logins are the
+//    ones `podman login` and `docker login` save.
+// 2. An index picks the manifest for the wanted platform.
+// 3. The config and layers are downloaded into a content-addressed store,
+//    `<root>/content/blobs/sha256/<hex>`, each verified against its digest and
+//    size while it streams. Blobs already in the store aren't downloaded again,
+//    and a `BlobSource` (LAN peers, see `p2p`) is tried before the registry.
+// 4. When a flat rootfs is wanted, the layers are unpacked in order into it,
+//    applying whiteouts (`.wh.<name>` deletes a file of a lower layer,
+//    `.wh..wh..opq` empties a directory) so the result matches what the
+//    runtimes would mount. Containers of the native runtime instead mount the
+//    layers with overlayfs (see `overlay`).
+//
+// Pushing goes the other way: podman or docker exports the local image as an
+// OCI layout, whose `blobs/sha256/` has the same shape as the store. Blobs the
+// repository lacks are uploaded (monolithic up to CHUNK_SIZE, chunked above
+// it), then the manifest is PUT under each tag.
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use reqwest::StatusCode;
+use reqwest::header::{
+    ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE,
+};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use tar::Archive;
-use tokio::fs;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, warn};
 
-#[derive(Debug, Clone)]
-pub struct RegistryClient {
-    pub base_url: String,
-    client: reqwest::Client,
-    auth_token: Option<String>,
+use crate::error::BoltError;
+use crate::runtime::platform::{self, Platform, PlatformChoice};
+
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const DOCKER_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// Blobs above this are uploaded in chunks of this size
+const CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+pub(super) const WHITEOUT_PREFIX: &str = ".wh.";
+pub(super) const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// `[registry/]repository[:tag][@digest]`, normalized the way Docker does:
+/// no registry means Docker Hub, and single-name Hub images live in `library/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ImageManifest {
-    #[serde(rename = "schemaVersion")]
-    pub schema_version: u32,
-    pub media_type: Option<String>,
-    pub config: ConfigDescriptor,
-    pub layers: Vec<LayerDescriptor>,
+impl Reference {
+    pub fn parse(image: &str) -> Result<Self> {
+        let (rest, digest) = match image.split_once('@') {
+            Some((rest, digest)) => (rest, Some(digest.to_string())),
+            None => (image, None),
+        };
+        // A tag follows the last colon, unless that colon is a registry port
+        let (name, tag) = match rest.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, path))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), path.to_string())
+            }
+            _ => ("docker.io".to_string(), name.to_string()),
+        };
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        if repository.is_empty() || repository.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(anyhow!("Invalid image reference: {}", image));
+        }
+        let tag = match (&tag, &digest) {
+            (None, None) => Some("latest".to_string()),
+            _ => tag,
+        };
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// Base URL of the registry's API
+    pub fn endpoint(&self) -> String {
+        let host = match self.registry.as_str() {
+            "docker.io" | "index.docker.io" => "registry-1.docker.io",
+            other => other,
+        };
+        let plain_http = host.starts_with("localhost") || host.starts_with("127.0.0.1");
+        format!(
+            "{}://{}/v2/{}",
+            if plain_http { "http" } else { "https" },
+            host,
+            self.repository
+        )
+    }
+
+    /// The digest when pinned, the tag otherwise
+    pub fn manifest_reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ConfigDescriptor {
-    pub media_type: String,
-    pub size: u64,
-    pub digest: String,
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(ref tag) = self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(ref digest) = self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct LayerDescriptor {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
     pub media_type: String,
-    pub size: u64,
     pub digest: String,
-    pub urls: Option<Vec<String>>,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
 }
 
-#[derive(Debug, Deserialize)]
-struct AuthResponse {
-    token: String,
+/// An image manifest or an index of them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub manifests: Option<Vec<Descriptor>>,
+    #[serde(default)]
+    pub config: Option<Descriptor>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    #[serde(default)]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Deserialize)]
-struct DockerHubToken {
-    access_token: String,
+/// The parts of an image config a container is started from
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImageConfigFile {
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default)]
+    pub config: Option<RunConfig>,
 }
 
-impl RegistryClient {
-    pub fn new(base_url: String) -> Self {
-        let client = reqwest::Client::builder()
-            .user_agent("bolt-container-runtime/0.1.0")
-            .timeout(std::time::Duration::from_secs(300))
-            .build()
-            .unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RunConfig {
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub exposed_ports: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub volumes: Option<HashMap<String, serde_json::Value>>,
+}
 
-        Self {
-            base_url,
-            client,
-            auth_token: None,
+/// An image pulled into the store, and unpacked when asked to
+#[derive(Debug, Clone)]
+pub struct PulledImage {
+    pub reference: Reference,
+    /// Digest of the platform's image manifest
+    pub digest: String,
+    /// The platform's image manifest, kept in the store as a blob
+    pub manifest: Descriptor,
+    pub platform: Platform,
+    pub config: ImageConfigFile,
+    pub layers: Vec<Descriptor>,
+    /// Annotations of the index and of the platform's manifest, which wins
+    pub annotations: HashMap<String, String>,
+    pub rootfs: Option<PathBuf>,
+}
+
+impl PulledImage {
+    /// Compressed size of the layers
+    pub fn size(&self) -> u64 {
+        self.layers.iter().map(|l| l.size).sum()
+    }
+}
+
+/// A `WWW-Authenticate` challenge, e.g.
+/// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub params: HashMap<String, String>,
+}
+
+impl Challenge {
+    pub fn parse(header: &str) -> Option<Self> {
+        let (scheme, rest) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+        let mut params = HashMap::new();
+        let mut chars = rest.chars().peekable();
+        loop {
+            while chars.peek().is_some_and(|c| *c == ',' || c.is_whitespace()) {
+                chars.next();
+            }
+            let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+            if key.is_empty() {
+                break;
+            }
+            let mut value = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value = chars.by_ref().take_while(|c| *c != ',').collect();
+            }
+            params.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
         }
+        (!scheme.is_empty()).then(|| Self {
+            scheme: scheme.to_ascii_lowercase(),
+            params,
+        })
     }
+}
 
-    pub async fn pull_image(&mut self, image_ref: &str) -> Result<PathBuf> {
-        info!("📥 Pulling image from registry: {}", image_ref);
+/// Registry login saved by `podman login` or `docker login`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
 
-        // Parse image reference
-        let (registry, namespace, image, tag) = self.parse_image_reference(image_ref)?;
+impl Credentials {
+    /// The login for `registry` in the first auth file that has one:
+    /// `$REGISTRY_AUTH_FILE`, podman's auth.json, then docker's config.json
+    pub fn lookup(registry: &str) -> Option<Self> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        files.extend(std::env::var_os("REGISTRY_AUTH_FILE").map(PathBuf::from));
+        files.extend(
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(|dir| PathBuf::from(dir).join("containers/auth.json")),
+        );
+        files.extend(dirs::config_dir().map(|dir| dir.join("containers/auth.json")));
+        files.extend(dirs::home_dir().map(|dir| dir.join(".docker/config.json")));
+        files
+            .iter()
+            .filter_map(|file| std::fs::read_to_string(file).ok())
+            .find_map(|json| Self::from_auth_file(&json, registry))
+    }
 
-        // Authenticate with registry
-        self.authenticate(&registry, &namespace, &image).await?;
+    /// The login for `registry` in an auth.json/config.json document
+    pub fn from_auth_file(json: &str, registry: &str) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct AuthFile {
+            #[serde(default)]
+            auths: HashMap<String, AuthEntry>,
+        }
+        #[derive(Deserialize)]
+        struct AuthEntry {
+            #[serde(default)]
+            auth: Option<String>,
+        }
 
-        // Fetch manifest
-        let manifest = self
-            .fetch_manifest(&registry, &namespace, &image, &tag)
-            .await?;
+        let file: AuthFile = serde_json::from_str(json).ok()?;
+        let hub = matches!(
+            registry,
+            "docker.io" | "index.docker.io" | "registry-1.docker.io"
+        );
+        let (_, entry) = file.auths.iter().find(|(key, _)| {
+            let host = key
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .split('/')
+                .next()
+                .unwrap_or_default();
+            host == registry
+                || (hub
+                    && matches!(
+                        host,
+                        "docker.io" | "index.docker.io" | "registry-1.docker.io"
+                    ))
+        })?;
+        let decoded = crate::runtime::ssh::base64_decode(entry.auth.as_deref()?)?;
+        let (username, password) = String::from_utf8(decoded)
+            .ok()?
+            .split_once(':')
+            .map(|(u, p)| (u.to_string(), p.to_string()))?;
+        Some(Self { username, password })
+    }
+}
 
-        // Create local storage directory
-        let storage_dir = PathBuf::from("/var/lib/bolt/images");
-        fs::create_dir_all(&storage_dir)
-            .await
-            .context("Failed to create image storage directory")?;
+enum Auth {
+    Bearer(String),
+    Basic(Credentials),
+}
 
-        let image_dir = storage_dir.join(format!("{}-{}-{}", namespace, image, tag));
-        fs::create_dir_all(&image_dir)
-            .await
-            .context("Failed to create image directory")?;
+/// Somewhere other than the registry blobs can come from, like other Bolt
+/// nodes on the LAN (see `p2p`)
+#[async_trait::async_trait]
+pub trait BlobSource: Send + Sync {
+    /// Called with the blobs of an image before they are downloaded
+    async fn prepare(&mut self, blobs: &[Descriptor]);
 
-        // Download config
-        info!("📄 Downloading image config");
-        let config_data = self
-            .fetch_blob(&registry, &namespace, &image, &manifest.config.digest)
-            .await?;
-        let config_path = image_dir.join("config.json");
-        fs::write(&config_path, &config_data)
-            .await
-            .context("Failed to write config")?;
+    /// Download a blob into `partial`. Returns true only once it's there and
+    /// verified against the descriptor's digest and size; false sends the
+    /// client to the registry.
+    async fn fetch(&mut self, descriptor: &Descriptor, partial: &Path) -> bool;
+}
 
-        // Download and extract layers
-        info!("📦 Downloading {} layers", manifest.layers.len());
-        for (idx, layer) in manifest.layers.iter().enumerate() {
-            info!(
-                "  Layer {}/{}: {} ({}MB)",
-                idx + 1,
-                manifest.layers.len(),
-                layer.digest,
-                layer.size / 1_000_000
-            );
+/// Client for one repository of a registry
+pub struct RegistryClient {
+    client: reqwest::Client,
+    reference: Reference,
+    credentials: Option<Credentials>,
+    auth: Option<Auth>,
+    source: Option<Box<dyn BlobSource>>,
+}
 
-            let layer_data = self
-                .fetch_blob(&registry, &namespace, &image, &layer.digest)
-                .await?;
+impl RegistryClient {
+    /// A client using the saved login for the registry, if any
+    pub fn new(reference: Reference) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("bolt/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(std::time::Duration::from_secs(30))
+            .build()?;
+        let credentials = Credentials::lookup(&reference.registry);
+        Ok(Self {
+            client,
+            reference,
+            credentials,
+            auth: None,
+            source: None,
+        })
+    }
 
-            // Save layer tarball
-            let layer_path = image_dir.join(format!("layer_{}.tar.gz", idx));
-            fs::write(&layer_path, &layer_data)
-                .await
-                .context("Failed to write layer")?;
+    /// Try `source` for each blob before the registry
+    pub fn with_source(mut self, source: Box<dyn BlobSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
 
-            // Extract layer to rootfs
-            let rootfs_dir = image_dir.join("rootfs");
-            fs::create_dir_all(&rootfs_dir).await?;
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.auth {
+            Some(Auth::Bearer(ref token)) => {
+                request.header(AUTHORIZATION, format!("Bearer {}", token))
+            }
+            Some(Auth::Basic(ref creds)) => {
+                request.basic_auth(&creds.username, Some(&creds.password))
+            }
+            None => request,
+        }
+    }
 
-            self.extract_layer(&layer_path, &rootfs_dir).await?;
+    /// `<endpoint>/<path>`, or a `Location` the registry handed out, which
+    /// may be relative to the host
+    fn url(&self, path_or_location: &str) -> String {
+        if path_or_location.starts_with("http://") || path_or_location.starts_with("https://") {
+            return path_or_location.to_string();
+        }
+        let endpoint = self.reference.endpoint();
+        if path_or_location.starts_with('/') {
+            let host_end = endpoint.find("/v2/").unwrap_or(endpoint.len());
+            return format!("{}{}", &endpoint[..host_end], path_or_location);
         }
+        format!("{}/{}", endpoint, path_or_location)
+    }
 
-        info!("✅ Image pulled successfully: {}", image_ref);
-        Ok(image_dir)
+    /// Send a request with a replayable body, answering an auth challenge once
+    async fn send(
+        &mut self,
+        method: reqwest::Method,
+        url: &str,
+        headers: &[(reqwest::header::HeaderName, String)],
+        body: Option<bytes::Bytes>,
+    ) -> Result<reqwest::Response> {
+        for attempt in 0..2 {
+            let mut request = self.authorize(self.client.request(method.clone(), url));
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            if let Some(ref body) = body {
+                request = request.body(body.clone());
+            }
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {}", self.reference.registry))?;
+            if response.status() != StatusCode::UNAUTHORIZED || attempt > 0 {
+                return Ok(response);
+            }
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(Challenge::parse)
+                .ok_or_else(|| anyhow!("{} refused access without a challenge", url))?;
+            self.authenticate(&challenge).await?;
+        }
+        unreachable!("the second attempt always returns")
     }
 
-    async fn authenticate(&mut self, registry: &str, namespace: &str, image: &str) -> Result<()> {
-        info!("🔐 Authenticating with registry: {}", registry);
+    /// GET a registry path, answering an auth challenge once
+    async fn get(&mut self, path: &str, accept: &str) -> Result<reqwest::Response> {
+        let url = self.url(path);
+        self.send(
+            reqwest::Method::GET,
+            &url,
+            &[(ACCEPT, accept.to_string())],
+            None,
+        )
+        .await
+    }
 
-        match registry {
-            "docker.io" | "registry-1.docker.io" => {
-                // Docker Hub authentication
-                let auth_url = format!(
-                    "https://auth.docker.io/token?service=registry.docker.io&scope=repository:{}/{}:pull",
-                    namespace, image
-                );
+    /// Answer a challenge: Basic with the saved login, or a Bearer token from
+    /// the challenge's realm, anonymous without a login
+    async fn authenticate(&mut self, challenge: &Challenge) -> Result<()> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            #[serde(default)]
+            token: Option<String>,
+            #[serde(default)]
+            access_token: Option<String>,
+        }
 
-                let response = self
-                    .client
-                    .get(&auth_url)
-                    .send()
-                    .await
-                    .context("Failed to authenticate with Docker Hub")?;
-
-                if response.status().is_success() {
-                    let auth: AuthResponse = response
-                        .json()
-                        .await
-                        .context("Failed to parse auth response")?;
-                    self.auth_token = Some(format!("Bearer {}", auth.token));
-                    info!("✅ Authenticated with Docker Hub");
+        if challenge.scheme == "basic" {
+            let credentials = self.credentials.clone().ok_or_else(|| {
+                anyhow!(
+                    "{} needs a login; run `podman login {}` or `docker login {}`",
+                    self.reference.registry,
+                    self.reference.registry,
+                    self.reference.registry
+                )
+            })?;
+            self.auth = Some(Auth::Basic(credentials));
+            return Ok(());
+        }
+        if challenge.scheme != "bearer" {
+            return Err(anyhow!(
+                "{} asks for unsupported {} authentication",
+                self.reference.registry,
+                challenge.scheme
+            ));
+        }
+        let realm = challenge.params.get("realm").ok_or_else(|| {
+            anyhow!(
+                "Auth challenge from {} has no realm",
+                self.reference.registry
+            )
+        })?;
+        let scope = challenge
+            .params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.reference.repository));
+        let mut query = vec![("scope", scope.clone())];
+        if let Some(service) = challenge.params.get("service") {
+            query.push(("service", service.clone()));
+        }
+        debug!("Requesting a token for {} from {}", scope, realm);
+        let mut request = self.client.get(realm).query(&query);
+        if let Some(ref credentials) = self.credentials {
+            request = request.basic_auth(&credentials.username, Some(&credentials.password));
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "{} refused a token for {}: HTTP {}{}",
+                realm,
+                scope,
+                response.status(),
+                if self.credentials.is_none() {
+                    format!("; log in with `podman login {}`", self.reference.registry)
                 } else {
-                    warn!("⚠️  Authentication failed, trying anonymous access");
+                    String::new()
                 }
-            }
-            _ => {
-                // Generic registry - try anonymous first
-                info!("Using anonymous access for registry: {}", registry);
-            }
+            ));
         }
-
+        let body: TokenResponse = response.json().await.context("Invalid token response")?;
+        self.auth = Some(Auth::Bearer(
+            body.token
+                .or(body.access_token)
+                .ok_or_else(|| anyhow!("{} returned no token", realm))?,
+        ));
         Ok(())
     }
 
-    async fn fetch_manifest(
-        &self,
-        registry: &str,
-        namespace: &str,
-        image: &str,
-        tag: &str,
-    ) -> Result<ImageManifest> {
-        info!("📋 Fetching manifest for {}:{}", image, tag);
-
-        let manifest_url = format!(
-            "https://{}/v2/{}/{}/manifests/{}",
-            registry, namespace, image, tag
-        );
-
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static("application/vnd.docker.distribution.manifest.v2+json"),
-        );
+    /// A manifest or index by tag or digest, with its digest
+    pub async fn manifest(&mut self, reference: &str) -> Result<(Manifest, String)> {
+        let (manifest, digest, _) = self.manifest_bytes(reference).await?;
+        Ok((manifest, digest))
+    }
 
-        if let Some(token) = &self.auth_token {
-            headers.insert(
-                AUTHORIZATION,
-                HeaderValue::from_str(token).context("Invalid auth token")?,
+    /// A manifest or index with its digest and content as served
+    async fn manifest_bytes(&mut self, reference: &str) -> Result<(Manifest, String, Vec<u8>)> {
+        let accept = [OCI_INDEX, DOCKER_LIST, OCI_MANIFEST, DOCKER_MANIFEST].join(", ");
+        let response = self
+            .get(&format!("manifests/{}", reference), &accept)
+            .await?;
+        if !response.status().is_success() {
+            return Err(
+                BoltError::Runtime(crate::error::RuntimeError::ImagePullFailed {
+                    image: format!(
+                        "{}: manifest {} returned HTTP {}",
+                        self.reference,
+                        reference,
+                        response.status()
+                    ),
+                })
+                .into(),
             );
         }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let bytes = response.bytes().await?;
+        let digest = format!("sha256:{:x}", Sha256::digest(&bytes));
+        if reference.starts_with("sha256:") && reference != digest {
+            return Err(anyhow!(
+                "Manifest {} of {} has digest {}",
+                reference,
+                self.reference,
+                digest
+            ));
+        }
+        let mut manifest: Manifest =
+            serde_json::from_slice(&bytes).context("Failed to parse manifest")?;
+        if manifest.media_type.is_none() {
+            manifest.media_type = content_type;
+        }
+        Ok((manifest, digest, bytes.to_vec()))
+    }
 
-        let response = self
-            .client
-            .get(&manifest_url)
-            .headers(headers)
-            .send()
-            .await
-            .context("Failed to fetch manifest")?;
+    /// Download a blob into the store unless it's already there, verifying
+    /// its digest and size
+    pub async fn blob(&mut self, descriptor: &Descriptor, store: &Path) -> Result<PathBuf> {
+        let path = blob_path(store, &descriptor.digest)?;
+        if std::fs::metadata(&path).is_ok_and(|m| m.len() == descriptor.size) {
+            debug!("Blob {} already in the store", descriptor.digest);
+            return Ok(path);
+        }
+        std::fs::create_dir_all(path.parent().unwrap_or(store))?;
+        let partial = path.with_extension("partial");
+        if let Some(source) = self.source.as_mut()
+            && source.fetch(descriptor, &partial).await
+        {
+            std::fs::rename(&partial, &path)?;
+            return Ok(path);
+        }
 
+        let mut response = self
+            .get(&format!("blobs/{}", descriptor.digest), "*/*")
+            .await?;
         if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch manifest: HTTP {}",
+            return Err(anyhow!(
+                "Blob {} of {} returned HTTP {}",
+                descriptor.digest,
+                self.reference,
                 response.status()
             ));
         }
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut hasher = Sha256::new();
+        let mut written = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            written += chunk.len() as u64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
 
-        let manifest: ImageManifest = response.json().await.context("Failed to parse manifest")?;
+        let digest = format!("sha256:{:x}", hasher.finalize());
+        if digest != descriptor.digest || written != descriptor.size {
+            let _ = std::fs::remove_file(&partial);
+            return Err(anyhow!(
+                "Blob {} of {} failed verification: got {} ({} bytes, expected {})",
+                descriptor.digest,
+                self.reference,
+                digest,
+                written,
+                descriptor.size
+            ));
+        }
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
 
-        debug!(
-            "Manifest: {} layers, config: {}",
-            manifest.layers.len(),
-            manifest.config.digest
+    /// Pull the image for `wanted` into `store`, and unpack it into `rootfs`
+    /// when there is one
+    pub async fn pull(
+        &mut self,
+        wanted: &Platform,
+        store: &Path,
+        rootfs: Option<&Path>,
+    ) -> Result<PulledImage> {
+        info!(
+            "📥 Pulling {} from {}",
+            self.reference, self.reference.registry
         );
+        let reference = self.reference.manifest_reference().to_string();
+        let (mut manifest, mut digest, mut bytes) = self.manifest_bytes(&reference).await?;
+        let mut annotations = HashMap::new();
+        if let Some(ref entries) = manifest.manifests {
+            annotations.extend(manifest.annotations.clone().unwrap_or_default());
+            let entry = select_manifest(&self.reference.to_string(), entries, wanted)?;
+            debug!("Using the {} manifest {}", wanted, entry.digest);
+            let entry_digest = entry.digest.clone();
+            (manifest, digest, bytes) = self.manifest_bytes(&entry_digest).await?;
+        }
+        let config_descriptor = manifest.config.clone().ok_or_else(|| {
+            anyhow!(
+                "{} has an unsupported manifest ({})",
+                self.reference,
+                manifest
+                    .media_type
+                    .as_deref()
+                    .unwrap_or("unknown media type")
+            )
+        })?;
+        annotations.extend(manifest.annotations.clone().unwrap_or_default());
 
-        Ok(manifest)
-    }
+        // Kept so the image can be handed to podman or docker as a layout
+        let manifest_path = blob_path(store, &digest)?;
+        std::fs::create_dir_all(manifest_path.parent().unwrap_or(store))?;
+        std::fs::write(&manifest_path, &bytes)?;
+        let manifest_descriptor = Descriptor {
+            media_type: manifest
+                .media_type
+                .clone()
+                .unwrap_or_else(|| OCI_MANIFEST.to_string()),
+            digest: digest.clone(),
+            size: bytes.len() as u64,
+            platform: None,
+        };
 
-    async fn fetch_blob(
-        &self,
-        registry: &str,
-        namespace: &str,
-        image: &str,
-        digest: &str,
-    ) -> Result<Vec<u8>> {
-        debug!("⬇️  Fetching blob: {}", digest);
+        if let Some(source) = self.source.as_mut() {
+            let blobs: Vec<Descriptor> = std::iter::once(config_descriptor.clone())
+                .chain(manifest.layers.iter().cloned())
+                .collect();
+            source.prepare(&blobs).await;
+        }
 
-        let blob_url = format!(
-            "https://{}/v2/{}/{}/blobs/{}",
-            registry, namespace, image, digest
-        );
+        let config_path = self.blob(&config_descriptor, store).await?;
+        let config: ImageConfigFile = serde_json::from_slice(&std::fs::read(&config_path)?)
+            .context("Failed to parse image config")?;
 
-        let mut headers = HeaderMap::new();
-        if let Some(token) = &self.auth_token {
-            headers.insert(
-                AUTHORIZATION,
-                HeaderValue::from_str(token).context("Invalid auth token")?,
+        let total = manifest.layers.len();
+        let mut layer_paths = Vec::with_capacity(total);
+        for (i, layer) in manifest.layers.iter().enumerate() {
+            info!(
+                "  ⬇️  Layer {}/{}: {} ({:.1} MB)",
+                i + 1,
+                total,
+                short_digest(&layer.digest),
+                layer.size as f64 / 1_000_000.0
             );
+            layer_paths.push(self.blob(layer, store).await?);
         }
 
+        if let Some(rootfs) = rootfs {
+            // ocicrypt layers are only decrypted by podman or skopeo
+            if manifest.layers.iter().any(|l| is_encrypted(&l.media_type)) {
+                return Err(BoltError::Runtime(
+                    crate::error::RuntimeError::ImageDecryptionFailed {
+                        image: self.reference.to_string(),
+                    },
+                )
+                .into());
+            }
+            info!("📂 Unpacking {} layers into {}", total, rootfs.display());
+            if rootfs.exists() {
+                std::fs::remove_dir_all(rootfs)?;
+            }
+            std::fs::create_dir_all(rootfs)?;
+            for (layer, path) in manifest.layers.iter().zip(&layer_paths) {
+                unpack_layer(path, &layer.media_type, rootfs)
+                    .with_context(|| format!("Failed to unpack layer {}", layer.digest))?;
+            }
+        }
+
+        Ok(PulledImage {
+            reference: self.reference.clone(),
+            digest,
+            manifest: manifest_descriptor,
+            platform: Platform::new(&config.os, &config.architecture, None),
+            config,
+            layers: manifest.layers,
+            annotations,
+            rootfs: rootfs.map(Path::to_path_buf),
+        })
+    }
+
+    /// Whether the repository already has a blob
+    pub async fn has_blob(&mut self, digest: &str) -> Result<bool> {
+        let url = self.url(&format!("blobs/{}", digest));
+        let response = self.send(reqwest::Method::HEAD, &url, &[], None).await?;
+        Ok(response.status().is_success())
+    }
+
+    /// Start an upload session and return its location
+    async fn start_upload(&mut self) -> Result<String> {
+        let url = self.url("blobs/uploads/");
         let response = self
-            .client
-            .get(&blob_url)
-            .headers(headers)
-            .send()
+            .send(reqwest::Method::POST, &url, &[], Some(bytes::Bytes::new()))
+            .await?;
+        if response.status() != StatusCode::ACCEPTED {
+            return Err(anyhow!(
+                "{} refused an upload to {}: HTTP {}",
+                self.reference.registry,
+                self.reference.repository,
+                response.status()
+            ));
+        }
+        location(&response)
+    }
+
+    /// Upload a blob from the store unless the repository has it: in one
+    /// request up to `CHUNK_SIZE`, in chunks above it
+    pub async fn push_blob(&mut self, descriptor: &Descriptor, store: &Path) -> Result<()> {
+        if self.has_blob(&descriptor.digest).await? {
+            debug!(
+                "Blob {} already in {}",
+                descriptor.digest, self.reference.repository
+            );
+            return Ok(());
+        }
+        let path = blob_path(store, &descriptor.digest)?;
+        let size = std::fs::metadata(&path)
+            .with_context(|| {
+                format!(
+                    "Blob {} is missing from {}",
+                    descriptor.digest,
+                    store.display()
+                )
+            })?
+            .len();
+        let mut upload = self.start_upload().await?;
+
+        if size > CHUNK_SIZE {
+            match self.upload_chunks(&upload, &path).await {
+                Ok(location) => {
+                    return self
+                        .finish_upload(&location, &descriptor.digest, None)
+                        .await;
+                }
+                // Some registries only take monolithic uploads
+                Err(e) => {
+                    warn!(
+                        "⚠️  Chunked upload refused ({}), uploading in one request",
+                        e
+                    );
+                    upload = self.start_upload().await?;
+                }
+            }
+        }
+        let file = tokio::fs::File::open(&path).await?;
+        self.finish_upload(&upload, &descriptor.digest, Some((file, size)))
             .await
-            .context("Failed to fetch blob")?;
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch blob: HTTP {}",
+    /// PATCH the blob in `CHUNK_SIZE` pieces; returns the final location
+    async fn upload_chunks(&self, upload: &str, path: &Path) -> Result<String> {
+        use tokio::io::AsyncReadExt;
+
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut location = upload.to_string();
+        let mut offset = 0u64;
+        let mut buffer = vec![0u8; CHUNK_SIZE as usize];
+        loop {
+            let mut filled = 0;
+            while filled < buffer.len() {
+                let read = file.read(&mut buffer[filled..]).await?;
+                if read == 0 {
+                    break;
+                }
+                filled += read;
+            }
+            if filled == 0 {
+                return Ok(location);
+            }
+            let response = self
+                .authorize(self.client.patch(self.url(&location)))
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header(CONTENT_LENGTH, filled)
+                .header(
+                    CONTENT_RANGE,
+                    format!("{}-{}", offset, offset + filled as u64 - 1),
+                )
+                .body(buffer[..filled].to_vec())
+                .send()
+                .await?;
+            if response.status() != StatusCode::ACCEPTED {
+                return Err(anyhow!("HTTP {}", response.status()));
+            }
+            location = self::location(&response)?;
+            offset += filled as u64;
+        }
+    }
+
+    /// Close an upload with the blob's digest, sending `body` (the whole blob)
+    /// when it wasn't uploaded in chunks
+    async fn finish_upload(
+        &self,
+        location: &str,
+        digest: &str,
+        body: Option<(tokio::fs::File, u64)>,
+    ) -> Result<()> {
+        let url = self.url(location);
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let mut request = self
+            .authorize(
+                self.client
+                    .put(format!("{}{}digest={}", url, separator, digest)),
+            )
+            .header(CONTENT_TYPE, "application/octet-stream");
+        request = match body {
+            Some((file, size)) => request.header(CONTENT_LENGTH, size).body(file),
+            None => request.header(CONTENT_LENGTH, 0),
+        };
+        let response = request.send().await?;
+        if response.status() != StatusCode::CREATED {
+            return Err(anyhow!(
+                "{} rejected blob {}: HTTP {}",
+                self.reference.registry,
+                digest,
                 response.status()
             ));
         }
+        Ok(())
+    }
 
-        let data = response.bytes().await.context("Failed to read blob data")?;
+    /// PUT manifest bytes under a tag or their digest
+    pub async fn push_manifest(
+        &mut self,
+        reference: &str,
+        media_type: &str,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let digest = format!("sha256:{:x}", Sha256::digest(&bytes));
+        let url = self.url(&format!("manifests/{}", reference));
+        let response = self
+            .send(
+                reqwest::Method::PUT,
+                &url,
+                &[(CONTENT_TYPE, media_type.to_string())],
+                Some(bytes.into()),
+            )
+            .await?;
+        if response.status() != StatusCode::CREATED {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "{} rejected the manifest for {}: HTTP {} {}",
+                self.reference.registry,
+                reference,
+                status,
+                detail.trim()
+            ));
+        }
+        if let Some(returned) = response
+            .headers()
+            .get("Docker-Content-Digest")
+            .and_then(|v| v.to_str().ok())
+            && returned != digest
+        {
+            return Err(anyhow!(
+                "{} stored manifest {} as {}",
+                self.reference.registry,
+                digest,
+                returned
+            ));
+        }
+        Ok(())
+    }
 
-        Ok(data.to_vec())
+    /// Push a manifest from an OCI layout with everything it references,
+    /// children of an index first, under `reference`
+    async fn push_tree(
+        &mut self,
+        descriptor: &Descriptor,
+        layout: &Path,
+        reference: &str,
+    ) -> Result<()> {
+        let bytes = std::fs::read(blob_path(layout, &descriptor.digest)?)
+            .with_context(|| format!("Manifest {} is missing from the image", descriptor.digest))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&bytes).context("Failed to parse manifest")?;
+        if let Some(ref children) = manifest.manifests {
+            for child in children {
+                Box::pin(self.push_tree(child, layout, &child.digest)).await?;
+            }
+        } else {
+            let config = manifest
+                .config
+                .as_ref()
+                .ok_or_else(|| anyhow!("Manifest {} has no config", descriptor.digest))?;
+            self.push_blob(config, layout).await?;
+            let total = manifest.layers.len();
+            for (i, layer) in manifest.layers.iter().enumerate() {
+                info!(
+                    "  ⬆️  Layer {}/{}: {} ({:.1} MB)",
+                    i + 1,
+                    total,
+                    short_digest(&layer.digest),
+                    layer.size as f64 / 1_000_000.0
+                );
+                self.push_blob(layer, layout).await?;
+            }
+        }
+        self.push_manifest(reference, &descriptor.media_type, bytes)
+            .await
     }
 
-    async fn extract_layer(&self, layer_path: &Path, rootfs_dir: &Path) -> Result<()> {
-        debug!("📂 Extracting layer to rootfs");
+    /// Push the image in an OCI layout directory under the reference's tag
+    /// and `extra_tags`; returns the manifest digest
+    pub async fn push_layout(&mut self, layout: &Path, extra_tags: &[String]) -> Result<String> {
+        let index: Manifest = serde_json::from_slice(
+            &std::fs::read(layout.join("index.json"))
+                .context("The image export has no index.json")?,
+        )?;
+        let top = index
+            .manifests
+            .as_deref()
+            .and_then(|m| m.first())
+            .ok_or_else(|| anyhow!("The image export lists no manifest"))?
+            .clone();
+        let tag = self
+            .reference
+            .tag
+            .clone()
+            .ok_or_else(|| anyhow!("Push to a tag, not a digest: {}", self.reference))?;
+
+        info!(
+            "📤 Pushing {} to {}",
+            self.reference, self.reference.registry
+        );
+        self.push_tree(&top, layout, &tag).await?;
+        let bytes = std::fs::read(blob_path(layout, &top.digest)?)?;
+        for extra in extra_tags {
+            info!("🏷️  Tagging {} as {}", short_digest(&top.digest), extra);
+            self.push_manifest(extra, &top.media_type, bytes.clone())
+                .await?;
+        }
+        Ok(top.digest)
+    }
+}
 
-        // Read the compressed layer
-        let layer_file = std::fs::File::open(layer_path).context("Failed to open layer file")?;
+/// The entry of an index for `wanted`, or the same platform mismatch error a
+/// runtime pull gives
+pub fn select_manifest<'a>(
+    image: &str,
+    entries: &'a [Descriptor],
+    wanted: &Platform,
+) -> Result<&'a Descriptor> {
+    if let Some(entry) = entries
+        .iter()
+        .find(|e| e.platform.as_ref().is_some_and(|p| p.serves(wanted)))
+    {
+        return Ok(entry);
+    }
+    let found: Vec<Platform> = entries
+        .iter()
+        .filter_map(|e| e.platform.clone())
+        .filter(|p| p.os != "unknown")
+        .collect();
+    Err(BoltError::Runtime(platform::mismatch(
+        image,
+        &found,
+        wanted,
+        &PlatformChoice::default(),
+        &Platform::emulated,
+    ))
+    .into())
+}
 
-        // Decompress and extract
-        let gz_decoder = GzDecoder::new(layer_file);
-        let mut archive = Archive::new(gz_decoder);
+/// Export a local podman or docker image as an OCI layout under `dir`;
+/// returns the layout's path
+pub async fn export_layout(runtime: &str, image: &str, dir: &Path) -> Result<PathBuf> {
+    let layout = dir.join("layout");
+    let output = if runtime == "podman" {
+        tokio::process::Command::new(runtime)
+            .args(["save", "--format", "oci-dir", "-o"])
+            .arg(&layout)
+            .arg(image)
+            .output()
+            .await?
+    } else {
+        tokio::process::Command::new(runtime)
+            .args(["save", "-o"])
+            .arg(dir.join("image.tar"))
+            .arg(image)
+            .output()
+            .await?
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to export {}: {}",
+            image,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    if runtime != "podman" {
+        let archive = dir.join("image.tar");
+        tar::Archive::new(std::fs::File::open(&archive)?).unpack(&layout)?;
+        std::fs::remove_file(&archive)?;
+    }
+    if !layout.join("index.json").exists() {
+        return Err(anyhow!(
+            "{} exported {} without an OCI layout; Docker 25 or newer is needed",
+            runtime,
+            image
+        ));
+    }
+    Ok(layout)
+}
 
-        // Extract to rootfs
-        archive
-            .unpack(rootfs_dir)
-            .context("Failed to extract layer")?;
+/// Add `annotations` to the top manifest of an OCI layout. The rewritten
+/// manifest is stored as a new blob and `index.json` pointed at it.
+pub fn annotate_layout(
+    layout: &Path,
+    annotations: &std::collections::BTreeMap<String, String>,
+) -> Result<()> {
+    if annotations.is_empty() {
+        return Ok(());
+    }
+    let index_path = layout.join("index.json");
+    let mut index: serde_json::Value = serde_json::from_slice(&std::fs::read(&index_path)?)?;
+    let top = index
+        .pointer_mut("/manifests/0")
+        .ok_or_else(|| anyhow!("The image export lists no manifest"))?;
+    let digest = top
+        .get("digest")
+        .and_then(|d| d.as_str())
+        .ok_or_else(|| anyhow!("The image export's manifest has no digest"))?;
 
-        Ok(())
+    let mut manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(blob_path(layout, digest)?)?)?;
+    let existing = manifest
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid manifest {}", digest))?
+        .entry("annotations")
+        .or_insert_with(|| serde_json::json!({}));
+    let existing = existing
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid annotations in manifest {}", digest))?;
+    for (key, value) in annotations {
+        existing.insert(key.clone(), value.clone().into());
     }
 
-    fn parse_image_reference(&self, image_ref: &str) -> Result<(String, String, String, String)> {
-        // Parse image reference: [registry/]namespace/image[:tag]
-        let parts: Vec<&str> = image_ref.split('/').collect();
+    let bytes = serde_json::to_vec(&manifest)?;
+    let digest = format!("sha256:{:x}", Sha256::digest(&bytes));
+    std::fs::write(blob_path(layout, &digest)?, &bytes)?;
+    top["digest"] = digest.into();
+    top["size"] = bytes.len().into();
+    std::fs::write(&index_path, serde_json::to_vec(&index)?)?;
+    Ok(())
+}
+
+/// The `Location` of an upload session
+fn location(response: &reqwest::Response) -> Result<String> {
+    response
+        .headers()
+        .get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("The registry gave no upload location"))
+}
+
+/// Where a blob lives in the content store
+pub fn blob_path(store: &Path, digest: &str) -> Result<PathBuf> {
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|h| h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("Unsupported digest: {}", digest))?;
+    Ok(store.join("blobs").join("sha256").join(hex))
+}
+
+/// What checking a content store found
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreCheck {
+    /// Blobs that matched their digest
+    pub blobs: usize,
+    pub bytes: u64,
+    /// Blobs that didn't and were removed, to be downloaded again
+    pub corrupt: Vec<String>,
+    /// Abandoned partial downloads that were removed
+    pub partial: usize,
+}
+
+/// Partial downloads younger than this may belong to a pull in progress
+const PARTIAL_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(3600);
 
-        let (registry, namespace, image_with_tag) = match parts.len() {
-            1 => {
-                // Just image name (e.g., "nginx")
-                ("registry-1.docker.io", "library", parts[0])
+/// Verify every blob in a content store against its digest, removing the
+/// ones that don't match and abandoned partial downloads
+pub fn fsck(store: &Path) -> Result<StoreCheck> {
+    let mut check = StoreCheck::default();
+    let dir = store.join("blobs").join("sha256");
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(check),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "partial") {
+            let abandoned = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > PARTIAL_MAX_AGE);
+            if abandoned && std::fs::remove_file(&path).is_ok() {
+                check.partial += 1;
             }
-            2 => {
-                // namespace/image (e.g., "myuser/myimage")
-                ("registry-1.docker.io", parts[0], parts[1])
+            continue;
+        }
+        let Some(hex) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let digest = format!("sha256:{}", hex);
+        if blob_path(store, &digest).is_err() {
+            continue;
+        }
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        let bytes = std::io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if format!("{:x}", hasher.finalize()) == hex {
+            check.blobs += 1;
+            check.bytes += bytes;
+        } else {
+            warn!("🩹 Blob {} does not match its digest, removing it", digest);
+            std::fs::remove_file(&path)?;
+            check.corrupt.push(digest);
+        }
+    }
+    check.corrupt.sort();
+    Ok(check)
+}
+
+fn short_digest(digest: &str) -> &str {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    &hex[..hex.len().min(12)]
+}
+
+/// An ocicrypt layer, e.g. `...tar+gzip+encrypted`
+fn is_encrypted(media_type: &str) -> bool {
+    media_type.ends_with("+encrypted")
+}
+
+pub(super) fn layer_reader(path: &Path, media_type: &str) -> Result<Box<dyn Read>> {
+    if is_encrypted(media_type) {
+        return Err(
+            BoltError::Runtime(crate::error::RuntimeError::ImageDecryptionFailed {
+                image: path.display().to_string(),
+            })
+            .into(),
+        );
+    }
+    let file = std::fs::File::open(path)?;
+    Ok(if media_type.ends_with("gzip") {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else if media_type.ends_with("zstd") {
+        Box::new(zstd::stream::read::Decoder::new(file)?)
+    } else if media_type.ends_with("tar") || media_type.contains(".tar") {
+        Box::new(file)
+    } else {
+        return Err(anyhow!("Unsupported layer media type: {}", media_type));
+    })
+}
+
+/// `rootfs/<relative>` when no directory on the way is a symlink, so a
+/// whiteout can't delete anything outside the rootfs
+pub(super) fn inside(rootfs: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut path = rootfs.to_path_buf();
+    let components: Vec<Component> = relative
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir | Component::RootDir))
+        .collect();
+    for (i, component) in components.iter().enumerate() {
+        let Component::Normal(part) = component else {
+            return None;
+        };
+        path.push(part);
+        let last = i + 1 == components.len();
+        if !last && std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+pub(super) fn remove_path(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Apply one layer on top of `rootfs`: whiteouts first, since they only
+/// affect lower layers, then the layer's own files
+pub fn unpack_layer(path: &Path, media_type: &str, rootfs: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(layer_reader(path, media_type)?);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        let Some(name) = entry_path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let parent = entry_path.parent().unwrap_or(Path::new(""));
+        if name == OPAQUE_WHITEOUT {
+            let Some(dir) = inside(rootfs, parent) else {
+                continue;
+            };
+            if let Ok(children) = std::fs::read_dir(&dir) {
+                for child in children {
+                    remove_path(&child?.path())?;
+                }
             }
-            3 => {
-                // registry/namespace/image
-                (parts[0], parts[1], parts[2])
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            if let Some(target) = inside(rootfs, &parent.join(hidden)) {
+                remove_path(&target)?;
             }
-            _ => {
-                return Err(anyhow::anyhow!("Invalid image reference: {}", image_ref));
+        }
+    }
+
+    let mut archive = tar::Archive::new(layer_reader(path, media_type)?);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        // The runtime provides /dev; device nodes can't be created rootless
+        if kind.is_character_special() || kind.is_block_special() {
+            continue;
+        }
+        let entry_path = entry.path()?.into_owned();
+        if entry_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(WHITEOUT_PREFIX))
+        {
+            continue;
+        }
+        // A file replacing a directory of a lower layer, or the reverse
+        if let Some(target) = inside(rootfs, &entry_path) {
+            let existing_dir = std::fs::symlink_metadata(&target).is_ok_and(|m| m.is_dir());
+            if existing_dir != kind.is_dir() {
+                remove_path(&target)?;
             }
-        };
+        }
+        entry.unpack_in(rootfs)?;
+    }
+    Ok(())
+}
 
-        // Split image and tag
-        let (image, tag) = if let Some(colon_pos) = image_with_tag.find(':') {
-            (
-                &image_with_tag[..colon_pos],
-                &image_with_tag[colon_pos + 1..],
-            )
-        } else {
-            (image_with_tag, "latest")
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok((
-            registry.to_string(),
-            namespace.to_string(),
-            image.to_string(),
-            tag.to_string(),
-        ))
+    fn layer(dir: &Path, name: &str, entries: &[(&str, Option<&str>)]) -> PathBuf {
+        let path = dir.join(name);
+        let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+        for (entry_path, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            match contents {
+                Some(data) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(data.len() as u64);
+                    header.set_mode(0o644);
+                    builder
+                        .append_data(&mut header, entry_path, data.as_bytes())
+                        .unwrap();
+                }
+                None => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_size(0);
+                    header.set_mode(0o755);
+                    builder
+                        .append_data(&mut header, entry_path, std::io::empty())
+                        .unwrap();
+                }
+            }
+        }
+        builder.finish().unwrap();
+        path
     }
 
-    pub async fn push_image(&self, image: &str, _data: &[u8]) -> Result<()> {
-        info!("📤 Pushing image to registry: {}", image);
-        warn!("Image push not yet implemented");
-        Ok(())
+    #[test]
+    fn parses_references_and_challenges_and_applies_whiteouts() {
+        let nginx = Reference::parse("nginx").unwrap();
+        assert_eq!(nginx.repository, "library/nginx");
+        assert_eq!(nginx.manifest_reference(), "latest");
+        assert_eq!(
+            nginx.endpoint(),
+            "https://registry-1.docker.io/v2/library/nginx"
+        );
+        let local = Reference::parse("localhost:5000/team/app@sha256:abc").unwrap();
+        assert_eq!(local.registry, "localhost:5000");
+        assert_eq!(local.repository, "team/app");
+        assert_eq!(local.tag, None);
+        assert_eq!(local.manifest_reference(), "sha256:abc");
+        assert!(local.endpoint().starts_with("http://"));
+        assert_eq!(
+            Reference::parse("ghcr.io/org/tool:1.2")
+                .unwrap()
+                .to_string(),
+            "ghcr.io/org/tool:1.2"
+        );
+
+        let challenge = Challenge::parse(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull""#,
+        )
+        .unwrap();
+        assert_eq!(challenge.scheme, "bearer");
+        assert_eq!(challenge.params["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge.params["scope"], "repository:library/nginx:pull");
+
+        let index: Manifest = serde_json::from_str(
+            r#"{"manifests": [
+                {"mediaType": "application/vnd.oci.image.manifest.v1+json", "digest": "sha256:a", "size": 1,
+                 "platform": {"architecture": "arm64", "os": "linux", "variant": "v8"}},
+                {"mediaType": "application/vnd.oci.image.manifest.v1+json", "digest": "sha256:b", "size": 1,
+                 "platform": {"architecture": "amd64", "os": "linux"}}
+            ]}"#,
+        )
+        .unwrap();
+        let entries = index.manifests.unwrap();
+        let amd64 = Platform::parse("linux/amd64").unwrap();
+        assert_eq!(
+            select_manifest("app", &entries, &amd64).unwrap().digest,
+            "sha256:b"
+        );
+        assert!(
+            select_manifest("app", &entries, &Platform::parse("linux/s390x").unwrap()).is_err()
+        );
+        assert!(blob_path(Path::new("/s"), "md5:00").is_err());
+
+        // "bolt:hunter2", as `docker login` saves it
+        let auth_file = r#"{"auths": {
+            "https://index.docker.io/v1/": {"auth": "Ym9sdDpodW50ZXIy"},
+            "ghcr.io": {"auth": "dGVhbTpnaHBfdG9rZW4="}
+        }}"#;
+        let hub = Credentials::from_auth_file(auth_file, "docker.io").unwrap();
+        assert_eq!(
+            (hub.username.as_str(), hub.password.as_str()),
+            ("bolt", "hunter2")
+        );
+        assert_eq!(
+            Credentials::from_auth_file(auth_file, "ghcr.io")
+                .unwrap()
+                .password,
+            "ghp_token"
+        );
+        assert!(Credentials::from_auth_file(auth_file, "quay.io").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path().join("rootfs");
+        std::fs::create_dir_all(&rootfs).unwrap();
+        let base = layer(
+            dir.path(),
+            "base.tar",
+            &[
+                ("etc", None),
+                ("etc/motd", Some("hello")),
+                ("etc/old.conf", Some("old")),
+                ("var", None),
+                ("var/cache", None),
+                ("var/cache/a", Some("a")),
+            ],
+        );
+        let top = layer(
+            dir.path(),
+            "top.tar",
+            &[
+                ("etc/.wh.old.conf", Some("")),
+                ("var/cache/.wh..wh..opq", Some("")),
+                ("var/cache/b", Some("b")),
+                ("etc/motd", Some("patched")),
+            ],
+        );
+        unpack_layer(&base, "application/vnd.oci.image.layer.v1.tar", &rootfs).unwrap();
+        unpack_layer(&top, "application/vnd.oci.image.layer.v1.tar", &rootfs).unwrap();
+        let encrypted = unpack_layer(
+            &top,
+            "application/vnd.oci.image.layer.v1.tar+gzip+encrypted",
+            &rootfs,
+        )
+        .unwrap_err();
+        assert_eq!(
+            BoltError::from(encrypted).code(),
+            crate::error::ErrorCode::ImageDecryptionFailed
+        );
+        assert_eq!(
+            std::fs::read_to_string(rootfs.join("etc/motd")).unwrap(),
+            "patched"
+        );
+        assert!(!rootfs.join("etc/old.conf").exists());
+        assert!(!rootfs.join("var/cache/a").exists());
+        assert!(rootfs.join("var/cache/b").exists());
+        assert!(!rootfs.join("etc/.wh.old.conf").exists());
+    }
+
+    /// Just enough of a registry to push to: Basic auth, blob uploads and
+    /// manifests, recording each authorized request
+    #[derive(Default)]
+    struct FakeRegistry {
+        blobs: HashMap<String, Vec<u8>>,
+        uploads: HashMap<String, Vec<u8>>,
+        manifests: HashMap<String, Vec<u8>>,
+        requests: Vec<String>,
+        next_upload: usize,
+        refuse_chunks: bool,
+    }
+
+    impl FakeRegistry {
+        fn handle(
+            &mut self,
+            method: &str,
+            path: &str,
+            query: &str,
+            auth: Option<String>,
+            range: Option<String>,
+            body: bytes::Bytes,
+        ) -> warp::http::Response<Vec<u8>> {
+            let reply = |status: u16| warp::http::Response::builder().status(status);
+            // "bolt:hunter2"
+            if auth.as_deref() != Some("Basic Ym9sdDpodW50ZXIy") {
+                return reply(401)
+                    .header("WWW-Authenticate", "Basic realm=\"fake\"")
+                    .body(Vec::new())
+                    .unwrap();
+            }
+            let path = path.strip_prefix("/v2/team/app/").unwrap();
+            self.requests.push(match range {
+                Some(range) => format!("{} {} {}", method, path, range),
+                None => format!("{} {}", method, path),
+            });
+
+            let response = match (method, path.split_once('/').unwrap()) {
+                ("HEAD", ("blobs", digest)) if self.blobs.contains_key(digest) => reply(200),
+                ("HEAD", _) => reply(404),
+                ("POST", ("blobs", "uploads/")) => {
+                    let upload = format!("uploads/{}", self.next_upload);
+                    self.next_upload += 1;
+                    self.uploads.insert(upload.clone(), Vec::new());
+                    reply(202).header("Location", format!("/v2/team/app/blobs/{}", upload))
+                }
+                ("PATCH", _) if self.refuse_chunks => reply(405),
+                ("PATCH", ("blobs", upload)) => {
+                    self.uploads
+                        .get_mut(upload)
+                        .unwrap()
+                        .extend_from_slice(&body);
+                    reply(202).header("Location", format!("/v2/team/app/blobs/{}", upload))
+                }
+                ("PUT", ("blobs", upload)) => {
+                    let mut data = self.uploads.remove(upload).unwrap();
+                    data.extend_from_slice(&body);
+                    let digest = query.strip_prefix("digest=").unwrap().replace("%3A", ":");
+                    if digest == format!("sha256:{:x}", Sha256::digest(&data)) {
+                        self.blobs.insert(digest, data);
+                        reply(201)
+                    } else {
+                        reply(400)
+                    }
+                }
+                ("PUT", ("manifests", tag)) => {
+                    self.manifests.insert(tag.to_string(), body.to_vec());
+                    reply(201).header(
+                        "Docker-Content-Digest",
+                        format!("sha256:{:x}", Sha256::digest(&body)),
+                    )
+                }
+                _ => reply(404),
+            };
+            response.body(Vec::new()).unwrap()
+        }
+    }
+
+    /// A client for `<fake registry>/team/app:v1` holding the fake's login
+    fn fake_registry() -> (
+        RegistryClient,
+        std::sync::Arc<std::sync::Mutex<FakeRegistry>>,
+    ) {
+        use warp::Filter;
+
+        let state = std::sync::Arc::new(std::sync::Mutex::new(FakeRegistry::default()));
+        let shared = state.clone();
+        let routes = warp::method()
+            .and(warp::path::full())
+            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::header::optional::<String>("content-range"))
+            .and(warp::body::bytes())
+            .map(
+                move |method: warp::http::Method,
+                      path: warp::path::FullPath,
+                      query: String,
+                      auth,
+                      range,
+                      body| {
+                    shared.lock().unwrap().handle(
+                        method.as_str(),
+                        path.as_str(),
+                        &query,
+                        auth,
+                        range,
+                        body,
+                    )
+                },
+            );
+        let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let reference = Reference::parse(&format!("{}/team/app:v1", address)).unwrap();
+        let mut client = RegistryClient::new(reference).unwrap();
+        client.credentials = Some(Credentials {
+            username: "bolt".to_string(),
+            password: "hunter2".to_string(),
+        });
+        (client, state)
+    }
+
+    /// Store a blob the way an OCI layout does and describe it
+    fn put_blob(layout: &Path, media_type: &str, data: &[u8]) -> Descriptor {
+        let digest = format!("sha256:{:x}", Sha256::digest(data));
+        let path = blob_path(layout, &digest).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, data).unwrap();
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest,
+            size: data.len() as u64,
+            platform: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn pushes_layouts_with_the_saved_login() {
+        let (mut client, registry) = fake_registry();
+        let dir = tempfile::tempdir().unwrap();
+        let layout = dir.path();
+
+        let config = put_blob(
+            layout,
+            "application/vnd.oci.image.config.v1+json",
+            br#"{"architecture":"amd64","os":"linux"}"#,
+        );
+        let layer = put_blob(layout, "application/vnd.oci.image.layer.v1.tar", b"layer");
+        let manifest_bytes = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "config": config,
+            "layers": [layer],
+        }))
+        .unwrap();
+        let manifest = put_blob(layout, OCI_MANIFEST, &manifest_bytes);
+        std::fs::write(
+            layout.join("index.json"),
+            serde_json::to_vec(&serde_json::json!({
+                "schemaVersion": 2,
+                "manifests": [manifest],
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        // The repository already has the config
+        registry
+            .lock()
+            .unwrap()
+            .blobs
+            .insert(config.digest.clone(), Vec::new());
+
+        let digest = client
+            .push_layout(layout, &["latest".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(digest, manifest.digest);
+        let registry = registry.lock().unwrap();
+        assert_eq!(registry.blobs[&layer.digest], b"layer");
+        assert_eq!(registry.manifests["v1"], manifest_bytes);
+        assert_eq!(registry.manifests["latest"], manifest_bytes);
+        assert_eq!(
+            registry.requests,
+            vec![
+                format!("HEAD blobs/{}", config.digest),
+                format!("HEAD blobs/{}", layer.digest),
+                "POST blobs/uploads/".to_string(),
+                "PUT blobs/uploads/0".to_string(),
+                "PUT manifests/v1".to_string(),
+                "PUT manifests/latest".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_large_blobs_in_chunks_or_in_one_request() {
+        let (mut client, registry) = fake_registry();
+        let dir = tempfile::tempdir().unwrap();
+        let size = CHUNK_SIZE as usize + 10;
+
+        let chunked = put_blob(dir.path(), "layer", &vec![1u8; size]);
+        client.push_blob(&chunked, dir.path()).await.unwrap();
+        {
+            let registry = registry.lock().unwrap();
+            assert_eq!(registry.blobs[&chunked.digest].len(), size);
+            assert_eq!(
+                registry.requests[2..],
+                [
+                    "PATCH blobs/uploads/0 0-16777215".to_string(),
+                    "PATCH blobs/uploads/0 16777216-16777225".to_string(),
+                    "PUT blobs/uploads/0".to_string(),
+                ]
+            );
+        }
+
+        // Registries without chunked uploads get the blob in one request
+        let monolithic = put_blob(dir.path(), "layer", &vec![2u8; size]);
+        registry.lock().unwrap().refuse_chunks = true;
+        registry.lock().unwrap().requests.clear();
+        client.push_blob(&monolithic, dir.path()).await.unwrap();
+        let registry = registry.lock().unwrap();
+        assert_eq!(registry.blobs[&monolithic.digest].len(), size);
+        assert_eq!(
+            registry.requests[1..],
+            [
+                "POST blobs/uploads/".to_string(),
+                "PATCH blobs/uploads/1 0-16777215".to_string(),
+                "POST blobs/uploads/".to_string(),
+                "PUT blobs/uploads/2".to_string(),
+            ]
+        );
     }
 }
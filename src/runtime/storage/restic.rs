@@ -0,0 +1,499 @@
+// Restic-compatible repository backend
+//
+// Volumes and capsule snapshots are backed up by running `restic` (or
+// `rustic`) against the repository in `[backup.restic]`, so encryption,
+// deduplication and pruning come from the repository format. Every backup is
+// tagged `bolt` plus `volume:<name>` or `snapshot:<id>`, which is how a
+// restore finds its way back to the right place.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use crate::capsules::export as capsule_export;
+use crate::config::ResticSettings;
+use crate::secrets::{self, SecretStore};
+
+const BOLT_TAG: &str = "bolt";
+
+/// Which CLI the repository is driven with; their flags differ slightly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flavor {
+    Restic,
+    Rustic,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResticRepository {
+    binary: String,
+    flavor: Flavor,
+    repository: String,
+    password: String,
+    env: Vec<(String, String)>,
+    keep: Vec<(&'static str, u32)>,
+}
+
+/// What a repository snapshot holds, from its tags
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum BackupTarget {
+    Volume(String),
+    Snapshot(String),
+}
+
+impl std::fmt::Display for BackupTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Volume(name) => write!(f, "volume {}", name),
+            Self::Snapshot(id) => write!(f, "snapshot {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResticSnapshot {
+    pub id: String,
+    pub time: String,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ResticSnapshot {
+    pub fn short_id(&self) -> &str {
+        &self.id[..self.id.len().min(8)]
+    }
+
+    pub fn target(&self) -> Option<BackupTarget> {
+        self.tags.iter().find_map(|tag| {
+            if let Some(name) = tag.strip_prefix("volume:") {
+                Some(BackupTarget::Volume(name.to_string()))
+            } else {
+                tag.strip_prefix("snapshot:")
+                    .map(|id| BackupTarget::Snapshot(id.to_string()))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResticBackupSummary {
+    pub target: BackupTarget,
+    pub snapshot_id: String,
+    pub data_added: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResticRestoreSummary {
+    pub target: BackupTarget,
+    pub snapshot_id: String,
+    pub restored_to: String,
+}
+
+impl ResticRepository {
+    pub fn from_settings(settings: &ResticSettings, secrets: &SecretStore) -> Result<Self> {
+        if settings.repository.is_empty() {
+            return Err(anyhow!("[backup.restic] repository is not set"));
+        }
+        let password = secrets
+            .resolve(&settings.password)
+            .context("Failed to resolve the restic repository password")?;
+
+        let mut env = Vec::new();
+        for (key, value) in &settings.env {
+            let value = if secrets::is_reference(value) {
+                secrets
+                    .resolve(value)
+                    .with_context(|| format!("Failed to resolve [backup.restic.env] {}", key))?
+            } else {
+                value.clone()
+            };
+            env.push((key.clone(), value));
+        }
+
+        let keep = [
+            ("--keep-last", settings.keep_last),
+            ("--keep-daily", settings.keep_daily),
+            ("--keep-weekly", settings.keep_weekly),
+            ("--keep-monthly", settings.keep_monthly),
+        ]
+        .into_iter()
+        .filter_map(|(flag, n)| n.map(|n| (flag, n)))
+        .collect();
+
+        let flavor = if Path::new(&settings.binary)
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with("rustic"))
+        {
+            Flavor::Rustic
+        } else {
+            Flavor::Restic
+        };
+
+        Ok(Self {
+            binary: settings.binary.clone(),
+            flavor,
+            repository: settings.repository.clone(),
+            password,
+            env,
+            keep,
+        })
+    }
+
+    fn command(&self) -> AsyncCommand {
+        let (repo_var, password_var) = match self.flavor {
+            Flavor::Restic => ("RESTIC_REPOSITORY", "RESTIC_PASSWORD"),
+            Flavor::Rustic => ("RUSTIC_REPOSITORY", "RUSTIC_PASSWORD"),
+        };
+        let mut cmd = AsyncCommand::new(&self.binary);
+        cmd.env(repo_var, &self.repository)
+            .env(password_var, &self.password)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<Vec<u8>> {
+        debug!("{} {}", self.binary, args.join(" "));
+        let output = self
+            .command()
+            .args(args)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}; is it installed?", self.binary))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{} {} failed: {}",
+                self.binary,
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    fn tag_filter(&self) -> &'static str {
+        match self.flavor {
+            Flavor::Restic => "--tag",
+            Flavor::Rustic => "--filter-tags",
+        }
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        self.run(&["init"]).await?;
+        info!("🗄️  Initialized repository {}", self.repository);
+        Ok(())
+    }
+
+    /// Bolt's snapshots in the repository, oldest first
+    pub async fn snapshots(&self) -> Result<Vec<ResticSnapshot>> {
+        let stdout = self
+            .run(&["snapshots", "--json", self.tag_filter(), BOLT_TAG])
+            .await?;
+        parse_snapshots(&stdout)
+    }
+
+    async fn backup_path(&self, path: &Path, target: BackupTarget) -> Result<ResticBackupSummary> {
+        let tags = match &target {
+            BackupTarget::Volume(name) => format!("{},volume:{}", BOLT_TAG, name),
+            BackupTarget::Snapshot(id) => format!("{},snapshot:{}", BOLT_TAG, id),
+        };
+        let path = path.to_string_lossy();
+        let stdout = self
+            .run(&["backup", "--json", "--tag", &tags, &path])
+            .await?;
+
+        // restic prints one message per line with a `summary` last; rustic
+        // prints the snapshot as a single document with its summary nested
+        let last = serde_json::from_slice::<serde_json::Value>(&stdout)
+            .ok()
+            .or_else(|| {
+                String::from_utf8_lossy(&stdout)
+                    .lines()
+                    .rev()
+                    .find_map(|l| serde_json::from_str(l).ok())
+            })
+            .unwrap_or_default();
+        let summary = last.get("summary").unwrap_or(&last);
+        let number = |key: &str| summary.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+
+        let summary = ResticBackupSummary {
+            snapshot_id: last
+                .get("snapshot_id")
+                .or_else(|| last.get("id"))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            data_added: number("data_added"),
+            total_bytes: number("total_bytes_processed"),
+            target,
+        };
+        info!(
+            "💾 Backed up {} as {}",
+            summary.target,
+            &summary.snapshot_id[..summary.snapshot_id.len().min(8)]
+        );
+        Ok(summary)
+    }
+
+    /// Apply the configured retention to Bolt's snapshots
+    pub async fn forget(&self) -> Result<()> {
+        if self.keep.is_empty() {
+            return Ok(());
+        }
+        let keep: Vec<String> = self
+            .keep
+            .iter()
+            .flat_map(|(flag, n)| [flag.to_string(), n.to_string()])
+            .collect();
+        let mut args = vec!["forget", "--prune", self.tag_filter(), BOLT_TAG];
+        args.extend(keep.iter().map(String::as_str));
+        self.run(&args).await?;
+        info!("🧹 Applied retention policy to {}", self.repository);
+        Ok(())
+    }
+
+    async fn restore_dir(&self, snapshot: &ResticSnapshot, target: &Path) -> Result<()> {
+        let source = snapshot
+            .paths
+            .first()
+            .ok_or_else(|| anyhow!("Snapshot {} has no paths", snapshot.short_id()))?;
+        let spec = format!("{}:{}", snapshot.id, source);
+        let target = target.to_string_lossy();
+        match self.flavor {
+            Flavor::Restic => self.run(&["restore", &spec, "--target", &target]).await?,
+            Flavor::Rustic => self.run(&["restore", &spec, &target]).await?,
+        };
+        Ok(())
+    }
+
+    async fn dump_file(&self, snapshot: &ResticSnapshot, output: &Path) -> Result<()> {
+        let source = snapshot
+            .paths
+            .first()
+            .ok_or_else(|| anyhow!("Snapshot {} has no paths", snapshot.short_id()))?;
+        let spec = format!("{}:{}", snapshot.id, source);
+        let args = match self.flavor {
+            Flavor::Restic => vec!["dump", snapshot.id.as_str(), source.as_str()],
+            Flavor::Rustic => vec!["dump", spec.as_str()],
+        };
+        let file = std::fs::File::create(output)?;
+        let status = self
+            .command()
+            .args(&args)
+            .stdout(file)
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {}", self.binary))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "{} dump of {} failed",
+                self.binary,
+                snapshot.short_id()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Back up volumes and capsule snapshots, then apply retention
+pub async fn backup(
+    repo: &ResticRepository,
+    runtime: &str,
+    capsules_root: &Path,
+    staging_dir: &Path,
+    volumes: &[String],
+    snapshots: &[String],
+) -> Result<Vec<ResticBackupSummary>> {
+    if volumes.is_empty() && snapshots.is_empty() {
+        return Err(anyhow!("Nothing to back up; pass --volume or --snapshot"));
+    }
+
+    let mut summaries = Vec::new();
+    for volume in volumes {
+        let mountpoint = volume_mountpoint(runtime, volume)
+            .await?
+            .ok_or_else(|| anyhow!("No such volume: {}", volume))?;
+        summaries.push(
+            repo.backup_path(&mountpoint, BackupTarget::Volume(volume.clone()))
+                .await?,
+        );
+    }
+
+    // Bundles are staged at a stable path so repeated backups of the same
+    // snapshot share a parent and deduplicate
+    for snapshot in snapshots {
+        let record = capsule_export::find_record(capsules_root, snapshot)?;
+        let dir = staging_dir.join(&record.snapshot.id);
+        std::fs::create_dir_all(&dir)?;
+        let bundle = dir.join("snapshot.bolt");
+        let result = async {
+            capsule_export::export_snapshot(capsules_root, &record.snapshot.id, &bundle)?;
+            repo.backup_path(&bundle, BackupTarget::Snapshot(record.snapshot.id.clone()))
+                .await
+        }
+        .await;
+        let _ = std::fs::remove_dir_all(&dir);
+        summaries.push(result?);
+    }
+
+    repo.forget().await?;
+    Ok(summaries)
+}
+
+/// Restore a repository snapshot to where it was backed up from, or to `target`
+pub async fn restore(
+    repo: &ResticRepository,
+    runtime: &str,
+    capsules_root: &Path,
+    staging_dir: &Path,
+    snapshot_id: &str,
+    target: Option<&Path>,
+) -> Result<ResticRestoreSummary> {
+    let snapshot = repo
+        .snapshots()
+        .await?
+        .into_iter()
+        .rev()
+        .find(|s| s.id.starts_with(snapshot_id))
+        .ok_or_else(|| anyhow!("No Bolt backup {} in the repository", snapshot_id))?;
+    let kind = snapshot.target().ok_or_else(|| {
+        anyhow!(
+            "Backup {} has no volume: or snapshot: tag",
+            snapshot.short_id()
+        )
+    })?;
+
+    let restored_to = match (&kind, target) {
+        (_, Some(dir)) => {
+            std::fs::create_dir_all(dir)?;
+            repo.restore_dir(&snapshot, dir).await?;
+            dir.display().to_string()
+        }
+        (BackupTarget::Volume(name), None) => {
+            let mountpoint = match volume_mountpoint(runtime, name).await? {
+                Some(path) => path,
+                None => {
+                    create_volume(runtime, name).await?;
+                    volume_mountpoint(runtime, name)
+                        .await?
+                        .ok_or_else(|| anyhow!("Volume {} has no mountpoint", name))?
+                }
+            };
+            warn!(
+                "Restoring into volume {}; stop containers using it first",
+                name
+            );
+            repo.restore_dir(&snapshot, &mountpoint).await?;
+            format!("volume {}", name)
+        }
+        (BackupTarget::Snapshot(_), None) => {
+            let dir = staging_dir.join(snapshot.short_id());
+            std::fs::create_dir_all(&dir)?;
+            let bundle = dir.join("snapshot.bolt");
+            let imported = async {
+                repo.dump_file(&snapshot, &bundle).await?;
+                capsule_export::import_snapshot(capsules_root, &bundle, None)
+            }
+            .await;
+            let _ = std::fs::remove_dir_all(&dir);
+            let record = imported?;
+            format!("capsule {}", record.capsule_name)
+        }
+    };
+
+    info!("♻️  Restored {} to {}", kind, restored_to);
+    Ok(ResticRestoreSummary {
+        target: kind,
+        snapshot_id: snapshot.id,
+        restored_to,
+    })
+}
+
+/// Accepts restic's flat list and rustic's `[group, snapshots]` pairs
+fn parse_snapshots(stdout: &[u8]) -> Result<Vec<ResticSnapshot>> {
+    let value: serde_json::Value =
+        serde_json::from_slice(stdout).context("Unexpected output from the snapshots command")?;
+    let mut snapshots = Vec::new();
+    for entry in value.as_array().into_iter().flatten() {
+        match entry.as_array() {
+            Some(pair) => {
+                for snapshot in pair.get(1).and_then(|s| s.as_array()).into_iter().flatten() {
+                    snapshots.push(serde_json::from_value(snapshot.clone())?);
+                }
+            }
+            None => snapshots.push(serde_json::from_value(entry.clone())?),
+        }
+    }
+    snapshots.sort_by(|a: &ResticSnapshot, b| a.time.cmp(&b.time));
+    Ok(snapshots)
+}
+
+pub(crate) async fn volume_mountpoint(runtime: &str, volume: &str) -> Result<Option<PathBuf>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["volume", "inspect", "--format", "{{.Mountpoint}}", volume])
+        .output()
+        .await?;
+    let mountpoint = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || mountpoint.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(PathBuf::from(mountpoint)))
+}
+
+async fn create_volume(runtime: &str, volume: &str) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .args(["volume", "create", volume])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to create volume {}: {}",
+            volume,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_restic_and_rustic_snapshot_lists() {
+        let restic = br#"[
+            {"id": "bbbb2222", "time": "2026-10-02T03:00:00Z", "hostname": "h",
+             "paths": ["/var/lib/containers/storage/volumes/db/_data"], "tags": ["bolt", "volume:db"]},
+            {"id": "aaaa1111", "time": "2026-10-01T03:00:00Z", "hostname": "h",
+             "paths": ["/tmp/snap-1/snapshot.bolt"], "tags": ["bolt", "snapshot:snap-1"]}
+        ]"#;
+        let snapshots = parse_snapshots(restic).unwrap();
+        assert_eq!(snapshots[0].id, "aaaa1111");
+        assert_eq!(
+            snapshots[0].target(),
+            Some(BackupTarget::Snapshot("snap-1".to_string()))
+        );
+        assert_eq!(
+            snapshots[1].target(),
+            Some(BackupTarget::Volume("db".to_string()))
+        );
+
+        let rustic = br#"[[{"hostname": "h"}, [
+            {"id": "cccc3333", "time": "2026-10-03T03:00:00Z", "paths": ["/data"], "tags": ["bolt", "volume:web"]}
+        ]]]"#;
+        let snapshots = parse_snapshots(rustic).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].short_id(), "cccc3333");
+        assert_eq!(
+            snapshots[0].target(),
+            Some(BackupTarget::Volume("web".to_string()))
+        );
+    }
+}
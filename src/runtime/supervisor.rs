@@ -0,0 +1,493 @@
+// Restart policies
+//
+// A service's `restart` is enforced by Bolt rather than handed to podman or
+// docker, so it behaves the same on both and its retries can be shown:
+//
+// - `no`: a container that exits stays down
+// - `on-failure[:N]`: restart it when it exits with a non-zero code, giving
+//   up after N restarts in a row
+// - `always` and `unless-stopped`: restart it whenever it exits
+//
+// A container stopped through Bolt (`bolt stop`, surge stopping a replica) is
+// left alone under every policy until it's started again. As with Docker
+// while its daemon runs, `always` and `unless-stopped` therefore behave the
+// same.
+//
+// `bolt supervise`, started by surge for detached services with a policy
+// other than `no`, polls the container and restarts it after a backoff that
+// starts at 1s and doubles up to 5 minutes. Once a container has stayed up
+// for 10s, its next exit starts over with the shortest backoff and a fresh
+// retry count. The accounting is kept in `<data_dir>/supervisor/<container>.json`
+// and shown by `surge status`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+use super::events::{ContainerEvent, EventLog};
+use super::watcher::{self, Watcher};
+use crate::config::RestartPolicy;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// How long a container must stay up for its next exit to count as a first
+const RESET_AFTER: Duration = Duration::from_secs(10);
+
+/// A service's `restart`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestartSpec {
+    pub policy: RestartPolicy,
+    /// Restarts in a row `on-failure` allows, unlimited when None
+    pub max_retries: Option<u32>,
+}
+
+/// What to do about a container that exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Leave,
+    Restart(Duration),
+    GiveUp,
+}
+
+impl RestartSpec {
+    /// no, always, on-failure[:max-retries] or unless-stopped
+    pub fn parse(restart: &str) -> Result<Self> {
+        let policy = |policy| {
+            Ok(Self {
+                policy,
+                max_retries: None,
+            })
+        };
+        match restart.split_once(':') {
+            None => match restart {
+                "no" => policy(RestartPolicy::No),
+                "always" => policy(RestartPolicy::Always),
+                "on-failure" => policy(RestartPolicy::OnFailure),
+                "unless-stopped" => policy(RestartPolicy::UnlessStopped),
+                _ => Err(anyhow!(
+                    "unknown restart policy '{}': expected no, always, on-failure or unless-stopped",
+                    restart
+                )),
+            },
+            Some(("on-failure", retries)) => Ok(Self {
+                policy: RestartPolicy::OnFailure,
+                max_retries: Some(
+                    retries
+                        .parse()
+                        .map_err(|_| anyhow!("invalid on-failure retry count '{}'", retries))?,
+                ),
+            }),
+            Some(_) => Err(anyhow!(
+                "only on-failure takes a retry count, as in on-failure:3; got '{}'",
+                restart
+            )),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.policy != RestartPolicy::No
+    }
+
+    /// What to do when the container exited with `exit_code` after
+    /// `in_a_row` restarts without staying up
+    pub fn decide(&self, exit_code: i64, in_a_row: u32) -> Decision {
+        match self.policy {
+            RestartPolicy::No => Decision::Leave,
+            RestartPolicy::OnFailure if exit_code == 0 => Decision::Leave,
+            RestartPolicy::OnFailure if self.max_retries.is_some_and(|max| in_a_row >= max) => {
+                Decision::GiveUp
+            }
+            _ => Decision::Restart(backoff(in_a_row)),
+        }
+    }
+}
+
+/// Delay before the restart that follows `in_a_row` earlier ones
+pub fn backoff(in_a_row: u32) -> Duration {
+    BACKOFF_BASE
+        .checked_mul(2u32.saturating_pow(in_a_row))
+        .map_or(BACKOFF_MAX, |delay| delay.min(BACKOFF_MAX))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorState {
+    pub container: String,
+    /// The policy as written in the Boltfile
+    pub restart: String,
+    pub watcher_pid: Option<u32>,
+    /// Restarts since surge started the container
+    pub restarts: u64,
+    /// Restarts since it last stayed up
+    pub in_a_row: u32,
+    pub last_exit_code: Option<i64>,
+    pub last_restart: Option<DateTime<Utc>>,
+    /// When the pending restart is due
+    pub next_restart: Option<DateTime<Utc>>,
+    /// Set once `on-failure:N` ran out of retries
+    pub gave_up: bool,
+    /// Stopped through Bolt; not restarted until it runs again
+    pub stopped: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SupervisorState {
+    /// `restarted 3 times`, `restarting in 8s (attempt 2/5)`, ... None while
+    /// there's nothing to tell
+    pub fn describe(&self) -> Option<String> {
+        let max = RestartSpec::parse(&self.restart)
+            .ok()
+            .and_then(|spec| spec.max_retries);
+        if self.gave_up {
+            return Some(format!(
+                "gave up after {} restarts, exit code {}",
+                self.in_a_row,
+                self.last_exit_code.unwrap_or_default()
+            ));
+        }
+        if let Some(next) = self.next_restart {
+            let attempt = match max {
+                Some(max) => format!("{}/{}", self.in_a_row + 1, max),
+                None => (self.in_a_row + 1).to_string(),
+            };
+            return Some(format!(
+                "restarting in {}s (attempt {})",
+                (next - Utc::now()).num_seconds().max(0),
+                attempt
+            ));
+        }
+        (self.restarts > 0).then(|| format!("restarted {} times", self.restarts))
+    }
+}
+
+const WATCHER: Watcher = Watcher::new("supervise");
+
+fn supervisor_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("supervisor")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    supervisor_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    supervisor_dir(data_dir).join(format!("{}.log", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<SupervisorState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+fn save(data_dir: &Path, state: &SupervisorState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Save the supervisor's changes unless `surge down` removed the record
+/// meanwhile
+fn update(data_dir: &Path, state: &SupervisorState) -> Result<()> {
+    watcher::update(&state_path(data_dir, &state.container), state)
+}
+
+/// Start enforcing `restart` for a running container
+pub fn start(data_dir: &Path, container: &str, restart: &str) -> Result<()> {
+    RestartSpec::parse(restart)?;
+    forget(data_dir, container);
+    let mut state = SupervisorState {
+        container: container.to_string(),
+        restart: restart.to_string(),
+        watcher_pid: None,
+        restarts: 0,
+        in_a_row: 0,
+        last_exit_code: None,
+        last_restart: None,
+        next_restart: None,
+        gave_up: false,
+        stopped: false,
+        updated_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["supervise", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    debug!("Restarting {} according to '{}'", container, restart);
+    Ok(())
+}
+
+/// Stop enforcing a container's restart policy and drop its record
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Note that a container is being stopped on purpose, so it isn't restarted.
+/// Called before stopping it.
+pub fn stopping(data_dir: &Path, container: &str) {
+    if let Some(mut state) = state(data_dir, container) {
+        state.stopped = true;
+        state.next_restart = None;
+        state.updated_at = Utc::now();
+        if let Err(e) = save(data_dir, &state) {
+            debug!("Could not mark {} as stopped: {}", container, e);
+        }
+    }
+}
+
+/// `(state, exit code)` of a container, None once it doesn't exist
+async fn inspect(runtime: &str, container: &str) -> Option<(String, i64)> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            "{{.State.Status}}|{{.State.ExitCode}}",
+            container,
+        ])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (state, exit_code) = stdout.trim().split_once('|')?;
+    Some((state.to_string(), exit_code.parse().unwrap_or_default()))
+}
+
+fn exited(state: &str) -> bool {
+    matches!(state, "exited" | "stopped" | "dead")
+}
+
+async fn start_container(runtime: &str, container: &str) -> Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .args(["start", container])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} start", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Restart the container whenever it exits and its policy says so. Run by
+/// `bolt supervise`.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    let runtime = super::detect_container_runtime().await?;
+    let events = EventLog::new(data_dir.to_path_buf())?;
+    // When it was first seen up since it last started
+    let mut running_since: Option<Instant> = None;
+    // Whether it went down after being stopped through Bolt, so that seeing
+    // it up again means someone started it
+    let mut down_while_stopped = false;
+
+    loop {
+        // Reread every time: `bolt stop` marks the record, `surge down`
+        // removes it
+        let Some(mut state) = state(data_dir, container) else {
+            debug!("Supervision of {} removed, stopping", container);
+            return Ok(());
+        };
+        let spec = RestartSpec::parse(&state.restart)?;
+        let Some((status, exit_code)) = inspect(&runtime, container).await else {
+            info!("{} no longer exists, no longer supervising it", container);
+            return Ok(());
+        };
+
+        if !exited(&status) {
+            if status == "running" {
+                let since = *running_since.get_or_insert_with(Instant::now);
+                let mut changed = false;
+                if state.stopped && down_while_stopped {
+                    state.stopped = false;
+                    down_while_stopped = false;
+                    changed = true;
+                }
+                if (state.in_a_row > 0 || state.gave_up) && since.elapsed() >= RESET_AFTER {
+                    state.in_a_row = 0;
+                    state.gave_up = false;
+                    changed = true;
+                }
+                if changed {
+                    state.updated_at = Utc::now();
+                    update(data_dir, &state)?;
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+
+        running_since = None;
+        if state.stopped {
+            down_while_stopped = true;
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+        if state.last_exit_code != Some(exit_code) {
+            state.last_exit_code = Some(exit_code);
+            state.updated_at = Utc::now();
+            update(data_dir, &state)?;
+        }
+
+        match spec.decide(exit_code, state.in_a_row) {
+            Decision::Leave => {}
+            Decision::GiveUp if state.gave_up => {}
+            Decision::GiveUp => {
+                warn!(
+                    "🛑 {} exited with code {} after {} restarts in a row, giving up",
+                    container, exit_code, state.in_a_row
+                );
+                record(
+                    &events,
+                    ContainerEvent::new(container, "restart_gave_up")
+                        .with_attribute("exit_code", exit_code.to_string())
+                        .with_attribute("restarts", state.in_a_row.to_string()),
+                );
+                state.gave_up = true;
+                state.updated_at = Utc::now();
+                update(data_dir, &state)?;
+            }
+            Decision::Restart(delay) => {
+                info!(
+                    "🔁 {} exited with code {}, restarting in {}s",
+                    container,
+                    exit_code,
+                    delay.as_secs()
+                );
+                state.next_restart = Some(Utc::now() + delay);
+                state.updated_at = Utc::now();
+                update(data_dir, &state)?;
+                tokio::time::sleep(delay).await;
+
+                // Stopped, removed or started by someone else meanwhile
+                let Some(mut state) = self::state(data_dir, container) else {
+                    continue;
+                };
+                let still_down = inspect(&runtime, container)
+                    .await
+                    .is_some_and(|(status, _)| exited(&status));
+                state.next_restart = None;
+                if state.stopped || !still_down {
+                    update(data_dir, &state)?;
+                    continue;
+                }
+
+                state.in_a_row += 1;
+                state.updated_at = Utc::now();
+                match start_container(&runtime, container).await {
+                    Ok(()) => {
+                        // A new network namespace comes without the egress rules
+                        crate::networking::egress::restore(&runtime, data_dir, container).await;
+                        state.restarts += 1;
+                        state.last_restart = Some(Utc::now());
+                        record(
+                            &events,
+                            ContainerEvent::new(container, "policy_restart")
+                                .with_attribute("exit_code", exit_code.to_string())
+                                .with_attribute("attempt", state.in_a_row.to_string()),
+                        );
+                    }
+                    // Counted all the same, so a container that can't start
+                    // backs off too
+                    Err(e) => warn!("Could not restart {}: {}", container, e),
+                }
+                update(data_dir, &state)?;
+                continue;
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn record(events: &EventLog, event: ContainerEvent) {
+    if let Err(e) = events.record(&event) {
+        debug!(
+            "Could not record {} of {}: {}",
+            event.action, event.container, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decides_restarts_with_backoff_and_retry_limits() {
+        let always = RestartSpec::parse("always").unwrap();
+        assert_eq!(
+            always.decide(0, 0),
+            Decision::Restart(Duration::from_secs(1))
+        );
+        assert_eq!(
+            always.decide(1, 3),
+            Decision::Restart(Duration::from_secs(8))
+        );
+        assert_eq!(always.decide(1, 40), Decision::Restart(BACKOFF_MAX));
+        assert_eq!(
+            RestartSpec::parse("unless-stopped").unwrap().decide(0, 1),
+            Decision::Restart(Duration::from_secs(2))
+        );
+        assert_eq!(
+            RestartSpec::parse("no").unwrap().decide(1, 0),
+            Decision::Leave
+        );
+
+        let on_failure = RestartSpec::parse("on-failure:2").unwrap();
+        assert_eq!(on_failure.max_retries, Some(2));
+        assert_eq!(on_failure.decide(0, 0), Decision::Leave);
+        assert_eq!(
+            on_failure.decide(137, 1),
+            Decision::Restart(Duration::from_secs(2))
+        );
+        assert_eq!(on_failure.decide(137, 2), Decision::GiveUp);
+        assert_eq!(
+            RestartSpec::parse("on-failure").unwrap().decide(1, 100),
+            Decision::Restart(BACKOFF_MAX)
+        );
+        assert!(RestartSpec::parse("always:3").is_err());
+        assert!(RestartSpec::parse("on-failure:x").is_err());
+
+        let mut state = SupervisorState {
+            container: "shop_web".to_string(),
+            restart: "on-failure:5".to_string(),
+            watcher_pid: None,
+            restarts: 0,
+            in_a_row: 0,
+            last_exit_code: Some(1),
+            last_restart: None,
+            next_restart: None,
+            gave_up: false,
+            stopped: false,
+            updated_at: Utc::now(),
+        };
+        assert_eq!(state.describe(), None);
+        state.restarts = 3;
+        assert_eq!(state.describe().unwrap(), "restarted 3 times");
+        state.in_a_row = 1;
+        state.next_restart = Some(Utc::now() + chrono::Duration::seconds(60));
+        assert!(state.describe().unwrap().ends_with("(attempt 2/5)"));
+        state.next_restart = None;
+        state.in_a_row = 5;
+        state.gave_up = true;
+        assert_eq!(
+            state.describe().unwrap(),
+            "gave up after 5 restarts, exit code 1"
+        );
+
+        // Marking a stop only touches supervised containers
+        let dir = tempfile::tempdir().unwrap();
+        stopping(dir.path(), "shop_web");
+        assert!(self::state(dir.path(), "shop_web").is_none());
+        state.gave_up = false;
+        save(dir.path(), &state).unwrap();
+        stopping(dir.path(), "shop_web");
+        assert!(self::state(dir.path(), "shop_web").unwrap().stopped);
+        forget(dir.path(), "shop_web");
+        assert!(self::state(dir.path(), "shop_web").is_none());
+    }
+}
@@ -0,0 +1,578 @@
+// Image trust policy
+//
+// A policy file (`[trust] policy` in config.toml, `<config_dir>/trust.toml`
+// by default) lists rules for registries and repositories. The first rule
+// whose `images` pattern matches an image applies to it; images no rule
+// matches are trusted.
+//
+//   [[rule]]
+//   images = "ghcr.io/acme/*"        # registry/repository, `*` wildcards
+//   require_signature = true         # cosign signature by `key` or identity
+//   key = "/etc/bolt/keys/acme.pub"
+//   require_provenance = true        # SLSA provenance attestation
+//   require_digest = true            # only `image@sha256:...`
+//   block_latest = true              # no `:latest` or untagged images
+//   max_age = "90d"                  # image creation date
+//
+// A rule for some tags only adds them after the repository pattern
+// (`docker.io/library/nginx:1.*`). Docker Hub images are matched by their
+// full name, so `nginx` is `docker.io/library/nginx`.
+//
+// Policies are enforced on pull and on the base images of builds, and
+// reported by `surge plan`. `--override-trust` lets an image through anyway,
+// but only for admins: root and members of the policy's `admin_group`. Every
+// override is appended to `<data_dir>/trust/overrides.log`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::BoltConfig;
+use crate::error::{BoltError, RuntimeError};
+
+const DEFAULT_ADMIN_GROUP: &str = "bolt-admin";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustPolicy {
+    /// Members of this group may override the policy
+    #[serde(default = "default_admin_group")]
+    pub admin_group: String,
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<TrustRule>,
+}
+
+fn default_admin_group() -> String {
+    DEFAULT_ADMIN_GROUP.to_string()
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustRule {
+    /// `registry/repository[:tag]` pattern
+    pub images: String,
+    #[serde(default)]
+    pub require_signature: bool,
+    /// Public key (or KMS URI) signatures and attestations are verified with
+    pub key: Option<String>,
+    /// Keyless signing: the signer's identity and OIDC issuer
+    pub certificate_identity: Option<String>,
+    pub certificate_oidc_issuer: Option<String>,
+    #[serde(default)]
+    pub require_provenance: bool,
+    #[serde(default)]
+    pub require_digest: bool,
+    #[serde(default)]
+    pub block_latest: bool,
+    /// Oldest allowed image, e.g. "90d"
+    pub max_age: Option<String>,
+}
+
+impl TrustRule {
+    fn validate(&self) -> Result<()> {
+        if self.images.trim().is_empty() {
+            return Err(anyhow!("A trust rule needs an images pattern"));
+        }
+        if (self.require_signature || self.require_provenance) && self.verifier_args().is_empty() {
+            return Err(anyhow!(
+                "Rule '{}' requires signatures but sets neither key nor certificate_identity and certificate_oidc_issuer",
+                self.images
+            ));
+        }
+        if let Some(ref age) = self.max_age {
+            parse_duration(age)
+                .with_context(|| format!("Invalid max_age in rule '{}'", self.images))?;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, image: &ImageName) -> bool {
+        let (repository, tag) = match self.images.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (self.images.as_str(), "*"),
+        };
+        crate::surge::dev::wildcard_match(repository, &image.repository)
+            && (tag == "*" || crate::surge::dev::wildcard_match(tag, image.tag()))
+    }
+
+    fn verifier_args(&self) -> Vec<String> {
+        match (
+            &self.key,
+            &self.certificate_identity,
+            &self.certificate_oidc_issuer,
+        ) {
+            (Some(key), _, _) => vec!["--key".to_string(), key.clone()],
+            (None, Some(identity), Some(issuer)) => vec![
+                "--certificate-identity".to_string(),
+                identity.clone(),
+                "--certificate-oidc-issuer".to_string(),
+                issuer.clone(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl TrustPolicy {
+    /// The configured policy, if there is one
+    pub fn load(config: &BoltConfig) -> Result<Option<Self>> {
+        let path = match config.trust.policy {
+            Some(ref path) => path.clone(),
+            None => {
+                let path = config.config_dir.join("trust.toml");
+                if !path.exists() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read trust policy {}", path.display()))?;
+        let policy: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse trust policy {}", path.display()))?;
+        for rule in &policy.rules {
+            rule.validate()
+                .with_context(|| format!("Invalid trust policy {}", path.display()))?;
+        }
+        Ok(Some(policy))
+    }
+
+    fn rule_for(&self, image: &ImageName) -> Option<&TrustRule> {
+        self.rules.iter().find(|rule| rule.matches(image))
+    }
+}
+
+/// An image reference split up, with Docker Hub names spelled out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageName {
+    /// `registry/repository`
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageName {
+    pub fn parse(image: &str) -> Self {
+        let (rest, digest) = match image.split_once('@') {
+            Some((rest, digest)) => (rest, Some(digest.to_string())),
+            None => (image, None),
+        };
+        let (repository, tag) = match rest.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        let first = repository.split('/').next().unwrap_or_default();
+        let has_registry = repository.contains('/')
+            && (first.contains('.') || first.contains(':') || first == "localhost");
+        let repository = match (has_registry, repository.contains('/')) {
+            (true, _) => repository.to_string(),
+            (false, true) => format!("docker.io/{}", repository),
+            (false, false) => format!("docker.io/library/{}", repository),
+        };
+        Self {
+            repository,
+            tag,
+            digest,
+        }
+    }
+
+    /// The tag, `latest` when there is none
+    pub fn tag(&self) -> &str {
+        self.tag.as_deref().unwrap_or("latest")
+    }
+}
+
+/// Where an image is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Pull,
+    /// A base image in a Dockerfile
+    Build,
+    /// An image a container is started from, by any entry point
+    Run,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Pull => "pull",
+            Stage::Build => "build",
+            Stage::Run => "run",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrustCheck {
+    Signature,
+    Provenance,
+    Digest,
+    Latest,
+    MaxAge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    pub image: String,
+    /// The `images` pattern of the rule that was violated
+    pub rule: String,
+    pub check: TrustCheck,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} (rule '{}')", self.image, self.message, self.rule)
+    }
+}
+
+/// Check `image` against the policy's rule for it
+pub async fn evaluate(runtime: &str, policy: &TrustPolicy, image: &str) -> Vec<Violation> {
+    let name = ImageName::parse(image);
+    let Some(rule) = policy.rule_for(&name) else {
+        return Vec::new();
+    };
+    let mut violations = Vec::new();
+    let mut violate = |check, message: String| {
+        violations.push(Violation {
+            image: image.to_string(),
+            rule: rule.images.clone(),
+            check,
+            message,
+        })
+    };
+
+    if rule.block_latest && name.digest.is_none() && name.tag() == "latest" {
+        violate(TrustCheck::Latest, "uses the latest tag".to_string());
+    }
+    if rule.require_digest && super::pull::pinned_digest(image).is_none() {
+        violate(
+            TrustCheck::Digest,
+            "is not pinned by digest (image@sha256:...)".to_string(),
+        );
+    }
+    if let Some(ref max_age) = rule.max_age {
+        // Validated when the policy was loaded
+        let max = parse_duration(max_age).unwrap_or_default();
+        match created(runtime, image).await {
+            Some(created) => {
+                let age = (Utc::now() - created).to_std().unwrap_or_default();
+                if age > max {
+                    violate(
+                        TrustCheck::MaxAge,
+                        format!(
+                            "was built {} days ago, more than {} allows",
+                            age.as_secs() / 86400,
+                            max_age
+                        ),
+                    );
+                }
+            }
+            None => violate(
+                TrustCheck::MaxAge,
+                "has no creation date to check against max_age".to_string(),
+            ),
+        }
+    }
+    if rule.require_signature {
+        if let Err(reason) = cosign(&["verify"], rule, image).await {
+            violate(
+                TrustCheck::Signature,
+                format!("has no valid signature: {}", reason),
+            );
+        }
+    }
+    if rule.require_provenance {
+        if let Err(reason) = cosign(
+            &["verify-attestation", "--type", "slsaprovenance"],
+            rule,
+            image,
+        )
+        .await
+        {
+            violate(
+                TrustCheck::Provenance,
+                format!("has no valid provenance attestation: {}", reason),
+            );
+        }
+    }
+    violations
+}
+
+async fn cosign(
+    command: &[&str],
+    rule: &TrustRule,
+    image: &str,
+) -> std::result::Result<(), String> {
+    let output = AsyncCommand::new("cosign")
+        .args(command)
+        .args(rule.verifier_args())
+        .arg(image)
+        .output()
+        .await
+        .map_err(|e| format!("cosign is not available ({})", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("verification failed")
+        .trim()
+        .to_string())
+}
+
+/// Creation date of the local image, or of the one in the registry
+async fn created(runtime: &str, image: &str) -> Option<DateTime<Utc>> {
+    let local = AsyncCommand::new(runtime)
+        .args(["image", "inspect", image])
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success());
+    if let Some(created) = local.and_then(|o| parse_created(&String::from_utf8_lossy(&o.stdout))) {
+        return Some(created);
+    }
+    let remote = AsyncCommand::new("skopeo")
+        .arg("inspect")
+        .arg(format!("docker://{}", image))
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())?;
+    parse_created(&String::from_utf8_lossy(&remote.stdout))
+}
+
+/// `Created` of `image inspect` (an array) or `skopeo inspect` (an object)
+fn parse_created(json: &str) -> Option<DateTime<Utc>> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    let object = match value {
+        serde_json::Value::Array(ref items) => items.first()?,
+        ref object => object,
+    };
+    let created = object.get("Created")?.as_str()?;
+    DateTime::parse_from_rfc3339(created)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// The images a Dockerfile builds from, leaving out `scratch`, earlier
+/// stages and references built from ARGs
+pub fn base_images(dockerfile: &str) -> Vec<String> {
+    let mut stages: Vec<String> = Vec::new();
+    let mut images = Vec::new();
+    for line in dockerfile.lines() {
+        let mut words = line.split_whitespace();
+        if !words.next().is_some_and(|w| w.eq_ignore_ascii_case("FROM")) {
+            continue;
+        }
+        let mut words = words.skip_while(|w| w.starts_with("--"));
+        let Some(image) = words.next() else {
+            continue;
+        };
+        let internal = image == "scratch"
+            || image.contains('$')
+            || stages.iter().any(|s| s.eq_ignore_ascii_case(image));
+        if !internal && !images.iter().any(|i| i == image) {
+            images.push(image.to_string());
+        }
+        if words.next().is_some_and(|w| w.eq_ignore_ascii_case("AS")) {
+            if let Some(stage) = words.next() {
+                stages.push(stage.to_string());
+            }
+        }
+    }
+    images
+}
+
+/// Base images of the Dockerfile of a build context
+pub fn build_base_images(context: &Path, dockerfile: &str) -> Result<Vec<String>> {
+    let path = context.join(dockerfile);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(base_images(&content))
+}
+
+/// Refuse `image` when it violates the trust policy, unless an admin
+/// overrides it with `--override-trust`
+pub async fn enforce(config: &BoltConfig, image: &str, stage: Stage) -> Result<()> {
+    let Some(policy) = TrustPolicy::load(config)? else {
+        return Ok(());
+    };
+    let runtime = super::detect_container_runtime().await?;
+    let violations = evaluate(&runtime, &policy, image).await;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    if !config.trust.override_violations {
+        let list: Vec<String> = violations.iter().map(|v| format!("  - {}", v)).collect();
+        return Err(BoltError::Runtime(RuntimeError::UntrustedImage {
+            image: image.to_string(),
+            violations: list.join("\n"),
+        })
+        .into());
+    }
+    if !is_admin(&policy.admin_group) {
+        return Err(anyhow!(
+            "--override-trust needs the admin role: root or a member of group '{}'",
+            policy.admin_group
+        ));
+    }
+    for violation in &violations {
+        warn!("⚠️  Trust policy overridden: {}", violation);
+    }
+    record_override(&config.data_dir, image, stage, &violations)?;
+    info!("🔓 {} let through on {} by an admin", image, stage.as_str());
+    Ok(())
+}
+
+/// Check every base image of a build
+pub async fn enforce_build(config: &BoltConfig, context: &Path, dockerfile: &str) -> Result<()> {
+    if TrustPolicy::load(config)?.is_none() {
+        return Ok(());
+    }
+    for image in build_base_images(context, dockerfile)? {
+        enforce(config, &image, Stage::Build).await?;
+    }
+    Ok(())
+}
+
+fn is_admin(group: &str) -> bool {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } == 0 {
+        return true;
+    }
+    let Ok(name) = std::ffi::CString::new(group) else {
+        return false;
+    };
+    // SAFETY: getgrnam gets a valid C string and returns null or a pointer to
+    // a static record, read right away
+    let gid = unsafe {
+        let entry = libc::getgrnam(name.as_ptr());
+        if entry.is_null() {
+            return false;
+        }
+        (*entry).gr_gid
+    };
+    // SAFETY: getegid has no preconditions
+    if unsafe { libc::getegid() } == gid {
+        return true;
+    }
+    let mut groups = vec![0 as libc::gid_t; 256];
+    // SAFETY: the buffer holds as many entries as we pass
+    let count = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+    count > 0 && groups[..count as usize].contains(&gid)
+}
+
+#[derive(Debug, Serialize)]
+struct OverrideRecord<'a> {
+    at: DateTime<Utc>,
+    user: Option<String>,
+    image: &'a str,
+    stage: Stage,
+    violations: &'a [Violation],
+}
+
+fn overrides_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("trust").join("overrides.log")
+}
+
+fn record_override(
+    data_dir: &Path,
+    image: &str,
+    stage: Stage,
+    violations: &[Violation],
+) -> Result<()> {
+    let path = overrides_path(data_dir);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let record = OverrideRecord {
+        at: Utc::now(),
+        user: super::workspace::current_user(),
+        image,
+        stage,
+        violations,
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn matches_rules_and_reports_tag_and_digest_violations() {
+        assert_eq!(
+            ImageName::parse("nginx"),
+            ImageName {
+                repository: "docker.io/library/nginx".to_string(),
+                tag: None,
+                digest: None,
+            }
+        );
+        let name = ImageName::parse("localhost:5000/arena/server:1.4@sha256:abc");
+        assert_eq!(name.repository, "localhost:5000/arena/server");
+        assert_eq!(name.tag(), "1.4");
+        assert_eq!(name.digest.as_deref(), Some("sha256:abc"));
+
+        let policy: TrustPolicy = toml::from_str(
+            r#"
+            [[rule]]
+            images = "docker.io/library/nginx:1.*"
+
+            [[rule]]
+            images = "docker.io/library/*"
+            block_latest = true
+            require_digest = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(policy.admin_group, "bolt-admin");
+        assert!(evaluate("docker", &policy, "nginx:1.25").await.is_empty());
+        assert!(
+            evaluate("docker", &policy, "ghcr.io/acme/tool")
+                .await
+                .is_empty()
+        );
+        let checks: Vec<TrustCheck> = evaluate("docker", &policy, "redis")
+            .await
+            .into_iter()
+            .map(|v| v.check)
+            .collect();
+        assert_eq!(checks, vec![TrustCheck::Latest, TrustCheck::Digest]);
+
+        let unsigned = TrustRule {
+            images: "*".to_string(),
+            require_signature: true,
+            ..Default::default()
+        };
+        assert!(unsigned.validate().is_err());
+
+        let dockerfile = "ARG BASE=alpine\nFROM --platform=$BUILDPLATFORM golang:1.22 AS build\nFROM ${BASE}\nFROM build AS test\nFROM scratch\nCOPY --from=build /app /app\nFROM gcr.io/distroless/static\n";
+        assert_eq!(
+            base_images(dockerfile),
+            vec!["golang:1.22", "gcr.io/distroless/static"]
+        );
+        assert_eq!(
+            parse_created(r#"[{"Id": "sha256:1", "Created": "2024-05-01T10:00:00.5Z"}]"#)
+                .map(|d| d.to_rfc3339()),
+            Some("2024-05-01T10:00:00.500+00:00".to_string())
+        );
+    }
+}
@@ -0,0 +1,711 @@
+// WebAssembly workloads
+//
+// A service with `runtime = "wasm"` runs a WASI module under wasmtime instead
+// of an OCI container:
+//
+//   [services.thumbnailer]
+//   runtime = "wasm"
+//   command = ["--quality", "80"]
+//   volumes = ["./media:/media:ro"]
+//
+//   [services.thumbnailer.wasm]
+//   module = "thumbnailer.wasm"
+//   max_memory = "128MB"
+//
+// Components get WASI 0.2 (preview2); core modules get preview1 on top of the
+// same implementation. `command` becomes the module's arguments, `env` its
+// environment and each `host:guest[:ro]` volume a preopened directory. Memory
+// (`max_memory`, default `memory_limit`), fuel and a wall-clock `timeout` are
+// enforced by the engine.
+//
+// Each workload runs in its own `bolt wasm-exec` process so that, like a
+// detached container, it outlives the CLI. Its files live in
+// `<data_dir>/wasm/<name>/`: `spec.json` (what to run), `state.json` (pid and
+// exit code) and `output.log`, which surge uses for status, logs and stop.
+// Executing modules needs the `wasm-runtime` feature.
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use super::watcher::{self, Watcher};
+use crate::builds::cache::{parse_duration, parse_size};
+use crate::config::Service;
+
+const SPEC_FILE: &str = "spec.json";
+const STATE_FILE: &str = "state.json";
+const LOG_FILE: &str = "output.log";
+
+/// What executes a service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceRuntime {
+    /// An OCI image run by podman or docker
+    #[default]
+    Oci,
+    /// A WebAssembly module run by wasmtime
+    Wasm,
+}
+
+/// A host directory the module can open
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preopen {
+    pub host: PathBuf,
+    pub guest: String,
+    pub read_only: bool,
+}
+
+/// Everything `bolt wasm-exec` needs to run a module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmSpec {
+    pub name: String,
+    pub module: PathBuf,
+    /// argv, starting with the module's file name
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub preopens: Vec<Preopen>,
+    pub max_memory: Option<u64>,
+    pub fuel: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub network: bool,
+}
+
+impl WasmSpec {
+    /// Build the spec of `service`, resolving relative paths against `base_dir`
+    /// (the Boltfile's directory)
+    pub fn from_service(name: &str, service: &Service, base_dir: &Path) -> Result<Self> {
+        let wasm = service.wasm.as_ref().ok_or_else(|| {
+            anyhow!("runtime = \"wasm\" needs a [wasm] table naming the module to run")
+        })?;
+        if service.image.is_some() || service.build.is_some() || service.capsule.is_some() {
+            return Err(anyhow!(
+                "runtime = \"wasm\" runs wasm.module; remove image, build and capsule"
+            ));
+        }
+        if service.ports.as_ref().is_some_and(|p| !p.is_empty()) {
+            return Err(anyhow!(
+                "ports are not published for WASM services; set wasm.network = true and listen on the host"
+            ));
+        }
+
+        let module = base_dir.join(&wasm.module);
+        let mut args = vec![
+            module
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| wasm.module.clone()),
+        ];
+        args.extend(service.command.iter().flatten().cloned());
+
+        let env: BTreeMap<String, String> = service
+            .environment
+            .iter()
+            .chain(service.env.iter())
+            .flatten()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        let preopens = service
+            .volumes
+            .iter()
+            .flatten()
+            .map(|v| parse_preopen(v, base_dir))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            module,
+            args,
+            env: env.into_iter().collect(),
+            preopens,
+            max_memory: wasm
+                .max_memory
+                .as_deref()
+                .or(service.memory_limit.as_deref())
+                .map(parse_size)
+                .transpose()?,
+            fuel: wasm.fuel,
+            timeout: wasm.timeout.as_deref().map(parse_duration).transpose()?,
+            network: wasm.network,
+        })
+    }
+}
+
+/// `./data:/data:ro` -> a read-only preopen of `<base_dir>/data` at `/data`
+fn parse_preopen(volume: &str, base_dir: &Path) -> Result<Preopen> {
+    let parts: Vec<&str> = volume.split(':').collect();
+    let (host, guest, mode) = match parts.as_slice() {
+        [host, guest] => (*host, *guest, None),
+        [host, guest, mode] => (*host, *guest, Some(*mode)),
+        _ => {
+            return Err(anyhow!(
+                "Invalid volume '{}' for a WASM service: expected host:guest[:ro]",
+                volume
+            ));
+        }
+    };
+    if !host.starts_with('/') && !host.starts_with('.') {
+        return Err(anyhow!(
+            "Volume '{}': WASM services can only mount host directories, not named volumes",
+            volume
+        ));
+    }
+    let read_only = match mode {
+        None | Some("rw") => false,
+        Some("ro") => true,
+        Some(other) => {
+            return Err(anyhow!(
+                "Volume '{}': unknown mode '{}' (use ro or rw)",
+                volume,
+                other
+            ));
+        }
+    };
+    Ok(Preopen {
+        host: base_dir.join(host),
+        guest: guest.to_string(),
+        read_only,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WasmStatus {
+    Running,
+    Exited,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmState {
+    pub name: String,
+    pub module: PathBuf,
+    pub pid: u32,
+    pub status: WasmStatus,
+    pub exit_code: Option<i32>,
+    /// Why the module stopped, when it trapped or failed to load
+    pub error: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl WasmState {
+    pub fn is_running(&self) -> bool {
+        self.status == WasmStatus::Running && EXEC.alive(self.pid)
+    }
+
+    /// Status as shown next to containers: "running", "exited (0)"
+    pub fn describe(&self) -> String {
+        if self.is_running() {
+            return "running".to_string();
+        }
+        match self.exit_code {
+            Some(code) => format!("exited ({})", code),
+            None => "exited".to_string(),
+        }
+    }
+}
+
+/// Each workload's own `bolt wasm-exec` process
+const EXEC: Watcher = Watcher::new("wasm-exec");
+
+fn workload_dir(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir.join("wasm").join(name)
+}
+
+pub fn state(data_dir: &Path, name: &str) -> Option<WasmState> {
+    watcher::load(&workload_dir(data_dir, name).join(STATE_FILE))
+}
+
+/// All known WASM workloads, running or not
+pub fn list(data_dir: &Path) -> Vec<WasmState> {
+    let Ok(entries) = std::fs::read_dir(data_dir.join("wasm")) else {
+        return Vec::new();
+    };
+    let mut states: Vec<WasmState> = entries
+        .flatten()
+        .filter_map(|e| state(data_dir, &e.file_name().to_string_lossy()))
+        .collect();
+    states.sort_by(|a, b| a.name.cmp(&b.name));
+    states
+}
+
+fn write_state(dir: &Path, state: &WasmState) -> Result<()> {
+    watcher::save(&dir.join(STATE_FILE), state)
+}
+
+/// Start a workload. Detached workloads log to `output.log`; otherwise the
+/// module's output goes to the terminal and this waits for it to exit.
+pub async fn start(data_dir: &Path, spec: &WasmSpec, detach: bool) -> Result<()> {
+    if !cfg!(feature = "wasm-runtime") {
+        return Err(anyhow!(
+            "{} needs WASM support; rebuild bolt with --features wasm-runtime",
+            spec.name
+        ));
+    }
+    if let Some(state) = state(data_dir, &spec.name) {
+        if state.is_running() {
+            return Err(anyhow!(
+                "WASM workload {} is already running (pid {})",
+                spec.name,
+                state.pid
+            ));
+        }
+    }
+    if !spec.module.is_file() {
+        return Err(anyhow!("WASM module {} not found", spec.module.display()));
+    }
+
+    let dir = workload_dir(data_dir, &spec.name);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let spec_path = dir.join(SPEC_FILE);
+    std::fs::write(&spec_path, serde_json::to_vec_pretty(spec)?)?;
+
+    info!("🧩 Starting WASM workload {}", spec.name);
+
+    if detach {
+        let pid = EXEC.spawn(
+            [OsStr::new("wasm-exec"), spec_path.as_os_str()],
+            &dir.join(LOG_FILE),
+        )?;
+        info!("✅ WASM workload {} running (pid {})", spec.name, pid);
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().context("Cannot find the bolt executable")?;
+    let status = tokio::process::Command::new(exe)
+        .arg("wasm-exec")
+        .arg(&spec_path)
+        .status()
+        .await
+        .context("Failed to start bolt wasm-exec")?;
+    if !status.success() {
+        return Err(anyhow!(
+            "WASM workload {} exited with {}",
+            spec.name,
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// Run the module described by `spec_path` in this process, recording its
+/// state. Returns the module's exit code.
+pub async fn exec(spec_path: &Path) -> Result<i32> {
+    let spec: WasmSpec = serde_json::from_slice(
+        &std::fs::read(spec_path)
+            .with_context(|| format!("Failed to read {}", spec_path.display()))?,
+    )?;
+    let dir = spec_path
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid spec path {}", spec_path.display()))?;
+
+    let mut state = WasmState {
+        name: spec.name.clone(),
+        module: spec.module.clone(),
+        pid: std::process::id(),
+        status: WasmStatus::Running,
+        exit_code: None,
+        error: None,
+        started_at: chrono::Utc::now(),
+        finished_at: None,
+    };
+    write_state(dir, &state)?;
+
+    let module = spec.clone();
+    let result = tokio::task::spawn_blocking(move || run_module(&module)).await?;
+
+    state.status = WasmStatus::Exited;
+    state.finished_at = Some(chrono::Utc::now());
+    let code = match result {
+        Ok(code) => code,
+        Err(e) => {
+            warn!("WASM workload {} failed: {:#}", spec.name, e);
+            state.error = Some(format!("{:#}", e));
+            1
+        }
+    };
+    state.exit_code = Some(code);
+    write_state(dir, &state)?;
+    debug!("WASM workload {} exited with {}", spec.name, code);
+    Ok(code)
+}
+
+/// Stop a workload, killing it if it is still running after `timeout`
+pub async fn stop(data_dir: &Path, name: &str, timeout: Duration) -> Result<()> {
+    let Some(mut state) = state(data_dir, name) else {
+        return Err(anyhow!("No WASM workload named {}", name));
+    };
+    if !state.is_running() {
+        return Ok(());
+    }
+    info!("🛑 Stopping WASM workload: {}", name);
+
+    EXEC.signal(state.pid, libc::SIGTERM);
+    let started = Instant::now();
+    let mut exit_code = 143;
+    while EXEC.alive(state.pid) {
+        if exit_code != 137 && started.elapsed() >= timeout {
+            warn!(
+                "{} did not stop in {}s; killing it",
+                name,
+                timeout.as_secs()
+            );
+            EXEC.signal(state.pid, libc::SIGKILL);
+            exit_code = 137;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // A signalled wasm-exec can't record its own exit
+    state.status = WasmStatus::Exited;
+    state.exit_code = Some(exit_code);
+    state.finished_at = Some(chrono::Utc::now());
+    write_state(&workload_dir(data_dir, name), &state)?;
+    info!("✅ WASM workload stopped: {}", name);
+    Ok(())
+}
+
+/// Forget a stopped workload, including its logs
+pub fn remove(data_dir: &Path, name: &str) -> Result<()> {
+    if state(data_dir, name).is_some_and(|s| s.is_running()) {
+        return Err(anyhow!("WASM workload {} is still running", name));
+    }
+    let dir = workload_dir(data_dir, name);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)
+            .with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Print a workload's output, like `docker logs`
+pub async fn logs(data_dir: &Path, name: &str, follow: bool, tail: Option<usize>) -> Result<()> {
+    let path = workload_dir(data_dir, name).join(LOG_FILE);
+    if !workload_dir(data_dir, name).exists() {
+        return Err(anyhow!("No WASM workload named {}", name));
+    }
+    let content = std::fs::read_to_string(&path).unwrap_or_default();
+    let lines: Vec<&str> = content.lines().collect();
+    let skip = tail.map(|n| lines.len().saturating_sub(n)).unwrap_or(0);
+    for line in &lines[skip..] {
+        println!("{}", line);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut offset = content.len() as u64;
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut appended = String::new();
+        if let Ok(mut file) = std::fs::File::open(&path) {
+            file.seek(SeekFrom::Start(offset))?;
+            offset += file.read_to_string(&mut appended)? as u64;
+        }
+        print!("{}", appended);
+        if appended.is_empty() && !state(data_dir, name).is_some_and(|s| s.is_running()) {
+            return Ok(());
+        }
+    }
+}
+
+/// Resource usage of a running workload, from its wasm-exec process
+pub fn metrics(data_dir: &Path, state: &WasmState) -> Option<crate::monitoring::ContainerMetrics> {
+    if !state.is_running() {
+        return None;
+    }
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", state.pid)).ok()?;
+    // Fields after the parenthesised command name, starting at the state (3rd)
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let cpu_ticks: u64 =
+        fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    let resident_pages: u64 = std::fs::read_to_string(format!("/proc/{}/statm", state.pid))
+        .ok()?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+
+    // SAFETY: sysconf has no memory safety requirements
+    let (ticks_per_sec, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+    let uptime = (chrono::Utc::now() - state.started_at)
+        .num_milliseconds()
+        .max(1) as f64
+        / 1000.0;
+    let cpu_seconds = cpu_ticks as f64 / ticks_per_sec.max(1) as f64;
+
+    let io = std::fs::read_to_string(format!("/proc/{}/io", state.pid)).unwrap_or_default();
+    let io_field = |name: &str| {
+        io.lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0)
+    };
+    let memory_limit = std::fs::read(workload_dir(data_dir, &state.name).join(SPEC_FILE))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<WasmSpec>(&b).ok())
+        .and_then(|spec| spec.max_memory)
+        .unwrap_or(0);
+
+    Some(crate::monitoring::ContainerMetrics {
+        container_id: format!("wasm-{}", state.pid),
+        name: state.name.clone(),
+        status: state.describe(),
+        cpu_usage_percent: cpu_seconds / uptime * 100.0,
+        memory_usage_bytes: resident_pages * page_size.max(0) as u64,
+        memory_limit_bytes: memory_limit,
+        network_rx_bytes: 0,
+        network_tx_bytes: 0,
+        disk_read_bytes: io_field("read_bytes:"),
+        disk_write_bytes: io_field("write_bytes:"),
+        uptime_seconds: uptime as u64,
+        restart_count: 0,
+        exit_code: None,
+        labels: Default::default(),
+        last_updated: std::time::SystemTime::now(),
+    })
+}
+
+#[cfg(not(feature = "wasm-runtime"))]
+fn run_module(_spec: &WasmSpec) -> Result<i32> {
+    Err(anyhow!(
+        "This build of bolt has no WASM support; rebuild with --features wasm-runtime"
+    ))
+}
+
+#[cfg(feature = "wasm-runtime")]
+fn run_module(spec: &WasmSpec) -> Result<i32> {
+    use wasmtime::{Config, Engine, StoreLimitsBuilder, Trap};
+    use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+    let mut config = Config::new();
+    config.consume_fuel(spec.fuel.is_some());
+    config.epoch_interruption(spec.timeout.is_some());
+    let engine = Engine::new(&config)?;
+
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.inherit_stdio().args(&spec.args).envs(&spec.env);
+    if spec.network {
+        wasi.inherit_network().allow_ip_name_lookup(true);
+    }
+    for preopen in &spec.preopens {
+        let (dir_perms, file_perms) = if preopen.read_only {
+            (DirPerms::READ, FilePerms::READ)
+        } else {
+            (DirPerms::all(), FilePerms::all())
+        };
+        wasi.preopened_dir(&preopen.host, &preopen.guest, dir_perms, file_perms)
+            .with_context(|| format!("Failed to open {}", preopen.host.display()))?;
+    }
+
+    let mut limits = StoreLimitsBuilder::new();
+    if let Some(max_memory) = spec.max_memory {
+        limits = limits.memory_size(max_memory as usize);
+    }
+    let limits = limits.build();
+
+    if let Some(timeout) = spec.timeout {
+        let engine = engine.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            engine.increment_epoch();
+        });
+    }
+
+    let bytes = std::fs::read(&spec.module)
+        .with_context(|| format!("Failed to read {}", spec.module.display()))?;
+    let result = if is_component(&bytes) {
+        info!("🧩 Running {} as a WASI 0.2 component", spec.name);
+        engine::run_component(&engine, &bytes, wasi.build(), limits, spec)
+    } else {
+        info!("🧩 Running {} as a WASI preview1 module", spec.name);
+        engine::run_core_module(&engine, &bytes, wasi.build_p1(), limits, spec)
+    };
+
+    match result {
+        Ok(code) => Ok(code),
+        Err(e) => {
+            if let Some(exit) = e.downcast_ref::<I32Exit>() {
+                return Ok(exit.0);
+            }
+            match e.downcast_ref::<Trap>() {
+                Some(Trap::OutOfFuel) => Err(anyhow!("{} ran out of fuel", spec.name)),
+                Some(Trap::Interrupt) => Err(anyhow!(
+                    "{} exceeded its timeout of {}s",
+                    spec.name,
+                    spec.timeout.unwrap_or_default().as_secs()
+                )),
+                _ => Err(e),
+            }
+        }
+    }
+}
+
+/// Components and core modules share the `\0asm` magic but differ in the
+/// layer field that follows the version
+#[cfg_attr(not(feature = "wasm-runtime"), allow(dead_code))]
+fn is_component(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\0asm") && bytes.get(6..8) == Some(&[1, 0])
+}
+
+#[cfg(feature = "wasm-runtime")]
+mod engine {
+    use super::WasmSpec;
+    use anyhow::{Result, anyhow};
+    use wasmtime::component::{Component, Linker as ComponentLinker, ResourceTable};
+    use wasmtime::{Engine, Linker, Module, Store, StoreLimits};
+    use wasmtime_wasi::preview1::WasiP1Ctx;
+    use wasmtime_wasi::{IoView, WasiCtx, WasiView};
+
+    struct ComponentState {
+        ctx: WasiCtx,
+        table: ResourceTable,
+        limits: StoreLimits,
+    }
+
+    impl IoView for ComponentState {
+        fn table(&mut self) -> &mut ResourceTable {
+            &mut self.table
+        }
+    }
+
+    impl WasiView for ComponentState {
+        fn ctx(&mut self) -> &mut WasiCtx {
+            &mut self.ctx
+        }
+    }
+
+    struct ModuleState {
+        ctx: WasiP1Ctx,
+        limits: StoreLimits,
+    }
+
+    fn configure<T>(store: &mut Store<T>, spec: &WasmSpec) -> Result<()> {
+        if let Some(fuel) = spec.fuel {
+            store.set_fuel(fuel)?;
+        }
+        if spec.timeout.is_some() {
+            store.set_epoch_deadline(1);
+        }
+        Ok(())
+    }
+
+    pub(super) fn run_component(
+        engine: &Engine,
+        bytes: &[u8],
+        ctx: WasiCtx,
+        limits: StoreLimits,
+        spec: &WasmSpec,
+    ) -> Result<i32> {
+        let component = Component::from_binary(engine, bytes)?;
+        let mut linker = ComponentLinker::new(engine);
+        wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+
+        let mut store = Store::new(
+            engine,
+            ComponentState {
+                ctx,
+                table: ResourceTable::new(),
+                limits,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        configure(&mut store, spec)?;
+
+        let command =
+            wasmtime_wasi::bindings::sync::Command::instantiate(&mut store, &component, &linker)?;
+        match command.wasi_cli_run().call_run(&mut store)? {
+            Ok(()) => Ok(0),
+            Err(()) => Ok(1),
+        }
+    }
+
+    pub(super) fn run_core_module(
+        engine: &Engine,
+        bytes: &[u8],
+        ctx: WasiP1Ctx,
+        limits: StoreLimits,
+        spec: &WasmSpec,
+    ) -> Result<i32> {
+        let module = Module::new(engine, bytes)?;
+        let mut linker = Linker::new(engine);
+        wasmtime_wasi::preview1::add_to_linker_sync(&mut linker, |state: &mut ModuleState| {
+            &mut state.ctx
+        })?;
+
+        let mut store = Store::new(engine, ModuleState { ctx, limits });
+        store.limiter(|state| &mut state.limits);
+        configure(&mut store, spec)?;
+
+        let instance = linker.instantiate(&mut store, &module)?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|_| anyhow!("{} has no _start export; is it a WASI command?", spec.name))?;
+        start.call(&mut store, ())?;
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WasmConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn builds_spec_from_service() {
+        let service = Service {
+            runtime: Some(ServiceRuntime::Wasm),
+            command: Some(vec!["--quality".to_string(), "80".to_string()]),
+            env: Some(HashMap::from([("MODE".to_string(), "fast".to_string())])),
+            volumes: Some(vec![
+                "./media:/media:ro".to_string(),
+                "/tmp/out:/out".to_string(),
+            ]),
+            memory_limit: Some("64MB".to_string()),
+            wasm: Some(WasmConfig {
+                module: "thumbs.wasm".to_string(),
+                timeout: Some("30s".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let spec = WasmSpec::from_service("app_thumbs", &service, Path::new("/srv/app")).unwrap();
+        assert_eq!(spec.module, Path::new("/srv/app/thumbs.wasm"));
+        assert_eq!(spec.args, vec!["thumbs.wasm", "--quality", "80"]);
+        assert_eq!(spec.env, vec![("MODE".to_string(), "fast".to_string())]);
+        assert_eq!(
+            spec.preopens[0],
+            Preopen {
+                host: PathBuf::from("/srv/app/./media"),
+                guest: "/media".to_string(),
+                read_only: true,
+            }
+        );
+        assert!(!spec.preopens[1].read_only);
+        assert_eq!(spec.max_memory, Some(parse_size("64MB").unwrap()));
+        assert_eq!(spec.timeout, Some(Duration::from_secs(30)));
+
+        let mut named = service.clone();
+        named.volumes = Some(vec!["cache:/cache".to_string()]);
+        assert!(WasmSpec::from_service("x", &named, Path::new(".")).is_err());
+        let mut with_image = service;
+        with_image.image = Some("nginx".to_string());
+        assert!(WasmSpec::from_service("x", &with_image, Path::new(".")).is_err());
+
+        let mut header = b"\0asm".to_vec();
+        header.extend([0x0d, 0, 1, 0]);
+        assert!(is_component(&header));
+        assert!(!is_component(b"\0asm\x01\0\0\0"));
+    }
+}
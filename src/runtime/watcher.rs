@@ -0,0 +1,230 @@
+// Detached helper processes
+//
+// Features that keep working after the bolt command returns (idle
+// suspension, egress sync, log hooks, health and restart supervision, ...)
+// start a process of their own, usually `bolt <hidden subcommand>
+// <container>`, in its own process group and with its output appended to a
+// log. The pid goes into the feature's JSON state file. Pids are reused, so a
+// recorded pid only counts as the helper while `/proc/<pid>/cmdline` still
+// has the helper's marker argument.
+use anyhow::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Stdio;
+
+/// A kind of helper process, recognised by its marker argument
+#[derive(Debug, Clone, Copy)]
+pub struct Watcher {
+    /// An argument that is, or is a path ending in, this name
+    marker: &'static str,
+    /// Signal the whole process group, for helpers whose children must go too
+    group: bool,
+}
+
+impl Watcher {
+    pub const fn new(marker: &'static str) -> Self {
+        Self {
+            marker,
+            group: false,
+        }
+    }
+
+    /// A helper that is stopped together with the processes it starts
+    pub const fn group(marker: &'static str) -> Self {
+        Self {
+            marker,
+            group: true,
+        }
+    }
+
+    /// Start `bolt <args>` detached, appending its output to `log`
+    pub fn spawn<I, S>(&self, args: I, log: &Path) -> Result<u32>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let exe = std::env::current_exe().context("Cannot find the bolt executable")?;
+        self.spawn_program(&exe, args, log)
+    }
+
+    /// Start `program <args>` detached, appending its output to `log`
+    pub fn spawn_program<I, S>(&self, program: &Path, args: I, log: &Path) -> Result<u32>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        use std::os::unix::process::CommandExt;
+
+        if let Some(dir) = log.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .with_context(|| format!("Failed to open {}", log.display()))?;
+        let child = std::process::Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .process_group(0)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.marker))?;
+        Ok(child.id())
+    }
+
+    /// Whether `pid` is still this helper and not a reused pid. Programs
+    /// like sshd retitle themselves into one space-separated string, so the
+    /// words of each argument count too.
+    pub fn alive(&self, pid: u32) -> bool {
+        std::fs::read(format!("/proc/{}/cmdline", pid))
+            .is_ok_and(|cmdline| marked(&cmdline, self.marker.as_bytes()))
+    }
+
+    /// Send `signal` to the helper if it is still running; false if it wasn't
+    pub fn signal(&self, pid: u32, signal: libc::c_int) -> bool {
+        if !self.alive(pid) {
+            return false;
+        }
+        let target = if self.group {
+            -(pid as libc::pid_t)
+        } else {
+            pid as libc::pid_t
+        };
+        // SAFETY: kill(2) has no memory safety requirements
+        unsafe { libc::kill(target, signal) == 0 }
+    }
+
+    /// Ask the helper to exit; false if it wasn't running
+    pub fn stop(&self, pid: u32) -> bool {
+        self.signal(pid, libc::SIGTERM)
+    }
+
+    /// Stop the helper recorded in `state` and remove that state file
+    pub fn forget(&self, state: &Path, pid: Option<u32>) {
+        if let Some(pid) = pid {
+            self.stop(pid);
+        }
+        let _ = std::fs::remove_file(state);
+    }
+}
+
+fn marked(cmdline: &[u8], marker: &[u8]) -> bool {
+    cmdline
+        .split(|b| *b == 0 || *b == b' ')
+        .any(|arg| marks(arg, marker))
+}
+
+fn marks(arg: &[u8], marker: &[u8]) -> bool {
+    arg == marker
+        || arg
+            .strip_suffix(marker)
+            .is_some_and(|prefix| prefix.ends_with(b"/"))
+}
+
+/// Read a state file; None if it is missing or unreadable
+pub fn load<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+/// Every readable `*.json` state file in `dir`
+pub fn list<T: DeserializeOwned>(dir: &Path) -> Vec<T> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .filter_map(|e| load(&e.path()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Write a state file, creating its directory
+pub fn save<T: Serialize>(path: &Path, state: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Readers never see a half-written file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Save a helper's changes unless the record was removed meanwhile, e.g. by
+/// `surge down`
+pub fn update<T: Serialize>(path: &Path, state: &T) -> Result<()> {
+    if path.exists() {
+        save(path, state)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_helpers_and_keeps_state_files() {
+        assert!(marks(b"idle-watch", b"idle-watch"));
+        assert!(marks(b"/srv/ssh/web/sshd_config", b"sshd_config"));
+        assert!(marks(b"/usr/bin/gamescope", b"gamescope"));
+        assert!(!marks(b"not-idle-watch", b"idle-watch"));
+        assert!(!marks(b"idle-watch-2", b"idle-watch"));
+        assert!(marked(b"/usr/bin/bolt\0idle-watch\0web\0", b"idle-watch"));
+        assert!(marked(
+            b"sshd: /usr/sbin/sshd -D -e -f /srv/ssh/web/sshd_config [listener] 0 of 10-100 startups\0",
+            b"sshd_config"
+        ));
+        assert!(!marked(b"/usr/bin/bolt\0log-watch\0web\0", b"idle-watch"));
+
+        // This test process isn't a helper, and pid 0 never is
+        let watcher = Watcher::new("idle-watch");
+        assert!(!watcher.alive(std::process::id()));
+        assert!(!watcher.stop(0));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("idle").join("web.json");
+        update(&path, &42u32).unwrap();
+        assert!(!path.exists());
+        save(&path, &42u32).unwrap();
+        update(&path, &43u32).unwrap();
+        assert_eq!(load::<u32>(&path), Some(43));
+        std::fs::write(dir.path().join("idle").join("web.log"), "").unwrap();
+        assert_eq!(list::<u32>(&dir.path().join("idle")), vec![43]);
+
+        watcher.forget(&path, None);
+        assert!(load::<u32>(&path).is_none());
+    }
+
+    #[test]
+    fn spawned_helpers_are_alive_until_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = Watcher::new("bolt-test-helper");
+        let log = dir.path().join("helpers").join("test.log");
+        let pid = watcher
+            .spawn_program(
+                Path::new("/bin/sh"),
+                ["-c", "echo started; sleep 30", "bolt-test-helper"],
+                &log,
+            )
+            .unwrap();
+        assert!(watcher.alive(pid));
+        assert!(!Watcher::new("other-helper").alive(pid));
+
+        assert!(watcher.stop(pid));
+        let stopped = (0..50).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            !watcher.alive(pid)
+        });
+        assert!(stopped);
+        assert!(std::fs::read_to_string(&log).unwrap().contains("started"));
+    }
+}
@@ -0,0 +1,696 @@
+// Workspaces
+//
+// A workspace gives the users assigned to it their own namespace on a shared
+// host, with a quota. Whatever a member creates is named `<workspace>.<name>`:
+// containers from `bolt run`, Surge projects and so their containers, the
+// networks a Boltfile declares and named volumes. Names without the prefix
+// refer to the member's own workspace, so Boltfiles work unchanged.
+//
+// Quotas are checked at admission, before a container starts, and running
+// containers are labelled `bolt.workspace=<workspace>` so they can be counted:
+//
+// - cpus and memory: the limits of the workspace's running containers add up
+//   to at most the quota. A service's `cpu_limit`/`memory_limit` become its
+//   container's limits; a container without one gets what is left.
+// - gpu_hours: containers of services with a GPU are accounted from start to
+//   stop, and new GPU containers are refused once the hours are used up.
+// - disk: new containers are refused while the workspace's volumes use more.
+//
+// Workspaces and user assignments are kept in `<data_dir>/workspaces.json`;
+// changing them needs root. The member is the invoking user (`SUDO_USER`
+// under sudo, otherwise `USER`); root picks a workspace with `BOLT_WORKSPACE`.
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info};
+
+use crate::builds::cache::format_size;
+use crate::config::BoltFile;
+
+/// Label identifying a container's workspace
+pub const LABEL: &str = "bolt.workspace";
+const STORE_FILE: &str = "workspaces.json";
+const NAME_MAX: usize = 32;
+/// Smallest memory limit container runtimes accept
+const MEMORY_MIN: u64 = 6 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Quota {
+    pub cpus: Option<f64>,
+    /// Bytes
+    pub memory: Option<u64>,
+    pub gpu_hours: Option<f64>,
+    /// Bytes
+    pub disk: Option<u64>,
+}
+
+/// A GPU container being accounted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuSession {
+    pub started_at: DateTime<Utc>,
+    /// Last time the container was seen running
+    pub last_seen: DateTime<Utc>,
+}
+
+impl GpuSession {
+    fn seconds_until(&self, end: DateTime<Utc>) -> u64 {
+        (end - self.started_at).num_seconds().max(0) as u64
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    pub quota: Quota,
+    pub created_at: DateTime<Utc>,
+    /// GPU time of containers that have stopped
+    #[serde(default)]
+    pub gpu_seconds: u64,
+    /// GPU containers still running, by container name
+    #[serde(default)]
+    pub gpu_sessions: BTreeMap<String, GpuSession>,
+}
+
+impl Workspace {
+    /// GPU hours used, counting running containers up to `now`
+    pub fn gpu_hours(&self, now: DateTime<Utc>) -> f64 {
+        let open: u64 = self
+            .gpu_sessions
+            .values()
+            .map(|s| s.seconds_until(now))
+            .sum();
+        (self.gpu_seconds + open) as f64 / 3600.0
+    }
+}
+
+/// `<data_dir>/workspaces.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceStore {
+    #[serde(default)]
+    pub workspaces: BTreeMap<String, Workspace>,
+    /// User name -> workspace
+    #[serde(default)]
+    pub users: BTreeMap<String, String>,
+}
+
+impl WorkspaceStore {
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = store_path(data_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(data_dir)?;
+        let path = store_path(data_dir);
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Workspace> {
+        self.workspaces
+            .get(name)
+            .ok_or_else(|| anyhow!("Workspace '{}' does not exist", name))
+    }
+
+    pub fn members(&self, workspace: &str) -> Vec<String> {
+        self.users
+            .iter()
+            .filter(|(_, w)| *w == workspace)
+            .map(|(user, _)| user.clone())
+            .collect()
+    }
+}
+
+fn store_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(STORE_FILE)
+}
+
+/// The user Bolt acts for: the account of the real uid, or whoever ran
+/// `sudo` when running as root. `$USER` is never trusted.
+pub fn current_user() -> Option<String> {
+    if is_root()
+        && let Some(user) = std::env::var("SUDO_USER").ok().filter(|u| !u.is_empty())
+    {
+        return Some(user);
+    }
+    // SAFETY: getuid has no preconditions
+    user_name(unsafe { libc::getuid() })
+}
+
+/// The passwd entry name of `uid`
+fn user_name(uid: libc::uid_t) -> Option<String> {
+    // SAFETY: getpwuid returns null or a pointer to static storage that
+    // stays valid until the next call, and the name is copied out at once
+    unsafe {
+        let passwd = libc::getpwuid(uid);
+        if passwd.is_null() {
+            return None;
+        }
+        Some(
+            std::ffi::CStr::from_ptr((*passwd).pw_name)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions
+    unsafe { libc::geteuid() == 0 }
+}
+
+pub fn require_root(action: &str) -> Result<()> {
+    if is_root() {
+        Ok(())
+    } else {
+        Err(anyhow!("Only root can {}", action))
+    }
+}
+
+/// The workspace the invoking user works in, if any
+pub fn resolve(data_dir: &Path) -> Result<Option<String>> {
+    let store = WorkspaceStore::load(data_dir)?;
+    let explicit = std::env::var("BOLT_WORKSPACE")
+        .ok()
+        .filter(|w| !w.is_empty());
+    let assigned = current_user().and_then(|user| store.users.get(&user).cloned());
+    match (explicit, assigned) {
+        (Some(explicit), Some(assigned)) if explicit != assigned => Err(anyhow!(
+            "BOLT_WORKSPACE={} but you are assigned to workspace '{}'",
+            explicit,
+            assigned
+        )),
+        (Some(_), None) if !is_root() => Err(anyhow!(
+            "Only root can choose a workspace with BOLT_WORKSPACE"
+        )),
+        (Some(explicit), _) => {
+            store.get(&explicit)?;
+            Ok(Some(explicit))
+        }
+        (None, assigned) => Ok(assigned),
+    }
+}
+
+/// A resource name inside a workspace
+pub fn scoped(workspace: &str, name: &str) -> String {
+    if is_scoped(workspace, name) {
+        name.to_string()
+    } else {
+        format!("{}.{}", workspace, name)
+    }
+}
+
+pub fn is_scoped(workspace: &str, name: &str) -> bool {
+    name.strip_prefix(workspace)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// `name:/path[:opts]` with a named volume scoped; bind mounts are kept
+pub fn scoped_volume(workspace: &str, spec: &str) -> String {
+    match spec.split_once(':') {
+        Some((source, rest))
+            if !source.is_empty()
+                && !source.contains('/')
+                && !source.starts_with('.')
+                && !source.starts_with('~') =>
+        {
+            format!("{}:{}", scoped(workspace, source), rest)
+        }
+        _ => spec.to_string(),
+    }
+}
+
+/// Move a Boltfile's project, declared networks and named volumes into
+/// the workspace
+pub fn scope_boltfile(workspace: &str, boltfile: &mut BoltFile) {
+    boltfile.project = scoped(workspace, &boltfile.project);
+    for (key, network) in boltfile.networks.iter_mut().flatten() {
+        if network.external.unwrap_or(false) {
+            continue;
+        }
+        let name = network.name.as_deref().unwrap_or(key);
+        network.name = Some(scoped(workspace, name));
+    }
+    for service in boltfile.services.values_mut() {
+        for volume in service.volumes.iter_mut().flatten() {
+            *volume = scoped_volume(workspace, volume);
+        }
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= NAME_MAX
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid workspace name '{}': use up to {} lowercase letters, digits, '-' and '_'",
+            name,
+            NAME_MAX
+        ));
+    }
+    Ok(())
+}
+
+/// Create a workspace, or change the quota of an existing one
+pub fn create(data_dir: &Path, name: &str, quota: Quota) -> Result<Workspace> {
+    require_root("create workspaces")?;
+    validate_name(name)?;
+    if quota.cpus.is_some_and(|c| c <= 0.0) || quota.gpu_hours.is_some_and(|h| h < 0.0) {
+        return Err(anyhow!("Workspace quotas must be positive"));
+    }
+
+    let mut store = WorkspaceStore::load(data_dir)?;
+    let workspace = store
+        .workspaces
+        .entry(name.to_string())
+        .and_modify(|w| w.quota = quota.clone())
+        .or_insert_with(|| Workspace {
+            name: name.to_string(),
+            quota: quota.clone(),
+            created_at: Utc::now(),
+            gpu_seconds: 0,
+            gpu_sessions: BTreeMap::new(),
+        })
+        .clone();
+    store.save(data_dir)?;
+    info!("🗂️  Workspace {} ready", name);
+    Ok(workspace)
+}
+
+/// Put a user in a workspace, or take them out of theirs with `None`
+pub fn assign(data_dir: &Path, user: &str, workspace: Option<&str>) -> Result<()> {
+    require_root("assign users to workspaces")?;
+    let mut store = WorkspaceStore::load(data_dir)?;
+    match workspace {
+        Some(workspace) => {
+            store.get(workspace)?;
+            store.users.insert(user.to_string(), workspace.to_string());
+            info!("👤 {} now works in {}", user, workspace);
+        }
+        None => {
+            store.users.remove(user);
+            info!("👤 {} left their workspace", user);
+        }
+    }
+    store.save(data_dir)
+}
+
+/// What a new container asks of its workspace
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionRequest {
+    pub cpus: Option<f64>,
+    pub memory: Option<u64>,
+    pub gpu: bool,
+}
+
+/// Check a new container against the workspace's quota, returning the
+/// `run` arguments that label and limit it
+pub async fn admit(
+    runtime: &str,
+    data_dir: &Path,
+    workspace: &str,
+    container: Option<&str>,
+    request: &AdmissionRequest,
+) -> Result<Vec<String>> {
+    let usage = usage(runtime, data_dir, workspace).await?;
+    let quota = &usage.quota;
+    let mut args = vec!["--label".to_string(), format!("{}={}", LABEL, workspace)];
+
+    let cpus = match quota.cpus {
+        Some(limit) => Some(allot("CPU", request.cpus, limit - usage.cpus, 0.01, |c| {
+            format!("{:.2}", c)
+        })?),
+        None => request.cpus,
+    };
+    if let Some(cpus) = cpus {
+        args.push(format!("--cpus={}", cpus));
+    }
+
+    let memory = match quota.memory {
+        Some(limit) => Some(allot(
+            "memory",
+            request.memory.map(|m| m as f64),
+            limit.saturating_sub(usage.memory) as f64,
+            MEMORY_MIN as f64,
+            |m| format_size(m as u64),
+        )? as u64),
+        None => request.memory,
+    };
+    if let Some(memory) = memory {
+        args.push(format!("--memory={}", memory));
+    }
+
+    if let Some(limit) = quota.disk.filter(|limit| usage.disk >= *limit) {
+        return Err(anyhow!(
+            "Workspace {} uses {} of its {} disk quota; free up volume space first",
+            workspace,
+            format_size(usage.disk),
+            format_size(limit)
+        ));
+    }
+
+    if request.gpu {
+        if let Some(limit) = quota.gpu_hours.filter(|limit| usage.gpu_hours >= *limit) {
+            return Err(anyhow!(
+                "Workspace {} used {:.1} of its {} GPU hours",
+                workspace,
+                usage.gpu_hours,
+                limit
+            ));
+        }
+        if let Some(container) = container {
+            let mut store = WorkspaceStore::load(data_dir)?;
+            if let Some(w) = store.workspaces.get_mut(workspace) {
+                let now = Utc::now();
+                w.gpu_sessions.insert(
+                    container.to_string(),
+                    GpuSession {
+                        started_at: now,
+                        last_seen: now,
+                    },
+                );
+                store.save(data_dir)?;
+            }
+        }
+    }
+
+    debug!("Admitted into workspace {}: {:?}", workspace, args);
+    Ok(args)
+}
+
+/// The amount to give a container: what it asked for, or all that is left
+fn allot(
+    resource: &str,
+    requested: Option<f64>,
+    remaining: f64,
+    minimum: f64,
+    show: impl Fn(f64) -> String,
+) -> Result<f64> {
+    if remaining < minimum {
+        return Err(anyhow!("The workspace's {} quota is used up", resource));
+    }
+    match requested {
+        Some(requested) if requested > remaining => Err(anyhow!(
+            "The container needs {} {} but the workspace only has {} left",
+            show(requested),
+            resource,
+            show(remaining)
+        )),
+        Some(requested) => Ok(requested),
+        None => Ok((remaining * 100.0).floor() / 100.0),
+    }
+}
+
+/// Stop accounting a GPU container's time
+pub fn stopped(data_dir: &Path, container: &str) {
+    let Ok(mut store) = WorkspaceStore::load(data_dir) else {
+        return;
+    };
+    let now = Utc::now();
+    let mut changed = false;
+    for workspace in store.workspaces.values_mut() {
+        if let Some(session) = workspace.gpu_sessions.remove(container) {
+            workspace.gpu_seconds += session.seconds_until(now);
+            changed = true;
+        }
+    }
+    if changed {
+        let _ = store.save(data_dir);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceUsage {
+    pub workspace: String,
+    pub users: Vec<String>,
+    pub quota: Quota,
+    pub containers: Vec<String>,
+    pub cpus: f64,
+    pub memory: u64,
+    pub gpu_hours: f64,
+    pub disk: u64,
+}
+
+/// What a workspace uses now. Closes the accounting of GPU containers that
+/// stopped on their own.
+pub async fn usage(runtime: &str, data_dir: &Path, workspace: &str) -> Result<WorkspaceUsage> {
+    let mut store = WorkspaceStore::load(data_dir)?;
+    store.get(workspace)?;
+    let containers = running_containers(runtime, workspace).await?;
+
+    let mut cpus = 0.0;
+    let mut memory = 0;
+    for container in &containers {
+        let limits = inspect(
+            runtime,
+            container,
+            "{{.HostConfig.NanoCpus}} {{.HostConfig.Memory}}",
+        )
+        .await
+        .unwrap_or_default();
+        let mut fields = limits.split_whitespace();
+        cpus += fields
+            .next()
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or(0) as f64
+            / 1e9;
+        memory += fields
+            .next()
+            .and_then(|m| m.parse::<u64>().ok())
+            .unwrap_or(0);
+    }
+
+    let now = Utc::now();
+    let w = store
+        .workspaces
+        .get_mut(workspace)
+        .expect("workspace checked above");
+    let sessions: Vec<String> = w.gpu_sessions.keys().cloned().collect();
+    for container in sessions {
+        if containers.contains(&container) {
+            if let Some(session) = w.gpu_sessions.get_mut(&container) {
+                session.last_seen = now;
+            }
+            continue;
+        }
+        let finished = inspect(runtime, &container, "{{.State.FinishedAt}}")
+            .await
+            .and_then(|t| parse_time(&t));
+        if let Some(session) = w.gpu_sessions.remove(&container) {
+            let end = finished
+                .filter(|t| *t >= session.started_at && *t <= now)
+                .unwrap_or(session.last_seen);
+            w.gpu_seconds += session.seconds_until(end);
+        }
+    }
+    let gpu_hours = w.gpu_hours(now);
+    let quota = w.quota.clone();
+    store.save(data_dir)?;
+
+    Ok(WorkspaceUsage {
+        workspace: workspace.to_string(),
+        users: store.members(workspace),
+        quota,
+        containers,
+        cpus,
+        memory,
+        gpu_hours,
+        disk: disk_usage(runtime, workspace).await,
+    })
+}
+
+async fn running_containers(runtime: &str, workspace: &str) -> Result<Vec<String>> {
+    let output = AsyncCommand::new(runtime)
+        .args([
+            "ps",
+            "--filter",
+            &format!("label={}={}", LABEL, workspace),
+            "--format",
+            "{{.Names}}",
+        ])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} ps", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} ps failed: {}",
+            runtime,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+async fn inspect(runtime: &str, container: &str, format: &str) -> Option<String> {
+    let output = AsyncCommand::new(runtime)
+        .args(["inspect", "--format", format, container])
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Docker's RFC 3339 or Podman's `2006-01-02 15:04:05.999 -0700 MST`
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let parts: Vec<&str> = value.split_whitespace().take(3).collect();
+    DateTime::parse_from_str(&parts.join(" "), "%Y-%m-%d %H:%M:%S%.f %z")
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Bytes used by the workspace's runtime and Bolt volumes
+async fn disk_usage(runtime: &str, workspace: &str) -> u64 {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Ok(output) = AsyncCommand::new(runtime)
+        .args(["volume", "ls", "--format", "{{.Name}}"])
+        .output()
+        .await
+    {
+        for name in String::from_utf8_lossy(&output.stdout).lines() {
+            let name = name.trim();
+            if !is_scoped(workspace, name) {
+                continue;
+            }
+            let mountpoint = AsyncCommand::new(runtime)
+                .args(["volume", "inspect", "--format", "{{.Mountpoint}}", name])
+                .output()
+                .await;
+            if let Ok(output) = mountpoint {
+                dirs.push(PathBuf::from(
+                    String::from_utf8_lossy(&output.stdout).trim(),
+                ));
+            }
+        }
+    }
+    let bolt_volumes = crate::volume::VolumeManager::new()
+        .map(|m| m.volumes_dir().to_path_buf())
+        .ok();
+    if let Some(Ok(entries)) = bolt_volumes.map(std::fs::read_dir) {
+        dirs.extend(
+            entries
+                .flatten()
+                .filter(|e| is_scoped(workspace, &e.file_name().to_string_lossy()))
+                .map(|e| e.path()),
+        );
+    }
+
+    dirs.iter()
+        .filter(|dir| dir.is_dir())
+        .flat_map(|dir| walkdir::WalkDir::new(dir).into_iter().flatten())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_users_from_the_passwd_database() {
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+        if !is_root() {
+            // SAFETY: getuid has no preconditions
+            assert_eq!(current_user(), user_name(unsafe { libc::getuid() }));
+        }
+    }
+
+    #[test]
+    fn scopes_names_and_allots_quota() {
+        assert_eq!(scoped("alice", "web"), "alice.web");
+        assert_eq!(scoped("alice", "alice.web"), "alice.web");
+        assert_eq!(scoped("alice", "alicex.web"), "alice.alicex.web");
+        assert_eq!(
+            scoped_volume("alice", "data:/data:ro"),
+            "alice.data:/data:ro"
+        );
+        assert_eq!(scoped_volume("alice", "./src:/app"), "./src:/app");
+        assert_eq!(scoped_volume("alice", "/srv/maps:/maps"), "/srv/maps:/maps");
+
+        let mut boltfile = BoltFile::parse(
+            r#"
+project = "arena"
+
+[networks.game]
+driver = "bridge"
+
+[networks.shared]
+driver = "bridge"
+external = true
+
+[services.server]
+image = "arena:latest"
+volumes = ["saves:/saves", "./config:/config"]
+networks = ["game", "shared"]
+"#,
+            Path::new("."),
+        )
+        .unwrap();
+        scope_boltfile("alice", &mut boltfile);
+        assert_eq!(boltfile.project, "alice.arena");
+        let networks = boltfile.networks.as_ref().unwrap();
+        assert_eq!(networks["game"].name.as_deref(), Some("alice.game"));
+        assert_eq!(networks["shared"].name, None);
+        assert_eq!(
+            boltfile.services["server"].volumes.as_ref().unwrap(),
+            &vec!["alice.saves:/saves", "./config:/config"]
+        );
+
+        assert!(validate_name("team-1").is_ok());
+        assert!(validate_name("Team.1").is_err());
+
+        let show = |v: f64| v.to_string();
+        assert_eq!(allot("CPU", Some(2.0), 3.5, 0.01, show).unwrap(), 2.0);
+        assert_eq!(allot("CPU", None, 3.456, 0.01, show).unwrap(), 3.45);
+        assert!(allot("CPU", Some(4.0), 3.5, 0.01, show).is_err());
+        assert!(allot("CPU", None, 0.0, 0.01, show).is_err());
+
+        let start = Utc::now() - chrono::Duration::hours(2);
+        let workspace = Workspace {
+            name: "alice".to_string(),
+            quota: Quota::default(),
+            created_at: start,
+            gpu_seconds: 1800,
+            gpu_sessions: BTreeMap::from([(
+                "alice.arena_server".to_string(),
+                GpuSession {
+                    started_at: start,
+                    last_seen: start,
+                },
+            )]),
+        };
+        let hours = workspace.gpu_hours(start + chrono::Duration::hours(2));
+        assert!((hours - 2.5).abs() < 1e-9);
+
+        assert_eq!(
+            parse_time("2024-05-01 10:00:00.5 +0000 UTC"),
+            parse_time("2024-05-01T10:00:00.5Z")
+        );
+        assert!(parse_time("0001-01-01T00:00:00Z").is_some());
+    }
+}
@@ -0,0 +1,182 @@
+// Secrets
+//
+// Values such as repository passwords and API keys are kept out of the
+// Boltfile and `config.toml`. Settings refer to them instead:
+//
+//   secret:<name>   stored with `bolt secret set` under `<config_dir>/secrets`
+//   env:<VAR>       read from the environment
+//   file:<path>     read from a file (a trailing newline is dropped)
+//   cmd:<command>   printed by a command, e.g. `cmd:pass show bolt/restic`
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const PREFIXES: [&str; 4] = ["secret:", "env:", "file:", "cmd:"];
+
+/// File-backed secret store at `<config_dir>/secrets`
+#[derive(Debug, Clone)]
+pub struct SecretStore {
+    dir: PathBuf,
+}
+
+impl SecretStore {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            dir: config_dir.join("secrets"),
+        }
+    }
+
+    /// Store a secret, readable by the current user only
+    pub fn set(&self, name: &str, value: &[u8]) -> Result<()> {
+        validate_name(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))?;
+
+        let path = self.dir.join(name);
+        let staging = self.dir.join(format!(".{}.tmp", name));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&staging)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        fs::rename(&staging, &path)?;
+
+        info!("🔑 Stored secret {}", name);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<String> {
+        validate_name(name)?;
+        let path = self.dir.join(name);
+        let value = fs::read_to_string(&path).map_err(|_| {
+            anyhow!(
+                "Secret '{}' is not set (use `bolt secret set {}`)",
+                name,
+                name
+            )
+        })?;
+        Ok(strip_newline(value))
+    }
+
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with('.') {
+                    names.push(name);
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Remove a secret, returning whether it existed
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        validate_name(name)?;
+        match fs::remove_file(self.dir.join(name)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Resolve a `secret:`, `env:`, `file:` or `cmd:` reference to its value
+    pub fn resolve(&self, reference: &str) -> Result<String> {
+        if let Some(name) = reference.strip_prefix("secret:") {
+            self.get(name)
+        } else if let Some(var) = reference.strip_prefix("env:") {
+            std::env::var(var).map_err(|_| anyhow!("Environment variable {} is not set", var))
+        } else if let Some(path) = reference.strip_prefix("file:") {
+            let value = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read secret file {}", path))?;
+            Ok(strip_newline(value))
+        } else if let Some(command) = reference.strip_prefix("cmd:") {
+            let output = std::process::Command::new("sh")
+                .args(["-c", command])
+                .output()
+                .with_context(|| format!("Failed to run secret command `{}`", command))?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Secret command `{}` failed: {}",
+                    command,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            Ok(strip_newline(String::from_utf8(output.stdout)?))
+        } else {
+            Err(anyhow!(
+                "'{}' is not a secret reference; use secret:<name>, env:<VAR>, file:<path> or cmd:<command>",
+                reference
+            ))
+        }
+    }
+}
+
+/// Whether a setting value is a reference rather than a literal
+pub fn is_reference(value: &str) -> bool {
+    PREFIXES.iter().any(|p| value.starts_with(p))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid secret name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        ))
+    }
+}
+
+fn strip_newline(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_resolves_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::new(dir.path());
+
+        store.set("restic-password", b"hunter2\n").unwrap();
+        assert_eq!(store.resolve("secret:restic-password").unwrap(), "hunter2");
+        assert_eq!(
+            store.resolve("cmd:echo from-command").unwrap(),
+            "from-command"
+        );
+        assert_eq!(store.list().unwrap(), vec!["restic-password"]);
+
+        let mode = fs::metadata(dir.path().join("secrets/restic-password"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        assert!(store.set("../escape", b"x").is_err());
+        assert!(store.resolve("hunter2").is_err());
+        assert!(store.remove("restic-password").unwrap());
+        assert!(store.resolve("secret:restic-password").is_err());
+    }
+}
@@ -0,0 +1,652 @@
+// Surge dev mode
+//
+// `bolt surge dev` starts the services, then watches their files and keeps
+// the running containers current, like Compose Watch. Each
+// `[[services.<name>.develop.watch]]` rule names a path and what a change
+// under it does:
+//
+// - `sync`: copy changed files into the container at `target` and delete
+//   removed ones
+// - `sync+restart`: sync, then restart the container
+// - `restart`: restart the container
+// - `rebuild`: rebuild the image and recreate the service, replacing scaled
+//   instances one at a time
+//
+// A service with `build` and no rules rebuilds when its build context
+// changes. Files are polled for size and modification time rather than
+// watched through inotify, so edits made through bind mounts and network
+// filesystems are seen too. Changes are applied once a poll finds nothing
+// new, so a burst of saves triggers one sync or rebuild.
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::process::Command as AsyncCommand;
+use tracing::{info, warn};
+
+use crate::Result;
+use crate::config::{BoltConfig, DevelopConfig, Service, WatchRule};
+use crate::runtime::{self, wasm::ServiceRuntime};
+
+/// Paths never watched
+const ALWAYS_IGNORED: &[&str] = &[".git"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchAction {
+    #[serde(rename = "sync")]
+    Sync,
+    #[serde(rename = "sync+restart")]
+    SyncRestart,
+    #[serde(rename = "restart")]
+    Restart,
+    #[serde(rename = "rebuild")]
+    Rebuild,
+}
+
+impl WatchAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchAction::Sync => "sync",
+            WatchAction::SyncRestart => "sync+restart",
+            WatchAction::Restart => "restart",
+            WatchAction::Rebuild => "rebuild",
+        }
+    }
+
+    fn syncs(&self) -> bool {
+        matches!(self, WatchAction::Sync | WatchAction::SyncRestart)
+    }
+}
+
+/// Check a service's watch rules
+pub fn validate(service: &Service, develop: &DevelopConfig) -> anyhow::Result<()> {
+    let wasm = service.runtime == Some(ServiceRuntime::Wasm);
+    for rule in &develop.watch {
+        if rule.path.trim().is_empty() {
+            return Err(anyhow!("watch path cannot be empty"));
+        }
+        match (rule.action, rule.target.as_deref()) {
+            (_, _) if wasm && rule.action != WatchAction::Restart => {
+                return Err(anyhow!(
+                    "WASM services only support action = \"restart\", not \"{}\"",
+                    rule.action.as_str()
+                ));
+            }
+            (WatchAction::Sync | WatchAction::SyncRestart, None) => {
+                return Err(anyhow!(
+                    "action = \"{}\" for '{}' needs a target in the container",
+                    rule.action.as_str(),
+                    rule.path
+                ));
+            }
+            (WatchAction::Sync | WatchAction::SyncRestart, Some(target))
+                if !target.starts_with('/') =>
+            {
+                return Err(anyhow!(
+                    "watch target must be an absolute path in the container, got '{}'",
+                    target
+                ));
+            }
+            (WatchAction::Rebuild, _) if service.build.is_none() => {
+                return Err(anyhow!(
+                    "action = \"rebuild\" needs a build context (build = \"...\")"
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Files that changed between two scans, relative to the watched path
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Changes {
+    changed: BTreeSet<PathBuf>,
+    removed: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    fn merge(&mut self, other: Changes) {
+        for path in other.changed {
+            self.removed.remove(&path);
+            self.changed.insert(path);
+        }
+        for path in other.removed {
+            self.changed.remove(&path);
+            self.removed.insert(path);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.changed.len() + self.removed.len()
+    }
+}
+
+/// A watch rule of one service, with the files it last saw
+struct Watch {
+    service: String,
+    container: String,
+    root: PathBuf,
+    action: WatchAction,
+    target: Option<String>,
+    ignore: Vec<String>,
+    files: HashMap<PathBuf, FileStamp>,
+}
+
+impl Watch {
+    fn new(service: &str, container: &str, project_dir: &Path, rule: &WatchRule) -> Self {
+        let root = project_dir.join(&rule.path);
+        let files = scan(&root, &rule.ignore);
+        Self {
+            service: service.to_string(),
+            container: container.to_string(),
+            root,
+            action: rule.action,
+            target: rule.target.clone(),
+            ignore: rule.ignore.clone(),
+            files,
+        }
+    }
+
+    fn poll(&mut self) -> Changes {
+        let files = scan(&self.root, &self.ignore);
+        let changes = diff(&self.files, &files);
+        self.files = files;
+        changes
+    }
+
+    /// Container path of a file under the watched path
+    fn destination(&self, rel: &Path) -> String {
+        let target = self.target.as_deref().unwrap_or("/");
+        if rel.as_os_str().is_empty() {
+            return target.to_string();
+        }
+        format!("{}/{}", target.trim_end_matches('/'), rel.display())
+    }
+}
+
+/// Watch rules for `service`: its `develop.watch` rules, or a rebuild on
+/// changes to its build context
+fn rules(service: &Service) -> Vec<WatchRule> {
+    match (&service.develop, &service.build) {
+        (Some(develop), _) if !develop.watch.is_empty() => develop.watch.clone(),
+        (_, Some(build)) => vec![WatchRule {
+            path: build.clone(),
+            action: WatchAction::Rebuild,
+            target: None,
+            ignore: Vec::new(),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// Start `services` (all by default) and keep them in sync with their files
+/// until interrupted
+pub async fn dev(
+    config: &BoltConfig,
+    services: &[String],
+    interval: Duration,
+    start: bool,
+) -> Result<()> {
+    let boltfile = config.load_boltfile()?;
+    let project_dir = super::project_dir(config)?;
+
+    let mut names: Vec<&String> = if services.is_empty() {
+        boltfile.services.keys().collect()
+    } else {
+        services.iter().collect()
+    };
+    names.sort();
+
+    let mut watches = Vec::new();
+    for name in &names {
+        let service = boltfile
+            .services
+            .get(name.as_str())
+            .ok_or_else(|| anyhow!("Service '{}' not found in Boltfile", name))?;
+        let container = format!("{}_{}", boltfile.project, name);
+        for rule in rules(service) {
+            watches.push(Watch::new(name, &container, &project_dir, &rule));
+        }
+    }
+    if watches.is_empty() {
+        return Err(anyhow!(
+            "Nothing to watch: add [[services.<name>.develop.watch]] rules or a build context"
+        )
+        .into());
+    }
+
+    if start {
+        let stopped = stopped_services(config, &boltfile, &names).await?;
+        if !stopped.is_empty() {
+            super::up(config, &stopped, true, true, None).await?;
+        }
+    }
+
+    info!(
+        "👀 Watching {} path(s), press Ctrl+C to stop",
+        watches.len()
+    );
+    for watch in &watches {
+        info!(
+            "  • {}: {} -> {}",
+            watch.service,
+            watch.root.display(),
+            watch.action.as_str()
+        );
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    let mut pending: Vec<Changes> = watches.iter().map(|_| Changes::default()).collect();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = ticker.tick() => {}
+        }
+
+        let mut quiet = true;
+        for (watch, pending) in watches.iter_mut().zip(pending.iter_mut()) {
+            let changes = watch.poll();
+            if !changes.is_empty() {
+                quiet = false;
+                pending.merge(changes);
+            }
+        }
+        if quiet && pending.iter().any(|c| !c.is_empty()) {
+            let batch = std::mem::replace(
+                &mut pending,
+                watches.iter().map(|_| Changes::default()).collect(),
+            );
+            apply(config, &runtime, &boltfile, &watches, batch).await;
+        }
+    }
+
+    info!("👋 Stopped watching");
+    Ok(())
+}
+
+/// Services among `names` whose container or WASM workload is not running
+async fn stopped_services(
+    config: &BoltConfig,
+    boltfile: &crate::config::BoltFile,
+    names: &[&String],
+) -> Result<Vec<String>> {
+    let running: BTreeSet<String> = runtime::list_containers_info(false)
+        .await?
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    Ok(names
+        .iter()
+        .filter(|name| {
+            let container = format!("{}_{}", boltfile.project, name);
+            let wasm = boltfile
+                .services
+                .get(name.as_str())
+                .is_some_and(|s| s.runtime == Some(ServiceRuntime::Wasm));
+            if wasm {
+                !runtime::wasm::state(&config.data_dir, &container).is_some_and(|s| s.is_running())
+            } else {
+                !running.contains(&container)
+            }
+        })
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Act on a batch of changes, one service at a time. A rebuild replaces the
+/// service, so its sync and restart rules are skipped.
+async fn apply(
+    config: &BoltConfig,
+    runtime: &str,
+    boltfile: &crate::config::BoltFile,
+    watches: &[Watch],
+    batch: Vec<Changes>,
+) {
+    let mut by_service: Vec<(&str, Vec<(&Watch, Changes)>)> = Vec::new();
+    for (watch, changes) in watches.iter().zip(batch) {
+        if changes.is_empty() {
+            continue;
+        }
+        match by_service.iter_mut().find(|(s, _)| *s == watch.service) {
+            Some((_, list)) => list.push((watch, changes)),
+            None => by_service.push((&watch.service, vec![(watch, changes)])),
+        }
+    }
+
+    for (service, list) in by_service {
+        let files: usize = list.iter().map(|(_, c)| c.len()).sum();
+        info!("📝 {} file(s) changed for {}", files, service);
+        if let Err(e) = apply_service(config, runtime, boltfile, service, &list).await {
+            warn!("Could not update {}: {}", service, e);
+        }
+    }
+}
+
+async fn apply_service(
+    config: &BoltConfig,
+    runtime: &str,
+    boltfile: &crate::config::BoltFile,
+    service: &str,
+    list: &[(&Watch, Changes)],
+) -> Result<()> {
+    let services = [service.to_string()];
+    if list.iter().any(|(w, _)| w.action == WatchAction::Rebuild) {
+        info!("🔨 Rebuilding {}", service);
+        return super::up(config, &services, true, true, None).await;
+    }
+
+    let wasm = boltfile
+        .services
+        .get(service)
+        .is_some_and(|s| s.runtime == Some(ServiceRuntime::Wasm));
+    if wasm {
+        info!("🔄 Restarting {}", service);
+        return super::up(config, &services, true, true, None).await;
+    }
+
+    let replicas = replicas(&list[0].0.container).await?;
+    if replicas.is_empty() {
+        warn!("{} is not running; start it with `bolt surge up`", service);
+        return Ok(());
+    }
+    for (watch, changes) in list.iter().filter(|(w, _)| w.action.syncs()) {
+        for container in &replicas {
+            sync(runtime, watch, changes, container).await?;
+        }
+        info!(
+            "🔁 Synced {} file(s) into {}",
+            changes.len(),
+            watch.target.as_deref().unwrap_or("/")
+        );
+    }
+    let restart = list
+        .iter()
+        .any(|(w, _)| matches!(w.action, WatchAction::Restart | WatchAction::SyncRestart));
+    if restart {
+        for container in &replicas {
+            runtime::restart_container(container, 10).await?;
+            crate::networking::egress::restore(runtime, &config.data_dir, container).await;
+        }
+    }
+    Ok(())
+}
+
+/// Running containers of a service: the main one and its scaled instances
+async fn replicas(container: &str) -> Result<Vec<String>> {
+    let prefix = format!("{}_", container);
+    let mut names: Vec<String> = runtime::list_containers_info(false)
+        .await?
+        .into_iter()
+        .map(|c| c.name)
+        .filter(|name| {
+            name == container
+                || name
+                    .strip_prefix(&prefix)
+                    .is_some_and(|n| n.parse::<u32>().is_ok())
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Copy changed files into `container` and delete removed ones
+async fn sync(
+    runtime: &str,
+    watch: &Watch,
+    changes: &Changes,
+    container: &str,
+) -> anyhow::Result<()> {
+    if !changes.removed.is_empty() {
+        let paths: Vec<String> = changes
+            .removed
+            .iter()
+            .map(|rel| watch.destination(rel))
+            .collect();
+        exec(runtime, container, "rm", &["-rf"], &paths).await?;
+    }
+    if changes.changed.is_empty() {
+        return Ok(());
+    }
+
+    let parents: BTreeSet<String> = changes
+        .changed
+        .iter()
+        .filter_map(|rel| {
+            let dest = watch.destination(rel);
+            Path::new(&dest)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+        })
+        .collect();
+    let parents: Vec<String> = parents.into_iter().collect();
+    exec(runtime, container, "mkdir", &["-p"], &parents).await?;
+
+    for rel in &changes.changed {
+        let source = watch.root.join(rel);
+        let output = AsyncCommand::new(runtime)
+            .arg("cp")
+            .arg(&source)
+            .arg(format!("{}:{}", container, watch.destination(rel)))
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {} cp", runtime))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to copy {} into {}: {}",
+                source.display(),
+                container,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+    Ok(())
+}
+
+async fn exec(
+    runtime: &str,
+    container: &str,
+    program: &str,
+    flags: &[&str],
+    paths: &[String],
+) -> anyhow::Result<()> {
+    let output = AsyncCommand::new(runtime)
+        .args(["exec", container, program])
+        .args(flags)
+        .args(paths)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} exec", runtime))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed in {}: {}",
+            program,
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Size and modification time of every file under `root`, keyed by path
+/// relative to it. A watched file is keyed by the empty path.
+fn scan(root: &Path, ignore: &[String]) -> HashMap<PathBuf, FileStamp> {
+    walkdir::WalkDir::new(root)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| {
+            let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            !is_ignored(rel, ignore)
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let rel = entry.path().strip_prefix(root).ok()?.to_path_buf();
+            Some((
+                rel,
+                FileStamp {
+                    modified: metadata.modified().ok(),
+                    len: metadata.len(),
+                },
+            ))
+        })
+        .collect()
+}
+
+fn diff(old: &HashMap<PathBuf, FileStamp>, new: &HashMap<PathBuf, FileStamp>) -> Changes {
+    Changes {
+        changed: new
+            .iter()
+            .filter(|(path, stamp)| old.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|path| !new.contains_key(*path))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Whether `rel` or one of its parent directories matches an ignore
+/// pattern. Patterns with a `/` match the path from the watched root, others
+/// match any single file or directory name, as in `.gitignore`.
+fn is_ignored(rel: &Path, patterns: &[String]) -> bool {
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let patterns = ALWAYS_IGNORED
+        .iter()
+        .copied()
+        .chain(patterns.iter().map(String::as_str))
+        .map(|p| p.trim_start_matches("./").trim_end_matches('/'));
+
+    patterns.filter(|p| !p.is_empty()).any(|pattern| {
+        (1..=parts.len()).any(|i| {
+            if pattern.contains('/') {
+                wildcard_match(pattern, &parts[..i].join("/"))
+            } else {
+                wildcard_match(pattern, &parts[i - 1])
+            }
+        })
+    })
+}
+
+/// `*` matches any run of characters (including `/`), `?` any one
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_changes_and_honours_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join("src/app.js"), "one").unwrap();
+        std::fs::write(root.join("src/old.js"), "old").unwrap();
+        std::fs::write(root.join("src/node_modules/pkg/index.js"), "dep").unwrap();
+        std::fs::write(root.join("debug.log"), "log").unwrap();
+        std::fs::write(root.join(".git/HEAD"), "ref").unwrap();
+
+        let ignore = vec!["node_modules/".to_string(), "*.log".to_string()];
+        let before = scan(root, &ignore);
+        let mut seen: Vec<_> = before.keys().cloned().collect();
+        seen.sort();
+        assert_eq!(
+            seen,
+            vec![PathBuf::from("src/app.js"), PathBuf::from("src/old.js")]
+        );
+
+        std::fs::write(root.join("src/app.js"), "two!").unwrap();
+        std::fs::remove_file(root.join("src/old.js")).unwrap();
+        std::fs::write(root.join("src/new.js"), "new").unwrap();
+        let changes = diff(&before, &scan(root, &ignore));
+        assert_eq!(
+            changes.changed,
+            BTreeSet::from([PathBuf::from("src/app.js"), PathBuf::from("src/new.js")])
+        );
+        assert_eq!(
+            changes.removed,
+            BTreeSet::from([PathBuf::from("src/old.js")])
+        );
+
+        assert!(is_ignored(
+            Path::new("build/out/a.o"),
+            &["build/out".into()]
+        ));
+        assert!(!is_ignored(
+            Path::new("src/build/out"),
+            &["build/out".into()]
+        ));
+        assert!(wildcard_match("*.min.js", "app.min.js"));
+        assert!(!wildcard_match("*.min.js", "app.js"));
+
+        let rule = |action: &str, target: Option<&str>| WatchRule {
+            path: "./src".to_string(),
+            action: serde_json::from_str(&format!("\"{}\"", action)).unwrap(),
+            target: target.map(str::to_string),
+            ignore: Vec::new(),
+        };
+        let image = Service {
+            image: Some("node:20".to_string()),
+            ..Default::default()
+        };
+        let develop = |rule: WatchRule| DevelopConfig { watch: vec![rule] };
+        assert!(validate(&image, &develop(rule("sync+restart", Some("/app")))).is_ok());
+        assert!(validate(&image, &develop(rule("sync", None))).is_err());
+        assert!(validate(&image, &develop(rule("sync", Some("app")))).is_err());
+        assert!(validate(&image, &develop(rule("rebuild", None))).is_err());
+
+        let built = Service {
+            build: Some("./web".to_string()),
+            ..Default::default()
+        };
+        let defaults = rules(&built);
+        assert_eq!(defaults.len(), 1);
+        assert_eq!(defaults[0].action, WatchAction::Rebuild);
+        assert_eq!(defaults[0].path, "./web");
+    }
+}
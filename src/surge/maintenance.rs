@@ -0,0 +1,745 @@
+// Maintenance windows
+//
+// A window ("Sun 03:00-05:00", "daily 02:00-03:00", "Mon-Fri 23:30-00:30")
+// comes from the Boltfile's `maintenance` or config.toml's
+// `[maintenance] window`, in local time. `bolt maintenance enable` starts a
+// hidden `bolt maintenance-watch` which, at the start of each window, queues
+// config.toml's `tasks` and then works through the queue until the window
+// closes:
+//
+// - image-updates: recreate services whose tag resolves to a newer image
+// - snapshot-prune: apply the `[snapshots]` retention policy
+// - storage-fsck: verify the content store, removing corrupt blobs
+// - reboot: run `reboot_command` when queued by hand or the host asks for
+//   one (/run/reboot-required), last of all
+//
+// Services marked `never_disturb` aren't updated, and a reboot waits for
+// the next window while any of them is running. The alert channels hear
+// of a window `notify_before` it opens, when it starts and what it did.
+// The queue and the last run live in <data_dir>/maintenance/state.json.
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::builds::cache::parse_duration;
+use crate::config::{BoltConfig, BoltFile};
+use crate::monitoring::alerts::{Alert, AlertSeverity, AlertingEngine};
+use crate::runtime;
+use crate::runtime::watcher::{self, Watcher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    ImageUpdates,
+    SnapshotPrune,
+    StorageFsck,
+    Reboot,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ImageUpdates => "image-updates",
+            Self::SnapshotPrune => "snapshot-prune",
+            Self::StorageFsck => "storage-fsck",
+            Self::Reboot => "reboot",
+        }
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A recurring window, in local time; it may run past midnight
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// Days the window opens on, every day when empty
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Window {
+    /// "Sun 03:00-05:00", "Sat,Sun 01:00-02:00", "Mon-Fri 23:30-00:30",
+    /// "daily 02:00-03:00" or just "02:00-03:00"
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (days, times) = match spec.rsplit_once(char::is_whitespace) {
+            Some((days, times)) => (days.trim(), times),
+            None => ("daily", spec),
+        };
+        let (start, end) = times
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid maintenance window {} (e.g. Sun 03:00-05:00)", spec))?;
+        let time = |t: &str| {
+            NaiveTime::parse_from_str(t, "%H:%M")
+                .map_err(|_| anyhow!("Invalid time {} in maintenance window {}", t, spec))
+        };
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            bail!("Maintenance window {} is empty", spec);
+        }
+
+        let day = |d: &str| {
+            d.trim()
+                .parse::<Weekday>()
+                .map_err(|_| anyhow!("Invalid day {} in maintenance window {}", d, spec))
+        };
+        let mut parsed = Vec::new();
+        if !matches!(days.to_lowercase().as_str(), "daily" | "*") {
+            for part in days.split(',') {
+                match part.split_once('-') {
+                    Some((from, to)) => {
+                        let (mut day, to) = (day(from)?, day(to)?);
+                        parsed.push(day);
+                        while day != to {
+                            day = day.succ();
+                            parsed.push(day);
+                        }
+                    }
+                    None => parsed.push(day(part)?),
+                }
+            }
+        }
+
+        Ok(Self {
+            days: parsed,
+            start,
+            end,
+        })
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn occurrence(&self, date: chrono::NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+        let start = date.and_time(self.start);
+        let end_date = if self.end > self.start {
+            date
+        } else {
+            date + Duration::days(1)
+        };
+        (start, end_date.and_time(self.end))
+    }
+
+    /// The start and end of the window `now` falls in
+    pub fn current(&self, now: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        [now.date(), now.date() - Duration::days(1)]
+            .into_iter()
+            .filter(|date| self.opens_on(date.weekday()))
+            .map(|date| self.occurrence(date))
+            .find(|(start, end)| *start <= now && now < *end)
+    }
+
+    /// The start and end of the next window opening after `now`
+    pub fn next(&self, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        (0..=7)
+            .map(|ahead| now.date() + Duration::days(ahead))
+            .filter(|date| self.opens_on(date.weekday()))
+            .map(|date| self.occurrence(date))
+            .find(|(start, _)| *start > now)
+            .expect("a window opens at least once a week")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOperation {
+    pub operation: Operation,
+    pub queued_at: DateTime<Utc>,
+    /// Queued with `bolt maintenance queue` rather than from `tasks`; a
+    /// reboot queued by hand happens whether or not the host asks for one
+    #[serde(default)]
+    pub manual: bool,
+    /// Held back until then, e.g. a reboot waiting out a never_disturb service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postponed: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationReport {
+    pub operation: Operation,
+    pub ok: bool,
+    /// Still queued afterwards
+    #[serde(default)]
+    pub postponed: bool,
+    pub summary: String,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    pub started_at: DateTime<Utc>,
+    pub operations: Vec<OperationReport>,
+}
+
+impl RunReport {
+    pub fn failed(&self) -> bool {
+        self.operations.iter().any(|o| !o.ok)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub queue: Vec<QueuedOperation>,
+    /// Start of the window `tasks` were last queued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_for: Option<NaiveDateTime>,
+    /// Start of the window the alert channels were last told about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notified_for: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<RunReport>,
+}
+
+impl MaintenanceState {
+    /// Queue an operation unless it already is; false if it was
+    pub fn enqueue(&mut self, operation: Operation, manual: bool) -> bool {
+        if let Some(queued) = self.queue.iter_mut().find(|q| q.operation == operation) {
+            queued.manual |= manual;
+            return false;
+        }
+        self.queue.push(QueuedOperation {
+            operation,
+            queued_at: Utc::now(),
+            manual,
+            not_before: None,
+            postponed: None,
+        });
+        true
+    }
+
+    /// Queued operations that may run at `now`, a reboot last
+    pub fn due(&self, now: NaiveDateTime) -> Vec<QueuedOperation> {
+        let mut due: Vec<QueuedOperation> = self
+            .queue
+            .iter()
+            .filter(|q| q.not_before.is_none_or(|t| t <= now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|q| q.operation == Operation::Reboot);
+        due
+    }
+}
+
+fn dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("maintenance")
+}
+
+fn state_path(data_dir: &Path) -> PathBuf {
+    dir(data_dir).join("state.json")
+}
+
+pub fn load(data_dir: &Path) -> MaintenanceState {
+    std::fs::read_to_string(state_path(data_dir))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save(data_dir: &Path, state: &MaintenanceState) -> Result<()> {
+    std::fs::create_dir_all(dir(data_dir))?;
+    std::fs::write(state_path(data_dir), serde_json::to_vec_pretty(state)?)?;
+    Ok(())
+}
+
+/// Queue an operation for the next window; false if it already was
+pub fn queue(data_dir: &Path, operation: Operation) -> Result<bool> {
+    let mut state = load(data_dir);
+    let added = state.enqueue(operation, true);
+    save(data_dir, &state)?;
+    Ok(added)
+}
+
+/// Drop a queued operation; false if it wasn't queued
+pub fn unqueue(data_dir: &Path, operation: Operation) -> Result<bool> {
+    let mut state = load(data_dir);
+    let before = state.queue.len();
+    state.queue.retain(|q| q.operation != operation);
+    save(data_dir, &state)?;
+    Ok(state.queue.len() < before)
+}
+
+/// The Boltfile's window, or config.toml's
+pub fn window(config: &BoltConfig, boltfile: Option<&BoltFile>) -> Result<Option<Window>> {
+    boltfile
+        .and_then(|b| b.maintenance.as_deref())
+        .or(config.maintenance.window.as_deref())
+        .map(Window::parse)
+        .transpose()
+}
+
+/// What `bolt maintenance status` shows
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceStatus {
+    pub window: Option<String>,
+    /// End of the window open right now
+    pub open_until: Option<NaiveDateTime>,
+    pub next_window: Option<NaiveDateTime>,
+    pub watcher: Option<WatchState>,
+    #[serde(flatten)]
+    pub state: MaintenanceState,
+}
+
+pub fn status(config: &BoltConfig) -> Result<MaintenanceStatus> {
+    let boltfile = config.load_boltfile().ok();
+    let spec = boltfile
+        .as_ref()
+        .and_then(|b| b.maintenance.clone())
+        .or_else(|| config.maintenance.window.clone());
+    let window = window(config, boltfile.as_ref())?;
+    let now = Local::now().naive_local();
+    Ok(MaintenanceStatus {
+        window: spec,
+        open_until: window
+            .as_ref()
+            .and_then(|w| w.current(now))
+            .map(|(_, end)| end),
+        next_window: window.as_ref().map(|w| w.next(now).0),
+        watcher: watch_status(&config.data_dir),
+        state: load(&config.data_dir),
+    })
+}
+
+enum Outcome {
+    Done(String),
+    /// Left queued, for this reason
+    Postponed(String),
+    /// Reboot the host once the run is recorded
+    Reboot,
+}
+
+/// Run the queued operations that are due, stopping before `until` (the end
+/// of the window) if given
+pub async fn run(config: &BoltConfig, until: Option<NaiveDateTime>) -> Result<RunReport> {
+    let data_dir = &config.data_dir;
+    let due = load(data_dir).due(Local::now().naive_local());
+    let mut report = RunReport {
+        started_at: Utc::now(),
+        operations: Vec::new(),
+    };
+    if due.is_empty() {
+        return Ok(report);
+    }
+
+    let alerts = AlertingEngine::new(data_dir, config.alerts.clone());
+    let names: Vec<&str> = due.iter().map(|q| q.operation.as_str()).collect();
+    info!("🔧 Maintenance starting: {}", names.join(", "));
+    notify(
+        &alerts,
+        "maintenance.started",
+        AlertSeverity::Info,
+        format!("Maintenance started: {}", names.join(", ")),
+    )
+    .await;
+
+    let boltfile = config.load_boltfile().ok();
+    let mut reboot = false;
+    for queued in due {
+        if until.is_some_and(|end| Local::now().naive_local() >= end) {
+            info!("⏰ Maintenance window closed, leaving the rest queued");
+            break;
+        }
+        let operation = queued.operation;
+        info!("🔧 Maintenance: {}", operation);
+        let outcome = perform(config, boltfile.as_ref(), &queued).await;
+
+        let mut state = load(data_dir);
+        let (ok, postponed, summary) = match outcome {
+            Ok(Outcome::Done(summary)) => (true, false, summary),
+            Ok(Outcome::Postponed(reason)) => (true, true, format!("postponed: {}", reason)),
+            Ok(Outcome::Reboot) => {
+                reboot = true;
+                (
+                    true,
+                    false,
+                    format!("rebooting with {}", config.maintenance.reboot_command),
+                )
+            }
+            Err(e) => (false, false, format!("{:#}", e)),
+        };
+        if postponed {
+            if let Some(entry) = state.queue.iter_mut().find(|q| q.operation == operation) {
+                entry.not_before = until;
+                entry.postponed = Some(summary.clone());
+            }
+            info!("⏸️  {} {}", operation, summary);
+        } else {
+            state.queue.retain(|q| q.operation != operation);
+            if ok {
+                info!("✅ {}: {}", operation, summary);
+            } else {
+                warn!("❌ {} failed: {}", operation, summary);
+            }
+        }
+        report.operations.push(OperationReport {
+            operation,
+            ok,
+            postponed,
+            summary,
+            finished_at: Utc::now(),
+        });
+        state.last_run = Some(report.clone());
+        save(data_dir, &state)?;
+    }
+
+    let lines: Vec<String> = report
+        .operations
+        .iter()
+        .map(|o| format!("{}: {}", o.operation, o.summary))
+        .collect();
+    let (severity, verdict) = if report.failed() {
+        (
+            AlertSeverity::Critical,
+            "Maintenance finished with failures",
+        )
+    } else {
+        (AlertSeverity::Info, "Maintenance finished")
+    };
+    notify(
+        &alerts,
+        "maintenance.finished",
+        severity,
+        format!("{}: {}", verdict, lines.join("; ")),
+    )
+    .await;
+
+    if reboot {
+        let command = &config.maintenance.reboot_command;
+        info!("🔁 Rebooting the host: {}", command);
+        let status = tokio::process::Command::new("sh")
+            .args(["-c", command])
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {}", command))?;
+        if !status.success() {
+            bail!("{} failed with {}", command, status);
+        }
+    }
+    Ok(report)
+}
+
+async fn notify(alerts: &AlertingEngine, key: &str, severity: AlertSeverity, message: String) {
+    if let Err(e) = alerts.notify(&Alert::new(key, severity, message)).await {
+        warn!("Failed to send the maintenance alert: {}", e);
+    }
+}
+
+async fn perform(
+    config: &BoltConfig,
+    boltfile: Option<&BoltFile>,
+    queued: &QueuedOperation,
+) -> Result<Outcome> {
+    match queued.operation {
+        Operation::ImageUpdates => image_updates(config, boltfile).await.map(Outcome::Done),
+        Operation::SnapshotPrune => {
+            let settings = boltfile.and_then(|b| b.snapshots.as_ref());
+            if settings.is_none() {
+                return Ok(Outcome::Done("no [snapshots] configured".to_string()));
+            }
+            let manager =
+                runtime::snapshots::SnapshotManager::open(&config.data_dir, settings).await?;
+            let removed = manager.cleanup(false).await?;
+            Ok(Outcome::Done(format!(
+                "removed {} snapshot(s)",
+                removed.len()
+            )))
+        }
+        Operation::StorageFsck => {
+            let store = config.data_dir.join("storage").join("content");
+            let check = runtime::storage::registry::fsck(&store)?;
+            if !check.corrupt.is_empty() {
+                bail!(
+                    "removed {} corrupt blob(s): {}",
+                    check.corrupt.len(),
+                    check.corrupt.join(", ")
+                );
+            }
+            Ok(Outcome::Done(format!(
+                "{} blob(s) verified, {} partial download(s) removed",
+                check.blobs, check.partial
+            )))
+        }
+        Operation::Reboot => {
+            if !queued.manual && !reboot_required() {
+                return Ok(Outcome::Done("the host doesn't need one".to_string()));
+            }
+            let busy = undisturbable_running(boltfile).await?;
+            if !busy.is_empty() {
+                return Ok(Outcome::Postponed(format!(
+                    "never_disturb service(s) running: {}",
+                    busy.join(", ")
+                )));
+            }
+            Ok(Outcome::Reboot)
+        }
+    }
+}
+
+/// Recreate the services whose tag resolves to a newer image, except
+/// never_disturb ones
+async fn image_updates(config: &BoltConfig, boltfile: Option<&BoltFile>) -> Result<String> {
+    let boltfile = boltfile.ok_or_else(|| anyhow!("No Boltfile to update"))?;
+    let mut updates = Vec::new();
+    let mut spared = Vec::new();
+    for plan in super::plan::plan(config, &[]).await? {
+        if !matches!(plan.action, super::plan::PlanAction::Update(_)) {
+            continue;
+        }
+        let never_disturb = boltfile
+            .services
+            .get(&plan.service)
+            .is_some_and(|s| s.never_disturb == Some(true));
+        if never_disturb {
+            spared.push(plan.service);
+        } else {
+            updates.push(plan.service);
+        }
+    }
+
+    let mut summary = if updates.is_empty() {
+        "images are current".to_string()
+    } else {
+        super::up(
+            config,
+            &updates,
+            true,
+            true,
+            Some(runtime::pull::PullPolicy::Always),
+        )
+        .await?;
+        format!("updated {}", updates.join(", "))
+    };
+    if !spared.is_empty() {
+        summary.push_str(&format!(
+            "; left {} alone (never_disturb)",
+            spared.join(", ")
+        ));
+    }
+    Ok(summary)
+}
+
+fn reboot_required() -> bool {
+    ["/run/reboot-required", "/var/run/reboot-required"]
+        .iter()
+        .any(|path| Path::new(path).exists())
+}
+
+/// never_disturb services whose container is running
+async fn undisturbable_running(boltfile: Option<&BoltFile>) -> Result<Vec<String>> {
+    let Some(boltfile) = boltfile else {
+        return Ok(Vec::new());
+    };
+    let protected: Vec<&String> = boltfile
+        .services
+        .iter()
+        .filter(|(_, service)| service.never_disturb == Some(true))
+        .map(|(name, _)| name)
+        .collect();
+    if protected.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    let mut running = Vec::new();
+    for name in protected {
+        let container = format!("{}_{}", boltfile.project, name);
+        if runtime::healthcheck::status(&runtime, &container)
+            .await
+            .is_some_and(|s| s.running())
+        {
+            running.push(name.clone());
+        }
+    }
+    Ok(running)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchState {
+    pub watcher_pid: u32,
+    pub since: DateTime<Utc>,
+}
+
+const WATCHER: Watcher = Watcher::new("maintenance-watch");
+
+fn watch_path(data_dir: &Path) -> PathBuf {
+    dir(data_dir).join("watch.json")
+}
+
+/// The running maintenance watcher
+pub fn watch_status(data_dir: &Path) -> Option<WatchState> {
+    let state: WatchState = watcher::load(&watch_path(data_dir))?;
+    WATCHER.alive(state.watcher_pid).then_some(state)
+}
+
+/// Start running maintenance windows in the background
+pub fn enable(data_dir: &Path, boltfile: &Path) -> Result<WatchState> {
+    if let Some(state) = watch_status(data_dir) {
+        return Ok(state);
+    }
+    let boltfile = std::path::absolute(boltfile)?;
+    let args = [
+        OsStr::new("--config"),
+        boltfile.as_os_str(),
+        OsStr::new("maintenance-watch"),
+    ];
+    let state = WatchState {
+        watcher_pid: WATCHER.spawn(args, &dir(data_dir).join("watch.log"))?,
+        since: Utc::now(),
+    };
+    watcher::save(&watch_path(data_dir), &state)?;
+    Ok(state)
+}
+
+/// Stop the maintenance watcher; false if it wasn't running
+pub fn disable(data_dir: &Path) -> bool {
+    let running = watch_status(data_dir);
+    WATCHER.forget(
+        &watch_path(data_dir),
+        running.as_ref().map(|s| s.watcher_pid),
+    );
+    running.is_some()
+}
+
+/// Announce upcoming windows, queue `tasks` when one opens and run the
+/// queue until it closes. Run by `bolt maintenance-watch`.
+pub async fn watch(config: &BoltConfig) -> Result<()> {
+    loop {
+        if !watch_path(&config.data_dir).exists() {
+            debug!("Maintenance watcher disabled, stopping");
+            return Ok(());
+        }
+        if let Err(e) = tick(config).await {
+            warn!("Maintenance: {:#}", e);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+    }
+}
+
+async fn tick(config: &BoltConfig) -> Result<()> {
+    let data_dir = &config.data_dir;
+    let boltfile = config.load_boltfile().ok();
+    let Some(window) = window(config, boltfile.as_ref())? else {
+        debug!("No maintenance window configured");
+        return Ok(());
+    };
+    let now = Local::now().naive_local();
+    let mut state = load(data_dir);
+
+    if let Some((start, end)) = window.current(now) {
+        if state.queued_for != Some(start) {
+            for task in &config.maintenance.tasks {
+                state.enqueue(*task, false);
+            }
+            state.queued_for = Some(start);
+            save(data_dir, &state)?;
+        }
+        if !state.due(now).is_empty() {
+            run(config, Some(end)).await?;
+        }
+        return Ok(());
+    }
+
+    let (start, end) = window.next(now);
+    let notice = parse_duration(&config.maintenance.notify_before)?;
+    let notice = Duration::from_std(notice).unwrap_or(Duration::zero());
+    if start - now <= notice && state.notified_for != Some(start) {
+        let mut operations: Vec<Operation> = state.queue.iter().map(|q| q.operation).collect();
+        for task in &config.maintenance.tasks {
+            if !operations.contains(task) {
+                operations.push(*task);
+            }
+        }
+        let names: Vec<&str> = operations.iter().map(|o| o.as_str()).collect();
+        let alerts = AlertingEngine::new(data_dir, config.alerts.clone());
+        notify(
+            &alerts,
+            "maintenance.upcoming",
+            AlertSeverity::Info,
+            format!(
+                "Maintenance window {} - {}: {}",
+                start.format("%a %Y-%m-%d %H:%M"),
+                end.format("%H:%M"),
+                names.join(", ")
+            ),
+        )
+        .await;
+        state.notified_for = Some(start);
+        save(data_dir, &state)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn windows_open_on_their_days_and_queue_runs_reboots_last() {
+        // 2026-10-18 is a Sunday
+        let sunday = Window::parse("Sun 03:00-05:00").unwrap();
+        assert_eq!(sunday.days, vec![Weekday::Sun]);
+        assert_eq!(
+            sunday.current(at("2026-10-18", "04:00")),
+            Some((at("2026-10-18", "03:00"), at("2026-10-18", "05:00")))
+        );
+        assert_eq!(sunday.current(at("2026-10-18", "05:00")), None);
+        assert_eq!(sunday.current(at("2026-10-17", "04:00")), None);
+        assert_eq!(
+            sunday.next(at("2026-10-18", "04:00")).0,
+            at("2026-10-25", "03:00")
+        );
+        assert_eq!(
+            sunday.next(at("2026-10-16", "12:00")).0,
+            at("2026-10-18", "03:00")
+        );
+
+        // Runs past midnight into the next day
+        let late = Window::parse("Mon-Fri 23:30-00:30").unwrap();
+        assert_eq!(late.days.len(), 5);
+        assert_eq!(
+            late.current(at("2026-10-17", "00:15")),
+            Some((at("2026-10-16", "23:30"), at("2026-10-17", "00:30")))
+        );
+        assert_eq!(late.current(at("2026-10-18", "00:15")), None);
+        assert_eq!(
+            late.next(at("2026-10-17", "00:15")).0,
+            at("2026-10-19", "23:30")
+        );
+
+        let daily = Window::parse("02:00-03:00").unwrap();
+        assert!(daily.days.is_empty());
+        assert!(daily.current(at("2026-10-14", "02:59")).is_some());
+        assert!(Window::parse("Sun 03:00-03:00").is_err());
+        assert!(Window::parse("Someday 03:00-05:00").is_err());
+        assert!(Window::parse("Sun 3am").is_err());
+
+        let mut state = MaintenanceState::default();
+        assert!(state.enqueue(Operation::Reboot, true));
+        assert!(state.enqueue(Operation::ImageUpdates, false));
+        assert!(!state.enqueue(Operation::ImageUpdates, true));
+        assert!(state.queue[1].manual);
+        let order: Vec<Operation> = state
+            .due(at("2026-10-18", "03:00"))
+            .iter()
+            .map(|q| q.operation)
+            .collect();
+        assert_eq!(order, vec![Operation::ImageUpdates, Operation::Reboot]);
+
+        state.queue[0].not_before = Some(at("2026-10-18", "05:00"));
+        assert_eq!(state.due(at("2026-10-18", "04:00")).len(), 1);
+        assert_eq!(state.due(at("2026-10-25", "03:00")).len(), 2);
+    }
+}
@@ -3,20 +3,62 @@ use crate::error::RuntimeError;
 use crate::runtime;
 use crate::{BoltError, Result};
 use anyhow::anyhow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tracing::{debug, error, info, warn};
 
+pub mod dev;
+pub mod maintenance;
+pub mod plan;
+pub mod ports;
+pub mod projects;
+pub mod report;
+pub mod resources;
 pub mod status_api;
+pub mod teardown;
+pub mod watch;
+
+/// How long a WASM workload gets to exit before it is killed
+const WASM_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How `surge up` starts services
+#[derive(Debug, Clone, Default)]
+pub struct UpOptions {
+    pub detach: bool,
+    pub force_recreate: bool,
+    /// Overrides every service's `pull_policy`
+    pub pull: Option<runtime::pull::PullPolicy>,
+    pub on_failure: report::FailureMode,
+}
 
+/// Start services, stopping at the first failure and returning it
 pub async fn up(
     config: &BoltConfig,
     services: &[String],
     detach: bool,
     force_recreate: bool,
+    pull: Option<runtime::pull::PullPolicy>,
 ) -> Result<()> {
+    let options = UpOptions {
+        detach,
+        force_recreate,
+        pull,
+        on_failure: report::FailureMode::FailFast,
+    };
+    up_with(config, services, &options).await?.into_result()
+}
+
+/// Start services and report what happened to each. Fails outright only
+/// when nothing could be attempted: an unreadable Boltfile, taken ports or
+/// unprepared hardware networks.
+pub async fn up_with(
+    config: &BoltConfig,
+    services: &[String],
+    options: &UpOptions,
+) -> Result<report::UpSummary> {
     info!("🚀 Surge orchestration starting up...");
+    let run_started = std::time::Instant::now();
 
-    let boltfile = config.load_boltfile().map_err(|e| {
+    let mut boltfile = config.load_boltfile().map_err(|e| {
         error!("Failed to load Boltfile: {}", e);
         BoltError::Other(anyhow!(
             "Cannot load Boltfile at {:?}: {}",
@@ -27,132 +69,376 @@ pub async fn up(
 
     info!("📦 Project: {}", boltfile.project);
 
+    // Refuse taken host ports before anything starts, or move them
+    let conflicts = ports::check(config, &boltfile, services).await?;
+    let refused: Vec<String> = conflicts
+        .iter()
+        .filter(|c| !c.remapped)
+        .map(|c| c.to_string())
+        .collect();
+    if !refused.is_empty() {
+        let mut port = refused.join("; ");
+        if conflicts.iter().any(|c| c.suggestion.is_some()) {
+            port.push_str(" (start with --remap-ports to use the suggested ports)");
+        }
+        return Err(BoltError::Runtime(RuntimeError::PortConflict { port }));
+    }
+    for conflict in &conflicts {
+        warn!("🔀 {}", conflict);
+    }
+    ports::remap(&mut boltfile, &conflicts);
+
     let target_services = if services.is_empty() {
         boltfile.services.keys().collect::<Vec<_>>()
     } else {
         services.iter().collect::<Vec<_>>()
     };
+    let target_services = start_order(&boltfile, target_services);
 
     info!("🎯 Target services: {:?}", target_services);
-    debug!("Detached: {}, Force recreate: {}", detach, force_recreate);
+    debug!(
+        "Detached: {}, Force recreate: {}, On failure: {:?}",
+        options.detach, options.force_recreate, options.on_failure
+    );
 
-    for service_name in target_services {
-        if let Some(service) = boltfile.services.get(service_name.as_str()) {
-            info!("🔧 Starting service: {}", service_name);
+    prepare_hardware_networks(&boltfile).await?;
+
+    let mut summary = report::UpSummary::new(&boltfile.project, options.on_failure);
+    let total = target_services.len();
+    let mut ready = HashSet::new();
+    for (index, service_name) in target_services.into_iter().enumerate() {
+        let step = format!("[{}/{}]", index + 1, total);
+        if let Some(reason) = summary.skip_reason(&boltfile, service_name) {
+            warn!("⏭️  {} Skipping {}: {}", step, service_name, reason);
+            record_up_event(config, &boltfile, service_name, "skipped", &reason);
+            summary.record_skipped(service_name, reason);
+            continue;
+        }
 
-            if let Some(ref gaming) = service.gaming {
-                info!("🎮 Gaming optimizations enabled for {}", service_name);
-                setup_gaming_service(service_name, gaming).await?;
+        info!("🔧 {} Starting service: {}", step, service_name);
+        let started = std::time::Instant::now();
+        let result = match boltfile.services.get(service_name.as_str()) {
+            Some(service) => {
+                start_service(config, &boltfile, service_name, service, options, &mut ready).await
+            }
+            None => Err(BoltError::Other(anyhow!(
+                "Service '{}' not found in Boltfile",
+                service_name
+            ))),
+        };
+        let elapsed = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(()) => {
+                record_up_event(config, &boltfile, service_name, "started", "");
+                summary.record_started(service_name, elapsed);
+            }
+            Err(e) => {
+                error!("❌ {} Service {} failed: {}", step, service_name, e);
+                record_up_event(config, &boltfile, service_name, "failed", &e.to_string());
+                summary.record_failed(service_name, e, elapsed);
             }
+        }
+    }
 
-            // Handle different service types
-            if let Some(ref image) = service.image {
-                info!("  📦 Image: {}", image);
+    projects::record(&config.data_dir, &boltfile, &summary.started_services());
+    summary.finish(run_started.elapsed().as_millis() as u64);
+    if summary.status == report::UpStatus::Ok {
+        info!("📋 {}", summary);
+    } else {
+        warn!("📋 {}", summary);
+    }
+    Ok(summary)
+}
 
-                // Prepare container arguments
-                let container_name = format!("{}_{}", boltfile.project, service_name);
-                let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
-                let env_vars = service
-                    .env
-                    .as_ref()
-                    .map(|env| {
-                        env.iter()
-                            .map(|(k, v)| format!("{}={}", k, v))
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
-                let volumes = service
-                    .volumes
-                    .as_ref()
-                    .map(|v| v.as_slice())
-                    .unwrap_or(&[]);
-
-                // Stop existing container if force_recreate
-                if force_recreate {
-                    let _ = runtime::stop_container(&container_name).await;
-                    let _ = runtime::remove_container(&container_name, true).await;
-                }
+/// Start one service, once the dependencies it waits for are ready
+async fn start_service(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service_name: &str,
+    service: &crate::config::Service,
+    options: &UpOptions,
+    ready: &mut HashSet<String>,
+) -> Result<()> {
+    let UpOptions {
+        detach,
+        force_recreate,
+        pull,
+        ..
+    } = *options;
+    wait_for_dependencies(boltfile, service_name, service, detach, ready).await?;
+
+    if let Some(ref gaming) = service.gaming {
+        info!("🎮 Gaming optimizations enabled for {}", service_name);
+        if gaming.gpu.is_some() {
+            crate::gaming::recovery::check_quarantine(&config.data_dir, service_name)?;
+        }
+        setup_gaming_service(service_name, gaming).await?;
+    }
+    check_egress(service_name, service, detach)?;
+
+    if let Some(ref workspace) = config.workspace {
+        if is_wasm(Some(service)) || service.capsule.is_some() {
+            return Err(BoltError::Other(anyhow!(
+                "Service '{}' can't run in workspace {}: only container services are admitted",
+                service_name,
+                workspace
+            )));
+        }
+    }
 
-                // Pull image if it doesn't exist locally
-                if let Err(_) = runtime::pull_image(image).await {
-                    warn!("Could not pull image {}, trying with local image", image);
-                }
+    // Handle different service types
+    if service.runtime == Some(runtime::wasm::ServiceRuntime::Wasm) {
+        let name = format!("{}_{}", boltfile.project, service_name);
+        start_wasm(config, service, &name, detach, force_recreate).await?;
+        info!("✅ Service {} started successfully", service_name);
+    } else if let Some(ref image) = service.image {
+        info!("  📦 Image: {}", image);
 
-                // Start the container
-                runtime::run_container(
-                    image,
-                    Some(&container_name),
-                    ports,
-                    &env_vars,
-                    volumes,
-                    detach,
-                )
-                .await?;
+        // Prepare container arguments
+        let container_name = format!("{}_{}", boltfile.project, service_name);
+        let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
+        let env_vars = service
+            .env
+            .as_ref()
+            .map(|env| {
+                env.iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let volumes = service
+            .volumes
+            .as_ref()
+            .map(|v| v.as_slice())
+            .unwrap_or(&[]);
 
-                info!("✅ Service {} started successfully", service_name);
-            } else if let Some(ref capsule) = service.capsule {
-                info!("  🔧 Capsule: {}", capsule);
+        // Stop existing container if force_recreate, draining it first
+        if force_recreate {
+            let _ = stop_replica(config, Some(service), &container_name).await;
+            let _ = runtime::remove_container(&container_name, true).await;
+        }
 
-                let container_name = format!("{}_{}", boltfile.project, service_name);
-                let bolt_image = format!("bolt://{}", capsule);
+        // Pull according to --pull or the service's pull_policy
+        runtime::trust::enforce(config, image, runtime::trust::Stage::Pull)
+            .await
+            .map_err(|e| e.context(format!("Cannot get image for service '{}'", service_name)))?;
+        let runtime_bin = runtime::detect_container_runtime().await?;
+        let keys = runtime::encryption::DecryptionKeys::load(config)?;
+        let platform = runtime::platform::PlatformChoice::new(
+            service.platform.as_deref(),
+            config.platform.auto_select,
+        );
+        let image_record = runtime::pull::ensure_image(
+            config,
+            &runtime_bin,
+            image,
+            pull.or(service.pull_policy),
+            &platform,
+            &keys,
+        )
+        .await
+        .map_err(|e| e.context(format!("Cannot get image for service '{}'", service_name)))?;
+
+        runtime::pull::record(&config.data_dir, &container_name, &image_record)?;
+
+        // Start the container
+        let (run_args, pending_networks) =
+            service_run_args(config, boltfile, service, image, &container_name, detach).await?;
+        if run_args.is_empty() {
+            runtime::run_container(
+                image,
+                Some(&container_name),
+                ports,
+                &env_vars,
+                volumes,
+                detach,
+            )
+            .await?;
+        } else {
+            runtime::run_oci_container_with_args(
+                image,
+                Some(&container_name),
+                ports,
+                &env_vars,
+                volumes,
+                detach,
+                &run_args,
+            )
+            .await?;
+        }
+        connect_networks(&container_name, &pending_networks).await?;
+        apply_cpu_policy(config, &container_name, service, detach).await?;
+        attach_sriov_vfs(boltfile, &container_name, service, detach).await?;
+        apply_egress(config, &container_name, service, detach).await?;
+        apply_idle(config, &container_name, service, detach)?;
+        apply_slo(config, &container_name, service, detach)?;
+        apply_log_hooks(config, &container_name, service, detach)?;
+        apply_health_watch(config, &container_name, service, detach)?;
+        apply_restart_policy(config, &container_name, service, detach)?;
+        apply_port_forward(config, &container_name, service, detach)?;
+        apply_external_dns(config, boltfile, &container_name, service, detach).await?;
+        apply_cache_profiles(config, boltfile, &container_name, service, detach).await?;
+        apply_gpu_priority(config, &container_name, service, detach)?;
+        verify_gpu_service(&container_name, service, detach).await?;
+        apply_prefix_cache(config, &container_name, service, detach).await?;
+
+        if force_recreate {
+            roll_instances(config, boltfile, service, image, &container_name, detach)
+                .await?;
+        }
 
-                runtime::run_container(&bolt_image, Some(&container_name), &[], &[], &[], detach)
-                    .await?;
+        info!("✅ Service {} started successfully", service_name);
+    } else if let Some(ref capsule) = service.capsule {
+        info!("  🔧 Capsule: {}", capsule);
+        if service.sandbox == Some(runtime::sandbox::SandboxTier::Hardened) {
+            warn!(
+                "sandbox = \"hardened\" only applies to OCI images; capsule {} runs unchanged",
+                capsule
+            );
+        }
 
-                info!("✅ Capsule {} started successfully", service_name);
-            } else if let Some(ref build) = service.build {
-                info!("  🔨 Build context: {}", build);
+        if service.desktop_integration.is_some() {
+            warn!(
+                "desktop_integration only applies to OCI images; capsule {} runs without it",
+                capsule
+            );
+        }
 
-                let image_tag = format!("{}_{}", boltfile.project, service_name);
-                let dockerfile = "Dockerfile"; // Default dockerfile name
+        let container_name = format!("{}_{}", boltfile.project, service_name);
+        let bolt_image = format!("bolt://{}", capsule);
+
+        runtime::run_container(&bolt_image, Some(&container_name), &[], &[], &[], detach)
+            .await?;
+        apply_idle(config, &container_name, service, detach)?;
+        apply_slo(config, &container_name, service, detach)?;
+        apply_log_hooks(config, &container_name, service, detach)?;
+
+        info!("✅ Capsule {} started successfully", service_name);
+    } else if let Some(ref build) = service.build {
+        info!("  🔨 Build context: {}", build);
+
+        let image_tag = format!("{}_{}", boltfile.project, service_name);
+        let dockerfile = "Dockerfile"; // Default dockerfile name
+
+        // Build the image
+        runtime::trust::enforce_build(config, std::path::Path::new(build), dockerfile)
+            .await
+            .map_err(|e| e.context(format!("Cannot build service '{}'", service_name)))?;
+        let caches = crate::builds::artifacts::resolve(
+            &config.data_dir,
+            service.cache_volumes.as_deref().unwrap_or_default(),
+        )?;
+        {
+            let _lease = crate::builds::artifacts::lease(&config.data_dir, &caches)?;
+            runtime::build_image_with_caches(build, Some(&image_tag), dockerfile, &caches).await?;
+        }
+        if !caches.is_empty() {
+            crate::builds::artifacts::enforce(config, &runtime::detect_container_runtime().await?)
+                .await;
+        }
 
-                // Build the image
-                runtime::build_image(build, Some(&image_tag), dockerfile).await?;
+        // Run the built image
+        let container_name = format!("{}_{}", boltfile.project, service_name);
+        if force_recreate {
+            let _ = stop_replica(config, Some(service), &container_name).await;
+            let _ = runtime::remove_container(&container_name, true).await;
+        }
+        let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
+        let env_vars = service
+            .env
+            .as_ref()
+            .map(|env| {
+                env.iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let volumes = service
+            .volumes
+            .as_ref()
+            .map(|v| v.as_slice())
+            .unwrap_or(&[]);
 
-                // Run the built image
-                let container_name = format!("{}_{}", boltfile.project, service_name);
-                let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
-                let env_vars = service
-                    .env
-                    .as_ref()
-                    .map(|env| {
-                        env.iter()
-                            .map(|(k, v)| format!("{}={}", k, v))
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
-                let volumes = service
-                    .volumes
-                    .as_ref()
-                    .map(|v| v.as_slice())
-                    .unwrap_or(&[]);
-
-                runtime::run_container(
-                    &image_tag,
-                    Some(&container_name),
-                    ports,
-                    &env_vars,
-                    volumes,
-                    detach,
-                )
+        let (run_args, pending_networks) =
+            service_run_args(config, boltfile, service, &image_tag, &container_name, detach)
+                .await?;
+        runtime::run_oci_container_with_args(
+            &image_tag,
+            Some(&container_name),
+            ports,
+            &env_vars,
+            volumes,
+            detach,
+            &run_args,
+        )
+        .await?;
+        connect_networks(&container_name, &pending_networks).await?;
+        apply_cpu_policy(config, &container_name, service, detach).await?;
+        attach_sriov_vfs(boltfile, &container_name, service, detach).await?;
+        apply_egress(config, &container_name, service, detach).await?;
+        apply_idle(config, &container_name, service, detach)?;
+        apply_slo(config, &container_name, service, detach)?;
+        apply_log_hooks(config, &container_name, service, detach)?;
+        apply_health_watch(config, &container_name, service, detach)?;
+        apply_restart_policy(config, &container_name, service, detach)?;
+        apply_port_forward(config, &container_name, service, detach)?;
+        apply_external_dns(config, boltfile, &container_name, service, detach).await?;
+        apply_cache_profiles(config, boltfile, &container_name, service, detach).await?;
+        apply_gpu_priority(config, &container_name, service, detach)?;
+        verify_gpu_service(&container_name, service, detach).await?;
+        apply_prefix_cache(config, &container_name, service, detach).await?;
+
+        if force_recreate {
+            roll_instances(config, boltfile, service, &image_tag, &container_name, detach)
                 .await?;
-
-                info!("✅ Service {} built and started successfully", service_name);
-            } else {
-                error!(
-                    "Service {} has no image, capsule, or build configuration",
-                    service_name
-                );
-            }
-        } else {
-            error!("Service '{}' not found in Boltfile", service_name);
         }
-    }
 
+        info!("✅ Service {} built and started successfully", service_name);
+    } else {
+        return Err(BoltError::Other(anyhow!(
+            "Service {} has no image, capsule, or build configuration",
+            service_name
+        )));
+    }
     Ok(())
 }
 
+/// Record a service's `surge up` outcome in its event history
+fn record_up_event(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service_name: &str,
+    outcome: &str,
+    detail: &str,
+) {
+    let container = format!("{}_{}", boltfile.project, service_name);
+    let mut event = runtime::events::ContainerEvent::new(&container, "surge-up")
+        .with_attribute("outcome", outcome);
+    if !detail.is_empty() {
+        event = event.with_attribute("detail", detail);
+    }
+    if let Err(e) =
+        runtime::events::EventLog::new(config.data_dir.clone()).and_then(|log| log.record(&event))
+    {
+        debug!("Failed to record surge-up event for {}: {}", container, e);
+    }
+}
+
 pub async fn down(config: &BoltConfig, services: &[String], remove_volumes: bool) -> Result<()> {
+    let options = teardown::DownOptions {
+        remove_volumes,
+        ..Default::default()
+    };
+    down_with(config, services, &options).await.map(|_| ())
+}
+
+/// Stop services dependents first, optionally remove orphans, and remove
+/// the project networks nothing uses any more. Reports what was removed.
+pub async fn down_with(
+    config: &BoltConfig,
+    services: &[String],
+    options: &teardown::DownOptions,
+) -> Result<teardown::DownSummary> {
     info!("🛑 Surge orchestration shutting down...");
 
     let boltfile = config.load_boltfile()?;
@@ -163,35 +449,190 @@ pub async fn down(config: &BoltConfig, services: &[String], remove_volumes: bool
     } else {
         services.iter().collect::<Vec<_>>()
     };
+    let target_services = teardown::stop_order(&boltfile, target_services);
 
     debug!("Target services: {:?}", target_services);
-    debug!("Remove volumes: {}", remove_volumes);
+    debug!(
+        "Remove volumes: {}, remove orphans: {}",
+        options.remove_volumes, options.remove_orphans
+    );
+
+    let existing = match runtime::list_containers_info(true).await {
+        Ok(containers) => Some(containers),
+        Err(e) => {
+            warn!("Could not list containers: {}", e);
+            None
+        }
+    };
+    let record = projects::get(&config.data_dir, &boltfile.project);
+    let mut summary = teardown::DownSummary::new(&boltfile.project);
 
-    for service_name in target_services {
+    for service_name in &target_services {
         info!("🛑 Stopping service: {}", service_name);
 
+        let service = boltfile.services.get(service_name.as_str());
         let container_name = format!("{}_{}", boltfile.project, service_name);
 
-        // Stop the container
-        if let Err(e) = runtime::stop_container(&container_name).await {
-            warn!("Failed to stop container {}: {}", container_name, e);
+        if is_wasm(service) {
+            let grace = teardown::grace_period(service);
+            if let Err(e) = runtime::wasm::stop(&config.data_dir, &container_name, grace).await {
+                warn!("Failed to stop WASM workload {}: {}", container_name, e);
+            }
+            let error = runtime::wasm::remove(&config.data_dir, &container_name)
+                .err()
+                .map(|e| {
+                    warn!("Failed to remove WASM workload {}: {}", container_name, e);
+                    e.to_string()
+                });
+            summary.stopped.push(teardown::StoppedContainer {
+                service: service_name.to_string(),
+                container: container_name,
+                killed: false,
+                error,
+            });
+            info!("✅ Service {} stopped successfully", service_name);
+            continue;
         }
 
-        // Remove the container
-        if let Err(e) = runtime::remove_container(&container_name, false).await {
-            warn!("Failed to remove container {}: {}", container_name, e);
+        let containers = match existing {
+            Some(ref existing) => teardown::service_containers(&container_name, existing),
+            None => vec![container_name.clone()],
+        };
+        if containers.is_empty() {
+            debug!("{} has no containers", service_name);
         }
-
-        // Remove volumes if requested
-        if remove_volumes {
-            info!("🗑️  Removing volumes for service: {}", service_name);
-            // Volume removal logic would go here
+        for container in containers {
+            let stopped = take_down(config, service_name, service, &container).await;
+            if options.remove_volumes {
+                info!("🗑️  Removing volumes for: {}", container);
+                // Volume removal logic would go here
+                runtime::identity::remove(&config.data_dir, &container);
+            }
+            summary.stopped.push(stopped);
         }
 
         info!("✅ Service {} stopped successfully", service_name);
     }
 
-    Ok(())
+    let orphans = teardown::orphans(
+        &boltfile,
+        existing.as_deref().unwrap_or_default(),
+        record.as_ref(),
+    );
+    let mut forgotten: Vec<String> = Vec::new();
+    if options.remove_orphans {
+        for (service_name, container) in &orphans {
+            info!("🧹 Removing orphan container: {}", container);
+            summary
+                .orphans_removed
+                .push(take_down(config, service_name, None, container).await);
+            forgotten.push(service_name.clone());
+        }
+    } else {
+        summary.orphans = orphans
+            .into_iter()
+            .map(|(_, container)| container)
+            .collect();
+        if !summary.orphans.is_empty() {
+            warn!(
+                "Found orphan containers ({}) for project {}: run with --remove-orphans to remove them",
+                summary.orphans.join(", "),
+                boltfile.project
+            );
+        }
+    }
+
+    // Forget the stopped services before counting who still joins a network
+    let orphan_networks = match record {
+        Some(ref record) if !forgotten.is_empty() => record.networks.clone(),
+        _ => Vec::new(),
+    };
+    let stopped: Vec<&String> = target_services
+        .iter()
+        .copied()
+        .chain(forgotten.iter())
+        .collect();
+    projects::forget(&config.data_dir, &boltfile, &stopped);
+
+    let networks = teardown::candidate_networks(&boltfile, &target_services, &orphan_networks);
+    if !networks.is_empty() {
+        let runtime = runtime::detect_container_runtime().await?;
+        let records = projects::list(&config.data_dir);
+        for network in networks {
+            let mut users = match teardown::network_users(&runtime, &network).await {
+                Ok(users) => users,
+                Err(e) => {
+                    warn!("Keeping network {}: {}", network, e);
+                    continue;
+                }
+            };
+            users.extend(
+                records
+                    .iter()
+                    .filter(|r| r.networks.contains(&network))
+                    .map(|r| format!("project {}", r.project)),
+            );
+            if !users.is_empty() {
+                debug!("Keeping network {}: used by {}", network, users.join(", "));
+                summary
+                    .networks_kept
+                    .push(teardown::KeptNetwork { network, users });
+                continue;
+            }
+            match crate::network::remove_network(&network).await {
+                Ok(()) => summary.networks_removed.push(network),
+                Err(e) => warn!("Failed to remove network {}: {}", network, e),
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Stop and remove one container of a service, forgetting its state
+async fn take_down(
+    config: &BoltConfig,
+    service_name: &str,
+    service: Option<&crate::config::Service>,
+    container_name: &str,
+) -> teardown::StoppedContainer {
+    // Stop watching for idleness first, so a stopped container isn't woken
+    runtime::idle::forget(&config.data_dir, container_name);
+    crate::monitoring::slo::forget(&config.data_dir, container_name);
+    runtime::log_hooks::forget(&config.data_dir, container_name);
+    runtime::healthcheck::forget(&config.data_dir, container_name);
+    runtime::supervisor::forget(&config.data_dir, container_name);
+    runtime::desktop::forget(&config.data_dir, container_name);
+
+    // Stop the container, letting its connections drain first
+    let killed = stop_replica(config, service, container_name)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to stop container {}: {}", container_name, e);
+            false
+        });
+
+    let error = runtime::remove_container(container_name, false)
+        .await
+        .err()
+        .map(|e| {
+            warn!("Failed to remove container {}: {}", container_name, e);
+            e.to_string()
+        });
+    runtime::pull::forget(&config.data_dir, container_name);
+    crate::networking::egress::forget(&config.data_dir, container_name);
+    crate::networking::nat::forget(&config.data_dir, container_name);
+    crate::networking::external_dns::release(config, container_name).await;
+    crate::volume::cache::forget(&config.data_dir, container_name);
+    crate::gaming::prefix::end_session(&config.data_dir, container_name).await;
+    crate::gaming::display::forget(&config.data_dir, container_name);
+
+    teardown::StoppedContainer {
+        service: service_name.to_string(),
+        container: container_name.to_string(),
+        killed,
+        error,
+    }
 }
 
 pub async fn status(config: &BoltConfig) -> Result<()> {
@@ -212,6 +653,12 @@ pub async fn status(config: &BoltConfig) -> Result<()> {
         let container = containers.iter().find(|c| c.name == container_name);
 
         let (status, container_id) = match container {
+            _ if is_wasm(Some(service)) => {
+                match runtime::wasm::state(&config.data_dir, &container_name) {
+                    Some(state) => (state.describe(), format!("wasm:{}", state.pid)),
+                    None => ("not running".to_string(), "-".to_string()),
+                }
+            }
             Some(c) => (c.status.clone(), c.id[..12].to_string()),
             None => ("not running".to_string(), "-".to_string()),
         };
@@ -223,6 +670,11 @@ pub async fn status(config: &BoltConfig) -> Result<()> {
             .unwrap_or_else(|| "-".to_string());
 
         println!("{:<15} {:<12} {:<15} {}", name, status, container_id, ports);
+        if let Some(restarts) = runtime::supervisor::state(&config.data_dir, &container_name)
+            .and_then(|state| state.describe())
+        {
+            println!("                ↻ {}", restarts);
+        }
     }
 
     Ok(())
@@ -236,57 +688,59 @@ pub async fn logs(
 ) -> Result<()> {
     let boltfile = config.load_boltfile()?;
     let runtime = crate::runtime::detect_container_runtime().await?;
+    let log_dir = config.container_log_dir();
 
     match service {
         Some(service_name) => {
             info!("📜 Showing logs for service: {}", service_name);
             let container_name = format!("{}_{}", boltfile.project, service_name);
-
-            let mut cmd = tokio::process::Command::new(&runtime);
-            cmd.arg("logs");
-
-            if follow {
-                cmd.arg("-f");
+            if is_wasm(boltfile.services.get(service_name)) {
+                return Ok(
+                    runtime::wasm::logs(&config.data_dir, &container_name, follow, tail).await?,
+                );
             }
+            let logging = boltfile
+                .services
+                .get(service_name)
+                .and_then(|s| s.logging.as_ref());
 
-            if let Some(tail_count) = tail {
-                cmd.arg("--tail").arg(tail_count.to_string());
+            if let Some(logging) = logging {
+                runtime::log_driver::rotate(&runtime, &container_name, logging, &log_dir).await?;
             }
-
-            cmd.arg(&container_name);
-
-            let output = cmd.output().await?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(BoltError::Runtime(RuntimeError::StartFailed {
-                    reason: format!("Failed to get logs: {}", stderr),
-                }));
-            }
-
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("{}", stdout);
+            runtime::log_driver::show_logs(&runtime, &container_name, logging, follow, tail)
+                .await
+                .map_err(|e| {
+                    BoltError::Runtime(RuntimeError::StartFailed {
+                        reason: e.to_string(),
+                    })
+                })?;
         }
         None => {
             info!("📜 Showing logs for all services");
-            for service_name in boltfile.services.keys() {
+            for (service_name, service) in &boltfile.services {
                 println!("==> {} <==", service_name);
                 let container_name = format!("{}_{}", boltfile.project, service_name);
-
-                let mut cmd = tokio::process::Command::new(&runtime);
-                cmd.arg("logs");
-
-                if let Some(tail_count) = tail {
-                    cmd.arg("--tail").arg(tail_count.to_string());
+                if is_wasm(Some(service)) {
+                    if let Err(e) =
+                        runtime::wasm::logs(&config.data_dir, &container_name, false, tail).await
+                    {
+                        debug!("{}", e);
+                    }
+                    println!();
+                    continue;
                 }
+                let logging = service.logging.as_ref();
 
-                cmd.arg(&container_name);
-
-                if let Ok(output) = cmd.output().await {
-                    if output.status.success() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        println!("{}", stdout);
-                    }
+                if let Some(logging) = logging {
+                    let _ =
+                        runtime::log_driver::rotate(&runtime, &container_name, logging, &log_dir)
+                            .await;
+                }
+                if let Err(e) =
+                    runtime::log_driver::show_logs(&runtime, &container_name, logging, false, tail)
+                        .await
+                {
+                    debug!("{}", e);
                 }
                 println!();
             }
@@ -337,36 +791,25 @@ pub async fn scale(config: &BoltConfig, services: &[String]) -> Result<()> {
         if count > current_count {
             // Scale up - start new instances
             let service = boltfile.services.get(service_name).unwrap();
+            let static_address = service
+                .networks
+                .as_ref()
+                .map(|n| n.attachments())
+                .into_iter()
+                .flatten()
+                .any(|(_, a)| a.ipv4_address.is_some() || a.ipv6_address.is_some());
+            if static_address && count > 1 {
+                return Err(BoltError::Other(anyhow!(
+                    "Cannot scale {} to {} instances: it has a static network address",
+                    service_name,
+                    count
+                )));
+            }
             for i in current_count..count {
                 let instance_name = format!("{}_{}", container_prefix, i + 1);
 
                 if let Some(ref image) = service.image {
-                    let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
-                    let env_vars = service
-                        .env
-                        .as_ref()
-                        .map(|env| {
-                            env.iter()
-                                .map(|(k, v)| format!("{}={}", k, v))
-                                .collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    let volumes = service
-                        .volumes
-                        .as_ref()
-                        .map(|v| v.as_slice())
-                        .unwrap_or(&[]);
-
-                    runtime::run_container(
-                        image,
-                        Some(&instance_name),
-                        ports,
-                        &env_vars,
-                        volumes,
-                        true, // Always detached for scaling
-                    )
-                    .await?;
-
+                    start_instance(config, &boltfile, service, image, &instance_name).await?;
                     info!("✅ Started instance: {}", instance_name);
                 }
             }
@@ -375,8 +818,21 @@ pub async fn scale(config: &BoltConfig, services: &[String]) -> Result<()> {
             let containers_to_stop = current_count - count;
             for i in 0..containers_to_stop {
                 if let Some(container) = current_containers.get(i as usize) {
-                    let _ = runtime::stop_container(&container.name).await;
+                    let service = boltfile.services.get(service_name);
+                    runtime::idle::forget(&config.data_dir, &container.name);
+                    crate::monitoring::slo::forget(&config.data_dir, &container.name);
+                    runtime::log_hooks::forget(&config.data_dir, &container.name);
+                    runtime::healthcheck::forget(&config.data_dir, &container.name);
+                    runtime::supervisor::forget(&config.data_dir, &container.name);
+                    runtime::desktop::forget(&config.data_dir, &container.name);
+                    let _ = stop_replica(config, service, &container.name).await;
                     let _ = runtime::remove_container(&container.name, false).await;
+                    crate::networking::egress::forget(&config.data_dir, &container.name);
+                    crate::networking::nat::forget(&config.data_dir, &container.name);
+                    crate::networking::external_dns::release(config, &container.name).await;
+                    crate::volume::cache::forget(&config.data_dir, &container.name);
+                    crate::gaming::prefix::end_session(&config.data_dir, &container.name).await;
+                    crate::gaming::display::forget(&config.data_dir, &container.name);
                     info!("✅ Stopped instance: {}", container.name);
                 }
             }
@@ -388,6 +844,138 @@ pub async fn scale(config: &BoltConfig, services: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Start one detached instance of a scaled service
+async fn start_instance(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service: &crate::config::Service,
+    image: &str,
+    instance_name: &str,
+) -> Result<()> {
+    let ports = service.ports.as_ref().map(|p| p.as_slice()).unwrap_or(&[]);
+    let env_vars = service
+        .env
+        .as_ref()
+        .map(|env| {
+            env.iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let volumes = service
+        .volumes
+        .as_ref()
+        .map(|v| v.as_slice())
+        .unwrap_or(&[]);
+
+    let (run_args, pending_networks) =
+        service_run_args(config, boltfile, service, image, instance_name, true).await?;
+    if run_args.is_empty() {
+        runtime::run_container(
+            image,
+            Some(instance_name),
+            ports,
+            &env_vars,
+            volumes,
+            true, // Always detached for scaling
+        )
+        .await?;
+    } else {
+        runtime::run_oci_container_with_args(
+            image,
+            Some(instance_name),
+            ports,
+            &env_vars,
+            volumes,
+            true,
+            &run_args,
+        )
+        .await?;
+    }
+    connect_networks(instance_name, &pending_networks).await?;
+    apply_cpu_policy(config, instance_name, service, true).await?;
+    attach_sriov_vfs(boltfile, instance_name, service, true).await?;
+    apply_egress(config, instance_name, service, true).await?;
+    apply_idle(config, instance_name, service, true)?;
+    apply_slo(config, instance_name, service, true)?;
+    apply_log_hooks(config, instance_name, service, true)?;
+    apply_health_watch(config, instance_name, service, true)?;
+    apply_restart_policy(config, instance_name, service, true)?;
+    apply_port_forward(config, instance_name, service, true)?;
+    apply_external_dns(config, boltfile, instance_name, service, true).await?;
+    apply_prefix_cache(config, instance_name, service, true).await?;
+    Ok(())
+}
+
+/// Stop a replica within its service's `stop_grace_period`, first draining
+/// its connections when the service has `[drain]` settings. Returns whether
+/// it had to be killed.
+async fn stop_replica(
+    config: &BoltConfig,
+    service: Option<&crate::config::Service>,
+    container_name: &str,
+) -> Result<bool> {
+    if let Some(drain) = service.and_then(|s| s.drain.as_ref()) {
+        let policy = runtime::drain::DrainPolicy::from_config(drain)?;
+        let runtime = runtime::detect_container_runtime().await?;
+        if let Err(e) =
+            runtime::drain::drain(&runtime, &config.data_dir, container_name, &policy).await
+        {
+            warn!("Could not drain {}: {}", container_name, e);
+        }
+    }
+    runtime::supervisor::stopping(&config.data_dir, container_name);
+    let result =
+        runtime::stop_container_within(container_name, teardown::grace_period(service)).await;
+    runtime::drain::finish(&config.data_dir, container_name);
+    runtime::workspace::stopped(&config.data_dir, container_name);
+    crate::optimizations::hybrid::forget(&config.data_dir, container_name);
+    result
+}
+
+/// Replace the scaled instances of a service one at a time, after the main
+/// container: each is drained, recreated and, with a readiness probe, ready
+/// again before the next one goes
+async fn roll_instances(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service: &crate::config::Service,
+    image: &str,
+    container_name: &str,
+    detach: bool,
+) -> Result<()> {
+    let prefix = format!("{}_", container_name);
+    let mut instances: Vec<String> = runtime::list_containers_info(true)
+        .await?
+        .into_iter()
+        .map(|c| c.name)
+        .filter(|name| {
+            name.strip_prefix(&prefix)
+                .is_some_and(|n| n.parse::<u32>().is_ok())
+        })
+        .collect();
+    if instances.is_empty() {
+        return Ok(());
+    }
+    instances.sort();
+
+    let runtime = runtime::detect_container_runtime().await?;
+    if let (Some(readiness), true) = (service.readiness.as_ref(), detach) {
+        runtime::readiness::wait_for(&runtime, container_name, readiness).await?;
+    }
+    for instance in &instances {
+        info!("🔄 Replacing instance {}", instance);
+        let _ = stop_replica(config, Some(service), instance).await;
+        let _ = runtime::remove_container(instance, true).await;
+        start_instance(config, boltfile, service, image, instance).await?;
+        if let (Some(readiness), true) = (service.readiness.as_ref(), detach) {
+            runtime::readiness::wait_for(&runtime, instance, readiness).await?;
+        }
+    }
+    info!("✅ Replaced {} instance(s)", instances.len());
+    Ok(())
+}
+
 async fn setup_gaming_service(
     service_name: &str,
     gaming_config: &crate::config::GamingConfig,
@@ -408,6 +996,12 @@ async fn setup_gaming_service(
         if let Some(ref amd) = gpu.amd {
             info!("    🔴 AMD GPU (device: {:?})", amd.device);
         }
+        if let Some(ref intel) = gpu.intel {
+            info!("    🔵 Intel GPU (device: {:?})", intel.device);
+            if intel.quicksync != Some(false) {
+                info!("    🎞️  QuickSync enabled");
+            }
+        }
     }
 
     if let Some(ref audio) = gaming_config.audio {
@@ -432,3 +1026,1219 @@ async fn setup_gaming_service(
 }
 
 // API-only functions for library usage
+
+/// Pin a started service container to its configured core type and set the
+/// energy/performance preference of those cores
+async fn apply_cpu_policy(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let placement = service.cpu_placement.unwrap_or_default();
+    if placement == crate::optimizations::hybrid::CorePlacement::Any
+        && service.energy_performance_preference.is_none()
+    {
+        return Ok(());
+    }
+    if !detach {
+        warn!(
+            "CPU placement for {} is only applied to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    crate::optimizations::hybrid::apply_to_container(
+        &runtime,
+        &config.data_dir,
+        container_name,
+        placement,
+        service.energy_performance_preference,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Create bonded/teamed uplinks and SR-IOV VFs for networks with a
+/// `hardware` section
+async fn prepare_hardware_networks(boltfile: &BoltFile) -> Result<()> {
+    let hardware_networks: Vec<_> = boltfile
+        .networks
+        .iter()
+        .flatten()
+        .filter_map(|(name, network)| network.hardware.as_ref().map(|hw| (name, network, hw)))
+        .collect();
+    if hardware_networks.is_empty() {
+        return Ok(());
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    for (name, network, hardware) in hardware_networks {
+        let name = network.name.as_deref().unwrap_or(name);
+        let subnet = network
+            .ipam
+            .as_ref()
+            .and_then(|ipam| ipam.config.as_ref())
+            .and_then(|config| config.first())
+            .and_then(|pool| pool.subnet.as_deref());
+        crate::networking::hardware::prepare_network(&runtime, name, hardware, subnet).await?;
+    }
+    Ok(())
+}
+
+/// Order services so each starts after the services it depends on
+pub(crate) fn start_order<'a>(boltfile: &BoltFile, targets: Vec<&'a String>) -> Vec<&'a String> {
+    fn visit<'a>(
+        name: &'a String,
+        boltfile: &BoltFile,
+        targets: &[&'a String],
+        visited: &mut HashSet<String>,
+        order: &mut Vec<&'a String>,
+    ) {
+        if !visited.insert(name.clone()) {
+            return;
+        }
+        let deps = boltfile
+            .services
+            .get(name)
+            .and_then(|s| s.depends_on.as_ref());
+        for dep in deps.into_iter().flat_map(|d| d.names()) {
+            // Only order among the services being started
+            if let Some(target) = targets.iter().find(|t| **t == dep) {
+                visit(target, boltfile, targets, visited, order);
+            }
+        }
+        order.push(name);
+    }
+
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    for name in &targets {
+        visit(name, boltfile, &targets, &mut visited, &mut order);
+    }
+    order
+}
+
+/// Hold a service back until the dependencies with a readiness probe pass
+/// it, and those it waits on to be healthy pass their healthcheck
+async fn wait_for_dependencies(
+    boltfile: &BoltFile,
+    service_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+    ready: &mut HashSet<String>,
+) -> Result<()> {
+    let Some(ref deps) = service.depends_on else {
+        return Ok(());
+    };
+    for dep in deps.names() {
+        let Some(dep_service) = boltfile.services.get(dep) else {
+            continue;
+        };
+        let healthcheck = match deps.condition(dep) {
+            crate::config::DependencyCondition::ServiceHealthy => dep_service.healthcheck.as_ref(),
+            crate::config::DependencyCondition::ServiceStarted => None,
+        };
+        // Waited for already, by another service depending on it
+        let waited = format!(
+            "{}{}",
+            dep,
+            if healthcheck.is_some() {
+                ":healthy"
+            } else {
+                ""
+            }
+        );
+        if (dep_service.readiness.is_none() && healthcheck.is_none()) || ready.contains(&waited) {
+            continue;
+        }
+        if !detach {
+            warn!(
+                "Not waiting for {} to become ready: readiness gating needs --detach",
+                dep
+            );
+            continue;
+        }
+
+        let runtime = runtime::detect_container_runtime().await?;
+        let container_name = format!("{}_{}", boltfile.project, dep);
+        let not_ready = |e: anyhow::Error, state: &str| {
+            BoltError::Other(anyhow!(
+                "{} depends on {}, which is not {}: {}",
+                service_name,
+                dep,
+                state,
+                e
+            ))
+        };
+        if let Some(healthcheck) = healthcheck {
+            let spec = runtime::healthcheck::HealthcheckSpec::from_config(healthcheck)?;
+            runtime::healthcheck::wait_healthy(&runtime, &container_name, &spec)
+                .await
+                .map_err(|e| not_ready(e, "healthy"))?;
+        }
+        if let Some(ref readiness) = dep_service.readiness {
+            runtime::readiness::wait_for(&runtime, &container_name, readiness)
+                .await
+                .map_err(|e| not_ready(e, "ready"))?;
+        }
+        ready.insert(dep.clone());
+        ready.insert(waited);
+    }
+    Ok(())
+}
+
+/// Hand each SR-IOV network the service joins a VF of its own
+async fn attach_sriov_vfs(
+    boltfile: &BoltFile,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref service_networks) = service.networks else {
+        return Ok(());
+    };
+
+    for network_name in service_networks.names() {
+        let Some(network) = boltfile
+            .networks
+            .as_ref()
+            .and_then(|networks| networks.get(network_name))
+        else {
+            continue;
+        };
+        let Some(sriov) = network.hardware.as_ref().and_then(|hw| hw.sriov.as_ref()) else {
+            continue;
+        };
+        if !detach {
+            warn!(
+                "SR-IOV VFs for {} are only attached to detached services",
+                container_name
+            );
+            return Ok(());
+        }
+
+        let runtime = runtime::detect_container_runtime().await?;
+        let name = network.name.as_deref().unwrap_or(network_name);
+        crate::networking::hardware::attach_vf(&runtime, container_name, name, sriov).await?;
+    }
+    Ok(())
+}
+
+/// Egress rules are loaded into a running container that waits at a gate
+/// until then, so an enforced policy can't protect a service that runs
+/// attached
+fn check_egress(service_name: &str, service: &crate::config::Service, detach: bool) -> Result<()> {
+    let Some(ref egress) = service.egress else {
+        return Ok(());
+    };
+    if detach {
+        return Ok(());
+    }
+    if egress.mode == crate::networking::egress::EgressMode::Enforce {
+        return Err(BoltError::Other(anyhow!(
+            "Service '{}' has an egress policy and must be started detached (-d)",
+            service_name
+        )));
+    }
+    warn!(
+        "Egress audit for {} only applies to detached services",
+        service_name
+    );
+    Ok(())
+}
+
+/// Load the service's egress policy into its container
+async fn apply_egress(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref egress) = service.egress else {
+        return Ok(());
+    };
+    if !detach {
+        return Ok(());
+    }
+    let policy = crate::networking::egress::EgressPolicy::from_config(egress)?;
+    let runtime = runtime::detect_container_runtime().await?;
+    crate::networking::egress::apply(&runtime, &config.data_dir, container_name, &policy).await?;
+    Ok(())
+}
+
+/// Watch a detached service for idleness
+fn apply_idle(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref idle) = service.idle else {
+        return Ok(());
+    };
+    if !detach {
+        warn!(
+            "Idle suspension for {} only applies to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    let ports = service.ports.as_deref().unwrap_or_default();
+    let policy = runtime::idle::IdlePolicy::from_config(idle, ports)?;
+    if policy.wake && policy.action != runtime::idle::IdleAction::Pause && policy.ports.is_empty() {
+        warn!(
+            "{} publishes no ports to wake it on; resume it with `bolt surge idle wake`",
+            container_name
+        );
+    }
+    runtime::idle::start(&config.data_dir, container_name, &policy)?;
+    Ok(())
+}
+
+/// Probe a detached service's published ports for its objectives
+fn apply_slo(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref slo) = service.slo else {
+        return Ok(());
+    };
+    if !detach {
+        warn!(
+            "The SLO of {} only applies to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    let ports = service.ports.as_deref().unwrap_or_default();
+    let spec = crate::monitoring::slo::SloSpec::from_config(slo, ports)?;
+    if spec.ports.is_empty() {
+        warn!(
+            "{} publishes no TCP ports to probe; its SLO isn't tracked",
+            container_name
+        );
+        return Ok(());
+    }
+    crate::monitoring::slo::start(&config.data_dir, container_name, &spec)?;
+    Ok(())
+}
+
+/// Follow a detached service's logs for its log hooks
+fn apply_log_hooks(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref hooks) = service.log_hooks else {
+        return Ok(());
+    };
+    if !detach {
+        warn!(
+            "Log hooks for {} only apply to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    runtime::log_hooks::start(&config.data_dir, container_name, hooks)?;
+    Ok(())
+}
+
+/// Restart a detached service whenever its healthcheck fails it, if its
+/// restart policy says to restart it at all
+fn apply_health_watch(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref healthcheck) = service.healthcheck else {
+        return Ok(());
+    };
+    let Some(ref restart) = service.restart else {
+        return Ok(());
+    };
+    if !runtime::healthcheck::HealthcheckSpec::from_config(healthcheck)?.enabled()
+        || runtime::healthcheck::restart_policy(restart)? == crate::config::RestartPolicy::No
+    {
+        return Ok(());
+    }
+    if !detach {
+        warn!(
+            "Restarting {} when unhealthy only applies to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    runtime::healthcheck::start(&config.data_dir, container_name)?;
+    Ok(())
+}
+
+/// Restart a detached service when it exits, as its restart policy says
+fn apply_restart_policy(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(ref restart) = service.restart else {
+        return Ok(());
+    };
+    if !runtime::supervisor::RestartSpec::parse(restart)?.enabled() {
+        return Ok(());
+    }
+    if !detach {
+        warn!(
+            "restart = \"{}\" only applies to detached services; {} runs once",
+            restart, container_name
+        );
+        return Ok(());
+    }
+    runtime::supervisor::start(&config.data_dir, container_name, restart)?;
+    Ok(())
+}
+
+/// Make a detached service's published ports reachable from the internet
+fn apply_port_forward(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    if service.port_forward != Some(true) {
+        return Ok(());
+    }
+    if !detach {
+        warn!(
+            "port_forward for {} only applies to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    let ports =
+        crate::networking::nat::published_ports(service.ports.as_deref().unwrap_or_default());
+    if ports.is_empty() {
+        warn!(
+            "{} publishes no ports to other machines; nothing to forward",
+            container_name
+        );
+        return Ok(());
+    }
+    if config.nat.protocol == crate::networking::nat::MappingProtocol::Off
+        && config.nat.relay.is_none()
+    {
+        warn!(
+            "[nat] protocol is off and no relay is set; ports of {} stay unforwarded",
+            container_name
+        );
+        return Ok(());
+    }
+    crate::networking::nat::start(&config.data_dir, container_name, ports)?;
+    Ok(())
+}
+
+/// Register a detached container with the external DNS providers of its
+/// networks and `[dns.published]`
+async fn apply_external_dns(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    if !detach {
+        return Ok(());
+    }
+    let runtime = runtime::detect_container_runtime().await?;
+    crate::networking::external_dns::register(&runtime, config, boltfile, service, container_name)
+        .await?;
+    Ok(())
+}
+
+/// Start the GPU arbiter for a detached interactive or batch GPU service
+fn apply_gpu_priority(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref()) else {
+        return Ok(());
+    };
+    let prioritized = crate::gaming::priority::GpuPriority::of(gpu)
+        != crate::gaming::priority::GpuPriority::Normal;
+    if !prioritized && gpu.fraction.is_none() {
+        return Ok(());
+    }
+    if prioritized && !detach {
+        warn!(
+            "GPU priority of {} is only enforced for detached services",
+            container_name
+        );
+        if gpu.fraction.is_none() {
+            return Ok(());
+        }
+    }
+    // The arbiter also releases GPU slices once their containers are gone
+    crate::gaming::priority::ensure_arbiter(&config.data_dir)?;
+    Ok(())
+}
+
+/// Write a cached Wine prefix back when the session ends: now for an
+/// attached service, whose container has exited, or once a detached one
+/// stops
+async fn apply_prefix_cache(
+    config: &BoltConfig,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    if service
+        .gaming
+        .as_ref()
+        .and_then(|g| g.wine.as_ref())
+        .and_then(crate::gaming::prefix::cached_prefix)
+        .is_none()
+    {
+        return Ok(());
+    }
+    if detach {
+        crate::gaming::prefix::start(&config.data_dir, container_name)?;
+    } else {
+        crate::gaming::prefix::end_session(&config.data_dir, container_name).await;
+    }
+    Ok(())
+}
+
+/// Tune the page cache of a detached service's volumes as their
+/// `cache_profile` and `hot_paths` say
+async fn apply_cache_profiles(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    let tuned = crate::volume::cache::tuned_volumes(boltfile, service);
+    if tuned.is_empty() {
+        return Ok(());
+    }
+    if !detach {
+        warn!(
+            "Volume cache profiles of {} only apply to detached services",
+            container_name
+        );
+        return Ok(());
+    }
+    let runtime_bin = runtime::detect_container_runtime().await?;
+    let mut volumes = Vec::new();
+    for (name, volume) in tuned {
+        let Some(mountpoint) = crate::volume::cache::mountpoint(&runtime_bin, &name).await else {
+            warn!(
+                "Cannot find where volume {} is stored; its cache profile is not applied",
+                name
+            );
+            continue;
+        };
+        volumes.push(crate::volume::cache::TunedVolume {
+            name,
+            mountpoint,
+            profile: volume.cache_profile.unwrap_or_default(),
+            hot_paths: volume.hot_paths.clone().unwrap_or_default(),
+        });
+    }
+    if !volumes.is_empty() {
+        crate::volume::cache::start(&config.data_dir, container_name, volumes)?;
+    }
+    Ok(())
+}
+
+/// Log hook state of the project's containers
+pub fn log_hook_states(config: &BoltConfig) -> Result<Vec<runtime::log_hooks::LogHookState>> {
+    let boltfile = config.load_boltfile()?;
+    let prefix = format!("{}_", boltfile.project);
+    Ok(runtime::log_hooks::list(&config.data_dir)
+        .into_iter()
+        .filter(|s| s.container.starts_with(&prefix))
+        .collect())
+}
+
+/// Events the log hooks of a service's containers recorded, oldest first
+pub fn log_hook_events(
+    config: &BoltConfig,
+    service_name: &str,
+) -> Result<Vec<runtime::events::ContainerEvent>> {
+    let boltfile = config.load_boltfile()?;
+    if !boltfile.services.contains_key(service_name) {
+        return Err(BoltError::Other(anyhow!(
+            "Service '{}' not found in Boltfile",
+            service_name
+        )));
+    }
+    let container_name = format!("{}_{}", boltfile.project, service_name);
+    let replica_prefix = format!("{}_", container_name);
+    let mut events = runtime::log_hooks::events(&config.data_dir, &container_name)?;
+    for state in runtime::log_hooks::list(&config.data_dir) {
+        let replica = state
+            .container
+            .strip_prefix(&replica_prefix)
+            .is_some_and(|n| n.parse::<u32>().is_ok());
+        if replica {
+            events.extend(runtime::log_hooks::events(
+                &config.data_dir,
+                &state.container,
+            )?);
+        }
+    }
+    events.sort_by_key(|e| e.timestamp);
+    Ok(events)
+}
+
+/// Idle state of the project's containers
+pub fn idle_states(config: &BoltConfig) -> Result<Vec<runtime::idle::IdleState>> {
+    let boltfile = config.load_boltfile()?;
+    let prefix = format!("{}_", boltfile.project);
+    Ok(runtime::idle::list(&config.data_dir)
+        .into_iter()
+        .filter(|s| s.container.starts_with(&prefix))
+        .collect())
+}
+
+/// Objectives of the project's containers with an slo
+pub fn slo_reports(config: &BoltConfig) -> Result<Vec<crate::monitoring::slo::SloReport>> {
+    let boltfile = config.load_boltfile()?;
+    let prefix = format!("{}_", boltfile.project);
+    Ok(crate::monitoring::slo::reports(&config.data_dir)
+        .into_iter()
+        .filter(|r| r.container.starts_with(&prefix))
+        .collect())
+}
+
+/// Resume a service's suspended containers
+pub fn wake(config: &BoltConfig, service_name: &str) -> Result<()> {
+    let boltfile = config.load_boltfile()?;
+    if !boltfile.services.contains_key(service_name) {
+        return Err(BoltError::Other(anyhow!(
+            "Service '{}' not found in Boltfile",
+            service_name
+        )));
+    }
+    let container_name = format!("{}_{}", boltfile.project, service_name);
+    let replica_prefix = format!("{}_", container_name);
+    let mut woken = 0;
+    for state in runtime::idle::list(&config.data_dir) {
+        let name = &state.container;
+        let replica = name
+            .strip_prefix(&replica_prefix)
+            .is_some_and(|n| n.parse::<u32>().is_ok());
+        if (*name == container_name || replica)
+            && runtime::idle::request_wake(&config.data_dir, name)?
+        {
+            info!("⏰ Waking {}", name);
+            woken += 1;
+        }
+    }
+    if woken == 0 {
+        info!("Service {} is not suspended", service_name);
+    }
+    Ok(())
+}
+
+/// Desktop sessions of the project's containers
+pub fn desktop_sessions(config: &BoltConfig) -> Result<Vec<runtime::desktop::DesktopSession>> {
+    let boltfile = config.load_boltfile()?;
+    let prefix = format!("{}_", boltfile.project);
+    Ok(runtime::desktop::list(&config.data_dir)
+        .into_iter()
+        .filter(|s| s.container.starts_with(&prefix))
+        .collect())
+}
+
+/// Copy files into a service's transfer directory
+pub fn desktop_send(
+    config: &BoltConfig,
+    service_name: &str,
+    files: &[std::path::PathBuf],
+) -> Result<Vec<std::path::PathBuf>> {
+    let boltfile = config.load_boltfile()?;
+    if !boltfile.services.contains_key(service_name) {
+        return Err(BoltError::Other(anyhow!(
+            "Service '{}' not found in Boltfile",
+            service_name
+        )));
+    }
+    let container_name = format!("{}_{}", boltfile.project, service_name);
+    Ok(runtime::desktop::send(
+        &config.data_dir,
+        &container_name,
+        files,
+    )?)
+}
+
+/// Absolute directory of the Boltfile, which relative service paths start from
+pub(crate) fn project_dir(config: &BoltConfig) -> Result<std::path::PathBuf> {
+    Ok(match config.boltfile_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::path::absolute(dir)?,
+        _ => std::env::current_dir()?,
+    })
+}
+
+fn is_wasm(service: Option<&crate::config::Service>) -> bool {
+    service.is_some_and(|s| s.runtime == Some(runtime::wasm::ServiceRuntime::Wasm))
+}
+
+/// Start a `runtime = "wasm"` service; a running one is only replaced with
+/// `--force-recreate`
+async fn start_wasm(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    name: &str,
+    detach: bool,
+    force_recreate: bool,
+) -> Result<()> {
+    let running = runtime::wasm::state(&config.data_dir, name).is_some_and(|s| s.is_running());
+    if running && !force_recreate {
+        info!("  🧩 {} is already running", name);
+        return Ok(());
+    }
+    if running {
+        runtime::wasm::stop(&config.data_dir, name, WASM_STOP_TIMEOUT).await?;
+    }
+    // wasm-exec may run from another directory, so paths must be absolute
+    let spec = runtime::wasm::WasmSpec::from_service(name, service, &project_dir(config)?)?;
+    info!("  🧩 Module: {}", spec.module.display());
+    Ok(runtime::wasm::start(&config.data_dir, &spec, detach).await?)
+}
+
+/// Extra `run` arguments for a service: networks, sandbox options, GPU
+/// libraries and the log driver. Also returns the networks that can only be
+/// joined once the container exists.
+async fn service_run_args(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    service: &crate::config::Service,
+    image: &str,
+    container_name: &str,
+    detach: bool,
+) -> Result<(Vec<String>, Vec<crate::network::attach::Attachment>)> {
+    let (mut args, pending_networks) = network_args(boltfile, service).await?;
+    if !image.starts_with("bolt://") {
+        if service.networks.is_none() && service.network_mode.is_none() {
+            args.extend(crate::networking::rootless::run_args(
+                &runtime::detect_container_runtime().await?,
+            ));
+        }
+        args.extend([
+            "--label".to_string(),
+            format!("{}={}", projects::LABEL, boltfile.project),
+        ]);
+        if let Some(ref annotations) = service.annotations {
+            args.extend(runtime::annotations::args(annotations));
+        }
+        args.extend(
+            runtime::identity::IdentitySpec::from_service(service)
+                .resolve(&config.data_dir, container_name)?
+                .run_args(),
+        );
+        args.extend(
+            runtime::emulation::run_args(
+                &runtime::detect_container_runtime().await?,
+                image,
+                config.platform.setup_emulation,
+            )
+            .await?,
+        );
+        if let Some(ref caches) = service.cache_volumes {
+            args.extend(crate::builds::artifacts::run_args(
+                &crate::builds::artifacts::resolve(&config.data_dir, caches)?,
+            ));
+        }
+        if let Some(proton) = service
+            .gaming
+            .as_ref()
+            .and_then(|g| g.wine.as_ref())
+            .and_then(|w| w.proton.as_deref())
+        {
+            let spec = crate::gaming::proton::Spec::parse(proton)?;
+            if let Some(tool) = crate::gaming::proton::ensure(&config.data_dir, &spec).await? {
+                info!("  🍷 {} {}", tool.flavor, tool.tag);
+                args.extend(tool.run_args());
+            }
+        }
+        if let Some(remote) = service
+            .gaming
+            .as_ref()
+            .and_then(|g| g.wine.as_ref())
+            .and_then(crate::gaming::prefix::cached_prefix)
+        {
+            let service_name = container_name
+                .strip_prefix(&format!("{}_", boltfile.project))
+                .unwrap_or(container_name);
+            args.extend(
+                crate::gaming::prefix::prepare(
+                    &config.data_dir,
+                    service_name,
+                    container_name,
+                    &remote,
+                )
+                .await?,
+            );
+        }
+    }
+    args.extend(sandbox_args(config, service, image).await?);
+    if let Some(ref healthcheck) = service.healthcheck {
+        args.extend(runtime::healthcheck::HealthcheckSpec::from_config(healthcheck)?.run_args());
+    }
+    let slice = gpu_slice(config, service, container_name).await?;
+    let placement = match slice {
+        Some(ref slice) => Some(slice.placement()),
+        None => gpu_placement(config, service, container_name).await?,
+    };
+    let gpu_args = gpu_injection_args(config, service, image, placement.as_ref()).await?;
+    if !gpu_args.is_empty() {
+        // Kept to tell when a driver upgrade leaves the container behind
+        if let Err(e) = crate::gaming::drivers::record(
+            &config.data_dir,
+            &boltfile.project,
+            container_name,
+            &gpu_args,
+        ) {
+            warn!(
+                "Could not record the GPU driver of {}: {}",
+                container_name, e
+            );
+        }
+    }
+    args.extend(gpu_args);
+    match (&slice, &placement) {
+        (Some(slice), _) => args.extend(slice.run_args()),
+        (None, Some(placement)) => args.extend(placement.run_args()),
+        (None, None) => {}
+    }
+    if let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref()) {
+        args.extend(crate::gaming::priority::run_args(gpu));
+    }
+    if let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref())
+        && let Some(ref ai) = gpu.ai
+    {
+        let report = crate::gaming::multi_gpu::prepare(
+            &config.data_dir,
+            container_name,
+            gpu,
+            ai,
+            placement.as_ref(),
+        )
+        .await
+        .map_err(|e| {
+            e.context(format!(
+                "Cannot prepare {} for multi-GPU training",
+                container_name
+            ))
+        })?;
+        args.extend(report.run_args());
+    }
+    args.extend(log_driver_args(config, service, container_name).await?);
+    if let Some(ref thermal) = service.thermal {
+        args.extend(crate::gaming::thermal::run_args(
+            config,
+            container_name,
+            thermal,
+        )?);
+    }
+    if let Some(ref workspace) = config.workspace {
+        args.extend(workspace_args(config, workspace, service, container_name).await?);
+    }
+    if let Some(ref desktop) = service.desktop_integration {
+        let policy = runtime::desktop::DesktopPolicy::from_config(desktop, &project_dir(config)?)?;
+        let session = runtime::desktop::start(&config.data_dir, container_name, &policy).await?;
+        args.extend(session.run_args(&config.data_dir));
+    }
+    if let Some(gaming) = service.gaming.as_ref()
+        && let Some(ref display) = gaming.display
+    {
+        let wrapped = display
+            .gamescope
+            .as_ref()
+            .is_some_and(|g| g.wrap == Some(true));
+        let command = if wrapped {
+            service_command(service, image).await?
+        } else {
+            explicit_command(service)
+        };
+        args.extend(
+            crate::gaming::display::run_args(
+                &config.data_dir,
+                container_name,
+                gaming,
+                display,
+                &command,
+            )
+            .await?,
+        );
+    }
+    if detach && service.egress.is_some() {
+        // Held at a gate until the policy is loaded, in front of the
+        // gamescope wrapper if there is one
+        let command = match args.iter().rposition(|a| a == "--entrypoint") {
+            Some(i) if i + 1 < args.len() => {
+                let wrapper = args.remove(i + 1);
+                args.remove(i);
+                vec![wrapper]
+            }
+            _ => service_command(service, image).await?,
+        };
+        args.extend(crate::networking::egress::gate(
+            &config.data_dir,
+            container_name,
+            &command,
+        )?);
+    }
+    Ok((args, pending_networks))
+}
+
+/// The service's `entrypoint` followed by its `command`
+fn explicit_command(service: &crate::config::Service) -> Vec<String> {
+    service
+        .entrypoint
+        .iter()
+        .chain(service.command.iter())
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// What the service's container runs: its explicit command, or else the
+/// image's default
+async fn service_command(service: &crate::config::Service, image: &str) -> Result<Vec<String>> {
+    let command = explicit_command(service);
+    if !command.is_empty() {
+        return Ok(command);
+    }
+    let runtime = runtime::detect_container_runtime().await?;
+    Ok(crate::gaming::display::image_command(&runtime, image).await?)
+}
+
+/// Admit the container into the user's workspace, limited to the service's
+/// `cpu_limit` and `memory_limit`
+async fn workspace_args(
+    config: &BoltConfig,
+    workspace: &str,
+    service: &crate::config::Service,
+    container_name: &str,
+) -> Result<Vec<String>> {
+    let cpus = service
+        .cpu_limit
+        .as_deref()
+        .map(|c| {
+            c.trim()
+                .parse::<f64>()
+                .map_err(|_| anyhow!("Invalid cpu_limit '{}': expected a number of CPUs", c))
+        })
+        .transpose()?;
+    let memory = service
+        .memory_limit
+        .as_deref()
+        .map(crate::builds::cache::parse_size)
+        .transpose()?;
+    let request = runtime::workspace::AdmissionRequest {
+        cpus,
+        memory,
+        gpu: service.gaming.as_ref().is_some_and(|g| g.gpu.is_some()),
+    };
+    let runtime = runtime::detect_container_runtime().await?;
+    runtime::workspace::admit(
+        &runtime,
+        &config.data_dir,
+        workspace,
+        Some(container_name),
+        &request,
+    )
+    .await
+    .map_err(|e| e.context(format!("{} was not admitted", container_name)).into())
+}
+
+async fn network_args(
+    boltfile: &BoltFile,
+    service: &crate::config::Service,
+) -> Result<(Vec<String>, Vec<crate::network::attach::Attachment>)> {
+    let Some(ref networks) = service.networks else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let runtime = runtime::detect_container_runtime().await?;
+    let attachments = crate::network::attach::prepare(&runtime, boltfile, networks).await?;
+    Ok(crate::network::attach::run_args(&runtime, &attachments))
+}
+
+async fn connect_networks(
+    container_name: &str,
+    pending: &[crate::network::attach::Attachment],
+) -> Result<()> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let runtime = runtime::detect_container_runtime().await?;
+    Ok(crate::network::attach::connect(&runtime, container_name, pending).await?)
+}
+
+async fn log_driver_args(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    container_name: &str,
+) -> Result<Vec<String>> {
+    let Some(ref logging) = service.logging else {
+        return Ok(Vec::new());
+    };
+    let runtime = runtime::detect_container_runtime().await?;
+    let log_dir = config.container_log_dir();
+    // Keep a restarted service's file log within its limits
+    if let Err(e) =
+        runtime::log_driver::rotate(&runtime, container_name, logging, &log_dir).await
+    {
+        warn!("Could not rotate log for {}: {}", container_name, e);
+    }
+    Ok(runtime::log_driver::run_args(
+        &runtime,
+        container_name,
+        logging,
+        &log_dir,
+    )?)
+}
+
+/// Carve a service's `gpu.fraction` out of a GPU
+async fn gpu_slice(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    container_name: &str,
+) -> Result<Option<crate::gaming::fraction::Slice>> {
+    let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref()) else {
+        return Ok(None);
+    };
+    let Some(fraction) = gpu.fraction else {
+        return Ok(None);
+    };
+    let runtime = runtime::detect_container_runtime().await?;
+    crate::gaming::fraction::allocate(&runtime, &config.data_dir, gpu, container_name, fraction)
+        .await
+        .map(Some)
+        .map_err(|e| {
+            e.context(format!("Cannot give {} a GPU slice", container_name))
+                .into()
+        })
+}
+
+/// Pick the GPUs for a service that sets `gpu.count` or `gpu.topology`
+async fn gpu_placement(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    container_name: &str,
+) -> Result<Option<crate::gaming::topology::Placement>> {
+    let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref()) else {
+        return Ok(None);
+    };
+    if gpu.count.is_none() && gpu.topology.is_none() {
+        return Ok(None);
+    }
+    let runtime = runtime::detect_container_runtime().await?;
+    crate::gaming::topology::place(&runtime, &config.data_dir, gpu)
+        .await
+        .map_err(|e| e.context(format!("Cannot place {} on GPUs", container_name)).into())
+}
+
+/// Mount the host GPU driver stack into the image's library layout. With a
+/// placement only the placed GPUs' devices are passed in.
+#[cfg(feature = "gaming")]
+async fn gpu_injection_args(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    image: &str,
+    placement: Option<&crate::gaming::topology::Placement>,
+) -> Result<Vec<String>> {
+    use runtime::gpu::distro::{self, BaseOs, GpuDriverStack};
+
+    let Some(gpu) = service.gaming.as_ref().and_then(|g| g.gpu.as_ref()) else {
+        return Ok(Vec::new());
+    };
+    if let Err(e) = crate::gaming::drivers::check_at_container_start(config).await {
+        warn!("Could not check the GPU driver version: {}", e);
+    }
+    if let Some(args) = nvidia_ctk_args(config, gpu, image, placement).await? {
+        return Ok(args);
+    }
+    // nvbind does its own injection
+    if gpu.passthrough == Some(false) || gpu.runtime.as_deref() == Some("nvbind") {
+        return Ok(Vec::new());
+    }
+    let stack = if gpu.nvidia.is_some() {
+        GpuDriverStack::Nvidia
+    } else if gpu.amd.is_some() || gpu.intel.is_some() {
+        GpuDriverStack::Mesa
+    } else {
+        return Ok(Vec::new());
+    };
+    if image.starts_with("bolt://") {
+        return Ok(Vec::new());
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    let host = BaseOs::detect_host();
+    let image_os = distro::detect_image(&runtime, image).await?;
+    info!("🐧 Image base OS: {}", image_os.display_name());
+
+    let manifest_dir = config
+        .data_dir
+        .join("gpu")
+        .join("manifests")
+        .join(image.replace(['/', ':'], "_"));
+    let vulkan = distro::plan_injection(
+        &host,
+        &image_os,
+        stack,
+        &service.vulkan.clone().unwrap_or_default(),
+        &manifest_dir,
+    );
+    let mut injection = match (vulkan, &gpu.intel) {
+        (Ok(injection), _) => injection,
+        // A transcoding host may have the media stack without Vulkan
+        (Err(e), Some(_)) => {
+            warn!("No Vulkan for the Intel GPU: {}", e);
+            distro::check_libc_compatible(&host, &image_os)?;
+            Default::default()
+        }
+        (Err(e), None) => return Err(e.into()),
+    };
+    if let Some(ref intel) = gpu.intel {
+        runtime::gpu::intel::apply(intel, &host.layout(), &image_os.layout(), &mut injection)?;
+    }
+    if placement.is_some() {
+        injection
+            .devices
+            .retain(|d| !crate::gaming::topology::is_gpu_device(d));
+    }
+    Ok(injection.run_args())
+}
+
+/// Injection by nvidia-container-toolkit for `gpu.runtime = "nvidia-ctk"`,
+/// or for nvbind when it isn't installed
+#[cfg(feature = "gaming")]
+async fn nvidia_ctk_args(
+    config: &BoltConfig,
+    gpu: &crate::config::GpuConfig,
+    image: &str,
+    placement: Option<&crate::gaming::topology::Placement>,
+) -> Result<Option<Vec<String>>> {
+    use runtime::gpu::nvidia_ctk::{self, Interop};
+
+    let requested = match gpu.runtime.as_deref() {
+        Some("nvidia-ctk") => true,
+        Some("nvbind") => false,
+        _ => return Ok(None),
+    };
+    if image.starts_with("bolt://") || gpu.passthrough == Some(false) {
+        return Ok(None);
+    }
+    if !requested
+        && runtime::gpu::nvbind::NvbindManager::detect().is_ok_and(|nvbind| nvbind.is_available)
+    {
+        return Ok(None);
+    }
+    let Some(interop) = Interop::detect() else {
+        if requested {
+            return Err(anyhow::anyhow!(
+                "gpu.runtime = \"nvidia-ctk\" but nvidia-container-toolkit is not set up: \
+                 run `nvidia-ctk cdi generate --output=/etc/cdi/nvidia.yaml` or install its prestart hook"
+            )
+            .into());
+        }
+        return Ok(None);
+    };
+    if !requested {
+        info!("nvbind is not installed; using nvidia-container-toolkit instead");
+    }
+
+    let placed: Vec<u32> = placement
+        .map(|p| p.gpus.iter().filter_map(|g| g.nvidia_index).collect())
+        .unwrap_or_default();
+    let runtime = runtime::detect_container_runtime().await?;
+    Ok(Some(nvidia_ctk::run_args(
+        &interop,
+        &runtime,
+        &nvidia_ctk::selection(gpu, &placed),
+        placement.is_some(),
+        &config.data_dir.join("gpu").join("nvidia-ctk"),
+    )?))
+}
+
+/// Gaming preflight: check that Vulkan works inside a started GPU service
+#[cfg(feature = "gaming")]
+async fn verify_gpu_service(
+    container_name: &str,
+    service: &crate::config::Service,
+    detach: bool,
+) -> Result<()> {
+    if !detach || service.gaming.as_ref().is_none_or(|g| g.gpu.is_none()) {
+        return Ok(());
+    }
+
+    let runtime = runtime::detect_container_runtime().await?;
+    match runtime::gpu::icd::verify_vulkan(&runtime, container_name).await {
+        Ok(devices) => info!("🎮 Vulkan OK in {}: {}", container_name, devices.join(", ")),
+        Err(e) => warn!("⚠️  Vulkan preflight failed for {}: {}", container_name, e),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "gaming"))]
+async fn verify_gpu_service(
+    _container_name: &str,
+    _service: &crate::config::Service,
+    _detach: bool,
+) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "gaming"))]
+async fn gpu_injection_args(
+    _config: &BoltConfig,
+    service: &crate::config::Service,
+    _image: &str,
+    _placement: Option<&crate::gaming::topology::Placement>,
+) -> Result<Vec<String>> {
+    if service.gaming.as_ref().is_some_and(|g| g.gpu.is_some()) {
+        warn!("GPU configuration ignored: Bolt was built without the gaming feature");
+    }
+    Ok(Vec::new())
+}
+
+/// `run` arguments for the service's sandbox tier
+async fn sandbox_args(
+    config: &BoltConfig,
+    service: &crate::config::Service,
+    image: &str,
+) -> Result<Vec<String>> {
+    let tier = service.sandbox.unwrap_or_default();
+    if tier == runtime::sandbox::SandboxTier::Default {
+        return Ok(Vec::new());
+    }
+    if image.starts_with("bolt://") {
+        return Err(BoltError::Other(anyhow!(
+            "sandbox = \"{}\" is only supported for OCI images, not {}",
+            tier.as_str(),
+            image
+        )));
+    }
+
+    info!("🛡️  Running {} with the {} sandbox", image, tier.as_str());
+    let runtime = runtime::detect_container_runtime().await?;
+    Ok(runtime::sandbox::run_args(
+        &runtime,
+        tier,
+        service.syscall_interception.unwrap_or(false),
+        &config.data_dir,
+    )
+    .await?)
+}
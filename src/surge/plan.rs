@@ -0,0 +1,205 @@
+// Surge plan
+//
+// `bolt surge plan` shows what `surge up` would do to each service without
+// touching anything: create missing containers, rebuild build contexts, and
+// flag containers whose image moved on since they were started, either
+// because the Boltfile names another image or because the tag now resolves
+// to a different digest upstream. Images and build base images that violate
+// the trust policy are listed with each service, and so are host ports taken
+// by other projects or processes, with the port `surge up` would remap them
+// to (see `ports.rs`). Settings changed by `--set` or a saved profile are
+// listed as overridden (see `config/overrides.rs`).
+use serde::{Deserialize, Serialize};
+use tokio::process::Command as AsyncCommand;
+
+use crate::Result;
+use crate::config::{BoltConfig, Service};
+use crate::runtime::{self, pull};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "action", content = "reason")]
+pub enum PlanAction {
+    Create,
+    Build,
+    /// The Boltfile's image differs from the one the container runs
+    Recreate(String),
+    /// The tag resolves to a newer image upstream
+    Update(String),
+    Keep,
+}
+
+impl PlanAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanAction::Create => "create",
+            PlanAction::Build => "build",
+            PlanAction::Recreate(_) => "recreate",
+            PlanAction::Update(_) => "update",
+            PlanAction::Keep => "keep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServicePlan {
+    pub service: String,
+    pub container: String,
+    pub image: Option<String>,
+    pub pull_policy: Option<pull::PullPolicy>,
+    pub action: PlanAction,
+    /// Digest the running container was started from
+    pub current_digest: Option<String>,
+    pub remote_digest: Option<String>,
+    /// Trust policy violations `surge up` would refuse
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trust: Vec<runtime::trust::Violation>,
+    /// Published host ports that are already taken
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<super::ports::PortConflict>,
+    /// Settings overridden for this run, e.g. "environment.DEBUG=1"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overridden: Vec<String>,
+}
+
+pub async fn plan(config: &BoltConfig, services: &[String]) -> Result<Vec<ServicePlan>> {
+    let boltfile = config.load_boltfile()?;
+    let runtime = runtime::detect_container_runtime().await?;
+    let trust = runtime::trust::TrustPolicy::load(config)?;
+    let overrides = config.active_overrides()?;
+
+    let mut names: Vec<&String> = if services.is_empty() {
+        boltfile.services.keys().collect()
+    } else {
+        services.iter().collect()
+    };
+    names.sort();
+    let targets: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+    let conflicts = super::ports::check(config, &boltfile, &targets).await?;
+
+    let mut plans = Vec::new();
+    for name in names {
+        let Some(service) = boltfile.services.get(name.as_str()) else {
+            return Err(anyhow::anyhow!("Service '{}' not found in Boltfile", name).into());
+        };
+        let container = format!("{}_{}", boltfile.project, name);
+        let mut plan = service_plan(config, &runtime, name, &container, service).await;
+        if let Some(ref policy) = trust {
+            plan.trust = trust_violations(&runtime, policy, service).await?;
+        }
+        plan.ports = conflicts
+            .iter()
+            .filter(|c| c.service == *name)
+            .cloned()
+            .collect();
+        plan.overridden = overrides
+            .iter()
+            .filter_map(|o| o.for_service(name))
+            .collect();
+        plans.push(plan);
+    }
+    Ok(plans)
+}
+
+async fn service_plan(
+    config: &BoltConfig,
+    runtime: &str,
+    name: &str,
+    container: &str,
+    service: &Service,
+) -> ServicePlan {
+    let recorded = pull::recorded(&config.data_dir, container);
+    let mut plan = ServicePlan {
+        service: name.to_string(),
+        container: container.to_string(),
+        image: service.image.clone(),
+        pull_policy: service.pull_policy,
+        action: PlanAction::Keep,
+        current_digest: recorded.as_ref().and_then(|r| r.digest.clone()),
+        remote_digest: None,
+        trust: Vec::new(),
+        ports: Vec::new(),
+        overridden: Vec::new(),
+    };
+
+    if service.build.is_some() {
+        plan.action = PlanAction::Build;
+        return plan;
+    }
+    if service.runtime == Some(runtime::wasm::ServiceRuntime::Wasm) {
+        let running =
+            runtime::wasm::state(&config.data_dir, container).is_some_and(|s| s.is_running());
+        if !running {
+            plan.action = PlanAction::Create;
+        }
+        return plan;
+    }
+    if !container_exists(runtime, container).await {
+        plan.action = PlanAction::Create;
+        return plan;
+    }
+    let Some(ref image) = service.image else {
+        return plan;
+    };
+
+    match recorded {
+        Some(ref record) if record.image != *image => {
+            plan.action = PlanAction::Recreate(format!("image changed from {}", record.image));
+        }
+        _ if service.pull_policy == Some(pull::PullPolicy::Never) => {}
+        _ => {
+            plan.remote_digest = pull::remote_digest(image).await;
+            if let (Some(current), Some(remote)) = (&plan.current_digest, &plan.remote_digest) {
+                if current != remote {
+                    plan.action = PlanAction::Update(format!(
+                        "upstream image changed ({} -> {})",
+                        short_digest(current),
+                        short_digest(remote)
+                    ));
+                }
+            }
+        }
+    }
+    plan
+}
+
+/// Violations of the service's image, or of its build's base images
+async fn trust_violations(
+    runtime: &str,
+    policy: &runtime::trust::TrustPolicy,
+    service: &Service,
+) -> Result<Vec<runtime::trust::Violation>> {
+    let images = match (&service.build, &service.image) {
+        (Some(build), _) => {
+            runtime::trust::build_base_images(std::path::Path::new(build), "Dockerfile")?
+        }
+        (None, Some(image)) if service.runtime != Some(runtime::wasm::ServiceRuntime::Wasm) => {
+            vec![image.clone()]
+        }
+        _ => Vec::new(),
+    };
+    let mut violations = Vec::new();
+    for image in images {
+        violations.extend(runtime::trust::evaluate(runtime, policy, &image).await);
+    }
+    Ok(violations)
+}
+
+async fn container_exists(runtime: &str, container: &str) -> bool {
+    AsyncCommand::new(runtime)
+        .args(["container", "inspect", container])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+/// `sha256:0123456789ab...` -> `sha256:0123456789ab`
+pub fn short_digest(digest: &str) -> &str {
+    let end = digest
+        .find(':')
+        .map(|i| i + 13)
+        .unwrap_or(12)
+        .min(digest.len());
+    &digest[..end]
+}
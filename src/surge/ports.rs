@@ -0,0 +1,315 @@
+// Host port conflicts
+//
+// Boltfile validation only catches two services of one file publishing the
+// same host port. Before any container starts, `surge up` and `surge plan`
+// also check each published port against:
+//
+// - other projects on this node, from what their `surge up` recorded, even
+//   while their containers are stopped
+// - TCP ports already listening on the host, except those the project's own
+//   running containers publish
+//
+// Every conflict comes with a suggested free port found by the offset
+// strategy: add `[ports] offset` (1000 by default) until the port is free,
+// so 8080 tries 9080, 10080, ... up to 65535. With `[ports] remap` or
+// `--remap-ports`, `surge up` publishes the suggested port instead of
+// refusing to start; the container port stays the same.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::process::Command as AsyncCommand;
+
+use crate::Result;
+use crate::config::{BoltConfig, BoltFile};
+
+/// What already holds a host port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum PortHolder {
+    /// A service of another project on this node
+    Project { project: String, service: String },
+    /// A process on the host outside Bolt's projects
+    Host,
+}
+
+impl std::fmt::Display for PortHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortHolder::Project { project, service } => {
+                write!(f, "service {} of project {}", service, project)
+            }
+            PortHolder::Host => f.write_str("another process on the host"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortConflict {
+    pub service: String,
+    /// The mapping as written in the Boltfile, e.g. "8080:80"
+    pub mapping: String,
+    pub host_port: u16,
+    pub held_by: PortHolder,
+    /// Free host port found by the offset strategy
+    pub suggestion: Option<u16>,
+    /// Whether `surge up` publishes `suggestion` instead
+    #[serde(default)]
+    pub remapped: bool,
+}
+
+impl std::fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: host port {} is taken by {}",
+            self.service, self.host_port, self.held_by
+        )?;
+        match self.suggestion {
+            Some(port) if self.remapped => write!(f, ", remapped to {}", port),
+            Some(port) => write!(f, ", try {}", port),
+            None => f.write_str(", no free port found"),
+        }
+    }
+}
+
+/// Host port of a `host:container` or single-port mapping
+pub fn host_port(mapping: &str) -> Option<u16> {
+    mapping.split(':').next()?.parse().ok()
+}
+
+/// Conflicts of the given services' published ports, each with a
+/// suggestion, marked remapped when `[ports] remap` is set
+pub async fn check(
+    config: &BoltConfig,
+    boltfile: &BoltFile,
+    services: &[String],
+) -> Result<Vec<PortConflict>> {
+    let mut claimed = HashMap::new();
+    for record in super::projects::list(&config.data_dir) {
+        if record.project == boltfile.project {
+            continue;
+        }
+        for (service, recorded) in &record.services {
+            for port in &recorded.ports {
+                claimed.insert(
+                    *port,
+                    PortHolder::Project {
+                        project: record.project.clone(),
+                        service: service.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    let mut listening = listening_ports();
+    if !listening.is_empty() {
+        let runtime = crate::runtime::detect_container_runtime().await?;
+        for (name, service) in &boltfile.services {
+            let container = format!("{}_{}", boltfile.project, name);
+            for port in published_ports(&runtime, &container).await {
+                listening.remove(&port);
+            }
+            // A service stopped for idleness has its idle watcher listening
+            // on its ports
+            if crate::runtime::idle::state(&config.data_dir, &container).is_some() {
+                for port in service.ports.iter().flatten().filter_map(|m| host_port(m)) {
+                    listening.remove(&port);
+                }
+            }
+        }
+    }
+
+    let mut conflicts = find_conflicts(
+        boltfile,
+        services,
+        &claimed,
+        &listening,
+        config.ports.offset,
+    );
+    for conflict in &mut conflicts {
+        conflict.remapped = config.ports.remap && conflict.suggestion.is_some();
+    }
+    Ok(conflicts)
+}
+
+fn find_conflicts(
+    boltfile: &BoltFile,
+    services: &[String],
+    claimed: &HashMap<u16, PortHolder>,
+    listening: &HashSet<u16>,
+    offset: u16,
+) -> Vec<PortConflict> {
+    let mut taken: HashSet<u16> = claimed.keys().chain(listening).copied().collect();
+    taken.extend(
+        boltfile
+            .services
+            .values()
+            .flat_map(|s| s.ports.iter().flatten())
+            .filter_map(|mapping| host_port(mapping)),
+    );
+
+    let targets: BTreeMap<&String, _> = boltfile
+        .services
+        .iter()
+        .filter(|(name, _)| services.is_empty() || services.contains(name))
+        .collect();
+
+    let mut conflicts = Vec::new();
+    for (name, service) in targets {
+        for mapping in service.ports.iter().flatten() {
+            let Some(port) = host_port(mapping) else {
+                continue;
+            };
+            let held_by = match claimed.get(&port) {
+                Some(holder) => holder.clone(),
+                None if listening.contains(&port) => PortHolder::Host,
+                None => continue,
+            };
+            let suggestion = suggest(port, offset, &taken);
+            taken.extend(suggestion);
+            conflicts.push(PortConflict {
+                service: name.clone(),
+                mapping: mapping.clone(),
+                host_port: port,
+                held_by,
+                suggestion,
+                remapped: false,
+            });
+        }
+    }
+    conflicts
+}
+
+/// First of `port + offset`, `port + 2 * offset`, ... that isn't taken
+fn suggest(port: u16, offset: u16, taken: &HashSet<u16>) -> Option<u16> {
+    if offset == 0 {
+        return None;
+    }
+    (1..)
+        .map(|k| port as u32 + k * offset as u32)
+        .take_while(|candidate| *candidate <= u16::MAX as u32)
+        .map(|candidate| candidate as u16)
+        .find(|candidate| !taken.contains(candidate))
+}
+
+/// Publish the suggested port for each remapped conflict
+pub fn remap(boltfile: &mut BoltFile, conflicts: &[PortConflict]) {
+    for conflict in conflicts.iter().filter(|c| c.remapped) {
+        let (Some(service), Some(port)) = (
+            boltfile.services.get_mut(&conflict.service),
+            conflict.suggestion,
+        ) else {
+            continue;
+        };
+        for mapping in service.ports.iter_mut().flatten() {
+            if *mapping == conflict.mapping {
+                *mapping = match mapping.split_once(':') {
+                    Some((_, container)) => format!("{}:{}", port, container),
+                    None => format!("{}:{}", port, mapping),
+                };
+            }
+        }
+    }
+}
+
+/// TCP ports in LISTEN state on any address
+fn listening_ports() -> HashSet<u16> {
+    let mut ports = HashSet::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(content) = std::fs::read_to_string(table) else {
+            continue;
+        };
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // 0A is TCP_LISTEN
+            if fields.get(3) != Some(&"0A") {
+                continue;
+            }
+            if let Some(port) = fields
+                .get(1)
+                .and_then(|local| local.rsplit_once(':'))
+                .and_then(|(_, port)| u16::from_str_radix(port, 16).ok())
+            {
+                ports.insert(port);
+            }
+        }
+    }
+    ports
+}
+
+/// Host ports a container publishes, from `port` lines such as
+/// "80/tcp -> 0.0.0.0:8080"
+async fn published_ports(runtime: &str, container: &str) -> Vec<u16> {
+    let Ok(output) = AsyncCommand::new(runtime)
+        .args(["port", container])
+        .output()
+        .await
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.rsplit_once(':')?.1.trim().parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_offset_ports_and_remaps_mappings() {
+        let mut boltfile: BoltFile = toml::from_str(
+            r#"
+project = "shop"
+
+[services.web]
+image = "nginx"
+ports = ["8080:80", "8443:443"]
+
+[services.api]
+image = "api"
+ports = ["9080"]
+"#,
+        )
+        .unwrap();
+        let claimed = HashMap::from([(
+            8080,
+            PortHolder::Project {
+                project: "blog".to_string(),
+                service: "web".to_string(),
+            },
+        )]);
+        let listening = HashSet::from([9080, 10080, 8443]);
+
+        let mut conflicts = find_conflicts(&boltfile, &[], &claimed, &listening, 1000);
+        let found: Vec<(&str, u16, Option<u16>)> = conflicts
+            .iter()
+            .map(|c| (c.service.as_str(), c.host_port, c.suggestion))
+            .collect();
+        // 10080 is listening and api already took 11080, so web's 8080 moves on
+        assert_eq!(
+            found,
+            vec![
+                ("api", 9080, Some(11080)),
+                ("web", 8080, Some(12080)),
+                ("web", 8443, Some(9443)),
+            ]
+        );
+        assert_eq!(conflicts[0].held_by, PortHolder::Host);
+
+        for conflict in &mut conflicts {
+            conflict.remapped = true;
+        }
+        remap(&mut boltfile, &conflicts);
+        assert_eq!(
+            boltfile.services["web"].ports,
+            Some(vec!["12080:80".to_string(), "9443:443".to_string()])
+        );
+        assert_eq!(
+            boltfile.services["api"].ports,
+            Some(vec!["11080:9080".to_string()])
+        );
+        assert_eq!(suggest(65000, 1000, &HashSet::new()), None);
+    }
+}
@@ -0,0 +1,230 @@
+// Surge projects on this node
+//
+// `surge up` records which services of a project it started, their
+// containers and the networks they joined, and `surge down` takes them out
+// again. The record outlives the Boltfile being in the current directory, so
+// node-wide views like `bolt health` can check every project deployed here
+// without knowing where its Boltfile lives or which template variables and
+// workspace it was started with.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::config::{BoltFile, Service};
+use crate::runtime::wasm::ServiceRuntime;
+
+/// Label carrying the project of every container `surge up` creates
+pub const LABEL: &str = "bolt.project";
+
+/// A project started on this node and what it runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectRecord {
+    pub project: String,
+    pub services: BTreeMap<String, RecordedService>,
+    /// Runtime names of the networks the services joined
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// Host interfaces the project's hardware networks bond or attach to
+    #[serde(default)]
+    pub uplinks: Vec<String>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Fingerprint of the Boltfile the last `surge up` applied
+    #[serde(default)]
+    pub applied: Option<String>,
+    /// When the Boltfile on disk was first seen to differ from `applied`
+    #[serde(default)]
+    pub dirty_since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedService {
+    /// Name of the first instance; scaled instances append `_<n>`
+    pub container: String,
+    #[serde(default)]
+    pub wasm: bool,
+    /// Host ports the service publishes
+    #[serde(default)]
+    pub ports: Vec<u16>,
+}
+
+fn projects_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("surge").join("projects")
+}
+
+fn record_path(data_dir: &Path, project: &str) -> PathBuf {
+    projects_dir(data_dir).join(format!("{}.json", project))
+}
+
+/// Record of one project, if any of its services are up
+pub fn get(data_dir: &Path, project: &str) -> Option<ProjectRecord> {
+    std::fs::read(record_path(data_dir, project))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+}
+
+/// Every project with services up on this node, by name
+pub fn list(data_dir: &Path) -> Vec<ProjectRecord> {
+    let mut records: Vec<ProjectRecord> = std::fs::read_dir(projects_dir(data_dir))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .filter_map(|e| std::fs::read(e.path()).ok())
+                .filter_map(|b| serde_json::from_slice(&b).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    records.sort_by(|a, b| a.project.cmp(&b.project));
+    records
+}
+
+/// Add the services `surge up` started to the project's record
+pub fn record(data_dir: &Path, boltfile: &BoltFile, started: &[&String]) {
+    let mut record = get(data_dir, &boltfile.project).unwrap_or_else(|| ProjectRecord {
+        project: boltfile.project.clone(),
+        ..Default::default()
+    });
+    for name in started {
+        if let Some(service) = boltfile.services.get(name.as_str()) {
+            record.services.insert(
+                name.to_string(),
+                RecordedService {
+                    container: format!("{}_{}", boltfile.project, name),
+                    wasm: service.runtime == Some(ServiceRuntime::Wasm),
+                    ports: service
+                        .ports
+                        .iter()
+                        .flatten()
+                        .filter_map(|mapping| super::ports::host_port(mapping))
+                        .collect(),
+                },
+            );
+        }
+    }
+    record.networks = networks(boltfile, &record);
+    record.uplinks = uplinks(boltfile, &record);
+    record.updated_at = chrono::Utc::now();
+    match super::watch::fingerprint(boltfile) {
+        Ok(fingerprint) => record.applied = Some(fingerprint),
+        Err(e) => warn!(
+            "Could not fingerprint the Boltfile of {}: {}",
+            record.project, e
+        ),
+    }
+    record.dirty_since = None;
+    save(data_dir, &record);
+}
+
+/// Take the services `surge down` stopped out of the project's record,
+/// dropping the record with the last one
+pub fn forget(data_dir: &Path, boltfile: &BoltFile, stopped: &[&String]) {
+    let Some(mut record) = get(data_dir, &boltfile.project) else {
+        return;
+    };
+    for name in stopped {
+        record.services.remove(name.as_str());
+    }
+    if record.services.is_empty() {
+        let _ = std::fs::remove_file(record_path(data_dir, &boltfile.project));
+        return;
+    }
+    record.networks = networks(boltfile, &record);
+    record.uplinks = uplinks(boltfile, &record);
+    record.updated_at = chrono::Utc::now();
+    save(data_dir, &record);
+}
+
+pub(super) fn save(data_dir: &Path, record: &ProjectRecord) {
+    let path = record_path(data_dir, &record.project);
+    let result = std::fs::create_dir_all(projects_dir(data_dir)).and_then(|()| {
+        std::fs::write(&path, serde_json::to_vec_pretty(record).unwrap_or_default())
+    });
+    if let Err(e) = result {
+        warn!("Failed to record project {}: {}", record.project, e);
+    }
+}
+
+fn recorded_services<'a>(
+    boltfile: &'a BoltFile,
+    record: &'a ProjectRecord,
+) -> impl Iterator<Item = &'a Service> {
+    record
+        .services
+        .keys()
+        .filter_map(|name| boltfile.services.get(name))
+}
+
+/// Runtime names of the networks the recorded services join
+fn networks(boltfile: &BoltFile, record: &ProjectRecord) -> Vec<String> {
+    let mut names: Vec<String> = recorded_services(boltfile, record)
+        .filter_map(|s| s.networks.as_ref())
+        .flat_map(|n| n.attachments())
+        .map(|(key, _)| {
+            boltfile
+                .networks
+                .as_ref()
+                .and_then(|n| n.get(key))
+                .and_then(|n| n.name.clone())
+                .unwrap_or_else(|| key.to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Uplinks of the hardware networks the recorded services join
+fn uplinks(boltfile: &BoltFile, record: &ProjectRecord) -> Vec<String> {
+    let mut names: Vec<String> = recorded_services(boltfile, record)
+        .filter_map(|s| s.networks.as_ref())
+        .flat_map(|n| n.attachments())
+        .filter_map(|(key, _)| boltfile.networks.as_ref()?.get(key)?.hardware.as_ref())
+        .filter_map(|hardware| hardware.uplink.as_ref())
+        .map(|uplink| uplink.name.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_started_services_and_drops_project_with_last_one() {
+        let boltfile: BoltFile = toml::from_str(
+            r#"
+project = "shop"
+
+[services.web]
+image = "nginx"
+networks = ["front"]
+
+[services.db]
+image = "postgres"
+
+[networks.front]
+driver = "bridge"
+name = "shop-front"
+"#,
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let web = "web".to_string();
+        let db = "db".to_string();
+
+        record(dir.path(), &boltfile, &[&web]);
+        record(dir.path(), &boltfile, &[&db]);
+        let recorded = get(dir.path(), "shop").unwrap();
+        assert_eq!(recorded.services.len(), 2);
+        assert_eq!(recorded.services["web"].container, "shop_web");
+        assert_eq!(recorded.networks, vec!["shop-front".to_string()]);
+
+        forget(dir.path(), &boltfile, &[&web]);
+        assert!(get(dir.path(), "shop").unwrap().networks.is_empty());
+        forget(dir.path(), &boltfile, &[&db]);
+        assert!(list(dir.path()).is_empty());
+    }
+}
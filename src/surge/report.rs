@@ -0,0 +1,297 @@
+// Surge up outcomes
+//
+// `surge up` records what happened to each target service instead of
+// stopping with the first error and leaving the rest unexplained:
+//
+// - `fail-fast` (the default) starts nothing more after the first failure;
+//   the remaining services are skipped
+// - `continue` keeps starting services that don't depend on a failed one,
+//   and skips only those that do, directly or through a skipped service
+//
+// The run ends with a report of every service: started, failed with its
+// error and error code, or skipped with the reason. It is also available as
+// JSON (`--summary`) for CI, and the exit code tells the cases apart: 0 when
+// every service started, 2 when some did and 1 when none did.
+use serde::{Deserialize, Serialize};
+
+use crate::config::BoltFile;
+use crate::error::ErrorCode;
+use crate::{BoltError, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureMode {
+    /// Stop starting services after the first failure
+    #[default]
+    FailFast,
+    /// Keep starting services that don't depend on a failed one
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "status")]
+pub enum ServiceOutcome {
+    Started,
+    Failed { error: String, code: ErrorCode },
+    Skipped { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceResult {
+    pub service: String,
+    #[serde(flatten)]
+    pub outcome: ServiceOutcome,
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpStatus {
+    /// Every target service started
+    #[default]
+    Ok,
+    /// Some services started, others failed or were skipped
+    Partial,
+    /// No service started
+    Failed,
+}
+
+impl UpStatus {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            UpStatus::Ok => 0,
+            UpStatus::Partial => 2,
+            UpStatus::Failed => 1,
+        }
+    }
+}
+
+/// What `surge up` did to each target service, in start order
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpSummary {
+    pub project: String,
+    pub on_failure: FailureMode,
+    pub status: UpStatus,
+    pub exit_code: i32,
+    pub started: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub duration_ms: u64,
+    pub services: Vec<ServiceResult>,
+    /// The failures themselves, for callers that want the first as an error
+    #[serde(skip)]
+    errors: Vec<BoltError>,
+}
+
+impl UpSummary {
+    pub fn new(project: &str, on_failure: FailureMode) -> Self {
+        Self {
+            project: project.to_string(),
+            on_failure,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_started(&mut self, service: &str, duration_ms: u64) {
+        self.push(service, ServiceOutcome::Started, duration_ms);
+    }
+
+    pub fn record_failed(&mut self, service: &str, error: BoltError, duration_ms: u64) {
+        let outcome = ServiceOutcome::Failed {
+            error: error.to_string(),
+            code: error.code(),
+        };
+        self.errors.push(error);
+        self.push(service, outcome, duration_ms);
+    }
+
+    pub fn record_skipped(&mut self, service: &str, reason: String) {
+        self.push(service, ServiceOutcome::Skipped { reason }, 0);
+    }
+
+    fn push(&mut self, service: &str, outcome: ServiceOutcome, duration_ms: u64) {
+        self.services.push(ServiceResult {
+            service: service.to_string(),
+            outcome,
+            duration_ms,
+        });
+    }
+
+    /// Why `service` must not be started, given the outcomes so far
+    pub fn skip_reason(&self, boltfile: &BoltFile, service: &str) -> Option<String> {
+        match self.on_failure {
+            FailureMode::FailFast => self
+                .services
+                .iter()
+                .find(|r| matches!(r.outcome, ServiceOutcome::Failed { .. }))
+                .map(|r| format!("stopped after {} failed", r.service)),
+            FailureMode::Continue => {
+                let deps = boltfile.services.get(service)?.depends_on.as_ref()?;
+                deps.names().find_map(|dep| {
+                    let result = self.services.iter().find(|r| &r.service == dep)?;
+                    match result.outcome {
+                        ServiceOutcome::Started => None,
+                        ServiceOutcome::Failed { .. } => {
+                            Some(format!("depends on {}, which failed", dep))
+                        }
+                        ServiceOutcome::Skipped { .. } => {
+                            Some(format!("depends on {}, which was skipped", dep))
+                        }
+                    }
+                })
+            }
+        }
+    }
+
+    /// Names of the services that started
+    pub fn started_services(&self) -> Vec<&String> {
+        self.services
+            .iter()
+            .filter(|r| r.outcome == ServiceOutcome::Started)
+            .map(|r| &r.service)
+            .collect()
+    }
+
+    /// Count the outcomes and settle the status
+    pub fn finish(&mut self, duration_ms: u64) {
+        self.duration_ms = duration_ms;
+        let count =
+            |f: fn(&ServiceOutcome) -> bool| self.services.iter().filter(|r| f(&r.outcome)).count();
+        self.started = count(|o| matches!(o, ServiceOutcome::Started));
+        self.failed = count(|o| matches!(o, ServiceOutcome::Failed { .. }));
+        self.skipped = count(|o| matches!(o, ServiceOutcome::Skipped { .. }));
+        self.status = if self.failed == 0 && self.skipped == 0 {
+            UpStatus::Ok
+        } else if self.started > 0 {
+            UpStatus::Partial
+        } else {
+            UpStatus::Failed
+        };
+        self.exit_code = self.status.exit_code();
+    }
+
+    /// Ok when every service started, otherwise the first failure
+    pub fn into_result(mut self) -> Result<()> {
+        if self.status == UpStatus::Ok {
+            return Ok(());
+        }
+        if self.errors.is_empty() {
+            return Err(BoltError::Other(anyhow::anyhow!("{}", self)));
+        }
+        Err(self.errors.remove(0))
+    }
+
+    /// End-of-run table, one line per service
+    pub fn print(&self) {
+        println!("{:<20} {:<9} {:>8}  DETAIL", "SERVICE", "STATUS", "TIME");
+        for result in &self.services {
+            let (status, detail) = match result.outcome {
+                ServiceOutcome::Started => ("started", String::new()),
+                ServiceOutcome::Failed { ref error, code } => {
+                    let code = serde_json::to_value(code).unwrap_or_default();
+                    (
+                        "failed",
+                        format!("{}: {}", code.as_str().unwrap_or("-"), error),
+                    )
+                }
+                ServiceOutcome::Skipped { ref reason } => ("skipped", reason.clone()),
+            };
+            println!(
+                "{:<20} {:<9} {:>7.1}s  {}",
+                result.service,
+                status,
+                result.duration_ms as f64 / 1000.0,
+                detail
+            );
+        }
+        println!("{}", self);
+    }
+}
+
+impl std::fmt::Display for UpSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} started, {} failed, {} skipped",
+            self.project, self.started, self.failed, self.skipped
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_dependents_of_failures_and_sets_exit_codes() {
+        let boltfile: BoltFile = toml::from_str(
+            r#"
+project = "shop"
+
+[services.db]
+image = "postgres"
+
+[services.api]
+image = "api"
+depends_on = ["db"]
+
+[services.web]
+image = "web"
+depends_on = ["api"]
+
+[services.cache]
+image = "redis"
+"#,
+        )
+        .unwrap();
+        let failure = || BoltError::Other(anyhow::anyhow!("image not found"));
+
+        let mut summary = UpSummary::new("shop", FailureMode::Continue);
+        summary.record_failed("db", failure(), 120);
+        let reason = summary.skip_reason(&boltfile, "api").unwrap();
+        assert_eq!(reason, "depends on db, which failed");
+        summary.record_skipped("api", reason);
+        assert_eq!(
+            summary.skip_reason(&boltfile, "web").unwrap(),
+            "depends on api, which was skipped"
+        );
+        assert_eq!(summary.skip_reason(&boltfile, "cache"), None);
+        summary.record_skipped("web", "depends on api, which was skipped".to_string());
+        summary.record_started("cache", 800);
+        summary.finish(1000);
+        assert_eq!(summary.status, UpStatus::Partial);
+        assert_eq!(
+            (summary.started, summary.failed, summary.skipped),
+            (1, 1, 2)
+        );
+        assert_eq!(summary.exit_code, 2);
+        assert_eq!(summary.started_services(), vec!["cache"]);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["status"], "partial");
+        assert_eq!(json["services"][0]["status"], "failed");
+        assert_eq!(
+            json["services"][0]["error"],
+            "Generic error: image not found"
+        );
+        assert_eq!(json["services"][1]["reason"], "depends on db, which failed");
+        assert!(summary.into_result().is_err());
+
+        let mut fail_fast = UpSummary::new("shop", FailureMode::FailFast);
+        fail_fast.record_failed("db", failure(), 0);
+        assert_eq!(
+            fail_fast.skip_reason(&boltfile, "cache").unwrap(),
+            "stopped after db failed"
+        );
+        fail_fast.record_skipped("cache", "stopped after db failed".to_string());
+        fail_fast.finish(0);
+        assert_eq!(fail_fast.exit_code, 1);
+
+        let mut ok = UpSummary::new("shop", FailureMode::FailFast);
+        ok.record_started("db", 10);
+        ok.finish(10);
+        assert_eq!(ok.exit_code, 0);
+        assert!(ok.into_result().is_ok());
+    }
+}
@@ -0,0 +1,534 @@
+// Surge resource feasibility
+//
+// `bolt surge plan` adds up what the services reserve (`cpu_limit`,
+// `memory_limit`, GPUs and `storage.size`) and compares it with this host:
+// its CPUs, its memory less some headroom for the host itself, its GPUs and
+// the free space where the runtime keeps containers. Services are placed in
+// start order; one that doesn't fit next to those before it is reported
+// with the resource it's short of. Memory limits are hard caps, so when
+// they add up to more than the host has, every service at its limit means
+// OOM kills. The suggestions scale the limits down to fit, and split the
+// start order into waves that fit on their own so services don't all reach
+// their startup peak at once.
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::process::Command as AsyncCommand;
+
+use crate::Result;
+use crate::builds::cache::{format_size, parse_size};
+use crate::config::{BoltConfig, Service};
+use crate::runtime;
+
+/// Share of host memory kept for the host itself
+const MEMORY_HEADROOM: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resource {
+    Cpu,
+    Memory,
+    Gpu,
+    Disk,
+}
+
+impl Resource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Cpu => "cpu",
+            Resource::Memory => "memory",
+            Resource::Gpu => "gpu",
+            Resource::Disk => "disk",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostCapacity {
+    pub cpus: f64,
+    /// Bytes of RAM
+    pub memory: u64,
+    pub gpus: u32,
+    /// Free bytes where the runtime stores containers, when known
+    pub disk: Option<u64>,
+}
+
+impl HostCapacity {
+    /// Memory services may reserve, after the headroom for the host
+    pub fn usable_memory(&self) -> u64 {
+        (self.memory as f64 * (1.0 - MEMORY_HEADROOM)) as u64
+    }
+}
+
+/// What one service reserves; unset limits reserve nothing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reservation {
+    pub service: String,
+    pub cpus: Option<f64>,
+    pub memory: Option<u64>,
+    pub gpus: u32,
+    /// `isolation_level = "exclusive"`: the GPUs can't be shared
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclusive_gpu: bool,
+    pub disk: Option<u64>,
+}
+
+impl Reservation {
+    pub fn from_service(name: &str, service: &Service) -> Result<Self> {
+        let cpus = service
+            .cpu_limit
+            .as_deref()
+            .map(|c| {
+                c.trim()
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid cpu_limit '{}': expected a number of CPUs", c))
+            })
+            .transpose()?;
+        let memory = service
+            .memory_limit
+            .as_deref()
+            .map(parse_size)
+            .transpose()?;
+        let gpu = service.gaming.as_ref().and_then(|g| g.gpu.as_ref());
+        let disk = service
+            .storage
+            .as_ref()
+            .map(|s| parse_size(&s.size))
+            .transpose()?;
+        Ok(Self {
+            service: name.to_string(),
+            cpus,
+            memory,
+            gpus: gpu.map(|g| g.count.unwrap_or(1)).unwrap_or(0),
+            exclusive_gpu: gpu.is_some_and(|g| g.isolation_level.as_deref() == Some("exclusive")),
+            disk,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Totals {
+    pub cpus: f64,
+    pub memory: u64,
+    /// GPUs reserved exclusively; shared ones don't add up
+    pub gpus: u32,
+    pub disk: u64,
+}
+
+/// A service that can't run alongside the services starting before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unsatisfiable {
+    pub service: String,
+    pub resource: Resource,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Feasibility {
+    pub host: HostCapacity,
+    pub totals: Totals,
+    /// In start order
+    pub reservations: Vec<Reservation>,
+    /// Resources whose reservations add up to more than the host has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overcommitted: Vec<Resource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unsatisfiable: Vec<Unsatisfiable>,
+    /// Services without a memory_limit, which may take all of the host's memory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbounded: Vec<String>,
+    /// Start order split into groups whose memory limits fit on their own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waves: Vec<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+}
+
+impl Feasibility {
+    pub fn is_feasible(&self) -> bool {
+        self.unsatisfiable.is_empty() && !self.overcommitted.contains(&Resource::Memory)
+    }
+
+    pub fn print(&self) {
+        println!("{:<10} {:>12} {:>12}", "RESOURCE", "RESERVED", "HOST");
+        println!(
+            "{:<10} {:>12} {:>12}",
+            "cpu",
+            format!("{:.1}", self.totals.cpus),
+            self.host.cpus
+        );
+        println!(
+            "{:<10} {:>12} {:>12}",
+            "memory",
+            format_size(self.totals.memory),
+            format_size(self.host.usable_memory())
+        );
+        println!(
+            "{:<10} {:>12} {:>12}",
+            "gpu", self.totals.gpus, self.host.gpus
+        );
+        println!(
+            "{:<10} {:>12} {:>12}",
+            "disk",
+            format_size(self.totals.disk),
+            self.host
+                .disk
+                .map(format_size)
+                .unwrap_or_else(|| "?".to_string())
+        );
+        for resource in &self.overcommitted {
+            println!("  ⚠️  {} is overcommitted", resource.as_str());
+        }
+        for unsatisfiable in &self.unsatisfiable {
+            println!("  ⛔ {}: {}", unsatisfiable.service, unsatisfiable.reason);
+        }
+        if !self.unbounded.is_empty() {
+            println!("  ⚠️  no memory_limit: {}", self.unbounded.join(", "));
+        }
+        for suggestion in &self.suggestions {
+            println!("  💡 {}", suggestion);
+        }
+    }
+}
+
+/// Place the reservations, in start order, on the host
+pub fn assess(host: HostCapacity, reservations: Vec<Reservation>) -> Feasibility {
+    let mut feasibility = Feasibility {
+        totals: Totals {
+            cpus: reservations.iter().filter_map(|r| r.cpus).sum(),
+            memory: reservations.iter().filter_map(|r| r.memory).sum(),
+            gpus: reservations
+                .iter()
+                .filter(|r| r.exclusive_gpu)
+                .map(|r| r.gpus)
+                .sum(),
+            disk: reservations.iter().filter_map(|r| r.disk).sum(),
+        },
+        unbounded: reservations
+            .iter()
+            .filter(|r| r.memory.is_none())
+            .map(|r| r.service.clone())
+            .collect(),
+        ..Default::default()
+    };
+    let memory_budget = host.usable_memory();
+    let totals = &feasibility.totals;
+    if totals.cpus > host.cpus {
+        feasibility.overcommitted.push(Resource::Cpu);
+    }
+    if totals.memory > memory_budget {
+        feasibility.overcommitted.push(Resource::Memory);
+    }
+    if totals.gpus > host.gpus {
+        feasibility.overcommitted.push(Resource::Gpu);
+    }
+    if host.disk.is_some_and(|free| totals.disk > free) {
+        feasibility.overcommitted.push(Resource::Disk);
+    }
+
+    // First fit in start order: what's left once the earlier services got theirs
+    let mut memory_left = memory_budget;
+    let mut gpus_left = host.gpus;
+    let mut disk_left = host.disk;
+    for r in &reservations {
+        let mut short = |resource, reason: String| {
+            feasibility.unsatisfiable.push(Unsatisfiable {
+                service: r.service.clone(),
+                resource,
+                reason,
+            })
+        };
+        if let Some(cpus) = r.cpus.filter(|c| *c > host.cpus) {
+            short(
+                Resource::Cpu,
+                format!(
+                    "cpu_limit {} is more than the host's {} CPUs",
+                    cpus, host.cpus
+                ),
+            );
+        }
+        if let Some(memory) = r.memory {
+            if memory > memory_left {
+                short(
+                    Resource::Memory,
+                    format!(
+                        "memory_limit {} doesn't fit, {} is left of {}",
+                        format_size(memory),
+                        format_size(memory_left),
+                        format_size(memory_budget)
+                    ),
+                );
+            } else {
+                memory_left -= memory;
+            }
+        }
+        if r.gpus > host.gpus {
+            short(
+                Resource::Gpu,
+                format!("needs {} GPUs, the host has {}", r.gpus, host.gpus),
+            );
+        } else if r.exclusive_gpu {
+            if r.gpus > gpus_left {
+                short(
+                    Resource::Gpu,
+                    format!(
+                        "needs {} exclusive GPUs, {} are left of {}",
+                        r.gpus, gpus_left, host.gpus
+                    ),
+                );
+            } else {
+                gpus_left -= r.gpus;
+            }
+        }
+        if let (Some(disk), Some(left)) = (r.disk, disk_left) {
+            if disk > left {
+                short(
+                    Resource::Disk,
+                    format!(
+                        "storage size {} doesn't fit, {} is free",
+                        format_size(disk),
+                        format_size(left)
+                    ),
+                );
+            } else {
+                disk_left = Some(left - disk);
+            }
+        }
+    }
+
+    if feasibility.overcommitted.contains(&Resource::Memory) {
+        feasibility.waves = waves(&reservations, memory_budget);
+    }
+    feasibility.suggestions = suggestions(&host, &reservations, &feasibility);
+    feasibility.reservations = reservations;
+    feasibility.host = host;
+    feasibility
+}
+
+/// Split the start order into consecutive groups whose memory limits fit
+/// the budget, so dependencies still start first
+fn waves(reservations: &[Reservation], budget: u64) -> Vec<Vec<String>> {
+    let mut waves: Vec<Vec<String>> = Vec::new();
+    let mut used = 0;
+    for r in reservations {
+        let memory = r.memory.unwrap_or(0);
+        match waves.last_mut() {
+            Some(wave) if used + memory <= budget => {
+                wave.push(r.service.clone());
+                used += memory;
+            }
+            _ => {
+                waves.push(vec![r.service.clone()]);
+                used = memory;
+            }
+        }
+    }
+    waves
+}
+
+fn suggestions(
+    host: &HostCapacity,
+    reservations: &[Reservation],
+    feasibility: &Feasibility,
+) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    let totals = &feasibility.totals;
+    if feasibility.overcommitted.contains(&Resource::Memory) {
+        let factor = host.usable_memory() as f64 / totals.memory as f64;
+        let limits: Vec<String> = reservations
+            .iter()
+            .filter_map(|r| {
+                let memory = r.memory?;
+                // Whole MB, rounded down so the sum stays within the budget
+                let scaled = (memory as f64 * factor) as u64 / (1024 * 1024);
+                Some(format!("{}={}MB", r.service, scaled))
+            })
+            .collect();
+        suggestions.push(format!(
+            "lower memory_limit to {:.0}% to fit: {}",
+            factor * 100.0,
+            limits.join(", ")
+        ));
+    }
+    if feasibility.waves.len() > 1 {
+        let commands: Vec<String> = feasibility
+            .waves
+            .iter()
+            .map(|wave| format!("bolt surge up {}", wave.join(" ")))
+            .collect();
+        suggestions.push(format!("start in waves: {}", commands.join(", then ")));
+    }
+    if feasibility.overcommitted.contains(&Resource::Cpu) {
+        suggestions.push(format!(
+            "cpu_limit adds up to {:.1} of {} CPUs; services will be throttled under load, lower them by {:.0}% to avoid it",
+            totals.cpus,
+            host.cpus,
+            (1.0 - host.cpus / totals.cpus) * 100.0
+        ));
+    }
+    if feasibility
+        .unsatisfiable
+        .iter()
+        .any(|u| u.resource == Resource::Gpu)
+    {
+        suggestions
+            .push("share GPUs with isolation_level = \"shared\", or lower gpu.count".to_string());
+    }
+    if feasibility.overcommitted.contains(&Resource::Disk) {
+        suggestions.push(format!(
+            "storage sizes add up to {}; free up space or lower storage.size",
+            format_size(totals.disk)
+        ));
+    }
+    // Services without a limit starting together are what OOM storms are made of
+    if feasibility.unbounded.len() > 1 {
+        suggestions.push(format!(
+            "set memory_limit on {} so a startup spike can't take the host's memory",
+            feasibility.unbounded.join(", ")
+        ));
+    }
+    suggestions
+}
+
+/// CPUs, memory and GPUs of this host, and free space in the runtime's storage
+pub async fn host_capacity(runtime: &str, data_dir: &Path) -> HostCapacity {
+    let memory = std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| {
+            meminfo
+                .lines()
+                .find_map(|line| line.strip_prefix("MemTotal:"))
+                .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        })
+        .map(|kb| kb * 1024)
+        .unwrap_or(0);
+    let storage = storage_root(runtime).await;
+    HostCapacity {
+        cpus: num_cpus::get() as f64,
+        memory,
+        gpus: crate::gaming::recovery::list_gpus().len() as u32,
+        disk: free_space(storage.as_deref().unwrap_or(data_dir)),
+    }
+}
+
+/// Where the runtime keeps images and container filesystems
+async fn storage_root(runtime: &str) -> Option<std::path::PathBuf> {
+    let format = match runtime {
+        "podman" => "{{.Store.GraphRoot}}",
+        _ => "{{.DockerRootDir}}",
+    };
+    let output = AsyncCommand::new(runtime)
+        .args(["info", "--format", format])
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())?;
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!root.is_empty()).then(|| root.into())
+}
+
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Feasibility of `services` (default: all) on this host
+pub async fn check(config: &BoltConfig, services: &[String]) -> Result<Feasibility> {
+    let boltfile = config.load_boltfile()?;
+    let runtime = runtime::detect_container_runtime().await?;
+    let targets: Vec<&String> = if services.is_empty() {
+        let mut names: Vec<&String> = boltfile.services.keys().collect();
+        names.sort();
+        names
+    } else {
+        services.iter().collect()
+    };
+    let mut reservations = Vec::new();
+    for name in super::start_order(&boltfile, targets) {
+        let Some(service) = boltfile.services.get(name.as_str()) else {
+            return Err(anyhow!("Service '{}' not found in Boltfile", name).into());
+        };
+        reservations.push(
+            Reservation::from_service(name, service)
+                .map_err(|e| anyhow!("Service '{}': {}", name, e))?,
+        );
+    }
+    let host = host_capacity(&runtime, &config.data_dir).await;
+    Ok(assess(host, reservations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn finds_what_does_not_fit_and_suggests_waves() {
+        let host = HostCapacity {
+            cpus: 4.0,
+            memory: 10 * GB,
+            gpus: 1,
+            disk: Some(50 * GB),
+        };
+        let reservation = |service: &str, cpus, memory, gpus| Reservation {
+            service: service.to_string(),
+            cpus,
+            memory,
+            gpus,
+            exclusive_gpu: gpus > 0,
+            disk: None,
+        };
+        let feasibility = assess(
+            host,
+            vec![
+                reservation("db", Some(2.0), Some(4 * GB), 0),
+                reservation("game", Some(4.0), Some(4 * GB), 1),
+                reservation("trainer", Some(2.0), Some(2 * GB), 1),
+                reservation("web", None, None, 0),
+            ],
+        );
+
+        assert!(!feasibility.is_feasible());
+        assert_eq!(feasibility.totals.memory, 10 * GB);
+        assert_eq!(
+            feasibility.overcommitted,
+            [Resource::Cpu, Resource::Memory, Resource::Gpu]
+        );
+        // 9 GB usable: the trainer's memory and its exclusive GPU are taken
+        let short: Vec<(&str, Resource)> = feasibility
+            .unsatisfiable
+            .iter()
+            .map(|u| (u.service.as_str(), u.resource))
+            .collect();
+        assert_eq!(
+            short,
+            [("trainer", Resource::Memory), ("trainer", Resource::Gpu)]
+        );
+        assert_eq!(feasibility.unbounded, ["web"]);
+        assert_eq!(
+            feasibility.waves,
+            [vec!["db", "game"], vec!["trainer", "web"]]
+        );
+        assert!(feasibility.suggestions[0].starts_with("lower memory_limit to 90%"));
+        assert!(feasibility.suggestions[1].contains("bolt surge up db game, then"));
+
+        let fits = assess(
+            HostCapacity {
+                cpus: 4.0,
+                memory: 10 * GB,
+                gpus: 0,
+                disk: None,
+            },
+            vec![reservation("db", Some(2.0), Some(4 * GB), 0)],
+        );
+        assert!(fits.is_feasible());
+        assert_eq!(fits.host.cpus, 4.0);
+        assert!(fits.suggestions.is_empty());
+    }
+}
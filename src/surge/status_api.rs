@@ -1,22 +1,39 @@
 use crate::Result;
 use crate::config::BoltConfig;
+use crate::runtime::{healthcheck, supervisor};
+use crate::runtime::wasm::{self, ServiceRuntime};
 use crate::{ServiceInfo, SurgeStatus};
 
 // API-only functions for library usage
 pub async fn status_info(config: &BoltConfig) -> Result<SurgeStatus> {
     let boltfile = config.load_boltfile()?;
 
+    // Without a runtime nothing of the project can be running
+    let runtime = crate::runtime::detect_container_runtime().await.ok();
     let mut services = Vec::new();
-    for (name, _service) in &boltfile.services {
+    for (name, service) in &boltfile.services {
+        let container = format!("{}_{}", boltfile.project, name);
+        let status = if service.runtime == Some(ServiceRuntime::Wasm) {
+            wasm::state(&config.data_dir, &container).map(|s| s.describe())
+        } else if let Some(ref runtime) = runtime {
+            // `running (healthy)`, `running (unhealthy)`, `exited`, ...
+            healthcheck::status(runtime, &container)
+                .await
+                .map(|h| h.describe())
+        } else {
+            None
+        };
         services.push(ServiceInfo {
             name: name.clone(),
-            status: "not running".to_string(), // TODO: Implement actual status
-            replicas: 1,                       // TODO: Implement actual replica count
+            status: status.unwrap_or_else(|| "not running".to_string()),
+            replicas: 1, // TODO: Implement actual replica count
+            restarts: supervisor::state(&config.data_dir, &container),
         });
     }
 
     Ok(SurgeStatus {
         services,
         networks: vec![], // TODO: Implement network status
+        dirty: super::watch::check(&config.data_dir, &boltfile)?,
     })
 }
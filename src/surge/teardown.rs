@@ -0,0 +1,393 @@
+// Surge down
+//
+// `surge down` stops services in the reverse of the order `surge up` starts
+// them, dependents before their dependencies, so nothing loses a dependency
+// while it still runs. Each container gets its service's `stop_grace_period`
+// to exit after SIGTERM before it is killed, and scaled instances go before
+// the service's first container.
+//
+// Orphans are containers of the project whose service is no longer in the
+// Boltfile, usually because it was renamed: containers labelled with the
+// project that match no service, and services the project record still
+// lists. They are reported, and removed with `--remove-orphans`.
+//
+// A Boltfile network the stopped services joined is removed once no
+// container uses it any more and no other project on this node joins it.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::process::Command as AsyncCommand;
+use tracing::warn;
+
+use super::projects::{self, ProjectRecord};
+use crate::ContainerInfo;
+use crate::config::{BoltFile, Service};
+
+/// How long a container gets to exit when its service sets no
+/// `stop_grace_period`, the same as podman's and docker's default
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How `surge down` stops services
+#[derive(Debug, Clone, Default)]
+pub struct DownOptions {
+    pub remove_volumes: bool,
+    pub remove_orphans: bool,
+}
+
+/// A container `surge down` took out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoppedContainer {
+    pub service: String,
+    pub container: String,
+    /// It didn't exit within the grace period
+    pub killed: bool,
+    /// Why it couldn't be removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A network left in place, and what still uses it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeptNetwork {
+    pub network: String,
+    pub users: Vec<String>,
+}
+
+/// What `surge down` removed, in the order it did
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownSummary {
+    pub project: String,
+    pub stopped: Vec<StoppedContainer>,
+    /// Orphans removed with `--remove-orphans`
+    pub orphans_removed: Vec<StoppedContainer>,
+    /// Orphans found and left alone
+    pub orphans: Vec<String>,
+    pub networks_removed: Vec<String>,
+    pub networks_kept: Vec<KeptNetwork>,
+}
+
+impl DownSummary {
+    pub fn new(project: &str) -> Self {
+        Self {
+            project: project.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// One line per container and network
+    pub fn print(&self) {
+        for stopped in self.stopped.iter().chain(&self.orphans_removed) {
+            let kind = if self.orphans_removed.contains(stopped) {
+                "orphan"
+            } else {
+                "removed"
+            };
+            match stopped.error {
+                Some(ref error) => println!(
+                    "❌ {:<20} {:<28} not removed: {}",
+                    stopped.service, stopped.container, error
+                ),
+                None if stopped.killed => println!(
+                    "⏱️  {:<20} {:<28} {}, killed after its grace period",
+                    stopped.service, stopped.container, kind
+                ),
+                None => println!(
+                    "🛑 {:<20} {:<28} {}",
+                    stopped.service, stopped.container, kind
+                ),
+            }
+        }
+        for network in &self.networks_removed {
+            println!("🌐 {:<20} {:<28} removed", "network", network);
+        }
+        for kept in &self.networks_kept {
+            println!(
+                "🌐 {:<20} {:<28} kept, used by {}",
+                "network",
+                kept.network,
+                kept.users.join(", ")
+            );
+        }
+        if !self.orphans.is_empty() {
+            println!(
+                "⚠️  Orphan containers of {}: {}; remove them with `bolt surge down --remove-orphans`",
+                self.project,
+                self.orphans.join(", ")
+            );
+        }
+        println!("{}", self);
+    }
+}
+
+impl std::fmt::Display for DownSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let removed = |list: &[StoppedContainer]| list.iter().filter(|s| s.error.is_none()).count();
+        write!(
+            f,
+            "{}: {} container(s) removed, {} orphan(s) removed, {} network(s) removed",
+            self.project,
+            removed(&self.stopped),
+            removed(&self.orphans_removed),
+            self.networks_removed.len()
+        )
+    }
+}
+
+/// Order services so each stops before the services it depends on
+pub fn stop_order<'a>(boltfile: &BoltFile, targets: Vec<&'a String>) -> Vec<&'a String> {
+    let mut order = super::start_order(boltfile, targets);
+    order.reverse();
+    order
+}
+
+/// The service's `stop_grace_period`, or the default when it has none or
+/// it doesn't parse
+pub fn grace_period(service: Option<&Service>) -> Duration {
+    let Some(period) = service.and_then(|s| s.stop_grace_period.as_deref()) else {
+        return DEFAULT_GRACE_PERIOD;
+    };
+    crate::builds::cache::parse_duration(period).unwrap_or_else(|e| {
+        warn!("Ignoring stop_grace_period '{}': {}", period, e);
+        DEFAULT_GRACE_PERIOD
+    })
+}
+
+/// `name` is `container` or one of its scaled instances (`<container>_<n>`)
+fn is_instance_of(name: &str, container: &str) -> bool {
+    name == container
+        || name
+            .strip_prefix(container)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .is_some_and(|n| n.parse::<u32>().is_ok())
+}
+
+/// Existing containers of a service, scaled instances first
+pub fn service_containers(container: &str, existing: &[ContainerInfo]) -> Vec<String> {
+    let mut names: Vec<String> = existing
+        .iter()
+        .map(|c| c.name.clone())
+        .filter(|name| name != container && is_instance_of(name, container))
+        .collect();
+    let instance = |name: &String| name[container.len() + 1..].parse::<u32>().unwrap_or(0);
+    names.sort_by_key(|name| std::cmp::Reverse(instance(name)));
+    if existing.iter().any(|c| c.name == container) {
+        names.push(container.to_string());
+    }
+    names
+}
+
+/// Containers of the project that belong to no service in the Boltfile,
+/// with the service they were probably started for
+pub fn orphans(
+    boltfile: &BoltFile,
+    existing: &[ContainerInfo],
+    record: Option<&ProjectRecord>,
+) -> Vec<(String, String)> {
+    let belongs = |name: &str| {
+        boltfile
+            .services
+            .keys()
+            .any(|s| is_instance_of(name, &format!("{}_{}", boltfile.project, s)))
+    };
+    let prefix = format!("{}_", boltfile.project);
+
+    let mut found: Vec<(String, String)> = existing
+        .iter()
+        .filter(|c| c.labels.get(projects::LABEL) == Some(&boltfile.project))
+        .filter(|c| !belongs(&c.name))
+        .map(|c| {
+            let service = c.name.strip_prefix(&prefix).unwrap_or(&c.name);
+            let service = match service.rsplit_once('_') {
+                Some((base, n)) if n.parse::<u32>().is_ok() => base,
+                _ => service,
+            };
+            (service.to_string(), c.name.clone())
+        })
+        .collect();
+
+    for (service, recorded) in record.into_iter().flat_map(|r| &r.services) {
+        if boltfile.services.contains_key(service) || recorded.wasm {
+            continue;
+        }
+        found.extend(
+            existing
+                .iter()
+                .filter(|c| is_instance_of(&c.name, &recorded.container))
+                .map(|c| (service.clone(), c.name.clone())),
+        );
+    }
+    found.sort_by(|a, b| a.1.cmp(&b.1));
+    found.dedup_by(|a, b| a.1 == b.1);
+    found
+}
+
+/// Runtime names of the networks the Boltfile declares and Bolt creates,
+/// i.e. not `external`, by Boltfile key
+fn declared_networks(boltfile: &BoltFile) -> Vec<(&String, String)> {
+    boltfile
+        .networks
+        .iter()
+        .flatten()
+        .filter(|(_, network)| !network.external.unwrap_or(false))
+        .map(|(key, network)| (key, network.name.clone().unwrap_or_else(|| key.clone())))
+        .collect()
+}
+
+/// The project's own networks that `services` join, plus those the record
+/// lists for removed orphans
+pub fn candidate_networks(
+    boltfile: &BoltFile,
+    services: &[&String],
+    orphan_networks: &[String],
+) -> Vec<String> {
+    let declared = declared_networks(boltfile);
+    let mut names: Vec<String> = services
+        .iter()
+        .filter_map(|name| boltfile.services.get(name.as_str())?.networks.as_ref())
+        .flat_map(|networks| networks.attachments())
+        .filter_map(|(key, _)| {
+            declared
+                .iter()
+                .find(|(k, _)| k.as_str() == key)
+                .map(|(_, n)| n.clone())
+        })
+        .chain(
+            orphan_networks
+                .iter()
+                .filter(|n| declared.iter().any(|(_, d)| d == *n))
+                .cloned(),
+        )
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Containers attached to `network`, running or not
+pub async fn network_users(runtime: &str, network: &str) -> anyhow::Result<Vec<String>> {
+    let output = AsyncCommand::new(runtime)
+        .args(["ps", "-a", "--format", "{{.Names}}", "--filter"])
+        .arg(format!("network={}", network))
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to list containers on {}: {}",
+            network,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn container(name: &str, project: Option<&str>) -> ContainerInfo {
+        ContainerInfo {
+            id: name.to_string(),
+            name: name.to_string(),
+            names: vec![name.to_string()],
+            image: "img".to_string(),
+            image_id: String::new(),
+            command: String::new(),
+            created: String::new(),
+            status: "running".to_string(),
+            ports: Vec::new(),
+            labels: project
+                .map(|p| HashMap::from([(projects::LABEL.to_string(), p.to_string())]))
+                .unwrap_or_default(),
+            uptime: None,
+            runtime: None,
+        }
+    }
+
+    #[test]
+    fn stops_dependents_first_and_finds_orphans() {
+        let boltfile: BoltFile = toml::from_str(
+            r#"
+project = "shop"
+
+[services.db]
+image = "postgres"
+networks = ["back"]
+
+[services.api]
+image = "api"
+depends_on = ["db"]
+networks = ["back", "shared"]
+stop_grace_period = "30s"
+
+[services.web]
+image = "web"
+depends_on = ["api"]
+
+[networks.back]
+driver = "bridge"
+name = "shop-back"
+
+[networks.shared]
+driver = "bridge"
+external = true
+"#,
+        )
+        .unwrap();
+        let (db, api, web) = ("db".to_string(), "api".to_string(), "web".to_string());
+        assert_eq!(
+            stop_order(&boltfile, vec![&db, &web, &api]),
+            vec![&web, &api, &db]
+        );
+        assert_eq!(
+            grace_period(boltfile.services.get("api")),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            grace_period(boltfile.services.get("db")),
+            DEFAULT_GRACE_PERIOD
+        );
+
+        let existing = vec![
+            container("shop_api", Some("shop")),
+            container("shop_api_2", Some("shop")),
+            container("shop_api_10", Some("shop")),
+            container("shop_api_gateway", None),
+            container("shop_frontend_1", Some("shop")),
+            container("shop_cache", None),
+            container("shopping_web", Some("shopping")),
+        ];
+        assert_eq!(
+            service_containers("shop_api", &existing),
+            vec!["shop_api_10", "shop_api_2", "shop_api"]
+        );
+
+        let mut record = ProjectRecord {
+            project: "shop".to_string(),
+            ..Default::default()
+        };
+        record.services.insert(
+            "cache".to_string(),
+            projects::RecordedService {
+                container: "shop_cache".to_string(),
+                wasm: false,
+                ports: Vec::new(),
+            },
+        );
+        assert_eq!(
+            orphans(&boltfile, &existing, Some(&record)),
+            vec![
+                ("cache".to_string(), "shop_cache".to_string()),
+                ("frontend".to_string(), "shop_frontend_1".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            candidate_networks(&boltfile, &[&api, &web], &["shared".to_string()]),
+            vec!["shop-back"]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Boltfile changes
+//
+// `surge up` keeps a fingerprint of the Boltfile it applied in the project's
+// record. When the Boltfile on disk no longer matches it, the running
+// services are out of date until the next `surge up`: the plan is dirty, and
+// `surge status` says so. The fingerprint is taken of the parsed Boltfile,
+// so editing comments or formatting doesn't dirty the plan, while a change
+// to an `include`d file or a template variable does.
+//
+// `surge status` checks when it runs. The API server started with
+// `--watch-boltfile` polls its Boltfile and logs as soon as the plan becomes
+// dirty, or clean again; the time it noticed is kept in the record.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::projects;
+use crate::BoltRuntime;
+use crate::config::BoltFile;
+
+/// The Boltfile on disk differs from the one the services run from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirtyPlan {
+    /// When the change was first seen
+    pub since: chrono::DateTime<chrono::Utc>,
+    /// When the project's services last changed
+    pub applied_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Fingerprint of what a Boltfile means, independent of its formatting
+pub fn fingerprint(boltfile: &BoltFile) -> Result<String> {
+    // Going through a `Value` sorts the maps, so the order services happen
+    // to be iterated in doesn't matter
+    let value = serde_json::to_value(boltfile)?;
+    Ok(hex::encode(Sha256::digest(serde_json::to_vec(&value)?)))
+}
+
+/// Compare a project's Boltfile with the one its services were started
+/// from, recording when it started to differ. None when nothing of the
+/// project is up.
+pub fn check(data_dir: &Path, boltfile: &BoltFile) -> Result<Option<DirtyPlan>> {
+    let Some(mut record) = projects::get(data_dir, &boltfile.project) else {
+        return Ok(None);
+    };
+    let Some(ref applied) = record.applied else {
+        return Ok(None);
+    };
+    let dirty = *applied != fingerprint(boltfile)?;
+    match (dirty, record.dirty_since) {
+        (true, Some(since)) => Ok(Some(DirtyPlan {
+            since,
+            applied_at: record.updated_at,
+        })),
+        (true, None) => {
+            let since = chrono::Utc::now();
+            record.dirty_since = Some(since);
+            projects::save(data_dir, &record);
+            Ok(Some(DirtyPlan {
+                since,
+                applied_at: record.updated_at,
+            }))
+        }
+        (false, Some(_)) => {
+            record.dirty_since = None;
+            projects::save(data_dir, &record);
+            Ok(None)
+        }
+        (false, None) => Ok(None),
+    }
+}
+
+/// Poll the runtime's Boltfile until cancelled, logging when the plan
+/// becomes dirty or clean. Follows config reloads.
+pub async fn watch(runtime: BoltRuntime, interval: Duration) {
+    info!("👀 Watching the Boltfile for changes");
+    let mut dirty = false;
+    let mut last_error = None;
+    loop {
+        let config = runtime.config();
+        let result = config
+            .load_boltfile()
+            .and_then(|boltfile| Ok((check(&config.data_dir, &boltfile)?, boltfile.project)));
+        match result {
+            Ok((plan, project)) => {
+                last_error = None;
+                match (plan.is_some(), dirty) {
+                    (true, false) => warn!(
+                        "📝 Boltfile of {} changed; its services run the old one until `bolt surge up`",
+                        project
+                    ),
+                    (false, true) => info!("✅ Boltfile of {} matches its services again", project),
+                    _ => {}
+                }
+                dirty = plan.is_some();
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                if last_error.as_ref() != Some(&message) {
+                    warn!("⚠️  Boltfile can't be loaded: {}", message);
+                    last_error = Some(message);
+                }
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_the_plan_dirty_until_the_boltfile_is_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let parse = |content: &str| -> BoltFile { toml::from_str(content).unwrap() };
+        let applied = parse(
+            "project = \"shop\"\n\n[services.web]\nimage = \"nginx\"\n\n[services.db]\nimage = \"postgres\"\n",
+        );
+        assert_eq!(check(dir.path(), &applied).unwrap(), None);
+
+        let web = "web".to_string();
+        projects::record(dir.path(), &applied, &[&web]);
+        assert_eq!(check(dir.path(), &applied).unwrap(), None);
+
+        // Reordered and reformatted: still the same Boltfile
+        let reformatted = parse(
+            "project = \"shop\"\n[services.db]\nimage = \"postgres\"\n# web\n[services.web]\nimage = \"nginx\"\n",
+        );
+        assert_eq!(
+            fingerprint(&reformatted).unwrap(),
+            fingerprint(&applied).unwrap()
+        );
+
+        let changed = parse("project = \"shop\"\n\n[services.web]\nimage = \"nginx:1.27\"\n");
+        let dirty = check(dir.path(), &changed).unwrap().unwrap();
+        // The first time it was seen sticks
+        assert_eq!(check(dir.path(), &changed).unwrap().unwrap(), dirty);
+        assert_eq!(
+            projects::get(dir.path(), "shop").unwrap().dirty_since,
+            Some(dirty.since)
+        );
+
+        // Reverting the change cleans the plan again
+        assert_eq!(check(dir.path(), &reformatted).unwrap(), None);
+        assert_eq!(projects::get(dir.path(), "shop").unwrap().dirty_since, None);
+
+        check(dir.path(), &changed).unwrap().unwrap();
+        projects::record(dir.path(), &changed, &[&web]);
+        assert_eq!(check(dir.path(), &changed).unwrap(), None);
+    }
+}
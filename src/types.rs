@@ -1,5 +1,5 @@
 /// Container information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerInfo {
     pub id: String,
     pub name: String,
@@ -16,22 +16,28 @@ pub struct ContainerInfo {
 }
 
 /// Surge orchestration status
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SurgeStatus {
     pub services: Vec<ServiceInfo>,
     pub networks: Vec<NetworkInfo>,
+    /// Set when the Boltfile changed since the services were started from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<crate::surge::watch::DirtyPlan>,
 }
 
 /// Service information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInfo {
     pub name: String,
     pub status: String,
     pub replicas: u32,
+    /// Restarts under the service's restart policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restarts: Option<crate::runtime::supervisor::SupervisorState>,
 }
 
 /// Network information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkInfo {
     pub id: String, // Docker API compatibility
     pub name: String,
@@ -40,4 +46,5 @@ pub struct NetworkInfo {
     pub created: Option<String>, // Docker API compatibility
 }
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -0,0 +1,574 @@
+// Per-volume page cache tuning
+//
+// Game assets are read in long sequential runs and want the kernel to read
+// far ahead; databases read scattered pages, where read-ahead only wastes
+// I/O and cache. A named volume's `cache_profile` picks the behaviour:
+//
+// - streaming: 4 MB device read-ahead, hot paths kept in the page cache
+// - database: 16 KB device read-ahead
+// - default: the device is left as it is
+//
+// Read-ahead is a property of the block device (queue/read_ahead_kb), so it
+// is set on the disk holding the volume; when volumes on one disk ask for
+// different values the largest wins. `hot_paths` are files or directories
+// in the volume pre-faulted into the page cache when the container starts,
+// vmtouch-style: POSIX_FADV_WILLNEED over every file, with mincore(2)
+// telling how much of it is resident.
+//
+// A detached service using such a volume gets a `bolt cache-tune` process
+// that applies this once the container runs and then every minute: a
+// read-ahead that was reset (by udev, or a disk coming back) is set again,
+// and hot paths of streaming volumes evicted below 90% resident are faulted
+// back in. When the container goes away the read-ahead it changed is put
+// back. SEQUENTIAL and RANDOM advice only holds for the file descriptor it
+// is given, so it can't be applied on the container's behalf; the device
+// read-ahead is what sets the default for every reader.
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::{BoltFile, Service};
+use crate::runtime::watcher::{self, Watcher};
+
+/// How often the tuner checks read-ahead and hot path residency
+const TUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Hot paths of streaming volumes are faulted back in below this fraction
+const REWARM_BELOW: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheProfile {
+    #[default]
+    Default,
+    /// Large sequential reads, such as game assets and media
+    Streaming,
+    /// Small random reads
+    Database,
+}
+
+impl CacheProfile {
+    /// Device read-ahead in KB; None leaves the device as it is
+    pub fn readahead_kb(self) -> Option<u32> {
+        match self {
+            CacheProfile::Default => None,
+            CacheProfile::Streaming => Some(4096),
+            CacheProfile::Database => Some(16),
+        }
+    }
+
+    /// Whether hot paths are faulted back in after being evicted
+    pub fn keeps_warm(self) -> bool {
+        self == CacheProfile::Streaming
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheProfile::Default => "default",
+            CacheProfile::Streaming => "streaming",
+            CacheProfile::Database => "database",
+        }
+    }
+}
+
+/// A volume a container uses, with its tuning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TunedVolume {
+    pub name: String,
+    pub mountpoint: PathBuf,
+    pub profile: CacheProfile,
+    #[serde(default)]
+    pub hot_paths: Vec<String>,
+}
+
+/// Read-ahead the tuner set on a disk, and what it was before
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceReadahead {
+    /// major:minor
+    pub device: String,
+    pub read_ahead_kb: u32,
+    pub previous_kb: u32,
+}
+
+/// How much of a hot path was in the page cache at the last check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HotPath {
+    pub volume: String,
+    pub path: String,
+    pub bytes: u64,
+    pub resident_bytes: u64,
+}
+
+/// A container's cache tuner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheState {
+    pub container: String,
+    pub volumes: Vec<TunedVolume>,
+    pub watcher_pid: Option<u32>,
+    #[serde(default)]
+    pub devices: Vec<DeviceReadahead>,
+    #[serde(default)]
+    pub hot_paths: Vec<HotPath>,
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CacheState {
+    /// Whether the tuner is still running
+    pub fn watching(&self) -> bool {
+        self.watcher_pid.is_some_and(|pid| WATCHER.alive(pid))
+    }
+}
+
+/// The Boltfile volumes a service mounts that have tuning, by source name
+pub fn tuned_volumes<'a>(
+    boltfile: &'a BoltFile,
+    service: &Service,
+) -> Vec<(String, &'a crate::config::Volume)> {
+    let Some(ref volumes) = boltfile.volumes else {
+        return Vec::new();
+    };
+    service
+        .volumes
+        .iter()
+        .flatten()
+        .filter_map(|mount| mount.split(':').next())
+        .filter_map(|source| {
+            let volume = volumes.get(source)?;
+            let tuned = volume
+                .cache_profile
+                .is_some_and(|p| p != CacheProfile::Default)
+                || volume.hot_paths.as_ref().is_some_and(|p| !p.is_empty());
+            tuned.then(|| {
+                (
+                    volume.name.clone().unwrap_or_else(|| source.to_string()),
+                    volume,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Where the runtime keeps a named volume, or a Bolt volume of that name
+pub async fn mountpoint(runtime: &str, name: &str) -> Option<PathBuf> {
+    if let Ok(Some(path)) = crate::runtime::storage::restic::volume_mountpoint(runtime, name).await
+    {
+        return Some(path);
+    }
+    let path = crate::volume::VolumeManager::new()
+        .ok()?
+        .volumes_dir()
+        .join(name);
+    path.is_dir().then_some(path)
+}
+
+const WATCHER: Watcher = Watcher::new("cache-tune");
+
+fn cache_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("cache-tune")
+}
+
+fn state_path(data_dir: &Path, container: &str) -> PathBuf {
+    cache_dir(data_dir).join(format!("{}.json", container))
+}
+
+fn log_path(data_dir: &Path, container: &str) -> PathBuf {
+    cache_dir(data_dir).join(format!("{}.log", container))
+}
+
+pub fn state(data_dir: &Path, container: &str) -> Option<CacheState> {
+    watcher::load(&state_path(data_dir, container))
+}
+
+/// Every tuned container, by name
+pub fn list(data_dir: &Path) -> Vec<CacheState> {
+    let mut states: Vec<CacheState> = watcher::list(&cache_dir(data_dir));
+    states.sort_by(|a, b| a.container.cmp(&b.container));
+    states
+}
+
+fn save(data_dir: &Path, state: &CacheState) -> Result<()> {
+    watcher::save(&state_path(data_dir, &state.container), state)
+}
+
+/// Start tuning the page cache for a running container's volumes
+pub fn start(data_dir: &Path, container: &str, volumes: Vec<TunedVolume>) -> Result<()> {
+    forget(data_dir, container);
+    let mut state = CacheState {
+        container: container.to_string(),
+        volumes,
+        watcher_pid: None,
+        devices: Vec::new(),
+        hot_paths: Vec::new(),
+        error: None,
+        updated_at: Utc::now(),
+    };
+    save(data_dir, &state)?;
+    state.watcher_pid =
+        Some(WATCHER.spawn(["cache-tune", container], &log_path(data_dir, container))?);
+    save(data_dir, &state)?;
+    let volumes: Vec<String> = state
+        .volumes
+        .iter()
+        .map(|v| format!("{} ({})", v.name, v.profile.as_str()))
+        .collect();
+    info!(
+        "💿 Tuning the page cache of {} for {}",
+        container,
+        volumes.join(", ")
+    );
+    Ok(())
+}
+
+/// Stop tuning a container's volumes and drop its record; the tuner puts
+/// the read-ahead back on the way out
+pub fn forget(data_dir: &Path, container: &str) {
+    let pid = state(data_dir, container).and_then(|s| s.watcher_pid);
+    WATCHER.forget(&state_path(data_dir, container), pid);
+}
+
+/// Keep the container's volumes tuned until it stops or the tuner is told
+/// to, then restore the read-ahead. Run by `bolt cache-tune`.
+pub async fn watch(data_dir: &Path, container: &str) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut devices = Vec::new();
+    let result = tokio::select! {
+        result = keep_tuned(data_dir, container, &mut devices) => result,
+        _ = terminate.recv() => Ok(()),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    restore_readahead(&devices);
+    result
+}
+
+async fn keep_tuned(
+    data_dir: &Path,
+    container: &str,
+    devices: &mut Vec<DeviceReadahead>,
+) -> Result<()> {
+    let runtime = crate::runtime::detect_container_runtime().await?;
+    let mut first = true;
+    loop {
+        let Some(mut state) = state(data_dir, container) else {
+            debug!("{} is no longer tuned, stopping", container);
+            return Ok(());
+        };
+        if !crate::runtime::healthcheck::status(&runtime, container)
+            .await
+            .is_some_and(|s| s.running())
+        {
+            info!("{} stopped, restoring read-ahead", container);
+            return Ok(());
+        }
+
+        let mut errors = set_readahead(&state.volumes, devices);
+        let volumes = state.volumes.clone();
+        let (hot_paths, warm_errors) =
+            tokio::task::spawn_blocking(move || warm(&volumes, first)).await?;
+        errors.extend(warm_errors);
+        first = false;
+
+        state.devices = devices.clone();
+        state.hot_paths = hot_paths;
+        state.error = (!errors.is_empty()).then(|| errors.join("; "));
+        state.updated_at = Utc::now();
+        // The record may have been removed by `surge down` meanwhile
+        watcher::update(&state_path(data_dir, container), &state)?;
+        tokio::time::sleep(TUNE_INTERVAL).await;
+    }
+}
+
+fn readahead_path(device: &str) -> PathBuf {
+    PathBuf::from(format!("/sys/dev/block/{}/queue/read_ahead_kb", device))
+}
+
+fn read_readahead(device: &str) -> Option<u32> {
+    std::fs::read_to_string(readahead_path(device))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The read-ahead each disk should have; volumes on one disk that disagree
+/// get the largest
+fn wanted_readahead(
+    volumes: &[TunedVolume],
+    device_of: impl Fn(&Path) -> Option<(u64, u64)>,
+) -> (BTreeMap<String, u32>, Vec<String>) {
+    let mut wanted = BTreeMap::new();
+    let mut errors = Vec::new();
+    for volume in volumes {
+        let Some(kb) = volume.profile.readahead_kb() else {
+            continue;
+        };
+        let Some((major, minor)) = device_of(&volume.mountpoint) else {
+            errors.push(format!(
+                "{}: no block device behind {}",
+                volume.name,
+                volume.mountpoint.display()
+            ));
+            continue;
+        };
+        let entry = wanted.entry(format!("{}:{}", major, minor)).or_insert(kb);
+        if *entry != kb {
+            debug!(
+                "Volumes on {}:{} want different read-ahead, using the largest",
+                major, minor
+            );
+            *entry = (*entry).max(kb);
+        }
+    }
+    (wanted, errors)
+}
+
+/// Set every disk's read-ahead, recording the first value seen on each so
+/// it can be put back
+fn set_readahead(volumes: &[TunedVolume], devices: &mut Vec<DeviceReadahead>) -> Vec<String> {
+    let (wanted, mut errors) = wanted_readahead(volumes, crate::runtime::cgroups::block_device);
+    for (device, kb) in wanted {
+        let current = read_readahead(&device);
+        if current == Some(kb) {
+            continue;
+        }
+        if let Err(e) = std::fs::write(readahead_path(&device), kb.to_string()) {
+            errors.push(format!("read-ahead of {}: {}", device, e));
+            continue;
+        }
+        match devices.iter_mut().find(|d| d.device == device) {
+            Some(known) => {
+                info!(
+                    "💿 Read-ahead of {} was reset, setting {} KB again",
+                    device, kb
+                );
+                known.read_ahead_kb = kb;
+            }
+            None => {
+                info!("💿 Read-ahead of {}: {} KB", device, kb);
+                devices.push(DeviceReadahead {
+                    device,
+                    read_ahead_kb: kb,
+                    previous_kb: current.unwrap_or(128),
+                });
+            }
+        }
+    }
+    errors
+}
+
+/// Put back the read-ahead of disks nobody changed since
+fn restore_readahead(devices: &[DeviceReadahead]) {
+    for device in devices {
+        if read_readahead(&device.device) != Some(device.read_ahead_kb) {
+            continue;
+        }
+        if let Err(e) = std::fs::write(
+            readahead_path(&device.device),
+            device.previous_kb.to_string(),
+        ) {
+            warn!("Failed to restore read-ahead of {}: {}", device.device, e);
+        }
+    }
+}
+
+/// Fault hot paths into the page cache: every one on the first pass, later
+/// those of streaming volumes that were evicted. At most half the available
+/// memory is faulted in per pass.
+fn warm(volumes: &[TunedVolume], first: bool) -> (Vec<HotPath>, Vec<String>) {
+    let mut budget = available_memory() / 2;
+    let mut hot_paths = Vec::new();
+    let mut errors = Vec::new();
+    for volume in volumes {
+        for path in &volume.hot_paths {
+            let files = files_under(&volume.mountpoint.join(path));
+            let (bytes, resident_bytes) = files
+                .iter()
+                .filter_map(|file| residency(file).ok())
+                .fold((0, 0), |(bytes, resident), (b, r)| {
+                    (bytes + b, resident + r)
+                });
+            hot_paths.push(HotPath {
+                volume: volume.name.clone(),
+                path: path.clone(),
+                bytes,
+                resident_bytes,
+            });
+
+            let evicted = (resident_bytes as f64) < bytes as f64 * REWARM_BELOW;
+            if !(first || (volume.profile.keeps_warm() && evicted)) {
+                continue;
+            }
+            let missing = bytes - resident_bytes;
+            if missing > budget {
+                errors.push(format!(
+                    "{}/{}: {} MB to fault in exceeds half the available memory",
+                    volume.name,
+                    path,
+                    missing >> 20
+                ));
+                continue;
+            }
+            budget -= missing;
+            for file in &files {
+                if let Err(e) = will_need(file) {
+                    debug!("Failed to fault in {}: {}", file.display(), e);
+                }
+            }
+            if missing > 0 {
+                debug!(
+                    "Faulting in {} MB of {}/{}",
+                    missing >> 20,
+                    volume.name,
+                    path
+                );
+            }
+        }
+    }
+    (hot_paths, errors)
+}
+
+/// Regular files at or under `path`, without following symlinks
+fn files_under(path: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// A file's size, and how many of its bytes are in the page cache
+fn residency(path: &Path) -> std::io::Result<(u64, u64)> {
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok((0, 0));
+    }
+    // SAFETY: sysconf has no memory safety requirements
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+    let mut pages = vec![0u8; (len as usize).div_ceil(page)];
+    // SAFETY: the file is mapped read-only, only handed to mincore(2) with a
+    // vector of one byte per page, and unmapped before returning
+    unsafe {
+        let addr = libc::mmap(
+            std::ptr::null_mut(),
+            len as usize,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        if addr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let result = libc::mincore(addr, len as usize, pages.as_mut_ptr());
+        let error = std::io::Error::last_os_error();
+        libc::munmap(addr, len as usize);
+        if result != 0 {
+            return Err(error);
+        }
+    }
+    let resident = pages.iter().filter(|p| *p & 1 == 1).count() as u64 * page as u64;
+    Ok((len, resident.min(len)))
+}
+
+/// Ask the kernel to read a whole file into the page cache
+fn will_need(path: &Path) -> std::io::Result<()> {
+    let file = std::fs::File::open(path)?;
+    // SAFETY: posix_fadvise(2) only takes a file descriptor we own
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_WILLNEED) } {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// MemAvailable from /proc/meminfo, in bytes
+fn available_memory() -> u64 {
+    std::fs::read_to_string("/proc/meminfo")
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_pick_readahead_and_hot_paths_are_measured() {
+        let boltfile: BoltFile = toml::from_str(
+            r#"
+            project = "game"
+
+            [services.server]
+            image = "itzg/minecraft-server"
+            volumes = ["worlds:/data", "db:/var/lib/db:ro", "./config:/config", "plain:/plain"]
+
+            [volumes.worlds]
+            cache_profile = "streaming"
+            hot_paths = ["region"]
+
+            [volumes.db]
+            name = "game_db"
+            cache_profile = "database"
+
+            [volumes.plain]
+            driver = "local"
+            "#,
+        )
+        .unwrap();
+        let tuned = tuned_volumes(&boltfile, &boltfile.services["server"]);
+        let names: Vec<&str> = tuned.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["worlds", "game_db"]);
+
+        let volume = |name: &str, mountpoint: &str, profile| TunedVolume {
+            name: name.to_string(),
+            mountpoint: PathBuf::from(mountpoint),
+            profile,
+            hot_paths: Vec::new(),
+        };
+        let volumes = [
+            volume("worlds", "/srv/a", CacheProfile::Streaming),
+            volume("db", "/srv/b", CacheProfile::Database),
+            volume("logs", "/srv/c", CacheProfile::Database),
+            volume("tmp", "/tmp/x", CacheProfile::Database),
+            volume("other", "/srv/d", CacheProfile::Default),
+        ];
+        let (wanted, errors) = wanted_readahead(&volumes, |path| match path.to_str() {
+            Some("/srv/a") | Some("/srv/b") => Some((259, 0)),
+            Some("/srv/c") => Some((8, 0)),
+            _ => None,
+        });
+        assert_eq!(wanted["259:0"], 4096);
+        assert_eq!(wanted["8:0"], 16);
+        assert_eq!(wanted.len(), 2);
+        assert_eq!(errors, vec!["tmp: no block device behind /tmp/x"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("region/r.0.0")).unwrap();
+        std::fs::write(dir.path().join("region/r.0.0/a.mca"), vec![7u8; 10_000]).unwrap();
+        std::fs::write(dir.path().join("region/b.mca"), vec![7u8; 5_000]).unwrap();
+        std::fs::write(dir.path().join("level.dat"), b"outside").unwrap();
+        let worlds = TunedVolume {
+            name: "worlds".to_string(),
+            mountpoint: dir.path().to_path_buf(),
+            profile: CacheProfile::Streaming,
+            hot_paths: vec!["region".to_string()],
+        };
+        let (hot_paths, _) = warm(std::slice::from_ref(&worlds), true);
+        assert_eq!(hot_paths.len(), 1);
+        assert_eq!(hot_paths[0].bytes, 15_000);
+        // Just written, so the pages are cached
+        assert!(hot_paths[0].resident_bytes > 0);
+        assert!(hot_paths[0].resident_bytes <= 15_000);
+    }
+}
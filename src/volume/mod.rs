@@ -3,15 +3,23 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
 use std::time::SystemTime;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+pub mod cache;
+
 /// Volume management for Bolt containers
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Cheap to clone; clones share state, so one manager can serve concurrent
+/// commands. Operations on the same volume are serialized by a per-volume
+/// lock, and the in-memory view is only updated once a change is on disk.
+#[derive(Debug, Clone)]
 pub struct VolumeManager {
-    pub volumes_dir: PathBuf,
-    pub volumes: HashMap<String, Volume>,
+    volumes_dir: PathBuf,
+    volumes: Arc<RwLock<HashMap<String, Volume>>>,
+    locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,17 +57,21 @@ pub struct VolumeInfo {
 impl VolumeManager {
     /// Create new volume manager
     pub fn new() -> Result<Self> {
-        let volumes_dir = PathBuf::from("/var/lib/bolt/volumes");
+        Self::open(crate::runtime::rootless::storage_root().join("volumes"))
+    }
 
+    /// Volume manager for volumes under `volumes_dir`
+    pub fn open(volumes_dir: PathBuf) -> Result<Self> {
         // Create volumes directory if it doesn't exist
         if !volumes_dir.exists() {
             fs::create_dir_all(&volumes_dir)?;
             info!("📁 Created volumes directory: {:?}", volumes_dir);
         }
 
-        let mut manager = Self {
+        let manager = Self {
             volumes_dir,
-            volumes: HashMap::new(),
+            volumes: Arc::new(RwLock::new(HashMap::new())),
+            locks: Arc::new(Mutex::new(HashMap::new())),
         };
 
         // Load existing volumes
@@ -68,12 +80,23 @@ impl VolumeManager {
         Ok(manager)
     }
 
+    pub fn volumes_dir(&self) -> &Path {
+        &self.volumes_dir
+    }
+
     /// Create a new volume
-    pub fn create_volume(&mut self, name: &str, options: VolumeCreateOptions) -> Result<Volume> {
+    pub fn create_volume(&self, name: &str, options: VolumeCreateOptions) -> Result<Volume> {
         info!("📦 Creating volume: {}", name);
 
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return Err(anyhow::anyhow!("Invalid volume name: '{}'", name));
+        }
+
+        let lock = self.lock_for(name);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
         // Check if volume already exists
-        if self.volumes.contains_key(name) {
+        if self.read().contains_key(name) {
             return Err(anyhow::anyhow!("Volume '{}' already exists", name));
         }
 
@@ -86,33 +109,12 @@ impl VolumeManager {
 
         // Create volume directory
         let volume_path = self.volumes_dir.join(name);
-        if !volume_path.exists() {
+        let created_dir = !volume_path.exists();
+        if created_dir {
             fs::create_dir_all(&volume_path)?;
             info!("  ✓ Created volume directory: {:?}", volume_path);
         }
 
-        // Set up volume based on driver
-        match options.driver.as_str() {
-            "local" => {
-                self.setup_local_volume(&volume_path, size_bytes)?;
-            }
-            "nfs" => {
-                self.setup_nfs_volume(&volume_path, &options.options)?;
-            }
-            "overlay" => {
-                self.setup_overlay_volume(&volume_path)?;
-            }
-            "tmpfs" => {
-                self.setup_tmpfs_volume(&volume_path, size_bytes)?;
-            }
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Unsupported volume driver: {}",
-                    options.driver
-                ));
-            }
-        }
-
         let volume = Volume {
             name: name.to_string(),
             driver: options.driver,
@@ -125,11 +127,19 @@ impl VolumeManager {
             used_by: Vec::new(),
         };
 
-        // Save volume metadata
-        self.save_volume_metadata(&volume)?;
+        // Set up the volume and record it; a failure undoes both, so a
+        // half-created volume is never visible or left behind
+        if let Err(e) = self.setup_volume(&volume) {
+            self.discard_directory(&volume, created_dir);
+            return Err(e);
+        }
+        if let Err(e) = self.save_volume_metadata(&volume) {
+            let _ = self.unmount_volume(&volume);
+            self.discard_directory(&volume, created_dir);
+            return Err(e);
+        }
 
-        // Store in memory
-        self.volumes.insert(name.to_string(), volume.clone());
+        self.write().insert(name.to_string(), volume.clone());
 
         info!("✅ Volume '{}' created successfully", name);
         Ok(volume)
@@ -137,7 +147,8 @@ impl VolumeManager {
 
     /// List all volumes
     pub fn list_volumes(&self) -> Vec<VolumeInfo> {
-        self.volumes
+        let mut volumes: Vec<VolumeInfo> = self
+            .read()
             .values()
             .map(|vol| VolumeInfo {
                 name: vol.name.clone(),
@@ -148,16 +159,22 @@ impl VolumeManager {
                 in_use: vol.in_use,
                 containers: vol.used_by.clone(),
             })
-            .collect()
+            .collect();
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        volumes
     }
 
     /// Remove a volume
-    pub fn remove_volume(&mut self, name: &str, force: bool) -> Result<()> {
+    pub fn remove_volume(&self, name: &str, force: bool) -> Result<()> {
         info!("🗑️ Removing volume: {} (force: {})", name, force);
 
+        let lock = self.lock_for(name);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
         let volume = self
-            .volumes
+            .read()
             .get(name)
+            .cloned()
             .ok_or_else(|| anyhow::anyhow!("Volume '{}' not found", name))?;
 
         // Check if volume is in use
@@ -170,25 +187,40 @@ impl VolumeManager {
         }
 
         // Unmount if necessary
-        self.unmount_volume(volume)?;
+        self.unmount_volume(&volume)?;
 
-        // Remove volume directory
+        // Move the data aside first: if that fails nothing has changed yet
         let volume_path = &volume.mount_point;
+        let trash =
+            self.volumes_dir
+                .join(format!(".{}.removing-{}", name, Uuid::new_v4().simple()));
         if volume_path.exists() {
-            fs::remove_dir_all(volume_path)?;
-            info!("  ✓ Removed volume directory: {:?}", volume_path);
+            fs::rename(volume_path, &trash)?;
         }
 
         // Remove metadata file
         let metadata_file = self.volumes_dir.join(format!("{}.json", name));
         if metadata_file.exists() {
-            fs::remove_file(metadata_file)?;
+            if let Err(e) = fs::remove_file(&metadata_file) {
+                if trash.exists() {
+                    let _ = fs::rename(&trash, volume_path);
+                }
+                return Err(e.into());
+            }
             info!("  ✓ Removed volume metadata");
         }
 
         // Remove from memory
-        self.volumes.remove(name);
+        self.write().remove(name);
 
+        if trash.exists() {
+            match fs::remove_dir_all(&trash) {
+                Ok(()) => info!("  ✓ Removed volume directory: {:?}", volume_path),
+                Err(e) => warn!("Failed to delete data of volume '{}': {}", name, e),
+            }
+        }
+
+        self.forget_lock(name, &lock);
         info!("✅ Volume '{}' removed successfully", name);
         Ok(())
     }
@@ -197,24 +229,26 @@ impl VolumeManager {
     pub fn inspect_volume(&self, name: &str) -> Result<Volume> {
         info!("🔍 Inspecting volume: {}", name);
 
-        self.volumes
+        self.read()
             .get(name)
             .cloned()
             .ok_or_else(|| anyhow::anyhow!("Volume '{}' not found", name))
     }
 
     /// Prune unused volumes
-    pub fn prune_volumes(&mut self, force: bool) -> Result<Vec<String>> {
+    pub fn prune_volumes(&self, force: bool) -> Result<Vec<String>> {
         info!("🧹 Pruning unused volumes (force: {})", force);
 
         let mut removed_volumes = Vec::new();
         let unused_volumes: Vec<String> = self
-            .volumes
+            .read()
             .iter()
             .filter(|(_, vol)| !vol.in_use)
             .map(|(name, _)| name.clone())
             .collect();
 
+        // Without force, remove_volume checks again under the volume's lock,
+        // so a volume mounted in the meantime is kept
         for volume_name in unused_volumes {
             match self.remove_volume(&volume_name, force) {
                 Ok(_) => {
@@ -231,27 +265,19 @@ impl VolumeManager {
     }
 
     /// Mount volume to container
-    pub fn mount_volume(&mut self, volume_name: &str, container_id: &str) -> Result<PathBuf> {
+    pub fn mount_volume(&self, volume_name: &str, container_id: &str) -> Result<PathBuf> {
         info!(
             "🔗 Mounting volume '{}' to container '{}'",
             volume_name, container_id
         );
 
-        let volume = self
-            .volumes
-            .get_mut(volume_name)
-            .ok_or_else(|| anyhow::anyhow!("Volume '{}' not found", volume_name))?;
-
-        // Add container to usage list
-        if !volume.used_by.contains(&container_id.to_string()) {
-            volume.used_by.push(container_id.to_string());
-            volume.in_use = true;
-        }
-
-        // Save updated metadata
-        let mount_point = volume.mount_point.clone();
-        let volume_clone = volume.clone();
-        self.save_volume_metadata(&volume_clone)?;
+        let mount_point = self.update_volume(volume_name, |volume| {
+            // Add container to usage list
+            if !volume.used_by.contains(&container_id.to_string()) {
+                volume.used_by.push(container_id.to_string());
+                volume.in_use = true;
+            }
+        })?;
 
         info!("  ✓ Volume mounted to: {:?}", mount_point);
         Ok(mount_point)
@@ -259,7 +285,7 @@ impl VolumeManager {
 
     /// Unmount volume from container
     pub fn unmount_volume_from_container(
-        &mut self,
+        &self,
         volume_name: &str,
         container_id: &str,
     ) -> Result<()> {
@@ -268,23 +294,82 @@ impl VolumeManager {
             volume_name, container_id
         );
 
-        let volume = self
-            .volumes
-            .get_mut(volume_name)
-            .ok_or_else(|| anyhow::anyhow!("Volume '{}' not found", volume_name))?;
+        self.update_volume(volume_name, |volume| {
+            // Remove container from usage list
+            volume.used_by.retain(|id| id != container_id);
+            volume.in_use = !volume.used_by.is_empty();
+        })?;
 
-        // Remove container from usage list
-        volume.used_by.retain(|id| id != container_id);
-        volume.in_use = !volume.used_by.is_empty();
+        info!("  ✓ Volume unmounted from container");
+        Ok(())
+    }
 
-        // Clone what we need before calling save_volume_metadata
-        let volume_clone = volume.clone();
+    /// Change a volume under its lock, saving it before the change becomes
+    /// visible. Returns the mount point.
+    fn update_volume(&self, name: &str, change: impl FnOnce(&mut Volume)) -> Result<PathBuf> {
+        let lock = self.lock_for(name);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
 
-        // Save updated metadata
-        self.save_volume_metadata(&volume_clone)?;
+        let mut volume = self
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Volume '{}' not found", name))?;
+        change(&mut volume);
+        self.save_volume_metadata(&volume)?;
 
-        info!("  ✓ Volume unmounted from container");
-        Ok(())
+        let mount_point = volume.mount_point.clone();
+        self.write().insert(name.to_string(), volume);
+        Ok(mount_point)
+    }
+
+    fn lock_for(&self, name: &str) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Drop a removed volume's lock unless another caller is already waiting on it
+    fn forget_lock(&self, name: &str, lock: &Arc<Mutex<()>>) {
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        // One reference is the map's and one is the caller's
+        if Arc::strong_count(lock) <= 2 {
+            locks.remove(name);
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Volume>> {
+        self.volumes.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Volume>> {
+        self.volumes.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set up volume based on driver
+    fn setup_volume(&self, volume: &Volume) -> Result<()> {
+        match volume.driver.as_str() {
+            "local" => self.setup_local_volume(&volume.mount_point, volume.size_bytes),
+            "nfs" => self.setup_nfs_volume(&volume.mount_point, &volume.options),
+            "overlay" => self.setup_overlay_volume(&volume.mount_point),
+            "tmpfs" => self.setup_tmpfs_volume(&volume.mount_point, volume.size_bytes),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported volume driver: {}",
+                volume.driver
+            )),
+        }
+    }
+
+    /// Roll back the directory of a volume whose creation failed
+    fn discard_directory(&self, volume: &Volume, created: bool) {
+        if created {
+            if let Err(e) = fs::remove_dir_all(&volume.mount_point) {
+                warn!("Failed to clean up {:?}: {}", volume.mount_point, e);
+            }
+        }
     }
 
     /// Setup local volume
@@ -498,16 +583,22 @@ impl VolumeManager {
         }
     }
 
-    /// Save volume metadata to disk
+    /// Save volume metadata to disk, replacing the old file atomically
     fn save_volume_metadata(&self, volume: &Volume) -> Result<()> {
         let metadata_file = self.volumes_dir.join(format!("{}.json", volume.name));
+        let staging = self.volumes_dir.join(format!(".{}.json.tmp", volume.name));
         let metadata_json = serde_json::to_string_pretty(volume)?;
-        fs::write(metadata_file, metadata_json)?;
+        fs::write(&staging, metadata_json)?;
+        if let Err(e) = fs::rename(&staging, &metadata_file) {
+            let _ = fs::remove_file(&staging);
+            return Err(e.into());
+        }
         Ok(())
     }
 
     /// Load existing volumes from disk
-    fn load_volumes(&mut self) -> Result<()> {
+    fn load_volumes(&self) -> Result<()> {
+        let mut volumes = self.write();
         if !self.volumes_dir.exists() {
             return Ok(());
         }
@@ -516,12 +607,18 @@ impl VolumeManager {
             let entry = entry?;
             let path = entry.path();
 
+            // Data of a removal that was interrupted
+            if entry.file_name().to_string_lossy().contains(".removing-") {
+                let _ = fs::remove_dir_all(&path);
+                continue;
+            }
+
             if path.extension() == Some(std::ffi::OsStr::new("json")) {
                 match fs::read_to_string(&path) {
                     Ok(content) => match serde_json::from_str::<Volume>(&content) {
                         Ok(volume) => {
                             debug!("Loaded volume: {}", volume.name);
-                            self.volumes.insert(volume.name.clone(), volume);
+                            volumes.insert(volume.name.clone(), volume);
                         }
                         Err(e) => {
                             warn!("Failed to parse volume metadata {:?}: {}", path, e);
@@ -534,7 +631,7 @@ impl VolumeManager {
             }
         }
 
-        info!("📦 Loaded {} volumes", self.volumes.len());
+        info!("📦 Loaded {} volumes", volumes.len());
         Ok(())
     }
 }
@@ -543,7 +640,8 @@ impl Default for VolumeManager {
     fn default() -> Self {
         Self::new().unwrap_or_else(|_| Self {
             volumes_dir: PathBuf::from("/tmp/bolt-volumes"),
-            volumes: HashMap::new(),
+            volumes: Arc::new(RwLock::new(HashMap::new())),
+            locks: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 }
@@ -558,3 +656,70 @@ impl Default for VolumeCreateOptions {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_creates_of_one_volume_have_one_winner() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = VolumeManager::open(dir.path().to_path_buf()).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let manager = manager.clone();
+                std::thread::spawn(move || {
+                    manager
+                        .create_volume("data", VolumeCreateOptions::default())
+                        .is_ok()
+                })
+            })
+            .collect();
+        let created = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|created| *created)
+            .count();
+        assert_eq!(created, 1);
+
+        // A failed create leaves nothing behind
+        let options = VolumeCreateOptions {
+            driver: "zfs".to_string(),
+            ..Default::default()
+        };
+        assert!(manager.create_volume("broken", options).is_err());
+        assert!(!dir.path().join("broken").exists());
+
+        manager.mount_volume("data", "web").unwrap();
+        assert!(manager.remove_volume("data", false).is_err());
+        manager
+            .unmount_volume_from_container("data", "web")
+            .unwrap();
+        manager.remove_volume("data", false).unwrap();
+        assert!(!dir.path().join("data").exists());
+
+        let reopened = VolumeManager::open(dir.path().to_path_buf()).unwrap();
+        assert!(reopened.list_volumes().is_empty());
+    }
+
+    #[test]
+    fn prune_removes_unused_volumes_and_their_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = VolumeManager::open(dir.path().to_path_buf()).unwrap();
+        for name in ["cache", "data"] {
+            manager
+                .create_volume(name, VolumeCreateOptions::default())
+                .unwrap();
+        }
+        manager.mount_volume("data", "web").unwrap();
+
+        assert_eq!(manager.prune_volumes(true).unwrap(), vec!["cache"]);
+        assert_eq!(manager.list_volumes().len(), 1);
+        assert!(!manager.locks.lock().unwrap().contains_key("cache"));
+
+        manager.remove_volume("data", true).unwrap();
+        assert!(manager.list_volumes().is_empty());
+        assert!(manager.locks.lock().unwrap().is_empty());
+    }
+}
@@ -30,6 +30,7 @@ async fn test_bolt_types_available() {
         name: "test".to_string(),
         status: "test".to_string(),
         replicas: 0,
+        restarts: None,
     };
 
     let _network = bolt::NetworkInfo {
@@ -36,6 +36,7 @@ async fn test_complete_api_surface() {
         name: "web-service".to_string(),
         status: "healthy".to_string(),
         replicas: 3,
+        restarts: None,
     };
     assert_eq!(service.replicas, 3);
 
@@ -49,6 +50,7 @@ async fn test_complete_api_surface() {
     let surge_status = SurgeStatus {
         services: vec![service.clone()],
         networks: vec![network.clone()],
+        dirty: None,
     };
     assert_eq!(surge_status.services.len(), 1);
     assert_eq!(surge_status.networks.len(), 1);
@@ -69,7 +71,7 @@ async fn test_complete_api_surface() {
             Service {
                 image: Some("nginx:alpine".to_string()),
                 ports: Some(vec!["80:80".to_string()]),
-                depends_on: Some(vec!["cache".to_string()]),
+                depends_on: Some(vec!["cache".to_string()].into()),
                 ..Default::default()
             },
         )
@@ -83,7 +85,16 @@ async fn test_complete_api_surface() {
     // Verify service dependencies
     let web_service = &boltfile.services["web"];
     assert!(web_service.depends_on.is_some());
-    assert_eq!(web_service.depends_on.as_ref().unwrap()[0], "cache");
+    assert_eq!(
+        web_service
+            .depends_on
+            .as_ref()
+            .unwrap()
+            .names()
+            .next()
+            .unwrap(),
+        "cache"
+    );
 }
 
 #[tokio::test]
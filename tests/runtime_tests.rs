@@ -22,6 +22,23 @@ async fn test_runtime_with_custom_config() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: true,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     let runtime = BoltRuntime::with_config(config);
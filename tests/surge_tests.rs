@@ -26,6 +26,23 @@ async fn test_surge_basic_deployment() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();
@@ -78,7 +95,7 @@ async fn test_surge_multi_service() {
                 image: Some("node:alpine".to_string()),
                 ports: Some(vec!["3000:3000".to_string()]),
                 env: Some(env),
-                depends_on: Some(vec!["db".to_string()]),
+                depends_on: Some(vec!["db".to_string()].into()),
                 ..Default::default()
             },
         )
@@ -87,7 +104,7 @@ async fn test_surge_multi_service() {
             Service {
                 image: Some("nginx:alpine".to_string()),
                 ports: Some(vec!["80:80".to_string()]),
-                depends_on: Some(vec!["api".to_string()]),
+                depends_on: Some(vec!["api".to_string()].into()),
                 ..Default::default()
             },
         )
@@ -98,6 +115,23 @@ async fn test_surge_multi_service() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();
@@ -147,7 +181,7 @@ async fn test_surge_with_dependencies() {
             "app",
             Service {
                 image: Some("alpine:latest".to_string()),
-                depends_on: Some(vec!["database".to_string(), "cache".to_string()]),
+                depends_on: Some(vec!["database".to_string(), "cache".to_string()].into()),
                 ..Default::default()
             },
         )
@@ -158,6 +192,23 @@ async fn test_surge_with_dependencies() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();
@@ -195,6 +246,23 @@ async fn test_surge_force_recreate() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();
@@ -255,6 +323,23 @@ async fn test_surge_selective_services() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();
@@ -306,6 +391,23 @@ async fn test_surge_with_volumes() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     // Create data directory
@@ -348,6 +450,7 @@ async fn test_surge_gaming_service() {
             proton: Some("8.0".to_string()),
             winver: Some("win10".to_string()),
             prefix: Some("/games/prefix".to_string()),
+            prefix_cache: None,
         }),
         performance: Some(PerformanceConfig {
             cpu_governor: Some("performance".to_string()),
@@ -365,6 +468,23 @@ async fn test_surge_gaming_service() {
         data_dir: temp_dir.path().join("data"),
         boltfile_path: temp_dir.path().join("Boltfile.toml"),
         verbose: false,
+        logging: Default::default(),
+        build_cache: Default::default(),
+        capacity: Default::default(),
+        alerts: Default::default(),
+        backup: Default::default(),
+        encryption: Default::default(),
+        trust: Default::default(),
+        ports: Default::default(),
+        platform: Default::default(),
+        p2p: Default::default(),
+        maintenance: Default::default(),
+        monitoring: Default::default(),
+        nat: Default::default(),
+        acme: Default::default(),
+        template: Default::default(),
+        overrides: Vec::new(),
+        workspace: None,
     };
 
     config.save_boltfile(&boltfile).unwrap();